@@ -0,0 +1,18 @@
+//! DHCPv4 packets from the LAN, broadcast by any client.
+#![no_main]
+
+use hr_dhcp::packet::DhcpPacket;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = hr_dhcp::options::parse_options(data);
+    let Ok(packet) = DhcpPacket::parse(data) else {
+        return;
+    };
+    let _ = (packet.mac_str(), packet.msg_type(), packet.hostname(), packet.client_id());
+    let _ = (packet.requested_ip(), packet.server_id());
+    // What we accepted must survive a round trip
+    let reparsed = DhcpPacket::parse(&packet.to_bytes()).expect("own packet must parse");
+    assert_eq!(reparsed.xid, packet.xid);
+    assert_eq!(reparsed.options.len(), packet.options.len());
+});
@@ -0,0 +1,21 @@
+//! Client queries as received by the DNS server on UDP/TCP 53.
+#![no_main]
+
+use hr_dns::packet::{
+    build_error_response, build_response, parse_query, parse_response_sections,
+    peek_edns_udp_size, truncate_for_udp, RCODE_SERVFAIL,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = peek_edns_udp_size(data);
+    let Ok(query) = parse_query(data) else {
+        return;
+    };
+    // Whatever we accepted must produce answers we can read back
+    let mut response = build_response(&query, &[], 0);
+    parse_response_sections(&response).expect("own response must parse");
+    truncate_for_udp(&mut response, 512);
+    let error = build_error_response(&query, RCODE_SERVFAIL);
+    parse_response_sections(&error).expect("own error response must parse");
+});
@@ -0,0 +1,16 @@
+//! Upstream answers, parsed for the cache and truncated for UDP clients.
+#![no_main]
+
+use hr_dns::packet::{parse_response_sections, truncate_for_udp};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_response_sections(data);
+    if let Some((&size, packet)) = data.split_first() {
+        // Any limit the client may advertise, including absurdly small ones
+        let max_size = size as usize * 4;
+        let mut response = packet.to_vec();
+        truncate_for_udp(&mut response, max_size);
+        assert!(response.len() <= packet.len());
+    }
+});
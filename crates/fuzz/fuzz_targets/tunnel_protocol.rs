@@ -0,0 +1,19 @@
+//! Stream headers and control messages read from the QUIC tunnel.
+#![no_main]
+
+use hr_tunnel::protocol::{ControlMessage, StreamHeader};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(header) = StreamHeader::decode(&mut &data[..]) {
+        let encoded = header.encode();
+        let decoded = StreamHeader::decode(&mut &encoded[..]).expect("own header must decode");
+        assert_eq!(decoded.client_ip, header.client_ip);
+        assert_eq!(decoded.timestamp, header.timestamp);
+        assert_eq!(decoded.proxy_ip, header.proxy_ip);
+    }
+    if let Ok(msg) = ControlMessage::decode(&mut &data[..]) {
+        let encoded = msg.encode().expect("decoded message must encode");
+        ControlMessage::decode(&mut &encoded[..]).expect("own message must decode");
+    }
+});
@@ -6,7 +6,7 @@ use hr_acme::{AcmeConfig, AcmeManager, WildcardType};
 use hr_common::config::EnvConfig;
 use hr_common::events::{CertReadyEvent, EventBus};
 use hr_common::service_registry::{
-    new_service_registry, now_millis, ServicePriorityLevel, ServiceState, ServiceStatus,
+    new_service_registry, ServicePriorityLevel, ServiceState, ServiceStatus,
 };
 use hr_dns::DnsState;
 use hr_proxy::{ProxyConfig, ProxyState, TlsManager};
@@ -16,7 +16,7 @@ use signal_hook_tokio::Signals;
 use std::net::{Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
 use std::sync::Arc;
-use supervisor::{spawn_supervised, ServicePriority};
+use supervisor::{spawn_supervised, ServicePriority, Supervisor};
 use tokio::sync::RwLock;
 use tokio_stream::StreamExt;
 use tracing::{debug, error, info, warn};
@@ -70,12 +70,14 @@ async fn main() -> anyhow::Result<()> {
     // Initialize event bus
     let events = Arc::new(EventBus::new());
 
-    // Initialize service registry
+    // Initialize service registry and supervisor
     let service_registry = new_service_registry();
+    let supervisor = Supervisor::new(service_registry.clone());
 
     // Initialize auth service
     let auth = AuthService::new(&env.auth_data_dir, &env.base_domain)?;
     auth.start_cleanup_task();
+    auth.throttle.set_events(events.clone());
     info!("Auth service initialized");
 
     // Initialize ACME (Let's Encrypt)
@@ -143,11 +145,10 @@ async fn main() -> anyhow::Result<()> {
     adblock_engine.set_whitelist(dns_dhcp_config.adblock.whitelist.clone());
 
     if dns_dhcp_config.adblock.enabled {
-        let cache_path = PathBuf::from(&dns_dhcp_config.adblock.data_dir).join("domains.json");
-        match hr_adblock::sources::load_cache(&cache_path) {
-            Ok(domains) => {
-                info!("Loaded {} blocked domains from cache", domains.len());
-                adblock_engine.set_blocked(domains);
+        match hr_adblock::sources::load_cache(std::path::Path::new(&dns_dhcp_config.adblock.data_dir)) {
+            Ok(rules) => {
+                info!("Loaded {} adblock rules from cache", rules.rules.len());
+                adblock_engine.set_rules(rules);
             }
             Err(_) => {
                 info!("No adblock cache found, will download on startup");
@@ -157,6 +158,11 @@ async fn main() -> anyhow::Result<()> {
 
     let adblock = Arc::new(RwLock::new(adblock_engine));
 
+    let adblock_schedule = Arc::new(hr_adblock::AdblockSchedule::new());
+    if let Err(e) = adblock_schedule.set_profiles(&dns_dhcp_config.adblock.profiles) {
+        warn!("Invalid adblock profiles, none applied: {}", e);
+    }
+
     // ── Initialize DHCP state ──────────────────────────────────────────
 
     let server_ip: Ipv4Addr = dns_dhcp_config
@@ -176,16 +182,39 @@ async fn main() -> anyhow::Result<()> {
         server_ip,
     }));
 
-    // Separate LeaseStore for DNS resolver (synced from DHCP state every 10s).
-    // DhcpState owns its LeaseStore directly; DnsState needs Arc<RwLock<LeaseStore>>.
-    // A background sync task keeps them in sync.
-    let lease_store_for_dns: Arc<RwLock<hr_dhcp::LeaseStore>> = {
-        let mut shared_lease_store = hr_dhcp::LeaseStore::new(&dns_dhcp_config.dhcp.lease_file);
-        if let Err(e) = shared_lease_store.load_from_file() {
-            warn!("Failed to load lease file for DNS: {}", e);
+    // Copy of the leases for the DNS resolver. DhcpState owns its LeaseStore
+    // directly; DnsState needs Arc<RwLock<LeaseStore>>. The copy follows the
+    // DHCP store change by change (hr_dhcp::mirror_leases).
+    let lease_store_for_dns: Arc<RwLock<hr_dhcp::LeaseStore>> =
+        Arc::new(RwLock::new(hr_dhcp::LeaseStore::new(&dns_dhcp_config.dhcp.lease_file)));
+    {
+        let (dhcp_state, lease_store_dns) = (dhcp_state.clone(), lease_store_for_dns.clone());
+        tokio::spawn(async move {
+            if let Err(e) = hr_dhcp::mirror_leases(dhcp_state, lease_store_dns).await {
+                error!("DHCP lease copy for DNS stopped: {}", e);
+            }
+        });
+    }
+
+    // ── Initialize metric history ──────────────────────────────────────
+
+    if let Err(e) = hr_common::tsdb::init(&env.data_dir.join("metrics.db")) {
+        warn!("Failed to open metric history database: {}", e);
+    }
+
+    // ── Initialize ban manager ─────────────────────────────────────────
+
+    let firewall_config = match hr_firewall::FirewallConfig::load_from_file(&env.firewall_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load firewall config: {}", e);
+            hr_firewall::FirewallConfig::default()
         }
-        Arc::new(RwLock::new(shared_lease_store))
     };
+    let bans = Arc::new(hr_firewall::BanManager::new(
+        firewall_config,
+        env.data_dir.join("firewall-bans.json"),
+    ));
 
     // ── Initialize DNS state ───────────────────────────────────────────
 
@@ -204,15 +233,35 @@ async fn main() -> anyhow::Result<()> {
         None
     };
 
+    let blocked_log = if !dns_dhcp_config.dns.blocked_log_path.is_empty() {
+        hr_dns::blocked_log::BlockedLog::persistent(
+            dns_dhcp_config.dns.blocked_log_size,
+            std::path::Path::new(&dns_dhcp_config.dns.blocked_log_path),
+        )
+    } else {
+        hr_dns::blocked_log::BlockedLog::in_memory(dns_dhcp_config.dns.blocked_log_size)
+    };
+
     let dns_state: hr_dns::SharedDnsState = Arc::new(RwLock::new(DnsState {
         config: dns_dhcp_config.dns.clone(),
         dns_cache,
         upstream,
         query_logger,
+        blocked_log,
         adblock: adblock.clone(),
         lease_store: lease_store_for_dns.clone(),
         adblock_enabled: dns_dhcp_config.adblock.enabled,
+        adblock_schedule: adblock_schedule.clone(),
+        safesearch: hr_dns::safesearch::SafeSearch::new(&dns_dhcp_config.dns.safesearch).unwrap_or_else(|e| {
+            warn!("Invalid SafeSearch config, disabled: {}", e);
+            Default::default()
+        }),
+        static_patterns: hr_dns::patterns::StaticPatterns::new(&dns_dhcp_config.dns.static_records),
+        route_records: Vec::new(),
         adblock_block_response: dns_dhcp_config.adblock.block_response.clone(),
+        stats: Default::default(),
+        bans: Some(bans.clone()),
+        health: Default::default(),
     }));
 
     // ── Initialize proxy ───────────────────────────────────────────────
@@ -238,9 +287,11 @@ async fn main() -> anyhow::Result<()> {
         if cert_path.exists() && key_path.exists() {
             match tls_manager.load_cert_from_files(cert_path, key_path) {
                 Ok(certified_key) => {
-                    let domain = cert_info.wildcard_type.domain_pattern(&env.base_domain);
-                    tls_manager.add_cert(&domain, certified_key);
-                    info!(domain = %domain, "Loaded certificate");
+                    // Every name on the certificate (the global one also covers the apex)
+                    for domain in &cert_info.domains {
+                        tls_manager.add_cert(domain, certified_key.clone());
+                        info!(domain = %domain, "Loaded certificate");
+                    }
                 }
                 Err(e) => {
                     warn!(cert_id = %cert_info.id, error = %e, "Failed to load certificate");
@@ -263,8 +314,10 @@ async fn main() -> anyhow::Result<()> {
 
     let proxy_state = Arc::new(
         ProxyState::new(proxy_config.clone(), env.api_port)
-            .with_auth(auth.clone()),
+            .with_auth(auth.clone())
+            .with_bans(bans.clone()),
     );
+    proxy_state.set_dns(dns_state.clone());
 
     let https_port = proxy_config.https_port;
     let http_port = proxy_config.http_port;
@@ -284,6 +337,7 @@ async fn main() -> anyhow::Result<()> {
     let proxy_config_path_reload = env.proxy_config_path.clone();
     let tls_manager = Arc::new(tls_manager);
     let tls_manager_reload = tls_manager.clone();
+    supervisor.mark_ready("tls", ServicePriority::Critical).await;
 
     // ── Spawn supervised services ──────────────────────────────────────
 
@@ -300,7 +354,7 @@ async fn main() -> anyhow::Result<()> {
         let addr: SocketAddr = addr_formatted.parse()?;
 
         let dns_state_c = dns_state.clone();
-        let reg = service_registry.clone();
+        let reg = supervisor.clone();
         spawn_supervised("dns-udp", ServicePriority::Critical, reg, move || {
             let state = dns_state_c.clone();
             let addr = addr;
@@ -308,32 +362,44 @@ async fn main() -> anyhow::Result<()> {
         });
 
         let dns_state_c = dns_state.clone();
-        let reg = service_registry.clone();
+        let reg = supervisor.clone();
         spawn_supervised("dns-tcp", ServicePriority::Critical, reg, move || {
             let state = dns_state_c.clone();
             let addr = addr;
             async move { hr_dns::server::run_tcp_server(addr, state).await }
         });
+
+        let dns_state_c = dns_state.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("dns-upstream-probe", ServicePriority::Background, reg, move || {
+            let state = dns_state_c.clone();
+            async move { hr_dns::health::run_upstream_probe(state).await }
+        });
+
+        let schedule = adblock_schedule.clone();
+        let events_c = events.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("adblock-schedule", ServicePriority::Background, reg, move || {
+            let schedule = schedule.clone();
+            let events = events_c.clone();
+            async move { hr_dns::schedule::run_adblock_schedule(schedule, events).await }
+        });
     }
 
     // DHCP server (Critical)
     if dns_dhcp_config.dhcp.enabled {
         let dhcp_state_c = dhcp_state.clone();
-        let reg = service_registry.clone();
+        let reg = supervisor.clone();
         spawn_supervised("dhcp", ServicePriority::Critical, reg, move || {
             let state = dhcp_state_c.clone();
             async move { hr_dhcp::server::run_dhcp_server(state).await }
         });
     } else {
         let mut reg = service_registry.write().await;
-        reg.insert("dhcp".into(), ServiceStatus {
-            name: "dhcp".into(),
-            state: ServiceState::Disabled,
-            priority: ServicePriorityLevel::Critical,
-            restart_count: 0,
-            last_state_change: now_millis(),
-            error: None,
-        });
+        reg.insert(
+            "dhcp".into(),
+            ServiceStatus::new("dhcp", ServiceState::Disabled, ServicePriorityLevel::Critical),
+        );
         drop(reg);
     }
 
@@ -341,7 +407,7 @@ async fn main() -> anyhow::Result<()> {
     {
         let proxy_state_c = proxy_state.clone();
         let tls_config_c = tls_config.clone();
-        let reg = service_registry.clone();
+        let reg = supervisor.clone();
         spawn_supervised("proxy-https", ServicePriority::Critical, reg, move || {
             let proxy_state = proxy_state_c.clone();
             let tls_config = tls_config_c.clone();
@@ -350,14 +416,200 @@ async fn main() -> anyhow::Result<()> {
         });
     }
 
-    // HTTP redirect (Critical)
+    // HTTP redirect + ACME HTTP-01 challenges (Critical)
     {
-        let base_domain = env.base_domain.clone();
-        let reg = service_registry.clone();
+        let acme_http = acme.clone();
+        let proxy_state_c = proxy_state.clone();
+        let reg = supervisor.clone();
         spawn_supervised("proxy-http", ServicePriority::Critical, reg, move || {
-            let base_domain = base_domain.clone();
+            let acme = acme_http.clone();
+            let proxy_state = proxy_state_c.clone();
             let port = http_port;
-            async move { run_http_redirect(port, &base_domain).await }
+            async move { run_http_redirect(port, acme, proxy_state).await }
+        });
+    }
+
+    // Stream proxy — TCP/UDP port forwards (Important)
+    let stream_config = match hr_stream::StreamConfig::load_from_file(&env.streams_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load stream config: {}", e);
+            hr_stream::StreamConfig::default()
+        }
+    };
+    info!("Stream proxy: {} port forwards configured", stream_config.streams.len());
+    let stream_proxy = Arc::new(hr_stream::StreamProxy::new(stream_config));
+    {
+        let stream_proxy_c = stream_proxy.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("stream-proxy", ServicePriority::Important, reg, move || {
+            let proxy = stream_proxy_c.clone();
+            async move { hr_stream::server::run_stream_proxy(proxy).await }
+        });
+    }
+
+    // Multicast reflector — mDNS/SSDP between VLANs (Background)
+    let reflector_config = match hr_reflector::ReflectorConfig::load_from_file(&env.reflector_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load reflector config: {}", e);
+            hr_reflector::ReflectorConfig::default()
+        }
+    };
+    let reflector = Arc::new(hr_reflector::Reflector::new(reflector_config));
+    {
+        let reflector_c = reflector.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("reflector", ServicePriority::Background, reg, move || {
+            let reflector = reflector_c.clone();
+            async move { hr_reflector::server::run_reflector(reflector).await }
+        });
+    }
+
+    // Syslog receiver — network gear logs (Background)
+    let syslog_config = match hr_syslog::SyslogConfig::load_from_file(&env.syslog_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load syslog config: {}", e);
+            hr_syslog::SyslogConfig::default()
+        }
+    };
+    let syslog_store = match hr_syslog::LogStore::open(&env.data_dir.join("syslog.db")) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Failed to open syslog database, keeping messages in memory: {}", e);
+            hr_syslog::LogStore::open_in_memory()?
+        }
+    };
+    let syslog = Arc::new(hr_syslog::Syslog::new(syslog_config, syslog_store));
+    {
+        let syslog_c = syslog.clone();
+        let dhcp_state_c = dhcp_state.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("syslog", ServicePriority::Background, reg, move || {
+            let syslog = syslog_c.clone();
+            let dhcp = dhcp_state_c.clone();
+            async move { hr_syslog::server::run_syslog(syslog, dhcp).await }
+        });
+    }
+
+    // RADIUS — WPA-Enterprise / MAC auth for the access points (Background)
+    let radius_config = match hr_radius::RadiusConfig::load_from_file(&env.radius_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load RADIUS config: {}", e);
+            hr_radius::RadiusConfig::default()
+        }
+    };
+    let radius_ca = hr_radius::RadiusCa::load_or_create(&env.data_dir.join("radius"))?;
+    let radius = Arc::new(hr_radius::Radius::new(radius_config, radius_ca, auth.clone())?);
+    {
+        let radius_c = radius.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("radius", ServicePriority::Background, reg, move || {
+            let radius = radius_c.clone();
+            async move { hr_radius::server::run_radius(radius).await }
+        });
+    }
+
+    // NTP server — time for LAN clients (Background)
+    let ntp_config = match hr_ntp::NtpConfig::load_from_file(&env.ntp_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load NTP config: {}", e);
+            hr_ntp::NtpConfig::default()
+        }
+    };
+    let ntp = Arc::new(hr_ntp::Ntp::new(ntp_config));
+    {
+        let ntp_c = ntp.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("ntp", ServicePriority::Background, reg, move || {
+            let ntp = ntp_c.clone();
+            async move { hr_ntp::server::run_ntp(ntp).await }
+        });
+    }
+
+    // Tailscale/Headscale — remote access fallback, VPN-only routes (Background)
+    let tailscale_config = match hr_tailscale::TailscaleConfig::load_from_file(&env.tailscale_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load Tailscale config: {}", e);
+            hr_tailscale::TailscaleConfig::default()
+        }
+    };
+    proxy_state.set_vpn_ranges(tailscale_config.vpn_networks());
+    let tailscale = Arc::new(hr_tailscale::Tailscale::new(tailscale_config));
+    {
+        let tailscale_c = tailscale.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("tailscale", ServicePriority::Background, reg, move || {
+            let tailscale = tailscale_c.clone();
+            async move { hr_tailscale::service::run_tailscale(tailscale).await }
+        });
+    }
+
+    // Traffic shaping — HTB/CAKE on the WAN link, devices from the DHCP leases (Background)
+    let qos_config = match hr_qos::QosConfig::load_from_file(&env.qos_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load QoS config: {}", e);
+            hr_qos::QosConfig::default()
+        }
+    };
+    let qos = Arc::new(hr_qos::Qos::new(qos_config));
+    {
+        let qos_c = qos.clone();
+        let dhcp_state_c = dhcp_state.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("qos", ServicePriority::Background, reg, move || {
+            let qos = qos_c.clone();
+            let dhcp = dhcp_state_c.clone();
+            async move { hr_qos::service::run_qos(qos, dhcp).await }
+        });
+    }
+
+    // Ban manager — expiry, persistence, nftables sync (Background)
+    {
+        let bans_c = bans.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("firewall", ServicePriority::Background, reg, move || {
+            let bans = bans_c.clone();
+            async move { hr_firewall::service::run_firewall(bans).await }
+        });
+    }
+
+    // Flow analytics — SNI and bytes of forwarded LAN traffic (Background)
+    let flow_config = match hr_flow::FlowConfig::load_from_file(&env.flow_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load flow config: {}", e);
+            hr_flow::FlowConfig::default()
+        }
+    };
+    let flows = Arc::new(hr_flow::FlowMonitor::new(flow_config));
+    {
+        let flows_c = flows.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("flow-monitor", ServicePriority::Background, reg, move || {
+            let flows = flows_c.clone();
+            async move { hr_flow::capture::run_flow_monitor(flows).await }
+        });
+    }
+
+    // Service control channel (API → supervisor)
+    let (service_control_tx, mut service_control_rx) =
+        tokio::sync::mpsc::channel::<hr_common::service_registry::ServiceControlCommand>(16);
+    {
+        let supervisor = supervisor.clone();
+        tokio::spawn(async move {
+            while let Some(cmd) = service_control_rx.recv().await {
+                let supervisor = supervisor.clone();
+                tokio::spawn(async move {
+                    let result = supervisor.control(&cmd.name, cmd.action).await;
+                    let _ = cmd.response_tx.send(result);
+                });
+            }
         });
     }
 
@@ -374,38 +626,79 @@ async fn main() -> anyhow::Result<()> {
     let cloud_relay_status: Arc<tokio::sync::RwLock<Option<hr_api::state::CloudRelayInfo>>> =
         Arc::new(tokio::sync::RwLock::new(None));
 
+    // Cloud relay traffic accounting — monthly totals per route, saved every minute
+    let relay_usage_config = match hr_tunnel::usage::RelayUsageConfig::load_from_file(&env.relay_usage_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load relay usage config: {}", e);
+            hr_tunnel::usage::RelayUsageConfig::default()
+        }
+    };
+    let relay_usage = Arc::new(hr_tunnel::usage::RelayUsage::new(
+        relay_usage_config,
+        env.data_dir.join("cloud-relay/usage.json"),
+    ));
+    {
+        let usage = relay_usage.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                let usage = usage.clone();
+                if let Ok(Err(e)) = tokio::task::spawn_blocking(move || usage.save()).await {
+                    tracing::debug!("Failed to save relay usage: {}", e);
+                }
+            }
+        });
+    }
+    {
+        let usage = relay_usage.clone();
+        supervisor.on_shutdown("cloud-relay-tunnel", std::time::Duration::from_secs(5), async move {
+            if let Ok(Err(e)) = tokio::task::spawn_blocking(move || usage.save()).await {
+                warn!("Failed to save relay usage on shutdown: {}", e);
+            }
+        });
+    }
+
     // Cloud Relay tunnel client — always spawned if host configured, waits for enable signal
     if let Some(ref relay_host) = env.cloud_relay_host {
-        let relay_host = relay_host.clone();
+        let mut relay_hosts = vec![relay_host.clone()];
+        relay_hosts.extend(env.cloud_relay_fallback_hosts.iter().cloned());
         let relay_port = env.cloud_relay_quic_port;
         let data_dir = env.data_dir.clone();
         let proxy_state_c = proxy_state.clone();
         let tls_config_c = tls_config.clone();
+        let streams_c = stream_proxy.clone();
+        let usage_c = relay_usage.clone();
         let events_c = events.clone();
         let cmd_rx = cloud_relay_cmd_rx.clone();
         let enabled_rx = cloud_relay_enabled_rx.clone();
         let status_handle = cloud_relay_status.clone();
-        let reg = service_registry.clone();
+        let reg = supervisor.clone();
         spawn_supervised(
             "cloud-relay-tunnel",
             ServicePriority::Critical,
             reg,
             move || {
-                let relay_host = relay_host.clone();
+                let relay_hosts = relay_hosts.clone();
                 let data_dir = data_dir.clone();
                 let proxy_state = proxy_state_c.clone();
                 let tls_config = tls_config_c.clone();
+                let streams = streams_c.clone();
+                let usage = usage_c.clone();
                 let events = events_c.clone();
                 let cmd_rx = cmd_rx.clone();
                 let enabled_rx = enabled_rx.clone();
                 let status_handle = status_handle.clone();
                 async move {
                     run_tunnel_client(
-                        &relay_host,
+                        &relay_hosts,
                         relay_port,
                         &data_dir,
                         proxy_state,
                         tls_config,
+                        streams,
+                        usage,
                         events,
                         cmd_rx,
                         enabled_rx,
@@ -428,7 +721,7 @@ async fn main() -> anyhow::Result<()> {
     if dns_dhcp_config.ipv6.enabled && dns_dhcp_config.ipv6.pd_enabled {
         let ipv6_config = dns_dhcp_config.ipv6.clone();
         let tx = prefix_tx.clone();
-        let reg = service_registry.clone();
+        let reg = supervisor.clone();
         spawn_supervised("ipv6-pd", ServicePriority::Important, reg, move || {
             let config = ipv6_config.clone();
             let tx = tx.clone();
@@ -436,14 +729,10 @@ async fn main() -> anyhow::Result<()> {
         });
     } else {
         let mut reg = service_registry.write().await;
-        reg.insert("ipv6-pd".into(), ServiceStatus {
-            name: "ipv6-pd".into(),
-            state: ServiceState::Disabled,
-            priority: ServicePriorityLevel::Important,
-            restart_count: 0,
-            last_state_change: now_millis(),
-            error: None,
-        });
+        reg.insert(
+            "ipv6-pd".into(),
+            ServiceStatus::new("ipv6-pd", ServiceState::Disabled, ServicePriorityLevel::Important),
+        );
         drop(reg);
     }
 
@@ -451,7 +740,7 @@ async fn main() -> anyhow::Result<()> {
     if dns_dhcp_config.ipv6.enabled && dns_dhcp_config.ipv6.ra_enabled {
         let ipv6_config = dns_dhcp_config.ipv6.clone();
         let rx = prefix_rx.clone();
-        let reg = service_registry.clone();
+        let reg = supervisor.clone();
         spawn_supervised("ipv6-ra", ServicePriority::Important, reg, move || {
             let config = ipv6_config.clone();
             let rx = rx.clone();
@@ -459,14 +748,10 @@ async fn main() -> anyhow::Result<()> {
         });
     } else {
         let mut reg = service_registry.write().await;
-        reg.insert("ipv6-ra".into(), ServiceStatus {
-            name: "ipv6-ra".into(),
-            state: ServiceState::Disabled,
-            priority: ServicePriorityLevel::Important,
-            restart_count: 0,
-            last_state_change: now_millis(),
-            error: None,
-        });
+        reg.insert(
+            "ipv6-ra".into(),
+            ServiceStatus::new("ipv6-ra", ServiceState::Disabled, ServicePriorityLevel::Important),
+        );
         drop(reg);
     }
 
@@ -474,7 +759,7 @@ async fn main() -> anyhow::Result<()> {
     if dns_dhcp_config.ipv6.enabled && dns_dhcp_config.ipv6.dhcpv6_enabled {
         let ipv6_config = dns_dhcp_config.ipv6.clone();
         let rx = prefix_rx.clone();
-        let reg = service_registry.clone();
+        let reg = supervisor.clone();
         spawn_supervised("dhcpv6", ServicePriority::Important, reg, move || {
             let config = ipv6_config.clone();
             let prefix_rx = rx.clone();
@@ -482,17 +767,75 @@ async fn main() -> anyhow::Result<()> {
         });
     } else {
         let mut reg = service_registry.write().await;
-        reg.insert("dhcpv6".into(), ServiceStatus {
-            name: "dhcpv6".into(),
-            state: ServiceState::Disabled,
-            priority: ServicePriorityLevel::Important,
-            restart_count: 0,
-            last_state_change: now_millis(),
-            error: None,
-        });
+        reg.insert(
+            "dhcpv6".into(),
+            ServiceStatus::new("dhcpv6", ServiceState::Disabled, ServicePriorityLevel::Important),
+        );
         drop(reg);
     }
 
+    // Dynamic DNS — WAN address detection and provider updates (Background).
+    // Installs configured through CF_* in .env only get their record migrated.
+    let ddns_config = if env.ddns_config_path.exists() {
+        match hr_ddns::DdnsConfig::load_from_file(&env.ddns_config_path) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Failed to load DDNS config: {}", e);
+                hr_ddns::DdnsConfig::default()
+            }
+        }
+    } else {
+        let config = hr_ddns::DdnsConfig::from_env(&env);
+        if config.enabled
+            && let Err(e) = config.save_to_file(&env.ddns_config_path)
+        {
+            warn!("Failed to save migrated DDNS config: {}", e);
+        }
+        config
+    };
+    let ddns = Arc::new(hr_ddns::Ddns::new(ddns_config));
+    {
+        let ddns_c = ddns.clone();
+        let events_c = events.clone();
+        let relay_rx = cloud_relay_enabled_rx.clone();
+        let prefix_rx_c = prefix_rx.clone();
+        let data_dir = env.data_dir.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("ddns", ServicePriority::Background, reg, move || {
+            let ddns = ddns_c.clone();
+            let events = events_c.clone();
+            let relay = relay_rx.clone();
+            let prefix = prefix_rx_c.clone();
+            let data_dir = data_dir.clone();
+            async move { hr_ddns::service::run_ddns(ddns, events, relay, prefix, data_dir).await }
+        });
+    }
+
+    // Network device inventory — ARP/NDP tables, DHCP leases, active scans (Background).
+    let scanner_config = match hr_scanner::ScannerConfig::load_from_file(&env.scanner_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load scanner config: {}", e);
+            hr_scanner::ScannerConfig::default()
+        }
+    };
+    let scanner = Arc::new(hr_scanner::Scanner::new(
+        scanner_config,
+        PathBuf::from("/var/lib/server-dashboard/devices.json"),
+    ));
+    {
+        let scanner_c = scanner.clone();
+        let dhcp_c = dhcp_state.clone();
+        let events_c = events.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("scanner", ServicePriority::Background, reg, move || {
+            let scanner = scanner_c.clone();
+            let dhcp = dhcp_c.clone();
+            let events = events_c.clone();
+            async move { hr_scanner::service::run_scanner(scanner, dhcp, events).await }
+        });
+    }
+
     // ── Agent Registry ──────────────────────────────────────────────
 
     let registry_state_path =
@@ -506,6 +849,161 @@ async fn main() -> anyhow::Result<()> {
     // Provide ACME manager to registry for per-app certificate management
     registry.set_acme(acme.clone()).await;
 
+    // Power metering — smart plugs, SNMP UPSes/PDUs, MQTT plugs (Background)
+    let energy_config = match hr_energy::EnergyConfig::load_from_file(&env.energy_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load energy config: {}", e);
+            hr_energy::EnergyConfig::default()
+        }
+    };
+    let energy_store = match hr_energy::EnergyStore::open(&env.data_dir.join("energy.db")) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Failed to open energy database, keeping samples in memory: {}", e);
+            hr_energy::EnergyStore::open_in_memory()?
+        }
+    };
+    let energy = Arc::new(hr_energy::PowerMeters::new(energy_config, energy_store));
+    {
+        let energy_c = energy.clone();
+        let registry_c = registry.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("energy", ServicePriority::Background, reg, move || {
+            let energy = energy_c.clone();
+            let registry = registry_c.clone();
+            async move { hr_energy::service::run_energy(energy, registry).await }
+        });
+    }
+
+    // Outbound mail relay — apps authenticate with their agent token (Background)
+    let mail_config = match hr_mail::MailConfig::load_from_file(&env.mail_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load mail config: {}", e);
+            hr_mail::MailConfig::default()
+        }
+    };
+    let mail_store = match hr_mail::MailStore::open(&env.data_dir.join("mail.db")) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Failed to open mail database, keeping the queue in memory: {}", e);
+            hr_mail::MailStore::open_in_memory()?
+        }
+    };
+    let mail = Arc::new(hr_mail::Mail::new(
+        mail_config,
+        &env.base_domain,
+        env.data_dir.join("mail/dkim"),
+        mail_store,
+    ));
+    {
+        let mail_c = mail.clone();
+        let registry_c = registry.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("mail", ServicePriority::Background, reg, move || {
+            let mail = mail_c.clone();
+            let auth = registry_c.clone();
+            async move { hr_mail::server::run_mail(mail, auth).await }
+        });
+    }
+
+    // S3-compatible object storage for the apps, under the data directory (Background)
+    let s3_config = match hr_s3::S3Config::load_from_file(&env.s3_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load S3 config: {}", e);
+            hr_s3::S3Config::default()
+        }
+    };
+    let object_store = match hr_s3::ObjectStore::open(&env.data_dir.join("objects")) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Failed to open object store, keeping objects in a temporary directory: {}", e);
+            hr_s3::ObjectStore::open(&std::env::temp_dir().join("homeroute-objects"))?
+        }
+    };
+    let s3 = Arc::new(hr_s3::ObjectStorage::new(s3_config, object_store));
+    {
+        let s3_c = s3.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("s3", ServicePriority::Background, reg, move || {
+            let s3 = s3_c.clone();
+            async move { hr_s3::server::run_s3(s3).await }
+        });
+    }
+
+    // Scheduled jobs of the apps, run in their containers through the registry (Background)
+    let cron_store = match hr_cron::CronStore::open(&env.data_dir.join("cron.db")) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Failed to open cron database, keeping jobs in memory: {}", e);
+            hr_cron::CronStore::open_in_memory()?
+        }
+    };
+    let cron = Arc::new(hr_cron::Cron::new(cron_store, registry.clone()));
+    {
+        let cron_c = cron.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("cron", ServicePriority::Background, reg, move || {
+            let cron = cron_c.clone();
+            async move { hr_cron::scheduler::run_cron(cron).await }
+        });
+    }
+
+    // Message queues of the apps (Background: depth gauges, dead letter retention)
+    let queue_store = match hr_queue::QueueStore::open(&env.data_dir.join("queue.db")) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Failed to open queue database, keeping messages in memory: {}", e);
+            hr_queue::QueueStore::open_in_memory()?
+        }
+    };
+    let queues = Arc::new(hr_queue::Queues::new(queue_store));
+    {
+        let queues_c = queues.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("queues", ServicePriority::Background, reg, move || {
+            let queues = queues_c.clone();
+            async move { hr_queue::monitor::run_queues(queues).await }
+        });
+    }
+
+    // Pub/sub between the apps (topic grants, deliveries over the agent connections)
+    let pubsub_config = match hr_pubsub::PubSubConfig::load_from_file(&env.pubsub_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load pub/sub config: {}", e);
+            hr_pubsub::PubSubConfig::default()
+        }
+    };
+    let pubsub = Arc::new(hr_pubsub::PubSub::new(pubsub_config, registry.clone(), events.clone()));
+
+    // AI gateway of the apps (Background: request log retention)
+    let ai_config = match hr_ai::AiConfig::load_from_file(&env.ai_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load AI gateway config: {}", e);
+            hr_ai::AiConfig::default()
+        }
+    };
+    let ai_store = match hr_ai::AiStore::open(&env.data_dir.join("ai.db")) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Failed to open AI gateway database, keeping usage in memory: {}", e);
+            hr_ai::AiStore::open_in_memory()?
+        }
+    };
+    let ai = Arc::new(hr_ai::Gateway::new(ai_config, ai_store));
+    {
+        let ai_c = ai.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("ai", ServicePriority::Background, reg, move || {
+            let ai = ai_c.clone();
+            async move { hr_ai::maintenance::run_ai(ai).await }
+        });
+    }
+
     // Request per-app wildcard certificates for existing applications that don't have one yet
     {
         let apps = registry.list_applications().await;
@@ -567,6 +1065,15 @@ async fn main() -> anyhow::Result<()> {
     proxy_state.set_registry(registry.clone());
     proxy_state.set_events(events.clone());
 
+    {
+        let proxy_state_c = proxy_state.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("proxy-health", ServicePriority::Background, reg, move || {
+            let proxy_state = proxy_state_c.clone();
+            async move { hr_proxy::health::run_health_checker(proxy_state).await }
+        });
+    }
+
     // Populate app routes for all applications with IPv4 addresses
     {
         let apps = registry.list_applications().await;
@@ -586,6 +1093,9 @@ async fn main() -> anyhow::Result<()> {
                             service_type: route.service_type,
                             wake_page_enabled: app.wake_page_enabled,
                             local_only: app.frontend.local_only,
+                            extra_targets: Vec::new(),
+                            load_balancing: Default::default(),
+                            sticky: true,
                         },
                     );
                 }
@@ -606,13 +1116,40 @@ async fn main() -> anyhow::Result<()> {
         Arc::new(env.clone()),
         events.clone(),
         registry.clone(),
+        Arc::new(hr_api::templates::TemplateCatalog::new(
+            PathBuf::from("/var/lib/server-dashboard/templates.json"),
+            PathBuf::from("/opt/homeroute/data/templates"),
+        )),
     ));
 
     // ── Restore local containers that were running before reboot ──────
     container_manager.restore_local_containers().await;
 
+    // ── Process apps (supervised on the host, no container) ─────────
+    let process_manager = Arc::new(hr_api::process_manager::ProcessManager::new(
+        PathBuf::from("/var/lib/server-dashboard/processes.json"),
+        Arc::new(env.clone()),
+        registry.clone(),
+        proxy_state.clone(),
+    ));
+    process_manager.restore().await;
+
     // ── Management API (Important) ────────────────────────────────────
 
+    // API plugins ({data_dir}/plugins/*/plugin.json)
+    let plugins = Arc::new(hr_api::plugins::PluginRegistry::new(env.data_dir.join("plugins")));
+    plugins.reload();
+
+    // Home Assistant MQTT bridge
+    let mqtt_config = match hr_api::mqtt::MqttConfig::load_from_file(&env.mqtt_config_path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to load MQTT config: {}", e);
+            hr_api::mqtt::MqttConfig::default()
+        }
+    };
+    let mqtt_bridge = Arc::new(hr_api::mqtt::MqttBridge::new(mqtt_config));
+
     let api_state = hr_api::state::ApiState {
         auth: auth.clone(),
         acme: acme.clone(),
@@ -627,21 +1164,153 @@ async fn main() -> anyhow::Result<()> {
         proxy_config_path: env.proxy_config_path.clone(),
         reverseproxy_config_path: env.reverseproxy_config_path.clone(),
         service_registry: service_registry.clone(),
+        service_control_tx: Some(service_control_tx),
 
         registry: Some(registry.clone()),
         container_manager: Some(container_manager.clone()),
+        process_manager: Some(process_manager.clone()),
         migrations: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
+        deployments: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
+        drains: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
+        host_upgrades: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
+        join_codes: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
+        interrupted_transfers: Arc::new(tokio::sync::Mutex::new(std::collections::HashMap::new())),
         renames: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
         dataverse_schemas: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
         cloud_relay_status: cloud_relay_status.clone(),
         cloud_relay_enabled: cloud_relay_enabled_tx,
         cloud_relay_cmd_tx: Some(cloud_relay_cmd_tx),
+        streams: stream_proxy.clone(),
+        plugins,
+        mqtt: mqtt_bridge,
+        reflector,
+        syslog,
+        radius,
+        ntp,
+        flows,
+        bans,
+        mail,
+        s3,
+        cron,
+        queues,
+        pubsub,
+        tailscale,
+        ai,
+        qos,
+        relay_usage,
+        ddns,
+        scanner,
+        energy,
+        host_scheduler: Arc::new(hr_api::host_schedules::HostScheduler::new(PathBuf::from(
+            "/var/lib/server-dashboard/host-schedule-runs.json",
+        ))),
+        backups: Arc::new(hr_api::backups::BackupManager::new(
+            PathBuf::from("/var/lib/server-dashboard/backups.json"),
+            PathBuf::from("/opt/homeroute/data/backups"),
+        )),
+        doctor: Arc::new(hr_api::doctor::Doctor::new(PathBuf::from(
+            "/var/lib/server-dashboard/doctor.json",
+        ))),
+        speedtest: Arc::new(hr_api::speedtest::Speedtest::new(PathBuf::from(
+            "/var/lib/server-dashboard/speedtest.json",
+        ))),
+        captures: Arc::new(hr_api::capture::CaptureManager::new(PathBuf::from(
+            "/opt/homeroute/data/captures",
+        ))),
     };
 
+    {
+        let state = api_state.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("mqtt", ServicePriority::Background, reg, move || {
+            let state = state.clone();
+            async move { hr_api::mqtt::run_mqtt_bridge(state).await }
+        });
+    }
+
+    {
+        let state = api_state.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("ws-fanout", ServicePriority::Background, reg, move || {
+            let state = state.clone();
+            async move { hr_api::fanout::run_event_fanout(state).await }
+        });
+    }
+
+    {
+        let state = api_state.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("metrics-history", ServicePriority::Background, reg, move || {
+            let state = state.clone();
+            async move { hr_api::history::run_history_sampler(state).await }
+        });
+    }
+
+    {
+        let state = api_state.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("custom-domains", ServicePriority::Background, reg, move || {
+            let state = state.clone();
+            async move { hr_api::custom_domains::run_custom_domain_watch(state).await }
+        });
+    }
+
+    {
+        let state = api_state.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("host-scheduler", ServicePriority::Background, reg, move || {
+            let state = state.clone();
+            async move { hr_api::host_schedules::run_host_scheduler(state).await }
+        });
+    }
+
+    {
+        let state = api_state.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("backup-scheduler", ServicePriority::Background, reg, move || {
+            let state = state.clone();
+            async move { hr_api::backups::run_backup_scheduler(state).await }
+        });
+    }
+
+    {
+        let state = api_state.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("network-doctor", ServicePriority::Background, reg, move || {
+            let state = state.clone();
+            async move { hr_api::doctor::run_doctor_scheduler(state).await }
+        });
+    }
+
+    {
+        let state = api_state.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("speedtest", ServicePriority::Background, reg, move || {
+            let state = state.clone();
+            async move { hr_api::speedtest::run_speedtest_scheduler(state).await }
+        });
+    }
+
+    {
+        let state = api_state.clone();
+        let reg = supervisor.clone();
+        spawn_supervised("dns-route-records", ServicePriority::Background, reg, move || {
+            let state = state.clone();
+            async move { hr_api::route_records::run_route_records(state).await }
+        });
+    }
+
+    {
+        let reg = supervisor.clone();
+        spawn_supervised("leak-watch", ServicePriority::Background, reg, || async {
+            hr_api::leakwatch::run_leak_watch().await
+        });
+    }
+
     let api_router = hr_api::build_router(api_state);
     let api_port = env.api_port;
 
-    let reg = service_registry.clone();
+    let reg = supervisor.clone();
     spawn_supervised("api", ServicePriority::Important, reg, move || {
         let router = api_router.clone();
         let port = api_port;
@@ -674,33 +1343,6 @@ async fn main() -> anyhow::Result<()> {
         });
     }
 
-    // Sync DHCP leases → DNS lease store (every 10s)
-    {
-        let dhcp_state_c = dhcp_state.clone();
-        let lease_store_dns = lease_store_for_dns.clone();
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(std::time::Duration::from_secs(10)).await;
-                // Copy all leases from DHCP state to DNS lease store
-                let dhcp_read = dhcp_state_c.read().await;
-                let all_leases: Vec<_> = dhcp_read
-                    .lease_store
-                    .all_leases()
-                    .into_iter()
-                    .cloned()
-                    .collect();
-                drop(dhcp_read);
-
-                let mut dns_ls = lease_store_dns.write().await;
-                // Rebuild: clear and re-add all (cheap, ~100 entries max)
-                // We can't clear easily, so just add/update each lease
-                for lease in all_leases {
-                    dns_ls.add_lease(lease);
-                }
-            }
-        });
-    }
-
     // DNS cache purge (every 30s)
     {
         let dns_state_c = dns_state.clone();
@@ -751,6 +1393,8 @@ async fn main() -> anyhow::Result<()> {
     {
         let tls_mgr = tls_manager.clone();
         let registry_cert = registry.clone();
+        let global_wildcard = WildcardType::Global.domain_pattern(&env.base_domain);
+        let base_domain_cert = env.base_domain.clone();
         let mut cert_rx = events.cert_ready.subscribe();
         tokio::spawn(async move {
             while let Ok(event) = cert_rx.recv().await {
@@ -758,6 +1402,13 @@ async fn main() -> anyhow::Result<()> {
                 let key_path = std::path::Path::new(&event.key_path);
                 match tls_mgr.load_cert_from_files(cert_path, key_path) {
                     Ok(certified_key) => {
+                        if event.wildcard_domain == global_wildcard {
+                            // The global certificate also covers the apex and is the SNI fallback
+                            tls_mgr.add_cert(&base_domain_cert, certified_key.clone());
+                            if let Err(e) = tls_mgr.set_fallback_certificate_from_pem(&event.cert_path, &event.key_path) {
+                                warn!(error = %e, "Failed to refresh fallback certificate");
+                            }
+                        }
                         tls_mgr.add_cert(&event.wildcard_domain, certified_key);
                         info!(domain = %event.wildcard_domain, "Dynamically loaded new certificate");
                     }
@@ -829,7 +1480,8 @@ async fn main() -> anyhow::Result<()> {
                                     let domain = new_cert.wildcard_type.domain_pattern(&base_domain_renewal);
                                     let _ = events_renewal.cert_ready.send(CertReadyEvent {
                                         slug: match &new_cert.wildcard_type {
-                                            hr_acme::WildcardType::App { slug } => slug.clone(),
+                                            hr_acme::WildcardType::App { slug }
+                                            | hr_acme::WildcardType::Custom { slug, .. } => slug.clone(),
                                             _ => String::new(),
                                         },
                                         wildcard_domain: domain,
@@ -940,291 +1592,608 @@ async fn main() -> anyhow::Result<()> {
     );
     info!("  Hosts: status via host-agent WebSocket");
 
-    // Wait for shutdown signal
-    tokio::signal::ctrl_c().await?;
-    info!("Shutting down...");
-
-    // Save leases on shutdown
-    {
+    // Save leases once DHCP is stopped
+    supervisor.on_shutdown("dhcp", std::time::Duration::from_secs(5), async move {
         let s = dhcp_state.read().await;
         if let Err(e) = s.lease_store.save_to_file() {
             error!("Failed to save leases on shutdown: {}", e);
         } else {
             info!("Leases saved successfully");
         }
+    });
+
+    // Wait for shutdown signal (Ctrl+C or SIGTERM from systemd)
+    let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        _ = sigterm.recv() => {}
     }
+    info!("Shutting down...");
+    supervisor.shutdown().await;
 
     Ok(())
 }
 
 // ── Cloud Relay tunnel client ─────────────────────────────────────────
 
+/// Keepalive probe period on the tunnel control stream.
+const TUNNEL_KEEPALIVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+/// Unanswered probes before the tunnel is considered dead.
+const TUNNEL_KEEPALIVE_MISSES: u32 = 3;
+/// Period of the client certificate expiry check while connected.
+const TUNNEL_CERT_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(12 * 3600);
+/// Time allowed to reach one relay.
+const TUNNEL_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// A tunnel that stayed up this long resets the reconnect backoff.
+const TUNNEL_STABLE_AFTER: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Keepalive state of a tunnel session.
+struct TunnelHealth {
+    latency_us: std::sync::atomic::AtomicU64,
+    last_pong: std::sync::Mutex<std::time::Instant>,
+    active_streams: Arc<std::sync::atomic::AtomicU32>,
+}
+
+/// Counts a relayed stream for as long as it is alive.
+struct StreamGuard(Arc<std::sync::atomic::AtomicU32>);
+
+impl StreamGuard {
+    fn new(count: &Arc<std::sync::atomic::AtomicU32>) -> Self {
+        count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Self(count.clone())
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+/// Delay before reconnect attempt `failures`: exponential from 1s up to a
+/// minute, plus up to 50% jitter so homes behind a restarted relay do not
+/// all come back at once.
+fn tunnel_reconnect_delay(failures: u32) -> std::time::Duration {
+    use rand::Rng;
+    let base_ms = (1000u64 << failures.min(6)).min(60_000);
+    std::time::Duration::from_millis(base_ms + rand::rng().random_range(0..=base_ms / 2))
+}
+
+/// Connect to the first reachable relay of `relay_hosts` (`host` or
+/// `host:port`), in order. Returns the connection and the host used.
+async fn connect_relay(
+    endpoint: &quinn::Endpoint,
+    relay_hosts: &[String],
+    default_port: u16,
+) -> anyhow::Result<(quinn::Connection, String)> {
+    let mut errors = Vec::new();
+    for relay in relay_hosts {
+        let (host, port) = match relay.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') => match port.parse() {
+                Ok(port) => (host, port),
+                Err(_) => (relay.as_str(), default_port),
+            },
+            _ => (relay.as_str(), default_port),
+        };
+        info!(host = %host, port, "Connecting QUIC tunnel to cloud relay...");
+        let attempt = async {
+            // Resolve hostname to IP (SocketAddr::parse only accepts IPs, not hostnames)
+            let server_addr = tokio::net::lookup_host(format!("{}:{}", host, port))
+                .await?
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Failed to resolve relay host: {}", host))?;
+            anyhow::Ok(endpoint.connect(server_addr, host)?.await?)
+        };
+        match tokio::time::timeout(TUNNEL_CONNECT_TIMEOUT, attempt).await {
+            Ok(Ok(connection)) => return Ok((connection, relay.clone())),
+            Ok(Err(e)) => errors.push(format!("{}: {}", relay, e)),
+            Err(_) => errors.push(format!("{}: timed out", relay)),
+        }
+        warn!("Cloud relay {} unreachable: {}", relay, errors.last().unwrap());
+    }
+    anyhow::bail!("No relay reachable ({})", errors.join("; "))
+}
+
 async fn run_tunnel_client(
-    relay_host: &str,
+    relay_hosts: &[String],
     relay_port: u16,
     data_dir: &std::path::Path,
     proxy_state: Arc<ProxyState>,
     tls_config: Arc<rustls::ServerConfig>,
+    streams: hr_stream::SharedStreamProxy,
+    usage: hr_tunnel::usage::SharedRelayUsage,
     events: Arc<EventBus>,
     cmd_rx: Arc<tokio::sync::Mutex<tokio::sync::mpsc::Receiver<hr_common::events::CloudRelayCommand>>>,
     mut enabled_rx: tokio::sync::watch::Receiver<bool>,
     status_handle: Arc<tokio::sync::RwLock<Option<hr_api::state::CloudRelayInfo>>>,
 ) -> anyhow::Result<()> {
     use hr_common::events::{CloudRelayCommand, CloudRelayEvent, CloudRelayStatus};
-    use hr_tunnel::protocol::StreamHeader;
+    use hr_tunnel::protocol::{ControlMessage, UdpDatagram};
     use hyper::server::conn::http1;
     use hyper::service::service_fn;
     use hyper_util::rt::TokioIo;
+    use std::sync::atomic::Ordering;
     use tokio_rustls::TlsAcceptor;
 
     // Helper: update shared status for API
     let update_status = |status_handle: &Arc<tokio::sync::RwLock<Option<hr_api::state::CloudRelayInfo>>>,
                          status: CloudRelayStatus,
-                         vps_ipv4: Option<String>| {
+                         vps_ipv4: Option<String>,
+                         relay_host: Option<String>| {
         let handle = status_handle.clone();
         async move {
             *handle.write().await = Some(hr_api::state::CloudRelayInfo {
                 status,
                 vps_ipv4,
+                relay_host,
                 latency_ms: None,
                 active_streams: None,
+                relay_cert_sha256: None,
             });
         }
     };
 
-    // ── Wait until relay is enabled ──────────────────────────────────
+    let tls_acceptor = TlsAcceptor::from(tls_config);
+
+    // Lock the command receiver for the lifetime of the client
+    let mut cmd_rx = cmd_rx.lock().await;
+
+    // Consecutive failed connections, for the reconnect backoff
+    let mut failures: u32 = 0;
+
     loop {
-        if *enabled_rx.borrow_and_update() {
-            break;
+        // ── Wait until relay is enabled ──────────────────────────────
+        loop {
+            if *enabled_rx.borrow_and_update() {
+                break;
+            }
+            info!("Cloud relay disabled, tunnel waiting for enable signal...");
+            update_status(&status_handle, CloudRelayStatus::Disconnected, None, None).await;
+            let _ = events.cloud_relay.send(CloudRelayEvent {
+                status: CloudRelayStatus::Disconnected,
+                latency_ms: None,
+                active_streams: None,
+                message: Some("Waiting for enable".to_string()),
+            });
+            enabled_rx
+                .changed()
+                .await
+                .map_err(|_| anyhow::anyhow!("Enabled watch channel closed"))?;
         }
-        info!("Cloud relay disabled, tunnel waiting for enable signal...");
-        update_status(&status_handle, CloudRelayStatus::Disconnected, None).await;
-        let _ = events.cloud_relay.send(CloudRelayEvent {
-            status: CloudRelayStatus::Disconnected,
-            latency_ms: None,
-            active_streams: None,
-            message: Some("Waiting for enable".to_string()),
-        });
-        enabled_rx
-            .changed()
-            .await
-            .map_err(|_| anyhow::anyhow!("Enabled watch channel closed"))?;
-    }
 
-    // ── Connect to VPS ───────────────────────────────────────────────
-    let relay_dir = data_dir.join("cloud-relay");
+        // ── Connect to VPS ───────────────────────────────────────────
+        let relay_dir = data_dir.join("cloud-relay");
 
-    // Load mTLS client certificates
-    let ca_pem = tokio::fs::read(relay_dir.join("ca.pem")).await?;
-    let client_pem = tokio::fs::read(relay_dir.join("client.pem")).await?;
-    let client_key_pem = tokio::fs::read(relay_dir.join("client-key.pem")).await?;
-
-    let client_config =
-        hr_tunnel::quic::build_client_config(&client_pem, &client_key_pem, &ca_pem)?;
+        // Renew the client certificate before it expires
+        if hr_tunnel::certs::client_cert_due(&relay_dir) {
+            match hr_tunnel::certs::renew_client_cert(&relay_dir) {
+                Ok(_) => info!("Cloud relay client certificate renewed"),
+                Err(e) => warn!("Cloud relay client certificate renewal failed: {}", e),
+            }
+        }
 
-    // Create QUIC endpoint (bind ephemeral port)
-    let mut endpoint = quinn::Endpoint::client("[::]:0".parse()?)?;
-    endpoint.set_default_client_config(client_config);
+        // Load mTLS client certificates and the relay pins
+        let ca_pem = tokio::fs::read(relay_dir.join("ca.pem")).await?;
+        let client_pem = tokio::fs::read(relay_dir.join("client.pem")).await?;
+        let client_key_pem = tokio::fs::read(relay_dir.join("client-key.pem")).await?;
+        let pins = hr_tunnel::certs::RelayPins::load(&relay_dir)
+            .map(|p| p.fingerprints())
+            .unwrap_or_else(|e| {
+                warn!("Invalid relay pins, ignoring them: {}", e);
+                Vec::new()
+            });
 
-    // Resolve hostname to IP (SocketAddr::parse only accepts IPs, not hostnames)
-    let server_addr = tokio::net::lookup_host(format!("{}:{}", relay_host, relay_port))
-        .await?
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("Failed to resolve relay host: {}", relay_host))?;
-    let server_name = relay_host.to_string();
+        let client_config =
+            hr_tunnel::quic::build_pinned_client_config(&client_pem, &client_key_pem, &ca_pem, &pins)?;
 
-    info!(host = %relay_host, port = relay_port, "Connecting QUIC tunnel to cloud relay...");
+        // Create QUIC endpoint (bind ephemeral port)
+        let mut endpoint = quinn::Endpoint::client("[::]:0".parse()?)?;
+        endpoint.set_default_client_config(client_config);
 
-    let _ = events.cloud_relay.send(CloudRelayEvent {
-        status: CloudRelayStatus::Reconnecting,
-        latency_ms: None,
-        active_streams: None,
-        message: Some(format!("Connecting to {}:{}", relay_host, relay_port)),
-    });
+        let _ = events.cloud_relay.send(CloudRelayEvent {
+            status: CloudRelayStatus::Reconnecting,
+            latency_ms: None,
+            active_streams: None,
+            message: Some(format!("Connecting to {}", relay_hosts.join(", "))),
+        });
 
-    // Connect to VPS
-    let connection = endpoint.connect(server_addr, &server_name)?.await?;
+        let connected = tokio::select! {
+            result = connect_relay(&endpoint, relay_hosts, relay_port) => result,
+            _ = enabled_rx.changed() => continue,
+        };
+        let (connection, relay_host) = match connected {
+            Ok(connected) => connected,
+            Err(e) => {
+                let delay = tunnel_reconnect_delay(failures);
+                failures = failures.saturating_add(1);
+                warn!("Cloud relay connection failed, retrying in {:?}: {}", delay, e);
+                update_status(&status_handle, CloudRelayStatus::Reconnecting, None, None).await;
+                let _ = events.cloud_relay.send(CloudRelayEvent {
+                    status: CloudRelayStatus::Reconnecting,
+                    latency_ms: None,
+                    active_streams: None,
+                    message: Some(format!("{} (retry in {}s)", e, delay.as_secs())),
+                });
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = enabled_rx.changed() => {}
+                }
+                continue;
+            }
+        };
 
-    info!("QUIC tunnel connected to {}", connection.remote_address());
+        info!("QUIC tunnel connected to {} ({})", relay_host, connection.remote_address());
+        if relay_hosts.first() != Some(&relay_host) {
+            warn!("Cloud relay failover: using {}", relay_host);
+        }
 
-    // Read VPS IPv4 from config for status
-    let vps_ipv4 = load_relay_vps_ipv4(data_dir);
-    update_status(&status_handle, CloudRelayStatus::Connected, vps_ipv4).await;
-    let _ = events.cloud_relay.send(CloudRelayEvent {
-        status: CloudRelayStatus::Connected,
-        latency_ms: None,
-        active_streams: None,
-        message: Some("Tunnel connected".to_string()),
-    });
+        // Read VPS IPv4 from config for status
+        let vps_ipv4 = load_relay_vps_ipv4(data_dir);
+        update_status(&status_handle, CloudRelayStatus::Connected, vps_ipv4, Some(relay_host.clone())).await;
+        if let Some(info) = status_handle.write().await.as_mut() {
+            info.relay_cert_sha256 = hr_tunnel::quic::peer_certificate_sha256(&connection);
+        }
+        let _ = events.cloud_relay.send(CloudRelayEvent {
+            status: CloudRelayStatus::Connected,
+            latency_ms: None,
+            active_streams: None,
+            message: Some(format!("Tunnel connected to {}", relay_host)),
+        });
 
-    let tls_acceptor = TlsAcceptor::from(tls_config);
+        // A relay provisioned with a downloaded binary gets the local build
+        let push_marker = relay_dir.join(hr_tunnel::provision::PUSH_BINARY_MARKER);
+        if push_marker.exists() {
+            match tokio::fs::read(hr_tunnel::provision::LOCAL_RELAY_BINARY).await {
+                Ok(binary) => {
+                    let sha256 = hr_tunnel::crypto::sha256_hex(&binary);
+                    match push_binary_update(&connection, &binary, &sha256).await {
+                        Ok(message) => {
+                            info!("Provisioned relay updated to the local build: {}", message);
+                            let _ = tokio::fs::remove_file(&push_marker).await;
+                        }
+                        Err(e) => warn!("Failed to push the relay binary: {}", e),
+                    }
+                }
+                Err(_) => {
+                    let _ = tokio::fs::remove_file(&push_marker).await;
+                }
+            }
+        }
 
-    // Lock the command receiver for this tunnel session
-    let mut cmd_rx = cmd_rx.lock().await;
+        let session_start = std::time::Instant::now();
+        let health = Arc::new(TunnelHealth {
+            latency_us: std::sync::atomic::AtomicU64::new(0),
+            last_pong: std::sync::Mutex::new(session_start),
+            active_streams: Arc::new(std::sync::atomic::AtomicU32::new(0)),
+        });
 
-    // Accept incoming bidirectional streams (each = one TCP connection from the internet)
-    loop {
-        let (mut quic_send, mut quic_recv) = tokio::select! {
-            result = connection.accept_bi() => {
-                match result {
-                    Ok(streams) => streams,
-                    Err(e) => {
-                        warn!("QUIC tunnel closed: {}", e);
-                        update_status(&status_handle, CloudRelayStatus::Disconnected, None).await;
-                        let _ = events.cloud_relay.send(CloudRelayEvent {
-                            status: CloudRelayStatus::Disconnected,
-                            latency_ms: None,
-                            active_streams: None,
-                            message: Some(format!("Tunnel closed: {}", e)),
-                        });
-                        return Err(e.into());
+        // Pongs come back on uni streams opened by the relay
+        let pong_conn = connection.clone();
+        let pong_health = health.clone();
+        let pong_task = tokio::spawn(async move {
+            while let Ok(mut recv) = pong_conn.accept_uni().await {
+                match hr_tunnel::quic::read_control_message(&mut recv).await {
+                    Ok(ControlMessage::Pong { ts, .. }) => {
+                        let rtt = unix_micros().saturating_sub(ts);
+                        pong_health.latency_us.store(rtt, Ordering::Relaxed);
+                        *pong_health.last_pong.lock().unwrap() = std::time::Instant::now();
                     }
+                    Ok(msg) => tracing::debug!("Received control message: {:?}", msg),
+                    Err(e) => tracing::debug!("Invalid control message: {}", e),
                 }
             }
-            cmd = cmd_rx.recv() => {
-                match cmd {
-                    Some(CloudRelayCommand::PushBinaryUpdate { binary_data, sha256, response_tx }) => {
-                        let result = push_binary_update(&connection, &binary_data, &sha256).await;
-                        let _ = response_tx.send(result);
+        });
+
+        // UDP port forwards exposed on the relay come as QUIC datagrams
+        let udp_conn = connection.clone();
+        let udp_streams = streams.clone();
+        let udp_bans = proxy_state.bans.clone();
+        let udp_usage = usage.clone();
+        let udp_task = tokio::spawn(async move {
+            let (replies_tx, mut replies_rx) = tokio::sync::mpsc::channel(1024);
+            let mut sessions = hr_stream::RelayUdpSessions::new(udp_streams, replies_tx);
+            // Relay port of each flow, for the accounting
+            let mut flow_ports: std::collections::HashMap<u32, u16> = std::collections::HashMap::new();
+            loop {
+                tokio::select! {
+                    datagram = udp_conn.read_datagram() => {
+                        let Ok(data) = datagram else {
+                            break;
+                        };
+                        let datagram = match UdpDatagram::decode(data) {
+                            Ok(d) => d,
+                            Err(e) => {
+                                tracing::debug!("Invalid UDP datagram: {}", e);
+                                continue;
+                            }
+                        };
+                        let flow = datagram.flow.map(|f| (f.port, f.client));
+                        if let (Some((_, client)), Some(bans)) = (flow, &udp_bans)
+                            && bans.is_banned(client.ip())
+                        {
+                            continue;
+                        }
+                        if udp_usage.cap_reached() {
+                            continue;
+                        }
+                        if let Some((port, _)) = flow {
+                            // The relay names the flow in every datagram: forgotten ports come back
+                            if flow_ports.len() >= 4096 {
+                                flow_ports.clear();
+                            }
+                            flow_ports.insert(datagram.flow_id, port);
+                        }
+                        if let Some(port) = flow_ports.get(&datagram.flow_id) {
+                            udp_usage.record(&format!("udp:{}", port), datagram.payload.len() as u64, 0);
+                        }
+                        sessions.forward(datagram.flow_id, flow, &datagram.payload).await;
                     }
-                    None => {
-                        // Channel closed, continue accepting streams
+                    Some((flow_id, payload)) = replies_rx.recv() => {
+                        let len = payload.len() as u64;
+                        let reply = UdpDatagram { flow_id, flow: None, payload: payload.into() };
+                        match udp_conn.send_datagram(reply.encode()) {
+                            Ok(()) => {
+                                if let Some(port) = flow_ports.get(&flow_id) {
+                                    udp_usage.record(&format!("udp:{}", port), 0, len);
+                                }
+                            }
+                            Err(e) => tracing::debug!("UDP reply dropped: {}", e),
+                        }
                     }
                 }
-                continue;
             }
-            _ = enabled_rx.changed() => {
-                if !*enabled_rx.borrow() {
-                    info!("Cloud relay disabled by user, closing tunnel");
-                    connection.close(0u32.into(), b"disabled");
-                    update_status(&status_handle, CloudRelayStatus::Disconnected, None).await;
+        });
+
+        let mut keepalive = tokio::time::interval(TUNNEL_KEEPALIVE_INTERVAL);
+        let mut renew_check = tokio::time::interval(TUNNEL_CERT_CHECK_INTERVAL);
+        renew_check.tick().await;
+
+        // Accept incoming bidirectional streams (each = one TCP connection from the internet)
+        let reason = loop {
+            let (mut quic_send, mut quic_recv) = tokio::select! {
+                result = connection.accept_bi() => {
+                    match result {
+                        Ok(streams) => streams,
+                        Err(e) => {
+                            warn!("QUIC tunnel closed: {}", e);
+                            break format!("Tunnel closed: {}", e);
+                        }
+                    }
+                }
+                _ = keepalive.tick() => {
+                    let silent = health.last_pong.lock().unwrap().elapsed();
+                    if silent > TUNNEL_KEEPALIVE_INTERVAL * TUNNEL_KEEPALIVE_MISSES {
+                        warn!("Cloud relay {} did not answer keepalives for {:?}, reconnecting", relay_host, silent);
+                        connection.close(0u32.into(), b"keepalive timeout");
+                        break "Keepalive timeout".to_string();
+                    }
+                    let ping = ControlMessage::Ping { ts: unix_micros() };
+                    if let Err(e) = hr_tunnel::quic::send_control_message(&connection, &ping).await {
+                        tracing::debug!("Failed to send keepalive: {}", e);
+                    }
+
+                    let latency_us = health.latency_us.load(Ordering::Relaxed);
+                    let latency_ms = (latency_us > 0).then(|| latency_us.div_ceil(1000));
+                    let active_streams = Some(health.active_streams.load(Ordering::Relaxed));
+                    if let Some(info) = status_handle.write().await.as_mut() {
+                        info.latency_ms = latency_ms;
+                        info.active_streams = active_streams;
+                    }
                     let _ = events.cloud_relay.send(CloudRelayEvent {
-                        status: CloudRelayStatus::Disconnected,
-                        latency_ms: None,
-                        active_streams: None,
-                        message: Some("Tunnel disabled by user".to_string()),
+                        status: CloudRelayStatus::Connected,
+                        latency_ms,
+                        active_streams,
+                        message: None,
                     });
-                    // Return error so supervisor restarts — will block at wait-for-enable
-                    anyhow::bail!("Cloud relay disabled");
+                    continue;
+                }
+                cmd = cmd_rx.recv() => {
+                    match cmd {
+                        Some(CloudRelayCommand::PushBinaryUpdate { binary_data, sha256, response_tx }) => {
+                            let result = push_binary_update(&connection, &binary_data, &sha256).await;
+                            let _ = response_tx.send(result);
+                        }
+                        Some(CloudRelayCommand::Reconnect { reason }) => {
+                            info!("Cloud relay reconnecting: {}", reason);
+                            connection.close(0u32.into(), b"reconnect");
+                            break reason;
+                        }
+                        None => {
+                            // Channel closed, continue accepting streams
+                        }
+                    }
+                    continue;
+                }
+                _ = renew_check.tick() => {
+                    if !hr_tunnel::certs::client_cert_due(&relay_dir) {
+                        continue;
+                    }
+                    match hr_tunnel::certs::renew_client_cert(&relay_dir) {
+                        Ok(_) => {
+                            info!("Cloud relay client certificate renewed, reconnecting");
+                            connection.close(0u32.into(), b"reconnect");
+                            break "Client certificate renewed".to_string();
+                        }
+                        Err(e) => {
+                            warn!("Cloud relay client certificate renewal failed: {}", e);
+                            continue;
+                        }
+                    }
                 }
+                _ = enabled_rx.changed() => {
+                    if !*enabled_rx.borrow() {
+                        info!("Cloud relay disabled by user, closing tunnel");
+                        connection.close(0u32.into(), b"disabled");
+                        break "Tunnel disabled by user".to_string();
+                    }
+                    continue;
+                }
+            };
+
+            if !usage.admit() {
+                // Monthly relay cap reached
+                let _ = quic_send.reset(0u32.into());
+                let _ = quic_recv.stop(0u32.into());
                 continue;
             }
-        };
 
-        let proxy_state = proxy_state.clone();
-        let acceptor = tls_acceptor.clone();
+            let proxy_state = proxy_state.clone();
+            let acceptor = tls_acceptor.clone();
+            let stream_guard = StreamGuard::new(&health.active_streams);
+            let meter = Arc::new(usage.meter());
 
-        tokio::spawn(async move {
-            // Read the StreamHeader to get client IP
-            let mut header_buf = vec![0u8; 26]; // max: 1 + 1 + 16 + 8 = 26
-            let n = match quic_recv.read(&mut header_buf).await {
-                Ok(Some(n)) => n,
-                Ok(None) => return,
-                Err(e) => {
-                    tracing::debug!("Failed to read stream header: {}", e);
-                    return;
-                }
-            };
+            hr_common::selfmon::spawn("cloud-relay", async move {
+                let _stream_guard = stream_guard;
+                // Read the StreamHeader to get client IP
+                let header = match hr_tunnel::quic::read_stream_header(&mut quic_recv).await {
+                    Ok(h) => h,
+                    Err(e) => {
+                        tracing::debug!("Invalid stream header: {}", e);
+                        return;
+                    }
+                };
 
-            let mut cursor = &header_buf[..n];
-            let header = match StreamHeader::decode(&mut cursor) {
-                Ok(h) => h,
-                Err(e) => {
-                    tracing::debug!("Invalid stream header: {}", e);
+                let client_ip = header.client_ip;
+                let upstream = header.proxy_ip.map(hr_proxy::RelayUpstream);
+                if proxy_state.bans.as_ref().is_some_and(|b| b.is_banned(client_ip)) {
                     return;
                 }
-            };
-
-            let client_ip = header.client_ip;
 
-            // Bridge QUIC streams to a single AsyncRead+AsyncWrite via duplex
-            let (quic_side, tls_side) = tokio::io::duplex(256 * 1024);
-            let (quic_reader, mut quic_writer) = tokio::io::split(quic_side);
-
-            // Task: QUIC recv → quic_writer → tls_side (readable by TLS acceptor)
-            tokio::spawn(async move {
-                use tokio::io::AsyncWriteExt;
-                let mut buf = vec![0u8; 65536];
-                loop {
-                    match quic_recv.read(&mut buf).await {
-                        Ok(Some(n)) => {
-                            if quic_writer.write_all(&buf[..n]).await.is_err() {
-                                break;
+                // Bridge QUIC streams to a single AsyncRead+AsyncWrite via duplex
+                let (quic_side, tls_side) = tokio::io::duplex(256 * 1024);
+                let (quic_reader, mut quic_writer) = tokio::io::split(quic_side);
+
+                // Task: QUIC recv → quic_writer → tls_side (readable by TLS acceptor)
+                let in_meter = meter.clone();
+                tokio::spawn(async move {
+                    use tokio::io::AsyncWriteExt;
+                    let mut pacer = in_meter.pacer();
+                    let mut buf = vec![0u8; 65536];
+                    loop {
+                        match quic_recv.read(&mut buf).await {
+                            Ok(Some(n)) => {
+                                in_meter.add_in(n as u64);
+                                if quic_writer.write_all(&buf[..n]).await.is_err() {
+                                    break;
+                                }
+                                pacer.pace(n).await;
                             }
+                            _ => break,
                         }
-                        _ => break,
                     }
-                }
-            });
-
-            // Task: quic_reader (data written by TLS) → QUIC send
-            tokio::spawn(async move {
-                use tokio::io::AsyncReadExt;
-                let mut reader = quic_reader;
-                let mut buf = vec![0u8; 65536];
-                loop {
-                    match reader.read(&mut buf).await {
-                        Ok(0) => break,
-                        Ok(n) => {
-                            if quic_send.write_all(&buf[..n]).await.is_err() {
-                                break;
+                });
+
+                // Task: quic_reader (data written by TLS) → QUIC send
+                let out_meter = meter.clone();
+                tokio::spawn(async move {
+                    use tokio::io::AsyncReadExt;
+                    let mut reader = quic_reader;
+                    let mut pacer = out_meter.pacer();
+                    let mut buf = vec![0u8; 65536];
+                    loop {
+                        match reader.read(&mut buf).await {
+                            Ok(0) => break,
+                            Ok(n) => {
+                                if quic_send.write_all(&buf[..n]).await.is_err() {
+                                    break;
+                                }
+                                out_meter.add_out(n as u64);
+                                pacer.pace(n).await;
                             }
+                            Err(_) => break,
                         }
-                        Err(_) => break,
                     }
-                }
-            });
+                });
 
-            // TLS termination on the duplex stream (Cloudflare → on-prem handshake)
-            let tls_stream = match acceptor.accept(tls_side).await {
-                Ok(s) => s,
-                Err(e) => {
-                    tracing::debug!(
-                        "TLS handshake failed from relay (client {}): {}",
-                        client_ip,
-                        e
-                    );
-                    return;
-                }
-            };
+                // TLS termination on the duplex stream (Cloudflare → on-prem handshake)
+                let tls_stream = match acceptor.accept(tls_side).await {
+                    Ok(s) => s,
+                    Err(e) => {
+                        tracing::debug!(
+                            "TLS handshake failed from relay (client {}): {}",
+                            client_ip,
+                            e
+                        );
+                        tls_handshake_failed("relay");
+                        if let Some(bans) = &proxy_state.bans {
+                            bans.report(client_ip, hr_firewall::Offense::TlsHandshake);
+                        }
+                        return;
+                    }
+                };
+                let _active = active_connections("relay").track();
+                meter.set_route(tls_stream.get_ref().1.server_name().unwrap_or(hr_tunnel::usage::UNKNOWN_ROUTE));
+
+                let io = TokioIo::new(tls_stream);
+                let service = service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
+                    let state = proxy_state.clone();
+                    async move {
+                        let (parts, body) = req.into_parts();
+                        let mut req =
+                            axum::extract::Request::from_parts(parts, axum::body::Body::new(body));
+                        if let Some(upstream) = upstream {
+                            req.extensions_mut().insert(upstream);
+                        }
+                        let resp = hr_proxy::proxy_handler(state, client_ip, req).await;
+                        Ok::<_, std::convert::Infallible>(axum::response::IntoResponse::into_response(
+                            resp,
+                        ))
+                    }
+                });
 
-            let io = TokioIo::new(tls_stream);
-            let service = service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
-                let state = proxy_state.clone();
-                async move {
-                    let (parts, body) = req.into_parts();
-                    let req =
-                        axum::extract::Request::from_parts(parts, axum::body::Body::new(body));
-                    let resp = hr_proxy::proxy_handler(state, client_ip, req).await;
-                    Ok::<_, std::convert::Infallible>(axum::response::IntoResponse::into_response(
-                        resp,
-                    ))
+                if let Err(e) = http1::Builder::new()
+                    .preserve_header_case(true)
+                    .title_case_headers(true)
+                    .serve_connection(io, service)
+                    .with_upgrades()
+                    .await
+                {
+                    let msg = e.to_string();
+                    if !msg.contains("connection closed")
+                        && !msg.contains("not connected")
+                        && !msg.contains("connection reset")
+                    {
+                        tracing::debug!(
+                            "HTTP/1 relay connection error (client {}): {}",
+                            client_ip,
+                            e
+                        );
+                    }
                 }
             });
+        };
+        pong_task.abort();
+        udp_task.abort();
 
-            if let Err(e) = http1::Builder::new()
-                .preserve_header_case(true)
-                .title_case_headers(true)
-                .serve_connection(io, service)
-                .with_upgrades()
-                .await
-            {
-                let msg = e.to_string();
-                if !msg.contains("connection closed")
-                    && !msg.contains("not connected")
-                    && !msg.contains("connection reset")
-                {
-                    tracing::debug!(
-                        "HTTP/1 relay connection error (client {}): {}",
-                        client_ip,
-                        e
-                    );
-                }
-            }
+        update_status(&status_handle, CloudRelayStatus::Disconnected, None, None).await;
+        let _ = events.cloud_relay.send(CloudRelayEvent {
+            status: CloudRelayStatus::Disconnected,
+            latency_ms: None,
+            active_streams: None,
+            message: Some(reason),
         });
+
+        // A tunnel dropping right after connecting backs off like a failed connection
+        if session_start.elapsed() >= TUNNEL_STABLE_AFTER {
+            failures = 0;
+        } else if *enabled_rx.borrow() {
+            let delay = tunnel_reconnect_delay(failures);
+            failures = failures.saturating_add(1);
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = enabled_rx.changed() => {}
+            }
+        }
     }
 }
 
+fn unix_micros() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
+
 /// Push a binary update to the VPS via a QUIC unidirectional stream.
 /// Format: [4-byte length][JSON ControlMessage::BinaryUpdate][raw binary bytes]
 async fn push_binary_update(
@@ -1301,20 +2270,29 @@ async fn run_https_server(
             }
         };
 
+        let client_ip = remote_addr.ip();
+        if proxy_state.bans.as_ref().is_some_and(|b| b.is_banned(client_ip)) {
+            continue;
+        }
+
         let acceptor = acceptor.clone();
         let proxy_state = proxy_state.clone();
-        let client_ip = remote_addr.ip();
 
-        tokio::spawn(async move {
+        hr_common::selfmon::spawn("proxy", async move {
             // TLS termination — all routing is handled at HTTP level by hr-proxy
             let tls_stream = match acceptor.accept(tcp_stream).await {
                 Ok(s) => s,
                 Err(e) => {
                     // TLS handshake failures are common (scanners, invalid SNI)
                     tracing::debug!("TLS handshake failed from {}: {}", remote_addr, e);
+                    tls_handshake_failed("https");
+                    if let Some(bans) = &proxy_state.bans {
+                        bans.report(client_ip, hr_firewall::Offense::TlsHandshake);
+                    }
                     return;
                 }
             };
+            let _active = active_connections("https").track();
 
             let io = TokioIo::new(tls_stream);
             let service = service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
@@ -1347,9 +2325,27 @@ async fn run_https_server(
     }
 }
 
+fn tls_handshake_failed(listener: &str) {
+    hr_common::metrics::registry()
+        .counter(
+            "homeroute_tls_handshake_failures_total",
+            "Failed TLS handshakes on the proxy listeners.",
+            &[("listener", listener)],
+        )
+        .inc();
+}
+
+fn active_connections(listener: &str) -> hr_common::metrics::Gauge {
+    hr_common::metrics::registry().gauge(
+        "homeroute_http_active_connections",
+        "Open client connections on the proxy listeners.",
+        &[("listener", listener)],
+    )
+}
+
 // ── HTTP redirect server ───────────────────────────────────────────────
 
-async fn run_http_redirect(port: u16, _base_domain: &str) -> anyhow::Result<()> {
+async fn run_http_redirect(port: u16, acme: Arc<AcmeManager>, proxy_state: Arc<ProxyState>) -> anyhow::Result<()> {
     use hyper::server::conn::http1;
     use hyper::service::service_fn;
     use hyper_util::rt::TokioIo;
@@ -1360,7 +2356,7 @@ async fn run_http_redirect(port: u16, _base_domain: &str) -> anyhow::Result<()>
     info!("HTTP redirect listening on {}", addr);
 
     loop {
-        let (stream, _remote) = match listener.accept().await {
+        let (stream, remote) = match listener.accept().await {
             Ok(r) => r,
             Err(e) => {
                 warn!("HTTP accept error: {}", e);
@@ -1369,24 +2365,55 @@ async fn run_http_redirect(port: u16, _base_domain: &str) -> anyhow::Result<()>
         };
 
         let io = TokioIo::new(stream);
+        let acme = acme.clone();
+        let proxy_state = proxy_state.clone();
 
-        tokio::spawn(async move {
-            let service = service_fn(|req: hyper::Request<hyper::body::Incoming>| async move {
-                let host = req
-                    .headers()
-                    .get("host")
-                    .and_then(|v| v.to_str().ok())
-                    .unwrap_or("localhost");
-                let path = req.uri().path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
-                let location = format!("https://{}{}", host, path);
-
-                Ok::<_, std::convert::Infallible>(
-                    hyper::Response::builder()
-                        .status(301)
-                        .header("Location", &location)
-                        .body(http_body_util::Empty::<hyper::body::Bytes>::new())
-                        .unwrap(),
-                )
+        hr_common::selfmon::spawn("proxy", async move {
+            let service = service_fn(|req: hyper::Request<hyper::body::Incoming>| {
+                let acme = acme.clone();
+                let proxy_state = proxy_state.clone();
+                async move {
+                    // HTTP-01 challenges of custom domain certificates
+                    if let Some(token) = req.uri().path().strip_prefix("/.well-known/acme-challenge/")
+                        && let Some(key_auth) = acme.http01_response(token)
+                    {
+                        return Ok::<_, std::convert::Infallible>(
+                            hyper::Response::builder()
+                                .status(200)
+                                .header("Content-Type", "text/plain")
+                                .body(http_body_util::Full::new(hyper::body::Bytes::from(key_auth)))
+                                .unwrap(),
+                        );
+                    }
+
+                    let host = req
+                        .headers()
+                        .get("host")
+                        .and_then(|v| v.to_str().ok())
+                        .unwrap_or("localhost");
+
+                    // Sites blocked by the adblock sinkhole: the page right
+                    // away, no certificate warning over plain HTTP
+                    if let Some(page) = proxy_state.sinkhole_page(host, remote.ip()).await {
+                        return Ok(hyper::Response::builder()
+                            .status(403)
+                            .header("Content-Type", "text/html; charset=utf-8")
+                            .header("Cache-Control", "no-store")
+                            .body(http_body_util::Full::new(hyper::body::Bytes::from(page)))
+                            .unwrap());
+                    }
+
+                    let path = req.uri().path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+                    let location = format!("https://{}{}", host, path);
+
+                    Ok::<_, std::convert::Infallible>(
+                        hyper::Response::builder()
+                            .status(301)
+                            .header("Location", &location)
+                            .body(http_body_util::Full::new(hyper::body::Bytes::new()))
+                            .unwrap(),
+                    )
+                }
             });
 
             if let Err(e) = http1::Builder::new().serve_connection(io, service).await {
@@ -1423,11 +2450,15 @@ async fn handle_sighup(
                         new_config.dns.upstream_servers.clone(),
                         new_config.dns.upstream_timeout_ms,
                     );
-                    s.config = new_config.dns;
+                    s.set_config(new_config.dns);
                     s.adblock_enabled = new_config.adblock.enabled;
                     s.adblock_block_response = new_config.adblock.block_response;
                     s.dns_cache.clear().await;
 
+                    if let Err(e) = s.adblock_schedule.set_profiles(&new_config.adblock.profiles) {
+                        error!("Invalid adblock profiles, keeping the previous ones: {}", e);
+                    }
+
                     let mut ab = adblock.write().await;
                     ab.set_whitelist(new_config.adblock.whitelist);
 
@@ -1465,18 +2496,13 @@ async fn do_adblock_update(
     data_dir: &str,
     _dns_state: &hr_dns::SharedDnsState,
 ) {
-    let (domains, _results) = hr_adblock::sources::download_all(sources).await;
-    let count = domains.len();
+    let (rules, _results) = hr_adblock::sources::update(sources, std::path::Path::new(data_dir)).await;
+    let count = rules.rules.len();
 
     {
         let mut ab = adblock.write().await;
-        ab.set_blocked(domains.clone());
-    }
-
-    let cache_path = PathBuf::from(data_dir).join("domains.json");
-    if let Err(e) = hr_adblock::sources::save_cache(&domains, &cache_path) {
-        warn!("Failed to save adblock cache: {}", e);
+        ab.set_rules(rules);
     }
 
-    info!("Adblock update complete: {} unique domains blocked", count);
+    info!("Adblock update complete: {} unique rules", count);
 }
@@ -1,30 +1,63 @@
+use std::collections::VecDeque;
 use std::future::Future;
-use std::sync::Arc;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use tokio::task::JoinHandle;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{AbortHandle, JoinHandle};
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
 use hr_common::service_registry::{
-    now_millis, ServicePriorityLevel, ServiceState, ServiceStatus, SharedServiceRegistry,
+    now_millis, RestartEvent, ServiceAction, ServicePriorityLevel, ServiceState, ServiceStatus,
+    SharedServiceRegistry, RESTART_HISTORY_LEN,
 };
 
+/// Dépendances déclarées : un service attend que les siens tournent avant de
+/// démarrer, et s'arrête avant eux. `tls` est l'initialisation du TlsManager.
+const DEPENDENCIES: &[(&str, &[&str])] = &[
+    ("proxy-https", &["tls"]),
+    ("proxy-http", &["tls"]),
+    ("proxy-health", &["proxy-https"]),
+    ("cloud-relay-tunnel", &["proxy-https", "stream-proxy"]),
+    ("dns-upstream-probe", &["dns-udp"]),
+    ("adblock-schedule", &["dns-udp"]),
+];
+
+/// Intervalle de vérification des dépendances d'un service en attente
+const DEPENDENCY_POLL: Duration = Duration::from_millis(250);
+
+/// Délai laissé à un service annulé pour se terminer à l'arrêt de homeroute
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Priorité d'un service, détermine le comportement de restart
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ServicePriority {
-    /// DNS, DHCP, proxy HTTPS — restart immédiat, retries illimités
+    /// DNS, DHCP, proxy HTTPS — restart immédiat ; budget épuisé, nouvel essai après une pause
     Critical,
-    /// API, IPv6 RA — restart avec backoff, max 10 retries
+    /// API, IPv6 RA — restart avec backoff, max 10 restarts en 10 min
     Important,
-    /// Analytics, DDNS, monitoring — restart lent, max 3 retries
+    /// Analytics, DDNS, monitoring — restart lent, max 3 restarts en 10 min
     Background,
 }
 
 impl ServicePriority {
-    fn max_retries(self) -> u32 {
+    /// Budget de restarts : au-delà de N sur la fenêtre, le circuit s'ouvre
+    fn restart_budget(self) -> (usize, Duration) {
+        match self {
+            Self::Critical => (20, Duration::from_secs(5 * 60)),
+            Self::Important => (10, Duration::from_secs(10 * 60)),
+            Self::Background => (3, Duration::from_secs(10 * 60)),
+        }
+    }
+
+    /// Pause avant un nouvel essai une fois le circuit ouvert ; sans pause,
+    /// le service reste en échec
+    fn circuit_cooldown(self) -> Option<Duration> {
         match self {
-            Self::Critical => u32::MAX,
-            Self::Important => 10,
-            Self::Background => 3,
+            Self::Critical => Some(Duration::from_secs(5 * 60)),
+            Self::Important | Self::Background => None,
         }
     }
 
@@ -36,6 +69,16 @@ impl ServicePriority {
         }
     }
 
+    /// Ordre d'arrêt à rang de dépendance égal : arrière-plan d'abord,
+    /// critiques en dernier
+    fn stop_order(self) -> u8 {
+        match self {
+            Self::Background => 0,
+            Self::Important => 1,
+            Self::Critical => 2,
+        }
+    }
+
     fn to_level(self) -> ServicePriorityLevel {
         match self {
             Self::Critical => ServicePriorityLevel::Critical,
@@ -45,14 +88,271 @@ impl ServicePriority {
     }
 }
 
+/// Dépendances déclarées de `name`
+fn dependencies_of(name: &str) -> Vec<String> {
+    DEPENDENCIES
+        .iter()
+        .filter(|(service, _)| *service == name)
+        .flat_map(|(_, deps)| deps.iter().map(|d| d.to_string()))
+        .collect()
+}
+
+/// Rang d'arrêt : 0 si aucun service ne dépend de `name`, sinon un de plus
+/// que le plus haut de ses dépendants
+fn stop_rank(name: &str) -> usize {
+    DEPENDENCIES
+        .iter()
+        .filter(|(_, deps)| deps.contains(&name))
+        .map(|(dependent, _)| stop_rank(dependent) + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+type ShutdownFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Travail à faire à l'arrêt d'un service (sauvegarde d'état…), borné par
+/// un délai
+struct ShutdownHook {
+    name: &'static str,
+    timeout: Duration,
+    hook: ShutdownFuture,
+}
+
+/// Commande start/stop/restart pour la boucle de supervision d'un service
+struct Control {
+    action: ServiceAction,
+    response_tx: oneshot::Sender<Result<(), String>>,
+}
+
+struct Supervised {
+    name: &'static str,
+    priority: ServicePriority,
+    /// Boucle de supervision
+    supervisor_task: AbortHandle,
+    control: mpsc::UnboundedSender<Control>,
+    /// Annule le service et sa boucle à l'arrêt de homeroute
+    token: CancellationToken,
+    /// Annulé quand la boucle de supervision se termine
+    done: CancellationToken,
+}
+
+/// Services supervisés : état dans le registre, dépendances, budgets de
+/// restart, contrôle depuis l'API et arrêt ordonné
+pub struct Supervisor {
+    registry: SharedServiceRegistry,
+    services: Mutex<Vec<Supervised>>,
+    hooks: Mutex<Vec<ShutdownHook>>,
+    shutting_down: AtomicBool,
+}
+
+impl Supervisor {
+    pub fn new(registry: SharedServiceRegistry) -> Arc<Self> {
+        Arc::new(Self {
+            registry,
+            services: Mutex::new(Vec::new()),
+            hooks: Mutex::new(Vec::new()),
+            shutting_down: AtomicBool::new(false),
+        })
+    }
+
+    /// Marque une étape d'initialisation comme prête, pour les services qui
+    /// en dépendent
+    pub async fn mark_ready(&self, name: &str, priority: ServicePriority) {
+        self.registry.write().await.insert(
+            name.to_string(),
+            ServiceStatus::new(name, ServiceState::Running, priority.to_level()),
+        );
+    }
+
+    /// `hook` est lancé à l'arrêt, juste après le service `name` (ou à la fin
+    /// si `name` n'est pas supervisé), pendant au plus `timeout`
+    pub fn on_shutdown<Fut>(&self, name: &'static str, timeout: Duration, hook: Fut)
+    where
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.hooks.lock().unwrap().push(ShutdownHook {
+            name,
+            timeout,
+            hook: Box::pin(hook),
+        });
+    }
+
+    /// Démarre, arrête ou redémarre un service supervisé. Un service arrêté
+    /// le reste jusqu'au prochain start ou restart ; un restart remet à zéro
+    /// son budget de restarts.
+    pub async fn control(&self, name: &str, action: ServiceAction) -> Result<(), String> {
+        if self.is_shutting_down() {
+            return Err("homeroute is shutting down".to_string());
+        }
+        let control = self
+            .services
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|s| s.name == name)
+            .map(|s| s.control.clone());
+        let Some(control) = control else {
+            return Err(if self.registry.read().await.contains_key(name) {
+                format!("{name} is not a supervised service")
+            } else {
+                format!("Unknown service: {name}")
+            });
+        };
+        let (response_tx, response_rx) = oneshot::channel();
+        control
+            .send(Control { action, response_tx })
+            .map_err(|_| format!("Supervision of {name} has ended"))?;
+        response_rx
+            .await
+            .map_err(|_| format!("Supervision of {name} has ended"))?
+    }
+
+    /// Arrête les services dans l'ordre inverse des dépendances, puis par
+    /// priorité (les critiques en dernier), en lançant leurs hooks d'arrêt
+    pub async fn shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        let mut services = std::mem::take(&mut *self.services.lock().unwrap());
+        services.sort_by_key(|s| (stop_rank(s.name), s.priority.stop_order()));
+        let mut hooks = std::mem::take(&mut *self.hooks.lock().unwrap());
+
+        for service in &services {
+            service.token.cancel();
+            if tokio::time::timeout(STOP_TIMEOUT, service.done.cancelled())
+                .await
+                .is_err()
+            {
+                warn!(
+                    "[supervisor] {} did not stop within {:?}",
+                    service.name, STOP_TIMEOUT
+                );
+            }
+            service.supervisor_task.abort();
+            self.set_stopped(service.name).await;
+            let (own, rest): (Vec<_>, Vec<_>) = hooks.into_iter().partition(|h| h.name == service.name);
+            hooks = rest;
+            for hook in own {
+                run_hook(hook).await;
+            }
+            info!("[supervisor] {} stopped", service.name);
+        }
+        for hook in hooks {
+            run_hook(hook).await;
+        }
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    async fn update(&self, name: &str, change: impl FnOnce(&mut ServiceStatus)) {
+        if let Some(entry) = self.registry.write().await.get_mut(name) {
+            change(entry);
+        }
+    }
+
+    async fn set_stopped(&self, name: &str) {
+        self.update(name, |entry| {
+            entry.state = ServiceState::Stopped;
+            entry.circuit_open = false;
+            entry.retry_at = None;
+            entry.last_state_change = now_millis();
+        })
+        .await;
+    }
+
+    /// Attend que chaque dépendance tourne (ou soit désactivée)
+    async fn wait_for_dependencies(&self, name: &str, depends_on: &[String]) {
+        let mut logged = false;
+        loop {
+            let pending: Vec<&str> = {
+                let reg = self.registry.read().await;
+                depends_on
+                    .iter()
+                    .filter(|dep| {
+                        !reg.get(dep.as_str())
+                            .is_some_and(|s| matches!(s.state, ServiceState::Running | ServiceState::Disabled))
+                    })
+                    .map(String::as_str)
+                    .collect()
+            };
+            if pending.is_empty() {
+                return;
+            }
+            if !logged {
+                info!("[supervisor] {name} waiting for {}", pending.join(", "));
+                logged = true;
+            }
+            tokio::time::sleep(DEPENDENCY_POLL).await;
+        }
+    }
+
+    /// Service à l'arrêt : attend la fin de `delay` (indéfiniment sans délai)
+    /// ou un start/restart. Un stop arrête aussi l'attente d'un restart
+    /// automatique.
+    async fn idle(
+        &self,
+        name: &str,
+        control_rx: &mut mpsc::UnboundedReceiver<Control>,
+        token: &CancellationToken,
+        mut delay: Option<Duration>,
+    ) -> Wake {
+        let deadline = delay.map(|d| tokio::time::Instant::now() + d);
+        loop {
+            let request = tokio::select! {
+                _ = token.cancelled() => return Wake::Cancelled,
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)), if delay.is_some() => {
+                    return Wake::Timer;
+                }
+                request = control_rx.recv() => match request {
+                    Some(request) => request,
+                    None => return Wake::Cancelled,
+                },
+            };
+            match request.action {
+                ServiceAction::Start | ServiceAction::Restart => {
+                    info!("[supervisor] {name} started from the API");
+                    let _ = request.response_tx.send(Ok(()));
+                    return Wake::Command;
+                }
+                ServiceAction::Stop => {
+                    if delay.take().is_some() {
+                        info!("[supervisor] {name} stopped from the API");
+                        self.set_stopped(name).await;
+                    }
+                    let _ = request.response_tx.send(Ok(()));
+                }
+            }
+        }
+    }
+}
+
+/// Fin d'une attente de [`Supervisor::idle`]
+enum Wake {
+    Timer,
+    Command,
+    Cancelled,
+}
+
+async fn run_hook(hook: ShutdownHook) {
+    if tokio::time::timeout(hook.timeout, hook.hook).await.is_err() {
+        warn!(
+            "[supervisor] shutdown of {} did not finish within {:?}",
+            hook.name, hook.timeout
+        );
+    }
+}
+
 /// Lance un service supervisé dans une tâche tokio
 ///
-/// Le service est redémarré automatiquement en cas de panne ou de panic,
-/// selon sa priorité. Les services critiques redémarrent indéfiniment.
+/// Le service démarre une fois ses dépendances prêtes, puis est redémarré en
+/// cas de panne ou de panic selon sa priorité, dans la limite de son budget de
+/// restarts. Chaque exécution tourne sous un jeton d'annulation : un stop ou
+/// un restart depuis l'API, ou l'arrêt de homeroute, abandonne le future du
+/// service à son prochain point d'attente.
 pub fn spawn_supervised<F, Fut>(
     name: &'static str,
     priority: ServicePriority,
-    registry: SharedServiceRegistry,
+    supervisor: Arc<Supervisor>,
     factory: F,
 ) -> JoinHandle<()>
 where
@@ -61,103 +361,175 @@ where
 {
     let factory = Arc::new(factory);
     let level = priority.to_level();
-    tokio::spawn(async move {
-        let max_retries = priority.max_retries();
-        let mut retries: u32 = 0;
-        let mut last_restart = Instant::now();
+    let (control, mut control_rx) = mpsc::unbounded_channel::<Control>();
+    let token = CancellationToken::new();
+    let done = CancellationToken::new();
+    let task_token = token.clone();
+    let task_done = done.clone();
+    let sup = supervisor.clone();
+    let handle = tokio::spawn(async move {
+        let _done = task_done.drop_guard();
+        let token = task_token;
+        let (budget, window) = priority.restart_budget();
+        let depends_on = dependencies_of(name);
+        let mut restarts: VecDeque<Instant> = VecDeque::new();
+
+        {
+            let mut reg = sup.registry.write().await;
+            let mut status = ServiceStatus::new(name, ServiceState::Starting, level);
+            status.depends_on = depends_on.clone();
+            reg.insert(name.to_string(), status);
+        }
+        tokio::select! {
+            _ = sup.wait_for_dependencies(name, &depends_on) => {}
+            _ = token.cancelled() => return,
+        }
 
         loop {
             info!("[supervisor] Starting service: {name}");
 
             // Mark as running
-            {
-                let mut reg = registry.write().await;
-                reg.insert(
-                    name.to_string(),
-                    ServiceStatus {
-                        name: name.to_string(),
-                        state: ServiceState::Running,
-                        priority: level.clone(),
-                        restart_count: retries,
-                        last_state_change: now_millis(),
-                        error: None,
+            sup.update(name, |entry| {
+                entry.state = ServiceState::Running;
+                entry.circuit_open = false;
+                entry.retry_at = None;
+                entry.last_state_change = now_millis();
+            })
+            .await;
+
+            let run_token = token.child_token();
+            let f = Arc::clone(&factory);
+            let task_run_token = run_token.clone();
+            let mut task = tokio::spawn(async move { task_run_token.run_until_cancelled(f()).await });
+
+            // Stop ou restart demandé pendant l'exécution
+            let mut requested: Option<Control> = None;
+            let result = loop {
+                tokio::select! {
+                    result = &mut task => break result,
+                    Some(request) = control_rx.recv(), if requested.is_none() => match request.action {
+                        ServiceAction::Start => {
+                            let _ = request.response_tx.send(Err(format!("{name} is already running")));
+                        }
+                        ServiceAction::Stop | ServiceAction::Restart => {
+                            run_token.cancel();
+                            requested = Some(request);
+                        }
                     },
-                );
+                }
+            };
+
+            if token.is_cancelled() {
+                break;
             }
 
-            let f = Arc::clone(&factory);
-            let result = tokio::spawn(async move {
-                let fut = f();
-                fut.await
-            })
-            .await;
+            if let Some(request) = requested {
+                let _ = request.response_tx.send(Ok(()));
+                restarts.clear();
+                if request.action == ServiceAction::Restart {
+                    info!("[supervisor] {name} restarted from the API");
+                    continue;
+                }
+                info!("[supervisor] {name} stopped from the API");
+                sup.set_stopped(name).await;
+                match sup.idle(name, &mut control_rx, &token, None).await {
+                    Wake::Cancelled => break,
+                    Wake::Timer | Wake::Command => continue,
+                }
+            }
 
-            match result {
-                Ok(Ok(())) => {
+            let err_msg = match result {
+                Ok(Some(Ok(()))) | Ok(None) => {
                     info!("[supervisor] {name} exited cleanly");
-                    let mut reg = registry.write().await;
-                    if let Some(entry) = reg.get_mut(name) {
-                        entry.state = ServiceState::Stopped;
-                        entry.last_state_change = now_millis();
+                    sup.set_stopped(name).await;
+                    match sup.idle(name, &mut control_rx, &token, None).await {
+                        Wake::Cancelled => break,
+                        Wake::Timer | Wake::Command => {
+                            restarts.clear();
+                            continue;
+                        }
                     }
-                    break;
                 }
-                Ok(Err(e)) => {
+                Ok(Some(Err(e))) => {
                     let err_msg = format!("{e:#}");
                     error!("[supervisor] {name} failed: {err_msg}");
-                    let mut reg = registry.write().await;
-                    if let Some(entry) = reg.get_mut(name) {
-                        entry.state = ServiceState::Failed;
-                        entry.error = Some(err_msg);
-                        entry.last_state_change = now_millis();
-                    }
+                    err_msg
                 }
                 Err(join_error) => {
                     let err_msg = format!("{join_error}");
                     error!("[supervisor] {name} task panicked: {err_msg}");
-                    let mut reg = registry.write().await;
-                    if let Some(entry) = reg.get_mut(name) {
-                        entry.state = ServiceState::Failed;
-                        entry.error = Some(err_msg);
-                        entry.last_state_change = now_millis();
-                    }
+                    err_msg
                 }
-            }
+            };
 
-            // Reset retry counter si le service a tourné plus de 60s
-            if last_restart.elapsed() > Duration::from_secs(60) {
-                retries = 0;
+            // Restarts de la fenêtre glissante
+            let now = Instant::now();
+            while restarts.front().is_some_and(|t| now.duration_since(*t) > window) {
+                restarts.pop_front();
             }
+            restarts.push_back(now);
+            let attempts = restarts.len();
 
-            retries = retries.saturating_add(1);
-
-            if retries > max_retries {
-                error!(
-                    "[supervisor] {name} exceeded max retries ({max_retries}), giving up"
-                );
-                let mut reg = registry.write().await;
-                if let Some(entry) = reg.get_mut(name) {
-                    entry.state = ServiceState::Stopped;
-                    entry.last_state_change = now_millis();
+            sup.update(name, |entry| {
+                entry.state = ServiceState::Failed;
+                entry.error = Some(err_msg.clone());
+                entry.last_state_change = now_millis();
+                entry.restart_count = entry.restart_count.saturating_add(1);
+                entry.restart_history.push(RestartEvent {
+                    at: now_millis(),
+                    error: err_msg,
+                });
+                if entry.restart_history.len() > RESTART_HISTORY_LEN {
+                    entry.restart_history.remove(0);
                 }
-                break;
-            }
-
-            let backoff = priority.backoff(retries);
-            warn!(
-                "[supervisor] {name} restarting in {backoff:?} (attempt {retries}/{max_retries})"
-            );
+            })
+            .await;
 
-            // Update restart count
-            {
-                let mut reg = registry.write().await;
-                if let Some(entry) = reg.get_mut(name) {
-                    entry.restart_count = retries;
+            let delay = if attempts > budget {
+                // Circuit ouvert : le service flappe
+                match priority.circuit_cooldown() {
+                    Some(cooldown) => {
+                        error!(
+                            "[supervisor] {name} failed {attempts} times within {window:?}, retrying in {cooldown:?}"
+                        );
+                        sup.update(name, |entry| {
+                            entry.circuit_open = true;
+                            entry.retry_at = Some(now_millis() + cooldown.as_millis() as u64);
+                        })
+                        .await;
+                        Some(cooldown)
+                    }
+                    None => {
+                        error!(
+                            "[supervisor] {name} failed {attempts} times within {window:?}, giving up"
+                        );
+                        sup.update(name, |entry| entry.circuit_open = true).await;
+                        None
+                    }
                 }
-            }
+            } else {
+                let backoff = priority.backoff(attempts as u32);
+                warn!(
+                    "[supervisor] {name} restarting in {backoff:?} (attempt {attempts}/{budget} within {window:?})"
+                );
+                Some(backoff)
+            };
 
-            tokio::time::sleep(backoff).await;
-            last_restart = Instant::now();
+            match sup.idle(name, &mut control_rx, &token, delay).await {
+                Wake::Cancelled => break,
+                Wake::Timer if attempts > budget => restarts.clear(),
+                Wake::Timer => {}
+                Wake::Command => restarts.clear(),
+            }
         }
-    })
+    });
+    supervisor.services.lock().unwrap().push(Supervised {
+        name,
+        priority,
+        supervisor_task: handle.abort_handle(),
+        control,
+        token,
+        done,
+    });
+    handle
 }
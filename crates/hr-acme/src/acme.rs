@@ -6,7 +6,8 @@ use instant_acme::{
     Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, NewAccount,
     NewOrder, OrderStatus,
 };
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 use tokio::sync::Mutex;
 use tracing::{debug, info, warn};
 
@@ -15,6 +16,8 @@ pub struct AcmeManager {
     config: AcmeConfig,
     storage: AcmeStorage,
     account: Arc<Mutex<Option<Account>>>,
+    /// Pending HTTP-01 challenges: token -> key authorization.
+    http01: RwLock<HashMap<String, String>>,
 }
 
 impl AcmeManager {
@@ -25,6 +28,7 @@ impl AcmeManager {
             config,
             storage,
             account: Arc::new(Mutex::new(None)),
+            http01: RwLock::new(HashMap::new()),
         }
     }
 
@@ -112,6 +116,7 @@ impl AcmeManager {
         let account = account_guard.as_ref().ok_or(AcmeError::NotInitialized)?;
 
         let wildcard_domain = wildcard_type.domain_pattern(&self.config.base_domain);
+        let domain_names = wildcard_type.domain_names(&self.config.base_domain);
 
         info!(
             wildcard = %wildcard_domain,
@@ -119,7 +124,8 @@ impl AcmeManager {
             "Requesting wildcard certificate from Let's Encrypt"
         );
 
-        let identifiers = vec![Identifier::Dns(wildcard_domain.clone())];
+        let identifiers: Vec<Identifier> =
+            domain_names.iter().cloned().map(Identifier::Dns).collect();
 
         // Create order
         let mut order = account
@@ -135,7 +141,22 @@ impl AcmeManager {
             .await
             .map_err(|e| AcmeError::ProtocolError(format!("Failed to get authorizations: {}", e)))?;
 
-        let mut challenge_records: Vec<(String, String)> = Vec::new();
+        // Custom domains use DNS-01 when their zone is in the Cloudflare
+        // account, HTTP-01 (served on port 80) otherwise.
+        let dns_zone = match &wildcard_type {
+            WildcardType::Custom { domain, .. } => {
+                cloudflare::find_zone_id(&self.config.cf_api_token, domain)
+                    .await
+                    .unwrap_or_else(|e| {
+                        debug!(domain = %domain, error = %e, "Cloudflare zone lookup failed");
+                        None
+                    })
+            }
+            _ => Some(self.config.cf_zone_id.clone()),
+        };
+
+        let mut challenge_records: Vec<(String, String, String)> = Vec::new();
+        let mut http01_tokens: Vec<String> = Vec::new();
 
         for auth in authorizations {
             if auth.status == AuthorizationStatus::Valid {
@@ -143,6 +164,32 @@ impl AcmeManager {
                 continue;
             }
 
+            let Some(zone_id) = &dns_zone else {
+                let challenge = auth
+                    .challenges
+                    .iter()
+                    .find(|c| c.r#type == ChallengeType::Http01)
+                    .ok_or_else(|| {
+                        AcmeError::ChallengeFailed("No HTTP-01 challenge available".into())
+                    })?;
+
+                let key_auth = order.key_authorization(challenge);
+                debug!(token = %challenge.token, "Serving HTTP-01 challenge");
+                self.http01
+                    .write()
+                    .unwrap()
+                    .insert(challenge.token.clone(), key_auth.as_str().to_string());
+                http01_tokens.push(challenge.token.clone());
+
+                order
+                    .set_challenge_ready(&challenge.url)
+                    .await
+                    .map_err(|e| {
+                        AcmeError::ProtocolError(format!("Failed to set challenge ready: {}", e))
+                    })?;
+                continue;
+            };
+
             let challenge = auth
                 .challenges
                 .iter()
@@ -168,14 +215,14 @@ impl AcmeManager {
             // Create DNS record via Cloudflare
             let record_id = cloudflare::create_acme_challenge_record(
                 &self.config.cf_api_token,
-                &self.config.cf_zone_id,
+                zone_id,
                 &dns_name,
                 &dns_value,
             )
             .await
             .map_err(AcmeError::CloudflareError)?;
 
-            challenge_records.push((dns_name.clone(), record_id));
+            challenge_records.push((dns_name.clone(), zone_id.clone(), record_id));
 
             // Wait for DNS propagation
             info!("Waiting for DNS propagation (45 seconds)...");
@@ -207,7 +254,7 @@ impl AcmeManager {
                 }
                 OrderStatus::Invalid => {
                     // Cleanup DNS records before returning error
-                    self.cleanup_challenge_records(&challenge_records).await;
+                    self.cleanup_challenges(&challenge_records, &http01_tokens).await;
                     return Err(AcmeError::ChallengeFailed(
                         "Order validation failed - order became invalid".into(),
                     ));
@@ -221,7 +268,7 @@ impl AcmeManager {
                     attempts += 1;
                     if attempts > 60 {
                         // 5 minutes timeout
-                        self.cleanup_challenge_records(&challenge_records).await;
+                        self.cleanup_challenges(&challenge_records, &http01_tokens).await;
                         return Err(AcmeError::ChallengeFailed(
                             "Timeout waiting for order validation".into(),
                         ));
@@ -231,11 +278,11 @@ impl AcmeManager {
         }
 
         // Cleanup DNS records
-        self.cleanup_challenge_records(&challenge_records).await;
+        self.cleanup_challenges(&challenge_records, &http01_tokens).await;
 
         // Generate CSR and finalize order
         info!("Generating CSR and finalizing order...");
-        let mut params = rcgen::CertificateParams::new(vec![wildcard_domain.clone()])
+        let mut params = rcgen::CertificateParams::new(domain_names.clone())
             .map_err(|e| AcmeError::ProtocolError(format!("Failed to create cert params: {}", e)))?;
         params.distinguished_name = rcgen::DistinguishedName::new();
 
@@ -283,7 +330,7 @@ impl AcmeManager {
         let cert_info = CertificateInfo {
             id: wildcard_type.id(),
             wildcard_type: wildcard_type.clone(),
-            domains: vec![wildcard_domain.clone()],
+            domains: domain_names,
             issued_at: now,
             expires_at: now + Duration::days(90), // Let's Encrypt certs are valid 90 days
             cert_path: cert_path.to_string_lossy().to_string(),
@@ -292,7 +339,7 @@ impl AcmeManager {
 
         // Update index
         let mut index = self.storage.load_index()?;
-        index.retain(|c| c.wildcard_type != wildcard_type);
+        index.retain(|c| c.id != cert_info.id && c.wildcard_type != wildcard_type);
         index.push(cert_info.clone());
         self.storage.save_index(&index)?;
 
@@ -305,12 +352,18 @@ impl AcmeManager {
         Ok(cert_info)
     }
 
-    /// Cleanup challenge DNS records
-    async fn cleanup_challenge_records(&self, records: &[(String, String)]) {
-        for (dns_name, record_id) in records {
+    /// Cleanup challenge DNS records and HTTP-01 tokens
+    async fn cleanup_challenges(&self, records: &[(String, String, String)], tokens: &[String]) {
+        {
+            let mut http01 = self.http01.write().unwrap();
+            for token in tokens {
+                http01.remove(token);
+            }
+        }
+        for (dns_name, zone_id, record_id) in records {
             if let Err(e) = cloudflare::delete_challenge_record(
                 &self.config.cf_api_token,
-                &self.config.cf_zone_id,
+                zone_id,
                 record_id,
             )
             .await
@@ -320,6 +373,12 @@ impl AcmeManager {
         }
     }
 
+    /// Key authorization for a pending HTTP-01 challenge token
+    /// (served at `/.well-known/acme-challenge/{token}`).
+    pub fn http01_response(&self, token: &str) -> Option<String> {
+        self.http01.read().unwrap().get(token).cloned()
+    }
+
     /// List all certificates
     pub fn list_certificates(&self) -> AcmeResult<Vec<CertificateInfo>> {
         self.storage.load_index()
@@ -380,12 +439,16 @@ impl AcmeManager {
 
     /// Delete certificate for a specific application
     pub fn delete_app_certificate(&self, slug: &str) -> AcmeResult<()> {
-        let wt = WildcardType::for_app(slug);
+        self.delete_certificate(&WildcardType::for_app(slug))
+    }
+
+    /// Delete a certificate and its files
+    pub fn delete_certificate(&self, wt: &WildcardType) -> AcmeResult<()> {
         let cert_id = wt.id();
 
         // Remove from index
         let mut index = self.storage.load_index()?;
-        index.retain(|c| c.wildcard_type != wt);
+        index.retain(|c| c.id != cert_id && c.wildcard_type != *wt);
         self.storage.save_index(&index)?;
 
         // Remove cert and key files (ignore errors if files don't exist)
@@ -161,3 +161,40 @@ pub async fn list_acme_challenge_records(
 
     Ok(records)
 }
+
+/// Find the Cloudflare zone holding `domain`, walking up its parent names.
+/// `None` when the token has no access to any of them.
+pub async fn find_zone_id(token: &str, domain: &str) -> Result<Option<String>, String> {
+    let client = reqwest::Client::new();
+    let labels: Vec<&str> = domain.trim_end_matches('.').split('.').collect();
+
+    #[derive(Deserialize)]
+    struct Zone {
+        id: String,
+    }
+
+    for start in 0..labels.len().saturating_sub(1) {
+        let name = labels[start..].join(".");
+        let url = format!("{}/zones?name={}", CF_API_BASE, name);
+        let resp = client
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", token))
+            .send()
+            .await
+            .map_err(|e| format!("HTTP request failed: {}", e))?;
+
+        let body: CloudflareResponse<Vec<Zone>> = resp
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse response: {}", e))?;
+
+        if !body.success {
+            return Err("Failed to list zones".into());
+        }
+        if let Some(zone) = body.result.unwrap_or_default().into_iter().next() {
+            debug!(domain, zone = %name, "Found Cloudflare zone");
+            return Ok(Some(zone.id));
+        }
+    }
+    Ok(None)
+}
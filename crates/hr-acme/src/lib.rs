@@ -1,7 +1,9 @@
 //! HomeRoute ACME certificate management
 //!
 //! This crate provides Let's Encrypt certificate management using DNS-01 challenges
-//! via Cloudflare API. It manages wildcard certificates for HomeRoute applications.
+//! via Cloudflare API. It manages wildcard certificates for HomeRoute applications,
+//! and single-name certificates for their custom domains (HTTP-01 when the domain's
+//! zone is not in Cloudflare).
 
 mod acme;
 mod cloudflare;
@@ -41,11 +41,13 @@ impl Default for AcmeConfig {
 /// - `"main"` or `"global"` deserializes to `Global`
 /// - `"code"` deserializes to `LegacyCode`
 /// - `{"app": "slug_value"}` deserializes to `App { slug: "slug_value" }`
+/// - `{"custom": "shop.example.org", "app": "slug_value"}` deserializes to `Custom`
 ///
 /// Serialization:
 /// - `Global` -> `"global"`
 /// - `LegacyCode` -> `"code"`
 /// - `App { slug }` -> `{"app": "slug_value"}`
+/// - `Custom { slug, domain }` -> `{"custom": "domain", "app": "slug_value"}`
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum WildcardType {
     /// *.mynetwk.biz - global wildcard (dashboard, redirections)
@@ -54,6 +56,9 @@ pub enum WildcardType {
     App { slug: String },
     /// *.code.mynetwk.biz - legacy code server wildcard
     LegacyCode,
+    /// shop.example.org - external domain attached to an application
+    /// (not a wildcard: HTTP-01 unless the zone is reachable in Cloudflare)
+    Custom { slug: String, domain: String },
 }
 
 impl WildcardType {
@@ -63,6 +68,16 @@ impl WildcardType {
             Self::Global => format!("*.{}", base_domain),
             Self::App { slug } => format!("*.{}.{}", slug, base_domain),
             Self::LegacyCode => format!("*.code.{}", base_domain),
+            Self::Custom { domain, .. } => domain.clone(),
+        }
+    }
+
+    /// Names on the certificate: the pattern, plus the bare base domain for
+    /// the global wildcard (a wildcard does not cover its apex)
+    pub fn domain_names(&self, base_domain: &str) -> Vec<String> {
+        match self {
+            Self::Global => vec![self.domain_pattern(base_domain), base_domain.to_string()],
+            _ => vec![self.domain_pattern(base_domain)],
         }
     }
 
@@ -72,6 +87,7 @@ impl WildcardType {
             Self::Global => "wildcard-global".to_string(),
             Self::App { slug } => format!("app-{}", slug),
             Self::LegacyCode => "wildcard-code".to_string(),
+            Self::Custom { domain, .. } => format!("custom-{}", domain),
         }
     }
 
@@ -81,6 +97,7 @@ impl WildcardType {
             Self::Global => "Global (Dashboard)".to_string(),
             Self::App { slug } => format!("App: {}", slug),
             Self::LegacyCode => "Code Server (Legacy)".to_string(),
+            Self::Custom { domain, .. } => format!("Custom: {}", domain),
         }
     }
 
@@ -90,6 +107,14 @@ impl WildcardType {
             slug: slug.to_string(),
         }
     }
+
+    /// Create a Custom type for an external domain of an application
+    pub fn for_custom(slug: &str, domain: &str) -> Self {
+        Self::Custom {
+            slug: slug.to_string(),
+            domain: domain.to_string(),
+        }
+    }
 }
 
 impl Serialize for WildcardType {
@@ -106,6 +131,13 @@ impl Serialize for WildcardType {
                 map.serialize_entry("app", slug)?;
                 map.end()
             }
+            Self::Custom { slug, domain } => {
+                use serde::ser::SerializeMap;
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("custom", domain)?;
+                map.serialize_entry("app", slug)?;
+                map.end()
+            }
         }
     }
 }
@@ -124,7 +156,7 @@ impl<'de> Deserialize<'de> for WildcardType {
 
             fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
                 formatter.write_str(
-                    r#"a string ("global", "main", "code") or a map ({"app": "slug"})"#,
+                    r#"a string ("global", "main", "code") or a map ({"app": "slug"}, {"custom": "domain", "app": "slug"})"#,
                 )
             }
 
@@ -146,16 +178,23 @@ impl<'de> Deserialize<'de> for WildcardType {
             where
                 A: de::MapAccess<'de>,
             {
-                let key: String = map
-                    .next_key()?
-                    .ok_or_else(|| de::Error::custom("expected a key in map"))?;
-
-                match key.as_str() {
-                    "app" => {
-                        let slug: String = map.next_value()?;
-                        Ok(WildcardType::App { slug })
+                let mut slug: Option<String> = None;
+                let mut domain: Option<String> = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "app" => slug = Some(map.next_value()?),
+                        "custom" => domain = Some(map.next_value()?),
+                        other => return Err(de::Error::unknown_field(other, &["app", "custom"])),
                     }
-                    other => Err(de::Error::unknown_field(other, &["app"])),
+                }
+
+                match (slug, domain) {
+                    (slug, Some(domain)) => Ok(WildcardType::Custom {
+                        slug: slug.unwrap_or_default(),
+                        domain,
+                    }),
+                    (Some(slug), None) => Ok(WildcardType::App { slug }),
+                    (None, None) => Err(de::Error::custom("expected a key in map")),
                 }
             }
         }
@@ -274,6 +313,16 @@ mod tests {
         assert_eq!(wt, WildcardType::App { slug: "www".to_string() });
     }
 
+    #[test]
+    fn test_wildcard_type_custom_roundtrip() {
+        let wt = WildcardType::for_custom("shop", "shop.example.org");
+        let json = serde_json::to_string(&wt).unwrap();
+        assert_eq!(json, r#"{"custom":"shop.example.org","app":"shop"}"#);
+        assert_eq!(serde_json::from_str::<WildcardType>(&json).unwrap(), wt);
+        assert_eq!(wt.id(), "custom-shop.example.org");
+        assert_eq!(wt.domain_pattern("mynetwk.biz"), "shop.example.org");
+    }
+
     #[test]
     fn test_wildcard_type_id() {
         assert_eq!(WildcardType::Global.id(), "wildcard-global");
@@ -289,6 +338,13 @@ mod tests {
         assert_eq!(WildcardType::for_app("www").domain_pattern(base), "*.www.mynetwk.biz");
     }
 
+    #[test]
+    fn test_wildcard_type_domain_names() {
+        let base = "mynetwk.biz";
+        assert_eq!(WildcardType::Global.domain_names(base), vec!["*.mynetwk.biz", "mynetwk.biz"]);
+        assert_eq!(WildcardType::for_app("www").domain_names(base), vec!["*.www.mynetwk.biz"]);
+    }
+
     #[test]
     fn test_wildcard_type_display_name() {
         assert_eq!(WildcardType::Global.display_name(), "Global (Dashboard)");
@@ -0,0 +1,36 @@
+//! Blocklist lookups as done for every DNS query, against a list the size
+//! of the usual public ones. Run with `cargo bench -p hr-adblock`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use hr_adblock::AdblockEngine;
+use rustc_hash::FxHashSet;
+
+const DOMAINS: usize = 200_000;
+
+fn engine() -> AdblockEngine {
+    let blocked: FxHashSet<String> = (0..DOMAINS).map(|i| format!("ads{}.tracker{}.com", i, i % 997)).collect();
+    let mut engine = AdblockEngine::new();
+    engine.set_blocked(blocked);
+    engine.set_whitelist(vec!["ads42.tracker42.com".to_string()]);
+    engine
+}
+
+fn lookups(c: &mut Criterion) {
+    let engine = engine();
+
+    c.bench_function("is_blocked_hit", |b| {
+        b.iter(|| engine.is_blocked(black_box("ads1234.tracker237.com")))
+    });
+    c.bench_function("is_blocked_subdomain", |b| {
+        b.iter(|| engine.is_blocked(black_box("cdn.eu.ads1234.tracker237.com")))
+    });
+    c.bench_function("is_blocked_miss", |b| {
+        b.iter(|| engine.is_blocked(black_box("www.example.org")))
+    });
+    c.bench_function("is_blocked_whitelisted", |b| {
+        b.iter(|| engine.is_blocked(black_box("ads42.tracker42.com")))
+    });
+}
+
+criterion_group!(benches, lookups);
+criterion_main!(benches);
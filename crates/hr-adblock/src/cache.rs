@@ -0,0 +1,337 @@
+//! Binary cache of the downloaded lists, kept per source so an update only
+//! re-parses the lists that changed.
+//!
+//! Layout (integers are LEB128 varints, strings a length then UTF-8):
+//!
+//! ```text
+//! "HRAB" version
+//! source count
+//! per source: name url etag last_modified fetched_at unsupported
+//!             rule count, rules front-coded: shared prefix length,
+//!             suffix length, suffix bytes
+//! ```
+//!
+//! Rules are stored reversed and sorted, so that the `.com^` style endings
+//! shared by most of a list collapse into the prefix of the previous one.
+//! The file is memory-mapped for loading.
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use rustc_hash::FxHashSet;
+
+use crate::filter::RuleList;
+use crate::rules::{self, Parsed, Pattern, Rule};
+
+const MAGIC: &[u8; 4] = b"HRAB";
+const VERSION: u8 = 3;
+
+/// One list as last downloaded, with the validators for the next
+/// conditional request.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceCache {
+    pub name: String,
+    /// A list whose URL changed is downloaded again in full.
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Unix time of the last download that returned the list.
+    pub fetched_at: i64,
+    pub unsupported: usize,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ListCache {
+    pub sources: Vec<SourceCache>,
+}
+
+impl ListCache {
+    pub fn source(&self, name: &str) -> Option<&SourceCache> {
+        self.sources.iter().find(|s| s.name == name)
+    }
+
+    /// The rules of every list, deduplicated: the first list carrying a
+    /// rule gets the attribution.
+    pub fn rule_list(&self) -> RuleList {
+        let mut list = RuleList::default();
+        list.rules.reserve(self.sources.iter().map(|s| s.rules.len()).sum());
+        let mut seen = FxHashSet::default();
+        for source in &self.sources {
+            let index = list.source_index(&source.name);
+            for rule in &source.rules {
+                if seen.insert(rule) {
+                    list.rules.push((rule.clone(), index));
+                }
+            }
+        }
+        list
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(1 << 16);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        put_varint(&mut out, self.sources.len() as u64);
+        for source in &self.sources {
+            put_str(&mut out, &source.name);
+            put_str(&mut out, &source.url);
+            put_str(&mut out, source.etag.as_deref().unwrap_or(""));
+            put_str(&mut out, source.last_modified.as_deref().unwrap_or(""));
+            put_varint(&mut out, source.fetched_at.max(0) as u64);
+            put_varint(&mut out, source.unsupported as u64);
+
+            let mut keys: Vec<Vec<u8>> = source
+                .rules
+                .iter()
+                .map(|r| r.text().into_bytes().into_iter().rev().collect())
+                .collect();
+            keys.sort_unstable();
+            keys.dedup();
+            put_varint(&mut out, keys.len() as u64);
+            let mut previous: &[u8] = &[];
+            for key in &keys {
+                let shared = previous.iter().zip(key.iter()).take_while(|(a, b)| a == b).count();
+                put_varint(&mut out, shared as u64);
+                put_varint(&mut out, (key.len() - shared) as u64);
+                out.extend_from_slice(&key[shared..]);
+                previous = key;
+            }
+        }
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < 5 || &data[..4] != MAGIC {
+            bail!("not an adblock cache");
+        }
+        if data[4] != VERSION {
+            bail!("unsupported adblock cache version {}", data[4]);
+        }
+        let mut reader = Reader { data, pos: 5 };
+        let count = reader.varint()? as usize;
+        let mut sources = Vec::with_capacity(count.min(256));
+        for _ in 0..count {
+            let name = reader.str()?.to_string();
+            let url = reader.str()?.to_string();
+            let etag = Some(reader.str()?.to_string()).filter(|s| !s.is_empty());
+            let last_modified = Some(reader.str()?.to_string()).filter(|s| !s.is_empty());
+            let fetched_at = reader.varint()? as i64;
+            let unsupported = reader.varint()? as usize;
+
+            let rule_count = reader.varint()? as usize;
+            let mut rules = Vec::with_capacity(rule_count.min(data.len()));
+            let mut key: Vec<u8> = Vec::with_capacity(64);
+            let mut text = Vec::with_capacity(64);
+            for _ in 0..rule_count {
+                let shared = reader.varint()? as usize;
+                let len = reader.varint()? as usize;
+                if shared > key.len() {
+                    bail!("corrupt adblock cache (rule prefix)");
+                }
+                key.truncate(shared);
+                key.extend_from_slice(reader.bytes(len)?);
+                text.clear();
+                text.extend(key.iter().rev());
+                let text = std::str::from_utf8(&text).context("corrupt adblock cache (rule text)")?;
+                if let Parsed::Rule(rule) = rules::parse_line(text) {
+                    rules.push(rule);
+                }
+            }
+            sources.push(SourceCache { name, url, etag, last_modified, fetched_at, unsupported, rules });
+        }
+        Ok(Self { sources })
+    }
+}
+
+/// Write the cache next to its final path, then move it in place.
+pub fn save(cache: &ListCache, path: &Path) -> Result<()> {
+    std::fs::create_dir_all(path.parent().unwrap_or(path))?;
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, cache.encode())?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<ListCache> {
+    let file = std::fs::File::open(path)?;
+    // SAFETY: the cache is only ever replaced by rename, never written in
+    // place, so the mapped file does not change under us.
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+    ListCache::decode(&map)
+}
+
+/// A `domains.json` cache from before the binary format: version 2 (rules
+/// with their source) or a bare list of domains, read as one source.
+pub fn load_legacy_json(path: &Path) -> Result<ListCache> {
+    #[derive(serde::Deserialize)]
+    struct JsonCache {
+        sources: Vec<String>,
+        rules: Vec<(u16, String)>,
+    }
+
+    let data = std::fs::read(path)?;
+    if let Ok(domains) = serde_json::from_slice::<Vec<String>>(&data) {
+        let rules = domains.into_iter().map(|d| Rule::block(Pattern::Subtree(d))).collect();
+        return Ok(ListCache { sources: vec![SourceCache { name: "cache".to_string(), rules, ..Default::default() }] });
+    }
+    let json: JsonCache = serde_json::from_slice(&data)?;
+    let mut sources: Vec<SourceCache> = json
+        .sources
+        .into_iter()
+        .map(|name| SourceCache { name, ..Default::default() })
+        .collect();
+    for (index, text) in json.rules {
+        if let (Some(source), Parsed::Rule(rule)) = (sources.get_mut(index as usize), rules::parse_line(&text)) {
+            source.rules.push(rule);
+        }
+    }
+    Ok(ListCache { sources })
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_varint(out, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let Some(&byte) = self.data.get(self.pos) else {
+                bail!("truncated adblock cache");
+            };
+            self.pos += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("corrupt adblock cache (varint)")
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.data.len());
+        let Some(end) = end else {
+            bail!("truncated adblock cache");
+        };
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn str(&mut self) -> Result<&'a str> {
+        let len = self.varint()? as usize;
+        std::str::from_utf8(self.bytes(len)?).context("corrupt adblock cache (string)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(text: &str) -> Rule {
+        match rules::parse_line(text) {
+            Parsed::Rule(r) => r,
+            other => panic!("{text}: {other:?}"),
+        }
+    }
+
+    fn sample() -> ListCache {
+        ListCache {
+            sources: vec![
+                SourceCache {
+                    name: "EasyList".to_string(),
+                    url: "https://lists.example/easylist.txt".to_string(),
+                    etag: Some("\"abc\"".to_string()),
+                    last_modified: Some("Sat, 17 Oct 2026 08:00:00 GMT".to_string()),
+                    fetched_at: 1_792_224_000,
+                    unsupported: 12,
+                    rules: vec![
+                        rule("||ads.example.com^"),
+                        rule("||tracker.example.com^"),
+                        rule("@@||ok.ads.example.com^"),
+                        rule("/^ad[0-9]+\\./"),
+                        rule("||ad*.example.net^$important"),
+                    ],
+                },
+                SourceCache {
+                    name: "Hosts".to_string(),
+                    rules: vec![rule("||ads.example.com^"), rule("|pixel.example.org^")],
+                    ..Default::default()
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let cache = sample();
+        let decoded = ListCache::decode(&cache.encode()).unwrap();
+        assert_eq!(decoded.sources.len(), 2);
+        for (a, b) in decoded.sources.iter().zip(&cache.sources) {
+            let mut expected = b.rules.clone();
+            let mut got = a.rules.clone();
+            expected.sort_by_key(Rule::text);
+            got.sort_by_key(Rule::text);
+            assert_eq!(got, expected);
+            assert_eq!((&a.name, &a.url, &a.etag, &a.last_modified), (&b.name, &b.url, &b.etag, &b.last_modified));
+            assert_eq!((a.fetched_at, a.unsupported), (b.fetched_at, b.unsupported));
+        }
+
+        assert!(ListCache::decode(b"HRAB\x01").is_err());
+        let data = cache.encode();
+        assert!(ListCache::decode(&data[..data.len() - 3]).is_err());
+    }
+
+    #[test]
+    fn test_rule_list_dedup() {
+        let list = sample().rule_list();
+        assert_eq!(list.sources, ["EasyList", "Hosts"]);
+        // ||ads.example.com^ attributed to the first list only
+        assert_eq!(list.rules.len(), 6);
+        let ads = list.rules.iter().filter(|(r, _)| r.text() == "||ads.example.com^").collect::<Vec<_>>();
+        assert_eq!(ads.len(), 1);
+        assert_eq!(ads[0].1, 0);
+    }
+
+    #[test]
+    fn test_compact() {
+        let rules: Vec<Rule> = (0..10_000).map(|i| rule(&format!("||ads{i}.tracker{}.com^", i % 97))).collect();
+        let json_size: usize = rules.iter().map(|r| r.text().len() + 6).sum();
+        let cache = ListCache { sources: vec![SourceCache { name: "big".to_string(), rules, ..Default::default() }] };
+        let data = cache.encode();
+        assert!(data.len() < json_size / 2, "{} bytes vs {}", data.len(), json_size);
+        assert_eq!(ListCache::decode(&data).unwrap().sources[0].rules.len(), 10_000);
+    }
+
+    #[test]
+    fn test_files() {
+        let dir = std::env::temp_dir().join(format!("hr-adblock-bin-{}", std::process::id()));
+        let path = dir.join("rules.bin");
+        save(&sample(), &path).unwrap();
+        assert_eq!(load(&path).unwrap().rule_list().rules.len(), 6);
+
+        let json = dir.join("domains.json");
+        std::fs::write(&json, r#"{"version":2,"sources":["A","B"],"rules":[[0,"||a.com^"],[1,"@@||b.a.com^"]]}"#).unwrap();
+        let legacy = load_legacy_json(&json).unwrap();
+        assert_eq!(legacy.sources[1].rules, vec![rule("@@||b.a.com^")]);
+        std::fs::write(&json, r#"["old.example.com"]"#).unwrap();
+        let legacy = load_legacy_json(&json).unwrap();
+        assert_eq!(legacy.sources[0].rules[0].pattern, Pattern::Subtree("old.example.com".to_string()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
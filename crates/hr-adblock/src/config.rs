@@ -16,6 +16,9 @@ pub struct AdblockConfig {
     pub data_dir: String,
     #[serde(default = "default_auto_update_hours")]
     pub auto_update_hours: u64,
+    /// Time-based blocking profiles, evaluated per client by the resolver.
+    #[serde(default)]
+    pub profiles: Vec<crate::schedule::AdblockProfile>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1,8 +1,141 @@
-use rustc_hash::FxHashSet;
+use regex::RegexSet;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::Serialize;
 
-/// Adblock domain filter using hierarchical matching.
+use crate::rules::{Pattern, Rule};
+
+/// Source name reported for the user whitelist.
+pub const WHITELIST_SOURCE: &str = "whitelist";
+
+/// Rules of all the lists, each with the index of its list in `sources`.
+#[derive(Debug, Clone, Default)]
+pub struct RuleList {
+    pub sources: Vec<String>,
+    pub rules: Vec<(Rule, u16)>,
+}
+
+impl RuleList {
+    /// Index of `name` in `sources`, added when missing.
+    pub fn source_index(&mut self, name: &str) -> u16 {
+        match self.sources.iter().position(|s| s == name) {
+            Some(i) => i as u16,
+            None => {
+                self.sources.push(name.to_string());
+                (self.sources.len() - 1) as u16
+            }
+        }
+    }
+}
+
+/// The rule deciding for a name, for the "why was this blocked" view.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleMatch {
+    pub blocked: bool,
+    /// Rule in adblock syntax (the domain for the whitelist).
+    pub rule: String,
+    /// List name, or `whitelist`.
+    pub source: String,
+    /// Name the rule matched: the query or, for CNAME cloaking, a target.
+    pub name: String,
+}
+
+/// Rules of one kind (block, exception, important block) indexed for
+/// lookups: hash maps for domain rules, a regex set for the others.
+#[derive(Default)]
+struct RuleTable {
+    subtree: FxHashMap<String, u16>,
+    exact: FxHashMap<String, u16>,
+    patterns: Option<RegexSet>,
+    /// (rule text, source) of each pattern of the set.
+    pattern_rules: Vec<(String, u16)>,
+    /// `@@` / `$important` around the rebuilt domain rules.
+    prefix: &'static str,
+    suffix: &'static str,
+}
+
+impl RuleTable {
+    fn build(rules: Vec<(&Rule, u16)>, prefix: &'static str, suffix: &'static str) -> Self {
+        let mut table = Self { prefix, suffix, ..Self::default() };
+        let mut regexes = Vec::new();
+        for (rule, source) in rules {
+            match &rule.pattern {
+                // First list wins the attribution
+                Pattern::Subtree(d) => {
+                    table.subtree.entry(d.clone()).or_insert(source);
+                }
+                Pattern::Exact(d) => {
+                    table.exact.entry(d.clone()).or_insert(source);
+                }
+                Pattern::Regex(re) => {
+                    regexes.push(re.clone());
+                    table.pattern_rules.push((rule.text(), source));
+                }
+            }
+        }
+        if !regexes.is_empty() {
+            match RegexSet::new(&regexes) {
+                Ok(set) => table.patterns = Some(set),
+                Err(e) => {
+                    tracing::warn!("Adblock regex rules rejected: {}", e);
+                    table.pattern_rules.clear();
+                }
+            }
+        }
+        table
+    }
+
+    fn len(&self) -> usize {
+        self.subtree.len() + self.exact.len() + self.pattern_rules.len()
+    }
+
+    /// Rule text and source matching `domain` (lowercase).
+    fn find(&self, domain: &str) -> Option<(String, u16)> {
+        if let Some(source) = self.exact.get(domain) {
+            return Some((format!("{}|{domain}^{}", self.prefix, self.suffix), *source));
+        }
+        let mut check = domain;
+        loop {
+            if let Some(source) = self.subtree.get(check) {
+                return Some((format!("{}||{check}^{}", self.prefix, self.suffix), *source));
+            }
+            match check.find('.') {
+                Some(pos) => check = &check[pos + 1..],
+                None => break,
+            }
+        }
+        let set = self.patterns.as_ref()?;
+        let i = set.matches(domain).into_iter().next()?;
+        self.pattern_rules.get(i).cloned()
+    }
+
+    fn contains(&self, domain: &str) -> bool {
+        if self.exact.contains_key(domain) {
+            return true;
+        }
+        let mut check = domain;
+        loop {
+            if self.subtree.contains_key(check) {
+                return true;
+            }
+            match check.find('.') {
+                Some(pos) => check = &check[pos + 1..],
+                None => break,
+            }
+        }
+        self.patterns.as_ref().is_some_and(|set| set.is_match(domain))
+    }
+}
+
+/// Adblock filter: domain rules with hierarchical matching, wildcard and
+/// regex rules, exceptions and `$important` rules, plus the user
+/// whitelist which always wins.
 pub struct AdblockEngine {
-    blocked: FxHashSet<String>,
+    blocked: RuleTable,
+    important: RuleTable,
+    allowed: RuleTable,
+    important_allowed: RuleTable,
+    sources: Vec<String>,
     whitelist: FxHashSet<String>,
     domain_count: usize,
 }
@@ -10,16 +143,36 @@ pub struct AdblockEngine {
 impl AdblockEngine {
     pub fn new() -> Self {
         Self {
-            blocked: FxHashSet::default(),
+            blocked: RuleTable::default(),
+            important: RuleTable::default(),
+            allowed: RuleTable::default(),
+            important_allowed: RuleTable::default(),
+            sources: Vec::new(),
             whitelist: FxHashSet::default(),
             domain_count: 0,
         }
     }
 
-    /// Replace the blocked domain set
+    /// Replace the rules with a plain set of blocked domains (and their
+    /// subdomains).
     pub fn set_blocked(&mut self, domains: FxHashSet<String>) {
-        self.domain_count = domains.len();
-        self.blocked = domains;
+        let mut list = RuleList::default();
+        let source = list.source_index("custom");
+        list.rules = domains.into_iter().map(|d| (Rule::block(Pattern::Subtree(d)), source)).collect();
+        self.set_rules(list);
+    }
+
+    /// Replace the rules of all lists.
+    pub fn set_rules(&mut self, list: RuleList) {
+        let by_kind = |f: &dyn Fn(&Rule) -> bool| -> Vec<(&Rule, u16)> {
+            list.rules.iter().filter(|(r, _)| f(r)).map(|(r, s)| (r, *s)).collect()
+        };
+        self.blocked = RuleTable::build(by_kind(&|r| !r.allow && !r.important), "", "");
+        self.important = RuleTable::build(by_kind(&|r| !r.allow && r.important), "", "$important");
+        self.allowed = RuleTable::build(by_kind(&|r| r.allow && !r.important), "@@", "");
+        self.important_allowed = RuleTable::build(by_kind(&|r| r.allow && r.important), "@@", "$important");
+        self.domain_count = self.blocked.len() + self.important.len();
+        self.sources = list.sources;
     }
 
     /// Replace the whitelist
@@ -30,46 +183,104 @@ impl AdblockEngine {
             .collect();
     }
 
-    /// Check if a domain is blocked (hierarchical matching with whitelist priority).
-    pub fn is_blocked(&self, domain: &str) -> bool {
-        let domain = domain.to_lowercase();
-
-        // Walk the domain hierarchy: ads.tracker.com → tracker.com → com
-        let mut check = domain.as_str();
+    fn whitelisted<'a>(&self, domain: &'a str) -> Option<&'a str> {
+        let mut check = domain;
         loop {
-            // Check whitelist first
             if self.whitelist.contains(check) {
-                return false;
+                return Some(check);
             }
-            // Check blocklist
-            if self.blocked.contains(check) {
-                return true;
-            }
-            // Walk up one level
             match check.find('.') {
                 Some(pos) => check = &check[pos + 1..],
-                None => break,
+                None => return None,
             }
         }
+    }
+
+    /// Check if a domain is blocked: whitelist, then `$important`
+    /// exceptions and rules, then exceptions, then blocking rules.
+    pub fn is_blocked(&self, domain: &str) -> bool {
+        let domain = domain.to_lowercase();
+        if self.whitelisted(&domain).is_some() || self.important_allowed.contains(&domain) {
+            return false;
+        }
+        if self.important.contains(&domain) {
+            return true;
+        }
+        if self.allowed.contains(&domain) {
+            return false;
+        }
+        self.blocked.contains(&domain)
+    }
+
+    /// The rule deciding for `domain`, `None` when no rule applies.
+    pub fn explain(&self, domain: &str) -> Option<RuleMatch> {
+        let domain = domain.to_lowercase();
+        let found = |blocked: bool, (rule, source): (String, u16)| RuleMatch {
+            blocked,
+            rule,
+            source: self.sources.get(source as usize).cloned().unwrap_or_default(),
+            name: domain.clone(),
+        };
+        if let Some(entry) = self.whitelisted(&domain) {
+            return Some(RuleMatch {
+                blocked: false,
+                rule: entry.to_string(),
+                source: WHITELIST_SOURCE.to_string(),
+                name: domain.clone(),
+            });
+        }
+        if let Some(m) = self.important_allowed.find(&domain) {
+            return Some(found(false, m));
+        }
+        if let Some(m) = self.important.find(&domain) {
+            return Some(found(true, m));
+        }
+        if let Some(m) = self.allowed.find(&domain) {
+            return Some(found(false, m));
+        }
+        self.blocked.find(&domain).map(|m| found(true, m))
+    }
 
-        false
+    /// CNAME cloaking: the first target of the chain of `name` that is
+    /// blocked, with the rule. A name allowed explicitly (whitelist or
+    /// exception) is trusted with its targets.
+    pub fn blocked_cname<'a>(&self, name: &str, targets: impl IntoIterator<Item = &'a str>) -> Option<RuleMatch> {
+        if self.explain(name).is_some_and(|m| !m.blocked) {
+            return None;
+        }
+        targets
+            .into_iter()
+            .filter(|t| self.is_blocked(t))
+            .find_map(|t| self.explain(t))
     }
 
-    /// Search blocked domains containing a query string
+    /// Search blocked domains and rules containing a query string
     pub fn search(&self, query: &str, limit: usize) -> Vec<String> {
         let query = query.to_lowercase();
-        self.blocked
+        let tables = [&self.important, &self.blocked];
+        tables
             .iter()
+            .flat_map(|t| {
+                t.subtree
+                    .keys()
+                    .chain(t.exact.keys())
+                    .chain(t.pattern_rules.iter().map(|(text, _)| text))
+            })
             .filter(|d| d.contains(&query))
             .take(limit)
             .cloned()
             .collect()
     }
 
+    /// Number of blocking rules (domains, wildcards and regexes).
     pub fn domain_count(&self) -> usize {
         self.domain_count
     }
 
+    pub fn exception_count(&self) -> usize {
+        self.allowed.len() + self.important_allowed.len()
+    }
+
     pub fn whitelist_domains(&self) -> Vec<String> {
         self.whitelist.iter().cloned().collect()
     }
@@ -84,6 +295,7 @@ impl Default for AdblockEngine {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::rules::{parse_line, Parsed};
 
     fn make_filter() -> AdblockEngine {
         let mut f = AdblockEngine::new();
@@ -96,6 +308,20 @@ mod tests {
         f
     }
 
+    fn rules_filter(lines: &[(&str, &str)]) -> AdblockEngine {
+        let mut list = RuleList::default();
+        for (source, line) in lines {
+            let index = list.source_index(source);
+            match parse_line(line) {
+                Parsed::Rule(rule) => list.rules.push((rule, index)),
+                other => panic!("{line}: {other:?}"),
+            }
+        }
+        let mut f = AdblockEngine::new();
+        f.set_rules(list);
+        f
+    }
+
     #[test]
     fn test_exact_match() {
         let f = make_filter();
@@ -141,4 +367,49 @@ mod tests {
         assert!(f.is_blocked("ADS.EXAMPLE.COM"));
         assert!(f.is_blocked("Tracker.Net"));
     }
+
+    #[test]
+    fn test_adblock_rules() {
+        let f = rules_filter(&[
+            ("AdGuard DNS", "||example.com^"),
+            ("AdGuard DNS", "@@||cdn.example.com^"),
+            ("AdGuard DNS", "|exact.org^"),
+            ("Custom", "||ads*.media.net^"),
+            ("Custom", r"/^track[0-9]+\./"),
+            ("Custom", "||metrics.cdn.example.com^$important"),
+        ]);
+        assert_eq!(f.domain_count(), 5);
+        assert_eq!(f.exception_count(), 1);
+
+        assert!(f.is_blocked("www.example.com"));
+        assert!(!f.is_blocked("img.cdn.example.com"));
+        assert!(f.is_blocked("metrics.cdn.example.com"));
+        assert!(f.is_blocked("exact.org"));
+        assert!(!f.is_blocked("www.exact.org"));
+        assert!(f.is_blocked("ads12.media.net") && f.is_blocked("eu.ads.media.net"));
+        assert!(!f.is_blocked("media.net"));
+        assert!(f.is_blocked("track42.example.org") && !f.is_blocked("tracker.example.org"));
+
+        let m = f.explain("www.example.com").unwrap();
+        assert_eq!((m.blocked, m.rule.as_str(), m.source.as_str()), (true, "||example.com^", "AdGuard DNS"));
+        let m = f.explain("img.cdn.example.com").unwrap();
+        assert_eq!((m.blocked, m.rule.as_str()), (false, "@@||cdn.example.com^"));
+        assert_eq!(f.explain("ads12.media.net").unwrap().rule, "||ads*.media.net^");
+        assert_eq!(f.explain("metrics.cdn.example.com").unwrap().rule, "||metrics.cdn.example.com^$important");
+        assert_eq!(f.explain("github.com"), None);
+        assert!(f.search("ads*", 10).contains(&"||ads*.media.net^".to_string()));
+    }
+
+    #[test]
+    fn test_cname_cloaking() {
+        let mut f = rules_filter(&[("Trackers", "||eulerian.net^")]);
+        f.set_whitelist(vec!["ok.eulerian.net".to_string()]);
+        let m = f.blocked_cname("shop.example.fr", ["shop.example.fr.eu1.cdn.net", "abc.eulerian.net"]).unwrap();
+        assert_eq!((m.name.as_str(), m.source.as_str()), ("abc.eulerian.net", "Trackers"));
+        assert_eq!(f.blocked_cname("shop.example.fr", ["ok.eulerian.net"]), None);
+        f.set_whitelist(vec!["ok.eulerian.net".to_string(), "example.fr".to_string()]);
+        assert_eq!(f.blocked_cname("shop.example.fr", ["abc.eulerian.net"]), None);
+        let m = f.explain("ok.eulerian.net").unwrap();
+        assert_eq!((m.blocked, m.source.as_str()), (false, WHITELIST_SOURCE));
+    }
 }
@@ -1,5 +1,9 @@
+pub mod cache;
 pub mod config;
 pub mod filter;
+pub mod rules;
+pub mod schedule;
 pub mod sources;
 
-pub use filter::AdblockEngine;
+pub use filter::{AdblockEngine, RuleList, RuleMatch};
+pub use schedule::{AdblockProfile, AdblockSchedule};
@@ -0,0 +1,244 @@
+//! AdGuard / Adblock Plus rule syntax, reduced to what applies to DNS:
+//! `||domain^` (domain and subdomains), `|domain^` and bare domains (that
+//! name only), `*` wildcards, `/regex/` rules, `@@` exceptions and the
+//! `$important` modifier. Cosmetic rules, URL rules (with a path) and rules
+//! carrying other modifiers are skipped, as AdGuard Home does.
+
+use serde::Serialize;
+
+/// What a rule matches.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "kind", content = "value", rename_all = "lowercase")]
+pub enum Pattern {
+    /// The domain and all its subdomains.
+    Subtree(String),
+    /// The domain alone.
+    Exact(String),
+    /// Regular expression tested against the whole name.
+    Regex(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rule {
+    pub pattern: Pattern,
+    /// `@@` exception.
+    pub allow: bool,
+    /// `$important`: wins over exceptions.
+    pub important: bool,
+    /// Text as written in the list, kept for wildcard and regex rules
+    /// (domain rules are rebuilt by `text()`).
+    pub source_text: Option<String>,
+}
+
+impl Rule {
+    pub fn block(pattern: Pattern) -> Self {
+        Self { pattern, allow: false, important: false, source_text: None }
+    }
+
+    /// The rule in adblock syntax.
+    pub fn text(&self) -> String {
+        if let Some(text) = &self.source_text {
+            return text.clone();
+        }
+        let body = match &self.pattern {
+            Pattern::Subtree(d) => format!("||{d}^"),
+            Pattern::Exact(d) => format!("|{d}^"),
+            Pattern::Regex(r) => format!("/{r}/"),
+        };
+        let prefix = if self.allow { "@@" } else { "" };
+        let suffix = if self.important { "$important" } else { "" };
+        format!("{prefix}{body}{suffix}")
+    }
+}
+
+/// Outcome of parsing one line.
+#[derive(Debug, PartialEq)]
+pub enum Parsed {
+    Rule(Rule),
+    /// Comment, header or blank line.
+    Ignored,
+    /// Valid for browsers but meaningless or unsupported for DNS.
+    Unsupported,
+}
+
+fn is_domain_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == '_'
+}
+
+/// Translate an ABP wildcard pattern (body without anchors) to a regex.
+fn wildcard_regex(body: &str, subtree_anchor: bool, start_anchor: bool, end_anchor: bool) -> String {
+    let mut re = String::new();
+    if subtree_anchor {
+        re.push_str(r"(^|\.)");
+    } else if start_anchor {
+        re.push('^');
+    }
+    for c in body.chars() {
+        match c {
+            '*' => re.push_str(".*"),
+            '.' => re.push_str(r"\."),
+            c => re.push(c),
+        }
+    }
+    if end_anchor {
+        re.push('$');
+    }
+    re
+}
+
+/// Parse one line of an AdGuard/ABP list.
+pub fn parse_line(line: &str) -> Parsed {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('!') || line.starts_with('#') || line.starts_with('[') {
+        return Parsed::Ignored;
+    }
+    // Cosmetic and scriptlet rules
+    if line.contains("##") || line.contains("#@#") || line.contains("#$#") || line.contains("#%#") || line.contains("#?#") {
+        return Parsed::Unsupported;
+    }
+
+    let (allow, rest) = match line.strip_prefix("@@") {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+
+    // Regex rule: /.../ with optional modifiers after the closing slash
+    let (body, modifiers) = if rest.starts_with('/') && rest.len() > 2 {
+        match rest.rfind('/') {
+            Some(end) if end > 0 => {
+                let tail = &rest[end + 1..];
+                match tail.strip_prefix('$') {
+                    Some(m) => (&rest[..=end], Some(m)),
+                    None if tail.is_empty() => (rest, None),
+                    None => return Parsed::Unsupported,
+                }
+            }
+            _ => return Parsed::Unsupported,
+        }
+    } else {
+        match rest.split_once('$') {
+            Some((body, m)) => (body, Some(m)),
+            None => (rest, None),
+        }
+    };
+
+    let mut important = false;
+    if let Some(modifiers) = modifiers {
+        for modifier in modifiers.split(',').map(str::trim) {
+            match modifier {
+                "important" => important = true,
+                _ => return Parsed::Unsupported,
+            }
+        }
+    }
+
+    let pattern = if body.len() > 2 && body.starts_with('/') && body.ends_with('/') {
+        let re = &body[1..body.len() - 1];
+        if regex::Regex::new(re).is_err() {
+            return Parsed::Unsupported;
+        }
+        Pattern::Regex(re.to_string())
+    } else {
+        let (subtree, body) = match body.strip_prefix("||") {
+            Some(b) => (true, b),
+            None => (false, body),
+        };
+        let (start, body) = match body.strip_prefix('|') {
+            Some(b) if !subtree => (true, b),
+            _ => (false, body),
+        };
+        let (end, body) = match body.strip_suffix('^').or_else(|| body.strip_suffix('|')) {
+            Some(b) => (true, b),
+            None => (false, body),
+        };
+        let body = body.to_lowercase();
+        if body.is_empty() || body == "*" || !body.chars().all(|c| is_domain_char(c) || c == '*') {
+            // Paths, ports and the like: URL rules
+            return Parsed::Unsupported;
+        }
+        let valid_domain = !body.contains('*')
+            && body.contains('.')
+            && !body.starts_with('.')
+            && !body.ends_with('.');
+        match (subtree, start, end) {
+            (true, _, _) if valid_domain => Pattern::Subtree(body),
+            (false, _, _) if valid_domain && (end || !start) => {
+                // `|example.org^` and AdGuard's domain-only `example.org`
+                Pattern::Exact(body)
+            }
+            _ => Pattern::Regex(wildcard_regex(&body, subtree, start, end)),
+        }
+    };
+    let source_text = matches!(pattern, Pattern::Regex(_)).then(|| line.to_string());
+    Parsed::Rule(Rule { pattern, allow, important, source_text })
+}
+
+/// Rules of an AdGuard/ABP list; unsupported ones are counted.
+pub fn parse_list(content: &str) -> (Vec<Rule>, usize) {
+    let mut rules = Vec::new();
+    let mut unsupported = 0;
+    for line in content.lines() {
+        match parse_line(line) {
+            Parsed::Rule(rule) => rules.push(rule),
+            Parsed::Ignored => {}
+            Parsed::Unsupported => unsupported += 1,
+        }
+    }
+    (rules, unsupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(line: &str) -> Rule {
+        match parse_line(line) {
+            Parsed::Rule(r) => r,
+            other => panic!("{line}: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_domain_rules() {
+        assert_eq!(rule("||Ads.Example.com^").pattern, Pattern::Subtree("ads.example.com".to_string()));
+        assert_eq!(rule("||ads.example.com").pattern, Pattern::Subtree("ads.example.com".to_string()));
+        assert_eq!(rule("|ads.example.com^").pattern, Pattern::Exact("ads.example.com".to_string()));
+        assert_eq!(rule("tracker.net").pattern, Pattern::Exact("tracker.net".to_string()));
+
+        let exception = rule("@@||cdn.example.com^");
+        assert!(exception.allow && !exception.important);
+        assert_eq!(exception.text(), "@@||cdn.example.com^");
+        let important = rule("||metrics.example.com^$important");
+        assert!(important.important);
+        assert_eq!(important.text(), "||metrics.example.com^$important");
+    }
+
+    #[test]
+    fn test_wildcards_and_regex() {
+        let r = rule("||ads*.example.com^");
+        assert_eq!(r.pattern, Pattern::Regex(r"(^|\.)ads.*\.example\.com$".to_string()));
+        assert_eq!(r.text(), "||ads*.example.com^");
+        assert_eq!(rule("*.doubleclick.*").pattern, Pattern::Regex(r".*\.doubleclick\..*".to_string()));
+        assert_eq!(rule("|ad*^").pattern, Pattern::Regex("^ad.*$".to_string()));
+
+        let re = rule(r"/^ad[0-9]+\./");
+        assert_eq!(re.pattern, Pattern::Regex(r"^ad[0-9]+\.".to_string()));
+        assert!(rule(r"@@/^safe\./$important").allow);
+        assert_eq!(parse_line("/[unclosed/"), Parsed::Unsupported);
+    }
+
+    #[test]
+    fn test_skipped_lines() {
+        assert_eq!(parse_line("! Title: list"), Parsed::Ignored);
+        assert_eq!(parse_line("[Adblock Plus 2.0]"), Parsed::Ignored);
+        assert_eq!(parse_line("# comment"), Parsed::Ignored);
+        assert_eq!(parse_line("example.com##.banner"), Parsed::Unsupported);
+        assert_eq!(parse_line("||example.com/ads/*"), Parsed::Unsupported);
+        assert_eq!(parse_line("||example.com^$third-party"), Parsed::Unsupported);
+        assert_eq!(parse_line("||example.com^$client=192.168.1.2"), Parsed::Unsupported);
+        assert_eq!(parse_line("||*^"), Parsed::Unsupported);
+
+        let (rules, unsupported) = parse_list("! header\n||a.com^\n@@||b.a.com^\nx.com##div\n");
+        assert_eq!((rules.len(), unsupported), (2, 1));
+    }
+}
@@ -0,0 +1,504 @@
+use std::net::IpAddr;
+use std::sync::RwLock;
+
+use chrono::{DateTime, Datelike, NaiveDateTime, Timelike, Utc};
+use ipnet::IpNet;
+use rustc_hash::FxHashSet;
+use serde::{Deserialize, Serialize};
+
+/// Time-based blocking profile, e.g. no YouTube on the kids' devices
+/// after 21:00.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdblockProfile {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default = "yes")]
+    pub enabled: bool,
+    /// Client IPs, CIDR ranges or MAC addresses (matched through the DHCP
+    /// leases); every client when empty.
+    #[serde(default)]
+    pub clients: Vec<String>,
+    /// Weekdays 0-7 (0 and 7 = Sunday); every day when empty.
+    #[serde(default)]
+    pub days: Vec<u8>,
+    /// `HH:MM`; the window closes the next day when `end` is not after `start`.
+    pub start: String,
+    pub end: String,
+    /// Domains blocked, with their subdomains, while the window is open.
+    pub domains: Vec<String>,
+}
+
+fn yes() -> bool {
+    true
+}
+
+fn parse_time(time: &str) -> Result<u32, String> {
+    let invalid = || format!("Invalid time: {time} (HH:MM)");
+    let (h, m) = time.trim().split_once(':').ok_or_else(invalid)?;
+    let (h, m): (u32, u32) = (h.parse().map_err(|_| invalid())?, m.parse().map_err(|_| invalid())?);
+    if h > 23 || m > 59 {
+        return Err(invalid());
+    }
+    Ok(h * 60 + m)
+}
+
+fn is_mac(client: &str) -> bool {
+    let parts: Vec<&str> = client.split([':', '-']).collect();
+    parts.len() == 6 && parts.iter().all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// A DNS client, as seen by the resolver.
+#[derive(Debug, Clone, Copy)]
+pub struct Client<'a> {
+    pub ip: IpAddr,
+    /// From the DHCP lease of `ip`, lowercase with colons.
+    pub mac: Option<&'a str>,
+}
+
+/// Clients given as IPs, CIDR ranges or MAC addresses.
+#[derive(Debug, Clone, Default)]
+pub struct ClientSet {
+    networks: Vec<IpNet>,
+    macs: Vec<String>,
+}
+
+impl ClientSet {
+    pub fn parse(clients: &[String]) -> Result<Self, String> {
+        let mut set = Self::default();
+        for client in clients {
+            let client = client.trim();
+            if is_mac(client) {
+                set.macs.push(client.to_lowercase().replace('-', ":"));
+            } else if let Ok(net) = client.parse::<IpNet>() {
+                set.networks.push(net);
+            } else if let Ok(ip) = client.parse::<IpAddr>() {
+                set.networks.push(IpNet::from(ip));
+            } else {
+                return Err(format!("Invalid client {client} (IP, CIDR or MAC)"));
+            }
+        }
+        Ok(set)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty() && self.macs.is_empty()
+    }
+
+    /// Whether matching needs the client's MAC address.
+    pub fn has_macs(&self) -> bool {
+        !self.macs.is_empty()
+    }
+
+    pub fn contains(&self, client: Client) -> bool {
+        self.networks.iter().any(|n| n.contains(&client.ip))
+            || client.mac.is_some_and(|mac| self.macs.iter().any(|m| m.eq_ignore_ascii_case(mac)))
+    }
+}
+
+struct CompiledProfile {
+    profile: AdblockProfile,
+    /// Bit n set for weekday n (0 = Sunday).
+    days: u8,
+    start: u32,
+    end: u32,
+    clients: ClientSet,
+    domains: FxHashSet<String>,
+}
+
+impl CompiledProfile {
+    fn compile(profile: &AdblockProfile) -> Result<Self, String> {
+        if profile.name.trim().is_empty() {
+            return Err("Profile name required".to_string());
+        }
+        if profile.days.iter().any(|d| *d > 7) {
+            return Err(format!("{}: days must be weekdays 0-7 (0 and 7 = Sunday)", profile.name));
+        }
+        let start = parse_time(&profile.start)?;
+        let end = parse_time(&profile.end)?;
+        if start == end {
+            return Err(format!("{}: the window starts and ends at the same time", profile.name));
+        }
+        let clients = ClientSet::parse(&profile.clients).map_err(|e| format!("{}: {e}", profile.name))?;
+        let domains: FxHashSet<String> = profile
+            .domains
+            .iter()
+            .map(|d| d.trim().trim_end_matches('.').to_lowercase())
+            .filter(|d| !d.is_empty())
+            .collect();
+        if domains.is_empty() {
+            return Err(format!("{}: no domain to block", profile.name));
+        }
+        let days = if profile.days.is_empty() {
+            0x7f
+        } else {
+            profile.days.iter().fold(0u8, |acc, d| acc | 1 << (d % 7))
+        };
+        Ok(Self { profile: profile.clone(), days, start, end, clients, domains })
+    }
+
+    fn active_at(&self, now: NaiveDateTime) -> bool {
+        if !self.profile.enabled {
+            return false;
+        }
+        let minute = now.hour() * 60 + now.minute();
+        let today = now.weekday().num_days_from_sunday();
+        let on = |day: u32| self.days & (1 << day) != 0;
+        if self.start < self.end {
+            on(today) && (self.start..self.end).contains(&minute)
+        } else {
+            // Overnight: the tail after midnight belongs to the previous day
+            (on(today) && minute >= self.start) || (on((today + 6) % 7) && minute < self.end)
+        }
+    }
+
+    fn applies_to(&self, client: Client) -> bool {
+        self.clients.is_empty() || self.clients.contains(client)
+    }
+
+    fn blocks(&self, domain: &str) -> bool {
+        let mut check = domain;
+        loop {
+            if self.domains.contains(check) {
+                return true;
+            }
+            match check.find('.') {
+                Some(pos) => check = &check[pos + 1..],
+                None => return false,
+            }
+        }
+    }
+}
+
+/// Blocking paused until `until`, for one client or every client.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pause {
+    pub client: Option<IpAddr>,
+    pub until: DateTime<Utc>,
+}
+
+/// A domain (with its subdomains) unblocked until `until`, for one client
+/// or every client.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemporaryAllow {
+    pub domain: String,
+    pub client: Option<IpAddr>,
+    pub until: DateTime<Utc>,
+}
+
+/// A pause, temporary allow or profile whose state flipped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScheduleChange {
+    Paused(Pause),
+    Resumed { client: Option<IpAddr> },
+    Allowed(TemporaryAllow),
+    AllowExpired { domain: String, client: Option<IpAddr> },
+    ProfileActive { id: String, name: String },
+    ProfileInactive { id: String, name: String },
+}
+
+fn compile_profiles(profiles: &[AdblockProfile]) -> Result<Vec<CompiledProfile>, String> {
+    let mut compiled = Vec::with_capacity(profiles.len());
+    let mut ids = FxHashSet::default();
+    for (i, profile) in profiles.iter().enumerate() {
+        let mut c = CompiledProfile::compile(profile)?;
+        if c.profile.id.is_empty() {
+            c.profile.id = format!("profile-{}", i + 1);
+        }
+        if !ids.insert(c.profile.id.clone()) {
+            return Err(format!("Duplicate profile id {}", c.profile.id));
+        }
+        compiled.push(c);
+    }
+    Ok(compiled)
+}
+
+/// Check profiles as `set_profiles` would, without applying them.
+pub fn validate_profiles(profiles: &[AdblockProfile]) -> Result<(), String> {
+    compile_profiles(profiles).map(|_| ())
+}
+
+#[derive(Default)]
+struct Inner {
+    pauses: Vec<Pause>,
+    allows: Vec<TemporaryAllow>,
+    profiles: Vec<CompiledProfile>,
+    /// Profiles seen active at the last tick.
+    active: FxHashSet<String>,
+}
+
+/// Pauses, temporary allows and scheduled profiles, shared by the DNS resolver and the API.
+/// `tick` reports the state flips, for event emission.
+#[derive(Default)]
+pub struct AdblockSchedule {
+    inner: RwLock<Inner>,
+}
+
+impl AdblockSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the profiles; ids are assigned to profiles without one.
+    pub fn set_profiles(&self, profiles: &[AdblockProfile]) -> Result<(), String> {
+        let compiled = compile_profiles(profiles)?;
+        self.inner.write().unwrap().profiles = compiled;
+        Ok(())
+    }
+
+    pub fn profiles(&self) -> Vec<AdblockProfile> {
+        self.inner.read().unwrap().profiles.iter().map(|c| c.profile.clone()).collect()
+    }
+
+    /// Pause blocking for `minutes`, for `client` or every client.
+    pub fn pause(&self, client: Option<IpAddr>, minutes: u32, now: DateTime<Utc>) -> Pause {
+        let pause = Pause { client, until: now + chrono::Duration::minutes(minutes as i64) };
+        let mut inner = self.inner.write().unwrap();
+        inner.pauses.retain(|p| p.client != client);
+        inner.pauses.push(pause.clone());
+        pause
+    }
+
+    /// End the pause of `client` (every client when `None`); `false` when
+    /// there was none.
+    pub fn resume(&self, client: Option<IpAddr>) -> bool {
+        let mut inner = self.inner.write().unwrap();
+        let before = inner.pauses.len();
+        inner.pauses.retain(|p| p.client != client);
+        inner.pauses.len() != before
+    }
+
+    pub fn pauses(&self, now: DateTime<Utc>) -> Vec<Pause> {
+        self.inner.read().unwrap().pauses.iter().filter(|p| p.until > now).cloned().collect()
+    }
+
+    /// Whether list blocking is paused for `ip`, globally or for it alone.
+    pub fn is_paused(&self, ip: IpAddr, now: DateTime<Utc>) -> bool {
+        self.inner
+            .read()
+            .unwrap()
+            .pauses
+            .iter()
+            .any(|p| p.until > now && p.client.is_none_or(|c| c == ip))
+    }
+
+    /// Unblock `domain` and its subdomains for `minutes`, for `client` or
+    /// every client.
+    pub fn allow(&self, domain: &str, client: Option<IpAddr>, minutes: u32, now: DateTime<Utc>) -> TemporaryAllow {
+        let allow = TemporaryAllow {
+            domain: domain.trim().trim_end_matches('.').to_lowercase(),
+            client,
+            until: now + chrono::Duration::minutes(minutes as i64),
+        };
+        let mut inner = self.inner.write().unwrap();
+        inner.allows.retain(|a| !(a.domain == allow.domain && a.client == client));
+        inner.allows.push(allow.clone());
+        allow
+    }
+
+    pub fn allows(&self, now: DateTime<Utc>) -> Vec<TemporaryAllow> {
+        self.inner.read().unwrap().allows.iter().filter(|a| a.until > now).cloned().collect()
+    }
+
+    /// Whether `domain` is temporarily unblocked for `ip`.
+    pub fn is_allowed(&self, domain: &str, ip: IpAddr, now: DateTime<Utc>) -> bool {
+        let inner = self.inner.read().unwrap();
+        if inner.allows.is_empty() {
+            return false;
+        }
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        inner.allows.iter().any(|a| {
+            a.until > now
+                && a.client.is_none_or(|c| c == ip)
+                && (domain == a.domain
+                    || domain.strip_suffix(a.domain.as_str()).is_some_and(|rest| rest.ends_with('.')))
+        })
+    }
+
+    /// Whether some profile matches clients by MAC, i.e. the resolver has
+    /// to look the client's lease up.
+    pub fn needs_mac(&self) -> bool {
+        self.inner.read().unwrap().profiles.iter().any(|c| c.clients.has_macs())
+    }
+
+    /// Name of the active profile blocking `domain` for `client`.
+    pub fn profile_block(&self, domain: &str, client: Client, now: NaiveDateTime) -> Option<String> {
+        let inner = self.inner.read().unwrap();
+        if inner.profiles.is_empty() {
+            return None;
+        }
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        inner
+            .profiles
+            .iter()
+            .find(|c| c.active_at(now) && c.applies_to(client) && c.blocks(&domain))
+            .map(|c| c.profile.name.clone())
+    }
+
+    /// Ids of the profiles whose window is open at `now`.
+    pub fn active_profiles(&self, now: NaiveDateTime) -> Vec<String> {
+        let inner = self.inner.read().unwrap();
+        inner.profiles.iter().filter(|c| c.active_at(now)).map(|c| c.profile.id.clone()).collect()
+    }
+
+    /// Drop expired pauses and allows, and note the profiles opening or closing since
+    /// the last tick. `utc` and `local` are the same instant.
+    pub fn tick(&self, utc: DateTime<Utc>, local: NaiveDateTime) -> Vec<ScheduleChange> {
+        let mut inner = self.inner.write().unwrap();
+        let mut changes = Vec::new();
+        inner.pauses.retain(|p| {
+            let live = p.until > utc;
+            if !live {
+                changes.push(ScheduleChange::Resumed { client: p.client });
+            }
+            live
+        });
+        inner.allows.retain(|a| {
+            let live = a.until > utc;
+            if !live {
+                changes.push(ScheduleChange::AllowExpired { domain: a.domain.clone(), client: a.client });
+            }
+            live
+        });
+
+        let mut active = FxHashSet::default();
+        for c in &inner.profiles {
+            if c.active_at(local) {
+                active.insert(c.profile.id.clone());
+                if !inner.active.contains(&c.profile.id) {
+                    changes.push(ScheduleChange::ProfileActive { id: c.profile.id.clone(), name: c.profile.name.clone() });
+                }
+            } else if inner.active.contains(&c.profile.id) {
+                changes.push(ScheduleChange::ProfileInactive { id: c.profile.id.clone(), name: c.profile.name.clone() });
+            }
+        }
+        inner.active = active;
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    // 2026-10-12 is a Monday
+    fn at(day: u32, h: u32, m: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, day).unwrap().and_hms_opt(h, m, 0).unwrap()
+    }
+
+    fn kids() -> AdblockProfile {
+        serde_json::from_value(serde_json::json!({
+            "name": "Kids",
+            "clients": ["192.168.1.50", "192.168.2.0/24", "AA-BB-CC-DD-EE-FF"],
+            "days": [1, 2, 3, 4, 7],
+            "start": "21:00",
+            "end": "07:00",
+            "domains": ["youtube.com"],
+        }))
+        .unwrap()
+    }
+
+    fn client(ip: &str) -> Client<'static> {
+        Client { ip: ip.parse().unwrap(), mac: None }
+    }
+
+    #[test]
+    fn test_profile_window() {
+        let schedule = AdblockSchedule::new();
+        schedule.set_profiles(&[kids()]).unwrap();
+        let kid = client("192.168.1.50");
+
+        assert_eq!(schedule.profile_block("www.youtube.com", kid, at(12, 21, 30)).as_deref(), Some("Kids"));
+        assert!(schedule.profile_block("youtube.com", kid, at(12, 20, 59)).is_none());
+        // Tuesday morning, tail of Monday's window
+        assert!(schedule.profile_block("youtube.com", kid, at(13, 6, 59)).is_some());
+        assert!(schedule.profile_block("youtube.com", kid, at(13, 7, 0)).is_none());
+        // Friday night is not scheduled, Saturday morning neither
+        assert!(schedule.profile_block("youtube.com", kid, at(16, 22, 0)).is_none());
+        assert!(schedule.profile_block("youtube.com", kid, at(17, 1, 0)).is_none());
+        // Sunday given as 7
+        assert!(schedule.profile_block("youtube.com", kid, at(18, 23, 0)).is_some());
+
+        assert!(schedule.profile_block("example.com", kid, at(12, 22, 0)).is_none());
+        assert!(schedule.profile_block("notyoutube.com", kid, at(12, 22, 0)).is_none());
+    }
+
+    #[test]
+    fn test_profile_clients() {
+        let schedule = AdblockSchedule::new();
+        schedule.set_profiles(&[kids()]).unwrap();
+        let night = at(12, 22, 0);
+
+        assert!(schedule.profile_block("youtube.com", client("192.168.2.7"), night).is_some());
+        assert!(schedule.profile_block("youtube.com", client("192.168.1.51"), night).is_none());
+        let by_mac = Client { ip: "192.168.1.51".parse().unwrap(), mac: Some("aa:bb:cc:dd:ee:ff") };
+        assert!(schedule.needs_mac());
+        assert!(schedule.profile_block("youtube.com", by_mac, night).is_some());
+
+        let mut bad = kids();
+        bad.clients = vec!["kid-laptop".to_string()];
+        assert!(schedule.set_profiles(&[bad]).is_err());
+    }
+
+    #[test]
+    fn test_pause() {
+        let schedule = AdblockSchedule::new();
+        let now = Utc::now();
+        let ip: IpAddr = "192.168.1.50".parse().unwrap();
+        let other: IpAddr = "192.168.1.51".parse().unwrap();
+
+        schedule.pause(Some(ip), 10, now);
+        assert!(schedule.is_paused(ip, now));
+        assert!(!schedule.is_paused(other, now));
+        assert!(!schedule.is_paused(ip, now + chrono::Duration::minutes(11)));
+
+        schedule.pause(None, 5, now);
+        assert!(schedule.is_paused(other, now));
+        assert!(schedule.resume(None));
+        assert!(!schedule.is_paused(other, now));
+        assert!(!schedule.resume(None));
+    }
+
+    #[test]
+    fn test_temporary_allow() {
+        let schedule = AdblockSchedule::new();
+        let now = Utc::now();
+        let ip: IpAddr = "192.168.1.50".parse().unwrap();
+        let other: IpAddr = "192.168.1.51".parse().unwrap();
+
+        schedule.allow("Ads.Example.", Some(ip), 15, now);
+        assert!(schedule.is_allowed("ads.example", ip, now));
+        assert!(schedule.is_allowed("cdn.ads.example.", ip, now));
+        assert!(!schedule.is_allowed("badads.example", ip, now));
+        assert!(!schedule.is_allowed("ads.example", other, now));
+        assert!(!schedule.is_allowed("ads.example", ip, now + chrono::Duration::minutes(16)));
+
+        schedule.allow("ads.example", None, 5, now);
+        assert!(schedule.is_allowed("ads.example", other, now));
+        assert_eq!(schedule.allows(now).len(), 2);
+
+        let changes = schedule.tick(now + chrono::Duration::minutes(6), at(12, 12, 0));
+        assert_eq!(changes, [ScheduleChange::AllowExpired { domain: "ads.example".to_string(), client: None }]);
+        assert!(!schedule.is_allowed("ads.example", other, now));
+    }
+
+    #[test]
+    fn test_tick_changes() {
+        let schedule = AdblockSchedule::new();
+        schedule.set_profiles(&[kids()]).unwrap();
+        let now = Utc::now();
+        schedule.pause(None, 1, now);
+
+        assert!(schedule.tick(now, at(12, 20, 0)).is_empty());
+        let changes = schedule.tick(now + chrono::Duration::minutes(2), at(12, 21, 0));
+        assert_eq!(changes.len(), 2);
+        assert!(changes.contains(&ScheduleChange::Resumed { client: None }));
+        assert!(matches!(&changes[1], ScheduleChange::ProfileActive { name, .. } if name == "Kids"));
+        assert!(schedule.tick(now, at(12, 22, 0)).is_empty());
+        assert!(matches!(schedule.tick(now, at(13, 7, 0))[..], [ScheduleChange::ProfileInactive { .. }]));
+    }
+}
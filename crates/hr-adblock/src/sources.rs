@@ -1,82 +1,176 @@
+use std::path::Path;
+
 use anyhow::Result;
-use rustc_hash::FxHashSet;
 use tracing::{info, warn};
 
+use crate::cache::{self, ListCache, SourceCache};
 use crate::config::AdblockSource;
+use crate::filter::RuleList;
+use crate::rules::{self, Pattern, Rule};
+
+/// File of the binary list cache, in the adblock data dir.
+pub const CACHE_FILE: &str = "rules.bin";
+/// JSON cache written before the binary format, read when there is no other.
+const LEGACY_CACHE_FILE: &str = "domains.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceStatus {
+    /// Downloaded and parsed.
+    Updated,
+    /// Not modified since the cached download (304).
+    Unchanged,
+    /// Download failed: the cached rules are kept, if any.
+    Failed,
+}
+
+impl SourceStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Updated => "updated",
+            Self::Unchanged => "unchanged",
+            Self::Failed => "failed",
+        }
+    }
+}
 
 /// Source download result
 pub struct SourceResult {
     pub name: String,
     pub domain_count: usize,
+    /// Lines of an adblock-syntax list that do not apply to DNS.
+    pub unsupported: usize,
+    pub status: SourceStatus,
 }
 
-/// Download and parse all adblock sources, returning a unified set of blocked domains.
-pub async fn download_all(sources: &[AdblockSource]) -> (FxHashSet<String>, Vec<SourceResult>) {
-    let mut all_domains = FxHashSet::with_capacity_and_hasher(80_000, Default::default());
-    let mut results = Vec::new();
+/// Refresh the lists against the cache of `data_dir`: only the lists that
+/// changed are downloaded and parsed again. The cache is saved and the
+/// merged rule list returned.
+pub async fn update(sources: &[AdblockSource], data_dir: &Path) -> (RuleList, Vec<SourceResult>) {
+    let previous = read_cache(data_dir).unwrap_or_default();
+    let (cache, results) = download_all(sources, previous).await;
+    if let Err(e) = cache::save(&cache, &data_dir.join(CACHE_FILE)) {
+        warn!("Failed to save adblock cache: {}", e);
+    }
+    let list = cache.rule_list();
+    info!("Total unique adblock rules: {}", list.rules.len());
+    (list, results)
+}
+
+/// Download the lists in parallel, conditionally when `previous` has them.
+/// Lists not modified, or failing, keep their cached rules.
+pub async fn download_all(sources: &[AdblockSource], mut previous: ListCache) -> (ListCache, Vec<SourceResult>) {
+    let mut cached: Vec<Option<SourceCache>> = sources
+        .iter()
+        .map(|source| {
+            let i = previous.sources.iter().position(|c| c.name == source.name && c.url == source.url)?;
+            Some(previous.sources.swap_remove(i))
+        })
+        .collect();
 
     // Download sources in parallel
     let mut handles = Vec::new();
-    for source in sources {
+    for (source, cached) in sources.iter().zip(&cached) {
         let source = source.clone();
+        let validators = cached.as_ref().map(|c| (c.etag.clone(), c.last_modified.clone()));
         handles.push(tokio::spawn(async move {
-            download_source(&source).await
+            download_source(&source, validators).await
         }));
     }
 
+    let mut cache = ListCache::default();
+    let mut results = Vec::new();
     for (i, handle) in handles.into_iter().enumerate() {
         let source_name = sources[i].name.clone();
-        match handle.await {
-            Ok(Ok(domains)) => {
-                let count = domains.len();
-                info!("Adblock source '{}': {} domains", source_name, count);
-                results.push(SourceResult {
-                    name: source_name,
-                    domain_count: count,
-                });
-                all_domains.extend(domains);
+        let outcome = match handle.await {
+            Ok(result) => result,
+            Err(e) => Err(anyhow::anyhow!("task panicked: {e}")),
+        };
+        let (entry, status) = match outcome {
+            Ok(Some(fetched)) => {
+                info!(
+                    "Adblock source '{}': {} rules ({} unsupported)",
+                    source_name, fetched.rules.len(), fetched.unsupported
+                );
+                (Some(fetched), SourceStatus::Updated)
             }
-            Ok(Err(e)) => {
-                warn!("Failed to download adblock source '{}': {}", source_name, e);
-                results.push(SourceResult {
-                    name: source_name,
-                    domain_count: 0,
-                });
+            Ok(None) => {
+                info!("Adblock source '{}' not modified", source_name);
+                (cached[i].take(), SourceStatus::Unchanged)
             }
             Err(e) => {
-                warn!("Task panicked for source '{}': {}", source_name, e);
-                results.push(SourceResult {
-                    name: source_name,
-                    domain_count: 0,
-                });
+                warn!("Failed to download adblock source '{}': {}", source_name, e);
+                (cached[i].take(), SourceStatus::Failed)
             }
-        }
+        };
+        results.push(SourceResult {
+            name: source_name,
+            domain_count: entry.as_ref().map_or(0, |e| e.rules.len()),
+            unsupported: entry.as_ref().map_or(0, |e| e.unsupported),
+            status,
+        });
+        cache.sources.extend(entry);
     }
+    (cache, results)
+}
 
-    info!("Total unique blocked domains: {}", all_domains.len());
-    (all_domains, results)
+/// Blocking rules of a list in `format`, with the count of skipped rules.
+pub fn parse_source(body: &str, format: &str) -> (Vec<Rule>, usize) {
+    let subtree = |domains: Vec<String>| domains.into_iter().map(|d| Rule::block(Pattern::Subtree(d))).collect();
+    match format {
+        "hosts" => (subtree(parse_hosts_file(body)), 0),
+        "domain_list" => (subtree(parse_domain_list(body)), 0),
+        "dnsmasq" => (subtree(parse_dnsmasq_format(body)), 0),
+        "adblock" | "adguard" | "abp" => rules::parse_list(body),
+        _ => {
+            warn!("Unknown adblock format '{}', trying hosts", format);
+            (subtree(parse_hosts_file(body)), 0)
+        }
+    }
 }
 
-async fn download_source(source: &AdblockSource) -> Result<Vec<String>> {
+/// The list, `None` when not modified since the download that returned
+/// `validators` (ETag, Last-Modified).
+async fn download_source(
+    source: &AdblockSource,
+    validators: Option<(Option<String>, Option<String>)>,
+) -> Result<Option<SourceCache>> {
     let client = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(120))
         .connect_timeout(std::time::Duration::from_secs(30))
         .build()?;
 
-    let response = client.get(&source.url).send().await?;
-    let body = response.text().await?;
-
-    let domains = match source.format.as_str() {
-        "hosts" => parse_hosts_file(&body),
-        "domain_list" => parse_domain_list(&body),
-        "dnsmasq" => parse_dnsmasq_format(&body),
-        _ => {
-            warn!("Unknown format '{}' for source '{}', trying hosts", source.format, source.name);
-            parse_hosts_file(&body)
+    let mut request = client.get(&source.url);
+    if let Some((etag, last_modified)) = validators {
+        if let Some(etag) = etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
         }
+        if let Some(last_modified) = last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let response = request.send().await?;
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    let response = response.error_for_status()?;
+    let header = |name| {
+        response.headers().get(name).and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok()).map(str::to_string)
     };
+    let etag = header(reqwest::header::ETAG);
+    let last_modified = header(reqwest::header::LAST_MODIFIED);
+    let body = response.text().await?;
 
-    Ok(domains)
+    let format = source.format.clone();
+    let (rules, unsupported) = tokio::task::spawn_blocking(move || parse_source(&body, &format)).await?;
+    Ok(Some(SourceCache {
+        name: source.name.clone(),
+        url: source.url.clone(),
+        etag,
+        last_modified,
+        fetched_at: chrono::Utc::now().timestamp(),
+        unsupported,
+        rules,
+    }))
 }
 
 /// Parse hosts file format: `0.0.0.0 domain` or `127.0.0.1 domain`
@@ -177,23 +271,26 @@ fn is_valid_domain(domain: &str) -> bool {
         .is_some_and(|c| c.is_alphanumeric())
 }
 
-/// Save domains to a binary cache file for fast startup.
-pub fn save_cache(domains: &FxHashSet<String>, path: &std::path::Path) -> Result<()> {
-    let domains_vec: Vec<&str> = domains.iter().map(|s| s.as_str()).collect();
-    let serialized = serde_json::to_vec(&domains_vec)?;
-    std::fs::create_dir_all(path.parent().unwrap_or(path))?;
-    let tmp = path.with_extension("tmp");
-    std::fs::write(&tmp, &serialized)?;
-    std::fs::rename(&tmp, path)?;
-    Ok(())
+/// The cached lists of `data_dir`, from the binary cache or the legacy
+/// JSON one.
+fn read_cache(data_dir: &Path) -> Result<ListCache> {
+    let path = data_dir.join(CACHE_FILE);
+    if path.exists() {
+        return cache::load(&path);
+    }
+    cache::load_legacy_json(&data_dir.join(LEGACY_CACHE_FILE))
 }
 
-/// Load domains from binary cache file.
-pub fn load_cache(path: &std::path::Path) -> Result<FxHashSet<String>> {
-    let data = std::fs::read(path)?;
-    let domains: Vec<String> = serde_json::from_slice(&data)?;
-    let set: FxHashSet<String> = domains.into_iter().collect();
-    Ok(set)
+/// The merged rules of the cached lists, for a fast startup.
+pub fn load_cache(data_dir: &Path) -> Result<RuleList> {
+    Ok(read_cache(data_dir)?.rule_list())
+}
+
+/// Modification time of the cache, i.e. of the last list update.
+pub fn cache_modified(data_dir: &Path) -> Option<std::time::SystemTime> {
+    [CACHE_FILE, LEGACY_CACHE_FILE]
+        .iter()
+        .find_map(|f| std::fs::metadata(data_dir.join(f)).and_then(|m| m.modified()).ok())
 }
 
 #[cfg(test)]
@@ -231,6 +328,52 @@ mod tests {
         assert_eq!(domains.len(), 2);
     }
 
+    #[test]
+    fn test_parse_source_and_cache() {
+        let (rules, unsupported) = parse_source("||ads.com^\n@@||ok.ads.com^\nads.com##.banner\n", "adguard");
+        assert_eq!((rules.len(), unsupported), (2, 1));
+        let (rules, _) = parse_source("0.0.0.0 ads.example.com\n", "hosts");
+        assert_eq!(rules[0].pattern, Pattern::Subtree("ads.example.com".to_string()));
+
+        let dir = std::env::temp_dir().join(format!("hr-adblock-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(LEGACY_CACHE_FILE), r#"["old.example.com"]"#).unwrap();
+        let legacy = load_cache(&dir).unwrap();
+        assert_eq!(legacy.rules[0].0.pattern, Pattern::Subtree("old.example.com".to_string()));
+
+        // The binary cache wins over the legacy one
+        let cache = ListCache {
+            sources: vec![SourceCache { name: "A".to_string(), rules: rules.clone(), ..Default::default() }],
+        };
+        cache::save(&cache, &dir.join(CACHE_FILE)).unwrap();
+        let loaded = load_cache(&dir).unwrap();
+        assert_eq!(loaded.sources, ["A"]);
+        assert_eq!(loaded.rules, vec![(rules[0].clone(), 0)]);
+        assert!(cache_modified(&dir).is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_source_keeps_cache() {
+        let source = AdblockSource {
+            name: "A".to_string(),
+            url: "http://127.0.0.1:9/list.txt".to_string(),
+            format: "hosts".to_string(),
+        };
+        let (rules, _) = parse_source("0.0.0.0 ads.example.com\n", "hosts");
+        let previous = ListCache {
+            sources: vec![
+                SourceCache { name: "A".to_string(), url: source.url.clone(), rules, ..Default::default() },
+                SourceCache { name: "Removed".to_string(), ..Default::default() },
+            ],
+        };
+        let (cache, results) = download_all(&[source], previous).await;
+        assert_eq!(results[0].status, SourceStatus::Failed);
+        assert_eq!(results[0].domain_count, 1);
+        assert_eq!(cache.sources.len(), 1);
+        assert_eq!(cache.sources[0].name, "A");
+    }
+
     #[test]
     fn test_valid_domain() {
         assert!(is_valid_domain("example.com"));
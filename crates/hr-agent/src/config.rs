@@ -8,13 +8,22 @@ pub struct AgentConfig {
     pub homeroute_address: String,
     /// HomeRoute API port (e.g. 3017)
     pub homeroute_port: u16,
-    /// Agent authentication token (64-char hex)
+    /// Agent authentication token (64-char hex), empty until enrolled
+    #[serde(default)]
     pub token: String,
-    /// Service/application name (slug)
+    /// Service/application name (slug), given when enrolling
+    #[serde(default)]
     pub service_name: String,
+    /// One-time code to enroll with, removed once the token is saved
+    #[serde(default)]
+    pub join_code: Option<String>,
     /// Network interface to detect IPv4 address (default: "eth0", nspawn: "host0")
     #[serde(default = "default_interface")]
     pub interface: String,
+    /// Public key (hex) the update manifests must be signed with; updates
+    /// are refused without it
+    #[serde(default)]
+    pub update_key: Option<String>,
 }
 
 fn default_interface() -> String {
@@ -29,6 +38,25 @@ impl AgentConfig {
             .with_context(|| format!("Failed to parse TOML config from {path}"))
     }
 
+    /// Write the credentials received when enrolling in place of the join code.
+    pub fn save_enrollment(path: &str, token: &str, service_name: &str) -> Result<()> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config from {path}"))?;
+        let mut lines: Vec<String> = content
+            .lines()
+            .filter(|l| {
+                let key = l.split('=').next().map(str::trim);
+                !matches!(key, Some("token" | "service_name" | "join_code"))
+            })
+            .map(str::to_string)
+            .collect();
+        lines.push(format!("token = \"{token}\""));
+        lines.push(format!("service_name = \"{service_name}\""));
+        let tmp = format!("{path}.tmp");
+        std::fs::write(&tmp, lines.join("\n") + "\n").context("Failed to write config")?;
+        std::fs::rename(&tmp, path).context("Failed to replace config")
+    }
+
     /// WebSocket URL to connect to HomeRoute registry
     pub fn ws_url(&self) -> String {
         // IPv6 addresses need brackets, IPv4 addresses don't
@@ -6,10 +6,45 @@ use tokio::sync::mpsc;
 use tokio_tungstenite::tungstenite::Message;
 use tracing::{debug, error, info, warn};
 
-use hr_registry::protocol::{AgentMessage, RegistryMessage};
+use hr_registry::protocol::{capability_list, AgentMessage, RegistryMessage, AGENT_CAPABILITIES, PROTOCOL_VERSION};
 
 use crate::config::AgentConfig;
 
+/// Trade a join code for the agent's token and service name.
+pub async fn enroll(config: &AgentConfig, join_code: &str) -> Result<(String, String)> {
+    let url = config.ws_url();
+    info!(url, "Enrolling with HomeRoute registry");
+
+    let (mut ws_stream, _response) = tokio_tungstenite::connect_async(&url)
+        .await
+        .map_err(|e| anyhow::anyhow!("WebSocket connect failed: {e}"))?;
+
+    let enroll_msg = AgentMessage::Enroll {
+        join_code: join_code.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    };
+    ws_stream.send(Message::Text(serde_json::to_string(&enroll_msg)?.into())).await?;
+
+    let response = ws_stream
+        .next()
+        .await
+        .ok_or_else(|| anyhow::anyhow!("Connection closed before enrollment response"))??;
+    let result: RegistryMessage = match response {
+        Message::Text(text) => serde_json::from_str(&text)?,
+        other => anyhow::bail!("Unexpected message type during enrollment: {other:?}"),
+    };
+
+    match result {
+        RegistryMessage::EnrollResult { success: true, token: Some(token), service_name: Some(service_name), .. } => {
+            Ok((token, service_name))
+        }
+        RegistryMessage::EnrollResult { error, .. } => {
+            anyhow::bail!("Enrollment failed: {}", error.unwrap_or_default())
+        }
+        _ => anyhow::bail!("Unexpected message during enrollment"),
+    }
+}
+
 /// Connect to HomeRoute, authenticate, and handle bidirectional communication.
 /// - `registry_tx`: Channel to send received RegistryMessages to the main loop.
 /// - `outbound_rx`: Channel to receive AgentMessages to send to the registry (metrics, etc.).
@@ -44,6 +79,9 @@ pub async fn run_connection(
         service_name: config.service_name.clone(),
         version: env!("CARGO_PKG_VERSION").to_string(),
         ipv4_address,
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: capability_list(AGENT_CAPABILITIES),
+        arch: Some(hr_registry::binaries::local_arch().to_string()),
     };
     let auth_json = serde_json::to_string(&auth_msg)?;
     ws_sink.send(Message::Text(auth_json.into())).await?;
@@ -62,8 +100,12 @@ pub async fn run_connection(
     };
 
     match auth_result {
-        RegistryMessage::AuthResult { success: true, .. } => {
-            info!("Authentication successful");
+        RegistryMessage::AuthResult { success: true, protocol_version, capabilities, .. } => {
+            info!(protocol_version, ?capabilities, "Authentication successful");
+            crate::update::set_registry_protocol(protocol_version);
+            if protocol_version < PROTOCOL_VERSION {
+                warn!(protocol_version, "Registry speaks an older protocol, newer messages will be ignored");
+            }
         }
         RegistryMessage::AuthResult { success: false, error, .. } => {
             anyhow::bail!("Authentication failed: {}", error.unwrap_or_default());
@@ -14,7 +14,11 @@ use anyhow::Result;
 use tokio::sync::mpsc;
 use tracing::{error, info};
 
-use hr_registry::protocol::{AgentMessage, AgentMetrics, AgentRoute, RegistryMessage, ServiceConfig, ServiceState, ServiceType};
+use hr_registry::logs::{LogSource, LogTasks};
+use hr_registry::protocol::{
+    capability_list, AgentMessage, AgentMetrics, AgentRoute, RegistryMessage, ServiceConfig, ServiceState, ServiceType,
+    AGENT_CAPABILITIES, PROTOCOL_VERSION,
+};
 
 use crate::mcp::SchemaQuerySignals;
 use crate::metrics::MetricsCollector;
@@ -73,7 +77,28 @@ async fn main() -> Result<()> {
 
     info!("HomeRoute Agent starting...");
 
-    let cfg = config::AgentConfig::load(CONFIG_PATH)?;
+    let mut cfg = config::AgentConfig::load(CONFIG_PATH)?;
+
+    // Installed with a join code: get the token first
+    let mut backoff = INITIAL_BACKOFF_SECS;
+    while cfg.token.is_empty() {
+        let Some(join_code) = cfg.join_code.clone() else {
+            anyhow::bail!("No token nor join_code in {CONFIG_PATH}");
+        };
+        match connection::enroll(&cfg, &join_code).await {
+            Ok((token, service_name)) => {
+                config::AgentConfig::save_enrollment(CONFIG_PATH, &token, &service_name)?;
+                info!(service = service_name, "Enrolled with HomeRoute");
+                cfg = config::AgentConfig::load(CONFIG_PATH)?;
+            }
+            Err(e) => {
+                error!("Enrollment failed: {e}");
+                tokio::time::sleep(std::time::Duration::from_secs(backoff)).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF_SECS);
+            }
+        }
+    }
+
     info!(
         service = cfg.service_name,
         homeroute = format!("{}:{}", cfg.homeroute_address, cfg.homeroute_port),
@@ -109,6 +134,9 @@ async fn main() -> Result<()> {
     let agent_proxy: Arc<proxy::AgentProxy> = Arc::new(proxy::AgentProxy::new(&cfg));
     let mut proxy_started = false;
 
+    // Log streams of the current connection
+    let log_tasks = LogTasks::default();
+
     // Reconnection loop with exponential backoff
     let mut backoff = INITIAL_BACKOFF_SECS;
 
@@ -233,6 +261,7 @@ async fn main() -> Result<()> {
                                 &schema_signals,
                                 &agent_proxy,
                                 &mut proxy_started,
+                                &log_tasks,
                                 msg
                             ).await;
                         }
@@ -256,6 +285,7 @@ async fn main() -> Result<()> {
         // Cancel background tasks
         metrics_handle.abort();
         schema_handle.abort();
+        log_tasks.stop_all();
 
         // Drain any remaining messages
         while let Ok(msg) = registry_rx.try_recv() {
@@ -268,6 +298,7 @@ async fn main() -> Result<()> {
                 &schema_signals,
                 &agent_proxy,
                 &mut proxy_started,
+                &log_tasks,
                 msg
             ).await;
         }
@@ -299,6 +330,9 @@ async fn start_mcp_with_registry() -> Result<()> {
         service_name: cfg.service_name.clone(),
         version: env!("CARGO_PKG_VERSION").to_string(),
         ipv4_address: None,
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: capability_list(AGENT_CAPABILITIES),
+        arch: Some(hr_registry::binaries::local_arch().to_string()),
     };
     ws_sink
         .send(Message::Text(serde_json::to_string(&auth_msg)?.into()))
@@ -392,6 +426,9 @@ async fn start_deploy_mcp() -> Result<()> {
         service_name: cfg.service_name.clone(),
         version: env!("CARGO_PKG_VERSION").to_string(),
         ipv4_address: None,
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: capability_list(AGENT_CAPABILITIES),
+        arch: Some(hr_registry::binaries::local_arch().to_string()),
     };
     ws_sink
         .send(Message::Text(serde_json::to_string(&auth_msg)?.into()))
@@ -460,6 +497,9 @@ async fn start_store_mcp() -> Result<()> {
         service_name: cfg.service_name.clone(),
         version: env!("CARGO_PKG_VERSION").to_string(),
         ipv4_address: None,
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: capability_list(AGENT_CAPABILITIES),
+        arch: Some(hr_registry::binaries::local_arch().to_string()),
     };
     ws_sink
         .send(Message::Text(serde_json::to_string(&auth_msg)?.into()))
@@ -506,6 +546,7 @@ async fn handle_registry_message(
     schema_signals: &SchemaQuerySignals,
     agent_proxy: &Arc<proxy::AgentProxy>,
     proxy_started: &mut bool,
+    log_tasks: &LogTasks,
     msg: RegistryMessage,
 ) {
     match msg {
@@ -593,14 +634,21 @@ async fn handle_registry_message(
             std::process::exit(0);
         }
 
-        RegistryMessage::AuthResult { .. } => {
+        RegistryMessage::AuthResult { .. } | RegistryMessage::EnrollResult { .. } => {
             // Handled in connection.rs
         }
 
-        RegistryMessage::UpdateAvailable { version, download_url, sha256 } => {
-            info!(version, download_url, "Update available, starting auto-update");
-            if let Err(e) = update::apply_update(&download_url, &sha256, &version).await {
-                error!("Auto-update failed: {e}");
+        RegistryMessage::UpdateAvailable { download_url, manifest } => {
+            let verified = config::AgentConfig::load(CONFIG_PATH)
+                .and_then(|cfg| update::verify_manifest(&cfg, &manifest));
+            match verified {
+                Ok(manifest) => {
+                    info!(version = manifest.version, download_url, "Update available, starting auto-update");
+                    if let Err(e) = update::apply_update(&download_url, &manifest).await {
+                        error!("Auto-update failed: {e}");
+                    }
+                }
+                Err(e) => error!("Update refused: {e}"),
             }
         }
 
@@ -664,6 +712,31 @@ async fn handle_registry_message(
             }
         }
 
+        RegistryMessage::LogStream { request_id, request } => {
+            info!(request_id, source = ?request.source, follow = request.follow, "Log stream requested");
+            let tx = outbound_tx.clone();
+            let id = request_id.clone();
+            log_tasks.spawn(request_id, async move {
+                // Only the journal of this container is ours to read
+                let result = match request.source {
+                    LogSource::Journal { .. } => {
+                        hr_registry::logs::stream_journal(&request, |lines| {
+                            let tx = tx.clone();
+                            let request_id = id.clone();
+                            async move { tx.send(AgentMessage::LogStreamData { request_id, lines }).await.is_ok() }
+                        })
+                        .await
+                    }
+                    LogSource::Container { .. } => Err("Container journals are read from the host".to_string()),
+                };
+                let _ = tx.send(AgentMessage::LogStreamEnd { request_id: id, error: result.err() }).await;
+            });
+        }
+
+        RegistryMessage::LogStreamClose { request_id } => {
+            log_tasks.stop(&request_id);
+        }
+
         RegistryMessage::CertRenewal { slug } => {
             info!(slug, "Certificate renewal notification, re-pulling certs");
             let proxy = Arc::clone(agent_proxy);
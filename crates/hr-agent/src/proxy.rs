@@ -6,7 +6,7 @@
 
 use std::collections::HashMap;
 use std::io::BufReader;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::{Arc, RwLock};
 use std::time::Instant;
 
@@ -117,6 +117,8 @@ pub struct AgentProxy {
     routes: Arc<RwLock<HashMap<String, LocalRoute>>>,
     auth_cache: Arc<RwLock<HashMap<String, (Instant, AuthResult)>>>,
     homeroute_url: String,
+    /// HomeRoute's address: the only peer whose forwarding headers are kept.
+    homeroute_ip: Option<IpAddr>,
     agent_token: String,
 }
 
@@ -133,6 +135,7 @@ impl AgentProxy {
             routes: Arc::new(RwLock::new(HashMap::new())),
             auth_cache: Arc::new(RwLock::new(HashMap::new())),
             homeroute_url: format!("http://{}:{}", host, config.homeroute_port),
+            homeroute_ip: config.homeroute_address.parse().ok(),
             agent_token: config.token.clone(),
         }
     }
@@ -275,6 +278,7 @@ impl AgentProxy {
             let routes = Arc::clone(&self.routes);
             let auth_cache = Arc::clone(&self.auth_cache);
             let homeroute_url = self.homeroute_url.clone();
+            let from_homeroute = self.homeroute_ip.is_some_and(|ip| ip.to_canonical() == peer_addr.ip().to_canonical());
 
             tokio::spawn(async move {
                 let tls_stream = match acceptor.accept(tcp_stream).await {
@@ -291,7 +295,7 @@ impl AgentProxy {
                     let auth_cache = Arc::clone(&auth_cache);
                     let homeroute_url = homeroute_url.clone();
                     async move {
-                        handle_request(req, peer_addr, &routes, &auth_cache, &homeroute_url).await
+                        handle_request(req, peer_addr, from_homeroute, &routes, &auth_cache, &homeroute_url).await
                     }
                 });
 
@@ -320,6 +324,7 @@ impl AgentProxy {
 async fn handle_request(
     mut req: Request<Incoming>,
     peer_addr: SocketAddr,
+    from_homeroute: bool,
     routes: &RwLock<HashMap<String, LocalRoute>>,
     auth_cache: &RwLock<HashMap<String, (Instant, AuthResult)>>,
     homeroute_url: &str,
@@ -379,13 +384,27 @@ async fn handle_request(
         }
     }
 
-    // Set forwarding headers
+    // Set forwarding headers. HomeRoute already resolved the client (direct
+    // or through the cloud relay): keep its chain and append ourselves.
     let headers = req.headers_mut();
+    let peer = peer_addr.ip().to_string();
+    let upstream_chain = headers
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| from_homeroute)
+        .map(|chain| format!("{}, {}", chain, peer));
+    let client_ip = headers
+        .get("x-real-ip")
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| from_homeroute)
+        .map(str::to_string);
     if let Ok(v) = hyper::header::HeaderValue::from_str(&host) {
         headers.insert("X-Forwarded-Host", v);
     }
-    if let Ok(v) = hyper::header::HeaderValue::from_str(&peer_addr.ip().to_string()) {
-        headers.insert("X-Forwarded-For", v.clone());
+    if let Ok(v) = hyper::header::HeaderValue::from_str(upstream_chain.as_deref().unwrap_or(&peer)) {
+        headers.insert("X-Forwarded-For", v);
+    }
+    if let Ok(v) = hyper::header::HeaderValue::from_str(client_ip.as_deref().unwrap_or(&peer)) {
         headers.insert("X-Real-IP", v);
     }
     headers.insert(
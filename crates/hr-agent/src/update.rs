@@ -1,13 +1,38 @@
 //! Auto-update module for hr-agent.
-//! Downloads a new binary, verifies SHA256, replaces itself, and restarts.
+//! Verifies the signed manifest, downloads the new binary, checks it against
+//! the manifest, replaces itself, and restarts.
+
+use std::sync::atomic::{AtomicU32, Ordering};
 
 use anyhow::{Context, Result};
+use hr_registry::update::{SignedManifest, UpdateManifest};
 use tracing::{error, info};
 
+use crate::config::AgentConfig;
+
 const SELF_PATH: &str = "/usr/local/bin/hr-agent";
 
+/// Protocol version of the registry, from the last `AuthResult`.
+static REGISTRY_PROTOCOL: AtomicU32 = AtomicU32::new(0);
+
+pub fn set_registry_protocol(version: u32) {
+    REGISTRY_PROTOCOL.store(version, Ordering::Relaxed);
+}
+
+/// The manifest of an update, once signed by the key pinned in the config
+/// and meant for this agent.
+pub fn verify_manifest(config: &AgentConfig, signed: &SignedManifest) -> Result<UpdateManifest> {
+    let key = config.update_key.as_deref().context("No update_key in the config")?;
+    let manifest = signed.verify(key).map_err(anyhow::Error::msg)?;
+    manifest
+        .check("hr-agent", env!("CARGO_PKG_VERSION"), REGISTRY_PROTOCOL.load(Ordering::Relaxed))
+        .map_err(anyhow::Error::msg)?;
+    Ok(manifest)
+}
+
 /// Download, verify and replace the current binary, then restart.
-pub async fn apply_update(download_url: &str, expected_sha256: &str, version: &str) -> Result<()> {
+pub async fn apply_update(download_url: &str, manifest: &UpdateManifest) -> Result<()> {
+    let version = manifest.version.as_str();
     info!(version, download_url, "Starting auto-update");
 
     // Download to a temporary file
@@ -25,16 +50,13 @@ pub async fn apply_update(download_url: &str, expected_sha256: &str, version: &s
         .await
         .context("Failed to read update body")?;
 
-    // Verify SHA256
+    // Verify SHA256 against the signed manifest
     use std::io::Write;
-    let digest = sha256_hex(&bytes);
-    if digest != expected_sha256 {
-        anyhow::bail!(
-            "SHA256 mismatch: expected {expected_sha256}, got {digest}"
-        );
+    if !manifest.matches(&bytes) {
+        anyhow::bail!("SHA256 mismatch: expected {}", manifest.sha256);
     }
 
-    info!(sha256 = digest, bytes = bytes.len(), "Download verified");
+    info!(sha256 = manifest.sha256, bytes = bytes.len(), "Download verified");
 
     // Write to tmp
     let mut file = std::fs::File::create(&tmp_path)
@@ -77,14 +99,3 @@ pub async fn apply_update(download_url: &str, expected_sha256: &str, version: &s
 
     Ok(())
 }
-
-fn sha256_hex(data: &[u8]) -> String {
-    use std::fmt::Write;
-    // Use ring for SHA256 since it's already in the dependency tree via rustls
-    let digest = ring::digest::digest(&ring::digest::SHA256, data);
-    let mut hex = String::with_capacity(64);
-    for byte in digest.as_ref() {
-        write!(hex, "{:02x}", byte).unwrap();
-    }
-    hex
-}
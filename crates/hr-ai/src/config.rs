@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// AI gateway (ai-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AiConfig {
+    #[serde(default)]
+    pub providers: Vec<Provider>,
+    /// Policy of the apps without their own entry in `apps`.
+    #[serde(default)]
+    pub default_policy: AppPolicy,
+    /// Per-app policies, by slug.
+    #[serde(default)]
+    pub apps: HashMap<String, AppPolicy>,
+    /// Days the request log is kept.
+    #[serde(default = "default_log_retention_days")]
+    pub log_retention_days: u32,
+}
+
+fn default_log_retention_days() -> u32 {
+    30
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    /// OpenAI and compatible APIs (`Authorization: Bearer`).
+    Openai,
+    /// Anthropic Messages API (`x-api-key`).
+    Anthropic,
+    /// Local Ollama runtime, no key.
+    Ollama,
+}
+
+/// An upstream LLM API; apps call it as `/api/ai/{name}/{path}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Provider {
+    pub name: String,
+    pub kind: ProviderKind,
+    /// e.g. `https://api.openai.com`, `http://127.0.0.1:11434`.
+    pub base_url: String,
+    /// Injected in the forwarded requests, never shown to the apps.
+    #[serde(default)]
+    pub api_key: String,
+    /// USD per million tokens, by model; `*` for the other models.
+    #[serde(default)]
+    pub prices: HashMap<String, Price>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    300
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Price {
+    #[serde(default)]
+    pub input: f64,
+    #[serde(default)]
+    pub output: f64,
+}
+
+impl Provider {
+    /// Cost in USD of a request, when the model has a price.
+    pub fn cost(&self, model: Option<&str>, input_tokens: u64, output_tokens: u64) -> Option<f64> {
+        let price = model.and_then(|m| self.prices.get(m)).or_else(|| self.prices.get("*"))?;
+        Some((input_tokens as f64 * price.input + output_tokens as f64 * price.output) / 1_000_000.0)
+    }
+}
+
+/// What the gateway keeps of the requests of an app.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogMode {
+    /// Usage counted, nothing logged.
+    Off,
+    /// Model, status, tokens, cost and latency.
+    #[default]
+    Metadata,
+    /// Also the request and response bodies (truncated).
+    Full,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppPolicy {
+    /// Providers the app may call; all when empty.
+    #[serde(default)]
+    pub providers: Vec<String>,
+    /// Tokens (input + output) per calendar month (UTC).
+    #[serde(default)]
+    pub monthly_tokens: Option<u64>,
+    /// USD per calendar month (UTC), from the provider prices.
+    #[serde(default)]
+    pub monthly_cost_usd: Option<f64>,
+    #[serde(default)]
+    pub log: LogMode,
+}
+
+impl AppPolicy {
+    pub fn allows(&self, provider: &str) -> bool {
+        self.providers.is_empty() || self.providers.iter().any(|p| p == provider)
+    }
+}
+
+impl Default for AiConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl AiConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn provider(&self, name: &str) -> Option<&Provider> {
+        self.providers.iter().find(|p| p.name == name)
+    }
+
+    pub fn policy(&self, app: &str) -> &AppPolicy {
+        self.apps.get(app).unwrap_or(&self.default_policy)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let mut names = std::collections::HashSet::new();
+        for p in &self.providers {
+            let valid_name = (1..=32).contains(&p.name.len())
+                && p.name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-');
+            if !valid_name {
+                return Err(format!("Invalid provider name (1-32 characters: a-z, 0-9, '-'): {}", p.name));
+            }
+            if !names.insert(p.name.as_str()) {
+                return Err(format!("Duplicate provider: {}", p.name));
+            }
+            if !p.base_url.starts_with("https://") && !p.base_url.starts_with("http://") {
+                return Err(format!("Invalid base URL for {}: {}", p.name, p.base_url));
+            }
+            if p.timeout_secs == 0 || p.timeout_secs > 3600 {
+                return Err(format!("Timeout of {} must be 1-3600 seconds", p.name));
+            }
+            if p.prices.values().any(|price| price.input < 0.0 || price.output < 0.0) {
+                return Err(format!("Negative price for {}", p.name));
+            }
+        }
+        for (app, policy) in std::iter::once(("default", &self.default_policy)).chain(self.apps.iter().map(|(a, p)| (a.as_str(), p))) {
+            if let Some(unknown) = policy.providers.iter().find(|p| !names.contains(p.as_str())) {
+                return Err(format!("Unknown provider {} in the policy of {}", unknown, app));
+            }
+            if policy.monthly_cost_usd.is_some_and(|c| c < 0.0) {
+                return Err(format!("Negative budget for {}", app));
+            }
+        }
+        if self.log_retention_days == 0 {
+            return Err("Log retention must be at least 1 day".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_and_cost() {
+        let mut config: AiConfig = serde_json::from_str(
+            r#"{
+                "providers": [{
+                    "name": "openai", "kind": "openai", "baseUrl": "https://api.openai.com", "apiKey": "sk-x",
+                    "prices": {"gpt-4o-mini": {"input": 0.15, "output": 0.6}, "*": {"input": 1, "output": 2}}
+                }],
+                "apps": {"shop": {"providers": ["openai"], "monthlyTokens": 100000, "log": "full"}}
+            }"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.policy("shop").log, LogMode::Full);
+        assert_eq!(config.policy("crm").log, LogMode::Metadata);
+        assert!(config.policy("crm").allows("anything"));
+
+        let openai = config.provider("openai").unwrap();
+        assert_eq!(openai.cost(Some("gpt-4o-mini"), 1_000_000, 1_000_000), Some(0.75));
+        assert_eq!(openai.cost(Some("gpt-4o"), 500_000, 0), Some(0.5));
+
+        config.apps.get_mut("shop").unwrap().providers = vec!["anthropic".to_string()];
+        assert!(config.validate().is_err());
+        config.apps.clear();
+        config.providers.push(config.providers[0].clone());
+        assert!(config.validate().is_err());
+    }
+}
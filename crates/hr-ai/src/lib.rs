@@ -0,0 +1,462 @@
+//! AI gateway for the apps: they call `/api/ai/{provider}/{path}` with
+//! their agent token, HomeRoute forwards to the configured LLM provider (or
+//! a local runtime) with the provider key, meters the tokens of the
+//! response and enforces the monthly budgets, so the keys live in one place
+//! instead of every container.
+
+pub mod config;
+pub mod maintenance;
+pub mod store;
+pub mod usage;
+
+pub use config::{AiConfig, AppPolicy, LogMode, Price, Provider, ProviderKind};
+pub use store::{AiStore, AppUsage, LogEntry, RequestRecord};
+
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures_util::Stream;
+use tracing::warn;
+
+use crate::usage::{Meter, Usage};
+
+/// Request and response bodies kept by the `full` log mode.
+pub const MAX_LOGGED_BODY: usize = 64 * 1024;
+
+/// Anthropic API version sent when the app gives none.
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GatewayError {
+    UnknownProvider(String),
+    /// The app's policy does not list the provider.
+    NotAllowed(String),
+    /// Monthly token or cost budget used up.
+    BudgetExhausted(String),
+    Upstream(String),
+    Storage(String),
+}
+
+impl std::fmt::Display for GatewayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GatewayError::UnknownProvider(p) => write!(f, "unknown AI provider {}", p),
+            GatewayError::NotAllowed(p) => write!(f, "provider {} not allowed for this app", p),
+            GatewayError::BudgetExhausted(e) => write!(f, "monthly AI budget exhausted ({})", e),
+            GatewayError::Upstream(e) => write!(f, "provider unreachable: {}", e),
+            GatewayError::Storage(e) => write!(f, "AI usage storage error: {}", e),
+        }
+    }
+}
+
+/// A request of an app, as received.
+pub struct ForwardRequest {
+    pub method: reqwest::Method,
+    /// Path under the provider base URL, e.g. `v1/chat/completions`.
+    pub path: String,
+    pub query: Option<String>,
+    /// Request headers; only content negotiation and provider options
+    /// (`anthropic-*`, `openai-*`) are passed on.
+    pub headers: Vec<(String, String)>,
+    pub body: Bytes,
+}
+
+/// The provider's response, its body metered as it streams to the app.
+pub struct Forwarded {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: MeteredBody,
+}
+
+/// Shared gateway handle: the API forwards the app requests, manages the
+/// config and reads the usage; the supervised `ai` service purges the log.
+pub struct Gateway {
+    config: RwLock<AiConfig>,
+    store: Arc<AiStore>,
+    client: reqwest::Client,
+}
+
+pub type SharedGateway = Arc<Gateway>;
+
+impl Gateway {
+    pub fn new(config: AiConfig, store: AiStore) -> Self {
+        Self { config: RwLock::new(config), store: Arc::new(store), client: reqwest::Client::new() }
+    }
+
+    pub fn config(&self) -> AiConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    pub fn apply(&self, config: AiConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    pub fn store(&self) -> Arc<AiStore> {
+        self.store.clone()
+    }
+
+    /// Usage of every app in the current month.
+    pub async fn month_usage(&self) -> Result<Vec<AppUsage>, GatewayError> {
+        let store = self.store.clone();
+        let since = month_start();
+        match tokio::task::spawn_blocking(move || store.usage(None, &since)).await {
+            Ok(result) => result.map_err(|e| GatewayError::Storage(e.to_string())),
+            Err(e) => Err(GatewayError::Storage(e.to_string())),
+        }
+    }
+
+    /// Forward a request of the app `app` to `provider`.
+    pub async fn forward(&self, app: &str, provider: &str, request: ForwardRequest) -> Result<Forwarded, GatewayError> {
+        let (provider, policy) = {
+            let config = self.config.read().unwrap();
+            let p = config.provider(provider).cloned().ok_or_else(|| GatewayError::UnknownProvider(provider.to_string()))?;
+            (p, config.policy(app).clone())
+        };
+        if !policy.allows(&provider.name) {
+            return Err(GatewayError::NotAllowed(provider.name));
+        }
+        self.check_budget(app, &policy).await?;
+
+        let model = serde_json::from_slice::<serde_json::Value>(&request.body)
+            .ok()
+            .and_then(|v| v.get("model").and_then(|m| m.as_str()).map(str::to_string));
+        let mut record = RequestRecord {
+            app: app.to_string(),
+            provider: provider.name.clone(),
+            model,
+            path: request.path.clone(),
+            logged: policy.log != LogMode::Off,
+            request_body: (policy.log == LogMode::Full).then(|| truncated(&request.body)),
+            created_at: chrono::Utc::now().timestamp_millis(),
+            ..Default::default()
+        };
+
+        let started = Instant::now();
+        let response = match self.upstream_request(&provider, request).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                record.status = 502;
+                record.error = Some(e.to_string());
+                record.latency_ms = started.elapsed().as_millis() as u64;
+                finish(self.store.clone(), &provider, record, None);
+                return Err(GatewayError::Upstream(e.to_string()));
+            }
+        };
+
+        record.status = response.status().as_u16();
+        let mut headers = Vec::new();
+        for name in ["content-type", "cache-control", "request-id", "x-request-id"] {
+            if let Some(value) = response.headers().get(name).and_then(|v| v.to_str().ok()) {
+                headers.push((name.to_string(), value.to_string()));
+            }
+        }
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        Ok(Forwarded {
+            status: record.status,
+            headers,
+            body: MeteredBody {
+                inner: Box::pin(response.bytes_stream()),
+                meter: Some(Meter::new(&content_type)),
+                response_body: (policy.log == LogMode::Full).then(Vec::new),
+                started,
+                pending: Some((record, provider, self.store.clone())),
+            },
+        })
+    }
+
+    async fn check_budget(&self, app: &str, policy: &AppPolicy) -> Result<(), GatewayError> {
+        if policy.monthly_tokens.is_none() && policy.monthly_cost_usd.is_none() {
+            return Ok(());
+        }
+        let store = self.store.clone();
+        let (app, since) = (app.to_string(), month_start());
+        let used = match tokio::task::spawn_blocking(move || store.usage(Some(&app), &since)).await {
+            Ok(Ok(usage)) => usage.into_iter().next().unwrap_or_default(),
+            Ok(Err(e)) => return Err(GatewayError::Storage(e.to_string())),
+            Err(e) => return Err(GatewayError::Storage(e.to_string())),
+        };
+        if let Some(limit) = policy.monthly_tokens
+            && used.input_tokens + used.output_tokens >= limit
+        {
+            return Err(GatewayError::BudgetExhausted(format!("{} tokens", limit)));
+        }
+        if let Some(limit) = policy.monthly_cost_usd
+            && used.cost_usd >= limit
+        {
+            return Err(GatewayError::BudgetExhausted(format!("{} USD", limit)));
+        }
+        Ok(())
+    }
+
+    fn upstream_request(&self, provider: &Provider, request: ForwardRequest) -> reqwest::RequestBuilder {
+        let mut url = format!("{}/{}", provider.base_url.trim_end_matches('/'), request.path.trim_start_matches('/'));
+        if let Some(query) = &request.query {
+            url.push('?');
+            url.push_str(query);
+        }
+        let mut builder = self
+            .client
+            .request(request.method, url)
+            .timeout(Duration::from_secs(provider.timeout_secs))
+            .body(request.body);
+        let mut has_version = false;
+        for (name, value) in &request.headers {
+            let name = name.to_ascii_lowercase();
+            let passed = matches!(name.as_str(), "content-type" | "accept")
+                || name.starts_with("anthropic-")
+                || (name.starts_with("openai-") && name != "openai-organization");
+            if passed {
+                has_version |= name == "anthropic-version";
+                builder = builder.header(name, value);
+            }
+        }
+        match provider.kind {
+            ProviderKind::Openai | ProviderKind::Ollama => {
+                if !provider.api_key.is_empty() {
+                    builder = builder.bearer_auth(&provider.api_key);
+                }
+            }
+            ProviderKind::Anthropic => {
+                builder = builder.header("x-api-key", &provider.api_key);
+                if !has_version {
+                    builder = builder.header("anthropic-version", ANTHROPIC_VERSION);
+                }
+            }
+        }
+        builder
+    }
+}
+
+/// Response body passed through to the app; the usage is recorded when it
+/// ends, fails or the app goes away.
+pub struct MeteredBody {
+    inner: Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>,
+    meter: Option<Meter>,
+    response_body: Option<Vec<u8>>,
+    started: Instant,
+    pending: Option<(RequestRecord, Provider, Arc<AiStore>)>,
+}
+
+impl MeteredBody {
+    fn complete(&mut self, error: Option<String>) {
+        let Some((mut record, provider, store)) = self.pending.take() else {
+            return;
+        };
+        record.latency_ms = self.started.elapsed().as_millis() as u64;
+        record.error = error;
+        record.response_body = self.response_body.take().map(|b| truncated(&b));
+        let usage = self.meter.take().and_then(Meter::finish);
+        finish(store, &provider, record, usage);
+    }
+}
+
+impl Stream for MeteredBody {
+    type Item = Result<Bytes, std::io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.inner.as_mut().poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                if let Some(meter) = &mut self.meter {
+                    meter.feed(&chunk);
+                }
+                if let Some(body) = &mut self.response_body
+                    && body.len() < MAX_LOGGED_BODY
+                {
+                    body.extend_from_slice(&chunk[..chunk.len().min(MAX_LOGGED_BODY - body.len())]);
+                }
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(Some(Err(e))) => {
+                self.complete(Some(e.to_string()));
+                Poll::Ready(Some(Err(std::io::Error::other(e))))
+            }
+            Poll::Ready(None) => {
+                self.complete(None);
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for MeteredBody {
+    fn drop(&mut self) {
+        self.complete(Some("response interrupted".to_string()));
+    }
+}
+
+/// Count the request (metrics, budgets) and log it.
+fn finish(store: Arc<AiStore>, provider: &Provider, mut record: RequestRecord, usage: Option<Usage>) {
+    let usage = usage.unwrap_or_default();
+    record.input_tokens = usage.input_tokens;
+    record.output_tokens = usage.output_tokens;
+    record.cost_usd = provider.cost(record.model.as_deref(), usage.input_tokens, usage.output_tokens);
+
+    let metrics = hr_common::metrics::registry();
+    let status = record.status.to_string();
+    metrics
+        .counter(
+            "homeroute_ai_requests_total",
+            "AI gateway requests, by app, provider and status.",
+            &[("app", &record.app), ("provider", &record.provider), ("status", &status)],
+        )
+        .inc();
+    for (kind, tokens) in [("input", usage.input_tokens), ("output", usage.output_tokens)] {
+        metrics
+            .counter(
+                "homeroute_ai_tokens_total",
+                "Tokens used through the AI gateway, by app, provider and type.",
+                &[("app", &record.app), ("provider", &record.provider), ("type", kind)],
+            )
+            .add(tokens);
+    }
+
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+    runtime.spawn_blocking(move || {
+        if let Err(e) = store.record(&record) {
+            warn!("Failed to record AI gateway usage for {}: {}", record.app, e);
+        }
+    });
+}
+
+fn truncated(body: &[u8]) -> String {
+    String::from_utf8_lossy(&body[..body.len().min(MAX_LOGGED_BODY)]).to_string()
+}
+
+/// First day of the current month (UTC), `YYYY-MM-DD`.
+fn month_start() -> String {
+    chrono::Utc::now().format("%Y-%m-01").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    async fn provider_server(body: &'static str, content_type: &'static str) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+                    let mut buf = vec![0u8; 65536];
+                    let n = socket.read(&mut buf).await.unwrap();
+                    let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                    // The key is injected, the app's token is not passed on
+                    let status = if request.contains("authorization: bearer sk-test") && !request.contains("agent-token") {
+                        "200 OK"
+                    } else {
+                        "401 Unauthorized"
+                    };
+                    let response = format!(
+                        "HTTP/1.1 {}\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        status,
+                        content_type,
+                        body.len(),
+                        body
+                    );
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn gateway(base_url: String, policy: AppPolicy) -> Gateway {
+        let mut config = AiConfig::default();
+        config.providers.push(Provider {
+            name: "openai".to_string(),
+            kind: ProviderKind::Openai,
+            base_url,
+            api_key: "sk-test".to_string(),
+            prices: [("*".to_string(), Price { input: 1.0, output: 2.0 })].into(),
+            timeout_secs: 10,
+        });
+        config.apps.insert("shop".to_string(), policy);
+        Gateway::new(config, AiStore::open_in_memory().unwrap())
+    }
+
+    fn request() -> ForwardRequest {
+        ForwardRequest {
+            method: reqwest::Method::POST,
+            path: "v1/chat/completions".to_string(),
+            query: None,
+            headers: vec![
+                ("content-type".to_string(), "application/json".to_string()),
+                ("authorization".to_string(), "Bearer agent-token".to_string()),
+            ],
+            body: Bytes::from_static(br#"{"model":"gpt-4o-mini","messages":[]}"#),
+        }
+    }
+
+    async fn drain(forwarded: Forwarded) -> String {
+        let mut body = forwarded.body;
+        let mut out = Vec::new();
+        while let Some(chunk) = body.next().await {
+            out.extend_from_slice(&chunk.unwrap());
+        }
+        drop(body);
+        // Recording runs on the blocking pool
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        String::from_utf8(out).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_forward_meters_and_enforces_budget() {
+        let url = provider_server(r#"{"usage":{"prompt_tokens":600,"completion_tokens":500}}"#, "application/json").await;
+        let policy = AppPolicy { monthly_tokens: Some(1000), log: LogMode::Full, ..Default::default() };
+        let gateway = gateway(url, policy);
+
+        let forwarded = gateway.forward("shop", "openai", request()).await.unwrap();
+        assert_eq!(forwarded.status, 200);
+        assert!(drain(forwarded).await.contains("prompt_tokens"));
+
+        let usage = gateway.month_usage().await.unwrap();
+        assert_eq!((usage[0].input_tokens, usage[0].output_tokens), (600, 500));
+        assert!((usage[0].cost_usd - 0.0016).abs() < 1e-9);
+        let log = gateway.store().log(Some("shop"), 10, true).unwrap();
+        assert_eq!(log[0].model.as_deref(), Some("gpt-4o-mini"));
+        assert!(log[0].response_body.as_deref().unwrap().contains("usage"));
+
+        // 1100 tokens used out of 1000
+        assert!(matches!(
+            gateway.forward("shop", "openai", request()).await,
+            Err(GatewayError::BudgetExhausted(_))
+        ));
+        // Other apps fall back to the default policy: no budget
+        assert!(gateway.forward("crm", "openai", request()).await.is_ok());
+        assert!(matches!(
+            gateway.forward("shop", "mistral", request()).await,
+            Err(GatewayError::UnknownProvider(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_policy_and_log_modes() {
+        let url = provider_server("data: {\"usage\":{\"prompt_tokens\":1,\"completion_tokens\":2}}\n\n", "text/event-stream").await;
+        let policy = AppPolicy { providers: vec!["local".to_string()], ..Default::default() };
+        let gateway = gateway(url.clone(), policy);
+        assert_eq!(
+            gateway.forward("shop", "openai", request()).await.err(),
+            Some(GatewayError::NotAllowed("openai".to_string()))
+        );
+
+        let gateway = self::gateway(url, AppPolicy { log: LogMode::Off, ..Default::default() });
+        drain(gateway.forward("shop", "openai", request()).await.unwrap()).await;
+        // Counted, not logged
+        assert_eq!(gateway.month_usage().await.unwrap()[0].output_tokens, 2);
+        assert!(gateway.store().log(None, 10, false).unwrap().is_empty());
+    }
+}
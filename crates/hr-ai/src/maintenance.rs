@@ -0,0 +1,30 @@
+//! The `ai` service: drops the request log past its retention.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::{info, warn};
+
+use crate::SharedGateway;
+
+const INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Daily usage counters are kept this long, for the monthly totals.
+const USAGE_RETENTION_DAYS: i64 = 400;
+
+pub async fn run_ai(gateway: SharedGateway) -> Result<()> {
+    let mut interval = tokio::time::interval(INTERVAL);
+    loop {
+        interval.tick().await;
+        let retention_days = gateway.config().log_retention_days as i64;
+        let now = chrono::Utc::now();
+        let before = (now - chrono::Duration::days(retention_days)).timestamp_millis();
+        let before_day = (now - chrono::Duration::days(USAGE_RETENTION_DAYS)).format("%Y-%m-%d").to_string();
+        let store = gateway.store();
+        match tokio::task::spawn_blocking(move || store.purge(before, &before_day)).await? {
+            Ok(0) => {}
+            Ok(removed) => info!("Purged {} AI gateway log entries", removed),
+            Err(e) => warn!("Failed to purge the AI gateway log: {}", e),
+        }
+    }
+}
@@ -0,0 +1,264 @@
+//! SQLite request log and usage counters (`ai.db`). Every request is
+//! counted, so budgets hold whatever the log mode; the log mode only
+//! decides whether the details are kept.
+
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// A forwarded request, once its response is over.
+#[derive(Debug, Clone, Default)]
+pub struct RequestRecord {
+    pub app: String,
+    pub provider: String,
+    pub model: Option<String>,
+    pub path: String,
+    pub status: u16,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: Option<f64>,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+    /// Keep the model, status and usage in the log.
+    pub logged: bool,
+    pub request_body: Option<String>,
+    pub response_body: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogEntry {
+    pub id: i64,
+    pub app: String,
+    pub provider: String,
+    pub model: Option<String>,
+    pub path: String,
+    pub status: u16,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: Option<f64>,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_body: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_body: Option<String>,
+    pub created_at: i64,
+}
+
+/// Usage of an app over a period.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppUsage {
+    pub app: String,
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+}
+
+pub struct AiStore {
+    conn: Mutex<Connection>,
+}
+
+impl AiStore {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS usage (
+                app TEXT NOT NULL,
+                provider TEXT NOT NULL,
+                day TEXT NOT NULL,
+                requests INTEGER NOT NULL,
+                input_tokens INTEGER NOT NULL,
+                output_tokens INTEGER NOT NULL,
+                cost_usd REAL NOT NULL,
+                PRIMARY KEY (app, provider, day)
+            );
+            CREATE TABLE IF NOT EXISTS requests (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                app TEXT NOT NULL,
+                provider TEXT NOT NULL,
+                model TEXT,
+                path TEXT NOT NULL,
+                status INTEGER NOT NULL,
+                input_tokens INTEGER NOT NULL,
+                output_tokens INTEGER NOT NULL,
+                cost_usd REAL,
+                latency_ms INTEGER NOT NULL,
+                error TEXT,
+                request_body TEXT,
+                response_body TEXT,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_requests_app ON requests (app, created_at);
+            CREATE INDEX IF NOT EXISTS idx_requests_created ON requests (created_at);",
+        )?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    pub fn record(&self, r: &RequestRecord) -> anyhow::Result<()> {
+        let day = chrono::DateTime::from_timestamp_millis(r.created_at)
+            .unwrap_or_default()
+            .format("%Y-%m-%d")
+            .to_string();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO usage (app, provider, day, requests, input_tokens, output_tokens, cost_usd)
+             VALUES (?1, ?2, ?3, 1, ?4, ?5, ?6)
+             ON CONFLICT (app, provider, day) DO UPDATE SET
+                requests = requests + 1,
+                input_tokens = input_tokens + excluded.input_tokens,
+                output_tokens = output_tokens + excluded.output_tokens,
+                cost_usd = cost_usd + excluded.cost_usd",
+            params![r.app, r.provider, day, r.input_tokens, r.output_tokens, r.cost_usd.unwrap_or(0.0)],
+        )?;
+        if r.logged {
+            tx.execute(
+                "INSERT INTO requests (app, provider, model, path, status, input_tokens, output_tokens, cost_usd,
+                    latency_ms, error, request_body, response_body, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                params![
+                    r.app,
+                    r.provider,
+                    r.model,
+                    r.path,
+                    r.status,
+                    r.input_tokens,
+                    r.output_tokens,
+                    r.cost_usd,
+                    r.latency_ms,
+                    r.error,
+                    r.request_body,
+                    r.response_body,
+                    r.created_at
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Usage per app since `since_day` (`YYYY-MM-DD`, included).
+    pub fn usage(&self, app: Option<&str>, since_day: &str) -> anyhow::Result<Vec<AppUsage>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT app, SUM(requests), SUM(input_tokens), SUM(output_tokens), SUM(cost_usd) FROM usage
+             WHERE day >= ?2 AND (?1 IS NULL OR app = ?1) GROUP BY app ORDER BY app",
+        )?;
+        let usage = stmt
+            .query_map(params![app, since_day], |row| {
+                Ok(AppUsage {
+                    app: row.get(0)?,
+                    requests: row.get(1)?,
+                    input_tokens: row.get(2)?,
+                    output_tokens: row.get(3)?,
+                    cost_usd: row.get(4)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(usage)
+    }
+
+    /// Newest requests first; the bodies only with `bodies`.
+    pub fn log(&self, app: Option<&str>, limit: u32, bodies: bool) -> anyhow::Result<Vec<LogEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, app, provider, model, path, status, input_tokens, output_tokens, cost_usd, latency_ms, error,
+                CASE WHEN ?3 THEN request_body END, CASE WHEN ?3 THEN response_body END, created_at
+             FROM requests WHERE ?1 IS NULL OR app = ?1 ORDER BY id DESC LIMIT ?2",
+        )?;
+        let entries = stmt
+            .query_map(params![app, limit, bodies], |row| {
+                Ok(LogEntry {
+                    id: row.get(0)?,
+                    app: row.get(1)?,
+                    provider: row.get(2)?,
+                    model: row.get(3)?,
+                    path: row.get(4)?,
+                    status: row.get(5)?,
+                    input_tokens: row.get(6)?,
+                    output_tokens: row.get(7)?,
+                    cost_usd: row.get(8)?,
+                    latency_ms: row.get(9)?,
+                    error: row.get(10)?,
+                    request_body: row.get(11)?,
+                    response_body: row.get(12)?,
+                    created_at: row.get(13)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(entries)
+    }
+
+    /// Drop the log entries older than `before` (ms) and the daily counters
+    /// older than `before_day`; returns the log entries removed.
+    pub fn purge(&self, before: i64, before_day: &str) -> anyhow::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let removed = conn.execute("DELETE FROM requests WHERE created_at < ?1", params![before])?;
+        conn.execute("DELETE FROM usage WHERE day < ?1", params![before_day])?;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(app: &str, tokens: u64, logged: bool, created_at: i64) -> RequestRecord {
+        RequestRecord {
+            app: app.to_string(),
+            provider: "openai".to_string(),
+            path: "v1/chat/completions".to_string(),
+            status: 200,
+            input_tokens: tokens,
+            output_tokens: tokens,
+            cost_usd: Some(0.5),
+            logged,
+            request_body: Some("{}".to_string()),
+            created_at,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_usage_and_log() {
+        let store = AiStore::open_in_memory().unwrap();
+        let march = chrono::DateTime::parse_from_rfc3339("2026-03-10T12:00:00Z").unwrap().timestamp_millis();
+        let april = chrono::DateTime::parse_from_rfc3339("2026-04-02T12:00:00Z").unwrap().timestamp_millis();
+        store.record(&record("shop", 10, true, march)).unwrap();
+        store.record(&record("shop", 20, true, april)).unwrap();
+        store.record(&record("shop", 30, false, april)).unwrap();
+        store.record(&record("crm", 5, true, april)).unwrap();
+
+        let usage = store.usage(Some("shop"), "2026-04-01").unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!((usage[0].requests, usage[0].input_tokens, usage[0].output_tokens), (2, 50, 50));
+        assert_eq!(usage[0].cost_usd, 1.0);
+        assert_eq!(store.usage(None, "2026-01-01").unwrap().len(), 2);
+
+        // Unlogged requests are counted but not listed
+        let log = store.log(Some("shop"), 10, false).unwrap();
+        assert_eq!(log.len(), 2);
+        assert!(log[0].request_body.is_none());
+        assert_eq!(store.log(None, 10, true).unwrap()[0].request_body.as_deref(), Some("{}"));
+
+        assert_eq!(store.purge(april - 1, "2026-04-01").unwrap(), 1);
+        assert_eq!(store.usage(None, "2026-01-01").unwrap().iter().map(|u| u.requests).sum::<u64>(), 3);
+    }
+}
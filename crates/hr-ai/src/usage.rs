@@ -0,0 +1,167 @@
+//! Token usage read out of the provider responses as they stream through:
+//! the `usage` object of OpenAI and Anthropic (whole JSON bodies or
+//! server-sent events) and the counters of Ollama (`prompt_eval_count`,
+//! `eval_count`, in JSON or NDJSON).
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Whole bodies larger than this are not parsed.
+const MAX_BODY: usize = 8 * 1024 * 1024;
+/// Longer stream lines are skipped.
+const MAX_LINE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+
+    /// Streams repeat or accumulate counters (Anthropic sends the input
+    /// tokens first, then the running output count): keep the largest.
+    fn merge(&mut self, other: Usage) {
+        self.input_tokens = self.input_tokens.max(other.input_tokens);
+        self.output_tokens = self.output_tokens.max(other.output_tokens);
+    }
+}
+
+/// Usage in one JSON document of any supported provider.
+pub fn usage_of(value: &Value) -> Option<Usage> {
+    let count = |v: &Value, key: &str| v.get(key).and_then(Value::as_u64);
+    let usage = value.get("usage").or_else(|| value.get("message").and_then(|m| m.get("usage")));
+    if let Some(usage) = usage.filter(|u| u.is_object()) {
+        let input = count(usage, "prompt_tokens").unwrap_or_else(|| {
+            count(usage, "input_tokens").unwrap_or(0)
+                + count(usage, "cache_creation_input_tokens").unwrap_or(0)
+                + count(usage, "cache_read_input_tokens").unwrap_or(0)
+        });
+        let output = count(usage, "completion_tokens").or_else(|| count(usage, "output_tokens")).unwrap_or(0);
+        return Some(Usage { input_tokens: input, output_tokens: output });
+    }
+    let input = count(value, "prompt_eval_count");
+    let output = count(value, "eval_count");
+    (input.is_some() || output.is_some()).then(|| Usage {
+        input_tokens: input.unwrap_or(0),
+        output_tokens: output.unwrap_or(0),
+    })
+}
+
+/// Accumulates a response body and reports the usage it carries.
+pub struct Meter {
+    /// Line by line (event streams, NDJSON) rather than one document.
+    lines: bool,
+    buffer: Vec<u8>,
+    overflow: bool,
+    usage: Option<Usage>,
+}
+
+impl Meter {
+    pub fn new(content_type: &str) -> Self {
+        let lines = content_type.starts_with("text/event-stream") || content_type.starts_with("application/x-ndjson");
+        Self { lines, buffer: Vec::new(), overflow: false, usage: None }
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) {
+        if !self.lines {
+            if self.buffer.len() + chunk.len() > MAX_BODY {
+                self.overflow = true;
+            } else {
+                self.buffer.extend_from_slice(chunk);
+            }
+            return;
+        }
+        let mut rest = chunk;
+        while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+            if !self.overflow {
+                self.buffer.extend_from_slice(&rest[..end]);
+                let line = std::mem::take(&mut self.buffer);
+                self.line(&line);
+            }
+            self.buffer.clear();
+            self.overflow = false;
+            rest = &rest[end + 1..];
+        }
+        if self.buffer.len() + rest.len() > MAX_LINE {
+            self.overflow = true;
+        } else if !self.overflow {
+            self.buffer.extend_from_slice(rest);
+        }
+    }
+
+    fn line(&mut self, line: &[u8]) {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let json = match line.strip_prefix(b"data:") {
+            Some(data) => data.trim_ascii_start(),
+            None if line.starts_with(b"{") => line,
+            None => return,
+        };
+        if let Some(usage) = serde_json::from_slice(json).ok().as_ref().and_then(usage_of) {
+            self.usage.get_or_insert_default().merge(usage);
+        }
+    }
+
+    /// Usage found in the body, if any.
+    pub fn finish(mut self) -> Option<Usage> {
+        if self.lines {
+            let line = std::mem::take(&mut self.buffer);
+            if !self.overflow && !line.is_empty() {
+                self.line(&line);
+            }
+            return self.usage;
+        }
+        if self.overflow {
+            return None;
+        }
+        serde_json::from_slice(&self.buffer).ok().as_ref().and_then(usage_of)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metered(content_type: &str, body: &str, chunk: usize) -> Option<Usage> {
+        let mut meter = Meter::new(content_type);
+        for part in body.as_bytes().chunks(chunk) {
+            meter.feed(part);
+        }
+        meter.finish()
+    }
+
+    #[test]
+    fn test_json_bodies() {
+        let openai = r#"{"id":"c","usage":{"prompt_tokens":12,"completion_tokens":30,"total_tokens":42}}"#;
+        assert_eq!(metered("application/json", openai, 7), Some(Usage { input_tokens: 12, output_tokens: 30 }));
+        let anthropic = r#"{"type":"message","usage":{"input_tokens":10,"cache_read_input_tokens":5,"output_tokens":3}}"#;
+        assert_eq!(metered("application/json", anthropic, 100), Some(Usage { input_tokens: 15, output_tokens: 3 }));
+        let ollama = r#"{"model":"llama3","done":true,"prompt_eval_count":8,"eval_count":20}"#;
+        assert_eq!(metered("application/json", ollama, 3), Some(Usage { input_tokens: 8, output_tokens: 20 }));
+        assert_eq!(metered("application/json", r#"{"data":[]}"#, 3), None);
+    }
+
+    #[test]
+    fn test_streams() {
+        let anthropic = "event: message_start\r\n\
+            data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":25,\"output_tokens\":1}}}\r\n\r\n\
+            event: content_block_delta\r\n\
+            data: {\"type\":\"content_block_delta\",\"delta\":{\"text\":\"Hi\"}}\r\n\r\n\
+            event: message_delta\r\n\
+            data: {\"type\":\"message_delta\",\"usage\":{\"output_tokens\":15}}\r\n\r\n";
+        assert_eq!(metered("text/event-stream", anthropic, 11), Some(Usage { input_tokens: 25, output_tokens: 15 }));
+
+        let openai = "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}],\"usage\":null}\n\n\
+            data: {\"choices\":[],\"usage\":{\"prompt_tokens\":9,\"completion_tokens\":4}}\n\n\
+            data: [DONE]\n\n";
+        assert_eq!(metered("text/event-stream; charset=utf-8", openai, 5), Some(Usage { input_tokens: 9, output_tokens: 4 }));
+
+        // Last line without a newline
+        let ollama = "{\"response\":\"Hi\",\"done\":false}\n{\"done\":true,\"prompt_eval_count\":3,\"eval_count\":2}";
+        assert_eq!(metered("application/x-ndjson", ollama, 4), Some(Usage { input_tokens: 3, output_tokens: 2 }));
+    }
+}
@@ -0,0 +1,1179 @@
+//! Container backups: snapshots of the rootfs and workspace of an nspawn
+//! container as tar streams (zstd unless disabled), cataloged per
+//! application and stored locally, on another host (over SSH) or in an
+//! S3-compatible bucket. The supervised `backup-scheduler` service takes
+//! them following each application's policy and deletes the oldest beyond
+//! its retention. Containers of remote hosts are exported by their host
+//! agent as for a migration (`StartNspawnExport`), the stream being written
+//! here as is instead of imported. A backup restores as a new container on
+//! the local host.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use axum::http::{HeaderMap, HeaderValue, Method, Uri};
+use chrono::{DateTime, Local, NaiveDateTime, Timelike, Utc};
+use hr_container::NspawnClient;
+use hr_cron::Schedule;
+use hr_registry::protocol::{capability, HostRegistryMessage};
+use hr_registry::transfer::{ChunkOrder, Compression, StreamManifest, TransferStream};
+use hr_registry::types::{Environment, FrontendEndpoint};
+use hr_registry::AgentRegistry;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+use crate::container_manager::{ContainerManager, ContainerV2Record, ContainerV2Status, RestoreContainerRequest};
+use crate::state::ApiState;
+
+/// Minutes evaluated after a stall, as for the host schedules.
+const MAX_CATCH_UP: i64 = 5;
+/// How long a host agent may take to export a container.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(3 * 3600);
+
+// ── Policy and catalog ───────────────────────────────────────────
+
+/// Where the backups of an application are kept.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum BackupDestination {
+    /// Under the backup root of this server.
+    #[default]
+    Local,
+    /// Copied over SSH to a host of hosts.json, under `path`.
+    Host {
+        #[serde(rename = "hostId")]
+        host_id: String,
+        #[serde(default = "default_remote_dir")]
+        path: String,
+    },
+    /// An S3-compatible bucket (path-style requests), under `prefix`.
+    S3 {
+        endpoint: String,
+        bucket: String,
+        #[serde(default = "default_region")]
+        region: String,
+        #[serde(default)]
+        prefix: String,
+        #[serde(rename = "accessKey")]
+        access_key: String,
+        #[serde(default, rename = "secretKey")]
+        secret_key: String,
+    },
+}
+
+fn default_remote_dir() -> String {
+    "/var/backups/homeroute".to_string()
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+impl BackupDestination {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            BackupDestination::Local => Ok(()),
+            BackupDestination::Host { host_id, path } => {
+                if host_id.is_empty() || host_id == "local" {
+                    return Err("The destination host must be a remote host".to_string());
+                }
+                if !path.starts_with('/') || path.contains('\'') {
+                    return Err(format!("Invalid remote path: {path}"));
+                }
+                Ok(())
+            }
+            BackupDestination::S3 { endpoint, bucket, access_key, secret_key, .. } => {
+                if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+                    return Err(format!("Invalid S3 endpoint: {endpoint}"));
+                }
+                if bucket.is_empty() || bucket.contains('/') {
+                    return Err(format!("Invalid S3 bucket: {bucket}"));
+                }
+                if access_key.is_empty() || secret_key.is_empty() {
+                    return Err("S3 credentials are required".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The destination as shown by the API, without the S3 secret.
+    pub fn redacted(&self) -> Self {
+        let mut dest = self.clone();
+        if let BackupDestination::S3 { secret_key, .. } = &mut dest {
+            secret_key.clear();
+        }
+        dest
+    }
+
+    /// Keep the secret of `previous` when an update omits it (as the API
+    /// never returns it) for the same bucket and key.
+    fn keep_secret(&mut self, previous: &BackupDestination) {
+        if let (
+            BackupDestination::S3 { endpoint, bucket, access_key, secret_key, .. },
+            BackupDestination::S3 { endpoint: e, bucket: b, access_key: a, secret_key: s, .. },
+        ) = (self, previous)
+            && secret_key.is_empty()
+            && (&*endpoint, &*bucket, &*access_key) == (e, b, a)
+        {
+            *secret_key = s.clone();
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupPolicy {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Cron expression, in local time.
+    #[serde(default = "default_schedule")]
+    pub schedule: String,
+    /// Backups kept for the application, manual ones included.
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+    #[serde(default)]
+    pub destination: BackupDestination,
+    /// zstd level, 0 for plain tar (transfer default when unset).
+    #[serde(default)]
+    pub compression_level: Option<i32>,
+}
+
+fn default_schedule() -> String {
+    "0 3 * * *".to_string()
+}
+
+fn default_keep() -> usize {
+    7
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            schedule: default_schedule(),
+            keep: default_keep(),
+            destination: BackupDestination::Local,
+            compression_level: None,
+        }
+    }
+}
+
+impl BackupPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        let schedule = Schedule::parse(&self.schedule).map_err(|e| format!("Invalid cron expression: {e}"))?;
+        if schedule.next_after(Local::now().naive_local()).is_none() {
+            return Err("The schedule never fires".to_string());
+        }
+        if self.keep == 0 {
+            return Err("At least one backup must be kept".to_string());
+        }
+        self.destination.validate()
+    }
+
+    /// Next scheduled backup after `after`, `None` when disabled.
+    pub fn next_run(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        if !self.enabled {
+            return None;
+        }
+        Schedule::parse(&self.schedule).ok()?.next_after(after)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupTrigger {
+    Scheduled,
+    Manual,
+}
+
+impl BackupTrigger {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackupTrigger::Scheduled => "scheduled",
+            BackupTrigger::Manual => "manual",
+        }
+    }
+}
+
+/// A backup of the catalog, with what a restore needs to recreate the
+/// application.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupRecord {
+    pub id: String,
+    pub app_id: String,
+    pub name: String,
+    pub slug: String,
+    pub environment: Environment,
+    pub frontend: FrontendEndpoint,
+    pub code_server_enabled: bool,
+    /// Host the container ran on.
+    pub host_id: String,
+    pub created_at: DateTime<Utc>,
+    pub trigger: BackupTrigger,
+    pub compression: Compression,
+    /// Stored size of the rootfs archive.
+    pub rootfs_bytes: u64,
+    /// Stored size of the workspace archive, if the container has one.
+    #[serde(default)]
+    pub workspace_bytes: Option<u64>,
+    pub destination: BackupDestination,
+}
+
+impl BackupRecord {
+    /// Archives of the backup.
+    pub fn files(&self) -> Vec<String> {
+        let mut files = vec![archive_name(TransferStream::Container, self.compression)];
+        if self.workspace_bytes.is_some() {
+            files.push(archive_name(TransferStream::Workspace, self.compression));
+        }
+        files
+    }
+
+    /// The record as shown by the API.
+    pub fn redacted(&self) -> Self {
+        Self { destination: self.destination.redacted(), ..self.clone() }
+    }
+}
+
+/// File name of the archive of `stream`.
+pub fn archive_name(stream: TransferStream, compression: Compression) -> String {
+    let base = match stream {
+        TransferStream::Container => "rootfs.tar",
+        TransferStream::Workspace => "workspace.tar",
+    };
+    match compression {
+        Compression::None => base.to_string(),
+        Compression::Zstd { .. } => format!("{base}.zst"),
+    }
+}
+
+/// Backups of `app_id` beyond the `keep` most recent ones.
+pub fn expired(backups: &[BackupRecord], app_id: &str, keep: usize) -> Vec<BackupRecord> {
+    let mut own: Vec<&BackupRecord> = backups.iter().filter(|b| b.app_id == app_id).collect();
+    own.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+    own.into_iter().skip(keep.max(1)).cloned().collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupRun {
+    pub at: String,
+    pub trigger: BackupTrigger,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Catalog {
+    #[serde(default)]
+    policies: HashMap<String, BackupPolicy>,
+    #[serde(default)]
+    backups: Vec<BackupRecord>,
+    /// Last run of each application.
+    #[serde(default)]
+    runs: HashMap<String, BackupRun>,
+}
+
+/// A remote export written as a backup.
+struct Receiver {
+    host_id: String,
+    dir: PathBuf,
+    compression: Compression,
+    /// The host replays its stream on `ResumeTransfer`.
+    resumable: bool,
+    stream: TransferStream,
+    file: tokio::fs::File,
+    manifest: StreamManifest,
+    resume_requested: bool,
+    rootfs_bytes: u64,
+    done: oneshot::Sender<Result<(u64, Option<u64>), String>>,
+}
+
+impl Receiver {
+    async fn finish(mut self, result: Result<(), String>) {
+        let result = match result {
+            Ok(()) => self.file.flush().await.map_err(|e| e.to_string()).map(|_| match self.stream {
+                TransferStream::Container => (self.manifest.offset(), None),
+                TransferStream::Workspace => (self.rootfs_bytes, Some(self.manifest.offset())),
+            }),
+            Err(e) => Err(e),
+        };
+        let _ = self.done.send(result);
+    }
+}
+
+/// Policies, catalog and remote exports in progress.
+pub struct BackupManager {
+    catalog: RwLock<Catalog>,
+    path: PathBuf,
+    /// Local backups and staging area of the others.
+    root: PathBuf,
+    /// Applications with a backup in progress.
+    running: Mutex<HashSet<String>>,
+    /// Remote exports being received, by transfer_id.
+    receivers: tokio::sync::Mutex<HashMap<String, Receiver>>,
+}
+
+impl BackupManager {
+    pub fn new(path: PathBuf, root: PathBuf) -> Self {
+        let catalog: Catalog = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        info!(backups = catalog.backups.len(), "Loaded backup catalog");
+        Self {
+            catalog: RwLock::new(catalog),
+            path,
+            root,
+            running: Mutex::new(HashSet::new()),
+            receivers: tokio::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn policy(&self, app_id: &str) -> BackupPolicy {
+        self.catalog.read().unwrap().policies.get(app_id).cloned().unwrap_or_default()
+    }
+
+    pub fn set_policy(&self, app_id: &str, mut policy: BackupPolicy) -> Result<BackupPolicy, String> {
+        policy.destination.keep_secret(&self.policy(app_id).destination);
+        policy.validate()?;
+        self.update(|catalog| {
+            catalog.policies.insert(app_id.to_string(), policy.clone());
+        });
+        Ok(policy)
+    }
+
+    /// Backups of an application, newest first.
+    pub fn backups(&self, app_id: &str) -> Vec<BackupRecord> {
+        let mut backups: Vec<BackupRecord> = self
+            .catalog
+            .read()
+            .unwrap()
+            .backups
+            .iter()
+            .filter(|b| b.app_id == app_id)
+            .cloned()
+            .collect();
+        backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+        backups
+    }
+
+    pub fn backup(&self, id: &str) -> Option<BackupRecord> {
+        self.catalog.read().unwrap().backups.iter().find(|b| b.id == id).cloned()
+    }
+
+    pub fn last_run(&self, app_id: &str) -> Option<BackupRun> {
+        self.catalog.read().unwrap().runs.get(app_id).cloned()
+    }
+
+    pub fn is_running(&self, app_id: &str) -> bool {
+        self.running.lock().unwrap().contains(app_id)
+    }
+
+    fn local_dir(&self, app_id: &str, backup_id: &str) -> PathBuf {
+        self.root.join(app_id).join(backup_id)
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Catalog)) {
+        let content = {
+            let mut catalog = self.catalog.write().unwrap();
+            f(&mut catalog);
+            serde_json::to_string_pretty(&*catalog)
+        };
+        let saved = content.map_err(anyhow::Error::from).and_then(|content| {
+            let tmp_path = self.path.with_extension("json.tmp");
+            std::fs::write(&tmp_path, content)?;
+            std::fs::rename(&tmp_path, &self.path)?;
+            Ok(())
+        });
+        if let Err(e) = saved {
+            warn!("Failed to save backup catalog: {}", e);
+        }
+    }
+
+    /// Delete a backup from its destination and the catalog.
+    pub async fn delete(&self, id: &str) -> Result<bool, String> {
+        let Some(record) = self.backup(id) else {
+            return Ok(false);
+        };
+        remove_stored(&record, &self.local_dir(&record.app_id, &record.id)).await?;
+        self.update(|catalog| catalog.backups.retain(|b| b.id != id));
+        Ok(true)
+    }
+
+    // ── Remote exports (routed by the host agent socket) ─────────
+    // Each handler returns whether the transfer is a backup.
+
+    pub async fn receives(&self, transfer_id: &str) -> bool {
+        self.receivers.lock().await.contains_key(transfer_id)
+    }
+
+    pub async fn on_chunk(&self, registry: &AgentRegistry, transfer_id: &str, sequence: u32, offset: u64, checksum: u32, data: &[u8]) -> bool {
+        let mut receivers = self.receivers.lock().await;
+        let Some(receiver) = receivers.get_mut(transfer_id) else { return false };
+        if receiver.resumable {
+            match receiver.manifest.check(sequence, offset) {
+                ChunkOrder::Next => receiver.resume_requested = false,
+                ChunkOrder::Duplicate => return true,
+                ChunkOrder::Gap => {
+                    if !receiver.resume_requested {
+                        receiver.resume_requested = true;
+                        let point = receiver.manifest.resume_point(receiver.stream);
+                        let _ = registry.send_host_command(
+                            &receiver.host_id,
+                            HostRegistryMessage::ResumeTransfer { transfer_id: transfer_id.to_string(), point },
+                        ).await;
+                    }
+                    return true;
+                }
+            }
+        }
+        if let Err(e) = receiver.file.write_all(data).await {
+            let receiver = receivers.remove(transfer_id).unwrap();
+            let _ = registry.send_host_command(
+                &receiver.host_id,
+                HostRegistryMessage::CancelTransfer { transfer_id: transfer_id.to_string() },
+            ).await;
+            receiver.finish(Err(format!("Write error: {e}"))).await;
+            return true;
+        }
+        receiver.manifest.record(data.len() as u32, checksum);
+        true
+    }
+
+    pub async fn on_workspace_ready(&self, transfer_id: &str) -> bool {
+        let mut receivers = self.receivers.lock().await;
+        let Some(receiver) = receivers.get_mut(transfer_id) else { return false };
+        if receiver.stream != TransferStream::Container || receiver.resume_requested {
+            return true;
+        }
+        let path = receiver.dir.join(archive_name(TransferStream::Workspace, receiver.compression));
+        let file = match tokio::fs::File::create(&path).await {
+            Ok(file) => file,
+            Err(e) => {
+                let receiver = receivers.remove(transfer_id).unwrap();
+                receiver.finish(Err(format!("Failed to create workspace archive: {e}"))).await;
+                return true;
+            }
+        };
+        let _ = receiver.file.flush().await;
+        receiver.rootfs_bytes = receiver.manifest.offset();
+        receiver.stream = TransferStream::Workspace;
+        receiver.file = file;
+        receiver.manifest = StreamManifest::default();
+        true
+    }
+
+    pub async fn on_manifest(&self, registry: &AgentRegistry, transfer_id: &str, stream: TransferStream, chunk_count: u32, total_bytes: u64, digest: u64) -> bool {
+        let mut receivers = self.receivers.lock().await;
+        let Some(receiver) = receivers.get_mut(transfer_id) else { return false };
+        if receiver.stream != stream {
+            return true;
+        }
+        if receiver.manifest.next_sequence() < chunk_count {
+            // The last chunks were lost: ask for them
+            if !receiver.resume_requested {
+                receiver.resume_requested = true;
+                let point = receiver.manifest.resume_point(stream);
+                let _ = registry.send_host_command(
+                    &receiver.host_id,
+                    HostRegistryMessage::ResumeTransfer { transfer_id: transfer_id.to_string(), point },
+                ).await;
+            }
+        } else if let Err(e) = receiver.manifest.verify(chunk_count, total_bytes, digest) {
+            let receiver = receivers.remove(transfer_id).unwrap();
+            receiver.finish(Err(e)).await;
+        }
+        true
+    }
+
+    pub async fn on_complete(&self, transfer_id: &str) -> bool {
+        let mut receivers = self.receivers.lock().await;
+        let Some(receiver) = receivers.get(transfer_id) else { return false };
+        // With a resume pending the end of the stream is missing: finish
+        // after the replay
+        if !receiver.resume_requested {
+            receivers.remove(transfer_id).unwrap().finish(Ok(())).await;
+        }
+        true
+    }
+
+    pub async fn on_failed(&self, transfer_id: &str, error: &str) -> bool {
+        let Some(receiver) = self.receivers.lock().await.remove(transfer_id) else { return false };
+        receiver.finish(Err(error.to_string())).await;
+        true
+    }
+
+    /// Ask a reconnected host to resume the exports it was sending.
+    pub async fn resume(&self, registry: &AgentRegistry, host_id: &str) {
+        for (transfer_id, receiver) in self.receivers.lock().await.iter_mut() {
+            if receiver.host_id != host_id || !receiver.resumable {
+                continue;
+            }
+            receiver.resume_requested = true;
+            let point = receiver.manifest.resume_point(receiver.stream);
+            info!(transfer_id = %transfer_id, ?point, "Resuming backup export from host");
+            let _ = registry.send_host_command(
+                host_id,
+                HostRegistryMessage::ResumeTransfer { transfer_id: transfer_id.clone(), point },
+            ).await;
+        }
+    }
+}
+
+// ── Backup ───────────────────────────────────────────────────────
+
+/// Start a backup of application `app_id` in the background, unless one is
+/// already running.
+pub fn start_backup(state: &ApiState, app_id: &str, trigger: BackupTrigger) -> bool {
+    if !state.backups.running.lock().unwrap().insert(app_id.to_string()) {
+        return false;
+    }
+    let (state, app_id) = (state.clone(), app_id.to_string());
+    tokio::spawn(async move {
+        run_backup(&state, &app_id, trigger).await;
+    });
+    true
+}
+
+/// Take a backup following the policy of the application, then apply the
+/// retention.
+async fn run_backup(state: &ApiState, app_id: &str, trigger: BackupTrigger) {
+    let manager = &state.backups;
+    let result = take_backup(state, app_id, trigger).await;
+    manager.running.lock().unwrap().remove(app_id);
+
+    match &result {
+        Ok(record) => info!(app_id, backup_id = %record.id, trigger = trigger.as_str(), "Backup complete"),
+        Err(e) => warn!(app_id, trigger = trigger.as_str(), "Backup failed: {e}"),
+    }
+    hr_common::metrics::registry()
+        .counter(
+            "homeroute_backups_total",
+            "Container backups by trigger and outcome.",
+            &[("trigger", trigger.as_str()), ("status", if result.is_ok() { "ok" } else { "error" })],
+        )
+        .inc();
+    let run = BackupRun {
+        at: Local::now().to_rfc3339(),
+        trigger,
+        success: result.is_ok(),
+        backup_id: result.as_ref().ok().map(|r| r.id.clone()),
+        error: result.as_ref().err().cloned(),
+    };
+    manager.update(|catalog| {
+        catalog.runs.insert(app_id.to_string(), run);
+    });
+}
+
+async fn take_backup(state: &ApiState, app_id: &str, trigger: BackupTrigger) -> Result<BackupRecord, String> {
+    let manager = &state.backups;
+    let cm = state.container_manager.as_ref().ok_or("Container manager not available")?;
+    let registry = state.registry.as_ref().ok_or("Registry not available")?;
+    let container = cm.record(app_id).await.ok_or("Container not found")?;
+    let app = registry.get_application(app_id).await.ok_or("Application not found")?;
+    let policy = manager.policy(app_id);
+
+    let supported = container.host_id == "local"
+        || registry.host_supports(&container.host_id, capability::ZSTD_TRANSFERS).await;
+    let compression = Compression::negotiate(policy.compression_level, supported);
+    let id = uuid::Uuid::new_v4().to_string();
+    let dir = manager.local_dir(app_id, &id);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+    info!(app_id, backup_id = %id, container = %container.container_name, host_id = %container.host_id, ?compression, "Starting backup");
+
+    let snapshot = if container.host_id == "local" {
+        snapshot_local(cm, &container, &dir, compression).await
+    } else {
+        snapshot_remote(state, registry, cm, &container, &dir, compression, &id).await
+    };
+    let (rootfs_bytes, workspace_bytes) = match snapshot {
+        Ok(sizes) => sizes,
+        Err(e) => {
+            let _ = tokio::fs::remove_dir_all(&dir).await;
+            return Err(e);
+        }
+    };
+
+    let record = BackupRecord {
+        id,
+        app_id: app_id.to_string(),
+        name: container.name.clone(),
+        slug: container.slug.clone(),
+        environment: container.environment,
+        frontend: app.frontend,
+        code_server_enabled: app.code_server_enabled,
+        host_id: container.host_id.clone(),
+        created_at: Utc::now(),
+        trigger,
+        compression,
+        rootfs_bytes,
+        workspace_bytes,
+        destination: policy.destination.clone(),
+    };
+    let stored = store(&record, &dir).await;
+    if record.destination != BackupDestination::Local || stored.is_err() {
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+    stored?;
+
+    let mut old = Vec::new();
+    manager.update(|catalog| {
+        catalog.backups.push(record.clone());
+        old = expired(&catalog.backups, app_id, policy.keep);
+    });
+    for backup in old {
+        info!(app_id, backup_id = %backup.id, "Deleting expired backup");
+        if let Err(e) = manager.delete(&backup.id).await {
+            warn!(backup_id = %backup.id, "Failed to delete expired backup: {e}");
+        }
+    }
+    Ok(record)
+}
+
+/// Archive a local container, stopped for the time of the snapshot.
+async fn snapshot_local(
+    cm: &ContainerManager,
+    container: &ContainerV2Record,
+    dir: &Path,
+    compression: Compression,
+) -> Result<(u64, Option<u64>), String> {
+    let storage = PathBuf::from(cm.resolve_storage_path("local").await);
+    let running = container.status == ContainerV2Status::Running;
+    if running {
+        NspawnClient::stop_container(&container.container_name)
+            .await
+            .map_err(|e| format!("Failed to stop container: {e}"))?;
+    }
+    let result = async {
+        let rootfs = archive(
+            &storage.join(&container.container_name),
+            &dir.join(archive_name(TransferStream::Container, compression)),
+            compression,
+        )
+        .await?;
+        let ws_dir = storage.join(format!("{}-workspace", container.container_name));
+        let workspace = if ws_dir.is_dir() {
+            Some(archive(&ws_dir, &dir.join(archive_name(TransferStream::Workspace, compression)), compression).await?)
+        } else {
+            None
+        };
+        Ok((rootfs, workspace))
+    }
+    .await;
+    if running && let Err(e) = NspawnClient::start_container(&container.container_name).await {
+        warn!(container = %container.container_name, "Failed to restart container after backup: {e}");
+    }
+    result
+}
+
+/// Have the host agent export the container, as for a migration, into
+/// `dir`. The agent stops the container; it is started again afterwards.
+async fn snapshot_remote(
+    state: &ApiState,
+    registry: &AgentRegistry,
+    cm: &ContainerManager,
+    container: &ContainerV2Record,
+    dir: &Path,
+    compression: Compression,
+    transfer_id: &str,
+) -> Result<(u64, Option<u64>), String> {
+    let manager = &state.backups;
+    let host_id = &container.host_id;
+    let path = dir.join(archive_name(TransferStream::Container, compression));
+    let file = tokio::fs::File::create(&path)
+        .await
+        .map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+    let (done, result) = oneshot::channel();
+    manager.receivers.lock().await.insert(transfer_id.to_string(), Receiver {
+        host_id: host_id.clone(),
+        dir: dir.to_path_buf(),
+        compression,
+        resumable: registry.host_supports(host_id, capability::RESUMABLE_TRANSFERS).await,
+        stream: TransferStream::Container,
+        file,
+        manifest: StreamManifest::default(),
+        resume_requested: false,
+        rootfs_bytes: 0,
+        done,
+    });
+
+    if let Err(e) = registry.send_host_command(
+        host_id,
+        HostRegistryMessage::StartNspawnExport {
+            container_name: container.container_name.clone(),
+            storage_path: cm.resolve_storage_path(host_id).await,
+            transfer_id: transfer_id.to_string(),
+            compression,
+        },
+    ).await {
+        manager.receivers.lock().await.remove(transfer_id);
+        return Err(format!("Failed to start export: {e}"));
+    }
+
+    let result = match tokio::time::timeout(EXPORT_TIMEOUT, result).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(_)) => Err("Export abandoned".to_string()),
+        Err(_) => {
+            manager.receivers.lock().await.remove(transfer_id);
+            let _ = registry.send_host_command(
+                host_id,
+                HostRegistryMessage::CancelTransfer { transfer_id: transfer_id.to_string() },
+            ).await;
+            Err("Export timed out".to_string())
+        }
+    };
+    if container.status == ContainerV2Status::Running
+        && let Err(e) = registry.send_host_command(
+            host_id,
+            HostRegistryMessage::StartContainer { container_name: container.container_name.clone() },
+        ).await
+    {
+        warn!(container = %container.container_name, host_id, "Failed to restart container after backup: {e}");
+    }
+    result
+}
+
+/// Write `dir` as tar to `dest`, compressed as requested. Returns the
+/// stored size.
+async fn archive(dir: &Path, dest: &Path, compression: Compression) -> Result<u64, String> {
+    let mut tar = tokio::process::Command::new("tar")
+        .args(["cf", "-", "-C", &dir.to_string_lossy(), "."])
+        .stdout(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to spawn tar: {e}"))?;
+    let mut reader = compression.encoder(tar.stdout.take().unwrap());
+    let mut file = tokio::fs::File::create(dest)
+        .await
+        .map_err(|e| format!("Failed to create {}: {e}", dest.display()))?;
+    let size = tokio::io::copy(&mut reader, &mut file)
+        .await
+        .map_err(|e| format!("Failed to archive {}: {e}", dir.display()))?;
+    file.flush().await.map_err(|e| e.to_string())?;
+    let status = tar.wait().await.map_err(|e| e.to_string())?;
+    if !status.success() {
+        return Err(format!("tar failed on {}: {status}", dir.display()));
+    }
+    Ok(size)
+}
+
+/// Extract an archive written by `archive` (or a host agent export) into `dir`.
+pub(crate) async fn extract(archive: &Path, dir: &Path, compression: Compression) -> Result<(), String> {
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+    let mut tar = tokio::process::Command::new("tar")
+        .args(["xf", "-", "--numeric-owner", "--xattrs", "--xattrs-include=*", "-C", &dir.to_string_lossy()])
+        .stdin(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to spawn tar: {e}"))?;
+    let mut writer = compression.decoder(tar.stdin.take().unwrap());
+    let mut file = tokio::fs::File::open(archive)
+        .await
+        .map_err(|e| format!("Failed to open {}: {e}", archive.display()))?;
+    let copied = tokio::io::copy(&mut file, &mut writer).await;
+    let shutdown = writer.shutdown().await;
+    drop(writer);
+    let output = tar.wait_with_output().await.map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(format!("tar extract failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    copied.and(shutdown).map_err(|e| format!("Failed to extract {}: {e}", archive.display()))?;
+    Ok(())
+}
+
+// ── Restore ──────────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+pub struct RestoreRequest {
+    pub slug: String,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Restore a backup as a new container on the local host. The application
+/// is created right away, the container deployed in the background.
+pub async fn restore_backup(state: &ApiState, backup: BackupRecord, req: RestoreRequest) -> Result<(ContainerV2Record, String), String> {
+    let cm = state.container_manager.clone().ok_or("Container manager not available")?;
+    let (record, token) = cm
+        .create_restored(RestoreContainerRequest {
+            name: req.name.unwrap_or_else(|| backup.name.clone()),
+            slug: req.slug,
+            environment: backup.environment,
+            frontend: backup.frontend.clone(),
+            code_server_enabled: backup.code_server_enabled,
+        })
+        .await?;
+    info!(backup_id = %backup.id, app_id = %record.id, slug = %record.slug, "Restoring backup as a new container");
+
+    let manager = state.backups.clone();
+    let (restored, token_deploy) = (record.clone(), token.clone());
+    tokio::spawn(async move {
+        let local = manager.local_dir(&backup.app_id, &backup.id);
+        let (dir, staged) = match &backup.destination {
+            BackupDestination::Local => (local, false),
+            _ => (manager.root.join("restore").join(&restored.id), true),
+        };
+        let fetched = fetch(&backup, &dir).await;
+        let archives = fetched.map(|()| {
+            let workspace = backup.workspace_bytes.map(|_| dir.join(archive_name(TransferStream::Workspace, backup.compression)));
+            (dir.join(archive_name(TransferStream::Container, backup.compression)), workspace, backup.compression)
+        });
+        cm.deploy_restored(&restored, &token_deploy, archives).await;
+        if staged {
+            let _ = tokio::fs::remove_dir_all(&dir).await;
+        }
+    });
+    Ok((record, token))
+}
+
+// ── Destinations ─────────────────────────────────────────────────
+
+/// Copy the archives of `dir` to the destination of `record`.
+async fn store(record: &BackupRecord, dir: &Path) -> Result<(), String> {
+    match &record.destination {
+        BackupDestination::Local => Ok(()),
+        BackupDestination::Host { host_id, path } => {
+            let host = SshHost::find(host_id).await?;
+            let remote = format!("{path}/{}/{}", record.app_id, record.id);
+            host.run(&format!("mkdir -p '{remote}'")).await?;
+            for name in record.files() {
+                host.copy(&dir.join(&name).to_string_lossy(), &host.remote(&format!("{remote}/{name}"))).await?;
+            }
+            Ok(())
+        }
+        BackupDestination::S3 { .. } => {
+            let bucket = S3Bucket::new(&record.destination);
+            for name in record.files() {
+                bucket.put(&bucket.key(record, &name), &dir.join(&name)).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Bring the archives of `record` into `dir`.
+async fn fetch(record: &BackupRecord, dir: &Path) -> Result<(), String> {
+    if record.destination != BackupDestination::Local {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+    }
+    match &record.destination {
+        BackupDestination::Local => Ok(()),
+        BackupDestination::Host { host_id, path } => {
+            let host = SshHost::find(host_id).await?;
+            for name in record.files() {
+                let remote = format!("{path}/{}/{}/{name}", record.app_id, record.id);
+                host.copy(&host.remote(&remote), &dir.join(&name).to_string_lossy()).await?;
+            }
+            Ok(())
+        }
+        BackupDestination::S3 { .. } => {
+            let bucket = S3Bucket::new(&record.destination);
+            for name in record.files() {
+                bucket.get(&bucket.key(record, &name), &dir.join(&name)).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Remove the archives of `record` (kept in `local` when local).
+async fn remove_stored(record: &BackupRecord, local: &Path) -> Result<(), String> {
+    match &record.destination {
+        BackupDestination::Local => match tokio::fs::remove_dir_all(local).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!("Failed to remove {}: {e}", local.display())),
+            _ => Ok(()),
+        },
+        BackupDestination::Host { host_id, path } => {
+            let host = SshHost::find(host_id).await?;
+            host.run(&format!("rm -rf '{path}/{}/{}'", record.app_id, record.id)).await.map(|_| ())
+        }
+        BackupDestination::S3 { .. } => {
+            let bucket = S3Bucket::new(&record.destination);
+            for name in record.files() {
+                bucket.delete(&bucket.key(record, &name)).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// A host of hosts.json, reached with the server's SSH key.
+struct SshHost {
+    addr: String,
+    port: u16,
+    user: String,
+}
+
+impl SshHost {
+    async fn find(host_id: &str) -> Result<Self, String> {
+        let data = crate::routes::hosts::load_hosts().await;
+        let host = crate::routes::hosts::find_host(&data, host_id).ok_or_else(|| format!("Host {host_id} not found"))?;
+        Ok(Self {
+            addr: host.get("host").and_then(|h| h.as_str()).unwrap_or_default().to_string(),
+            port: host.get("port").and_then(|p| p.as_u64()).unwrap_or(22) as u16,
+            user: host.get("username").and_then(|u| u.as_str()).unwrap_or("root").to_string(),
+        })
+    }
+
+    fn remote(&self, path: &str) -> String {
+        format!("{}@{}:{}", self.user, self.addr, path)
+    }
+
+    async fn run(&self, command: &str) -> Result<String, String> {
+        crate::routes::hosts::ssh_command(&self.addr, self.port, &self.user, command).await
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<(), String> {
+        let output = tokio::process::Command::new("scp")
+            .args([
+                "-i", crate::routes::hosts::SSH_KEY_PATH,
+                "-o", "StrictHostKeyChecking=no",
+                "-o", "ConnectTimeout=15",
+                "-o", "BatchMode=yes",
+                "-P", &self.port.to_string(),
+                from,
+                to,
+            ])
+            .output()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(format!("scp failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(())
+    }
+}
+
+/// Minimal S3 client: whole-object PUT, GET and DELETE signed with SigV4.
+struct S3Bucket<'a> {
+    endpoint: &'a str,
+    bucket: &'a str,
+    region: &'a str,
+    prefix: &'a str,
+    access_key: &'a str,
+    secret_key: &'a str,
+}
+
+impl<'a> S3Bucket<'a> {
+    fn new(destination: &'a BackupDestination) -> Self {
+        let BackupDestination::S3 { endpoint, bucket, region, prefix, access_key, secret_key } = destination else {
+            unreachable!("not an S3 destination");
+        };
+        Self { endpoint, bucket, region, prefix, access_key, secret_key }
+    }
+
+    fn key(&self, record: &BackupRecord, name: &str) -> String {
+        let prefix = self.prefix.trim_matches('/');
+        let prefix = if prefix.is_empty() { String::new() } else { format!("{prefix}/") };
+        format!("{prefix}{}/{}/{name}", record.app_id, record.id)
+    }
+
+    async fn send(&self, method: Method, key: &str, body: Option<reqwest::Body>, length: u64) -> Result<reqwest::Response, String> {
+        use hr_s3::sigv4;
+
+        let url = format!(
+            "{}/{}/{}",
+            self.endpoint.trim_end_matches('/'),
+            sigv4::uri_encode(self.bucket, true),
+            sigv4::uri_encode(key, false)
+        );
+        let parsed = reqwest::Url::parse(&url).map_err(|e| format!("Invalid S3 URL {url}: {e}"))?;
+        let host = match parsed.port() {
+            Some(port) => format!("{}:{port}", parsed.host_str().unwrap_or_default()),
+            None => parsed.host_str().unwrap_or_default().to_string(),
+        };
+        let uri: Uri = url.parse().map_err(|e| format!("Invalid S3 URL {url}: {e}"))?;
+        let mut headers = HeaderMap::new();
+        let header = |v: &str| HeaderValue::from_str(v).map_err(|e| e.to_string());
+        headers.insert("host", header(&host)?);
+        headers.insert("x-amz-date", header(&Utc::now().format("%Y%m%dT%H%M%SZ").to_string())?);
+        headers.insert("x-amz-content-sha256", HeaderValue::from_static(sigv4::UNSIGNED_PAYLOAD));
+        let authorization = sigv4::authorization(&method, &uri, &headers, self.access_key, self.secret_key, self.region)
+            .map_err(|e| e.to_string())?;
+        headers.remove("host");
+
+        let mut request = reqwest::Client::new()
+            .request(method.clone(), parsed)
+            .headers(headers)
+            .header("authorization", authorization);
+        if let Some(body) = body {
+            request = request.header("content-length", length).body(body);
+        }
+        let response = request.send().await.map_err(|e| format!("S3 {method} {key}: {e}"))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(format!("S3 {method} {key}: {status} {}", text.trim()));
+        }
+        Ok(response)
+    }
+
+    async fn put(&self, key: &str, path: &Path) -> Result<(), String> {
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+        let length = file.metadata().await.map_err(|e| e.to_string())?.len();
+        self.send(Method::PUT, key, Some(file.into()), length).await.map(|_| ())
+    }
+
+    async fn get(&self, key: &str, path: &Path) -> Result<(), String> {
+        let mut response = self.send(Method::GET, key, None, 0).await?;
+        let mut file = tokio::fs::File::create(path)
+            .await
+            .map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+        while let Some(chunk) = response.chunk().await.map_err(|e| format!("S3 GET {key}: {e}"))? {
+            file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+        }
+        file.flush().await.map_err(|e| e.to_string())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), String> {
+        self.send(Method::DELETE, key, None, 0).await.map(|_| ())
+    }
+}
+
+// ── Scheduler ────────────────────────────────────────────────────
+
+/// Wakes every minute and starts the backups due in the minutes elapsed.
+pub async fn run_backup_scheduler(state: ApiState) -> anyhow::Result<()> {
+    let mut last = minute(Local::now().naive_local());
+    loop {
+        let now = Local::now().naive_local();
+        let wait = 60 - now.second() as u64;
+        tokio::time::sleep(Duration::from_secs(wait)).await;
+
+        let current = minute(Local::now().naive_local());
+        let first = (last + chrono::Duration::minutes(1)).max(current - chrono::Duration::minutes(MAX_CATCH_UP - 1));
+        if current < first {
+            // Clock moved back: do not fire the same minutes twice
+            last = last.max(current);
+            continue;
+        }
+        let policies: Vec<(String, BackupPolicy)> = state
+            .backups
+            .catalog
+            .read()
+            .unwrap()
+            .policies
+            .iter()
+            .filter(|(_, p)| p.enabled)
+            .map(|(id, p)| (id.clone(), p.clone()))
+            .collect();
+        for (app_id, policy) in policies {
+            let Ok(schedule) = Schedule::parse(&policy.schedule) else { continue };
+            let mut t = first;
+            let mut due = false;
+            while t <= current && !due {
+                due = schedule.matches(t);
+                t += chrono::Duration::minutes(1);
+            }
+            if due && !start_backup(&state, &app_id, BackupTrigger::Scheduled) {
+                warn!(app_id, "Scheduled backup skipped, the previous one is still running");
+            }
+        }
+        last = current;
+    }
+}
+
+fn minute(t: NaiveDateTime) -> NaiveDateTime {
+    t.with_second(0).and_then(|t| t.with_nanosecond(0)).unwrap_or(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, app_id: &str, minutes: i64) -> BackupRecord {
+        BackupRecord {
+            id: id.to_string(),
+            app_id: app_id.to_string(),
+            name: "App".to_string(),
+            slug: "app".to_string(),
+            environment: Environment::Development,
+            frontend: serde_json::from_value(serde_json::json!({"target_port": 3000})).unwrap(),
+            code_server_enabled: true,
+            host_id: "local".to_string(),
+            created_at: DateTime::from_timestamp(1_790_000_000 + minutes * 60, 0).unwrap(),
+            trigger: BackupTrigger::Scheduled,
+            compression: Compression::Zstd { level: 3 },
+            rootfs_bytes: 1,
+            workspace_bytes: None,
+            destination: BackupDestination::Local,
+        }
+    }
+
+    #[test]
+    fn test_retention() {
+        let backups = vec![
+            record("a1", "a", 0),
+            record("b1", "b", 1),
+            record("a3", "a", 20),
+            record("a2", "a", 10),
+        ];
+        let ids = |list: Vec<BackupRecord>| list.into_iter().map(|b| b.id).collect::<Vec<_>>();
+        assert_eq!(ids(expired(&backups, "a", 2)), vec!["a1"]);
+        assert_eq!(ids(expired(&backups, "a", 1)), vec!["a2", "a1"]);
+        assert!(expired(&backups, "a", 3).is_empty());
+        // The latest backup is never expired
+        assert_eq!(ids(expired(&backups, "b", 0)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy: BackupPolicy = serde_json::from_value(serde_json::json!({
+            "enabled": true,
+            "destination": {"type": "s3", "endpoint": "https://s3.example.com", "bucket": "backups", "accessKey": "AK", "secretKey": "SK"}
+        }))
+        .unwrap();
+        assert_eq!(policy.schedule, "0 3 * * *");
+        assert_eq!(policy.keep, 7);
+        assert!(policy.validate().is_ok());
+        let next = policy.next_run(NaiveDateTime::parse_from_str("2026-06-01 04:00", "%Y-%m-%d %H:%M").unwrap());
+        assert_eq!(next.unwrap().to_string(), "2026-06-02 03:00:00");
+
+        // The API never returns the secret, an update without it keeps it
+        let shown = serde_json::to_value(policy.destination.redacted()).unwrap();
+        assert_eq!(shown["secretKey"], "");
+        let mut updated: BackupDestination = serde_json::from_value(shown).unwrap();
+        updated.keep_secret(&policy.destination);
+        assert_eq!(updated, policy.destination);
+
+        let invalid = |p: BackupPolicy| p.validate().is_err();
+        assert!(invalid(BackupPolicy { schedule: "61 * * * *".to_string(), ..Default::default() }));
+        assert!(invalid(BackupPolicy { keep: 0, ..Default::default() }));
+        assert!(invalid(BackupPolicy {
+            destination: BackupDestination::Host { host_id: "h1".to_string(), path: "backups".to_string() },
+            ..Default::default()
+        }));
+        assert!(!invalid(BackupPolicy {
+            destination: BackupDestination::Host { host_id: "h1".to_string(), path: default_remote_dir() },
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn test_archive_names() {
+        let mut backup = record("a1", "a", 0);
+        assert_eq!(backup.files(), vec!["rootfs.tar.zst"]);
+        backup.workspace_bytes = Some(10);
+        backup.compression = Compression::None;
+        assert_eq!(backup.files(), vec!["rootfs.tar", "workspace.tar"]);
+    }
+}
@@ -0,0 +1,352 @@
+//! On-demand packet captures: tcpdump on one interface with a filter built
+//! from a host, a port and a protocol, stopped at a time or size limit
+//! (or on request), written to a pcap that can be followed while it grows
+//! or downloaded afterwards. Who started and stopped each capture is logged.
+
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Captures kept on disk; the oldest finished ones are deleted.
+const MAX_KEPT: usize = 10;
+const MAX_RUNNING: usize = 2;
+const MAX_DURATION_SECS: u32 = 600;
+const MAX_SIZE_MB: u32 = 200;
+/// How often the pcap size is checked.
+const SIZE_CHECK: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureRequest {
+    pub interface: String,
+    #[serde(default)]
+    pub host: Option<IpAddr>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// `tcp`, `udp`, `icmp`, `icmp6` or `arp`.
+    #[serde(default)]
+    pub protocol: Option<String>,
+    #[serde(default = "default_duration")]
+    pub duration_secs: u32,
+    #[serde(default = "default_size")]
+    pub max_size_mb: u32,
+    /// Bytes kept per packet, 0 = whole packets.
+    #[serde(default)]
+    pub snaplen: u32,
+}
+
+fn default_duration() -> u32 {
+    60
+}
+
+fn default_size() -> u32 {
+    20
+}
+
+fn valid_interface(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 15
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '@'))
+}
+
+impl CaptureRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interface != "any" && !valid_interface(&self.interface) {
+            return Err(format!("Invalid interface: {}", self.interface));
+        }
+        if !(1..=MAX_DURATION_SECS).contains(&self.duration_secs) {
+            return Err(format!("The duration must be between 1 and {} seconds", MAX_DURATION_SECS));
+        }
+        if !(1..=MAX_SIZE_MB).contains(&self.max_size_mb) {
+            return Err(format!("The size limit must be between 1 and {} MB", MAX_SIZE_MB));
+        }
+        if let Some(protocol) = &self.protocol
+            && !matches!(protocol.as_str(), "tcp" | "udp" | "icmp" | "icmp6" | "arp")
+        {
+            return Err(format!("Unknown protocol: {}", protocol));
+        }
+        if self.port.is_some() && matches!(self.protocol.as_deref(), Some("icmp" | "icmp6" | "arp")) {
+            return Err("A port filter needs TCP or UDP".to_string());
+        }
+        Ok(())
+    }
+
+    /// BPF expression, empty when everything is captured.
+    pub fn filter(&self) -> String {
+        let mut terms = Vec::new();
+        if let Some(protocol) = &self.protocol {
+            terms.push(protocol.clone());
+        }
+        if let Some(host) = self.host {
+            terms.push(format!("host {}", host));
+        }
+        if let Some(port) = self.port {
+            terms.push(format!("port {}", port));
+        }
+        terms.join(" and ")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureStatus {
+    Running,
+    /// Time or size limit reached.
+    Completed,
+    Stopped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capture {
+    pub id: String,
+    pub interface: String,
+    pub filter: String,
+    pub started_by: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub duration_secs: u32,
+    pub max_size_mb: u32,
+    pub status: CaptureStatus,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct Entry {
+    capture: Capture,
+    /// `true` asks the capture to stop.
+    stop: watch::Sender<bool>,
+}
+
+/// Captures of this run (pcaps under `dir`).
+pub struct CaptureManager {
+    dir: PathBuf,
+    captures: Mutex<Vec<Entry>>,
+}
+
+impl CaptureManager {
+    pub fn new(dir: PathBuf) -> Self {
+        // Files of a previous run have no metadata anymore
+        if let Ok(entries) = std::fs::read_dir(&dir) {
+            for entry in entries.flatten() {
+                if entry.path().extension().is_some_and(|e| e == "pcap") {
+                    let _ = std::fs::remove_file(entry.path());
+                }
+            }
+        }
+        Self { dir, captures: Mutex::new(Vec::new()) }
+    }
+
+    pub fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.pcap", id))
+    }
+
+    /// Newest first.
+    pub fn list(&self) -> Vec<Capture> {
+        self.captures.lock().unwrap().iter().rev().map(|e| e.capture.clone()).collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Capture> {
+        self.captures.lock().unwrap().iter().find(|e| e.capture.id == id).map(|e| e.capture.clone())
+    }
+
+    fn update(&self, id: &str, f: impl FnOnce(&mut Capture)) {
+        if let Some(entry) = self.captures.lock().unwrap().iter_mut().find(|e| e.capture.id == id) {
+            f(&mut entry.capture);
+        }
+    }
+
+    /// Start tcpdump in the background.
+    pub fn start(self: &Arc<Self>, request: CaptureRequest, user: &str) -> Result<Capture, String> {
+        request.validate()?;
+        std::fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+        let id = uuid::Uuid::new_v4().to_string();
+        let path = self.path(&id);
+        let filter = request.filter();
+
+        let mut cmd = tokio::process::Command::new("tcpdump");
+        // -Z root: the pcap directory is not writable by the tcpdump user
+        cmd.args(["-i", &request.interface, "-n", "-U", "-Z", "root", "-s", &request.snaplen.to_string(), "-w"])
+            .arg(&path)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+        if !filter.is_empty() {
+            cmd.arg(&filter);
+        }
+
+        let capture = Capture {
+            id: id.clone(),
+            interface: request.interface.clone(),
+            filter: filter.clone(),
+            started_by: user.to_string(),
+            started_at: Utc::now(),
+            ended_at: None,
+            duration_secs: request.duration_secs,
+            max_size_mb: request.max_size_mb,
+            status: CaptureStatus::Running,
+            size: 0,
+            error: None,
+        };
+        let (stop, stop_rx) = watch::channel(false);
+        {
+            let mut captures = self.captures.lock().unwrap();
+            if captures.iter().filter(|e| e.capture.status == CaptureStatus::Running).count() >= MAX_RUNNING {
+                return Err(format!("At most {} captures can run at once", MAX_RUNNING));
+            }
+            let child = cmd.spawn().map_err(|e| format!("Failed to start tcpdump: {}", e))?;
+            captures.push(Entry { capture: capture.clone(), stop });
+            self.prune(&mut captures);
+            let manager = self.clone();
+            tokio::spawn(async move { manager.supervise(id, child, stop_rx).await });
+        }
+        info!(
+            user,
+            interface = %request.interface,
+            filter = %filter,
+            duration_secs = request.duration_secs,
+            max_size_mb = request.max_size_mb,
+            "Packet capture {} started",
+            capture.id
+        );
+        Ok(capture)
+    }
+
+    /// Ask a running capture to stop.
+    pub fn stop(&self, id: &str, user: &str) -> Result<(), String> {
+        let captures = self.captures.lock().unwrap();
+        let entry = captures.iter().find(|e| e.capture.id == id).ok_or("Capture not found")?;
+        if entry.capture.status != CaptureStatus::Running {
+            return Err("The capture is not running".to_string());
+        }
+        entry.stop.send_replace(true);
+        info!(user, "Packet capture {} stop requested", id);
+        Ok(())
+    }
+
+    /// Delete a finished capture and its pcap.
+    pub fn delete(&self, id: &str, user: &str) -> Result<(), String> {
+        let mut captures = self.captures.lock().unwrap();
+        let index = captures.iter().position(|e| e.capture.id == id).ok_or("Capture not found")?;
+        if captures[index].capture.status == CaptureStatus::Running {
+            return Err("Stop the capture first".to_string());
+        }
+        captures.remove(index);
+        let _ = std::fs::remove_file(self.path(id));
+        info!(user, "Packet capture {} deleted", id);
+        Ok(())
+    }
+
+    /// Drop the oldest finished captures past `MAX_KEPT`.
+    fn prune(&self, captures: &mut Vec<Entry>) {
+        while captures.len() > MAX_KEPT {
+            let Some(index) = captures.iter().position(|e| e.capture.status != CaptureStatus::Running) else {
+                break;
+            };
+            let _ = std::fs::remove_file(self.path(&captures[index].capture.id));
+            captures.remove(index);
+        }
+    }
+
+    /// Wait for the first of: tcpdump exiting, the time limit, the size
+    /// limit, a stop request.
+    async fn supervise(&self, id: String, mut child: tokio::process::Child, mut stop: watch::Receiver<bool>) {
+        let path = self.path(&id);
+        let (limit, max_bytes) = match self.get(&id) {
+            Some(c) => (Duration::from_secs(c.duration_secs as u64), c.max_size_mb as u64 * 1_000_000),
+            None => return,
+        };
+        let deadline = tokio::time::sleep(limit);
+        tokio::pin!(deadline);
+        let mut tick = tokio::time::interval(SIZE_CHECK);
+        let size = || std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+
+        let (status, error) = loop {
+            tokio::select! {
+                exited = child.wait() => {
+                    let error = match exited {
+                        Ok(status) if status.success() => None,
+                        Ok(status) => Some(stderr_tail(&mut child).await.unwrap_or_else(|| format!("tcpdump exited with {}", status))),
+                        Err(e) => Some(e.to_string()),
+                    };
+                    break match error {
+                        Some(e) => (CaptureStatus::Failed, Some(e)),
+                        None => (CaptureStatus::Completed, None),
+                    };
+                }
+                _ = &mut deadline => break (CaptureStatus::Completed, None),
+                _ = stop.changed() => break (CaptureStatus::Stopped, None),
+                _ = tick.tick() => {
+                    let bytes = size();
+                    self.update(&id, |c| c.size = bytes);
+                    if bytes >= max_bytes {
+                        break (CaptureStatus::Completed, None);
+                    }
+                }
+            }
+        };
+        // -U writes every packet as it comes: nothing is lost by a kill
+        let _ = child.kill().await;
+
+        let bytes = size();
+        if let Some(e) = &error {
+            warn!("Packet capture {} failed: {}", id, e);
+        } else {
+            info!(bytes, "Packet capture {} ended ({:?})", id, status);
+        }
+        self.update(&id, |c| {
+            c.status = status;
+            c.error = error;
+            c.size = bytes;
+            c.ended_at = Some(Utc::now());
+        });
+    }
+}
+
+async fn stderr_tail(child: &mut tokio::process::Child) -> Option<String> {
+    use tokio::io::AsyncReadExt;
+    let mut stderr = child.stderr.take()?;
+    let mut out = String::new();
+    stderr.read_to_string(&mut out).await.ok()?;
+    out.lines().rev().find(|l| !l.trim().is_empty()).map(|l| l.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: &str) -> CaptureRequest {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_filter() {
+        assert_eq!(request(r#"{"interface":"eth0"}"#).filter(), "");
+        assert_eq!(
+            request(r#"{"interface":"br-lan","host":"192.168.1.20","port":67,"protocol":"udp"}"#).filter(),
+            "udp and host 192.168.1.20 and port 67"
+        );
+        assert_eq!(request(r#"{"interface":"any","host":"fd00::1"}"#).filter(), "host fd00::1");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(request(r#"{"interface":"eth0"}"#).validate().is_ok());
+        assert!(request(r#"{"interface":"any","protocol":"icmp6"}"#).validate().is_ok());
+        assert!(request(r#"{"interface":"eth0; reboot"}"#).validate().is_err());
+        assert!(request(r#"{"interface":"eth0","protocol":"tcp or 1=1"}"#).validate().is_err());
+        assert!(request(r#"{"interface":"eth0","protocol":"icmp","port":53}"#).validate().is_err());
+        assert!(request(r#"{"interface":"eth0","durationSecs":3600}"#).validate().is_err());
+        assert!(request(r#"{"interface":"eth0","maxSizeMb":0}"#).validate().is_err());
+        assert!(serde_json::from_str::<CaptureRequest>(r#"{"interface":"eth0","host":"evil"}"#).is_err());
+    }
+}
@@ -0,0 +1,387 @@
+//! Backup of the whole configuration as one encrypted archive: every
+//! service config, hosts.json, the users, and optionally the ACME account
+//! and certificates. The archive is a JSON bundle (manifest plus files)
+//! sealed with AES-256-GCM under a key derived from a passphrase
+//! (PBKDF2-HMAC-SHA256). A restore checks the format version and the
+//! files before replacing any of them, then swaps them all in.
+
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::config_tx::{self, ConfigDocument};
+use crate::state::ApiState;
+
+/// Bundle layout version; restores refuse newer ones.
+pub const BACKUP_FORMAT: u32 = 1;
+const MAGIC: &[u8; 4] = b"HRBK";
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 200_000;
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// Contents of an archive.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupManifest {
+    pub format: u32,
+    /// homeroute version that made the backup.
+    pub version: String,
+    pub created_at: String,
+    pub base_domain: String,
+    pub files: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    manifest: BackupManifest,
+    /// Archive name → base64 content.
+    files: BTreeMap<String, String>,
+}
+
+/// Outcome of a restore.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Restored {
+    pub manifest: BackupManifest,
+    /// Files not reloaded live: homeroute must restart to use them.
+    pub restart_required: Vec<String>,
+}
+
+/// Archive name → path on this server of every file a backup may hold,
+/// ACME excluded.
+fn config_files(state: &ApiState) -> Vec<(String, PathBuf)> {
+    let env = &state.env;
+    let configs = [
+        &env.dns_dhcp_config_path,
+        &env.proxy_config_path,
+        &env.reverseproxy_config_path,
+        &env.streams_config_path,
+        &env.mqtt_config_path,
+        &env.reflector_config_path,
+        &env.syslog_config_path,
+        &env.radius_config_path,
+        &env.ntp_config_path,
+        &env.flow_config_path,
+        &env.firewall_config_path,
+        &env.mail_config_path,
+        &env.s3_config_path,
+        &env.tailscale_config_path,
+        &env.qos_config_path,
+        &env.relay_usage_config_path,
+        &env.pubsub_config_path,
+        &env.ai_config_path,
+        &env.ddns_config_path,
+        &env.scanner_config_path,
+        &env.energy_config_path,
+    ];
+    let mut files: Vec<(String, PathBuf)> = configs
+        .into_iter()
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?;
+            Some((format!("config/{}", name), path.clone()))
+        })
+        .collect();
+    files.push(("hosts.json".to_string(), PathBuf::from(crate::routes::hosts::HOSTS_FILE)));
+    files.push(("auth/users.yml".to_string(), env.auth_data_dir.join("users.yml")));
+    files
+}
+
+/// Path of an archive name on this server, `None` for unknown names.
+fn restore_path(state: &ApiState, name: &str) -> Option<PathBuf> {
+    if let Some(relative) = name.strip_prefix("acme/") {
+        let safe = !relative.is_empty()
+            && Path::new(relative)
+                .components()
+                .all(|c| matches!(c, std::path::Component::Normal(_)));
+        return safe.then(|| state.env.acme_storage_path.join(relative));
+    }
+    config_files(state)
+        .into_iter()
+        .find(|(known, _)| known == name)
+        .map(|(_, path)| path)
+}
+
+/// Files under `dir`, as archive names under `prefix`.
+fn collect_dir(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{}/{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            collect_dir(&entry.path(), &name, files)?;
+        } else {
+            files.push((name, entry.path()));
+        }
+    }
+    Ok(())
+}
+
+/// Encrypted archive of the configuration.
+pub fn create(state: &ApiState, passphrase: &str, include_acme: bool) -> Result<Vec<u8>, String> {
+    let mut sources = config_files(state);
+    if include_acme && state.env.acme_storage_path.is_dir() {
+        collect_dir(&state.env.acme_storage_path, "acme", &mut sources)
+            .map_err(|e| format!("Read ACME storage: {}", e))?;
+    }
+
+    let mut files = BTreeMap::new();
+    for (name, path) in sources {
+        match std::fs::read(&path) {
+            Ok(content) => {
+                files.insert(name, base64::engine::general_purpose::STANDARD.encode(content));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Read {}: {}", path.display(), e)),
+        }
+    }
+    let bundle = Bundle {
+        manifest: BackupManifest {
+            format: BACKUP_FORMAT,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            base_domain: state.env.base_domain.clone(),
+            files: files.keys().cloned().collect(),
+        },
+        files,
+    };
+    let plain = serde_json::to_vec(&bundle).map_err(|e| format!("Serialize error: {}", e))?;
+    seal(passphrase, plain)
+}
+
+/// Decrypt and check an archive, then replace the files it holds and
+/// reload DNS/DHCP and the reverse proxy.
+pub async fn restore(state: &ApiState, archive: &[u8], passphrase: &str) -> Result<Restored, String> {
+    let plain = open(passphrase, archive)?;
+    let bundle: Bundle = serde_json::from_slice(&plain).map_err(|e| format!("Invalid backup: {}", e))?;
+    if bundle.manifest.format > BACKUP_FORMAT {
+        return Err(format!(
+            "Backup format {} (homeroute {}) is newer than this homeroute supports ({})",
+            bundle.manifest.format, bundle.manifest.version, BACKUP_FORMAT
+        ));
+    }
+
+    // Everything checked before the first write
+    let mut files = Vec::new();
+    for (name, encoded) in &bundle.files {
+        let path = restore_path(state, name).ok_or_else(|| format!("Unexpected file in backup: {}", name))?;
+        let content = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| format!("{}: {}", name, e))?;
+        if name.ends_with(".json") {
+            let value: serde_json::Value =
+                serde_json::from_slice(&content).map_err(|e| format!("{}: {}", name, e))?;
+            for doc in ConfigDocument::ALL {
+                if doc.path(state) == path {
+                    let validation = config_tx::validate(doc, &value).await;
+                    if !validation.is_valid() {
+                        return Err(format!("{}: {}", name, validation.errors.join("; ")));
+                    }
+                }
+            }
+        }
+        files.push((name.clone(), path, content));
+    }
+
+    let previous_rp = config_tx::current(state, ConfigDocument::ReverseProxy).await.ok();
+    let previous_dns = config_tx::current(state, ConfigDocument::DnsDhcp).await.ok();
+    let to_swap = files.clone();
+    tokio::task::spawn_blocking(move || swap_in(&to_swap))
+        .await
+        .map_err(|e| format!("Restore failed: {}", e))?
+        .map_err(|e| format!("Restore failed, nothing changed: {}", e))?;
+
+    // Live reload of what supports it, recorded in the config history
+    let mut restart_required = Vec::new();
+    for (doc, previous) in [
+        (ConfigDocument::DnsDhcp, previous_dns),
+        (ConfigDocument::ReverseProxy, previous_rp),
+    ] {
+        if !files.iter().any(|(_, path, _)| *path == doc.path(state)) {
+            continue;
+        }
+        let reloaded = match doc {
+            ConfigDocument::DnsDhcp => crate::routes::dns_dhcp::apply(state).await,
+            ConfigDocument::ReverseProxy => crate::routes::reverseproxy::sync_and_reload(state).await,
+        };
+        if let Err(e) = reloaded {
+            tracing::warn!("Reloading restored {} config failed: {}", doc.name(), e);
+            restart_required.push(doc.name().to_string());
+        }
+        if let Ok(config) = config_tx::current(state, doc).await {
+            let changes = previous.as_ref().map_or(0, |p| config_tx::diff(p, &config).len());
+            if let Err(e) = config_tx::record(state, doc, previous.as_ref(), &config, changes, "restore", None).await {
+                tracing::warn!("Failed to record restored {} config: {}", doc.name(), e);
+            }
+        }
+    }
+    let live: Vec<PathBuf> = ConfigDocument::ALL
+        .into_iter()
+        .map(|doc| doc.path(state))
+        .chain([state.env.proxy_config_path.clone()])
+        .collect();
+    restart_required.extend(
+        files
+            .iter()
+            .filter(|(name, path, _)| !live.contains(path) && !name.starts_with("acme/"))
+            .map(|(name, _, _)| name.clone()),
+    );
+    if files.iter().any(|(name, _, _)| name.starts_with("acme/")) {
+        restart_required.push("acme".to_string());
+    }
+
+    Ok(Restored {
+        manifest: bundle.manifest,
+        restart_required,
+    })
+}
+
+/// Write every file next to its target, then rename them all in. A failed
+/// rename puts back the files already replaced.
+fn swap_in(files: &[(String, PathBuf, Vec<u8>)]) -> std::io::Result<()> {
+    let staged = |path: &Path| with_suffix(path, ".restore-tmp");
+    let saved = |path: &Path| with_suffix(path, ".pre-restore");
+
+    for (_, path, content) in files {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if let Err(e) = std::fs::write(staged(path), content) {
+            for (_, path, _) in files {
+                let _ = std::fs::remove_file(staged(path));
+            }
+            return Err(e);
+        }
+    }
+
+    let mut done: Vec<(&Path, bool)> = Vec::new();
+    for (_, path, _) in files {
+        let existed = path.exists();
+        let result = (|| {
+            if existed {
+                std::fs::copy(path, saved(path))?;
+            }
+            std::fs::rename(staged(path), path)
+        })();
+        if let Err(e) = result {
+            for (path, existed) in done.iter().chain([(path.as_path(), existed)].iter()) {
+                if *existed {
+                    let _ = std::fs::rename(saved(path), path);
+                } else {
+                    let _ = std::fs::remove_file(path);
+                }
+            }
+            for (_, path, _) in files {
+                let _ = std::fs::remove_file(staged(path));
+            }
+            return Err(e);
+        }
+        done.push((path, existed));
+    }
+    for (path, existed) in done {
+        if existed {
+            let _ = std::fs::remove_file(saved(path));
+        }
+    }
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// ── Encryption ───────────────────────────────────────────────────
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> LessSafeKey {
+    let mut key = [0u8; 32];
+    ring::pbkdf2::derive(
+        ring::pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).expect("AES-256 key length"))
+}
+
+/// `MAGIC | salt | nonce | ciphertext+tag`, the header being authenticated.
+fn seal(passphrase: &str, mut plain: Vec<u8>) -> Result<Vec<u8>, String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(format!("The passphrase must be at least {} characters", MIN_PASSPHRASE_LEN));
+    }
+    let rng = SystemRandom::new();
+    let mut header = MAGIC.to_vec();
+    let mut random = [0u8; SALT_LEN + NONCE_LEN];
+    rng.fill(&mut random).map_err(|_| "No randomness available".to_string())?;
+    header.extend_from_slice(&random);
+
+    let key = derive_key(passphrase, &random[..SALT_LEN]);
+    let nonce = Nonce::try_assume_unique_for_key(&random[SALT_LEN..]).map_err(|_| "Invalid nonce".to_string())?;
+    key.seal_in_place_append_tag(nonce, Aad::from(&header), &mut plain)
+        .map_err(|_| "Encryption failed".to_string())?;
+    header.extend_from_slice(&plain);
+    Ok(header)
+}
+
+fn open(passphrase: &str, archive: &[u8]) -> Result<Vec<u8>, String> {
+    let header_len = MAGIC.len() + SALT_LEN + NONCE_LEN;
+    if archive.len() < header_len || &archive[..MAGIC.len()] != MAGIC {
+        return Err("Not a homeroute backup".to_string());
+    }
+    let (header, sealed) = archive.split_at(header_len);
+    let salt = &header[MAGIC.len()..MAGIC.len() + SALT_LEN];
+    let key = derive_key(passphrase, salt);
+    let nonce = Nonce::try_assume_unique_for_key(&header[MAGIC.len() + SALT_LEN..])
+        .map_err(|_| "Invalid nonce".to_string())?;
+    let mut sealed = sealed.to_vec();
+    let plain = key
+        .open_in_place(nonce, Aad::from(header), &mut sealed)
+        .map_err(|_| "Wrong passphrase or corrupted backup".to_string())?;
+    Ok(plain.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_open_roundtrip() {
+        let archive = seal("correct horse", b"{\"files\":{}}".to_vec()).unwrap();
+        assert_eq!(&archive[..4], MAGIC);
+        assert_eq!(open("correct horse", &archive).unwrap(), b"{\"files\":{}}");
+        assert_eq!(open("wrong horse!", &archive).unwrap_err(), "Wrong passphrase or corrupted backup");
+
+        let mut tampered = archive.clone();
+        tampered[6] ^= 1;
+        assert!(open("correct horse", &tampered).is_err());
+        assert!(open("correct horse", b"HRB").is_err());
+    }
+
+    #[test]
+    fn short_passphrase_refused() {
+        assert!(seal("short", Vec::new()).is_err());
+    }
+
+    #[test]
+    fn swap_in_replaces_all_files() {
+        let dir = std::env::temp_dir().join(format!("hr-backup-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.json"), "old").unwrap();
+        let files = vec![
+            ("a".to_string(), dir.join("a.json"), b"new a".to_vec()),
+            ("b".to_string(), dir.join("sub/b.yml"), b"new b".to_vec()),
+        ];
+        swap_in(&files).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("a.json")).unwrap(), "new a");
+        assert_eq!(std::fs::read_to_string(dir.join("sub/b.yml")).unwrap(), "new b");
+        assert!(!dir.join("a.json.pre-restore").exists());
+        assert!(!dir.join("a.json.restore-tmp").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
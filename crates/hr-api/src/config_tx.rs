@@ -0,0 +1,569 @@
+//! Config transactions for the documents edited from the API
+//! (dns-dhcp-config.json, reverseproxy-config.json). A change is staged,
+//! validated (typed parse plus cross-checks), previewed as a diff against
+//! the live file, then applied: the file is replaced atomically and the
+//! component reloaded, the previous file being restored if the reload
+//! fails. Every applied version is kept under
+//! `data/config-history/{document}/` and can be reverted to.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::state::ApiState;
+
+/// Versions kept per document.
+const HISTORY_LEN: usize = 50;
+/// How long a route target may take to accept a TCP connection.
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// One apply at a time, across documents.
+static APPLY_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigDocument {
+    DnsDhcp,
+    ReverseProxy,
+}
+
+impl ConfigDocument {
+    pub const ALL: [ConfigDocument; 2] = [ConfigDocument::DnsDhcp, ConfigDocument::ReverseProxy];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|doc| doc.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ConfigDocument::DnsDhcp => "dns-dhcp",
+            ConfigDocument::ReverseProxy => "reverseproxy",
+        }
+    }
+
+    pub fn path(self, state: &ApiState) -> PathBuf {
+        match self {
+            ConfigDocument::DnsDhcp => state.dns_dhcp_config_path.clone(),
+            ConfigDocument::ReverseProxy => state.reverseproxy_config_path.clone(),
+        }
+    }
+
+    fn history_dir(self, state: &ApiState) -> PathBuf {
+        state.env.data_dir.join("config-history").join(self.name())
+    }
+}
+
+/// Result of the checks of a config. Warnings do not block an apply.
+#[derive(Debug, Default, Serialize)]
+pub struct Validation {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl Validation {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// A value added, removed or changed at `path` (`dhcp.range_start`,
+/// `hosts[id=abc].targetPort`…).
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Change {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+/// An applied version of a document.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Version {
+    pub version: u64,
+    /// Unix milliseconds.
+    pub at: u64,
+    /// `initial`, `apply`, `revert`, `edit`, `restore` or `declarative`.
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Changes from the previous version.
+    pub changes: usize,
+}
+
+/// Outcome of a successful apply.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Applied {
+    pub version: u64,
+    pub changes: Vec<Change>,
+    pub warnings: Vec<String>,
+}
+
+// ── Validation ───────────────────────────────────────────────────
+
+/// Typed parse of the document and its cross-checks.
+pub async fn validate(doc: ConfigDocument, config: &Value) -> Validation {
+    let mut validation = Validation::default();
+    if !config.is_object() {
+        validation.errors.push("The config must be a JSON object".to_string());
+        return validation;
+    }
+    match doc {
+        ConfigDocument::DnsDhcp => validate_dns_dhcp(config, &mut validation),
+        ConfigDocument::ReverseProxy => validate_reverseproxy(config, &mut validation).await,
+    }
+    validation
+}
+
+fn validate_dns_dhcp(config: &Value, validation: &mut Validation) {
+    let sections = match crate::routes::dns_dhcp::parse_config(config) {
+        Ok(sections) => sections,
+        Err(e) => {
+            validation.errors.push(e);
+            return;
+        }
+    };
+    if let Err(e) = sections.dhcp.validate() {
+        validation.errors.push(format!("dhcp: {}", e));
+    }
+    if let Err(e) = sections.ipv6.validate() {
+        validation.errors.push(format!("ipv6: {}", e));
+    }
+    if let Err(e) = hr_adblock::schedule::validate_profiles(&sections.adblock.profiles) {
+        validation.errors.push(format!("adblock: {}", e));
+    }
+    if let Err(e) = sections.dns.ecs.validate() {
+        validation.errors.push(format!("dns: {}", e));
+    }
+    if let Err(e) = sections.dns.route_records.validate() {
+        validation.errors.push(format!("dns: {}", e));
+    }
+    for record in &sections.dns.static_records {
+        if let Err(e) = hr_dns::patterns::validate(record) {
+            validation.errors.push(format!("dns: {}", e));
+        }
+    }
+    if sections.dns.upstream_servers.is_empty() {
+        validation.warnings.push("dns: no upstream server, only local names will resolve".to_string());
+    }
+    for upstream in &sections.dns.upstream_servers {
+        let host = upstream.trim_start_matches('[').split(']').next().unwrap_or(upstream);
+        let host = if host.matches(':').count() == 1 { host.split(':').next().unwrap_or(host) } else { host };
+        if host.parse::<std::net::IpAddr>().is_err() {
+            validation.errors.push(format!("dns: invalid upstream server '{}'", upstream));
+        }
+    }
+}
+
+async fn validate_reverseproxy(config: &Value, validation: &mut Validation) {
+    let Some(hosts) = config.get("hosts").and_then(|h| h.as_array()) else {
+        validation.errors.push("hosts must be an array".to_string());
+        return;
+    };
+    let base_domain = config.get("baseDomain").and_then(|d| d.as_str()).unwrap_or("");
+    let mut ids = HashSet::new();
+    let mut domains = HashSet::new();
+    let mut targets = tokio::task::JoinSet::new();
+    for host in hosts {
+        let id = host.get("id").and_then(|i| i.as_str()).unwrap_or("");
+        if id.is_empty() {
+            validation.errors.push("A host has no id".to_string());
+            continue;
+        }
+        if !ids.insert(id) {
+            validation.errors.push(format!("Duplicate host id {}", id));
+        }
+        match crate::routes::reverseproxy::host_domain(host, base_domain) {
+            Some(domain) => {
+                if !domains.insert(domain.to_lowercase()) {
+                    validation.errors.push(format!("{} is served by several hosts", domain));
+                }
+            }
+            None => validation.errors.push(format!("Host {} has no subdomain or custom domain", id)),
+        }
+        let target_host = host.get("targetHost").and_then(|t| t.as_str()).unwrap_or("localhost");
+        let target_port = match host.get("targetPort") {
+            None => 80,
+            Some(port) => match port.as_u64().filter(|p| (1..=65535).contains(p)) {
+                Some(port) => port as u16,
+                None => {
+                    validation.errors.push(format!("Host {}: invalid target port {}", id, port));
+                    continue;
+                }
+            },
+        };
+        if target_host.is_empty() {
+            validation.errors.push(format!("Host {}: empty target host", id));
+            continue;
+        }
+        if host.get("enabled").and_then(|e| e.as_bool()) == Some(true) {
+            let (id, target_host) = (id.to_string(), target_host.to_string());
+            targets.spawn(async move {
+                let reachable = tokio::time::timeout(
+                    REACHABILITY_TIMEOUT,
+                    tokio::net::TcpStream::connect((target_host.as_str(), target_port)),
+                )
+                .await
+                .is_ok_and(|r| r.is_ok());
+                (!reachable).then(|| format!("Host {}: {}:{} is not reachable", id, target_host, target_port))
+            });
+        }
+    }
+    while let Some(result) = targets.join_next().await {
+        if let Ok(Some(warning)) = result {
+            validation.warnings.push(warning);
+        }
+    }
+    validation.warnings.sort();
+}
+
+// ── Diff ─────────────────────────────────────────────────────────
+
+/// Changes from `before` to `after`. Arrays of objects with an `id` are
+/// matched by id, other arrays by index.
+pub fn diff(before: &Value, after: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_at(String::new(), before, after, &mut changes);
+    changes
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) }
+}
+
+fn diff_at(path: String, before: &Value, after: &Value, changes: &mut Vec<Change>) {
+    match (before, after) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (key, value) in a {
+                match b.get(key) {
+                    Some(other) => diff_at(join(&path, key), value, other, changes),
+                    None => changes.push(Change { path: join(&path, key), before: Some(value.clone()), after: None }),
+                }
+            }
+            for (key, value) in b {
+                if !a.contains_key(key) {
+                    changes.push(Change { path: join(&path, key), before: None, after: Some(value.clone()) });
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) if by_id(a) && by_id(b) => {
+            let id = |v: &Value| v.get("id").and_then(|i| i.as_str()).unwrap_or("").to_string();
+            for item in a {
+                let item_path = format!("{}[id={}]", path, id(item));
+                match b.iter().find(|other| id(other) == id(item)) {
+                    Some(other) => diff_at(item_path, item, other, changes),
+                    None => changes.push(Change { path: item_path, before: Some(item.clone()), after: None }),
+                }
+            }
+            for item in b {
+                if !a.iter().any(|other| id(other) == id(item)) {
+                    changes.push(Change { path: format!("{}[id={}]", path, id(item)), before: None, after: Some(item.clone()) });
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for i in 0..a.len().max(b.len()) {
+                let item_path = format!("{}[{}]", path, i);
+                match (a.get(i), b.get(i)) {
+                    (Some(x), Some(y)) => diff_at(item_path, x, y, changes),
+                    (x, y) => changes.push(Change { path: item_path, before: x.cloned(), after: y.cloned() }),
+                }
+            }
+        }
+        _ if before != after => changes.push(Change {
+            path,
+            before: Some(before.clone()),
+            after: Some(after.clone()),
+        }),
+        _ => {}
+    }
+}
+
+/// Every item is an object with a distinct string `id`.
+fn by_id(items: &[Value]) -> bool {
+    let mut ids = HashSet::new();
+    items
+        .iter()
+        .all(|item| item.get("id").and_then(|i| i.as_str()).is_some_and(|id| ids.insert(id)))
+}
+
+// ── Files ────────────────────────────────────────────────────────
+
+async fn read_json(path: &Path) -> Result<Option<Value>, String> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) => serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| format!("Parse error in {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Read error: {}", e)),
+    }
+}
+
+async fn write_json(path: &Path, value: &Value) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Create {}: {}", parent.display(), e))?;
+    }
+    let content = serde_json::to_string_pretty(value).map_err(|e| format!("Serialize error: {}", e))?;
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, &content)
+        .await
+        .map_err(|e| format!("Write error: {}", e))?;
+    tokio::fs::rename(&tmp, path)
+        .await
+        .map_err(|e| format!("Rename error: {}", e))
+}
+
+/// Live content of the document (`{}` when the file does not exist yet).
+pub async fn current(state: &ApiState, doc: ConfigDocument) -> Result<Value, String> {
+    Ok(read_json(&doc.path(state)).await?.unwrap_or_else(|| serde_json::json!({})))
+}
+
+// ── Staging ──────────────────────────────────────────────────────
+
+fn staged_path(state: &ApiState, doc: ConfigDocument) -> PathBuf {
+    doc.history_dir(state).join("staged.json")
+}
+
+/// Stage a full document after validating it. Invalid documents are not
+/// staged.
+pub async fn stage(state: &ApiState, doc: ConfigDocument, config: Value) -> Result<(Validation, Vec<Change>), String> {
+    let validation = validate(doc, &config).await;
+    let changes = diff(&current(state, doc).await?, &config);
+    if validation.is_valid() {
+        write_json(&staged_path(state, doc), &config).await?;
+    }
+    Ok((validation, changes))
+}
+
+pub async fn staged(state: &ApiState, doc: ConfigDocument) -> Result<Option<Value>, String> {
+    read_json(&staged_path(state, doc)).await
+}
+
+pub async fn discard(state: &ApiState, doc: ConfigDocument) -> Result<(), String> {
+    match tokio::fs::remove_file(staged_path(state, doc)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!("Remove error: {}", e)),
+        _ => Ok(()),
+    }
+}
+
+// ── Apply ────────────────────────────────────────────────────────
+
+async fn reload(state: &ApiState, doc: ConfigDocument) -> Result<(), String> {
+    match doc {
+        ConfigDocument::DnsDhcp => crate::routes::dns_dhcp::apply(state).await,
+        ConfigDocument::ReverseProxy => crate::routes::reverseproxy::sync_and_reload(state).await,
+    }
+}
+
+/// Validate `config`, replace the document with it and reload its
+/// component. If the reload fails the previous file is put back and
+/// reloaded. Recorded as a new version on success.
+pub async fn commit(
+    state: &ApiState,
+    doc: ConfigDocument,
+    config: Value,
+    source: &str,
+    user: Option<String>,
+) -> Result<Applied, String> {
+    let validation = validate(doc, &config).await;
+    if !validation.is_valid() {
+        return Err(validation.errors.join("; "));
+    }
+
+    let _guard = APPLY_LOCK.lock().await;
+    let path = doc.path(state);
+    let previous = read_json(&path).await?;
+    let changes = diff(previous.as_ref().unwrap_or(&serde_json::json!({})), &config);
+
+    write_json(&path, &config).await?;
+    if let Err(e) = reload(state, doc).await {
+        tracing::error!("Applying {} failed, rolling back: {}", doc.name(), e);
+        let restored = match &previous {
+            Some(previous) => write_json(&path, previous).await,
+            None => tokio::fs::remove_file(&path).await.map_err(|e| e.to_string()),
+        };
+        match restored {
+            Ok(()) => {
+                if let Err(e) = reload(state, doc).await {
+                    tracing::error!("Reloading the previous {} config failed: {}", doc.name(), e);
+                }
+            }
+            Err(e) => tracing::error!("Restoring the previous {} config failed: {}", doc.name(), e),
+        }
+        return Err(format!("{} (previous config restored)", e));
+    }
+
+    let version = record(state, doc, previous.as_ref(), &config, changes.len(), source, user).await?;
+    let _ = discard(state, doc).await;
+    Ok(Applied {
+        version,
+        changes,
+        warnings: validation.warnings,
+    })
+}
+
+// ── History ──────────────────────────────────────────────────────
+
+fn version_path(state: &ApiState, doc: ConfigDocument, version: u64) -> PathBuf {
+    doc.history_dir(state).join(format!("v{}.json", version))
+}
+
+pub async fn history(state: &ApiState, doc: ConfigDocument) -> Result<Vec<Version>, String> {
+    let Some(index) = read_json(&doc.history_dir(state).join("history.json")).await? else {
+        return Ok(Vec::new());
+    };
+    serde_json::from_value(index).map_err(|e| format!("Invalid history: {}", e))
+}
+
+pub async fn version(state: &ApiState, doc: ConfigDocument, version: u64) -> Result<Value, String> {
+    read_json(&version_path(state, doc, version))
+        .await?
+        .ok_or_else(|| format!("Unknown version {} of {}", version, doc.name()))
+}
+
+/// Record `config` as the latest version of the document. The first
+/// record also keeps `previous`, the content before any tracked change.
+pub(crate) async fn record(
+    state: &ApiState,
+    doc: ConfigDocument,
+    previous: Option<&Value>,
+    config: &Value,
+    changes: usize,
+    source: &str,
+    user: Option<String>,
+) -> Result<u64, String> {
+    let mut versions = history(state, doc).await?;
+    let now = hr_common::service_registry::now_millis();
+    if versions.is_empty()
+        && let Some(previous) = previous
+    {
+        write_json(&version_path(state, doc, 1), previous).await?;
+        versions.push(Version {
+            version: 1,
+            at: now,
+            source: "initial".to_string(),
+            user: None,
+            changes: 0,
+        });
+    }
+    let next = versions.last().map_or(1, |v| v.version + 1);
+    write_json(&version_path(state, doc, next), config).await?;
+    versions.push(Version {
+        version: next,
+        at: now,
+        source: source.to_string(),
+        user,
+        changes,
+    });
+    while versions.len() > HISTORY_LEN {
+        let old = versions.remove(0);
+        let _ = tokio::fs::remove_file(version_path(state, doc, old.version)).await;
+    }
+    let index = serde_json::to_value(&versions).map_err(|e| format!("Serialize error: {}", e))?;
+    write_json(&doc.history_dir(state).join("history.json"), &index).await?;
+    Ok(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn diff_objects() {
+        let before = json!({"dns": {"port": 53, "cache_size": 1000}, "old": true});
+        let after = json!({"dns": {"port": 5353, "cache_size": 1000}, "new": 1});
+        assert_eq!(
+            diff(&before, &after),
+            vec![
+                Change { path: "dns.port".into(), before: Some(json!(53)), after: Some(json!(5353)) },
+                Change { path: "old".into(), before: Some(json!(true)), after: None },
+                Change { path: "new".into(), before: None, after: Some(json!(1)) },
+            ]
+        );
+        assert!(diff(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn diff_arrays_by_id() {
+        let before = json!({"hosts": [{"id": "a", "targetPort": 80}, {"id": "b"}]});
+        let after = json!({"hosts": [{"id": "c"}, {"id": "a", "targetPort": 8080}]});
+        let paths: Vec<_> = diff(&before, &after).into_iter().map(|c| c.path).collect();
+        assert_eq!(paths, ["hosts[id=a].targetPort", "hosts[id=b]", "hosts[id=c]"]);
+
+        let paths: Vec<_> = diff(&json!({"hosts": []}), &after).into_iter().map(|c| c.path).collect();
+        assert_eq!(paths, ["hosts[id=c]", "hosts[id=a]"]);
+    }
+
+    #[test]
+    fn diff_arrays_by_index() {
+        let before = json!({"upstream_servers": ["1.1.1.1", "8.8.8.8"]});
+        let after = json!({"upstream_servers": ["1.1.1.1"]});
+        assert_eq!(
+            diff(&before, &after),
+            vec![Change { path: "upstream_servers[1]".into(), before: Some(json!("8.8.8.8")), after: None }]
+        );
+    }
+
+    #[tokio::test]
+    async fn validate_reverseproxy_hosts() {
+        let config = json!({
+            "baseDomain": "example.com",
+            "hosts": [
+                {"id": "a", "subdomain": "app", "targetPort": 80},
+                {"id": "b", "subdomain": "app", "targetPort": 70000},
+                {"id": "a", "customDomain": "other.org"}
+            ]
+        });
+        let validation = validate(ConfigDocument::ReverseProxy, &config).await;
+        assert_eq!(
+            validation.errors,
+            [
+                "app.example.com is served by several hosts",
+                "Host b: invalid target port 70000",
+                "Duplicate host id a",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn validate_dns_dhcp_sections() {
+        let config = json!({
+            "dns": {"upstream_servers": ["1.1.1.1", "[2606:4700::1111]:53", "9.9.9.9:53", "dns.example"]},
+            "dhcp": {"range_start": "10.0.0.10", "range_end": "10.0.1.10"}
+        });
+        let validation = validate(ConfigDocument::DnsDhcp, &config).await;
+        assert_eq!(validation.errors.len(), 2);
+        assert!(validation.errors[0].starts_with("dhcp:"));
+        assert_eq!(validation.errors[1], "dns: invalid upstream server 'dns.example'");
+
+        let validation = validate(ConfigDocument::DnsDhcp, &json!({"dhcp": {"enabled": "yes"}})).await;
+        assert!(!validation.is_valid());
+
+        let config = json!({"ipv6": {"enabled": true, "dhcpv6_dns_servers": ["10.0.0.1"]}});
+        let validation = validate(ConfigDocument::DnsDhcp, &config).await;
+        assert_eq!(validation.errors, ["ipv6: Invalid DHCPv6 DNS server: '10.0.0.1'"]);
+
+        let config = json!({"ipv6": {"enabled": true, "pd_enabled": true, "pd_wan_interface": "eth0",
+            "pd_prefix_hint_len": 60, "pd_subnet_id": 16}});
+        let validation = validate(ConfigDocument::DnsDhcp, &config).await;
+        assert_eq!(validation.errors, ["ipv6: PD subnet id 16 does not fit in a /60 prefix"]);
+    }
+
+    #[tokio::test]
+    async fn validate_static_record_patterns() {
+        let config = json!({"dns": {"static_records": [
+            {"name": "/pr-[0-9]+\\.lab\\.home\\.arpa/", "type": "A", "value": "10.0.0.50"},
+            {"name": "/.+\\.com/", "type": "A", "value": "10.0.0.51"},
+        ]}});
+        let validation = validate(ConfigDocument::DnsDhcp, &config).await;
+        assert_eq!(validation.errors, ["dns: Pattern /.+\\.com/ is too broad (matches example.com)"]);
+    }
+}
@@ -13,12 +13,28 @@ use tracing::{error, info, warn};
 
 use hr_common::config::EnvConfig;
 use hr_common::events::{AgentStatusEvent, EventBus, MigrationPhase};
-use hr_container::NspawnClient;
-use hr_registry::protocol::{HostRegistryMessage, ServiceAction, ServiceType};
+use hr_container::{ContainerTemplate, ContainerUsage, HostPreflight, NspawnClient, ResourceLimits};
+use hr_registry::binaries::{local_arch, BinaryStore};
+use hr_registry::protocol::{capability, HostRegistryMessage, ServiceAction, ServiceType};
+use hr_registry::transfer::{Compression, ResumePoint, TransferStream};
 use hr_registry::types::{AgentStatus, CreateApplicationRequest, Environment, UpdateApplicationRequest};
 use hr_registry::AgentRegistry;
 
+use crate::routes::applications::{RemotePush, StreamError};
+use crate::placement::{self, PlacementRequest};
 use crate::state::MigrationState;
+use crate::templates::TemplateCatalog;
+
+/// How long a migration waits for a disconnected target host to come back
+/// and resume the transfer.
+const RESUME_TIMEOUT: Duration = Duration::from_secs(300);
+/// How long the target host may take to extract and start an imported container.
+const IMPORT_TIMEOUT: Duration = Duration::from_secs(600);
+/// Throughput assumed to estimate the duration of a migration (gigabit
+/// LAN, export and import included).
+const ESTIMATED_THROUGHPUT: u64 = 60 * 1024 * 1024;
+/// Space left on the target after a migration, for the container to run.
+const DISK_HEADROOM: u64 = 1024 * 1024 * 1024;
 
 // ── Types ────────────────────────────────────────────────────────
 
@@ -72,6 +88,16 @@ pub struct ContainerV2Record {
     pub environment: hr_registry::types::Environment,
     pub status: ContainerV2Status,
     pub created_at: DateTime<Utc>,
+    /// CPU/memory/disk limits, reapplied when the container moves.
+    #[serde(default, skip_serializing_if = "ResourceLimits::is_empty")]
+    pub limits: ResourceLimits,
+    /// Template the rootfs was built from (absent: restored or pre-templates).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// Container of the previous blue/green deployment, kept running for
+    /// rollback (see `crate::deployments`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub standby_container: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -85,14 +111,31 @@ pub struct CreateContainerRequest {
     pub linked_app_id: Option<String>,
     #[serde(default = "default_true")]
     pub code_server_enabled: bool,
+    /// Host of the container, chosen by the placement engine when unset
+    /// or `"auto"`.
     #[serde(default)]
     pub host_id: Option<String>,
+    /// Template id, the default template when unset.
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Constraints of an automatic placement.
+    #[serde(default)]
+    pub placement: PlacementRequest,
 }
 
 fn default_true() -> bool {
     true
 }
 
+/// An application recreated from a backup (see `crate::backups`).
+pub struct RestoreContainerRequest {
+    pub name: String,
+    pub slug: String,
+    pub environment: Environment,
+    pub frontend: hr_registry::types::FrontendEndpoint,
+    pub code_server_enabled: bool,
+}
+
 #[derive(Deserialize)]
 pub struct RenameContainerRequest {
     pub new_slug: String,
@@ -132,6 +175,54 @@ pub enum RenamePhase {
 #[derive(Deserialize)]
 pub struct MigrateContainerRequest {
     pub target_host_id: String,
+    /// zstd level of the transfer (0: uncompressed), default when absent.
+    #[serde(default)]
+    pub compression_level: Option<i32>,
+}
+
+/// Outcome of one migration pre-flight check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    /// The migration can run, slower or with a risk.
+    Warning,
+    /// The migration would fail.
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PreflightCheck {
+    pub check: &'static str,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+/// What a migration would do, checked before anything is stopped.
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationPreflight {
+    pub app_id: String,
+    pub source_host_id: String,
+    pub target_host_id: String,
+    /// No check failed.
+    pub ok: bool,
+    pub checks: Vec<PreflightCheck>,
+    pub compression: Compression,
+    /// Size of the rootfs and workspace to send.
+    pub transfer_bytes: Option<u64>,
+    pub estimated_seconds: Option<u64>,
+}
+
+impl MigrationPreflight {
+    /// Messages of the failed checks.
+    pub fn failures(&self) -> String {
+        self.checks
+            .iter()
+            .filter(|c| c.status == CheckStatus::Failed)
+            .map(|c| c.message.as_str())
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
 }
 
 #[derive(Deserialize)]
@@ -151,6 +242,7 @@ pub struct ContainerManager {
     pub env: Arc<EnvConfig>,
     pub events: Arc<EventBus>,
     pub registry: Arc<AgentRegistry>,
+    pub templates: Arc<TemplateCatalog>,
 }
 
 impl ContainerManager {
@@ -160,6 +252,7 @@ impl ContainerManager {
         env: Arc<EnvConfig>,
         events: Arc<EventBus>,
         registry: Arc<AgentRegistry>,
+        templates: Arc<TemplateCatalog>,
     ) -> Self {
         let state = match std::fs::read_to_string(&state_path) {
             Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
@@ -180,6 +273,7 @@ impl ContainerManager {
             env,
             events,
             registry,
+            templates,
         }
     }
 
@@ -274,7 +368,22 @@ impl ContainerManager {
             req.frontend.auth_required = true;
         }
 
-        let host_id = req.host_id.clone().unwrap_or_else(|| "local".to_string());
+        let host_id = match req.host_id.clone().filter(|h| h != "auto") {
+            Some(host_id) => host_id,
+            None => {
+                let host_id = placement::decide(self, &req.placement).await.chosen()?;
+                info!(slug = %req.slug, host_id = %host_id, "Placed new application");
+                host_id
+            }
+        };
+        if crate::routes::hosts::host_draining(&host_id).await {
+            return Err(format!("Host {host_id} is in maintenance (draining)"));
+        }
+
+        let template = self.templates.resolve(req.template.as_deref())?;
+        if req.environment == Environment::Development && !template.distro.supports_dev() {
+            return Err(format!("Template {} cannot host development containers", template.id));
+        }
 
         // Clone fields needed for auto-PROD creation (before req is partially moved)
         let auto_prod_name = req.name.clone();
@@ -282,6 +391,7 @@ impl ContainerManager {
         let auto_prod_frontend = req.frontend.clone();
         let auto_prod_linked = req.linked_app_id.is_none();
         let auto_prod_env = req.environment;
+        let auto_prod_template = template.id.clone();
 
         // Create application in registry (headless — container deploy is managed separately)
         let create_req = CreateApplicationRequest {
@@ -295,6 +405,7 @@ impl ContainerManager {
             services: Default::default(),
             power_policy: Default::default(),
             wake_page_enabled: true,
+            process: None,
         };
 
         let (app, token) = self
@@ -315,6 +426,9 @@ impl ContainerManager {
             environment: req.environment,
             status: ContainerV2Status::Deploying,
             created_at: Utc::now(),
+            limits: ResourceLimits::default(),
+            template: Some(template.id.clone()),
+            standby_container: None,
         };
 
         // Persist the record
@@ -333,11 +447,11 @@ impl ContainerManager {
         tokio::spawn(async move {
             match environment {
                 hr_registry::types::Environment::Development => {
-                    mgr.run_nspawn_deploy_dev(&app_id, &slug, &container_name, &host_id, &token_deploy)
+                    mgr.run_nspawn_deploy_dev(&app_id, &slug, &container_name, &host_id, &token_deploy, &template)
                         .await;
                 }
                 hr_registry::types::Environment::Production => {
-                    mgr.run_nspawn_deploy_prod(&app_id, &slug, &container_name, &host_id, &token_deploy)
+                    mgr.run_nspawn_deploy_prod(&app_id, &slug, &container_name, &host_id, &token_deploy, &template)
                         .await;
                 }
             }
@@ -353,6 +467,8 @@ impl ContainerManager {
                 linked_app_id: Some(app.id.clone()),
                 code_server_enabled: false,
                 frontend: auto_prod_frontend,
+                template: Some(auto_prod_template),
+                placement: Default::default(),
             };
             let mgr_prod = Arc::clone(self);
             tokio::spawn(async move {
@@ -366,6 +482,168 @@ impl ContainerManager {
         Ok((record, token))
     }
 
+    /// Container V2 record of an application.
+    pub async fn record(&self, id: &str) -> Option<ContainerV2Record> {
+        self.state.read().await.containers.iter().find(|c| c.id == id).cloned()
+    }
+
+    /// Container V2 records of all applications.
+    pub async fn records(&self) -> Vec<ContainerV2Record> {
+        self.state.read().await.containers.clone()
+    }
+
+    /// Make `active` the container of an application and `standby` the one
+    /// kept for rollback (blue/green deployments).
+    pub async fn set_active_container(&self, id: &str, active: &str, standby: Option<String>) -> Result<(), String> {
+        let slug = {
+            let mut state = self.state.write().await;
+            let record = state.containers.iter_mut().find(|c| c.id == id).ok_or("Container not found")?;
+            record.container_name = active.to_string();
+            record.standby_container = standby;
+            record.slug.clone()
+        };
+        self.save_state().await?;
+        self.registry
+            .rename_application(id, &slug, active)
+            .await
+            .map_err(|e| format!("Failed to update application: {e}"))?;
+        Ok(())
+    }
+
+    // ── Restore ──────────────────────────────────────────────────
+
+    /// Register the application of a container restored from a backup on
+    /// the local host; `deploy_restored` then extracts and starts it.
+    pub async fn create_restored(&self, req: RestoreContainerRequest) -> Result<(ContainerV2Record, String), String> {
+        let mut frontend = req.frontend;
+        if req.environment == Environment::Development {
+            frontend.auth_required = true;
+        }
+        let create_req = CreateApplicationRequest {
+            name: req.name.clone(),
+            slug: req.slug.clone(),
+            host_id: Some("local".to_string()),
+            frontend,
+            environment: req.environment,
+            linked_app_id: None,
+            code_server_enabled: req.code_server_enabled,
+            services: Default::default(),
+            power_policy: Default::default(),
+            wake_page_enabled: true,
+            process: None,
+        };
+        let (app, token) = self
+            .registry
+            .create_application_headless(create_req)
+            .await
+            .map_err(|e| format!("Failed to create application record: {e}"))?;
+
+        let record = ContainerV2Record {
+            id: app.id.clone(),
+            name: req.name,
+            slug: req.slug,
+            container_name: app.container_name.clone(),
+            host_id: "local".to_string(),
+            environment: req.environment,
+            status: ContainerV2Status::Deploying,
+            created_at: Utc::now(),
+            limits: ResourceLimits::default(),
+            template: None,
+            standby_container: None,
+        };
+        {
+            let mut state = self.state.write().await;
+            state.containers.push(record.clone());
+        }
+        let _ = self.save_state().await;
+        Ok((record, token))
+    }
+
+    /// Extract the archives of a backup (rootfs, workspace) as the container
+    /// of `record` and start it with a fresh agent config.
+    pub async fn deploy_restored(
+        &self,
+        record: &ContainerV2Record,
+        token: &str,
+        archives: Result<(PathBuf, Option<PathBuf>, Compression), String>,
+    ) {
+        let emit = |message: &str| {
+            let _ = self.events.agent_status.send(AgentStatusEvent {
+                app_id: record.id.clone(),
+                slug: record.slug.clone(),
+                status: "deploying".to_string(),
+                message: Some(message.to_string()),
+            });
+        };
+
+        if let Err(e) = self.restore_rootfs(record, token, archives, &emit).await {
+            error!(container = %record.container_name, "Restore failed: {e}");
+            emit(&format!("Erreur: {e}"));
+            self.set_container_status(&record.id, ContainerV2Status::Error).await;
+            return;
+        }
+
+        self.set_container_status(&record.id, ContainerV2Status::Running).await;
+        let _ = self.events.agent_status.send(AgentStatusEvent {
+            app_id: record.id.clone(),
+            slug: record.slug.clone(),
+            status: "pending".to_string(),
+            message: Some("Restauration terminee".to_string()),
+        });
+        self.registry.request_app_cert(&record.slug).await;
+        info!(container = %record.container_name, "Container restored from backup");
+    }
+
+    async fn restore_rootfs(
+        &self,
+        record: &ContainerV2Record,
+        token: &str,
+        archives: Result<(PathBuf, Option<PathBuf>, Compression), String>,
+        emit: &impl Fn(&str),
+    ) -> Result<(), String> {
+        let (rootfs, workspace, compression) = archives?;
+        let storage_path = self.resolve_storage_path("local").await;
+        let storage = Path::new(&storage_path);
+        let network_mode = self.resolve_network_mode("local").await?;
+        let name = &record.container_name;
+
+        emit("Restauration du rootfs...");
+        let rootfs_dir = storage.join(name);
+        if let Err(e) = crate::backups::extract(&rootfs, &rootfs_dir, compression).await {
+            let _ = tokio::fs::remove_dir_all(&rootfs_dir).await;
+            return Err(e);
+        }
+
+        // Dev containers always get a workspace, empty when not backed up
+        let with_workspace = workspace.is_some() || record.environment == Environment::Development;
+        let ws_dir = storage.join(format!("{name}-workspace"));
+        if let Some(ws) = &workspace {
+            emit("Restauration du workspace...");
+            if let Err(e) = crate::backups::extract(ws, &ws_dir, compression).await {
+                warn!(container = %name, "Workspace restore failed, starting with an empty workspace: {e}");
+            }
+        }
+        if with_workspace {
+            let _ = tokio::fs::create_dir_all(&ws_dir).await;
+        }
+
+        emit("Configuration de l'agent...");
+        tokio::fs::write(rootfs_dir.join("etc/hr-agent.toml"), self.agent_config(token, &record.slug, &network_mode))
+            .await
+            .map_err(|e| format!("Failed to write agent config: {e}"))?;
+        NspawnClient::write_nspawn_unit(name, storage, &network_mode, with_workspace)
+            .await
+            .map_err(|e| format!("Failed to write nspawn unit: {e}"))?;
+        NspawnClient::write_network_config(name, storage)
+            .await
+            .map_err(|e| format!("Failed to write network config: {e}"))?;
+
+        emit("Demarrage du conteneur...");
+        NspawnClient::start_container(name)
+            .await
+            .map_err(|e| format!("Failed to start container: {e}"))
+    }
+
     /// Remove a container: stop nspawn, delete rootfs, remove from registry.
     pub async fn remove_container(&self, id: &str) -> Result<bool, String> {
         let record = {
@@ -384,6 +662,10 @@ impl ContainerManager {
             let _ =
                 NspawnClient::delete_container(&record.container_name, Path::new(&storage_path))
                     .await;
+            if let Some(standby) = &record.standby_container {
+                let _ = NspawnClient::stop_container(standby).await;
+                let _ = NspawnClient::delete_container(standby, Path::new(&storage_path)).await;
+            }
         } else {
             let _ = self
                 .registry
@@ -483,6 +765,64 @@ impl ContainerManager {
         Ok(true)
     }
 
+    // ── Resource limits ──────────────────────────────────────────
+
+    /// Replace the resource limits of a container and apply them on its host.
+    pub async fn set_limits(&self, id: &str, limits: ResourceLimits) -> Result<Option<ContainerV2Record>, String> {
+        limits.validate()?;
+        let Some(record) = self.record(id).await else {
+            return Ok(None);
+        };
+        self.apply_limits(&record.host_id, &record.container_name, &limits).await?;
+
+        let record = {
+            let mut state = self.state.write().await;
+            let Some(c) = state.containers.iter_mut().find(|c| c.id == id) else {
+                return Ok(None);
+            };
+            c.limits = limits;
+            c.clone()
+        };
+        let _ = self.save_state().await;
+        info!(id, limits = ?record.limits, "Container V2 limits updated");
+        Ok(Some(record))
+    }
+
+    async fn apply_limits(&self, host_id: &str, container_name: &str, limits: &ResourceLimits) -> Result<(), String> {
+        let storage_path = self.resolve_storage_path(host_id).await;
+        if host_id == "local" {
+            NspawnClient::apply_limits(container_name, Path::new(&storage_path), limits)
+                .await
+                .map_err(|e| e.to_string())
+        } else {
+            self.registry
+                .send_host_command(
+                    host_id,
+                    HostRegistryMessage::SetContainerLimits {
+                        container_name: container_name.to_string(),
+                        storage_path,
+                        limits: limits.clone(),
+                    },
+                )
+                .await
+        }
+    }
+
+    /// Current resource usage of a container, `None` when it is not running
+    /// (or its host agent does not report usage).
+    pub async fn usage(&self, record: &ContainerV2Record) -> Option<ContainerUsage> {
+        if record.host_id == "local" {
+            let storage_path = self.resolve_storage_path("local").await;
+            NspawnClient::usage(&record.container_name, Path::new(&storage_path)).await
+        } else {
+            self.registry
+                .host_usage(&record.host_id)
+                .await
+                .into_iter()
+                .find(|u| u.name == record.container_name)
+        }
+    }
+
     /// Update a V2 container's configuration (endpoints, name, code-server).
     pub async fn update_container(&self, id: &str, mut req: UpdateContainerRequest) -> Result<bool, String> {
         // Check container exists and get its environment
@@ -612,6 +952,11 @@ impl ContainerManager {
         }
     }
 
+    /// Whether a migration end can (de)compress zstd transfer streams.
+    async fn supports_zstd(&self, host_id: &str) -> bool {
+        host_id == "local" || self.registry.host_supports(host_id, capability::ZSTD_TRANSFERS).await
+    }
+
     pub async fn resolve_network_mode(&self, host_id: &str) -> Result<String, String> {
         if host_id == "local" {
             // Local host MUST use bridge mode: macvlan isolation prevents
@@ -668,6 +1013,7 @@ impl ContainerManager {
         container_name: &str,
         host_id: &str,
         token: &str,
+        template: &ContainerTemplate,
     ) {
         let emit = |message: &str| {
             let _ = self.events.agent_status.send(AgentStatusEvent {
@@ -694,7 +1040,7 @@ impl ContainerManager {
 
         // Phase 1: Create the nspawn container (dev → with workspace)
         emit("Creation du conteneur nspawn...");
-        if let Err(e) = NspawnClient::create_container(container_name, storage, &network_mode, true).await {
+        if let Err(e) = NspawnClient::create_from_template(container_name, storage, &network_mode, true, template, self.templates.cache()).await {
             error!(container = container_name, "Nspawn creation failed: {e}");
             emit(&format!("Erreur: {e}"));
             self.set_container_status(app_id, ContainerV2Status::Error)
@@ -704,14 +1050,14 @@ impl ContainerManager {
 
         // Phase 2: Deploy agent binary
         emit("Deploiement du binaire agent...");
-        let agent_binary = PathBuf::from("/opt/homeroute/data/agent-binaries/hr-agent");
-        if !agent_binary.exists() {
+        // Local container: the server's architecture
+        let Some(agent_binary) = BinaryStore::default().find("hr-agent", local_arch()) else {
             let msg = "Agent binary not found";
             emit(msg);
             self.set_container_status(app_id, ContainerV2Status::Error)
                 .await;
             return;
-        }
+        };
 
         if let Err(e) =
             NspawnClient::push_file(container_name, &agent_binary, "usr/local/bin/hr-agent", storage)
@@ -724,12 +1070,7 @@ impl ContainerManager {
             return;
         }
 
-        if let Err(e) = NspawnClient::exec(
-            container_name,
-            &["chmod", "+x", "/usr/local/bin/hr-agent"],
-        )
-        .await
-        {
+        if let Err(e) = NspawnClient::exec_sh(container_name, template.distro, "chmod +x /usr/local/bin/hr-agent").await {
             error!(container = container_name, "chmod failed: {e}");
             emit(&format!("Erreur: {e}"));
             self.set_container_status(app_id, ContainerV2Status::Error)
@@ -739,21 +1080,7 @@ impl ContainerManager {
 
         // Phase 3: Generate and push agent config
         emit("Configuration de l'agent...");
-        let api_port = self.env.api_port;
-        // Derive container-internal interface name from network_mode
-        let agent_interface = if let Some(parent) = network_mode.strip_prefix("macvlan:") {
-            format!("mv-{parent}")
-        } else {
-            "host0".to_string()
-        };
-        let config_content = format!(
-            r#"homeroute_address = "10.0.0.254"
-homeroute_port = {api_port}
-token = "{token}"
-service_name = "{slug}"
-interface = "{agent_interface}"
-"#
-        );
+        let config_content = self.agent_config(token, slug, &network_mode);
 
         let tmp_config = PathBuf::from(format!("/tmp/hr-agent-v2-{slug}.toml"));
         if let Err(e) = tokio::fs::write(&tmp_config, &config_content).await {
@@ -765,53 +1092,29 @@ interface = "{agent_interface}"
         let _ = NspawnClient::push_file(container_name, &tmp_config, "etc/hr-agent.toml", storage).await;
         let _ = tokio::fs::remove_file(&tmp_config).await;
 
-        // Phase 4: Push systemd unit
-        let unit_content = r#"[Unit]
-Description=HomeRoute Agent
-After=network-online.target
-Wants=network-online.target
-
-[Service]
-ExecStart=/usr/local/bin/hr-agent
-Restart=always
-RestartSec=5
-Environment=RUST_LOG=info
-
-[Install]
-WantedBy=multi-user.target
-"#;
-        let tmp_unit = PathBuf::from(format!("/tmp/hr-agent-v2-{slug}.service"));
-        let _ = tokio::fs::write(&tmp_unit, unit_content).await;
-        let _ = NspawnClient::push_file(
-            container_name,
-            &tmp_unit,
-            "etc/systemd/system/hr-agent.service",
-            storage,
-        )
-        .await;
-        let _ = tokio::fs::remove_file(&tmp_unit).await;
-
-        // Phase 5: Enable and start agent
-        emit("Demarrage de l'agent...");
-        let _ = NspawnClient::exec(container_name, &["systemctl", "daemon-reload"]).await;
-        let _ =
-            NspawnClient::exec(container_name, &["systemctl", "enable", "--now", "hr-agent"])
-                .await;
+        // Phase 4-5: Install and start the agent service
+        if template.recipe.install_agent {
+            emit("Demarrage de l'agent...");
+            if let Err(e) = NspawnClient::install_agent_service(container_name, storage, template.distro).await {
+                warn!(container = container_name, "Agent service install failed: {e}");
+            }
+        }
 
         // Phase 6: Wait for network
         emit("Attente de la connectivite reseau...");
-        if let Err(e) = NspawnClient::wait_for_network(container_name, 30).await {
+        if let Err(e) = NspawnClient::wait_online(container_name, template.distro, 30).await {
             warn!(container = container_name, "Network wait failed: {e}");
         }
 
-        // Phase 7: Install dependencies (curl now pre-installed in rootfs, this is a safety net)
-        emit("Installation des dependances...");
-        let _ = NspawnClient::exec_with_retry(
-            container_name,
-            &["apt-get update -qq && apt-get install -y -qq curl ca-certificates"],
-            3,
-        )
-        .await;
+        // Phase 7: Provision from the template recipe (packages, users, commands)
+        emit(&format!("Provisionnement ({})...", template.name));
+        if let Err(e) = NspawnClient::provision(container_name, storage, template).await {
+            error!(container = container_name, "Provisioning failed: {e}");
+            emit(&format!("Erreur: {e}"));
+            self.set_container_status(app_id, ContainerV2Status::Error)
+                .await;
+            return;
+        }
 
         // Phase 8: Install code-server
         emit("Installation de code-server...");
@@ -1052,6 +1355,7 @@ WantedBy=multi-user.target
         container_name: &str,
         host_id: &str,
         token: &str,
+        template: &ContainerTemplate,
     ) {
         let emit = |message: &str| {
             let _ = self.events.agent_status.send(AgentStatusEvent {
@@ -1078,7 +1382,7 @@ WantedBy=multi-user.target
 
         // Phase 1: Create the nspawn container (prod → no workspace)
         emit("Creation du conteneur nspawn...");
-        if let Err(e) = NspawnClient::create_container(container_name, storage, &network_mode, false).await {
+        if let Err(e) = NspawnClient::create_from_template(container_name, storage, &network_mode, false, template, self.templates.cache()).await {
             error!(container = container_name, "Nspawn creation failed: {e}");
             emit(&format!("Erreur: {e}"));
             self.set_container_status(app_id, ContainerV2Status::Error)
@@ -1088,14 +1392,14 @@ WantedBy=multi-user.target
 
         // Phase 2: Deploy agent binary
         emit("Deploiement du binaire agent...");
-        let agent_binary = PathBuf::from("/opt/homeroute/data/agent-binaries/hr-agent");
-        if !agent_binary.exists() {
+        // Local container: the server's architecture
+        let Some(agent_binary) = BinaryStore::default().find("hr-agent", local_arch()) else {
             let msg = "Agent binary not found";
             emit(msg);
             self.set_container_status(app_id, ContainerV2Status::Error)
                 .await;
             return;
-        }
+        };
 
         if let Err(e) =
             NspawnClient::push_file(container_name, &agent_binary, "usr/local/bin/hr-agent", storage)
@@ -1108,12 +1412,7 @@ WantedBy=multi-user.target
             return;
         }
 
-        if let Err(e) = NspawnClient::exec(
-            container_name,
-            &["chmod", "+x", "/usr/local/bin/hr-agent"],
-        )
-        .await
-        {
+        if let Err(e) = NspawnClient::exec_sh(container_name, template.distro, "chmod +x /usr/local/bin/hr-agent").await {
             error!(container = container_name, "chmod failed: {e}");
             emit(&format!("Erreur: {e}"));
             self.set_container_status(app_id, ContainerV2Status::Error)
@@ -1123,20 +1422,7 @@ WantedBy=multi-user.target
 
         // Phase 3: Generate and push agent config
         emit("Configuration de l'agent...");
-        let api_port = self.env.api_port;
-        let agent_interface = if let Some(parent) = network_mode.strip_prefix("macvlan:") {
-            format!("mv-{parent}")
-        } else {
-            "host0".to_string()
-        };
-        let config_content = format!(
-            r#"homeroute_address = "10.0.0.254"
-homeroute_port = {api_port}
-token = "{token}"
-service_name = "{slug}"
-interface = "{agent_interface}"
-"#
-        );
+        let config_content = self.agent_config(token, slug, &network_mode);
 
         let tmp_config = PathBuf::from(format!("/tmp/hr-agent-v2-{slug}.toml"));
         if let Err(e) = tokio::fs::write(&tmp_config, &config_content).await {
@@ -1148,53 +1434,29 @@ interface = "{agent_interface}"
         let _ = NspawnClient::push_file(container_name, &tmp_config, "etc/hr-agent.toml", storage).await;
         let _ = tokio::fs::remove_file(&tmp_config).await;
 
-        // Phase 4: Push systemd unit
-        let unit_content = r#"[Unit]
-Description=HomeRoute Agent
-After=network-online.target
-Wants=network-online.target
-
-[Service]
-ExecStart=/usr/local/bin/hr-agent
-Restart=always
-RestartSec=5
-Environment=RUST_LOG=info
-
-[Install]
-WantedBy=multi-user.target
-"#;
-        let tmp_unit = PathBuf::from(format!("/tmp/hr-agent-v2-{slug}.service"));
-        let _ = tokio::fs::write(&tmp_unit, unit_content).await;
-        let _ = NspawnClient::push_file(
-            container_name,
-            &tmp_unit,
-            "etc/systemd/system/hr-agent.service",
-            storage,
-        )
-        .await;
-        let _ = tokio::fs::remove_file(&tmp_unit).await;
-
-        // Phase 5: Enable and start agent
-        emit("Demarrage de l'agent...");
-        let _ = NspawnClient::exec(container_name, &["systemctl", "daemon-reload"]).await;
-        let _ =
-            NspawnClient::exec(container_name, &["systemctl", "enable", "--now", "hr-agent"])
-                .await;
+        // Phase 4-5: Install and start the agent service
+        if template.recipe.install_agent {
+            emit("Demarrage de l'agent...");
+            if let Err(e) = NspawnClient::install_agent_service(container_name, storage, template.distro).await {
+                warn!(container = container_name, "Agent service install failed: {e}");
+            }
+        }
 
         // Phase 6: Wait for network
         emit("Attente de la connectivite reseau...");
-        if let Err(e) = NspawnClient::wait_for_network(container_name, 30).await {
+        if let Err(e) = NspawnClient::wait_online(container_name, template.distro, 30).await {
             warn!(container = container_name, "Network wait failed: {e}");
         }
 
-        // Phase 7: Install dependencies (curl for agent binary updates)
-        emit("Installation des dependances...");
-        let _ = NspawnClient::exec_with_retry(
-            container_name,
-            &["apt-get update -qq && apt-get install -y -qq curl ca-certificates"],
-            3,
-        )
-        .await;
+        // Phase 7: Provision from the template recipe (packages, users, commands)
+        emit(&format!("Provisionnement ({})...", template.name));
+        if let Err(e) = NspawnClient::provision(container_name, storage, template).await {
+            error!(container = container_name, "Provisioning failed: {e}");
+            emit(&format!("Erreur: {e}"));
+            self.set_container_status(app_id, ContainerV2Status::Error)
+                .await;
+            return;
+        }
 
         // Update status (no workspace for prod containers)
         self.set_container_status(app_id, ContainerV2Status::Running)
@@ -1214,6 +1476,26 @@ WantedBy=multi-user.target
         info!(container = container_name, "Container V2 prod deploy complete");
     }
 
+    /// `/etc/hr-agent.toml` of a container.
+    fn agent_config(&self, token: &str, slug: &str, network_mode: &str) -> String {
+        let api_port = self.env.api_port;
+        // Derive container-internal interface name from network_mode
+        let agent_interface = if let Some(parent) = network_mode.strip_prefix("macvlan:") {
+            format!("mv-{parent}")
+        } else {
+            "host0".to_string()
+        };
+        format!(
+            r#"homeroute_address = "10.0.0.254"
+homeroute_port = {api_port}
+token = "{token}"
+service_name = "{slug}"
+interface = "{agent_interface}"
+{}"#,
+            hr_registry::update::config_line()
+        )
+    }
+
     /// Update the status of a container V2 record and the corresponding application.
     async fn set_container_status(&self, id: &str, status: ContainerV2Status) {
         {
@@ -1245,11 +1527,147 @@ WantedBy=multi-user.target
 
     // ── Inter-host migration ─────────────────────────────────────
 
+    /// Check that a container can move to `target_host_id`: both hosts
+    /// reachable, same architecture, nspawn and the container network on the
+    /// target, room for the rootfs and workspace, no container of the same
+    /// name there. Nothing is changed.
+    pub async fn preflight_migration(
+        &self,
+        container_id: &str,
+        target_host_id: &str,
+        compression_level: Option<i32>,
+    ) -> Result<MigrationPreflight, String> {
+        let record = self.record(container_id).await.ok_or("Container not found")?;
+        let source_host_id = record.host_id.clone();
+        let mut checks = Vec::new();
+        let mut check = |check: &'static str, status: CheckStatus, message: String| {
+            checks.push(PreflightCheck { check, status, message });
+        };
+
+        if source_host_id == target_host_id {
+            check("container", CheckStatus::Failed, "Container is already on target host".to_string());
+        } else if record.standby_container.is_some() {
+            check("container", CheckStatus::Failed, "Discard the standby container of the last deployment first".to_string());
+        } else if matches!(record.status, ContainerV2Status::Deploying | ContainerV2Status::Migrating) {
+            check("container", CheckStatus::Failed, format!("Container is {:?}", record.status).to_lowercase());
+        } else {
+            check("container", CheckStatus::Ok, record.container_name.clone());
+        }
+
+        if crate::routes::hosts::host_draining(target_host_id).await {
+            check("target_host", CheckStatus::Failed, format!("Host {target_host_id} is in maintenance (draining)"));
+        }
+
+        let network_mode = match self.resolve_network_mode(target_host_id).await {
+            Ok(mode) => Some(mode),
+            Err(e) => {
+                check("network", CheckStatus::Failed, e);
+                None
+            }
+        };
+        let name = Some(record.container_name.clone());
+        let source = self.host_preflight(&source_host_id, None, name.clone()).await;
+        let target = self.host_preflight(target_host_id, network_mode.clone(), name).await;
+
+        for (label, host_id, report) in [("source_host", &source_host_id, &source), ("target_host", &target_host_id.to_string(), &target)] {
+            match report {
+                Err(e) => check(label, CheckStatus::Failed, e.clone()),
+                Ok(None) => check(label, CheckStatus::Warning, format!("Host agent of {host_id} cannot run pre-flight checks, upgrade it")),
+                Ok(Some(_)) => match self.registry.host_protocol(host_id).await {
+                    Some((version, protocol)) if protocol.outdated() => check(
+                        label,
+                        CheckStatus::Warning,
+                        format!("Host agent {} of {host_id} speaks an older protocol, upgrade it", version.unwrap_or_default()),
+                    ),
+                    Some((_, protocol)) if !protocol.supports(capability::RESUMABLE_TRANSFERS) => {
+                        check(label, CheckStatus::Warning, format!("Transfers from or to {host_id} cannot resume after a disconnect"))
+                    }
+                    _ => check(label, CheckStatus::Ok, host_id.clone()),
+                },
+            }
+        }
+
+        let (source, target) = (source.ok().flatten(), target.ok().flatten());
+        if let Some(target) = &target {
+            if !target.nspawn {
+                check("nspawn", CheckStatus::Failed, "machinectl or systemd-nspawn missing on target host".to_string());
+            }
+            if network_mode.is_some() && !target.network_ok {
+                let device = target.network_device.clone().unwrap_or_default();
+                check("network", CheckStatus::Failed, format!("Interface {device} not found on target host"));
+            }
+            if target.container_bytes.is_some() {
+                check("container_name", CheckStatus::Failed, format!("{} already exists on target host", record.container_name));
+            }
+        }
+        if let (Some(source), Some(target)) = (&source, &target)
+            && source.arch != target.arch
+        {
+            check("architecture", CheckStatus::Failed, format!("Source is {}, target is {}", source.arch, target.arch));
+        }
+
+        let transfer_bytes = source.as_ref().and_then(|s| s.container_bytes);
+        match (transfer_bytes, target.as_ref().and_then(|t| t.free_bytes)) {
+            (Some(needed), Some(free)) if free < needed + DISK_HEADROOM => check(
+                "disk_space",
+                CheckStatus::Failed,
+                format!("{needed} bytes to receive, {free} bytes free on target host"),
+            ),
+            (Some(needed), Some(free)) => {
+                check("disk_space", CheckStatus::Ok, format!("{needed} bytes to receive, {free} bytes free"))
+            }
+            _ => check("disk_space", CheckStatus::Warning, "Free space on target host not verified".to_string()),
+        }
+
+        let compression = Compression::negotiate(
+            compression_level,
+            self.supports_zstd(&source_host_id).await && self.supports_zstd(target_host_id).await,
+        );
+        let ok = !checks.iter().any(|c| c.status == CheckStatus::Failed);
+        Ok(MigrationPreflight {
+            app_id: container_id.to_string(),
+            source_host_id,
+            target_host_id: target_host_id.to_string(),
+            ok,
+            checks,
+            compression,
+            transfer_bytes,
+            estimated_seconds: transfer_bytes.map(|bytes| bytes.div_ceil(ESTIMATED_THROUGHPUT)),
+        })
+    }
+
+    /// Pre-flight report of a host; `None` when its agent cannot make one.
+    async fn host_preflight(
+        &self,
+        host_id: &str,
+        network_mode: Option<String>,
+        container_name: Option<String>,
+    ) -> Result<Option<HostPreflight>, String> {
+        let storage_path = self.resolve_storage_path(host_id).await;
+        if host_id == "local" {
+            let report =
+                hr_container::preflight::probe(&storage_path, network_mode.as_deref(), container_name.as_deref()).await;
+            return Ok(Some(report));
+        }
+        if !self.registry.is_host_connected(host_id).await {
+            return Err(format!("Host {host_id} is not connected"));
+        }
+        if !self.registry.host_supports(host_id, capability::MIGRATION_PREFLIGHT).await {
+            return Ok(None);
+        }
+        self.registry
+            .host_preflight(host_id, &storage_path, network_mode, container_name)
+            .await
+            .map(Some)
+            .map_err(|e| format!("Pre-flight of {host_id} failed: {e}"))
+    }
+
     /// Start migration of a V2 container to another host.
     pub async fn migrate_container(
         self: &Arc<Self>,
         container_id: &str,
         target_host_id: &str,
+        compression_level: Option<i32>,
         migrations: &Arc<RwLock<std::collections::HashMap<String, MigrationState>>>,
     ) -> Result<String, String> {
         let record = {
@@ -1264,16 +1682,15 @@ WantedBy=multi-user.target
         let record = record.ok_or("Container not found")?;
         let source_host_id = record.host_id.clone();
 
-        if source_host_id == target_host_id {
-            return Err("Container is already on target host".to_string());
-        }
-
-        if target_host_id != "local" && !self.registry.is_host_connected(target_host_id).await {
-            return Err("Target host is not connected".to_string());
+        // Refuse now what would fail halfway through the export
+        let preflight = self.preflight_migration(container_id, target_host_id, compression_level).await?;
+        if !preflight.ok {
+            return Err(preflight.failures());
         }
 
         let transfer_id = uuid::Uuid::new_v4().to_string();
         let cancelled = Arc::new(AtomicBool::new(false));
+        let compression = preflight.compression;
 
         let migration_state = MigrationState {
             app_id: container_id.to_string(),
@@ -1287,6 +1704,7 @@ WantedBy=multi-user.target
             started_at: Utc::now(),
             error: None,
             cancelled: cancelled.clone(),
+            compression,
         };
 
         {
@@ -1436,6 +1854,12 @@ WantedBy=multi-user.target
 
         let source_storage = self.resolve_storage_path(source_host_id).await;
         let target_storage = self.resolve_storage_path(target_host_id).await;
+        let compression = migrations
+            .read()
+            .await
+            .get(transfer_id)
+            .map(|m| m.compression)
+            .unwrap_or_default();
 
         // Phase 1: Stopping
         crate::routes::applications::update_migration_phase(
@@ -1504,7 +1928,7 @@ WantedBy=multi-user.target
 
             if !target_is_local {
                 // Local → Remote: stream rootfs tar to target
-                let import_rx = registry.register_migration_signal(transfer_id).await;
+                let mut import_rx = registry.register_migration_signal(transfer_id).await;
 
                 let target_network_mode = self.resolve_network_mode(target_host_id).await?;
                 let _ = registry
@@ -1515,127 +1939,136 @@ WantedBy=multi-user.target
                             storage_path: target_storage.clone(),
                             transfer_id: transfer_id.to_string(),
                             network_mode: target_network_mode,
+                            compression,
                         },
                     )
                     .await
                     .map_err(|e| format!("Failed to notify target: {e}"))?;
 
-                // Spawn tar
-                let mut tar_child = tokio::process::Command::new("tar")
-                    .args(["cf", "-", "-C", &rootfs_path.to_string_lossy(), "."])
-                    .stdout(std::process::Stdio::piped())
-                    .spawn()
-                    .map_err(|e| format!("Failed to spawn tar: {e}"))?;
-
-                let mut tar_stdout = tar_child.stdout.take().unwrap();
+                let ws_path = Path::new(&source_storage).join(format!("{}-workspace", container_name));
+                let ws_size = if tokio::fs::metadata(&ws_path).await.is_ok() {
+                    let ws_size_output = tokio::process::Command::new("du")
+                        .args(["-sb", &ws_path.to_string_lossy()])
+                        .output()
+                        .await;
+                    Some(
+                        ws_size_output
+                            .ok()
+                            .map(|o| {
+                                String::from_utf8_lossy(&o.stdout)
+                                    .split_whitespace()
+                                    .next()
+                                    .and_then(|s| s.parse().ok())
+                                    .unwrap_or(0)
+                            })
+                            .unwrap_or(0),
+                    )
+                } else {
+                    None
+                };
 
-                let (_transferred, _seq) = crate::routes::applications::stream_to_remote(
+                // Targets speaking `resumable_transfers` survive disconnections:
+                // wait for their resume point instead of failing.
+                let resumes = if registry.host_supports(target_host_id, capability::RESUMABLE_TRANSFERS).await {
+                    Some(registry.register_transfer_resume(transfer_id).await)
+                } else {
+                    None
+                };
+                let mut push = RemotePush {
                     registry,
                     target_host_id,
                     transfer_id,
-                    &mut tar_stdout,
-                    total_bytes,
+                    app_id,
                     cancelled,
                     migrations,
                     events,
-                    app_id,
-                    20,
-                    80,
-                    MigrationPhase::Transferring,
-                )
-                .await?;
-
-                let _ = tar_child.wait().await;
-
-                // Stream workspace if exists
-                let ws_path = Path::new(&source_storage).join(format!("{}-workspace", container_name));
-                if tokio::fs::metadata(&ws_path).await.is_ok() {
-                    let ws_size_output = tokio::process::Command::new("du")
-                        .args(["-sb", &ws_path.to_string_lossy()])
-                        .output()
-                        .await;
-                    let ws_size: u64 = ws_size_output
-                        .ok()
-                        .map(|o| {
-                            String::from_utf8_lossy(&o.stdout)
-                                .split_whitespace()
-                                .next()
-                                .and_then(|s| s.parse().ok())
-                                .unwrap_or(0)
-                        })
-                        .unwrap_or(0);
-
-                    let _ = registry
-                        .send_host_command(
-                            target_host_id,
-                            HostRegistryMessage::WorkspaceReady {
-                                transfer_id: transfer_id.to_string(),
-                                size_bytes: ws_size,
-                            },
-                        )
-                        .await;
-
-                    if let Ok(mut ws_child) = tokio::process::Command::new("tar")
-                        .args(["cf", "-", "-C", &ws_path.to_string_lossy(), "."])
-                        .stdout(std::process::Stdio::piped())
-                        .spawn()
-                    {
-                        if let Some(mut ws_stdout) = ws_child.stdout.take() {
-                            let _ = crate::routes::applications::stream_to_remote(
-                                registry,
-                                target_host_id,
-                                transfer_id,
-                                &mut ws_stdout,
-                                ws_size,
-                                cancelled,
-                                migrations,
-                                events,
-                                app_id,
-                                82,
-                                84,
-                                MigrationPhase::TransferringWorkspace,
-                            )
-                            .await;
+                    resumes,
+                    compression,
+                };
+
+                let mut from = Some(ResumePoint::default());
+                let import_deadline = tokio::time::sleep(IMPORT_TIMEOUT);
+                tokio::pin!(import_deadline);
+                let result = loop {
+                    if let Some(point) = from.take() {
+                        match push_nspawn_streams(&mut push, &rootfs_path, total_bytes, &ws_path, ws_size, point).await {
+                            Ok(()) => {
+                                import_deadline.as_mut().reset(tokio::time::Instant::now() + IMPORT_TIMEOUT);
+                                crate::routes::applications::update_migration_phase(
+                                    migrations,
+                                    events,
+                                    app_id,
+                                    transfer_id,
+                                    MigrationPhase::Importing,
+                                    85,
+                                    0,
+                                    0,
+                                    None,
+                                )
+                                .await;
+                            }
+                            Err(StreamError::Failed(e)) => break Err(e),
+                            Err(StreamError::Interrupted(Some(point))) => {
+                                from = Some(point);
+                                continue;
+                            }
+                            Err(StreamError::Interrupted(None)) => {
+                                crate::routes::applications::update_migration_phase(
+                                    migrations,
+                                    events,
+                                    app_id,
+                                    transfer_id,
+                                    MigrationPhase::Resuming,
+                                    20,
+                                    0,
+                                    total_bytes,
+                                    None,
+                                )
+                                .await;
+                                let Some(resumes) = push.resumes.as_mut() else {
+                                    break Err("Transfer interrupted".to_string());
+                                };
+                                match tokio::time::timeout(RESUME_TIMEOUT, resumes.recv()).await {
+                                    Ok(Some(point)) => {
+                                        from = Some(point);
+                                        continue;
+                                    }
+                                    _ => break Err("Target host did not reconnect to resume the transfer".to_string()),
+                                }
+                            }
                         }
-                        let _ = ws_child.wait().await;
                     }
-                }
 
-                let _ = registry
-                    .send_host_command(
-                        target_host_id,
-                        HostRegistryMessage::TransferComplete {
-                            transfer_id: transfer_id.to_string(),
+                    // Transfer sent: wait for the import, or replay the end the
+                    // target missed if it reconnects meanwhile
+                    let resumed = async {
+                        match push.resumes.as_mut() {
+                            Some(resumes) => resumes.recv().await,
+                            None => std::future::pending().await,
+                        }
+                    };
+                    tokio::select! {
+                        result = &mut import_rx => break match result {
+                            Ok(hr_registry::MigrationResult::ImportComplete { .. }) => {
+                                info!(transfer_id, "Nspawn import confirmed by target host");
+                                Ok(())
+                            }
+                            Ok(hr_registry::MigrationResult::ImportFailed { error }) => {
+                                Err(format!("Migration failed on target: {error}"))
+                            }
+                            Ok(hr_registry::MigrationResult::ExportFailed { error }) => {
+                                Err(format!("Migration failed: {error}"))
+                            }
+                            Err(_) => Err("Migration signal lost".to_string()),
                         },
-                    )
-                    .await;
-
-                crate::routes::applications::update_migration_phase(
-                    migrations,
-                    events,
-                    app_id,
-                    transfer_id,
-                    MigrationPhase::Importing,
-                    85,
-                    0,
-                    0,
-                    None,
-                )
-                .await;
-
-                match tokio::time::timeout(Duration::from_secs(120), import_rx).await {
-                    Ok(Ok(hr_registry::MigrationResult::ImportComplete { .. })) => {
-                        info!(transfer_id, "Nspawn import confirmed by target host");
-                    }
-                    Ok(Ok(hr_registry::MigrationResult::ImportFailed { error })) => {
-                        return Err(format!("Migration failed on target: {error}"));
-                    }
-                    Ok(Ok(hr_registry::MigrationResult::ExportFailed { error })) => {
-                        return Err(format!("Migration failed: {error}"));
+                        Some(point) = resumed => from = Some(point),
+                        _ = &mut import_deadline => {
+                            break Err(format!("Import timed out after {}s", IMPORT_TIMEOUT.as_secs()));
+                        }
                     }
-                    Ok(Err(_)) => return Err("Migration signal lost".to_string()),
-                    Err(_) => return Err("Import timed out after 120s".to_string()),
-                }
+                };
+                registry.unregister_transfer_resume(transfer_id).await;
+                result?;
             } else {
                 // Local → Local: unlikely but handle gracefully
                 return Err("Local-to-local nspawn migration not supported".to_string());
@@ -1662,6 +2095,7 @@ WantedBy=multi-user.target
                             storage_path: target_storage.clone(),
                             transfer_id: transfer_id.to_string(),
                             network_mode: target_network_mode,
+                            compression,
                         },
                     )
                     .await
@@ -1675,6 +2109,7 @@ WantedBy=multi-user.target
                         container_name: container_name.to_string(),
                         storage_path: source_storage.clone(),
                         transfer_id: transfer_id.to_string(),
+                        compression,
                     },
                 )
                 .await
@@ -1853,6 +2288,14 @@ WantedBy=multi-user.target
             }
         }
 
+        // Limits are per host: apply them on the target
+        if let Some(record) = self.record(app_id).await
+            && !record.limits.is_empty()
+            && let Err(e) = self.apply_limits(target_host_id, container_name, &record.limits).await
+        {
+            warn!(app_id, "Failed to apply resource limits on {}: {e}", target_host_id);
+        }
+
         // Update container status
         {
             let mut state = self.state.write().await;
@@ -1913,6 +2356,9 @@ WantedBy=multi-user.target
         };
         let record = record.ok_or("Container not found")?;
         let old_slug = record.slug.clone();
+        if record.standby_container.is_some() {
+            return Err("Discard the standby container of the last deployment first".to_string());
+        }
 
         if old_slug == new_slug {
             return Err("New slug is the same as the current slug".to_string());
@@ -2230,7 +2676,15 @@ WantedBy=multi-user.target
 
         // ── Phase 7: Start containers ────────────────────────────
         Self::set_rename_phase(renames, rename_id, RenamePhase::StartingContainers, None).await;
-        for (_, _, new_name, _) in &app_infos {
+        for (aid, old_name, new_name, _) in &app_infos {
+            // The limits drop-in is named after the container
+            NspawnClient::remove_limits(old_name).await;
+            if let Some(record) = self.record(aid).await
+                && !record.limits.is_empty()
+                && let Err(e) = self.apply_limits("local", new_name, &record.limits).await
+            {
+                warn!(container = new_name.as_str(), "Failed to apply resource limits: {e}");
+            }
             if let Err(e) = NspawnClient::start_container(new_name).await {
                 error!(container = new_name.as_str(), "Failed to start renamed container: {e}");
             }
@@ -2378,3 +2832,66 @@ WantedBy=multi-user.target
         }
     }
 }
+
+/// Push the rootfs and workspace streams of a local container from `from`,
+/// then `TransferComplete`.
+async fn push_nspawn_streams(
+    push: &mut RemotePush<'_>,
+    rootfs_path: &Path,
+    total_bytes: u64,
+    ws_path: &Path,
+    ws_size: Option<u64>,
+    from: ResumePoint,
+) -> Result<(), StreamError> {
+    if from.stream == TransferStream::Container {
+        push.stream(
+            TransferStream::Container,
+            rootfs_path,
+            total_bytes,
+            (20, 80, MigrationPhase::Transferring),
+            from,
+        )
+        .await?;
+    }
+
+    if let Some(ws_size) = ws_size {
+        if from.stream == TransferStream::Container {
+            push.registry
+                .send_host_command(
+                    push.target_host_id,
+                    HostRegistryMessage::WorkspaceReady {
+                        transfer_id: push.transfer_id.to_string(),
+                        size_bytes: ws_size,
+                    },
+                )
+                .await
+                .map_err(|e| push.lost(e))?;
+        }
+        match push
+            .stream(
+                TransferStream::Workspace,
+                ws_path,
+                ws_size,
+                (82, 84, MigrationPhase::TransferringWorkspace),
+                from,
+            )
+            .await
+        {
+            Err(StreamError::Failed(e)) => warn!(transfer_id = push.transfer_id, "Workspace transfer failed (non-fatal): {e}"),
+            result => {
+                result?;
+            }
+        }
+    }
+
+    push.registry
+        .send_host_command(
+            push.target_host_id,
+            HostRegistryMessage::TransferComplete {
+                transfer_id: push.transfer_id.to_string(),
+            },
+        )
+        .await
+        .map_err(|e| push.lost(e))?;
+    Ok(())
+}
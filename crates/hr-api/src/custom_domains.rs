@@ -0,0 +1,190 @@
+//! Custom domains ("bring your own domain"): an external name is attached to
+//! a production application, checked to point at us through public
+//! resolvers, given its own certificate, then routed (and resolved on the
+//! LAN) like `{slug}.{base}`.
+
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use hr_acme::WildcardType;
+use hr_common::events::CertReadyEvent;
+use hr_dns::config::StaticRecord;
+use hr_dns::packet::{encode_name, parse_response_records};
+use hr_dns::records::{RData, RecordType};
+use hr_dns::upstream::UpstreamForwarder;
+use hr_dns::SharedDnsState;
+use hr_proxy::ProxyState;
+use hr_registry::types::{Application, CustomDomainStatus};
+use tracing::{info, warn};
+
+use crate::state::ApiState;
+
+/// Public resolvers used for the pointing check (not our own DNS, which
+/// answers the base domain locally).
+const PUBLIC_RESOLVERS: &[&str] = &["1.1.1.1", "8.8.8.8"];
+/// How often pending domains are checked again.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Check that `domain` resolves to the same addresses as `target`
+/// (`{slug}.{base}`), or is a CNAME to it.
+pub async fn check_pointing(domain: &str, target: &str) -> Result<(), String> {
+    let upstream = UpstreamForwarder::new(PUBLIC_RESOLVERS.iter().map(|s| s.to_string()).collect(), 3000);
+
+    let mut expected = HashSet::new();
+    let mut found = HashSet::new();
+    for qtype in [RecordType::A, RecordType::AAAA] {
+        let (_, target_addrs) = lookup(&upstream, target, qtype).await?;
+        expected.extend(target_addrs);
+        let (cnames, addrs) = lookup(&upstream, domain, qtype).await?;
+        if cnames.iter().any(|c| c.trim_end_matches('.').eq_ignore_ascii_case(target)) {
+            return Ok(());
+        }
+        found.extend(addrs);
+    }
+
+    if expected.is_empty() {
+        return Err(format!("{} does not resolve publicly", target));
+    }
+    if found.is_empty() {
+        return Err(format!("{} does not resolve: add a CNAME to {}", domain, target));
+    }
+    if found.is_disjoint(&expected) {
+        let mut found: Vec<String> = found.iter().map(|ip| ip.to_string()).collect();
+        found.sort();
+        return Err(format!(
+            "{} points to {}, not to {} (add a CNAME to {})",
+            domain,
+            found.join(", "),
+            target,
+            target
+        ));
+    }
+    Ok(())
+}
+
+/// CNAME targets and addresses in the answer for `name`/`qtype`.
+async fn lookup(
+    upstream: &UpstreamForwarder,
+    name: &str,
+    qtype: RecordType,
+) -> Result<(Vec<String>, Vec<IpAddr>), String> {
+    // The forwarder picks a random TXID itself
+    let mut query = vec![0x00, 0x00, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+    encode_name(name, &mut query);
+    query.extend_from_slice(&qtype.to_u16().to_be_bytes());
+    query.extend_from_slice(&[0x00, 0x01]);
+
+    let response = upstream
+        .forward(&query)
+        .await
+        .map_err(|e| format!("DNS lookup of {} failed: {}", name, e))?;
+    let (_, records) =
+        parse_response_records(&response).map_err(|e| format!("Invalid DNS response for {}: {}", name, e))?;
+
+    let mut cnames = Vec::new();
+    let mut addrs = Vec::new();
+    for record in records {
+        match record.rdata {
+            RData::CNAME(target) => cnames.push(target),
+            RData::A(ip) => addrs.push(IpAddr::V4(ip)),
+            RData::AAAA(ip) => addrs.push(IpAddr::V6(ip)),
+            _ => {}
+        }
+    }
+    Ok((cnames, addrs))
+}
+
+/// Route the active custom domains of `app` like its primary domain.
+pub fn alias_routes(proxy: &ProxyState, app: &Application, base_domain: &str) {
+    let primary = format!("{}.{}", app.slug, base_domain);
+    let Some(route) = proxy.get_app_route(&primary) else {
+        for domain in app.active_custom_domains() {
+            proxy.remove_app_route(domain);
+        }
+        return;
+    };
+    for domain in app.active_custom_domains() {
+        proxy.set_app_route(domain.to_string(), route.clone());
+    }
+}
+
+/// Resolve the active custom domains of `app` on the LAN like its primary
+/// domain (ALIAS records, flattened by our DNS: custom domains may be apexes).
+pub async fn alias_dns(dns: &SharedDnsState, app: &Application, base_domain: &str) {
+    let primary = format!("{}.{}", app.slug, base_domain);
+    let mut dns = dns.write().await;
+    for domain in app.active_custom_domains() {
+        dns.add_static_record(StaticRecord {
+            name: domain.to_string(),
+            record_type: "ALIAS".to_string(),
+            value: primary.clone(),
+            ttl: 60,
+        });
+    }
+}
+
+/// Check DNS, then issue the certificate and route the domain. The status
+/// is recorded at each step; returns the final one.
+pub async fn verify_and_activate(state: &ApiState, app_id: &str, domain: &str) -> CustomDomainStatus {
+    let Some(registry) = &state.registry else {
+        return CustomDomainStatus::Pending;
+    };
+    let Some(app) = registry.get_application(app_id).await else {
+        return CustomDomainStatus::Pending;
+    };
+    let base_domain = &state.env.base_domain;
+    let target = format!("{}.{}", app.slug, base_domain);
+
+    if let Err(e) = check_pointing(domain, &target).await {
+        let _ = registry
+            .set_custom_domain_status(app_id, domain, CustomDomainStatus::Pending, Some(e))
+            .await;
+        return CustomDomainStatus::Pending;
+    }
+    let _ = registry.set_custom_domain_status(app_id, domain, CustomDomainStatus::Verified, None).await;
+    info!(app = %app.slug, domain, "Custom domain DNS verified, requesting certificate");
+
+    let cert = match state.acme.request_wildcard(WildcardType::for_custom(&app.slug, domain)).await {
+        Ok(cert) => cert,
+        Err(e) => {
+            warn!(app = %app.slug, domain, error = %e, "Custom domain certificate failed");
+            let _ = registry
+                .set_custom_domain_status(app_id, domain, CustomDomainStatus::Failed, Some(e.to_string()))
+                .await;
+            return CustomDomainStatus::Failed;
+        }
+    };
+    let _ = state.events.cert_ready.send(CertReadyEvent {
+        slug: app.slug.clone(),
+        wildcard_domain: domain.to_string(),
+        cert_path: cert.cert_path,
+        key_path: cert.key_path,
+    });
+
+    let _ = registry.set_custom_domain_status(app_id, domain, CustomDomainStatus::Active, None).await;
+    if let Some(app) = registry.get_application(app_id).await {
+        alias_routes(&state.proxy, &app, base_domain);
+        alias_dns(&state.dns, &app, base_domain).await;
+    }
+    info!(app = %app.slug, domain, "Custom domain active");
+    CustomDomainStatus::Active
+}
+
+/// Periodically retry the domains still waiting for their DNS.
+pub async fn run_custom_domain_watch(state: ApiState) -> anyhow::Result<()> {
+    let Some(registry) = state.registry.clone() else {
+        return std::future::pending().await;
+    };
+    let mut tick = tokio::time::interval(RECHECK_INTERVAL);
+    loop {
+        tick.tick().await;
+        for app in registry.list_applications().await {
+            for custom in &app.custom_domains {
+                if custom.status == CustomDomainStatus::Pending {
+                    verify_and_activate(&state, &app.id, &custom.domain).await;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,637 @@
+//! Declarative apply: a single YAML (or JSON) document describing the
+//! routes, DNS records, DHCP reservations, firewall and applications. Each
+//! section is optional; a section that is present is the full desired
+//! set (undeclared routes, records and reservations are removed), an
+//! absent one is left untouched. The document is planned (diff against
+//! the live state plus validation) then converged through the config
+//! transactions, so every applied document shows up in the history of
+//! dns-dhcp and reverseproxy.
+//!
+//! Applications cannot be created or removed this way: declared
+//! applications must already exist (matched by slug), the others are
+//! reported as unmanaged.
+
+use std::collections::HashSet;
+
+use hr_dhcp::config::StaticLease;
+use hr_dns::config::StaticRecord;
+use hr_firewall::FirewallConfig;
+use hr_registry::types::{Application, UpdateApplicationRequest};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::config_tx::{self, Change, ConfigDocument};
+use crate::container_manager::UpdateContainerRequest;
+use crate::state::ApiState;
+
+/// One declarative apply at a time.
+static CONVERGE_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Host fields owned by HomeRoute, not by the document.
+const HOST_MANAGED_FIELDS: [&str; 2] = ["id", "createdAt"];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Declaration {
+    /// Reverse proxy hosts, matched by domain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub routes: Option<Vec<Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns_records: Option<Vec<StaticRecord>>,
+    /// Matched by MAC address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dhcp_reservations: Option<Vec<StaticLease>>,
+    /// Ban manager settings (thresholds, whitelisted networks, nftables).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub firewall: Option<FirewallConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub applications: Option<Vec<AppDeclaration>>,
+}
+
+/// Settings of an existing application. Absent fields are left as is.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AppDeclaration {
+    pub slug: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_required: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_groups: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_only: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_server_enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wake_page_enabled: Option<bool>,
+}
+
+impl AppDeclaration {
+    fn from_application(app: &Application) -> Self {
+        AppDeclaration {
+            slug: app.slug.clone(),
+            name: Some(app.name.clone()),
+            target_port: Some(app.frontend.target_port),
+            auth_required: Some(app.frontend.auth_required),
+            allowed_groups: Some(app.frontend.allowed_groups.clone()),
+            local_only: Some(app.frontend.local_only),
+            code_server_enabled: Some(app.code_server_enabled),
+            wake_page_enabled: Some(app.wake_page_enabled),
+        }
+    }
+
+    /// The current values of the fields this declaration sets.
+    fn current(&self, app: &Application) -> AppDeclaration {
+        let all = Self::from_application(app);
+        AppDeclaration {
+            slug: all.slug,
+            name: self.name.as_ref().and(all.name),
+            target_port: self.target_port.and(all.target_port),
+            auth_required: self.auth_required.and(all.auth_required),
+            allowed_groups: self.allowed_groups.as_ref().and(all.allowed_groups),
+            local_only: self.local_only.and(all.local_only),
+            code_server_enabled: self.code_server_enabled.and(all.code_server_enabled),
+            wake_page_enabled: self.wake_page_enabled.and(all.wake_page_enabled),
+        }
+    }
+}
+
+/// Live state the document is compared with.
+struct Snapshot {
+    dns_dhcp: Value,
+    reverseproxy: Value,
+    firewall: FirewallConfig,
+    applications: Vec<Application>,
+}
+
+/// What converging a document would do.
+#[derive(Debug, Default, Serialize)]
+pub struct Plan {
+    /// Paths are prefixed by the section (`routes[id=app.example.com].targetPort`).
+    pub changes: Vec<Change>,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    /// Slugs of the applications not declared in the document.
+    pub unmanaged: Vec<String>,
+}
+
+impl Plan {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Target state computed from a document.
+struct Convergence {
+    plan: Plan,
+    dns_dhcp: Option<Value>,
+    reverseproxy: Option<Value>,
+    firewall: Option<FirewallConfig>,
+    /// Application id and its declaration, for the ones that change.
+    applications: Vec<(String, AppDeclaration)>,
+}
+
+/// Outcome of a successful apply.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Converged {
+    pub changes: Vec<Change>,
+    pub warnings: Vec<String>,
+    pub unmanaged: Vec<String>,
+    /// New versions of the config documents, by document name.
+    #[schema(value_type = Object)]
+    pub versions: Map<String, Value>,
+}
+
+/// Parse a document. JSON being valid YAML, both are accepted.
+pub fn parse(text: &str) -> Result<Declaration, String> {
+    serde_yaml::from_str(text).map_err(|e| format!("Invalid document: {}", e))
+}
+
+// ── Snapshot ─────────────────────────────────────────────────────
+
+async fn snapshot(state: &ApiState) -> Result<Snapshot, String> {
+    let applications = match &state.registry {
+        Some(registry) => registry.list_applications().await,
+        None => Vec::new(),
+    };
+    Ok(Snapshot {
+        dns_dhcp: config_tx::current(state, ConfigDocument::DnsDhcp).await?,
+        reverseproxy: config_tx::current(state, ConfigDocument::ReverseProxy).await?,
+        firewall: state.bans.config(),
+        applications,
+    })
+}
+
+/// The live state as a document, every section filled.
+pub async fn export(state: &ApiState) -> Result<Declaration, String> {
+    let current = snapshot(state).await?;
+    let sections = crate::routes::dns_dhcp::parse_config(&current.dns_dhcp)?;
+    let routes = hosts(&current.reverseproxy)
+        .iter()
+        .map(|host| without_managed_fields(host.clone()))
+        .collect();
+    let mut applications: Vec<_> = current.applications.iter().map(AppDeclaration::from_application).collect();
+    applications.sort_by(|a, b| a.slug.cmp(&b.slug));
+    Ok(Declaration {
+        routes: Some(routes),
+        dns_records: Some(sections.dns.static_records),
+        dhcp_reservations: Some(sections.dhcp.static_leases),
+        firewall: Some(current.firewall),
+        applications: Some(applications),
+    })
+}
+
+// ── Convergence ──────────────────────────────────────────────────
+
+fn hosts(reverseproxy: &Value) -> Vec<Value> {
+    reverseproxy.get("hosts").and_then(|h| h.as_array()).cloned().unwrap_or_default()
+}
+
+fn without_managed_fields(mut host: Value) -> Value {
+    if let Some(host) = host.as_object_mut() {
+        for field in HOST_MANAGED_FIELDS {
+            host.remove(field);
+        }
+    }
+    host
+}
+
+/// Items keyed by `id`, so that the diff matches them by key.
+fn keyed(items: impl IntoIterator<Item = (String, Value)>) -> Value {
+    Value::Array(
+        items
+            .into_iter()
+            .map(|(key, mut item)| {
+                if let Some(object) = item.as_object_mut() {
+                    object.insert("id".to_string(), json!(key));
+                }
+                item
+            })
+            .collect(),
+    )
+}
+
+fn record_key(record: &StaticRecord) -> String {
+    format!("{} {} {}", record.name.to_lowercase(), record.record_type.to_uppercase(), record.value)
+}
+
+/// The reverseproxy document serving exactly `routes`. Hosts keep the id
+/// and creation date of the live host with the same domain.
+fn converge_routes(reverseproxy: &Value, routes: &[Value], errors: &mut Vec<String>) -> Value {
+    let base_domain = reverseproxy.get("baseDomain").and_then(|d| d.as_str()).unwrap_or("");
+    let current = hosts(reverseproxy);
+    let mut desired = Vec::new();
+    for route in routes {
+        if !route.is_object() {
+            errors.push("routes: every route must be an object".to_string());
+            continue;
+        }
+        let mut host = without_managed_fields(route.clone());
+        let Some(domain) = crate::routes::reverseproxy::host_domain(&host, base_domain) else {
+            errors.push("routes: a route has no subdomain or customDomain".to_string());
+            continue;
+        };
+        let live = current.iter().find(|h| {
+            crate::routes::reverseproxy::host_domain(h, base_domain).is_some_and(|d| d.eq_ignore_ascii_case(&domain))
+        });
+        host["id"] = match live.and_then(|h| h.get("id")) {
+            Some(id) => id.clone(),
+            None => json!(uuid::Uuid::new_v4().to_string()),
+        };
+        host["createdAt"] = match live.and_then(|h| h.get("createdAt")) {
+            Some(at) => at.clone(),
+            None => json!(chrono::Utc::now().to_rfc3339()),
+        };
+        if host.get("enabled").is_none() {
+            host["enabled"] = json!(true);
+        }
+        desired.push(host);
+    }
+    let mut config = reverseproxy.clone();
+    config["hosts"] = Value::Array(desired);
+    config
+}
+
+fn routes_view(reverseproxy: &Value) -> Value {
+    let base_domain = reverseproxy.get("baseDomain").and_then(|d| d.as_str()).unwrap_or("");
+    keyed(hosts(reverseproxy).into_iter().map(|host| {
+        let domain = crate::routes::reverseproxy::host_domain(&host, base_domain).unwrap_or_default();
+        (domain.to_lowercase(), without_managed_fields(host))
+    }))
+}
+
+/// Compute the target documents and the changes, without the checks that
+/// need I/O.
+fn converge(current: &Snapshot, desired: &Declaration) -> Convergence {
+    let mut errors = Vec::new();
+    let mut before = Map::new();
+    let mut after = Map::new();
+
+    let reverseproxy = desired.routes.as_ref().map(|routes| {
+        let config = converge_routes(&current.reverseproxy, routes, &mut errors);
+        before.insert("routes".to_string(), routes_view(&current.reverseproxy));
+        after.insert("routes".to_string(), routes_view(&config));
+        config
+    });
+
+    let mut dns_dhcp = None;
+    if desired.dns_records.is_some() || desired.dhcp_reservations.is_some() {
+        match crate::routes::dns_dhcp::parse_config(&current.dns_dhcp) {
+            Ok(sections) => {
+                let mut config = current.dns_dhcp.clone();
+                if let Some(records) = &desired.dns_records {
+                    let mut keys = HashSet::new();
+                    for record in records {
+                        if !keys.insert(record_key(record)) {
+                            errors.push(format!("dnsRecords: duplicate record {}", record_key(record)));
+                        }
+                    }
+                    let view = |records: &[StaticRecord]| {
+                        keyed(records.iter().map(|r| (record_key(r), serde_json::to_value(r).unwrap_or_default())))
+                    };
+                    before.insert("dnsRecords".to_string(), view(&sections.dns.static_records));
+                    after.insert("dnsRecords".to_string(), view(records));
+                    config["dns"]["static_records"] = serde_json::to_value(records).unwrap_or_default();
+                }
+                if let Some(leases) = &desired.dhcp_reservations {
+                    let view = |leases: &[StaticLease]| {
+                        keyed(leases.iter().map(|l| (l.mac.to_lowercase(), serde_json::to_value(l).unwrap_or_default())))
+                    };
+                    before.insert("dhcpReservations".to_string(), view(&sections.dhcp.static_leases));
+                    after.insert("dhcpReservations".to_string(), view(leases));
+                    config["dhcp"]["static_leases"] = serde_json::to_value(leases).unwrap_or_default();
+                }
+                dns_dhcp = Some(config);
+            }
+            Err(e) => errors.push(format!("The live dns-dhcp config cannot be read: {}", e)),
+        }
+    }
+
+    if let Some(firewall) = &desired.firewall {
+        if let Err(e) = firewall.validate() {
+            errors.push(format!("firewall: {}", e));
+        }
+        before.insert("firewall".to_string(), serde_json::to_value(&current.firewall).unwrap_or_default());
+        after.insert("firewall".to_string(), serde_json::to_value(firewall).unwrap_or_default());
+    }
+
+    let mut applications = Vec::new();
+    let mut unmanaged = Vec::new();
+    if let Some(declared) = &desired.applications {
+        let mut current_view = Vec::new();
+        let mut desired_view = Vec::new();
+        let mut slugs = HashSet::new();
+        for app in declared {
+            if !slugs.insert(app.slug.as_str()) {
+                errors.push(format!("applications: {} is declared several times", app.slug));
+                continue;
+            }
+            if app.target_port == Some(0) {
+                errors.push(format!("applications: {}: invalid target port 0", app.slug));
+            }
+            let Some(live) = current.applications.iter().find(|a| a.slug == app.slug) else {
+                errors.push(format!(
+                    "applications: unknown application {} (applications are created from the containers page)",
+                    app.slug
+                ));
+                continue;
+            };
+            let live_view = serde_json::to_value(app.current(live)).unwrap_or_default();
+            let app_view = serde_json::to_value(app).unwrap_or_default();
+            if live_view != app_view {
+                applications.push((live.id.clone(), app.clone()));
+            }
+            current_view.push((app.slug.clone(), live_view));
+            desired_view.push((app.slug.clone(), app_view));
+        }
+        unmanaged = current
+            .applications
+            .iter()
+            .filter(|a| !slugs.contains(a.slug.as_str()))
+            .map(|a| a.slug.clone())
+            .collect();
+        unmanaged.sort();
+        before.insert("applications".to_string(), keyed(current_view));
+        after.insert("applications".to_string(), keyed(desired_view));
+    }
+
+    let changes = config_tx::diff(&Value::Object(before), &Value::Object(after));
+    let changed = |section: &str| changes.iter().any(|c| c.path.starts_with(section));
+    Convergence {
+        plan: Plan { changes: changes.clone(), errors, warnings: Vec::new(), unmanaged },
+        dns_dhcp: dns_dhcp.filter(|_| changed("dnsRecords") || changed("dhcpReservations")),
+        reverseproxy: reverseproxy.filter(|_| changed("routes")),
+        firewall: desired.firewall.clone().filter(|_| changed("firewall")),
+        applications,
+    }
+}
+
+/// Converge and run the document checks of the config transactions.
+async fn prepare(state: &ApiState, desired: &Declaration) -> Result<Convergence, String> {
+    let current = snapshot(state).await?;
+    if desired.applications.is_some() && state.registry.is_none() {
+        return Err("The application registry is not available".to_string());
+    }
+    let mut convergence = converge(&current, desired);
+    for (doc, config) in [
+        (ConfigDocument::DnsDhcp, &convergence.dns_dhcp),
+        (ConfigDocument::ReverseProxy, &convergence.reverseproxy),
+    ] {
+        if let Some(config) = config {
+            let validation = config_tx::validate(doc, config).await;
+            let plan = &mut convergence.plan;
+            plan.errors.extend(validation.errors.into_iter().map(|e| format!("{}: {}", doc.name(), e)));
+            plan.warnings.extend(validation.warnings.into_iter().map(|w| format!("{}: {}", doc.name(), w)));
+        }
+    }
+    Ok(convergence)
+}
+
+/// What applying `desired` would change. Nothing is modified.
+pub async fn plan(state: &ApiState, desired: &Declaration) -> Result<Plan, String> {
+    Ok(prepare(state, desired).await?.plan)
+}
+
+// ── Apply ────────────────────────────────────────────────────────
+
+async fn save_firewall(state: &ApiState, config: FirewallConfig) -> Result<(), String> {
+    let path = state.env.firewall_config_path.clone();
+    let to_save = config.clone();
+    tokio::task::spawn_blocking(move || to_save.save_to_file(&path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Write failed: {}", e))?;
+    state.bans.apply(config);
+    Ok(())
+}
+
+async fn update_application(state: &ApiState, id: &str, app: AppDeclaration) -> Result<(), String> {
+    let registry = state.registry.as_ref().ok_or("The application registry is not available")?;
+    let live = registry.get_application(id).await.ok_or_else(|| format!("Application {} disappeared", app.slug))?;
+    let mut frontend = live.frontend.clone();
+    frontend.target_port = app.target_port.unwrap_or(frontend.target_port);
+    frontend.auth_required = app.auth_required.unwrap_or(frontend.auth_required);
+    frontend.local_only = app.local_only.unwrap_or(frontend.local_only);
+    if let Some(groups) = app.allowed_groups {
+        frontend.allowed_groups = groups;
+    }
+    // Through the container manager when it runs, to keep its records in
+    // sync (and the development rules enforced)
+    match &state.container_manager {
+        Some(manager) => {
+            let request = UpdateContainerRequest {
+                name: app.name,
+                frontend: Some(frontend),
+                code_server_enabled: app.code_server_enabled,
+            };
+            manager.update_container(id, request).await?;
+            if app.wake_page_enabled.is_some() {
+                let request = UpdateApplicationRequest {
+                    wake_page_enabled: app.wake_page_enabled,
+                    ..Default::default()
+                };
+                registry.update_application(id, request).await.map_err(|e| e.to_string())?;
+            }
+        }
+        None => {
+            let request = UpdateApplicationRequest {
+                name: app.name,
+                frontend: Some(frontend),
+                code_server_enabled: app.code_server_enabled,
+                wake_page_enabled: app.wake_page_enabled,
+                ..Default::default()
+            };
+            registry.update_application(id, request).await.map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// A step already applied, undone if a later one fails.
+enum Undo {
+    Document(ConfigDocument, Value),
+    Firewall(FirewallConfig),
+}
+
+async fn rollback(state: &ApiState, applied: Vec<Undo>, user: &Option<String>) {
+    for undo in applied.into_iter().rev() {
+        let result = match undo {
+            Undo::Document(doc, previous) => {
+                config_tx::commit(state, doc, previous, "declarative", user.clone()).await.map(|_| ())
+            }
+            Undo::Firewall(previous) => save_firewall(state, previous).await,
+        };
+        if let Err(e) = result {
+            tracing::error!("Declarative apply rollback failed: {}", e);
+        }
+    }
+}
+
+/// Validate `desired` and converge to it: dns-dhcp, reverseproxy and the
+/// firewall are replaced in turn, the earlier ones being restored if a
+/// later one fails; applications are updated last.
+pub async fn apply(state: &ApiState, desired: &Declaration, user: Option<String>) -> Result<Converged, String> {
+    let _guard = CONVERGE_LOCK.lock().await;
+    let convergence = prepare(state, desired).await?;
+    let plan = convergence.plan;
+    if !plan.is_valid() {
+        return Err(plan.errors.join("; "));
+    }
+
+    let mut applied = Vec::new();
+    let mut versions = Map::new();
+    for (doc, config) in [
+        (ConfigDocument::DnsDhcp, convergence.dns_dhcp),
+        (ConfigDocument::ReverseProxy, convergence.reverseproxy),
+    ] {
+        let Some(config) = config else { continue };
+        let previous = config_tx::current(state, doc).await?;
+        match config_tx::commit(state, doc, config, "declarative", user.clone()).await {
+            Ok(result) => {
+                versions.insert(doc.name().to_string(), json!(result.version));
+                applied.push(Undo::Document(doc, previous));
+            }
+            Err(e) => {
+                rollback(state, applied, &user).await;
+                return Err(format!("{}: {}", doc.name(), e));
+            }
+        }
+    }
+    if let Some(firewall) = convergence.firewall {
+        let previous = state.bans.config();
+        if let Err(e) = save_firewall(state, firewall).await {
+            rollback(state, applied, &user).await;
+            return Err(format!("firewall: {}", e));
+        }
+        applied.push(Undo::Firewall(previous));
+    }
+
+    let mut updated = Vec::new();
+    for (id, app) in convergence.applications {
+        let slug = app.slug.clone();
+        if let Err(e) = update_application(state, &id, app).await {
+            let done = if updated.is_empty() { String::new() } else { format!(", already updated: {}", updated.join(", ")) };
+            return Err(format!("applications: {}: {}{}", slug, e, done));
+        }
+        updated.push(slug);
+    }
+
+    Ok(Converged {
+        changes: plan.changes,
+        warnings: plan.warnings,
+        unmanaged: plan.unmanaged,
+        versions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            dns_dhcp: json!({
+                "dns": {"static_records": [{"name": "nas.lan", "type": "A", "value": "192.168.1.5", "ttl": 300}]},
+                "dhcp": {"static_leases": [{"mac": "AA:BB:CC:DD:EE:FF", "ip": "192.168.1.10", "hostname": "tv"}]}
+            }),
+            reverseproxy: json!({
+                "baseDomain": "example.com",
+                "hosts": [
+                    {"id": "h1", "createdAt": "2024-01-01T00:00:00Z", "subdomain": "app", "targetPort": 3000, "enabled": true},
+                    {"id": "h2", "createdAt": "2024-01-01T00:00:00Z", "subdomain": "old", "targetPort": 80, "enabled": true}
+                ]
+            }),
+            firewall: serde_json::from_value(json!({})).unwrap(),
+            applications: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parse_yaml_and_json() {
+        let declaration = parse("routes:\n  - subdomain: app\n    targetPort: 8080\ndnsRecords: []\n").unwrap();
+        assert_eq!(declaration.routes.unwrap()[0]["targetPort"], json!(8080));
+        assert!(declaration.dns_records.unwrap().is_empty());
+        assert!(declaration.firewall.is_none());
+
+        let declaration = parse(r#"{"dhcpReservations": [{"mac": "aa:bb:cc:dd:ee:01", "ip": "192.168.1.20"}]}"#).unwrap();
+        assert_eq!(declaration.dhcp_reservations.unwrap()[0].ip, "192.168.1.20");
+
+        assert!(parse("zones: []").is_err());
+    }
+
+    #[test]
+    fn absent_sections_are_untouched() {
+        let convergence = converge(&snapshot(), &Declaration::default());
+        assert!(convergence.plan.changes.is_empty());
+        assert!(convergence.dns_dhcp.is_none());
+        assert!(convergence.reverseproxy.is_none());
+        assert!(convergence.firewall.is_none());
+    }
+
+    #[test]
+    fn routes_are_matched_by_domain() {
+        let declaration = parse(
+            "routes:\n  - subdomain: APP\n    targetPort: 3001\n    enabled: true\n  - customDomain: shop.example.org\n    targetPort: 80\n",
+        )
+        .unwrap();
+        let convergence = converge(&snapshot(), &declaration);
+        assert!(convergence.plan.is_valid());
+        let paths: Vec<_> = convergence.plan.changes.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["routes[id=app.example.com].subdomain", "routes[id=app.example.com].targetPort", "routes[id=old.example.com]", "routes[id=shop.example.org]"]
+        );
+
+        let config = convergence.reverseproxy.unwrap();
+        let hosts = config["hosts"].as_array().unwrap();
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts[0]["id"], json!("h1"));
+        assert_eq!(hosts[0]["createdAt"], json!("2024-01-01T00:00:00Z"));
+        assert_ne!(hosts[1]["id"], json!("h2"));
+        assert_eq!(hosts[1]["enabled"], json!(true));
+        assert_eq!(config["baseDomain"], json!("example.com"));
+    }
+
+    #[test]
+    fn records_and_reservations() {
+        let declaration = parse(
+            "dnsRecords:\n  - {name: nas.lan, type: A, value: 192.168.1.5, ttl: 60}\n  - {name: www.lan, type: CNAME, value: nas.lan}\n\
+             dhcpReservations:\n  - {mac: 'aa:bb:cc:dd:ee:ff', ip: 192.168.1.10, hostname: tv}\n",
+        )
+        .unwrap();
+        let convergence = converge(&snapshot(), &declaration);
+        let paths: Vec<_> = convergence.plan.changes.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["dhcpReservations[id=aa:bb:cc:dd:ee:ff].mac", "dnsRecords[id=nas.lan A 192.168.1.5].ttl", "dnsRecords[id=www.lan CNAME nas.lan]"]
+        );
+        let config = convergence.dns_dhcp.unwrap();
+        assert_eq!(config["dns"]["static_records"].as_array().unwrap().len(), 2);
+        assert_eq!(config["dhcp"]["static_leases"][0]["mac"], json!("aa:bb:cc:dd:ee:ff"));
+        assert!(convergence.reverseproxy.is_none());
+
+        let duplicate = parse("dnsRecords:\n  - {name: a.lan, type: A, value: 10.0.0.1}\n  - {name: A.lan, type: a, value: 10.0.0.1}\n").unwrap();
+        assert!(!converge(&snapshot(), &duplicate).plan.is_valid());
+    }
+
+    #[test]
+    fn unchanged_sections_are_not_applied() {
+        let declaration = parse("dhcpReservations:\n  - {mac: 'AA:BB:CC:DD:EE:FF', ip: 192.168.1.10, hostname: tv}\n").unwrap();
+        let convergence = converge(&snapshot(), &declaration);
+        assert!(convergence.plan.changes.is_empty());
+        assert!(convergence.dns_dhcp.is_none());
+    }
+
+    #[test]
+    fn unknown_applications_are_errors() {
+        let declaration = parse("applications:\n  - {slug: blog, targetPort: 3000}\n").unwrap();
+        let plan = converge(&snapshot(), &declaration).plan;
+        assert_eq!(plan.errors.len(), 1);
+        assert!(plan.errors[0].contains("blog"));
+    }
+}
@@ -0,0 +1,469 @@
+//! Blue/green deployments of production containers.
+//!
+//! The running container (blue) is cloned into the other slot (green:
+//! `<name>-green`, or back to `<name>`), the new binary is installed in the
+//! clone and the clone started with its agent held back, so that it serves
+//! nothing yet. Once the app answers on the green address, the green agent
+//! is started: it connects and publishes its routes, which switches the
+//! proxy to the new address while the old one drains, as after a
+//! migration. The blue agent is then stopped but its container keeps
+//! running as the standby: a rollback is the same switch the other way.
+//! The next deployment replaces the standby.
+//!
+//! Only containers of the local host with a systemd distro (the agent is
+//! held back by leaving its unit disabled in the clone) can be deployed
+//! this way; the others keep the in-place deploy.
+
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+use hr_common::events::{DeploymentPhase, DeploymentProgressEvent};
+use hr_container::{Distro, NspawnClient};
+use hr_registry::protocol::ServiceConfig;
+use hr_registry::types::{Environment, UpdateApplicationRequest};
+use hr_registry::AgentRegistry;
+
+use crate::container_manager::{ContainerManager, ContainerV2Record, ContainerV2Status};
+use crate::routes::applications::APP_SERVICE_UNIT;
+use crate::state::ApiState;
+
+/// Suffix of the second slot of a container.
+const GREEN_SUFFIX: &str = "-green";
+/// How long the clone may take to get an address.
+const ADDRESS_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long the new version may take to answer its health check.
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(120);
+const HEALTH_INTERVAL: Duration = Duration::from_secs(2);
+/// How long the agent of the new container may take to take the routes over.
+const SWITCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Progress of the last deployment (or rollback) of an application.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeploymentState {
+    pub deploy_id: String,
+    pub app_id: String,
+    pub phase: DeploymentPhase,
+    /// Container serving the app once the deployment completes.
+    pub container_name: String,
+    /// Container serving the app when it started.
+    pub previous_container: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub message: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Name of the other slot of `container_name`.
+pub fn other_slot(container_name: &str) -> String {
+    match container_name.strip_suffix(GREEN_SUFFIX) {
+        Some(blue) => blue.to_string(),
+        None => format!("{container_name}{GREEN_SUFFIX}"),
+    }
+}
+
+/// Start deploying `binary` to production app `app_id` in a new container.
+/// Returns the deploy_id; progress is reported as `deployment:progress`
+/// events and by `status`.
+pub async fn start(state: &ApiState, app_id: &str, binary: Vec<u8>) -> Result<String, String> {
+    let (registry, cm) = services(state)?;
+    let record = cm.record(app_id).await.ok_or("Container not found")?;
+    if record.environment != Environment::Production {
+        return Err("Blue/green deployments are for production containers".to_string());
+    }
+    if record.host_id != "local" {
+        return Err("Blue/green deployments are only supported on the local host".to_string());
+    }
+    if record.status != ContainerV2Status::Running {
+        return Err("Container is not running".to_string());
+    }
+    let template = cm.templates.resolve(record.template.as_deref())?;
+    if !matches!(template.distro, Distro::Ubuntu | Distro::Debian) {
+        return Err(format!("Blue/green deployments are not supported on {} containers", template.id));
+    }
+
+    let green = other_slot(&record.container_name);
+    let deploy_id = begin(state, &record, &green, DeploymentPhase::Cloning).await?;
+    info!(app_id, deploy_id, green, "Blue/green deployment started");
+
+    let state = state.clone();
+    let id = deploy_id.clone();
+    tokio::spawn(async move {
+        let result = deploy(&state, &registry, &cm, &record, &green, binary).await;
+        match result {
+            Ok(()) => {
+                report(&state, &record.id, DeploymentPhase::Complete, Some("Nouvelle version en service"), None).await;
+                info!(app_id = record.id, deploy_id = id, green, "Blue/green deployment complete");
+            }
+            Err(e) => {
+                error!(app_id = record.id, deploy_id = id, "Blue/green deployment failed: {e}");
+                // The old container still serves: drop the new one
+                let storage = cm.resolve_storage_path("local").await;
+                let _ = NspawnClient::delete_container(&green, Path::new(&storage)).await;
+                report(&state, &record.id, DeploymentPhase::Failed, None, Some(e)).await;
+            }
+        }
+    });
+    Ok(deploy_id)
+}
+
+/// Switch production app `app_id` back to its standby container.
+pub async fn rollback(state: &ApiState, app_id: &str) -> Result<String, String> {
+    let (registry, cm) = services(state)?;
+    let record = cm.record(app_id).await.ok_or("Container not found")?;
+    let standby = record.standby_container.clone().ok_or("No previous version to roll back to")?;
+    let deploy_id = begin(state, &record, &standby, DeploymentPhase::RollingBack).await?;
+    info!(app_id, deploy_id, standby, "Rolling back to the previous container");
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        let result = async {
+            // The standby does not survive a reboot
+            if !is_running(&standby).await {
+                NspawnClient::start_container(&standby)
+                    .await
+                    .map_err(|e| format!("Failed to start {standby}: {e}"))?;
+            }
+            switch(&registry, &cm, &record, &standby, Some(record.container_name.clone())).await
+        }
+        .await;
+        match result {
+            Ok(()) => report(&state, &record.id, DeploymentPhase::RolledBack, Some("Version precedente en service"), None).await,
+            Err(e) => {
+                error!(app_id = record.id, "Rollback failed: {e}");
+                report(&state, &record.id, DeploymentPhase::Failed, None, Some(e)).await;
+            }
+        }
+    });
+    Ok(deploy_id)
+}
+
+/// Delete the standby container of production app `app_id`, giving up
+/// the rollback.
+pub async fn discard_standby(state: &ApiState, app_id: &str) -> Result<bool, String> {
+    let (_, cm) = services(state)?;
+    let Some(record) = cm.record(app_id).await else {
+        return Err("Container not found".to_string());
+    };
+    let Some(standby) = &record.standby_container else {
+        return Ok(false);
+    };
+    if in_progress(state, app_id).await {
+        return Err("A deployment is in progress".to_string());
+    }
+    let storage = cm.resolve_storage_path("local").await;
+    NspawnClient::delete_container(standby, Path::new(&storage))
+        .await
+        .map_err(|e| format!("Failed to delete {standby}: {e}"))?;
+    cm.set_active_container(app_id, &record.container_name, None).await?;
+    info!(app_id, standby, "Standby container discarded");
+    Ok(true)
+}
+
+/// Last deployment of `app_id` with its standby container, if any.
+pub async fn status(state: &ApiState, app_id: &str) -> serde_json::Value {
+    let deployment = state.deployments.read().await.get(app_id).cloned();
+    let standby = match &state.container_manager {
+        Some(cm) => cm.record(app_id).await.and_then(|r| r.standby_container),
+        None => None,
+    };
+    serde_json::json!({ "deployment": deployment, "standby_container": standby })
+}
+
+fn services(state: &ApiState) -> Result<(Arc<AgentRegistry>, Arc<ContainerManager>), String> {
+    let registry = state.registry.clone().ok_or("Registry not available")?;
+    let cm = state.container_manager.clone().ok_or("Container manager not available")?;
+    Ok((registry, cm))
+}
+
+async fn in_progress(state: &ApiState, app_id: &str) -> bool {
+    state.deployments.read().await.get(app_id).is_some_and(|d| !d.phase.is_finished())
+}
+
+/// Record a new deployment of `record` to `target`, unless one is running.
+async fn begin(
+    state: &ApiState,
+    record: &ContainerV2Record,
+    target: &str,
+    phase: DeploymentPhase,
+) -> Result<String, String> {
+    let mut deployments = state.deployments.write().await;
+    if deployments.get(&record.id).is_some_and(|d| !d.phase.is_finished()) {
+        return Err("A deployment is already in progress".to_string());
+    }
+    let deploy_id = uuid::Uuid::new_v4().to_string();
+    let deployment = DeploymentState {
+        deploy_id: deploy_id.clone(),
+        app_id: record.id.clone(),
+        phase,
+        container_name: target.to_string(),
+        previous_container: record.container_name.clone(),
+        started_at: Utc::now(),
+        finished_at: None,
+        message: None,
+        error: None,
+    };
+    deployments.insert(record.id.clone(), deployment);
+    drop(deployments);
+    send_event(state, &record.id).await;
+    Ok(deploy_id)
+}
+
+/// Move the deployment of `app_id` to `phase` and tell the UI.
+async fn report(state: &ApiState, app_id: &str, phase: DeploymentPhase, message: Option<&str>, error: Option<String>) {
+    {
+        let mut deployments = state.deployments.write().await;
+        let Some(deployment) = deployments.get_mut(app_id) else {
+            return;
+        };
+        deployment.phase = phase;
+        deployment.message = message.map(str::to_string);
+        deployment.error = error;
+        if phase.is_finished() {
+            deployment.finished_at = Some(Utc::now());
+        }
+    }
+    send_event(state, app_id).await;
+}
+
+async fn send_event(state: &ApiState, app_id: &str) {
+    let Some(deployment) = state.deployments.read().await.get(app_id).cloned() else {
+        return;
+    };
+    let _ = state.events.deployment_progress.send(DeploymentProgressEvent {
+        app_id: deployment.app_id,
+        deploy_id: deployment.deploy_id,
+        phase: deployment.phase,
+        container_name: deployment.container_name,
+        message: deployment.message,
+        error: deployment.error,
+    });
+}
+
+async fn deploy(
+    state: &ApiState,
+    registry: &AgentRegistry,
+    cm: &ContainerManager,
+    record: &ContainerV2Record,
+    green: &str,
+    binary: Vec<u8>,
+) -> Result<(), String> {
+    let storage_path = cm.resolve_storage_path("local").await;
+    let storage = Path::new(&storage_path);
+
+    // The standby of the previous deployment is the slot being reused
+    if let Some(standby) = &record.standby_container {
+        NspawnClient::delete_container(standby, storage)
+            .await
+            .map_err(|e| format!("Failed to delete the previous standby {standby}: {e}"))?;
+        cm.set_active_container(&record.id, &record.container_name, None).await?;
+    } else if storage.join(green).exists() {
+        // Leftover of an interrupted deployment
+        let _ = NspawnClient::delete_container(green, storage).await;
+    }
+    NspawnClient::clone_rootfs(&record.container_name, green, storage)
+        .await
+        .map_err(|e| format!("Failed to clone {}: {e}", record.container_name))?;
+
+    report(state, &record.id, DeploymentPhase::Installing, Some("Installation du binaire"), None).await;
+    install(&storage.join(green), &binary).await?;
+    let services = UpdateApplicationRequest {
+        services: Some(ServiceConfig { app: vec!["app.service".to_string()], db: vec![] }),
+        ..Default::default()
+    };
+    if let Err(e) = registry.update_application(&record.id, services).await {
+        warn!(app_id = record.id, "Failed to update prod ServiceConfig: {e}");
+    }
+
+    report(state, &record.id, DeploymentPhase::Starting, Some("Demarrage du nouveau conteneur"), None).await;
+    let network_mode = cm.resolve_network_mode("local").await?;
+    NspawnClient::write_nspawn_unit(green, storage, &network_mode, false)
+        .await
+        .map_err(|e| format!("Failed to write nspawn unit: {e}"))?;
+    NspawnClient::write_network_config(green, storage)
+        .await
+        .map_err(|e| format!("Failed to write network config: {e}"))?;
+    if !record.limits.is_empty()
+        && let Err(e) = NspawnClient::apply_limits(green, storage, &record.limits).await
+    {
+        warn!(container = green, "Failed to apply limits: {e}");
+    }
+    NspawnClient::start_container(green)
+        .await
+        .map_err(|e| format!("Failed to start {green}: {e}"))?;
+    let address = wait_for_address(green).await?;
+
+    let port = app_port(state, registry, &record.id).await?;
+    let check = format!("Verification de {address}:{port}");
+    report(state, &record.id, DeploymentPhase::HealthCheck, Some(check.as_str()), None).await;
+    wait_healthy(address, port).await?;
+
+    report(state, &record.id, DeploymentPhase::Switching, Some("Bascule du trafic"), None).await;
+    switch(registry, cm, record, green, Some(record.container_name.clone())).await
+}
+
+/// Put the binary and its unit in a cloned rootfs, and keep its agent from
+/// starting with the container.
+async fn install(rootfs: &Path, binary: &[u8]) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let app_dir = rootfs.join("opt/app");
+    tokio::fs::create_dir_all(&app_dir)
+        .await
+        .map_err(|e| format!("Failed to create /opt/app: {e}"))?;
+    let app = app_dir.join("app");
+    let _ = tokio::fs::remove_file(&app).await;
+    tokio::fs::write(&app, binary)
+        .await
+        .map_err(|e| format!("Failed to write binary: {e}"))?;
+    tokio::fs::set_permissions(&app, std::fs::Permissions::from_mode(0o755))
+        .await
+        .map_err(|e| format!("Failed to make binary executable: {e}"))?;
+
+    let units = rootfs.join("etc/systemd/system");
+    let wants = units.join("multi-user.target.wants");
+    tokio::fs::create_dir_all(&wants)
+        .await
+        .map_err(|e| format!("Failed to create {}: {e}", wants.display()))?;
+    let unit = units.join("app.service");
+    if !unit.exists() {
+        tokio::fs::write(&unit, APP_SERVICE_UNIT)
+            .await
+            .map_err(|e| format!("Failed to write app.service: {e}"))?;
+    }
+    let enabled = wants.join("app.service");
+    if !enabled.is_symlink() {
+        tokio::fs::symlink("/etc/systemd/system/app.service", &enabled)
+            .await
+            .map_err(|e| format!("Failed to enable app.service: {e}"))?;
+    }
+    // Enabled by `switch` once the new version is healthy
+    let _ = tokio::fs::remove_file(wants.join("hr-agent.service")).await;
+    Ok(())
+}
+
+async fn wait_for_address(container: &str) -> Result<Ipv4Addr, String> {
+    let deadline = tokio::time::Instant::now() + ADDRESS_TIMEOUT;
+    loop {
+        if let Ok(output) = NspawnClient::exec(container, &["ip", "-4", "-o", "addr", "show", "scope", "global"]).await
+            && let Some(address) = parse_ipv4(&output)
+        {
+            return Ok(address);
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(format!("{container} got no address within {}s", ADDRESS_TIMEOUT.as_secs()));
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// First global IPv4 address in `ip -4 -o addr` output.
+fn parse_ipv4(output: &str) -> Option<Ipv4Addr> {
+    output.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        parts.find(|&p| p == "inet")?;
+        let address: Ipv4Addr = parts.next()?.split('/').next()?.parse().ok()?;
+        (!address.is_loopback() && !address.is_link_local()).then_some(address)
+    })
+}
+
+/// Port the app serves on, from the routes its agent published.
+async fn app_port(state: &ApiState, registry: &AgentRegistry, app_id: &str) -> Result<u16, String> {
+    let app = registry.get_application(app_id).await.ok_or("Application not found")?;
+    app.domains(&state.env.base_domain)
+        .iter()
+        .find_map(|domain| state.proxy.get_app_route(domain))
+        .map(|route| route.target_port)
+        .ok_or_else(|| "The app publishes no route to check".to_string())
+}
+
+/// Wait for the app to answer HTTP without a server error.
+async fn wait_healthy(address: Ipv4Addr, port: u16) -> Result<(), String> {
+    let client = reqwest::Client::new();
+    let url = format!("http://{address}:{port}/");
+    let deadline = tokio::time::Instant::now() + HEALTH_TIMEOUT;
+    let mut last_error = String::new();
+    loop {
+        match client.get(&url).timeout(Duration::from_secs(5)).send().await {
+            Ok(response) if !response.status().is_server_error() => return Ok(()),
+            Ok(response) => last_error = format!("HTTP {}", response.status()),
+            Err(e) => last_error = e.to_string(),
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(format!("Health check of {url} failed: {last_error}"));
+        }
+        tokio::time::sleep(HEALTH_INTERVAL).await;
+    }
+}
+
+/// Hand the app over to `target`: start its agent, wait for it to take the
+/// routes, then stop the agent of the current container and keep that
+/// container as `standby`.
+async fn switch(
+    registry: &AgentRegistry,
+    cm: &ContainerManager,
+    record: &ContainerV2Record,
+    target: &str,
+    standby: Option<String>,
+) -> Result<(), String> {
+    let address = wait_for_address(target).await?;
+    NspawnClient::exec(target, &["systemctl", "enable", "--now", "hr-agent"])
+        .await
+        .map_err(|e| format!("Failed to start the agent of {target}: {e}"))?;
+
+    // The agent reports its address on connect, then publishes its routes
+    let deadline = tokio::time::Instant::now() + SWITCH_TIMEOUT;
+    loop {
+        let current = registry.get_application(&record.id).await.and_then(|app| app.ipv4_address);
+        if current == Some(address) {
+            break;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            let _ = NspawnClient::exec(target, &["systemctl", "disable", "--now", "hr-agent"]).await;
+            return Err(format!("The agent of {target} did not connect within {}s", SWITCH_TIMEOUT.as_secs()));
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    // The routes moved: from here on failing would leave the record behind
+    if let Err(e) = cm.set_active_container(&record.id, target, standby).await {
+        error!(app_id = record.id, target, "Failed to record the new container: {e}");
+    }
+    if let Err(e) = NspawnClient::exec(&record.container_name, &["systemctl", "disable", "--now", "hr-agent"]).await {
+        warn!(container = record.container_name, "Failed to stop the previous agent: {e}");
+    }
+    Ok(())
+}
+
+async fn is_running(container: &str) -> bool {
+    NspawnClient::list_containers()
+        .await
+        .is_ok_and(|containers| containers.iter().any(|c| c.name == container))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_other_slot() {
+        assert_eq!(other_slot("hr-v2-blog"), "hr-v2-blog-green");
+        assert_eq!(other_slot("hr-v2-blog-green"), "hr-v2-blog");
+    }
+
+    #[test]
+    fn test_parse_ipv4() {
+        let output = "\
+1: lo    inet 127.0.0.1/8 scope host lo\\       valid_lft forever preferred_lft forever
+2: host0    inet 169.254.12.3/16 metric 2048 brd 169.254.255.255 scope link host0
+2: host0    inet 10.0.0.123/24 metric 1024 brd 10.0.0.255 scope global dynamic host0
+";
+        assert_eq!(parse_ipv4(output), Some(Ipv4Addr::new(10, 0, 0, 123)));
+        assert_eq!(parse_ipv4(""), None);
+    }
+}
@@ -0,0 +1,411 @@
+//! Connectivity doctor: end-to-end checks of what the box needs from the
+//! outside (WAN addresses, upstream DNS, time, Let's Encrypt, the
+//! Cloudflare API, the cloud relay), each failure with a hint on what to
+//! fix. Run on demand from `/api/network/doctor` and by the supervised
+//! `network-doctor` service, which logs and exports status changes.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use hr_common::events::CloudRelayStatus;
+use hr_common::metrics;
+use hr_dns::diagnostics::{self, CheckStatus, UpstreamReport};
+use hr_dns::upstream::UpstreamForwarder;
+use hr_ntp::NtpStatus;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::state::{ApiState, CloudRelayInfo};
+
+const LE_DIRECTORY: &str = "https://acme-v02.api.letsencrypt.org/directory";
+const LE_STAGING_DIRECTORY: &str = "https://acme-staging-v02.api.letsencrypt.org/directory";
+/// Clock offsets (ms) past which the time check warns, then fails
+/// (certificate validation and TOTP start breaking around a second).
+const NTP_WARN_MS: f64 = 100.0;
+const NTP_FAIL_MS: f64 = 1000.0;
+/// Delay before the first scheduled run, so the tunnel and NTP can settle.
+const STARTUP_DELAY: Duration = Duration::from_secs(120);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DoctorConfig {
+    /// Minutes between scheduled runs, 0 = on demand only.
+    #[serde(default = "default_interval")]
+    pub interval_minutes: u32,
+}
+
+fn default_interval() -> u32 {
+    60
+}
+
+impl Default for DoctorConfig {
+    fn default() -> Self {
+        Self { interval_minutes: default_interval() }
+    }
+}
+
+impl DoctorConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interval_minutes != 0 && !(5..=1440).contains(&self.interval_minutes) {
+            return Err("The interval must be 0 (off) or between 5 and 1440 minutes".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// What to look at when the check does not pass.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Finding {
+    fn new(check: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self { check, status, detail: detail.into(), hint: None }
+    }
+
+    fn hint(mut self, hint: &str) -> Self {
+        self.hint = Some(hint.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DoctorReport {
+    /// Worst status of the findings.
+    pub status: CheckStatus,
+    pub ran_at: DateTime<Utc>,
+    pub duration_ms: f64,
+    pub findings: Vec<Finding>,
+}
+
+/// Schedule (doctor.json) and last report of the doctor.
+pub struct Doctor {
+    config: RwLock<DoctorConfig>,
+    path: PathBuf,
+    last: RwLock<Option<DoctorReport>>,
+    /// Serializes runs (scheduler and API).
+    running: tokio::sync::Mutex<()>,
+}
+
+impl Doctor {
+    pub fn new(path: PathBuf) -> Self {
+        let config = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        Self {
+            config: RwLock::new(config),
+            path,
+            last: RwLock::new(None),
+            running: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn config(&self) -> DoctorConfig {
+        *self.config.read().unwrap()
+    }
+
+    pub fn set_config(&self, config: DoctorConfig) -> Result<(), String> {
+        config.validate()?;
+        let content = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content).map_err(|e| e.to_string())?;
+        std::fs::rename(&tmp_path, &self.path).map_err(|e| e.to_string())?;
+        *self.config.write().unwrap() = config;
+        Ok(())
+    }
+
+    pub fn last(&self) -> Option<DoctorReport> {
+        self.last.read().unwrap().clone()
+    }
+
+    /// Keep the report, then log and export what changed since the last one.
+    fn record(&self, report: DoctorReport) {
+        let previous = self.last.write().unwrap().replace(report.clone());
+        let before: HashMap<&str, CheckStatus> = previous
+            .as_ref()
+            .map(|r| r.findings.iter().map(|f| (f.check, f.status)).collect())
+            .unwrap_or_default();
+        let registry = metrics::registry();
+        for finding in &report.findings {
+            let was = before.get(finding.check).copied().unwrap_or(CheckStatus::Pass);
+            if degraded(was, finding.status) {
+                warn!(
+                    "Network doctor: {} is {:?}: {}",
+                    finding.check,
+                    finding.status,
+                    finding.detail
+                );
+            } else if degraded(finding.status, was) {
+                info!("Network doctor: {} recovered", finding.check);
+            }
+            registry
+                .gauge(
+                    "homeroute_doctor_check_status",
+                    "Connectivity doctor check: 0 pass or skipped, 1 warn, 2 fail.",
+                    &[("check", finding.check)],
+                )
+                .set(match finding.status {
+                    CheckStatus::Skipped | CheckStatus::Pass => 0.0,
+                    CheckStatus::Warn => 1.0,
+                    CheckStatus::Fail => 2.0,
+                });
+        }
+    }
+}
+
+/// `now` is a warning or failure that `was` was not.
+fn degraded(was: CheckStatus, now: CheckStatus) -> bool {
+    now >= CheckStatus::Warn && now > was
+}
+
+fn http_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder().timeout(HTTP_TIMEOUT).build().map_err(|e| e.to_string())
+}
+
+async fn wan_findings(state: &ApiState, http: &reqwest::Client) -> [Finding; 2] {
+    let ddns = state.ddns.config();
+    let (v4, v6) = tokio::join!(
+        hr_ddns::detect::web_ipv4(http, &ddns.ipv4.url),
+        hr_ddns::detect::web_ipv6(http, &ddns.ipv6.url),
+    );
+    let v4 = match v4 {
+        Ok(ip) => Finding::new("wan_ipv4", CheckStatus::Pass, format!("Reachable as {}", ip)),
+        Err(e) => Finding::new("wan_ipv4", CheckStatus::Fail, e)
+            .hint("Check the WAN link, the default IPv4 route and the ISP box"),
+    };
+    let v6 = match v6 {
+        Ok(ip) => Finding::new("wan_ipv6", CheckStatus::Pass, format!("Reachable as {}", ip)),
+        Err(e) => Finding::new("wan_ipv6", CheckStatus::Warn, e)
+            .hint("Check the IPv6 default route and the prefix delegation (DHCPv6-PD) from the ISP"),
+    };
+    [v4, v6]
+}
+
+fn dns_finding(upstreams: &[UpstreamReport]) -> Finding {
+    if upstreams.is_empty() {
+        return Finding::new("dns", CheckStatus::Fail, "No upstream DNS server configured")
+            .hint("Add upstream servers in the DNS settings");
+    }
+    let check = diagnostics::upstream_check(upstreams);
+    let finding = Finding::new("dns", check.status, check.detail);
+    match check.status {
+        CheckStatus::Fail => finding.hint("Upstream DNS is unreachable: check the WAN link and outbound port 53"),
+        CheckStatus::Warn => finding.hint("Replace or remove the upstreams that fail (see the DNS diagnostics)"),
+        _ => finding,
+    }
+}
+
+fn ntp_finding(status: &NtpStatus) -> Finding {
+    if !status.running {
+        return Finding::new("ntp", CheckStatus::Skipped, "NTP service not running");
+    }
+    let Some(offset) = status.offset_ms.filter(|_| status.synced) else {
+        let detail = status.last_error.clone().unwrap_or_else(|| "Not synchronized".to_string());
+        return Finding::new("ntp", CheckStatus::Warn, detail)
+            .hint("Check that outbound UDP 123 is allowed and the NTP upstreams resolve");
+    };
+    let source = status.source.as_deref().unwrap_or("upstream");
+    let detail = format!("Offset {:.1} ms from {}", offset, source);
+    if offset.abs() > NTP_FAIL_MS {
+        Finding::new("ntp", CheckStatus::Fail, detail).hint("The clock is off by more than a second: TLS and TOTP may fail")
+    } else if offset.abs() > NTP_WARN_MS {
+        Finding::new("ntp", CheckStatus::Warn, detail).hint("The clock drifts: check the NTP upstreams")
+    } else {
+        Finding::new("ntp", CheckStatus::Pass, detail)
+    }
+}
+
+async fn acme_finding(state: &ApiState, http: &reqwest::Client) -> Finding {
+    let url = if state.env.acme_staging { LE_STAGING_DIRECTORY } else { LE_DIRECTORY };
+    let start = Instant::now();
+    let result = async {
+        let resp = http.get(url).send().await.map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("HTTP {}", resp.status()));
+        }
+        let body: serde_json::Value = resp.json().await.map_err(|e| e.to_string())?;
+        body.get("newOrder").map(|_| ()).ok_or_else(|| "Not an ACME directory".to_string())
+    }
+    .await;
+    match result {
+        Ok(()) => Finding::new(
+            "acme",
+            CheckStatus::Pass,
+            format!("Let's Encrypt directory answered in {} ms", start.elapsed().as_millis()),
+        ),
+        Err(e) => Finding::new("acme", CheckStatus::Fail, format!("{}: {}", url, e))
+            .hint("Certificates cannot be renewed: check outbound HTTPS and DNS"),
+    }
+}
+
+async fn cloudflare_finding(state: &ApiState) -> Finding {
+    let (Some(token), Some(zone_id)) = (&state.env.cf_api_token, &state.env.cf_zone_id) else {
+        return Finding::new("cloudflare", CheckStatus::Skipped, "No Cloudflare token configured");
+    };
+    match hr_registry::cloudflare::verify_token(token, zone_id).await {
+        Ok(()) => Finding::new("cloudflare", CheckStatus::Pass, "Token active with access to the zone"),
+        Err(e) => Finding::new("cloudflare", CheckStatus::Fail, e)
+            .hint("Create a new API token with Zone:DNS:Edit on the zone and update CF_API_TOKEN"),
+    }
+}
+
+/// The tunnel client does the QUIC handshake and keepalive probes; its
+/// live state is the answer.
+fn relay_finding(enabled: bool, info: Option<&CloudRelayInfo>) -> Finding {
+    if !enabled {
+        return Finding::new("cloud_relay", CheckStatus::Skipped, "Cloud relay disabled");
+    }
+    match info {
+        Some(info) if info.status == CloudRelayStatus::Connected => {
+            let host = info.relay_host.as_deref().unwrap_or("relay");
+            let detail = match info.latency_ms {
+                Some(ms) => format!("QUIC tunnel to {} up, {} ms", host, ms),
+                None => format!("QUIC tunnel to {} up", host),
+            };
+            Finding::new("cloud_relay", CheckStatus::Pass, detail)
+        }
+        Some(info) => Finding::new("cloud_relay", CheckStatus::Fail, format!("Tunnel {}", info.status))
+            .hint("Check that the VPS is up and its firewall allows the tunnel's UDP port"),
+        None => Finding::new("cloud_relay", CheckStatus::Fail, "Tunnel never connected")
+            .hint("Check the relay configuration and that the VPS was bootstrapped"),
+    }
+}
+
+/// Run every check and keep the report.
+pub async fn run(state: &ApiState) -> DoctorReport {
+    let _running = state.doctor.running.lock().await;
+    let ran_at = Utc::now();
+    let start = Instant::now();
+
+    let forwarder = {
+        let dns = state.dns.read().await;
+        UpstreamForwarder::new(dns.config.upstream_servers.clone(), dns.config.upstream_timeout_ms)
+    };
+    let mut findings = Vec::new();
+    match http_client() {
+        Ok(http) => {
+            let (wan, upstreams, acme, cloudflare) = tokio::join!(
+                wan_findings(state, &http),
+                diagnostics::probe_upstreams(Arc::new(forwarder)),
+                acme_finding(state, &http),
+                cloudflare_finding(state),
+            );
+            findings.extend(wan);
+            findings.push(dns_finding(&upstreams));
+            findings.push(ntp_finding(&state.ntp.status()));
+            findings.push(acme);
+            findings.push(cloudflare);
+        }
+        Err(e) => findings.push(Finding::new("http", CheckStatus::Fail, e)),
+    }
+    let enabled = *state.cloud_relay_enabled.borrow();
+    findings.push(relay_finding(enabled, state.cloud_relay_status.read().await.as_ref()));
+
+    let report = DoctorReport {
+        status: findings.iter().map(|f| f.status).max().unwrap_or(CheckStatus::Skipped),
+        ran_at,
+        duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+        findings,
+    };
+    state.doctor.record(report.clone());
+    report
+}
+
+/// Run the doctor every `interval_minutes` (config read each minute).
+pub async fn run_doctor_scheduler(state: ApiState) -> anyhow::Result<()> {
+    tokio::time::sleep(STARTUP_DELAY).await;
+    let mut tick = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        tick.tick().await;
+        let interval = state.doctor.config().interval_minutes;
+        if interval == 0 {
+            continue;
+        }
+        let due = state
+            .doctor
+            .last()
+            .is_none_or(|r| Utc::now() - r.ran_at >= chrono::Duration::minutes(interval as i64));
+        if due {
+            let report = run(&state).await;
+            info!(status = ?report.status, "Network doctor run in {:.0} ms", report.duration_ms);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ntp(synced: bool, offset_ms: Option<f64>) -> NtpStatus {
+        NtpStatus {
+            running: true,
+            synced,
+            stratum: if synced { 3 } else { 16 },
+            offset_ms,
+            delay_ms: None,
+            source: Some("pool.ntp.org".to_string()),
+            last_sync: None,
+            upstreams: Vec::new(),
+            served: 0,
+            last_error: None,
+        }
+    }
+
+    #[test]
+    fn test_ntp_finding() {
+        assert_eq!(ntp_finding(&NtpStatus { running: false, ..ntp(false, None) }).status, CheckStatus::Skipped);
+        assert_eq!(ntp_finding(&ntp(false, Some(2.0))).status, CheckStatus::Warn);
+        assert_eq!(ntp_finding(&ntp(true, Some(-3.5))).status, CheckStatus::Pass);
+        assert_eq!(ntp_finding(&ntp(true, Some(250.0))).status, CheckStatus::Warn);
+        let fail = ntp_finding(&ntp(true, Some(-1500.0)));
+        assert_eq!(fail.status, CheckStatus::Fail);
+        assert!(fail.hint.is_some());
+    }
+
+    #[test]
+    fn test_relay_finding() {
+        let info = |status| CloudRelayInfo {
+            status,
+            vps_ipv4: None,
+            relay_host: Some("relay.example.com".to_string()),
+            latency_ms: Some(18),
+            active_streams: None,
+            relay_cert_sha256: None,
+        };
+        assert_eq!(relay_finding(false, None).status, CheckStatus::Skipped);
+        assert_eq!(relay_finding(true, None).status, CheckStatus::Fail);
+        let up = relay_finding(true, Some(&info(CloudRelayStatus::Connected)));
+        assert_eq!(up.status, CheckStatus::Pass);
+        assert!(up.detail.contains("18 ms"));
+        assert_eq!(relay_finding(true, Some(&info(CloudRelayStatus::Reconnecting))).status, CheckStatus::Fail);
+    }
+
+    #[test]
+    fn test_degraded() {
+        assert!(degraded(CheckStatus::Pass, CheckStatus::Warn));
+        assert!(degraded(CheckStatus::Warn, CheckStatus::Fail));
+        assert!(!degraded(CheckStatus::Fail, CheckStatus::Fail));
+        assert!(!degraded(CheckStatus::Fail, CheckStatus::Pass));
+        assert!(!degraded(CheckStatus::Pass, CheckStatus::Skipped));
+    }
+
+    #[test]
+    fn test_config_validate() {
+        assert!(DoctorConfig::default().validate().is_ok());
+        assert!(DoctorConfig { interval_minutes: 0 }.validate().is_ok());
+        assert!(DoctorConfig { interval_minutes: 2 }.validate().is_err());
+        let parsed: DoctorConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(parsed.interval_minutes, 60);
+    }
+}
@@ -0,0 +1,243 @@
+//! Host maintenance (drain mode).
+//!
+//! Draining a host marks it in hosts.json, so that no container is created
+//! on it or migrated to it, then empties it one container at a time: each
+//! one migrates to the target host when one is given, and is stopped
+//! otherwise (or when its migration fails), its proxy routes removed
+//! first. The containers the drain stopped are remembered in hosts.json
+//! and started again when the host is undrained.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+use hr_common::events::MigrationPhase;
+
+use crate::container_manager::{ContainerManager, ContainerV2Record, ContainerV2Status};
+use crate::routes::hosts::{add_drain_stopped, host_draining, set_host_draining};
+use crate::state::ApiState;
+
+/// How often a drain checks on the migration it waits for.
+const MIGRATION_POLL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrainAction {
+    Migrate,
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrainAppStatus {
+    Pending,
+    InProgress,
+    Done,
+    Failed,
+}
+
+/// A container of a draining host.
+#[derive(Debug, Clone, Serialize)]
+pub struct DrainedApp {
+    pub app_id: String,
+    pub container_name: String,
+    pub action: DrainAction,
+    pub status: DrainAppStatus,
+    pub error: Option<String>,
+}
+
+/// Progress of the last drain of a host.
+#[derive(Debug, Clone, Serialize)]
+pub struct DrainState {
+    pub host_id: String,
+    /// Host receiving the containers; None stops them.
+    pub target_host_id: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub apps: Vec<DrainedApp>,
+}
+
+/// Put `host_id` in maintenance and move (or stop) its containers in the
+/// background. Returns the initial state; `status` follows it.
+pub async fn start(state: &ApiState, host_id: &str, target_host_id: Option<String>) -> Result<DrainState, String> {
+    let cm = state.container_manager.clone().ok_or("Container manager not available")?;
+    if host_id == "local" {
+        return Err("The local host cannot be drained".to_string());
+    }
+    if let Some(target) = &target_host_id {
+        if target == host_id {
+            return Err("Target host is the drained host".to_string());
+        }
+        if host_draining(target).await {
+            return Err(format!("Target host {target} is draining too"));
+        }
+    }
+    if state.drains.read().await.get(host_id).is_some_and(|d| d.finished_at.is_none()) {
+        return Err("A drain of this host is already running".to_string());
+    }
+
+    let apps: Vec<DrainedApp> = cm
+        .records()
+        .await
+        .into_iter()
+        .filter(|r| r.host_id == host_id)
+        .map(|r| DrainedApp {
+            app_id: r.id,
+            container_name: r.container_name,
+            action: if target_host_id.is_some() { DrainAction::Migrate } else { DrainAction::Stop },
+            status: DrainAppStatus::Pending,
+            error: None,
+        })
+        .collect();
+    set_host_draining(host_id, true).await?;
+
+    let drain = DrainState {
+        host_id: host_id.to_string(),
+        target_host_id,
+        started_at: Utc::now(),
+        finished_at: None,
+        apps,
+    };
+    state.drains.write().await.insert(host_id.to_string(), drain.clone());
+    info!(host_id, apps = drain.apps.len(), target = ?drain.target_host_id, "Draining host");
+
+    let state = state.clone();
+    let host_id = host_id.to_string();
+    tokio::spawn(async move {
+        run(&state, &cm, &host_id).await;
+    });
+    Ok(drain)
+}
+
+/// Take `host_id` out of maintenance and start again the containers its
+/// drain stopped. Returns the errors of those that did not start.
+pub async fn undrain(state: &ApiState, host_id: &str) -> Result<Vec<String>, String> {
+    if state.drains.read().await.get(host_id).is_some_and(|d| d.finished_at.is_none()) {
+        return Err("The drain of this host is still running".to_string());
+    }
+    let stopped = set_host_draining(host_id, false).await?;
+    let mut errors = Vec::new();
+    if let Some(cm) = &state.container_manager {
+        for app_id in stopped {
+            // Only the containers still there (not removed or moved since)
+            let Some(record) = cm.record(&app_id).await.filter(|r| r.host_id == host_id) else {
+                continue;
+            };
+            if let Err(e) = cm.start_container(&app_id).await {
+                warn!(host_id, app_id, "Failed to restart drained container: {e}");
+                errors.push(format!("{}: {e}", record.container_name));
+            }
+        }
+    }
+    info!(host_id, "Host undrained");
+    Ok(errors)
+}
+
+/// Last drain of `host_id`.
+pub async fn status(state: &ApiState, host_id: &str) -> Option<DrainState> {
+    state.drains.read().await.get(host_id).cloned()
+}
+
+async fn run(state: &ApiState, cm: &Arc<ContainerManager>, host_id: &str) {
+    let Some(drain) = status(state, host_id).await else {
+        return;
+    };
+    for app in &drain.apps {
+        set_app(state, host_id, &app.app_id, |a| a.status = DrainAppStatus::InProgress).await;
+        let Some(record) = cm.record(&app.app_id).await.filter(|r| r.host_id == host_id) else {
+            // Removed or moved away meanwhile
+            set_app(state, host_id, &app.app_id, |a| a.status = DrainAppStatus::Done).await;
+            continue;
+        };
+
+        let mut migrate_error = None;
+        if let Some(target) = &drain.target_host_id {
+            match migrate(state, cm, &record.id, target).await {
+                Ok(()) => {
+                    set_app(state, host_id, &app.app_id, |a| a.status = DrainAppStatus::Done).await;
+                    continue;
+                }
+                Err(e) => {
+                    warn!(host_id, app_id = %record.id, "Drain migration failed, stopping the container: {e}");
+                    migrate_error = Some(e);
+                }
+            }
+        }
+
+        let result = stop(state, cm, &record).await;
+        set_app(state, host_id, &app.app_id, |a| {
+            a.action = DrainAction::Stop;
+            match result {
+                Ok(()) => {
+                    a.status = DrainAppStatus::Done;
+                    a.error = migrate_error.map(|e| format!("Migration failed: {e}"));
+                }
+                Err(e) => {
+                    a.status = DrainAppStatus::Failed;
+                    a.error = Some(e);
+                }
+            }
+        })
+        .await;
+    }
+
+    let failed = {
+        let mut drains = state.drains.write().await;
+        let Some(drain) = drains.get_mut(host_id) else {
+            return;
+        };
+        drain.finished_at = Some(Utc::now());
+        drain.apps.iter().filter(|a| a.status == DrainAppStatus::Failed).count()
+    };
+    if failed > 0 {
+        error!(host_id, failed, "Host drain finished with containers still running");
+    } else {
+        info!(host_id, "Host drained");
+    }
+}
+
+/// Migrate a container and wait for the migration to end.
+async fn migrate(state: &ApiState, cm: &Arc<ContainerManager>, app_id: &str, target: &str) -> Result<(), String> {
+    let transfer_id = cm.migrate_container(app_id, target, None, &state.migrations).await?;
+    loop {
+        tokio::time::sleep(MIGRATION_POLL).await;
+        let migrations = state.migrations.read().await;
+        let migration = migrations.get(&transfer_id).ok_or("Migration state lost")?;
+        match migration.phase {
+            MigrationPhase::Complete => return Ok(()),
+            MigrationPhase::Failed => {
+                return Err(migration.error.clone().unwrap_or_else(|| "Migration failed".to_string()));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Remove the proxy routes of a container, then stop it.
+async fn stop(state: &ApiState, cm: &ContainerManager, record: &ContainerV2Record) -> Result<(), String> {
+    if let Some(registry) = &state.registry
+        && let Some(app) = registry.get_application(&record.id).await
+    {
+        for domain in app.domains(&state.env.base_domain) {
+            state.proxy.remove_app_route(&domain);
+        }
+    }
+    if record.status != ContainerV2Status::Running {
+        return Ok(());
+    }
+    cm.stop_container(&record.id).await?;
+    add_drain_stopped(&record.host_id, &record.id).await
+}
+
+async fn set_app(state: &ApiState, host_id: &str, app_id: &str, update: impl FnOnce(&mut DrainedApp)) {
+    let mut drains = state.drains.write().await;
+    if let Some(app) = drains
+        .get_mut(host_id)
+        .and_then(|d| d.apps.iter_mut().find(|a| a.app_id == app_id))
+    {
+        update(app);
+    }
+}
@@ -0,0 +1,140 @@
+//! Agent enrollment with one-time join codes.
+//!
+//! Instead of HomeRoute installing an agent over SSH, an admin issues a
+//! join code and installs the agent with it. On its first connection the
+//! agent sends `Enroll` with the code in place of `Auth`, and gets back its
+//! permanent token (a host is added to hosts.json at that point). A code
+//! works once and expires after a few minutes; codes live in memory only,
+//! so a restart invalidates them.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+/// Unambiguous characters of a code (no 0/O, 1/I/L).
+const ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+const CODE_LEN: usize = 12;
+
+pub const DEFAULT_TTL: Duration = Duration::from_secs(15 * 60);
+pub const MAX_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// What an agent enrolling with a code becomes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JoinTarget {
+    /// A new host; `name` overrides the one the agent asks for.
+    Host {
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        groups: Vec<String>,
+    },
+    /// The agent of an existing application.
+    App { app_id: String },
+}
+
+/// A join code waiting for its agent.
+#[derive(Debug, Clone, Serialize)]
+pub struct JoinCode {
+    pub code: String,
+    pub target: JoinTarget,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Join codes keyed by their normalized form.
+pub type JoinCodes = HashMap<String, JoinCode>;
+
+/// Issue a code for `target`, valid for `ttl` (capped at `MAX_TTL`).
+pub fn issue(codes: &mut JoinCodes, target: JoinTarget, ttl: Duration) -> Result<JoinCode, String> {
+    let now = Utc::now();
+    purge(codes, now);
+    let ttl = chrono::Duration::from_std(ttl.min(MAX_TTL)).map_err(|e| e.to_string())?;
+    let code = generate()?;
+    let join = JoinCode { code: format_code(&code), target, created_at: now, expires_at: now + ttl };
+    codes.insert(code, join.clone());
+    Ok(join)
+}
+
+/// Take the code `code` (any case, with or without dashes). It cannot be
+/// used again, whether it succeeds or not.
+pub fn redeem(codes: &mut JoinCodes, code: &str, now: DateTime<Utc>) -> Result<JoinTarget, String> {
+    purge(codes, now);
+    codes.remove(&normalize(code)).map(|j| j.target).ok_or_else(|| "Invalid or expired join code".to_string())
+}
+
+/// Withdraw a code before it is used.
+pub fn revoke(codes: &mut JoinCodes, code: &str) -> bool {
+    codes.remove(&normalize(code)).is_some()
+}
+
+/// Codes still valid, oldest first.
+pub fn pending(codes: &mut JoinCodes) -> Vec<JoinCode> {
+    purge(codes, Utc::now());
+    let mut list: Vec<JoinCode> = codes.values().cloned().collect();
+    list.sort_by_key(|j| j.created_at);
+    list
+}
+
+fn purge(codes: &mut JoinCodes, now: DateTime<Utc>) {
+    codes.retain(|_, j| j.expires_at > now);
+}
+
+fn generate() -> Result<String, String> {
+    let mut bytes = [0u8; CODE_LEN];
+    SystemRandom::new().fill(&mut bytes).map_err(|_| "Random generator failed".to_string())?;
+    // 256 is not a multiple of the alphabet size: the bias is negligible here
+    Ok(bytes.iter().map(|b| ALPHABET[*b as usize % ALPHABET.len()] as char).collect())
+}
+
+/// `ABCDEFGHJKMN` → `ABCD-EFGH-JKMN`.
+fn format_code(code: &str) -> String {
+    code.as_bytes()
+        .chunks(4)
+        .map(|c| String::from_utf8_lossy(c).into_owned())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn normalize(code: &str) -> String {
+    code.chars().filter(|c| c.is_ascii_alphanumeric()).map(|c| c.to_ascii_uppercase()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host() -> JoinTarget {
+        JoinTarget::Host { name: Some("nas".into()), groups: Vec::new() }
+    }
+
+    #[test]
+    fn test_redeem_once() {
+        let mut codes = JoinCodes::new();
+        let join = issue(&mut codes, host(), DEFAULT_TTL).unwrap();
+        assert_eq!(join.code.len(), CODE_LEN + 2);
+        assert!(join.code.chars().all(|c| c == '-' || ALPHABET.contains(&(c as u8))));
+
+        let typed = join.code.to_lowercase().replace('-', " ");
+        assert_eq!(redeem(&mut codes, &typed, Utc::now()), Ok(host()));
+        assert!(redeem(&mut codes, &join.code, Utc::now()).is_err());
+    }
+
+    #[test]
+    fn test_expiry_and_revoke() {
+        let mut codes = JoinCodes::new();
+        let join = issue(&mut codes, host(), Duration::from_secs(60)).unwrap();
+        let later = Utc::now() + chrono::Duration::minutes(2);
+        assert!(redeem(&mut codes, &join.code, later).is_err());
+        assert!(codes.is_empty());
+
+        let join = issue(&mut codes, JoinTarget::App { app_id: "a1".into() }, MAX_TTL * 2).unwrap();
+        assert!(join.expires_at <= Utc::now() + chrono::Duration::hours(24));
+        assert_eq!(pending(&mut codes).len(), 1);
+        assert!(revoke(&mut codes, &join.code));
+        assert!(pending(&mut codes).is_empty());
+    }
+}
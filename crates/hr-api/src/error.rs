@@ -0,0 +1,171 @@
+//! Error envelope of the API: `{"success": false, "code": "...", "error":
+//! "..."}` with the matching HTTP status. `code` is stable and meant for
+//! clients, `error` is the message shown to the user.
+
+use std::fmt::Display;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Malformed request: unreadable body, unknown action.
+    BadRequest,
+    /// No valid session.
+    Unauthorized,
+    /// The session lacks the required group.
+    Forbidden,
+    NotFound,
+    /// Not possible in the current state (nothing staged, service stopped…).
+    Conflict,
+    /// The content was understood but failed validation.
+    Invalid,
+    /// A component needed by the request is not running.
+    Unavailable,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::Invalid => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::BAD_REQUEST => ErrorCode::BadRequest,
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::CONFLICT => ErrorCode::Conflict,
+            StatusCode::UNPROCESSABLE_ENTITY => ErrorCode::Invalid,
+            StatusCode::SERVICE_UNAVAILABLE => ErrorCode::Unavailable,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiError {
+    /// Always `false`.
+    pub success: bool,
+    pub code: ErrorCode,
+    pub error: String,
+    /// Every problem found, when validation fails on several.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, error: impl Display) -> Self {
+        ApiError {
+            success: false,
+            code,
+            error: error.to_string(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Validation failure listing every error.
+    pub fn invalid(errors: Vec<String>) -> Self {
+        ApiError {
+            error: errors.first().cloned().unwrap_or_else(|| "Invalid request".to_string()),
+            errors,
+            ..ApiError::new(ErrorCode::Invalid, "")
+        }
+    }
+
+    pub fn bad_request(error: impl Display) -> Self {
+        ApiError::new(ErrorCode::BadRequest, error)
+    }
+
+    pub fn not_found(error: impl Display) -> Self {
+        ApiError::new(ErrorCode::NotFound, error)
+    }
+
+    pub fn conflict(error: impl Display) -> Self {
+        ApiError::new(ErrorCode::Conflict, error)
+    }
+
+    pub fn unavailable(error: impl Display) -> Self {
+        ApiError::new(ErrorCode::Unavailable, error)
+    }
+
+    pub fn internal(error: impl Display) -> Self {
+        ApiError::new(ErrorCode::Internal, error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.code.status(), Json(self)).into_response()
+    }
+}
+
+/// Errors of the session checks (`session_user`, `admin_user`).
+impl From<(StatusCode, Json<Value>)> for ApiError {
+    fn from((status, Json(body)): (StatusCode, Json<Value>)) -> Self {
+        ApiError::new(
+            ErrorCode::from_status(status),
+            body.get("error").and_then(|e| e.as_str()).unwrap_or_default(),
+        )
+    }
+}
+
+/// Body of a successful request with nothing else to return.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Ack {
+    pub success: bool,
+}
+
+impl Default for Ack {
+    fn default() -> Self {
+        Ack { success: true }
+    }
+}
+
+pub type ApiResult<T> = Result<Json<T>, ApiError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn envelope() {
+        let error = ApiError::not_found("Unknown service: foo");
+        assert_eq!(error.code.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({"success": false, "code": "not_found", "error": "Unknown service: foo"})
+        );
+
+        let error = ApiError::invalid(vec!["a".into(), "b".into()]);
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({"success": false, "code": "invalid", "error": "a", "errors": ["a", "b"]})
+        );
+    }
+
+    #[test]
+    fn from_session_error() {
+        let error: ApiError = (StatusCode::FORBIDDEN, Json(json!({"success": false, "error": "Reserve aux administrateurs"}))).into();
+        assert_eq!(error.code, ErrorCode::Forbidden);
+        assert_eq!(error.error, "Reserve aux administrateurs");
+    }
+}
@@ -0,0 +1,442 @@
+//! Websocket fan-out: the events of the bus, and the changes of the state
+//! that has no event (services, DHCP leases, DNS stats), are turned once
+//! into the messages sent to the clients and kept in the event journal,
+//! by topic. `routes::ws` then only filters and replays the journal.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use hr_common::events::{EventJournal, MigrationPhase, UpdateEvent};
+use hr_common::selfmon;
+use serde_json::{json, Value};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::state::ApiState;
+
+/// Topics a client can subscribe to.
+pub const TOPICS: &[&str] = &[
+    "hosts",
+    "services",
+    "dhcp.leases",
+    "dns.stats",
+    "certs",
+    "migrations",
+    "agents",
+    "updates",
+    "dataverse",
+    "cloud_relay",
+    "proxy",
+    "auth",
+    "pubsub",
+    "ddns",
+    "network",
+    "adblock",
+];
+
+const SERVICES_INTERVAL: Duration = Duration::from_secs(2);
+const LEASES_INTERVAL: Duration = Duration::from_secs(5);
+const DNS_STATS_INTERVAL: Duration = Duration::from_secs(10);
+
+fn received<T>(result: Result<T, RecvError>, channel: &'static str) -> anyhow::Result<Option<T>> {
+    match result {
+        Ok(event) => Ok(Some(event)),
+        Err(RecvError::Lagged(n)) => {
+            selfmon::record_lag(channel, n);
+            Ok(None)
+        }
+        Err(RecvError::Closed) => anyhow::bail!("Event channel {} closed", channel),
+    }
+}
+
+pub async fn run_event_fanout(state: ApiState) -> anyhow::Result<()> {
+    let events = state.events.clone();
+    let journal = &events.journal;
+
+    let mut host_rx = events.host_status.subscribe();
+    let mut updates_rx = events.updates.subscribe();
+    let mut agent_rx = events.agent_status.subscribe();
+    let mut metrics_rx = events.agent_metrics.subscribe();
+    let mut service_cmd_rx = events.service_command.subscribe();
+    let mut agent_update_rx = events.agent_update.subscribe();
+    let mut migration_rx = events.migration_progress.subscribe();
+    let mut deployment_rx = events.deployment_progress.subscribe();
+    let mut dv_schema_rx = events.dataverse_schema.subscribe();
+    let mut dv_data_rx = events.dataverse_data.subscribe();
+    let mut host_metrics_rx = events.host_metrics.subscribe();
+    let mut host_power_rx = events.host_power.subscribe();
+    let mut cloud_relay_rx = events.cloud_relay.subscribe();
+    let mut cert_rx = events.cert_ready.subscribe();
+    let mut backend_health_rx = events.backend_health.subscribe();
+    let mut auth_security_rx = events.auth_security.subscribe();
+    let mut pubsub_rx = events.pubsub.subscribe();
+    let mut ddns_rx = events.ddns.subscribe();
+    let mut network_device_rx = events.network_device.subscribe();
+    let mut adblock_rx = events.adblock.subscribe();
+    let mut disk_health_rx = events.disk_health.subscribe();
+    let mut host_upgrade_rx = events.host_upgrade.subscribe();
+
+    let mut services_tick = tokio::time::interval(SERVICES_INTERVAL);
+    let mut leases_tick = tokio::time::interval(LEASES_INTERVAL);
+    let mut dns_stats_tick = tokio::time::interval(DNS_STATS_INTERVAL);
+    // The first diff only takes the current state
+    let mut services = services_state(&state).await;
+    let mut leases = leases_state(&state).await;
+
+    loop {
+        tokio::select! {
+            result = host_rx.recv() => if let Some(event) = received(result, "host_status")? {
+                journal.publish("hosts", json!({
+                    "type": "hosts:status",
+                    "data": {
+                        "hostId": event.host_id,
+                        "online": event.status == "online",
+                        "status": event.status,
+                        "latency": event.latency_ms.unwrap_or(0),
+                        "lastSeen": chrono::Utc::now().to_rfc3339()
+                    }
+                }));
+            },
+            result = host_metrics_rx.recv() => if let Some(event) = received(result, "host_metrics")? {
+                journal.publish("hosts", json!({
+                    "type": "hosts:metrics",
+                    "data": {
+                        "hostId": event.host_id,
+                        "cpuPercent": event.cpu_percent,
+                        "memoryUsedBytes": event.memory_used_bytes,
+                        "memoryTotalBytes": event.memory_total_bytes,
+                    }
+                }));
+            },
+            result = host_power_rx.recv() => if let Some(event) = received(result, "host_power")? {
+                journal.publish("hosts", json!({
+                    "type": "hosts:power",
+                    "data": {
+                        "hostId": event.host_id,
+                        "state": event.state,
+                        "message": event.message,
+                    }
+                }));
+            },
+            result = disk_health_rx.recv() => if let Some(event) = received(result, "disk_health")? {
+                journal.publish("hosts", json!({"type": "host:disk_health", "data": event}));
+            },
+            result = host_upgrade_rx.recv() => if let Some(event) = received(result, "host_upgrade")? {
+                journal.publish("hosts", json!({"type": "host:upgrade", "data": event}));
+            },
+            result = updates_rx.recv() => if let Some(event) = received(result, "updates")? {
+                journal.publish("updates", update_message(event));
+            },
+            result = agent_rx.recv() => if let Some(event) = received(result, "agent_status")? {
+                let mut data = json!({
+                    "appId": event.app_id,
+                    "slug": event.slug,
+                    "status": event.status
+                });
+                if let Some(message) = &event.message {
+                    data["message"] = json!(message);
+                }
+                journal.publish("agents", json!({"type": "agent:status", "data": data}));
+            },
+            result = metrics_rx.recv() => if let Some(event) = received(result, "agent_metrics")? {
+                journal.publish("agents", json!({
+                    "type": "agent:metrics",
+                    "data": {
+                        "appId": event.app_id,
+                        "codeServerStatus": event.code_server_status,
+                        "appStatus": event.app_status,
+                        "dbStatus": event.db_status,
+                        "memoryBytes": event.memory_bytes,
+                        "cpuPercent": event.cpu_percent,
+                        "codeServerIdleSecs": event.code_server_idle_secs,
+                    }
+                }));
+            },
+            result = service_cmd_rx.recv() => if let Some(event) = received(result, "service_command")? {
+                journal.publish("agents", json!({
+                    "type": "agent:service-command",
+                    "data": {
+                        "appId": event.app_id,
+                        "serviceType": event.service_type,
+                        "action": event.action,
+                        "success": event.success,
+                    }
+                }));
+            },
+            result = agent_update_rx.recv() => if let Some(event) = received(result, "agent_update")? {
+                journal.publish("agents", json!({
+                    "type": "agent:update",
+                    "data": {
+                        "appId": event.app_id,
+                        "slug": event.slug,
+                        "status": format!("{:?}", event.status).to_lowercase(),
+                        "version": event.version,
+                        "error": event.error,
+                    }
+                }));
+            },
+            result = migration_rx.recv() => if let Some(event) = received(result, "migration_progress")? {
+                journal.publish("migrations", json!({
+                    "type": "migration:progress",
+                    "data": {
+                        "appId": event.app_id,
+                        "transferId": event.transfer_id,
+                        "phase": event.phase,
+                        "progressPct": event.progress_pct,
+                        "bytesTransferred": event.bytes_transferred,
+                        "totalBytes": event.total_bytes,
+                        "error": event.error,
+                    }
+                }));
+            },
+            result = deployment_rx.recv() => if let Some(event) = received(result, "deployment_progress")? {
+                journal.publish("migrations", json!({
+                    "type": "deployment:progress",
+                    "data": {
+                        "appId": event.app_id,
+                        "deployId": event.deploy_id,
+                        "phase": event.phase,
+                        "containerName": event.container_name,
+                        "message": event.message,
+                        "error": event.error,
+                    }
+                }));
+            },
+            result = dv_schema_rx.recv() => if let Some(event) = received(result, "dataverse_schema")? {
+                journal.publish("dataverse", json!({
+                    "type": "dataverse:schema",
+                    "data": {
+                        "appId": event.app_id,
+                        "slug": event.slug,
+                        "tables": event.tables,
+                        "relationsCount": event.relations_count,
+                        "version": event.version,
+                    }
+                }));
+            },
+            result = dv_data_rx.recv() => if let Some(event) = received(result, "dataverse_data")? {
+                journal.publish("dataverse", json!({
+                    "type": "dataverse:data",
+                    "data": {
+                        "appId": event.app_id,
+                        "slug": event.slug,
+                        "tableName": event.table_name,
+                        "operation": event.operation,
+                        "rowCount": event.row_count,
+                    }
+                }));
+            },
+            result = cloud_relay_rx.recv() => if let Some(event) = received(result, "cloud_relay")? {
+                journal.publish("cloud_relay", json!({"type": "cloud_relay:status", "data": event}));
+            },
+            result = cert_rx.recv() => if let Some(event) = received(result, "cert_ready")? {
+                journal.publish("certs", json!({
+                    "type": "certs:ready",
+                    "data": {"slug": event.slug, "wildcardDomain": event.wildcard_domain}
+                }));
+            },
+            result = backend_health_rx.recv() => if let Some(event) = received(result, "backend_health")? {
+                journal.publish("proxy", json!({"type": "proxy:health", "data": event}));
+            },
+            result = auth_security_rx.recv() => if let Some(event) = received(result, "auth_security")? {
+                journal.publish("auth", json!({"type": "auth:security", "data": event}));
+            },
+            result = pubsub_rx.recv() => if let Some(event) = received(result, "pubsub")? {
+                journal.publish("pubsub", json!({"type": "pubsub:message", "data": event}));
+            },
+            result = ddns_rx.recv() => if let Some(event) = received(result, "ddns")? {
+                journal.publish("ddns", json!({"type": "ddns:updated", "data": event}));
+            },
+            result = network_device_rx.recv() => if let Some(event) = received(result, "network_device")? {
+                journal.publish("network", json!({"type": "network:device", "data": event}));
+            },
+            result = adblock_rx.recv() => if let Some(event) = received(result, "adblock")? {
+                journal.publish("adblock", json!({"type": "adblock:schedule", "data": event}));
+            },
+
+            _ = services_tick.tick() => {
+                let current = services_state(&state).await;
+                publish_services(journal, &services, &current);
+                services = current;
+            },
+            _ = leases_tick.tick() => {
+                let current = leases_state(&state).await;
+                publish_leases(journal, &leases, &current);
+                leases = current;
+            },
+            _ = dns_stats_tick.tick() => {
+                let stats = state.dns.read().await.stats.snapshot();
+                journal.publish_volatile("dns.stats", json!({"type": "dns:stats", "data": stats}));
+            },
+        }
+    }
+}
+
+fn update_message(event: UpdateEvent) -> Value {
+    match event {
+        UpdateEvent::Started => json!({"type": "updates:started"}),
+        UpdateEvent::Phase { phase, message } => json!({"type": "updates:phase", "data": {"phase": phase, "message": message}}),
+        UpdateEvent::Output { line } => json!({"type": "updates:output", "data": {"line": line}}),
+        UpdateEvent::AptComplete { packages, security_count } => json!({"type": "updates:apt-complete", "data": {"packages": packages, "securityCount": security_count}}),
+        UpdateEvent::SnapComplete { snaps } => json!({"type": "updates:snap-complete", "data": {"snaps": snaps}}),
+        UpdateEvent::NeedrestartComplete(data) => json!({"type": "updates:needrestart-complete", "data": data}),
+        UpdateEvent::Complete { success, summary, duration } => json!({"type": "updates:complete", "data": {"success": success, "summary": summary, "duration": duration}}),
+        UpdateEvent::Cancelled => json!({"type": "updates:cancelled"}),
+        UpdateEvent::Error { error } => json!({"type": "updates:error", "data": {"error": error}}),
+        UpdateEvent::UpgradeStarted { upgrade_type } => json!({"type": "updates:upgrade-started", "data": {"type": upgrade_type}}),
+        UpdateEvent::UpgradeOutput { line } => json!({"type": "updates:upgrade-output", "data": {"line": line}}),
+        UpdateEvent::UpgradeComplete { upgrade_type, success, duration, error } => json!({"type": "updates:upgrade-complete", "data": {"type": upgrade_type, "success": success, "duration": duration, "error": error}}),
+        UpdateEvent::UpgradeCancelled => json!({"type": "updates:upgrade-cancelled"}),
+    }
+}
+
+/// Supervised services by name, as sent to the clients.
+async fn services_state(state: &ApiState) -> HashMap<String, Value> {
+    state
+        .service_registry
+        .read()
+        .await
+        .iter()
+        .map(|(name, status)| (name.clone(), json!(status)))
+        .collect()
+}
+
+fn publish_services(journal: &EventJournal, previous: &HashMap<String, Value>, current: &HashMap<String, Value>) {
+    for (name, status) in current {
+        if previous.get(name) != Some(status) {
+            journal.publish("services", json!({"type": "services:status", "data": status}));
+        }
+    }
+}
+
+/// Active leases: MAC → (IP, hostname).
+type Leases = HashMap<String, (String, Option<String>)>;
+
+async fn leases_state(state: &ApiState) -> Leases {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    state
+        .dhcp
+        .read()
+        .await
+        .lease_store
+        .all_leases()
+        .into_iter()
+        .filter(|l| l.expiry > now)
+        .map(|l| (l.mac.to_lowercase(), (l.ip.to_string(), l.hostname.clone())))
+        .collect()
+}
+
+fn lease_changes(previous: &Leases, current: &Leases) -> Vec<Value> {
+    let mut changes = Vec::new();
+    for (mac, (ip, hostname)) in current {
+        let action = match previous.get(mac) {
+            None => "added",
+            Some(lease) if lease != &(ip.clone(), hostname.clone()) => "changed",
+            Some(_) => continue,
+        };
+        changes.push(json!({"action": action, "mac": mac, "ip": ip, "hostname": hostname}));
+    }
+    for (mac, (ip, hostname)) in previous {
+        if !current.contains_key(mac) {
+            changes.push(json!({"action": "removed", "mac": mac, "ip": ip, "hostname": hostname}));
+        }
+    }
+    changes
+}
+
+fn publish_leases(journal: &EventJournal, previous: &Leases, current: &Leases) {
+    for change in lease_changes(previous, current) {
+        journal.publish("dhcp.leases", json!({"type": "dhcp:lease", "data": change}));
+    }
+}
+
+/// Current state of a topic, sent on subscription instead of replaying
+/// everything. `None` for topics that only have events.
+pub async fn snapshot(state: &ApiState, topic: &str) -> Option<Value> {
+    let data = match topic {
+        "hosts" => json!(crate::routes::hosts::hosts_with_status(state).await),
+        "services" => {
+            let mut services: Vec<_> = state.service_registry.read().await.values().cloned().collect();
+            services.sort_by(|a, b| a.priority.cmp(&b.priority).then(a.name.cmp(&b.name)));
+            json!(services)
+        }
+        "dhcp.leases" => json!(crate::routes::dns_dhcp::leases(state).await),
+        "dns.stats" => state.dns.read().await.stats.snapshot(),
+        "certs" => json!(state.acme.list_certificates().unwrap_or_default()),
+        "migrations" => {
+            let migrations: Vec<_> = state
+                .migrations
+                .read()
+                .await
+                .values()
+                .filter(|m| !matches!(m.phase, MigrationPhase::Complete | MigrationPhase::Failed))
+                .map(|m| {
+                    json!({
+                        "appId": m.app_id,
+                        "transferId": m.transfer_id,
+                        "phase": m.phase,
+                        "progressPct": m.progress_pct,
+                        "bytesTransferred": m.bytes_transferred,
+                        "totalBytes": m.total_bytes,
+                        "error": m.error,
+                    })
+                })
+                .collect();
+            let deployments: Vec<_> = state
+                .deployments
+                .read()
+                .await
+                .values()
+                .filter(|d| !d.phase.is_finished())
+                .map(|d| {
+                    json!({
+                        "appId": d.app_id,
+                        "deployId": d.deploy_id,
+                        "phase": d.phase,
+                        "containerName": d.container_name,
+                        "message": d.message,
+                        "error": d.error,
+                    })
+                })
+                .collect();
+            json!({"migrations": migrations, "deployments": deployments})
+        }
+        _ => return None,
+    };
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lease(ip: &str, hostname: Option<&str>) -> (String, Option<String>) {
+        (ip.to_string(), hostname.map(str::to_string))
+    }
+
+    #[test]
+    fn lease_diff() {
+        let previous: Leases = [
+            ("aa".to_string(), lease("10.0.0.2", Some("nas"))),
+            ("bb".to_string(), lease("10.0.0.3", None)),
+            ("cc".to_string(), lease("10.0.0.4", None)),
+        ]
+        .into();
+        let current: Leases = [
+            ("aa".to_string(), lease("10.0.0.2", Some("nas"))),
+            ("bb".to_string(), lease("10.0.0.9", None)),
+            ("dd".to_string(), lease("10.0.0.5", Some("phone"))),
+        ]
+        .into();
+
+        let mut changes: Vec<_> = lease_changes(&previous, &current)
+            .iter()
+            .map(|c| format!("{} {} {}", c["action"].as_str().unwrap(), c["mac"].as_str().unwrap(), c["ip"].as_str().unwrap()))
+            .collect();
+        changes.sort();
+        assert_eq!(changes, ["added dd 10.0.0.5", "changed bb 10.0.0.9", "removed cc 10.0.0.4"]);
+        assert!(lease_changes(&current, &current).is_empty());
+    }
+}
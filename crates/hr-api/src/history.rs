@@ -0,0 +1,94 @@
+//! Metric history: the metrics registry is sampled into the embedded TSDB
+//! so the dashboard can chart any series over time. Connected hosts are
+//! sampled with it; the samples a host agent took while disconnected come
+//! later in a `MetricsBackfill`, see `record_backfill`.
+
+use std::time::Duration;
+
+use hr_common::{metrics, tsdb};
+use hr_registry::protocol::{HostMetrics, MetricsSample};
+use tracing::{debug, warn};
+
+use crate::routes::metrics::refresh_gauges;
+use crate::state::ApiState;
+
+/// Interval between samples (the finest TSDB tier).
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+/// Samples between two retention passes (one hour).
+const PRUNE_EVERY: u32 = 120;
+
+pub async fn run_history_sampler(state: ApiState) -> anyhow::Result<()> {
+    let Some(db) = tsdb::global() else {
+        warn!("Metric history disabled: TSDB not available");
+        return std::future::pending().await;
+    };
+    let mut tick = tokio::time::interval(SAMPLE_INTERVAL);
+    let mut samples_taken = 0u32;
+    loop {
+        tick.tick().await;
+        refresh_gauges(&state).await;
+        let mut samples = metrics::registry().samples();
+        if let Some(registry) = &state.registry {
+            for (host_id, conn) in registry.host_connections.read().await.iter() {
+                if let Some(m) = &conn.metrics {
+                    samples.extend(host_series(host_id, m));
+                }
+            }
+        }
+        let now = chrono::Utc::now().timestamp();
+        samples_taken = samples_taken.wrapping_add(1);
+        let prune = samples_taken.is_multiple_of(PRUNE_EVERY);
+
+        let result = tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+            db.record_batch(now, &samples)?;
+            if prune {
+                let removed = db.prune(now)?;
+                debug!("Metric history: pruned {} buckets", removed);
+            }
+            Ok(())
+        })
+        .await?;
+        if let Err(e) = result {
+            warn!("Failed to record metric history: {}", e);
+        }
+    }
+}
+
+/// Series of a host's metrics, named like the registry's gauges.
+pub(crate) fn host_series(host_id: &str, m: &HostMetrics) -> Vec<(String, f64)> {
+    let labels = format!("{{host=\"{}\"}}", host_id.replace('\\', "\\\\").replace('"', "\\\""));
+    [
+        ("homeroute_host_cpu_percent", m.cpu_percent as f64),
+        ("homeroute_host_memory_used_bytes", m.memory_used_bytes as f64),
+        ("homeroute_host_memory_total_bytes", m.memory_total_bytes as f64),
+        ("homeroute_host_disk_used_bytes", m.disk_used_bytes as f64),
+        ("homeroute_host_disk_total_bytes", m.disk_total_bytes as f64),
+        ("homeroute_host_load1", m.load_avg[0] as f64),
+    ]
+    .into_iter()
+    .chain(m.cpu_temp_c.map(|t| ("homeroute_host_cpu_temp_celsius", t as f64)))
+    .map(|(name, value)| (format!("{name}{labels}"), value))
+    .collect()
+}
+
+/// Record the samples a host agent buffered while disconnected, at the
+/// time they were taken.
+pub async fn record_backfill(host_id: &str, samples: Vec<MetricsSample>) {
+    let Some(db) = tsdb::global() else {
+        return;
+    };
+    let host_id = host_id.to_string();
+    let count = samples.len();
+    let result = tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+        for sample in &samples {
+            db.record_batch(sample.ts, &host_series(&host_id, &sample.metrics))?;
+        }
+        Ok(())
+    })
+    .await;
+    match result {
+        Ok(Ok(())) => debug!("Metric history: backfilled {} host samples", count),
+        Ok(Err(e)) => warn!("Failed to record host metrics backfill: {}", e),
+        Err(e) => warn!("Failed to record host metrics backfill: {}", e),
+    }
+}
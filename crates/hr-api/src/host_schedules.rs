@@ -0,0 +1,330 @@
+//! Power schedules of the hosts: each host of hosts.json carries a list of
+//! rules (a cron expression with an action, or a weekday/time window woken
+//! at its start and shut down or suspended at its end). The supervised
+//! `host-scheduler` service evaluates them every minute and runs the
+//! actions through the same power paths as the API (registry state machine,
+//! agent, SSH fallback).
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use chrono::{Local, NaiveDateTime, Timelike};
+use hr_cron::Schedule;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::state::ApiState;
+
+/// Minutes evaluated after a stall (suspend, slow tick); older ones are
+/// skipped like a missed cron run.
+const MAX_CATCH_UP: i64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduledAction {
+    Wake,
+    #[default]
+    Shutdown,
+    Sleep,
+}
+
+impl ScheduledAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduledAction::Wake => "wake",
+            ScheduledAction::Shutdown => "shutdown",
+            ScheduledAction::Sleep => "sleep",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ScheduleRule {
+    /// `0 7 * * 1-5` + action.
+    Cron { cron: String, action: ScheduledAction },
+    /// Woken at `start`, `offAction` at `end` (`HH:MM`, next day when not
+    /// after `start`), on the given weekdays (0 or 7 = Sunday).
+    Window {
+        days: Vec<u8>,
+        start: String,
+        end: String,
+        #[serde(default, rename = "offAction")]
+        off_action: ScheduledAction,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostSchedule {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default = "yes")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub description: String,
+    #[serde(flatten)]
+    pub rule: ScheduleRule,
+}
+
+fn yes() -> bool {
+    true
+}
+
+fn parse_time(time: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Invalid time: {time} (HH:MM)");
+    let (h, m) = time.trim().split_once(':').ok_or_else(invalid)?;
+    let (h, m): (u32, u32) = (h.parse().map_err(|_| invalid())?, m.parse().map_err(|_| invalid())?);
+    if h > 23 || m > 59 {
+        return Err(invalid());
+    }
+    Ok((h, m))
+}
+
+impl HostSchedule {
+    /// Cron expressions equivalent to the rule, with the action each fires.
+    pub fn triggers(&self) -> Result<Vec<(Schedule, ScheduledAction)>, String> {
+        match &self.rule {
+            ScheduleRule::Cron { cron, action } => {
+                let schedule = Schedule::parse(cron).map_err(|e| format!("Invalid cron expression: {e}"))?;
+                Ok(vec![(schedule, *action)])
+            }
+            ScheduleRule::Window { days, start, end, off_action } => {
+                if days.is_empty() || days.iter().any(|d| *d > 7) {
+                    return Err("Window days must be weekdays 0-7 (0 and 7 = Sunday)".to_string());
+                }
+                let start = parse_time(start)?;
+                let end = parse_time(end)?;
+                if start == end {
+                    return Err("The window starts and ends at the same time".to_string());
+                }
+                let list = |shift: u8| {
+                    let mut days: Vec<u8> = days.iter().map(|d| (d % 7 + shift) % 7).collect();
+                    days.sort_unstable();
+                    days.dedup();
+                    days.iter().map(u8::to_string).collect::<Vec<_>>().join(",")
+                };
+                let on = format!("{} {} * * {}", start.1, start.0, list(0));
+                // Overnight window: it closes the next day
+                let off = format!("{} {} * * {}", end.1, end.0, list(if end <= start { 1 } else { 0 }));
+                Ok(vec![
+                    (Schedule::parse(&on)?, ScheduledAction::Wake),
+                    (Schedule::parse(&off)?, *off_action),
+                ])
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let now = Local::now().naive_local();
+        if self.triggers()?.iter().all(|(s, _)| s.next_after(now).is_none()) {
+            return Err("The schedule never fires".to_string());
+        }
+        Ok(())
+    }
+
+    /// Next action after `after`, `None` when disabled or never firing.
+    pub fn next_run(&self, after: NaiveDateTime) -> Option<(NaiveDateTime, ScheduledAction)> {
+        if !self.enabled {
+            return None;
+        }
+        self.triggers()
+            .ok()?
+            .into_iter()
+            .filter_map(|(s, action)| s.next_after(after).map(|t| (t, action)))
+            .min_by_key(|(t, _)| *t)
+    }
+}
+
+/// Schedules of a host entry of hosts.json; entries not in this format
+/// (e.g. migrated from wol-schedules.json) are skipped.
+pub fn host_schedules(host: &Value) -> Vec<HostSchedule> {
+    host.get("schedules")
+        .and_then(|s| s.as_array())
+        .map(|list| list.iter().filter_map(|s| serde_json::from_value(s.clone()).ok()).collect())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleRun {
+    pub at: String,
+    pub action: ScheduledAction,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Last run of each schedule, kept across restarts.
+pub struct HostScheduler {
+    runs: RwLock<HashMap<String, ScheduleRun>>,
+    path: PathBuf,
+}
+
+impl HostScheduler {
+    pub fn new(path: PathBuf) -> Self {
+        let runs = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        Self { runs: RwLock::new(runs), path }
+    }
+
+    pub fn last_run(&self, schedule_id: &str) -> Option<ScheduleRun> {
+        self.runs.read().unwrap().get(schedule_id).cloned()
+    }
+
+    /// Drop the history of schedules that no longer exist.
+    pub fn retain(&self, ids: &[String]) {
+        self.runs.write().unwrap().retain(|id, _| ids.contains(id));
+    }
+
+    fn record(&self, schedule_id: &str, run: ScheduleRun) {
+        let content = {
+            let mut runs = self.runs.write().unwrap();
+            runs.insert(schedule_id.to_string(), run);
+            serde_json::to_string_pretty(&*runs)
+        };
+        let saved = content.map_err(anyhow::Error::from).and_then(|content| {
+            let tmp_path = self.path.with_extension("json.tmp");
+            std::fs::write(&tmp_path, content)?;
+            std::fs::rename(&tmp_path, &self.path)?;
+            Ok(())
+        });
+        if let Err(e) = saved {
+            warn!("Failed to save host schedule runs: {}", e);
+        }
+    }
+}
+
+/// Wakes every minute and runs the actions due in the minutes elapsed.
+pub async fn run_host_scheduler(state: ApiState) -> anyhow::Result<()> {
+    let mut last = minute(Local::now().naive_local());
+    loop {
+        let now = Local::now().naive_local();
+        let wait = 60 - now.second() as u64;
+        tokio::time::sleep(Duration::from_secs(wait)).await;
+
+        let current = minute(Local::now().naive_local());
+        let first = (last + chrono::Duration::minutes(1)).max(current - chrono::Duration::minutes(MAX_CATCH_UP - 1));
+        if current < first {
+            // Clock moved back: do not fire the same minutes twice
+            last = last.max(current);
+            continue;
+        }
+        let data = crate::routes::hosts::load_hosts().await;
+        let hosts = data.get("hosts").and_then(|h| h.as_array()).cloned().unwrap_or_default();
+        let mut t = first;
+        while t <= current {
+            for host in &hosts {
+                let Some(host_id) = host.get("id").and_then(|i| i.as_str()) else { continue };
+                for schedule in host_schedules(host).iter().filter(|s| s.enabled) {
+                    let Ok(triggers) = schedule.triggers() else { continue };
+                    for (_, action) in triggers.iter().filter(|(s, _)| s.matches(t)) {
+                        execute(&state, host_id, schedule, *action).await;
+                    }
+                }
+            }
+            t += chrono::Duration::minutes(1);
+        }
+        last = current;
+    }
+}
+
+fn minute(t: NaiveDateTime) -> NaiveDateTime {
+    t.with_second(0).and_then(|t| t.with_nanosecond(0)).unwrap_or(t)
+}
+
+async fn execute(state: &ApiState, host_id: &str, schedule: &HostSchedule, action: ScheduledAction) {
+    let result = match action {
+        ScheduledAction::Wake => crate::routes::hosts::wake_host(state, host_id).await,
+        ScheduledAction::Shutdown => crate::routes::hosts::power_off_host(state, host_id).await,
+        ScheduledAction::Sleep => crate::routes::hosts::suspend_host(state, host_id).await,
+    }
+    .0;
+    let success = result.get("success").and_then(|s| s.as_bool()).unwrap_or(false);
+    let error = result.get("error").map(|e| e.as_str().map(str::to_string).unwrap_or_else(|| e.to_string()));
+    match &error {
+        None => info!("Host schedule {} ({}): {} {}", schedule.id, schedule.description, action.as_str(), host_id),
+        Some(e) => warn!("Host schedule {}: {} {} failed: {}", schedule.id, action.as_str(), host_id, e),
+    }
+    hr_common::metrics::registry()
+        .counter(
+            "homeroute_host_schedule_runs_total",
+            "Scheduled host power actions by action and outcome.",
+            &[("action", action.as_str()), ("status", if success { "ok" } else { "error" })],
+        )
+        .inc();
+    state.host_scheduler.record(
+        &schedule.id,
+        ScheduleRun { at: Local::now().to_rfc3339(), action, success, error },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, h: u32, m: u32) -> NaiveDateTime {
+        // 2026-06-01 is a Monday
+        NaiveDate::from_ymd_opt(2026, 6, day).unwrap().and_hms_opt(h, m, 0).unwrap()
+    }
+
+    fn window(days: Vec<u8>, start: &str, end: &str) -> HostSchedule {
+        HostSchedule {
+            id: "s1".to_string(),
+            enabled: true,
+            description: String::new(),
+            rule: ScheduleRule::Window {
+                days,
+                start: start.to_string(),
+                end: end.to_string(),
+                off_action: ScheduledAction::Sleep,
+            },
+        }
+    }
+
+    #[test]
+    fn test_window() {
+        let office = window(vec![1, 2, 3, 4, 5], "07:30", "19:00");
+        assert_eq!(office.next_run(at(1, 6, 0)), Some((at(1, 7, 30), ScheduledAction::Wake)));
+        assert_eq!(office.next_run(at(1, 8, 0)), Some((at(1, 19, 0), ScheduledAction::Sleep)));
+        // Friday evening → Monday morning
+        assert_eq!(office.next_run(at(5, 20, 0)), Some((at(8, 7, 30), ScheduledAction::Wake)));
+
+        // Saturday night, closed on Sunday morning
+        let night = window(vec![6], "22:00", "02:00");
+        assert_eq!(night.next_run(at(6, 23, 0)), Some((at(7, 2, 0), ScheduledAction::Sleep)));
+        assert!(night.validate().is_ok());
+
+        assert!(window(vec![], "07:00", "08:00").validate().is_err());
+        assert!(window(vec![8], "07:00", "08:00").validate().is_err());
+        assert!(window(vec![1], "07:00", "07:00").validate().is_err());
+        assert!(window(vec![1], "25:00", "07:00").validate().is_err());
+
+        let mut disabled = office.clone();
+        disabled.enabled = false;
+        assert_eq!(disabled.next_run(at(1, 6, 0)), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let host = serde_json::json!({"schedules": [
+            {"id": "a", "type": "cron", "cron": "0 2 * * *", "action": "shutdown"},
+            {"id": "b", "type": "window", "days": [0, 6], "start": "09:00", "end": "12:00"},
+            {"id": "legacy", "serverId": "x", "time": "07:00"}
+        ]});
+        let schedules = host_schedules(&host);
+        assert_eq!(schedules.len(), 2);
+        assert_eq!(schedules[0].rule, ScheduleRule::Cron { cron: "0 2 * * *".to_string(), action: ScheduledAction::Shutdown });
+        assert!(schedules[0].enabled);
+        assert!(matches!(schedules[1].rule, ScheduleRule::Window { off_action: ScheduledAction::Shutdown, .. }));
+        assert_eq!(serde_json::to_value(&schedules[1]).unwrap()["type"], "window");
+
+        let bad = HostSchedule { rule: ScheduleRule::Cron { cron: "61 * * * *".to_string(), action: ScheduledAction::Wake }, ..schedules[0].clone() };
+        assert!(bad.validate().is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! OS package updates of remote hosts.
+//!
+//! Host agents report their pending updates (apt or dnf) a few times a day;
+//! the last report is kept in hosts.json. An upgrade runs on the agent,
+//! which streams its output back (`host:upgrade` events); when it is done
+//! and the host needs a reboot, the reboot goes through the power state
+//! machine like a manual one, if the upgrade was started with `reboot`.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use hr_common::events::{HostPowerState, HostUpgradeEvent, PowerAction};
+use hr_registry::protocol::{HostRegistryMessage, PackageStatus};
+
+use crate::routes::hosts::set_host_package_updates;
+use crate::state::ApiState;
+
+/// Output lines kept for `GET /api/updates/hosts/{id}/upgrade`.
+const OUTPUT_LINES: usize = 500;
+
+/// The last upgrade of a host.
+#[derive(Debug, Clone, Serialize)]
+pub struct HostUpgrade {
+    pub upgrade_id: String,
+    pub host_id: String,
+    pub full: bool,
+    pub snapshot_requested: bool,
+    /// Reboot the host afterwards when the upgrade requires it.
+    pub reboot: bool,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub success: Option<bool>,
+    pub error: Option<String>,
+    pub snapshot: Option<String>,
+    pub reboot_required: bool,
+    pub rebooting: bool,
+    pub output: Vec<String>,
+}
+
+impl HostUpgrade {
+    pub fn running(&self) -> bool {
+        self.finished_at.is_none()
+    }
+}
+
+/// Ask `host_id` to upgrade its packages. The host must be online and not
+/// upgrading already.
+pub async fn start(state: &ApiState, host_id: &str, full: bool, snapshot: bool, reboot: bool) -> Result<HostUpgrade, String> {
+    let registry = state.registry.as_ref().ok_or("No registry")?;
+    if registry.get_host_power_state(host_id).await != HostPowerState::Online {
+        return Err("L'hote n'est pas en ligne".to_string());
+    }
+    if state.host_upgrades.read().await.get(host_id).is_some_and(|u| u.running()) {
+        return Err("Mise a jour deja en cours".to_string());
+    }
+    let upgrade = HostUpgrade {
+        upgrade_id: uuid::Uuid::new_v4().to_string(),
+        host_id: host_id.to_string(),
+        full,
+        snapshot_requested: snapshot,
+        reboot,
+        started_at: Utc::now(),
+        finished_at: None,
+        success: None,
+        error: None,
+        snapshot: None,
+        reboot_required: false,
+        rebooting: false,
+        output: Vec::new(),
+    };
+    registry
+        .send_host_command(host_id, HostRegistryMessage::UpgradePackages {
+            upgrade_id: upgrade.upgrade_id.clone(),
+            full,
+            snapshot,
+        })
+        .await?;
+    info!(host_id, upgrade_id = %upgrade.upgrade_id, full, snapshot, reboot, "Host package upgrade started");
+    state.host_upgrades.write().await.insert(host_id.to_string(), upgrade.clone());
+    let _ = state.events.host_upgrade.send(HostUpgradeEvent::Started {
+        host_id: host_id.to_string(),
+        upgrade_id: upgrade.upgrade_id.clone(),
+    });
+    Ok(upgrade)
+}
+
+/// Store the pending updates reported by a host.
+pub async fn record_status(host_id: &str, status: &PackageStatus) {
+    if let Err(e) = set_host_package_updates(host_id, status).await {
+        warn!(host_id, "Failed to save package updates: {e}");
+    }
+}
+
+pub async fn on_output(state: &ApiState, host_id: &str, upgrade_id: &str, line: String) {
+    if let Some(upgrade) = state.host_upgrades.write().await.get_mut(host_id)
+        && upgrade.upgrade_id == upgrade_id
+    {
+        if upgrade.output.len() == OUTPUT_LINES {
+            upgrade.output.remove(0);
+        }
+        upgrade.output.push(line.clone());
+    }
+    let _ = state.events.host_upgrade.send(HostUpgradeEvent::Output {
+        host_id: host_id.to_string(),
+        upgrade_id: upgrade_id.to_string(),
+        line,
+    });
+}
+
+/// Record the outcome of an upgrade, and reboot the host when it needs it
+/// and the upgrade asked for it.
+pub async fn on_finished(
+    state: &ApiState,
+    host_id: &str,
+    upgrade_id: &str,
+    success: bool,
+    error: Option<String>,
+    snapshot: Option<String>,
+    reboot_required: bool,
+) {
+    let reboot = {
+        let mut upgrades = state.host_upgrades.write().await;
+        match upgrades.get_mut(host_id) {
+            Some(upgrade) if upgrade.upgrade_id == upgrade_id => {
+                upgrade.finished_at = Some(Utc::now());
+                upgrade.success = Some(success);
+                upgrade.error = error.clone();
+                upgrade.snapshot = snapshot.clone();
+                upgrade.reboot_required = reboot_required;
+                upgrade.reboot
+            }
+            _ => false,
+        }
+    };
+    info!(host_id, upgrade_id, success, reboot_required, "Host package upgrade finished");
+
+    let mut rebooting = false;
+    if success && reboot && reboot_required
+        && let Some(registry) = &state.registry
+    {
+        match registry.request_power_action(host_id, PowerAction::Reboot).await {
+            Ok(()) => match registry.send_host_command(host_id, HostRegistryMessage::Reboot).await {
+                Ok(()) => rebooting = true,
+                Err(e) => warn!(host_id, "Reboot after upgrade failed: {e}"),
+            },
+            Err(e) => warn!(host_id, "Reboot after upgrade refused: {e}"),
+        }
+    }
+    if rebooting && let Some(upgrade) = state.host_upgrades.write().await.get_mut(host_id) {
+        upgrade.rebooting = true;
+    }
+
+    let _ = state.events.host_upgrade.send(HostUpgradeEvent::Complete {
+        host_id: host_id.to_string(),
+        upgrade_id: upgrade_id.to_string(),
+        success,
+        error,
+        snapshot,
+        reboot_required,
+        rebooting,
+    });
+}
+
+/// The agent of `host_id` went away: an upgrade it was running will never
+/// report its end.
+pub async fn on_host_disconnected(state: &ApiState, host_id: &str) {
+    let upgrade_id = match state.host_upgrades.read().await.get(host_id) {
+        Some(upgrade) if upgrade.running() => upgrade.upgrade_id.clone(),
+        _ => return,
+    };
+    on_finished(
+        state,
+        host_id,
+        &upgrade_id,
+        false,
+        Some("Host disconnected during the upgrade".to_string()),
+        None,
+        false,
+    )
+    .await;
+}
@@ -0,0 +1,103 @@
+//! Leak watch: the self-monitoring series (memory, file descriptors, tasks,
+//! event backlog) are read back from the metric history and flagged when
+//! their daily floor keeps rising for several days.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use hr_common::{metrics, selfmon, tsdb};
+use tracing::{info, warn};
+
+/// How often the history is checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+/// Whole days the floor must rise on.
+const TREND_DAYS: usize = 3;
+/// Overall growth of the floor over those days before it counts (10 %).
+const MIN_GROWTH: f64 = 0.1;
+/// Series prefixes watched for a trend.
+const WATCHED: &[&str] = &[
+    "homeroute_process_",
+    "homeroute_tokio_alive_tasks",
+    "homeroute_tasks",
+    "homeroute_event_backlog",
+];
+
+pub async fn run_leak_watch() -> anyhow::Result<()> {
+    let Some(db) = tsdb::global() else {
+        warn!("Leak watch disabled: TSDB not available");
+        return std::future::pending().await;
+    };
+    let mut tick = tokio::time::interval(CHECK_INTERVAL);
+    let mut suspects = HashSet::new();
+    loop {
+        tick.tick().await;
+        let rising = tokio::task::spawn_blocking(move || rising_series(db)).await??;
+
+        let registry = metrics::registry();
+        for name in rising.difference(&suspects) {
+            warn!(
+                "Possible leak: {} has grown every day for {} days (daily minimum up more than {:.0}%)",
+                name,
+                TREND_DAYS,
+                MIN_GROWTH * 100.0
+            );
+        }
+        for name in suspects.difference(&rising) {
+            info!("Leak watch: {} no longer trending up", name);
+        }
+        for name in suspects.union(&rising) {
+            registry
+                .gauge(
+                    "homeroute_leak_suspected",
+                    "1 when a self-monitoring series has been rising for days.",
+                    &[("series", name)],
+                )
+                .set(if rising.contains(name) { 1.0 } else { 0.0 });
+        }
+        suspects = rising;
+    }
+}
+
+/// Watched series whose daily floor rose over the last `TREND_DAYS`.
+fn rising_series(db: &tsdb::Tsdb) -> anyhow::Result<HashSet<String>> {
+    let now = chrono::Utc::now().timestamp();
+    let from = now - TREND_DAYS as i64 * 86400;
+    let mut rising = HashSet::new();
+    for prefix in WATCHED {
+        for name in db.series(prefix)? {
+            let range = db.query(&name, from, now, 10_000)?;
+            if let Some(floors) = selfmon::daily_floors(&range.points, now, TREND_DAYS)
+                && selfmon::rising_floors(&floors, MIN_GROWTH)
+            {
+                rising.insert(name);
+            }
+        }
+    }
+    Ok(rising)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rising_series() {
+        let db = tsdb::Tsdb::open_in_memory().unwrap();
+        let now = chrono::Utc::now().timestamp();
+        for i in 0..(TREND_DAYS as i64 * 24) {
+            let ts = now - TREND_DAYS as i64 * 86400 + i * 3600 + 60;
+            let day = (i / 24) as f64;
+            db.record_batch(
+                ts,
+                &[
+                    ("homeroute_process_open_fds".to_string(), 100.0 + day * 20.0),
+                    ("homeroute_process_resident_memory_bytes".to_string(), 80e6),
+                    ("homeroute_dns_cache_entries".to_string(), 100.0 + day * 500.0),
+                ],
+            )
+            .unwrap();
+        }
+        let rising = rising_series(&db).unwrap();
+        assert_eq!(rising, HashSet::from(["homeroute_process_open_fds".to_string()]));
+    }
+}
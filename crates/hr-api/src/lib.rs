@@ -1,6 +1,30 @@
+pub mod backups;
+pub mod capture;
+pub mod config_backup;
+pub mod config_tx;
 pub mod container_manager;
+pub mod custom_domains;
+pub mod declarative;
+pub mod deployments;
+pub mod doctor;
+pub mod drain;
+pub mod enrollment;
+pub mod error;
+pub mod fanout;
+pub mod history;
+pub mod host_schedules;
+pub mod host_updates;
+pub mod leakwatch;
+pub mod mqtt;
+pub mod placement;
+pub mod plugins;
+pub mod process_manager;
+pub mod route_records;
 pub mod routes;
+pub mod speedtest;
 pub mod state;
+pub mod templates;
+pub mod terminal;
 
 use axum::http::{header, HeaderValue, Method};
 use axum::Router;
@@ -52,12 +76,34 @@ fn api_routes() -> Router<ApiState> {
         .nest("/updates", routes::updates::router())
         .nest("/hosts", routes::hosts::router())
         .nest("/services", routes::services::router())
+        .nest("/config", routes::config::router())
+        .nest("/declarative", routes::declarative::router())
 
         .nest("/applications", routes::applications::router())
         .nest("/containers", routes::containers::router())
+        .nest("/processes", routes::processes::router())
         .nest("/dataverse", routes::dataverse::router())
         .nest("/cloud-relay", routes::cloud_relay::router())
         .nest("/store", routes::store::router())
+        .nest("/streams", routes::streams::router())
+        .nest("/plugins", routes::plugins::router())
+        .nest("/ext", routes::plugins::ext_router())
+        .nest("/mqtt", routes::mqtt::router())
+        .nest("/reflector", routes::reflector::router())
+        .nest("/syslog", routes::syslog::router())
+        .nest("/radius", routes::radius::router())
+        .nest("/network", routes::network::router())
+        .nest("/usage", routes::usage::router())
+        .nest("/firewall", routes::firewall::router())
+        .nest("/mail", routes::mail::router())
+        .nest("/storage", routes::storage::router())
+        .nest("/queues", routes::queues::router())
+        .nest("/pubsub", routes::pubsub::router())
+        .nest("/ai", routes::ai::router())
+        .nest("/history", routes::history::router())
+        .nest("/system", routes::system::router())
         .merge(routes::ws::router())
         .merge(routes::health::router())
+        .merge(routes::openapi::router())
+        .merge(routes::metrics::router())
 }
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+/// Configuration du pont MQTT (Home Assistant discovery), persistée dans
+/// `mqtt-config.json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MqttConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Broker hostname or IP.
+    #[serde(default)]
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    /// Home Assistant discovery prefix.
+    #[serde(default = "default_discovery_prefix")]
+    pub discovery_prefix: String,
+    /// Root of HomeRoute's own state/command topics.
+    #[serde(default = "default_base_topic")]
+    pub base_topic: String,
+    #[serde(default = "default_publish_interval_secs")]
+    pub publish_interval_secs: u64,
+    #[serde(default = "default_true")]
+    pub expose_hosts: bool,
+    #[serde(default = "default_true")]
+    pub expose_applications: bool,
+    /// Accept wake/shutdown/start/stop commands coming back from MQTT.
+    #[serde(default = "default_true")]
+    pub allow_commands: bool,
+}
+
+fn default_port() -> u16 {
+    1883
+}
+fn default_client_id() -> String {
+    "homeroute".to_string()
+}
+fn default_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+fn default_base_topic() -> String {
+    "homeroute".to_string()
+}
+fn default_publish_interval_secs() -> u64 {
+    30
+}
+fn default_true() -> bool {
+    true
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl MqttConfig {
+    pub fn load_from_file(path: &std::path::Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        let config: Self = serde_json::from_str(&content)?;
+        Ok(config)
+    }
+
+    pub fn save_to_file(&self, path: &std::path::Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, &content)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.enabled && self.host.trim().is_empty() {
+            return Err("Hôte du broker MQTT requis".to_string());
+        }
+        for (label, topic) in [("discovery_prefix", &self.discovery_prefix), ("base_topic", &self.base_topic)] {
+            if topic.is_empty() || topic.contains(['+', '#']) || topic.starts_with('/') || topic.ends_with('/') {
+                return Err(format!("{} invalide: {}", label, topic));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let config = MqttConfig::default();
+        assert!(!config.enabled);
+        assert_eq!(config.port, 1883);
+        assert_eq!(config.discovery_prefix, "homeassistant");
+        assert_eq!(config.base_topic, "homeroute");
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = MqttConfig { enabled: true, ..Default::default() };
+        assert!(config.validate().is_err());
+        config.host = "broker.lan".to_string();
+        assert!(config.validate().is_ok());
+        config.base_topic = "home/#".to_string();
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! Home Assistant MQTT discovery payloads and command topic parsing.
+//!
+//! Every entity reads a JSON state topic published by the bridge:
+//! - `{base}/host/{id}/state`  → `{"state": "ON"|"OFF", "power_state": ...}`
+//! - `{base}/app/{id}/state`   → `{"state": "ON"|"OFF", "status", "cpu", "memory_mb"}`
+//! - `{base}/system/state`     → `{"dns_queries", "dns_blocked", "wan": "ON"|"OFF", "wan_ip"}`
+//!
+//! Switches send `ON`/`OFF` to `{base}/host/{id}/set` and `{base}/app/{id}/set`.
+
+use serde_json::{json, Value};
+
+/// Topic layout derived from the configured prefixes.
+#[derive(Debug, Clone)]
+pub struct Topics {
+    pub discovery_prefix: String,
+    pub base: String,
+}
+
+impl Topics {
+    pub fn availability(&self) -> String {
+        format!("{}/status", self.base)
+    }
+
+    pub fn state(&self, kind: &str, id: &str) -> String {
+        format!("{}/{}/{}/state", self.base, kind, object_id(id))
+    }
+
+    pub fn command(&self, kind: &str, id: &str) -> String {
+        format!("{}/{}/{}/set", self.base, kind, object_id(id))
+    }
+
+    pub fn system_state(&self) -> String {
+        format!("{}/system/state", self.base)
+    }
+
+    /// Wildcard subscription covering every command topic.
+    pub fn command_filter(&self) -> String {
+        format!("{}/+/+/set", self.base)
+    }
+
+    /// `{prefix}/{component}/homeroute_{object}/config`
+    pub fn config(&self, component: &str, object: &str) -> String {
+        format!("{}/{}/homeroute_{}/config", self.discovery_prefix, component, object_id(object))
+    }
+}
+
+/// HA object ids only accept `[a-zA-Z0-9_-]`.
+pub fn object_id(raw: &str) -> String {
+    raw.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// Command received on a `…/set` topic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MqttCommand {
+    WakeHost(String),
+    ShutdownHost(String),
+    StartApp(String),
+    StopApp(String),
+}
+
+/// Map an incoming publish to a command. Unknown topics/payloads yield `None`.
+pub fn parse_command(topics: &Topics, topic: &str, payload: &[u8]) -> Option<MqttCommand> {
+    let rest = topic.strip_prefix(&topics.base)?.strip_prefix('/')?;
+    let mut parts = rest.split('/');
+    let (kind, id, leaf) = (parts.next()?, parts.next()?, parts.next()?);
+    if leaf != "set" || parts.next().is_some() || id.is_empty() {
+        return None;
+    }
+    let on = match std::str::from_utf8(payload).ok()?.trim().to_ascii_uppercase().as_str() {
+        "ON" => true,
+        "OFF" => false,
+        _ => return None,
+    };
+    let id = id.to_string();
+    match (kind, on) {
+        ("host", true) => Some(MqttCommand::WakeHost(id)),
+        ("host", false) => Some(MqttCommand::ShutdownHost(id)),
+        ("app", true) => Some(MqttCommand::StartApp(id)),
+        ("app", false) => Some(MqttCommand::StopApp(id)),
+        _ => None,
+    }
+}
+
+fn device(topics: &Topics) -> Value {
+    json!({
+        "identifiers": [format!("homeroute_{}", object_id(&topics.base))],
+        "name": "HomeRoute",
+        "manufacturer": "HomeRoute",
+        "model": "Router",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    })
+}
+
+fn entity(topics: &Topics, unique: &str, name: &str, state_topic: &str) -> Value {
+    json!({
+        "unique_id": format!("homeroute_{}", object_id(unique)),
+        "object_id": format!("homeroute_{}", object_id(unique)),
+        "name": name,
+        "state_topic": state_topic,
+        "availability_topic": topics.availability(),
+        "device": device(topics),
+    })
+}
+
+fn merge(mut base: Value, extra: Value) -> Value {
+    if let (Some(b), Some(e)) = (base.as_object_mut(), extra.as_object()) {
+        for (k, v) in e {
+            b.insert(k.clone(), v.clone());
+        }
+    }
+    base
+}
+
+/// Power switch for a host (ON = wake-on-LAN, OFF = shutdown).
+pub fn host_switch(topics: &Topics, id: &str, name: &str, commands: bool) -> (String, Value) {
+    let state_topic = topics.state("host", id);
+    let mut payload = merge(
+        entity(topics, &format!("host_{id}"), name, &state_topic),
+        json!({
+            "icon": "mdi:server",
+            "value_template": "{{ value_json.state }}",
+            "json_attributes_topic": state_topic,
+        }),
+    );
+    if commands {
+        payload["command_topic"] = json!(topics.command("host", id));
+    }
+    (topics.config("switch", &format!("host_{id}")), payload)
+}
+
+/// Switch for an application's main service.
+pub fn app_switch(topics: &Topics, id: &str, name: &str, commands: bool) -> (String, Value) {
+    let state_topic = topics.state("app", id);
+    let mut payload = merge(
+        entity(topics, &format!("app_{id}"), name, &state_topic),
+        json!({
+            "icon": "mdi:application",
+            "value_template": "{{ value_json.state }}",
+            "json_attributes_topic": state_topic,
+        }),
+    );
+    if commands {
+        payload["command_topic"] = json!(topics.command("app", id));
+    }
+    (topics.config("switch", &format!("app_{id}")), payload)
+}
+
+/// CPU and memory sensors for an application.
+pub fn app_sensors(topics: &Topics, id: &str, name: &str) -> Vec<(String, Value)> {
+    let state_topic = topics.state("app", id);
+    vec![
+        (
+            topics.config("sensor", &format!("app_{id}_cpu")),
+            merge(
+                entity(topics, &format!("app_{id}_cpu"), &format!("{name} CPU"), &state_topic),
+                json!({
+                    "unit_of_measurement": "%",
+                    "state_class": "measurement",
+                    "value_template": "{{ value_json.cpu }}",
+                }),
+            ),
+        ),
+        (
+            topics.config("sensor", &format!("app_{id}_memory")),
+            merge(
+                entity(topics, &format!("app_{id}_memory"), &format!("{name} mémoire"), &state_topic),
+                json!({
+                    "unit_of_measurement": "MB",
+                    "state_class": "measurement",
+                    "value_template": "{{ value_json.memory_mb }}",
+                }),
+            ),
+        ),
+    ]
+}
+
+/// DNS counters and WAN status entities.
+pub fn system_entities(topics: &Topics) -> Vec<(String, Value)> {
+    let state_topic = topics.system_state();
+    vec![
+        (
+            topics.config("sensor", "dns_queries"),
+            merge(
+                entity(topics, "dns_queries", "Requêtes DNS", &state_topic),
+                json!({
+                    "icon": "mdi:dns",
+                    "state_class": "total_increasing",
+                    "value_template": "{{ value_json.dns_queries }}",
+                }),
+            ),
+        ),
+        (
+            topics.config("sensor", "dns_blocked"),
+            merge(
+                entity(topics, "dns_blocked", "Requêtes DNS bloquées", &state_topic),
+                json!({
+                    "icon": "mdi:shield-check",
+                    "state_class": "total_increasing",
+                    "value_template": "{{ value_json.dns_blocked }}",
+                }),
+            ),
+        ),
+        (
+            topics.config("binary_sensor", "wan"),
+            merge(
+                entity(topics, "wan", "WAN", &state_topic),
+                json!({
+                    "device_class": "connectivity",
+                    "value_template": "{{ value_json.wan }}",
+                    "json_attributes_topic": state_topic,
+                }),
+            ),
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topics() -> Topics {
+        Topics {
+            discovery_prefix: "homeassistant".to_string(),
+            base: "homeroute".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let t = topics();
+        assert_eq!(
+            parse_command(&t, "homeroute/host/abc/set", b"ON"),
+            Some(MqttCommand::WakeHost("abc".to_string()))
+        );
+        assert_eq!(
+            parse_command(&t, "homeroute/host/abc/set", b"off\n"),
+            Some(MqttCommand::ShutdownHost("abc".to_string()))
+        );
+        assert_eq!(
+            parse_command(&t, "homeroute/app/x1/set", b"ON"),
+            Some(MqttCommand::StartApp("x1".to_string()))
+        );
+        assert_eq!(parse_command(&t, "homeroute/app/x1/set", b"TOGGLE"), None);
+        assert_eq!(parse_command(&t, "homeroute/app/x1/state", b"ON"), None);
+        assert_eq!(parse_command(&t, "homeroutex/app/x1/set", b"ON"), None);
+        assert_eq!(parse_command(&t, "homeroute/dns/x/set", b"ON"), None);
+    }
+
+    #[test]
+    fn test_host_switch_payload() {
+        let t = topics();
+        let (topic, payload) = host_switch(&t, "host:1", "NAS", true);
+        assert_eq!(topic, "homeassistant/switch/homeroute_host_host_1/config");
+        assert_eq!(payload["unique_id"], "homeroute_host_host_1");
+        assert_eq!(payload["state_topic"], "homeroute/host/host_1/state");
+        assert_eq!(payload["command_topic"], "homeroute/host/host_1/set");
+        assert_eq!(payload["availability_topic"], "homeroute/status");
+
+        let (_, payload) = host_switch(&t, "h", "NAS", false);
+        assert!(payload.get("command_topic").is_none());
+    }
+}
@@ -0,0 +1,437 @@
+//! MQTT bridge exposing HomeRoute to Home Assistant via MQTT discovery.
+//!
+//! Hosts become power switches, applications become switches + CPU/memory
+//! sensors, and DNS counters / WAN status are published as system entities.
+//! Commands from Home Assistant (wake, shutdown, app start/stop) come back on
+//! `{base}/{host|app}/{id}/set`.
+
+pub mod config;
+pub mod discovery;
+
+pub use config::MqttConfig;
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, QoS};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::{watch, Notify};
+use tracing::{debug, info, warn};
+
+use hr_common::events::{CloudRelayStatus, HostPowerState};
+use hr_registry::protocol::{ServiceAction, ServiceState, ServiceType};
+
+use crate::state::ApiState;
+use discovery::{MqttCommand, Topics};
+
+/// Delay between reconnection attempts after a broker error.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MqttStatus {
+    pub connected: bool,
+    pub last_error: Option<String>,
+    /// Unix millis of the last state publication.
+    pub last_publish: Option<i64>,
+    pub entities: usize,
+}
+
+/// Runtime handle shared between the API (config/status) and the bridge task.
+pub struct MqttBridge {
+    config: watch::Sender<MqttConfig>,
+    status: std::sync::RwLock<MqttStatus>,
+}
+
+impl MqttBridge {
+    pub fn new(config: MqttConfig) -> Self {
+        Self {
+            config: watch::Sender::new(config),
+            status: std::sync::RwLock::new(MqttStatus::default()),
+        }
+    }
+
+    pub fn config(&self) -> MqttConfig {
+        self.config.borrow().clone()
+    }
+
+    /// Replace the configuration; the bridge reconnects with the new settings.
+    pub fn apply(&self, config: MqttConfig) {
+        self.config.send_replace(config);
+    }
+
+    pub fn status(&self) -> MqttStatus {
+        self.status.read().unwrap().clone()
+    }
+
+    fn update_status(&self, f: impl FnOnce(&mut MqttStatus)) {
+        f(&mut self.status.write().unwrap());
+    }
+}
+
+/// Main loop: (re)connects to the broker whenever the configuration changes.
+pub async fn run_mqtt_bridge(state: ApiState) -> anyhow::Result<()> {
+    let mut config_rx = state.mqtt.config.subscribe();
+
+    loop {
+        let config = config_rx.borrow_and_update().clone();
+        state.mqtt.update_status(|s| *s = MqttStatus::default());
+
+        if config.enabled && !config.host.is_empty() {
+            info!("MQTT bridge connecting to {}:{}", config.host, config.port);
+            tokio::select! {
+                _ = run_session(&state, &config) => {}
+                changed = config_rx.changed() => {
+                    if changed.is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+        } else if config_rx.changed().await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Aborts the publisher task when the session ends.
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+async fn run_session(state: &ApiState, config: &MqttConfig) {
+    let topics = Topics {
+        discovery_prefix: config.discovery_prefix.clone(),
+        base: config.base_topic.clone(),
+    };
+
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    options.set_max_packet_size(256 * 1024, 256 * 1024);
+    options.set_last_will(LastWill::new(topics.availability(), "offline", QoS::AtLeastOnce, true));
+    if let Some(user) = &config.username {
+        options.set_credentials(user, config.password.clone().unwrap_or_default());
+    }
+
+    let (client, mut eventloop): (AsyncClient, EventLoop) = AsyncClient::new(options, 256);
+    let connects = Arc::new(AtomicU64::new(0));
+    let publish_now = Arc::new(Notify::new());
+    let _publisher = AbortOnDrop(tokio::spawn(run_publisher(
+        state.clone(),
+        config.clone(),
+        topics.clone(),
+        client.clone(),
+        connects.clone(),
+        publish_now.clone(),
+    )));
+
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("MQTT bridge connected to {}:{}", config.host, config.port);
+                state.mqtt.update_status(|s| {
+                    s.connected = true;
+                    s.last_error = None;
+                });
+                if config.allow_commands
+                    && let Err(e) = client.try_subscribe(topics.command_filter(), QoS::AtLeastOnce)
+                {
+                    warn!("MQTT subscribe failed: {}", e);
+                }
+                connects.fetch_add(1, Ordering::Relaxed);
+                publish_now.notify_one();
+            }
+            Ok(Event::Incoming(Packet::Publish(p))) => {
+                if !config.allow_commands {
+                    continue;
+                }
+                let Some(cmd) = discovery::parse_command(&topics, &p.topic, &p.payload) else {
+                    debug!("MQTT ignoring message on {}", p.topic);
+                    continue;
+                };
+                let state = state.clone();
+                let publish_now = publish_now.clone();
+                tokio::spawn(async move {
+                    handle_command(&state, cmd).await;
+                    publish_now.notify_one();
+                });
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("MQTT connection error: {}", e);
+                state.mqtt.update_status(|s| {
+                    s.connected = false;
+                    s.last_error = Some(e.to_string());
+                });
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Periodically publishes discovery configs (for new entities) and states.
+/// Discovery and availability are re-sent in full after every (re)connection.
+async fn run_publisher(
+    state: ApiState,
+    config: MqttConfig,
+    topics: Topics,
+    client: AsyncClient,
+    connects: Arc<AtomicU64>,
+    publish_now: Arc<Notify>,
+) {
+    let interval = Duration::from_secs(config.publish_interval_secs.max(5));
+    let mut announced: HashSet<String> = HashSet::new();
+    let mut seen_connect = 0;
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = publish_now.notified() => {}
+        }
+
+        let connect = connects.load(Ordering::Relaxed);
+        if connect == 0 {
+            continue;
+        }
+        if connect != seen_connect {
+            announced.clear();
+            if let Err(e) = client
+                .publish(topics.availability(), QoS::AtLeastOnce, true, "online")
+                .await
+            {
+                warn!("MQTT publish failed: {}", e);
+                continue;
+            }
+            seen_connect = connect;
+        }
+
+        let snapshot = collect_snapshot(&state, &config).await;
+        if let Err(e) = publish_snapshot(&client, &config, &topics, &snapshot, &mut announced).await {
+            warn!("MQTT publish failed: {}", e);
+            continue;
+        }
+        state.mqtt.update_status(|s| {
+            s.last_publish = Some(chrono::Utc::now().timestamp_millis());
+            s.entities = announced.len();
+        });
+    }
+}
+
+struct HostEntity {
+    id: String,
+    name: String,
+    power_state: HostPowerState,
+}
+
+struct AppEntity {
+    id: String,
+    name: String,
+    status: String,
+    running: bool,
+    cpu: f32,
+    memory_mb: u64,
+}
+
+struct Snapshot {
+    hosts: Vec<HostEntity>,
+    apps: Vec<AppEntity>,
+    system: Value,
+}
+
+async fn collect_snapshot(state: &ApiState, config: &MqttConfig) -> Snapshot {
+    let mut hosts = Vec::new();
+    if config.expose_hosts {
+        let data = crate::routes::hosts::load_hosts().await;
+        for host in data.get("hosts").and_then(|h| h.as_array()).into_iter().flatten() {
+            let Some(id) = host.get("id").and_then(|v| v.as_str()) else {
+                continue;
+            };
+            let name = host.get("name").and_then(|v| v.as_str()).unwrap_or(id).to_string();
+            let power_state = match &state.registry {
+                Some(registry) => registry.get_host_power_state(id).await,
+                None => HostPowerState::Offline,
+            };
+            hosts.push(HostEntity {
+                id: id.to_string(),
+                name,
+                power_state,
+            });
+        }
+    }
+
+    let mut apps = Vec::new();
+    if let (true, Some(registry)) = (config.expose_applications, &state.registry) {
+        for app in registry.list_applications().await {
+            let (running, cpu, memory_mb) = match &app.metrics {
+                Some(m) => (
+                    matches!(m.app_status, ServiceState::Running | ServiceState::Starting),
+                    m.cpu_percent,
+                    m.memory_bytes / (1024 * 1024),
+                ),
+                None => (false, 0.0, 0),
+            };
+            apps.push(AppEntity {
+                id: app.id,
+                name: app.name,
+                status: serde_json::to_value(app.status)
+                    .ok()
+                    .and_then(|v| v.as_str().map(str::to_string))
+                    .unwrap_or_default(),
+                running,
+                cpu,
+                memory_mb,
+            });
+        }
+    }
+
+    let (dns_queries, dns_blocked) = {
+        let dns = state.dns.read().await;
+        (
+            dns.stats.queries.load(Ordering::Relaxed),
+            dns.stats.blocked.load(Ordering::Relaxed),
+        )
+    };
+
+    // WAN: relay connected in relay mode, a global IPv6 on the WAN interface otherwise
+    let relay_enabled = *state.cloud_relay_enabled.borrow();
+    let relay_status = state
+        .cloud_relay_status
+        .read()
+        .await
+        .as_ref()
+        .map(|i| i.status);
+    let wan_ip = hr_ddns::detect::interface_ipv6(&state.env.cf_interface).await;
+    let wan_up = if relay_enabled {
+        matches!(relay_status, Some(CloudRelayStatus::Connected))
+    } else {
+        wan_ip.is_some()
+    };
+
+    Snapshot {
+        hosts,
+        apps,
+        system: json!({
+            "dns_queries": dns_queries,
+            "dns_blocked": dns_blocked,
+            "wan": if wan_up { "ON" } else { "OFF" },
+            "wan_ip": wan_ip,
+            "mode": if relay_enabled { "relay" } else { "direct" },
+            "relay_status": relay_status.map(|s| s.to_string()),
+        }),
+    }
+}
+
+async fn publish_json(client: &AsyncClient, topic: String, payload: &Value, retain: bool) -> Result<(), rumqttc::ClientError> {
+    client
+        .publish(topic, QoS::AtLeastOnce, retain, payload.to_string())
+        .await
+}
+
+async fn publish_snapshot(
+    client: &AsyncClient,
+    config: &MqttConfig,
+    topics: &Topics,
+    snapshot: &Snapshot,
+    announced: &mut HashSet<String>,
+) -> Result<(), rumqttc::ClientError> {
+    let mut configs = discovery::system_entities(topics);
+    for host in &snapshot.hosts {
+        configs.push(discovery::host_switch(topics, &host.id, &host.name, config.allow_commands));
+    }
+    for app in &snapshot.apps {
+        configs.push(discovery::app_switch(topics, &app.id, &app.name, config.allow_commands));
+        configs.extend(discovery::app_sensors(topics, &app.id, &app.name));
+    }
+
+    // Remove entities that disappeared (empty retained config), announce new ones
+    let current: HashSet<String> = configs.iter().map(|(t, _)| t.clone()).collect();
+    for stale in announced.difference(&current) {
+        client.publish(stale.clone(), QoS::AtLeastOnce, true, Vec::new()).await?;
+    }
+    announced.retain(|t| current.contains(t));
+    for (topic, payload) in configs {
+        if announced.insert(topic.clone()) {
+            publish_json(client, topic, &payload, true).await?;
+        }
+    }
+
+    publish_json(client, topics.system_state(), &snapshot.system, false).await?;
+    for host in &snapshot.hosts {
+        let on = host.power_state == HostPowerState::Online;
+        let payload = json!({
+            "state": if on { "ON" } else { "OFF" },
+            "power_state": host.power_state.to_string(),
+        });
+        publish_json(client, topics.state("host", &host.id), &payload, false).await?;
+    }
+    for app in &snapshot.apps {
+        let payload = json!({
+            "state": if app.running { "ON" } else { "OFF" },
+            "status": app.status,
+            "cpu": (app.cpu * 10.0).round() / 10.0,
+            "memory_mb": app.memory_mb,
+        });
+        publish_json(client, topics.state("app", &app.id), &payload, false).await?;
+    }
+    Ok(())
+}
+
+/// Resolve the sanitized object id from a topic back to the real id.
+async fn resolve_host_id(object: &str) -> Option<String> {
+    let data = crate::routes::hosts::load_hosts().await;
+    data.get("hosts")?
+        .as_array()?
+        .iter()
+        .filter_map(|h| h.get("id").and_then(|v| v.as_str()))
+        .find(|id| discovery::object_id(id) == object)
+        .map(str::to_string)
+}
+
+async fn resolve_app_id(state: &ApiState, object: &str) -> Option<String> {
+    let registry = state.registry.as_ref()?;
+    registry
+        .list_applications()
+        .await
+        .into_iter()
+        .map(|a| a.id)
+        .find(|id| discovery::object_id(id) == object)
+}
+
+async fn handle_command(state: &ApiState, cmd: MqttCommand) {
+    info!("MQTT command: {:?}", cmd);
+    let result: Value = match cmd {
+        MqttCommand::WakeHost(object) => match resolve_host_id(&object).await {
+            Some(id) => crate::routes::hosts::wake_host(state, &id).await.0,
+            None => json!({"success": false, "error": format!("Hote non trouve: {object}")}),
+        },
+        MqttCommand::ShutdownHost(object) => match resolve_host_id(&object).await {
+            Some(id) => crate::routes::hosts::power_off_host(state, &id).await.0,
+            None => json!({"success": false, "error": format!("Hote non trouve: {object}")}),
+        },
+        MqttCommand::StartApp(object) => app_service_command(state, &object, ServiceAction::Start).await,
+        MqttCommand::StopApp(object) => app_service_command(state, &object, ServiceAction::Stop).await,
+    };
+    if result.get("success").and_then(|v| v.as_bool()) != Some(true) {
+        warn!("MQTT command failed: {}", result);
+    }
+}
+
+async fn app_service_command(state: &ApiState, object: &str, action: ServiceAction) -> Value {
+    let Some(registry) = &state.registry else {
+        return json!({"success": false, "error": "Registry not available"});
+    };
+    let Some(id) = resolve_app_id(state, object).await else {
+        return json!({"success": false, "error": format!("Application introuvable: {object}")});
+    };
+    match registry.send_service_command(&id, ServiceType::App, action).await {
+        Ok(true) => json!({"success": true}),
+        Ok(false) => json!({"success": false, "error": "Application not found or not connected"}),
+        Err(e) => json!({"success": false, "error": e.to_string()}),
+    }
+}
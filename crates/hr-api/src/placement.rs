@@ -0,0 +1,347 @@
+//! Placement of new applications: picks the host of a container created
+//! without one (or with `host_id: "auto"`).
+//!
+//! Every known host is a candidate: HomeRoute itself and the hosts whose
+//! agent is connected. Hosts in maintenance, without the required labels
+//! (the host groups), too full or running an app the new one must avoid
+//! are excluded. The others are scored on their live metrics (idle CPU,
+//! free memory, free disk), on how few apps they run, and on the preferred
+//! labels and the apps the new one should sit next to. The preview
+//! endpoint returns the whole ranking with the reason of each score.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use hr_registry::protocol::HostMetrics;
+
+use crate::container_manager::ContainerManager;
+use crate::routes::hosts::{load_hosts, local_host_metrics};
+
+/// A host must keep this much disk free to receive a container.
+const MIN_FREE_DISK: u64 = 2 * 1024 * 1024 * 1024;
+/// Hosts with more memory in use than this receive nothing.
+const MAX_MEMORY_USED: f64 = 0.95;
+/// Free disk counted as "plenty" when only the free bytes are known.
+const PLENTY_OF_DISK: u64 = 50 * 1024 * 1024 * 1024;
+
+const CPU_WEIGHT: f64 = 30.0;
+const MEMORY_WEIGHT: f64 = 30.0;
+const DISK_WEIGHT: f64 = 20.0;
+const APPS_WEIGHT: f64 = 20.0;
+const PREFERRED_LABEL_BONUS: f64 = 10.0;
+const AFFINITY_BONUS: f64 = 15.0;
+
+/// Constraints of a new application on its host.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlacementRequest {
+    /// Labels (host groups) the host must have.
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Labels counting in favour of a host.
+    #[serde(default)]
+    pub preferred_labels: Vec<String>,
+    /// Apps the new one should run next to.
+    #[serde(default)]
+    pub affinity: Vec<String>,
+    /// Apps the new one must not share a host with.
+    #[serde(default)]
+    pub anti_affinity: Vec<String>,
+}
+
+/// What placement knows about a host.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub host_id: String,
+    pub name: String,
+    pub labels: Vec<String>,
+    pub online: bool,
+    pub draining: bool,
+    pub metrics: Option<HostMetrics>,
+    /// Free disk when the metrics have none (HomeRoute's storage path).
+    pub free_disk_bytes: Option<u64>,
+    /// Apps (container ids) on the host.
+    pub apps: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HostScore {
+    pub host_id: String,
+    pub name: String,
+    pub eligible: bool,
+    pub score: f64,
+    pub reasons: Vec<String>,
+}
+
+/// Ranking of the hosts, best first.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlacementDecision {
+    /// Chosen host; None when no host is eligible.
+    pub host_id: Option<String>,
+    pub hosts: Vec<HostScore>,
+}
+
+impl PlacementDecision {
+    /// The chosen host, or why there is none.
+    pub fn chosen(&self) -> Result<String, String> {
+        self.host_id.clone().ok_or_else(|| {
+            let reasons: Vec<String> = self
+                .hosts
+                .iter()
+                .map(|h| format!("{}: {}", h.name, h.reasons.join(", ")))
+                .collect();
+            format!("No host can receive the application ({})", reasons.join("; "))
+        })
+    }
+}
+
+/// Score every candidate for `req`.
+pub fn rank(candidates: &[Candidate], req: &PlacementRequest) -> PlacementDecision {
+    let mut hosts: Vec<HostScore> = candidates.iter().map(|c| score(c, req)).collect();
+    hosts.sort_by(|a, b| b.eligible.cmp(&a.eligible).then(b.score.total_cmp(&a.score)));
+    PlacementDecision {
+        host_id: hosts.first().filter(|h| h.eligible).map(|h| h.host_id.clone()),
+        hosts,
+    }
+}
+
+fn score(c: &Candidate, req: &PlacementRequest) -> HostScore {
+    let mut excluded = Vec::new();
+    let mut reasons = Vec::new();
+    if !c.online {
+        excluded.push("offline".to_string());
+    }
+    if c.draining {
+        excluded.push("in maintenance".to_string());
+    }
+    let missing: Vec<&str> = req
+        .labels
+        .iter()
+        .filter(|l| !c.labels.contains(l))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        excluded.push(format!("missing labels {}", missing.join(", ")));
+    }
+    let avoided: Vec<&str> = req
+        .anti_affinity
+        .iter()
+        .filter(|a| c.apps.contains(a))
+        .map(String::as_str)
+        .collect();
+    if !avoided.is_empty() {
+        excluded.push(format!("runs {}", avoided.join(", ")));
+    }
+
+    let mut score = 0.0;
+    match &c.metrics {
+        Some(m) => {
+            let cpu = f64::from(m.cpu_percent).clamp(0.0, 100.0);
+            score += CPU_WEIGHT * (1.0 - cpu / 100.0);
+            reasons.push(format!("CPU {cpu:.0}%"));
+
+            if m.memory_total_bytes > 0 {
+                let used = m.memory_used_bytes as f64 / m.memory_total_bytes as f64;
+                if used > MAX_MEMORY_USED {
+                    excluded.push(format!("memory {:.0}% used", used * 100.0));
+                }
+                score += MEMORY_WEIGHT * (1.0 - used).max(0.0);
+                reasons.push(format!("memory {:.0}% used", used * 100.0));
+            }
+        }
+        None => {
+            // Unknown load: middle of the range rather than best or worst
+            score += (CPU_WEIGHT + MEMORY_WEIGHT) / 2.0;
+            reasons.push("no metrics".to_string());
+        }
+    }
+
+    let disk = c.metrics.as_ref().filter(|m| m.disk_total_bytes > 0).map(|m| {
+        let free = m.disk_total_bytes.saturating_sub(m.disk_used_bytes);
+        (free, free as f64 / m.disk_total_bytes as f64)
+    });
+    let disk = disk.or_else(|| {
+        c.free_disk_bytes
+            .map(|free| (free, (free as f64 / PLENTY_OF_DISK as f64).min(1.0)))
+    });
+    match disk {
+        Some((free, fraction)) => {
+            if free < MIN_FREE_DISK {
+                excluded.push(format!("{} MiB of disk free", free / (1024 * 1024)));
+            }
+            score += DISK_WEIGHT * fraction;
+            reasons.push(format!("{} GiB of disk free", free / (1024 * 1024 * 1024)));
+        }
+        None => score += DISK_WEIGHT / 2.0,
+    }
+
+    score += APPS_WEIGHT / (1.0 + c.apps.len() as f64);
+    reasons.push(format!("{} apps", c.apps.len()));
+
+    for label in req.preferred_labels.iter().filter(|l| c.labels.contains(l)) {
+        score += PREFERRED_LABEL_BONUS;
+        reasons.push(format!("label {label}"));
+    }
+    for app in req.affinity.iter().filter(|a| c.apps.contains(a)) {
+        score += AFFINITY_BONUS;
+        reasons.push(format!("next to {app}"));
+    }
+
+    let eligible = excluded.is_empty();
+    if !eligible {
+        reasons = excluded;
+    }
+    HostScore {
+        host_id: c.host_id.clone(),
+        name: c.name.clone(),
+        eligible,
+        score: (score * 10.0).round() / 10.0,
+        reasons,
+    }
+}
+
+/// HomeRoute and the hosts of hosts.json, as they are now.
+pub async fn candidates(cm: &ContainerManager) -> Vec<Candidate> {
+    let mut apps: HashMap<String, Vec<String>> = HashMap::new();
+    for record in cm.records().await {
+        apps.entry(record.host_id).or_default().push(record.id);
+    }
+
+    let storage_path = cm.resolve_storage_path("local").await;
+    let local_metrics = local_host_metrics().await;
+    let local_free = hr_container::preflight::probe(&storage_path, None, None).await.free_bytes;
+    let mut candidates = vec![Candidate {
+        host_id: "local".to_string(),
+        name: "HomeRoute".to_string(),
+        labels: Vec::new(),
+        online: true,
+        draining: false,
+        metrics: local_metrics,
+        free_disk_bytes: local_free,
+        apps: apps.remove("local").unwrap_or_default(),
+    }];
+
+    let data = load_hosts().await;
+    let hosts = data.get("hosts").and_then(|h| h.as_array()).cloned().unwrap_or_default();
+    let conns = cm.registry.host_connections.read().await;
+    for host in hosts {
+        let Some(id) = host.get("id").and_then(|i| i.as_str()).map(str::to_string) else {
+            continue;
+        };
+        let conn = conns.get(&id);
+        candidates.push(Candidate {
+            name: host.get("name").and_then(|n| n.as_str()).unwrap_or(&id).to_string(),
+            labels: host
+                .get("groups")
+                .and_then(|g| serde_json::from_value(g.clone()).ok())
+                .unwrap_or_default(),
+            online: conn.is_some(),
+            draining: host.get("draining").and_then(|d| d.as_bool()).unwrap_or(false),
+            metrics: conn.and_then(|c| c.metrics.clone()),
+            free_disk_bytes: None,
+            apps: apps.remove(&id).unwrap_or_default(),
+            host_id: id,
+        });
+    }
+    candidates
+}
+
+/// Rank the current hosts for `req`.
+pub async fn decide(cm: &ContainerManager, req: &PlacementRequest) -> PlacementDecision {
+    rank(&candidates(cm).await, req)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn host(id: &str, cpu: f32, memory_used: f64, apps: &[&str]) -> Candidate {
+        Candidate {
+            host_id: id.to_string(),
+            name: id.to_string(),
+            labels: Vec::new(),
+            online: true,
+            draining: false,
+            metrics: Some(HostMetrics {
+                cpu_percent: cpu,
+                memory_used_bytes: (memory_used * 16.0 * GIB as f64) as u64,
+                memory_total_bytes: 16 * GIB,
+                disk_used_bytes: 100 * GIB,
+                disk_total_bytes: 500 * GIB,
+                load_avg: [0.0; 3],
+                ..Default::default()
+            }),
+            free_disk_bytes: None,
+            apps: apps.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_rank_prefers_idle_host() {
+        let hosts = [host("busy", 90.0, 0.8, &["a", "b"]), host("idle", 5.0, 0.2, &[])];
+        let decision = rank(&hosts, &PlacementRequest::default());
+        assert_eq!(decision.host_id.as_deref(), Some("idle"));
+        assert_eq!(decision.hosts[0].host_id, "idle");
+        assert!(decision.hosts[0].score > decision.hosts[1].score);
+    }
+
+    #[test]
+    fn test_rank_exclusions() {
+        let mut offline = host("offline", 0.0, 0.0, &[]);
+        offline.online = false;
+        let mut draining = host("draining", 0.0, 0.0, &[]);
+        draining.draining = true;
+        let full = host("full", 0.0, 0.99, &[]);
+        let mut no_disk = host("no-disk", 0.0, 0.0, &[]);
+        no_disk.metrics.as_mut().unwrap().disk_used_bytes = 499 * GIB;
+        let busy = host("busy", 95.0, 0.9, &[]);
+
+        let decision = rank(&[offline, draining, full, no_disk, busy], &PlacementRequest::default());
+        assert_eq!(decision.host_id.as_deref(), Some("busy"));
+        assert_eq!(decision.hosts.iter().filter(|h| !h.eligible).count(), 4);
+
+        let decision = rank(&[host("x", 0.0, 0.0, &[])], &PlacementRequest {
+            labels: vec!["gpu".to_string()],
+            ..Default::default()
+        });
+        assert_eq!(decision.host_id, None);
+        assert!(decision.chosen().unwrap_err().contains("missing labels gpu"));
+    }
+
+    #[test]
+    fn test_rank_labels_and_affinity() {
+        let mut gpu = host("gpu", 20.0, 0.3, &["db"]);
+        gpu.labels = vec!["gpu".to_string()];
+        let plain = host("plain", 10.0, 0.1, &[]);
+
+        let req = PlacementRequest {
+            preferred_labels: vec!["gpu".to_string()],
+            affinity: vec!["db".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(rank(&[plain.clone(), gpu.clone()], &req).host_id.as_deref(), Some("gpu"));
+
+        let req = PlacementRequest { anti_affinity: vec!["db".to_string()], ..Default::default() };
+        let decision = rank(&[gpu, plain], &req);
+        assert_eq!(decision.host_id.as_deref(), Some("plain"));
+        assert!(decision.hosts[1].reasons.iter().any(|r| r == "runs db"));
+    }
+
+    #[test]
+    fn test_rank_without_metrics() {
+        let mut local = host("local", 0.0, 0.0, &[]);
+        local.metrics = None;
+        local.free_disk_bytes = Some(GIB);
+        let decision = rank(&[local], &PlacementRequest::default());
+        assert_eq!(decision.host_id, None);
+
+        let mut local = host("local", 0.0, 0.0, &[]);
+        local.metrics = None;
+        local.free_disk_bytes = Some(100 * GIB);
+        let decision = rank(&[local], &PlacementRequest::default());
+        assert_eq!(decision.host_id.as_deref(), Some("local"));
+        assert!(decision.hosts[0].reasons.contains(&"no metrics".to_string()));
+    }
+}
@@ -0,0 +1,379 @@
+//! Subprocess plugins mounted under `/api/ext/{name}`.
+//!
+//! Each plugin lives in `{data_dir}/plugins/{name}/` with a `plugin.json`
+//! manifest. For every request the plugin command is spawned in its directory,
+//! receives one JSON [`PluginRequest`] on stdin and must print one JSON
+//! [`PluginResponse`] on stdout before the timeout.
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{info, warn};
+
+/// Version of the stdin/stdout contract sent in every request.
+pub const PLUGIN_RPC_VERSION: u32 = 1;
+
+/// Maximum size of a plugin response on stdout.
+const MAX_OUTPUT_BYTES: usize = 10 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginManifest {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Executable, absolute or relative to the plugin directory.
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Reject requests without a valid session.
+    #[serde(default = "default_true")]
+    pub require_auth: bool,
+    /// Reject requests from non-admin users.
+    #[serde(default)]
+    pub require_admin: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Directory the manifest was loaded from (not part of the file).
+    #[serde(skip_deserializing)]
+    pub dir: PathBuf,
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+fn default_true() -> bool {
+    true
+}
+
+/// User attached to a plugin request when a session cookie is present.
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginUser {
+    pub username: String,
+    pub groups: Vec<String>,
+}
+
+/// Request written to the plugin's stdin.
+#[derive(Debug, Serialize)]
+pub struct PluginRequest {
+    pub version: u32,
+    pub plugin: String,
+    pub method: String,
+    /// Path below `/api/ext/{name}`, always starting with `/`.
+    pub path: String,
+    pub query: String,
+    pub headers: BTreeMap<String, String>,
+    pub body_base64: String,
+    pub user: Option<PluginUser>,
+}
+
+/// Response read from the plugin's stdout.
+#[derive(Debug, Deserialize)]
+pub struct PluginResponse {
+    #[serde(default = "default_status")]
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// String bodies are sent as-is, any other JSON value is serialized.
+    #[serde(default)]
+    pub body: Option<serde_json::Value>,
+    /// Binary body (takes precedence over `body`).
+    #[serde(default)]
+    pub body_base64: Option<String>,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+/// Decoded plugin response: status, headers, body.
+pub type PluginReply = (u16, BTreeMap<String, String>, Vec<u8>);
+
+impl PluginResponse {
+    /// Decode the body and pick a default content type.
+    pub fn into_parts(self) -> Result<PluginReply, String> {
+        let mut headers = self.headers;
+        let (body, default_type) = if let Some(b64) = self.body_base64 {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(b64)
+                .map_err(|e| format!("body_base64 invalide: {}", e))?;
+            (bytes, "application/octet-stream")
+        } else {
+            match self.body {
+                None | Some(serde_json::Value::Null) => (Vec::new(), "text/plain; charset=utf-8"),
+                Some(serde_json::Value::String(s)) => (s.into_bytes(), "text/plain; charset=utf-8"),
+                Some(v) => (v.to_string().into_bytes(), "application/json"),
+            }
+        };
+        if !headers.keys().any(|k| k.eq_ignore_ascii_case("content-type")) {
+            headers.insert("content-type".to_string(), default_type.to_string());
+        }
+        Ok((self.status, headers, body))
+    }
+}
+
+/// Plugin names double as URL segments and directory names.
+pub fn is_valid_plugin_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Registry of plugins discovered in the plugins directory.
+pub struct PluginRegistry {
+    dir: PathBuf,
+    plugins: RwLock<HashMap<String, PluginManifest>>,
+}
+
+impl PluginRegistry {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            plugins: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Rescan the plugins directory. Invalid manifests are skipped and reported.
+    pub fn reload(&self) -> Vec<String> {
+        let mut found = HashMap::new();
+        let mut errors = Vec::new();
+
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(e) => e,
+            Err(_) => {
+                *self.plugins.write().unwrap() = found;
+                return errors;
+            }
+        };
+
+        for entry in entries.flatten() {
+            let dir = entry.path();
+            let manifest_path = dir.join("plugin.json");
+            if !manifest_path.is_file() {
+                continue;
+            }
+            match load_manifest(&manifest_path, &dir) {
+                Ok(manifest) => {
+                    found.insert(manifest.name.clone(), manifest);
+                }
+                Err(e) => {
+                    warn!("Skipping plugin {}: {}", dir.display(), e);
+                    errors.push(format!("{}: {}", dir.display(), e));
+                }
+            }
+        }
+
+        info!("Loaded {} API plugins from {}", found.len(), self.dir.display());
+        *self.plugins.write().unwrap() = found;
+        errors
+    }
+
+    pub fn get(&self, name: &str) -> Option<PluginManifest> {
+        self.plugins.read().unwrap().get(name).cloned()
+    }
+
+    pub fn list(&self) -> Vec<PluginManifest> {
+        let mut list: Vec<_> = self.plugins.read().unwrap().values().cloned().collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+}
+
+fn load_manifest(path: &Path, dir: &Path) -> Result<PluginManifest, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut manifest: PluginManifest =
+        serde_json::from_str(&content).map_err(|e| format!("plugin.json invalide: {}", e))?;
+    if !is_valid_plugin_name(&manifest.name) {
+        return Err(format!("nom de plugin invalide: {}", manifest.name));
+    }
+    if manifest.command.trim().is_empty() {
+        return Err("command requis".to_string());
+    }
+    manifest.dir = dir.to_path_buf();
+    Ok(manifest)
+}
+
+/// Run one request through a plugin process.
+pub async fn invoke(manifest: &PluginManifest, request: &PluginRequest) -> Result<PluginResponse, String> {
+    let command = if Path::new(&manifest.command).is_absolute() {
+        PathBuf::from(&manifest.command)
+    } else {
+        manifest.dir.join(&manifest.command)
+    };
+
+    let mut child = tokio::process::Command::new(&command)
+        .args(&manifest.args)
+        .envs(&manifest.env)
+        .env("HOMEROUTE_PLUGIN", &manifest.name)
+        .current_dir(&manifest.dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to spawn {}: {}", command.display(), e))?;
+
+    let input = serde_json::to_vec(request).map_err(|e| e.to_string())?;
+    let mut stdin = child.stdin.take().ok_or("stdin unavailable")?;
+    let mut stdout = child.stdout.take().ok_or("stdout unavailable")?;
+    let mut stderr = child.stderr.take().ok_or("stderr unavailable")?;
+
+    let run = async {
+        // Write the request from its own task and close stdin so the plugin
+        // sees EOF. A plugin exiting without reading it is reported through
+        // its exit status.
+        let writer = tokio::spawn(async move {
+            match stdin.write_all(&input).await {
+                Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e.to_string()),
+                _ => Ok(()),
+            }
+        });
+        let write = async { writer.await.map_err(|e| e.to_string())? };
+
+        // Drain both pipes at once so a plugin filling one of them while we
+        // wait on the other cannot block.
+        let read_stdout = async {
+            let mut output = Vec::new();
+            let mut limited = (&mut stdout).take(MAX_OUTPUT_BYTES as u64 + 1);
+            limited.read_to_end(&mut output).await.map_err(|e| e.to_string())?;
+            if output.len() > MAX_OUTPUT_BYTES {
+                return Err("plugin output too large".to_string());
+            }
+            Ok(output)
+        };
+        let read_stderr = async {
+            // Keep the first 4 KiB for the error message, discard the rest.
+            let mut err = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = stderr.read(&mut buf).await.map_err(|e| e.to_string())?;
+                if n == 0 {
+                    break;
+                }
+                let keep = n.min(4096 - err.len());
+                err.extend_from_slice(&buf[..keep]);
+            }
+            Ok::<_, String>(err)
+        };
+
+        let ((), output, err) = tokio::try_join!(write, read_stdout, read_stderr)?;
+
+        let status = child.wait().await.map_err(|e| e.to_string())?;
+        if !status.success() {
+            let err = String::from_utf8_lossy(&err);
+            return Err(format!("plugin exited with {}: {}", status, err.trim()));
+        }
+        Ok(output)
+    };
+
+    let output = tokio::time::timeout(Duration::from_millis(manifest.timeout_ms), run)
+        .await
+        .map_err(|_| format!("plugin timed out after {} ms", manifest.timeout_ms))??;
+
+    serde_json::from_slice(&output).map_err(|e| format!("invalid plugin response: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(dir: &Path, script: &str) -> PluginManifest {
+        PluginManifest {
+            name: "test".to_string(),
+            description: String::new(),
+            command: "/bin/sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            env: BTreeMap::new(),
+            timeout_ms: 2000,
+            require_auth: false,
+            require_admin: false,
+            enabled: true,
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    fn request() -> PluginRequest {
+        PluginRequest {
+            version: PLUGIN_RPC_VERSION,
+            plugin: "test".to_string(),
+            method: "GET".to_string(),
+            path: "/hello".to_string(),
+            query: String::new(),
+            headers: BTreeMap::new(),
+            body_base64: String::new(),
+            user: None,
+        }
+    }
+
+    #[test]
+    fn test_plugin_names() {
+        assert!(is_valid_plugin_name("home-assistant_2"));
+        assert!(!is_valid_plugin_name(""));
+        assert!(!is_valid_plugin_name("../etc"));
+        assert!(!is_valid_plugin_name("Upper"));
+    }
+
+    #[test]
+    fn test_response_body_defaults() {
+        let resp: PluginResponse = serde_json::from_str(r#"{"body":{"ok":true}}"#).unwrap();
+        let (status, headers, body) = resp.into_parts().unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(headers["content-type"], "application/json");
+        assert_eq!(body, br#"{"ok":true}"#);
+
+        let resp: PluginResponse =
+            serde_json::from_str(r#"{"status":201,"headers":{"Content-Type":"text/html"},"body":"<p>hi</p>"}"#)
+                .unwrap();
+        let (status, headers, body) = resp.into_parts().unwrap();
+        assert_eq!(status, 201);
+        assert_eq!(headers.len(), 1);
+        assert_eq!(body, b"<p>hi</p>");
+    }
+
+    #[tokio::test]
+    async fn test_invoke_roundtrip() {
+        let m = manifest(
+            &std::env::temp_dir(),
+            r#"read req; echo "{\"status\":200,\"body\":\"$HOMEROUTE_PLUGIN\"}""#,
+        );
+        let resp = invoke(&m, &request()).await.unwrap();
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.body, Some(serde_json::json!("test")));
+    }
+
+    #[tokio::test]
+    async fn test_invoke_timeout_and_failure() {
+        let mut m = manifest(&std::env::temp_dir(), "sleep 5");
+        m.timeout_ms = 100;
+        assert!(invoke(&m, &request()).await.unwrap_err().contains("timed out"));
+
+        let m = manifest(&std::env::temp_dir(), "echo boom >&2; exit 3");
+        assert!(invoke(&m, &request()).await.unwrap_err().contains("boom"));
+    }
+
+    #[tokio::test]
+    async fn test_invoke_drains_stderr_before_exit() {
+        // More stderr than a pipe buffer holds, written before stdin is read.
+        let m = manifest(
+            &std::env::temp_dir(),
+            r#"head -c 200000 /dev/zero >&2; cat >/dev/null; echo '{"status":204}'"#,
+        );
+        let mut req = request();
+        req.body_base64 = "A".repeat(200_000);
+        assert_eq!(invoke(&m, &req).await.unwrap().status, 204);
+    }
+}
@@ -0,0 +1,723 @@
+//! Process apps: applications run on the HomeRoute host as a supervised
+//! process (or an existing systemd unit) instead of a container, with the
+//! same routes, certificates and auth as the containerized apps.
+//!
+//! There is no agent: the manager publishes the routes itself (to
+//! `127.0.0.1:{target_port}`), health-checks the app and reports its status
+//! to the registry (`connected` once healthy).
+
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::{watch, RwLock};
+use tracing::{info, warn};
+
+use hr_common::config::EnvConfig;
+use hr_proxy::{AppRoute, ProxyState};
+use hr_registry::types::{
+    AgentStatus, Application, CreateApplicationRequest, Environment, FrontendEndpoint, ProcessSpec,
+    UpdateApplicationRequest,
+};
+use hr_registry::AgentRegistry;
+
+/// Output lines kept per app.
+pub const LOG_LINES: usize = 500;
+
+const HEALTH_INTERVAL: Duration = Duration::from_secs(10);
+/// Time to become healthy before failed checks count.
+const STARTUP_GRACE: Duration = Duration::from_secs(60);
+/// Consecutive failed checks before a restart.
+const UNHEALTHY_RESTART: u32 = 3;
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// SIGTERM, then SIGKILL after this long.
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// ── Types ────────────────────────────────────────────────────────
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProcessRecord {
+    pub id: String,
+    pub slug: String,
+    /// Agent token given to the process (`HOMEROUTE_TOKEN`), for the app
+    /// APIs (mail, storage, queues...).
+    pub token: String,
+    /// Started at boot and restarted when it exits.
+    pub running: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ProcessState {
+    #[serde(default)]
+    processes: Vec<ProcessRecord>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessStatus {
+    Stopped,
+    Starting,
+    Running,
+    Unhealthy,
+    /// Exited or restarted after failed health checks, restart pending.
+    Crashed,
+}
+
+struct Runtime {
+    status: ProcessStatus,
+    pid: Option<u32>,
+    restarts: u32,
+    started_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    logs: VecDeque<String>,
+    stop: Option<watch::Sender<bool>>,
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self {
+            status: ProcessStatus::Stopped,
+            pid: None,
+            restarts: 0,
+            started_at: None,
+            last_error: None,
+            logs: VecDeque::new(),
+            stop: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateProcessRequest {
+    pub name: String,
+    pub slug: String,
+    pub frontend: FrontendEndpoint,
+    pub process: ProcessSpec,
+    #[serde(default = "default_true")]
+    pub start: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct UpdateProcessRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub frontend: Option<FrontendEndpoint>,
+    #[serde(default)]
+    pub process: Option<ProcessSpec>,
+}
+
+/// How a supervised run ended.
+enum Exit {
+    Stopped,
+    Failed { reason: String, was_healthy: bool },
+}
+
+/// The running app: a child process or a systemd unit.
+enum Handle {
+    Child(Child),
+    Unit(String),
+}
+
+impl Handle {
+    /// Resolves when the child exits; units are polled in the health loop.
+    async fn exited(&mut self) -> String {
+        match self {
+            Handle::Child(child) => match child.wait().await {
+                Ok(status) => format!("exited ({})", status),
+                Err(e) => format!("wait failed: {}", e),
+            },
+            Handle::Unit(_) => std::future::pending().await,
+        }
+    }
+
+    async fn terminate(&mut self) {
+        match self {
+            Handle::Child(child) => {
+                if let Some(pid) = child.id() {
+                    let _ = Command::new("kill").args(["-TERM", &pid.to_string()]).status().await;
+                }
+                if tokio::time::timeout(STOP_TIMEOUT, child.wait()).await.is_err() {
+                    let _ = child.kill().await;
+                }
+            }
+            Handle::Unit(unit) => {
+                let _ = systemctl("stop", unit).await;
+            }
+        }
+    }
+}
+
+async fn systemctl(action: &str, unit: &str) -> Result<(), String> {
+    let output = Command::new("systemctl")
+        .args([action, unit])
+        .output()
+        .await
+        .map_err(|e| format!("systemctl: {}", e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// Program and arguments of a command spec, through `runuser` for `user`.
+pub fn command_line(spec: &ProcessSpec) -> Option<(String, Vec<String>)> {
+    let (program, args) = spec.command.split_first()?;
+    match &spec.user {
+        Some(user) => {
+            let mut full = vec!["-u".to_string(), user.clone(), "--".to_string(), program.clone()];
+            full.extend(args.iter().cloned());
+            Some(("runuser".to_string(), full))
+        }
+        None => Some((program.clone(), args.to_vec())),
+    }
+}
+
+/// Delay before restart number `failures` (1, 2, 4... up to a minute).
+pub fn backoff(failures: u32) -> Duration {
+    Duration::from_secs(1u64 << failures.saturating_sub(1).min(6)).min(MAX_BACKOFF)
+}
+
+/// Health of the app listening on `port`: HTTP status below 400 on
+/// `health_path`, or a TCP connect.
+async fn probe(port: u16, health_path: Option<&str>) -> bool {
+    match health_path {
+        Some(path) => {
+            let client = reqwest::Client::new();
+            client
+                .get(format!("http://127.0.0.1:{}{}", port, path))
+                .timeout(Duration::from_secs(5))
+                .send()
+                .await
+                .is_ok_and(|r| r.status().as_u16() < 400)
+        }
+        None => tokio::time::timeout(
+            Duration::from_secs(2),
+            tokio::net::TcpStream::connect(("127.0.0.1", port)),
+        )
+        .await
+        .is_ok_and(|r| r.is_ok()),
+    }
+}
+
+// ── ProcessManager ───────────────────────────────────────────────
+
+pub struct ProcessManager {
+    state: RwLock<ProcessState>,
+    state_path: PathBuf,
+    env: Arc<EnvConfig>,
+    registry: Arc<AgentRegistry>,
+    proxy: Arc<ProxyState>,
+    runtimes: Mutex<HashMap<String, Runtime>>,
+}
+
+impl ProcessManager {
+    /// Load the process apps state from disk.
+    pub fn new(
+        state_path: PathBuf,
+        env: Arc<EnvConfig>,
+        registry: Arc<AgentRegistry>,
+        proxy: Arc<ProxyState>,
+    ) -> Self {
+        let state = match std::fs::read_to_string(&state_path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Failed to parse process apps state, starting fresh: {e}");
+                ProcessState::default()
+            }),
+            Err(_) => ProcessState::default(),
+        };
+        info!(processes = state.processes.len(), "Loaded process apps state");
+        Self {
+            state: RwLock::new(state),
+            state_path,
+            env,
+            registry,
+            proxy,
+            runtimes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start the process apps that were running before a restart.
+    pub async fn restore(self: &Arc<Self>) {
+        let ids: Vec<String> = {
+            let state = self.state.read().await;
+            state.processes.iter().filter(|p| p.running).map(|p| p.id.clone()).collect()
+        };
+        for id in ids {
+            self.spawn_supervisor(&id);
+        }
+    }
+
+    /// Persist state to disk (atomic write, the tokens make it 0600).
+    async fn save_state(&self) -> Result<(), String> {
+        let state = self.state.read().await;
+        let json = serde_json::to_string_pretty(&*state).map_err(|e| e.to_string())?;
+        let tmp = self.state_path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, &json).await.map_err(|e| e.to_string())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = tokio::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600)).await;
+        }
+        tokio::fs::rename(&tmp, &self.state_path).await.map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    async fn record(&self, id: &str) -> Option<ProcessRecord> {
+        self.state.read().await.processes.iter().find(|p| p.id == id).cloned()
+    }
+
+    async fn set_running(&self, id: &str, running: bool) {
+        {
+            let mut state = self.state.write().await;
+            if let Some(p) = state.processes.iter_mut().find(|p| p.id == id) {
+                p.running = running;
+            }
+        }
+        let _ = self.save_state().await;
+    }
+
+    // ── CRUD ─────────────────────────────────────────────────────
+
+    /// Register the app (production, on the local host), publish its
+    /// routes, request its certificate and start it.
+    pub async fn create(self: &Arc<Self>, req: CreateProcessRequest) -> Result<(ProcessRecord, String), String> {
+        req.process.validate()?;
+        let valid_slug = (1..=63).contains(&req.slug.len())
+            && !req.slug.starts_with('-')
+            && req.slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if !valid_slug {
+            return Err(format!("Invalid slug: {}", req.slug));
+        }
+        let apps = self.registry.list_applications().await;
+        if apps.iter().any(|a| a.slug == req.slug && a.environment == Environment::Production) {
+            return Err(format!("Slug already in use: {}", req.slug));
+        }
+        let create_req = CreateApplicationRequest {
+            name: req.name,
+            slug: req.slug.clone(),
+            host_id: Some("local".to_string()),
+            frontend: req.frontend,
+            environment: Environment::Production,
+            linked_app_id: None,
+            code_server_enabled: false,
+            services: Default::default(),
+            power_policy: Default::default(),
+            wake_page_enabled: true,
+            process: Some(req.process),
+        };
+        let (app, token) = self
+            .registry
+            .create_application_headless(create_req)
+            .await
+            .map_err(|e| format!("Failed to create application record: {e}"))?;
+
+        let record = ProcessRecord {
+            id: app.id.clone(),
+            slug: app.slug.clone(),
+            token: token.clone(),
+            running: req.start,
+            created_at: Utc::now(),
+        };
+        self.state.write().await.processes.push(record.clone());
+        self.save_state().await?;
+
+        self.publish_routes(&app);
+        let registry = self.registry.clone();
+        let slug = app.slug.clone();
+        tokio::spawn(async move { registry.request_app_cert(&slug).await });
+
+        if req.start {
+            self.spawn_supervisor(&app.id);
+        } else {
+            self.registry.set_app_status(&app.id, AgentStatus::Disconnected, None).await;
+        }
+        info!(slug = app.slug, "Process app created");
+        Ok((record, token))
+    }
+
+    /// Update the name, endpoint or process; a running app is restarted
+    /// when its process or port changes.
+    pub async fn update(self: &Arc<Self>, id: &str, req: UpdateProcessRequest) -> Result<bool, String> {
+        let Some(record) = self.record(id).await else {
+            return Ok(false);
+        };
+        if let Some(spec) = &req.process {
+            spec.validate()?;
+        }
+        let Some(before) = self.registry.get_application(id).await else {
+            return Ok(false);
+        };
+        let update_req = UpdateApplicationRequest {
+            name: req.name,
+            frontend: req.frontend,
+            process: req.process,
+            ..Default::default()
+        };
+        let Some(app) = self.registry.update_application(id, update_req).await.map_err(|e| e.to_string())? else {
+            return Ok(false);
+        };
+        self.publish_routes(&app);
+        let restart = app.process != before.process || app.frontend.target_port != before.frontend.target_port;
+        if restart && record.running {
+            self.stop_supervisor(id).await;
+            self.spawn_supervisor(id);
+        }
+        info!(id, "Process app updated via API");
+        Ok(true)
+    }
+
+    /// Stop the app, drop its routes and its registry entry.
+    pub async fn remove(&self, id: &str) -> Result<bool, String> {
+        if self.record(id).await.is_none() {
+            return Ok(false);
+        }
+        self.stop_supervisor(id).await;
+        if let Some(app) = self.registry.get_application(id).await {
+            for domain in app.domains(&self.env.base_domain) {
+                self.proxy.remove_app_route(&domain);
+            }
+        }
+        let _ = self.registry.remove_application(id).await;
+        self.runtimes.lock().unwrap().remove(id);
+        self.state.write().await.processes.retain(|p| p.id != id);
+        self.save_state().await?;
+        info!(id, "Process app removed");
+        Ok(true)
+    }
+
+    pub async fn start(self: &Arc<Self>, id: &str) -> Result<bool, String> {
+        if self.record(id).await.is_none() {
+            return Ok(false);
+        }
+        self.set_running(id, true).await;
+        self.spawn_supervisor(id);
+        Ok(true)
+    }
+
+    pub async fn stop(&self, id: &str) -> Result<bool, String> {
+        if self.record(id).await.is_none() {
+            return Ok(false);
+        }
+        self.set_running(id, false).await;
+        self.stop_supervisor(id).await;
+        Ok(true)
+    }
+
+    pub async fn restart(self: &Arc<Self>, id: &str) -> Result<bool, String> {
+        if self.record(id).await.is_none() {
+            return Ok(false);
+        }
+        self.set_running(id, true).await;
+        self.stop_supervisor(id).await;
+        self.spawn_supervisor(id);
+        Ok(true)
+    }
+
+    /// All process apps with their spec, endpoint and live status.
+    pub async fn list(&self) -> Vec<serde_json::Value> {
+        let records = self.state.read().await.processes.clone();
+        let apps = self.registry.list_applications().await;
+        let runtimes = self.runtimes.lock().unwrap();
+        records
+            .iter()
+            .map(|record| {
+                let app = apps.iter().find(|a| a.id == record.id);
+                let runtime = runtimes.get(&record.id);
+                serde_json::json!({
+                    "id": record.id,
+                    "slug": record.slug,
+                    "name": app.map(|a| a.name.clone()),
+                    "running": record.running,
+                    "created_at": record.created_at,
+                    "frontend": app.map(|a| &a.frontend),
+                    "process": app.and_then(|a| a.process.as_ref()),
+                    "agent_status": app.map(|a| &a.status),
+                    "status": runtime.map_or(ProcessStatus::Stopped, |r| r.status),
+                    "pid": runtime.and_then(|r| r.pid),
+                    "restarts": runtime.map_or(0, |r| r.restarts),
+                    "started_at": runtime.and_then(|r| r.started_at),
+                    "last_error": runtime.and_then(|r| r.last_error.clone()),
+                })
+            })
+            .collect()
+    }
+
+    /// Last output lines (stdout and stderr) of a command app.
+    pub async fn logs(&self, id: &str) -> Option<Vec<String>> {
+        self.record(id).await?;
+        let runtimes = self.runtimes.lock().unwrap();
+        Some(runtimes.get(id).map(|r| r.logs.iter().cloned().collect()).unwrap_or_default())
+    }
+
+    // ── Routing ──────────────────────────────────────────────────
+
+    fn publish_routes(&self, app: &Application) {
+        let base_domain = &self.env.base_domain;
+        for route in app.routes(base_domain) {
+            self.proxy.set_app_route(
+                route.domain,
+                AppRoute {
+                    app_id: app.id.clone(),
+                    host_id: app.host_id.clone(),
+                    target_ip: std::net::Ipv4Addr::LOCALHOST,
+                    target_port: route.target_port,
+                    auth_required: route.auth_required,
+                    allowed_groups: route.allowed_groups,
+                    service_type: route.service_type,
+                    wake_page_enabled: app.wake_page_enabled,
+                    local_only: app.frontend.local_only,
+                    extra_targets: Vec::new(),
+                    load_balancing: Default::default(),
+                    sticky: true,
+                },
+            );
+        }
+        crate::custom_domains::alias_routes(&self.proxy, app, base_domain);
+    }
+
+    // ── Supervision ──────────────────────────────────────────────
+
+    fn spawn_supervisor(self: &Arc<Self>, id: &str) {
+        let (stop_tx, stop_rx) = watch::channel(false);
+        {
+            let mut runtimes = self.runtimes.lock().unwrap();
+            let runtime = runtimes.entry(id.to_string()).or_default();
+            // A closed channel is a supervisor that gave up (app removed)
+            if runtime.stop.as_ref().is_some_and(|s| !s.is_closed()) {
+                return; // Already supervised
+            }
+            runtime.stop = Some(stop_tx);
+        }
+        let mgr = Arc::clone(self);
+        let id = id.to_string();
+        tokio::spawn(async move { mgr.supervise(id, stop_rx).await });
+    }
+
+    async fn stop_supervisor(&self, id: &str) {
+        let stop = self.runtimes.lock().unwrap().get_mut(id).and_then(|r| r.stop.take());
+        let Some(stop) = stop else {
+            return;
+        };
+        let _ = stop.send(true);
+        // The supervisor drops its receiver once the app is down
+        stop.closed().await;
+    }
+
+    fn update_runtime(&self, id: &str, f: impl FnOnce(&mut Runtime)) {
+        if let Some(runtime) = self.runtimes.lock().unwrap().get_mut(id) {
+            f(runtime);
+        }
+    }
+
+    async fn set_status(&self, id: &str, status: ProcessStatus, message: Option<String>) {
+        self.update_runtime(id, |r| r.status = status);
+        let agent_status = match status {
+            ProcessStatus::Running => AgentStatus::Connected,
+            ProcessStatus::Starting => AgentStatus::Pending,
+            ProcessStatus::Crashed => AgentStatus::Error,
+            ProcessStatus::Stopped | ProcessStatus::Unhealthy => AgentStatus::Disconnected,
+        };
+        self.registry.set_app_status(id, agent_status, message).await;
+    }
+
+    async fn supervise(self: Arc<Self>, id: String, mut stop: watch::Receiver<bool>) {
+        let mut failures = 0u32;
+        loop {
+            let Some(app) = self.registry.get_application(&id).await else {
+                return;
+            };
+            let Some(spec) = app.process.clone() else {
+                return;
+            };
+            let token = self.record(&id).await.map(|r| r.token).unwrap_or_default();
+            self.set_status(&id, ProcessStatus::Starting, None).await;
+
+            let exit = match self.launch(&app, &spec, &token).await {
+                Ok(mut handle) => self.monitor(&app, &spec, &mut handle, &mut stop).await,
+                Err(reason) => Exit::Failed { reason, was_healthy: false },
+            };
+            self.update_runtime(&id, |r| r.pid = None);
+
+            let reason = match exit {
+                Exit::Stopped => {
+                    self.set_status(&id, ProcessStatus::Stopped, None).await;
+                    info!(slug = app.slug, "Process app stopped");
+                    return;
+                }
+                Exit::Failed { reason, was_healthy } => {
+                    if was_healthy {
+                        failures = 0;
+                    }
+                    reason
+                }
+            };
+            failures += 1;
+            warn!(slug = app.slug, failures, "Process app failed: {reason}");
+            self.update_runtime(&id, |r| {
+                r.restarts += 1;
+                r.last_error = Some(reason.clone());
+            });
+            hr_common::metrics::registry()
+                .counter(
+                    "homeroute_process_app_restarts_total",
+                    "Restarts of the process apps after a crash or failed health checks.",
+                    &[("app", &app.slug)],
+                )
+                .inc();
+            self.set_status(&id, ProcessStatus::Crashed, Some(reason)).await;
+
+            tokio::select! {
+                _ = tokio::time::sleep(backoff(failures)) => {}
+                _ = stop.changed() => {
+                    self.set_status(&id, ProcessStatus::Stopped, None).await;
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn launch(self: &Arc<Self>, app: &Application, spec: &ProcessSpec, token: &str) -> Result<Handle, String> {
+        if let Some(unit) = &spec.systemd_unit {
+            systemctl("start", unit).await?;
+            self.update_runtime(&app.id, |r| r.started_at = Some(Utc::now()));
+            return Ok(Handle::Unit(unit.clone()));
+        }
+        let (program, args) = command_line(spec).ok_or("No command")?;
+        let mut command = Command::new(&program);
+        command
+            .args(&args)
+            .envs(&spec.env)
+            .env("PORT", app.frontend.target_port.to_string())
+            .env("HOMEROUTE_APP", &app.slug)
+            .env("HOMEROUTE_TOKEN", token)
+            .env("HOMEROUTE_API", format!("http://127.0.0.1:{}", self.env.api_port))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(dir) = &spec.working_dir {
+            command.current_dir(dir);
+        }
+        let mut child = command.spawn().map_err(|e| format!("Failed to start {}: {}", program, e))?;
+        if let Some(stdout) = child.stdout.take() {
+            self.capture(&app.id, stdout);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            self.capture(&app.id, stderr);
+        }
+        let pid = child.id();
+        self.update_runtime(&app.id, |r| {
+            r.pid = pid;
+            r.started_at = Some(Utc::now());
+        });
+        info!(slug = app.slug, pid, "Process app started");
+        Ok(Handle::Child(child))
+    }
+
+    /// Keep the last output lines of the app.
+    fn capture(self: &Arc<Self>, id: &str, output: impl AsyncRead + Unpin + Send + 'static) {
+        let mgr = Arc::clone(self);
+        let id = id.to_string();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(output).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                mgr.update_runtime(&id, |r| {
+                    if r.logs.len() == LOG_LINES {
+                        r.logs.pop_front();
+                    }
+                    r.logs.push_back(line);
+                });
+            }
+        });
+    }
+
+    async fn monitor(
+        &self,
+        app: &Application,
+        spec: &ProcessSpec,
+        handle: &mut Handle,
+        stop: &mut watch::Receiver<bool>,
+    ) -> Exit {
+        let started = Instant::now();
+        let mut interval = tokio::time::interval(HEALTH_INTERVAL);
+        let mut healthy = false;
+        let mut failed_checks = 0u32;
+        loop {
+            tokio::select! {
+                reason = handle.exited() => {
+                    return Exit::Failed { reason, was_healthy: healthy };
+                }
+                _ = stop.changed() => {
+                    handle.terminate().await;
+                    return Exit::Stopped;
+                }
+                _ = interval.tick() => {
+                    if let Handle::Unit(unit) = handle
+                        && systemctl("is-active", unit).await.is_err()
+                    {
+                        return Exit::Failed { reason: format!("unit {} inactive", unit), was_healthy: healthy };
+                    }
+                    if probe(app.frontend.target_port, spec.health_path.as_deref()).await {
+                        if failed_checks > 0 || !healthy {
+                            self.set_status(&app.id, ProcessStatus::Running, None).await;
+                        }
+                        healthy = true;
+                        failed_checks = 0;
+                    } else if healthy || started.elapsed() > STARTUP_GRACE {
+                        failed_checks += 1;
+                        self.set_status(&app.id, ProcessStatus::Unhealthy, Some("Health check failed".to_string())).await;
+                        if failed_checks >= UNHEALTHY_RESTART {
+                            handle.terminate().await;
+                            return Exit::Failed { reason: "health checks failed".to_string(), was_healthy: healthy };
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_line_and_backoff() {
+        let mut spec = ProcessSpec {
+            command: vec!["/opt/shop/shop".to_string(), "--port".to_string(), "8080".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            command_line(&spec),
+            Some(("/opt/shop/shop".to_string(), vec!["--port".to_string(), "8080".to_string()]))
+        );
+        spec.user = Some("shop".to_string());
+        let (program, args) = command_line(&spec).unwrap();
+        assert_eq!(program, "runuser");
+        assert_eq!(args, ["-u", "shop", "--", "/opt/shop/shop", "--port", "8080"]);
+        assert!(spec.validate().is_ok());
+
+        assert!(ProcessSpec::default().validate().is_err());
+        let unit = ProcessSpec { systemd_unit: Some("shop.service".to_string()), ..Default::default() };
+        assert!(unit.validate().is_ok());
+        let unit = ProcessSpec { systemd_unit: Some("shop; reboot".to_string()), ..Default::default() };
+        assert!(unit.validate().is_err());
+
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(3), Duration::from_secs(4));
+        assert_eq!(backoff(30), MAX_BACKOFF);
+    }
+}
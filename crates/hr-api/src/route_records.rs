@@ -0,0 +1,131 @@
+//! Local DNS records of the proxy routes and applications: on the LAN their
+//! domains resolve to the router (hairpin) instead of the public or relay
+//! address. Reconciled on every route change, and every minute for the
+//! router addresses.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use hr_dns::config::{RouteRecordsConfig, StaticRecord};
+use hr_proxy::ProxyState;
+use tracing::info;
+
+use crate::state::ApiState;
+
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A/AAAA records of `domains`, minus the excluded ones and wildcards.
+pub fn desired(
+    config: &RouteRecordsConfig,
+    domains: impl IntoIterator<Item = String>,
+    ipv4: Option<Ipv4Addr>,
+    ipv6: Option<Ipv6Addr>,
+) -> Vec<StaticRecord> {
+    let excluded = |domain: &str| config.exclude.iter().any(|e| e.trim().eq_ignore_ascii_case(domain));
+    let mut domains: Vec<String> = domains
+        .into_iter()
+        .map(|d| d.trim().trim_end_matches('.').to_lowercase())
+        .filter(|d| !d.is_empty() && !d.contains('*') && !excluded(d))
+        .collect();
+    domains.sort();
+    domains.dedup();
+
+    let record = |name: &str, record_type: &str, value: String| StaticRecord {
+        name: name.to_string(),
+        record_type: record_type.to_string(),
+        value,
+        ttl: config.ttl,
+    };
+    let mut records = Vec::new();
+    for domain in &domains {
+        if let Some(ipv4) = ipv4 {
+            records.push(record(domain, "A", ipv4.to_string()));
+        }
+        if let Some(ipv6) = ipv6 {
+            records.push(record(domain, "AAAA", ipv6.to_string()));
+        }
+    }
+    records
+}
+
+/// Enabled static routes that did not opt out, and the application routes.
+fn route_domains(proxy: &ProxyState) -> Vec<String> {
+    let config = proxy.config();
+    config
+        .routes
+        .into_iter()
+        .filter(|r| r.enabled && r.local_dns)
+        .map(|r| r.domain)
+        .chain(proxy.app_route_domains())
+        .collect()
+}
+
+/// The configured addresses, else the DHCP gateway and the global address
+/// of the DHCP interface.
+async fn router_addresses(state: &ApiState, config: &RouteRecordsConfig) -> (Option<Ipv4Addr>, Option<Ipv6Addr>) {
+    let (gateway, interface) = {
+        let dhcp = state.dhcp.read().await;
+        (dhcp.config.gateway.clone(), dhcp.config.interface.clone())
+    };
+    let ipv4 = if config.ipv4.is_empty() { gateway.parse().ok() } else { config.ipv4.parse().ok() };
+    let ipv6 = if config.ipv6.is_empty() {
+        hr_ddns::detect::interface_ipv6(&interface).await
+    } else {
+        config.ipv6.parse().ok()
+    };
+    (ipv4, ipv6)
+}
+
+/// Publish the records of the current routes (none when disabled).
+pub async fn reconcile(state: &ApiState) {
+    let config = state.dns.read().await.config.route_records.clone();
+    let records = if config.enabled {
+        let (ipv4, ipv6) = router_addresses(state, &config).await;
+        desired(&config, route_domains(&state.proxy), ipv4, ipv6)
+    } else {
+        Vec::new()
+    };
+    let mut dns = state.dns.write().await;
+    if dns.route_records != records {
+        info!("Local DNS route records: {} published", records.len());
+        dns.route_records = records;
+    }
+}
+
+pub async fn run_route_records(state: ApiState) -> anyhow::Result<()> {
+    loop {
+        reconcile(&state).await;
+        tokio::select! {
+            _ = state.proxy.routes_changed.notified() => {}
+            _ = tokio::time::sleep(RECONCILE_INTERVAL) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_desired() {
+        let config = RouteRecordsConfig { exclude: vec!["private.example.com".to_string()], ..Default::default() };
+        let domains = ["Nas.example.com.", "app.example.com", "nas.example.com", "private.example.com", "*.dev.example.com"];
+        let records = desired(
+            &config,
+            domains.map(String::from),
+            Some(Ipv4Addr::new(10, 0, 0, 254)),
+            Some("2a01:e0a:1::fe".parse().unwrap()),
+        );
+        let records: Vec<_> = records.iter().map(|r| (r.name.as_str(), r.record_type.as_str(), r.value.as_str())).collect();
+        assert_eq!(
+            records,
+            [
+                ("app.example.com", "A", "10.0.0.254"),
+                ("app.example.com", "AAAA", "2a01:e0a:1::fe"),
+                ("nas.example.com", "A", "10.0.0.254"),
+                ("nas.example.com", "AAAA", "2a01:e0a:1::fe"),
+            ]
+        );
+        assert!(desired(&config, ["app.example.com".to_string()], None, None).is_empty());
+    }
+}
@@ -26,6 +26,7 @@ fn wildcard_type_label(wt: &WildcardType) -> &'static str {
         WildcardType::Global => "global",
         WildcardType::App { .. } => "app",
         WildcardType::LegacyCode => "legacy_code",
+        WildcardType::Custom { .. } => "custom",
     }
 }
 
@@ -104,7 +105,7 @@ async fn list_certificates(State(state): State<ApiState>) -> Json<Value> {
     }
 }
 
-/// Force renewal of all certificates that need it (global, legacy code, and per-app).
+/// Force renewal of all certificates that need it (global, legacy code, per-app and custom domains).
 async fn renew_certificates(State(state): State<ApiState>) -> Json<Value> {
     let mut renewed = Vec::new();
     let mut errors = Vec::new();
@@ -133,9 +134,9 @@ async fn renew_certificates(State(state): State<ApiState>) -> Json<Value> {
         types_to_renew.push(WildcardType::LegacyCode);
     }
 
-    // Check per-app wildcards
+    // Check per-app wildcards and custom domain certificates
     for cert in &certs {
-        if let WildcardType::App { .. } = &cert.wildcard_type {
+        if let WildcardType::App { .. } | WildcardType::Custom { .. } = &cert.wildcard_type {
             if cert.needs_renewal(state.acme.renewal_threshold_days()) {
                 types_to_renew.push(cert.wildcard_type.clone());
             }
@@ -1,11 +1,18 @@
 use axum::{
     extract::{Query, State},
+    http::StatusCode,
     routing::{delete, get, post},
     Json, Router,
 };
+use axum_extra::extract::CookieJar;
+use hr_adblock::AdblockProfile;
+use hr_adblock::schedule::ScheduleChange;
+use hr_dns::blocked_log::BlockReason;
+use hr_dns::safesearch::{SafeSearch, SafeSearchConfig};
 use serde::Deserialize;
 use serde_json::{json, Value};
 
+use crate::routes::auth::admin_user;
 use crate::state::ApiState;
 
 pub fn router() -> Router<ApiState> {
@@ -15,6 +22,14 @@ pub fn router() -> Router<ApiState> {
         .route("/whitelist/{domain}", delete(remove_whitelist))
         .route("/update", post(trigger_update))
         .route("/search", get(search))
+        .route("/explain", get(explain))
+        .route("/pause", post(pause).delete(resume))
+        .route("/allow", post(allow_temporarily))
+        .route("/schedule", get(schedule))
+        .route("/profiles", get(get_profiles).put(update_profiles))
+        .route("/blocked", get(blocked))
+        .route("/blocked/{id}/allow", post(allow_blocked))
+        .route("/safesearch", get(get_safesearch).put(update_safesearch))
 }
 
 async fn stats(State(state): State<ApiState>) -> Json<Value> {
@@ -25,10 +40,8 @@ async fn stats(State(state): State<ApiState>) -> Json<Value> {
     let sources = read_adblock_sources(&state).await;
 
     // Check cache file mtime for lastUpdate
-    let last_update = tokio::fs::metadata("/var/lib/server-dashboard/adblock/domains.json")
-        .await
-        .ok()
-        .and_then(|m| m.modified().ok())
+    let data_dir = read_adblock_config(&state).await.unwrap_or_default().data_dir;
+    let last_update = hr_adblock::sources::cache_modified(std::path::Path::new(&data_dir))
         .map(|t| {
             t.duration_since(std::time::UNIX_EPOCH)
                 .unwrap_or_default()
@@ -39,6 +52,7 @@ async fn stats(State(state): State<ApiState>) -> Json<Value> {
         "success": true,
         "stats": {
             "domainCount": engine.domain_count(),
+            "exceptionCount": engine.exception_count(),
             "sources": sources,
             "lastUpdate": last_update,
             "enabled": dns.adblock_enabled
@@ -66,16 +80,25 @@ async fn add_whitelist(
         return Json(json!({"success": false, "error": "Domain requis"}));
     }
 
+    if let Err(e) = whitelist_domain(&state, &domain).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+
+    Json(json!({"success": true, "domain": domain}))
+}
+
+/// Add `domain` to the whitelist, in the config file and the engine.
+async fn whitelist_domain(state: &ApiState, domain: &str) -> Result<(), String> {
     // Read current whitelist, add domain, save to config file
     let config_path = &state.dns_dhcp_config_path;
     let content = match tokio::fs::read_to_string(config_path).await {
         Ok(c) => c,
-        Err(e) => return Json(json!({"success": false, "error": format!("Config read error: {}", e)})),
+        Err(e) => return Err(format!("Config read error: {}", e)),
     };
 
     let mut config: Value = match serde_json::from_str(&content) {
         Ok(v) => v,
-        Err(e) => return Json(json!({"success": false, "error": format!("Config parse error: {}", e)})),
+        Err(e) => return Err(format!("Config parse error: {}", e)),
     };
 
     // Update whitelist in config
@@ -104,13 +127,13 @@ async fn add_whitelist(
     {
         let mut engine = state.adblock.write().await;
         let mut domains = engine.whitelist_domains();
-        if !domains.contains(&domain) {
-            domains.push(domain.clone());
+        if !domains.iter().any(|d| d == domain) {
+            domains.push(domain.to_string());
         }
         engine.set_whitelist(domains);
     }
 
-    Json(json!({"success": true, "domain": domain}))
+    Ok(())
 }
 
 async fn remove_whitelist(
@@ -168,24 +191,26 @@ async fn trigger_update(State(state): State<ApiState>) -> Json<Value> {
         _ => hr_adblock::config::AdblockConfig::default(),
     };
 
-    // Download and update
-    let (domains, results) = hr_adblock::sources::download_all(&adblock_config.sources).await;
-    let count = domains.len();
-
-    // Save cache
-    let cache_path = std::path::PathBuf::from(&adblock_config.data_dir).join("domains.json");
-    let _ = hr_adblock::sources::save_cache(&domains, &cache_path);
+    // Download the changed lists, merge with the cached ones
+    let data_dir = std::path::PathBuf::from(&adblock_config.data_dir);
+    let (rules, results) = hr_adblock::sources::update(&adblock_config.sources, &data_dir).await;
+    let count = rules.rules.len();
 
     // Apply to engine
     {
         let mut engine = state.adblock.write().await;
-        engine.set_blocked(domains);
+        engine.set_rules(rules);
         engine.set_whitelist(adblock_config.whitelist);
     }
 
     let source_results: Vec<Value> = results
         .iter()
-        .map(|r| json!({"name": r.name, "domains": r.domain_count}))
+        .map(|r| json!({
+            "name": r.name,
+            "domains": r.domain_count,
+            "unsupported": r.unsupported,
+            "status": r.status.as_str(),
+        }))
         .collect();
 
     Json(json!({
@@ -195,6 +220,13 @@ async fn trigger_update(State(state): State<ApiState>) -> Json<Value> {
     }))
 }
 
+/// The adblock section of the config file.
+async fn read_adblock_config(state: &ApiState) -> Option<hr_adblock::config::AdblockConfig> {
+    let content = tokio::fs::read_to_string(&state.dns_dhcp_config_path).await.ok()?;
+    let config: Value = serde_json::from_str(&content).ok()?;
+    serde_json::from_value(config.get("adblock")?.clone()).ok()
+}
+
 /// Read adblock sources from config file for frontend display.
 async fn read_adblock_sources(state: &ApiState) -> Vec<Value> {
     let config_path = &state.dns_dhcp_config_path;
@@ -216,7 +248,8 @@ async fn read_adblock_sources(state: &ApiState) -> Vec<Value> {
                 .map(|s| {
                     json!({
                         "name": s.get("name").and_then(|v| v.as_str()).unwrap_or(""),
-                        "url": s.get("url").and_then(|v| v.as_str()).unwrap_or("")
+                        "url": s.get("url").and_then(|v| v.as_str()).unwrap_or(""),
+                        "format": s.get("format").and_then(|v| v.as_str()).unwrap_or("hosts")
                     })
                 })
                 .collect()
@@ -246,6 +279,270 @@ async fn search(
         "success": true,
         "query": q,
         "is_blocked": is_blocked,
+        "match": engine.explain(&q),
         "results": results
     }))
 }
+
+#[derive(Deserialize)]
+struct ExplainQuery {
+    domain: String,
+    /// CNAME targets of the name, comma-separated.
+    cname: Option<String>,
+}
+
+/// Why a name is blocked or allowed: the deciding rule and its list. With
+/// `cname` targets, CNAME cloaking is checked too.
+async fn explain(
+    State(state): State<ApiState>,
+    Query(query): Query<ExplainQuery>,
+) -> Json<Value> {
+    let normalize = |name: &str| name.trim().trim_end_matches('.').to_lowercase();
+    let domain = normalize(&query.domain);
+    if domain.is_empty() {
+        return Json(json!({"success": false, "error": "Domain requis"}));
+    }
+    let engine = state.adblock.read().await;
+    let mut decision = engine.explain(&domain);
+    if !engine.is_blocked(&domain)
+        && let Some(targets) = &query.cname
+    {
+        let targets: Vec<String> = targets.split(',').map(normalize).collect();
+        if let Some(cloaked) = engine.blocked_cname(&domain, targets.iter().map(String::as_str)) {
+            decision = Some(cloaked);
+        }
+    }
+    Json(json!({
+        "success": true,
+        "domain": domain,
+        "blocked": decision.as_ref().is_some_and(|d| d.blocked),
+        "match": decision,
+    }))
+}
+
+#[derive(Deserialize)]
+struct PauseRequest {
+    minutes: u32,
+    /// Client IP; every client when absent.
+    client: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResumeQuery {
+    client: Option<String>,
+}
+
+fn parse_client(client: Option<&str>) -> Result<Option<std::net::IpAddr>, String> {
+    match client.map(str::trim).filter(|c| !c.is_empty()) {
+        Some(c) => c.parse().map(Some).map_err(|_| format!("Invalid client IP: {c}")),
+        None => Ok(None),
+    }
+}
+
+/// Pause blocking (lists and profiles) for N minutes, globally or for one
+/// client.
+async fn pause(State(state): State<ApiState>, Json(body): Json<PauseRequest>) -> Json<Value> {
+    if body.minutes == 0 || body.minutes > 24 * 60 {
+        return Json(json!({"success": false, "error": "Durée entre 1 et 1440 minutes"}));
+    }
+    let client = match parse_client(body.client.as_deref()) {
+        Ok(c) => c,
+        Err(e) => return Json(json!({"success": false, "error": e})),
+    };
+    let schedule = state.dns.read().await.adblock_schedule.clone();
+    let pause = schedule.pause(client, body.minutes, chrono::Utc::now());
+    hr_dns::schedule::publish(&state.events, &ScheduleChange::Paused(pause.clone()));
+    Json(json!({"success": true, "pause": pause}))
+}
+
+async fn resume(State(state): State<ApiState>, Query(query): Query<ResumeQuery>) -> Json<Value> {
+    let client = match parse_client(query.client.as_deref()) {
+        Ok(c) => c,
+        Err(e) => return Json(json!({"success": false, "error": e})),
+    };
+    let schedule = state.dns.read().await.adblock_schedule.clone();
+    if !schedule.resume(client) {
+        return Json(json!({"success": false, "error": "Aucune pause en cours"}));
+    }
+    hr_dns::schedule::publish(&state.events, &ScheduleChange::Resumed { client });
+    Json(json!({"success": true}))
+}
+
+#[derive(Deserialize)]
+struct AllowRequest {
+    domain: String,
+    /// Client IP; every client when absent.
+    client: Option<String>,
+    minutes: Option<u32>,
+}
+
+/// Unblock a domain for a while (lists and profiles), e.g. from the
+/// sinkhole page. Administrators only: profiles must not be lifted by the
+/// clients they restrict.
+async fn allow_temporarily(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Json(body): Json<AllowRequest>,
+) -> (StatusCode, Json<Value>) {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    let minutes = body.minutes.unwrap_or(hr_proxy::sinkhole::ALLOW_MINUTES);
+    if minutes == 0 || minutes > 24 * 60 {
+        return (StatusCode::BAD_REQUEST, Json(json!({"success": false, "error": "Durée entre 1 et 1440 minutes"})));
+    }
+    let domain = body.domain.trim().trim_end_matches('.');
+    if domain.is_empty() || !domain.contains('.') {
+        return (StatusCode::BAD_REQUEST, Json(json!({"success": false, "error": "Domaine invalide"})));
+    }
+    let client = match parse_client(body.client.as_deref()) {
+        Ok(c) => c,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({"success": false, "error": e}))),
+    };
+    let schedule = state.dns.read().await.adblock_schedule.clone();
+    let allow = schedule.allow(domain, client, minutes, chrono::Utc::now());
+    hr_dns::schedule::publish(&state.events, &ScheduleChange::Allowed(allow.clone()));
+    (StatusCode::OK, Json(json!({"success": true, "allow": allow})))
+}
+
+/// Running pauses and temporary allows, and the profiles whose window is
+/// open.
+async fn schedule(State(state): State<ApiState>) -> Json<Value> {
+    let schedule = state.dns.read().await.adblock_schedule.clone();
+    let now = chrono::Utc::now();
+    Json(json!({
+        "success": true,
+        "pauses": schedule.pauses(now),
+        "allows": schedule.allows(now),
+        "activeProfiles": schedule.active_profiles(now.with_timezone(&chrono::Local).naive_local()),
+    }))
+}
+
+async fn get_profiles(State(state): State<ApiState>) -> Json<Value> {
+    let schedule = state.dns.read().await.adblock_schedule.clone();
+    Json(json!({"success": true, "profiles": schedule.profiles()}))
+}
+
+#[derive(Deserialize)]
+struct UpdateProfilesRequest {
+    profiles: Vec<AdblockProfile>,
+}
+
+/// Replace the scheduled profiles: validated and applied, then saved in
+/// the adblock config.
+async fn update_profiles(
+    State(state): State<ApiState>,
+    Json(body): Json<UpdateProfilesRequest>,
+) -> Json<Value> {
+    let schedule = state.dns.read().await.adblock_schedule.clone();
+    if let Err(e) = schedule.set_profiles(&body.profiles) {
+        return Json(json!({"success": false, "error": e}));
+    }
+    let profiles = schedule.profiles();
+
+    let config_path = &state.dns_dhcp_config_path;
+    let content = match tokio::fs::read_to_string(config_path).await {
+        Ok(c) => c,
+        Err(e) => return Json(json!({"success": false, "error": format!("Config read error: {}", e)})),
+    };
+    let mut config: Value = match serde_json::from_str(&content) {
+        Ok(v) => v,
+        Err(e) => return Json(json!({"success": false, "error": format!("Config parse error: {}", e)})),
+    };
+    if let Some(adblock) = config.get_mut("adblock").and_then(|a| a.as_object_mut()) {
+        adblock.insert("profiles".to_string(), json!(profiles));
+    }
+    if let Ok(new_content) = serde_json::to_string_pretty(&config) {
+        let tmp = config_path.with_extension("json.tmp");
+        if let Err(e) = tokio::fs::write(&tmp, &new_content).await {
+            return Json(json!({"success": false, "error": format!("Config write error: {}", e)}));
+        }
+        let _ = tokio::fs::rename(&tmp, config_path).await;
+    }
+
+    Json(json!({"success": true, "profiles": profiles}))
+}
+
+#[derive(Deserialize)]
+struct BlockedQuery {
+    /// RFC 3339; the whole log when absent.
+    since: Option<String>,
+    client: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Recently blocked queries, newest first, with the client and the rule,
+/// CNAME target or profile that blocked them.
+async fn blocked(State(state): State<ApiState>, Query(query): Query<BlockedQuery>) -> Json<Value> {
+    let since = match query.since.as_deref().filter(|s| !s.is_empty()) {
+        Some(s) => match chrono::DateTime::parse_from_rfc3339(s) {
+            Ok(t) => Some(t.with_timezone(&chrono::Utc)),
+            Err(_) => return Json(json!({"success": false, "error": format!("Invalid since: {s} (RFC 3339)")})),
+        },
+        None => None,
+    };
+    let limit = query.limit.unwrap_or(200).min(1000);
+    let dns = state.dns.read().await;
+    let entries = dns.blocked_log.since(since, query.client.as_deref(), limit);
+    Json(json!({"success": true, "entries": entries}))
+}
+
+/// Whitelist the domain of a blocked query log entry.
+async fn allow_blocked(
+    State(state): State<ApiState>,
+    axum::extract::Path(id): axum::extract::Path<u64>,
+) -> Json<Value> {
+    let entry = state.dns.read().await.blocked_log.get(id);
+    let Some(entry) = entry else {
+        return Json(json!({"success": false, "error": "Entrée introuvable"}));
+    };
+    if let BlockReason::Profile { profile } = &entry.reason {
+        return Json(json!({"success": false, "error": format!("Bloqué par le profil {profile}, à modifier dans les profils")}));
+    }
+    if let Err(e) = whitelist_domain(&state, &entry.domain).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+    Json(json!({"success": true, "domain": entry.domain}))
+}
+
+async fn get_safesearch(State(state): State<ApiState>) -> Json<Value> {
+    let dns = state.dns.read().await;
+    Json(json!({"success": true, "safesearch": dns.safesearch.config()}))
+}
+
+/// Replace the SafeSearch settings (global switch, services, YouTube mode,
+/// client overrides), applied to the resolver and saved in the DNS config.
+async fn update_safesearch(
+    State(state): State<ApiState>,
+    Json(config): Json<SafeSearchConfig>,
+) -> Json<Value> {
+    let safesearch = match SafeSearch::new(&config) {
+        Ok(s) => s,
+        Err(e) => return Json(json!({"success": false, "error": e})),
+    };
+
+    let config_path = &state.dns_dhcp_config_path;
+    let content = match tokio::fs::read_to_string(config_path).await {
+        Ok(c) => c,
+        Err(e) => return Json(json!({"success": false, "error": format!("Config read error: {}", e)})),
+    };
+    let mut file: Value = match serde_json::from_str(&content) {
+        Ok(v) => v,
+        Err(e) => return Json(json!({"success": false, "error": format!("Config parse error: {}", e)})),
+    };
+    if let Some(dns) = file.get_mut("dns").and_then(|d| d.as_object_mut()) {
+        dns.insert("safesearch".to_string(), json!(config));
+    }
+    if let Ok(new_content) = serde_json::to_string_pretty(&file) {
+        let tmp = config_path.with_extension("json.tmp");
+        if let Err(e) = tokio::fs::write(&tmp, &new_content).await {
+            return Json(json!({"success": false, "error": format!("Config write error: {}", e)}));
+        }
+        let _ = tokio::fs::rename(&tmp, config_path).await;
+    }
+
+    let mut dns = state.dns.write().await;
+    dns.config.safesearch = config.clone();
+    dns.safesearch = safesearch;
+    Json(json!({"success": true, "safesearch": config}))
+}
@@ -0,0 +1,181 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing::{any, get},
+    Json, Router,
+};
+use axum_extra::extract::CookieJar;
+use hr_ai::{AiConfig, ForwardRequest, GatewayError};
+use hr_mail::AppAuth;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::routes::auth::admin_user;
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/", get(overview).put(update_config))
+        .route("/log", get(request_log))
+        .route("/{provider}/{*path}", any(forward))
+}
+
+type ApiResult = (StatusCode, Json<Value>);
+
+fn failure(status: StatusCode, error: impl std::fmt::Display) -> ApiResult {
+    (status, Json(json!({"success": false, "error": error.to_string()})))
+}
+
+fn error_status(e: &GatewayError) -> StatusCode {
+    match e {
+        GatewayError::UnknownProvider(_) => StatusCode::NOT_FOUND,
+        GatewayError::NotAllowed(_) => StatusCode::FORBIDDEN,
+        GatewayError::BudgetExhausted(_) => StatusCode::TOO_MANY_REQUESTS,
+        GatewayError::Upstream(_) => StatusCode::BAD_GATEWAY,
+        GatewayError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Providers (keys hidden), policies and usage of the month per app.
+async fn overview(State(state): State<ApiState>, jar: CookieJar) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    let config = state.ai.config();
+    let usage = match state.ai.month_usage().await {
+        Ok(usage) => usage,
+        Err(e) => return failure(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    let usage: Vec<Value> = usage
+        .into_iter()
+        .map(|u| {
+            let policy = config.policy(&u.app);
+            json!({
+                "app": u.app,
+                "requests": u.requests,
+                "inputTokens": u.input_tokens,
+                "outputTokens": u.output_tokens,
+                "costUsd": u.cost_usd,
+                "monthlyTokens": policy.monthly_tokens,
+                "monthlyCostUsd": policy.monthly_cost_usd,
+            })
+        })
+        .collect();
+    let providers: Vec<Value> = config
+        .providers
+        .iter()
+        .map(|p| {
+            let mut value = serde_json::to_value(p).unwrap_or_default();
+            value["apiKey"] = json!("");
+            value["hasApiKey"] = json!(!p.api_key.is_empty());
+            value
+        })
+        .collect();
+    let mut config = serde_json::to_value(&config).unwrap_or_default();
+    config["providers"] = json!(providers);
+    (StatusCode::OK, Json(json!({"success": true, "config": config, "usage": usage})))
+}
+
+/// An empty `apiKey` keeps the stored key of the provider with that name.
+async fn update_config(State(state): State<ApiState>, jar: CookieJar, Json(mut config): Json<AiConfig>) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    let current = state.ai.config();
+    for provider in &mut config.providers {
+        if provider.api_key.is_empty()
+            && let Some(stored) = current.provider(&provider.name)
+        {
+            provider.api_key = stored.api_key.clone();
+        }
+    }
+    if let Err(e) = config.validate() {
+        return failure(StatusCode::BAD_REQUEST, e);
+    }
+    let path = state.env.ai_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return failure(StatusCode::INTERNAL_SERVER_ERROR, format!("Write failed: {}", e)),
+        Err(e) => return failure(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+    state.ai.apply(config);
+    (StatusCode::OK, Json(json!({"success": true})))
+}
+
+#[derive(Deserialize)]
+struct LogQuery {
+    app: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: u32,
+    #[serde(default)]
+    bodies: bool,
+}
+
+fn default_limit() -> u32 {
+    100
+}
+
+/// GET /api/ai/log?app=&limit=&bodies=
+async fn request_log(State(state): State<ApiState>, jar: CookieJar, Query(q): Query<LogQuery>) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    let store = state.ai.store();
+    let limit = q.limit.min(1000);
+    match tokio::task::spawn_blocking(move || store.log(q.app.as_deref(), limit, q.bodies)).await {
+        Ok(Ok(entries)) => (StatusCode::OK, Json(json!({"success": true, "entries": entries}))),
+        Ok(Err(e)) => failure(StatusCode::INTERNAL_SERVER_ERROR, e),
+        Err(e) => failure(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// /api/ai/{provider}/{path} — forwarded to the provider with its key.
+/// Auth via the agent token, as `Authorization: Bearer` or `x-api-key`
+/// so the provider SDKs only need their base URL and key changed.
+async fn forward(
+    State(state): State<ApiState>,
+    Path((provider, path)): Path<(String, String)>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(registry) = &state.registry else {
+        return failure(StatusCode::SERVICE_UNAVAILABLE, "Registry not available").into_response();
+    };
+    let Some(token) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()))
+    else {
+        return failure(StatusCode::UNAUTHORIZED, "Missing agent token").into_response();
+    };
+    let Some(app) = registry.app_for(None, token).await else {
+        return failure(StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+    };
+    let request = ForwardRequest {
+        method,
+        path,
+        query: uri.query().map(str::to_string),
+        headers: headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect(),
+        body,
+    };
+    let forwarded = match state.ai.forward(&app, &provider, request).await {
+        Ok(forwarded) => forwarded,
+        Err(e) => return failure(error_status(&e), e).into_response(),
+    };
+    let mut response = Response::builder().status(forwarded.status);
+    for (name, value) in &forwarded.headers {
+        response = response.header(name, value);
+    }
+    response
+        .body(Body::from_stream(forwarded.body))
+        .unwrap_or_else(|e| failure(StatusCode::BAD_GATEWAY, e).into_response())
+}
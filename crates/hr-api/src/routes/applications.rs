@@ -3,23 +3,28 @@
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
 use axum::http::header;
 use axum::response::IntoResponse;
-use axum::routing::{get, post, put};
+use axum::routing::{delete, get, post, put};
 use axum::extract::DefaultBodyLimit;
 use axum::{Json, Router};
-use tokio::io::AsyncReadExt;
 use tracing::{error, info, warn};
 
 use hr_proxy::AppRoute;
-use hr_registry::protocol::{AgentMessage, HostRegistryMessage, PowerPolicy, ServiceAction, ServiceConfig, ServiceType};
+use hr_registry::protocol::{AgentMessage, HostRegistryMessage, Negotiated, PowerPolicy, ServiceAction, ServiceConfig, ServiceType, AGENT_CAPABILITIES, PROTOCOL_VERSION};
+use hr_registry::binaries::BinaryStore;
+use hr_registry::transfer::{ChunkReader, Compression, Counted, ResumePoint, TransferStream};
 use hr_registry::types::{TriggerUpdateRequest, UpdateApplicationRequest};
 use hr_common::events::{MigrationPhase, MigrationProgressEvent};
 use hr_acme::types::WildcardType;
 use hr_dns::config::StaticRecord;
 
+use crate::deployments;
+use crate::enrollment::{self, JoinTarget};
+use crate::placement::{self, PlacementRequest};
+use crate::routes::hosts::ArchQuery;
 use crate::state::{ApiState, MigrationState};
 
 pub fn router() -> Router<ApiState> {
@@ -27,13 +32,22 @@ pub fn router() -> Router<ApiState> {
         .route("/{id}/services/{service_type}/start", post(start_service))
         .route("/{id}/services/{service_type}/stop", post(stop_service))
         .route("/{id}/power-policy", put(update_power_policy))
+        .route("/{id}/domains", get(list_custom_domains).post(add_custom_domain))
+        .route("/{id}/domains/{domain}/verify", post(verify_custom_domain))
+        .route("/{id}/domains/{domain}", delete(remove_custom_domain))
         .route("/{id}/update/fix", post(fix_agent_update))
+        .route("/{id}/join-code", post(create_join_code))
         .route("/{id}/exec", post(exec_in_container))
         .route("/{id}/deploy", post(deploy_to_production).layer(DefaultBodyLimit::max(200 * 1024 * 1024)))
+        .route("/{id}/deploy/status", get(get_deploy_status))
+        .route("/{id}/deploy/rollback", post(rollback_deploy))
+        .route("/{id}/deploy/standby", delete(discard_deploy_standby))
+        .route("/{id}/migrate", post(super::containers::migrate_container))
         .route("/{id}/prod/status", get(get_prod_status))
         .route("/{id}/prod/logs", get(get_prod_logs))
         .route("/{id}/prod/exec", post(prod_exec))
         .route("/{id}/prod/push", post(prod_push).layer(DefaultBodyLimit::max(200 * 1024 * 1024)))
+        .route("/placement/preview", post(preview_placement))
         .route("/deploys/{deploy_id}/artifact", get(get_deploy_artifact))
         .route("/agents/version", get(agent_version))
         .route("/agents/binary", get(agent_binary))
@@ -41,6 +55,11 @@ pub fn router() -> Router<ApiState> {
         .route("/agents/update", post(trigger_agent_update))
         .route("/agents/update/status", get(get_update_status))
         .route("/agents/ws", get(agent_ws))
+        .merge(super::cron::router())
+        .merge(super::backups::router())
+        .merge(super::resources::router())
+        .merge(super::logs::router())
+        .merge(super::templates::router())
 }
 
 // ── REST handlers ────────────────────────────────────────────
@@ -127,15 +146,151 @@ async fn update_power_policy(
     }
 }
 
+// ── Custom domains ───────────────────────────────────────────
+
+#[derive(serde::Deserialize)]
+struct AddCustomDomainRequest {
+    domain: String,
+}
+
+/// GET /api/applications/{id}/domains
+/// Custom domains with their status, and the CNAME target to configure.
+async fn list_custom_domains(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let Some(registry) = &state.registry else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"success": false, "error": "Registry not available"}))).into_response();
+    };
+    let Some(app) = registry.get_application(&id).await else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"success": false, "error": "Application not found"}))).into_response();
+    };
+    Json(serde_json::json!({
+        "success": true,
+        "cname_target": format!("{}.{}", app.slug, state.env.base_domain),
+        "domains": app.custom_domains,
+    })).into_response()
+}
+
+/// POST /api/applications/{id}/domains
+/// Attach a domain; DNS verification and certificate issuance run in the background.
+async fn add_custom_domain(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(req): Json<AddCustomDomainRequest>,
+) -> impl IntoResponse {
+    let Some(registry) = &state.registry else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"success": false, "error": "Registry not available"}))).into_response();
+    };
+    match registry.add_custom_domain(&id, &req.domain).await {
+        Ok(Some(custom)) => {
+            let domain = custom.domain.clone();
+            let state_bg = state.clone();
+            tokio::spawn(async move {
+                crate::custom_domains::verify_and_activate(&state_bg, &id, &domain).await;
+            });
+            (StatusCode::ACCEPTED, Json(serde_json::json!({"success": true, "domain": custom}))).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, Json(serde_json::json!({"success": false, "error": "Application not found"}))).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({"success": false, "error": e.to_string()}))).into_response(),
+    }
+}
+
+/// POST /api/applications/{id}/domains/{domain}/verify
+/// Check DNS now; when it points at us, the certificate is requested in the background.
+async fn verify_custom_domain(
+    State(state): State<ApiState>,
+    Path((id, domain)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let Some(registry) = &state.registry else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"success": false, "error": "Registry not available"}))).into_response();
+    };
+    let Some(app) = registry.get_application(&id).await else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"success": false, "error": "Application not found"}))).into_response();
+    };
+    if !app.custom_domains.iter().any(|d| d.domain == domain) {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"success": false, "error": "Domain not found"}))).into_response();
+    }
+
+    let target = format!("{}.{}", app.slug, state.env.base_domain);
+    if let Err(e) = crate::custom_domains::check_pointing(&domain, &target).await {
+        let _ = registry
+            .set_custom_domain_status(&id, &domain, hr_registry::types::CustomDomainStatus::Pending, Some(e.clone()))
+            .await;
+        return Json(serde_json::json!({"success": false, "error": e})).into_response();
+    }
+    tokio::spawn(async move {
+        crate::custom_domains::verify_and_activate(&state, &id, &domain).await;
+    });
+    (StatusCode::ACCEPTED, Json(serde_json::json!({"success": true}))).into_response()
+}
+
+/// DELETE /api/applications/{id}/domains/{domain}
+async fn remove_custom_domain(
+    State(state): State<ApiState>,
+    Path((id, domain)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let Some(registry) = &state.registry else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"success": false, "error": "Registry not available"}))).into_response();
+    };
+    match registry.remove_custom_domain(&id, &domain).await {
+        Ok(true) => {
+            state.proxy.remove_app_route(&domain);
+            state.tls_manager.remove_certificate(&domain);
+            state.dns.write().await.remove_static_record(&domain, "ALIAS");
+            Json(serde_json::json!({"success": true})).into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, Json(serde_json::json!({"success": false, "error": "Domain not found"}))).into_response(),
+        Err(e) => {
+            error!("Failed to remove custom domain: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"success": false, "error": e.to_string()}))).into_response()
+        }
+    }
+}
+
+// ── Placement ────────────────────────────────────────────────
+
+/// POST /api/applications/placement/preview — where a new application
+/// would be placed, with the score of every host and its reasons.
+async fn preview_placement(
+    State(state): State<ApiState>,
+    Json(req): Json<PlacementRequest>,
+) -> impl IntoResponse {
+    let Some(cm) = &state.container_manager else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"success": false, "error": "Container manager not available"}))).into_response();
+    };
+    let decision = placement::decide(cm, &req).await;
+    Json(serde_json::json!({"success": true, "host_id": decision.host_id, "hosts": decision.hosts})).into_response()
+}
+
 // ── Deploy (dev → prod) handlers ─────────────────────────────
 
+#[derive(Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DeployStrategy {
+    /// Stop the service, replace the binary, start it again.
+    #[default]
+    InPlace,
+    /// New container alongside the old one (see `crate::deployments`).
+    BlueGreen,
+}
+
+#[derive(serde::Deserialize)]
+struct DeployQuery {
+    #[serde(default)]
+    strategy: DeployStrategy,
+}
+
 /// POST /api/applications/{dev_id}/deploy
 /// Accepts raw binary body (application/octet-stream).
 /// Copies binary to /opt/app/app in prod, creates systemd unit if needed, restarts service.
-/// Synchronous — blocks until deploy completes.
+/// Synchronous — blocks until deploy completes. With `?strategy=blue_green`
+/// the binary goes to a new prod container instead and the call returns
+/// once the deployment started (progress: `deployment:progress` events).
 async fn deploy_to_production(
     State(state): State<ApiState>,
     Path(dev_id): Path<String>,
+    Query(query): Query<DeployQuery>,
     body: axum::body::Bytes,
 ) -> impl IntoResponse {
     let Some(registry) = &state.registry else {
@@ -173,7 +328,19 @@ async fn deploy_to_production(
     }
 
     let binary_size = body.len();
-    info!(dev_id, prod_id = prod_id.as_str(), binary_bytes = binary_size, "Deploy binary to production");
+    info!(dev_id, prod_id = prod_id.as_str(), binary_bytes = binary_size, strategy = ?query.strategy, "Deploy binary to production");
+
+    if query.strategy == DeployStrategy::BlueGreen {
+        return match deployments::start(&state, &prod_id, body.to_vec()).await {
+            Ok(deploy_id) => (StatusCode::ACCEPTED, Json(serde_json::json!({
+                "success": true,
+                "deploy_id": deploy_id,
+                "prod_id": prod_id,
+                "binary_size": binary_size,
+            }))).into_response(),
+            Err(e) => (StatusCode::CONFLICT, Json(serde_json::json!({"success": false, "error": e}))).into_response(),
+        };
+    }
 
     // Execute deploy synchronously
     match execute_deploy(
@@ -199,20 +366,64 @@ async fn deploy_to_production(
     }
 }
 
-/// Execute the deploy pipeline synchronously: setup → stop → copy binary → start.
-/// Returns Ok(message) on success or Err(error) on failure.
-async fn execute_deploy(
-    registry: &Arc<hr_registry::AgentRegistry>,
-    prod_id: &str,
-    prod_container: &str,
-    prod_host: &str,
-    binary_data: Vec<u8>,
-) -> Result<String, String> {
-    let deploy_id = uuid::Uuid::new_v4().to_string();
+/// Production app targeted by the deploy routes: `id` itself, or the app
+/// linked to it when `id` is a development app.
+async fn deploy_target(registry: &hr_registry::AgentRegistry, id: &str) -> Option<String> {
+    let app = registry.get_application(id).await?;
+    match app.environment {
+        hr_registry::types::Environment::Development => app.linked_app_id,
+        hr_registry::types::Environment::Production => Some(app.id),
+    }
+}
 
-    // Phase 0: Ensure /opt/app exists and app.service runs /opt/app/app directly
-    let setup_cmd = r#"mkdir -p /opt/app && if [ ! -f /etc/systemd/system/app.service ]; then cat > /etc/systemd/system/app.service << 'SVCEOF'
-[Unit]
+/// GET /api/applications/{id}/deploy/status
+/// Last blue/green deployment and the standby container kept for rollback.
+async fn get_deploy_status(State(state): State<ApiState>, Path(id): Path<String>) -> impl IntoResponse {
+    let Some(registry) = &state.registry else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"success": false, "error": "Registry not available"}))).into_response();
+    };
+    let Some(prod_id) = deploy_target(registry, &id).await else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"success": false, "error": "Production application not found"}))).into_response();
+    };
+    let mut status = deployments::status(&state, &prod_id).await;
+    status["success"] = true.into();
+    status["prod_id"] = prod_id.into();
+    Json(status).into_response()
+}
+
+/// POST /api/applications/{id}/deploy/rollback
+/// Switch the traffic back to the standby container of the last deployment.
+async fn rollback_deploy(State(state): State<ApiState>, Path(id): Path<String>) -> impl IntoResponse {
+    let Some(registry) = &state.registry else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"success": false, "error": "Registry not available"}))).into_response();
+    };
+    let Some(prod_id) = deploy_target(registry, &id).await else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"success": false, "error": "Production application not found"}))).into_response();
+    };
+    match deployments::rollback(&state, &prod_id).await {
+        Ok(deploy_id) => (StatusCode::ACCEPTED, Json(serde_json::json!({"success": true, "deploy_id": deploy_id, "prod_id": prod_id}))).into_response(),
+        Err(e) => (StatusCode::CONFLICT, Json(serde_json::json!({"success": false, "error": e}))).into_response(),
+    }
+}
+
+/// DELETE /api/applications/{id}/deploy/standby
+/// Delete the standby container, keeping the current version for good.
+async fn discard_deploy_standby(State(state): State<ApiState>, Path(id): Path<String>) -> impl IntoResponse {
+    let Some(registry) = &state.registry else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"success": false, "error": "Registry not available"}))).into_response();
+    };
+    let Some(prod_id) = deploy_target(registry, &id).await else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"success": false, "error": "Production application not found"}))).into_response();
+    };
+    match deployments::discard_standby(&state, &prod_id).await {
+        Ok(true) => Json(serde_json::json!({"success": true})).into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, Json(serde_json::json!({"success": false, "error": "No standby container"}))).into_response(),
+        Err(e) => (StatusCode::CONFLICT, Json(serde_json::json!({"success": false, "error": e}))).into_response(),
+    }
+}
+
+/// Unit running the binary deployed to `/opt/app/app`.
+pub(crate) const APP_SERVICE_UNIT: &str = "[Unit]
 Description=Application Service
 After=network.target
 
@@ -225,19 +436,36 @@ RestartSec=5
 
 [Install]
 WantedBy=multi-user.target
-SVCEOF
+";
+
+/// Execute the deploy pipeline synchronously: setup → stop → copy binary → start.
+/// Returns Ok(message) on success or Err(error) on failure.
+async fn execute_deploy(
+    registry: &Arc<hr_registry::AgentRegistry>,
+    prod_id: &str,
+    prod_container: &str,
+    prod_host: &str,
+    binary_data: Vec<u8>,
+) -> Result<String, String> {
+    let deploy_id = uuid::Uuid::new_v4().to_string();
+
+    // Phase 0: Ensure /opt/app exists and app.service runs /opt/app/app directly
+    let setup_cmd = format!(
+        "mkdir -p /opt/app && if [ ! -f /etc/systemd/system/app.service ]; then cat > /etc/systemd/system/app.service << 'SVCEOF'
+{APP_SERVICE_UNIT}SVCEOF
 systemctl daemon-reload
 systemctl enable app.service
-fi"#;
+fi"
+    );
 
     let setup_result = if prod_host == "local" {
         let out = tokio::process::Command::new("machinectl")
-            .args(["shell", prod_container, "/bin/bash", "-c", setup_cmd])
+            .args(["shell", prod_container, "/bin/bash", "-c", &setup_cmd])
             .output()
             .await;
         out.map(|o| o.status.success()).unwrap_or(false)
     } else {
-        registry.exec_in_remote_container(prod_host, prod_container, vec![setup_cmd.to_string()])
+        registry.exec_in_remote_container(prod_host, prod_container, vec![setup_cmd])
             .await.map(|(ok, _, _)| ok).unwrap_or(false)
     };
     if !setup_result {
@@ -773,6 +1001,46 @@ async fn get_update_status(State(state): State<ApiState>) -> impl IntoResponse {
     }
 }
 
+/// POST /api/applications/{id}/join-code
+/// Join code for installing the app's agent by hand. Enrolling with it
+/// gives the agent a new token: the current one stops working.
+async fn create_join_code(State(state): State<ApiState>, Path(id): Path<String>) -> impl IntoResponse {
+    let Some(registry) = &state.registry else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"success": false, "error": "Registry not available"}))).into_response();
+    };
+    if registry.get_application(&id).await.is_none() {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"success": false, "error": "Application not found"}))).into_response();
+    }
+    let target = JoinTarget::App { app_id: id.clone() };
+    match enrollment::issue(&mut *state.join_codes.write().await, target, enrollment::DEFAULT_TTL) {
+        Ok(join) => {
+            info!(app_id = id, expires_at = %join.expires_at, "Agent join code issued");
+            let config = format!(
+                "homeroute_address = \"10.0.0.254\"\nhomeroute_port = {}\njoin_code = \"{}\"\ninterface = \"host0\"\n{}",
+                state.env.api_port, join.code, hr_registry::update::config_line()
+            );
+            Json(serde_json::json!({"success": true, "join_code": join, "config": config})).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"success": false, "error": e}))).into_response(),
+    }
+}
+
+/// Give the agent enrolling with `join_code` a new token. Returns its
+/// token and service name.
+async fn enroll_agent(state: &ApiState, registry: &hr_registry::AgentRegistry, join_code: &str) -> Result<(String, String), String> {
+    let target = enrollment::redeem(&mut *state.join_codes.write().await, join_code, chrono::Utc::now())?;
+    let JoinTarget::App { app_id } = target else {
+        return Err("This join code is for a host".to_string());
+    };
+    let app = registry.get_application(&app_id).await.ok_or("Application not found")?;
+    let token = registry
+        .regenerate_token(&app_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Application not found")?;
+    Ok((token, app.slug))
+}
+
 /// Fix a failed agent update via machinectl exec (local) or remote exec (remote host).
 async fn fix_agent_update(
     State(state): State<ApiState>,
@@ -807,7 +1075,7 @@ async fn fix_agent_update(
             let cmd = vec![
                 "bash".to_string(), "-c".to_string(),
                 format!(
-                    "curl -fsSL http://10.0.0.254:{}/api/applications/agents/binary -o /usr/local/bin/hr-agent.new && \
+                    "curl -fsSL \"http://10.0.0.254:{}/api/applications/agents/binary?arch=$(uname -m)\" -o /usr/local/bin/hr-agent.new && \
                      chmod +x /usr/local/bin/hr-agent.new && \
                      mv /usr/local/bin/hr-agent.new /usr/local/bin/hr-agent && \
                      systemctl restart hr-agent",
@@ -896,59 +1164,40 @@ async fn exec_in_container(
 
 // ── Agent binary distribution ────────────────────────────────
 
-const AGENT_BINARY_PATH: &str = "/opt/homeroute/data/agent-binaries/hr-agent";
+/// How long the previous address of a migrated app keeps serving the
+/// sessions pinned to it before its routes drop it.
+const MIGRATION_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
 
-async fn agent_version() -> impl IntoResponse {
-    let binary_path = std::path::Path::new(AGENT_BINARY_PATH);
-    if !binary_path.exists() {
-        return (
+async fn agent_version(Query(query): Query<ArchQuery>) -> impl IntoResponse {
+    match BinaryStore::default().artifact("hr-agent", query.arch()) {
+        Ok(Some(artifact)) => Json(serde_json::json!({
+            "success": true,
+            "arch": artifact.arch,
+            "version": artifact.version,
+            "sha256": artifact.sha256,
+            "size": artifact.size
+        }))
+        .into_response(),
+        Ok(None) => (
             StatusCode::NOT_FOUND,
             Json(serde_json::json!({"success": false, "error": "Agent binary not found"})),
         )
-            .into_response();
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"success": false, "error": e})),
+        )
+            .into_response(),
     }
-
-    // Read binary and compute SHA256
-    let bytes = match tokio::fs::read(binary_path).await {
-        Ok(b) => b,
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"success": false, "error": e.to_string()})),
-            )
-                .into_response();
-        }
-    };
-
-    let digest = ring::digest::digest(&ring::digest::SHA256, &bytes);
-    let sha256: String = digest.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
-
-    // Version from the binary metadata (or file mtime as fallback)
-    let version = match tokio::fs::metadata(binary_path).await {
-        Ok(m) => {
-            if let Ok(modified) = m.modified() {
-                let dt: chrono::DateTime<chrono::Utc> = modified.into();
-                dt.format("%Y%m%d-%H%M%S").to_string()
-            } else {
-                "unknown".to_string()
-            }
-        }
-        Err(_) => "unknown".to_string(),
-    };
-
-    Json(serde_json::json!({
-        "success": true,
-        "version": version,
-        "sha256": sha256,
-        "size": bytes.len()
-    }))
-    .into_response()
 }
 
-async fn agent_binary() -> impl IntoResponse {
-    let binary_path = std::path::Path::new(AGENT_BINARY_PATH);
-    match tokio::fs::read(binary_path).await {
-        Ok(bytes) => (
+async fn agent_binary(Query(query): Query<ArchQuery>) -> impl IntoResponse {
+    let bytes = match BinaryStore::default().find("hr-agent", query.arch()) {
+        Some(path) => tokio::fs::read(path).await.ok(),
+        None => None,
+    };
+    match bytes {
+        Some(bytes) => (
             StatusCode::OK,
             [
                 (header::CONTENT_TYPE, "application/octet-stream"),
@@ -960,7 +1209,7 @@ async fn agent_binary() -> impl IntoResponse {
             bytes,
         )
             .into_response(),
-        Err(_) => (
+        None => (
             StatusCode::NOT_FOUND,
             Json(serde_json::json!({"error": "Agent binary not found"})),
         )
@@ -1096,11 +1345,38 @@ async fn handle_agent_ws(state: ApiState, mut socket: WebSocket) {
     // Wait for Auth message with a timeout
     let auth_msg = tokio::time::timeout(std::time::Duration::from_secs(5), socket.recv()).await;
 
-    let (token, service_name, version, reported_ipv4) = match auth_msg {
+    let (token, service_name, version, reported_ipv4, protocol) = match auth_msg {
         Ok(Some(Ok(Message::Text(text)))) => {
             match serde_json::from_str::<AgentMessage>(&text) {
-                Ok(AgentMessage::Auth { token, service_name, version, ipv4_address }) => {
-                    (token, service_name, version, ipv4_address)
+                Ok(AgentMessage::Auth { token, service_name, version, ipv4_address, protocol_version, capabilities, arch }) => {
+                    let protocol = Negotiated::new(protocol_version, &capabilities, AGENT_CAPABILITIES).with_arch(arch.as_deref());
+                    (token, service_name, version, ipv4_address, protocol)
+                }
+                Ok(AgentMessage::Enroll { join_code, version }) => {
+                    let result = match enroll_agent(&state, &registry, &join_code).await {
+                        Ok((token, service_name)) => {
+                            info!(service = service_name, version, "Agent enrolled");
+                            hr_registry::protocol::RegistryMessage::EnrollResult {
+                                success: true,
+                                error: None,
+                                token: Some(token),
+                                service_name: Some(service_name),
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Agent enrollment failed: {e}");
+                            hr_registry::protocol::RegistryMessage::EnrollResult {
+                                success: false,
+                                error: Some(e),
+                                token: None,
+                                service_name: None,
+                            }
+                        }
+                    };
+                    // The agent connects again with Auth
+                    let _ = socket.send(Message::Text(serde_json::to_string(&result).unwrap().into())).await;
+                    let _ = socket.send(Message::Close(None)).await;
+                    return;
                 }
                 _ => {
                     warn!("Agent WS: expected Auth message, got something else");
@@ -1122,6 +1398,8 @@ async fn handle_agent_ws(state: ApiState, mut socket: WebSocket) {
             success: false,
             error: Some("Invalid credentials".into()),
             app_id: None,
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: Vec::new(),
         };
         let _ = socket.send(Message::Text(serde_json::to_string(&reject).unwrap().into())).await;
         let _ = socket.send(Message::Close(None)).await;
@@ -1134,7 +1412,8 @@ async fn handle_agent_ws(state: ApiState, mut socket: WebSocket) {
     let (tx, mut rx) = tokio::sync::mpsc::channel(32);
 
     // Notify registry of connection (pushes config, increments active count)
-    if let Err(e) = registry.on_agent_connected(&app_id, tx, version, reported_ipv4).await {
+    let capabilities = protocol.capabilities.clone();
+    if let Err(e) = registry.on_agent_connected(&app_id, tx, version, protocol, reported_ipv4).await {
         error!(app_id, "Agent provisioning failed: {e}");
         // Decrement the count that was already incremented
         registry.on_agent_disconnected(&app_id).await;
@@ -1149,6 +1428,8 @@ async fn handle_agent_ws(state: ApiState, mut socket: WebSocket) {
         success: true,
         error: None,
         app_id: Some(app_id.clone()),
+        protocol_version: PROTOCOL_VERSION,
+        capabilities,
     };
     if socket.send(Message::Text(serde_json::to_string(&success).unwrap().into())).await.is_err() {
         registry.on_agent_disconnected(&app_id).await;
@@ -1182,7 +1463,7 @@ async fn handle_agent_ws(state: ApiState, mut socket: WebSocket) {
                             Ok(AgentMessage::Error { message }) => {
                                 warn!(app_id, message, "Agent reported error");
                             }
-                            Ok(AgentMessage::Auth { .. }) => {
+                            Ok(AgentMessage::Auth { .. } | AgentMessage::Enroll { .. }) => {
                                 // Duplicate auth, ignore
                             }
                             Ok(AgentMessage::Metrics(m)) => {
@@ -1276,6 +1557,54 @@ async fn handle_agent_ws(state: ApiState, mut socket: WebSocket) {
                                     schemas: overviews,
                                 }).await;
                             }
+                            Ok(AgentMessage::QueueRequest { request_id, request }) => {
+                                // Answered from a task: a receive may wait for a message
+                                let queues = state.queues.clone();
+                                let registry = registry.clone();
+                                let app_id = app_id.clone();
+                                tokio::spawn(async move {
+                                    let result = match registry.get_application(&app_id).await {
+                                        Some(app) => queues.handle(&app.slug, request).await.map_err(|e| e.to_string()),
+                                        None => Err("Application not found".to_string()),
+                                    };
+                                    let (data, error) = match result {
+                                        Ok(v) => (Some(v), None),
+                                        Err(e) => (None, Some(e)),
+                                    };
+                                    let _ = registry.send_to_agent(&app_id, hr_registry::protocol::RegistryMessage::QueueResult {
+                                        request_id,
+                                        data,
+                                        error,
+                                    }).await;
+                                });
+                            }
+                            Ok(AgentMessage::PubSubRequest { request_id, request }) => {
+                                // Answered from a task: publishing waits for the deliveries
+                                let pubsub = state.pubsub.clone();
+                                let registry = registry.clone();
+                                let app_id = app_id.clone();
+                                tokio::spawn(async move {
+                                    let result = match registry.get_application(&app_id).await {
+                                        Some(app) => pubsub.handle(&app_id, &app.slug, request).await.map_err(|e| e.to_string()),
+                                        None => Err("Application not found".to_string()),
+                                    };
+                                    let (data, error) = match result {
+                                        Ok(v) => (Some(v), None),
+                                        Err(e) => (None, Some(e)),
+                                    };
+                                    let _ = registry.send_to_agent(&app_id, hr_registry::protocol::RegistryMessage::PubSubResult {
+                                        request_id,
+                                        data,
+                                        error,
+                                    }).await;
+                                });
+                            }
+                            Ok(AgentMessage::LogStreamData { request_id, lines }) => {
+                                registry.on_log_stream_data(&request_id, lines).await;
+                            }
+                            Ok(AgentMessage::LogStreamEnd { request_id, error }) => {
+                                registry.on_log_stream_end(&request_id, error).await;
+                            }
                             Ok(AgentMessage::IpUpdate { ipv4_address }) => {
                                 info!(app_id, ipv4_address, "Agent reported IP update");
                                 // Remove old DNS records for previous IP
@@ -1295,14 +1624,21 @@ async fn handle_agent_ws(state: ApiState, mut socket: WebSocket) {
                                 let apps = registry.list_applications().await;
                                 if let Some(app) = apps.iter().find(|a| a.id == app_id) {
                                     if let Some(target_ip) = app.ipv4_address {
-                                        // Clear old routes for this app
+                                        // Clear routes the agent no longer publishes
+                                        // (custom domains follow the primary route below)
                                         let base_domain = &state.env.base_domain;
                                         for domain in app.domains(base_domain) {
-                                            state.proxy.remove_app_route(&domain);
+                                            if !routes.iter().any(|r| r.domain == domain)
+                                                && !app.active_custom_domains().any(|d| d == domain)
+                                            {
+                                                state.proxy.remove_app_route(&domain);
+                                            }
                                         }
-                                        // Set new routes from agent
+                                        // Set new routes from agent. After a migration the previous
+                                        // address keeps its pinned sessions until they close.
+                                        let mut drained = Vec::new();
                                         for route in &routes {
-                                            state.proxy.set_app_route(route.domain.clone(), AppRoute {
+                                            let previous = state.proxy.set_app_route_draining(route.domain.clone(), AppRoute {
                                                 app_id: app.id.clone(),
                                                 host_id: app.host_id.clone(),
                                                 target_ip,
@@ -1312,11 +1648,28 @@ async fn handle_agent_ws(state: ApiState, mut socket: WebSocket) {
                                                 service_type: route.service_type,
                                                 wake_page_enabled: app.wake_page_enabled,
                                                 local_only: app.frontend.local_only,
+                                                extra_targets: Vec::new(),
+                                                load_balancing: Default::default(),
+                                                sticky: true,
+                                            });
+                                            if let Some(old) = previous
+                                                && !drained.contains(&old)
+                                            {
+                                                drained.push(old);
+                                            }
+                                        }
+                                        crate::custom_domains::alias_routes(&state.proxy, app, base_domain);
+                                        for target in drained {
+                                            let proxy = state.proxy.clone();
+                                            let app_id = app.id.clone();
+                                            tokio::spawn(async move {
+                                                proxy.finish_app_drain(&app_id, &target, MIGRATION_DRAIN_TIMEOUT).await;
                                             });
                                         }
                                         // Add local DNS A records for direct local access
                                         let ip_str = target_ip.to_string();
                                         add_agent_dns_records(&state.dns, &app.slug, base_domain, &ip_str, app.environment).await;
+                                        crate::custom_domains::alias_dns(&state.dns, app, base_domain).await;
                                     }
                                 }
                             }
@@ -1335,6 +1688,7 @@ async fn handle_agent_ws(state: ApiState, mut socket: WebSocket) {
     // Decrement connection count. Only remove routes when the LAST connection closes.
     let is_last = registry.on_agent_disconnected(&app_id).await;
     if is_last {
+        state.pubsub.remove_subscriber(&app_id);
         let apps = registry.list_applications().await;
         if let Some(app) = apps.iter().find(|a| a.id == app_id) {
             let base_domain = &state.env.base_domain;
@@ -1387,78 +1741,132 @@ pub(crate) async fn update_migration_phase(
     });
 }
 
-/// Stream data from an AsyncRead source to a remote host-agent in 512KB binary chunks.
-/// Returns total bytes transferred and final sequence number.
-pub(crate) async fn stream_to_remote(
-    registry: &Arc<hr_registry::AgentRegistry>,
-    target_host_id: &str,
-    transfer_id: &str,
-    reader: &mut (impl tokio::io::AsyncRead + Unpin),
-    total_bytes: u64,
-    cancelled: &Arc<AtomicBool>,
-    migrations: &Arc<tokio::sync::RwLock<std::collections::HashMap<String, MigrationState>>>,
-    events: &Arc<hr_common::events::EventBus>,
-    app_id: &str,
-    pct_start: u8,
-    pct_end: u8,
-    phase: MigrationPhase,
-) -> Result<(u64, u32), String> {
-    let mut buf = vec![0u8; 524288]; // 512KB
-    let mut transferred: u64 = 0;
-    let mut sequence: u32 = 0;
-    loop {
-        if cancelled.load(Ordering::SeqCst) {
-            let _ = registry.send_host_command(
-                target_host_id,
-                HostRegistryMessage::CancelTransfer { transfer_id: transfer_id.to_string() },
-            ).await;
-            return Err("Migration cancelled by user".to_string());
-        }
+/// Why a stream pushed to a remote host stopped before its end.
+pub(crate) enum StreamError {
+    Failed(String),
+    /// The target lost chunks: resume from its point, or wait for it when
+    /// the point is not known yet (the target disconnected).
+    Interrupted(Option<ResumePoint>),
+}
 
-        let n = match reader.read(&mut buf).await {
-            Ok(0) => break,
-            Ok(n) => n,
-            Err(e) => return Err(format!("Read error: {e}")),
-        };
+/// A transfer pushed to a remote host-agent, with its progress reporting.
+pub(crate) struct RemotePush<'a> {
+    pub registry: &'a Arc<hr_registry::AgentRegistry>,
+    pub target_host_id: &'a str,
+    pub transfer_id: &'a str,
+    pub app_id: &'a str,
+    pub cancelled: &'a Arc<AtomicBool>,
+    pub migrations: &'a Arc<tokio::sync::RwLock<std::collections::HashMap<String, MigrationState>>>,
+    pub events: &'a Arc<hr_common::events::EventBus>,
+    /// `ResumeTransfer` points of the target; `None` when it cannot resume.
+    pub resumes: Option<tokio::sync::mpsc::UnboundedReceiver<ResumePoint>>,
+    pub compression: Compression,
+}
 
-        let chunk = &buf[..n];
-        let checksum = xxhash_rust::xxh32::xxh32(chunk, 0);
-
-        if let Err(e) = registry.send_host_command(
-            target_host_id,
-            HostRegistryMessage::ReceiveChunkBinary {
-                transfer_id: transfer_id.to_string(),
-                sequence,
-                size: n as u32,
-                checksum,
-            },
-        ).await {
-            return Err(format!("Send chunk metadata failed: {e}"));
+impl RemotePush<'_> {
+    /// A message to the target could not be sent.
+    pub(crate) fn lost(&self, error: String) -> StreamError {
+        if self.resumes.is_some() {
+            warn!(transfer_id = self.transfer_id, %error, "Transfer interrupted, waiting for the target to resume");
+            StreamError::Interrupted(None)
+        } else {
+            StreamError::Failed(error)
         }
+    }
 
-        if let Err(e) = registry.send_host_binary(
-            target_host_id,
-            chunk.to_vec(),
-        ).await {
-            return Err(format!("Send binary chunk failed: {e}"));
+    /// Stream a directory as tar to the target in fixed chunks, compressed as
+    /// agreed, ending with the stream's `TransferManifest`. Chunks before
+    /// `from` are regenerated and checked but not sent. Returns the tar bytes
+    /// streamed.
+    pub(crate) async fn stream(
+        &mut self,
+        stream: TransferStream,
+        dir: &std::path::Path,
+        total_bytes: u64,
+        (pct_start, pct_end, phase): (u8, u8, MigrationPhase),
+        from: ResumePoint,
+    ) -> Result<u64, StreamError> {
+        let mut tar_child = tokio::process::Command::new("tar")
+            .args(["cf", "-", "-C", &dir.to_string_lossy(), "."])
+            .stdout(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| StreamError::Failed(format!("Failed to spawn tar: {e}")))?;
+        let (tar_out, tar_bytes) = Counted::new(tar_child.stdout.take().unwrap());
+        let mut reader = ChunkReader::new(self.compression.encoder(tar_out));
+        if from.stream == stream && from.next_sequence > 0 {
+            reader
+                .skip_to(from.next_sequence, from.last_checksum)
+                .await
+                .map_err(StreamError::Failed)?;
+            info!(transfer_id = self.transfer_id, ?stream, sequence = from.next_sequence, "Resuming transfer stream");
         }
 
-        transferred += n as u64;
-        sequence += 1;
-        let pct = (pct_start as u64 + (transferred * (pct_end - pct_start) as u64 / total_bytes.max(1))) as u8;
+        loop {
+            if self.cancelled.load(Ordering::SeqCst) {
+                let _ = self.registry.send_host_command(
+                    self.target_host_id,
+                    HostRegistryMessage::CancelTransfer { transfer_id: self.transfer_id.to_string() },
+                ).await;
+                return Err(StreamError::Failed("Migration cancelled by user".to_string()));
+            }
+            if let Some(resumes) = &mut self.resumes
+                && let Ok(point) = resumes.try_recv()
+            {
+                return Err(StreamError::Interrupted(Some(point)));
+            }
 
-        if sequence % 4 == 0 || transferred >= total_bytes {
-            update_migration_phase(migrations, events, app_id, transfer_id, phase.clone(), pct.min(pct_end), transferred, total_bytes, None).await;
-        } else {
-            let mut m = migrations.write().await;
-            if let Some(state) = m.get_mut(transfer_id) {
-                state.progress_pct = pct.min(pct_end);
-                state.bytes_transferred = transferred;
+            let (entry, chunk) = match reader.next_chunk().await {
+                Ok(Some(c)) => c,
+                Ok(None) => break,
+                Err(e) => return Err(StreamError::Failed(format!("Read error: {e}"))),
+            };
+            let transferred = tar_bytes.load(Ordering::Relaxed);
+
+            if let Err(e) = self.registry.send_host_chunk(
+                self.target_host_id,
+                HostRegistryMessage::ReceiveChunkBinary {
+                    transfer_id: self.transfer_id.to_string(),
+                    sequence: entry.sequence,
+                    size: entry.size,
+                    checksum: entry.checksum,
+                    offset: entry.offset,
+                },
+                chunk.to_vec(),
+            ).await {
+                return Err(self.lost(format!("Send binary chunk failed: {e}")));
+            }
+
+            let pct = (pct_start as u64 + (transferred * (pct_end - pct_start) as u64 / total_bytes.max(1))) as u8;
+            if entry.sequence % 4 == 3 || transferred >= total_bytes {
+                update_migration_phase(self.migrations, self.events, self.app_id, self.transfer_id, phase.clone(), pct.min(pct_end), transferred, total_bytes, None).await;
+            } else {
+                let mut m = self.migrations.write().await;
+                if let Some(state) = m.get_mut(self.transfer_id) {
+                    state.progress_pct = pct.min(pct_end);
+                    state.bytes_transferred = transferred;
+                }
             }
         }
+        let _ = tar_child.wait().await;
+
+        let (chunk_count, total, digest) = reader.summary();
+        if self.resumes.is_some()
+            && let Err(e) = self.registry.send_host_command(
+                self.target_host_id,
+                HostRegistryMessage::TransferManifest {
+                    transfer_id: self.transfer_id.to_string(),
+                    stream,
+                    chunk_count,
+                    total_bytes: total,
+                    digest,
+                },
+            ).await
+        {
+            return Err(self.lost(format!("Send transfer manifest failed: {e}")));
+        }
+        Ok(tar_bytes.load(Ordering::Relaxed))
     }
-
-    Ok((transferred, sequence))
 }
 
 // Inter-host nspawn migration is in container_manager.rs
@@ -1,11 +1,18 @@
 use axum::{
     extract::State,
-    extract::Path,
-    http::{header, HeaderMap},
+    extract::{Path, Query, RawQuery},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
     routing::{delete, get, post},
-    Json, Router,
+    Form, Json, Router,
 };
 use axum_extra::extract::CookieJar;
+use base64::Engine;
+use hr_auth::oidc::{AuthorizeOutcome, AuthorizeRequest, TokenRequest};
+use hr_auth::sso::SsoConfig;
+use hr_auth::throttle::LoginCheck;
+use hr_auth::users::UserInfo;
+use hr_auth::webauthn::{AuthenticationCredential, RegistrationCredential};
 use serde::Deserialize;
 use serde_json::{json, Value};
 
@@ -18,8 +25,26 @@ pub fn router() -> Router<ApiState> {
         .route("/check", get(check))
         .route("/forward-check", get(forward_check))
         .route("/me", get(me))
-        .route("/sessions", get(list_sessions))
+        .route("/sessions", get(list_sessions).delete(revoke_all_sessions))
         .route("/sessions/{id}", delete(revoke_session))
+        .route("/lockouts", get(list_lockouts).delete(clear_lockouts))
+        .route("/webauthn/register/start", post(webauthn_register_start))
+        .route("/webauthn/register/finish", post(webauthn_register_finish))
+        .route("/webauthn/login/start", post(webauthn_login_start))
+        .route("/webauthn/login/finish", post(webauthn_login_finish))
+        .route("/webauthn/credentials", get(list_passkeys))
+        .route("/webauthn/credentials/{id}", delete(delete_passkey))
+        .route("/oidc/.well-known/openid-configuration", get(oidc_discovery))
+        .route("/oidc/jwks", get(oidc_jwks))
+        .route("/oidc/authorize", get(oidc_authorize))
+        .route("/oidc/token", post(oidc_token))
+        .route("/oidc/userinfo", get(oidc_userinfo).post(oidc_userinfo))
+        .route("/oidc/clients", get(list_oidc_clients).post(create_oidc_client))
+        .route("/oidc/clients/{id}", delete(delete_oidc_client))
+        .route("/sso", get(sso_info))
+        .route("/sso/login", get(sso_login))
+        .route("/sso/callback", get(sso_callback))
+        .route("/sso/config", get(get_sso_config).put(update_sso_config))
 }
 
 #[derive(Deserialize)]
@@ -28,6 +53,9 @@ struct LoginRequest {
     password: String,
     #[serde(default)]
     remember_me: bool,
+    /// Code TOTP ou code de secours (comptes avec second facteur)
+    #[serde(default)]
+    totp_code: Option<String>,
 }
 
 fn cookie_domain(headers: &HeaderMap, base_domain: &str) -> Option<String> {
@@ -74,6 +102,20 @@ fn build_set_cookie(session_id: &str, max_age_secs: Option<i64>, headers: &Heade
     parts.join("; ")
 }
 
+/// Client address set by hr-proxy (already resolved through the cloud relay
+/// and trusted proxies), else the last hop of X-Forwarded-For.
+fn client_ip(headers: &HeaderMap) -> Option<String> {
+    if let Some(ip) = headers.get("x-real-ip").and_then(|v| v.to_str().ok()) {
+        return Some(ip.trim().to_string());
+    }
+    headers
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|chain| chain.rsplit(',').next())
+        .map(|ip| ip.trim().to_string())
+        .filter(|ip| !ip.is_empty())
+}
+
 fn clear_cookie(headers: &HeaderMap, base_domain: &str) -> String {
     build_set_cookie("deleted", Some(0), headers, base_domain)
 }
@@ -93,9 +135,28 @@ async fn login(
         );
     }
 
+    // Verrouillage avant tout calcul Argon2
+    let ip = client_ip(&headers);
+    match state.auth.throttle.check(&username, ip.as_deref()) {
+        LoginCheck::Allowed => {}
+        LoginCheck::Locked { retry_after } => {
+            let secs = retry_after.as_secs() + 1;
+            return (
+                axum::http::StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, secs.to_string())],
+                Json(json!({
+                    "success": false,
+                    "retry_after": secs,
+                    "error": format!("Trop de tentatives, reessayez dans {} s", secs)
+                })),
+            );
+        }
+    }
+
     let user = match state.auth.users.get_with_password(&username) {
         Some(u) => u,
         None => {
+            state.auth.throttle.record_failure(&username, ip.as_deref());
             return (
                 axum::http::StatusCode::UNAUTHORIZED,
                 [(header::SET_COOKIE, String::new())],
@@ -113,6 +174,7 @@ async fn login(
     }
 
     if !hr_auth::users::verify_password(&body.password, &user.password_hash) {
+        state.auth.throttle.record_failure(&username, ip.as_deref());
         return (
             axum::http::StatusCode::UNAUTHORIZED,
             [(header::SET_COOKIE, String::new())],
@@ -120,13 +182,59 @@ async fn login(
         );
     }
 
-    let ip = headers.get("x-real-ip")
-        .or_else(|| headers.get("x-forwarded-for"))
-        .and_then(|v| v.to_str().ok());
+    // Second facteur : le client renvoie identifiants + code
+    let mfa = state.auth.users.totp_enabled(&username);
+    if mfa {
+        let code = body.totp_code.as_deref().map(str::trim).filter(|c| !c.is_empty());
+        let error = match code {
+            None => Some("Code de verification requis"),
+            Some(code) if !state.auth.users.verify_totp(&username, code) => Some("Code de verification invalide"),
+            Some(_) => None,
+        };
+        if let Some(error) = error {
+            // Un code absent est l'étape normale, pas un échec
+            if code.is_some() {
+                state.auth.throttle.record_failure(&username, ip.as_deref());
+            }
+            return (
+                axum::http::StatusCode::UNAUTHORIZED,
+                [(header::SET_COOKIE, String::new())],
+                Json(json!({"success": false, "totp_required": true, "error": error})),
+            );
+        }
+    }
+    state.auth.throttle.record_success(&username, ip.as_deref());
+
+    open_session(
+        &state,
+        &headers,
+        &username,
+        json!({
+            "username": user.username,
+            "displayname": user.displayname,
+            "email": user.email,
+            "groups": user.groups
+        }),
+        body.remember_me,
+        mfa,
+    )
+}
+
+/// Ouvre une session et pose le cookie (connexion par mot de passe ou passkey).
+/// `mfa` : second facteur vérifié (routes sensibles).
+fn open_session(
+    state: &ApiState,
+    headers: &HeaderMap,
+    username: &str,
+    user: Value,
+    remember_me: bool,
+    mfa: bool,
+) -> (axum::http::StatusCode, [(header::HeaderName, String); 1], Json<Value>) {
+    let ip = client_ip(headers);
     let ua = headers.get("user-agent").and_then(|v| v.to_str().ok());
 
     let (session_id, expires_at) = match state.auth.sessions.create(
-        &username, ip, ua, body.remember_me,
+        username, ip.as_deref(), ua, remember_me, mfa,
     ) {
         Ok(v) => v,
         Err(e) => {
@@ -139,22 +247,17 @@ async fn login(
         }
     };
 
-    state.auth.users.update_last_login(&username);
+    state.auth.users.update_last_login(username);
 
-    let max_age = if body.remember_me { Some(30 * 24 * 60 * 60) } else { None };
-    let cookie = build_set_cookie(&session_id, max_age, &headers, &state.auth.base_domain);
+    let max_age = if remember_me { Some(30 * 24 * 60 * 60) } else { None };
+    let cookie = build_set_cookie(&session_id, max_age, headers, &state.auth.base_domain);
 
     (
         axum::http::StatusCode::OK,
         [(header::SET_COOKIE, cookie)],
         Json(json!({
             "success": true,
-            "user": {
-                "username": user.username,
-                "displayname": user.displayname,
-                "email": user.email,
-                "groups": user.groups
-            },
+            "user": user,
             "expires_at": expires_at
         })),
     )
@@ -271,12 +374,14 @@ async fn me(
                 "displayName": user.displayname,
                 "email": user.email,
                 "groups": user.groups,
-                "isAdmin": is_admin
+                "isAdmin": is_admin,
+                "totpEnabled": user.totp_enabled
             },
             "session": {
                 "created_at": session.created_at,
                 "expires_at": session.expires_at,
-                "ip_address": session.ip_address
+                "ip_address": session.ip_address,
+                "mfa": session.mfa
             },
             "authMethod": "session"
         })),
@@ -285,6 +390,7 @@ async fn me(
 
 async fn list_sessions(
     State(state): State<ApiState>,
+    headers: HeaderMap,
     jar: CookieJar,
 ) -> (axum::http::StatusCode, Json<Value>) {
     let session_id = match jar.get("auth_session") {
@@ -292,7 +398,8 @@ async fn list_sessions(
         None => return (axum::http::StatusCode::UNAUTHORIZED, Json(json!({"success": false, "error": "Non authentifie"}))),
     };
 
-    let session = match state.auth.sessions.validate(&session_id) {
+    let ip = client_ip(&headers);
+    let session = match state.auth.sessions.validate_from(&session_id, ip.as_deref()) {
         Ok(Some(s)) => s,
         _ => return (axum::http::StatusCode::UNAUTHORIZED, Json(json!({"success": false, "error": "Session expiree"}))),
     };
@@ -305,11 +412,15 @@ async fn list_sessions(
             json!({
                 "id": s.id,
                 "current": s.id == session_id,
+                "device": s.device(),
                 "ip_address": s.ip_address,
+                "last_ip": s.last_ip,
                 "user_agent": s.user_agent,
                 "created_at": s.created_at,
                 "last_activity": s.last_activity,
-                "remember_me": s.remember_me
+                "expires_at": s.expires_at,
+                "remember_me": s.remember_me,
+                "mfa": s.mfa
             })
         })
         .collect();
@@ -350,6 +461,263 @@ async fn revoke_session(
     (axum::http::StatusCode::OK, Json(json!({"success": true})))
 }
 
+#[derive(Deserialize)]
+struct RevokeAllQuery {
+    /// Révoquer aussi la session courante (déconnexion partout)
+    #[serde(default)]
+    current: bool,
+}
+
+/// Révoque toutes les autres sessions de l'utilisateur (cookie volé, appareil
+/// perdu) ; avec `?current=true`, la session courante aussi.
+async fn revoke_all_sessions(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Query(query): Query<RevokeAllQuery>,
+) -> (axum::http::StatusCode, [(header::HeaderName, String); 1], Json<Value>) {
+    let unauthorized = |error: &str| {
+        (
+            axum::http::StatusCode::UNAUTHORIZED,
+            [(header::SET_COOKIE, String::new())],
+            Json(json!({"success": false, "error": error})),
+        )
+    };
+    let Some(session_id) = jar.get("auth_session").map(|c| c.value().to_string()) else {
+        return unauthorized("Non authentifie");
+    };
+    let session = match state.auth.sessions.validate(&session_id) {
+        Ok(Some(s)) => s,
+        _ => return unauthorized("Session expiree"),
+    };
+
+    let mut revoked = match state.auth.sessions.delete_others(&session.user_id, &session_id) {
+        Ok(count) => count,
+        Err(e) => {
+            tracing::error!("Session revocation failed: {}", e);
+            return (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                [(header::SET_COOKIE, String::new())],
+                Json(json!({"success": false, "error": "Erreur lors de la revocation"})),
+            );
+        }
+    };
+    let mut cookie = String::new();
+    if query.current {
+        let _ = state.auth.sessions.delete(&session_id);
+        cookie = clear_cookie(&headers, &state.auth.base_domain);
+        revoked += 1;
+    }
+    tracing::info!(user = %session.user_id, revoked, "Sessions revoked");
+
+    (
+        axum::http::StatusCode::OK,
+        [(header::SET_COOKIE, cookie)],
+        Json(json!({"success": true, "revoked": revoked})),
+    )
+}
+
+/// Utilisateur de la session courante (gestion des passkeys)
+pub(crate) fn session_user(state: &ApiState, jar: &CookieJar) -> Result<UserInfo, (axum::http::StatusCode, Json<Value>)> {
+    let unauthorized = |error: &str| (axum::http::StatusCode::UNAUTHORIZED, Json(json!({"success": false, "code": "unauthorized", "error": error})));
+    let Some(cookie) = jar.get("auth_session") else {
+        return Err(unauthorized("Non authentifie"));
+    };
+    let session = match state.auth.sessions.validate(cookie.value()) {
+        Ok(Some(s)) => s,
+        _ => return Err(unauthorized("Session expiree")),
+    };
+    match state.auth.users.get(&session.user_id) {
+        Some(user) if !user.disabled => Ok(user),
+        _ => Err(unauthorized("Utilisateur non trouve")),
+    }
+}
+
+async fn webauthn_register_start(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+) -> (axum::http::StatusCode, Json<Value>) {
+    let user = match session_user(&state, &jar) {
+        Ok(u) => u,
+        Err(e) => return e,
+    };
+    let existing = state.auth.users.passkeys(&user.username);
+    match state.auth.webauthn.start_registration(&user, &existing) {
+        Ok(options) => (axum::http::StatusCode::OK, Json(json!({"success": true, "publicKey": options}))),
+        Err(e) => (axum::http::StatusCode::SERVICE_UNAVAILABLE, Json(json!({"success": false, "error": e.to_string()}))),
+    }
+}
+
+#[derive(Deserialize)]
+struct PasskeyRegisterRequest {
+    #[serde(default)]
+    name: String,
+    credential: RegistrationCredential,
+}
+
+async fn webauthn_register_finish(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Json(body): Json<PasskeyRegisterRequest>,
+) -> (axum::http::StatusCode, Json<Value>) {
+    let user = match session_user(&state, &jar) {
+        Ok(u) => u,
+        Err(e) => return e,
+    };
+
+    let passkey = match state.auth.webauthn.finish_registration(&user.username, &body.name, &body.credential) {
+        Ok(p) => p,
+        Err(e) => {
+            tracing::debug!("Passkey registration failed for {}: {}", user.username, e);
+            return (axum::http::StatusCode::BAD_REQUEST, Json(json!({"success": false, "error": e.to_string()})));
+        }
+    };
+
+    let result = state.auth.users.add_passkey(&user.username, passkey.clone());
+    if !result.success {
+        return (axum::http::StatusCode::BAD_REQUEST, Json(json!({"success": false, "error": result.error})));
+    }
+
+    (
+        axum::http::StatusCode::OK,
+        Json(json!({"success": true, "passkey": {"id": passkey.id, "name": passkey.name, "created": passkey.created}})),
+    )
+}
+
+#[derive(Deserialize, Default)]
+struct PasskeyLoginStartRequest {
+    /// Sans nom d'utilisateur : clés découvrables
+    #[serde(default)]
+    username: Option<String>,
+}
+
+async fn webauthn_login_start(
+    State(state): State<ApiState>,
+    body: Option<Json<PasskeyLoginStartRequest>>,
+) -> (axum::http::StatusCode, Json<Value>) {
+    let username = body
+        .and_then(|Json(b)| b.username)
+        .map(|u| u.trim().to_lowercase())
+        .filter(|u| !u.is_empty());
+    // An unknown user gets an empty allow list rather than an error, so the
+    // endpoint can't be used to enumerate accounts
+    let allowed = username.as_deref().map(|u| state.auth.users.passkeys(u)).unwrap_or_default();
+    match state.auth.webauthn.start_authentication(username.as_deref(), &allowed) {
+        Ok(options) => (axum::http::StatusCode::OK, Json(json!({"success": true, "publicKey": options}))),
+        Err(e) => (axum::http::StatusCode::SERVICE_UNAVAILABLE, Json(json!({"success": false, "error": e.to_string()}))),
+    }
+}
+
+#[derive(Deserialize)]
+struct PasskeyLoginRequest {
+    credential: AuthenticationCredential,
+    #[serde(default)]
+    remember_me: bool,
+}
+
+async fn webauthn_login_finish(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Json(body): Json<PasskeyLoginRequest>,
+) -> (axum::http::StatusCode, [(header::HeaderName, String); 1], Json<Value>) {
+    let invalid = || {
+        (
+            axum::http::StatusCode::UNAUTHORIZED,
+            [(header::SET_COOKIE, String::new())],
+            Json(json!({"success": false, "error": "Passkey invalide"})),
+        )
+    };
+
+    let Some((username, passkey)) = state.auth.users.find_passkey(&body.credential.id) else {
+        return invalid();
+    };
+
+    // Même verrouillage que la connexion par mot de passe
+    let ip = client_ip(&headers);
+    if let LoginCheck::Locked { retry_after } = state.auth.throttle.check(&username, ip.as_deref()) {
+        let secs = retry_after.as_secs() + 1;
+        return (
+            axum::http::StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, secs.to_string())],
+            Json(json!({
+                "success": false,
+                "retry_after": secs,
+                "error": format!("Trop de tentatives, reessayez dans {} s", secs)
+            })),
+        );
+    }
+    let sign_count = match state.auth.webauthn.finish_authentication(&body.credential, &username, &passkey) {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::warn!("Passkey login failed for {}: {}", username, e);
+            state.auth.throttle.record_failure(&username, ip.as_deref());
+            return invalid();
+        }
+    };
+
+    let Some(user) = state.auth.users.get(&username) else {
+        return invalid();
+    };
+    if user.disabled {
+        return (
+            axum::http::StatusCode::UNAUTHORIZED,
+            [(header::SET_COOKIE, String::new())],
+            Json(json!({"success": false, "error": "Compte desactive"})),
+        );
+    }
+
+    state.auth.users.record_passkey_use(&username, &passkey.id, sign_count);
+    state.auth.throttle.record_success(&username, ip.as_deref());
+
+    // Passkey avec vérification de l'utilisateur (UV exigé) : vaut second facteur
+    open_session(
+        &state,
+        &headers,
+        &username,
+        json!({
+            "username": user.username,
+            "displayname": user.displayname,
+            "email": user.email,
+            "groups": user.groups
+        }),
+        body.remember_me,
+        true,
+    )
+}
+
+async fn list_passkeys(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+) -> (axum::http::StatusCode, Json<Value>) {
+    let user = match session_user(&state, &jar) {
+        Ok(u) => u,
+        Err(e) => return e,
+    };
+    let passkeys: Vec<Value> = state
+        .auth
+        .users
+        .passkeys(&user.username)
+        .iter()
+        .map(|p| json!({"id": p.id, "name": p.name, "created": p.created, "last_used": p.last_used}))
+        .collect();
+    (axum::http::StatusCode::OK, Json(json!({"success": true, "passkeys": passkeys})))
+}
+
+async fn delete_passkey(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path(id): Path<String>,
+) -> (axum::http::StatusCode, Json<Value>) {
+    let user = match session_user(&state, &jar) {
+        Ok(u) => u,
+        Err(e) => return e,
+    };
+    if !state.auth.users.remove_passkey(&user.username, &id) {
+        return (axum::http::StatusCode::NOT_FOUND, Json(json!({"success": false, "error": "Passkey non trouvee"})));
+    }
+    (axum::http::StatusCode::OK, Json(json!({"success": true})))
+}
+
 /// Query parameters for forward-check (used by agent proxies).
 #[derive(Deserialize, Default)]
 struct ForwardCheckQuery {
@@ -359,10 +727,13 @@ struct ForwardCheckQuery {
     uri: Option<String>,
     #[serde(default)]
     groups: Option<String>,
+    /// Route sensible : second facteur exigé
+    #[serde(default)]
+    mfa: bool,
 }
 
 /// Forward-auth endpoint for agent reverse proxies.
-/// Accepts query params: host, uri, groups (comma-separated) — or X-Forwarded-* headers.
+/// Accepts query params: host, uri, groups (comma-separated), mfa — or X-Forwarded-* headers.
 /// Returns 200 + user/groups on success, 401 + login_url on unauthenticated, 403 on forbidden.
 async fn forward_check(
     State(state): State<ApiState>,
@@ -397,7 +768,8 @@ async fn forward_check(
             })
         });
 
-    match check_forward_auth(&state.auth, cookie_value, forwarded_host, forwarded_uri, forwarded_proto, &allowed_groups) {
+    let ip = client_ip(&headers);
+    match check_forward_auth(&state.auth, cookie_value, ip.as_deref(), forwarded_host, forwarded_uri, forwarded_proto, &allowed_groups, query.mfa) {
         ForwardAuthResult::Success { user } => {
             let groups = user.groups.join(",");
             (axum::http::StatusCode::OK, Json(json!({"user": user.username, "groups": groups})))
@@ -410,3 +782,365 @@ async fn forward_check(
         }
     }
 }
+
+// ── OpenID Connect ──────────────────────────────────────────────────────
+
+async fn oidc_discovery(State(state): State<ApiState>) -> Json<Value> {
+    Json(state.auth.oidc.discovery())
+}
+
+async fn oidc_jwks(State(state): State<ApiState>) -> Json<Value> {
+    Json(state.auth.oidc.jwks())
+}
+
+/// Authorization endpoint: issues a code for the session user, or sends the
+/// browser through the login page and back here.
+async fn oidc_authorize(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    RawQuery(raw_query): RawQuery,
+    Query(req): Query<AuthorizeRequest>,
+) -> Response {
+    let session = jar
+        .get("auth_session")
+        .and_then(|c| state.auth.sessions.validate(c.value()).ok().flatten());
+    let user = session.as_ref().and_then(|s| state.auth.users.get(&s.user_id));
+    let mfa = session.is_some_and(|s| s.mfa);
+
+    match state.auth.oidc.authorize(&req, user.as_ref(), mfa) {
+        AuthorizeOutcome::Login => {
+            Redirect::to(&state.auth.oidc.login_url(raw_query.as_deref().unwrap_or(""))).into_response()
+        }
+        AuthorizeOutcome::Redirect(url) => Redirect::to(&url).into_response(),
+        AuthorizeOutcome::Invalid(error) => {
+            (StatusCode::BAD_REQUEST, Json(json!({"success": false, "error": error}))).into_response()
+        }
+    }
+}
+
+/// Token endpoint (client_secret_basic, client_secret_post or PKCE only).
+async fn oidc_token(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Form(mut req): Form<TokenRequest>,
+) -> Response {
+    if let Some((id, secret)) = basic_credentials(&headers) {
+        req.client_id = id;
+        req.client_secret = Some(secret);
+    }
+    let no_store = [(header::CACHE_CONTROL, "no-store")];
+    match state.auth.oidc.exchange_code(&req) {
+        Ok(tokens) => (no_store, Json(tokens)).into_response(),
+        Err(e) => {
+            let status = if e.error == "invalid_client" { StatusCode::UNAUTHORIZED } else { StatusCode::BAD_REQUEST };
+            tracing::debug!("OIDC token request from {} rejected: {}", req.client_id, e.description);
+            (
+                status,
+                no_store,
+                Json(json!({"error": e.error, "error_description": e.description})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// `Authorization: Basic base64(client_id:client_secret)`
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let encoded = value.strip_prefix("Basic ").or_else(|| value.strip_prefix("basic "))?;
+    let decoded = base64::engine::general_purpose::STANDARD.decode(encoded.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (id, secret) = decoded.split_once(':')?;
+    Some((id.to_string(), secret.to_string()))
+}
+
+async fn oidc_userinfo(State(state): State<ApiState>, headers: HeaderMap) -> Response {
+    let invalid = || {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer error=\"invalid_token\"")],
+            Json(json!({"error": "invalid_token"})),
+        )
+            .into_response()
+    };
+    let Some(token) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    else {
+        return invalid();
+    };
+    let Some(access) = state.auth.oidc.verify_access_token(token.trim()) else {
+        return invalid();
+    };
+    match state.auth.users.get(&access.username) {
+        Some(user) if !user.disabled => {
+            Json(Value::Object(hr_auth::oidc::user_claims(&user, &access.scope))).into_response()
+        }
+        _ => invalid(),
+    }
+}
+
+/// Utilisateurs et IP verrouillés après trop d'échecs de connexion (admins)
+async fn list_lockouts(State(state): State<ApiState>, jar: CookieJar) -> (StatusCode, Json<Value>) {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    (StatusCode::OK, Json(json!({"success": true, "lockouts": state.auth.throttle.lockouts()})))
+}
+
+/// Lève tous les verrouillages de connexion (admins)
+async fn clear_lockouts(State(state): State<ApiState>, jar: CookieJar) -> (StatusCode, Json<Value>) {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    state.auth.throttle.clear();
+    (StatusCode::OK, Json(json!({"success": true})))
+}
+
+/// Client registration is reserved to the admins group.
+pub(crate) fn admin_user(state: &ApiState, jar: &CookieJar) -> Result<UserInfo, (StatusCode, Json<Value>)> {
+    let user = session_user(state, jar)?;
+    if !user.groups.iter().any(|g| g == "admins") {
+        return Err((StatusCode::FORBIDDEN, Json(json!({"success": false, "code": "forbidden", "error": "Reserve aux administrateurs"}))));
+    }
+    Ok(user)
+}
+
+async fn list_oidc_clients(State(state): State<ApiState>, jar: CookieJar) -> (StatusCode, Json<Value>) {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    let clients: Vec<Value> = state
+        .auth
+        .oidc
+        .clients()
+        .iter()
+        .map(|c| {
+            json!({
+                "clientId": c.client_id,
+                "name": c.name,
+                "public": c.is_public(),
+                "redirectUris": c.redirect_uris,
+                "allowedGroups": c.allowed_groups,
+                "created": c.created
+            })
+        })
+        .collect();
+    (
+        StatusCode::OK,
+        Json(json!({"success": true, "issuer": state.auth.oidc.issuer(), "clients": clients})),
+    )
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateOidcClientRequest {
+    name: String,
+    redirect_uris: Vec<String>,
+    #[serde(default)]
+    allowed_groups: Vec<String>,
+    /// Public client (SPA, mobile): no secret, PKCE required
+    #[serde(default)]
+    public: bool,
+}
+
+async fn create_oidc_client(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Json(body): Json<CreateOidcClientRequest>,
+) -> (StatusCode, Json<Value>) {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    match state
+        .auth
+        .oidc
+        .register_client(&body.name, body.redirect_uris, body.allowed_groups, body.public)
+    {
+        Ok((client, secret)) => (
+            StatusCode::OK,
+            Json(json!({
+                "success": true,
+                "clientId": client.client_id,
+                "clientSecret": secret,
+                "issuer": state.auth.oidc.issuer()
+            })),
+        ),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(json!({"success": false, "error": e.to_string()}))),
+    }
+}
+
+async fn delete_oidc_client(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path(id): Path<String>,
+) -> (StatusCode, Json<Value>) {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    if !state.auth.oidc.delete_client(&id) {
+        return (StatusCode::NOT_FOUND, Json(json!({"success": false, "error": "Client non trouve"})));
+    }
+    (StatusCode::OK, Json(json!({"success": true})))
+}
+
+// ── External identity provider ──────────────────────────────────────────
+
+/// Login page button: whether external login is offered and its label.
+async fn sso_info(State(state): State<ApiState>) -> Json<Value> {
+    let config = state.auth.sso.config();
+    Json(json!({"success": true, "enabled": config.enabled, "name": config.name}))
+}
+
+#[derive(Deserialize, Default)]
+struct SsoLoginQuery {
+    #[serde(default)]
+    rd: Option<String>,
+}
+
+/// Send the browser to the provider; `rd` is reopened after login.
+async fn sso_login(State(state): State<ApiState>, Query(query): Query<SsoLoginQuery>) -> Response {
+    let base = &state.auth.base_domain;
+    let return_to = query.rd.filter(|rd| is_own_url(rd, base));
+    match state.auth.sso.start(return_to).await {
+        Ok(url) => Redirect::to(&url).into_response(),
+        Err(e) => Redirect::to(&sso_error_url(base, &e.to_string())).into_response(),
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct SsoCallbackQuery {
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    state: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+/// Provider callback: exchange the code, provision the account, open a
+/// session and go back to the requested page.
+async fn sso_callback(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Query(query): Query<SsoCallbackQuery>,
+) -> Response {
+    let base = state.auth.base_domain.clone();
+    let fail = |error: &str| Redirect::to(&sso_error_url(&base, error)).into_response();
+
+    if let Some(error) = query.error {
+        return fail(query.error_description.as_deref().unwrap_or(&error));
+    }
+    let (Some(code), Some(sso_state)) = (query.code, query.state) else {
+        return fail("Reponse du fournisseur incomplete");
+    };
+    let (identity, return_to) = match state.auth.sso.finish(&code, &sso_state).await {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("SSO login failed: {:#}", e);
+            return fail(&e.to_string());
+        }
+    };
+
+    let config = state.auth.sso.config();
+    let result = state.auth.users.login_external(&identity, config.auto_provision);
+    let user = match result.user {
+        Some(user) if result.success => user,
+        _ => return fail(result.error.as_deref().unwrap_or("Connexion refusee")),
+    };
+    if user.disabled {
+        return fail("Compte desactive");
+    }
+
+    let ip = client_ip(&headers);
+    let ua = headers.get("user-agent").and_then(|v| v.to_str().ok());
+    let session_id = match state.auth.sessions.create(&user.username, ip.as_deref(), ua, false, false) {
+        Ok((id, _)) => id,
+        Err(e) => {
+            tracing::error!("Session creation failed: {}", e);
+            return fail("Erreur lors de la connexion");
+        }
+    };
+    state.auth.users.update_last_login(&user.username);
+    tracing::info!("SSO login: {} ({})", user.username, identity.subject);
+
+    let cookie = build_set_cookie(&session_id, None, &headers, &base);
+    let target = return_to.unwrap_or_else(|| format!("https://auth.{}/", base));
+    ([(header::SET_COOKIE, cookie)], Redirect::to(&target)).into_response()
+}
+
+/// Login page with the error shown to the user.
+fn sso_error_url(base_domain: &str, error: &str) -> String {
+    let encoded: String = error
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!("https://auth.{}/login?sso_error={}", base_domain, encoded)
+}
+
+/// HTTPS URL on the base domain or one of its subdomains (no open redirect).
+fn is_own_url(url: &str, base_domain: &str) -> bool {
+    let Some(rest) = url.strip_prefix("https://") else {
+        return false;
+    };
+    let host = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host = host.rsplit_once(':').map_or(host, |(h, _)| h);
+    host == base_domain || host.ends_with(&format!(".{}", base_domain))
+}
+
+async fn get_sso_config(State(state): State<ApiState>, jar: CookieJar) -> (StatusCode, Json<Value>) {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    let mut config = state.auth.sso.config();
+    let has_secret = !config.client_secret.is_empty();
+    config.client_secret.clear();
+    (
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "config": config,
+            "hasClientSecret": has_secret,
+            "redirectUri": state.auth.sso.redirect_uri()
+        })),
+    )
+}
+
+/// An empty `client_secret` keeps the stored one.
+async fn update_sso_config(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Json(mut config): Json<SsoConfig>,
+) -> (StatusCode, Json<Value>) {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    if config.client_secret.is_empty() {
+        config.client_secret = state.auth.sso.config().client_secret;
+    }
+    match state.auth.sso.save_config(&config) {
+        Ok(()) => (StatusCode::OK, Json(json!({"success": true}))),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(json!({"success": false, "error": e.to_string()}))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_own_url() {
+        assert!(is_own_url("https://grafana.example.com/d/abc", "example.com"));
+        assert!(is_own_url("https://example.com:8443/", "example.com"));
+        assert!(!is_own_url("https://example.com.evil.net/", "example.com"));
+        assert!(!is_own_url("https://evilexample.com/", "example.com"));
+        assert!(!is_own_url("http://app.example.com/", "example.com"));
+        assert!(!is_own_url("//app.example.com/", "example.com"));
+    }
+}
@@ -0,0 +1,134 @@
+//! Backups of an application's container, under `/api/applications/{id}/backups`.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use chrono::TimeZone;
+use serde_json::{json, Value};
+
+use crate::backups::{self, BackupPolicy, BackupRecord, BackupTrigger, RestoreRequest};
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/{id}/backups", get(list_backups).post(create_backup))
+        .route("/{id}/backups/policy", get(get_policy).put(update_policy))
+        .route("/{id}/backups/{backup_id}", delete(delete_backup))
+        .route("/{id}/backups/{backup_id}/restore", post(restore_backup))
+}
+
+type ApiResult = (StatusCode, Json<Value>);
+
+fn failure(status: StatusCode, error: impl std::fmt::Display) -> ApiResult {
+    (status, Json(json!({"success": false, "error": error.to_string()})))
+}
+
+/// The app must have a container.
+async fn check_app(state: &ApiState, id: &str) -> Result<(), ApiResult> {
+    let Some(cm) = &state.container_manager else {
+        return Err(failure(StatusCode::SERVICE_UNAVAILABLE, "Container manager not available"));
+    };
+    if cm.record(id).await.is_none() {
+        return Err(failure(StatusCode::NOT_FOUND, "Container not found"));
+    }
+    Ok(())
+}
+
+/// Backup `backup_id`, which must belong to app `id` (the app itself may
+/// be gone).
+fn app_backup(state: &ApiState, id: &str, backup_id: &str) -> Result<BackupRecord, ApiResult> {
+    match state.backups.backup(backup_id) {
+        Some(backup) if backup.app_id == id => Ok(backup),
+        _ => Err(failure(StatusCode::NOT_FOUND, "Backup not found")),
+    }
+}
+
+/// Next scheduled backup, in epoch milliseconds.
+fn next_run(policy: &BackupPolicy) -> Option<i64> {
+    let next = policy.next_run(chrono::Local::now().naive_local())?;
+    Some(chrono::Local.from_local_datetime(&next).earliest()?.timestamp_millis())
+}
+
+fn policy_json(policy: &BackupPolicy) -> Value {
+    let shown = BackupPolicy { destination: policy.destination.redacted(), ..policy.clone() };
+    json!(shown)
+}
+
+/// Backups of the app, newest first, with its policy and last run.
+async fn list_backups(State(state): State<ApiState>, Path(id): Path<String>) -> ApiResult {
+    let policy = state.backups.policy(&id);
+    let backups: Vec<BackupRecord> = state.backups.backups(&id).iter().map(BackupRecord::redacted).collect();
+    (
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "backups": backups,
+            "policy": policy_json(&policy),
+            "nextRun": next_run(&policy),
+            "running": state.backups.is_running(&id),
+            "lastRun": state.backups.last_run(&id),
+        })),
+    )
+}
+
+/// Start a backup now; the outcome shows as the last run.
+async fn create_backup(State(state): State<ApiState>, Path(id): Path<String>) -> ApiResult {
+    if let Err(e) = check_app(&state, &id).await {
+        return e;
+    }
+    if !backups::start_backup(&state, &id, BackupTrigger::Manual) {
+        return failure(StatusCode::CONFLICT, "A backup of this application is already running");
+    }
+    (StatusCode::ACCEPTED, Json(json!({"success": true})))
+}
+
+async fn get_policy(State(state): State<ApiState>, Path(id): Path<String>) -> ApiResult {
+    let policy = state.backups.policy(&id);
+    (StatusCode::OK, Json(json!({"success": true, "policy": policy_json(&policy), "nextRun": next_run(&policy)})))
+}
+
+async fn update_policy(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(policy): Json<BackupPolicy>,
+) -> ApiResult {
+    if let Err(e) = check_app(&state, &id).await {
+        return e;
+    }
+    match state.backups.set_policy(&id, policy) {
+        Ok(policy) => (StatusCode::OK, Json(json!({"success": true, "policy": policy_json(&policy), "nextRun": next_run(&policy)}))),
+        Err(e) => failure(StatusCode::BAD_REQUEST, e),
+    }
+}
+
+async fn delete_backup(State(state): State<ApiState>, Path((id, backup_id)): Path<(String, String)>) -> ApiResult {
+    if let Err(e) = app_backup(&state, &id, &backup_id) {
+        return e;
+    }
+    match state.backups.delete(&backup_id).await {
+        Ok(_) => (StatusCode::OK, Json(json!({"success": true}))),
+        Err(e) => failure(StatusCode::BAD_GATEWAY, e),
+    }
+}
+
+/// Restore as a new container on the local host, deployed in the background.
+async fn restore_backup(
+    State(state): State<ApiState>,
+    Path((id, backup_id)): Path<(String, String)>,
+    Json(req): Json<RestoreRequest>,
+) -> ApiResult {
+    let backup = match app_backup(&state, &id, &backup_id) {
+        Ok(backup) => backup,
+        Err(e) => return e,
+    };
+    match backups::restore_backup(&state, backup, req).await {
+        Ok((container, token)) => (
+            StatusCode::ACCEPTED,
+            Json(json!({"success": true, "container": container, "token": token})),
+        ),
+        Err(e) => failure(StatusCode::BAD_REQUEST, e),
+    }
+}
@@ -0,0 +1,139 @@
+//! `/api/network/capture`: packet captures, admin only.
+
+use std::time::Duration;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use serde_json::json;
+use tokio::io::AsyncReadExt;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::info;
+
+use crate::capture::{CaptureRequest, CaptureStatus};
+use crate::routes::auth::admin_user;
+use crate::state::ApiState;
+
+/// How often a followed pcap is read again for new packets.
+const FOLLOW_POLL: Duration = Duration::from_millis(250);
+
+fn not_found() -> Response {
+    (StatusCode::NOT_FOUND, Json(json!({"success": false, "error": "Capture not found"}))).into_response()
+}
+
+fn failure(e: String) -> Response {
+    Json(json!({"success": false, "error": e})).into_response()
+}
+
+pub(crate) async fn list_captures(State(state): State<ApiState>, jar: CookieJar) -> Response {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e.into_response();
+    }
+    Json(json!({"success": true, "captures": state.captures.list()})).into_response()
+}
+
+pub(crate) async fn start_capture(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Json(request): Json<CaptureRequest>,
+) -> Response {
+    let user = match admin_user(&state, &jar) {
+        Ok(user) => user,
+        Err(e) => return e.into_response(),
+    };
+    match state.captures.start(request, &user.username) {
+        Ok(capture) => Json(json!({"success": true, "capture": capture})).into_response(),
+        Err(e) => failure(e),
+    }
+}
+
+pub(crate) async fn get_capture(State(state): State<ApiState>, jar: CookieJar, Path(id): Path<String>) -> Response {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e.into_response();
+    }
+    match state.captures.get(&id) {
+        Some(capture) => Json(json!({"success": true, "capture": capture})).into_response(),
+        None => not_found(),
+    }
+}
+
+pub(crate) async fn stop_capture(State(state): State<ApiState>, jar: CookieJar, Path(id): Path<String>) -> Response {
+    let user = match admin_user(&state, &jar) {
+        Ok(user) => user,
+        Err(e) => return e.into_response(),
+    };
+    match state.captures.stop(&id, &user.username) {
+        Ok(()) => Json(json!({"success": true})).into_response(),
+        Err(e) => failure(e),
+    }
+}
+
+pub(crate) async fn delete_capture(State(state): State<ApiState>, jar: CookieJar, Path(id): Path<String>) -> Response {
+    let user = match admin_user(&state, &jar) {
+        Ok(user) => user,
+        Err(e) => return e.into_response(),
+    };
+    match state.captures.delete(&id, &user.username) {
+        Ok(()) => Json(json!({"success": true})).into_response(),
+        Err(e) => failure(e),
+    }
+}
+
+/// The pcap; while the capture runs, the response follows it as packets
+/// are written (`curl -N ... | wireshark -k -i -`) and ends with it.
+pub(crate) async fn download_capture(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path(id): Path<String>,
+) -> Response {
+    let user = match admin_user(&state, &jar) {
+        Ok(user) => user,
+        Err(e) => return e.into_response(),
+    };
+    if state.captures.get(&id).is_none() {
+        return not_found();
+    }
+    let mut file = match tokio::fs::File::open(state.captures.path(&id)).await {
+        Ok(file) => file,
+        Err(e) => return failure(format!("Failed to open the capture: {}", e)),
+    };
+    info!(user = %user.username, "Packet capture {} downloaded", id);
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Bytes, std::io::Error>>(8);
+    let captures = state.captures.clone();
+    let disposition = format!("attachment; filename=\"capture-{}.pcap\"", id);
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            // Read the state before the file: what it wrote until then is seen
+            let running = captures.get(&id).is_some_and(|c| c.status == CaptureStatus::Running);
+            match file.read(&mut buf).await {
+                Ok(0) if running => tokio::time::sleep(FOLLOW_POLL).await,
+                Ok(0) => break,
+                Ok(n) => {
+                    if tx.send(Ok(Bytes::copy_from_slice(&buf[..n]))).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(e)).await;
+                    break;
+                }
+            }
+        }
+    });
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/vnd.tcpdump.pcap".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(ReceiverStream::new(rx)),
+    )
+        .into_response()
+}
@@ -1,9 +1,13 @@
 use axum::{
     extract::State,
-    http::StatusCode,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
     routing::{get, post, put},
     Json, Router,
 };
+use base64::Engine;
+use hr_tunnel::crypto::TunnelCerts;
+use hr_tunnel::provision::{ProvisionOptions, LOCAL_RELAY_BINARY};
 use serde::{Deserialize, Serialize};
 
 use crate::state::ApiState;
@@ -17,6 +21,8 @@ struct RelayStatusResponse {
     vps_ipv4: Option<String>,
     ssh_user: Option<String>,
     ssh_port: Option<u16>,
+    /// Relay in use, when connected
+    relay_host: Option<String>,
     latency_ms: Option<u64>,
     active_streams: Option<u32>,
 }
@@ -87,6 +93,7 @@ async fn get_status(State(state): State<ApiState>) -> Json<RelayStatusResponse>
         vps_ipv4,
         ssh_user: disk_config.as_ref().map(|c| c.ssh_user.clone()),
         ssh_port: disk_config.as_ref().map(|c| c.ssh_port),
+        relay_host: relay_info.as_ref().and_then(|info| info.relay_host.clone()),
         latency_ms: relay_info.as_ref().and_then(|info| info.latency_ms),
         active_streams: relay_info.as_ref().and_then(|info| info.active_streams),
     })
@@ -98,6 +105,12 @@ async fn enable_relay(
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     let relay_config = load_relay_config(&state.env.data_dir)
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Relay not configured: {}", e)))?;
+    if relay_config.vps_ipv4.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "VPS IPv4 unknown: provision the relay with its IP address".to_string(),
+        ));
+    }
 
     // Switch DNS to relay mode
     if let (Some(token), Some(zone_id)) = (&state.env.cf_api_token, &state.env.cf_zone_id) {
@@ -205,7 +218,7 @@ async fn bootstrap_vps(
         });
 
     // 1. Check that hr-cloud-relay binary exists
-    let binary_path = "/opt/homeroute/crates/target/release/hr-cloud-relay";
+    let binary_path = LOCAL_RELAY_BINARY;
     if tokio::fs::metadata(binary_path).await.is_err() {
         return Err((
             StatusCode::BAD_REQUEST,
@@ -220,19 +233,9 @@ async fn bootstrap_vps(
 
     // 3. Save client certs locally
     let relay_dir = state.env.data_dir.join("cloud-relay");
-    tokio::fs::create_dir_all(&relay_dir)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    tokio::fs::write(relay_dir.join("ca.pem"), &certs.ca_cert_pem)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    tokio::fs::write(relay_dir.join("client.pem"), &certs.client_cert_pem)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    tokio::fs::write(relay_dir.join("client-key.pem"), &certs.client_key_pem)
+    save_client_certs(&relay_dir, &certs)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
 
     let ssh_port = req.ssh_port.unwrap_or(22);
     let ssh_user = &req.ssh_user;
@@ -271,29 +274,8 @@ async fn bootstrap_vps(
     }
 
     // 5. SSH: install binary, write config, create systemd unit, start
-    let config_toml = r#"quic_port = 4443
-tcp_listen_port = 443
-http_redirect_port = 80
-
-[tls]
-ca_cert = "/etc/hr-cloud-relay/ca.pem"
-server_cert = "/etc/hr-cloud-relay/server.pem"
-server_key = "/etc/hr-cloud-relay/server-key.pem"
-"#;
-
-    let service_unit = r#"[Unit]
-Description=HomeRoute Cloud Relay
-After=network.target
-
-[Service]
-ExecStart=/usr/local/bin/hr-cloud-relay
-Restart=always
-RestartSec=5
-Environment=RUST_LOG=info
-
-[Install]
-WantedBy=multi-user.target
-"#;
+    let config_toml = hr_tunnel::provision::relay_config_toml(&ProvisionOptions::new(host));
+    let service_unit = hr_tunnel::provision::SYSTEMD_UNIT;
 
     let setup_script = format!(
         r#"
@@ -388,7 +370,7 @@ async fn push_update(
     use sha2::{Digest, Sha256};
 
     // 1. Read the binary from disk
-    let binary_path = "/opt/homeroute/crates/target/release/hr-cloud-relay";
+    let binary_path = LOCAL_RELAY_BINARY;
     let binary_data = tokio::fs::read(binary_path)
         .await
         .map_err(|e| {
@@ -441,8 +423,166 @@ async fn push_update(
     }
 }
 
+/// Provisioning request: the relay to install and the file to produce.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ProvisionRequest {
+    #[serde(flatten)]
+    options: ProvisionOptions,
+    /// `script` (bash, run as root) or `cloud-init` (user data).
+    #[serde(default = "default_provision_format")]
+    format: String,
+}
+
+fn default_provision_format() -> String {
+    "script".to_string()
+}
+
+/// POST /api/network/relay/provision — New tunnel certificates and the
+/// install script (or cloud-init user data) of a relay VPS, to run there
+/// without giving HomeRoute SSH access. The client side is saved like by
+/// the bootstrap; with a binary URL the local build is pushed through the
+/// tunnel once it connects.
+pub(crate) async fn provision_relay(
+    State(state): State<ApiState>,
+    Json(req): Json<ProvisionRequest>,
+) -> Response {
+    let options = req.options;
+    if let Err(e) = options.validate() {
+        return Json(serde_json::json!({"success": false, "error": e})).into_response();
+    }
+    let cloud_init = match req.format.as_str() {
+        "script" => false,
+        "cloud-init" => true,
+        other => {
+            return Json(serde_json::json!({"success": false, "error": format!("Unknown format: {}", other)}))
+                .into_response();
+        }
+    };
+    // User data is limited to a few KB by most providers
+    if cloud_init && options.binary_url.is_none() {
+        return Json(serde_json::json!({
+            "success": false,
+            "error": "cloud-init needs a binary URL, the relay binary does not fit in user data",
+        }))
+        .into_response();
+    }
+
+    let binary = match &options.binary_url {
+        Some(_) => None,
+        None => match tokio::fs::read(LOCAL_RELAY_BINARY).await {
+            Ok(data) => Some(base64::engine::general_purpose::STANDARD.encode(data)),
+            Err(_) => {
+                return Json(serde_json::json!({
+                    "success": false,
+                    "error": "hr-cloud-relay binary not found, set a binary URL or run 'cargo build --release -p hr-cloud-relay'",
+                }))
+                .into_response();
+            }
+        },
+    };
+
+    let host = options.host.trim().to_string();
+    let certs = match hr_tunnel::crypto::generate_tunnel_certs(&host) {
+        Ok(certs) => certs,
+        Err(e) => {
+            return Json(serde_json::json!({"success": false, "error": format!("Cert generation failed: {}", e)}))
+                .into_response();
+        }
+    };
+    let script = hr_tunnel::provision::install_script(&options, &certs, binary.as_deref());
+
+    let relay_dir = state.env.data_dir.join("cloud-relay");
+    if let Err(e) = save_client_certs(&relay_dir, &certs).await {
+        return Json(serde_json::json!({"success": false, "error": e})).into_response();
+    }
+    if options.binary_url.is_some() {
+        // The downloaded binary may be older than this HomeRoute
+        let _ = tokio::fs::write(relay_dir.join(hr_tunnel::provision::PUSH_BINARY_MARKER), b"").await;
+    }
+
+    // The public IPv4 is only known once the relay runs, unless the host is one
+    let vps_ipv4 = match tokio::net::lookup_host((host.as_str(), options.quic_port)).await {
+        Ok(addrs) => addrs
+            .filter(|a| a.is_ipv4())
+            .map(|a| a.ip().to_string())
+            .next()
+            .unwrap_or_default(),
+        Err(_) => String::new(),
+    };
+    let relay_config = serde_json::json!({
+        "vps_host": host,
+        "vps_ipv4": vps_ipv4,
+        "ssh_user": "root",
+        "ssh_port": 22,
+        "quic_port": options.quic_port,
+    });
+    if let Err(e) = tokio::fs::write(
+        relay_dir.join("config.json"),
+        serde_json::to_string_pretty(&relay_config).unwrap(),
+    )
+    .await
+    {
+        return Json(serde_json::json!({"success": false, "error": e.to_string()})).into_response();
+    }
+    if let Err(e) = update_env_var("CLOUD_RELAY_HOST", &host) {
+        return Json(serde_json::json!({"success": false, "error": e})).into_response();
+    }
+    if let Err(e) = update_env_var("CLOUD_RELAY_QUIC_PORT", &options.quic_port.to_string()) {
+        return Json(serde_json::json!({"success": false, "error": e})).into_response();
+    }
+
+    let (content, filename, content_type) = if cloud_init {
+        (
+            hr_tunnel::provision::cloud_init(&script),
+            "hr-cloud-relay-user-data.yaml",
+            "text/cloud-config",
+        )
+    } else {
+        (script, "hr-cloud-relay-install.sh", "text/x-shellscript")
+    };
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        content,
+    )
+        .into_response()
+}
+
 // ── Helper functions ──────────────────────────────────────────────────
 
+/// Save the HomeRoute side of new tunnel certificates in `relay_dir`:
+/// the client pair, the CA and its key (to renew the client certificate).
+async fn save_client_certs(relay_dir: &std::path::Path, certs: &TunnelCerts) -> Result<(), String> {
+    tokio::fs::create_dir_all(relay_dir)
+        .await
+        .map_err(|e| e.to_string())?;
+    for (name, content) in [
+        ("ca.pem", &certs.ca_cert_pem),
+        ("client.pem", &certs.client_cert_pem),
+        ("client-key.pem", &certs.client_key_pem),
+        ("ca-key.pem", &certs.ca_key_pem),
+    ] {
+        tokio::fs::write(relay_dir.join(name), content)
+            .await
+            .map_err(|e| format!("Cannot write {}: {}", name, e))?;
+    }
+    let _ = tokio::fs::set_permissions(
+        relay_dir.join("ca-key.pem"),
+        <std::fs::Permissions as std::os::unix::fs::PermissionsExt>::from_mode(0o600),
+    )
+    .await;
+    // A new CA: pins of the previous relay certificate no longer apply
+    let _ = tokio::fs::remove_file(relay_dir.join("pins.json")).await;
+    Ok(())
+}
+
+
 fn load_relay_config(data_dir: &std::path::Path) -> Result<RelayConfig, String> {
     let path = data_dir.join("cloud-relay/config.json");
     let content = std::fs::read_to_string(&path)
@@ -0,0 +1,262 @@
+//! Config transactions, under `/api/config/{document}`: staged changes,
+//! diff preview, apply with rollback and version history.
+
+use axum::{
+    extract::{Path, State},
+    routing::{get, post, put},
+    Json, Router,
+};
+use axum_extra::extract::CookieJar;
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::config_tx::{self, Applied, Change, ConfigDocument, Version};
+use crate::error::{Ack, ApiError, ApiResult, ErrorCode};
+use crate::routes::auth::admin_user;
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/", get(list_documents))
+        .route("/{doc}", get(get_document))
+        .route("/{doc}/staged", put(stage).get(get_staged).delete(discard))
+        .route("/{doc}/apply", post(apply))
+        .route("/{doc}/history", get(history))
+        .route("/{doc}/history/{version}", get(get_version))
+        .route("/{doc}/history/{version}/revert", post(revert))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DocumentSummary {
+    pub name: &'static str,
+    /// Latest recorded version.
+    pub version: Option<u64>,
+    /// A change is staged.
+    pub staged: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DocumentsResponse {
+    pub success: bool,
+    pub documents: Vec<DocumentSummary>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DocumentResponse {
+    pub success: bool,
+    pub config: Value,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StageResponse {
+    pub success: bool,
+    pub warnings: Vec<String>,
+    /// Diff against the live config.
+    pub changes: Vec<Change>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StagedResponse {
+    pub success: bool,
+    /// `null` when nothing is staged.
+    pub staged: Option<Value>,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AppliedResponse {
+    pub success: bool,
+    #[serde(flatten)]
+    pub applied: Applied,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct HistoryResponse {
+    pub success: bool,
+    /// Latest first.
+    pub versions: Vec<Version>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VersionResponse {
+    pub success: bool,
+    pub config: Value,
+    /// What reverting to this version would change.
+    pub changes: Vec<Change>,
+}
+
+fn document(name: &str) -> Result<ConfigDocument, ApiError> {
+    ConfigDocument::from_name(name).ok_or_else(|| ApiError::not_found(format!("Unknown config document: {}", name)))
+}
+
+/// Config documents with their latest version.
+#[utoipa::path(get, path = "/api/config", responses((status = 200, body = DocumentsResponse)))]
+async fn list_documents(State(state): State<ApiState>) -> ApiResult<DocumentsResponse> {
+    let mut documents = Vec::new();
+    for doc in ConfigDocument::ALL {
+        let versions = config_tx::history(&state, doc).await.unwrap_or_default();
+        let staged = config_tx::staged(&state, doc).await.ok().flatten().is_some();
+        documents.push(DocumentSummary {
+            name: doc.name(),
+            version: versions.last().map(|v| v.version),
+            staged,
+        });
+    }
+    Ok(Json(DocumentsResponse { success: true, documents }))
+}
+
+/// Live content of a document.
+#[utoipa::path(
+    get,
+    path = "/api/config/{doc}",
+    params(("doc" = String, Path, description = "`dns-dhcp` or `reverseproxy`")),
+    responses((status = 200, body = DocumentResponse), (status = 404, body = ApiError))
+)]
+async fn get_document(State(state): State<ApiState>, Path(doc): Path<String>) -> ApiResult<DocumentResponse> {
+    let doc = document(&doc)?;
+    let config = config_tx::current(&state, doc).await.map_err(ApiError::internal)?;
+    Ok(Json(DocumentResponse { success: true, config }))
+}
+
+/// Validate and stage a full document; the response carries the diff
+/// against the live config. Invalid documents are not staged.
+#[utoipa::path(
+    put,
+    path = "/api/config/{doc}/staged",
+    params(("doc" = String, Path, description = "`dns-dhcp` or `reverseproxy`")),
+    request_body = Object,
+    responses((status = 200, body = StageResponse), (status = 422, body = ApiError))
+)]
+async fn stage(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path(doc): Path<String>,
+    Json(config): Json<Value>,
+) -> ApiResult<StageResponse> {
+    admin_user(&state, &jar)?;
+    let doc = document(&doc)?;
+    let (validation, changes) = config_tx::stage(&state, doc, config).await.map_err(ApiError::internal)?;
+    if !validation.is_valid() {
+        return Err(ApiError::invalid(validation.errors));
+    }
+    Ok(Json(StageResponse {
+        success: true,
+        warnings: validation.warnings,
+        changes,
+    }))
+}
+
+/// Staged document and its diff against the live config.
+#[utoipa::path(
+    get,
+    path = "/api/config/{doc}/staged",
+    params(("doc" = String, Path, description = "`dns-dhcp` or `reverseproxy`")),
+    responses((status = 200, body = StagedResponse), (status = 404, body = ApiError))
+)]
+async fn get_staged(State(state): State<ApiState>, Path(doc): Path<String>) -> ApiResult<StagedResponse> {
+    let doc = document(&doc)?;
+    let Some(staged) = config_tx::staged(&state, doc).await.map_err(ApiError::internal)? else {
+        return Ok(Json(StagedResponse { success: true, staged: None, changes: Vec::new() }));
+    };
+    let current = config_tx::current(&state, doc).await.map_err(ApiError::internal)?;
+    let changes = config_tx::diff(&current, &staged);
+    Ok(Json(StagedResponse { success: true, staged: Some(staged), changes }))
+}
+
+/// Drop the staged document.
+#[utoipa::path(
+    delete,
+    path = "/api/config/{doc}/staged",
+    params(("doc" = String, Path, description = "`dns-dhcp` or `reverseproxy`")),
+    responses((status = 200, body = Ack), (status = 404, body = ApiError))
+)]
+async fn discard(State(state): State<ApiState>, jar: CookieJar, Path(doc): Path<String>) -> ApiResult<Ack> {
+    admin_user(&state, &jar)?;
+    let doc = document(&doc)?;
+    config_tx::discard(&state, doc).await.map_err(ApiError::internal)?;
+    Ok(Json(Ack::default()))
+}
+
+async fn commit(state: &ApiState, doc: ConfigDocument, config: Value, source: &str, user: String) -> ApiResult<AppliedResponse> {
+    match config_tx::commit(state, doc, config, source, Some(user)).await {
+        Ok(applied) => Ok(Json(AppliedResponse { success: true, applied })),
+        Err(e) => Err(ApiError::new(ErrorCode::Invalid, e)),
+    }
+}
+
+/// Apply the staged document.
+#[utoipa::path(
+    post,
+    path = "/api/config/{doc}/apply",
+    params(("doc" = String, Path, description = "`dns-dhcp` or `reverseproxy`")),
+    responses(
+        (status = 200, body = AppliedResponse),
+        (status = 409, description = "Nothing staged", body = ApiError),
+        (status = 422, description = "Invalid document or reload failed (previous config restored)", body = ApiError)
+    )
+)]
+async fn apply(State(state): State<ApiState>, jar: CookieJar, Path(doc): Path<String>) -> ApiResult<AppliedResponse> {
+    let user = admin_user(&state, &jar)?;
+    let doc = document(&doc)?;
+    match config_tx::staged(&state, doc).await.map_err(ApiError::internal)? {
+        Some(staged) => commit(&state, doc, staged, "apply", user.username).await,
+        None => Err(ApiError::conflict("Nothing staged")),
+    }
+}
+
+/// Applied versions of a document.
+#[utoipa::path(
+    get,
+    path = "/api/config/{doc}/history",
+    params(("doc" = String, Path, description = "`dns-dhcp` or `reverseproxy`")),
+    responses((status = 200, body = HistoryResponse), (status = 404, body = ApiError))
+)]
+async fn history(State(state): State<ApiState>, Path(doc): Path<String>) -> ApiResult<HistoryResponse> {
+    let doc = document(&doc)?;
+    let mut versions = config_tx::history(&state, doc).await.map_err(ApiError::internal)?;
+    versions.reverse();
+    Ok(Json(HistoryResponse { success: true, versions }))
+}
+
+/// A version and what reverting to it would change.
+#[utoipa::path(
+    get,
+    path = "/api/config/{doc}/history/{version}",
+    params(
+        ("doc" = String, Path, description = "`dns-dhcp` or `reverseproxy`"),
+        ("version" = u64, Path)
+    ),
+    responses((status = 200, body = VersionResponse), (status = 404, body = ApiError))
+)]
+async fn get_version(
+    State(state): State<ApiState>,
+    Path((doc, version)): Path<(String, u64)>,
+) -> ApiResult<VersionResponse> {
+    let doc = document(&doc)?;
+    let config = config_tx::version(&state, doc, version).await.map_err(ApiError::not_found)?;
+    let current = config_tx::current(&state, doc).await.map_err(ApiError::internal)?;
+    let changes = config_tx::diff(&current, &config);
+    Ok(Json(VersionResponse { success: true, config, changes }))
+}
+
+/// Apply a previous version again.
+#[utoipa::path(
+    post,
+    path = "/api/config/{doc}/history/{version}/revert",
+    params(
+        ("doc" = String, Path, description = "`dns-dhcp` or `reverseproxy`"),
+        ("version" = u64, Path)
+    ),
+    responses((status = 200, body = AppliedResponse), (status = 404, body = ApiError), (status = 422, body = ApiError))
+)]
+async fn revert(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path((doc, version)): Path<(String, u64)>,
+) -> ApiResult<AppliedResponse> {
+    let user = admin_user(&state, &jar)?;
+    let doc = document(&doc)?;
+    let config = config_tx::version(&state, doc, version).await.map_err(ApiError::not_found)?;
+    commit(&state, doc, config, "revert", user.username).await
+}
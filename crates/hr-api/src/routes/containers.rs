@@ -3,14 +3,12 @@
 use std::sync::atomic::Ordering;
 
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::routing::{get, post, put};
 use axum::{Json, Router};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::process::Command;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use hr_common::events::MigrationPhase;
 
@@ -19,6 +17,7 @@ use crate::container_manager::{
     UpdateContainerRequest,
 };
 use crate::state::ApiState;
+use crate::terminal::{self, TerminalQuery};
 
 pub fn router() -> Router<ApiState> {
     Router::new()
@@ -94,8 +93,24 @@ async fn delete_container(
             .into_response();
     };
 
+    // Resolved before the app record goes away with the container
+    let slug = match &state.registry {
+        Some(registry) => registry.get_application(&id).await.map(|app| app.slug),
+        None => None,
+    };
+
     match mgr.remove_container(&id).await {
-        Ok(true) => Json(serde_json::json!({"success": true})).into_response(),
+        Ok(true) => {
+            if let Err(e) = state.cron.remove_app(&id) {
+                warn!("Failed to remove the cron jobs of {id}: {e}");
+            }
+            if let Some(config) = slug.and_then(|slug| state.pubsub.remove_app(&slug))
+                && let Err(e) = crate::routes::pubsub::save_config(&state, config).await
+            {
+                warn!("Failed to remove the pub/sub grants of {id}: {e}");
+            }
+            Json(serde_json::json!({"success": true})).into_response()
+        }
         Ok(false) => (
             StatusCode::NOT_FOUND,
             Json(serde_json::json!({"success": false, "error": "Not found"})),
@@ -236,9 +251,17 @@ async fn update_config(
 
 // ── Migration handlers ───────────────────────────────────────────
 
-async fn migrate_container(
+#[derive(serde::Deserialize)]
+pub(super) struct MigrateQuery {
+    /// Only run the pre-flight checks and return their report.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+pub(super) async fn migrate_container(
     State(state): State<ApiState>,
     Path(id): Path<String>,
+    Query(query): Query<MigrateQuery>,
     Json(req): Json<MigrateContainerRequest>,
 ) -> impl IntoResponse {
     let Some(ref mgr) = state.container_manager else {
@@ -249,8 +272,19 @@ async fn migrate_container(
             .into_response();
     };
 
+    if query.dry_run {
+        return match mgr.preflight_migration(&id, &req.target_host_id, req.compression_level).await {
+            Ok(report) => Json(report).into_response(),
+            Err(e) => (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": e})),
+            )
+                .into_response(),
+        };
+    }
+
     match mgr
-        .migrate_container(&id, &req.target_host_id, &state.migrations)
+        .migrate_container(&id, &req.target_host_id, req.compression_level, &state.migrations)
         .await
     {
         Ok(transfer_id) => {
@@ -408,12 +442,13 @@ async fn rename_status(
 async fn terminal_ws(
     State(state): State<ApiState>,
     Path(id): Path<String>,
+    Query(query): Query<TerminalQuery>,
     ws: WebSocketUpgrade,
 ) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_terminal_ws(state, id, socket))
+    ws.on_upgrade(move |socket| handle_terminal_ws(state, id, query, socket))
 }
 
-async fn handle_terminal_ws(state: ApiState, container_id: String, mut socket: WebSocket) {
+async fn handle_terminal_ws(state: ApiState, container_id: String, query: TerminalQuery, mut socket: WebSocket) {
     let Some(ref mgr) = state.container_manager else {
         let _ = socket.send(Message::Close(None)).await;
         return;
@@ -426,13 +461,7 @@ async fn handle_terminal_ws(state: ApiState, container_id: String, mut socket: W
         .find(|c| c.get("id").and_then(|v| v.as_str()) == Some(&container_id));
 
     let Some(record) = container_record else {
-        let _ = socket
-            .send(Message::Text(
-                serde_json::json!({"error": "Container not found"})
-                    .to_string()
-                    .into(),
-            ))
-            .await;
+        terminal::send_error(&mut socket, "Container not found").await;
         let _ = socket.send(Message::Close(None)).await;
         return;
     };
@@ -449,13 +478,7 @@ async fn handle_terminal_ws(state: ApiState, container_id: String, mut socket: W
         .to_string();
 
     if container.is_empty() {
-        let _ = socket
-            .send(Message::Text(
-                serde_json::json!({"error": "Container not found"})
-                    .to_string()
-                    .into(),
-            ))
-            .await;
+        terminal::send_error(&mut socket, "Container not found").await;
         let _ = socket.send(Message::Close(None)).await;
         return;
     }
@@ -463,256 +486,13 @@ async fn handle_terminal_ws(state: ApiState, container_id: String, mut socket: W
     info!(container, host_id, "Container V2 terminal WebSocket opened");
 
     if host_id == "local" {
-        handle_terminal_local(&container, &mut socket).await;
+        terminal::run_local(&mut socket, Some(&container), query.size()).await;
+    } else if let Some(registry) = &state.registry {
+        terminal::run_remote(registry, &mut socket, &host_id, Some(&container), query.size()).await;
     } else {
-        handle_terminal_remote(&state, &host_id, &container, &mut socket).await;
+        terminal::send_error(&mut socket, "Registry not available").await;
     }
 
     let _ = socket.send(Message::Close(None)).await;
     info!(container, "Container V2 terminal WebSocket closed");
 }
-
-/// Handle terminal for a local container (machinectl + nsenter).
-async fn handle_terminal_local(container: &str, socket: &mut WebSocket) {
-    // Get the container's leader PID via machinectl show
-    let leader_pid = match Command::new("machinectl")
-        .args(["show", container, "--property=Leader", "--value"])
-        .output()
-        .await
-    {
-        Ok(output) if output.status.success() => {
-            String::from_utf8_lossy(&output.stdout).trim().to_string()
-        }
-        Ok(output) => {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            error!(container, "Failed to get leader PID: {stderr}");
-            let _ = socket
-                .send(Message::Text(
-                    serde_json::json!({"error": format!("Failed to get container PID: {stderr}")})
-                        .to_string()
-                        .into(),
-                ))
-                .await;
-            return;
-        }
-        Err(e) => {
-            error!(container, "Failed to run machinectl show: {e}");
-            let _ = socket
-                .send(Message::Text(
-                    serde_json::json!({"error": format!("Failed to get container PID: {e}")})
-                        .to_string()
-                        .into(),
-                ))
-                .await;
-            return;
-        }
-    };
-
-    // Use script to allocate a PTY for nsenter+bash (interactive shell with echo/prompts)
-    let nsenter_cmd = format!(
-        "nsenter -t {} -m -u -i -n -p -- /bin/bash -l",
-        leader_pid
-    );
-    let mut child = match Command::new("script")
-        .args(["-qfec", &nsenter_cmd, "/dev/null"])
-        .env("TERM", "xterm-256color")
-        .env("HOME", "/root")
-        .stdin(std::process::Stdio::piped())
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-    {
-        Ok(c) => c,
-        Err(e) => {
-            error!(container, "Failed to spawn nsenter shell: {e}");
-            let _ = socket
-                .send(Message::Text(
-                    serde_json::json!({"error": format!("Failed to start shell: {e}")})
-                        .to_string()
-                        .into(),
-                ))
-                .await;
-            return;
-        }
-    };
-
-    let mut stdin = child.stdin.take().unwrap();
-    let mut stdout = child.stdout.take().unwrap();
-    let mut stderr = child.stderr.take().unwrap();
-
-    let mut stdout_buf = vec![0u8; 4096];
-    let mut stderr_buf = vec![0u8; 4096];
-
-    loop {
-        tokio::select! {
-            n = stdout.read(&mut stdout_buf) => {
-                match n {
-                    Ok(0) | Err(_) => break,
-                    Ok(n) => {
-                        if socket.send(Message::Binary(stdout_buf[..n].to_vec().into())).await.is_err() {
-                            break;
-                        }
-                    }
-                }
-            }
-            n = stderr.read(&mut stderr_buf) => {
-                match n {
-                    Ok(0) | Err(_) => break,
-                    Ok(n) => {
-                        if socket.send(Message::Binary(stderr_buf[..n].to_vec().into())).await.is_err() {
-                            break;
-                        }
-                    }
-                }
-            }
-            ws_msg = socket.recv() => {
-                match ws_msg {
-                    Some(Ok(Message::Text(text))) => {
-                        if stdin.write_all(text.as_bytes()).await.is_err() {
-                            break;
-                        }
-                    }
-                    Some(Ok(Message::Binary(data))) => {
-                        if stdin.write_all(&data).await.is_err() {
-                            break;
-                        }
-                    }
-                    Some(Ok(Message::Close(_))) | None => break,
-                    _ => {}
-                }
-            }
-            status = child.wait() => {
-                match status {
-                    Ok(s) => info!(container, status = ?s, "Shell process exited"),
-                    Err(e) => error!(container, "Shell process error: {e}"),
-                }
-                break;
-            }
-        }
-    }
-
-    let _ = child.kill().await;
-}
-
-/// Handle terminal for a remote container (proxied through host-agent WebSocket).
-async fn handle_terminal_remote(
-    state: &ApiState,
-    host_id: &str,
-    container: &str,
-    socket: &mut WebSocket,
-) {
-    let registry = match &state.registry {
-        Some(r) => r,
-        None => {
-            let _ = socket
-                .send(Message::Text(
-                    serde_json::json!({"error": "Registry not available"})
-                        .to_string()
-                        .into(),
-                ))
-                .await;
-            return;
-        }
-    };
-
-    if !registry.is_host_connected(host_id).await {
-        let _ = socket
-            .send(Message::Text(
-                serde_json::json!({"error": "Host is not connected"})
-                    .to_string()
-                    .into(),
-            ))
-            .await;
-        return;
-    }
-
-    let session_id = uuid::Uuid::new_v4().to_string();
-    let (tx, mut rx) = tokio::sync::mpsc::channel::<Vec<u8>>(256);
-
-    // Register session so data from host-agent is routed to us
-    registry.register_terminal_session(&session_id, tx).await;
-
-    // Send TerminalOpen to host-agent
-    if let Err(e) = registry
-        .send_host_command(
-            host_id,
-            hr_registry::protocol::HostRegistryMessage::TerminalOpen {
-                session_id: session_id.clone(),
-                container_name: container.to_string(),
-            },
-        )
-        .await
-    {
-        error!(container, host_id, "Failed to send TerminalOpen: {e}");
-        let _ = socket
-            .send(Message::Text(
-                serde_json::json!({"error": format!("Failed to open remote terminal: {e}")})
-                    .to_string()
-                    .into(),
-            ))
-            .await;
-        registry.unregister_terminal_session(&session_id).await;
-        return;
-    }
-
-    info!(container, host_id, session_id, "Remote terminal session started");
-
-    // Bidirectional relay loop
-    loop {
-        tokio::select! {
-            // Data from host-agent (terminal output) → WebSocket client
-            data = rx.recv() => {
-                match data {
-                    Some(d) if d.is_empty() => {
-                        // Empty data signals session closed by host-agent
-                        break;
-                    }
-                    Some(d) => {
-                        if socket.send(Message::Binary(d.into())).await.is_err() {
-                            break;
-                        }
-                    }
-                    None => break, // Channel closed
-                }
-            }
-            // Data from WebSocket client (user input) → host-agent
-            ws_msg = socket.recv() => {
-                match ws_msg {
-                    Some(Ok(Message::Text(text))) => {
-                        let _ = registry.send_host_command(
-                            host_id,
-                            hr_registry::protocol::HostRegistryMessage::TerminalData {
-                                session_id: session_id.clone(),
-                                data: text.as_bytes().to_vec(),
-                            },
-                        ).await;
-                    }
-                    Some(Ok(Message::Binary(data))) => {
-                        let _ = registry.send_host_command(
-                            host_id,
-                            hr_registry::protocol::HostRegistryMessage::TerminalData {
-                                session_id: session_id.clone(),
-                                data: data.to_vec(),
-                            },
-                        ).await;
-                    }
-                    Some(Ok(Message::Close(_))) | None => break,
-                    _ => {}
-                }
-            }
-        }
-    }
-
-    // Cleanup: send TerminalClose and unregister
-    let _ = registry
-        .send_host_command(
-            host_id,
-            hr_registry::protocol::HostRegistryMessage::TerminalClose {
-                session_id: session_id.clone(),
-            },
-        )
-        .await;
-    registry.unregister_terminal_session(&session_id).await;
-
-    info!(container, host_id, session_id, "Remote terminal session ended");
-}
@@ -0,0 +1,181 @@
+//! Scheduled jobs of an application, under `/api/applications/{id}/cron`.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post, put},
+    Json, Router,
+};
+use chrono::TimeZone;
+use hr_cron::{CronJob, JobSpec, SaveJob, Schedule, Trigger, Triggered};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/{id}/cron", get(list_jobs).post(create_job))
+        .route("/{id}/cron/{job_id}", put(update_job).delete(delete_job))
+        .route("/{id}/cron/{job_id}/run", post(run_job))
+        .route("/{id}/cron/{job_id}/runs", get(list_runs))
+}
+
+type ApiResult = (StatusCode, Json<Value>);
+
+fn failure(status: StatusCode, error: impl std::fmt::Display) -> ApiResult {
+    (status, Json(json!({"success": false, "error": error.to_string()})))
+}
+
+/// Run a cron store call on the blocking pool.
+async fn blocking<T: Send + 'static>(
+    state: &ApiState,
+    f: impl FnOnce(&hr_cron::CronStore) -> anyhow::Result<T> + Send + 'static,
+) -> Result<T, ApiResult> {
+    let store = state.cron.store();
+    match tokio::task::spawn_blocking(move || f(&store)).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(failure(StatusCode::INTERNAL_SERVER_ERROR, e)),
+        Err(e) => Err(failure(StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+/// The app must be known to the registry (when it runs).
+async fn check_app(state: &ApiState, id: &str) -> Result<(), ApiResult> {
+    if let Some(registry) = &state.registry
+        && registry.get_application(id).await.is_none()
+    {
+        return Err(failure(StatusCode::NOT_FOUND, "Application not found"));
+    }
+    Ok(())
+}
+
+/// Job `job_id`, which must belong to app `id`.
+async fn app_job(state: &ApiState, id: &str, job_id: String) -> Result<CronJob, ApiResult> {
+    match blocking(state, move |store| store.job(&job_id)).await? {
+        Some(job) if job.app_id == id => Ok(job),
+        _ => Err(failure(StatusCode::NOT_FOUND, "Job not found")),
+    }
+}
+
+/// Next firing of `job`, in epoch milliseconds.
+fn next_run(job: &CronJob) -> Option<i64> {
+    let schedule = Schedule::parse(&job.spec.schedule).ok()?;
+    let next = schedule.next_after(chrono::Local::now().naive_local())?;
+    Some(chrono::Local.from_local_datetime(&next).earliest()?.timestamp_millis())
+}
+
+async fn list_jobs(State(state): State<ApiState>, Path(id): Path<String>) -> ApiResult {
+    if let Err(e) = check_app(&state, &id).await {
+        return e;
+    }
+    let listed = blocking(&state, move |store| {
+        store
+            .jobs(Some(&id))?
+            .into_iter()
+            .map(|job| {
+                let last_run = store.last_run(&job.id)?;
+                Ok((job, last_run))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+    })
+    .await;
+    let jobs = match listed {
+        Ok(jobs) => jobs,
+        Err(e) => return e,
+    };
+    let jobs: Vec<Value> = jobs
+        .into_iter()
+        .map(|(job, last_run)| {
+            json!({
+                "job": job,
+                "nextRun": job.spec.enabled.then(|| next_run(&job)).flatten(),
+                "running": state.cron.running(&job.id),
+                "lastRun": last_run,
+            })
+        })
+        .collect();
+    (StatusCode::OK, Json(json!({"success": true, "jobs": jobs})))
+}
+
+async fn create_job(State(state): State<ApiState>, Path(id): Path<String>, Json(spec): Json<JobSpec>) -> ApiResult {
+    if let Err(e) = spec.validate() {
+        return failure(StatusCode::BAD_REQUEST, e);
+    }
+    if let Err(e) = check_app(&state, &id).await {
+        return e;
+    }
+    let now = chrono::Utc::now().timestamp_millis();
+    saved(blocking(&state, move |store| store.create_job(&id, &spec, now)).await)
+}
+
+async fn update_job(
+    State(state): State<ApiState>,
+    Path((id, job_id)): Path<(String, String)>,
+    Json(spec): Json<JobSpec>,
+) -> ApiResult {
+    if let Err(e) = spec.validate() {
+        return failure(StatusCode::BAD_REQUEST, e);
+    }
+    if let Err(e) = app_job(&state, &id, job_id.clone()).await {
+        return e;
+    }
+    let now = chrono::Utc::now().timestamp_millis();
+    saved(blocking(&state, move |store| store.update_job(&job_id, &spec, now)).await)
+}
+
+fn saved(result: Result<SaveJob, ApiResult>) -> ApiResult {
+    match result {
+        Ok(SaveJob::Saved(job)) => (StatusCode::OK, Json(json!({"success": true, "job": job}))),
+        Ok(SaveJob::NotFound) => failure(StatusCode::NOT_FOUND, "Job not found"),
+        Ok(SaveJob::NameTaken) => failure(StatusCode::CONFLICT, "The application already has a job with this name"),
+        Err(e) => e,
+    }
+}
+
+/// Runs in flight are cancelled with the job.
+async fn delete_job(State(state): State<ApiState>, Path((id, job_id)): Path<(String, String)>) -> ApiResult {
+    if let Err(e) = app_job(&state, &id, job_id.clone()).await {
+        return e;
+    }
+    match state.cron.delete_job(&job_id) {
+        Ok(_) => (StatusCode::OK, Json(json!({"success": true}))),
+        Err(e) => failure(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// Start a run now, disabled jobs included; the concurrency policy applies.
+async fn run_job(State(state): State<ApiState>, Path((id, job_id)): Path<(String, String)>) -> ApiResult {
+    let job = match app_job(&state, &id, job_id).await {
+        Ok(job) => job,
+        Err(e) => return e,
+    };
+    match state.cron.trigger(&job, Trigger::Manual) {
+        Ok(Triggered::Started(run_id)) => (StatusCode::OK, Json(json!({"success": true, "runId": run_id}))),
+        Ok(Triggered::Skipped(run_id)) => (
+            StatusCode::CONFLICT,
+            Json(json!({"success": false, "runId": run_id, "error": "The previous run is still going"})),
+        ),
+        Err(e) => failure(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+#[derive(Deserialize)]
+struct RunsQuery {
+    limit: Option<u32>,
+}
+
+/// Latest runs, newest first.
+async fn list_runs(
+    State(state): State<ApiState>,
+    Path((id, job_id)): Path<(String, String)>,
+    Query(query): Query<RunsQuery>,
+) -> ApiResult {
+    if let Err(e) = app_job(&state, &id, job_id.clone()).await {
+        return e;
+    }
+    match blocking(&state, move |store| store.runs(&job_id, query.limit)).await {
+        Ok(runs) => (StatusCode::OK, Json(json!({"success": true, "runs": runs}))),
+        Err(e) => e,
+    }
+}
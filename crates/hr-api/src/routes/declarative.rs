@@ -0,0 +1,100 @@
+//! Declarative apply, under `/api/declarative`: export the live state as a
+//! document, plan a document (diff and checks) and converge to it. The
+//! document is sent as the raw body, YAML or JSON.
+
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use axum_extra::extract::CookieJar;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::config_tx::Change;
+use crate::declarative::{self, Converged, Declaration};
+use crate::error::{ApiError, ApiResult, ErrorCode};
+use crate::routes::auth::admin_user;
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/", get(export))
+        .route("/plan", post(plan))
+        .route("/apply", post(apply))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PlanResponse {
+    pub success: bool,
+    /// Paths are prefixed by the section (`routes[id=app.example.com].targetPort`).
+    pub changes: Vec<Change>,
+    pub warnings: Vec<String>,
+    /// Applications not declared, left untouched.
+    pub unmanaged: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ConvergedResponse {
+    pub success: bool,
+    #[serde(flatten)]
+    pub converged: Converged,
+}
+
+fn document(body: &str) -> Result<Declaration, ApiError> {
+    declarative::parse(body).map_err(ApiError::bad_request)
+}
+
+/// The live state as a YAML document, to start a repository from.
+#[utoipa::path(
+    get,
+    path = "/api/declarative",
+    responses((status = 200, content_type = "application/yaml", body = String), (status = 403, body = ApiError))
+)]
+async fn export(State(state): State<ApiState>, jar: CookieJar) -> Result<Response, ApiError> {
+    admin_user(&state, &jar)?;
+    let declaration = declarative::export(&state).await.map_err(ApiError::internal)?;
+    let yaml = serde_yaml::to_string(&declaration).map_err(ApiError::internal)?;
+    Ok(([(header::CONTENT_TYPE, "application/yaml")], yaml).into_response())
+}
+
+/// What applying the document would change. Nothing is modified.
+#[utoipa::path(
+    post,
+    path = "/api/declarative/plan",
+    request_body(content = String, content_type = "application/yaml"),
+    responses((status = 200, body = PlanResponse), (status = 400, body = ApiError), (status = 422, body = ApiError))
+)]
+async fn plan(State(state): State<ApiState>, jar: CookieJar, body: String) -> ApiResult<PlanResponse> {
+    admin_user(&state, &jar)?;
+    let declaration = document(&body)?;
+    let plan = declarative::plan(&state, &declaration).await.map_err(ApiError::internal)?;
+    if !plan.is_valid() {
+        return Err(ApiError::invalid(plan.errors));
+    }
+    Ok(Json(PlanResponse {
+        success: true,
+        changes: plan.changes,
+        warnings: plan.warnings,
+        unmanaged: plan.unmanaged,
+    }))
+}
+
+/// Converge to the document.
+#[utoipa::path(
+    post,
+    path = "/api/declarative/apply",
+    operation_id = "declarative_apply",
+    request_body(content = String, content_type = "application/yaml"),
+    responses((status = 200, body = ConvergedResponse), (status = 400, body = ApiError), (status = 422, body = ApiError))
+)]
+async fn apply(State(state): State<ApiState>, jar: CookieJar, body: String) -> ApiResult<ConvergedResponse> {
+    let user = admin_user(&state, &jar)?;
+    let declaration = document(&body)?;
+    match declarative::apply(&state, &declaration, Some(user.username)).await {
+        Ok(converged) => Ok(Json(ConvergedResponse { success: true, converged })),
+        Err(e) => Err(ApiError::new(ErrorCode::Invalid, e)),
+    }
+}
@@ -13,6 +13,8 @@ pub fn router() -> Router<ApiState> {
     Router::new()
         .route("/cache-stats", get(cache_stats))
         .route("/status", get(status))
+        .route("/diagnostics", get(diagnostics))
+        .route("/route-records", get(route_records))
 }
 
 async fn cache_stats(State(state): State<ApiState>) -> Json<Value> {
@@ -21,7 +23,8 @@ async fn cache_stats(State(state): State<ApiState>) -> Json<Value> {
     Json(json!({
         "success": true,
         "cache_size": cache_size,
-        "adblock_enabled": dns.adblock_enabled
+        "adblock_enabled": dns.adblock_enabled,
+        "queries": dns.stats.snapshot()
     }))
 }
 
@@ -34,6 +37,24 @@ async fn status(State(state): State<ApiState>) -> Json<Value> {
         "upstream_servers": dns.config.upstream_servers,
         "cache_size": dns.config.cache_size,
         "local_domain": dns.config.local_domain,
-        "adblock_enabled": dns.adblock_enabled
+        "adblock_enabled": dns.adblock_enabled,
+        "upstream": dns.health.snapshot()
+    }))
+}
+
+/// Self-test of the resolver: upstream latency and DNSSEC, block response,
+/// cache.
+async fn diagnostics(State(state): State<ApiState>) -> Json<Value> {
+    let report = hr_dns::diagnostics::run(&state.dns).await;
+    Json(json!({"success": true, "report": report}))
+}
+
+/// Records published for the proxy routes and applications.
+async fn route_records(State(state): State<ApiState>) -> Json<Value> {
+    let dns = state.dns.read().await;
+    Json(json!({
+        "success": true,
+        "config": dns.config.route_records,
+        "records": dns.route_records
     }))
 }
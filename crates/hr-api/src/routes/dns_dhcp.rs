@@ -3,8 +3,11 @@ use axum::{
     routing::{get, post},
     Json, Router,
 };
+use axum_extra::extract::CookieJar;
 use serde_json::{json, Value};
 
+use crate::config_tx::{self, ConfigDocument};
+use crate::routes::auth::session_user;
 use crate::state::ApiState;
 
 pub fn router() -> Router<ApiState> {
@@ -15,59 +18,75 @@ pub fn router() -> Router<ApiState> {
         .route("/leases", get(get_leases))
 }
 
-async fn status() -> Json<Value> {
+async fn status(State(state): State<ApiState>) -> Json<Value> {
     // In the unified binary, the DNS/DHCP service is always running
+    let dns = state.dns.read().await;
     Json(json!({
         "success": true,
         "active": true,
-        "service": "integrated"
+        "service": "integrated",
+        "degraded": dns.health.is_degraded(),
+        "upstream": dns.health.snapshot()
     }))
 }
 
 async fn reload(State(state): State<ApiState>) -> Json<Value> {
-    // Reload DNS/DHCP config from file and apply
-    let config_path = &state.dns_dhcp_config_path;
-    let content = match tokio::fs::read_to_string(config_path).await {
-        Ok(c) => c,
-        Err(e) => {
-            return Json(json!({"success": false, "error": format!("Failed to read config: {}", e)}));
-        }
-    };
+    match apply(&state).await {
+        Ok(()) => Json(json!({"success": true})),
+        Err(e) => Json(json!({"success": false, "error": e})),
+    }
+}
 
-    let combined: Value = match serde_json::from_str(&content) {
-        Ok(v) => v,
-        Err(e) => {
-            return Json(json!({"success": false, "error": format!("Invalid config: {}", e)}));
-        }
-    };
+/// Sections of dns-dhcp-config.json.
+#[derive(serde::Deserialize)]
+pub(crate) struct DnsDhcpSections {
+    #[serde(default)]
+    pub dns: hr_dns::DnsConfig,
+    #[serde(default)]
+    pub dhcp: hr_dhcp::DhcpConfig,
+    /// Only checked here, applied by the IPv6 services at startup.
+    #[serde(default)]
+    pub ipv6: hr_ipv6::Ipv6Config,
+    #[serde(default)]
+    pub adblock: hr_adblock::config::AdblockConfig,
+}
 
-    // Reload DNS config
-    if let Ok(dns_config) = serde_json::from_value::<hr_dns::DnsConfig>(
-        combined.get("dns").cloned().unwrap_or(json!({})),
-    ) {
-        let mut dns = state.dns.write().await;
-        dns.config = dns_config;
-    }
+pub(crate) fn parse_config(config: &Value) -> Result<DnsDhcpSections, String> {
+    serde_json::from_value(config.clone()).map_err(|e| format!("Invalid config: {}", e))
+}
 
-    // Reload DHCP config
-    if let Ok(dhcp_config) = serde_json::from_value::<hr_dhcp::DhcpConfig>(
-        combined.get("dhcp").cloned().unwrap_or(json!({})),
-    ) {
-        let mut dhcp = state.dhcp.write().await;
-        dhcp.config = dhcp_config;
+/// Reload DNS/DHCP config from file and apply it to the running DNS, DHCP
+/// and adblock.
+pub(crate) async fn apply(state: &ApiState) -> Result<(), String> {
+    let content = tokio::fs::read_to_string(&state.dns_dhcp_config_path)
+        .await
+        .map_err(|e| format!("Failed to read config: {}", e))?;
+    let combined: Value =
+        serde_json::from_str(&content).map_err(|e| format!("Invalid config: {}", e))?;
+    let config = parse_config(&combined)?;
+
+    // Adblock profiles first: the only part that can still be refused
+    {
+        let dns = state.dns.read().await;
+        dns.adblock_schedule.set_profiles(&config.adblock.profiles)?;
     }
 
-    // Reload adblock config
-    if let Some(adblock_val) = combined.get("adblock") {
-        if let Ok(adblock_config) =
-            serde_json::from_value::<hr_adblock::config::AdblockConfig>(adblock_val.clone())
-        {
-            let mut engine = state.adblock.write().await;
-            engine.set_whitelist(adblock_config.whitelist);
-        }
+    {
+        let mut dns = state.dns.write().await;
+        dns.upstream = hr_dns::upstream::UpstreamForwarder::new(
+            config.dns.upstream_servers.clone(),
+            config.dns.upstream_timeout_ms,
+        );
+        dns.set_config(config.dns);
+        dns.adblock_enabled = config.adblock.enabled;
+        dns.adblock_block_response = config.adblock.block_response;
+        dns.dns_cache.clear().await;
     }
 
-    Json(json!({"success": true}))
+    state.dhcp.write().await.config = config.dhcp;
+    state.adblock.write().await.set_whitelist(config.adblock.whitelist);
+    crate::route_records::reconcile(state).await;
+    Ok(())
 }
 
 async fn get_config(State(state): State<ApiState>) -> Json<Value> {
@@ -93,31 +112,22 @@ async fn get_config(State(state): State<ApiState>) -> Json<Value> {
 
 async fn update_config(
     State(state): State<ApiState>,
+    jar: CookieJar,
     Json(body): Json<Value>,
 ) -> Json<Value> {
-    let config_path = &state.dns_dhcp_config_path;
-
-    // Write the new config
-    let content = match serde_json::to_string_pretty(&body) {
-        Ok(c) => c,
-        Err(e) => {
-            return Json(json!({"success": false, "error": format!("Serialization error: {}", e)}));
-        }
-    };
-
-    let tmp_path = config_path.with_extension("json.tmp");
-    if let Err(e) = tokio::fs::write(&tmp_path, &content).await {
-        return Json(json!({"success": false, "error": format!("Write failed: {}", e)}));
-    }
-    if let Err(e) = tokio::fs::rename(&tmp_path, config_path).await {
-        return Json(json!({"success": false, "error": format!("Rename failed: {}", e)}));
+    let user = session_user(&state, &jar).ok().map(|u| u.username);
+    match config_tx::commit(&state, ConfigDocument::DnsDhcp, body, "apply", user).await {
+        Ok(applied) => Json(json!({"success": true, "version": applied.version, "warnings": applied.warnings})),
+        Err(e) => Json(json!({"success": false, "error": e})),
     }
-
-    // Apply config by reloading
-    reload(State(state)).await
 }
 
 async fn get_leases(State(state): State<ApiState>) -> Json<Value> {
+    Json(json!({"success": true, "leases": leases(&state).await}))
+}
+
+/// Active DHCPv4 leases enriched with the DHCPv6 addresses of the same MAC.
+pub(crate) async fn leases(state: &ApiState) -> Vec<Value> {
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
@@ -166,7 +176,7 @@ async fn get_leases(State(state): State<ApiState>) -> Json<Value> {
     };
 
     // Build result: DHCPv4 leases enriched with DHCPv6 addresses
-    let result: Vec<serde_json::Value> = dhcpv4_leases
+    dhcpv4_leases
         .iter()
         .map(|(expiry, mac, ip, hostname, client_id)| {
             let ipv6 = dhcpv6_leases.get(&mac.to_lowercase())
@@ -182,7 +192,5 @@ async fn get_leases(State(state): State<ApiState>) -> Json<Value> {
                 "ipv6_addresses": ipv6
             })
         })
-        .collect();
-
-    Json(json!({"success": true, "leases": result}))
+        .collect()
 }
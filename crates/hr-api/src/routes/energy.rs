@@ -1,9 +1,10 @@
 use axum::{
-    extract::Query,
+    extract::{Query, State},
     response::sse::{Event, Sse},
     routing::{get, post},
     Json, Router,
 };
+use hr_energy::{EnergyConfig, SeriesPoint};
 use serde::Deserialize;
 use serde_json::{json, Value};
 use std::convert::Infallible;
@@ -29,6 +30,9 @@ pub fn router() -> Router<ApiState> {
         .route("/benchmark/start", post(start_benchmark))
         .route("/benchmark/stop", post(stop_benchmark))
         .route("/events", get(sse_events))
+        .route("/meters", get(get_meters).put(update_meters))
+        .route("/consumption", get(consumption))
+        .route("/hosts", get(host_consumption))
 }
 
 async fn cpu_info() -> Json<Value> {
@@ -463,6 +467,182 @@ async fn stop_benchmark() -> Json<Value> {
     Json(json!({"success": true}))
 }
 
+// ── Power meters ─────────────────────────────────────────────────────────
+
+/// Meter settings, service state and the latest reading of each meter.
+async fn get_meters(State(state): State<ApiState>) -> Json<Value> {
+    Json(json!({
+        "success": true,
+        "config": state.energy.config(),
+        "status": state.energy.status(),
+        "readings": state.energy.readings(),
+    }))
+}
+
+/// Validate, persist to energy-config.json, then apply; the samples of
+/// removed meters are dropped.
+async fn update_meters(State(state): State<ApiState>, Json(config): Json<EnergyConfig>) -> Json<Value> {
+    if let Err(e) = config.validate() {
+        return Json(json!({"success": false, "error": e}));
+    }
+    let path = state.env.energy_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Json(json!({"success": false, "error": format!("Write failed: {}", e)})),
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    }
+    let removed: Vec<String> = state
+        .energy
+        .config()
+        .meters
+        .into_iter()
+        .map(|m| m.id)
+        .filter(|id| !config.meters.iter().any(|m| &m.id == id))
+        .collect();
+    state.energy.apply(config.clone());
+    let energy = state.energy.clone();
+    let _ = tokio::task::spawn_blocking(move || {
+        for id in removed {
+            if let Err(e) = energy.store().remove_meter(&id) {
+                tracing::warn!("Failed to drop the samples of meter {}: {}", id, e);
+            }
+        }
+    })
+    .await;
+    Json(json!({"success": true, "config": config}))
+}
+
+#[derive(Deserialize)]
+struct ConsumptionQuery {
+    meter: Option<String>,
+    /// All the meters of a host, summed.
+    host: Option<String>,
+    #[serde(default = "default_hours")]
+    hours: i64,
+    /// Bucket size in seconds; picked from the period when absent.
+    bucket: Option<i64>,
+}
+
+fn default_hours() -> i64 {
+    24
+}
+
+/// Sum series bucket by bucket.
+fn merge_series(series: Vec<Vec<SeriesPoint>>) -> Vec<SeriesPoint> {
+    let mut merged: std::collections::BTreeMap<i64, SeriesPoint> = std::collections::BTreeMap::new();
+    for point in series.into_iter().flatten() {
+        merged
+            .entry(point.ts)
+            .and_modify(|p| {
+                p.avg_watts += point.avg_watts;
+                p.max_watts += point.max_watts;
+                p.wh += point.wh;
+            })
+            .or_insert(point);
+    }
+    merged.into_values().collect()
+}
+
+/// Draw and watt-hour series of a meter or a host.
+async fn consumption(State(state): State<ApiState>, Query(query): Query<ConsumptionQuery>) -> Json<Value> {
+    let config = state.energy.config();
+    let meter_ids: Vec<String> = match (&query.meter, &query.host) {
+        (Some(meter), _) => config.meters.iter().filter(|m| &m.id == meter).map(|m| m.id.clone()).collect(),
+        (None, Some(host)) => config
+            .meters
+            .iter()
+            .filter(|m| m.host_id.as_ref() == Some(host))
+            .map(|m| m.id.clone())
+            .collect(),
+        (None, None) => return Json(json!({"success": false, "error": "meter or host required"})),
+    };
+    if meter_ids.is_empty() {
+        return Json(json!({"success": false, "error": "No meter found"}));
+    }
+    let hours = query.hours.clamp(1, 24 * 366);
+    let bucket = query.bucket.unwrap_or(match hours {
+        ..=24 => 900,
+        25..=168 => 3600,
+        _ => 86_400,
+    });
+    if bucket < 60 {
+        return Json(json!({"success": false, "error": "The bucket must be at least 60 s"}));
+    }
+    let until = chrono::Utc::now().timestamp() + 1;
+    let since = until - hours * 3600;
+    let energy = state.energy.clone();
+    let ids = meter_ids.clone();
+    let series = tokio::task::spawn_blocking(move || {
+        ids.iter()
+            .map(|id| energy.store().series(id, since, until, bucket))
+            .collect::<anyhow::Result<Vec<_>>>()
+    })
+    .await;
+    match series {
+        Ok(Ok(series)) => {
+            let points = merge_series(series);
+            let total_wh: f64 = points.iter().map(|p| p.wh).sum();
+            Json(json!({
+                "success": true,
+                "meters": meter_ids,
+                "bucket": bucket,
+                "totalWh": total_wh,
+                "points": points,
+            }))
+        }
+        Ok(Err(e)) => Json(json!({"success": false, "error": e.to_string()})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
+
+/// Hosts of hosts.json with their power state, current draw and today's
+/// consumption from the meters attached to them.
+async fn host_consumption(State(state): State<ApiState>) -> Json<Value> {
+    let config = state.energy.config();
+    let data = crate::routes::hosts::load_hosts().await;
+    let hosts = data.get("hosts").and_then(|h| h.as_array()).cloned().unwrap_or_default();
+
+    let midnight = chrono::Local::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|t| t.and_local_timezone(chrono::Local).earliest())
+        .map(|t| t.timestamp())
+        .unwrap_or(0);
+    let energy = state.energy.clone();
+    let totals = tokio::task::spawn_blocking(move || energy.store().totals(midnight, i64::MAX))
+        .await
+        .ok()
+        .and_then(|r| r.ok())
+        .unwrap_or_default();
+
+    let mut result = Vec::new();
+    for host in &hosts {
+        let Some(id) = host.get("id").and_then(|i| i.as_str()) else { continue };
+        let meters: Vec<&str> = config
+            .meters
+            .iter()
+            .filter(|m| m.host_id.as_deref() == Some(id))
+            .map(|m| m.id.as_str())
+            .collect();
+        let power_state = match &state.registry {
+            Some(registry) => Some(registry.get_host_power_state(id).await),
+            None => None,
+        };
+        let today_wh: f64 = meters.iter().filter_map(|m| totals.get(*m)).sum();
+        result.push(json!({
+            "id": id,
+            "name": host.get("name").and_then(|n| n.as_str()).unwrap_or(id),
+            "powerState": power_state,
+            "meters": meters,
+            "watts": state.energy.host_watts(id),
+            "todayWh": today_wh,
+            "busy": state.energy.host_busy(id),
+        }));
+    }
+    Json(json!({"success": true, "hosts": result}))
+}
+
 /// SSE endpoint for real-time energy events.
 /// Sends periodic keepalive comments to maintain the connection.
 async fn sse_events() -> Sse<impl tokio_stream::Stream<Item = Result<Event, Infallible>>> {
@@ -0,0 +1,82 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    routing::{delete, get},
+    Json, Router,
+};
+use hr_firewall::{BanSource, FirewallConfig};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/config", get(get_config).put(update_config))
+        .route("/bans", get(list_bans).post(add_ban))
+        .route("/bans/{ip}", delete(remove_ban))
+}
+
+async fn get_config(State(state): State<ApiState>) -> Json<Value> {
+    Json(json!({"success": true, "config": state.bans.config()}))
+}
+
+/// Validate, persist to firewall-config.json, then apply (thresholds and
+/// whitelist take effect immediately, nftables is resynced).
+async fn update_config(
+    State(state): State<ApiState>,
+    Json(config): Json<FirewallConfig>,
+) -> Json<Value> {
+    if let Err(e) = config.validate() {
+        return Json(json!({"success": false, "error": e}));
+    }
+    let path = state.env.firewall_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Json(json!({"success": false, "error": format!("Write failed: {}", e)})),
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    }
+    state.bans.apply(config.clone());
+    Json(json!({"success": true, "config": config}))
+}
+
+async fn list_bans(State(state): State<ApiState>) -> Json<Value> {
+    Json(json!({"success": true, "bans": state.bans.list()}))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AddBanRequest {
+    ip: String,
+    /// Permanent ban when absent.
+    duration_secs: Option<u64>,
+    reason: Option<String>,
+}
+
+async fn add_ban(State(state): State<ApiState>, Json(body): Json<AddBanRequest>) -> Json<Value> {
+    let Ok(ip) = body.ip.trim().parse::<IpAddr>() else {
+        return Json(json!({"success": false, "error": "Adresse IP invalide"}));
+    };
+    if ip.is_loopback() || ip.is_unspecified() {
+        return Json(json!({"success": false, "error": "Adresse non bannissable"}));
+    }
+    let reason = body
+        .reason
+        .filter(|r| !r.trim().is_empty())
+        .unwrap_or_else(|| "Bannissement manuel".to_string());
+    let ban = state.bans.ban(ip, body.duration_secs.map(Duration::from_secs), reason, BanSource::Manual);
+    Json(json!({"success": true, "ban": ban}))
+}
+
+async fn remove_ban(State(state): State<ApiState>, Path(ip): Path<String>) -> Json<Value> {
+    let Ok(ip) = ip.parse::<IpAddr>() else {
+        return Json(json!({"success": false, "error": "Adresse IP invalide"}));
+    };
+    if !state.bans.unban(ip) {
+        return Json(json!({"success": false, "error": "Adresse non bannie"}));
+    }
+    Json(json!({"success": true}))
+}
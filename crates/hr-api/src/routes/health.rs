@@ -1,5 +1,6 @@
 use axum::{routing::get, Json, Router};
-use serde_json::{json, Value};
+use serde::Serialize;
+use utoipa::ToSchema;
 
 use crate::state::ApiState;
 
@@ -7,9 +8,19 @@ pub fn router() -> Router<ApiState> {
     Router::new().route("/health", get(health))
 }
 
-async fn health() -> Json<Value> {
-    Json(json!({
-        "status": "ok",
-        "timestamp": chrono::Utc::now().to_rfc3339()
-    }))
+#[derive(Serialize, ToSchema)]
+pub struct HealthResponse {
+    /// Always `ok`.
+    pub status: &'static str,
+    /// RFC 3339.
+    pub timestamp: String,
+}
+
+/// Liveness probe.
+#[utoipa::path(get, path = "/api/health", responses((status = 200, body = HealthResponse)))]
+async fn health() -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "ok",
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    })
 }
@@ -0,0 +1,77 @@
+use axum::{
+    extract::Query,
+    routing::get,
+    Json, Router,
+};
+use hr_common::tsdb;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/series", get(list_series))
+        .route("/query", get(query))
+}
+
+#[derive(Deserialize)]
+struct SeriesQuery {
+    #[serde(default)]
+    prefix: String,
+}
+
+async fn list_series(Query(q): Query<SeriesQuery>) -> Json<Value> {
+    let Some(db) = tsdb::global() else {
+        return Json(json!({"success": false, "error": "Historique indisponible"}));
+    };
+    match tokio::task::spawn_blocking(move || db.series(&q.prefix)).await {
+        Ok(Ok(series)) => Json(json!({"success": true, "series": series})),
+        Ok(Err(e)) => Json(json!({"success": false, "error": e.to_string()})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
+
+#[derive(Deserialize)]
+struct RangeQuery {
+    series: String,
+    /// Unix seconds, default one hour before `to`.
+    from: Option<i64>,
+    /// Unix seconds, default now.
+    to: Option<i64>,
+    /// Upper bound on returned buckets (picks the tier).
+    points: Option<usize>,
+    /// Per-second rate of a counter instead of raw buckets.
+    #[serde(default)]
+    rate: bool,
+}
+
+/// Range query for the dashboard charts.
+async fn query(Query(q): Query<RangeQuery>) -> Json<Value> {
+    let Some(db) = tsdb::global() else {
+        return Json(json!({"success": false, "error": "Historique indisponible"}));
+    };
+    let to = q.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    let from = q.from.unwrap_or(to - 3600);
+    if from > to {
+        return Json(json!({"success": false, "error": "Intervalle invalide"}));
+    }
+    let points = q.points.unwrap_or(300).clamp(1, 5000);
+    let series = q.series.clone();
+    match tokio::task::spawn_blocking(move || db.query(&series, from, to, points)).await {
+        Ok(Ok(range)) if q.rate => Json(json!({
+            "success": true,
+            "series": q.series,
+            "step": range.step,
+            "rate": tsdb::rate(&range),
+        })),
+        Ok(Ok(range)) => Json(json!({
+            "success": true,
+            "series": q.series,
+            "step": range.step,
+            "points": range.points,
+        })),
+        Ok(Err(e)) => Json(json!({"success": false, "error": e.to_string()})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
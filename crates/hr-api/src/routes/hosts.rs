@@ -1,5 +1,5 @@
 use axum::{
-    extract::{Path, State, WebSocketUpgrade, ws::{Message, WebSocket}},
+    extract::{Path, Query, State, WebSocketUpgrade, ws::{Message, WebSocket}},
     response::IntoResponse,
     routing::{get, post, put},
     Json, Router,
@@ -8,14 +8,50 @@ use serde::Deserialize;
 use serde_json::{json, Value};
 use tokio::sync::mpsc;
 
-use crate::state::ApiState;
+use hr_common::events::MigrationPhase;
+use hr_registry::protocol::{
+    is_service_unit, service_allowed, ContainerRuntime, DiskHealth, HostMetrics, PackageStatus, RuntimeAction,
+    RuntimeContainerInfo, SystemdAction,
+};
+use hr_registry::transfer::{ChunkOrder, Counted, StreamManifest};
+use hr_registry::binaries::{local_arch, normalize_arch, Artifact, BinaryStore, ARCHES};
+use hr_registry::update;
+
+use crate::enrollment::{self, JoinTarget};
+use crate::host_schedules::{host_schedules, HostSchedule};
+use crate::state::{ApiState, InboundTransfer, TransferPhase};
 
-const HOSTS_FILE: &str = "/data/hosts.json";
-const SSH_KEY_PATH: &str = "/data/ssh/id_rsa";
+pub(crate) const HOSTS_FILE: &str = "/data/hosts.json";
+pub(crate) const SSH_KEY_PATH: &str = "/data/ssh/id_rsa";
 const SSH_PUB_KEY_PATH: &str = "/data/ssh/id_rsa.pub";
-const HOST_AGENT_BINARY: &str = "/opt/homeroute/data/agent-binaries/hr-host-agent";
 const HOMEROUTE_LAN_IP: &str = "10.0.0.254";
+/// Host fields holding credentials: never returned nor set through `update_host`.
+const CREDENTIAL_FIELDS: &[&str] = &["token_hash", "previous_token_hash", "previous_token_expires", "token_revoked"];
 const API_PORT: u16 = 4000;
+/// systemd services that can be listed and controlled on a host without a
+/// `service_allowlist` of its own. The host agent is left out: restarting
+/// it drops the connection carrying the request.
+const DEFAULT_SERVICE_ALLOWLIST: &[&str] = &[
+    "ssh.service",
+    "sshd.service",
+    "docker.service",
+    "containerd.service",
+    "podman.service",
+    "smbd.service",
+    "nmbd.service",
+    "nfs-server.service",
+    "nginx.service",
+    "apache2.service",
+    "postgresql*.service",
+    "mariadb.service",
+    "mysql.service",
+    "redis*.service",
+    "cron.service",
+    "smartd.service",
+    "tailscaled.service",
+    "wg-quick@*.service",
+    "zfs-zed.service",
+];
 
 pub fn router() -> Router<ApiState> {
     Router::new()
@@ -25,6 +61,9 @@ pub fn router() -> Router<ApiState> {
         // Agent routes (must be before /{id} to avoid path conflicts)
         .route("/agents/update", post(update_host_agents))
         .route("/agents/binary", get(serve_host_agent_binary))
+        .route("/join-codes", get(list_join_codes).post(create_join_code))
+        .route("/join-codes/{code}", axum::routing::delete(revoke_join_code))
+        .route("/join-codes/{code}/install.sh", get(join_install_script))
         // Local host routes (must be before /{id} to avoid path conflicts)
         .route("/local/interfaces", get(get_local_interfaces_handler))
         .route("/local/config", put(update_local_config))
@@ -40,20 +79,38 @@ pub fn router() -> Router<ApiState> {
         .route("/{id}/wol-mac", post(set_wol_mac))
         .route("/{id}/auto-off", post(set_auto_off))
         .route("/{id}/metrics", get(get_host_metrics))
+        .route("/{id}/disks", get(get_host_disks))
+        .route("/{id}/services", get(list_host_services))
+        .route("/{id}/services/{unit}", get(host_service_status))
+        .route("/{id}/services/{unit}/{action}", post(host_service_action))
+        .route("/{id}/schedules", get(get_schedules).put(update_schedules))
+        .route("/{id}/drain", get(drain_status).post(drain_host).delete(undrain_host))
+        .route("/{id}/rotate-token", post(rotate_token))
+        .route("/{id}/revoke-token", post(revoke_token))
         .route("/bulk/wake", post(bulk_wake))
         .route("/bulk/shutdown", post(bulk_shutdown))
         // Container management on remote hosts
+        .route("/{id}/containers", get(list_containers))
+        .route(
+            "/{id}/containers/docker/{name}/{action}",
+            post(|path, state| runtime_container_action(ContainerRuntime::Docker, path, state)),
+        )
+        .route(
+            "/{id}/containers/podman/{name}/{action}",
+            post(|path, state| runtime_container_action(ContainerRuntime::Podman, path, state)),
+        )
         .route("/{id}/containers/{name}/start", post(start_container))
         .route("/{id}/containers/{name}/stop", post(stop_container))
         .route("/{id}/containers/{name}/delete", post(delete_container))
         .route("/{id}/exec", post(exec_on_host))
+        .route("/{id}/terminal", get(host_terminal_ws))
         // Host-agent WebSocket
         .route("/agent/ws", get(host_agent_ws))
 }
 
 // ── Data access ──────────────────────────────────────────────────────────
 
-async fn load_hosts() -> Value {
+pub(crate) async fn load_hosts() -> Value {
     match tokio::fs::read_to_string(HOSTS_FILE).await {
         Ok(content) => serde_json::from_str(&content).unwrap_or(json!({"hosts": []})),
         Err(_) => json!({"hosts": []}),
@@ -72,6 +129,48 @@ async fn save_hosts(data: &Value) -> Result<(), String> {
     Ok(())
 }
 
+/// Whether a host is in maintenance (draining): no container is placed on it.
+pub(crate) async fn host_draining(id: &str) -> bool {
+    let data = load_hosts().await;
+    find_host(&data, id).and_then(|h| h.get("draining")).and_then(|d| d.as_bool()).unwrap_or(false)
+}
+
+/// Set or clear the draining flag of a host. Clearing it returns the
+/// containers the drain stopped.
+pub(crate) async fn set_host_draining(id: &str, draining: bool) -> Result<Vec<String>, String> {
+    let mut data = load_hosts().await;
+    let host = find_host_mut(&mut data, id).ok_or("Hote non trouve")?;
+    let stopped: Vec<String> = host
+        .get("drain_stopped")
+        .and_then(|s| serde_json::from_value(s.clone()).ok())
+        .unwrap_or_default();
+    host["draining"] = json!(draining);
+    host["drain_stopped"] = json!([]);
+    host["updatedAt"] = json!(chrono::Utc::now().to_rfc3339());
+    save_hosts(&data).await?;
+    Ok(if draining { Vec::new() } else { stopped })
+}
+
+/// Remember a container stopped by the drain of a host, to start it again
+/// once the host is back.
+pub(crate) async fn add_drain_stopped(id: &str, app_id: &str) -> Result<(), String> {
+    let mut data = load_hosts().await;
+    let host = find_host_mut(&mut data, id).ok_or("Hote non trouve")?;
+    match host.get_mut("drain_stopped").and_then(|s| s.as_array_mut()) {
+        Some(stopped) => stopped.push(json!(app_id)),
+        None => host["drain_stopped"] = json!([app_id]),
+    }
+    save_hosts(&data).await
+}
+
+/// Save the pending package updates a host reported.
+pub(crate) async fn set_host_package_updates(id: &str, status: &PackageStatus) -> Result<(), String> {
+    let mut data = load_hosts().await;
+    let host = find_host_mut(&mut data, id).ok_or("Hote non trouve")?;
+    host["package_updates"] = json!(status);
+    save_hosts(&data).await
+}
+
 /// Migrate old servers.json + wol-schedules.json into hosts.json on first load.
 pub async fn ensure_hosts_file() {
     if tokio::fs::metadata(HOSTS_FILE).await.is_ok() {
@@ -140,6 +239,11 @@ pub async fn ensure_hosts_file() {
 // ── Host CRUD ────────────────────────────────────────────────────────────
 
 async fn list_hosts(State(state): State<ApiState>) -> Json<Value> {
+    Json(json!({"success": true, "hosts": hosts_with_status(&state).await}))
+}
+
+/// Hosts with their live status, the local one first.
+pub(crate) async fn hosts_with_status(state: &ApiState) -> Vec<Value> {
     let data = load_hosts().await;
     let mut hosts = data.get("hosts").cloned().unwrap_or(json!([]));
 
@@ -158,11 +262,17 @@ async fn list_hosts(State(state): State<ApiState>) -> Json<Value> {
                     host["power_state"] = json!(power_state);
                     // Include latest metrics from live connection
                     if let Some(conn) = conns.get(id.as_str()) {
+                        host["agent_protocol"] = json!({
+                            "version": conn.protocol.version,
+                            "capabilities": conn.protocol.capabilities,
+                            "outdated": conn.protocol.outdated(),
+                        });
                         if let Some(ref m) = conn.metrics {
                             host["metrics"] = json!({
                                 "cpuPercent": m.cpu_percent,
                                 "memoryUsedBytes": m.memory_used_bytes,
                                 "memoryTotalBytes": m.memory_total_bytes,
+                                "cpuTempC": m.cpu_temp_c,
                             });
                         }
                     }
@@ -197,10 +307,9 @@ async fn list_hosts(State(state): State<ApiState>) -> Json<Value> {
 
     let mut result = vec![local_host];
     if let Some(arr) = hosts.as_array() {
-        result.extend(arr.iter().cloned());
+        result.extend(arr.iter().cloned().map(redact_host));
     }
-
-    Json(json!({"success": true, "hosts": result}))
+    result
 }
 
 async fn list_groups() -> Json<Value> {
@@ -257,6 +366,17 @@ async fn get_local_interfaces() -> Result<Vec<Value>, String> {
 }
 
 async fn get_local_metrics() -> Option<Value> {
+    let m = local_host_metrics().await?;
+    Some(json!({
+        "cpuPercent": (m.cpu_percent * 10.0).round() / 10.0,
+        "memoryUsedBytes": m.memory_used_bytes,
+        "memoryTotalBytes": m.memory_total_bytes,
+    }))
+}
+
+/// CPU, memory and load of the HomeRoute host, as host agents report
+/// theirs (the disk is left at zero).
+pub(crate) async fn local_host_metrics() -> Option<HostMetrics> {
     // CPU: read /proc/stat twice with a short interval
     let read_cpu = || -> Option<(u64, u64)> {
         let content = std::fs::read_to_string("/proc/stat").ok()?;
@@ -273,6 +393,8 @@ async fn get_local_metrics() -> Option<Value> {
     let diff_idle = idle2.saturating_sub(idle1) as f64;
     let diff_total = total2.saturating_sub(total1) as f64;
     let cpu_percent = if diff_total > 0.0 { (1.0 - diff_idle / diff_total) * 100.0 } else { 0.0 };
+    let load_avg = std::fs::read_to_string("/proc/loadavg").unwrap_or_default();
+    let mut load = load_avg.split_whitespace().map(|v| v.parse::<f32>().unwrap_or(0.0));
 
     // Memory: read /proc/meminfo
     let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
@@ -286,11 +408,15 @@ async fn get_local_metrics() -> Option<Value> {
     let used = (total_kb - avail_kb) * 1024;
     let total = total_kb * 1024;
 
-    Some(json!({
-        "cpuPercent": (cpu_percent * 10.0).round() / 10.0,
-        "memoryUsedBytes": used,
-        "memoryTotalBytes": total,
-    }))
+    Some(HostMetrics {
+        cpu_percent: cpu_percent as f32,
+        memory_used_bytes: used,
+        memory_total_bytes: total,
+        disk_used_bytes: 0,
+        disk_total_bytes: 0,
+        load_avg: [load.next().unwrap_or(0.0), load.next().unwrap_or(0.0), load.next().unwrap_or(0.0)],
+        ..Default::default()
+    })
 }
 
 async fn get_local_interfaces_handler() -> Json<Value> {
@@ -335,7 +461,7 @@ async fn get_host(Path(id): Path<String>) -> Json<Value> {
     let data = load_hosts().await;
     if let Some(hosts) = data.get("hosts").and_then(|s| s.as_array()) {
         if let Some(host) = hosts.iter().find(|h| h.get("id").and_then(|i| i.as_str()) == Some(&id)) {
-            return Json(json!({"success": true, "host": host}));
+            return Json(json!({"success": true, "host": redact_host(host.clone())}));
         }
     }
     Json(json!({"success": false, "error": "Hote non trouve"}))
@@ -403,8 +529,15 @@ async fn add_host(Json(body): Json<AddHostRequest>) -> Json<Value> {
         })
     });
 
+    // Per-host credentials: the agent gets the token, hosts.json its hash
+    let token = hr_registry::generate_token();
+    let token_hash = match hr_registry::hash_token(&token) {
+        Ok(h) => h,
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    };
+
     // Deploy hr-host-agent on the remote host
-    if let Err(e) = deploy_host_agent(&body.host, body.port, &body.username, body.password.as_deref(), &body.name, detected_lan_interface.as_deref()).await {
+    if let Err(e) = deploy_host_agent(&body.host, body.port, &body.username, body.password.as_deref(), &body.name, detected_lan_interface.as_deref(), &token).await {
         return Json(json!({"success": false, "error": format!("Agent deploy failed: {}", e)}));
     }
     tracing::info!("hr-host-agent deployed on {}", body.host);
@@ -425,6 +558,7 @@ async fn add_host(Json(body): Json<AddHostRequest>) -> Json<Value> {
         "latency": 0,
         "lastSeen": null,
         "lxc": null,
+        "token_hash": token_hash,
         "createdAt": chrono::Utc::now().to_rfc3339()
     });
 
@@ -439,7 +573,7 @@ async fn add_host(Json(body): Json<AddHostRequest>) -> Json<Value> {
         return Json(json!({"success": false, "error": e}));
     }
 
-    Json(json!({"success": true, "host": host}))
+    Json(json!({"success": true, "host": redact_host(host)}))
 }
 
 async fn update_host(Path(id): Path<String>, Json(updates): Json<Value>) -> Json<Value> {
@@ -448,7 +582,7 @@ async fn update_host(Path(id): Path<String>, Json(updates): Json<Value>) -> Json
         if let Some(host) = hosts.iter_mut().find(|h| h.get("id").and_then(|i| i.as_str()) == Some(&id)) {
             if let Some(obj) = updates.as_object() {
                 for (k, v) in obj {
-                    if k != "id" && k != "schedules" {
+                    if k != "id" && k != "schedules" && !CREDENTIAL_FIELDS.contains(&k.as_str()) {
                         host[k] = v.clone();
                     }
                 }
@@ -519,9 +653,14 @@ async fn get_host_info(Path(id): Path<String>) -> Json<Value> {
 // ── Power actions ────────────────────────────────────────────────────────
 
 async fn wake(Path(id): Path<String>, State(state): State<ApiState>) -> Json<Value> {
+    wake_host(&state, &id).await
+}
+
+/// Wake a host (registry state machine, or direct WOL without registry).
+pub(crate) async fn wake_host(state: &ApiState, id: &str) -> Json<Value> {
     // Use registry state machine if available
     if let Some(registry) = &state.registry {
-        match registry.request_wake_host(&id).await {
+        match registry.request_wake_host(id).await {
             Ok(result) => {
                 let action = match result {
                     hr_common::events::WakeResult::WolSent => "wol_sent",
@@ -536,7 +675,7 @@ async fn wake(Path(id): Path<String>, State(state): State<ApiState>) -> Json<Val
 
     // Fallback: direct WOL if no registry
     let data = load_hosts().await;
-    let host = match find_host(&data, &id) {
+    let host = match find_host(&data, id) {
         Some(h) => h,
         None => return Json(json!({"success": false, "error": "Hote non trouve"})),
     };
@@ -552,14 +691,19 @@ async fn wake(Path(id): Path<String>, State(state): State<ApiState>) -> Json<Val
 }
 
 async fn shutdown_host(Path(id): Path<String>, State(state): State<ApiState>) -> Json<Value> {
+    power_off_host(&state, &id).await
+}
+
+/// Shut a host down via its agent, falling back to SSH.
+pub(crate) async fn power_off_host(state: &ApiState, id: &str) -> Json<Value> {
     // Check power state conflicts
     if let Some(registry) = &state.registry {
-        if let Err(e) = registry.request_power_action(&id, hr_common::events::PowerAction::Shutdown).await {
+        if let Err(e) = registry.request_power_action(id, hr_common::events::PowerAction::Shutdown).await {
             return Json(json!({"success": false, "error": e}));
         }
         // Try agent first
         if registry.send_host_command(
-            &id,
+            id,
             hr_registry::protocol::HostRegistryMessage::PowerOff,
         ).await.is_ok() {
             return Json(json!({"success": true, "action": "poweroff", "via": "agent"}));
@@ -567,7 +711,7 @@ async fn shutdown_host(Path(id): Path<String>, State(state): State<ApiState>) ->
     }
     // SSH fallback
     let data = load_hosts().await;
-    let host = match find_host(&data, &id) {
+    let host = match find_host(&data, id) {
         Some(h) => h,
         None => return Json(json!({"success": false, "error": "Hote non trouve"})),
     };
@@ -651,20 +795,25 @@ async fn bulk_shutdown(Json(body): Json<BulkRequest>) -> Json<Value> {
 }
 
 async fn sleep_host(Path(id): Path<String>, State(state): State<ApiState>) -> Json<Value> {
+    suspend_host(&state, &id).await
+}
+
+/// Suspend a host via its agent, falling back to SSH.
+pub(crate) async fn suspend_host(state: &ApiState, id: &str) -> Json<Value> {
     // Check power state conflicts
     if let Some(registry) = &state.registry {
-        if let Err(e) = registry.request_power_action(&id, hr_common::events::PowerAction::Suspend).await {
+        if let Err(e) = registry.request_power_action(id, hr_common::events::PowerAction::Suspend).await {
             return Json(json!({"success": false, "error": e}));
         }
         if registry.send_host_command(
-            &id,
+            id,
             hr_registry::protocol::HostRegistryMessage::SuspendHost,
         ).await.is_ok() {
             return Json(json!({"success": true, "action": "sleep", "via": "agent"}));
         }
     }
     let data = load_hosts().await;
-    let host = match find_host(&data, &id) {
+    let host = match find_host(&data, id) {
         Some(h) => h,
         None => return Json(json!({"success": false, "error": "Hote non trouve"})),
     };
@@ -751,6 +900,192 @@ async fn set_auto_off(
     Json(json!({"success": true}))
 }
 
+// ── Power schedules ──────────────────────────────────────────────────────
+
+/// Schedules of a host with their next and last run.
+async fn get_schedules(Path(id): Path<String>, State(state): State<ApiState>) -> Json<Value> {
+    let data = load_hosts().await;
+    let Some(host) = find_host(&data, &id) else {
+        return Json(json!({"success": false, "error": "Hote non trouve"}));
+    };
+    let now = chrono::Local::now().naive_local();
+    let schedules: Vec<Value> = host_schedules(host)
+        .into_iter()
+        .map(|schedule| {
+            let next = schedule.next_run(now);
+            let mut entry = serde_json::to_value(&schedule).unwrap_or_default();
+            entry["error"] = json!(schedule.validate().err());
+            entry["nextRun"] = json!(next.map(|(t, _)| t.format("%Y-%m-%dT%H:%M:00").to_string()));
+            entry["nextAction"] = json!(next.map(|(_, a)| a.as_str()));
+            entry["lastRun"] = json!(state.host_scheduler.last_run(&schedule.id));
+            entry
+        })
+        .collect();
+    Json(json!({"success": true, "schedules": schedules}))
+}
+
+/// Replace the schedules of a host; new ones get an id.
+async fn update_schedules(
+    Path(id): Path<String>,
+    State(state): State<ApiState>,
+    Json(mut schedules): Json<Vec<HostSchedule>>,
+) -> Json<Value> {
+    for schedule in &mut schedules {
+        if let Err(e) = schedule.validate() {
+            return Json(json!({"success": false, "error": e}));
+        }
+        if schedule.id.is_empty() {
+            schedule.id = uuid::Uuid::new_v4().to_string();
+        }
+        schedule.description = schedule.description.trim().to_string();
+    }
+    let mut data = load_hosts().await;
+    let Some(host) = find_host_mut(&mut data, &id) else {
+        return Json(json!({"success": false, "error": "Hote non trouve"}));
+    };
+    host["schedules"] = json!(schedules);
+    host["updatedAt"] = json!(chrono::Utc::now().to_rfc3339());
+    if let Err(e) = save_hosts(&data).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+    let ids: Vec<String> = data
+        .get("hosts")
+        .and_then(|h| h.as_array())
+        .into_iter()
+        .flatten()
+        .flat_map(|h| host_schedules(h).into_iter().map(|s| s.id))
+        .collect();
+    state.host_scheduler.retain(&ids);
+    Json(json!({"success": true, "schedules": schedules}))
+}
+
+// ── Maintenance (drain) ──────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+struct DrainRequest {
+    /// Host receiving the containers; they are stopped when absent.
+    #[serde(default)]
+    target_host_id: Option<String>,
+}
+
+async fn drain_status(Path(id): Path<String>, State(state): State<ApiState>) -> Json<Value> {
+    Json(json!({
+        "success": true,
+        "draining": host_draining(&id).await,
+        "drain": crate::drain::status(&state, &id).await,
+    }))
+}
+
+async fn drain_host(
+    Path(id): Path<String>,
+    State(state): State<ApiState>,
+    Json(body): Json<DrainRequest>,
+) -> Json<Value> {
+    match crate::drain::start(&state, &id, body.target_host_id).await {
+        Ok(drain) => Json(json!({"success": true, "drain": drain})),
+        Err(e) => Json(json!({"success": false, "error": e})),
+    }
+}
+
+async fn undrain_host(Path(id): Path<String>, State(state): State<ApiState>) -> Json<Value> {
+    match crate::drain::undrain(&state, &id).await {
+        Ok(errors) => Json(json!({"success": true, "errors": errors})),
+        Err(e) => Json(json!({"success": false, "error": e})),
+    }
+}
+
+// ── Credentials ──────────────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+struct RotateTokenRequest {
+    /// Minutes the previous token stays valid.
+    #[serde(default = "default_token_grace")]
+    grace_minutes: u32,
+}
+
+fn default_token_grace() -> u32 { 60 }
+
+/// Give a host a new token, sent to its agent when connected. The previous
+/// token keeps working for the grace window, or until the agent uses the
+/// new one. The token is returned once, to configure an offline agent.
+async fn rotate_token(
+    Path(id): Path<String>,
+    State(state): State<ApiState>,
+    Json(body): Json<RotateTokenRequest>,
+) -> Json<Value> {
+    let token = hr_registry::generate_token();
+    let token_hash = match hr_registry::hash_token(&token) {
+        Ok(h) => h,
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    };
+    let grace_until = chrono::Utc::now() + chrono::Duration::minutes(body.grace_minutes as i64);
+
+    let mut data = load_hosts().await;
+    let Some(host) = find_host_mut(&mut data, &id) else {
+        return Json(json!({"success": false, "error": "Hote non trouve"}));
+    };
+    let revoked = host.get("token_revoked").and_then(|v| v.as_bool()).unwrap_or(false);
+    match host.get("token_hash").cloned() {
+        Some(previous) if body.grace_minutes > 0 && !revoked => {
+            host["previous_token_hash"] = previous;
+            host["previous_token_expires"] = json!(grace_until.to_rfc3339());
+        }
+        _ => {
+            if let Some(obj) = host.as_object_mut() {
+                obj.remove("previous_token_hash");
+                obj.remove("previous_token_expires");
+            }
+        }
+    }
+    host["token_hash"] = json!(token_hash);
+    host["token_revoked"] = json!(false);
+    host["updatedAt"] = json!(chrono::Utc::now().to_rfc3339());
+    if let Err(e) = save_hosts(&data).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+
+    let pushed = match &state.registry {
+        Some(registry) => registry
+            .send_host_command(&id, hr_registry::protocol::HostRegistryMessage::RotateToken { token: token.clone() })
+            .await
+            .is_ok(),
+        None => false,
+    };
+    tracing::info!(host_id = %id, pushed, "Host token rotated");
+    Json(json!({
+        "success": true,
+        "token": token,
+        "pushed": pushed,
+        "grace_until": (body.grace_minutes > 0 && !revoked).then(|| grace_until.to_rfc3339()),
+    }))
+}
+
+/// Revoke the credentials of a host and disconnect its agent now. It
+/// cannot connect again until its token is rotated.
+async fn revoke_token(Path(id): Path<String>, State(state): State<ApiState>) -> Json<Value> {
+    let mut data = load_hosts().await;
+    let Some(host) = find_host_mut(&mut data, &id) else {
+        return Json(json!({"success": false, "error": "Hote non trouve"}));
+    };
+    if let Some(obj) = host.as_object_mut() {
+        obj.remove("token_hash");
+        obj.remove("previous_token_hash");
+        obj.remove("previous_token_expires");
+    }
+    host["token_revoked"] = json!(true);
+    host["updatedAt"] = json!(chrono::Utc::now().to_rfc3339());
+    if let Err(e) = save_hosts(&data).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+
+    let disconnected = match &state.registry {
+        Some(registry) => registry.disconnect_host(&id, "Token revoked").await,
+        None => false,
+    };
+    tracing::warn!(host_id = %id, disconnected, "Host token revoked");
+    Json(json!({"success": true, "disconnected": disconnected}))
+}
+
 async fn get_host_metrics(Path(id): Path<String>, State(state): State<ApiState>) -> Json<Value> {
     let registry = match &state.registry {
         Some(r) => r,
@@ -768,6 +1103,10 @@ async fn get_host_metrics(Path(id): Path<String>, State(state): State<ApiState>)
                     "diskUsedBytes": metrics.disk_used_bytes,
                     "diskTotalBytes": metrics.disk_total_bytes,
                     "loadAvg": metrics.load_avg,
+                    "cpuTempC": metrics.cpu_temp_c,
+                    "nvmeTemps": metrics.nvme_temps,
+                    "fans": metrics.fans,
+                    "gpus": metrics.gpus,
                 }
             }));
         }
@@ -775,61 +1114,169 @@ async fn get_host_metrics(Path(id): Path<String>, State(state): State<ApiState>)
     Json(json!({"success": false, "error": "No metrics available"}))
 }
 
-async fn update_host_agents(State(state): State<ApiState>) -> Json<Value> {
+/// Last SMART report of the host's disks, kept in hosts.json so it stays
+/// available while the host is offline.
+async fn get_host_disks(Path(id): Path<String>) -> Json<Value> {
+    let data = load_hosts().await;
+    let Some(host) = find_host(&data, &id) else {
+        return Json(json!({"success": false, "error": "Host not found"}));
+    };
+    Json(json!({
+        "success": true,
+        "disks": host.get("disks").cloned().unwrap_or(json!([])),
+        "updatedAt": host.get("disksUpdatedAt").cloned().unwrap_or(Value::Null),
+    }))
+}
+
+/// Service patterns of a host: its `service_allowlist`, or the default one.
+fn service_allowlist(host: &Value) -> Vec<String> {
+    match host.get("service_allowlist").and_then(|v| v.as_array()) {
+        Some(list) => list.iter().filter_map(|p| p.as_str().map(str::to_string)).collect(),
+        None => DEFAULT_SERVICE_ALLOWLIST.iter().map(|p| p.to_string()).collect(),
+    }
+}
+
+/// Allowlist of host `id`, after checking `unit` is on it.
+async fn allowed_service(id: &str, unit: &str) -> Result<(), String> {
+    let data = load_hosts().await;
+    let host = find_host(&data, id).ok_or("Host not found")?;
+    if !is_service_unit(unit) || !service_allowed(unit, &service_allowlist(host)) {
+        return Err(format!("Service {unit} is not in the allowlist of this host"));
+    }
+    Ok(())
+}
+
+async fn list_host_services(Path(id): Path<String>, State(state): State<ApiState>) -> Json<Value> {
     let registry = match &state.registry {
         Some(r) => r,
         None => return Json(json!({"success": false, "error": "No registry"})),
     };
+    let data = load_hosts().await;
+    let Some(host) = find_host(&data, &id) else {
+        return Json(json!({"success": false, "error": "Host not found"}));
+    };
+    let allowlist = service_allowlist(host);
+    match registry.list_host_services(&id, allowlist.clone()).await {
+        Ok(services) => Json(json!({"success": true, "services": services, "allowlist": allowlist})),
+        Err(e) => Json(json!({"success": false, "error": format!("{e}")})),
+    }
+}
 
-    let binary_path = std::path::Path::new(HOST_AGENT_BINARY);
-    if !binary_path.exists() {
-        return Json(json!({"success": false, "error": "Host agent binary not found"}));
+async fn host_service_status(
+    Path((id, unit)): Path<(String, String)>,
+    State(state): State<ApiState>,
+) -> Json<Value> {
+    let registry = match &state.registry {
+        Some(r) => r,
+        None => return Json(json!({"success": false, "error": "No registry"})),
+    };
+    if let Err(e) = allowed_service(&id, &unit).await {
+        return Json(json!({"success": false, "error": e}));
     }
+    match registry.host_service_action(&id, &unit, SystemdAction::Status).await {
+        Ok((true, stdout, _)) => Json(json!({"success": true, "unit": unit, "status": stdout})),
+        Ok((false, _, stderr)) => Json(json!({"success": false, "error": stderr})),
+        Err(e) => Json(json!({"success": false, "error": format!("{e}")})),
+    }
+}
 
-    use std::io::Read;
-    let mut file = match std::fs::File::open(binary_path) {
-        Ok(f) => f,
-        Err(e) => return Json(json!({"success": false, "error": format!("Open binary: {}", e)})),
+async fn host_service_action(
+    Path((id, unit, action)): Path<(String, String, String)>,
+    State(state): State<ApiState>,
+) -> Json<Value> {
+    let registry = match &state.registry {
+        Some(r) => r,
+        None => return Json(json!({"success": false, "error": "No registry"})),
+    };
+    let action = match action.as_str() {
+        "start" => SystemdAction::Start,
+        "stop" => SystemdAction::Stop,
+        "restart" => SystemdAction::Restart,
+        _ => return Json(json!({"success": false, "error": format!("Unknown action: {action}")})),
     };
-    use ring::digest::{Context, SHA256};
-    let mut ctx = Context::new(&SHA256);
-    let mut buf = [0u8; 8192];
-    loop {
-        let n = file.read(&mut buf).unwrap_or(0);
-        if n == 0 { break; }
-        ctx.update(&buf[..n]);
+    if let Err(e) = allowed_service(&id, &unit).await {
+        return Json(json!({"success": false, "error": e}));
     }
-    let sha256 = hex::encode(ctx.finish().as_ref());
+    tracing::info!(host_id = %id, unit = %unit, ?action, "Host service action");
+    match registry.host_service_action(&id, &unit, action).await {
+        Ok((true, _, _)) => {
+            // Fresh state of the unit for the caller
+            let service = registry
+                .list_host_services(&id, vec![unit.clone()])
+                .await
+                .ok()
+                .and_then(|list| list.into_iter().next());
+            Json(json!({"success": true, "service": service}))
+        }
+        Ok((false, _, stderr)) => Json(json!({"success": false, "error": stderr})),
+        Err(e) => Json(json!({"success": false, "error": format!("{e}")})),
+    }
+}
 
-    let version = std::fs::metadata(binary_path)
-        .ok()
-        .and_then(|m| m.modified().ok())
-        .map(|t| {
-            let dt: chrono::DateTime<chrono::Utc> = t.into();
-            dt.format("%Y%m%d-%H%M%S").to_string()
-        })
-        .unwrap_or_else(|| "unknown".to_string());
+async fn update_host_agents(State(state): State<ApiState>) -> Json<Value> {
+    let registry = match &state.registry {
+        Some(r) => r,
+        None => return Json(json!({"success": false, "error": "No registry"})),
+    };
 
-    let download_url = format!("http://{}:{}/api/hosts/agents/binary", HOMEROUTE_LAN_IP, API_PORT);
+    let store = BinaryStore::default();
+    let artifacts: std::collections::HashMap<&str, Artifact> = ARCHES
+        .iter()
+        .filter_map(|arch| Some((*arch, store.artifact("hr-host-agent", arch).ok().flatten()?)))
+        .collect();
+    if artifacts.is_empty() {
+        return Json(json!({"success": false, "error": "Host agent binary not found"}));
+    }
 
     let conns = registry.host_connections.read().await;
     let mut notified = 0u32;
-    for (_host_id, conn) in conns.iter() {
+    let mut skipped = Vec::new();
+    for (host_id, conn) in conns.iter() {
+        // Agents older than the arch field run on the server's architecture
+        let arch = conn.protocol.arch.as_deref().unwrap_or(local_arch());
+        let Some(artifact) = artifacts.get(arch) else {
+            skipped.push(json!({"host_id": host_id, "arch": arch, "reason": "no_binary_for_arch"}));
+            continue;
+        };
+        // The manifest signed at release time, relayed as it is
+        let Some(manifest) = artifact.manifest.clone() else {
+            skipped.push(json!({"host_id": host_id, "arch": arch, "reason": "unsigned_binary"}));
+            continue;
+        };
         let msg = hr_registry::protocol::HostRegistryMessage::PushAgentUpdate {
-            version: version.clone(),
-            download_url: download_url.clone(),
-            sha256: sha256.clone(),
+            download_url: format!("http://{}:{}/api/hosts/agents/binary?arch={}", HOMEROUTE_LAN_IP, API_PORT, arch),
+            manifest,
         };
         if conn.tx.send(hr_registry::OutgoingHostMessage::Text(msg)).await.is_ok() {
             notified += 1;
         }
     }
 
-    Json(json!({"success": true, "notified": notified, "version": version, "sha256": sha256}))
+    let artifacts: Vec<&Artifact> = artifacts.values().collect();
+    Json(json!({"success": true, "notified": notified, "skipped": skipped, "artifacts": artifacts}))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ArchQuery {
+    /// Architecture of the agent, the server's when absent. `uname -m`
+    /// names are accepted.
+    pub arch: Option<String>,
 }
 
-async fn serve_host_agent_binary() -> impl IntoResponse {
-    match tokio::fs::read(HOST_AGENT_BINARY).await {
+impl ArchQuery {
+    pub(crate) fn arch(&self) -> &str {
+        match &self.arch {
+            Some(arch) => normalize_arch(arch).unwrap_or(arch),
+            None => local_arch(),
+        }
+    }
+}
+
+async fn serve_host_agent_binary(Query(query): Query<ArchQuery>) -> impl IntoResponse {
+    let Some(path) = BinaryStore::default().find("hr-host-agent", query.arch()) else {
+        return (axum::http::StatusCode::NOT_FOUND, "Binary not found").into_response();
+    };
+    match tokio::fs::read(path).await {
         Ok(data) => (
             axum::http::StatusCode::OK,
             [(axum::http::header::CONTENT_TYPE, "application/octet-stream")],
@@ -839,8 +1286,297 @@ async fn serve_host_agent_binary() -> impl IntoResponse {
     }
 }
 
+// ── Enrollment ───────────────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+struct JoinCodeRequest {
+    /// Name of the host; the agent's hostname when absent.
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    groups: Vec<String>,
+    #[serde(default)]
+    ttl_minutes: Option<u64>,
+}
+
+/// Issue a join code for a new host, with the command installing its agent.
+async fn create_join_code(State(state): State<ApiState>, Json(body): Json<JoinCodeRequest>) -> Json<Value> {
+    let name = body.name.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+    if let Some(name) = &name {
+        // Written as is in the install script
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+            return Json(json!({"success": false, "error": "Nom invalide (lettres, chiffres, - _ .)"}));
+        }
+        if host_id_by_name(&load_hosts().await, name).is_some() {
+            return Json(json!({"success": false, "error": format!("Host {name} already exists")}));
+        }
+    }
+    let ttl = body.ttl_minutes.map_or(enrollment::DEFAULT_TTL, |m| std::time::Duration::from_secs(m * 60));
+    let target = JoinTarget::Host { name, groups: body.groups };
+    let join = match enrollment::issue(&mut *state.join_codes.write().await, target, ttl) {
+        Ok(j) => j,
+        Err(e) => return Json(json!({"success": false, "error": e})),
+    };
+    tracing::info!(expires_at = %join.expires_at, "Host join code issued");
+    let install_command = format!(
+        "curl -fsSL http://{HOMEROUTE_LAN_IP}:{API_PORT}/api/hosts/join-codes/{}/install.sh | sudo bash",
+        join.code
+    );
+    Json(json!({"success": true, "join_code": join, "install_command": install_command}))
+}
+
+async fn list_join_codes(State(state): State<ApiState>) -> Json<Value> {
+    let codes: Vec<_> = enrollment::pending(&mut *state.join_codes.write().await)
+        .into_iter()
+        .filter(|j| matches!(j.target, JoinTarget::Host { .. }))
+        .collect();
+    Json(json!({"success": true, "join_codes": codes}))
+}
+
+async fn revoke_join_code(Path(code): Path<String>, State(state): State<ApiState>) -> Json<Value> {
+    if enrollment::revoke(&mut *state.join_codes.write().await, &code) {
+        Json(json!({"success": true}))
+    } else {
+        Json(json!({"success": false, "error": "Code inconnu ou expire"}))
+    }
+}
+
+/// Script installing hr-host-agent set up to enroll with `code`. Served
+/// without consuming the code, as long as it is valid.
+async fn join_install_script(Path(code): Path<String>, State(state): State<ApiState>) -> impl IntoResponse {
+    let join = enrollment::pending(&mut *state.join_codes.write().await)
+        .into_iter()
+        .find(|j| j.code.eq_ignore_ascii_case(&code));
+    let Some(JoinTarget::Host { name, .. }) = join.map(|j| j.target) else {
+        return (axum::http::StatusCode::NOT_FOUND, "Invalid or expired join code\n".to_string()).into_response();
+    };
+    // Expanded on the host: its hostname, and the interface reaching HomeRoute
+    let host_name = name.unwrap_or_else(|| "$(hostname -s)".to_string());
+    let config = format!(
+        r#"homeroute_url = "{HOMEROUTE_LAN_IP}:{API_PORT}"
+join_code = "{code}"
+host_name = "{host_name}"
+lan_interface = "$(ip -o route get {HOMEROUTE_LAN_IP} | sed -n 's/.* dev \([^ ]*\).*/\1/p')"
+container_storage_path = "/var/lib/machines"
+container_runtime = "nspawn"
+{}"#,
+        update::config_line(),
+    );
+    let script = format!(
+        "#!/bin/sh\nset -e\ncurl -fsSL \"http://{HOMEROUTE_LAN_IP}:{API_PORT}/api/hosts/agents/binary?arch=$(uname -m)\" -o /tmp/hr-host-agent\n{}\n",
+        install_commands(&config, true)
+    );
+    ([(axum::http::header::CONTENT_TYPE, "text/x-shellscript")], script).into_response()
+}
+
+/// Register the host enrolling with `join_code`. Returns its id, name and
+/// token.
+async fn enroll_host(
+    state: &ApiState,
+    join_code: &str,
+    host_name: String,
+    lan_interface: Option<String>,
+    container_storage_path: Option<String>,
+    interfaces: Vec<hr_registry::protocol::NetworkInterfaceInfo>,
+) -> Result<(String, String, String), String> {
+    let target = enrollment::redeem(&mut *state.join_codes.write().await, join_code, chrono::Utc::now())?;
+    let JoinTarget::Host { name, groups } = target else {
+        return Err("This join code is for an application".to_string());
+    };
+    let name = name.unwrap_or(host_name);
+    if name.is_empty() {
+        return Err("Missing host name".to_string());
+    }
+
+    let mut data = load_hosts().await;
+    if host_id_by_name(&data, &name).is_some() {
+        return Err(format!("Host {name} already exists"));
+    }
+    // Address and MAC of the LAN interface, or of the first one up
+    let lan = interfaces
+        .iter()
+        .find(|i| lan_interface.as_deref() == Some(i.name.as_str()) && i.ipv4.is_some())
+        .or_else(|| interfaces.iter().find(|i| i.is_up && i.ipv4.is_some()));
+    let address = lan.and_then(|i| i.ipv4.clone()).ok_or("No IPv4 address reported")?;
+    let token = hr_registry::generate_token();
+    let token_hash = hr_registry::hash_token(&token).map_err(|e| e.to_string())?;
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let host = json!({
+        "id": id,
+        "name": name,
+        "host": address,
+        "interface": lan.map(|i| i.name.clone()),
+        "mac": lan.map(|i| i.mac.clone()),
+        "lan_interface": lan_interface,
+        "container_storage_path": container_storage_path,
+        "groups": groups,
+        "interfaces": interfaces.iter().map(|i| json!({
+            "ifname": i.name,
+            "address": i.mac,
+            "operstate": if i.is_up { "UP" } else { "DOWN" },
+            "addr_info": i.ipv4.iter().map(|ip| json!({"family": "inet", "local": ip})).collect::<Vec<_>>(),
+        })).collect::<Vec<_>>(),
+        "status": "unknown",
+        "latency": 0,
+        "lastSeen": null,
+        "lxc": null,
+        "token_hash": token_hash,
+        "enrolled": true,
+        "createdAt": chrono::Utc::now().to_rfc3339()
+    });
+    match data.get_mut("hosts").and_then(|s| s.as_array_mut()) {
+        Some(arr) => arr.push(host),
+        None => data["hosts"] = json!([host]),
+    }
+    save_hosts(&data).await?;
+    Ok((id, name, token))
+}
+
+fn host_id_by_name<'a>(data: &'a Value, name: &str) -> Option<&'a str> {
+    data.get("hosts")?
+        .as_array()?
+        .iter()
+        .find(|h| h.get("name").and_then(|n| n.as_str()) == Some(name))?
+        .get("id")?
+        .as_str()
+}
+
 // ── Remote container management ──────────────────────────────────────────
 
+/// Containers of a host with a `runtime` discriminator: nspawn ones from
+/// the container manager, Docker/Podman ones from the host agent, and the
+/// proxy routes suggested for the ports those publish.
+async fn list_containers(Path(id): Path<String>, State(state): State<ApiState>) -> Json<Value> {
+    let mut containers = Vec::new();
+    if let Some(cm) = &state.container_manager {
+        for record in cm.records().await.into_iter().filter(|r| r.host_id == id) {
+            containers.push(json!({
+                "runtime": "nspawn",
+                "id": record.id,
+                "name": record.container_name,
+                "status": record.status,
+            }));
+        }
+    }
+
+    let (online, lxc_containers, runtime_containers) = match &state.registry {
+        Some(registry) => match registry.host_containers(&id).await {
+            Some((lxc, runtime)) => (true, lxc, runtime),
+            None => (false, Vec::new(), Vec::new()),
+        },
+        None => (false, Vec::new(), Vec::new()),
+    };
+    containers.extend(lxc_containers.iter().map(|c| json!({
+        "runtime": "lxc",
+        "name": c.name,
+        "status": c.status,
+        "ipv4": c.ipv4,
+    })));
+    containers.extend(runtime_containers.iter().map(|c| json!(c)));
+
+    // Routes need the LAN address of the host and skip ports already routed
+    let host_ip = if id == "local" {
+        Some(HOMEROUTE_LAN_IP.to_string())
+    } else {
+        let data = load_hosts().await;
+        data.get("hosts")
+            .and_then(|h| h.as_array())
+            .and_then(|hosts| hosts.iter().find(|h| h.get("id").and_then(|i| i.as_str()) == Some(&id)))
+            .and_then(|h| h.get("host"))
+            .and_then(|h| h.as_str())
+            .map(str::to_string)
+    };
+    let routed: Vec<(String, u64)> = super::reverseproxy::load_rp_config(&state)
+        .await
+        .ok()
+        .and_then(|config| config.get("hosts").and_then(|h| h.as_array()).cloned())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|h| {
+            let target = h.get("targetHost")?.as_str()?.to_string();
+            Some((target, h.get("targetPort")?.as_u64()?))
+        })
+        .collect();
+    let suggestions = host_ip
+        .map(|ip| suggest_routes(&ip, &runtime_containers, &routed))
+        .unwrap_or_default();
+
+    Json(json!({
+        "success": true,
+        "online": online,
+        "containers": containers,
+        "suggestedRoutes": suggestions,
+    }))
+}
+
+/// One proxy route per TCP port published by a running container, shaped
+/// like a reverse proxy host (`POST /api/reverseproxy/hosts`). Ports bound
+/// to loopback are not reachable from HomeRoute and are left out.
+fn suggest_routes(host_ip: &str, containers: &[RuntimeContainerInfo], routed: &[(String, u64)]) -> Vec<Value> {
+    let mut suggestions = Vec::new();
+    for container in containers.iter().filter(|c| c.state == "running") {
+        let ports: Vec<_> = container
+            .ports
+            .iter()
+            .filter(|p| p.protocol == "tcp")
+            .filter(|p| p.host_ip.as_deref().is_none_or(|ip| !ip.starts_with("127.") && ip != "::1"))
+            .collect();
+        let base: String = container
+            .name
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        let base = base.trim_matches('-');
+        for port in &ports {
+            let subdomain = if ports.len() > 1 { format!("{base}-{}", port.host_port) } else { base.to_string() };
+            let target_host = port.host_ip.clone().unwrap_or_else(|| host_ip.to_string());
+            let exists = routed.iter().any(|(host, p)| *host == target_host && *p == u64::from(port.host_port));
+            suggestions.push(json!({
+                "runtime": container.runtime,
+                "container": container.name,
+                "containerPort": port.container_port,
+                "subdomain": subdomain,
+                "targetHost": target_host,
+                "targetPort": port.host_port,
+                "exists": exists,
+            }));
+        }
+    }
+    suggestions
+}
+
+async fn runtime_container_action(
+    runtime: ContainerRuntime,
+    Path((id, name, action)): Path<(String, String, String)>,
+    State(state): State<ApiState>,
+) -> Json<Value> {
+    let registry = match &state.registry {
+        Some(r) => r,
+        None => return Json(json!({"success": false, "error": "No registry"})),
+    };
+    let action = match action.as_str() {
+        "start" => RuntimeAction::Start,
+        "stop" => RuntimeAction::Stop,
+        "inspect" => RuntimeAction::Inspect,
+        _ => return Json(json!({"success": false, "error": format!("Unknown action: {action}")})),
+    };
+    match registry.runtime_container_action(&id, runtime, &name, action).await {
+        Ok((true, stdout, _)) if action == RuntimeAction::Inspect => {
+            // `inspect` prints an array with one object
+            let details = serde_json::from_str::<Value>(&stdout)
+                .ok()
+                .and_then(|v| v.as_array().and_then(|a| a.first().cloned()))
+                .unwrap_or(Value::String(stdout));
+            Json(json!({"success": true, "container": details}))
+        }
+        Ok((true, _, _)) => Json(json!({"success": true})),
+        Ok((false, _, stderr)) => Json(json!({"success": false, "error": stderr})),
+        Err(e) => Json(json!({"success": false, "error": format!("{e}")})),
+    }
+}
+
 async fn start_container(
     Path((id, name)): Path<(String, String)>,
     State(state): State<ApiState>,
@@ -920,6 +1656,42 @@ async fn exec_on_host(
     }
 }
 
+// ── Web terminal ─────────────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+struct HostTerminalQuery {
+    /// Container to enter; the host itself when absent.
+    #[serde(default)]
+    container: Option<String>,
+    #[serde(default)]
+    cols: u16,
+    #[serde(default)]
+    rows: u16,
+}
+
+/// Shell on a host or in one of its containers, see [`crate::terminal`].
+async fn host_terminal_ws(
+    Path(id): Path<String>,
+    Query(query): Query<HostTerminalQuery>,
+    State(state): State<ApiState>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |mut socket| async move {
+        let size = hr_container::pty::WindowSize::or_default(query.cols, query.rows);
+        let container = query.container.as_deref().filter(|c| !c.is_empty());
+        tracing::info!(host_id = %id, ?container, "Host terminal WebSocket opened");
+        if id == "local" {
+            crate::terminal::run_local(&mut socket, container, size).await;
+        } else if let Some(registry) = &state.registry {
+            crate::terminal::run_remote(registry, &mut socket, &id, container, size).await;
+        } else {
+            crate::terminal::send_error(&mut socket, "Registry not available").await;
+        }
+        let _ = socket.send(Message::Close(None)).await;
+        tracing::info!(host_id = %id, "Host terminal WebSocket closed");
+    })
+}
+
 // ── Host-agent WebSocket ─────────────────────────────────────────────────
 
 async fn host_agent_ws(
@@ -930,7 +1702,7 @@ async fn host_agent_ws(
 }
 
 async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
-    use hr_registry::protocol::{HostAgentMessage, HostRegistryMessage};
+    use hr_registry::protocol::{HostAgentMessage, HostRegistryMessage, Negotiated, HOST_AGENT_CAPABILITIES, PROTOCOL_VERSION};
 
     let registry = match &state.registry {
         Some(r) => r.clone(),
@@ -942,59 +1714,77 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
 
     // Wait for Auth message (5s timeout)
     let auth_msg = tokio::time::timeout(std::time::Duration::from_secs(5), socket.recv()).await;
-    let (host_id, host_name, version) = match auth_msg {
+    let (host_id, host_name, version, protocol, issued_token) = match auth_msg {
         Ok(Some(Ok(Message::Text(text)))) => {
             match serde_json::from_str::<HostAgentMessage>(&text) {
-                Ok(HostAgentMessage::Auth { token: _, host_name, version, lan_interface, container_storage_path }) => {
-                    let mut data = load_hosts().await;
-                    let host_id = data
-                        .get("hosts")
-                        .and_then(|h| h.as_array())
-                        .and_then(|hosts| {
-                            hosts.iter().find(|h| {
-                                h.get("name").and_then(|n| n.as_str()) == Some(&host_name)
-                            })
-                        })
-                        .and_then(|h| h.get("id").and_then(|i| i.as_str()))
-                        .map(|s| s.to_string());
-
-                    // Store lan_interface and container_storage_path from host agent
-                    if let Some(ref id) = host_id {
-                        if let Some(host) = find_host_mut(&mut data, id) {
-                            let mut changed = false;
-                            if let Some(ref iface) = lan_interface {
-                                if host.get("lan_interface").and_then(|v| v.as_str()) != Some(iface) {
-                                    host["lan_interface"] = json!(iface);
-                                    changed = true;
+                Ok(HostAgentMessage::Auth {
+                    token, host_name, version, lan_interface, container_storage_path, protocol_version, capabilities, arch,
+                }) => {
+                    let protocol = Negotiated::new(protocol_version, &capabilities, HOST_AGENT_CAPABILITIES).with_arch(arch.as_deref());
+                    let host_id = host_id_by_name(&load_hosts().await, &host_name).map(str::to_string);
+
+                    let can_rotate = protocol.supports(hr_registry::protocol::capability::TOKEN_ROTATION);
+                    let verified = match host_id {
+                        Some(id) => check_host_token(&id, &token, can_rotate).await.map(|issued| (id, issued)),
+                        None => Err("Unknown host".to_string()),
+                    };
+                    match verified {
+                        Ok((id, issued)) => {
+                            // Store lan_interface and container_storage_path, only once the token is verified
+                            let mut data = load_hosts().await;
+                            if let Some(host) = find_host_mut(&mut data, &id) {
+                                let mut changed = false;
+                                if let Some(ref iface) = lan_interface {
+                                    if host.get("lan_interface").and_then(|v| v.as_str()) != Some(iface) {
+                                        host["lan_interface"] = json!(iface);
+                                        changed = true;
+                                    }
                                 }
-                            }
-                            if let Some(ref sp) = container_storage_path {
-                                if host.get("container_storage_path").and_then(|v| v.as_str()) != Some(sp) {
-                                    host["container_storage_path"] = json!(sp);
-                                    changed = true;
+                                if let Some(ref sp) = container_storage_path {
+                                    if host.get("container_storage_path").and_then(|v| v.as_str()) != Some(sp) {
+                                        host["container_storage_path"] = json!(sp);
+                                        changed = true;
+                                    }
+                                }
+                                if changed {
+                                    let _ = save_hosts(&data).await;
+                                    tracing::info!(host = %host_name, "Updated host config from agent: lan_interface={:?}, storage_path={:?}", lan_interface, container_storage_path);
                                 }
                             }
-                            if changed {
-                                let _ = save_hosts(&data).await;
-                                tracing::info!(host = %host_name, "Updated host config from agent: lan_interface={:?}, storage_path={:?}", lan_interface, container_storage_path);
-                            }
+                            (id, host_name, version, protocol, issued)
                         }
-                    }
-
-                    match host_id {
-                        Some(id) => (id, host_name, version),
-                        None => {
-                            tracing::warn!("Host agent auth failed: unknown host '{}'", host_name);
+                        Err(e) => {
+                            tracing::warn!("Host agent auth failed for '{}': {}", host_name, e);
                             let _ = socket.send(Message::Text(
                                 serde_json::to_string(&HostRegistryMessage::AuthResult {
                                     success: false,
-                                    error: Some("Unknown host".to_string()),
+                                    error: Some(e),
+                                    protocol_version: PROTOCOL_VERSION,
+                                    capabilities: Vec::new(),
                                 }).unwrap().into()
                             )).await;
                             return;
                         }
                     }
                 }
+                Ok(HostAgentMessage::Enroll {
+                    join_code, host_name, version, lan_interface, container_storage_path, interfaces,
+                }) => {
+                    let result = match enroll_host(&state, &join_code, host_name, lan_interface, container_storage_path, interfaces).await {
+                        Ok((id, name, token)) => {
+                            tracing::info!(host_id = %id, host = %name, version = %version, "Host enrolled");
+                            HostRegistryMessage::EnrollResult { success: true, error: None, token: Some(token), host_name: Some(name) }
+                        }
+                        Err(e) => {
+                            tracing::warn!("Host enrollment failed: {}", e);
+                            HostRegistryMessage::EnrollResult { success: false, error: Some(e), token: None, host_name: None }
+                        }
+                    };
+                    // The agent connects again with Auth
+                    let _ = socket.send(Message::Text(serde_json::to_string(&result).unwrap().into())).await;
+                    let _ = socket.send(Message::Close(None)).await;
+                    return;
+                }
                 _ => {
                     tracing::warn!("Host agent: expected Auth message");
                     return;
@@ -1012,6 +1802,8 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
         serde_json::to_string(&HostRegistryMessage::AuthResult {
             success: true,
             error: None,
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: protocol.capabilities.clone(),
         }).unwrap().into()
     )).await.is_err() {
         return;
@@ -1021,7 +1813,14 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
 
     // Register connection
     let (tx, mut rx) = mpsc::channel::<hr_registry::OutgoingHostMessage>(512);
-    registry.on_host_connected(host_id.clone(), host_name.clone(), tx, version).await;
+    let binary_frames = protocol.supports(hr_registry::protocol::capability::BINARY_FRAMES);
+    let resumable = protocol.supports(hr_registry::protocol::capability::RESUMABLE_TRANSFERS);
+    registry.on_host_connected(host_id.clone(), host_name.clone(), tx, version, protocol).await;
+
+    // First token of a host enrolled before per-host credentials
+    if let Some(token) = issued_token {
+        let _ = registry.send_host_command(&host_id, HostRegistryMessage::RotateToken { token }).await;
+    }
 
     // Mark host online
     update_host_status(&host_id, "online", &state.events.host_status).await;
@@ -1051,35 +1850,45 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
         }
     }
 
+    // Metered draw above the busy threshold: hold auto-off from the start
+    if state.energy.host_busy(&host_id) {
+        let _ = registry.send_host_command(
+            &host_id,
+            hr_registry::protocol::HostRegistryMessage::PowerBusy { busy: true },
+        ).await;
+    }
+
     // Restore containers that should be running on this host
     if let Some(cm) = &state.container_manager {
         cm.restore_host_containers(&host_id).await;
     }
 
-    // Track which transfer_ids are being relayed (remote→remote)
-    let mut relay_transfers: std::collections::HashSet<String> = std::collections::HashSet::new();
+    // Track which transfer_ids are being relayed (remote→remote), and
+    // whether their target can resume after a disconnection
+    let mut relay_transfers: std::collections::HashMap<String, bool> = std::collections::HashMap::new();
 
-    // Track local nspawn imports (remote→local)
-    #[derive(Debug, Clone, Copy, PartialEq)]
-    enum TransferPhase { ReceivingContainer, ReceivingWorkspace }
-    struct ActiveTransfer {
-        container_name: String,
-        storage_path: String,
-        network_mode: String,
-        file: tokio::fs::File,
-        phase: TransferPhase,
-        workspace_file: Option<tokio::fs::File>,
-        total_bytes: u64,
-        bytes_received: u64,
-        chunk_count: u32,
-        app_id: String,
-        transfer_id: String,
+    // Track local nspawn imports (remote→local), picking up those
+    // interrupted by the previous disconnection of this host
+    let mut active_transfers = state.interrupted_transfers.lock().await.remove(&host_id).unwrap_or_default();
+    if !active_transfers.is_empty() {
+        let migrations = state.migrations.read().await;
+        active_transfers.retain(|transfer_id, _| {
+            migrations.get(transfer_id).is_some_and(|m| !matches!(m.phase, MigrationPhase::Complete | MigrationPhase::Failed))
+        });
+    }
+    for (transfer_id, transfer) in &active_transfers {
+        let point = transfer.manifest.resume_point(transfer.stream());
+        tracing::info!(transfer_id = %transfer_id, ?point, "Resuming interrupted transfer from host");
+        let _ = registry.send_host_command(
+            &host_id,
+            HostRegistryMessage::ResumeTransfer { transfer_id: transfer_id.clone(), point },
+        ).await;
     }
-    let mut active_transfers: std::collections::HashMap<String, ActiveTransfer> = std::collections::HashMap::new();
+    state.backups.resume(&registry, &host_id).await;
 
-    // Pending binary chunk metadata: (transfer_id, sequence, checksum)
-    // Set when TransferChunkBinary text arrives, consumed when the next Binary frame arrives.
-    let mut pending_binary_meta: Option<(String, u32, u32)> = None;
+    // Pending TransferChunkBinary of a legacy agent (no `binary_frames`):
+    // set when the text message arrives, consumed by the next Binary frame.
+    let mut pending_binary_meta: Option<HostAgentMessage> = None;
 
     // Heartbeat timeout: agent sends every 5s, detect offline within 10s
     let heartbeat_timeout = std::time::Duration::from_secs(10);
@@ -1087,30 +1896,33 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
     tokio::pin!(timeout_sleep);
 
     // Bidirectional message loop
-    loop {
+    'session: loop {
         tokio::select! {
             // Messages from registry → host-agent
             Some(msg) = rx.recv() => {
-                let ws_msg = match msg {
-                    hr_registry::OutgoingHostMessage::Text(m) => {
-                        match serde_json::to_string(&m) {
-                            Ok(t) => Message::Text(t.into()),
-                            Err(_) => continue,
-                        }
-                    }
-                    hr_registry::OutgoingHostMessage::Binary(data) => {
-                        Message::Binary(data.into())
+                if let hr_registry::OutgoingHostMessage::Close(reason) = msg {
+                    tracing::warn!(host = %host_id, %reason, "Closing host agent connection");
+                    let _ = socket.send(Message::Close(None)).await;
+                    break 'session;
+                }
+                let ws_msgs = match host_ws_messages(msg, binary_frames) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        tracing::warn!(host = %host_id, %e, "Failed to encode host message");
+                        continue;
                     }
                 };
-                match tokio::time::timeout(
-                    std::time::Duration::from_secs(30),
-                    socket.send(ws_msg),
-                ).await {
-                    Ok(Ok(())) => {}
-                    Ok(Err(_)) => break,    // WebSocket send error
-                    Err(_) => {             // 30s timeout
-                        tracing::warn!("WebSocket send timeout for host {host_id}, disconnecting");
-                        break;
+                for ws_msg in ws_msgs {
+                    match tokio::time::timeout(
+                        std::time::Duration::from_secs(30),
+                        socket.send(ws_msg),
+                    ).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(_)) => break 'session,    // WebSocket send error
+                        Err(_) => {                      // 30s timeout
+                            tracing::warn!("WebSocket send timeout for host {host_id}, disconnecting");
+                            break 'session;
+                        }
                     }
                 }
             }
@@ -1122,10 +1934,34 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
             // Messages from host-agent → registry
             msg = socket.recv() => {
                 match msg {
-                    Some(Ok(Message::Text(text))) => {
+                    Some(Ok(frame @ (Message::Text(_) | Message::Binary(_)))) => {
                         // Any message from the agent resets the heartbeat deadline
                         timeout_sleep.as_mut().reset(tokio::time::Instant::now() + heartbeat_timeout);
-                        if let Ok(agent_msg) = serde_json::from_str::<HostAgentMessage>(&text) {
+                        let incoming = match frame {
+                            Message::Text(text) => serde_json::from_str::<HostAgentMessage>(&text).ok().map(|m| (m, None)),
+                            Message::Binary(data) if binary_frames => {
+                                match hr_registry::codec::decode::<HostAgentMessage>(&data) {
+                                    Ok((m, payload)) => {
+                                        let offset = data.len() - payload.len();
+                                        Some((m, Some(data.slice(offset..))))
+                                    }
+                                    Err(e) => {
+                                        tracing::warn!(host = %host_id, %e, "Invalid frame from host agent");
+                                        None
+                                    }
+                                }
+                            }
+                            // Legacy agents: raw data following a TransferChunkBinary text message
+                            Message::Binary(data) => match pending_binary_meta.take() {
+                                Some(meta) => Some((meta, Some(data))),
+                                None => {
+                                    tracing::warn!("Received Binary frame without pending TransferChunkBinary metadata");
+                                    None
+                                }
+                            },
+                            _ => None,
+                        };
+                        if let Some((agent_msg, chunk)) = incoming {
                             match agent_msg {
                                 HostAgentMessage::Heartbeat { .. } => {
                                     registry.update_host_heartbeat(&host_id).await;
@@ -1140,6 +1976,22 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
                                         memory_total_bytes: metrics.memory_total_bytes,
                                     });
                                 }
+                                HostAgentMessage::MetricsBackfill(samples) => {
+                                    tracing::info!(host_id = %host_id, samples = samples.len(), "Host metrics backfill");
+                                    crate::history::record_backfill(&host_id, samples).await;
+                                }
+                                HostAgentMessage::DiskHealth(disks) => {
+                                    record_disk_health(&state, &host_id, disks).await;
+                                }
+                                HostAgentMessage::PackageUpdates(status) => {
+                                    crate::host_updates::record_status(&host_id, &status).await;
+                                }
+                                HostAgentMessage::UpgradeOutput { upgrade_id, line } => {
+                                    crate::host_updates::on_output(&state, &host_id, &upgrade_id, line).await;
+                                }
+                                HostAgentMessage::UpgradeFinished { upgrade_id, success, error, snapshot, reboot_required } => {
+                                    crate::host_updates::on_finished(&state, &host_id, &upgrade_id, success, error, snapshot, reboot_required).await;
+                                }
                                 HostAgentMessage::NetworkInterfaces(interfaces) => {
                                     registry.update_host_interfaces(&host_id, interfaces.clone()).await;
                                     // Persist to hosts.json
@@ -1171,10 +2023,13 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
                                     registry.on_host_exec_result(&host_id, &request_id, success, &stdout, &stderr).await;
                                 }
                                 HostAgentMessage::ExportReady { transfer_id, container_name: _, size_bytes } => {
-                                    // Check if this is a remote→remote relay
-                                    if let Some((target_host_id, _cname)) = registry.get_transfer_relay_target(&transfer_id).await {
+                                    if state.backups.receives(&transfer_id).await {
+                                        // Backup: the receiver is already set up
+                                        tracing::info!(transfer_id = %transfer_id, size_bytes, "Receiving backup export");
+                                    } else if let Some((target_host_id, _cname)) = registry.get_transfer_relay_target(&transfer_id).await {
                                         tracing::info!(transfer_id = %transfer_id, target = %target_host_id, size_bytes, "Relaying ExportReady to target host");
-                                        relay_transfers.insert(transfer_id.clone());
+                                        let target_resumable = registry.host_supports(&target_host_id, hr_registry::protocol::capability::RESUMABLE_TRANSFERS).await;
+                                        relay_transfers.insert(transfer_id.clone(), target_resumable);
                                     } else if let Some(cname) = registry.take_transfer_container_name(&transfer_id).await {
                                         // Remote→Local nspawn import: set up file receiver
                                         let file_path = format!("/tmp/{}.tar.gz", transfer_id);
@@ -1189,24 +2044,28 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
                                                 } else {
                                                     ("/var/lib/machines".to_string(), "bridge:br-lan".to_string())
                                                 };
-                                                // Look up app_id from migration state
-                                                let app_id = {
+                                                // Look up app_id and compression from migration state
+                                                let (app_id, compression) = {
                                                     let m = state.migrations.read().await;
-                                                    m.get(&transfer_id).map(|s| s.app_id.clone()).unwrap_or_default()
+                                                    m.get(&transfer_id).map(|s| (s.app_id.clone(), s.compression)).unwrap_or_default()
                                                 };
-                                                tracing::info!(transfer_id = %transfer_id, container = %cname, size_bytes, "Setting up local nspawn import receiver");
-                                                active_transfers.insert(transfer_id.clone(), ActiveTransfer {
+                                                tracing::info!(transfer_id = %transfer_id, container = %cname, size_bytes, ?compression, "Setting up local nspawn import receiver");
+                                                let (file, bytes_received) = Counted::new(file);
+                                                active_transfers.insert(transfer_id.clone(), InboundTransfer {
                                                     container_name: cname,
                                                     storage_path,
                                                     network_mode,
-                                                    file,
+                                                    file: compression.decoder(file),
                                                     phase: TransferPhase::ReceivingContainer,
                                                     workspace_file: None,
+                                                    compression,
                                                     total_bytes: size_bytes,
-                                                    bytes_received: 0,
+                                                    bytes_received,
                                                     chunk_count: 0,
                                                     app_id,
                                                     transfer_id: transfer_id.clone(),
+                                                    manifest: StreamManifest::default(),
+                                                    resume_requested: false,
                                                 });
                                             }
                                             Err(e) => {
@@ -1217,6 +2076,9 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
                                     }
                                 }
                                 HostAgentMessage::ExportFailed { transfer_id, error } => {
+                                    if state.backups.on_failed(&transfer_id, &error).await {
+                                        continue;
+                                    }
                                     tracing::error!(transfer_id = %transfer_id, %error, "Host export failed");
                                     relay_transfers.remove(&transfer_id);
                                     registry.take_transfer_relay_target(&transfer_id).await;
@@ -1225,26 +2087,102 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
                                     let _ = tokio::fs::remove_file(format!("/tmp/{}-workspace.tar.gz", transfer_id)).await;
                                     registry.on_host_export_failed(&host_id, &transfer_id, &error).await;
                                 }
-                                HostAgentMessage::TransferChunkBinary { transfer_id, sequence, size, checksum } => {
-                                    if relay_transfers.contains(&transfer_id) {
-                                        // Relay mode: forward metadata to target host
+                                HostAgentMessage::TransferChunkBinary { transfer_id, sequence, size, checksum, offset } => {
+                                    let Some(data) = chunk else {
+                                        // Legacy agent: the next Binary frame carries the actual data
+                                        pending_binary_meta = Some(HostAgentMessage::TransferChunkBinary { transfer_id, sequence, size, checksum, offset });
+                                        continue;
+                                    };
+                                    if state.backups.on_chunk(&registry, &transfer_id, sequence, offset, checksum, &data).await {
+                                        continue;
+                                    }
+                                    if let Some(&target_resumable) = relay_transfers.get(&transfer_id) {
+                                        // Relay mode: forward the chunk to the target host
                                         if let Some((target_host_id, _)) = registry.get_transfer_relay_target(&transfer_id).await {
-                                            let _ = registry.send_host_command(
-                                                &target_host_id,
-                                                hr_registry::protocol::HostRegistryMessage::ReceiveChunkBinary {
-                                                    transfer_id: transfer_id.clone(),
-                                                    sequence,
-                                                    size,
-                                                    checksum,
-                                                },
-                                            ).await;
+                                            let meta = hr_registry::protocol::HostRegistryMessage::ReceiveChunkBinary {
+                                                transfer_id: transfer_id.clone(),
+                                                sequence,
+                                                size,
+                                                checksum,
+                                                offset,
+                                            };
+                                            if let Err(e) = registry.send_host_chunk(&target_host_id, meta, data.to_vec()).await {
+                                                if target_resumable {
+                                                    // The target asks the source to resume when it reconnects
+                                                    tracing::debug!(transfer_id = %transfer_id, %e, "Relay target unreachable, chunk dropped");
+                                                    continue;
+                                                }
+                                                tracing::error!(transfer_id = %transfer_id, %e, "Failed to relay binary chunk to target");
+                                                relay_transfers.remove(&transfer_id);
+                                                registry.take_transfer_relay_target(&transfer_id).await;
+                                                registry.on_host_import_failed(&host_id, &transfer_id, &format!("Relay binary send failed: {e}")).await;
+                                            }
+                                        }
+                                    } else if let Some(transfer) = active_transfers.get_mut(&transfer_id) {
+                                        if resumable {
+                                            match transfer.manifest.check(sequence, offset) {
+                                                ChunkOrder::Next => transfer.resume_requested = false,
+                                                ChunkOrder::Duplicate => continue,
+                                                ChunkOrder::Gap => {
+                                                    if !transfer.resume_requested {
+                                                        transfer.resume_requested = true;
+                                                        let _ = registry.send_host_command(
+                                                            &host_id,
+                                                            HostRegistryMessage::ResumeTransfer {
+                                                                transfer_id: transfer_id.clone(),
+                                                                point: transfer.manifest.resume_point(transfer.stream()),
+                                                            },
+                                                        ).await;
+                                                    }
+                                                    continue;
+                                                }
+                                            }
+                                        }
+                                        // Local import mode: write binary data to file
+                                        use tokio::io::AsyncWriteExt;
+                                        let data_len = data.len() as u64;
+                                        let target_file = match transfer.phase {
+                                            TransferPhase::ReceivingWorkspace => transfer.workspace_file.as_mut(),
+                                            _ => Some(&mut transfer.file),
+                                        };
+                                        if let Some(file) = target_file {
+                                            if let Err(e) = file.write_all(&data).await {
+                                                tracing::error!(transfer_id = %transfer_id, %e, "Failed to write binary chunk to local file");
+                                                active_transfers.remove(&transfer_id);
+                                                registry.on_host_import_failed(&host_id, &transfer_id, &format!("File write error: {e}")).await;
+                                            } else {
+                                                transfer.manifest.record(data_len as u32, checksum);
+                                                // Update progress tracking
+                                                let bytes_received = transfer.bytes_received.load(std::sync::atomic::Ordering::Relaxed);
+                                                transfer.chunk_count += 1;
+                                                if transfer.chunk_count % 4 == 0 && transfer.total_bytes > 0 && !transfer.app_id.is_empty() {
+                                                    // Container data: 10% → 85%, workspace: 85% → 92%
+                                                    let (pct_start, pct_end) = match transfer.phase {
+                                                        TransferPhase::ReceivingContainer => (10u8, 85u8),
+                                                        TransferPhase::ReceivingWorkspace => (85u8, 92u8),
+                                                    };
+                                                    let ratio = (bytes_received as f64 / transfer.total_bytes as f64).min(1.0);
+                                                    let pct = pct_start + (ratio * (pct_end - pct_start) as f64) as u8;
+                                                    crate::routes::applications::update_migration_phase(
+                                                        &state.migrations,
+                                                        &state.events,
+                                                        &transfer.app_id,
+                                                        &transfer.transfer_id,
+                                                        hr_common::events::MigrationPhase::Importing,
+                                                        pct,
+                                                        bytes_received,
+                                                        transfer.total_bytes,
+                                                        None,
+                                                    ).await;
+                                                }
+                                            }
                                         }
                                     }
-                                    // Store metadata; the next Binary frame carries the actual data
-                                    pending_binary_meta = Some((transfer_id, sequence, checksum));
                                 }
                                 HostAgentMessage::TransferComplete { transfer_id } => {
-                                    if relay_transfers.remove(&transfer_id) {
+                                    if state.backups.on_complete(&transfer_id).await {
+                                        tracing::info!(transfer_id = %transfer_id, "Backup export received");
+                                    } else if relay_transfers.remove(&transfer_id).is_some() {
                                         // Relay mode: forward TransferComplete to target host
                                         tracing::info!(transfer_id = %transfer_id, "Relaying TransferComplete to target host");
                                         if let Some((target_host_id, _)) = registry.get_transfer_relay_target(&transfer_id).await {
@@ -1257,14 +2195,17 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
                                         }
                                         // Clean up relay target (import result will come from target host)
                                         registry.take_transfer_relay_target(&transfer_id).await;
+                                    } else if active_transfers.get(&transfer_id).is_some_and(|t| t.resume_requested) {
+                                        // The end of the stream is missing: finalize after the replay
+                                        tracing::debug!(transfer_id = %transfer_id, "TransferComplete while resuming, ignored");
                                     } else if let Some(mut transfer) = active_transfers.remove(&transfer_id) {
                                         // Local nspawn import: finalize
                                         use tokio::io::AsyncWriteExt;
-                                        let _ = transfer.file.flush().await;
+                                        let _ = transfer.file.shutdown().await;
                                         drop(transfer.file);
                                         let has_workspace = transfer.phase == TransferPhase::ReceivingWorkspace;
                                         if let Some(mut ws_file) = transfer.workspace_file.take() {
-                                            let _ = ws_file.flush().await;
+                                            let _ = ws_file.shutdown().await;
                                             drop(ws_file);
                                         }
                                         let tid = transfer_id.clone();
@@ -1295,7 +2236,9 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
                                     let _ = registry.request_power_action(&host_id, action).await;
                                 }
                                 HostAgentMessage::WorkspaceReady { transfer_id, size_bytes } => {
-                                    if relay_transfers.contains(&transfer_id) {
+                                    if state.backups.on_workspace_ready(&transfer_id).await {
+                                        tracing::info!(transfer_id = %transfer_id, size_bytes, "Receiving workspace for backup");
+                                    } else if relay_transfers.contains_key(&transfer_id) {
                                         // Relay mode: forward WorkspaceReady to target host
                                         tracing::info!(transfer_id = %transfer_id, size_bytes, "Relaying WorkspaceReady to target host");
                                         if let Some((target_host_id, _)) = registry.get_transfer_relay_target(&transfer_id).await {
@@ -1307,18 +2250,23 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
                                                 },
                                             ).await;
                                         }
-                                    } else if let Some(transfer) = active_transfers.get_mut(&transfer_id) {
+                                    } else if let Some(transfer) = active_transfers.get_mut(&transfer_id)
+                                        && transfer.phase == TransferPhase::ReceivingContainer
+                                        && !transfer.resume_requested
+                                    {
                                         // Local import: transition to workspace phase
                                         tracing::info!(transfer_id = %transfer_id, size_bytes, "Receiving workspace for local import");
                                         let ws_path = format!("/tmp/{}-workspace.tar.gz", transfer_id);
                                         match tokio::fs::File::create(&ws_path).await {
                                             Ok(ws_file) => {
+                                                let (ws_file, bytes_received) = Counted::new(ws_file);
                                                 transfer.phase = TransferPhase::ReceivingWorkspace;
-                                                transfer.workspace_file = Some(ws_file);
+                                                transfer.workspace_file = Some(transfer.compression.decoder(ws_file));
                                                 // Reset byte counters for workspace phase
                                                 transfer.total_bytes = size_bytes;
-                                                transfer.bytes_received = 0;
+                                                transfer.bytes_received = bytes_received;
                                                 transfer.chunk_count = 0;
+                                                transfer.manifest = StreamManifest::default();
                                             }
                                             Err(e) => {
                                                 tracing::error!(transfer_id = %transfer_id, %e, "Failed to create workspace file for local import");
@@ -1326,6 +2274,68 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
                                         }
                                     }
                                 }
+                                HostAgentMessage::TransferManifest { transfer_id, stream, chunk_count, total_bytes, digest } => {
+                                    if state.backups.on_manifest(&registry, &transfer_id, stream, chunk_count, total_bytes, digest).await {
+                                        // Backup export, checked by its receiver
+                                    } else if relay_transfers.contains_key(&transfer_id) {
+                                        // Relay mode: the target checks the manifest
+                                        if let Some((target_host_id, _)) = registry.get_transfer_relay_target(&transfer_id).await {
+                                            let _ = registry.send_host_command(
+                                                &target_host_id,
+                                                HostRegistryMessage::TransferManifest { transfer_id, stream, chunk_count, total_bytes, digest },
+                                            ).await;
+                                        }
+                                    } else if let Some(transfer) = active_transfers.get_mut(&transfer_id)
+                                        && transfer.stream() == stream
+                                        && transfer.manifest.next_sequence() < chunk_count
+                                    {
+                                        // The last chunks were lost: ask for them
+                                        if !transfer.resume_requested {
+                                            transfer.resume_requested = true;
+                                            let _ = registry.send_host_command(
+                                                &host_id,
+                                                HostRegistryMessage::ResumeTransfer {
+                                                    transfer_id: transfer_id.clone(),
+                                                    point: transfer.manifest.resume_point(stream),
+                                                },
+                                            ).await;
+                                        }
+                                    } else if let Some(transfer) = active_transfers.get(&transfer_id)
+                                        && transfer.stream() == stream
+                                        && let Err(e) = transfer.manifest.verify(chunk_count, total_bytes, digest)
+                                    {
+                                        tracing::error!(transfer_id = %transfer_id, ?stream, %e, "Transfer manifest mismatch");
+                                        active_transfers.remove(&transfer_id);
+                                        let _ = tokio::fs::remove_file(format!("/tmp/{}.tar.gz", transfer_id)).await;
+                                        let _ = tokio::fs::remove_file(format!("/tmp/{}-workspace.tar.gz", transfer_id)).await;
+                                        registry.on_host_import_failed(&host_id, &transfer_id, &e).await;
+                                    }
+                                }
+                                HostAgentMessage::ResumeTransfer { transfer_id, point } => {
+                                    // This host receives the transfer and lost chunks
+                                    if registry.on_transfer_resume(&transfer_id, point).await {
+                                        tracing::info!(transfer_id = %transfer_id, ?point, "Host asked to resume transfer");
+                                    } else if let Some((target_host_id, _)) = registry.get_transfer_relay_target(&transfer_id).await
+                                        && target_host_id == host_id
+                                    {
+                                        // Relay mode: the source replays the stream
+                                        let source = state.migrations.read().await.get(&transfer_id).map(|m| m.source_host_id.clone());
+                                        if let Some(source_host_id) = source {
+                                            tracing::info!(transfer_id = %transfer_id, ?point, source = %source_host_id, "Relaying resume request to source host");
+                                            let _ = registry.send_host_command(
+                                                &source_host_id,
+                                                HostRegistryMessage::ResumeTransfer { transfer_id, point },
+                                            ).await;
+                                        }
+                                    } else {
+                                        // Leftover of a finished or failed migration
+                                        tracing::info!(transfer_id = %transfer_id, "Unknown transfer to resume, cancelling it");
+                                        let _ = registry.send_host_command(
+                                            &host_id,
+                                            HostRegistryMessage::CancelTransfer { transfer_id },
+                                        ).await;
+                                    }
+                                }
                                 HostAgentMessage::TerminalData { session_id, data } => {
                                     registry.send_terminal_data(&session_id, data).await;
                                 }
@@ -1337,69 +2347,26 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
                                     // Send empty data to signal close to the API WS handler
                                     registry.send_terminal_data(&session_id, Vec::new()).await;
                                 }
-                                HostAgentMessage::Auth { .. } => {}
+                                HostAgentMessage::MigrationPreflight { request_id, report } => {
+                                    registry.on_host_preflight(&request_id, report).await;
+                                }
+                                HostAgentMessage::Auth { .. } | HostAgentMessage::Enroll { .. } => {}
                                 HostAgentMessage::NspawnContainerList(_) => {
                                     // TODO: track nspawn containers separately if needed
                                 }
-                            }
-                        }
-                    }
-                    Some(Ok(Message::Binary(data))) => {
-                        // Binary frame following a TransferChunkBinary metadata message
-                        timeout_sleep.as_mut().reset(tokio::time::Instant::now() + heartbeat_timeout);
-                        if let Some((transfer_id, _sequence, _checksum)) = pending_binary_meta.take() {
-                            if relay_transfers.contains(&transfer_id) {
-                                // Relay mode: forward binary data to target host
-                                if let Some((target_host_id, _)) = registry.get_transfer_relay_target(&transfer_id).await {
-                                    if let Err(e) = registry.send_host_binary(&target_host_id, data.to_vec()).await {
-                                        tracing::error!(transfer_id = %transfer_id, %e, "Failed to relay binary chunk to target");
-                                        relay_transfers.remove(&transfer_id);
-                                        registry.take_transfer_relay_target(&transfer_id).await;
-                                        registry.on_host_import_failed(&host_id, &transfer_id, &format!("Relay binary send failed: {e}")).await;
-                                    }
+                                HostAgentMessage::ContainerUsage(usage) => {
+                                    registry.update_host_usage(&host_id, usage).await;
                                 }
-                            } else if let Some(transfer) = active_transfers.get_mut(&transfer_id) {
-                                // Local import mode: write binary data to file
-                                use tokio::io::AsyncWriteExt;
-                                let data_len = data.len() as u64;
-                                let target_file = match transfer.phase {
-                                    TransferPhase::ReceivingWorkspace => transfer.workspace_file.as_mut(),
-                                    _ => Some(&mut transfer.file),
-                                };
-                                if let Some(file) = target_file {
-                                    if let Err(e) = file.write_all(&data).await {
-                                        tracing::error!(transfer_id = %transfer_id, %e, "Failed to write binary chunk to local file");
-                                        active_transfers.remove(&transfer_id);
-                                        registry.on_host_import_failed(&host_id, &transfer_id, &format!("File write error: {e}")).await;
-                                    } else {
-                                        // Update progress tracking
-                                        transfer.bytes_received += data_len;
-                                        transfer.chunk_count += 1;
-                                        if transfer.chunk_count % 4 == 0 && transfer.total_bytes > 0 && !transfer.app_id.is_empty() {
-                                            // Container data: 10% → 85%, workspace: 85% → 92%
-                                            let (pct_start, pct_end) = match transfer.phase {
-                                                TransferPhase::ReceivingContainer => (10u8, 85u8),
-                                                TransferPhase::ReceivingWorkspace => (85u8, 92u8),
-                                            };
-                                            let ratio = (transfer.bytes_received as f64 / transfer.total_bytes as f64).min(1.0);
-                                            let pct = pct_start + (ratio * (pct_end - pct_start) as f64) as u8;
-                                            crate::routes::applications::update_migration_phase(
-                                                &state.migrations,
-                                                &state.events,
-                                                &transfer.app_id,
-                                                &transfer.transfer_id,
-                                                hr_common::events::MigrationPhase::Importing,
-                                                pct,
-                                                transfer.bytes_received,
-                                                transfer.total_bytes,
-                                                None,
-                                            ).await;
-                                        }
-                                    }
+                                HostAgentMessage::RuntimeContainers(containers) => {
+                                    registry.update_host_runtime_containers(&host_id, containers).await;
+                                }
+                                HostAgentMessage::LogStreamData { request_id, lines } => {
+                                    registry.on_log_stream_data(&request_id, lines).await;
+                                }
+                                HostAgentMessage::LogStreamEnd { request_id, error } => {
+                                    registry.on_log_stream_end(&request_id, error).await;
                                 }
                             }
-                        } else {
-                            tracing::warn!("Received Binary frame without pending TransferChunkBinary metadata");
                         }
                     }
                     Some(Ok(Message::Ping(data))) => {
@@ -1415,10 +2382,34 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
     }
 
     // Clean up any pending relay transfers
-    for tid in relay_transfers {
+    for tid in relay_transfers.into_keys() {
         registry.take_transfer_relay_target(&tid).await;
     }
 
+    // Keep local imports until the host comes back to resume them
+    if resumable && !active_transfers.is_empty() {
+        for (transfer_id, transfer) in &active_transfers {
+            let progress = state.migrations.read().await.get(transfer_id)
+                .map(|m| (m.progress_pct, m.bytes_transferred, m.total_bytes));
+            if let Some((pct, transferred, total)) = progress {
+                crate::routes::applications::update_migration_phase(
+                    &state.migrations,
+                    &state.events,
+                    &transfer.app_id,
+                    transfer_id,
+                    MigrationPhase::Resuming,
+                    pct,
+                    transferred,
+                    total,
+                    None,
+                ).await;
+            }
+        }
+        state.interrupted_transfers.lock().await.insert(host_id.clone(), active_transfers);
+    }
+
+    crate::host_updates::on_host_disconnected(&state, &host_id).await;
+
     // Mark host offline
     update_host_status(&host_id, "offline", &state.events.host_status).await;
 
@@ -1426,6 +2417,23 @@ async fn handle_host_agent_socket(mut socket: WebSocket, state: ApiState) {
     tracing::info!("Host agent disconnected: {} ({})", host_name, host_id);
 }
 
+/// Encode a message for a host agent: one MessagePack frame with
+/// `binary_frames`, JSON text (followed by the raw chunk data) otherwise.
+fn host_ws_messages(msg: hr_registry::OutgoingHostMessage, binary_frames: bool) -> Result<Vec<Message>, String> {
+    use hr_registry::OutgoingHostMessage;
+    Ok(match msg {
+        OutgoingHostMessage::Text(m) if binary_frames => vec![Message::Binary(hr_registry::codec::encode(&m, &[])?.into())],
+        OutgoingHostMessage::Chunk(m, data) if binary_frames => vec![Message::Binary(hr_registry::codec::encode(&m, &data)?.into())],
+        OutgoingHostMessage::Text(m) => {
+            vec![Message::Text(serde_json::to_string(&m).map_err(|e| e.to_string())?.into())]
+        }
+        OutgoingHostMessage::Chunk(m, data) => vec![
+            Message::Text(serde_json::to_string(&m).map_err(|e| e.to_string())?.into()),
+            Message::Binary(data.into()),
+        ],
+        OutgoingHostMessage::Close(_) => vec![Message::Close(None)],
+    })
+}
 
 // ── Local nspawn import for remote→local migration ─────────────────────
 
@@ -1465,7 +2473,7 @@ async fn handle_local_nspawn_import(
     let ws_dir = format!("{}/{}-workspace", storage_path, container_name);
 
     // Create rootfs directory
-    if let Err(e) = tokio::fs::create_dir_all(&rootfs_dir).await {
+    if let Err(e) = hr_container::limits::create_rootfs_dir(std::path::Path::new(&rootfs_dir)).await {
         tracing::error!(transfer_id = %transfer_id, %e, "Failed to create rootfs directory");
         registry.on_host_import_failed(&source_host_id, &transfer_id, &format!("Failed to create rootfs dir: {e}")).await;
         let _ = tokio::fs::remove_file(&import_path).await;
@@ -1598,9 +2606,113 @@ async fn update_host_last_seen(host_id: &str) {
     }
 }
 
+/// Store a SMART report and raise an event for each disk that got worse
+/// since the previous one.
+async fn record_disk_health(state: &ApiState, host_id: &str, disks: Vec<DiskHealth>) {
+    let mut data = load_hosts().await;
+    let Some(host) = find_host_mut(&mut data, host_id) else {
+        return;
+    };
+    let previous: Vec<DiskHealth> = host
+        .get("disks")
+        .and_then(|d| serde_json::from_value(d.clone()).ok())
+        .unwrap_or_default();
+    let host_name = host.get("name").and_then(|n| n.as_str()).unwrap_or(host_id).to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+    for disk in &disks {
+        // Devices get renamed across reboots: match on the serial first
+        let before = previous.iter().find(|p| match (&p.serial, &disk.serial) {
+            (Some(a), Some(b)) => a == b,
+            _ => p.device == disk.device,
+        });
+        let reasons = disk.degradation(before);
+        if reasons.is_empty() {
+            continue;
+        }
+        tracing::warn!(host_id = %host_id, device = %disk.device, ?reasons, "Host disk degraded");
+        let _ = state.events.disk_health.send(hr_common::events::DiskHealthEvent {
+            host_id: host_id.to_string(),
+            host_name: host_name.clone(),
+            device: disk.device.clone(),
+            model: disk.model.clone(),
+            serial: disk.serial.clone(),
+            passed: disk.passed,
+            reasons,
+            at: now.clone(),
+        });
+    }
+    host["disks"] = json!(disks);
+    host["disksUpdatedAt"] = json!(now);
+    let _ = save_hosts(&data).await;
+}
+
 // ── Helpers ──────────────────────────────────────────────────────────────
 
-fn find_host<'a>(data: &'a Value, id: &str) -> Option<&'a Value> {
+/// A host as returned by the API: without its credentials.
+fn redact_host(mut host: Value) -> Value {
+    if let Some(obj) = host.as_object_mut() {
+        let revoked = obj.get("token_revoked").and_then(|v| v.as_bool()).unwrap_or(false);
+        let has_token = obj.contains_key("token_hash");
+        for field in CREDENTIAL_FIELDS {
+            obj.remove(*field);
+        }
+        let credentials = if revoked { "revoked" } else if has_token { "token" } else { "none" };
+        obj.insert("credentials".to_string(), json!(credentials));
+    }
+    host
+}
+
+/// Check the token of a connecting host agent against its hash (or the
+/// previous one during a rotation's grace window).
+///
+/// Hosts enrolled before per-host tokens have none. The first agent that
+/// connects under such a host's name and can store a token is issued one
+/// (trust on first use): whoever connects first owns the host, so upgrade
+/// these agents from a trusted network, or rotate the token afterwards. An
+/// agent that cannot store a token is refused.
+async fn check_host_token(id: &str, token: &str, can_rotate: bool) -> Result<Option<String>, String> {
+    let mut data = load_hosts().await;
+    let host = find_host_mut(&mut data, id).ok_or("Unknown host")?;
+    if host.get("token_revoked").and_then(|v| v.as_bool()).unwrap_or(false) {
+        return Err("Token revoked".to_string());
+    }
+    let Some(hash) = host.get("token_hash").and_then(|v| v.as_str()).map(str::to_string) else {
+        if !can_rotate {
+            return Err("No token on record and the agent cannot store one, upgrade it".to_string());
+        }
+        let token = hr_registry::generate_token();
+        host["token_hash"] = json!(hr_registry::hash_token(&token).map_err(|e| e.to_string())?);
+        save_hosts(&data).await?;
+        tracing::warn!(host_id = id, "Issued a first token to the host agent connecting under this name (trust on first use)");
+        return Ok(Some(token));
+    };
+
+    if hr_registry::verify_token(token, &hash) {
+        // The agent took the new token: the rotation is over
+        if let Some(obj) = host.as_object_mut()
+            && obj.remove("previous_token_hash").is_some()
+        {
+            obj.remove("previous_token_expires");
+            save_hosts(&data).await?;
+        }
+        return Ok(None);
+    }
+    let in_grace = host
+        .get("previous_token_expires")
+        .and_then(|v| v.as_str())
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .is_some_and(|t| t > chrono::Utc::now());
+    if in_grace
+        && let Some(previous) = host.get("previous_token_hash").and_then(|v| v.as_str())
+        && hr_registry::verify_token(token, previous)
+    {
+        tracing::warn!(host_id = id, "Host agent authenticated with its previous token");
+        return Ok(None);
+    }
+    Err("Invalid token".to_string())
+}
+
+pub(crate) fn find_host<'a>(data: &'a Value, id: &str) -> Option<&'a Value> {
     data.get("hosts")?
         .as_array()?
         .iter()
@@ -1700,7 +2812,7 @@ async fn setup_ssh_key(host: &str, port: u16, user: &str, password: &str) -> Res
     Ok(())
 }
 
-async fn ssh_command(host: &str, port: u16, user: &str, command: &str) -> Result<String, String> {
+pub(crate) async fn ssh_command(host: &str, port: u16, user: &str, command: &str) -> Result<String, String> {
     let output = tokio::process::Command::new("ssh")
         .args([
             "-i", SSH_KEY_PATH,
@@ -1765,13 +2877,15 @@ async fn get_remote_interfaces(
 
 // ── Host-agent deployment ────────────────────────────────────────────────
 
-async fn deploy_host_agent(host: &str, port: u16, user: &str, password: Option<&str>, host_name: &str, lan_interface: Option<&str>) -> Result<(), String> {
-    if tokio::fs::metadata(HOST_AGENT_BINARY).await.is_err() {
-        return Err("hr-host-agent binary not found".to_string());
-    }
-
+async fn deploy_host_agent(host: &str, port: u16, user: &str, password: Option<&str>, host_name: &str, lan_interface: Option<&str>, token: &str) -> Result<(), String> {
     let password = password.ok_or("Password required for agent deployment")?;
 
+    let uname = ssh_command(host, port, user, "uname -m").await?;
+    let arch = normalize_arch(uname.trim()).ok_or_else(|| format!("Unsupported architecture: {}", uname.trim()))?;
+    let binary = BinaryStore::default()
+        .find("hr-host-agent", arch)
+        .ok_or_else(|| format!("hr-host-agent binary not found for {}", arch))?;
+
     // 1. SCP binary to /tmp/
     let scp_output = tokio::process::Command::new("scp")
         .args([
@@ -1779,7 +2893,7 @@ async fn deploy_host_agent(host: &str, port: u16, user: &str, password: Option<&
             "-o", "StrictHostKeyChecking=no",
             "-o", "ConnectTimeout=15",
             "-P", &port.to_string(),
-            HOST_AGENT_BINARY,
+            &binary.to_string_lossy(),
             &format!("{}@{}:/tmp/hr-host-agent", user, host),
         ])
         .output()
@@ -1798,13 +2912,31 @@ async fn deploy_host_agent(host: &str, port: u16, user: &str, password: Option<&
     };
     let config = format!(
         r#"homeroute_url = "{HOMEROUTE_LAN_IP}:{API_PORT}"
-token = ""
+token = "{token}"
 host_name = "{host_name}"
 {lan_line}container_storage_path = "/var/lib/machines"
 container_runtime = "nspawn"
-"#,
+{}"#,
+        update::config_line(),
     );
 
+    // Use a single sudo -S bash -c to run all commands with one password prompt
+    let inner_cmds = install_commands(&config, false);
+
+    // Escape single quotes in inner_cmds for shell wrapping
+    let escaped = inner_cmds.replace('\'', "'\\''");
+
+    let setup_cmd = format!("echo '{password}' | sudo -S bash -c '{escaped}'");
+
+    ssh_command(host, port, user, &setup_cmd).await?;
+
+    Ok(())
+}
+
+/// Shell commands installing the agent binary found at /tmp/hr-host-agent
+/// with `config`, as a systemd service. `expand_config` lets the shell
+/// substitute `$(...)` in the config.
+fn install_commands(config: &str, expand_config: bool) -> String {
     let service_unit = r#"[Unit]
 Description=HomeRoute Host Agent
 After=network.target
@@ -1818,27 +2950,18 @@ Environment=RUST_LOG=info
 [Install]
 WantedBy=multi-user.target
 "#;
-
-    // Use a single sudo -S bash -c to run all commands with one password prompt
-    let inner_cmds = format!(
+    let conf_marker = if expand_config { "CONF" } else { "'CONF'" };
+    format!(
         r#"mv /tmp/hr-host-agent /usr/local/bin/hr-host-agent && \
 chmod +x /usr/local/bin/hr-host-agent && \
 mkdir -p /etc/hr-host-agent && \
-cat > /etc/hr-host-agent/config.toml << 'CONF'
+cat > /etc/hr-host-agent/config.toml << {conf_marker}
 {config}CONF
+chmod 600 /etc/hr-host-agent/config.toml && \
 cat > /etc/systemd/system/hr-host-agent.service << 'SVC'
 {service_unit}SVC
 apt-get install -y systemd-container debootstrap && \
 systemctl daemon-reload && \
 systemctl enable --now hr-host-agent"#,
-    );
-
-    // Escape single quotes in inner_cmds for shell wrapping
-    let escaped = inner_cmds.replace('\'', "'\\''");
-
-    let setup_cmd = format!("echo '{password}' | sudo -S bash -c '{escaped}'");
-
-    ssh_command(host, port, user, &setup_cmd).await?;
-
-    Ok(())
+    )
 }
@@ -0,0 +1,150 @@
+//! Live logs of an application, under `/api/applications/{id}/logs/stream`.
+//!
+//! The WebSocket receives [`LogEvent`]s as JSON text frames: `lines`
+//! batches, a `dropped` count when the browser did not keep up, and an
+//! `error` before the close when the stream failed. Query: `unit`
+//! (`app.service`, whole journal when absent), `lines` of history and
+//! `follow` (default true).
+//!
+//! The app agent reads the journal from inside the container; when it is
+//! not connected (crashed app, older agent) the container journal is read
+//! from the host instead.
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, State};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use hr_registry::logs::{LogEvent, LogRequest, LogSink, LogSource, DEFAULT_TAIL_LINES};
+use hr_registry::protocol::capability;
+
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new().route("/{id}/logs/stream", get(logs_stream_ws))
+}
+
+#[derive(Deserialize)]
+struct LogStreamQuery {
+    #[serde(default)]
+    unit: Option<String>,
+    #[serde(default)]
+    lines: Option<u32>,
+    #[serde(default)]
+    follow: Option<bool>,
+}
+
+async fn logs_stream_ws(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Query(query): Query<LogStreamQuery>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| handle_logs_ws(state, id, query, socket))
+}
+
+/// Where the lines of a stream come from.
+enum Stream {
+    /// Served by an agent through the registry.
+    Remote(String),
+    /// `journalctl -M` run here for a container of this host.
+    Local(tokio::task::JoinHandle<()>),
+}
+
+async fn send_error(socket: &mut WebSocket, error: impl std::fmt::Display) {
+    let event = LogEvent::Error { error: error.to_string() };
+    let _ = socket.send(Message::Text(serde_json::to_string(&event).unwrap().into())).await;
+}
+
+async fn handle_logs_ws(state: ApiState, app_id: String, query: LogStreamQuery, mut socket: WebSocket) {
+    let Some(registry) = state.registry.clone() else {
+        send_error(&mut socket, "Registry not available").await;
+        let _ = socket.send(Message::Close(None)).await;
+        return;
+    };
+    let Some(app) = registry.get_application(&app_id).await else {
+        send_error(&mut socket, "Application not found").await;
+        let _ = socket.send(Message::Close(None)).await;
+        return;
+    };
+
+    let lines = query.lines.unwrap_or(DEFAULT_TAIL_LINES);
+    let follow = query.follow.unwrap_or(true);
+    let opened = if registry.agent_supports(&app_id, capability::LOG_STREAM).await {
+        let request = LogRequest { source: LogSource::Journal { unit: query.unit }, lines, follow };
+        registry
+            .open_agent_log_stream(&app_id, request)
+            .await
+            .map(|(request_id, rx)| (Stream::Remote(request_id), rx))
+            .map_err(|e| e.to_string())
+    } else {
+        let source = LogSource::Container { container_name: app.container_name.clone(), unit: query.unit };
+        let request = LogRequest { source, lines, follow };
+        if app.host_id == "local" {
+            Ok(open_local_stream(request))
+        } else {
+            registry
+                .open_host_log_stream(&app.host_id, request)
+                .await
+                .map(|(request_id, rx)| (Stream::Remote(request_id), rx))
+                .map_err(|e| e.to_string())
+        }
+    };
+    let (stream, mut rx) = match opened {
+        Ok(opened) => opened,
+        Err(e) => {
+            warn!(app_id, "Failed to open log stream: {e}");
+            send_error(&mut socket, format!("Failed to open log stream: {e}")).await;
+            let _ = socket.send(Message::Close(None)).await;
+            return;
+        }
+    };
+    info!(app_id, follow, "Log stream opened");
+
+    // Sending waits on the browser; meanwhile the queue fills and the
+    // registry drops what does not fit
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let Some(event) = event else { break };
+                let json = serde_json::to_string(&event).unwrap();
+                if socket.send(Message::Text(json.into())).await.is_err() {
+                    break;
+                }
+            }
+            ws_msg = socket.recv() => {
+                match ws_msg {
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    match stream {
+        Stream::Remote(request_id) => registry.close_log_stream(&request_id).await,
+        // Aborting drops journalctl, killed on drop
+        Stream::Local(task) => task.abort(),
+    }
+    let _ = socket.send(Message::Close(None)).await;
+    info!(app_id, "Log stream closed");
+}
+
+/// Stream the journal of a local container through the same bounded
+/// queue as remote streams.
+fn open_local_stream(request: LogRequest) -> (Stream, mpsc::Receiver<LogEvent>) {
+    let (mut sink, rx) = LogSink::new();
+    let task = tokio::spawn(async move {
+        let result = hr_registry::logs::stream_journal(&request, |lines| {
+            let alive = sink.push(lines);
+            async move { alive }
+        })
+        .await;
+        sink.end(result.err());
+    });
+    (Stream::Local(task), rx)
+}
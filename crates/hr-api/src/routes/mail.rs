@@ -0,0 +1,140 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use hr_mail::message::Outgoing;
+use hr_mail::{AppAuth, LogQuery, MailConfig, SubmitError};
+use serde_json::{json, Value};
+
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/", get(get_mail).put(update_mail))
+        .route("/log", get(delivery_log))
+        .route("/log/{id}/retry", post(retry_message))
+        .route("/send", post(send_message))
+}
+
+async fn get_mail(State(state): State<ApiState>) -> Json<Value> {
+    let mut config = state.mail.config();
+    let has_password = !config.smarthost.password.is_empty();
+    config.smarthost.password.clear();
+
+    let store = state.mail.store();
+    let stats = tokio::task::spawn_blocking(move || {
+        let counts = store.counts().ok();
+        let usage = store.usage(chrono::Utc::now().timestamp_millis()).ok();
+        (counts, usage)
+    })
+    .await
+    .ok();
+    let (counts, usage) = stats.unwrap_or_default();
+
+    Json(json!({
+        "success": true,
+        "config": config,
+        "hasPassword": has_password,
+        "domain": state.mail.domain(),
+        "status": state.mail.status(),
+        "dkim": state.mail.dkim_record(),
+        "queued": counts.map(|c| c.0),
+        "failed": counts.map(|c| c.1),
+        "usage": usage,
+    }))
+}
+
+/// Validate, persist to mail-config.json, then apply (the submission
+/// listener is rebound). An empty smarthost password keeps the stored one.
+async fn update_mail(
+    State(state): State<ApiState>,
+    Json(mut config): Json<MailConfig>,
+) -> Json<Value> {
+    if config.smarthost.password.is_empty() {
+        config.smarthost.password = state.mail.config().smarthost.password;
+    }
+    if let Err(e) = config.validate() {
+        return Json(json!({"success": false, "error": e}));
+    }
+    let path = state.env.mail_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Json(json!({"success": false, "error": format!("Write failed: {}", e)})),
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    }
+    state.mail.apply(config);
+    Json(json!({"success": true, "dkim": state.mail.dkim_record()}))
+}
+
+async fn delivery_log(
+    State(state): State<ApiState>,
+    Query(query): Query<LogQuery>,
+) -> Json<Value> {
+    let store = state.mail.store();
+    match tokio::task::spawn_blocking(move || store.log(&query)).await {
+        Ok(Ok(messages)) => Json(json!({"success": true, "messages": messages})),
+        Ok(Err(e)) => Json(json!({"success": false, "error": e.to_string()})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
+
+async fn retry_message(State(state): State<ApiState>, Path(id): Path<i64>) -> Json<Value> {
+    let mail = state.mail.clone();
+    match tokio::task::spawn_blocking(move || mail.retry(id)).await {
+        Ok(Ok(true)) => Json(json!({"success": true})),
+        Ok(Ok(false)) => Json(json!({"success": false, "error": "Message not failed or no longer stored"})),
+        Ok(Err(e)) => Json(json!({"success": false, "error": e.to_string()})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
+
+/// POST /api/mail/send
+/// Auth via `Authorization: Bearer {agent_token}`; the message is sent as
+/// the app owning the token, within its quotas.
+async fn send_message(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Json(outgoing): Json<Outgoing>,
+) -> Response {
+    let Some(registry) = &state.registry else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"error": "Registry not available"}))).into_response();
+    };
+    let Some(token) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    else {
+        return (StatusCode::UNAUTHORIZED, Json(json!({"error": "Missing or invalid Authorization header"}))).into_response();
+    };
+    let Some(app) = registry.app_for(None, token).await else {
+        return (StatusCode::UNAUTHORIZED, Json(json!({"error": "Invalid token"}))).into_response();
+    };
+
+    let built = match outgoing.build(&app, &state.mail.domain(), chrono::Utc::now()) {
+        Ok(built) => built,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))).into_response(),
+    };
+    let mail = state.mail.clone();
+    let submitted = tokio::task::spawn_blocking(move || {
+        mail.submit(&app, &built.sender, &built.recipients, &built.data)
+    })
+    .await;
+    match submitted {
+        Ok(Ok(id)) => (StatusCode::ACCEPTED, Json(json!({"success": true, "id": id}))).into_response(),
+        Ok(Err(e)) => {
+            let status = match e {
+                SubmitError::Disabled | SubmitError::Storage(_) => StatusCode::SERVICE_UNAVAILABLE,
+                SubmitError::Sender(_) => StatusCode::FORBIDDEN,
+                SubmitError::Recipient(_) => StatusCode::BAD_REQUEST,
+                SubmitError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+                SubmitError::Quota { .. } => StatusCode::TOO_MANY_REQUESTS,
+            };
+            (status, Json(json!({"error": e.to_string()}))).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))).into_response(),
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::atomic::Ordering;
+
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+use hr_common::{metrics, selfmon};
+
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new().route("/metrics", get(get_metrics))
+}
+
+/// Prometheus scrape endpoint. Counters and histograms are updated by the
+/// services as they run; point-in-time gauges are refreshed here.
+async fn get_metrics(State(state): State<ApiState>) -> impl IntoResponse {
+    refresh_gauges(&state).await;
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        metrics::registry().render(),
+    )
+}
+
+/// Update the gauges read from service state rather than maintained live.
+pub(crate) async fn refresh_gauges(state: &ApiState) {
+    let registry = metrics::registry();
+
+    selfmon::refresh();
+    for (channel, queued) in state.events.backlog() {
+        registry
+            .gauge("homeroute_event_backlog", "Events queued in a bus channel, by channel.", &[("channel", channel)])
+            .set(queued as f64);
+    }
+
+    {
+        let dns = state.dns.read().await;
+        let queries = dns.stats.queries.load(Ordering::Relaxed);
+        let cached = dns.stats.cached.load(Ordering::Relaxed);
+        let ratio = if queries > 0 { cached as f64 / queries as f64 } else { 0.0 };
+        registry
+            .gauge("homeroute_dns_cache_hit_ratio", "Share of DNS queries answered from cache since start.", &[])
+            .set(ratio);
+        registry
+            .gauge("homeroute_dns_cache_entries", "Entries in the DNS cache.", &[])
+            .set(dns.dns_cache.len().await as f64);
+    }
+
+    {
+        let dhcp = state.dhcp.read().await;
+        let (size, used) = dhcp.pool_usage();
+        registry
+            .gauge("homeroute_dhcp_pool_size", "Addresses in the DHCP dynamic range.", &[])
+            .set(size as f64);
+        registry
+            .gauge("homeroute_dhcp_pool_used", "Addresses of the DHCP dynamic range currently leased.", &[])
+            .set(used as f64);
+        registry
+            .gauge("homeroute_dhcp_leases", "Active DHCP leases.", &[])
+            .set(dhcp.lease_store.all_leases().iter().filter(|l| dhcp.lease_store.is_ip_in_use(l.ip)).count() as f64);
+    }
+
+    // Containers: usage is 0 when stopped, limits are 0 when unlimited
+    if let Some(cm) = &state.container_manager {
+        for record in cm.records().await {
+            let usage = cm.usage(&record).await.unwrap_or_default();
+            let labels = [("container", record.container_name.as_str()), ("host", record.host_id.as_str())];
+            registry
+                .gauge("homeroute_container_memory_bytes", "Memory used by an app container.", &labels)
+                .set(usage.memory_bytes as f64);
+            registry
+                .gauge("homeroute_container_memory_limit_bytes", "Memory limit of an app container.", &labels)
+                .set(record.limits.memory_bytes.unwrap_or(0) as f64);
+            registry
+                .gauge("homeroute_container_cpu_seconds", "CPU time used by an app container since it started.", &labels)
+                .set(usage.cpu_usage_usec as f64 / 1e6);
+            registry
+                .gauge("homeroute_container_cpu_quota_percent", "CPU quota of an app container, in percent of one core.", &labels)
+                .set(record.limits.cpu_percent.unwrap_or(0) as f64);
+            registry
+                .gauge("homeroute_container_disk_bytes", "Disk used by an app container rootfs (btrfs quotas only).", &labels)
+                .set(usage.disk_bytes.unwrap_or(0) as f64);
+            registry
+                .gauge("homeroute_container_disk_limit_bytes", "Disk quota of an app container rootfs.", &labels)
+                .set(record.limits.disk_bytes.unwrap_or(0) as f64);
+        }
+    }
+}
@@ -1,6 +1,9 @@
 pub mod health;
+pub mod openapi;
 pub mod auth;
 pub mod users;
+pub mod config;
+pub mod declarative;
 pub mod dns_dhcp;
 pub mod dns;
 pub mod adblock;
@@ -16,7 +19,31 @@ pub mod services;
 pub mod ws;
 
 pub mod applications;
+pub mod backups;
 pub mod containers;
+pub mod resources;
+pub mod logs;
+pub mod templates;
 pub mod dataverse;
 pub mod cloud_relay;
 pub mod store;
+pub mod streams;
+pub mod plugins;
+pub mod processes;
+pub mod mqtt;
+pub mod reflector;
+pub mod syslog;
+pub mod radius;
+pub mod network;
+pub mod capture;
+pub mod usage;
+pub mod firewall;
+pub mod mail;
+pub mod storage;
+pub mod cron;
+pub mod queues;
+pub mod pubsub;
+pub mod ai;
+pub mod history;
+pub mod metrics;
+pub mod system;
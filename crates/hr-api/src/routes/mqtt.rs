@@ -0,0 +1,49 @@
+use axum::{extract::State, routing::get, Json, Router};
+use serde_json::{json, Value};
+
+use crate::mqtt::MqttConfig;
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new().route("/", get(get_mqtt).put(update_mqtt))
+}
+
+/// Config without the broker password (only whether one is set).
+fn config_json(config: &MqttConfig) -> Value {
+    let mut value = serde_json::to_value(config).unwrap_or(json!({}));
+    value["password"] = Value::Null;
+    value["hasPassword"] = json!(config.password.as_deref().is_some_and(|p| !p.is_empty()));
+    value
+}
+
+async fn get_mqtt(State(state): State<ApiState>) -> Json<Value> {
+    Json(json!({
+        "success": true,
+        "config": config_json(&state.mqtt.config()),
+        "status": state.mqtt.status(),
+    }))
+}
+
+async fn update_mqtt(
+    State(state): State<ApiState>,
+    Json(mut config): Json<MqttConfig>,
+) -> Json<Value> {
+    if let Err(e) = config.validate() {
+        return Json(json!({"success": false, "error": e}));
+    }
+    // An omitted password keeps the stored one
+    if config.password.is_none() {
+        config.password = state.mqtt.config().password;
+    }
+
+    let path = state.env.mqtt_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Json(json!({"success": false, "error": format!("Write failed: {}", e)})),
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    }
+
+    state.mqtt.apply(config.clone());
+    Json(json!({"success": true, "config": config_json(&config)}))
+}
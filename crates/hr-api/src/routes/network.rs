@@ -0,0 +1,466 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use hr_ntp::NtpConfig;
+use hr_qos::QosConfig;
+use hr_scanner::ScannerConfig;
+use hr_tailscale::TailscaleConfig;
+use hr_tunnel::certs::RelayPins;
+use hr_tunnel::usage::RelayUsageConfig;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/time", get(get_time).put(update_time))
+        .route("/tailscale", get(get_tailscale).put(update_tailscale))
+        .route("/qos", get(get_qos).put(update_qos))
+        .route("/relay/usage", get(get_relay_usage).delete(reset_relay_usage))
+        .route("/relay/usage/config", put(update_relay_usage_config))
+        .route("/relay/certs", get(get_relay_certs))
+        .route("/relay/certs/pins", put(update_relay_pins))
+        .route("/relay/certs/renew", post(renew_relay_client_cert))
+        .route("/relay/provision", post(super::cloud_relay::provision_relay))
+        .route("/discovery", get(get_discovery))
+        .route("/discovery/{id}/route", post(create_discovery_route))
+        .route("/devices", get(get_devices))
+        .route("/devices/config", put(update_devices_config))
+        .route("/devices/scan", post(scan_devices))
+        .route("/devices/{mac}", delete(forget_device))
+        .route("/devices/{mac}/wake", post(wake_device))
+        .route("/doctor", get(get_doctor).post(run_doctor))
+        .route("/doctor/config", put(update_doctor_config))
+        .route("/speedtest", get(get_speedtest).post(run_speedtest).delete(clear_speedtest))
+        .route("/speedtest/config", put(update_speedtest_config))
+        .route("/capture", get(super::capture::list_captures).post(super::capture::start_capture))
+        .route("/capture/{id}", get(super::capture::get_capture).delete(super::capture::delete_capture))
+        .route("/capture/{id}/stop", post(super::capture::stop_capture))
+        .route("/capture/{id}/pcap", get(super::capture::download_capture))
+}
+
+/// NTP server status (stratum, offset, upstreams) and the NTP servers the
+/// DHCP server advertises (option 42).
+async fn get_time(State(state): State<ApiState>) -> Json<Value> {
+    let dhcp_ntp_servers = state.dhcp.read().await.config.ntp_servers.clone();
+    Json(json!({
+        "success": true,
+        "config": state.ntp.config(),
+        "status": state.ntp.status(),
+        "dhcpNtpServers": dhcp_ntp_servers,
+    }))
+}
+
+/// Validate, persist to ntp-config.json, then hand the new config to the
+/// running server (upstreams are polled again without a restart).
+async fn update_time(
+    State(state): State<ApiState>,
+    Json(config): Json<NtpConfig>,
+) -> Json<Value> {
+    if let Err(e) = config.validate() {
+        return Json(json!({"success": false, "error": e}));
+    }
+    let path = state.env.ntp_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Json(json!({"success": false, "error": format!("Write failed: {}", e)})),
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    }
+    state.ntp.apply(config.clone());
+    Json(json!({"success": true, "config": config}))
+}
+
+/// Tailscale node state (backend state, tailnet addresses, approved and
+/// pending subnet routes) and the integration settings, auth key withheld.
+async fn get_tailscale(State(state): State<ApiState>) -> Json<Value> {
+    let mut config = state.tailscale.config();
+    let has_auth_key = !config.auth_key.is_empty();
+    config.auth_key.clear();
+    Json(json!({
+        "success": true,
+        "config": config,
+        "hasAuthKey": has_auth_key,
+        "status": state.tailscale.status(),
+    }))
+}
+
+/// Validate, persist to tailscale-config.json, then apply: the proxy takes
+/// the new VPN ranges and a managed node is reconfigured. An empty auth key
+/// keeps the stored one.
+async fn update_tailscale(
+    State(state): State<ApiState>,
+    Json(mut config): Json<TailscaleConfig>,
+) -> Json<Value> {
+    if config.auth_key.is_empty() {
+        config.auth_key = state.tailscale.config().auth_key;
+    }
+    if let Err(e) = config.validate() {
+        return Json(json!({"success": false, "error": e}));
+    }
+    let path = state.env.tailscale_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Json(json!({"success": false, "error": format!("Write failed: {}", e)})),
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    }
+    state.proxy.set_vpn_ranges(config.vpn_networks());
+    state.tailscale.apply(config);
+    Json(json!({"success": true}))
+}
+
+/// Shaping settings and state, with the current DHCP leases to pick the
+/// devices from.
+async fn get_qos(State(state): State<ApiState>) -> Json<Value> {
+    let leases: Vec<Value> = {
+        let dhcp = state.dhcp.read().await;
+        dhcp.lease_store
+            .all_leases()
+            .iter()
+            .map(|l| json!({"mac": l.mac, "ip": l.ip.to_string(), "hostname": l.hostname}))
+            .collect()
+    };
+    Json(json!({
+        "success": true,
+        "config": state.qos.config(),
+        "status": state.qos.status(),
+        "leases": leases,
+    }))
+}
+
+/// Validate, persist to qos-config.json, then apply: the shaper is rebuilt
+/// (or removed when disabled).
+async fn update_qos(
+    State(state): State<ApiState>,
+    Json(mut config): Json<QosConfig>,
+) -> Json<Value> {
+    config.normalize();
+    if let Err(e) = config.validate() {
+        return Json(json!({"success": false, "error": e}));
+    }
+    let path = state.env.qos_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Json(json!({"success": false, "error": format!("Write failed: {}", e)})),
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    }
+    state.qos.apply(config.clone());
+    Json(json!({"success": true, "config": config}))
+}
+
+/// Cloud relay traffic of the month, per route (SNI or `udp:<port>`).
+async fn get_relay_usage(State(state): State<ApiState>) -> Json<Value> {
+    let config = state.relay_usage.config();
+    let usage = state.relay_usage.current();
+    let cap_bytes = config.monthly_cap_gb * 1_000_000_000;
+    let remaining = (cap_bytes > 0).then(|| cap_bytes.saturating_sub(usage.total()));
+    Json(json!({
+        "success": true,
+        "config": config,
+        "usage": usage,
+        "capReached": state.relay_usage.cap_reached(),
+        "remainingBytes": remaining,
+    }))
+}
+
+async fn reset_relay_usage(State(state): State<ApiState>) -> Json<Value> {
+    state.relay_usage.reset();
+    let usage = state.relay_usage.clone();
+    let _ = tokio::task::spawn_blocking(move || usage.save()).await;
+    Json(json!({"success": true}))
+}
+
+/// Validate, persist to relay-usage-config.json, then apply: the cap at
+/// once, the stream rate to the streams opened from now.
+async fn update_relay_usage_config(
+    State(state): State<ApiState>,
+    Json(config): Json<RelayUsageConfig>,
+) -> Json<Value> {
+    if let Err(e) = config.validate() {
+        return Json(json!({"success": false, "error": e}));
+    }
+    let path = state.env.relay_usage_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Json(json!({"success": false, "error": format!("Write failed: {}", e)})),
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    }
+    state.relay_usage.apply(config.clone());
+    Json(json!({"success": true, "config": config}))
+}
+
+/// Tunnel client certificate, relay pins and the fingerprint of the
+/// certificate the connected relay presented.
+async fn get_relay_certs(State(state): State<ApiState>) -> Json<Value> {
+    let relay_dir = state.env.data_dir.join("cloud-relay");
+    let pins = RelayPins::load(&relay_dir).unwrap_or_default();
+    let client = hr_tunnel::certs::client_cert_status(&relay_dir).ok();
+    let relay_sha256 = state
+        .cloud_relay_status
+        .read()
+        .await
+        .as_ref()
+        .and_then(|info| info.relay_cert_sha256.clone());
+    let pinned = relay_sha256
+        .as_ref()
+        .is_some_and(|sha| pins.pins.iter().any(|p| &p.sha256 == sha));
+    Json(json!({
+        "success": true,
+        "client": client,
+        "pins": pins.pins,
+        "relaySha256": relay_sha256,
+        "relayPinned": pinned,
+    }))
+}
+
+/// Replace the relay pins (pins.json) and reconnect the tunnel with them.
+/// Pin the next certificate before rotating it on the relay.
+async fn update_relay_pins(State(state): State<ApiState>, Json(mut pins): Json<RelayPins>) -> Json<Value> {
+    if let Err(e) = pins.normalize() {
+        return Json(json!({"success": false, "error": e}));
+    }
+    let relay_dir = state.env.data_dir.join("cloud-relay");
+    let to_save = pins.clone();
+    match tokio::task::spawn_blocking(move || to_save.save(&relay_dir)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Json(json!({"success": false, "error": format!("Write failed: {}", e)})),
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    }
+    reconnect_tunnel(&state, "Relay pins updated").await;
+    Json(json!({"success": true, "pins": pins.pins}))
+}
+
+/// Issue a new tunnel client certificate now and reconnect with it.
+async fn renew_relay_client_cert(State(state): State<ApiState>) -> Json<Value> {
+    let relay_dir = state.env.data_dir.join("cloud-relay");
+    let client = match tokio::task::spawn_blocking(move || hr_tunnel::certs::renew_client_cert(&relay_dir)).await {
+        Ok(Ok(client)) => client,
+        Ok(Err(e)) => return Json(json!({"success": false, "error": e.to_string()})),
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    };
+    reconnect_tunnel(&state, "Client certificate renewed").await;
+    Json(json!({"success": true, "client": client}))
+}
+
+async fn reconnect_tunnel(state: &ApiState, reason: &str) {
+    if let Some(tx) = &state.cloud_relay_cmd_tx {
+        let _ = tx
+            .send(hr_common::events::CloudRelayCommand::Reconnect {
+                reason: reason.to_string(),
+            })
+            .await;
+    }
+}
+
+/// Services announced by mDNS on the reflector's interfaces.
+async fn get_discovery(State(state): State<ApiState>) -> Json<Value> {
+    let config = state.reflector.config();
+    Json(json!({
+        "success": true,
+        "enabled": config.enabled && config.mdns && config.discovery,
+        "services": state.reflector.discovered(),
+        "types": state.reflector.discovered_types(),
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DiscoveryRouteRequest {
+    subdomain: String,
+    /// Discovered devices are LAN-only unless asked otherwise.
+    #[serde(default = "default_true")]
+    local_only: bool,
+    #[serde(default)]
+    require_auth: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Publish a discovered HTTP service behind the reverse proxy
+/// (`{subdomain}.{base domain}` → its address and port).
+async fn create_discovery_route(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(req): Json<DiscoveryRouteRequest>,
+) -> Json<Value> {
+    let Some(service) = state.reflector.discovered_service(&id) else {
+        return Json(json!({"success": false, "error": "Service not found"}));
+    };
+    let (Some(port), true) = (service.port, service.web) else {
+        return Json(json!({"success": false, "error": "Not an HTTP service"}));
+    };
+    let subdomain = req.subdomain.trim().to_ascii_lowercase();
+    if subdomain.is_empty()
+        || subdomain.len() > 63
+        || subdomain.starts_with('-')
+        || !subdomain.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Json(json!({"success": false, "error": "Invalid subdomain"}));
+    }
+    // Prefer the IPv4 address: the device may only listen on it
+    let Some(target) = service
+        .addresses
+        .iter()
+        .find(|a| a.parse::<std::net::Ipv4Addr>().is_ok())
+        .or(service.addresses.first())
+    else {
+        return Json(json!({"success": false, "error": "No address for this service"}));
+    };
+    let host = json!({
+        "subdomain": subdomain,
+        "targetHost": target,
+        "targetPort": port,
+        "localOnly": req.local_only,
+        "requireAuth": req.require_auth,
+        "discoveredService": {"id": service.id, "name": service.name, "host": service.host},
+    });
+    match crate::routes::reverseproxy::create_host(&state, host).await {
+        Ok(host) => Json(json!({"success": true, "host": host})),
+        Err(e) => Json(json!({"success": false, "error": e})),
+    }
+}
+
+/// Device inventory (neighbor tables, DHCP leases, active scans) with the
+/// scanner settings and state.
+async fn get_devices(State(state): State<ApiState>) -> Json<Value> {
+    let devices = state.scanner.devices();
+    let online = devices.iter().filter(|d| d.online).count();
+    Json(json!({
+        "success": true,
+        "config": state.scanner.config(),
+        "status": state.scanner.status(),
+        "online": online,
+        "devices": devices,
+    }))
+}
+
+/// Validate, persist to scanner-config.json, then apply.
+async fn update_devices_config(
+    State(state): State<ApiState>,
+    Json(config): Json<ScannerConfig>,
+) -> Json<Value> {
+    if let Err(e) = config.validate() {
+        return Json(json!({"success": false, "error": e}));
+    }
+    let path = state.env.scanner_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Json(json!({"success": false, "error": format!("Write failed: {}", e)})),
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    }
+    state.scanner.apply(config.clone());
+    Json(json!({"success": true, "config": config}))
+}
+
+/// Sweep the LAN now (ping and ports), even with periodic scans disabled.
+async fn scan_devices(State(state): State<ApiState>) -> Json<Value> {
+    if !state.scanner.config().enabled {
+        return Json(json!({"success": false, "error": "Device inventory disabled"}));
+    }
+    if state.scanner.status().scanning {
+        return Json(json!({"success": false, "error": "A scan is already running"}));
+    }
+    state.scanner.scan_now();
+    Json(json!({"success": true}))
+}
+
+/// Remove a device from the inventory (it is listed again once seen).
+async fn forget_device(State(state): State<ApiState>, Path(mac): Path<String>) -> Json<Value> {
+    match state.scanner.forget(&mac) {
+        Some(_) => Json(json!({"success": true})),
+        None => Json(json!({"success": false, "error": "Device not found"})),
+    }
+}
+
+/// Wake-on-LAN magic packet to an inventoried device.
+async fn wake_device(State(state): State<ApiState>, Path(mac): Path<String>) -> Json<Value> {
+    let Some(device) = state.scanner.device(&mac) else {
+        return Json(json!({"success": false, "error": "Device not found"}));
+    };
+    match hr_registry::AgentRegistry::send_wol_packet(&device.mac).await {
+        Ok(()) => Json(json!({"success": true})),
+        Err(e) => Json(json!({"success": false, "error": e})),
+    }
+}
+
+/// Last connectivity doctor report (a run is made when there is none yet)
+/// and its schedule.
+async fn get_doctor(State(state): State<ApiState>) -> Json<Value> {
+    let report = match state.doctor.last() {
+        Some(report) => report,
+        None => crate::doctor::run(&state).await,
+    };
+    Json(json!({"success": true, "config": state.doctor.config(), "report": report}))
+}
+
+/// Run every connectivity check now.
+async fn run_doctor(State(state): State<ApiState>) -> Json<Value> {
+    let report = crate::doctor::run(&state).await;
+    Json(json!({"success": true, "report": report}))
+}
+
+async fn update_doctor_config(
+    State(state): State<ApiState>,
+    Json(config): Json<crate::doctor::DoctorConfig>,
+) -> Json<Value> {
+    let doctor = state.doctor.clone();
+    match tokio::task::spawn_blocking(move || doctor.set_config(config)).await {
+        Ok(Ok(())) => Json(json!({"success": true, "config": config})),
+        Ok(Err(e)) => Json(json!({"success": false, "error": e})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
+
+#[derive(Deserialize)]
+struct SpeedtestQuery {
+    limit: Option<usize>,
+}
+
+/// Speed test settings and results, newest first (`?limit=`, 100 by default).
+async fn get_speedtest(State(state): State<ApiState>, Query(query): Query<SpeedtestQuery>) -> Json<Value> {
+    Json(json!({
+        "success": true,
+        "config": state.speedtest.config(),
+        "running": state.speedtest.is_running(),
+        "results": state.speedtest.results(query.limit.unwrap_or(100)),
+    }))
+}
+
+/// Measure now (takes about twice the configured duration).
+async fn run_speedtest(State(state): State<ApiState>) -> Json<Value> {
+    match crate::speedtest::run(&state).await {
+        Ok(result) => Json(json!({"success": true, "result": result})),
+        Err(e) => Json(json!({"success": false, "error": e})),
+    }
+}
+
+async fn clear_speedtest(State(state): State<ApiState>) -> Json<Value> {
+    let speedtest = state.speedtest.clone();
+    match tokio::task::spawn_blocking(move || speedtest.clear()).await {
+        Ok(Ok(())) => Json(json!({"success": true})),
+        Ok(Err(e)) => Json(json!({"success": false, "error": e})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
+
+async fn update_speedtest_config(
+    State(state): State<ApiState>,
+    Json(config): Json<crate::speedtest::SpeedtestConfig>,
+) -> Json<Value> {
+    let speedtest = state.speedtest.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || speedtest.set_config(to_save)).await {
+        Ok(Ok(())) => Json(json!({"success": true, "config": config})),
+        Ok(Err(e)) => Json(json!({"success": false, "error": e})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
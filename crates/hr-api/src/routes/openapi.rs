@@ -0,0 +1,74 @@
+//! OpenAPI document of the typed routes, served at `/api/openapi.json`.
+//! Errors of these routes use the envelope of [`crate::error::ApiError`].
+
+use axum::{routing::get, Json, Router};
+use utoipa::OpenApi;
+
+use crate::error::ErrorCode;
+use crate::state::ApiState;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "HomeRoute API"),
+    paths(
+        super::health::health,
+        super::services::status,
+        super::services::service,
+        super::services::control,
+        super::config::list_documents,
+        super::config::get_document,
+        super::config::stage,
+        super::config::get_staged,
+        super::config::discard,
+        super::config::apply,
+        super::config::history,
+        super::config::get_version,
+        super::config::revert,
+        super::declarative::export,
+        super::declarative::plan,
+        super::declarative::apply,
+    ),
+    components(schemas(ErrorCode)),
+    tags(
+        (name = "health", description = "Liveness"),
+        (name = "services", description = "Supervised services"),
+        (name = "config", description = "Config transactions: staging, apply with rollback, history"),
+        (name = "declarative", description = "Declarative config document: export, plan, apply"),
+    )
+)]
+pub struct ApiDoc;
+
+pub fn router() -> Router<ApiState> {
+    Router::new().route("/openapi.json", get(spec))
+}
+
+async fn spec() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn spec_is_consistent() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let paths = spec["paths"].as_object().unwrap();
+        assert!(paths.contains_key("/api/services/{name}/{action}"));
+        assert!(paths.contains_key("/api/config/{doc}/history/{version}/revert"));
+
+        let mut operations = HashSet::new();
+        for path in paths.values() {
+            for operation in path.as_object().unwrap().values() {
+                let id = operation["operationId"].as_str().unwrap();
+                assert!(operations.insert(id.to_string()), "duplicate operationId {}", id);
+            }
+        }
+
+        let schemas = spec["components"]["schemas"].as_object().unwrap();
+        for schema in ["ApiError", "ErrorCode", "AppliedResponse", "Change"] {
+            assert!(schemas.contains_key(schema), "missing schema {}", schema);
+        }
+    }
+}
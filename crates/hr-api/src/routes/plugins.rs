@@ -0,0 +1,147 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing::{any, get, post},
+    Json, Router,
+};
+use axum_extra::extract::CookieJar;
+use base64::Engine;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::plugins::{self, PluginRequest, PluginUser, PLUGIN_RPC_VERSION};
+use crate::state::ApiState;
+
+/// Management routes, mounted under `/api/plugins`.
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/", get(list_plugins))
+        .route("/reload", post(reload_plugins))
+}
+
+/// Plugin dispatch, mounted under `/api/ext`.
+pub fn ext_router() -> Router<ApiState> {
+    Router::new()
+        .route("/{name}", any(dispatch))
+        .route("/{name}/{*path}", any(dispatch))
+}
+
+fn plugin_json(m: &plugins::PluginManifest) -> Value {
+    json!({
+        "name": m.name,
+        "description": m.description,
+        "enabled": m.enabled,
+        "requireAuth": m.require_auth,
+        "requireAdmin": m.require_admin,
+        "timeoutMs": m.timeout_ms,
+        "mount": format!("/api/ext/{}", m.name),
+    })
+}
+
+async fn list_plugins(State(state): State<ApiState>) -> Json<Value> {
+    let list: Vec<Value> = state.plugins.list().iter().map(plugin_json).collect();
+    Json(json!({
+        "success": true,
+        "dir": state.plugins.dir(),
+        "plugins": list,
+    }))
+}
+
+async fn reload_plugins(State(state): State<ApiState>) -> Json<Value> {
+    let registry = state.plugins.clone();
+    let errors = match tokio::task::spawn_blocking(move || registry.reload()).await {
+        Ok(e) => e,
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    };
+    let list: Vec<Value> = state.plugins.list().iter().map(plugin_json).collect();
+    Json(json!({"success": true, "plugins": list, "errors": errors}))
+}
+
+fn error_response(status: StatusCode, error: &str) -> Response {
+    (status, Json(json!({"success": false, "error": error}))).into_response()
+}
+
+fn session_user(state: &ApiState, jar: &CookieJar) -> Option<PluginUser> {
+    let session_id = jar.get("auth_session")?.value().to_string();
+    let session = state.auth.sessions.validate(&session_id).ok()??;
+    let user = state.auth.users.get(&session.user_id)?;
+    if user.disabled {
+        return None;
+    }
+    Some(PluginUser {
+        username: user.username,
+        groups: user.groups,
+    })
+}
+
+async fn dispatch(
+    State(state): State<ApiState>,
+    Path(params): Path<HashMap<String, String>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    jar: CookieJar,
+    body: Bytes,
+) -> Response {
+    let name = params.get("name").cloned().unwrap_or_default();
+    let Some(manifest) = state.plugins.get(&name) else {
+        return error_response(StatusCode::NOT_FOUND, "Plugin introuvable");
+    };
+    if !manifest.enabled {
+        return error_response(StatusCode::SERVICE_UNAVAILABLE, "Plugin désactivé");
+    }
+
+    let user = session_user(&state, &jar);
+    if manifest.require_auth && user.is_none() {
+        return error_response(StatusCode::UNAUTHORIZED, "Non authentifié");
+    }
+    if manifest.require_admin && !user.as_ref().is_some_and(|u| u.groups.iter().any(|g| g == "admins")) {
+        return error_response(StatusCode::FORBIDDEN, "Accès réservé aux administrateurs");
+    }
+
+    // Session cookies and credentials are never forwarded to plugins
+    let forwarded: BTreeMap<String, String> = headers
+        .iter()
+        .filter(|(k, _)| *k != axum::http::header::COOKIE && *k != axum::http::header::AUTHORIZATION)
+        .filter_map(|(k, v)| Some((k.as_str().to_string(), v.to_str().ok()?.to_string())))
+        .collect();
+
+    let request = PluginRequest {
+        version: PLUGIN_RPC_VERSION,
+        plugin: manifest.name.clone(),
+        method: method.to_string(),
+        path: format!("/{}", params.get("path").map(String::as_str).unwrap_or("")),
+        query: uri.query().unwrap_or("").to_string(),
+        headers: forwarded,
+        body_base64: base64::engine::general_purpose::STANDARD.encode(&body),
+        user,
+    };
+
+    let response = match plugins::invoke(&manifest, &request).await {
+        Ok(r) => r,
+        Err(e) => {
+            warn!("Plugin {} failed: {}", manifest.name, e);
+            return error_response(StatusCode::BAD_GATEWAY, &e);
+        }
+    };
+
+    let (status, resp_headers, body) = match response.into_parts() {
+        Ok(p) => p,
+        Err(e) => return error_response(StatusCode::BAD_GATEWAY, &e),
+    };
+
+    let mut builder = Response::builder()
+        .status(StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY));
+    for (k, v) in resp_headers {
+        if let (Ok(k), Ok(v)) = (HeaderName::try_from(k), HeaderValue::try_from(v)) {
+            builder = builder.header(k, v);
+        }
+    }
+    builder
+        .body(Body::from(body))
+        .unwrap_or_else(|_| error_response(StatusCode::BAD_GATEWAY, "Réponse plugin invalide"))
+}
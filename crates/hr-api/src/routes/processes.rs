@@ -0,0 +1,139 @@
+//! REST API for the process apps (supervised on the HomeRoute host).
+
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use axum_extra::extract::CookieJar;
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::process_manager::{CreateProcessRequest, ProcessManager, UpdateProcessRequest};
+use crate::routes::auth::admin_user;
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/", get(list_processes).post(create_process))
+        .route("/{id}", put(update_process).delete(delete_process))
+        .route("/{id}/start", post(start_process))
+        .route("/{id}/stop", post(stop_process))
+        .route("/{id}/restart", post(restart_process))
+        .route("/{id}/logs", get(process_logs))
+}
+
+type ApiResult = (StatusCode, Json<Value>);
+
+fn failure(status: StatusCode, error: impl std::fmt::Display) -> ApiResult {
+    (status, Json(json!({"success": false, "error": error.to_string()})))
+}
+
+/// Admin session and the manager; the apps run commands as root.
+fn manager(state: &ApiState, jar: &CookieJar) -> Result<Arc<ProcessManager>, ApiResult> {
+    admin_user(state, jar)?;
+    state
+        .process_manager
+        .clone()
+        .ok_or_else(|| failure(StatusCode::SERVICE_UNAVAILABLE, "Process manager not available"))
+}
+
+fn done(result: Result<bool, String>) -> ApiResult {
+    match result {
+        Ok(true) => (StatusCode::OK, Json(json!({"success": true}))),
+        Ok(false) => failure(StatusCode::NOT_FOUND, "Not found"),
+        Err(e) => failure(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+async fn list_processes(State(state): State<ApiState>, jar: CookieJar) -> ApiResult {
+    let mgr = match manager(&state, &jar) {
+        Ok(mgr) => mgr,
+        Err(e) => return e,
+    };
+    (StatusCode::OK, Json(json!({"success": true, "processes": mgr.list().await})))
+}
+
+/// The agent token is returned once; the process receives it as
+/// `HOMEROUTE_TOKEN`.
+async fn create_process(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Json(req): Json<CreateProcessRequest>,
+) -> ApiResult {
+    let mgr = match manager(&state, &jar) {
+        Ok(mgr) => mgr,
+        Err(e) => return e,
+    };
+    match mgr.create(req).await {
+        Ok((record, token)) => {
+            info!(slug = record.slug, "Process app created via API");
+            (
+                StatusCode::CREATED,
+                Json(json!({"success": true, "id": record.id, "slug": record.slug, "token": token})),
+            )
+        }
+        Err(e) => failure(StatusCode::BAD_REQUEST, e),
+    }
+}
+
+async fn update_process(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path(id): Path<String>,
+    Json(req): Json<UpdateProcessRequest>,
+) -> ApiResult {
+    let mgr = match manager(&state, &jar) {
+        Ok(mgr) => mgr,
+        Err(e) => return e,
+    };
+    match mgr.update(&id, req).await {
+        Err(e) => failure(StatusCode::BAD_REQUEST, e),
+        result => done(result),
+    }
+}
+
+async fn delete_process(State(state): State<ApiState>, jar: CookieJar, Path(id): Path<String>) -> ApiResult {
+    let mgr = match manager(&state, &jar) {
+        Ok(mgr) => mgr,
+        Err(e) => return e,
+    };
+    done(mgr.remove(&id).await)
+}
+
+async fn start_process(State(state): State<ApiState>, jar: CookieJar, Path(id): Path<String>) -> ApiResult {
+    let mgr = match manager(&state, &jar) {
+        Ok(mgr) => mgr,
+        Err(e) => return e,
+    };
+    done(mgr.start(&id).await)
+}
+
+async fn stop_process(State(state): State<ApiState>, jar: CookieJar, Path(id): Path<String>) -> ApiResult {
+    let mgr = match manager(&state, &jar) {
+        Ok(mgr) => mgr,
+        Err(e) => return e,
+    };
+    done(mgr.stop(&id).await)
+}
+
+async fn restart_process(State(state): State<ApiState>, jar: CookieJar, Path(id): Path<String>) -> ApiResult {
+    let mgr = match manager(&state, &jar) {
+        Ok(mgr) => mgr,
+        Err(e) => return e,
+    };
+    done(mgr.restart(&id).await)
+}
+
+/// Last output lines of a command app (not of systemd units: `journalctl`).
+async fn process_logs(State(state): State<ApiState>, jar: CookieJar, Path(id): Path<String>) -> ApiResult {
+    let mgr = match manager(&state, &jar) {
+        Ok(mgr) => mgr,
+        Err(e) => return e,
+    };
+    match mgr.logs(&id).await {
+        Some(lines) => (StatusCode::OK, Json(json!({"success": true, "lines": lines}))),
+        None => failure(StatusCode::NOT_FOUND, "Not found"),
+    }
+}
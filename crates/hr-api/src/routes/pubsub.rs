@@ -0,0 +1,143 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use axum_extra::extract::CookieJar;
+use hr_mail::AppAuth;
+use hr_pubsub::{Access, PubSubConfig, PubSubError, TopicGrant};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::routes::auth::admin_user;
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/", get(overview))
+        .route("/publish/{topic}", post(publish))
+        .route("/grants", post(create_grant))
+        .route("/grants/{id}", delete(delete_grant))
+}
+
+type ApiResult = (StatusCode, Json<Value>);
+
+fn failure(status: StatusCode, error: impl std::fmt::Display) -> ApiResult {
+    (status, Json(json!({"success": false, "error": error.to_string()})))
+}
+
+fn error_status(e: &PubSubError) -> StatusCode {
+    match e {
+        PubSubError::InvalidTopic(_) => StatusCode::BAD_REQUEST,
+        PubSubError::Forbidden(_) => StatusCode::FORBIDDEN,
+        PubSubError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        PubSubError::TooManySubscriptions => StatusCode::TOO_MANY_REQUESTS,
+    }
+}
+
+/// Persist to pubsub-config.json, then apply.
+pub(crate) async fn save_config(state: &ApiState, config: PubSubConfig) -> Result<(), String> {
+    let path = state.env.pubsub_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Err(format!("Write failed: {}", e)),
+        Err(e) => return Err(e.to_string()),
+    }
+    state.pubsub.apply(config);
+    Ok(())
+}
+
+/// POST /api/pubsub/publish/{topic} — the body is the payload.
+/// Auth via `Authorization: Bearer {agent_token}`, for apps publishing
+/// without their agent connection; subscribing needs the connection.
+async fn publish(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Path(topic): Path<String>,
+    Json(payload): Json<Value>,
+) -> ApiResult {
+    let Some(registry) = &state.registry else {
+        return failure(StatusCode::SERVICE_UNAVAILABLE, "Registry not available");
+    };
+    let Some(token) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    else {
+        return failure(StatusCode::UNAUTHORIZED, "Missing or invalid Authorization header");
+    };
+    let Some(app) = registry.app_for(None, token).await else {
+        return failure(StatusCode::UNAUTHORIZED, "Invalid token");
+    };
+    match state.pubsub.publish(&app, &topic, payload).await {
+        Ok((id, delivered)) => (StatusCode::OK, Json(json!({"success": true, "id": id, "delivered": delivered}))),
+        Err(e) => failure(error_status(&e), e),
+    }
+}
+
+/// Grants and live subscriptions.
+async fn overview(State(state): State<ApiState>, jar: CookieJar) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    (
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "config": state.pubsub.config(),
+            "subscriptions": state.pubsub.subscriptions(),
+        })),
+    )
+}
+
+#[derive(Deserialize)]
+struct GrantRequest {
+    topics: String,
+    app: String,
+    access: Access,
+}
+
+async fn create_grant(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Json(req): Json<GrantRequest>,
+) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    let grant = TopicGrant {
+        id: uuid::Uuid::new_v4().to_string(),
+        topics: req.topics,
+        app: req.app,
+        access: req.access,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    if let Err(e) = grant.validate() {
+        return failure(StatusCode::BAD_REQUEST, e);
+    }
+    let mut config = state.pubsub.config();
+    config.grants.push(grant.clone());
+    match save_config(&state, config).await {
+        Ok(()) => (StatusCode::CREATED, Json(json!({"success": true, "grant": grant}))),
+        Err(e) => failure(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// Revoking stops the deliveries to existing subscriptions right away.
+async fn delete_grant(State(state): State<ApiState>, jar: CookieJar, Path(id): Path<String>) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    let mut config = state.pubsub.config();
+    let before = config.grants.len();
+    config.grants.retain(|g| g.id != id);
+    if config.grants.len() == before {
+        return failure(StatusCode::NOT_FOUND, "Grant not found");
+    }
+    match save_config(&state, config).await {
+        Ok(()) => (StatusCode::OK, Json(json!({"success": true}))),
+        Err(e) => failure(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
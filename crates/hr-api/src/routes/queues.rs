@@ -0,0 +1,182 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    routing::{get, post, put},
+    Json, Router,
+};
+use axum_extra::extract::CookieJar;
+use hr_mail::AppAuth;
+use hr_queue::{QueueError, QueueSettings};
+use hr_registry::protocol::QueueRequest;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::routes::auth::admin_user;
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/", get(list_queues))
+        .route("/{queue}/{op}", post(app_operation))
+        .route("/apps/{app}/{queue}", put(update_queue).delete(delete_queue))
+        .route("/apps/{app}/{queue}/dead", get(dead_letters))
+        .route("/apps/{app}/{queue}/redrive", post(redrive))
+}
+
+type ApiResult = (StatusCode, Json<Value>);
+
+fn failure(status: StatusCode, error: impl std::fmt::Display) -> ApiResult {
+    (status, Json(json!({"success": false, "error": error.to_string()})))
+}
+
+/// Status code of a refused queue operation.
+fn error_status(e: &QueueError) -> StatusCode {
+    match e {
+        QueueError::InvalidName | QueueError::Invalid(_) => StatusCode::BAD_REQUEST,
+        QueueError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        QueueError::TooManyQueues => StatusCode::TOO_MANY_REQUESTS,
+        QueueError::ReceiptNotFound => StatusCode::NOT_FOUND,
+        QueueError::Storage(_) => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+/// Run a queue store call on the blocking pool.
+async fn blocking<T: Send + 'static>(
+    state: &ApiState,
+    f: impl FnOnce(&hr_queue::QueueStore) -> anyhow::Result<T> + Send + 'static,
+) -> Result<T, ApiResult> {
+    let store = state.queues.store();
+    match tokio::task::spawn_blocking(move || f(&store)).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(failure(StatusCode::INTERNAL_SERVER_ERROR, e)),
+        Err(e) => Err(failure(StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+/// POST /api/queues/{queue}/{op} — op is send, receive, ack, nack or
+/// extend, its fields in the body (see `QueueRequest`).
+/// Auth via `Authorization: Bearer {agent_token}`; the queue belongs to the
+/// app owning the token.
+async fn app_operation(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Path((queue, op)): Path<(String, String)>,
+    body: Option<Json<Value>>,
+) -> ApiResult {
+    let Some(registry) = &state.registry else {
+        return failure(StatusCode::SERVICE_UNAVAILABLE, "Registry not available");
+    };
+    let Some(token) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    else {
+        return failure(StatusCode::UNAUTHORIZED, "Missing or invalid Authorization header");
+    };
+    let Some(app) = registry.app_for(None, token).await else {
+        return failure(StatusCode::UNAUTHORIZED, "Invalid token");
+    };
+
+    let mut fields = match body {
+        Some(Json(Value::Object(fields))) => fields,
+        Some(_) => return failure(StatusCode::BAD_REQUEST, "Body must be a JSON object"),
+        None => Default::default(),
+    };
+    fields.insert("op".to_string(), Value::String(op));
+    fields.insert("queue".to_string(), Value::String(queue));
+    let request: QueueRequest = match serde_json::from_value(Value::Object(fields)) {
+        Ok(request) => request,
+        Err(e) => return failure(StatusCode::BAD_REQUEST, e),
+    };
+    match state.queues.handle(&app, request).await {
+        Ok(Value::Object(mut data)) => {
+            data.insert("success".to_string(), Value::Bool(true));
+            (StatusCode::OK, Json(Value::Object(data)))
+        }
+        Ok(data) => (StatusCode::OK, Json(json!({"success": true, "data": data}))),
+        Err(e) => failure(error_status(&e), e),
+    }
+}
+
+async fn list_queues(State(state): State<ApiState>, jar: CookieJar) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    let now = chrono::Utc::now().timestamp_millis();
+    match blocking(&state, move |store| store.stats(None, now)).await {
+        Ok(queues) => (StatusCode::OK, Json(json!({"success": true, "queues": queues}))),
+        Err(e) => e,
+    }
+}
+
+async fn update_queue(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path((app, queue)): Path<(String, String)>,
+    Json(settings): Json<QueueSettings>,
+) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    if let Err(e) = settings.validate() {
+        return failure(StatusCode::BAD_REQUEST, e);
+    }
+    match blocking(&state, move |store| store.set_settings(&app, &queue, &settings)).await {
+        Ok(true) => (StatusCode::OK, Json(json!({"success": true}))),
+        Ok(false) => failure(StatusCode::NOT_FOUND, "Queue not found"),
+        Err(e) => e,
+    }
+}
+
+/// Deletes the queue with all its messages.
+async fn delete_queue(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path((app, queue)): Path<(String, String)>,
+) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    match blocking(&state, move |store| store.delete_queue(&app, &queue)).await {
+        Ok(true) => (StatusCode::OK, Json(json!({"success": true}))),
+        Ok(false) => failure(StatusCode::NOT_FOUND, "Queue not found"),
+        Err(e) => e,
+    }
+}
+
+#[derive(Deserialize)]
+struct DeadQuery {
+    limit: Option<u32>,
+}
+
+async fn dead_letters(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path((app, queue)): Path<(String, String)>,
+    Query(query): Query<DeadQuery>,
+) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    match blocking(&state, move |store| store.dead_letters(&app, &queue, limit)).await {
+        Ok(messages) => (StatusCode::OK, Json(json!({"success": true, "messages": messages}))),
+        Err(e) => e,
+    }
+}
+
+/// Put the dead letters back in the queue.
+async fn redrive(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path((app, queue)): Path<(String, String)>,
+) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    let now = chrono::Utc::now().timestamp_millis();
+    match blocking(&state, move |store| store.redrive(&app, &queue, now)).await {
+        Ok(count) => (StatusCode::OK, Json(json!({"success": true, "redriven": count}))),
+        Err(e) => e,
+    }
+}
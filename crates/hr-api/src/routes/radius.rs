@@ -0,0 +1,107 @@
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+    routing::{delete, get},
+    Json, Router,
+};
+use hr_radius::RadiusConfig;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/", get(get_radius).put(update_radius))
+        .route("/ca", get(get_ca))
+        .route("/certificates", get(list_certificates).post(issue_certificate))
+        .route("/certificates/{fingerprint}", delete(revoke_certificate))
+}
+
+async fn get_radius(State(state): State<ApiState>) -> Json<Value> {
+    Json(json!({
+        "success": true,
+        "config": state.radius.config(),
+        "status": state.radius.status(),
+        "events": state.radius.events(),
+    }))
+}
+
+/// Validate, persist to radius-config.json, then hand the new config to the
+/// running server (the socket is rebound without a restart).
+async fn update_radius(
+    State(state): State<ApiState>,
+    Json(config): Json<RadiusConfig>,
+) -> Json<Value> {
+    if let Err(e) = config.validate() {
+        return Json(json!({"success": false, "error": e}));
+    }
+    let path = state.env.radius_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Json(json!({"success": false, "error": format!("Write failed: {}", e)})),
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    }
+    state.radius.apply(config.clone());
+    Json(json!({"success": true, "config": config}))
+}
+
+/// CA certificate to install on Wi-Fi clients.
+async fn get_ca(State(state): State<ApiState>) -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "application/x-pem-file"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"homeroute-radius-ca.crt\"",
+            ),
+        ],
+        state.radius.ca().ca_pem().to_string(),
+    )
+}
+
+async fn list_certificates(State(state): State<ApiState>) -> Json<Value> {
+    Json(json!({"success": true, "certificates": state.radius.ca().list()}))
+}
+
+#[derive(Deserialize)]
+struct IssueRequest {
+    username: String,
+    #[serde(default)]
+    label: String,
+}
+
+/// Issue an EAP-TLS client certificate for a HomeRoute user. The private key
+/// is only returned here, never stored.
+async fn issue_certificate(
+    State(state): State<ApiState>,
+    Json(req): Json<IssueRequest>,
+) -> Json<Value> {
+    match state.auth.users.get(&req.username) {
+        Some(user) if !user.disabled => {}
+        Some(_) => return Json(json!({"success": false, "error": "Utilisateur désactivé"})),
+        None => return Json(json!({"success": false, "error": "Utilisateur introuvable"})),
+    }
+    let radius = state.radius.clone();
+    let days = radius.config().cert_validity_days;
+    match tokio::task::spawn_blocking(move || radius.ca().issue_client(&req.username, &req.label, days)).await {
+        Ok(Ok(bundle)) => Json(json!({"success": true, "bundle": bundle})),
+        Ok(Err(e)) => Json(json!({"success": false, "error": e.to_string()})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
+
+async fn revoke_certificate(
+    State(state): State<ApiState>,
+    Path(fingerprint): Path<String>,
+) -> Json<Value> {
+    let radius = state.radius.clone();
+    match tokio::task::spawn_blocking(move || radius.ca().revoke(&fingerprint)).await {
+        Ok(Ok(true)) => Json(json!({"success": true})),
+        Ok(Ok(false)) => Json(json!({"success": false, "error": "Certificat introuvable"})),
+        Ok(Err(e)) => Json(json!({"success": false, "error": e.to_string()})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
@@ -0,0 +1,96 @@
+use axum::{
+    extract::{Path, State},
+    routing::{get, put},
+    Json, Router,
+};
+use hr_reflector::{PairingRule, ReflectorConfig};
+use serde_json::{json, Value};
+
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/", get(get_reflector).put(update_reflector))
+        .route("/rules", axum::routing::post(create_rule))
+        .route("/rules/{id}", put(update_rule).delete(delete_rule))
+}
+
+async fn get_reflector(State(state): State<ApiState>) -> Json<Value> {
+    Json(json!({
+        "success": true,
+        "config": state.reflector.config(),
+        "status": state.reflector.status(),
+    }))
+}
+
+async fn update_reflector(
+    State(state): State<ApiState>,
+    Json(config): Json<ReflectorConfig>,
+) -> Json<Value> {
+    if let Err(e) = save_and_apply(&state, config.clone()).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+    Json(json!({"success": true, "config": config}))
+}
+
+async fn create_rule(
+    State(state): State<ApiState>,
+    Json(mut rule): Json<PairingRule>,
+) -> Json<Value> {
+    if rule.id.trim().is_empty() {
+        rule.id = uuid::Uuid::new_v4().to_string();
+    }
+    let mut config = state.reflector.config();
+    if config.rules.iter().any(|r| r.id == rule.id) {
+        return Json(json!({"success": false, "error": "Une règle avec cet id existe déjà"}));
+    }
+    config.rules.push(rule.clone());
+    if let Err(e) = save_and_apply(&state, config).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+    Json(json!({"success": true, "rule": rule}))
+}
+
+async fn update_rule(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(mut rule): Json<PairingRule>,
+) -> Json<Value> {
+    rule.id = id.clone();
+    let mut config = state.reflector.config();
+    let Some(existing) = config.rules.iter_mut().find(|r| r.id == id) else {
+        return Json(json!({"success": false, "error": "Règle introuvable"}));
+    };
+    *existing = rule.clone();
+    if let Err(e) = save_and_apply(&state, config).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+    Json(json!({"success": true, "rule": rule}))
+}
+
+async fn delete_rule(State(state): State<ApiState>, Path(id): Path<String>) -> Json<Value> {
+    let mut config = state.reflector.config();
+    let before = config.rules.len();
+    config.rules.retain(|r| r.id != id);
+    if config.rules.len() == before {
+        return Json(json!({"success": false, "error": "Règle introuvable"}));
+    }
+    if let Err(e) = save_and_apply(&state, config).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+    Json(json!({"success": true}))
+}
+
+/// Validate, persist to reflector-config.json, then hand the new config to
+/// the running reflector (sockets are rebuilt without a restart).
+async fn save_and_apply(state: &ApiState, config: ReflectorConfig) -> Result<(), String> {
+    config.validate()?;
+    let path = state.env.reflector_config_path.clone();
+    let to_save = config.clone();
+    tokio::task::spawn_blocking(move || to_save.save_to_file(&path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Write failed: {}", e))?;
+    state.reflector.apply(config);
+    Ok(())
+}
@@ -0,0 +1,62 @@
+//! Resource limits of an application's container, under
+//! `/api/applications/{id}/resources`.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use hr_container::ResourceLimits;
+use serde_json::{json, Value};
+
+use crate::container_manager::ContainerV2Record;
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new().route("/{id}/resources", get(get_resources).put(update_resources))
+}
+
+type ApiResult = (StatusCode, Json<Value>);
+
+fn failure(status: StatusCode, error: impl std::fmt::Display) -> ApiResult {
+    (status, Json(json!({"success": false, "error": error.to_string()})))
+}
+
+/// Limits of the container with its current usage (null when stopped).
+async fn resources_json(state: &ApiState, record: &ContainerV2Record) -> Value {
+    let usage = match &state.container_manager {
+        Some(cm) => cm.usage(record).await,
+        None => None,
+    };
+    json!({"success": true, "limits": record.limits, "usage": usage})
+}
+
+async fn get_resources(State(state): State<ApiState>, Path(id): Path<String>) -> ApiResult {
+    let Some(cm) = &state.container_manager else {
+        return failure(StatusCode::SERVICE_UNAVAILABLE, "Container manager not available");
+    };
+    match cm.record(&id).await {
+        Some(record) => (StatusCode::OK, Json(resources_json(&state, &record).await)),
+        None => failure(StatusCode::NOT_FOUND, "Container not found"),
+    }
+}
+
+/// Replace the limits; unset fields lift the corresponding limit.
+async fn update_resources(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(limits): Json<ResourceLimits>,
+) -> ApiResult {
+    let Some(cm) = &state.container_manager else {
+        return failure(StatusCode::SERVICE_UNAVAILABLE, "Container manager not available");
+    };
+    if let Err(e) = limits.validate() {
+        return failure(StatusCode::BAD_REQUEST, e);
+    }
+    match cm.set_limits(&id, limits).await {
+        Ok(Some(record)) => (StatusCode::OK, Json(resources_json(&state, &record).await)),
+        Ok(None) => failure(StatusCode::NOT_FOUND, "Container not found"),
+        Err(e) => failure(StatusCode::BAD_GATEWAY, e),
+    }
+}
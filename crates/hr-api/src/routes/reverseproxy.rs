@@ -1,36 +1,58 @@
 use axum::{
-    extract::{Path, State},
+    extract::{
+        ws::{Message, WebSocket},
+        Path, Query, State, WebSocketUpgrade,
+    },
+    http::StatusCode,
+    response::IntoResponse,
     routing::{delete, get, post, put},
     Json, Router,
 };
+use axum_extra::extract::CookieJar;
+use hr_auth::shares::SHARE_PARAM;
+use hr_proxy::{AccessLogFilter, ForwardingConfig, MaintenanceConfig};
 use serde::Deserialize;
 use serde_json::{json, Value};
+use tokio::sync::broadcast;
 
+use crate::config_tx::{self, ConfigDocument};
+use crate::routes::auth::admin_user;
 use crate::state::ApiState;
 
 pub fn router() -> Router<ApiState> {
     Router::new()
         .route("/config", get(get_config))
         .route("/config/domain", put(update_domain))
+        .route("/config/geoip", put(update_geoip))
+        .route("/config/forwarding", get(get_forwarding).put(update_forwarding))
+        .route("/config/apex", put(update_apex))
         .route("/hosts", get(list_hosts).post(add_host))
         .route("/hosts/{id}", put(update_host).delete(delete_host))
         .route("/hosts/{id}/toggle", post(toggle_host))
         .route("/status", get(proxy_status))
+        .route("/health", get(all_routes_health))
+        .route("/routes/{id}/health", get(route_health))
+        .route("/routes/{id}/maintenance", get(get_maintenance).put(update_maintenance))
+        .route("/routes/{id}/share", get(list_shares).post(create_share))
+        .route("/routes/{id}/share/{share_id}", delete(revoke_share))
+        .route("/logs", get(access_logs))
+        .route("/logs/stream", get(access_logs_stream))
         .route("/reload", post(reload_proxy))
         .route("/certificates/status", get(certificates_status))
         .route("/certificates/renew", post(renew_certificates))
 }
 
 /// Load the reverseproxy-config.json
-async fn load_rp_config(state: &ApiState) -> Result<Value, String> {
+pub(crate) async fn load_rp_config(state: &ApiState) -> Result<Value, String> {
     let content = tokio::fs::read_to_string(&state.reverseproxy_config_path)
         .await
         .map_err(|e| format!("Read error: {}", e))?;
     serde_json::from_str(&content).map_err(|e| format!("Parse error: {}", e))
 }
 
-/// Save the reverseproxy-config.json
+/// Save the reverseproxy-config.json, as a new version of its history
 async fn save_rp_config(state: &ApiState, config: &Value) -> Result<(), String> {
+    let previous = load_rp_config(state).await.ok();
     let content =
         serde_json::to_string_pretty(config).map_err(|e| format!("Serialize error: {}", e))?;
     let tmp = state.reverseproxy_config_path.with_extension("json.tmp");
@@ -40,11 +62,39 @@ async fn save_rp_config(state: &ApiState, config: &Value) -> Result<(), String>
     tokio::fs::rename(&tmp, &state.reverseproxy_config_path)
         .await
         .map_err(|e| format!("Rename error: {}", e))?;
+
+    let changes = previous
+        .as_ref()
+        .map_or(0, |previous| config_tx::diff(previous, config).len());
+    if let Err(e) = config_tx::record(
+        state,
+        ConfigDocument::ReverseProxy,
+        previous.as_ref(),
+        config,
+        changes,
+        "edit",
+        None,
+    )
+    .await
+    {
+        tracing::warn!("Failed to record reverseproxy config version: {}", e);
+    }
     Ok(())
 }
 
+/// Domain served by a host: its custom domain, else `{subdomain}.{base}`.
+pub(crate) fn host_domain(host: &Value, base_domain: &str) -> Option<String> {
+    match host.get("customDomain").and_then(|d| d.as_str()) {
+        Some(custom) if !custom.is_empty() => Some(custom.to_string()),
+        _ => host
+            .get("subdomain")
+            .and_then(|s| s.as_str())
+            .map(|sub| format!("{}.{}", sub, base_domain)),
+    }
+}
+
 /// Sync all routes to rust-proxy-config.json and reload proxy
-async fn sync_and_reload(state: &ApiState) -> Result<(), String> {
+pub(crate) async fn sync_and_reload(state: &ApiState) -> Result<(), String> {
     let rp_config = load_rp_config(state).await?;
     let base_domain = rp_config
         .get("baseDomain")
@@ -65,17 +115,7 @@ async fn sync_and_reload(state: &ApiState) -> Result<(), String> {
         if host.get("enabled").and_then(|e| e.as_bool()) != Some(true) {
             continue;
         }
-        let domain = if let Some(custom) = host.get("customDomain").and_then(|d| d.as_str()) {
-            if !custom.is_empty() {
-                custom.to_string()
-            } else if let Some(sub) = host.get("subdomain").and_then(|s| s.as_str()) {
-                format!("{}.{}", sub, base_domain)
-            } else {
-                continue;
-            }
-        } else if let Some(sub) = host.get("subdomain").and_then(|s| s.as_str()) {
-            format!("{}.{}", sub, base_domain)
-        } else {
+        let Some(domain) = host_domain(host, &base_domain) else {
             continue;
         };
 
@@ -86,8 +126,20 @@ async fn sync_and_reload(state: &ApiState) -> Result<(), String> {
             "target_host": host.get("targetHost").unwrap_or(&json!("localhost")),
             "target_port": host.get("targetPort").unwrap_or(&json!(80)),
             "local_only": host.get("localOnly").unwrap_or(&json!(false)),
+            "vpn_only": host.get("vpnOnly").unwrap_or(&json!(false)),
             "require_auth": host.get("requireAuth").unwrap_or(&json!(false)),
-            "enabled": true
+            "require_mfa": host.get("requireMfa").unwrap_or(&json!(false)),
+            "enabled": true,
+            "health_check": host.get("healthCheck").unwrap_or(&json!({})),
+            "targets": host.get("targets").unwrap_or(&json!([])),
+            "load_balancing": host.get("loadBalancing").unwrap_or(&json!("round_robin")),
+            "sticky": host.get("sticky").unwrap_or(&json!(false)),
+            "draining": host.get("draining").unwrap_or(&json!([])),
+            "geo": host.get("geo").unwrap_or(&json!({})),
+            "maintenance": host.get("maintenance").unwrap_or(&json!({})),
+            "forwarding": host.get("forwarding").unwrap_or(&json!({})),
+            "backend_tls": host.get("backendTls").unwrap_or(&json!(false)),
+            "local_dns": host.get("localDns").unwrap_or(&json!(true))
         }));
     }
 
@@ -101,6 +153,18 @@ async fn sync_and_reload(state: &ApiState) -> Result<(), String> {
 
     proxy_config["routes"] = json!(routes);
     proxy_config["base_domain"] = json!(base_domain);
+    proxy_config["geoip_database"] = rp_config
+        .get("geoipDatabase")
+        .filter(|p| p.as_str().is_some_and(|p| !p.is_empty()))
+        .cloned()
+        .unwrap_or(Value::Null);
+    proxy_config["forwarding"] = rp_config.get("forwarding").cloned().unwrap_or_else(|| json!({}));
+    // A bare label ("www") is a subdomain of the base domain
+    proxy_config["apex_target"] = match rp_config.get("apexTarget").and_then(|t| t.as_str()) {
+        Some(t) if !t.is_empty() && !t.contains('.') => json!(format!("{}.{}", t, base_domain)),
+        Some(t) if !t.is_empty() => json!(t),
+        _ => Value::Null,
+    };
 
     let content =
         serde_json::to_string_pretty(&proxy_config).map_err(|e| format!("Serialize: {}", e))?;
@@ -113,24 +177,23 @@ async fn sync_and_reload(state: &ApiState) -> Result<(), String> {
         .map_err(|e| format!("Rename: {}", e))?;
 
     // Reload proxy config and ACME wildcard certificates
-    if let Ok(new_proxy_config) =
-        hr_proxy::ProxyConfig::load_from_file(proxy_config_path)
-    {
-        // Load all ACME wildcard certificates into TLS manager (global, legacy code, per-app)
-        if let Ok(certs) = state.acme.list_certificates() {
-            for cert_info in &certs {
-                let wildcard_domain = cert_info.wildcard_type.domain_pattern(&base_domain);
-                if let Err(e) = state.tls_manager.load_certificate_from_pem(
-                    &wildcard_domain,
-                    &cert_info.cert_path,
-                    &cert_info.key_path,
-                ) {
-                    tracing::error!("Failed to load wildcard cert for {}: {}", wildcard_domain, e);
-                }
+    let new_proxy_config = hr_proxy::ProxyConfig::load_from_file(proxy_config_path)
+        .map_err(|e| format!("Proxy config: {}", e))?;
+
+    // Load all ACME wildcard certificates into TLS manager (global, legacy code, per-app)
+    if let Ok(certs) = state.acme.list_certificates() {
+        for cert_info in &certs {
+            let wildcard_domain = cert_info.wildcard_type.domain_pattern(&base_domain);
+            if let Err(e) = state.tls_manager.load_certificate_from_pem(
+                &wildcard_domain,
+                &cert_info.cert_path,
+                &cert_info.key_path,
+            ) {
+                tracing::error!("Failed to load wildcard cert for {}: {}", wildcard_domain, e);
             }
         }
-        state.proxy.reload_config(new_proxy_config);
     }
+    state.proxy.reload_config(new_proxy_config);
 
     Ok(())
 }
@@ -169,6 +232,101 @@ async fn update_domain(
     Json(json!({"success": true}))
 }
 
+#[derive(Deserialize)]
+struct UpdateGeoIpRequest {
+    /// Path of the MMDB country database, empty to disable GeoIP.
+    database: String,
+}
+
+async fn update_geoip(
+    State(state): State<ApiState>,
+    Json(body): Json<UpdateGeoIpRequest>,
+) -> Json<Value> {
+    let database = body.database.trim().to_string();
+    if !database.is_empty() && !std::path::Path::new(&database).is_file() {
+        return Json(json!({"success": false, "error": "Base GeoIP introuvable"}));
+    }
+
+    let mut config = match load_rp_config(&state).await {
+        Ok(c) => c,
+        Err(e) => return Json(json!({"success": false, "error": e})),
+    };
+
+    config["geoipDatabase"] = json!(database);
+
+    if let Err(e) = save_rp_config(&state, &config).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+
+    if let Err(e) = sync_and_reload(&state).await {
+        return Json(json!({"success": false, "error": format!("Sync failed: {}", e)}));
+    }
+
+    Json(json!({"success": true, "geoip": state.proxy.geoip_status()}))
+}
+
+async fn get_forwarding(State(state): State<ApiState>) -> Json<Value> {
+    Json(json!({"success": true, "forwarding": state.proxy.config().forwarding}))
+}
+
+/// Forwarding headers policy of app routes and management domains (static
+/// hosts carry their own `forwarding` object).
+async fn update_forwarding(
+    State(state): State<ApiState>,
+    Json(forwarding): Json<ForwardingConfig>,
+) -> Json<Value> {
+    if let Err(e) = forwarding.validate() {
+        return Json(json!({"success": false, "error": e}));
+    }
+
+    let mut config = match load_rp_config(&state).await {
+        Ok(c) => c,
+        Err(e) => return Json(json!({"success": false, "error": e})),
+    };
+
+    config["forwarding"] = json!(forwarding);
+
+    if let Err(e) = save_rp_config(&state, &config).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+
+    if let Err(e) = sync_and_reload(&state).await {
+        return Json(json!({"success": false, "error": format!("Sync failed: {}", e)}));
+    }
+
+    Json(json!({"success": true, "forwarding": forwarding}))
+}
+
+#[derive(Deserialize)]
+struct UpdateApexRequest {
+    /// Domain (or subdomain label) whose route serves the bare base domain,
+    /// empty to leave the apex unrouted.
+    target: String,
+}
+
+async fn update_apex(
+    State(state): State<ApiState>,
+    Json(body): Json<UpdateApexRequest>,
+) -> Json<Value> {
+    let target = body.target.trim().trim_end_matches('.').to_ascii_lowercase();
+
+    let mut config = match load_rp_config(&state).await {
+        Ok(c) => c,
+        Err(e) => return Json(json!({"success": false, "error": e})),
+    };
+
+    config["apexTarget"] = json!(target);
+
+    if let Err(e) = save_rp_config(&state, &config).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+
+    if let Err(e) = sync_and_reload(&state).await {
+        return Json(json!({"success": false, "error": format!("Sync failed: {}", e)}));
+    }
+
+    Json(json!({"success": true, "apex_target": state.proxy.config().apex_target}))
+}
 
 async fn list_hosts(State(state): State<ApiState>) -> Json<Value> {
     match load_rp_config(&state).await {
@@ -181,14 +339,17 @@ async fn list_hosts(State(state): State<ApiState>) -> Json<Value> {
 }
 
 async fn add_host(State(state): State<ApiState>, Json(body): Json<Value>) -> Json<Value> {
-    let mut config = match load_rp_config(&state).await {
-        Ok(c) => c,
-        Err(e) => return Json(json!({"success": false, "error": e})),
-    };
+    match create_host(&state, body).await {
+        Ok(host) => Json(json!({"success": true, "host": host})),
+        Err(e) => Json(json!({"success": false, "error": e})),
+    }
+}
+
+/// Add a standalone host and publish it (also used by the LAN discovery).
+pub(crate) async fn create_host(state: &ApiState, mut host: Value) -> Result<Value, String> {
+    let mut config = load_rp_config(state).await?;
 
-    let id = uuid::Uuid::new_v4().to_string();
-    let mut host = body;
-    host["id"] = json!(id);
+    host["id"] = json!(uuid::Uuid::new_v4().to_string());
     host["createdAt"] = json!(chrono::Utc::now().to_rfc3339());
     if host.get("enabled").is_none() {
         host["enabled"] = json!(true);
@@ -202,14 +363,11 @@ async fn add_host(State(state): State<ApiState>, Json(body): Json<Value>) -> Jso
         None => config["hosts"] = json!([host]),
     }
 
-    if let Err(e) = save_rp_config(&state, &config).await {
-        return Json(json!({"success": false, "error": e}));
-    }
-    if let Err(e) = sync_and_reload(&state).await {
-        return Json(json!({"success": false, "error": format!("Sync failed: {}", e)}));
-    }
-
-    Json(json!({"success": true, "host": host}))
+    save_rp_config(state, &config).await?;
+    sync_and_reload(state)
+        .await
+        .map_err(|e| format!("Sync failed: {}", e))?;
+    Ok(host)
 }
 
 async fn update_host(
@@ -289,6 +447,145 @@ async fn toggle_host(State(state): State<ApiState>, Path(id): Path<String>) -> J
     Json(json!({"success": true}))
 }
 
+async fn get_maintenance(State(state): State<ApiState>, Path(id): Path<String>) -> Json<Value> {
+    let config = match load_rp_config(&state).await {
+        Ok(c) => c,
+        Err(e) => return Json(json!({"success": false, "error": e})),
+    };
+    let host = config
+        .get("hosts")
+        .and_then(|h| h.as_array())
+        .and_then(|hosts| hosts.iter().find(|h| h.get("id").and_then(|i| i.as_str()) == Some(&id)));
+    match host {
+        Some(host) => {
+            let maintenance: MaintenanceConfig = host
+                .get("maintenance")
+                .and_then(|m| serde_json::from_value(m.clone()).ok())
+                .unwrap_or_default();
+            Json(json!({"success": true, "maintenance": maintenance}))
+        }
+        None => Json(json!({"success": false, "error": "Host non trouve"})),
+    }
+}
+
+/// Turn maintenance mode on/off for a host (page content and admin
+/// allowlist included), then resync the proxy.
+async fn update_maintenance(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(maintenance): Json<MaintenanceConfig>,
+) -> Json<Value> {
+    if let Err(e) = maintenance.validate() {
+        return Json(json!({"success": false, "error": e}));
+    }
+
+    let mut config = match load_rp_config(&state).await {
+        Ok(c) => c,
+        Err(e) => return Json(json!({"success": false, "error": e})),
+    };
+
+    let host = config
+        .get_mut("hosts")
+        .and_then(|h| h.as_array_mut())
+        .and_then(|hosts| hosts.iter_mut().find(|h| h.get("id").and_then(|i| i.as_str()) == Some(&id)));
+    let Some(host) = host else {
+        return Json(json!({"success": false, "error": "Host non trouve"}));
+    };
+    host["maintenance"] = json!(maintenance);
+    host["updatedAt"] = json!(chrono::Utc::now().to_rfc3339());
+
+    if let Err(e) = save_rp_config(&state, &config).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+    if let Err(e) = sync_and_reload(&state).await {
+        return Json(json!({"success": false, "error": format!("Sync failed: {}", e)}));
+    }
+
+    Json(json!({"success": true, "maintenance": maintenance}))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ShareRequest {
+    hours: u32,
+    #[serde(default)]
+    max_uses: Option<u32>,
+    #[serde(default)]
+    label: Option<String>,
+}
+
+/// Generate a guest link for a protected host (admins only). The token is
+/// returned once, inside the URL; the proxy trades it for a cookie.
+async fn create_share(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path(id): Path<String>,
+    Json(req): Json<ShareRequest>,
+) -> (StatusCode, Json<Value>) {
+    let admin = match admin_user(&state, &jar) {
+        Ok(u) => u,
+        Err(e) => return e,
+    };
+    let config = match load_rp_config(&state).await {
+        Ok(c) => c,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"success": false, "error": e}))),
+    };
+    let base_domain = config.get("baseDomain").and_then(|d| d.as_str()).unwrap_or("");
+    let host = config
+        .get("hosts")
+        .and_then(|h| h.as_array())
+        .and_then(|hosts| hosts.iter().find(|h| h.get("id").and_then(|i| i.as_str()) == Some(&id)));
+    let Some(host) = host else {
+        return (StatusCode::NOT_FOUND, Json(json!({"success": false, "error": "Host non trouve"})));
+    };
+    if host.get("requireAuth").and_then(|r| r.as_bool()) != Some(true) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"success": false, "error": "Route publique, pas besoin de lien de partage"})),
+        );
+    }
+    let Some(domain) = host_domain(host, base_domain) else {
+        return (StatusCode::BAD_REQUEST, Json(json!({"success": false, "error": "Host sans domaine"})));
+    };
+
+    match state.auth.shares.create(&id, req.hours, req.max_uses, req.label.as_deref(), Some(&admin.username)) {
+        Ok((link, token)) => {
+            let url = format!("https://{}/?{}={}", domain, SHARE_PARAM, token);
+            (StatusCode::OK, Json(json!({"success": true, "url": url, "share": link})))
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, Json(json!({"success": false, "error": e.to_string()}))),
+    }
+}
+
+async fn list_shares(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path(id): Path<String>,
+) -> (StatusCode, Json<Value>) {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    match state.auth.shares.list(&id) {
+        Ok(shares) => (StatusCode::OK, Json(json!({"success": true, "shares": shares}))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"success": false, "error": e.to_string()}))),
+    }
+}
+
+async fn revoke_share(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path((id, share_id)): Path<(String, String)>,
+) -> (StatusCode, Json<Value>) {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    match state.auth.shares.revoke(&id, &share_id) {
+        Ok(true) => (StatusCode::OK, Json(json!({"success": true}))),
+        Ok(false) => (StatusCode::NOT_FOUND, Json(json!({"success": false, "error": "Lien non trouve"}))),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"success": false, "error": e.to_string()}))),
+    }
+}
+
 async fn proxy_status(State(state): State<ApiState>) -> Json<Value> {
     let config = state.proxy.config();
     let route_count = config.routes.len();
@@ -299,10 +596,83 @@ async fn proxy_status(State(state): State<ApiState>) -> Json<Value> {
         "active": true,
         "routes": route_count,
         "active_routes": active_count,
-        "base_domain": config.base_domain
+        "base_domain": config.base_domain,
+        "geoip": state.proxy.geoip_status()
     }))
 }
 
+async fn all_routes_health(State(state): State<ApiState>) -> Json<Value> {
+    Json(json!({"success": true, "health": state.proxy.all_route_health()}))
+}
+
+async fn route_health(State(state): State<ApiState>, Path(id): Path<String>) -> Json<Value> {
+    let config = state.proxy.config();
+    let Some(route) = config.routes.iter().find(|r| r.id == id) else {
+        return Json(json!({"success": false, "error": "Route non trouvee"}));
+    };
+    Json(json!({
+        "success": true,
+        "routeId": route.id,
+        "domain": route.domain,
+        "check": route.health_check,
+        "targets": route.upstreams(),
+        "loadBalancing": route.load_balancing,
+        "sticky": route.sticky,
+        "draining": route.draining,
+        "connections": route.upstreams().iter()
+            .map(|t| (t.clone(), state.proxy.target_connections(t)))
+            .collect::<std::collections::HashMap<_, _>>(),
+        "health": state.proxy.route_health(&id),
+    }))
+}
+
+/// Recent access log entries (in-memory buffer), newest first.
+async fn access_logs(
+    State(state): State<ApiState>,
+    Query(filter): Query<AccessLogFilter>,
+) -> Json<Value> {
+    let entries = state.proxy.access_logger.recent(&filter);
+    Json(json!({"success": true, "count": entries.len(), "entries": entries}))
+}
+
+/// Live tail of the access log over WebSocket, with the same filters.
+async fn access_logs_stream(
+    ws: WebSocketUpgrade,
+    State(state): State<ApiState>,
+    Query(filter): Query<AccessLogFilter>,
+) -> impl IntoResponse {
+    let rx = state.proxy.access_logger.subscribe();
+    ws.on_upgrade(move |socket| tail_access_logs(socket, rx, filter))
+}
+
+async fn tail_access_logs(
+    mut socket: WebSocket,
+    mut rx: broadcast::Receiver<hr_proxy::AccessLogEntry>,
+    filter: AccessLogFilter,
+) {
+    loop {
+        tokio::select! {
+            entry = rx.recv() => {
+                let msg = match entry {
+                    Ok(entry) if filter.matches(&entry) => json!({"type": "entry", "data": entry}),
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => json!({"type": "lagged", "skipped": skipped}),
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if socket.send(Message::Text(msg.to_string().into())).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
 async fn reload_proxy(State(state): State<ApiState>) -> Json<Value> {
     match sync_and_reload(&state).await {
         Ok(()) => Json(json!({"success": true})),
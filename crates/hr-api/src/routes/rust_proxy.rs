@@ -41,6 +41,7 @@ async fn routes(State(state): State<ApiState>) -> Json<Value> {
                 "target_port": r.target_port,
                 "local_only": r.local_only,
                 "require_auth": r.require_auth,
+                "require_mfa": r.require_mfa,
                 "enabled": r.enabled
             })
         })
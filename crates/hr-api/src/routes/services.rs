@@ -1,13 +1,41 @@
-use axum::{extract::State, routing::get, Json, Router};
-use serde_json::{json, Value};
+use axum::{
+    extract::{Path, State},
+    routing::{get, post},
+    Json, Router,
+};
+use hr_common::service_registry::{ServiceAction, ServiceControlCommand, ServiceStatus};
+use serde::Serialize;
+use utoipa::ToSchema;
 
+use crate::error::{ApiError, ApiResult, ErrorCode};
 use crate::state::ApiState;
 
 pub fn router() -> Router<ApiState> {
-    Router::new().route("/status", get(status))
+    Router::new()
+        .route("/", get(status))
+        .route("/status", get(status))
+        .route("/{name}", get(service))
+        .route("/{name}/{action}", post(control))
 }
 
-async fn status(State(state): State<ApiState>) -> Json<Value> {
+#[derive(Serialize, ToSchema)]
+pub struct ServicesResponse {
+    pub success: bool,
+    #[schema(value_type = Vec<Object>)]
+    pub services: Vec<ServiceStatus>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ServiceResponse {
+    pub success: bool,
+    /// State after the request.
+    #[schema(value_type = Object)]
+    pub service: Option<ServiceStatus>,
+}
+
+/// Supervised services with their dependencies and restart history.
+#[utoipa::path(get, path = "/api/services", responses((status = 200, body = ServicesResponse)))]
+async fn status(State(state): State<ApiState>) -> ApiResult<ServicesResponse> {
     let registry = state.service_registry.read().await;
 
     let mut services: Vec<_> = registry.values().cloned().collect();
@@ -15,8 +43,72 @@ async fn status(State(state): State<ApiState>) -> Json<Value> {
         a.priority.cmp(&b.priority).then(a.name.cmp(&b.name))
     });
 
-    Json(json!({
-        "success": true,
-        "services": services
-    }))
+    Ok(Json(ServicesResponse { success: true, services }))
+}
+
+/// One supervised service.
+#[utoipa::path(
+    get,
+    path = "/api/services/{name}",
+    params(("name" = String, Path)),
+    responses((status = 200, body = ServiceResponse), (status = 404, body = ApiError))
+)]
+async fn service(State(state): State<ApiState>, Path(name): Path<String>) -> ApiResult<ServiceResponse> {
+    match state.service_registry.read().await.get(&name) {
+        Some(service) => Ok(Json(ServiceResponse { success: true, service: Some(service.clone()) })),
+        None => Err(ApiError::not_found(format!("Unknown service: {}", name))),
+    }
+}
+
+/// Start, stop or restart one supervised service.
+#[utoipa::path(
+    post,
+    path = "/api/services/{name}/{action}",
+    params(("name" = String, Path), ("action" = String, Path, description = "`start`, `stop` or `restart`")),
+    responses(
+        (status = 200, body = ServiceResponse),
+        (status = 400, description = "Unknown action", body = ApiError),
+        (status = 404, body = ApiError),
+        (status = 409, description = "Refused by the supervisor", body = ApiError),
+        (status = 503, description = "Supervisor not running", body = ApiError)
+    )
+)]
+async fn control(
+    State(state): State<ApiState>,
+    Path((name, action)): Path<(String, String)>,
+) -> ApiResult<ServiceResponse> {
+    let Some(tx) = &state.service_control_tx else {
+        return Err(ApiError::unavailable("Service control unavailable"));
+    };
+    let action = match action.as_str() {
+        "start" => ServiceAction::Start,
+        "stop" => ServiceAction::Stop,
+        "restart" => ServiceAction::Restart,
+        _ => return Err(ApiError::bad_request(format!("Unknown action: {action}"))),
+    };
+    if !state.service_registry.read().await.contains_key(&name) {
+        return Err(ApiError::not_found(format!("Unknown service: {}", name)));
+    }
+    if name == "api" && action == ServiceAction::Stop {
+        return Err(ApiError::conflict("The API cannot stop itself"));
+    }
+
+    tracing::info!(service = %name, ?action, "Service action");
+    let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+    let cmd = ServiceControlCommand {
+        name: name.clone(),
+        action,
+        response_tx,
+    };
+    if tx.send(cmd).await.is_err() {
+        return Err(ApiError::unavailable("Supervisor not running"));
+    }
+    match response_rx.await {
+        Ok(Ok(())) => {
+            let service = state.service_registry.read().await.get(&name).cloned();
+            Ok(Json(ServiceResponse { success: true, service }))
+        }
+        Ok(Err(e)) => Err(ApiError::new(ErrorCode::Conflict, e)),
+        Err(_) => Err(ApiError::unavailable("Supervisor not running")),
+    }
 }
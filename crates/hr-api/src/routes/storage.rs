@@ -0,0 +1,203 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use axum_extra::extract::CookieJar;
+use hr_s3::{DeleteBucket, S3Config};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::routes::auth::admin_user;
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/", get(get_storage).put(update_storage))
+        .route("/buckets", get(list_buckets).post(create_bucket))
+        .route("/buckets/{name}", delete(delete_bucket))
+        .route("/keys", get(list_keys).post(create_key))
+        .route("/keys/{id}", delete(delete_key))
+}
+
+type ApiResult = (StatusCode, Json<Value>);
+
+fn failure(status: StatusCode, error: impl std::fmt::Display) -> ApiResult {
+    (status, Json(json!({"success": false, "error": error.to_string()})))
+}
+
+/// Run an object store call on the blocking pool.
+async fn blocking<T: Send + 'static>(
+    state: &ApiState,
+    f: impl FnOnce(&hr_s3::ObjectStore) -> anyhow::Result<T> + Send + 'static,
+) -> Result<T, ApiResult> {
+    let store = state.s3.store();
+    match tokio::task::spawn_blocking(move || f(&store)).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(failure(StatusCode::INTERNAL_SERVER_ERROR, e)),
+        Err(e) => Err(failure(StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+/// The app must be known to the registry (when it runs).
+async fn check_app(state: &ApiState, app: &str) -> Result<(), ApiResult> {
+    if let Some(registry) = &state.registry
+        && !registry.list_applications().await.iter().any(|a| a.slug == app)
+    {
+        return Err(failure(StatusCode::BAD_REQUEST, format!("Unknown application: {}", app)));
+    }
+    Ok(())
+}
+
+async fn get_storage(State(state): State<ApiState>, jar: CookieJar) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    let buckets = match blocking(&state, |store| store.buckets(None)).await {
+        Ok(b) => b,
+        Err(e) => return e,
+    };
+    (
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "config": state.s3.config(),
+            "status": state.s3.status(),
+            "buckets": buckets,
+        })),
+    )
+}
+
+/// Validate, persist to s3-config.json, then apply (the S3 listener is
+/// rebound).
+async fn update_storage(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Json(config): Json<S3Config>,
+) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    if let Err(e) = config.validate() {
+        return failure(StatusCode::BAD_REQUEST, e);
+    }
+    let path = state.env.s3_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return failure(StatusCode::INTERNAL_SERVER_ERROR, format!("Write failed: {}", e)),
+        Err(e) => return failure(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+    state.s3.apply(config);
+    (StatusCode::OK, Json(json!({"success": true})))
+}
+
+async fn list_buckets(State(state): State<ApiState>, jar: CookieJar) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    match blocking(&state, |store| store.buckets(None)).await {
+        Ok(buckets) => (StatusCode::OK, Json(json!({"success": true, "buckets": buckets}))),
+        Err(e) => e,
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateBucketRequest {
+    app: String,
+    name: String,
+}
+
+async fn create_bucket(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Json(req): Json<CreateBucketRequest>,
+) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    if !hr_s3::valid_bucket_name(&req.name) {
+        return failure(StatusCode::BAD_REQUEST, "Invalid bucket name (3-63 characters: a-z, 0-9, '.', '-')");
+    }
+    if let Err(e) = check_app(&state, &req.app).await {
+        return e;
+    }
+    let max = state.s3.config().max_buckets_per_app as usize;
+    let app = req.app.clone();
+    let created = blocking(&state, move |store| {
+        if store.buckets(Some(&app))?.len() >= max {
+            return Ok(None);
+        }
+        Ok(Some(store.create_bucket(&app, &req.name)?))
+    })
+    .await;
+    match created {
+        Ok(Some(true)) => (StatusCode::OK, Json(json!({"success": true}))),
+        Ok(Some(false)) => failure(StatusCode::CONFLICT, "Bucket name already taken"),
+        Ok(None) => failure(StatusCode::BAD_REQUEST, format!("At most {} buckets per application", max)),
+        Err(e) => e,
+    }
+}
+
+/// Only empty buckets are deleted.
+async fn delete_bucket(State(state): State<ApiState>, jar: CookieJar, Path(name): Path<String>) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    match blocking(&state, move |store| store.delete_bucket(&name)).await {
+        Ok(DeleteBucket::Deleted) => (StatusCode::OK, Json(json!({"success": true}))),
+        Ok(DeleteBucket::NotFound) => failure(StatusCode::NOT_FOUND, "Bucket not found"),
+        Ok(DeleteBucket::NotEmpty) => failure(StatusCode::CONFLICT, "Bucket is not empty"),
+        Err(e) => e,
+    }
+}
+
+async fn list_keys(State(state): State<ApiState>, jar: CookieJar) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    match blocking(&state, |store| store.keys(None)).await {
+        Ok(keys) => (StatusCode::OK, Json(json!({"success": true, "keys": keys}))),
+        Err(e) => e,
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateKeyRequest {
+    app: String,
+    #[serde(default)]
+    description: String,
+}
+
+/// New access key for an app; the secret is only returned here.
+async fn create_key(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Json(req): Json<CreateKeyRequest>,
+) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    if let Err(e) = check_app(&state, &req.app).await {
+        return e;
+    }
+    match blocking(&state, move |store| store.create_key(&req.app, &req.description)).await {
+        Ok(key) => (
+            StatusCode::OK,
+            Json(json!({"success": true, "key": key, "secret": key.secret})),
+        ),
+        Err(e) => e,
+    }
+}
+
+async fn delete_key(State(state): State<ApiState>, jar: CookieJar, Path(id): Path<String>) -> ApiResult {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e;
+    }
+    match blocking(&state, move |store| store.delete_key(&id)).await {
+        Ok(true) => (StatusCode::OK, Json(json!({"success": true}))),
+        Ok(false) => failure(StatusCode::NOT_FOUND, "Access key not found"),
+        Err(e) => e,
+    }
+}
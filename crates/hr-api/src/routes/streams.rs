@@ -0,0 +1,102 @@
+use axum::{
+    extract::{Path, State},
+    routing::get,
+    Json, Router,
+};
+use hr_stream::{StreamConfig, StreamRule};
+use serde_json::{json, Value};
+
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/", get(list_streams).post(create_stream))
+        .route("/{id}", get(get_stream).put(update_stream).delete(delete_stream))
+}
+
+fn stream_json(state: &ApiState, rule: &StreamRule) -> Value {
+    json!({
+        "rule": rule,
+        "stats": state.streams.stats(&rule.id),
+    })
+}
+
+async fn list_streams(State(state): State<ApiState>) -> Json<Value> {
+    let config = state.streams.config();
+    let streams: Vec<Value> = config
+        .streams
+        .iter()
+        .map(|r| stream_json(&state, r))
+        .collect();
+    Json(json!({"success": true, "streams": streams}))
+}
+
+async fn get_stream(State(state): State<ApiState>, Path(id): Path<String>) -> Json<Value> {
+    let config = state.streams.config();
+    match config.streams.iter().find(|r| r.id == id) {
+        Some(rule) => Json(json!({"success": true, "stream": stream_json(&state, rule)})),
+        None => Json(json!({"success": false, "error": "Stream introuvable"})),
+    }
+}
+
+async fn create_stream(
+    State(state): State<ApiState>,
+    Json(mut rule): Json<StreamRule>,
+) -> Json<Value> {
+    if rule.id.trim().is_empty() {
+        rule.id = uuid::Uuid::new_v4().to_string();
+    }
+    let mut config = state.streams.config();
+    if config.streams.iter().any(|r| r.id == rule.id) {
+        return Json(json!({"success": false, "error": "Un stream avec cet id existe déjà"}));
+    }
+    config.streams.push(rule.clone());
+    if let Err(e) = save_and_apply(&state, config).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+    Json(json!({"success": true, "stream": rule}))
+}
+
+async fn update_stream(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(mut rule): Json<StreamRule>,
+) -> Json<Value> {
+    rule.id = id.clone();
+    let mut config = state.streams.config();
+    let Some(existing) = config.streams.iter_mut().find(|r| r.id == id) else {
+        return Json(json!({"success": false, "error": "Stream introuvable"}));
+    };
+    *existing = rule.clone();
+    if let Err(e) = save_and_apply(&state, config).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+    Json(json!({"success": true, "stream": rule}))
+}
+
+async fn delete_stream(State(state): State<ApiState>, Path(id): Path<String>) -> Json<Value> {
+    let mut config = state.streams.config();
+    let before = config.streams.len();
+    config.streams.retain(|r| r.id != id);
+    if config.streams.len() == before {
+        return Json(json!({"success": false, "error": "Stream introuvable"}));
+    }
+    if let Err(e) = save_and_apply(&state, config).await {
+        return Json(json!({"success": false, "error": e}));
+    }
+    Json(json!({"success": true}))
+}
+
+/// Validate, persist to streams-config.json, then hand the new config to the
+/// running stream proxy (listeners are reconciled without a restart).
+async fn save_and_apply(state: &ApiState, config: StreamConfig) -> Result<(), String> {
+    config.validate()?;
+    let path = state.env.streams_config_path.clone();
+    let to_save = config.clone();
+    tokio::task::spawn_blocking(move || to_save.save_to_file(&path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Write failed: {}", e))?;
+    state.streams.apply(config);
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use hr_syslog::{LogQuery, SyslogConfig};
+use serde_json::{json, Value};
+
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/", get(get_syslog).put(update_syslog))
+        .route("/messages", get(list_messages).delete(clear_messages))
+        .route("/sources", get(list_sources))
+}
+
+async fn get_syslog(State(state): State<ApiState>) -> Json<Value> {
+    let store = state.syslog.store();
+    let stored = tokio::task::spawn_blocking(move || store.count().ok())
+        .await
+        .ok()
+        .flatten();
+    Json(json!({
+        "success": true,
+        "config": state.syslog.config(),
+        "status": state.syslog.status(),
+        "stored": stored,
+    }))
+}
+
+/// Validate, persist to syslog-config.json, then hand the new config to the
+/// running receiver (listeners are rebound without a restart).
+async fn update_syslog(
+    State(state): State<ApiState>,
+    Json(config): Json<SyslogConfig>,
+) -> Json<Value> {
+    if let Err(e) = config.validate() {
+        return Json(json!({"success": false, "error": e}));
+    }
+    let path = state.env.syslog_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Json(json!({"success": false, "error": format!("Write failed: {}", e)})),
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    }
+    state.syslog.apply(config.clone());
+    Json(json!({"success": true, "config": config}))
+}
+
+async fn list_messages(
+    State(state): State<ApiState>,
+    Query(query): Query<LogQuery>,
+) -> Json<Value> {
+    let store = state.syslog.store();
+    match tokio::task::spawn_blocking(move || store.query(&query)).await {
+        Ok(Ok(messages)) => Json(json!({"success": true, "messages": messages})),
+        Ok(Err(e)) => Json(json!({"success": false, "error": e.to_string()})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
+
+async fn clear_messages(State(state): State<ApiState>) -> Json<Value> {
+    let store = state.syslog.store();
+    match tokio::task::spawn_blocking(move || store.clear()).await {
+        Ok(Ok(deleted)) => Json(json!({"success": true, "deleted": deleted})),
+        Ok(Err(e)) => Json(json!({"success": false, "error": e.to_string()})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
+
+/// Devices that sent logs, with the inventory name matched at reception.
+async fn list_sources(State(state): State<ApiState>) -> Json<Value> {
+    let store = state.syslog.store();
+    match tokio::task::spawn_blocking(move || store.sources()).await {
+        Ok(Ok(sources)) => Json(json!({"success": true, "sources": sources})),
+        Ok(Err(e)) => Json(json!({"success": false, "error": e.to_string()})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
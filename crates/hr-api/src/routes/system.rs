@@ -0,0 +1,211 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use axum::{extract::State, routing::{get, post}, Json, Router};
+use hr_dns::packet::{build_response, encode_name, parse_query, RCODE_NOERROR};
+use hr_dns::records::DnsRecord;
+use ring::{aead, digest};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::routes::metrics::refresh_gauges;
+use crate::state::ApiState;
+
+/// Time spent on each measurement of the self-test.
+const BUDGET: Duration = Duration::from_millis(250);
+
+/// Only one self-test at a time: concurrent runs would skew each other.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/benchmark", post(benchmark))
+        .route("/selfmon", get(selfmon))
+}
+
+/// Quick self-test on the actual hardware: the CPU-bound hot paths (DNS
+/// wire format, blocklist lookups, TLS/tunnel crypto) and the loopback
+/// network stack. Takes about a second and a half.
+async fn benchmark(State(state): State<ApiState>) -> Json<Value> {
+    if RUNNING.swap(true, Ordering::AcqRel) {
+        return Json(json!({"success": false, "error": "A benchmark is already running"}));
+    }
+    let started = Instant::now();
+    let adblock = state.adblock.clone();
+    let cpu = tokio::task::spawn_blocking(move || {
+        let engine = adblock.blocking_read();
+        json!({
+            "dnsPacketsPerSec": dns_packets(),
+            "adblockLookupsPerSec": adblock_lookups(&engine),
+            "adblockDomains": engine.domain_count(),
+            "sha256MBps": sha256_mbps(),
+            "aesGcmMBps": aes_gcm_mbps(),
+        })
+    })
+    .await;
+    let tcp = loopback_tcp_mbps().await;
+    RUNNING.store(false, Ordering::Release);
+
+    let mut results = match cpu {
+        Ok(results) => results,
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    };
+    match tcp {
+        Ok(mbps) => results["loopbackTcpMBps"] = json!(mbps),
+        Err(e) => return Json(json!({"success": false, "error": format!("Loopback test failed: {}", e)})),
+    }
+    Json(json!({
+        "success": true,
+        "cpus": std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        "durationMs": started.elapsed().as_millis() as u64,
+        "results": results,
+    }))
+}
+
+/// Current self-monitoring gauges (memory, FDs, tasks, event bus) and the
+/// series the leak watch currently flags.
+async fn selfmon(State(state): State<ApiState>) -> Json<Value> {
+    refresh_gauges(&state).await;
+    let mut current = serde_json::Map::new();
+    let mut suspected = Vec::new();
+    for (name, value) in hr_common::metrics::registry().samples() {
+        if let Some(series) = name
+            .strip_prefix("homeroute_leak_suspected{series=\"")
+            .and_then(|s| s.strip_suffix("\"}"))
+        {
+            if value > 0.0 {
+                suspected.push(series.replace("\\\"", "\""));
+            }
+        } else if SELFMON_PREFIXES.iter().any(|p| name.starts_with(p)) {
+            current.insert(name, json!(value));
+        }
+    }
+    Json(json!({"success": true, "current": current, "leakSuspected": suspected}))
+}
+
+const SELFMON_PREFIXES: &[&str] = &[
+    "homeroute_process_",
+    "homeroute_tokio_",
+    "homeroute_tasks",
+    "homeroute_event_",
+];
+
+/// Repeat `op` for `BUDGET` and return the units it reported per second.
+fn per_second(mut op: impl FnMut() -> u64) -> f64 {
+    let started = Instant::now();
+    let mut units = 0u64;
+    while started.elapsed() < BUDGET {
+        units += op();
+    }
+    (units as f64 / started.elapsed().as_secs_f64()).round()
+}
+
+fn mbps(bytes_per_sec: f64) -> f64 {
+    (bytes_per_sec / 1e6 * 10.0).round() / 10.0
+}
+
+/// Parse a query and encode its answer, as the DNS server does per packet.
+fn dns_packets() -> f64 {
+    let mut raw = vec![0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+    encode_name("www.example.com", &mut raw);
+    raw.extend_from_slice(&[0x00, 0x01, 0x00, 0x01]);
+    let answers = [DnsRecord::a("www.example.com", [192, 0, 2, 1].into(), 300)];
+    per_second(|| {
+        let Ok(query) = parse_query(std::hint::black_box(&raw)) else {
+            return 0;
+        };
+        std::hint::black_box(build_response(&query, &answers, RCODE_NOERROR));
+        1
+    })
+}
+
+/// Lookups against the live blocklist, mixing subdomains and misses.
+fn adblock_lookups(engine: &hr_adblock::AdblockEngine) -> f64 {
+    const NAMES: [&str; 4] = [
+        "www.example.com",
+        "cdn.eu.ads.doubleclick.net",
+        "tracker.analytics.example.org",
+        "api.github.com",
+    ];
+    let mut i = 0;
+    per_second(|| {
+        std::hint::black_box(engine.is_blocked(NAMES[i % NAMES.len()]));
+        i += 1;
+        1
+    })
+}
+
+fn sha256_mbps() -> f64 {
+    let data = vec![0x5au8; 64 * 1024];
+    mbps(per_second(|| {
+        std::hint::black_box(digest::digest(&digest::SHA256, &data));
+        data.len() as u64
+    }))
+}
+
+/// AES-256-GCM on 16 KiB records, the TLS and QUIC bulk cipher.
+fn aes_gcm_mbps() -> f64 {
+    let Ok(unbound) = aead::UnboundKey::new(&aead::AES_256_GCM, &[7u8; 32]) else {
+        return 0.0;
+    };
+    let key = aead::LessSafeKey::new(unbound);
+    let mut record = vec![0u8; 16 * 1024];
+    let mut counter = 0u64;
+    mbps(per_second(|| {
+        counter += 1;
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+        let nonce = aead::Nonce::assume_unique_for_key(nonce);
+        match key.seal_in_place_separate_tag(nonce, aead::Aad::empty(), &mut record) {
+            Ok(_) => record.len() as u64,
+            Err(_) => 0,
+        }
+    }))
+}
+
+/// Stream bytes through a 127.0.0.1 TCP connection for `BUDGET`.
+async fn loopback_tcp_mbps() -> anyhow::Result<f64> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let sink = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await?;
+        let mut buf = vec![0u8; 256 * 1024];
+        let mut total = 0u64;
+        loop {
+            let n = socket.read(&mut buf).await?;
+            if n == 0 {
+                return Ok::<_, std::io::Error>(total);
+            }
+            total += n as u64;
+        }
+    });
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    let chunk = vec![0u8; 256 * 1024];
+    let started = Instant::now();
+    while started.elapsed() < BUDGET {
+        stream.write_all(&chunk).await?;
+    }
+    stream.shutdown().await?;
+    let total = sink.await??;
+    Ok(mbps(total as f64 / started.elapsed().as_secs_f64()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_measurements_are_positive() {
+        assert!(dns_packets() > 0.0);
+        assert!(sha256_mbps() > 0.0);
+        assert!(aes_gcm_mbps() > 0.0);
+        let engine = hr_adblock::AdblockEngine::new();
+        assert!(adblock_lookups(&engine) > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_loopback_tcp() {
+        assert!(loopback_tcp_mbps().await.unwrap() > 0.0);
+    }
+}
@@ -0,0 +1,140 @@
+//! Container templates, under `/api/applications/templates`.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use hr_container::template::TemplateSource;
+use hr_container::ContainerTemplate;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::state::ApiState;
+use crate::templates::TemplateCatalog;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/templates", get(list_templates).post(save_template))
+        .route(
+            "/templates/{template_id}",
+            get(get_template).put(update_template).delete(delete_template),
+        )
+        .route("/templates/{template_id}/fetch", post(fetch_template))
+}
+
+type ApiResult = (StatusCode, Json<Value>);
+
+fn failure(status: StatusCode, error: impl std::fmt::Display) -> ApiResult {
+    (status, Json(json!({"success": false, "error": error.to_string()})))
+}
+
+fn catalog(state: &ApiState) -> Result<&TemplateCatalog, ApiResult> {
+    match &state.container_manager {
+        Some(cm) => Ok(&cm.templates),
+        None => Err(failure(StatusCode::SERVICE_UNAVAILABLE, "Container manager not available")),
+    }
+}
+
+/// A template with the checksum of its cached tarball.
+fn template_json(catalog: &TemplateCatalog, template: &ContainerTemplate) -> Value {
+    let mut value = json!(template);
+    value["cachedSha256"] = json!(catalog.cache().cached(&template.id));
+    value
+}
+
+async fn list_templates(State(state): State<ApiState>) -> ApiResult {
+    let catalog = match catalog(&state) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+    let templates: Vec<Value> = catalog.list().iter().map(|t| template_json(catalog, t)).collect();
+    (StatusCode::OK, Json(json!({"success": true, "templates": templates})))
+}
+
+async fn get_template(State(state): State<ApiState>, Path(template_id): Path<String>) -> ApiResult {
+    let catalog = match catalog(&state) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+    match catalog.get(&template_id) {
+        Some(template) => (StatusCode::OK, Json(json!({"success": true, "template": template_json(catalog, &template)}))),
+        None => failure(StatusCode::NOT_FOUND, "Template not found"),
+    }
+}
+
+async fn save_template(State(state): State<ApiState>, Json(template): Json<ContainerTemplate>) -> ApiResult {
+    let catalog = match catalog(&state) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+    if catalog.get(&template.id).is_some() {
+        return failure(StatusCode::CONFLICT, "A template with this id already exists");
+    }
+    match catalog.save(template).await {
+        Ok(template) => (StatusCode::CREATED, Json(json!({"success": true, "template": template_json(catalog, &template)}))),
+        Err(e) => failure(StatusCode::BAD_REQUEST, e),
+    }
+}
+
+async fn update_template(
+    State(state): State<ApiState>,
+    Path(template_id): Path<String>,
+    Json(mut template): Json<ContainerTemplate>,
+) -> ApiResult {
+    let catalog = match catalog(&state) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+    if catalog.get(&template_id).is_none() {
+        return failure(StatusCode::NOT_FOUND, "Template not found");
+    }
+    template.id = template_id;
+    match catalog.save(template).await {
+        Ok(template) => (StatusCode::OK, Json(json!({"success": true, "template": template_json(catalog, &template)}))),
+        Err(e) => failure(StatusCode::BAD_REQUEST, e),
+    }
+}
+
+async fn delete_template(State(state): State<ApiState>, Path(template_id): Path<String>) -> ApiResult {
+    let catalog = match catalog(&state) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+    match catalog.delete(&template_id).await {
+        Ok(true) => (StatusCode::OK, Json(json!({"success": true}))),
+        Ok(false) => failure(StatusCode::NOT_FOUND, "Template not found"),
+        Err(e) => failure(StatusCode::BAD_REQUEST, e),
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct FetchRequest {
+    /// Download again and pin the new checksum (moving URLs).
+    #[serde(default)]
+    refresh: bool,
+}
+
+/// Download (or check) the tarball of a template ahead of a first container.
+async fn fetch_template(
+    State(state): State<ApiState>,
+    Path(template_id): Path<String>,
+    body: Option<Json<FetchRequest>>,
+) -> ApiResult {
+    let catalog = match catalog(&state) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+    let Some(template) = catalog.get(&template_id) else {
+        return failure(StatusCode::NOT_FOUND, "Template not found");
+    };
+    if !matches!(template.source, TemplateSource::Tarball { .. }) {
+        return failure(StatusCode::BAD_REQUEST, "Template is built with debootstrap, nothing to download");
+    }
+    let refresh = body.map(|Json(b)| b.refresh).unwrap_or_default();
+    match catalog.cache().fetch(&template, refresh).await {
+        Ok((_, sha256)) => (StatusCode::OK, Json(json!({"success": true, "sha256": sha256}))),
+        Err(e) => failure(StatusCode::BAD_GATEWAY, e),
+    }
+}
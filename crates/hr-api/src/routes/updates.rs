@@ -1,14 +1,24 @@
 use axum::{
-    extract::State,
-    routing::{get, post},
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
     Json, Router,
 };
+use axum_extra::extract::CookieJar;
+use base64::Engine;
 use hr_common::events::UpdateEvent;
+use hr_registry::binaries::{normalize_arch, BinaryStore};
+use hr_registry::protocol::{HostRegistryMessage, PackageStatus};
+use hr_registry::update::SignedManifest;
+use serde::Deserialize;
 use serde_json::{json, Value};
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::sync::broadcast;
 use tracing::error;
 
+use crate::config_backup;
+use crate::routes::auth::admin_user;
 use crate::state::ApiState;
 
 static CHECK_RUNNING: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
@@ -26,6 +36,110 @@ pub fn router() -> Router<ApiState> {
         .route("/upgrade/apt-full", post(upgrade_apt_full))
         .route("/upgrade/snap", post(upgrade_snap))
         .route("/upgrade/cancel", post(cancel_upgrade))
+        // Remote hosts (through their agent)
+        .route("/hosts", get(list_host_updates))
+        .route("/hosts/{id}/check", post(check_host_updates))
+        .route("/hosts/{id}/upgrade", get(host_upgrade_status).post(upgrade_host))
+        // Agent binaries, per component and architecture
+        .route("/agents", get(list_agent_binaries))
+        .route(
+            "/agents/{component}/{arch}",
+            put(upload_agent_binary).delete(delete_agent_binary).layer(DefaultBodyLimit::max(256 * 1024 * 1024)),
+        )
+        // Release key the agent update manifests must be signed with
+        .route("/agents/key", get(agent_update_key))
+        // Configuration backup
+        .route("/backup", post(create_backup))
+        .route("/restore", post(restore_backup).layer(DefaultBodyLimit::max(64 * 1024 * 1024)))
+}
+
+/// Public key to pin as `update_key` in the configs of agents installed
+/// before manifests were signed. Read only: the key is installed with
+/// HomeRoute, its private half never is.
+async fn agent_update_key() -> Json<Value> {
+    match hr_registry::update::update_key() {
+        Ok(key) => Json(json!({"success": true, "update_key": key})),
+        Err(e) => Json(json!({"success": false, "error": e})),
+    }
+}
+
+/// Stored agent binaries with their checksum.
+async fn list_agent_binaries() -> Json<Value> {
+    let artifacts = tokio::task::spawn_blocking(|| BinaryStore::default().list())
+        .await
+        .unwrap_or_default();
+    Json(json!({"success": true, "artifacts": artifacts}))
+}
+
+/// Binary and signed manifest of an upload (multipart fields `binary` and
+/// `manifest`).
+async fn read_upload(mut multipart: Multipart) -> Result<(axum::body::Bytes, SignedManifest), String> {
+    let (mut binary, mut manifest) = (None, None);
+    while let Some(field) = multipart.next_field().await.map_err(|e| format!("Invalid upload: {}", e))? {
+        match field.name() {
+            Some("binary") => binary = Some(field.bytes().await.map_err(|e| format!("Invalid upload: {}", e))?),
+            Some("manifest") => {
+                let data = field.bytes().await.map_err(|e| format!("Invalid upload: {}", e))?;
+                manifest = Some(serde_json::from_slice(&data).map_err(|e| format!("Invalid manifest: {}", e))?);
+            }
+            _ => {}
+        }
+    }
+    match (binary, manifest) {
+        (Some(binary), Some(manifest)) => Ok((binary, manifest)),
+        (None, _) => Err("Missing binary".to_string()),
+        (_, None) => Err("Missing manifest: binaries are only accepted with their signed release manifest".to_string()),
+    }
+}
+
+/// Store the binary of `component` for `arch` with its manifest, signed at
+/// release time by the key of [`hr_registry::update::UPDATE_KEY_PATH`].
+async fn upload_agent_binary(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path((component, arch)): Path<(String, String)>,
+    multipart: Multipart,
+) -> Response {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e.into_response();
+    }
+    let (body, manifest) = match read_upload(multipart).await {
+        Ok(upload) => upload,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({"success": false, "error": e}))).into_response(),
+    };
+    let update_key = match hr_registry::update::update_key() {
+        Ok(key) => key,
+        Err(e) => return (StatusCode::CONFLICT, Json(json!({"success": false, "error": e}))).into_response(),
+    };
+    let arch = normalize_arch(&arch).map(str::to_string).unwrap_or(arch);
+    let stored = tokio::task::spawn_blocking(move || {
+        BinaryStore::default().store(&component, &arch, &body, &manifest, &update_key)
+    })
+    .await
+    .unwrap_or_else(|e| Err(format!("Upload failed: {}", e)));
+    match stored {
+        Ok(artifact) => {
+            tracing::info!(component = artifact.component, arch = artifact.arch, version = artifact.version, sha256 = artifact.sha256, "Signed agent binary uploaded");
+            Json(json!({"success": true, "artifact": artifact})).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, Json(json!({"success": false, "error": e}))).into_response(),
+    }
+}
+
+async fn delete_agent_binary(
+    State(state): State<ApiState>,
+    jar: CookieJar,
+    Path((component, arch)): Path<(String, String)>,
+) -> Response {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e.into_response();
+    }
+    let arch = normalize_arch(&arch).map(str::to_string).unwrap_or(arch);
+    match BinaryStore::default().remove(&component, &arch) {
+        Ok(true) => Json(json!({"success": true})).into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, Json(json!({"success": false, "error": "Binary not found"}))).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"success": false, "error": e}))).into_response(),
+    }
 }
 
 const LAST_CHECK_PATH: &str = "/var/lib/server-dashboard/last-update-check.json";
@@ -296,6 +410,93 @@ async fn cancel_upgrade(State(state): State<ApiState>) -> Json<Value> {
     Json(json!({"success": true}))
 }
 
+/// Pending updates and last upgrade of every host.
+async fn list_host_updates(State(state): State<ApiState>) -> Json<Value> {
+    let data = crate::routes::hosts::load_hosts().await;
+    let upgrades = state.host_upgrades.read().await;
+    let mut hosts = Vec::new();
+    for host in data.get("hosts").and_then(|h| h.as_array()).into_iter().flatten() {
+        let Some(id) = host.get("id").and_then(|i| i.as_str()) else {
+            continue;
+        };
+        let status: Option<PackageStatus> =
+            host.get("package_updates").and_then(|s| serde_json::from_value(s.clone()).ok());
+        let online = match &state.registry {
+            Some(registry) => registry.is_host_connected(id).await,
+            None => false,
+        };
+        let upgrade = upgrades.get(id).map(|u| {
+            json!({
+                "upgradeId": u.upgrade_id,
+                "running": u.running(),
+                "startedAt": u.started_at,
+                "finishedAt": u.finished_at,
+                "success": u.success,
+                "error": u.error,
+                "snapshot": u.snapshot,
+                "rebooting": u.rebooting,
+            })
+        });
+        hosts.push(json!({
+            "id": id,
+            "name": host.get("name"),
+            "online": online,
+            "manager": status.as_ref().map(|s| s.manager.clone()),
+            "updateCount": status.as_ref().map_or(0, |s| s.updates.len()),
+            "securityCount": status.as_ref().map_or(0, |s| s.updates.iter().filter(|u| u.security).count()),
+            "rebootRequired": status.as_ref().is_some_and(|s| s.reboot_required),
+            "checkedAt": status.as_ref().and_then(|s| chrono::DateTime::from_timestamp(s.checked_at, 0)),
+            "updates": status.map(|s| s.updates).unwrap_or_default(),
+            "upgrade": upgrade,
+        }));
+    }
+    Json(json!({"success": true, "hosts": hosts}))
+}
+
+/// Ask a host to refresh its package lists; the result arrives through its
+/// next report.
+async fn check_host_updates(Path(id): Path<String>, State(state): State<ApiState>) -> Json<Value> {
+    let Some(registry) = &state.registry else {
+        return Json(json!({"success": false, "error": "No registry"}));
+    };
+    match registry.send_host_command(&id, HostRegistryMessage::CheckPackageUpdates).await {
+        Ok(()) => Json(json!({"success": true, "message": "Verification lancee"})),
+        Err(e) => Json(json!({"success": false, "error": e})),
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct HostUpgradeRequest {
+    /// dist-upgrade (apt) or --allowerasing (dnf).
+    #[serde(default)]
+    full: bool,
+    /// Snapshot the root filesystem first.
+    #[serde(default)]
+    snapshot: bool,
+    /// Reboot afterwards if the upgrade requires it.
+    #[serde(default)]
+    reboot: bool,
+}
+
+async fn upgrade_host(
+    Path(id): Path<String>,
+    State(state): State<ApiState>,
+    body: Option<Json<HostUpgradeRequest>>,
+) -> Json<Value> {
+    let request = body.map(|Json(b)| b).unwrap_or_default();
+    match crate::host_updates::start(&state, &id, request.full, request.snapshot, request.reboot).await {
+        Ok(upgrade) => Json(json!({"success": true, "upgradeId": upgrade.upgrade_id})),
+        Err(e) => Json(json!({"success": false, "error": e})),
+    }
+}
+
+async fn host_upgrade_status(Path(id): Path<String>, State(state): State<ApiState>) -> Json<Value> {
+    match state.host_upgrades.read().await.get(&id) {
+        Some(upgrade) => Json(json!({"success": true, "running": upgrade.running(), "upgrade": upgrade})),
+        None => Json(json!({"success": true, "running": false, "upgrade": null})),
+    }
+}
+
 /// Stream command output line by line as UpdateEvent::Output
 async fn stream_command(tx: &broadcast::Sender<UpdateEvent>, cmd: &str, args: &[&str]) {
     let mut child = match tokio::process::Command::new(cmd)
@@ -388,3 +589,79 @@ fn parse_needrestart(output: &str) -> Value {
         "services": services
     })
 }
+
+// ── Configuration backup ──────────────────────────────────────────
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupRequest {
+    passphrase: String,
+    #[serde(default)]
+    include_acme: bool,
+}
+
+/// Encrypted archive of every config, downloaded as an attachment.
+async fn create_backup(State(state): State<ApiState>, jar: CookieJar, Json(req): Json<BackupRequest>) -> Response {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e.into_response();
+    }
+    let backup_state = state.clone();
+    let archive = tokio::task::spawn_blocking(move || {
+        config_backup::create(&backup_state, &req.passphrase, req.include_acme)
+    })
+    .await
+    .unwrap_or_else(|e| Err(format!("Backup failed: {}", e)));
+    match archive {
+        Ok(archive) => {
+            let filename = format!(
+                "homeroute-backup-{}.hrbak",
+                chrono::Local::now().format("%Y%m%d-%H%M")
+            );
+            (
+                [
+                    (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                    (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+                ],
+                archive,
+            )
+                .into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, Json(json!({"success": false, "error": e}))).into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct RestoreRequest {
+    passphrase: String,
+    /// Archive, base64.
+    archive: String,
+}
+
+async fn restore_backup(State(state): State<ApiState>, jar: CookieJar, Json(req): Json<RestoreRequest>) -> Response {
+    if let Err(e) = admin_user(&state, &jar) {
+        return e.into_response();
+    }
+    let archive = match base64::engine::general_purpose::STANDARD.decode(req.archive.trim()) {
+        Ok(archive) => archive,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, Json(json!({"success": false, "error": format!("Invalid archive: {}", e)})))
+                .into_response();
+        }
+    };
+    match config_backup::restore(&state, &archive, &req.passphrase).await {
+        Ok(restored) => {
+            tracing::info!(
+                "Configuration restored from a backup of {} ({} files)",
+                restored.manifest.created_at,
+                restored.manifest.files.len()
+            );
+            Json(json!({
+                "success": true,
+                "manifest": restored.manifest,
+                "restartRequired": restored.restart_required,
+            }))
+            .into_response()
+        }
+        Err(e) => (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({"success": false, "error": e}))).into_response(),
+    }
+}
@@ -0,0 +1,89 @@
+use std::net::IpAddr;
+
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, put},
+    Json, Router,
+};
+use hr_flow::{DeviceUsage, FlowConfig};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::state::ApiState;
+
+pub fn router() -> Router<ApiState> {
+    Router::new()
+        .route("/", get(get_usage).delete(reset_usage))
+        .route("/config", put(update_config))
+        .route("/devices/{ip}", get(device_usage))
+}
+
+#[derive(Deserialize)]
+struct UsageQuery {
+    /// Destinations listed per device.
+    limit: Option<usize>,
+}
+
+/// Device usage with the DHCP lease (hostname, MAC) when there is one.
+async fn with_lease(state: &ApiState, device: &DeviceUsage) -> Value {
+    let dhcp = state.dhcp.read().await;
+    let lease = match device.device {
+        IpAddr::V4(ip) => dhcp.lease_store.get_lease(ip),
+        IpAddr::V6(_) => None,
+    };
+    let mut value = json!(device);
+    value["hostname"] = json!(lease.and_then(|l| l.hostname.clone()));
+    value["mac"] = json!(lease.map(|l| l.mac.clone()));
+    value
+}
+
+/// Per-device totals of forwarded traffic and their top destinations.
+async fn get_usage(State(state): State<ApiState>, Query(query): Query<UsageQuery>) -> Json<Value> {
+    let limit = query.limit.unwrap_or(10);
+    let mut devices = Vec::new();
+    for mut device in state.flows.devices() {
+        device.destinations.truncate(limit);
+        devices.push(with_lease(&state, &device).await);
+    }
+    Json(json!({
+        "success": true,
+        "config": state.flows.config(),
+        "status": state.flows.status(),
+        "devices": devices,
+    }))
+}
+
+/// Every destination of one device.
+async fn device_usage(State(state): State<ApiState>, Path(ip): Path<String>) -> Json<Value> {
+    let Ok(ip) = ip.parse::<IpAddr>() else {
+        return Json(json!({"success": false, "error": "Adresse IP invalide"}));
+    };
+    match state.flows.devices().into_iter().find(|d| d.device == ip) {
+        Some(device) => Json(json!({"success": true, "device": with_lease(&state, &device).await})),
+        None => Json(json!({"success": false, "error": "Aucun trafic pour cet appareil"})),
+    }
+}
+
+async fn reset_usage(State(state): State<ApiState>) -> Json<Value> {
+    state.flows.reset();
+    Json(json!({"success": true}))
+}
+
+/// Validate, persist to flow-config.json, then restart capture with it.
+async fn update_config(
+    State(state): State<ApiState>,
+    Json(config): Json<FlowConfig>,
+) -> Json<Value> {
+    if let Err(e) = config.validate() {
+        return Json(json!({"success": false, "error": e}));
+    }
+    let path = state.env.flow_config_path.clone();
+    let to_save = config.clone();
+    match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Json(json!({"success": false, "error": format!("Write failed: {}", e)})),
+        Err(e) => return Json(json!({"success": false, "error": e.to_string()})),
+    }
+    state.flows.apply(config.clone());
+    Json(json!({"success": true, "config": config}))
+}
@@ -1,6 +1,6 @@
 use axum::{
     extract::{Path, State},
-    routing::{get, put},
+    routing::{get, post, put},
     Json, Router,
 };
 use serde::Deserialize;
@@ -13,6 +13,11 @@ pub fn router() -> Router<ApiState> {
         .route("/", get(list_users).post(create_user))
         .route("/{username}", get(get_user).put(update_user).delete(delete_user))
         .route("/{username}/password", put(change_password))
+        .route(
+            "/{username}/totp",
+            get(totp_status).post(totp_enroll).put(totp_confirm).delete(totp_disable),
+        )
+        .route("/{username}/totp/recovery-codes", post(totp_recovery_codes))
         .route("/groups", get(list_groups))
 }
 
@@ -81,6 +86,66 @@ async fn change_password(
     Json(json!(result))
 }
 
+async fn totp_status(State(state): State<ApiState>, Path(username): Path<String>) -> (axum::http::StatusCode, Json<Value>) {
+    match state.auth.users.totp_status(&username) {
+        Some((enabled, pending, recovery_codes_left)) => (
+            axum::http::StatusCode::OK,
+            Json(json!({
+                "success": true,
+                "enabled": enabled,
+                "pending": pending,
+                "recoveryCodesLeft": recovery_codes_left
+            })),
+        ),
+        None => (axum::http::StatusCode::NOT_FOUND, Json(json!({"success": false, "error": "Utilisateur non trouve"}))),
+    }
+}
+
+/// Nouveau secret TOTP : à scanner puis confirmer avec un premier code (PUT)
+async fn totp_enroll(State(state): State<ApiState>, Path(username): Path<String>) -> Json<Value> {
+    match state.auth.users.start_totp(&username) {
+        Ok(totp) => {
+            let account = format!("{}@{}", username, state.auth.base_domain);
+            Json(json!({
+                "success": true,
+                "secret": totp.secret,
+                "uri": totp.provisioning_uri("HomeRoute", &account)
+            }))
+        }
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
+
+#[derive(Deserialize)]
+struct TotpConfirmRequest {
+    code: String,
+}
+
+async fn totp_confirm(
+    State(state): State<ApiState>,
+    Path(username): Path<String>,
+    Json(body): Json<TotpConfirmRequest>,
+) -> Json<Value> {
+    match state.auth.users.confirm_totp(&username, &body.code) {
+        Ok(codes) => Json(json!({"success": true, "recoveryCodes": codes})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
+
+async fn totp_disable(State(state): State<ApiState>, Path(username): Path<String>) -> Json<Value> {
+    if !state.auth.users.disable_totp(&username) {
+        return Json(json!({"success": false, "error": "TOTP non configure"}));
+    }
+    Json(json!({"success": true}))
+}
+
+async fn totp_recovery_codes(State(state): State<ApiState>, Path(username): Path<String>) -> Json<Value> {
+    match state.auth.users.regenerate_recovery_codes(&username) {
+        Ok(codes) => Json(json!({"success": true, "recoveryCodes": codes})),
+        Err(e) => Json(json!({"success": false, "error": e.to_string()})),
+    }
+}
+
 async fn list_groups(State(state): State<ApiState>) -> Json<Value> {
     // Return predefined groups + custom groups from users
     let users = state.auth.users.get_all();
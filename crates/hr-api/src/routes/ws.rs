@@ -1,14 +1,30 @@
+//! Live events. Messages come from the event journal filled by
+//! `fanout`, with their topic and sequence number. A client that sends no
+//! subscription receives every topic, as before. Otherwise:
+//!
+//! - `{"op": "subscribe", "topics": [...]}`: a `<topic>:snapshot` of each
+//!   topic that has one, then its messages;
+//! - with `"since": seq, "epoch": epoch` (from a previous connection): the
+//!   messages missed since `seq` instead, or a `replay:gap` notice and the
+//!   snapshots when they are no longer kept;
+//! - `{"op": "unsubscribe", "topics": [...]}`.
+
+use std::collections::{HashMap, HashSet};
+
 use axum::{
     extract::{State, WebSocketUpgrade, ws::{Message, WebSocket}},
     response::IntoResponse,
     routing::get,
     Router,
 };
-use serde_json::json;
+use hr_common::events::JournalEntry;
+use hr_common::selfmon;
+use serde::Deserialize;
+use serde_json::{json, Value};
 use tokio::sync::broadcast;
-use tracing::{debug, warn};
+use tracing::debug;
 
-use hr_common::events::MigrationPhase;
+use crate::fanout::{self, TOPICS};
 use crate::state::ApiState;
 
 pub fn router() -> Router<ApiState> {
@@ -22,352 +38,164 @@ async fn ws_handler(
     ws.on_upgrade(move |socket| handle_socket(socket, state))
 }
 
-async fn handle_socket(mut socket: WebSocket, state: ApiState) {
-    debug!("WebSocket client connected");
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum ClientOp {
+    Subscribe {
+        topics: Vec<String>,
+        since: Option<u64>,
+        epoch: Option<u64>,
+    },
+    Unsubscribe {
+        topics: Vec<String>,
+    },
+}
 
-    let mut host_rx = state.events.host_status.subscribe();
-    let mut updates_rx = state.events.updates.subscribe();
-    let mut agent_rx = state.events.agent_status.subscribe();
-    let mut metrics_rx = state.events.agent_metrics.subscribe();
-    let mut service_cmd_rx = state.events.service_command.subscribe();
-    let mut agent_update_rx = state.events.agent_update.subscribe();
-    let mut migration_rx = state.events.migration_progress.subscribe();
-    let mut dv_schema_rx = state.events.dataverse_schema.subscribe();
-    let mut dv_data_rx = state.events.dataverse_data.subscribe();
-    let mut host_metrics_rx = state.events.host_metrics.subscribe();
-    let mut host_power_rx = state.events.host_power.subscribe();
-    let mut cloud_relay_rx = state.events.cloud_relay.subscribe();
+async fn send(socket: &mut WebSocket, msg: Value) -> Result<(), axum::Error> {
+    socket.send(Message::Text(msg.to_string().into())).await
+}
 
-    // Send current active migrations so reconnecting clients get up-to-date state
-    {
-        let migrations = state.migrations.read().await;
-        for m in migrations.values() {
-            if !matches!(m.phase, MigrationPhase::Complete | MigrationPhase::Failed) {
-                let msg = json!({
-                    "type": "migration:progress",
-                    "data": {
-                        "appId": m.app_id,
-                        "transferId": m.transfer_id,
-                        "phase": m.phase,
-                        "progressPct": m.progress_pct,
-                        "bytesTransferred": m.bytes_transferred,
-                        "totalBytes": m.total_bytes,
-                        "error": m.error,
-                    }
-                });
-                if socket.send(Message::Text(msg.to_string().into())).await.is_err() {
-                    debug!("WebSocket client disconnected during migration sync");
-                    return;
-                }
-            }
-        }
+/// The journal message with its topic and sequence number.
+fn message(entry: &JournalEntry) -> Value {
+    let mut msg = entry.message.clone();
+    msg["topic"] = json!(entry.topic);
+    if let Some(seq) = entry.seq {
+        msg["seq"] = json!(seq);
     }
+    msg
+}
 
-    loop {
-        tokio::select! {
-            // Host status events (new)
-            result = host_rx.recv() => {
-                match result {
-                    Ok(event) => {
-                        let msg = json!({
-                            "type": "hosts:status",
-                            "data": {
-                                "hostId": event.host_id,
-                                "online": event.status == "online",
-                                "status": event.status,
-                                "latency": event.latency_ms.unwrap_or(0),
-                                "lastSeen": chrono::Utc::now().to_rfc3339()
-                            }
-                        });
-                        if socket.send(Message::Text(msg.to_string().into())).await.is_err() {
-                            break;
-                        }
-                    }
-                    Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!("WebSocket host_status lagged by {}", n);
-                    }
-                    Err(broadcast::error::RecvError::Closed) => break,
-                }
-            }
-
-            // Host metrics events
-            result = host_metrics_rx.recv() => {
-                match result {
-                    Ok(event) => {
-                        let msg = json!({
-                            "type": "hosts:metrics",
-                            "data": {
-                                "hostId": event.host_id,
-                                "cpuPercent": event.cpu_percent,
-                                "memoryUsedBytes": event.memory_used_bytes,
-                                "memoryTotalBytes": event.memory_total_bytes,
-                            }
-                        });
-                        if socket.send(Message::Text(msg.to_string().into())).await.is_err() {
-                            break;
-                        }
-                    }
-                    Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!("WebSocket host_metrics lagged by {}", n);
-                    }
-                    Err(broadcast::error::RecvError::Closed) => break,
-                }
-            }
-
-            // Host power state events (WOL, shutdown, reboot, suspend transitions)
-            result = host_power_rx.recv() => {
-                match result {
-                    Ok(event) => {
-                        let msg = json!({
-                            "type": "hosts:power",
-                            "data": {
-                                "hostId": event.host_id,
-                                "state": event.state,
-                                "message": event.message,
-                            }
-                        });
-                        if socket.send(Message::Text(msg.to_string().into())).await.is_err() {
-                            break;
-                        }
-                    }
-                    Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!("WebSocket host_power lagged by {}", n);
-                    }
-                    Err(broadcast::error::RecvError::Closed) => break,
-                }
-            }
-
-            // Update events
-            result = updates_rx.recv() => {
-                match result {
-                    Ok(event) => {
-                        use hr_common::events::UpdateEvent;
-                        let msg = match event {
-                            UpdateEvent::Started => json!({"type": "updates:started"}),
-                            UpdateEvent::Phase { phase, message } => json!({"type": "updates:phase", "data": {"phase": phase, "message": message}}),
-                            UpdateEvent::Output { line } => json!({"type": "updates:output", "data": {"line": line}}),
-                            UpdateEvent::AptComplete { packages, security_count } => json!({"type": "updates:apt-complete", "data": {"packages": packages, "securityCount": security_count}}),
-                            UpdateEvent::SnapComplete { snaps } => json!({"type": "updates:snap-complete", "data": {"snaps": snaps}}),
-                            UpdateEvent::NeedrestartComplete(data) => json!({"type": "updates:needrestart-complete", "data": data}),
-                            UpdateEvent::Complete { success, summary, duration } => json!({"type": "updates:complete", "data": {"success": success, "summary": summary, "duration": duration}}),
-                            UpdateEvent::Cancelled => json!({"type": "updates:cancelled"}),
-                            UpdateEvent::Error { error } => json!({"type": "updates:error", "data": {"error": error}}),
-                            UpdateEvent::UpgradeStarted { upgrade_type } => json!({"type": "updates:upgrade-started", "data": {"type": upgrade_type}}),
-                            UpdateEvent::UpgradeOutput { line } => json!({"type": "updates:upgrade-output", "data": {"line": line}}),
-                            UpdateEvent::UpgradeComplete { upgrade_type, success, duration, error } => json!({"type": "updates:upgrade-complete", "data": {"type": upgrade_type, "success": success, "duration": duration, "error": error}}),
-                            UpdateEvent::UpgradeCancelled => json!({"type": "updates:upgrade-cancelled"}),
-                        };
-                        if socket.send(Message::Text(msg.to_string().into())).await.is_err() {
-                            break;
-                        }
-                    }
-                    Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!("WebSocket updates lagged by {}", n);
-                    }
-                    Err(broadcast::error::RecvError::Closed) => break,
-                }
-            }
+fn follows(topics: &Option<HashSet<String>>, topic: &str) -> bool {
+    topics.as_ref().is_none_or(|t| t.contains(topic))
+}
 
-            // Agent status events
-            result = agent_rx.recv() => {
-                match result {
-                    Ok(event) => {
-                        let mut data = json!({
-                            "appId": event.app_id,
-                            "slug": event.slug,
-                            "status": event.status
-                        });
-                        if let Some(message) = &event.message {
-                            data["message"] = json!(message);
-                        }
-                        let msg = json!({
-                            "type": "agent:status",
-                            "data": data
-                        });
-                        if socket.send(Message::Text(msg.to_string().into())).await.is_err() {
-                            break;
-                        }
-                    }
-                    Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!("WebSocket agent_status lagged by {}", n);
-                    }
-                    Err(broadcast::error::RecvError::Closed) => break,
+/// Sequence number up to which each topic was caught up: older live
+/// messages were already sent by a replay or are covered by a snapshot.
+type Floors = HashMap<String, u64>;
+
+/// Bring a client up to date on `topics`: the messages after `since` when
+/// the journal still has them, the snapshots otherwise.
+async fn catch_up(
+    socket: &mut WebSocket,
+    state: &ApiState,
+    floors: &mut Floors,
+    topics: &[String],
+    since: Option<u64>,
+    epoch: Option<u64>,
+) -> Result<(), axum::Error> {
+    let journal = &state.events.journal;
+    if let Some(since) = since {
+        // Numbers of another run of the journal mean nothing
+        let entries = match epoch {
+            Some(epoch) if epoch != journal.epoch() => None,
+            _ => journal.since(since),
+        };
+        if let Some(entries) = entries {
+            let mut last = since;
+            for entry in entries {
+                last = entry.seq.unwrap_or(last);
+                if topics.iter().any(|t| t == entry.topic) {
+                    send(socket, message(&entry)).await?;
                 }
             }
+            floors.extend(topics.iter().map(|t| (t.clone(), last)));
+            return Ok(());
+        }
+        send(socket, json!({"type": "replay:gap", "data": {"since": since, "seq": journal.last_seq()}})).await?;
+    }
 
-            // Agent metrics events
-            result = metrics_rx.recv() => {
-                match result {
-                    Ok(event) => {
-                        let msg = json!({
-                            "type": "agent:metrics",
-                            "data": {
-                                "appId": event.app_id,
-                                "codeServerStatus": event.code_server_status,
-                                "appStatus": event.app_status,
-                                "dbStatus": event.db_status,
-                                "memoryBytes": event.memory_bytes,
-                                "cpuPercent": event.cpu_percent,
-                                "codeServerIdleSecs": event.code_server_idle_secs,
-                            }
-                        });
-                        if socket.send(Message::Text(msg.to_string().into())).await.is_err() {
-                            break;
-                        }
-                    }
-                    Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!("WebSocket agent_metrics lagged by {}", n);
-                    }
-                    Err(broadcast::error::RecvError::Closed) => break,
-                }
-            }
+    let seq = journal.last_seq();
+    for topic in topics {
+        if let Some(data) = fanout::snapshot(state, topic).await {
+            send(socket, json!({"type": format!("{}:snapshot", topic), "topic": topic, "seq": seq, "data": data})).await?;
+        }
+    }
+    floors.extend(topics.iter().map(|t| (t.clone(), seq)));
+    Ok(())
+}
 
-            // Service command completion events
-            result = service_cmd_rx.recv() => {
-                match result {
-                    Ok(event) => {
-                        let msg = json!({
-                            "type": "agent:service-command",
-                            "data": {
-                                "appId": event.app_id,
-                                "serviceType": event.service_type,
-                                "action": event.action,
-                                "success": event.success,
-                            }
-                        });
-                        if socket.send(Message::Text(msg.to_string().into())).await.is_err() {
-                            break;
-                        }
-                    }
-                    Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!("WebSocket service_command lagged by {}", n);
-                    }
-                    Err(broadcast::error::RecvError::Closed) => break,
-                }
-            }
+/// Add `requested` to the topics of the client and catch up on them.
+async fn subscribe(
+    socket: &mut WebSocket,
+    state: &ApiState,
+    floors: &mut Floors,
+    topics: &mut Option<HashSet<String>>,
+    requested: Vec<String>,
+    since: Option<u64>,
+    epoch: Option<u64>,
+) -> Result<(), axum::Error> {
+    let followed = topics.get_or_insert_with(HashSet::new);
+    let mut added = Vec::new();
+    for topic in requested {
+        if !TOPICS.contains(&topic.as_str()) {
+            send(socket, json!({"type": "error", "data": {"error": format!("Unknown topic: {}", topic)}})).await?;
+        } else if followed.insert(topic.clone()) {
+            added.push(topic);
+        }
+    }
+    catch_up(socket, state, floors, &added, since, epoch).await
+}
 
-            // Agent update events
-            result = agent_update_rx.recv() => {
-                match result {
-                    Ok(event) => {
-                        let msg = json!({
-                            "type": "agent:update",
-                            "data": {
-                                "appId": event.app_id,
-                                "slug": event.slug,
-                                "status": format!("{:?}", event.status).to_lowercase(),
-                                "version": event.version,
-                                "error": event.error,
-                            }
-                        });
-                        if socket.send(Message::Text(msg.to_string().into())).await.is_err() {
-                            break;
-                        }
-                    }
-                    Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!("WebSocket agent_update lagged by {}", n);
-                    }
-                    Err(broadcast::error::RecvError::Closed) => break,
-                }
-            }
+/// Active migrations and deployments, for clients that do not subscribe.
+async fn legacy_sync(socket: &mut WebSocket, state: &ApiState) -> Result<(), axum::Error> {
+    let Some(snapshot) = fanout::snapshot(state, "migrations").await else {
+        return Ok(());
+    };
+    for (key, kind) in [("migrations", "migration:progress"), ("deployments", "deployment:progress")] {
+        for data in snapshot[key].as_array().into_iter().flatten() {
+            send(socket, json!({"type": kind, "data": data})).await?;
+        }
+    }
+    Ok(())
+}
 
-            // Migration progress events
-            result = migration_rx.recv() => {
-                match result {
-                    Ok(event) => {
-                        let msg = json!({
-                            "type": "migration:progress",
-                            "data": {
-                                "appId": event.app_id,
-                                "transferId": event.transfer_id,
-                                "phase": event.phase,
-                                "progressPct": event.progress_pct,
-                                "bytesTransferred": event.bytes_transferred,
-                                "totalBytes": event.total_bytes,
-                                "error": event.error,
-                            }
-                        });
-                        if socket.send(Message::Text(msg.to_string().into())).await.is_err() {
-                            break;
-                        }
-                    }
-                    Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!("WebSocket migration_progress lagged by {}", n);
-                    }
-                    Err(broadcast::error::RecvError::Closed) => break,
-                }
-            }
+async fn handle_socket(mut socket: WebSocket, state: ApiState) {
+    debug!("WebSocket client connected");
+    let _task = selfmon::track("websocket");
+
+    let journal = &state.events.journal;
+    let mut live = journal.subscribe();
+    // Last sequence number received, where to replay from after a lag
+    let mut last_seq = journal.last_seq();
+    let mut floors = Floors::new();
+    // `None` until the first subscription: every topic
+    let mut topics: Option<HashSet<String>> = None;
+
+    let hello = json!({"type": "hello", "epoch": journal.epoch(), "seq": last_seq, "topics": TOPICS});
+    if send(&mut socket, hello).await.is_err() || legacy_sync(&mut socket, &state).await.is_err() {
+        debug!("WebSocket client disconnected during sync");
+        return;
+    }
 
-            // Dataverse schema events
-            result = dv_schema_rx.recv() => {
+    loop {
+        tokio::select! {
+            result = live.recv() => {
                 match result {
-                    Ok(event) => {
-                        let msg = json!({
-                            "type": "dataverse:schema",
-                            "data": {
-                                "appId": event.app_id,
-                                "slug": event.slug,
-                                "tables": event.tables,
-                                "relationsCount": event.relations_count,
-                                "version": event.version,
+                    Ok(entry) => {
+                        if let Some(seq) = entry.seq {
+                            last_seq = last_seq.max(seq);
+                            if floors.get(entry.topic).is_some_and(|floor| seq <= *floor) {
+                                continue;
                             }
-                        });
-                        if socket.send(Message::Text(msg.to_string().into())).await.is_err() {
-                            break;
                         }
-                    }
-                    Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!("WebSocket dataverse_schema lagged by {}", n);
-                    }
-                    Err(broadcast::error::RecvError::Closed) => break,
-                }
-            }
-
-            // Dataverse data events
-            result = dv_data_rx.recv() => {
-                match result {
-                    Ok(event) => {
-                        let msg = json!({
-                            "type": "dataverse:data",
-                            "data": {
-                                "appId": event.app_id,
-                                "slug": event.slug,
-                                "tableName": event.table_name,
-                                "operation": event.operation,
-                                "rowCount": event.row_count,
-                            }
-                        });
-                        if socket.send(Message::Text(msg.to_string().into())).await.is_err() {
+                        if follows(&topics, entry.topic) && send(&mut socket, message(&entry)).await.is_err() {
                             break;
                         }
                     }
                     Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!("WebSocket dataverse_data lagged by {}", n);
-                    }
-                    Err(broadcast::error::RecvError::Closed) => break,
-                }
-            }
-
-            // Cloud relay status events
-            result = cloud_relay_rx.recv() => {
-                match result {
-                    Ok(event) => {
-                        let msg = json!({
-                            "type": "cloud_relay:status",
-                            "data": event,
-                        });
-                        if socket.send(Message::Text(msg.to_string().into())).await.is_err() {
+                        selfmon::record_lag("websocket", n);
+                        let followed: Vec<String> = match &topics {
+                            Some(t) => t.iter().cloned().collect(),
+                            None => TOPICS.iter().map(|t| t.to_string()).collect(),
+                        };
+                        if catch_up(&mut socket, &state, &mut floors, &followed, Some(last_seq), None).await.is_err() {
                             break;
                         }
                     }
-                    Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!("WebSocket cloud_relay lagged by {}", n);
-                    }
                     Err(broadcast::error::RecvError::Closed) => break,
                 }
             }
 
-            // Client disconnect
             msg = socket.recv() => {
                 match msg {
                     Some(Ok(Message::Close(_))) | None => break,
@@ -376,6 +204,24 @@ async fn handle_socket(mut socket: WebSocket, state: ApiState) {
                             break;
                         }
                     }
+                    Some(Ok(Message::Text(text))) => {
+                        let result = match serde_json::from_str::<ClientOp>(&text) {
+                            Ok(ClientOp::Subscribe { topics: requested, since, epoch }) => {
+                                subscribe(&mut socket, &state, &mut floors, &mut topics, requested, since, epoch).await
+                            }
+                            Ok(ClientOp::Unsubscribe { topics: removed }) => {
+                                let followed = topics.get_or_insert_with(|| TOPICS.iter().map(|t| t.to_string()).collect());
+                                for topic in &removed {
+                                    followed.remove(topic);
+                                }
+                                Ok(())
+                            }
+                            Err(e) => send(&mut socket, json!({"type": "error", "data": {"error": e.to_string()}})).await,
+                        };
+                        if result.is_err() {
+                            break;
+                        }
+                    }
                     _ => {} // Ignore other messages
                 }
             }
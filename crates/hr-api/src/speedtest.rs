@@ -0,0 +1,479 @@
+//! WAN speed tests: latency, download and upload measured against a
+//! Cloudflare-compatible speed server (`__down?bytes=N`, `POST __up`), kept
+//! in speedtest.json so ISP degradation can be documented over time. Run on
+//! demand from `/api/network/speedtest` and by the supervised `speedtest`
+//! service; with `qosAdjust`, the QoS shaper is lifted during the test and
+//! its rates are set back to a share of the measured line rates.
+
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use hr_common::metrics;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::state::ApiState;
+
+/// Results kept in the history (about 40 days at 1 per hour).
+const MAX_RESULTS: usize = 1000;
+/// Bytes per download request; streams stop at the deadline anyway.
+const DOWN_BYTES: u64 = 100_000_000;
+/// Bytes per upload request.
+const UP_BYTES: usize = 10_000_000;
+const LATENCY_SAMPLES: usize = 10;
+/// Let the shaper be torn down before measuring.
+const QOS_SETTLE: Duration = Duration::from_secs(2);
+/// Rate changes below this share are not applied (avoids churn).
+const QOS_MIN_CHANGE: f64 = 0.05;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeedtestConfig {
+    /// Hours between scheduled tests, 0 = on demand only.
+    #[serde(default = "default_interval")]
+    pub interval_hours: u32,
+    /// Base URL of the speed server.
+    #[serde(default = "default_server")]
+    pub server: String,
+    /// Parallel connections per direction.
+    #[serde(default = "default_streams")]
+    pub streams: u8,
+    /// Seconds measured per direction.
+    #[serde(default = "default_duration")]
+    pub duration_secs: u32,
+    /// Set the QoS rates from each result.
+    #[serde(default)]
+    pub qos_adjust: bool,
+    /// Share of the measured rates the shaper gets.
+    #[serde(default = "default_qos_percent")]
+    pub qos_percent: u8,
+}
+
+fn default_interval() -> u32 {
+    6
+}
+
+fn default_server() -> String {
+    "https://speed.cloudflare.com".to_string()
+}
+
+fn default_streams() -> u8 {
+    4
+}
+
+fn default_duration() -> u32 {
+    10
+}
+
+fn default_qos_percent() -> u8 {
+    90
+}
+
+impl Default for SpeedtestConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl SpeedtestConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interval_hours > 168 {
+            return Err("The interval must be at most 168 hours (0 = off)".to_string());
+        }
+        if !self.server.starts_with("https://") && !self.server.starts_with("http://") {
+            return Err(format!("Invalid speed server URL: {}", self.server));
+        }
+        if !(1..=16).contains(&self.streams) {
+            return Err("Streams must be between 1 and 16".to_string());
+        }
+        if !(3..=60).contains(&self.duration_secs) {
+            return Err("The duration must be between 3 and 60 seconds".to_string());
+        }
+        if !(50..=100).contains(&self.qos_percent) {
+            return Err("The QoS share must be between 50 and 100 %".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// QoS rates set from a result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QosAdjustment {
+    pub download_mbit: u32,
+    pub upload_mbit: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeedtestResult {
+    pub ran_at: DateTime<Utc>,
+    pub server: String,
+    pub latency_ms: Option<f64>,
+    pub jitter_ms: Option<f64>,
+    pub download_mbps: Option<f64>,
+    pub upload_mbps: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qos: Option<QosAdjustment>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Store {
+    #[serde(default)]
+    config: SpeedtestConfig,
+    /// Oldest first.
+    #[serde(default)]
+    results: Vec<SpeedtestResult>,
+}
+
+/// Settings and history of the speed tests (speedtest.json).
+pub struct Speedtest {
+    store: RwLock<Store>,
+    path: PathBuf,
+    /// Serializes tests (scheduler and API).
+    running: tokio::sync::Mutex<()>,
+}
+
+impl Speedtest {
+    pub fn new(path: PathBuf) -> Self {
+        let store: Store = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        Self { store: RwLock::new(store), path, running: tokio::sync::Mutex::new(()) }
+    }
+
+    pub fn config(&self) -> SpeedtestConfig {
+        self.store.read().unwrap().config.clone()
+    }
+
+    pub fn set_config(&self, config: SpeedtestConfig) -> Result<(), String> {
+        config.validate()?;
+        self.update(|store| store.config = config)
+    }
+
+    /// Newest first.
+    pub fn results(&self, limit: usize) -> Vec<SpeedtestResult> {
+        self.store.read().unwrap().results.iter().rev().take(limit).cloned().collect()
+    }
+
+    pub fn clear(&self) -> Result<(), String> {
+        self.update(|store| store.results.clear())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.try_lock().is_err()
+    }
+
+    fn push(&self, result: SpeedtestResult) {
+        let saved = self.update(|store| append(&mut store.results, result, MAX_RESULTS));
+        if let Err(e) = saved {
+            warn!("Failed to save speed test history: {}", e);
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Store)) -> Result<(), String> {
+        let mut store = self.store.write().unwrap();
+        f(&mut store);
+        let content = serde_json::to_string(&*store).map_err(|e| e.to_string())?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content).map_err(|e| e.to_string())?;
+        std::fs::rename(&tmp_path, &self.path).map_err(|e| e.to_string())
+    }
+}
+
+/// Add a result, dropping the oldest past `max`.
+fn append(results: &mut Vec<SpeedtestResult>, result: SpeedtestResult, max: usize) {
+    results.push(result);
+    let excess = results.len().saturating_sub(max);
+    results.drain(..excess);
+}
+
+/// Median and mean deviation between consecutive samples.
+fn latency_stats(samples: &[f64]) -> Option<(f64, f64)> {
+    if samples.is_empty() {
+        return None;
+    }
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    let jitter = if samples.len() > 1 {
+        samples.windows(2).map(|w| (w[1] - w[0]).abs()).sum::<f64>() / (samples.len() - 1) as f64
+    } else {
+        0.0
+    };
+    Some((round(median), round(jitter)))
+}
+
+fn round(v: f64) -> f64 {
+    (v * 10.0).round() / 10.0
+}
+
+fn mbps(bytes: u64, elapsed: Duration) -> f64 {
+    round(bytes as f64 * 8.0 / elapsed.as_secs_f64().max(0.001) / 1_000_000.0)
+}
+
+/// New shaper rates from the measured ones, `None` when they barely move.
+fn shaped_rates(download_mbps: f64, upload_mbps: f64, percent: u8, current: QosAdjustment) -> Option<QosAdjustment> {
+    let share = |rate: f64| ((rate * percent as f64 / 100.0) as u32).max(1);
+    let next = QosAdjustment { download_mbit: share(download_mbps), upload_mbit: share(upload_mbps) };
+    let moved = |new: u32, old: u32| (new as f64 - old as f64).abs() > old as f64 * QOS_MIN_CHANGE;
+    (moved(next.download_mbit, current.download_mbit) || moved(next.upload_mbit, current.upload_mbit))
+        .then_some(next)
+}
+
+async fn measure_latency(http: &reqwest::Client, server: &str) -> Result<Vec<f64>, String> {
+    let url = format!("{}/__down?bytes=0", server);
+    let mut samples = Vec::new();
+    for _ in 0..LATENCY_SAMPLES {
+        let start = Instant::now();
+        let resp = http.get(&url).send().await.map_err(|e| e.to_string())?;
+        resp.bytes().await.map_err(|e| e.to_string())?;
+        samples.push(start.elapsed().as_secs_f64() * 1000.0);
+    }
+    Ok(samples)
+}
+
+async fn download_stream(http: reqwest::Client, url: String, deadline: Instant) -> Result<u64, String> {
+    let mut total = 0;
+    while Instant::now() < deadline {
+        let mut resp = http.get(&url).send().await.map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("Download: HTTP {}", resp.status()));
+        }
+        loop {
+            match tokio::time::timeout_at(deadline.into(), resp.chunk()).await {
+                Ok(Ok(Some(chunk))) => total += chunk.len() as u64,
+                Ok(Ok(None)) => break,
+                Ok(Err(e)) => return Err(e.to_string()),
+                Err(_) => return Ok(total),
+            }
+        }
+    }
+    Ok(total)
+}
+
+/// Only completed uploads count.
+async fn upload_stream(http: reqwest::Client, url: String, deadline: Instant) -> Result<u64, String> {
+    let body = vec![0u8; UP_BYTES];
+    let mut total = 0;
+    while Instant::now() < deadline {
+        let sent = tokio::time::timeout_at(deadline.into(), http.post(&url).body(body.clone()).send()).await;
+        match sent {
+            Ok(Ok(resp)) if resp.status().is_success() => total += UP_BYTES as u64,
+            Ok(Ok(resp)) => return Err(format!("Upload: HTTP {}", resp.status())),
+            Ok(Err(e)) => return Err(e.to_string()),
+            Err(_) => break,
+        }
+    }
+    Ok(total)
+}
+
+/// Bits per second over `streams` parallel connections, in Mbit/s.
+async fn measure(config: &SpeedtestConfig, http: &reqwest::Client, upload: bool) -> Result<f64, String> {
+    let start = Instant::now();
+    let deadline = start + Duration::from_secs(config.duration_secs as u64);
+    let mut tasks = tokio::task::JoinSet::new();
+    for _ in 0..config.streams {
+        let http = http.clone();
+        if upload {
+            tasks.spawn(upload_stream(http, format!("{}/__up", config.server), deadline));
+        } else {
+            tasks.spawn(download_stream(http, format!("{}/__down?bytes={}", config.server, DOWN_BYTES), deadline));
+        }
+    }
+    let mut total = 0;
+    for result in tasks.join_all().await {
+        total += result?;
+    }
+    Ok(mbps(total, start.elapsed()))
+}
+
+async fn measure_all(config: &SpeedtestConfig, result: &mut SpeedtestResult) -> Result<(), String> {
+    let http = reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.duration_secs as u64 + 30))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let samples = measure_latency(&http, &config.server).await?;
+    if let Some((latency, jitter)) = latency_stats(&samples) {
+        result.latency_ms = Some(latency);
+        result.jitter_ms = Some(jitter);
+    }
+    result.download_mbps = Some(measure(config, &http, false).await?);
+    result.upload_mbps = Some(measure(config, &http, true).await?);
+    Ok(())
+}
+
+/// Run a test now and record it; errors when one is already running.
+pub async fn run(state: &ApiState) -> Result<SpeedtestResult, String> {
+    let Ok(_running) = state.speedtest.running.try_lock() else {
+        return Err("A speed test is already running".to_string());
+    };
+    let config = state.speedtest.config();
+    let mut result = SpeedtestResult {
+        ran_at: Utc::now(),
+        server: config.server.clone(),
+        latency_ms: None,
+        jitter_ms: None,
+        download_mbps: None,
+        upload_mbps: None,
+        qos: None,
+        error: None,
+    };
+
+    // The shaper caps the rates: lift it to measure the line
+    let qos = state.qos.config();
+    let lift = config.qos_adjust && qos.enabled;
+    if lift {
+        state.qos.apply(hr_qos::QosConfig { enabled: false, ..qos.clone() });
+        tokio::time::sleep(QOS_SETTLE).await;
+    }
+    let measured = measure_all(&config, &mut result).await;
+    if lift {
+        let current = QosAdjustment { download_mbit: qos.download_mbit, upload_mbit: qos.upload_mbit };
+        let rates = match (&measured, result.download_mbps, result.upload_mbps) {
+            (Ok(()), Some(down), Some(up)) => shaped_rates(down, up, config.qos_percent, current),
+            _ => None,
+        };
+        let mut restored = qos.clone();
+        if let Some(rates) = rates {
+            let adjusted = hr_qos::QosConfig {
+                download_mbit: rates.download_mbit,
+                upload_mbit: rates.upload_mbit,
+                ..qos.clone()
+            };
+            let path = state.env.qos_config_path.clone();
+            let to_save = adjusted.clone();
+            match tokio::task::spawn_blocking(move || to_save.save_to_file(&path)).await {
+                Ok(Ok(())) => {
+                    info!("QoS rates set to {}/{} Mbit/s from the speed test", rates.download_mbit, rates.upload_mbit);
+                    result.qos = Some(rates);
+                    restored = adjusted;
+                }
+                Ok(Err(e)) => warn!("Failed to save the adjusted QoS config: {}", e),
+                Err(e) => warn!("Failed to save the adjusted QoS config: {}", e),
+            }
+        }
+        state.qos.apply(restored);
+    }
+    if let Err(e) = measured {
+        warn!("Speed test failed: {}", e);
+        result.error = Some(e);
+    }
+
+    let registry = metrics::registry();
+    for (name, help, value) in [
+        ("homeroute_speedtest_download_mbps", "Download rate of the last speed test.", result.download_mbps),
+        ("homeroute_speedtest_upload_mbps", "Upload rate of the last speed test.", result.upload_mbps),
+        ("homeroute_speedtest_latency_ms", "Latency of the last speed test.", result.latency_ms),
+    ] {
+        if let Some(value) = value {
+            registry.gauge(name, help, &[]).set(value);
+        }
+    }
+    state.speedtest.push(result.clone());
+    Ok(result)
+}
+
+/// Run a test every `interval_hours` (config read each minute).
+pub async fn run_speedtest_scheduler(state: ApiState) -> anyhow::Result<()> {
+    let mut tick = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        tick.tick().await;
+        let interval = state.speedtest.config().interval_hours;
+        if interval == 0 {
+            continue;
+        }
+        let due = state
+            .speedtest
+            .results(1)
+            .first()
+            .is_none_or(|r| Utc::now() - r.ran_at >= chrono::Duration::hours(interval as i64));
+        if due && let Ok(result) = run(&state).await {
+            info!(
+                "Speed test: {:?} Mbit/s down, {:?} Mbit/s up, {:?} ms",
+                result.download_mbps, result.upload_mbps, result.latency_ms
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let config = SpeedtestConfig::default();
+        assert_eq!(config.interval_hours, 6);
+        assert_eq!(config.qos_percent, 90);
+        assert!(config.validate().is_ok());
+        assert!(SpeedtestConfig { streams: 0, ..config.clone() }.validate().is_err());
+        assert!(SpeedtestConfig { server: "speed.example".to_string(), ..config.clone() }.validate().is_err());
+        assert!(SpeedtestConfig { qos_percent: 30, ..config }.validate().is_err());
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        assert_eq!(latency_stats(&[]), None);
+        assert_eq!(latency_stats(&[12.0]), Some((12.0, 0.0)));
+        assert_eq!(latency_stats(&[10.0, 14.0, 12.0, 30.0, 11.0]), Some((12.0, 10.8)));
+    }
+
+    #[test]
+    fn test_mbps() {
+        assert_eq!(mbps(125_000_000, Duration::from_secs(10)), 100.0);
+    }
+
+    #[test]
+    fn test_shaped_rates() {
+        let current = QosAdjustment { download_mbit: 450, upload_mbit: 90 };
+        assert_eq!(shaped_rates(500.0, 100.0, 90, current), None);
+        assert_eq!(shaped_rates(503.0, 101.0, 90, current), None);
+        assert_eq!(
+            shaped_rates(300.0, 100.0, 90, current),
+            Some(QosAdjustment { download_mbit: 270, upload_mbit: 90 })
+        );
+        assert_eq!(shaped_rates(0.2, 0.1, 90, current), Some(QosAdjustment { download_mbit: 1, upload_mbit: 1 }));
+    }
+
+    fn result(latency_ms: f64) -> SpeedtestResult {
+        SpeedtestResult {
+            ran_at: Utc::now(),
+            server: default_server(),
+            latency_ms: Some(latency_ms),
+            jitter_ms: None,
+            download_mbps: None,
+            upload_mbps: None,
+            qos: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_append() {
+        let mut results = Vec::new();
+        for i in 0..5 {
+            append(&mut results, result(i as f64), 3);
+        }
+        let kept: Vec<_> = results.iter().filter_map(|r| r.latency_ms).collect();
+        assert_eq!(kept, vec![2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_history_persisted() {
+        let dir = std::env::temp_dir().join(format!("hr-speedtest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let speedtest = Speedtest::new(dir.join("speedtest.json"));
+        speedtest.push(result(12.0));
+        speedtest.push(result(15.0));
+        let reloaded = Speedtest::new(dir.join("speedtest.json"));
+        assert_eq!(reloaded.results(1)[0].latency_ms, Some(15.0));
+        assert_eq!(reloaded.results(10).len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
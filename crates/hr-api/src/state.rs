@@ -3,20 +3,84 @@ use hr_auth::AuthService;
 use hr_acme::AcmeManager;
 use hr_common::config::EnvConfig;
 use hr_common::events::{CloudRelayCommand, CloudRelayStatus, EventBus, MigrationPhase};
-use hr_common::service_registry::SharedServiceRegistry;
+use hr_common::service_registry::{ServiceControlCommand, SharedServiceRegistry};
 use hr_dns::SharedDnsState;
 use hr_dhcp::SharedDhcpState;
 
 use hr_proxy::{ProxyState, TlsManager};
 use hr_registry::AgentRegistry;
+use hr_registry::transfer::{Compression, StreamManifest, TransferStream};
 use hr_registry::types::Environment;
+use hr_reflector::SharedReflector;
+use hr_ntp::SharedNtp;
+use hr_flow::SharedFlowMonitor;
+use hr_firewall::SharedBanManager;
+use hr_radius::SharedRadius;
+use hr_syslog::SharedSyslog;
+use hr_mail::SharedMail;
+use hr_s3::SharedObjectStorage;
+use hr_cron::SharedCron;
+use hr_queue::SharedQueues;
+use hr_pubsub::SharedPubSub;
+use hr_ai::SharedGateway;
+use hr_tailscale::SharedTailscale;
+use hr_qos::SharedQos;
+use hr_ddns::SharedDdns;
+use hr_scanner::SharedScanner;
+use hr_energy::SharedPowerMeters;
+use hr_stream::SharedStreamProxy;
 use crate::container_manager::{ContainerManager, RenameState};
+use crate::deployments::DeploymentState;
+use crate::drain::DrainState;
+use crate::enrollment::JoinCodes;
+use crate::host_updates::HostUpgrade;
+use crate::process_manager::ProcessManager;
+use crate::mqtt::MqttBridge;
+use crate::plugins::PluginRegistry;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, AtomicU64};
 use tokio::sync::RwLock;
 
+/// Stream of a remote→local transfer being received.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransferPhase {
+    ReceivingContainer,
+    ReceivingWorkspace,
+}
+
+/// Remote→local nspawn transfer written to /tmp while the source host exports.
+pub struct InboundTransfer {
+    pub container_name: String,
+    pub storage_path: String,
+    pub network_mode: String,
+    /// Import file, through the transfer's decompression.
+    pub file: Box<dyn tokio::io::AsyncWrite + Unpin + Send + Sync>,
+    pub phase: TransferPhase,
+    pub workspace_file: Option<Box<dyn tokio::io::AsyncWrite + Unpin + Send + Sync>>,
+    pub compression: Compression,
+    pub total_bytes: u64,
+    /// Tar bytes written for the current stream (after decompression).
+    pub bytes_received: Arc<AtomicU64>,
+    pub chunk_count: u32,
+    pub app_id: String,
+    pub transfer_id: String,
+    /// Chunks written for the current stream.
+    pub manifest: StreamManifest,
+    /// A `ResumeTransfer` is on its way, chunks past the gap are dropped.
+    pub resume_requested: bool,
+}
+
+impl InboundTransfer {
+    pub fn stream(&self) -> TransferStream {
+        match self.phase {
+            TransferPhase::ReceivingContainer => TransferStream::Container,
+            TransferPhase::ReceivingWorkspace => TransferStream::Workspace,
+        }
+    }
+}
+
 /// In-memory state of an active migration.
 #[derive(Debug, serde::Serialize)]
 pub struct MigrationState {
@@ -33,6 +97,8 @@ pub struct MigrationState {
     /// Cancel flag: set by the cancel endpoint, checked by the migration task.
     #[serde(skip)]
     pub cancelled: Arc<AtomicBool>,
+    /// Compression of the transfer streams, agreed with both hosts.
+    pub compression: Compression,
 }
 
 /// Cached Dataverse schema metadata for an application.
@@ -77,8 +143,13 @@ pub struct CachedRelationInfo {
 pub struct CloudRelayInfo {
     pub status: CloudRelayStatus,
     pub vps_ipv4: Option<String>,
+    /// Relay the tunnel is connected to (a fallback one after a failover).
+    pub relay_host: Option<String>,
+    /// Round trip of the last keepalive probe.
     pub latency_ms: Option<u64>,
     pub active_streams: Option<u32>,
+    /// SHA-256 of the certificate the relay presented.
+    pub relay_cert_sha256: Option<String>,
 }
 
 /// Shared application state for all API routes.
@@ -94,15 +165,36 @@ pub struct ApiState {
     pub events: Arc<EventBus>,
     pub env: Arc<EnvConfig>,
     pub service_registry: SharedServiceRegistry,
+    /// Start/stop/restart of supervised services.
+    pub service_control_tx: Option<tokio::sync::mpsc::Sender<ServiceControlCommand>>,
 
     pub registry: Option<Arc<AgentRegistry>>,
 
     /// Container V2 manager (nspawn).
     pub container_manager: Option<Arc<ContainerManager>>,
 
+    /// Process apps run on the host (no container).
+    pub process_manager: Option<Arc<ProcessManager>>,
+
     /// Active migrations keyed by transfer_id.
     pub migrations: Arc<RwLock<HashMap<String, MigrationState>>>,
 
+    /// Remote→local transfers of disconnected source hosts, keyed by host
+    /// then transfer_id, resumed when the host reconnects.
+    pub interrupted_transfers: Arc<tokio::sync::Mutex<HashMap<String, HashMap<String, InboundTransfer>>>>,
+
+    /// Blue/green deployments keyed by app_id (the last one of each app).
+    pub deployments: Arc<RwLock<HashMap<String, DeploymentState>>>,
+
+    /// Host drains keyed by host_id (the last one of each host).
+    pub drains: Arc<RwLock<HashMap<String, DrainState>>>,
+
+    /// Package upgrades keyed by host_id (the last one of each host).
+    pub host_upgrades: Arc<RwLock<HashMap<String, HostUpgrade>>>,
+
+    /// Agent join codes not used yet.
+    pub join_codes: Arc<RwLock<JoinCodes>>,
+
     /// Active slug renames keyed by rename_id.
     pub renames: Arc<RwLock<HashMap<String, RenameState>>>,
 
@@ -118,6 +210,84 @@ pub struct ApiState {
     /// Channel to send commands to the tunnel client (e.g. push binary update).
     pub cloud_relay_cmd_tx: Option<tokio::sync::mpsc::Sender<CloudRelayCommand>>,
 
+    /// TCP/UDP stream proxy (port forwards).
+    pub streams: SharedStreamProxy,
+
+    /// Subprocess plugins mounted under /api/ext/{name}.
+    pub plugins: Arc<PluginRegistry>,
+
+    /// Home Assistant MQTT bridge (config + live status).
+    pub mqtt: Arc<MqttBridge>,
+
+    /// mDNS/SSDP reflector between VLANs.
+    pub reflector: SharedReflector,
+
+    /// Syslog receiver and message store.
+    pub syslog: SharedSyslog,
+
+    /// RADIUS server (WPA-Enterprise, MAC auth) and its certificate authority.
+    pub radius: SharedRadius,
+
+    /// NTP server for LAN clients.
+    pub ntp: SharedNtp,
+
+    /// Passive SNI/bytes analytics of forwarded traffic.
+    pub flows: SharedFlowMonitor,
+
+    /// Ban manager (fail2ban-style client bans).
+    pub bans: SharedBanManager,
+
+    /// Outbound mail relay (queue, DKIM, delivery log).
+    pub mail: SharedMail,
+
+    /// S3-compatible object storage (per-app buckets and access keys).
+    pub s3: SharedObjectStorage,
+
+    /// Scheduled jobs of the applications and their run history.
+    pub cron: SharedCron,
+
+    /// Message queues of the applications.
+    pub queues: SharedQueues,
+
+    /// Realtime pub/sub between the applications (topic grants, subscriptions).
+    pub pubsub: SharedPubSub,
+
+    /// AI gateway of the applications (provider keys, usage, budgets).
+    pub ai: SharedGateway,
+
+    /// Tailscale/Headscale node (remote access fallback, VPN-only routes).
+    pub tailscale: SharedTailscale,
+
+    /// Traffic shaping on the WAN link (per-device priorities and caps).
+    pub qos: SharedQos,
+
+    /// Cloud relay traffic totals of the month and their limits.
+    pub relay_usage: hr_tunnel::usage::SharedRelayUsage,
+
+    /// Dynamic DNS (providers, WAN address detection, update status).
+    pub ddns: SharedDdns,
+
+    /// LAN device inventory (neighbor tables, leases, active scans).
+    pub scanner: SharedScanner,
+
+    /// Power meters (smart plugs, SNMP, MQTT) and their watt-hour series.
+    pub energy: SharedPowerMeters,
+
+    /// Last runs of the host power schedules (wake/shutdown/sleep rules).
+    pub host_scheduler: Arc<crate::host_schedules::HostScheduler>,
+
+    /// Container backups (policies, catalog, remote exports in progress).
+    pub backups: Arc<crate::backups::BackupManager>,
+
+    /// Connectivity doctor (schedule, last report).
+    pub doctor: Arc<crate::doctor::Doctor>,
+
+    /// WAN speed tests (schedule, history).
+    pub speedtest: Arc<crate::speedtest::Speedtest>,
+
+    /// On-demand packet captures (pcaps under the data directory).
+    pub captures: Arc<crate::capture::CaptureManager>,
+
     /// Path to dns-dhcp-config.json
     pub dns_dhcp_config_path: PathBuf,
     /// Path to rust-proxy-config.json
@@ -0,0 +1,135 @@
+//! Container templates: the built-in base images (Ubuntu, Debian, Alpine,
+//! NixOS) and the ones added through the API, with their provisioning
+//! recipe. Tarball sources are downloaded once into a cache and pinned by
+//! sha256, so every container of a template starts from the same rootfs.
+
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use hr_container::template::{builtin_templates, DEFAULT_TEMPLATE};
+use hr_container::{ContainerTemplate, TemplateCache};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+#[derive(Default, Serialize, Deserialize)]
+struct Catalog {
+    templates: Vec<ContainerTemplate>,
+}
+
+pub struct TemplateCatalog {
+    catalog: RwLock<Catalog>,
+    path: PathBuf,
+    cache: TemplateCache,
+}
+
+impl TemplateCatalog {
+    pub fn new(path: PathBuf, cache_dir: PathBuf) -> Self {
+        let catalog: Catalog = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        info!(templates = catalog.templates.len(), "Loaded container templates");
+        Self { catalog: RwLock::new(catalog), path, cache: TemplateCache::new(cache_dir) }
+    }
+
+    pub fn cache(&self) -> &TemplateCache {
+        &self.cache
+    }
+
+    /// Built-in templates first, then custom ones.
+    pub fn list(&self) -> Vec<ContainerTemplate> {
+        let mut templates = builtin_templates();
+        templates.extend(self.catalog.read().unwrap().templates.iter().cloned());
+        templates
+    }
+
+    pub fn get(&self, id: &str) -> Option<ContainerTemplate> {
+        self.list().into_iter().find(|t| t.id == id)
+    }
+
+    /// Template of a new container, the default one when unspecified.
+    pub fn resolve(&self, id: Option<&str>) -> Result<ContainerTemplate, String> {
+        let id = id.unwrap_or(DEFAULT_TEMPLATE);
+        self.get(id).ok_or_else(|| format!("Unknown template: {id}"))
+    }
+
+    /// Add or replace a custom template.
+    pub async fn save(&self, mut template: ContainerTemplate) -> Result<ContainerTemplate, String> {
+        template.builtin = false;
+        template.validate()?;
+        if builtin_templates().iter().any(|t| t.id == template.id) {
+            return Err(format!("{} is a built-in template", template.id));
+        }
+        let previous = self.get(&template.id);
+        self.update(|catalog| {
+            catalog.templates.retain(|t| t.id != template.id);
+            catalog.templates.push(template.clone());
+        });
+        // A new source needs a new download
+        if previous.is_some_and(|p| p.source != template.source) {
+            self.cache.remove(&template.id).await;
+        }
+        Ok(template)
+    }
+
+    /// Delete a custom template and its cached tarball.
+    pub async fn delete(&self, id: &str) -> Result<bool, String> {
+        if builtin_templates().iter().any(|t| t.id == id) {
+            return Err(format!("{id} is a built-in template"));
+        }
+        let mut found = false;
+        self.update(|catalog| {
+            let before = catalog.templates.len();
+            catalog.templates.retain(|t| t.id != id);
+            found = catalog.templates.len() != before;
+        });
+        if found {
+            self.cache.remove(id).await;
+        }
+        Ok(found)
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Catalog)) {
+        let content = {
+            let mut catalog = self.catalog.write().unwrap();
+            f(&mut catalog);
+            serde_json::to_string_pretty(&*catalog)
+        };
+        let saved = content.map_err(anyhow::Error::from).and_then(|content| {
+            let tmp_path = self.path.with_extension("json.tmp");
+            std::fs::write(&tmp_path, content)?;
+            std::fs::rename(&tmp_path, &self.path)?;
+            Ok(())
+        });
+        if let Err(e) = saved {
+            warn!("Failed to save container templates: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_custom_templates() {
+        let dir = std::env::temp_dir().join(format!("hr-templates-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let catalog = TemplateCatalog::new(dir.join("templates.json"), dir.join("cache"));
+
+        assert_eq!(catalog.resolve(None).unwrap().id, DEFAULT_TEMPLATE);
+        let mut template = catalog.get("debian-bookworm").unwrap();
+        assert!(catalog.save(template.clone()).await.is_err());
+        assert!(catalog.delete("debian-bookworm").await.is_err());
+
+        template.id = "debian-tools".to_string();
+        template.recipe.packages.push("git".to_string());
+        assert!(!catalog.save(template).await.unwrap().builtin);
+        let reloaded = TemplateCatalog::new(dir.join("templates.json"), dir.join("cache"));
+        assert_eq!(reloaded.resolve(Some("debian-tools")).unwrap().recipe.packages.last().unwrap(), "git");
+        assert!(reloaded.delete("debian-tools").await.unwrap());
+        assert!(reloaded.resolve(Some("debian-tools")).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
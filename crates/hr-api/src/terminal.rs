@@ -0,0 +1,187 @@
+//! Web terminal sessions: a browser WebSocket relayed to a login shell on a
+//! pty, on this machine or through a host agent, in a container or on the
+//! host itself.
+//!
+//! Binary frames carry keystrokes. Text frames do too, unless they hold a
+//! control message: `{"type":"resize","cols":120,"rows":40}`. The output
+//! goes back as binary frames, failures as `{"error": "..."}` text frames.
+
+use axum::extract::ws::{Message, WebSocket};
+use serde::Deserialize;
+use tracing::{debug, error, info};
+
+use hr_container::pty::{self, Pty, WindowSize};
+use hr_registry::protocol::HostRegistryMessage;
+use hr_registry::AgentRegistry;
+
+/// Initial window size, from the query of the WebSocket URL.
+#[derive(Debug, Default, Deserialize)]
+pub struct TerminalQuery {
+    #[serde(default)]
+    pub cols: u16,
+    #[serde(default)]
+    pub rows: u16,
+}
+
+impl TerminalQuery {
+    pub fn size(&self) -> WindowSize {
+        WindowSize::or_default(self.cols, self.rows)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Control {
+    Resize { cols: u16, rows: u16 },
+}
+
+enum Input {
+    Data(Vec<u8>),
+    Resize(WindowSize),
+    Close,
+    None,
+}
+
+fn input(msg: Option<Result<Message, axum::Error>>) -> Input {
+    match msg {
+        Some(Ok(Message::Binary(data))) => Input::Data(data.to_vec()),
+        Some(Ok(Message::Text(text))) => match serde_json::from_str::<Control>(&text) {
+            Ok(Control::Resize { cols, rows }) => Input::Resize(WindowSize::or_default(cols, rows)),
+            Err(_) => Input::Data(text.as_bytes().to_vec()),
+        },
+        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => Input::Close,
+        Some(Ok(_)) => Input::None,
+    }
+}
+
+pub async fn send_error(socket: &mut WebSocket, error: impl std::fmt::Display) {
+    let _ = socket
+        .send(Message::Text(serde_json::json!({"error": error.to_string()}).to_string().into()))
+        .await;
+}
+
+/// Shell on this machine: in `container` (nsenter) or on the host.
+pub async fn run_local(socket: &mut WebSocket, container: Option<&str>, size: WindowSize) {
+    let command = match container {
+        Some(container) => pty::container_shell(container).await,
+        None => Ok(pty::host_shell()),
+    };
+    let spawned = command.and_then(|command| Pty::spawn(command, size).map_err(|e| format!("Failed to start shell: {e}")));
+    let (pty, mut child) = match spawned {
+        Ok(spawned) => spawned,
+        Err(e) => {
+            error!(?container, "Failed to open terminal: {e}");
+            send_error(socket, e).await;
+            return;
+        }
+    };
+
+    let mut buf = vec![0u8; 4096];
+    loop {
+        tokio::select! {
+            n = pty.read(&mut buf) => match n {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if socket.send(Message::Binary(buf[..n].to_vec().into())).await.is_err() {
+                        break;
+                    }
+                }
+            },
+            ws_msg = socket.recv() => match input(ws_msg) {
+                Input::Data(data) => {
+                    if pty.write_all(&data).await.is_err() {
+                        break;
+                    }
+                }
+                Input::Resize(size) => {
+                    if let Err(e) = pty.resize(size) {
+                        debug!("Terminal resize failed: {e}");
+                    }
+                }
+                Input::Close => break,
+                Input::None => {}
+            },
+        }
+    }
+
+    let _ = child.kill().await;
+    let status = child.wait().await;
+    info!(?container, ?status, "Shell process exited");
+}
+
+/// Shell on a host through its agent: in `container` or on the host.
+pub async fn run_remote(
+    registry: &AgentRegistry,
+    socket: &mut WebSocket,
+    host_id: &str,
+    container: Option<&str>,
+    size: WindowSize,
+) {
+    if !registry.is_host_connected(host_id).await {
+        send_error(socket, "Host is not connected").await;
+        return;
+    }
+
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Vec<u8>>(256);
+
+    // Register session so data from host-agent is routed to us
+    registry.register_terminal_session(&session_id, tx).await;
+
+    let open = HostRegistryMessage::TerminalOpen {
+        session_id: session_id.clone(),
+        container_name: container.map(str::to_string),
+        cols: size.cols,
+        rows: size.rows,
+    };
+    if let Err(e) = registry.send_host_command(host_id, open).await {
+        error!(?container, host_id, "Failed to send TerminalOpen: {e}");
+        send_error(socket, format!("Failed to open remote terminal: {e}")).await;
+        registry.unregister_terminal_session(&session_id).await;
+        return;
+    }
+
+    info!(?container, host_id, session_id, "Remote terminal session started");
+
+    loop {
+        tokio::select! {
+            // Data from host-agent (terminal output) → WebSocket client
+            data = rx.recv() => {
+                match data {
+                    // Empty data signals session closed by host-agent
+                    Some(d) if d.is_empty() => break,
+                    Some(d) => {
+                        if socket.send(Message::Binary(d.into())).await.is_err() {
+                            break;
+                        }
+                    }
+                    None => break,
+                }
+            }
+            // Keystrokes and resizes from the WebSocket client → host-agent
+            ws_msg = socket.recv() => {
+                let msg = match input(ws_msg) {
+                    Input::Data(data) => HostRegistryMessage::TerminalData { session_id: session_id.clone(), data },
+                    Input::Resize(size) => HostRegistryMessage::TerminalResize {
+                        session_id: session_id.clone(),
+                        cols: size.cols,
+                        rows: size.rows,
+                    },
+                    Input::Close => break,
+                    Input::None => continue,
+                };
+                if let Err(e) = registry.send_host_command(host_id, msg).await {
+                    // Agents without `pty_terminal` cannot resize
+                    debug!(session_id, "Terminal message not relayed: {e}");
+                }
+            }
+        }
+    }
+
+    let _ = registry
+        .send_host_command(host_id, HostRegistryMessage::TerminalClose { session_id: session_id.clone() })
+        .await;
+    registry.unregister_terminal_session(&session_id).await;
+
+    info!(?container, host_id, session_id, "Remote terminal session ended");
+}
@@ -0,0 +1,201 @@
+//! Décodeur CBOR minimal (RFC 8949) pour les objets WebAuthn : objet
+//! d'attestation et clés COSE. Longueurs définies uniquement, comme l'impose
+//! l'encodage canonique CTAP2.
+
+use anyhow::{anyhow, bail, Result};
+
+/// Profondeur maximale d'imbrication acceptée
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Uint(u64),
+    /// Entier négatif (toujours < 0)
+    Neg(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Bool(bool),
+    Null,
+}
+
+impl Value {
+    pub fn as_int(&self) -> Option<i128> {
+        match self {
+            Value::Uint(n) => Some(*n as i128),
+            Value::Neg(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Entrée d'une map à clé texte (objet d'attestation)
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(k, _)| k.as_text() == Some(key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Entrée d'une map à clé entière (clé COSE)
+    pub fn get_int(&self, key: i128) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(k, _)| k.as_int() == Some(key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+/// Décode une valeur occupant tout le buffer.
+pub fn decode(data: &[u8]) -> Result<Value> {
+    let (value, used) = decode_prefix(data)?;
+    if used != data.len() {
+        bail!("CBOR: {} octets en trop", data.len() - used);
+    }
+    Ok(value)
+}
+
+/// Décode la valeur en tête du buffer ; renvoie aussi sa taille.
+pub fn decode_prefix(data: &[u8]) -> Result<(Value, usize)> {
+    let mut reader = Reader { data, pos: 0 };
+    let value = reader.value(0)?;
+    Ok((value, reader.pos))
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| anyhow!("CBOR tronqué"))?;
+        let slice = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn argument(&mut self, info: u8) -> Result<u64> {
+        Ok(match info {
+            0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => u16::from_be_bytes(self.take(2)?.try_into()?) as u64,
+            26 => u32::from_be_bytes(self.take(4)?.try_into()?) as u64,
+            27 => u64::from_be_bytes(self.take(8)?.try_into()?),
+            _ => bail!("CBOR: longueur indéfinie ou invalide ({})", info),
+        })
+    }
+
+    /// Nombre d'éléments annoncé, borné par ce qui reste à lire
+    fn count(&mut self, info: u8) -> Result<usize> {
+        let n = self.argument(info)?;
+        if n > (self.data.len() - self.pos) as u64 {
+            bail!("CBOR tronqué");
+        }
+        Ok(n as usize)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            bail!("CBOR trop imbriqué");
+        }
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        Ok(match major {
+            0 => Value::Uint(self.argument(info)?),
+            1 => Value::Neg(-1 - self.argument(info)? as i128),
+            2 => {
+                let n = self.count(info)?;
+                Value::Bytes(self.take(n)?.to_vec())
+            }
+            3 => {
+                let n = self.count(info)?;
+                Value::Text(String::from_utf8(self.take(n)?.to_vec())?)
+            }
+            4 => {
+                let n = self.count(info)?;
+                let mut items = Vec::with_capacity(n);
+                for _ in 0..n {
+                    items.push(self.value(depth + 1)?);
+                }
+                Value::Array(items)
+            }
+            5 => {
+                let n = self.count(info)?;
+                let mut entries = Vec::with_capacity(n);
+                for _ in 0..n {
+                    let key = self.value(depth + 1)?;
+                    let value = self.value(depth + 1)?;
+                    entries.push((key, value));
+                }
+                Value::Map(entries)
+            }
+            // Tags : seule la valeur étiquetée compte ici
+            6 => {
+                self.argument(info)?;
+                self.value(depth + 1)?
+            }
+            7 => match info {
+                20 => Value::Bool(false),
+                21 => Value::Bool(true),
+                22 | 23 => Value::Null,
+                _ => bail!("CBOR: valeur simple non supportée ({})", info),
+            },
+            _ => unreachable!(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_cose_key() {
+        // {1: 2, 3: -7, -1: 1, -2: h'0102'}
+        let data = [0xa4, 0x01, 0x02, 0x03, 0x26, 0x20, 0x01, 0x21, 0x42, 0x01, 0x02];
+        let value = decode(&data).unwrap();
+        assert_eq!(value.get_int(1).and_then(Value::as_int), Some(2));
+        assert_eq!(value.get_int(3).and_then(Value::as_int), Some(-7));
+        assert_eq!(value.get_int(-2).and_then(Value::as_bytes), Some(&[1u8, 2][..]));
+    }
+
+    #[test]
+    fn test_decode_prefix_and_errors() {
+        // {"fmt": "none"} suivi d'octets (extensions de authData)
+        let data = [0xa1, 0x63, b'f', b'm', b't', 0x64, b'n', b'o', b'n', b'e', 0xff];
+        let (value, used) = decode_prefix(&data).unwrap();
+        assert_eq!(used, 10);
+        assert_eq!(value.get("fmt").and_then(Value::as_text), Some("none"));
+        assert!(decode(&data).is_err());
+
+        // Longueur annoncée plus grande que le buffer
+        assert!(decode(&[0x5a, 0xff, 0xff, 0xff, 0xff]).is_err());
+        // Longueur indéfinie
+        assert!(decode(&[0x9f, 0x01, 0xff]).is_err());
+    }
+}
@@ -40,13 +40,18 @@ impl From<&UserInfo> for ForwardAuthHeaders {
 /// Vérifie l'authentification pour le reverse proxy
 ///
 /// Appelé directement (sans HTTP) depuis le proxy pour chaque requête authentifiée.
+/// Les routes sensibles (`require_mfa`) exigent une session ouverte avec un
+/// second facteur. `client_ip` est noté comme dernière IP de la session.
+#[allow(clippy::too_many_arguments)]
 pub fn check_forward_auth(
     auth: &Arc<AuthService>,
     session_cookie: Option<&str>,
+    client_ip: Option<&str>,
     forwarded_host: &str,
     forwarded_uri: &str,
     forwarded_proto: &str,
     allowed_groups: &[String],
+    require_mfa: bool,
 ) -> ForwardAuthResult {
     let original_url = format!("{}://{}{}", forwarded_proto, forwarded_host, forwarded_uri);
     let login_url = format!(
@@ -61,7 +66,7 @@ pub fn check_forward_auth(
     };
 
     // Valider la session
-    let session = match auth.sessions.validate(session_id) {
+    let session = match auth.sessions.validate_from(session_id, client_ip) {
         Ok(Some(s)) => s,
         _ => return ForwardAuthResult::Unauthorized { login_url },
     };
@@ -90,11 +95,22 @@ pub fn check_forward_auth(
         }
     }
 
+    // Second facteur : une session ouverte avant l'activation du TOTP doit
+    // se reconnecter ; sans TOTP ni passkey, l'accès est refusé
+    if require_mfa && !session.mfa {
+        if user.totp_enabled {
+            return ForwardAuthResult::Unauthorized { login_url };
+        }
+        return ForwardAuthResult::Forbidden {
+            message: "Two-factor authentication required".to_string(),
+        };
+    }
+
     ForwardAuthResult::Success { user }
 }
 
 /// URL-encode basique
-fn urlencoded(s: &str) -> String {
+pub(crate) fn urlencoded(s: &str) -> String {
     let mut result = String::with_capacity(s.len());
     for c in s.chars() {
         match c {
@@ -121,4 +137,27 @@ mod tests {
             "https%3A%2F%2Fexample.com%2Fpath%3Fa%3D1"
         );
     }
+
+    #[test]
+    fn test_require_mfa() {
+        let dir = tempfile::tempdir().unwrap();
+        let auth = AuthService::new(dir.path(), "example.com").unwrap();
+        assert!(auth.users.create("alice", "password123", None, None, vec![]).success);
+        let (plain, _) = auth.sessions.create("alice", None, None, false, false).unwrap();
+        let (strong, _) = auth.sessions.create("alice", None, None, false, true).unwrap();
+        let check = |session: &str, require_mfa: bool| {
+            check_forward_auth(&auth, Some(session), None, "app.example.com", "/", "https", &[], require_mfa)
+        };
+
+        assert!(matches!(check(&plain, false), ForwardAuthResult::Success { .. }));
+        assert!(matches!(check(&strong, true), ForwardAuthResult::Success { .. }));
+        // Pas de second facteur configuré : refus
+        assert!(matches!(check(&plain, true), ForwardAuthResult::Forbidden { .. }));
+
+        // TOTP activé depuis : reconnexion avec le code
+        let totp = auth.users.start_totp("alice").unwrap();
+        let now = chrono::Utc::now().timestamp() as u64;
+        auth.users.confirm_totp("alice", &totp.code_at(now)).unwrap();
+        assert!(matches!(check(&plain, true), ForwardAuthResult::Unauthorized { .. }));
+    }
 }
@@ -1,10 +1,22 @@
+mod cbor;
 pub mod forward_auth;
 pub mod middleware;
+pub mod oidc;
 pub mod sessions;
+pub mod shares;
+pub mod sso;
+pub mod throttle;
+pub mod totp;
 pub mod users;
+pub mod webauthn;
 
+use crate::oidc::OidcProvider;
 use crate::sessions::SessionStore;
+use crate::shares::ShareStore;
+use crate::sso::ExternalSso;
+use crate::throttle::LoginThrottle;
 use crate::users::UserStore;
+use crate::webauthn::WebAuthn;
 use std::path::Path;
 use std::sync::Arc;
 
@@ -15,6 +27,16 @@ use std::sync::Arc;
 pub struct AuthService {
     pub sessions: SessionStore,
     pub users: UserStore,
+    /// Liens de partage invités vers les routes protégées
+    pub shares: ShareStore,
+    /// Échecs de connexion par utilisateur et par IP (verrouillage, CAPTCHA)
+    pub throttle: LoginThrottle,
+    /// Passkeys : cérémonies en cours (RP ID = domaine de base)
+    pub webauthn: WebAuthn,
+    /// Fournisseur OpenID Connect pour les applications auto-hébergées
+    pub oidc: OidcProvider,
+    /// Connexion déléguée à un fournisseur OIDC externe (sso.yml)
+    pub sso: ExternalSso,
     pub base_domain: String,
 }
 
@@ -22,16 +44,23 @@ impl AuthService {
     /// Crée et initialise le service d'authentification
     pub fn new(data_dir: &Path, base_domain: &str) -> anyhow::Result<Arc<Self>> {
         let sessions = SessionStore::new(data_dir)?;
+        let shares = ShareStore::new(data_dir)?;
         let users = UserStore::new(data_dir);
+        let oidc = OidcProvider::new(data_dir, base_domain)?;
 
         Ok(Arc::new(Self {
             sessions,
             users,
+            shares,
+            throttle: LoginThrottle::new(),
+            webauthn: WebAuthn::new(base_domain),
+            oidc,
+            sso: ExternalSso::new(data_dir, base_domain),
             base_domain: base_domain.to_string(),
         }))
     }
 
-    /// Démarre le nettoyage périodique des sessions expirées
+    /// Démarre le nettoyage périodique des sessions et liens de partage expirés
     pub fn start_cleanup_task(self: &Arc<Self>) {
         let this = Arc::clone(self);
         tokio::spawn(async move {
@@ -41,6 +70,9 @@ impl AuthService {
                 if let Err(e) = this.sessions.cleanup_expired() {
                     tracing::warn!("Session cleanup error: {}", e);
                 }
+                if let Err(e) = this.shares.cleanup_expired() {
+                    tracing::warn!("Share link cleanup error: {}", e);
+                }
             }
         });
     }
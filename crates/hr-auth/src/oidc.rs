@@ -0,0 +1,754 @@
+//! Fournisseur OpenID Connect minimal (Grafana, Gitea, Immich...).
+//!
+//! Flux "authorization code" uniquement, PKCE S256 obligatoire pour les
+//! clients publics. Les jetons (ID et accès) sont des JWT ES256 signés avec
+//! une clé P-256 conservée à côté de la base de sessions. Les codes
+//! d'autorisation restent en mémoire, à usage unique, une minute.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use rand_core::{OsRng, RngCore};
+use ring::digest::{digest, SHA256};
+use ring::rand::SystemRandom;
+use ring::signature::{self, EcdsaKeyPair, KeyPair, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::users::UserInfo;
+
+/// Durée de validité d'un code d'autorisation
+const CODE_TTL: Duration = Duration::from_secs(60);
+/// Codes en attente au maximum
+const MAX_PENDING: usize = 1024;
+/// Durée de vie des jetons d'accès et d'identité
+pub const TOKEN_TTL_SECS: i64 = 3600;
+
+const KEY_FILE: &str = "oidc-signing-key.pk8";
+const CLIENTS_FILE: &str = "oidc-clients.yml";
+
+/// Application cliente enregistrée
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcClient {
+    pub client_id: String,
+    pub name: String,
+    /// Condensat SHA-256 du secret ; absent pour un client public (PKCE seul)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_hash: Option<String>,
+    pub redirect_uris: Vec<String>,
+    /// Groupes autorisés (vide = tous les utilisateurs)
+    #[serde(default)]
+    pub allowed_groups: Vec<String>,
+    pub created: String,
+}
+
+impl OidcClient {
+    pub fn is_public(&self) -> bool {
+        self.secret_hash.is_none()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ClientsFile {
+    #[serde(default)]
+    clients: Vec<OidcClient>,
+}
+
+/// Paramètres de `/authorize`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuthorizeRequest {
+    #[serde(default)]
+    pub response_type: String,
+    #[serde(default)]
+    pub client_id: String,
+    #[serde(default)]
+    pub redirect_uri: String,
+    #[serde(default)]
+    pub scope: String,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub nonce: Option<String>,
+    #[serde(default)]
+    pub code_challenge: Option<String>,
+    #[serde(default)]
+    pub code_challenge_method: Option<String>,
+}
+
+/// Issue de `/authorize`
+#[derive(Debug, PartialEq)]
+pub enum AuthorizeOutcome {
+    /// Pas de session : passer par la page de connexion puis revenir
+    Login,
+    /// Retour vers le client (code ou erreur OAuth)
+    Redirect(String),
+    /// Client ou redirect_uri invalide : afficher l'erreur sans rediriger
+    Invalid(String),
+}
+
+/// Paramètres de `/token` (le secret peut aussi venir de l'en-tête Basic)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TokenRequest {
+    #[serde(default)]
+    pub grant_type: String,
+    #[serde(default)]
+    pub code: String,
+    #[serde(default)]
+    pub redirect_uri: String,
+    #[serde(default)]
+    pub client_id: String,
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    #[serde(default)]
+    pub code_verifier: Option<String>,
+}
+
+/// Erreur OAuth de `/token` (RFC 6749 §5.2)
+#[derive(Debug, PartialEq)]
+pub struct TokenError {
+    pub error: &'static str,
+    pub description: String,
+}
+
+impl TokenError {
+    fn new(error: &'static str, description: &str) -> Self {
+        Self {
+            error,
+            description: description.to_string(),
+        }
+    }
+}
+
+/// Contenu d'un jeton d'accès valide
+#[derive(Debug, Clone)]
+pub struct AccessToken {
+    pub username: String,
+    pub client_id: String,
+    pub scope: String,
+}
+
+struct PendingCode {
+    client_id: String,
+    redirect_uri: String,
+    scope: String,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    user: UserInfo,
+    mfa: bool,
+    auth_time: i64,
+    expires: Instant,
+}
+
+/// Fournisseur OIDC d'un domaine (issuer `https://auth.{domaine}/api/auth/oidc`)
+pub struct OidcProvider {
+    issuer: String,
+    login_page: String,
+    key: EcdsaKeyPair,
+    kid: String,
+    clients_path: PathBuf,
+    codes: Mutex<HashMap<String, PendingCode>>,
+}
+
+impl OidcProvider {
+    /// Charge la clé de signature (créée au premier démarrage)
+    pub fn new(data_dir: &Path, base_domain: &str) -> Result<Self> {
+        let key = load_or_create_key(&data_dir.join(KEY_FILE))?;
+        let kid = URL_SAFE_NO_PAD.encode(&digest(&SHA256, key.public_key().as_ref()).as_ref()[..12]);
+        Ok(Self {
+            issuer: format!("https://auth.{}/api/auth/oidc", base_domain),
+            login_page: format!("https://auth.{}/login", base_domain),
+            key,
+            kid,
+            clients_path: data_dir.join(CLIENTS_FILE),
+            codes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// Page de connexion, qui renvoie vers `/authorize` (même requête) une
+    /// fois la session ouverte
+    pub fn login_url(&self, authorize_query: &str) -> String {
+        let back = format!("{}/authorize?{}", self.issuer, authorize_query);
+        format!("{}?rd={}", self.login_page, crate::forward_auth::urlencoded(&back))
+    }
+
+    /// Document `/.well-known/openid-configuration`
+    pub fn discovery(&self) -> Value {
+        json!({
+            "issuer": self.issuer,
+            "authorization_endpoint": format!("{}/authorize", self.issuer),
+            "token_endpoint": format!("{}/token", self.issuer),
+            "userinfo_endpoint": format!("{}/userinfo", self.issuer),
+            "jwks_uri": format!("{}/jwks", self.issuer),
+            "response_types_supported": ["code"],
+            "grant_types_supported": ["authorization_code"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": ["ES256"],
+            "scopes_supported": ["openid", "profile", "email", "groups"],
+            "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+            "code_challenge_methods_supported": ["S256"],
+            "claims_supported": ["sub", "name", "preferred_username", "email", "groups", "nonce", "auth_time", "amr"]
+        })
+    }
+
+    /// Clé publique de signature (JWKS)
+    pub fn jwks(&self) -> Value {
+        // Point non compressé : 0x04 || x || y
+        let point = self.key.public_key().as_ref();
+        json!({
+            "keys": [{
+                "kty": "EC",
+                "crv": "P-256",
+                "use": "sig",
+                "alg": "ES256",
+                "kid": self.kid,
+                "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+                "y": URL_SAFE_NO_PAD.encode(&point[33..65])
+            }]
+        })
+    }
+
+    // ── Clients ──────────────────────────────────────────────────────
+
+    fn load_clients(&self) -> ClientsFile {
+        std::fs::read_to_string(&self.clients_path)
+            .ok()
+            .and_then(|content| serde_yaml::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_clients(&self, file: &ClientsFile) -> Result<()> {
+        let content = serde_yaml::to_string(file)?;
+        std::fs::write(&self.clients_path, content)?;
+        Ok(())
+    }
+
+    pub fn clients(&self) -> Vec<OidcClient> {
+        self.load_clients().clients
+    }
+
+    pub fn client(&self, client_id: &str) -> Option<OidcClient> {
+        self.load_clients()
+            .clients
+            .into_iter()
+            .find(|c| c.client_id == client_id)
+    }
+
+    /// Enregistre un client. Renvoie le secret en clair pour un client
+    /// confidentiel (affiché une seule fois).
+    pub fn register_client(
+        &self,
+        name: &str,
+        redirect_uris: Vec<String>,
+        allowed_groups: Vec<String>,
+        public: bool,
+    ) -> Result<(OidcClient, Option<String>)> {
+        let name = name.trim();
+        if name.is_empty() {
+            bail!("Nom requis");
+        }
+        if redirect_uris.is_empty() {
+            bail!("Au moins une redirect_uri requise");
+        }
+        for uri in &redirect_uris {
+            validate_redirect_uri(uri)?;
+        }
+
+        let secret = (!public).then(|| random_token(32));
+        let client = OidcClient {
+            client_id: random_token(16),
+            name: name.to_string(),
+            secret_hash: secret.as_deref().map(hash_secret),
+            redirect_uris,
+            allowed_groups,
+            created: chrono::Utc::now().to_rfc3339(),
+        };
+        let mut file = self.load_clients();
+        file.clients.push(client.clone());
+        self.save_clients(&file)?;
+        Ok((client, secret))
+    }
+
+    pub fn delete_client(&self, client_id: &str) -> bool {
+        let mut file = self.load_clients();
+        let before = file.clients.len();
+        file.clients.retain(|c| c.client_id != client_id);
+        file.clients.len() != before && self.save_clients(&file).is_ok()
+    }
+
+    // ── Flux authorization code ──────────────────────────────────────
+
+    /// Traite une demande d'autorisation pour l'utilisateur de la session
+    /// (`None` : pas connecté). `mfa` : session ouverte avec second facteur.
+    pub fn authorize(&self, req: &AuthorizeRequest, user: Option<&UserInfo>, mfa: bool) -> AuthorizeOutcome {
+        let Some(client) = self.client(&req.client_id) else {
+            return AuthorizeOutcome::Invalid("Client inconnu".to_string());
+        };
+        if !client.redirect_uris.iter().any(|u| u == &req.redirect_uri) {
+            return AuthorizeOutcome::Invalid("redirect_uri non enregistrée pour ce client".to_string());
+        }
+
+        let error = |error: &str, description: &str| {
+            AuthorizeOutcome::Redirect(redirect_with(&req.redirect_uri, &[
+                ("error", Some(error)),
+                ("error_description", Some(description)),
+                ("state", req.state.as_deref()),
+            ]))
+        };
+        if req.response_type != "code" {
+            return error("unsupported_response_type", "Seul response_type=code est pris en charge");
+        }
+        if !req.scope.split_whitespace().any(|s| s == "openid") {
+            return error("invalid_scope", "Le scope openid est requis");
+        }
+        match (req.code_challenge.as_deref(), req.code_challenge_method.as_deref()) {
+            (None, _) if client.is_public() => {
+                return error("invalid_request", "PKCE requis pour un client public");
+            }
+            (Some(_), method) if method != Some("S256") => {
+                return error("invalid_request", "Seul code_challenge_method=S256 est pris en charge");
+            }
+            _ => {}
+        }
+
+        let Some(user) = user else {
+            return AuthorizeOutcome::Login;
+        };
+        if user.disabled {
+            return error("access_denied", "Compte desactive");
+        }
+        if !client.allowed_groups.is_empty()
+            && !user.groups.iter().any(|g| client.allowed_groups.contains(g))
+        {
+            return error("access_denied", "Acces refuse pour cette application");
+        }
+
+        let code = random_token(32);
+        let mut codes = self.codes.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        codes.retain(|_, c| c.expires > now);
+        if codes.len() >= MAX_PENDING {
+            return error("temporarily_unavailable", "Trop de demandes en cours");
+        }
+        codes.insert(code.clone(), PendingCode {
+            client_id: client.client_id,
+            redirect_uri: req.redirect_uri.clone(),
+            scope: req.scope.clone(),
+            nonce: req.nonce.clone(),
+            code_challenge: req.code_challenge.clone(),
+            user: user.clone(),
+            mfa,
+            auth_time: chrono::Utc::now().timestamp(),
+            expires: now + CODE_TTL,
+        });
+        AuthorizeOutcome::Redirect(redirect_with(&req.redirect_uri, &[
+            ("code", Some(&code)),
+            ("state", req.state.as_deref()),
+        ]))
+    }
+
+    /// Échange un code contre les jetons (réponse JSON de `/token`)
+    pub fn exchange_code(&self, req: &TokenRequest) -> Result<Value, TokenError> {
+        if req.grant_type != "authorization_code" {
+            return Err(TokenError::new("unsupported_grant_type", "Seul authorization_code est pris en charge"));
+        }
+        let client = self
+            .client(&req.client_id)
+            .ok_or_else(|| TokenError::new("invalid_client", "Client inconnu"))?;
+        if let Some(hash) = &client.secret_hash {
+            let secret = req.client_secret.as_deref().unwrap_or("");
+            if !constant_time_eq(hash_secret(secret).as_bytes(), hash.as_bytes()) {
+                return Err(TokenError::new("invalid_client", "Secret invalide"));
+            }
+        }
+
+        // Le code est consommé quelle que soit l'issue de la vérification
+        let pending = self
+            .codes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&req.code)
+            .filter(|p| p.expires > Instant::now())
+            .ok_or_else(|| TokenError::new("invalid_grant", "Code invalide ou expire"))?;
+        if pending.client_id != client.client_id || pending.redirect_uri != req.redirect_uri {
+            return Err(TokenError::new("invalid_grant", "Code emis pour un autre client"));
+        }
+        if let Some(challenge) = &pending.code_challenge {
+            let verifier = req.code_verifier.as_deref().unwrap_or("");
+            if !(43..=128).contains(&verifier.len())
+                || !constant_time_eq(pkce_challenge(verifier).as_bytes(), challenge.as_bytes())
+            {
+                return Err(TokenError::new("invalid_grant", "code_verifier invalide"));
+            }
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let mut id_claims = user_claims(&pending.user, &pending.scope);
+        id_claims.extend([
+            ("iss".to_string(), json!(self.issuer)),
+            ("aud".to_string(), json!(client.client_id)),
+            ("iat".to_string(), json!(now)),
+            ("exp".to_string(), json!(now + TOKEN_TTL_SECS)),
+            ("auth_time".to_string(), json!(pending.auth_time)),
+            ("amr".to_string(), json!(if pending.mfa { vec!["pwd", "mfa"] } else { vec!["pwd"] })),
+        ]);
+        if let Some(nonce) = &pending.nonce {
+            id_claims.insert("nonce".to_string(), json!(nonce));
+        }
+        let access_claims = json!({
+            "iss": self.issuer,
+            "sub": pending.user.username,
+            "aud": self.issuer,
+            "client_id": client.client_id,
+            "scope": pending.scope,
+            "iat": now,
+            "exp": now + TOKEN_TTL_SECS,
+            "jti": random_token(16)
+        });
+
+        let sign = |claims: &Value| {
+            self.sign_jwt(claims)
+                .map_err(|e| TokenError::new("server_error", &e.to_string()))
+        };
+        Ok(json!({
+            "access_token": sign(&access_claims)?,
+            "token_type": "Bearer",
+            "expires_in": TOKEN_TTL_SECS,
+            "id_token": sign(&Value::Object(id_claims))?,
+            "scope": pending.scope
+        }))
+    }
+
+    /// Vérifie un jeton d'accès émis par ce fournisseur (`/userinfo`)
+    pub fn verify_access_token(&self, token: &str) -> Option<AccessToken> {
+        let claims = self.verify_jwt(token).ok()?;
+        if claims["aud"] != self.issuer.as_str() {
+            return None;
+        }
+        Some(AccessToken {
+            username: claims["sub"].as_str()?.to_string(),
+            client_id: claims["client_id"].as_str()?.to_string(),
+            scope: claims["scope"].as_str().unwrap_or("").to_string(),
+        })
+    }
+
+    // ── JWT ──────────────────────────────────────────────────────────
+
+    fn sign_jwt(&self, claims: &Value) -> Result<String> {
+        let header = json!({"alg": "ES256", "typ": "JWT", "kid": self.kid});
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?)
+        );
+        let sig = self
+            .key
+            .sign(&SystemRandom::new(), signing_input.as_bytes())
+            .map_err(|_| anyhow!("Signature impossible"))?;
+        Ok(format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(sig.as_ref())))
+    }
+
+    /// Signature, émetteur et expiration ; renvoie les claims
+    fn verify_jwt(&self, token: &str) -> Result<Value> {
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(sig), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            bail!("JWT mal forme");
+        };
+        let public = UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, self.key.public_key().as_ref());
+        public
+            .verify(
+                format!("{}.{}", header, payload).as_bytes(),
+                &URL_SAFE_NO_PAD.decode(sig)?,
+            )
+            .map_err(|_| anyhow!("Signature invalide"))?;
+
+        let claims: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload)?)?;
+        if claims["iss"] != self.issuer.as_str() {
+            bail!("Emetteur invalide");
+        }
+        if claims["exp"].as_i64().unwrap_or(0) <= chrono::Utc::now().timestamp() {
+            bail!("Jeton expire");
+        }
+        Ok(claims)
+    }
+}
+
+/// Claims d'identité selon les scopes accordés (ID token et `/userinfo`)
+pub fn user_claims(user: &UserInfo, scope: &str) -> Map<String, Value> {
+    let mut claims = Map::new();
+    claims.insert("sub".to_string(), json!(user.username));
+    for scope in scope.split_whitespace() {
+        match scope {
+            "profile" => {
+                claims.insert("name".to_string(), json!(user.displayname));
+                claims.insert("preferred_username".to_string(), json!(user.username));
+            }
+            "email" if !user.email.is_empty() => {
+                claims.insert("email".to_string(), json!(user.email));
+            }
+            "groups" => {
+                claims.insert("groups".to_string(), json!(user.groups));
+            }
+            _ => {}
+        }
+    }
+    claims
+}
+
+fn load_or_create_key(path: &Path) -> Result<EcdsaKeyPair> {
+    let rng = SystemRandom::new();
+    let pkcs8 = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let doc = EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                .map_err(|_| anyhow!("Generation de la cle OIDC impossible"))?;
+            write_private(path, doc.as_ref())?;
+            tracing::info!("Cle de signature OIDC creee: {}", path.display());
+            doc.as_ref().to_vec()
+        }
+        Err(e) => return Err(e).with_context(|| format!("Lecture de {}", path.display())),
+    };
+    EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &pkcs8, &rng)
+        .map_err(|_| anyhow!("Cle OIDC invalide: {}", path.display()))
+}
+
+pub(crate) fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(bytes)?;
+    Ok(())
+}
+
+/// HTTPS, ou HTTP vers la machine locale (applications de bureau)
+fn validate_redirect_uri(uri: &str) -> Result<()> {
+    let rest = uri
+        .strip_prefix("https://")
+        .or_else(|| {
+            uri.strip_prefix("http://")
+                .filter(|r| r.starts_with("localhost") || r.starts_with("127.0.0.1") || r.starts_with("[::1]"))
+        })
+        .ok_or_else(|| anyhow!("redirect_uri invalide (https requis): {}", uri))?;
+    if rest.is_empty() || rest.starts_with('/') || uri.contains('#') {
+        bail!("redirect_uri invalide: {}", uri);
+    }
+    Ok(())
+}
+
+fn redirect_with(redirect_uri: &str, params: &[(&str, Option<&str>)]) -> String {
+    let mut url = redirect_uri.to_string();
+    let mut sep = if url.contains('?') { '&' } else { '?' };
+    for (name, value) in params {
+        if let Some(value) = value {
+            url.push(sep);
+            url.push_str(name);
+            url.push('=');
+            url.push_str(&crate::forward_auth::urlencoded(value));
+            sep = '&';
+        }
+    }
+    url
+}
+
+fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(digest(&SHA256, verifier.as_bytes()))
+}
+
+fn hash_secret(secret: &str) -> String {
+    STANDARD.encode(digest(&SHA256, secret.as_bytes()))
+}
+
+fn random_token(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const REDIRECT: &str = "https://grafana.example.com/login/generic_oauth";
+
+    fn user(groups: &[&str]) -> UserInfo {
+        UserInfo {
+            username: "alice".to_string(),
+            displayname: "Alice".to_string(),
+            email: "alice@example.com".to_string(),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            disabled: false,
+            created: None,
+            last_login: None,
+            totp_enabled: false,
+        }
+    }
+
+    fn request(client_id: &str) -> AuthorizeRequest {
+        AuthorizeRequest {
+            response_type: "code".to_string(),
+            client_id: client_id.to_string(),
+            redirect_uri: REDIRECT.to_string(),
+            scope: "openid profile email groups".to_string(),
+            state: Some("xyz".to_string()),
+            nonce: Some("n-0S6".to_string()),
+            code_challenge: Some(pkce_challenge(VERIFIER)),
+            code_challenge_method: Some("S256".to_string()),
+        }
+    }
+
+    fn code_from(outcome: AuthorizeOutcome) -> String {
+        let AuthorizeOutcome::Redirect(url) = outcome else {
+            panic!("redirect attendu: {:?}", outcome);
+        };
+        assert!(url.starts_with(&format!("{}?code=", REDIRECT)), "{}", url);
+        assert!(url.ends_with("&state=xyz"));
+        url[REDIRECT.len() + 6..url.len() - 10].to_string()
+    }
+
+    fn claims(token: &str) -> Value {
+        let payload = token.split('.').nth(1).unwrap();
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_pkce_rfc7636_vector() {
+        assert_eq!(pkce_challenge(VERIFIER), "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+    }
+
+    #[test]
+    fn test_public_client_code_flow() {
+        let dir = tempfile::tempdir().unwrap();
+        let oidc = OidcProvider::new(dir.path(), "example.com").unwrap();
+        let (client, secret) = oidc
+            .register_client("Grafana", vec![REDIRECT.to_string()], vec![], true)
+            .unwrap();
+        assert!(secret.is_none() && client.is_public());
+
+        // Sans session : connexion d'abord
+        let req = request(&client.client_id);
+        assert_eq!(oidc.authorize(&req, None, false), AuthorizeOutcome::Login);
+
+        let code = code_from(oidc.authorize(&req, Some(&user(&["users"])), true));
+        let mut token_req = TokenRequest {
+            grant_type: "authorization_code".to_string(),
+            code: code.clone(),
+            redirect_uri: REDIRECT.to_string(),
+            client_id: client.client_id.clone(),
+            client_secret: None,
+            code_verifier: Some(VERIFIER.to_string()),
+        };
+        let tokens = oidc.exchange_code(&token_req).unwrap();
+
+        let id_token = tokens["id_token"].as_str().unwrap();
+        let id = oidc.verify_jwt(id_token).unwrap();
+        assert_eq!(id["sub"], "alice");
+        assert_eq!(id["aud"], client.client_id.as_str());
+        assert_eq!(id["nonce"], "n-0S6");
+        assert_eq!(id["email"], "alice@example.com");
+        assert_eq!(id["groups"], json!(["users"]));
+        assert_eq!(id["amr"], json!(["pwd", "mfa"]));
+
+        let access = oidc.verify_access_token(tokens["access_token"].as_str().unwrap()).unwrap();
+        assert_eq!(access.username, "alice");
+        assert_eq!(access.client_id, client.client_id);
+        // Un ID token n'est pas un jeton d'accès
+        assert!(oidc.verify_access_token(id_token).is_none());
+
+        // Code à usage unique
+        let err = oidc.exchange_code(&token_req).unwrap_err();
+        assert_eq!(err.error, "invalid_grant");
+
+        // Mauvais verifier : code consommé quand même
+        token_req.code = code_from(oidc.authorize(&req, Some(&user(&[])), false));
+        token_req.code_verifier = Some("x".repeat(43));
+        assert_eq!(oidc.exchange_code(&token_req).unwrap_err().error, "invalid_grant");
+        token_req.code_verifier = Some(VERIFIER.to_string());
+        assert_eq!(oidc.exchange_code(&token_req).unwrap_err().error, "invalid_grant");
+    }
+
+    #[test]
+    fn test_authorize_validation() {
+        let dir = tempfile::tempdir().unwrap();
+        let oidc = OidcProvider::new(dir.path(), "example.com").unwrap();
+        let (client, secret) = oidc
+            .register_client("Gitea", vec![REDIRECT.to_string()], vec!["admins".to_string()], false)
+            .unwrap();
+        let alice = user(&["users"]);
+
+        let mut req = request(&client.client_id);
+        req.redirect_uri = "https://evil.example.net/cb".to_string();
+        assert!(matches!(oidc.authorize(&req, Some(&alice), false), AuthorizeOutcome::Invalid(_)));
+        req.client_id = "unknown".to_string();
+        assert!(matches!(oidc.authorize(&req, Some(&alice), false), AuthorizeOutcome::Invalid(_)));
+
+        // Groupe non autorisé : erreur renvoyée au client
+        let req = request(&client.client_id);
+        let AuthorizeOutcome::Redirect(url) = oidc.authorize(&req, Some(&alice), false) else {
+            panic!();
+        };
+        assert!(url.contains("error=access_denied") && url.ends_with("&state=xyz"), "{}", url);
+
+        let mut plain = request(&client.client_id);
+        plain.code_challenge_method = Some("plain".to_string());
+        let AuthorizeOutcome::Redirect(url) = oidc.authorize(&plain, Some(&user(&["admins"])), false) else {
+            panic!();
+        };
+        assert!(url.contains("error=invalid_request"));
+
+        // Client confidentiel : secret vérifié
+        let code = code_from(oidc.authorize(&req, Some(&user(&["admins"])), false));
+        let mut token_req = TokenRequest {
+            grant_type: "authorization_code".to_string(),
+            code,
+            redirect_uri: REDIRECT.to_string(),
+            client_id: client.client_id.clone(),
+            client_secret: Some("wrong".to_string()),
+            code_verifier: Some(VERIFIER.to_string()),
+        };
+        assert_eq!(oidc.exchange_code(&token_req).unwrap_err().error, "invalid_client");
+        token_req.client_secret = secret;
+        let tokens = oidc.exchange_code(&token_req).unwrap();
+        assert_eq!(claims(tokens["id_token"].as_str().unwrap())["amr"], json!(["pwd"]));
+    }
+
+    #[test]
+    fn test_signing_key_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = OidcProvider::new(dir.path(), "example.com").unwrap();
+        let second = OidcProvider::new(dir.path(), "example.com").unwrap();
+        assert_eq!(first.jwks(), second.jwks());
+        assert_eq!(first.discovery()["issuer"], "https://auth.example.com/api/auth/oidc");
+        assert_eq!(
+            first.login_url("client_id=a&state=b"),
+            "https://auth.example.com/login?rd=https%3A%2F%2Fauth.example.com%2Fapi%2Fauth%2Foidc%2Fauthorize%3Fclient_id%3Da%26state%3Db"
+        );
+
+        let token = first.sign_jwt(&json!({"iss": first.issuer, "exp": i64::MAX})).unwrap();
+        assert!(second.verify_jwt(&token).is_ok());
+        let tampered = format!("{}x", &token[..token.len() - 1]);
+        assert!(second.verify_jwt(&tampered).is_err());
+
+        assert!(validate_redirect_uri("http://localhost:8080/cb").is_ok());
+        assert!(validate_redirect_uri("http://app.example.com/cb").is_err());
+        assert!(validate_redirect_uri("https://app.example.com/cb#frag").is_err());
+    }
+}
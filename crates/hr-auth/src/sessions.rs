@@ -18,6 +18,22 @@ pub struct Session {
     pub user_agent: Option<String>,
     pub last_activity: i64,
     pub remember_me: bool,
+    /// Ouverte avec un second facteur (TOTP ou passkey)
+    pub mfa: bool,
+    /// Dernière IP vue pour cette session (connexion, puis chaque validation)
+    pub last_ip: Option<String>,
+}
+
+impl Session {
+    /// Encore utilisable : ni expirée, ni inactive trop longtemps
+    fn is_live(&self, now: i64) -> bool {
+        self.expires_at >= now && (self.remember_me || now - self.last_activity <= INACTIVITY_TIMEOUT_MS)
+    }
+
+    /// Appareil lisible ("Firefox sur Linux"), d'après le user-agent
+    pub fn device(&self) -> String {
+        self.user_agent.as_deref().map(device_label).unwrap_or_else(|| "Inconnu".to_string())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +44,7 @@ pub struct SessionInfo {
     pub ip_address: Option<String>,
     pub user_agent: Option<String>,
     pub remember_me: bool,
+    pub mfa: bool,
 }
 
 /// Store de sessions SQLite (thread-safe via Mutex)
@@ -58,18 +75,34 @@ impl SessionStore {
             CREATE INDEX IF NOT EXISTS idx_sessions_expires_at ON sessions(expires_at);",
         )?;
 
+        // Bases créées avant le second facteur
+        let has_mfa = conn
+            .prepare("SELECT 1 FROM pragma_table_info('sessions') WHERE name = 'mfa'")?
+            .exists([])?;
+        if !has_mfa {
+            conn.execute_batch("ALTER TABLE sessions ADD COLUMN mfa INTEGER DEFAULT 0")?;
+        }
+        // Bases créées avant le suivi de la dernière IP
+        let has_last_ip = conn
+            .prepare("SELECT 1 FROM pragma_table_info('sessions') WHERE name = 'last_ip'")?
+            .exists([])?;
+        if !has_last_ip {
+            conn.execute_batch("ALTER TABLE sessions ADD COLUMN last_ip TEXT")?;
+        }
+
         Ok(Self {
             conn: Mutex::new(conn),
         })
     }
 
-    /// Crée une nouvelle session
+    /// Crée une nouvelle session (`mfa` : second facteur vérifié)
     pub fn create(
         &self,
         user_id: &str,
         ip_address: Option<&str>,
         user_agent: Option<&str>,
         remember_me: bool,
+        mfa: bool,
     ) -> anyhow::Result<(String, i64)> {
         let session_id = uuid::Uuid::new_v4().to_string();
         let now = now_ms();
@@ -82,8 +115,8 @@ impl SessionStore {
 
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO sessions (id, user_id, created_at, expires_at, ip_address, user_agent, last_activity, remember_me)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            "INSERT INTO sessions (id, user_id, created_at, expires_at, ip_address, user_agent, last_activity, remember_me, mfa, last_ip)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?5)",
             params![
                 session_id,
                 user_id,
@@ -93,6 +126,7 @@ impl SessionStore {
                 user_agent,
                 now,
                 remember_me as i32,
+                mfa as i32,
             ],
         )?;
 
@@ -102,25 +136,9 @@ impl SessionStore {
     /// Récupère une session par ID (vérifie expiration et inactivité)
     pub fn get(&self, session_id: &str) -> anyhow::Result<Option<Session>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, user_id, created_at, expires_at, ip_address, user_agent, last_activity, remember_me
-             FROM sessions WHERE id = ?1",
-        )?;
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM sessions WHERE id = ?1", SESSION_COLUMNS))?;
 
-        let session = stmt
-            .query_row(params![session_id], |row| {
-                Ok(Session {
-                    id: row.get(0)?,
-                    user_id: row.get(1)?,
-                    created_at: row.get(2)?,
-                    expires_at: row.get(3)?,
-                    ip_address: row.get(4)?,
-                    user_agent: row.get(5)?,
-                    last_activity: row.get(6)?,
-                    remember_me: row.get::<_, i32>(7)? == 1,
-                })
-            })
-            .optional()?;
+        let session = stmt.query_row(params![session_id], session_from_row).optional()?;
 
         let Some(session) = session else {
             return Ok(None);
@@ -147,14 +165,19 @@ impl SessionStore {
 
     /// Valide une session et met à jour l'activité
     pub fn validate(&self, session_id: &str) -> anyhow::Result<Option<SessionInfo>> {
+        self.validate_from(session_id, None)
+    }
+
+    /// Comme [`Self::validate`], en notant l'IP du client quand elle est connue
+    pub fn validate_from(&self, session_id: &str, ip: Option<&str>) -> anyhow::Result<Option<SessionInfo>> {
         let session = self.get(session_id)?;
 
         let Some(session) = session else {
             return Ok(None);
         };
 
-        // Mettre à jour last_activity
-        self.update_activity(session_id)?;
+        // Mettre à jour last_activity (et last_ip)
+        self.update_activity(session_id, ip)?;
 
         Ok(Some(SessionInfo {
             user_id: session.user_id,
@@ -163,15 +186,16 @@ impl SessionStore {
             ip_address: session.ip_address,
             user_agent: session.user_agent,
             remember_me: session.remember_me,
+            mfa: session.mfa,
         }))
     }
 
-    /// Met à jour le timestamp d'activité
-    pub fn update_activity(&self, session_id: &str) -> anyhow::Result<()> {
+    /// Met à jour le timestamp d'activité (et la dernière IP si fournie)
+    pub fn update_activity(&self, session_id: &str, ip: Option<&str>) -> anyhow::Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "UPDATE sessions SET last_activity = ?1 WHERE id = ?2",
-            params![now_ms(), session_id],
+            "UPDATE sessions SET last_activity = ?1, last_ip = COALESCE(?3, last_ip) WHERE id = ?2",
+            params![now_ms(), session_id, ip],
         )?;
         Ok(())
     }
@@ -193,27 +217,30 @@ impl SessionStore {
         Ok(())
     }
 
-    /// Récupère toutes les sessions d'un utilisateur
-    pub fn get_by_user(&self, user_id: &str) -> anyhow::Result<Vec<Session>> {
+    /// Supprime toutes les sessions d'un utilisateur sauf `keep` (la session
+    /// courante) ; retourne le nombre de sessions révoquées
+    pub fn delete_others(&self, user_id: &str, keep: &str) -> anyhow::Result<usize> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, user_id, created_at, expires_at, ip_address, user_agent, last_activity, remember_me
-             FROM sessions WHERE user_id = ?1",
+        let count = conn.execute(
+            "DELETE FROM sessions WHERE user_id = ?1 AND id != ?2",
+            params![user_id, keep],
         )?;
+        Ok(count)
+    }
 
+    /// Sessions encore actives d'un utilisateur, la plus récemment utilisée
+    /// en premier (les sessions expirées ou inactives sont ignorées)
+    pub fn get_by_user(&self, user_id: &str) -> anyhow::Result<Vec<Session>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM sessions WHERE user_id = ?1 ORDER BY last_activity DESC",
+            SESSION_COLUMNS
+        ))?;
+
+        let now = now_ms();
         let sessions = stmt
-            .query_map(params![user_id], |row| {
-                Ok(Session {
-                    id: row.get(0)?,
-                    user_id: row.get(1)?,
-                    created_at: row.get(2)?,
-                    expires_at: row.get(3)?,
-                    ip_address: row.get(4)?,
-                    user_agent: row.get(5)?,
-                    last_activity: row.get(6)?,
-                    remember_me: row.get::<_, i32>(7)? == 1,
-                })
-            })?
+            .query_map(params![user_id], session_from_row)?
+            .filter(|s| s.as_ref().map_or(true, |s| s.is_live(now)))
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(sessions)
@@ -230,6 +257,54 @@ impl SessionStore {
     }
 }
 
+const SESSION_COLUMNS: &str =
+    "id, user_id, created_at, expires_at, ip_address, user_agent, last_activity, remember_me, mfa, last_ip";
+
+fn session_from_row(row: &rusqlite::Row) -> rusqlite::Result<Session> {
+    Ok(Session {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        created_at: row.get(2)?,
+        expires_at: row.get(3)?,
+        ip_address: row.get(4)?,
+        user_agent: row.get(5)?,
+        last_activity: row.get(6)?,
+        remember_me: row.get::<_, i32>(7)? == 1,
+        mfa: row.get::<_, Option<i32>>(8)? == Some(1),
+        last_ip: row.get(9)?,
+    })
+}
+
+/// "Navigateur sur Système" à partir d'un user-agent (détection grossière,
+/// juste pour reconnaître ses appareils dans la liste des sessions)
+pub fn device_label(user_agent: &str) -> String {
+    // L'ordre compte : Edge et Opera s'annoncent aussi Chrome, Chrome aussi Safari
+    const BROWSERS: &[(&str, &str)] = &[
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+        ("okhttp", "Application Android"),
+        ("curl/", "curl"),
+    ];
+    const SYSTEMS: &[(&str, &str)] = &[
+        ("Android", "Android"),
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "macOS"),
+        ("CrOS", "ChromeOS"),
+        ("Linux", "Linux"),
+    ];
+    let find = |table: &[(&str, &'static str)]| table.iter().find(|(needle, _)| user_agent.contains(needle)).map(|(_, name)| *name);
+    match (find(BROWSERS), find(SYSTEMS)) {
+        (Some(browser), Some(system)) => format!("{} sur {}", browser, system),
+        (Some(name), None) | (None, Some(name)) => name.to_string(),
+        (None, None) => user_agent.chars().take(40).collect(),
+    }
+}
+
 /// Trait d'extension pour rusqlite optionnel
 trait OptionalExt<T> {
     fn optional(self) -> rusqlite::Result<Option<T>>;
@@ -248,3 +323,51 @@ impl<T> OptionalExt<T> for rusqlite::Result<T> {
 fn now_ms() -> i64 {
     chrono::Utc::now().timestamp_millis()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_label() {
+        let firefox = "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0";
+        let edge = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36 Edg/120.0";
+        let iphone = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Mobile/15E148 Safari/604.1";
+        assert_eq!(device_label(firefox), "Firefox sur Linux");
+        assert_eq!(device_label(edge), "Edge sur Windows");
+        assert_eq!(device_label(iphone), "Safari sur iOS");
+        assert_eq!(device_label("curl/8.5.0"), "curl");
+    }
+
+    #[test]
+    fn test_list_and_revoke() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path()).unwrap();
+        let (laptop, _) = store.create("alice", Some("10.0.0.2"), Some("curl/8"), false, false).unwrap();
+        let (phone, _) = store.create("alice", Some("10.0.0.3"), None, true, false).unwrap();
+        let (stale, _) = store.create("alice", None, None, false, false).unwrap();
+        store.create("bob", None, None, false, false).unwrap();
+
+        // Session inactive depuis trop longtemps : plus listée
+        store.conn.lock().unwrap()
+            .execute("UPDATE sessions SET last_activity = 0 WHERE id = ?1", params![stale])
+            .unwrap();
+        store.validate_from(&phone, Some("203.0.113.7")).unwrap().unwrap();
+
+        let sessions = store.get_by_user("alice").unwrap();
+        let ids: Vec<&str> = sessions.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&laptop.as_str()) && ids.contains(&phone.as_str()));
+        let phone_session = sessions.iter().find(|s| s.id == phone).unwrap();
+        assert_eq!(phone_session.ip_address.as_deref(), Some("10.0.0.3"));
+        assert_eq!(phone_session.last_ip.as_deref(), Some("203.0.113.7"));
+        // Validation sans IP connue : la dernière est conservée
+        store.validate(&phone).unwrap().unwrap();
+        assert_eq!(store.get(&phone).unwrap().unwrap().last_ip.as_deref(), Some("203.0.113.7"));
+
+        // Tout révoquer sauf la session courante
+        assert_eq!(store.delete_others("alice", &laptop).unwrap(), 2);
+        assert_eq!(store.get_by_user("alice").unwrap().len(), 1);
+        assert_eq!(store.get_by_user("bob").unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,315 @@
+//! Liens de partage invités : accès temporaire à une route protégée sans compte.
+//!
+//! Le jeton est `{id}.{signature}`, la signature HMAC-SHA256 liant l'id à la
+//! route (clé conservée à côté de la base de sessions). L'état du lien
+//! (expiration, utilisations, révocation) est dans la table `share_links`.
+//! Ouvrir le lien compte une utilisation et échange le jeton contre un cookie,
+//! qui donne accès jusqu'à l'expiration sans nouvelle utilisation.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand_core::{OsRng, RngCore};
+use ring::hmac;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+/// Paramètre de requête portant le jeton dans l'URL partagée
+pub const SHARE_PARAM: &str = "hr_share";
+/// Cookie posé à l'ouverture du lien (valeur = jeton)
+pub const SHARE_COOKIE: &str = "hr_share";
+/// Durée maximale d'un lien (30 jours)
+pub const MAX_SHARE_HOURS: u32 = 30 * 24;
+
+const KEY_FILE: &str = "share-links.key";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareLink {
+    pub id: String,
+    /// Route (hôte du reverse proxy) ouverte par le lien
+    pub route_id: String,
+    pub label: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: i64,
+    pub expires_at: i64,
+    /// Ouvertures autorisées ; illimité jusqu'à l'expiration si absent
+    pub max_uses: Option<u32>,
+    pub uses: u32,
+}
+
+impl ShareLink {
+    /// Secondes restantes avant expiration (durée du cookie)
+    pub fn remaining_secs(&self) -> i64 {
+        ((self.expires_at - now_ms()) / 1000).max(0)
+    }
+}
+
+/// Store des liens de partage (SQLite, table dans auth.db)
+pub struct ShareStore {
+    conn: Mutex<Connection>,
+    key: hmac::Key,
+}
+
+impl ShareStore {
+    pub fn new(data_dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(data_dir)?;
+        let conn = Connection::open(data_dir.join("auth.db"))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS share_links (
+                id TEXT PRIMARY KEY,
+                route_id TEXT NOT NULL,
+                label TEXT,
+                created_by TEXT,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL,
+                max_uses INTEGER,
+                uses INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE INDEX IF NOT EXISTS idx_share_links_route_id ON share_links(route_id);",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            key: load_or_create_key(&data_dir.join(KEY_FILE))?,
+        })
+    }
+
+    /// Crée un lien valable `hours` heures (et `max_uses` ouvertures).
+    /// Renvoie le lien et son jeton, qui n'est pas conservé.
+    pub fn create(
+        &self,
+        route_id: &str,
+        hours: u32,
+        max_uses: Option<u32>,
+        label: Option<&str>,
+        created_by: Option<&str>,
+    ) -> Result<(ShareLink, String)> {
+        if hours == 0 || hours > MAX_SHARE_HOURS {
+            bail!("Durée invalide (1 à {} heures)", MAX_SHARE_HOURS);
+        }
+        if max_uses == Some(0) {
+            bail!("Nombre d'utilisations invalide");
+        }
+        let mut bytes = [0u8; 16];
+        OsRng.fill_bytes(&mut bytes);
+        let now = now_ms();
+        let link = ShareLink {
+            id: URL_SAFE_NO_PAD.encode(bytes),
+            route_id: route_id.to_string(),
+            label: label.map(str::trim).filter(|l| !l.is_empty()).map(str::to_string),
+            created_by: created_by.map(str::to_string),
+            created_at: now,
+            expires_at: now + i64::from(hours) * 3_600_000,
+            max_uses,
+            uses: 0,
+        };
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO share_links (id, route_id, label, created_by, created_at, expires_at, max_uses, uses)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0)",
+            params![
+                link.id,
+                link.route_id,
+                link.label,
+                link.created_by,
+                link.created_at,
+                link.expires_at,
+                link.max_uses,
+            ],
+        )?;
+        let token = self.token(&link.id, route_id);
+        Ok((link, token))
+    }
+
+    /// Liens encore valables d'une route, les plus récents d'abord
+    pub fn list(&self, route_id: &str) -> Result<Vec<ShareLink>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM share_links WHERE route_id = ?1 AND expires_at > ?2 ORDER BY created_at DESC",
+            SHARE_COLUMNS
+        ))?;
+        let links = stmt
+            .query_map(params![route_id, now_ms()], link_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(links)
+    }
+
+    /// Révoque un lien ; false s'il n'existe pas pour cette route
+    pub fn revoke(&self, route_id: &str, id: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let deleted = conn.execute(
+            "DELETE FROM share_links WHERE id = ?1 AND route_id = ?2",
+            params![id, route_id],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    /// Ouverture du lien (jeton dans l'URL) : compte une utilisation si le
+    /// lien est valable pour `route_id` et qu'il en reste
+    pub fn redeem(&self, token: &str, route_id: &str) -> Option<ShareLink> {
+        let id = self.verify(token, route_id)?;
+        let conn = self.conn.lock().unwrap();
+        let updated = conn
+            .execute(
+                "UPDATE share_links SET uses = uses + 1
+                 WHERE id = ?1 AND route_id = ?2 AND expires_at > ?3
+                   AND (max_uses IS NULL OR uses < max_uses)",
+                params![id, route_id, now_ms()],
+            )
+            .ok()?;
+        if updated == 0 {
+            return None;
+        }
+        get(&conn, id).ok().flatten()
+    }
+
+    /// Cookie posé à l'ouverture : valable jusqu'à l'expiration du lien,
+    /// sans compter d'utilisation
+    pub fn validate(&self, token: &str, route_id: &str) -> Option<ShareLink> {
+        let id = self.verify(token, route_id)?;
+        let conn = self.conn.lock().unwrap();
+        get(&conn, id)
+            .ok()
+            .flatten()
+            .filter(|link| link.route_id == route_id && link.expires_at > now_ms())
+    }
+
+    /// Supprime les liens expirés
+    pub fn cleanup_expired(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM share_links WHERE expires_at < ?1", params![now_ms()])?;
+        Ok(())
+    }
+
+    fn token(&self, id: &str, route_id: &str) -> String {
+        let tag = hmac::sign(&self.key, signed_input(id, route_id).as_bytes());
+        format!("{}.{}", id, URL_SAFE_NO_PAD.encode(tag.as_ref()))
+    }
+
+    /// Id du lien si la signature correspond à la route
+    fn verify<'a>(&self, token: &'a str, route_id: &str) -> Option<&'a str> {
+        let (id, signature) = token.split_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        hmac::verify(&self.key, signed_input(id, route_id).as_bytes(), &signature).ok()?;
+        Some(id)
+    }
+}
+
+fn signed_input(id: &str, route_id: &str) -> String {
+    format!("{}:{}", id, route_id)
+}
+
+fn get(conn: &Connection, id: &str) -> Result<Option<ShareLink>> {
+    let link = conn
+        .query_row(
+            &format!("SELECT {} FROM share_links WHERE id = ?1", SHARE_COLUMNS),
+            params![id],
+            link_from_row,
+        )
+        .optional()?;
+    Ok(link)
+}
+
+const SHARE_COLUMNS: &str = "id, route_id, label, created_by, created_at, expires_at, max_uses, uses";
+
+fn link_from_row(row: &rusqlite::Row) -> rusqlite::Result<ShareLink> {
+    Ok(ShareLink {
+        id: row.get(0)?,
+        route_id: row.get(1)?,
+        label: row.get(2)?,
+        created_by: row.get(3)?,
+        created_at: row.get(4)?,
+        expires_at: row.get(5)?,
+        max_uses: row.get(6)?,
+        uses: row.get(7)?,
+    })
+}
+
+fn load_or_create_key(path: &Path) -> Result<hmac::Key> {
+    let secret = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let mut bytes = vec![0u8; 32];
+            OsRng.fill_bytes(&mut bytes);
+            crate::oidc::write_private(path, &bytes)?;
+            tracing::info!("Cle des liens de partage creee: {}", path.display());
+            bytes
+        }
+        Err(e) => return Err(e).with_context(|| format!("Lecture de {}", path.display())),
+    };
+    if secret.len() < 32 {
+        return Err(anyhow!("Cle des liens de partage invalide: {}", path.display()));
+    }
+    Ok(hmac::Key::new(hmac::HMAC_SHA256, &secret))
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redeem_counts_uses() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ShareStore::new(dir.path()).unwrap();
+        let (link, token) = store.create("photos", 24, Some(2), Some(" Album "), Some("alice")).unwrap();
+        assert_eq!(link.label.as_deref(), Some("Album"));
+
+        assert_eq!(store.redeem(&token, "photos").unwrap().uses, 1);
+        assert_eq!(store.redeem(&token, "photos").unwrap().uses, 2);
+        assert!(store.redeem(&token, "photos").is_none());
+        // Le cookie reste valable une fois les ouvertures épuisées
+        assert!(store.validate(&token, "photos").is_some());
+    }
+
+    #[test]
+    fn test_token_bound_to_route() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ShareStore::new(dir.path()).unwrap();
+        let (link, token) = store.create("photos", 1, None, None, None).unwrap();
+        assert!(store.validate(&token, "grafana").is_none());
+        assert!(store.redeem(&token, "grafana").is_none());
+        assert!(store.validate(&format!("{}.AAAA", link.id), "photos").is_none());
+        assert!(store.validate(&link.id, "photos").is_none());
+
+        // Même clé après redémarrage
+        let reopened = ShareStore::new(dir.path()).unwrap();
+        assert!(reopened.validate(&token, "photos").is_some());
+    }
+
+    #[test]
+    fn test_expired_and_revoked() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ShareStore::new(dir.path()).unwrap();
+        let (expired, expired_token) = store.create("photos", 1, None, None, None).unwrap();
+        let (revoked, revoked_token) = store.create("photos", 1, None, None, None).unwrap();
+        let (kept, _) = store.create("photos", 1, None, None, None).unwrap();
+        store.conn.lock().unwrap()
+            .execute("UPDATE share_links SET expires_at = 0 WHERE id = ?1", params![expired.id])
+            .unwrap();
+        assert!(store.redeem(&expired_token, "photos").is_none());
+        assert!(store.validate(&expired_token, "photos").is_none());
+
+        assert!(store.revoke("photos", &revoked.id).unwrap());
+        assert!(!store.revoke("photos", &revoked.id).unwrap());
+        assert!(store.validate(&revoked_token, "photos").is_none());
+
+        let listed: Vec<_> = store.list("photos").unwrap().into_iter().map(|l| l.id).collect();
+        assert_eq!(listed, vec![kept.id]);
+    }
+
+    #[test]
+    fn test_create_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ShareStore::new(dir.path()).unwrap();
+        assert!(store.create("photos", 0, None, None, None).is_err());
+        assert!(store.create("photos", MAX_SHARE_HOURS + 1, None, None, None).is_err());
+        assert!(store.create("photos", 1, Some(0), None, None).is_err());
+    }
+}
@@ -0,0 +1,531 @@
+//! Connexion déléguée à un fournisseur OpenID Connect externe (Google,
+//! Authentik, Keycloak...).
+//!
+//! Flux "authorization code" avec PKCE : `/sso/login` redirige vers le
+//! fournisseur, `/sso/callback` échange le code et vérifie l'ID token
+//! (signature RS256/ES256 via le JWKS, émetteur, audience, expiration,
+//! nonce). Les comptes sont créés à la première connexion et leurs groupes
+//! dérivés de ceux du fournisseur via `group_mapping`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand_core::{OsRng, RngCore};
+use ring::digest::{digest, SHA256};
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Durée pour revenir du fournisseur
+const LOGIN_TTL: Duration = Duration::from_secs(600);
+/// Connexions en attente au maximum (la route est publique)
+const MAX_PENDING: usize = 1024;
+/// Durée de cache de la configuration du fournisseur et de ses clés
+const DISCOVERY_TTL: Duration = Duration::from_secs(3600);
+
+const CONFIG_FILE: &str = "sso.yml";
+
+/// Fournisseur externe (sso.yml)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SsoConfig {
+    pub enabled: bool,
+    /// Libellé du bouton de connexion
+    pub name: String,
+    /// Émetteur (découverte via `/.well-known/openid-configuration`)
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scopes: Vec<String>,
+    /// Claim donnant le nom d'utilisateur (repli : partie locale de l'email)
+    pub username_claim: String,
+    /// Claim listant les groupes chez le fournisseur
+    pub groups_claim: String,
+    /// Groupe du fournisseur -> groupes HomeRoute
+    pub group_mapping: HashMap<String, Vec<String>>,
+    /// Groupes donnés à tout compte SSO
+    pub default_groups: Vec<String>,
+    /// Créer le compte à la première connexion
+    pub auto_provision: bool,
+    /// Domaines email acceptés (vide = tous)
+    pub allowed_email_domains: Vec<String>,
+}
+
+impl Default for SsoConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            name: "SSO".to_string(),
+            issuer: String::new(),
+            client_id: String::new(),
+            client_secret: String::new(),
+            scopes: vec!["openid".to_string(), "profile".to_string(), "email".to_string()],
+            username_claim: "preferred_username".to_string(),
+            groups_claim: "groups".to_string(),
+            group_mapping: HashMap::new(),
+            default_groups: Vec::new(),
+            auto_provision: true,
+            allowed_email_domains: Vec::new(),
+        }
+    }
+}
+
+impl SsoConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if !self.issuer.starts_with("https://") {
+            return Err("L'emetteur doit etre une URL https".to_string());
+        }
+        if self.client_id.is_empty() {
+            return Err("client_id requis".to_string());
+        }
+        if !self.scopes.iter().any(|s| s == "openid") {
+            return Err("Le scope openid est requis".to_string());
+        }
+        Ok(())
+    }
+
+    /// Groupes HomeRoute pour les groupes du fournisseur. `None` si le
+    /// fournisseur ne donne pas de groupes et qu'aucun mapping n'est défini
+    /// (les groupes du compte sont alors gérés localement).
+    pub fn map_groups(&self, claims: &Map<String, Value>) -> Option<Vec<String>> {
+        let external: Vec<&str> = match claims.get(&self.groups_claim) {
+            Some(Value::Array(groups)) => groups.iter().filter_map(Value::as_str).collect(),
+            Some(Value::String(group)) => vec![group.as_str()],
+            _ => Vec::new(),
+        };
+        if external.is_empty() && self.group_mapping.is_empty() && self.default_groups.is_empty() {
+            return None;
+        }
+        let mut groups = self.default_groups.clone();
+        for group in external {
+            for mapped in self.group_mapping.get(group).into_iter().flatten() {
+                if !groups.contains(mapped) {
+                    groups.push(mapped.clone());
+                }
+            }
+        }
+        Some(groups)
+    }
+
+    fn email_allowed(&self, email: &str) -> bool {
+        self.allowed_email_domains.is_empty()
+            || email
+                .rsplit_once('@')
+                .is_some_and(|(_, domain)| {
+                    self.allowed_email_domains.iter().any(|d| d.eq_ignore_ascii_case(domain))
+                })
+    }
+}
+
+/// Utilisateur authentifié par le fournisseur
+#[derive(Debug, Clone)]
+pub struct ExternalIdentity {
+    /// Identifiant stable : `issuer|sub`
+    pub subject: String,
+    /// Nom souhaité (ajusté au format local à la création)
+    pub username: String,
+    pub displayname: String,
+    pub email: String,
+    /// Groupes HomeRoute (`None` : ne pas toucher aux groupes du compte)
+    pub groups: Option<Vec<String>>,
+}
+
+/// Métadonnées du fournisseur (document de découverte)
+#[derive(Debug, Clone, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+struct PendingLogin {
+    verifier: String,
+    nonce: String,
+    return_to: Option<String>,
+    expires: Instant,
+}
+
+/// Connexion par fournisseur externe pour un domaine
+pub struct ExternalSso {
+    config_path: PathBuf,
+    redirect_uri: String,
+    http: reqwest::Client,
+    pending: Mutex<HashMap<String, PendingLogin>>,
+    /// (émetteur, découverte, JWKS, date)
+    cache: Mutex<Option<(String, Discovery, Value, Instant)>>,
+}
+
+impl ExternalSso {
+    pub fn new(data_dir: &Path, base_domain: &str) -> Self {
+        Self {
+            config_path: data_dir.join(CONFIG_FILE),
+            redirect_uri: format!("https://auth.{}/api/auth/sso/callback", base_domain),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            pending: Mutex::new(HashMap::new()),
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// URL de retour à déclarer chez le fournisseur
+    pub fn redirect_uri(&self) -> &str {
+        &self.redirect_uri
+    }
+
+    pub fn config(&self) -> SsoConfig {
+        std::fs::read_to_string(&self.config_path)
+            .ok()
+            .and_then(|content| serde_yaml::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save_config(&self, config: &SsoConfig) -> Result<()> {
+        config.validate().map_err(|e| anyhow!(e))?;
+        std::fs::write(&self.config_path, serde_yaml::to_string(config)?)?;
+        *self.cache.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        Ok(())
+    }
+
+    /// URL d'autorisation chez le fournisseur. `return_to` : page à
+    /// rouvrir après la connexion.
+    pub async fn start(&self, return_to: Option<String>) -> Result<String> {
+        let config = self.config();
+        if !config.enabled {
+            bail!("Connexion SSO desactivee");
+        }
+        let (discovery, _) = self.provider(&config).await?;
+
+        let state = random_token();
+        let verifier = random_token();
+        let nonce = random_token();
+        let challenge = URL_SAFE_NO_PAD.encode(digest(&SHA256, verifier.as_bytes()));
+        {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            pending.retain(|_, p| p.expires > now);
+            if pending.len() >= MAX_PENDING {
+                bail!("Trop de connexions en cours");
+            }
+            pending.insert(state.clone(), PendingLogin {
+                verifier,
+                nonce: nonce.clone(),
+                return_to,
+                expires: now + LOGIN_TTL,
+            });
+        }
+
+        let params = [
+            ("response_type", "code"),
+            ("client_id", config.client_id.as_str()),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("scope", &config.scopes.join(" ")),
+            ("state", &state),
+            ("nonce", &nonce),
+            ("code_challenge", &challenge),
+            ("code_challenge_method", "S256"),
+        ];
+        let query: Vec<String> = params
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, crate::forward_auth::urlencoded(v)))
+            .collect();
+        let sep = if discovery.authorization_endpoint.contains('?') { '&' } else { '?' };
+        Ok(format!("{}{}{}", discovery.authorization_endpoint, sep, query.join("&")))
+    }
+
+    /// Échange le code reçu sur le callback et vérifie l'ID token. Renvoie
+    /// l'identité et la page à rouvrir.
+    pub async fn finish(&self, code: &str, state: &str) -> Result<(ExternalIdentity, Option<String>)> {
+        let pending = self
+            .pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(state)
+            .filter(|p| p.expires > Instant::now())
+            .ok_or_else(|| anyhow!("Connexion expiree, recommencez"))?;
+        let config = self.config();
+        if !config.enabled {
+            bail!("Connexion SSO desactivee");
+        }
+        let (discovery, jwks) = self.provider(&config).await?;
+
+        let resp = self
+            .http
+            .post(&discovery.token_endpoint)
+            .basic_auth(&config.client_id, Some(&config.client_secret))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("client_id", config.client_id.as_str()),
+                ("code_verifier", pending.verifier.as_str()),
+            ])
+            .send()
+            .await
+            .context("Fournisseur injoignable")?;
+        let status = resp.status();
+        let body: Value = resp.json().await.context("Reponse du fournisseur invalide")?;
+        if !status.is_success() {
+            bail!(
+                "Echange du code refuse: {}",
+                body["error_description"].as_str().or(body["error"].as_str()).unwrap_or("erreur inconnue")
+            );
+        }
+        let id_token = body["id_token"].as_str().ok_or_else(|| anyhow!("id_token manquant"))?;
+
+        let claims = verify_id_token(
+            id_token,
+            &jwks,
+            &discovery.issuer,
+            &config.client_id,
+            &pending.nonce,
+            chrono::Utc::now().timestamp(),
+        )?;
+        let identity = identity_from_claims(&config, &discovery.issuer, &claims)?;
+        Ok((identity, pending.return_to))
+    }
+
+    /// Découverte et JWKS du fournisseur (en cache une heure)
+    async fn provider(&self, config: &SsoConfig) -> Result<(Discovery, Value)> {
+        if let Some((issuer, discovery, jwks, fetched)) = &*self.cache.lock().unwrap_or_else(|e| e.into_inner())
+            && issuer == &config.issuer
+            && fetched.elapsed() < DISCOVERY_TTL
+        {
+            return Ok((discovery.clone(), jwks.clone()));
+        }
+
+        let url = format!("{}/.well-known/openid-configuration", config.issuer.trim_end_matches('/'));
+        let discovery: Discovery = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("Decouverte impossible: {}", url))?
+            .json()
+            .await
+            .context("Document de decouverte invalide")?;
+        if discovery.issuer.trim_end_matches('/') != config.issuer.trim_end_matches('/') {
+            bail!("Emetteur annonce different: {}", discovery.issuer);
+        }
+        let jwks: Value = self
+            .http
+            .get(&discovery.jwks_uri)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .context("JWKS injoignable")?
+            .json()
+            .await
+            .context("JWKS invalide")?;
+
+        *self.cache.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some((config.issuer.clone(), discovery.clone(), jwks.clone(), Instant::now()));
+        Ok((discovery, jwks))
+    }
+}
+
+/// Vérifie un ID token (signature, émetteur, audience, expiration, nonce)
+/// et renvoie ses claims.
+fn verify_id_token(
+    token: &str,
+    jwks: &Value,
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+    now: i64,
+) -> Result<Map<String, Value>> {
+    let mut parts = token.split('.');
+    let (Some(header_b64), Some(payload_b64), Some(sig_b64), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        bail!("ID token mal forme");
+    };
+    let header: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header_b64)?)?;
+    let alg = header["alg"].as_str().unwrap_or("");
+    let kid = header["kid"].as_str();
+
+    let keys = jwks["keys"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let key = keys
+        .iter()
+        .filter(|k| kid.is_none() || k["kid"].as_str() == kid)
+        .find(|k| k["alg"].as_str().is_none_or(|a| a == alg))
+        .ok_or_else(|| anyhow!("Cle de signature inconnue"))?;
+
+    let message = format!("{}.{}", header_b64, payload_b64);
+    let sig = URL_SAFE_NO_PAD.decode(sig_b64)?;
+    let jwk = |name: &str| -> Result<Vec<u8>> {
+        Ok(URL_SAFE_NO_PAD.decode(key[name].as_str().ok_or_else(|| anyhow!("JWK incomplet"))?)?)
+    };
+    let verified = match (alg, key["kty"].as_str()) {
+        ("RS256", Some("RSA")) => RsaPublicKeyComponents { n: jwk("n")?, e: jwk("e")? }
+            .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message.as_bytes(), &sig)
+            .is_ok(),
+        ("ES256", Some("EC")) => {
+            let mut point = vec![0x04];
+            point.extend(jwk("x")?);
+            point.extend(jwk("y")?);
+            UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                .verify(message.as_bytes(), &sig)
+                .is_ok()
+        }
+        _ => bail!("Algorithme non pris en charge: {}", alg),
+    };
+    if !verified {
+        bail!("Signature de l'ID token invalide");
+    }
+
+    let claims: Map<String, Value> = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload_b64)?)?;
+    if claims.get("iss").and_then(Value::as_str) != Some(issuer) {
+        bail!("Emetteur de l'ID token invalide");
+    }
+    let audience_ok = match claims.get("aud") {
+        Some(Value::String(aud)) => aud == client_id,
+        Some(Value::Array(auds)) => auds.iter().any(|a| a == client_id),
+        _ => false,
+    };
+    if !audience_ok {
+        bail!("ID token emis pour un autre client");
+    }
+    if claims.get("exp").and_then(Value::as_i64).unwrap_or(0) <= now {
+        bail!("ID token expire");
+    }
+    if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+        bail!("Nonce invalide");
+    }
+    Ok(claims)
+}
+
+fn identity_from_claims(config: &SsoConfig, issuer: &str, claims: &Map<String, Value>) -> Result<ExternalIdentity> {
+    let claim = |name: &str| claims.get(name).and_then(Value::as_str).unwrap_or("").to_string();
+    let sub = claim("sub");
+    if sub.is_empty() {
+        bail!("Claim sub manquant");
+    }
+    let email = claim("email");
+    // Un email non vérifié ne prouve pas l'appartenance au domaine
+    let email_verified = claims.get("email_verified").and_then(Value::as_bool).unwrap_or(true);
+    if !config.allowed_email_domains.is_empty() && (!email_verified || !config.email_allowed(&email)) {
+        bail!("Domaine email non autorise");
+    }
+
+    let username = Some(claim(&config.username_claim))
+        .filter(|u| !u.is_empty())
+        .or_else(|| email.split_once('@').map(|(local, _)| local.to_string()))
+        .unwrap_or_else(|| sub.clone());
+    let displayname = Some(claim("name")).filter(|n| !n.is_empty()).unwrap_or_else(|| username.clone());
+    Ok(ExternalIdentity {
+        subject: format!("{}|{}", issuer, sub),
+        username,
+        displayname,
+        email,
+        groups: config.map_groups(claims),
+    })
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair};
+    use serde_json::json;
+
+    const ISSUER: &str = "https://idp.example.com";
+
+    fn keypair() -> EcdsaKeyPair {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap()
+    }
+
+    fn jwks(key: &EcdsaKeyPair) -> Value {
+        let point = key.public_key().as_ref();
+        json!({"keys": [{
+            "kty": "EC", "crv": "P-256", "alg": "ES256", "kid": "k1",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..65])
+        }]})
+    }
+
+    fn sign(key: &EcdsaKeyPair, claims: &Value) -> String {
+        let input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(json!({"alg": "ES256", "kid": "k1"}).to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let sig = key.sign(&SystemRandom::new(), input.as_bytes()).unwrap();
+        format!("{}.{}", input, URL_SAFE_NO_PAD.encode(sig.as_ref()))
+    }
+
+    fn claims() -> Value {
+        json!({
+            "iss": ISSUER, "aud": "homeroute", "sub": "42", "exp": 2_000,
+            "nonce": "n1", "email": "bob@family.example", "name": "Bob",
+            "groups": ["parents", "other"]
+        })
+    }
+
+    #[test]
+    fn test_verify_id_token() {
+        let key = keypair();
+        let jwks = jwks(&key);
+        let token = sign(&key, &claims());
+        let verified = verify_id_token(&token, &jwks, ISSUER, "homeroute", "n1", 1_000).unwrap();
+        assert_eq!(verified["sub"], "42");
+
+        assert!(verify_id_token(&token, &jwks, ISSUER, "homeroute", "n2", 1_000).is_err());
+        assert!(verify_id_token(&token, &jwks, ISSUER, "other", "n1", 1_000).is_err());
+        assert!(verify_id_token(&token, &jwks, "https://evil.example", "homeroute", "n1", 1_000).is_err());
+        assert!(verify_id_token(&token, &jwks, ISSUER, "homeroute", "n1", 3_000).is_err());
+        // Signé par une autre clé
+        let forged = sign(&keypair(), &claims());
+        assert!(verify_id_token(&forged, &jwks, ISSUER, "homeroute", "n1", 1_000).is_err());
+    }
+
+    #[test]
+    fn test_identity_and_group_mapping() {
+        let mut config = SsoConfig {
+            enabled: true,
+            issuer: ISSUER.to_string(),
+            client_id: "homeroute".to_string(),
+            default_groups: vec!["users".to_string()],
+            ..Default::default()
+        };
+        config.group_mapping.insert("parents".to_string(), vec!["admins".to_string(), "users".to_string()]);
+        let Value::Object(claims) = claims() else { unreachable!() };
+
+        let identity = identity_from_claims(&config, ISSUER, &claims).unwrap();
+        assert_eq!(identity.subject, "https://idp.example.com|42");
+        // Pas de preferred_username : partie locale de l'email
+        assert_eq!(identity.username, "bob");
+        assert_eq!(identity.displayname, "Bob");
+        assert_eq!(identity.groups, Some(vec!["users".to_string(), "admins".to_string()]));
+
+        config.allowed_email_domains = vec!["other.example".to_string()];
+        assert!(identity_from_claims(&config, ISSUER, &claims).is_err());
+
+        // Ni groupes ni mapping : groupes gérés localement
+        let plain = SsoConfig::default();
+        let mut without_groups = claims.clone();
+        without_groups.remove("groups");
+        assert_eq!(plain.map_groups(&without_groups), None);
+        assert!(plain.validate().is_ok());
+        assert!(SsoConfig { enabled: true, ..Default::default() }.validate().is_err());
+    }
+}
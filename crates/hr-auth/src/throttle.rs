@@ -0,0 +1,329 @@
+//! Protection contre la force brute sur la connexion (mot de passe, TOTP ou passkey).
+//!
+//! Les échecs sont comptés par nom d'utilisateur et par IP (en mémoire,
+//! oubliés après une heure sans échec). Au-delà d'un seuil, la clé est
+//! verrouillée pour une durée qui double à chaque nouvel échec. Chaque échec
+//! et chaque verrouillage est publié sur l'EventBus (`auth_security`).
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use hr_common::events::{AuthSecurityEvent, AuthSecurityKind, EventBus};
+use serde::Serialize;
+
+/// Échecs tolérés par utilisateur avant verrouillage
+const USER_THRESHOLD: u32 = 5;
+/// Échecs tolérés par IP (plusieurs personnes derrière un NAT)
+const IP_THRESHOLD: u32 = 20;
+/// Premier verrouillage, doublé à chaque échec suivant
+const BASE_LOCKOUT: Duration = Duration::from_secs(30);
+const MAX_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+/// Compteurs oubliés après cette durée sans échec
+const FAILURE_WINDOW: Duration = Duration::from_secs(3600);
+/// Utilisateurs distincts essayés depuis une IP : credential stuffing
+const STUFFING_USERS: usize = 10;
+/// Au-delà, les entrées périmées sont purgées à chaque échec
+const MAX_ENTRIES: usize = 10_000;
+
+/// Décision avant de vérifier le mot de passe
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoginCheck {
+    Allowed,
+    /// Utilisateur ou IP verrouillé
+    Locked { retry_after: Duration },
+}
+
+/// Verrouillage en cours (liste admin)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lockout {
+    /// "user" ou "ip"
+    pub kind: &'static str,
+    pub key: String,
+    pub failures: u32,
+    pub remaining_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    User(String),
+    Ip(String),
+}
+
+impl Key {
+    fn threshold(&self) -> u32 {
+        match self {
+            Key::User(_) => USER_THRESHOLD,
+            Key::Ip(_) => IP_THRESHOLD,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    failures: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+    /// Utilisateurs essayés (entrées IP uniquement)
+    users: HashSet<String>,
+    stuffing_reported: bool,
+}
+
+impl Entry {
+    fn is_stale(&self, now: Instant) -> bool {
+        self.locked_until.is_none_or(|until| until <= now) && now.duration_since(self.last_failure) > FAILURE_WINDOW
+    }
+
+    fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.locked_until.filter(|until| *until > now).map(|until| until - now)
+    }
+}
+
+/// Suivi des échecs de connexion (thread-safe, partagé via AuthService)
+#[derive(Default)]
+pub struct LoginThrottle {
+    entries: Mutex<HashMap<Key, Entry>>,
+    events: RwLock<Option<Arc<EventBus>>>,
+}
+
+impl LoginThrottle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bus d'événements pour les alertes (UI)
+    pub fn set_events(&self, events: Arc<EventBus>) {
+        *self.events.write().unwrap() = Some(events);
+    }
+
+    /// À appeler avant de vérifier le mot de passe ou la passkey
+    pub fn check(&self, username: &str, ip: Option<&str>) -> LoginCheck {
+        self.check_at(username, ip, Instant::now())
+    }
+
+    fn check_at(&self, username: &str, ip: Option<&str>, now: Instant) -> LoginCheck {
+        let entries = self.entries.lock().unwrap();
+        let retry_after = keys(username, ip)
+            .iter()
+            .filter_map(|key| entries.get(key).filter(|e| !e.is_stale(now)))
+            .filter_map(|entry| entry.remaining(now))
+            .max();
+        match retry_after {
+            Some(retry_after) => LoginCheck::Locked { retry_after },
+            None => LoginCheck::Allowed,
+        }
+    }
+
+    /// Mot de passe, code TOTP ou assertion de passkey refusé
+    pub fn record_failure(&self, username: &str, ip: Option<&str>) {
+        self.record_failure_at(username, ip, Instant::now());
+    }
+
+    fn record_failure_at(&self, username: &str, ip: Option<&str>, now: Instant) {
+        let mut events = Vec::new();
+        let mut distinct_users = 0;
+        let mut failures = 0;
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if entries.len() > MAX_ENTRIES {
+                entries.retain(|_, e| !e.is_stale(now));
+            }
+            for key in keys(username, ip) {
+                let entry = entries.entry(key.clone()).or_insert_with(|| Entry {
+                    failures: 0,
+                    last_failure: now,
+                    locked_until: None,
+                    users: HashSet::new(),
+                    stuffing_reported: false,
+                });
+                if entry.is_stale(now) {
+                    entry.failures = 0;
+                    entry.users.clear();
+                    entry.stuffing_reported = false;
+                }
+                entry.failures += 1;
+                entry.last_failure = now;
+                failures = failures.max(entry.failures);
+
+                let threshold = key.threshold();
+                if entry.failures >= threshold {
+                    let lockout = lockout_for(entry.failures - threshold);
+                    entry.locked_until = Some(now + lockout);
+                    events.push((AuthSecurityKind::LockedOut, entry.failures, Some(lockout.as_secs())));
+                    hr_common::metrics::registry()
+                        .counter(
+                            "homeroute_auth_lockouts_total",
+                            "Login lockouts, by locked key (user or ip).",
+                            &[("key", key_kind(&key))],
+                        )
+                        .inc();
+                }
+
+                if let Key::Ip(_) = key {
+                    if entry.users.len() < STUFFING_USERS * 4 {
+                        entry.users.insert(username.to_string());
+                    }
+                    distinct_users = entry.users.len();
+                    if distinct_users >= STUFFING_USERS && !entry.stuffing_reported {
+                        entry.stuffing_reported = true;
+                        events.push((AuthSecurityKind::CredentialStuffing, entry.failures, None));
+                    }
+                }
+            }
+        }
+
+        hr_common::metrics::registry()
+            .counter("homeroute_auth_login_failures_total", "Failed password, TOTP or passkey logins.", &[])
+            .inc();
+        match events.iter().find(|(kind, ..)| *kind == AuthSecurityKind::LockedOut) {
+            Some(_) => tracing::warn!(username, ip = ?ip, failures, "Connexion verrouillee apres trop d'echecs"),
+            None => tracing::info!(username, ip = ?ip, failures, "Echec de connexion"),
+        }
+        if events.iter().any(|(kind, ..)| *kind == AuthSecurityKind::CredentialStuffing) {
+            tracing::warn!(ip = ?ip, distinct_users, "Credential stuffing probable");
+        }
+
+        let Some(bus) = self.events.read().unwrap().clone() else {
+            return;
+        };
+        events.insert(0, (AuthSecurityKind::LoginFailed, failures, None));
+        for (kind, failures, locked_for_secs) in events {
+            let _ = bus.auth_security.send(AuthSecurityEvent {
+                kind,
+                username: username.to_string(),
+                ip: ip.map(str::to_string),
+                failures,
+                locked_for_secs,
+                distinct_users,
+            });
+        }
+    }
+
+    /// Connexion réussie : les compteurs de l'utilisateur et de l'IP repartent à zéro
+    pub fn record_success(&self, username: &str, ip: Option<&str>) {
+        let mut entries = self.entries.lock().unwrap();
+        for key in keys(username, ip) {
+            entries.remove(&key);
+        }
+    }
+
+    /// Verrouillages en cours
+    pub fn lockouts(&self) -> Vec<Lockout> {
+        let now = Instant::now();
+        let entries = self.entries.lock().unwrap();
+        let mut lockouts: Vec<Lockout> = entries
+            .iter()
+            .filter_map(|(key, entry)| {
+                let remaining = entry.remaining(now)?;
+                let (kind, key) = match key {
+                    Key::User(u) => ("user", u.clone()),
+                    Key::Ip(ip) => ("ip", ip.clone()),
+                };
+                Some(Lockout { kind, key, failures: entry.failures, remaining_secs: remaining.as_secs() + 1 })
+            })
+            .collect();
+        lockouts.sort_by_key(|l| std::cmp::Reverse(l.remaining_secs));
+        lockouts
+    }
+
+    /// Lève tous les verrouillages et oublie les échecs
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+fn keys(username: &str, ip: Option<&str>) -> Vec<Key> {
+    let mut keys = vec![Key::User(username.to_string())];
+    if let Some(ip) = ip {
+        keys.push(Key::Ip(ip.to_string()));
+    }
+    keys
+}
+
+fn key_kind(key: &Key) -> &'static str {
+    match key {
+        Key::User(_) => "user",
+        Key::Ip(_) => "ip",
+    }
+}
+
+/// Durée du verrouillage au `extra`-ième échec au-delà du seuil
+fn lockout_for(extra: u32) -> Duration {
+    BASE_LOCKOUT.saturating_mul(1 << extra.min(16)).min(MAX_LOCKOUT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_lockout_doubles() {
+        let throttle = LoginThrottle::new();
+        let t0 = Instant::now();
+        for _ in 0..USER_THRESHOLD - 1 {
+            throttle.record_failure_at("alice", Some("10.0.0.2"), t0);
+        }
+        assert_eq!(throttle.check_at("alice", Some("10.0.0.2"), t0), LoginCheck::Allowed);
+
+        throttle.record_failure_at("alice", Some("10.0.0.2"), t0);
+        assert_eq!(
+            throttle.check_at("alice", Some("10.0.0.9"), t0),
+            LoginCheck::Locked { retry_after: BASE_LOCKOUT }
+        );
+        // Autre utilisateur depuis la même IP : pas verrouillé
+        assert_eq!(throttle.check_at("bob", Some("10.0.0.2"), t0), LoginCheck::Allowed);
+
+        let t1 = t0 + BASE_LOCKOUT;
+        assert_eq!(throttle.check_at("alice", None, t1), LoginCheck::Allowed);
+        throttle.record_failure_at("alice", None, t1);
+        assert_eq!(
+            throttle.check_at("alice", None, t1),
+            LoginCheck::Locked { retry_after: BASE_LOCKOUT * 2 }
+        );
+        assert_eq!(throttle.lockouts().len(), 1);
+
+        throttle.record_success("alice", None);
+        assert_eq!(throttle.check_at("alice", None, t1), LoginCheck::Allowed);
+    }
+
+    #[test]
+    fn test_failures_forgotten_after_window() {
+        let throttle = LoginThrottle::new();
+        let t0 = Instant::now();
+        for _ in 0..USER_THRESHOLD - 1 {
+            throttle.record_failure_at("alice", None, t0);
+        }
+        let later = t0 + FAILURE_WINDOW + Duration::from_secs(1);
+        throttle.record_failure_at("alice", None, later);
+        assert_eq!(throttle.check_at("alice", None, later), LoginCheck::Allowed);
+    }
+
+    #[test]
+    fn test_credential_stuffing_event() {
+        let throttle = LoginThrottle::new();
+        let bus = Arc::new(EventBus::new());
+        let mut rx = bus.auth_security.subscribe();
+        throttle.set_events(bus);
+        let t0 = Instant::now();
+        for i in 0..STUFFING_USERS {
+            throttle.record_failure_at(&format!("user{}", i), Some("203.0.113.7"), t0);
+        }
+        let mut stuffing = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            if event.kind == AuthSecurityKind::CredentialStuffing {
+                stuffing.push(event);
+            }
+        }
+        assert_eq!(stuffing.len(), 1);
+        assert_eq!(stuffing[0].ip.as_deref(), Some("203.0.113.7"));
+        assert_eq!(stuffing[0].distinct_users, STUFFING_USERS);
+    }
+
+    #[test]
+    fn test_lockout_capped() {
+        assert_eq!(lockout_for(0), BASE_LOCKOUT);
+        assert_eq!(lockout_for(3), BASE_LOCKOUT * 8);
+        assert_eq!(lockout_for(40), MAX_LOCKOUT);
+    }
+}
@@ -0,0 +1,272 @@
+//! TOTP (RFC 6238) : second facteur par application d'authentification.
+//!
+//! HMAC-SHA1, 6 chiffres, pas de 30 s — les paramètres que toutes les
+//! applications comprennent. Une dérive d'un pas de chaque côté est tolérée
+//! et un pas déjà utilisé est refusé (rejeu). Les codes de secours sont à
+//! usage unique et seul leur condensat SHA-256 est stocké.
+
+use base64::Engine;
+use rand_core::{OsRng, RngCore};
+use ring::{digest, hmac};
+use serde::{Deserialize, Serialize};
+
+/// Durée d'un pas
+pub const STEP_SECS: u64 = 30;
+/// Pas tolérés de chaque côté du pas courant (horloge du téléphone)
+pub const DRIFT_STEPS: u64 = 1;
+const DIGITS: u32 = 6;
+const SECRET_LEN: usize = 20;
+/// Nombre de codes de secours générés
+pub const RECOVERY_CODES: usize = 10;
+
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// État TOTP d'un utilisateur (users.yml)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TotpState {
+    /// Secret partagé (base32)
+    pub secret: String,
+    /// Faux tant que l'enrôlement n'a pas été confirmé par un code
+    #[serde(default)]
+    pub enabled: bool,
+    /// Dernier pas accepté (anti-rejeu)
+    #[serde(default)]
+    pub last_step: Option<u64>,
+    /// Condensats des codes de secours restants
+    #[serde(default)]
+    pub recovery_codes: Vec<String>,
+    #[serde(default)]
+    pub created: Option<String>,
+}
+
+impl TotpState {
+    /// Nouvel enrôlement (non actif) avec un secret aléatoire
+    pub fn generate() -> Self {
+        let mut secret = [0u8; SECRET_LEN];
+        OsRng.fill_bytes(&mut secret);
+        Self {
+            secret: base32_encode(&secret),
+            created: Some(chrono::Utc::now().to_rfc3339()),
+            ..Default::default()
+        }
+    }
+
+    /// URI `otpauth://` à afficher en QR code dans l'application
+    pub fn provisioning_uri(&self, issuer: &str, account: &str) -> String {
+        format!(
+            "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            uri_encode(issuer),
+            uri_encode(account),
+            self.secret,
+            uri_encode(issuer),
+            DIGITS,
+            STEP_SECS
+        )
+    }
+
+    /// Vérifie un code à l'instant `now` (secondes Unix). En cas de succès,
+    /// le pas est mémorisé pour qu'un même code ne serve qu'une fois.
+    pub fn verify(&mut self, code: &str, now: u64) -> bool {
+        let code = code.trim();
+        if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return false;
+        }
+        let Some(key) = base32_decode(&self.secret) else {
+            return false;
+        };
+        let current = now / STEP_SECS;
+        let first = current.saturating_sub(DRIFT_STEPS);
+        for step in first..=current + DRIFT_STEPS {
+            if self.last_step.is_some_and(|last| step <= last) {
+                continue;
+            }
+            if constant_time_eq(code.as_bytes(), format_code(hotp(&key, step)).as_bytes()) {
+                self.last_step = Some(step);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Code attendu à l'instant `now`
+    #[cfg(test)]
+    pub(crate) fn code_at(&self, now: u64) -> String {
+        let key = base32_decode(&self.secret).unwrap_or_default();
+        format_code(hotp(&key, now / STEP_SECS))
+    }
+
+    /// Remplace les codes de secours ; renvoie les codes en clair (affichés
+    /// une seule fois).
+    pub fn regenerate_recovery_codes(&mut self) -> Vec<String> {
+        let codes: Vec<String> = (0..RECOVERY_CODES).map(|_| recovery_code()).collect();
+        self.recovery_codes = codes.iter().map(|c| hash_recovery_code(c)).collect();
+        codes
+    }
+
+    /// Consomme un code de secours
+    pub fn use_recovery_code(&mut self, code: &str) -> bool {
+        let hash = hash_recovery_code(code);
+        let before = self.recovery_codes.len();
+        self.recovery_codes.retain(|h| !constant_time_eq(h.as_bytes(), hash.as_bytes()));
+        self.recovery_codes.len() != before
+    }
+}
+
+/// HOTP (RFC 4226) : troncature dynamique de HMAC-SHA1(compteur)
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, key);
+    let tag = hmac::sign(&key, &counter.to_be_bytes());
+    let mac = tag.as_ref();
+    let offset = (mac[mac.len() - 1] & 0x0f) as usize;
+    let bin = u32::from_be_bytes([mac[offset], mac[offset + 1], mac[offset + 2], mac[offset + 3]])
+        & 0x7fff_ffff;
+    bin % 10u32.pow(DIGITS)
+}
+
+fn format_code(value: u32) -> String {
+    format!("{:0width$}", value, width = DIGITS as usize)
+}
+
+/// Code de secours : 10 caractères base32 groupés par 5 (ex. `K7Q2M-XD4TA`)
+fn recovery_code() -> String {
+    let mut bytes = [0u8; 7];
+    OsRng.fill_bytes(&mut bytes);
+    let encoded = base32_encode(&bytes);
+    format!("{}-{}", &encoded[..5], &encoded[5..10])
+}
+
+/// Les tirets, espaces et la casse sont ignorés à la saisie
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let hash = digest::digest(&digest::SHA256, normalized.as_bytes());
+    base64::engine::general_purpose::STANDARD_NO_PAD.encode(hash.as_ref())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Base32 RFC 4648 sans padding (format des secrets TOTP)
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+fn base32_decode(input: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in input.chars().filter(|c| *c != '=' && !c.is_whitespace()) {
+        let value = BASE32.iter().position(|b| *b as char == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+fn uri_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Secret des vecteurs de test de la RFC 6238 ("12345678901234567890")
+    fn rfc_state() -> TotpState {
+        TotpState {
+            secret: base32_encode(b"12345678901234567890"),
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        let key = b"12345678901234567890";
+        // Annexe B de la RFC 6238 (8 chiffres tronqués à 6)
+        assert_eq!(hotp(key, 59 / STEP_SECS), 287082);
+        assert_eq!(hotp(key, 1111111109 / STEP_SECS), 81804);
+        assert_eq!(hotp(key, 1234567890 / STEP_SECS), 5924);
+        assert_eq!(format_code(hotp(key, 1234567890 / STEP_SECS)), "005924");
+    }
+
+    #[test]
+    fn test_base32_roundtrip() {
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32_decode("MZXW6YTBOI").unwrap(), b"foobar");
+        assert_eq!(base32_decode("mzxw6ytboi======").unwrap(), b"foobar");
+        assert!(base32_decode("MZXW1").is_none());
+        let state = TotpState::generate();
+        assert_eq!(base32_decode(&state.secret).unwrap().len(), SECRET_LEN);
+        assert!(!state.enabled);
+    }
+
+    #[test]
+    fn test_drift_window_and_replay() {
+        let now = 1111111109;
+        let key = b"12345678901234567890";
+        let previous = format_code(hotp(key, now / STEP_SECS - 1));
+        let current = format_code(hotp(key, now / STEP_SECS));
+        let too_old = format_code(hotp(key, now / STEP_SECS - 2));
+
+        let mut state = rfc_state();
+        assert!(!state.verify(&too_old, now));
+        assert!(!state.verify("12345", now));
+        assert!(state.verify(&previous, now));
+        // Pas déjà utilisé, puis pas suivant accepté une seule fois
+        assert!(!state.verify(&previous, now));
+        assert!(state.verify(&current, now));
+        assert!(!state.verify(&current, now));
+    }
+
+    #[test]
+    fn test_recovery_codes_single_use() {
+        let mut state = rfc_state();
+        let codes = state.regenerate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODES);
+        assert_eq!(state.recovery_codes.len(), RECOVERY_CODES);
+        assert!(!state.recovery_codes.contains(&codes[0]));
+
+        let typed = codes[0].to_lowercase().replace('-', " ");
+        assert!(state.use_recovery_code(&typed));
+        assert!(!state.use_recovery_code(&codes[0]));
+        assert_eq!(state.recovery_codes.len(), RECOVERY_CODES - 1);
+    }
+
+    #[test]
+    fn test_provisioning_uri() {
+        let state = rfc_state();
+        let uri = state.provisioning_uri("HomeRoute", "alice@example.com");
+        assert!(uri.starts_with("otpauth://totp/HomeRoute:alice%40example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&"));
+        assert!(uri.contains("&period=30"));
+    }
+}
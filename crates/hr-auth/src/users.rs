@@ -6,6 +6,9 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
+use crate::sso::ExternalIdentity;
+use crate::totp::TotpState;
+
 /// Données utilisateur sérialisées en YAML
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct UsersFile {
@@ -29,6 +32,34 @@ struct UserData {
     created: Option<String>,
     #[serde(default)]
     last_login: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    passkeys: Vec<Passkey>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    totp: Option<TotpState>,
+    /// Identité chez le fournisseur externe (`issuer|sub`), comptes SSO
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    external_id: Option<String>,
+}
+
+impl UserData {
+    fn totp_enabled(&self) -> bool {
+        self.totp.as_ref().is_some_and(|t| t.enabled)
+    }
+}
+
+/// Passkey (clé WebAuthn) enregistrée par un utilisateur
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Passkey {
+    /// Identifiant de la clé (base64url)
+    pub id: String,
+    pub name: String,
+    /// Clé publique COSE (base64url)
+    pub public_key: String,
+    #[serde(default)]
+    pub sign_count: u32,
+    pub created: String,
+    #[serde(default)]
+    pub last_used: Option<String>,
 }
 
 /// Informations utilisateur (sans mot de passe)
@@ -42,6 +73,9 @@ pub struct UserInfo {
     pub disabled: bool,
     pub created: Option<String>,
     pub last_login: Option<String>,
+    /// Second facteur TOTP actif
+    #[serde(default)]
+    pub totp_enabled: bool,
 }
 
 /// Informations utilisateur avec hash du mot de passe (pour l'auth)
@@ -111,6 +145,7 @@ impl UserStore {
                 disabled: ud.disabled,
                 created: ud.created.clone(),
                 last_login: ud.last_login.clone(),
+                totp_enabled: ud.totp_enabled(),
             })
             .collect()
     }
@@ -126,6 +161,7 @@ impl UserStore {
             disabled: ud.disabled,
             created: ud.created.clone(),
             last_login: ud.last_login.clone(),
+            totp_enabled: ud.totp_enabled(),
         })
     }
 
@@ -214,6 +250,9 @@ impl UserStore {
                 disabled: false,
                 created: Some(now),
                 last_login: None,
+                passkeys: Vec::new(),
+                totp: None,
+                external_id: None,
             },
         );
 
@@ -357,6 +396,240 @@ impl UserStore {
         }
     }
 
+    /// Passkeys d'un utilisateur
+    pub fn passkeys(&self, username: &str) -> Vec<Passkey> {
+        let data = self.load();
+        data.users.get(username).map(|u| u.passkeys.clone()).unwrap_or_default()
+    }
+
+    /// Retrouve une passkey et son propriétaire par identifiant
+    pub fn find_passkey(&self, id: &str) -> Option<(String, Passkey)> {
+        let data = self.load();
+        data.users.iter().find_map(|(username, u)| {
+            u.passkeys
+                .iter()
+                .find(|p| p.id == id)
+                .map(|p| (username.clone(), p.clone()))
+        })
+    }
+
+    /// Ajoute une passkey à un utilisateur
+    pub fn add_passkey(&self, username: &str, passkey: Passkey) -> UserOpResult {
+        let mut data = self.load();
+        if data.users.values().any(|u| u.passkeys.iter().any(|p| p.id == passkey.id)) {
+            return UserOpResult {
+                success: false,
+                error: Some("Passkey deja enregistree".to_string()),
+                user: None,
+            };
+        }
+        let Some(user) = data.users.get_mut(username) else {
+            return UserOpResult {
+                success: false,
+                error: Some("Utilisateur non trouve".to_string()),
+                user: None,
+            };
+        };
+        user.passkeys.push(passkey);
+
+        if !self.save(&data) {
+            return UserOpResult {
+                success: false,
+                error: Some("Erreur lors de la sauvegarde".to_string()),
+                user: None,
+            };
+        }
+
+        UserOpResult {
+            success: true,
+            error: None,
+            user: None,
+        }
+    }
+
+    /// Supprime une passkey d'un utilisateur
+    pub fn remove_passkey(&self, username: &str, id: &str) -> bool {
+        let mut data = self.load();
+        let Some(user) = data.users.get_mut(username) else {
+            return false;
+        };
+        let before = user.passkeys.len();
+        user.passkeys.retain(|p| p.id != id);
+        user.passkeys.len() != before && self.save(&data)
+    }
+
+    /// Enregistre l'utilisation d'une passkey (compteur de signatures)
+    pub fn record_passkey_use(&self, username: &str, id: &str, sign_count: u32) -> bool {
+        let mut data = self.load();
+        let passkey = data
+            .users
+            .get_mut(username)
+            .and_then(|u| u.passkeys.iter_mut().find(|p| p.id == id));
+        match passkey {
+            Some(passkey) => {
+                passkey.sign_count = sign_count;
+                passkey.last_used = Some(chrono::Utc::now().to_rfc3339());
+                self.save(&data)
+            }
+            None => false,
+        }
+    }
+
+    /// Démarre l'enrôlement TOTP : nouveau secret, actif après confirmation
+    pub fn start_totp(&self, username: &str) -> anyhow::Result<TotpState> {
+        let mut data = self.load();
+        let Some(user) = data.users.get_mut(username) else {
+            anyhow::bail!("Utilisateur non trouve");
+        };
+        if user.totp_enabled() {
+            anyhow::bail!("TOTP deja actif");
+        }
+        let totp = TotpState::generate();
+        user.totp = Some(totp.clone());
+        if !self.save(&data) {
+            anyhow::bail!("Erreur lors de la sauvegarde");
+        }
+        Ok(totp)
+    }
+
+    /// Confirme l'enrôlement avec un premier code ; renvoie les codes de secours
+    pub fn confirm_totp(&self, username: &str, code: &str) -> anyhow::Result<Vec<String>> {
+        let mut data = self.load();
+        let Some(totp) = data.users.get_mut(username).and_then(|u| u.totp.as_mut()) else {
+            anyhow::bail!("Aucun enrolement TOTP en cours");
+        };
+        if totp.enabled {
+            anyhow::bail!("TOTP deja actif");
+        }
+        if !totp.verify(code, unix_now()) {
+            anyhow::bail!("Code invalide");
+        }
+        totp.enabled = true;
+        let codes = totp.regenerate_recovery_codes();
+        if !self.save(&data) {
+            anyhow::bail!("Erreur lors de la sauvegarde");
+        }
+        Ok(codes)
+    }
+
+    /// Désactive le TOTP (et abandonne un enrôlement en cours)
+    pub fn disable_totp(&self, username: &str) -> bool {
+        let mut data = self.load();
+        match data.users.get_mut(username) {
+            Some(user) if user.totp.is_some() => {
+                user.totp = None;
+                self.save(&data)
+            }
+            _ => false,
+        }
+    }
+
+    /// État TOTP : (actif, enrôlement en cours, codes de secours restants)
+    pub fn totp_status(&self, username: &str) -> Option<(bool, bool, usize)> {
+        let data = self.load();
+        let user = data.users.get(username)?;
+        Some(match &user.totp {
+            Some(t) => (t.enabled, !t.enabled, t.recovery_codes.len()),
+            None => (false, false, 0),
+        })
+    }
+
+    /// Vrai si la connexion de l'utilisateur exige un code TOTP
+    pub fn totp_enabled(&self, username: &str) -> bool {
+        self.load().users.get(username).is_some_and(|u| u.totp_enabled())
+    }
+
+    /// Vérifie un code TOTP ou, à défaut, consomme un code de secours
+    pub fn verify_totp(&self, username: &str, code: &str) -> bool {
+        let mut data = self.load();
+        let Some(totp) = data.users.get_mut(username).and_then(|u| u.totp.as_mut()) else {
+            return false;
+        };
+        if !totp.enabled {
+            return false;
+        }
+        let ok = totp.verify(code, unix_now()) || totp.use_recovery_code(code);
+        // Pas et codes de secours consommés doivent être persistés
+        ok && self.save(&data)
+    }
+
+    /// Régénère les codes de secours d'un TOTP actif
+    pub fn regenerate_recovery_codes(&self, username: &str) -> anyhow::Result<Vec<String>> {
+        let mut data = self.load();
+        let Some(totp) = data.users.get_mut(username).and_then(|u| u.totp.as_mut()).filter(|t| t.enabled) else {
+            anyhow::bail!("TOTP non actif");
+        };
+        let codes = totp.regenerate_recovery_codes();
+        if !self.save(&data) {
+            anyhow::bail!("Erreur lors de la sauvegarde");
+        }
+        Ok(codes)
+    }
+
+    /// Connexion par le fournisseur externe : compte lié à l'identité, créé
+    /// au besoin (`provision`). Un compte local existant n'est jamais lié
+    /// automatiquement : en cas de conflit de nom, un suffixe est ajouté.
+    /// Les groupes fournis sont resynchronisés à chaque connexion.
+    pub fn login_external(&self, identity: &ExternalIdentity, provision: bool) -> UserOpResult {
+        let failure = |error: &str| UserOpResult {
+            success: false,
+            error: Some(error.to_string()),
+            user: None,
+        };
+        let mut data = self.load();
+
+        let linked = data
+            .users
+            .iter()
+            .find(|(_, u)| u.external_id.as_deref() == Some(identity.subject.as_str()))
+            .map(|(name, _)| name.clone());
+        let username = match linked {
+            Some(username) => {
+                let user = data.users.get_mut(&username).expect("linked user exists");
+                if let Some(groups) = &identity.groups {
+                    user.groups = groups.clone();
+                }
+                if !identity.email.is_empty() {
+                    user.email = Some(identity.email.clone());
+                }
+                username
+            }
+            None if !provision => return failure("Aucun compte associe a cette identite"),
+            None => {
+                let Some(username) = free_username(&data, &identity.username) else {
+                    return failure("Nom d'utilisateur indisponible");
+                };
+                data.users.insert(
+                    username.clone(),
+                    UserData {
+                        displayname: Some(identity.displayname.clone()),
+                        email: Some(identity.email.clone()),
+                        // Pas de mot de passe : connexion par le fournisseur uniquement
+                        password: None,
+                        groups: identity.groups.clone().unwrap_or_default(),
+                        disabled: false,
+                        created: Some(chrono::Utc::now().to_rfc3339()),
+                        last_login: None,
+                        passkeys: Vec::new(),
+                        totp: None,
+                        external_id: Some(identity.subject.clone()),
+                    },
+                );
+                tracing::info!("Compte SSO cree: {} ({})", username, identity.subject);
+                username
+            }
+        };
+
+        if !self.save(&data) {
+            return failure("Erreur lors de la sauvegarde");
+        }
+        UserOpResult {
+            success: true,
+            error: None,
+            user: self.get(&username),
+        }
+    }
+
     /// Vérifie si un utilisateur est admin
     pub fn is_admin(&self, username: &str) -> bool {
         self.get(username)
@@ -374,6 +647,27 @@ pub struct UserUpdates {
     pub disabled: Option<bool>,
 }
 
+/// Nom valide (3-32 caractères : lettres, chiffres, _ ou -) dérivé de
+/// `wanted`, suffixé si déjà pris
+fn free_username(data: &UsersFile, wanted: &str) -> Option<String> {
+    let mut base: String = wanted
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '_' || c == '-' { c } else { '-' })
+        .take(28)
+        .collect();
+    while base.chars().count() < 3 {
+        base.push('_');
+    }
+    std::iter::once(base.clone())
+        .chain((2..100).map(|n| format!("{}-{}", base, n)))
+        .find(|name| !data.users.contains_key(name))
+}
+
+fn unix_now() -> u64 {
+    chrono::Utc::now().timestamp().max(0) as u64
+}
+
 /// Hash un mot de passe avec Argon2id (mêmes paramètres que le backend Node.js)
 pub fn hash_password(password: &str) -> anyhow::Result<String> {
     let salt = SaltString::generate(&mut rand_core::OsRng);
@@ -411,6 +705,70 @@ mod tests {
         assert!(!verify_password("wrong_password", &hash));
     }
 
+    #[test]
+    fn test_totp_enrollment() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = UserStore::new(dir.path());
+        assert!(store.create("alice", "password123", None, None, vec![]).success);
+
+        let pending = store.start_totp("alice").unwrap();
+        assert!(!store.totp_enabled("alice"));
+        assert_eq!(store.totp_status("alice"), Some((false, true, 0)));
+        assert!(store.confirm_totp("alice", "abcdef").is_err());
+
+        let code = pending.code_at(unix_now());
+        let codes = store.confirm_totp("alice", &code).unwrap();
+        assert!(store.totp_enabled("alice"));
+        assert!(store.get("alice").unwrap().totp_enabled);
+        assert!(store.start_totp("alice").is_err());
+
+        // Le code de confirmation ne peut pas être rejoué ; les codes de secours
+        // sont à usage unique
+        assert!(!store.verify_totp("alice", &code));
+        assert!(store.verify_totp("alice", &codes[0]));
+        assert!(!store.verify_totp("alice", &codes[0]));
+        assert_eq!(store.totp_status("alice"), Some((true, false, codes.len() - 1)));
+
+        assert!(store.disable_totp("alice"));
+        assert!(!store.totp_enabled("alice"));
+        assert!(!store.verify_totp("alice", &codes[1]));
+    }
+
+    #[test]
+    fn test_login_external_provisioning() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = UserStore::new(dir.path());
+        assert!(store.create("alice", "password123", None, None, vec![]).success);
+
+        let mut identity = ExternalIdentity {
+            subject: "https://accounts.example.com|1234".to_string(),
+            username: "Alice".to_string(),
+            displayname: "Alice Martin".to_string(),
+            email: "alice@example.com".to_string(),
+            groups: Some(vec!["users".to_string()]),
+        };
+        assert!(!store.login_external(&identity, false).success);
+
+        // Le compte local "alice" n'est pas repris
+        let created = store.login_external(&identity, true).user.unwrap();
+        assert_eq!(created.username, "alice-2");
+        assert_eq!(created.groups, vec!["users"]);
+        assert!(store.get_with_password("alice-2").is_none());
+
+        // Connexion suivante : même compte, groupes resynchronisés
+        identity.groups = Some(vec!["admins".to_string()]);
+        let again = store.login_external(&identity, true).user.unwrap();
+        assert_eq!(again.username, "alice-2");
+        assert!(store.is_admin("alice-2"));
+        assert_eq!(store.get_all().len(), 2);
+
+        let mut data = store.load();
+        assert_eq!(free_username(&data, "é.x").as_deref(), Some("é-x"));
+        data.users.clear();
+        assert_eq!(free_username(&data, "a@b").as_deref(), Some("a-b"));
+        assert_eq!(free_username(&data, "").as_deref(), Some("___"));
+    }
+
     #[test]
     fn test_verify_node_compatible() {
         // Un hash généré par argon2 de Node.js devrait être vérifiable
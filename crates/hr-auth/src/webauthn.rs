@@ -0,0 +1,540 @@
+//! Passkeys (WebAuthn niveau 2) : enregistrement et connexion.
+//!
+//! Seule l'attestation "none" est demandée : la clé publique est prise dans
+//! les données d'authentificateur, sans vérifier le modèle de la clé. Les
+//! signatures sont vérifiées avec ring (ES256, EdDSA, RS256). Les défis en
+//! attente restent en mémoire quelques minutes.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand_core::RngCore;
+use ring::digest::{digest, SHA256};
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::cbor;
+use crate::users::{Passkey, UserInfo};
+
+/// Durée de validité d'un défi
+const CHALLENGE_TTL: Duration = Duration::from_secs(300);
+/// Défis en attente au maximum (la connexion est accessible sans session)
+const MAX_PENDING: usize = 1024;
+
+/// Algorithmes COSE acceptés, par ordre de préférence
+const ALG_ES256: i128 = -7;
+const ALG_EDDSA: i128 = -8;
+const ALG_RS256: i128 = -257;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_DATA: u8 = 0x40;
+
+/// Réponse du navigateur à `navigator.credentials.create()` (`toJSON()`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationCredential {
+    pub id: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+/// Réponse du navigateur à `navigator.credentials.get()` (`toJSON()`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticationCredential {
+    pub id: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(default)]
+    pub user_handle: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Ceremony {
+    Registration { username: String },
+    /// `None` : clé découvrable, l'utilisateur est donné par la clé
+    Authentication { username: Option<String> },
+}
+
+struct Pending {
+    ceremony: Ceremony,
+    expires: Instant,
+}
+
+/// Cérémonies WebAuthn pour un domaine (RP ID = domaine de base, valable
+/// pour le dashboard et toutes les applications sous ce domaine).
+pub struct WebAuthn {
+    rp_id: String,
+    pending: Mutex<HashMap<String, Pending>>,
+}
+
+impl WebAuthn {
+    pub fn new(rp_id: &str) -> Self {
+        Self {
+            rp_id: rp_id.to_string(),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Options `publicKey` de `navigator.credentials.create()`
+    pub fn start_registration(&self, user: &UserInfo, existing: &[Passkey]) -> Result<Value> {
+        let challenge = self.new_challenge(Ceremony::Registration {
+            username: user.username.clone(),
+        })?;
+        let params: Vec<Value> = [ALG_ES256, ALG_EDDSA, ALG_RS256]
+            .iter()
+            .map(|alg| json!({"type": "public-key", "alg": alg}))
+            .collect();
+        let exclude: Vec<Value> = existing
+            .iter()
+            .map(|p| json!({"type": "public-key", "id": p.id}))
+            .collect();
+        Ok(json!({
+            "challenge": challenge,
+            "rp": {"id": self.rp_id, "name": "HomeRoute"},
+            "user": {
+                "id": URL_SAFE_NO_PAD.encode(user.username.as_bytes()),
+                "name": user.username,
+                "displayName": user.displayname
+            },
+            "pubKeyCredParams": params,
+            "excludeCredentials": exclude,
+            "authenticatorSelection": {
+                "residentKey": "preferred",
+                "userVerification": "required"
+            },
+            "attestation": "none",
+            "timeout": CHALLENGE_TTL.as_millis() as u64
+        }))
+    }
+
+    /// Vérifie la réponse d'enregistrement et renvoie la nouvelle passkey.
+    pub fn finish_registration(
+        &self,
+        username: &str,
+        name: &str,
+        credential: &RegistrationCredential,
+    ) -> Result<Passkey> {
+        let client_data_raw = b64(&credential.response.client_data_json)?;
+        self.check_client_data(
+            &client_data_raw,
+            "webauthn.create",
+            |c| c == &Ceremony::Registration { username: username.to_string() },
+        )?;
+
+        let attestation = cbor::decode(&b64(&credential.response.attestation_object)?)?;
+        let auth_data = attestation
+            .get("authData")
+            .and_then(cbor::Value::as_bytes)
+            .ok_or_else(|| anyhow!("authData manquant"))?;
+        let parsed = self.parse_auth_data(auth_data)?;
+        let (credential_id, public_key) = parsed
+            .attested
+            .ok_or_else(|| anyhow!("Aucune clé dans la réponse"))?;
+        if URL_SAFE_NO_PAD.encode(&credential_id) != credential.id {
+            bail!("Identifiant de clé incohérent");
+        }
+        cose_algorithm(&cbor::decode(&public_key)?)?;
+
+        let name = name.trim();
+        Ok(Passkey {
+            id: credential.id.clone(),
+            name: if name.is_empty() { "Passkey".to_string() } else { name.to_string() },
+            public_key: URL_SAFE_NO_PAD.encode(&public_key),
+            sign_count: parsed.sign_count,
+            created: chrono::Utc::now().to_rfc3339(),
+            last_used: None,
+        })
+    }
+
+    /// Options `publicKey` de `navigator.credentials.get()`. Sans nom
+    /// d'utilisateur, toute clé découvrable du domaine est acceptée.
+    pub fn start_authentication(&self, username: Option<&str>, allowed: &[Passkey]) -> Result<Value> {
+        let challenge = self.new_challenge(Ceremony::Authentication {
+            username: username.map(str::to_string),
+        })?;
+        let allow: Vec<Value> = allowed
+            .iter()
+            .map(|p| json!({"type": "public-key", "id": p.id}))
+            .collect();
+        Ok(json!({
+            "challenge": challenge,
+            "rpId": self.rp_id,
+            "allowCredentials": allow,
+            "userVerification": "required",
+            "timeout": CHALLENGE_TTL.as_millis() as u64
+        }))
+    }
+
+    /// Vérifie une assertion signée par `passkey` (appartenant à `owner`).
+    /// Renvoie le nouveau compteur de signatures.
+    pub fn finish_authentication(
+        &self,
+        credential: &AuthenticationCredential,
+        owner: &str,
+        passkey: &Passkey,
+    ) -> Result<u32> {
+        let response = &credential.response;
+        if let Some(handle) = response.user_handle.as_deref().filter(|h| !h.is_empty())
+            && b64(handle)? != owner.as_bytes()
+        {
+            bail!("Clé d'un autre utilisateur");
+        }
+
+        let client_data_raw = b64(&response.client_data_json)?;
+        self.check_client_data(&client_data_raw, "webauthn.get", |c| match c {
+            Ceremony::Authentication { username: None } => true,
+            Ceremony::Authentication { username: Some(u) } => u == owner,
+            _ => false,
+        })?;
+
+        let auth_data = b64(&response.authenticator_data)?;
+        let parsed = self.parse_auth_data(&auth_data)?;
+
+        let mut signed = auth_data.clone();
+        signed.extend_from_slice(digest(&SHA256, &client_data_raw).as_ref());
+        let key = cbor::decode(&b64(&passkey.public_key)?)?;
+        verify_signature(&key, &signed, &b64(&response.signature)?)?;
+
+        // Compteur qui n'avance pas : clé probablement clonée
+        if parsed.sign_count != 0 && parsed.sign_count <= passkey.sign_count {
+            bail!("Compteur de signatures incohérent");
+        }
+        Ok(parsed.sign_count)
+    }
+
+    fn new_challenge(&self, ceremony: Ceremony) -> Result<String> {
+        let mut bytes = [0u8; 32];
+        rand_core::OsRng.fill_bytes(&mut bytes);
+        let challenge = URL_SAFE_NO_PAD.encode(bytes);
+
+        let now = Instant::now();
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, p| p.expires > now);
+        if pending.len() >= MAX_PENDING {
+            bail!("Trop de demandes en cours");
+        }
+        pending.insert(
+            challenge.clone(),
+            Pending {
+                ceremony,
+                expires: now + CHALLENGE_TTL,
+            },
+        );
+        Ok(challenge)
+    }
+
+    /// Vérifie clientDataJSON (type, origine) et consomme son défi.
+    fn check_client_data(
+        &self,
+        raw: &[u8],
+        expected_type: &str,
+        ceremony_ok: impl Fn(&Ceremony) -> bool,
+    ) -> Result<()> {
+        let client_data: Value = serde_json::from_slice(raw)?;
+        if client_data.get("type").and_then(Value::as_str) != Some(expected_type) {
+            bail!("Type de cérémonie invalide");
+        }
+        let origin = client_data.get("origin").and_then(Value::as_str).unwrap_or("");
+        if !self.origin_allowed(origin) {
+            bail!("Origine non autorisée: {}", origin);
+        }
+        let challenge = client_data
+            .get("challenge")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("Défi manquant"))?;
+
+        let pending = self.pending.lock().unwrap().remove(challenge);
+        match pending {
+            Some(p) if p.expires > Instant::now() && ceremony_ok(&p.ceremony) => Ok(()),
+            _ => bail!("Défi inconnu ou expiré"),
+        }
+    }
+
+    /// `https://` sur le domaine de base ou un de ses sous-domaines
+    /// (`http://localhost` accepté en développement).
+    fn origin_allowed(&self, origin: &str) -> bool {
+        let (secure, rest) = match origin.split_once("://") {
+            Some(("https", rest)) => (true, rest),
+            Some(("http", rest)) => (false, rest),
+            _ => return false,
+        };
+        let host = rest.rsplit_once(':').map_or(rest, |(host, _)| host);
+        let in_domain = host == self.rp_id || host.ends_with(&format!(".{}", self.rp_id));
+        in_domain && (secure || self.rp_id == "localhost")
+    }
+
+    fn parse_auth_data(&self, data: &[u8]) -> Result<AuthData> {
+        if data.len() < 37 {
+            bail!("authData trop court");
+        }
+        if data[..32] != *digest(&SHA256, self.rp_id.as_bytes()).as_ref() {
+            bail!("RP ID invalide");
+        }
+        let flags = data[32];
+        if flags & FLAG_USER_PRESENT == 0 {
+            bail!("Présence de l'utilisateur non vérifiée");
+        }
+        // Une passkey vaut second facteur : PIN ou biométrie obligatoire
+        if flags & FLAG_USER_VERIFIED == 0 {
+            bail!("Utilisateur non vérifié par l'authentificateur");
+        }
+        let sign_count = u32::from_be_bytes(data[33..37].try_into()?);
+
+        let mut attested = None;
+        if flags & FLAG_ATTESTED_DATA != 0 {
+            // AAGUID (16) + longueur de l'identifiant (2)
+            let rest = data.get(37..).filter(|r| r.len() >= 18).ok_or_else(|| anyhow!("authData tronqué"))?;
+            let id_len = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+            let id = rest.get(18..18 + id_len).ok_or_else(|| anyhow!("authData tronqué"))?;
+            let (_, key_len) = cbor::decode_prefix(&rest[18 + id_len..])?;
+            let key = &rest[18 + id_len..18 + id_len + key_len];
+            attested = Some((id.to_vec(), key.to_vec()));
+        }
+        Ok(AuthData { sign_count, attested })
+    }
+}
+
+struct AuthData {
+    sign_count: u32,
+    /// Identifiant et clé publique COSE (enregistrement uniquement)
+    attested: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+fn b64(value: &str) -> Result<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|e| anyhow!("base64url invalide: {}", e))
+}
+
+/// Algorithme d'une clé COSE, si supporté.
+fn cose_algorithm(key: &cbor::Value) -> Result<i128> {
+    let alg = key.get_int(3).and_then(cbor::Value::as_int).ok_or_else(|| anyhow!("Clé COSE sans algorithme"))?;
+    match alg {
+        ALG_ES256 | ALG_EDDSA | ALG_RS256 => Ok(alg),
+        _ => bail!("Algorithme non supporté: {}", alg),
+    }
+}
+
+fn cose_bytes(key: &cbor::Value, label: i128) -> Result<&[u8]> {
+    key.get_int(label)
+        .and_then(cbor::Value::as_bytes)
+        .ok_or_else(|| anyhow!("Clé COSE incomplète"))
+}
+
+fn verify_signature(key: &cbor::Value, message: &[u8], sig: &[u8]) -> Result<()> {
+    let result = match cose_algorithm(key)? {
+        ALG_ES256 => {
+            let mut point = vec![0x04];
+            point.extend_from_slice(cose_bytes(key, -2)?);
+            point.extend_from_slice(cose_bytes(key, -3)?);
+            UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, point).verify(message, sig)
+        }
+        ALG_EDDSA => UnparsedPublicKey::new(&signature::ED25519, cose_bytes(key, -2)?).verify(message, sig),
+        _ => RsaPublicKeyComponents {
+            n: cose_bytes(key, -1)?,
+            e: cose_bytes(key, -2)?,
+        }
+        .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, sig),
+    };
+    result.map_err(|_| anyhow!("Signature invalide"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+    const ORIGIN: &str = "https://auth.example.com";
+
+    fn user() -> UserInfo {
+        UserInfo {
+            username: "alice".to_string(),
+            displayname: "Alice".to_string(),
+            email: String::new(),
+            groups: vec![],
+            disabled: false,
+            created: None,
+            last_login: None,
+            totp_enabled: false,
+        }
+    }
+
+    fn cbor_head(major: u8, len: usize) -> Vec<u8> {
+        match len {
+            0..=23 => vec![major << 5 | len as u8],
+            24..=255 => vec![major << 5 | 24, len as u8],
+            _ => vec![major << 5 | 25, (len >> 8) as u8, len as u8],
+        }
+    }
+
+    fn cbor_bytes(data: &[u8]) -> Vec<u8> {
+        [cbor_head(2, data.len()), data.to_vec()].concat()
+    }
+
+    fn cbor_text(text: &str) -> Vec<u8> {
+        [cbor_head(3, text.len()), text.as_bytes().to_vec()].concat()
+    }
+
+    /// Clé COSE ES256 d'une clé publique non compressée
+    fn cose_es256(public: &[u8]) -> Vec<u8> {
+        let mut key = vec![0xa5, 0x01, 0x02, 0x03, 0x26, 0x20, 0x01, 0x21];
+        key.extend(cbor_bytes(&public[1..33]));
+        key.push(0x22);
+        key.extend(cbor_bytes(&public[33..65]));
+        key
+    }
+
+    fn client_data(kind: &str, challenge: &str) -> String {
+        let json = json!({"type": kind, "challenge": challenge, "origin": ORIGIN});
+        URL_SAFE_NO_PAD.encode(json.to_string())
+    }
+
+    fn auth_data(flags: u8, count: u32, attested: Option<(&[u8], &[u8])>) -> Vec<u8> {
+        let mut data = digest(&SHA256, b"example.com").as_ref().to_vec();
+        data.push(flags);
+        data.extend(count.to_be_bytes());
+        if let Some((id, key)) = attested {
+            data.extend([0u8; 16]);
+            data.extend((id.len() as u16).to_be_bytes());
+            data.extend(id);
+            data.extend(key);
+        }
+        data
+    }
+
+    fn register(webauthn: &WebAuthn, key: &EcdsaKeyPair) -> Passkey {
+        let options = webauthn.start_registration(&user(), &[]).unwrap();
+        let challenge = options["challenge"].as_str().unwrap();
+        let cred_id = b"credential-1";
+        let data = auth_data(0x45, 0, Some((cred_id, &cose_es256(key.public_key().as_ref()))));
+        let mut attestation = vec![0xa3];
+        attestation.extend(cbor_text("fmt"));
+        attestation.extend(cbor_text("none"));
+        attestation.extend(cbor_text("attStmt"));
+        attestation.push(0xa0);
+        attestation.extend(cbor_text("authData"));
+        attestation.extend(cbor_bytes(&data));
+
+        let credential = RegistrationCredential {
+            id: URL_SAFE_NO_PAD.encode(cred_id),
+            response: AttestationResponse {
+                client_data_json: client_data("webauthn.create", challenge),
+                attestation_object: URL_SAFE_NO_PAD.encode(attestation),
+            },
+        };
+        webauthn.finish_registration("alice", "YubiKey", &credential).unwrap()
+    }
+
+    fn assertion(webauthn: &WebAuthn, key: &EcdsaKeyPair, passkey: &Passkey, count: u32) -> AuthenticationCredential {
+        assertion_with_flags(webauthn, key, passkey, count, 0x05)
+    }
+
+    fn assertion_with_flags(
+        webauthn: &WebAuthn,
+        key: &EcdsaKeyPair,
+        passkey: &Passkey,
+        count: u32,
+        flags: u8,
+    ) -> AuthenticationCredential {
+        let options = webauthn.start_authentication(None, &[]).unwrap();
+        let client_data_json = client_data("webauthn.get", options["challenge"].as_str().unwrap());
+        let data = auth_data(flags, count, None);
+        let mut signed = data.clone();
+        signed.extend_from_slice(digest(&SHA256, &b64(&client_data_json).unwrap()).as_ref());
+        let sig = key.sign(&SystemRandom::new(), &signed).unwrap();
+        AuthenticationCredential {
+            id: passkey.id.clone(),
+            response: AssertionResponse {
+                client_data_json,
+                authenticator_data: URL_SAFE_NO_PAD.encode(data),
+                signature: URL_SAFE_NO_PAD.encode(sig.as_ref()),
+                user_handle: Some(URL_SAFE_NO_PAD.encode("alice")),
+            },
+        }
+    }
+
+    fn key_pair() -> EcdsaKeyPair {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng).unwrap()
+    }
+
+    #[test]
+    fn test_register_and_authenticate() {
+        let webauthn = WebAuthn::new("example.com");
+        let key = key_pair();
+        let passkey = register(&webauthn, &key);
+        assert_eq!(passkey.name, "YubiKey");
+
+        let credential = assertion(&webauthn, &key, &passkey, 1);
+        assert_eq!(webauthn.finish_authentication(&credential, "alice", &passkey).unwrap(), 1);
+
+        // Défi à usage unique
+        assert!(webauthn.finish_authentication(&credential, "alice", &passkey).is_err());
+        // La clé appartient à alice
+        let credential = assertion(&webauthn, &key, &passkey, 2);
+        assert!(webauthn.finish_authentication(&credential, "bob", &passkey).is_err());
+    }
+
+    #[test]
+    fn test_rejects_bad_signature_and_counter() {
+        let webauthn = WebAuthn::new("example.com");
+        let key = key_pair();
+        let mut passkey = register(&webauthn, &key);
+
+        let other = register(&webauthn, &key_pair());
+        let credential = assertion(&webauthn, &key_pair(), &passkey, 1);
+        assert!(webauthn.finish_authentication(&credential, "alice", &other).is_err());
+
+        passkey.sign_count = 5;
+        let credential = assertion(&webauthn, &key, &passkey, 5);
+        assert!(webauthn.finish_authentication(&credential, "alice", &passkey).is_err());
+    }
+
+    #[test]
+    fn test_rejects_presence_without_verification() {
+        let webauthn = WebAuthn::new("example.com");
+        let key = key_pair();
+        let passkey = register(&webauthn, &key);
+
+        // UP sans UV : clé touchée, mais ni PIN ni biométrie
+        let credential = assertion_with_flags(&webauthn, &key, &passkey, 1, FLAG_USER_PRESENT);
+        assert!(webauthn.finish_authentication(&credential, "alice", &passkey).is_err());
+
+        let credential = assertion(&webauthn, &key, &passkey, 2);
+        assert_eq!(webauthn.finish_authentication(&credential, "alice", &passkey).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_origin_allowed() {
+        let webauthn = WebAuthn::new("example.com");
+        assert!(webauthn.origin_allowed("https://example.com"));
+        assert!(webauthn.origin_allowed("https://auth.example.com:8443"));
+        assert!(!webauthn.origin_allowed("http://auth.example.com"));
+        assert!(!webauthn.origin_allowed("https://evilexample.com"));
+        assert!(WebAuthn::new("localhost").origin_allowed("http://localhost:5173"));
+    }
+}
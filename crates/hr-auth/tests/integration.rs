@@ -67,7 +67,7 @@ fn test_session_lifecycle() {
 
     // Créer une session
     let (session_id, expires_at) = store
-        .create("admin", Some("127.0.0.1"), Some("test-agent"), false)
+        .create("admin", Some("127.0.0.1"), Some("test-agent"), false, false)
         .unwrap();
 
     assert!(!session_id.is_empty());
@@ -79,6 +79,7 @@ fn test_session_lifecycle() {
     let info = info.unwrap();
     assert_eq!(info.user_id, "admin");
     assert!(!info.remember_me);
+    assert!(!info.mfa);
 
     // Lister les sessions de l'utilisateur
     let sessions = store.get_by_user("admin").unwrap();
@@ -100,11 +101,12 @@ fn test_session_remember_me() {
     let store = SessionStore::new(dir.path()).unwrap();
 
     let (session_id, _) = store
-        .create("admin", None, None, true)
+        .create("admin", None, None, true, true)
         .unwrap();
 
     let info = store.validate(&session_id).unwrap().unwrap();
     assert!(info.remember_me);
+    assert!(info.mfa);
 }
 
 /// Vérifie la compatibilité avec la DB SQLite existante
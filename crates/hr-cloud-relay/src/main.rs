@@ -1,16 +1,21 @@
+mod proxy_protocol;
 mod relay;
+mod sni;
+mod tenants;
+mod udp;
 
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
+use proxy_protocol::{ProxyProtocolConfig, TrustedProxies};
 use quinn::Endpoint;
-use relay::ActiveConnection;
 use serde::Deserialize;
+use tenants::{Relay, TenantConfig};
 use tokio::net::TcpListener;
-use tokio::sync::RwLock;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+use udp::{UdpFlows, UdpPortConfig};
 
 // ── Configuration ─────────────────────────────────────────────────────
 
@@ -22,7 +27,22 @@ struct Config {
     tcp_listen_port: u16,
     #[serde(default = "default_http_redirect_port")]
     http_redirect_port: u16,
+    /// Address of the status API; empty to disable it.
+    #[serde(default = "default_status_listen")]
+    status_listen: String,
     tls: TlsConfig,
+    /// Home sites served by this relay. Empty: a single home, whatever
+    /// its client cert CN.
+    #[serde(default)]
+    tenants: Vec<TenantConfig>,
+    /// Tenant for connections without SNI or with an unknown one.
+    #[serde(default)]
+    default_tenant: Option<String>,
+    #[serde(default)]
+    proxy_protocol: ProxyProtocolConfig,
+    /// UDP ports relayed to the home sites.
+    #[serde(default)]
+    udp: Vec<UdpPortConfig>,
 }
 
 #[derive(Deserialize)]
@@ -41,6 +61,9 @@ fn default_tcp_listen_port() -> u16 {
 fn default_http_redirect_port() -> u16 {
     80
 }
+fn default_status_listen() -> String {
+    "127.0.0.1:8404".to_string()
+}
 
 // ── Main ──────────────────────────────────────────────────────────────
 
@@ -87,8 +110,19 @@ async fn main() -> Result<()> {
     let endpoint = Endpoint::server(server_config, quic_addr)?;
     info!("QUIC endpoint listening on {}", quic_addr);
 
-    // Shared active connection state
-    let active_conn: ActiveConnection = Arc::new(RwLock::new(None));
+    // Tunnels of the home sites, keyed by client cert CN
+    let tunnels = Arc::new(Relay::new(&config.tenants, config.default_tenant.clone())?);
+    if config.tenants.is_empty() {
+        info!("No tenants configured, serving a single home site");
+    } else {
+        info!("Serving {} tenants", config.tenants.len());
+    }
+
+    // Load balancers announcing clients with the PROXY protocol
+    let trusted_proxies = Arc::new(TrustedProxies::new(&config.proxy_protocol)?);
+    if !trusted_proxies.is_empty() {
+        info!("Accepting PROXY protocol v2 from {:?}", config.proxy_protocol.trusted);
+    }
 
     // Bind TCP relay listener
     let tcp_addr: SocketAddr = format!("[::]:{}", config.tcp_listen_port).parse()?;
@@ -97,13 +131,50 @@ async fn main() -> Result<()> {
         .with_context(|| format!("Failed to bind TCP relay on {}", tcp_addr))?;
 
     // Spawn TCP relay
-    let relay_conn = active_conn.clone();
+    let tcp_relay = tunnels.clone();
     tokio::spawn(async move {
-        if let Err(e) = relay::run_tcp_relay(tcp_listener, relay_conn).await {
+        if let Err(e) = relay::run_tcp_relay(tcp_listener, tcp_relay, trusted_proxies).await {
             error!("TCP relay error: {}", e);
         }
     });
 
+    // Spawn status API
+    if !config.status_listen.is_empty() {
+        let status_addr: SocketAddr = config
+            .status_listen
+            .parse()
+            .with_context(|| format!("Invalid status_listen: {}", config.status_listen))?;
+        let status_relay = tunnels.clone();
+        tokio::spawn(async move {
+            if let Err(e) = relay::run_status_server(status_addr, status_relay).await {
+                error!("Status API error: {}", e);
+            }
+        });
+    }
+
+    // Spawn UDP relay
+    let udp_flows = Arc::new(UdpFlows::default());
+    if !config.udp.is_empty() {
+        for port in &config.udp {
+            if let Some(name) = &port.tenant {
+                anyhow::ensure!(
+                    config.tenants.is_empty() || config.tenants.iter().any(|t| &t.name == name),
+                    "UDP port {}: unknown tenant {}",
+                    port.port,
+                    name
+                );
+            }
+        }
+        let udp_relay = tunnels.clone();
+        let flows = udp_flows.clone();
+        let ports = config.udp.clone();
+        tokio::spawn(async move {
+            if let Err(e) = udp::run_udp_ports(ports, udp_relay, flows).await {
+                error!("UDP relay error: {}", e);
+            }
+        });
+    }
+
     // Spawn HTTP redirect server
     let http_port = config.http_redirect_port;
     tokio::spawn(async move {
@@ -126,35 +197,40 @@ async fn main() -> Result<()> {
                     break;
                 };
 
-                let active = active_conn.clone();
+                let tunnels = tunnels.clone();
+                let udp_flows = udp_flows.clone();
                 tokio::spawn(async move {
                     match incoming.await {
                         Ok(connection) => {
                             let remote = connection.remote_address();
-                            info!("Tunnel connection established from {}", remote);
-
-                            // Replace the active connection
-                            *active.write().await = Some(connection.clone());
+                            let Some(name) = hr_tunnel::quic::peer_common_name(&connection) else {
+                                warn!("Tunnel connection from {} has no client cert CN", remote);
+                                connection.close(1u32.into(), b"no client certificate CN");
+                                return;
+                            };
+                            let Some(tenant) = tunnels.attach(&name, connection.clone()).await else {
+                                warn!("Tunnel connection from {} rejected: unknown tenant {}", remote, name);
+                                connection.close(1u32.into(), b"unknown tenant");
+                                return;
+                            };
+                            info!("Tunnel connection for {} established from {}", name, remote);
 
                             // Spawn control stream handler
                             let ctrl_conn = connection.clone();
+                            let admin = tenant.admin;
                             tokio::spawn(async move {
-                                relay::handle_control_stream(&ctrl_conn).await;
+                                relay::handle_control_stream(&ctrl_conn, admin).await;
                             });
 
+                            // Spawn UDP replies handler
+                            tokio::spawn(udp::run_tunnel_datagrams(connection.clone(), tenant.clone(), udp_flows));
+
                             // Monitor connection lifetime
-                            let conn_id = connection.stable_id();
-                            let active_for_cleanup = active.clone();
                             let err = connection.closed().await;
-                            info!("Tunnel connection from {} closed: {}", remote, err);
-
-                            // Clear active connection if it's still this one
-                            let mut guard = active_for_cleanup.write().await;
-                            if let Some(ref current) = *guard {
-                                if current.stable_id() == conn_id {
-                                    *guard = None;
-                                }
-                            }
+                            info!("Tunnel connection for {} from {} closed: {}", name, remote, err);
+
+                            // Clear the tenant's connection if it's still this one
+                            tunnels.detach(&tenant, connection.stable_id()).await;
                         }
                         Err(e) => {
                             error!("Failed to accept QUIC connection: {}", e);
@@ -0,0 +1,119 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Deserialize;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+/// PROXY protocol v2 signature.
+const SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+/// Time allowed for the proxy to send its header.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `[proxy_protocol]` section: proxies in front of the relay that announce
+/// the real client with a PROXY protocol v2 header.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProxyProtocolConfig {
+    /// Addresses or CIDR ranges (`10.0.0.0/8`) of those proxies.
+    #[serde(default)]
+    pub trusted: Vec<String>,
+}
+
+/// Peers whose connections must start with a PROXY protocol header.
+pub struct TrustedProxies(Vec<(IpAddr, u8)>);
+
+impl TrustedProxies {
+    pub fn new(config: &ProxyProtocolConfig) -> Result<Self> {
+        let mut ranges = Vec::new();
+        for entry in &config.trusted {
+            let (ip, prefix) = match entry.split_once('/') {
+                Some((ip, prefix)) => (ip, Some(prefix)),
+                None => (entry.as_str(), None),
+            };
+            let ip: IpAddr = ip
+                .trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid trusted proxy: {}", entry))?;
+            let max = if ip.is_ipv4() { 32 } else { 128 };
+            let prefix = match prefix {
+                Some(p) => p
+                    .trim()
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|p| *p <= max)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid trusted proxy: {}", entry))?,
+                None => max,
+            };
+            ranges.push((ip, prefix));
+        }
+        Ok(Self(ranges))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|&(net, prefix)| match (net, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        })
+    }
+}
+
+/// Read the PROXY protocol v2 header at the start of `stream`. Returns the
+/// client address it announces, or `None` for a `LOCAL` connection (the
+/// proxy's own health checks) or an address family other than TCP/UDP
+/// over IPv4/IPv6.
+pub async fn read_header(stream: &mut TcpStream) -> Result<Option<SocketAddr>> {
+    tokio::time::timeout(READ_TIMEOUT, async {
+        let mut fixed = [0u8; 16];
+        stream.read_exact(&mut fixed).await?;
+        anyhow::ensure!(fixed[..12] == SIGNATURE, "Missing PROXY protocol v2 header");
+        let len = u16::from_be_bytes([fixed[14], fixed[15]]) as usize;
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).await?;
+        parse(fixed[12], fixed[13], &body)
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("Timed out reading PROXY protocol header"))?
+}
+
+fn parse(version_command: u8, family: u8, body: &[u8]) -> Result<Option<SocketAddr>> {
+    anyhow::ensure!(
+        version_command >> 4 == 2,
+        "Unsupported PROXY protocol version {}",
+        version_command >> 4
+    );
+    match version_command & 0x0f {
+        0 => return Ok(None), // LOCAL
+        1 => {}               // PROXY
+        other => anyhow::bail!("Invalid PROXY protocol command {}", other),
+    }
+    // High nibble: address family, low nibble: transport (STREAM or DGRAM)
+    let source = match family >> 4 {
+        1 => {
+            anyhow::ensure!(body.len() >= 12, "Truncated PROXY protocol IPv4 addresses");
+            let ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            SocketAddr::new(ip.into(), u16::from_be_bytes([body[8], body[9]]))
+        }
+        2 => {
+            anyhow::ensure!(body.len() >= 36, "Truncated PROXY protocol IPv6 addresses");
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&body[..16]);
+            let ip = Ipv6Addr::from(octets);
+            SocketAddr::new(ip.into(), u16::from_be_bytes([body[32], body[33]]))
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(source))
+}
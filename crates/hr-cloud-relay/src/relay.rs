@@ -1,21 +1,29 @@
 use std::net::SocketAddr;
 use std::os::unix::fs::PermissionsExt;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 use anyhow::Result;
 use hr_tunnel::protocol::{ControlMessage, StreamHeader};
+use hr_tunnel::quic::{read_control_message, send_control_message};
 use quinn::Connection;
 use sha2::{Digest, Sha256};
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpListener;
-use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 
-/// Shared state: the active QUIC connection from on-prem (if any).
-pub type ActiveConnection = Arc<RwLock<Option<Connection>>>;
-
-/// Accept incoming TCP connections on the relay port and forward them through the QUIC tunnel.
-pub async fn run_tcp_relay(listener: TcpListener, active_conn: ActiveConnection) -> Result<()> {
+use crate::proxy_protocol::{self, TrustedProxies};
+use crate::sni;
+use crate::tenants::Relay;
+
+/// Accept incoming TCP connections on the relay port and forward each one
+/// through the tunnel of the tenant its SNI belongs to. Connections from
+/// `trusted` proxies start with a PROXY protocol header naming the client.
+pub async fn run_tcp_relay(
+    listener: TcpListener,
+    relay: Arc<Relay>,
+    trusted: Arc<TrustedProxies>,
+) -> Result<()> {
     info!("TCP relay listening on {}", listener.local_addr()?);
 
     loop {
@@ -27,9 +35,10 @@ pub async fn run_tcp_relay(listener: TcpListener, active_conn: ActiveConnection)
             }
         };
 
-        let conn = active_conn.clone();
+        let relay = relay.clone();
+        let trusted = trusted.clone();
         tokio::spawn(async move {
-            if let Err(e) = handle_tcp_connection(tcp_stream, peer_addr, conn).await {
+            if let Err(e) = handle_tcp_connection(tcp_stream, peer_addr, relay, &trusted).await {
                 debug!("Relay connection from {} error: {}", peer_addr, e);
             }
         });
@@ -39,52 +48,137 @@ pub async fn run_tcp_relay(listener: TcpListener, active_conn: ActiveConnection)
 async fn handle_tcp_connection(
     mut tcp_stream: tokio::net::TcpStream,
     peer_addr: SocketAddr,
-    active_conn: ActiveConnection,
+    relay: Arc<Relay>,
+    trusted: &TrustedProxies,
 ) -> Result<()> {
-    // Get the active QUIC connection (fail if not connected)
-    let conn = active_conn
-        .read()
+    // Behind a load balancer the peer is the balancer, not the client
+    let (client_addr, proxy_ip) = if trusted.contains(peer_addr.ip()) {
+        match proxy_protocol::read_header(&mut tcp_stream).await? {
+            Some(client_addr) => (client_addr, Some(peer_addr.ip())),
+            None => (peer_addr, None),
+        }
+    } else {
+        (peer_addr, None)
+    };
+
+    let sni = sni::peek_sni(&tcp_stream).await;
+    let tenant = relay
+        .route(sni.as_deref())
         .await
-        .as_ref()
-        .ok_or_else(|| anyhow::anyhow!("No active tunnel connection"))?
-        .clone();
+        .ok_or_else(|| anyhow::anyhow!("No tenant for SNI {:?} (client {})", sni, client_addr))?;
+
+    // Get the tenant's QUIC connection (fail if not connected)
+    let Some(conn) = tenant.connection().await else {
+        tenant.rejected.fetch_add(1, Ordering::Relaxed);
+        anyhow::bail!("No active tunnel connection for {}", tenant.name);
+    };
 
     // Open a bidirectional QUIC stream
     let (mut quic_send, mut quic_recv) = conn.open_bi().await?;
+    tenant.total_streams.fetch_add(1, Ordering::Relaxed);
+    tenant.active_streams.fetch_add(1, Ordering::Relaxed);
 
-    // Send StreamHeader with peer IP and current timestamp
+    // Send StreamHeader with client IP and current timestamp
     let header = StreamHeader {
-        client_ip: peer_addr.ip(),
+        client_ip: client_addr.ip(),
+        proxy_ip,
         timestamp: std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_millis() as u64,
     };
-    quic_send.write_all(&header.encode()).await?;
-
-    // Bidirectional copy between TCP and QUIC
-    let (mut tcp_read, mut tcp_write) = tcp_stream.split();
-
-    let client_to_server = tokio::io::copy(&mut tcp_read, &mut quic_send);
-    let server_to_client = tokio::io::copy(&mut quic_recv, &mut tcp_write);
-
-    tokio::select! {
-        result = client_to_server => {
-            if let Err(e) = result {
-                debug!("TCP->QUIC copy error: {}", e);
+    let result = quic_send.write_all(&header.encode()).await;
+    if result.is_ok() {
+        // Bidirectional copy between TCP and QUIC
+        let (mut tcp_read, mut tcp_write) = tcp_stream.split();
+
+        let client_to_server = copy_counted(&mut tcp_read, &mut quic_send, &tenant.bytes_in);
+        let server_to_client = copy_counted(&mut quic_recv, &mut tcp_write, &tenant.bytes_out);
+
+        tokio::select! {
+            result = client_to_server => {
+                if let Err(e) = result {
+                    debug!("TCP->QUIC copy error: {}", e);
+                }
+                let _ = quic_send.finish();
             }
-            let _ = quic_send.finish();
-        }
-        result = server_to_client => {
-            if let Err(e) = result {
-                debug!("QUIC->TCP copy error: {}", e);
+            result = server_to_client => {
+                if let Err(e) = result {
+                    debug!("QUIC->TCP copy error: {}", e);
+                }
             }
         }
     }
+    tenant.active_streams.fetch_sub(1, Ordering::Relaxed);
+    result?;
 
     Ok(())
 }
 
+/// Copy `reader` into `writer` until EOF, adding each chunk to `counter`
+/// so the status API sees long-lived streams grow.
+async fn copy_counted<R, W>(reader: &mut R, writer: &mut W, counter: &AtomicU64) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let mut buf = vec![0u8; 32 * 1024];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        writer.write_all(&buf[..n]).await?;
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+/// Serve `GET /status`: per-tenant connection state and counters, as JSON.
+pub async fn run_status_server(addr: SocketAddr, relay: Arc<Relay>) -> Result<()> {
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper_util::rt::TokioIo;
+
+    let listener = TcpListener::bind(addr).await?;
+    info!("Status API listening on {}", addr);
+
+    loop {
+        let (stream, _remote) = match listener.accept().await {
+            Ok(r) => r,
+            Err(e) => {
+                warn!("Status API accept error: {}", e);
+                continue;
+            }
+        };
+
+        let io = TokioIo::new(stream);
+        let relay = relay.clone();
+
+        tokio::spawn(async move {
+            let service = service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
+                let relay = relay.clone();
+                async move {
+                    let response = if req.method() == hyper::Method::GET && req.uri().path() == "/status" {
+                        let body = serde_json::to_vec(&relay.status().await).unwrap_or_default();
+                        hyper::Response::builder()
+                            .header("Content-Type", "application/json")
+                            .body(http_body_util::Full::new(hyper::body::Bytes::from(body)))
+                    } else {
+                        hyper::Response::builder()
+                            .status(404)
+                            .body(http_body_util::Full::new(hyper::body::Bytes::new()))
+                    };
+                    Ok::<_, std::convert::Infallible>(response.unwrap())
+                }
+            });
+
+            if let Err(e) = http1::Builder::new().serve_connection(io, service).await {
+                debug!("Status API error: {}", e);
+            }
+        });
+    }
+}
+
 /// Simple HTTP server that redirects all requests to HTTPS.
 pub async fn run_http_redirect(port: u16) -> Result<()> {
     use hyper::server::conn::http1;
@@ -140,29 +234,15 @@ pub async fn run_http_redirect(port: u16) -> Result<()> {
     }
 }
 
-/// Handle the control stream for a tunnel connection (ping/pong, binary updates).
-pub async fn handle_control_stream(conn: &Connection) {
+/// Handle the control stream for a tunnel connection (ping/pong, binary
+/// updates). Binary updates are only taken from `admin` tunnels.
+pub async fn handle_control_stream(conn: &Connection, admin: bool) {
     loop {
         match conn.accept_uni().await {
             Ok(mut recv) => {
+                let conn = conn.clone();
                 tokio::spawn(async move {
-                    // Read length-prefixed control message
-                    let mut len_buf = [0u8; 4];
-                    if recv.read_exact(&mut len_buf).await.is_err() {
-                        return;
-                    }
-                    let msg_len = u32::from_be_bytes(len_buf) as usize;
-                    if msg_len > 1024 * 1024 {
-                        warn!("Control message too large: {} bytes", msg_len);
-                        return;
-                    }
-
-                    let mut json_buf = vec![0u8; msg_len];
-                    if recv.read_exact(&mut json_buf).await.is_err() {
-                        return;
-                    }
-
-                    let msg: ControlMessage = match serde_json::from_slice(&json_buf) {
+                    let msg = match read_control_message(&mut recv).await {
                         Ok(m) => m,
                         Err(e) => {
                             debug!("Invalid control message: {}", e);
@@ -171,6 +251,9 @@ pub async fn handle_control_stream(conn: &Connection) {
                     };
 
                     match msg {
+                        ControlMessage::BinaryUpdate { .. } if !admin => {
+                            warn!("Binary update refused: tunnel is not an admin tenant");
+                        }
                         ControlMessage::BinaryUpdate { size, sha256 } => {
                             info!("Receiving binary update: {} bytes, sha256={}", size, sha256);
                             if let Err(e) = handle_binary_update(&mut recv, size, &sha256).await {
@@ -178,7 +261,13 @@ pub async fn handle_control_stream(conn: &Connection) {
                             }
                         }
                         ControlMessage::Ping { ts } => {
-                            debug!("Received ping ts={}", ts);
+                            let pong = ControlMessage::Pong {
+                                ts,
+                                latency_us: conn.rtt().as_micros() as u64,
+                            };
+                            if let Err(e) = send_control_message(&conn, &pong).await {
+                                debug!("Failed to answer ping: {}", e);
+                            }
                         }
                         _ => {
                             debug!("Received control message: {:?}", msg);
@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+
+/// How long a client may take to send its ClientHello.
+const PEEK_TIMEOUT: Duration = Duration::from_secs(5);
+/// A TLS record is at most 16 KiB plus its 5-byte header.
+const MAX_RECORD: usize = 16384 + 5;
+
+enum Parse {
+    Sni(String),
+    NoSni,
+    Incomplete,
+}
+
+/// Server name of the TLS ClientHello at the start of `stream`, read
+/// without consuming it so the stream can be relayed untouched. `None`
+/// for non-TLS traffic, a ClientHello without SNI, or a silent client.
+pub async fn peek_sni(stream: &TcpStream) -> Option<String> {
+    let mut buf = vec![0u8; MAX_RECORD];
+    let deadline = tokio::time::Instant::now() + PEEK_TIMEOUT;
+    let mut seen = 0;
+    loop {
+        let n = tokio::time::timeout_at(deadline, stream.peek(&mut buf)).await.ok()?.ok()?;
+        if n == 0 {
+            return None;
+        }
+        match parse_client_hello(&buf[..n]) {
+            Parse::Sni(name) => return Some(name),
+            Parse::NoSni => return None,
+            Parse::Incomplete if n == buf.len() => return None,
+            // peek returns as soon as anything is buffered: wait for more
+            Parse::Incomplete => {
+                if n == seen {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+                seen = n;
+                if tokio::time::Instant::now() >= deadline {
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Find the server_name extension in the first TLS record of `data`.
+fn parse_client_hello(data: &[u8]) -> Parse {
+    // Record header: handshake (22), version, length
+    if data.len() < 5 {
+        return Parse::Incomplete;
+    }
+    if data[0] != 22 {
+        return Parse::NoSni;
+    }
+    let record_len = u16::from_be_bytes([data[3], data[4]]) as usize;
+    let Some(record) = data.get(5..5 + record_len) else {
+        return Parse::Incomplete;
+    };
+    client_hello_sni(record).map_or(Parse::NoSni, Parse::Sni)
+}
+
+fn client_hello_sni(record: &[u8]) -> Option<String> {
+    let mut r = Reader(record);
+    // Handshake header: ClientHello (1), 24-bit length
+    if r.u8()? != 1 {
+        return None;
+    }
+    r.take(3)?;
+    r.take(2 + 32)?; // legacy_version, random
+    let session_id = r.u8()? as usize;
+    r.take(session_id)?;
+    let cipher_suites = r.u16()? as usize;
+    r.take(cipher_suites)?;
+    let compression = r.u8()? as usize;
+    r.take(compression)?;
+    let extensions_len = r.u16()? as usize;
+    let mut extensions = Reader(r.take(extensions_len)?);
+    while let Some(kind) = extensions.u16() {
+        let len = extensions.u16()? as usize;
+        let body = extensions.take(len)?;
+        if kind != 0 {
+            continue;
+        }
+        let mut list = Reader(body);
+        let list_len = list.u16()? as usize;
+        let mut names = Reader(list.take(list_len)?);
+        while let Some(name_type) = names.u8() {
+            let len = names.u16()? as usize;
+            let name = names.take(len)?;
+            if name_type == 0 {
+                let name = std::str::from_utf8(name).ok()?;
+                return Some(name.trim_end_matches('.').to_ascii_lowercase());
+            }
+        }
+        return None;
+    }
+    None
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+}
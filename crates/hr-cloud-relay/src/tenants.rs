@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use quinn::Connection;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+/// A home site allowed on this relay.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TenantConfig {
+    /// CN of the client certificate of its tunnel.
+    pub name: String,
+    /// Domains served by this tenant; subdomains included.
+    #[serde(default)]
+    pub domains: Vec<String>,
+    /// May push relay binary updates.
+    #[serde(default)]
+    pub admin: bool,
+}
+
+/// A tunnel and its counters.
+pub struct Tenant {
+    pub name: String,
+    pub admin: bool,
+    connection: RwLock<Option<Connection>>,
+    remote: Mutex<Option<SocketAddr>>,
+    connected_since: AtomicU64,
+    pub active_streams: AtomicU32,
+    pub total_streams: AtomicU64,
+    pub bytes_in: AtomicU64,
+    pub bytes_out: AtomicU64,
+    pub rejected: AtomicU64,
+}
+
+impl Tenant {
+    fn new(name: &str, admin: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            admin,
+            connection: RwLock::new(None),
+            remote: Mutex::new(None),
+            connected_since: AtomicU64::new(0),
+            active_streams: AtomicU32::new(0),
+            total_streams: AtomicU64::new(0),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn connection(&self) -> Option<Connection> {
+        self.connection.read().await.clone()
+    }
+}
+
+/// Per-tenant entry of `GET /status`.
+#[derive(Serialize)]
+pub struct TenantStatus {
+    pub name: String,
+    pub domains: Vec<String>,
+    pub connected: bool,
+    pub remote: Option<SocketAddr>,
+    /// Unix seconds.
+    pub connected_since: Option<u64>,
+    pub active_streams: u32,
+    pub total_streams: u64,
+    /// Bytes from internet clients to the home site.
+    pub bytes_in: u64,
+    /// Bytes from the home site to internet clients.
+    pub bytes_out: u64,
+    /// Connections dropped because the tunnel was down.
+    pub rejected: u64,
+}
+
+#[derive(Serialize)]
+pub struct RelayStatus {
+    pub tenants: Vec<TenantStatus>,
+    /// Connections no tenant claimed.
+    pub unrouted: u64,
+}
+
+/// Tunnels connected to the relay, keyed by client cert CN.
+///
+/// Without `[[tenants]]` in the config the relay serves a single home:
+/// any tunnel signed by the CA is accepted and the last one to connect
+/// gets all the traffic.
+pub struct Relay {
+    tenants: RwLock<HashMap<String, Arc<Tenant>>>,
+    /// `(domain, tenant)`, longest domain first.
+    routes: Vec<(String, String)>,
+    default_tenant: Option<String>,
+    single: bool,
+    last_connected: RwLock<Option<String>>,
+    unrouted: AtomicU64,
+}
+
+impl Relay {
+    pub fn new(configs: &[TenantConfig], default_tenant: Option<String>) -> Result<Self> {
+        let mut tenants = HashMap::new();
+        let mut routes = Vec::new();
+        for config in configs {
+            anyhow::ensure!(
+                !tenants.contains_key(&config.name),
+                "Duplicate tenant {}",
+                config.name
+            );
+            tenants.insert(config.name.clone(), Arc::new(Tenant::new(&config.name, config.admin)));
+            for domain in &config.domains {
+                let domain = domain
+                    .trim_start_matches("*.")
+                    .trim_end_matches('.')
+                    .to_ascii_lowercase();
+                if let Some((_, other)) = routes.iter().find(|(d, _)| *d == domain) {
+                    anyhow::bail!("Domain {} claimed by {} and {}", domain, other, config.name);
+                }
+                routes.push((domain, config.name.clone()));
+            }
+        }
+        if let Some(name) = &default_tenant {
+            anyhow::ensure!(
+                configs.is_empty() || tenants.contains_key(name),
+                "Unknown default tenant {}",
+                name
+            );
+        }
+        routes.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        Ok(Self {
+            tenants: RwLock::new(tenants),
+            routes,
+            default_tenant,
+            single: configs.is_empty(),
+            last_connected: RwLock::new(None),
+            unrouted: AtomicU64::new(0),
+        })
+    }
+
+    /// Register the tunnel of `name`, replacing its previous connection.
+    /// `None` when `name` is not a configured tenant.
+    pub async fn attach(&self, name: &str, connection: Connection) -> Option<Arc<Tenant>> {
+        let tenant = if self.single {
+            self.tenants
+                .write()
+                .await
+                .entry(name.to_string())
+                .or_insert_with(|| Arc::new(Tenant::new(name, true)))
+                .clone()
+        } else {
+            self.tenants.read().await.get(name)?.clone()
+        };
+        *tenant.remote.lock().unwrap() = Some(connection.remote_address());
+        tenant.connected_since.store(unix_now(), Ordering::Relaxed);
+        *tenant.connection.write().await = Some(connection);
+        *self.last_connected.write().await = Some(name.to_string());
+        Some(tenant)
+    }
+
+    /// Forget the connection `stable_id` of `tenant`, unless it was
+    /// already replaced by a newer one.
+    pub async fn detach(&self, tenant: &Tenant, stable_id: usize) {
+        let mut current = tenant.connection.write().await;
+        if current.as_ref().is_some_and(|c| c.stable_id() == stable_id) {
+            *current = None;
+            *tenant.remote.lock().unwrap() = None;
+            tenant.connected_since.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Tenant serving `sni`: the longest matching domain, else the default
+    /// tenant, else (single-home relay) the last tunnel to connect.
+    pub async fn route(&self, sni: Option<&str>) -> Option<Arc<Tenant>> {
+        let tenant = self.lookup(sni).await;
+        if tenant.is_none() {
+            self.unrouted.fetch_add(1, Ordering::Relaxed);
+        }
+        tenant
+    }
+
+    async fn lookup(&self, sni: Option<&str>) -> Option<Arc<Tenant>> {
+        let name = sni
+            .and_then(|sni| {
+                self.routes
+                    .iter()
+                    .find(|(domain, _)| {
+                        sni == domain.as_str()
+                            || sni.strip_suffix(domain.as_str()).is_some_and(|p| p.ends_with('.'))
+                    })
+                    .map(|(_, tenant)| tenant.clone())
+            })
+            .or_else(|| self.default_tenant.clone());
+        let name = match name {
+            Some(name) => name,
+            None if self.single => self.last_connected.read().await.clone()?,
+            None => return None,
+        };
+        self.tenants.read().await.get(&name).cloned()
+    }
+
+    /// Tenant of a UDP port: `tenant` when the port names one, else the
+    /// one of connections without SNI.
+    pub async fn route_udp(&self, tenant: Option<&str>) -> Option<Arc<Tenant>> {
+        match tenant {
+            Some(name) => self.tenants.read().await.get(name).cloned(),
+            None => self.lookup(None).await,
+        }
+    }
+
+    pub async fn status(&self) -> RelayStatus {
+        let tenants = self.tenants.read().await;
+        let mut statuses = Vec::with_capacity(tenants.len());
+        for tenant in tenants.values() {
+            let since = tenant.connected_since.load(Ordering::Relaxed);
+            statuses.push(TenantStatus {
+                name: tenant.name.clone(),
+                domains: self
+                    .routes
+                    .iter()
+                    .filter(|(_, t)| *t == tenant.name)
+                    .map(|(d, _)| d.clone())
+                    .collect(),
+                connected: tenant.connection.read().await.is_some(),
+                remote: *tenant.remote.lock().unwrap(),
+                connected_since: (since > 0).then_some(since),
+                active_streams: tenant.active_streams.load(Ordering::Relaxed),
+                total_streams: tenant.total_streams.load(Ordering::Relaxed),
+                bytes_in: tenant.bytes_in.load(Ordering::Relaxed),
+                bytes_out: tenant.bytes_out.load(Ordering::Relaxed),
+                rejected: tenant.rejected.load(Ordering::Relaxed),
+            });
+        }
+        statuses.sort_by(|a, b| a.name.cmp(&b.name));
+        RelayStatus {
+            tenants: statuses,
+            unrouted: self.unrouted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
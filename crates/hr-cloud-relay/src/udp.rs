@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use hr_tunnel::protocol::{UdpDatagram, UdpFlow};
+use quinn::Connection;
+use serde::Deserialize;
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
+
+use crate::tenants::{Relay, Tenant};
+
+/// A flow without traffic for this long is forgotten.
+const FLOW_IDLE: Duration = Duration::from_secs(180);
+
+/// `[[udp]]` section: a public UDP port relayed to a home site.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UdpPortConfig {
+    pub port: u16,
+    /// Tenant serving the port; the default one when unset.
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+struct Flow {
+    tenant: String,
+    socket: Arc<UdpSocket>,
+    client: SocketAddr,
+    port: u16,
+    last_seen: Instant,
+}
+
+/// UDP flows of the relayed ports, keyed by id in both directions.
+#[derive(Default)]
+pub struct UdpFlows {
+    flows: Mutex<FlowTable>,
+}
+
+#[derive(Default)]
+struct FlowTable {
+    next_id: u32,
+    by_id: HashMap<u32, Flow>,
+    by_client: HashMap<(u16, SocketAddr), u32>,
+}
+
+impl UdpFlows {
+    /// Id of the flow of `client` on `port`, created on first sight.
+    fn flow_id(&self, tenant: &str, port: u16, client: SocketAddr, socket: &Arc<UdpSocket>) -> u32 {
+        let mut guard = self.flows.lock().unwrap();
+        let table = &mut *guard;
+        if let Some(&id) = table.by_client.get(&(port, client))
+            && let Some(flow) = table.by_id.get_mut(&id)
+            && flow.tenant == tenant
+        {
+            flow.last_seen = Instant::now();
+            return id;
+        }
+        table.next_id = table.next_id.wrapping_add(1);
+        let id = table.next_id;
+        table.by_id.insert(id, Flow {
+            tenant: tenant.to_string(),
+            socket: socket.clone(),
+            client,
+            port,
+            last_seen: Instant::now(),
+        });
+        table.by_client.insert((port, client), id);
+        id
+    }
+
+    /// Where to send a reply of `tenant` on `flow_id`; a tenant can only
+    /// answer its own flows.
+    fn reply_target(&self, tenant: &str, flow_id: u32) -> Option<(Arc<UdpSocket>, SocketAddr)> {
+        let mut table = self.flows.lock().unwrap();
+        let flow = table.by_id.get_mut(&flow_id).filter(|f| f.tenant == tenant)?;
+        flow.last_seen = Instant::now();
+        Some((flow.socket.clone(), flow.client))
+    }
+
+    fn expire(&self) {
+        let mut table = self.flows.lock().unwrap();
+        let expired: Vec<u32> = table
+            .by_id
+            .iter()
+            .filter(|(_, f)| f.last_seen.elapsed() > FLOW_IDLE)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            if let Some(flow) = table.by_id.remove(&id) {
+                table.by_client.remove(&(flow.port, flow.client));
+                debug!("UDP flow {} of {} on port {} expired", id, flow.client, flow.port);
+            }
+        }
+    }
+}
+
+/// Bind every configured UDP port and relay its datagrams to its tenant.
+pub async fn run_udp_ports(ports: Vec<UdpPortConfig>, relay: Arc<Relay>, flows: Arc<UdpFlows>) -> Result<()> {
+    for config in ports {
+        let addr: SocketAddr = format!("[::]:{}", config.port).parse()?;
+        let socket = Arc::new(
+            UdpSocket::bind(addr)
+                .await
+                .with_context(|| format!("Failed to bind UDP relay on {}", addr))?,
+        );
+        info!("UDP relay listening on {}", addr);
+        let relay = relay.clone();
+        let flows = flows.clone();
+        tokio::spawn(async move {
+            run_udp_port(socket, config, relay, flows).await;
+        });
+    }
+
+    let mut sweep = tokio::time::interval(FLOW_IDLE / 3);
+    loop {
+        sweep.tick().await;
+        flows.expire();
+    }
+}
+
+async fn run_udp_port(socket: Arc<UdpSocket>, config: UdpPortConfig, relay: Arc<Relay>, flows: Arc<UdpFlows>) {
+    let mut buf = vec![0u8; 65535];
+    loop {
+        let (len, client) = match socket.recv_from(&mut buf).await {
+            Ok(r) => r,
+            Err(e) => {
+                warn!("UDP recv error on port {}: {}", config.port, e);
+                continue;
+            }
+        };
+        let Some(tenant) = relay.route_udp(config.tenant.as_deref()).await else {
+            continue;
+        };
+        let Some(conn) = tenant.connection().await else {
+            tenant.rejected.fetch_add(1, Ordering::Relaxed);
+            continue;
+        };
+        let flow_id = flows.flow_id(&tenant.name, config.port, client, &socket);
+        let datagram = UdpDatagram {
+            flow_id,
+            flow: Some(UdpFlow {
+                port: config.port,
+                client,
+            }),
+            payload: Bytes::copy_from_slice(&buf[..len]),
+        };
+        // Too large for a QUIC datagram on this path: dropped like on a
+        // lossy link, the sender has to lower its MTU
+        match conn.send_datagram(datagram.encode()) {
+            Ok(()) => {
+                tenant.bytes_in.fetch_add(len as u64, Ordering::Relaxed);
+            }
+            Err(e) => debug!("UDP datagram from {} on port {} dropped: {}", client, config.port, e),
+        }
+    }
+}
+
+/// Send the replies coming back through the tunnel of `tenant` to their clients.
+pub async fn run_tunnel_datagrams(conn: Connection, tenant: Arc<Tenant>, flows: Arc<UdpFlows>) {
+    while let Ok(data) = conn.read_datagram().await {
+        let datagram = match UdpDatagram::decode(data) {
+            Ok(d) => d,
+            Err(e) => {
+                debug!("Invalid UDP datagram from {}: {}", tenant.name, e);
+                continue;
+            }
+        };
+        let Some((socket, client)) = flows.reply_target(&tenant.name, datagram.flow_id) else {
+            continue;
+        };
+        if socket.send_to(&datagram.payload, client).await.is_ok() {
+            tenant.bytes_out.fetch_add(datagram.payload.len() as u64, Ordering::Relaxed);
+        }
+    }
+}
@@ -21,6 +21,24 @@ pub struct EnvConfig {
     pub proxy_config_path: PathBuf,
     pub dns_dhcp_config_path: PathBuf,
     pub reverseproxy_config_path: PathBuf,
+    pub streams_config_path: PathBuf,
+    pub mqtt_config_path: PathBuf,
+    pub reflector_config_path: PathBuf,
+    pub syslog_config_path: PathBuf,
+    pub radius_config_path: PathBuf,
+    pub ntp_config_path: PathBuf,
+    pub flow_config_path: PathBuf,
+    pub firewall_config_path: PathBuf,
+    pub mail_config_path: PathBuf,
+    pub s3_config_path: PathBuf,
+    pub tailscale_config_path: PathBuf,
+    pub qos_config_path: PathBuf,
+    pub relay_usage_config_path: PathBuf,
+    pub pubsub_config_path: PathBuf,
+    pub ai_config_path: PathBuf,
+    pub ddns_config_path: PathBuf,
+    pub scanner_config_path: PathBuf,
+    pub energy_config_path: PathBuf,
     /// Répertoire ACME (Let's Encrypt)
     pub acme_storage_path: PathBuf,
     /// Email pour le compte ACME
@@ -36,6 +54,9 @@ pub struct EnvConfig {
     /// Cloud Relay
     pub cloud_relay_enabled: bool,
     pub cloud_relay_host: Option<String>,
+    /// Relais de secours (`host` ou `host:port`), essayés dans l'ordre quand
+    /// le relais principal ne répond pas
+    pub cloud_relay_fallback_hosts: Vec<String>,
     pub cloud_relay_quic_port: u16,
     pub cloud_relay_ssh_user: Option<String>,
     pub cloud_relay_ssh_port: u16,
@@ -62,6 +83,60 @@ impl Default for EnvConfig {
             reverseproxy_config_path: PathBuf::from(
                 "/var/lib/server-dashboard/reverseproxy-config.json",
             ),
+            streams_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/streams-config.json",
+            ),
+            mqtt_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/mqtt-config.json",
+            ),
+            reflector_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/reflector-config.json",
+            ),
+            syslog_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/syslog-config.json",
+            ),
+            radius_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/radius-config.json",
+            ),
+            ntp_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/ntp-config.json",
+            ),
+            flow_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/flow-config.json",
+            ),
+            firewall_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/firewall-config.json",
+            ),
+            mail_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/mail-config.json",
+            ),
+            s3_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/s3-config.json",
+            ),
+            tailscale_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/tailscale-config.json",
+            ),
+            qos_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/qos-config.json",
+            ),
+            relay_usage_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/relay-usage-config.json",
+            ),
+            pubsub_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/pubsub-config.json",
+            ),
+            ai_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/ai-config.json",
+            ),
+            ddns_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/ddns-config.json",
+            ),
+            scanner_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/scanner-config.json",
+            ),
+            energy_config_path: PathBuf::from(
+                "/var/lib/server-dashboard/energy-config.json",
+            ),
             acme_storage_path: PathBuf::from("/var/lib/server-dashboard/acme"),
             acme_email: None,
             acme_staging: false,
@@ -70,6 +145,7 @@ impl Default for EnvConfig {
             web_dist_path: PathBuf::from("/opt/homeroute/web/dist"),
             cloud_relay_enabled: false,
             cloud_relay_host: None,
+            cloud_relay_fallback_hosts: Vec::new(),
             cloud_relay_quic_port: 4443,
             cloud_relay_ssh_user: None,
             cloud_relay_ssh_port: 22,
@@ -135,6 +211,13 @@ impl EnvConfig {
         if let Ok(v) = std::env::var("CLOUD_RELAY_HOST") {
             config.cloud_relay_host = Some(v);
         }
+        if let Ok(v) = std::env::var("CLOUD_RELAY_FALLBACK_HOSTS") {
+            config.cloud_relay_fallback_hosts = v
+                .split(',')
+                .map(|h| h.trim().to_string())
+                .filter(|h| !h.is_empty())
+                .collect();
+        }
         if let Ok(v) = std::env::var("CLOUD_RELAY_QUIC_PORT") {
             if let Ok(port) = v.parse() {
                 config.cloud_relay_quic_port = port;
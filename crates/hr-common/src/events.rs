@@ -1,3 +1,6 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
 
@@ -19,6 +22,8 @@ pub struct EventBus {
     pub agent_update: broadcast::Sender<AgentUpdateEvent>,
     /// Migration progress events (API → websocket)
     pub migration_progress: broadcast::Sender<MigrationProgressEvent>,
+    /// Blue/green deployment progress events (API → websocket)
+    pub deployment_progress: broadcast::Sender<DeploymentProgressEvent>,
     /// Dataverse schema change events (registry → websocket)
     pub dataverse_schema: broadcast::Sender<DataverseSchemaEvent>,
     /// Dataverse data change events (registry → websocket)
@@ -31,6 +36,24 @@ pub struct EventBus {
     pub cloud_relay: broadcast::Sender<CloudRelayEvent>,
     /// Certificate ready events (ACME → main for dynamic TLS loading)
     pub cert_ready: broadcast::Sender<CertReadyEvent>,
+    /// Backend health transitions (proxy health checker → websocket)
+    pub backend_health: broadcast::Sender<BackendHealthEvent>,
+    /// Login failures, lockouts and credential stuffing (auth throttling → websocket)
+    pub auth_security: broadcast::Sender<AuthSecurityEvent>,
+    /// Messages published by apps on pub/sub topics (pub/sub hub → websocket)
+    pub pubsub: broadcast::Sender<PubSubEvent>,
+    /// DDNS records updated at their provider (ddns service → websocket)
+    pub ddns: broadcast::Sender<DdnsEvent>,
+    /// Devices appearing, going on/offline or changing address (scanner → websocket)
+    pub network_device: broadcast::Sender<NetworkDeviceEvent>,
+    /// Adblock pauses and scheduled profiles flipping (DNS → websocket)
+    pub adblock: broadcast::Sender<AdblockEvent>,
+    /// Host disks whose SMART report got worse (host agents → websocket)
+    pub disk_health: broadcast::Sender<DiskHealthEvent>,
+    /// Package upgrades of hosts: output and outcome (host agents → websocket)
+    pub host_upgrade: broadcast::Sender<HostUpgradeEvent>,
+    /// Messages of the websocket topics, numbered for replay (fan-out → websocket)
+    pub journal: EventJournal,
 }
 
 impl EventBus {
@@ -44,14 +67,54 @@ impl EventBus {
             service_command: broadcast::channel(64).0,
             agent_update: broadcast::channel(64).0,
             migration_progress: broadcast::channel(64).0,
+            deployment_progress: broadcast::channel(64).0,
             dataverse_schema: broadcast::channel(64).0,
             dataverse_data: broadcast::channel(64).0,
             host_metrics: broadcast::channel(64).0,
             host_power: broadcast::channel(64).0,
             cloud_relay: broadcast::channel(64).0,
             cert_ready: broadcast::channel(16).0,
+            backend_health: broadcast::channel(64).0,
+            auth_security: broadcast::channel(64).0,
+            pubsub: broadcast::channel(256).0,
+            ddns: broadcast::channel(16).0,
+            network_device: broadcast::channel(64).0,
+            adblock: broadcast::channel(16).0,
+            disk_health: broadcast::channel(16).0,
+            host_upgrade: broadcast::channel(256).0,
+            journal: EventJournal::new(JOURNAL_CAPACITY),
         }
     }
+
+    /// Events queued in each channel and not yet seen by every subscriber.
+    pub fn backlog(&self) -> Vec<(&'static str, usize)> {
+        vec![
+            ("host_status", self.host_status.len()),
+            ("config_changed", self.config_changed.len()),
+            ("updates", self.updates.len()),
+            ("agent_status", self.agent_status.len()),
+            ("agent_metrics", self.agent_metrics.len()),
+            ("service_command", self.service_command.len()),
+            ("agent_update", self.agent_update.len()),
+            ("migration_progress", self.migration_progress.len()),
+            ("deployment_progress", self.deployment_progress.len()),
+            ("dataverse_schema", self.dataverse_schema.len()),
+            ("dataverse_data", self.dataverse_data.len()),
+            ("host_metrics", self.host_metrics.len()),
+            ("host_power", self.host_power.len()),
+            ("cloud_relay", self.cloud_relay.len()),
+            ("cert_ready", self.cert_ready.len()),
+            ("backend_health", self.backend_health.len()),
+            ("auth_security", self.auth_security.len()),
+            ("pubsub", self.pubsub.len()),
+            ("ddns", self.ddns.len()),
+            ("network_device", self.network_device.len()),
+            ("adblock", self.adblock.len()),
+            ("disk_health", self.disk_health.len()),
+            ("host_upgrade", self.host_upgrade.len()),
+            ("journal", self.journal.live.len()),
+        ]
+    }
 }
 
 impl Default for EventBus {
@@ -171,6 +234,8 @@ pub enum MigrationPhase {
     Exporting,
     Transferring,
     TransferringWorkspace,
+    /// The target host disconnected mid-transfer, waiting for it to resume.
+    Resuming,
     Importing,
     ImportingWorkspace,
     Verifying,
@@ -179,6 +244,41 @@ pub enum MigrationPhase {
     Failed,
 }
 
+/// Blue/green deployment progress event (API → websocket for frontend display).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentProgressEvent {
+    pub app_id: String,
+    pub deploy_id: String,
+    pub phase: DeploymentPhase,
+    /// Container receiving the traffic once the phase completes.
+    pub container_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Phase of a blue/green deployment or of its rollback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeploymentPhase {
+    Cloning,
+    Installing,
+    Starting,
+    HealthCheck,
+    Switching,
+    Complete,
+    RollingBack,
+    RolledBack,
+    Failed,
+}
+
+impl DeploymentPhase {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Complete | Self::RolledBack | Self::Failed)
+    }
+}
+
 /// Dataverse schema change event (registry → websocket for frontend live view).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DataverseSchemaEvent {
@@ -315,6 +415,232 @@ pub struct CertReadyEvent {
     pub key_path: String,
 }
 
+/// Emitted when a proxy route target changes health state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendHealthEvent {
+    pub route_id: String,
+    pub domain: String,
+    pub target: String,
+    pub healthy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Emitted by login throttling: each failed login, each lockout, and the
+/// first time one IP has tried too many usernames.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthSecurityEvent {
+    pub kind: AuthSecurityKind,
+    pub username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    /// Consecutive failures (of the locked key for `locked_out`).
+    pub failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locked_for_secs: Option<u64>,
+    /// Distinct usernames tried from `ip` within the failure window.
+    pub distinct_users: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthSecurityKind {
+    LoginFailed,
+    /// The username or the IP is locked out.
+    LockedOut,
+    /// One IP failing against many usernames.
+    CredentialStuffing,
+}
+
+/// Message an app published on a pub/sub topic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PubSubEvent {
+    pub id: String,
+    pub topic: String,
+    /// Slug of the publishing app.
+    pub publisher: String,
+    pub payload: serde_json::Value,
+    pub published_at: String,
+}
+
+/// A dynamic DNS record was updated at its provider.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DdnsEvent {
+    pub record_id: String,
+    pub name: String,
+    pub provider: String,
+    pub ipv4: Option<String>,
+    pub ipv6: Option<String>,
+    pub previous_ipv4: Option<String>,
+    pub previous_ipv6: Option<String>,
+    pub updated_at: String,
+}
+
+/// A LAN device appeared, went online or offline, or changed address.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkDeviceEvent {
+    /// `new`, `online`, `offline` or `ip_changed`
+    pub change: String,
+    pub mac: String,
+    pub ip: Option<String>,
+    pub previous_ip: Option<String>,
+    pub hostname: Option<String>,
+    pub vendor: Option<String>,
+    pub at: String,
+}
+
+/// Adblock blocking paused or resumed, a domain temporarily allowed, or a
+/// scheduled profile opening or closing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdblockEvent {
+    /// `paused`, `resumed`, `allowed`, `allow_expired`, `profile_active`
+    /// or `profile_inactive`
+    pub change: String,
+    /// Paused or allowed client, `None` for every client
+    pub client: Option<String>,
+    pub profile: Option<String>,
+    /// Temporarily allowed domain
+    pub domain: Option<String>,
+    /// End of the pause or allow
+    pub until: Option<String>,
+    pub at: String,
+}
+
+/// A disk of a host degraded: SMART check failed, bad sectors or media
+/// errors grew, or the SSD is nearly worn out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskHealthEvent {
+    pub host_id: String,
+    pub host_name: String,
+    pub device: String,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    pub passed: Option<bool>,
+    pub reasons: Vec<String>,
+    pub at: String,
+}
+
+/// Progress of a package upgrade on a host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum HostUpgradeEvent {
+    Started {
+        host_id: String,
+        upgrade_id: String,
+    },
+    Output {
+        host_id: String,
+        upgrade_id: String,
+        line: String,
+    },
+    Complete {
+        host_id: String,
+        upgrade_id: String,
+        success: bool,
+        error: Option<String>,
+        snapshot: Option<String>,
+        reboot_required: bool,
+        /// The registry rebooted the host to finish the upgrade.
+        rebooting: bool,
+    },
+}
+
+/// Messages kept for replay.
+const JOURNAL_CAPACITY: usize = 2048;
+
+/// A message of a websocket topic, as sent to the clients.
+#[derive(Debug, Clone, Serialize)]
+pub struct JournalEntry {
+    /// `None` for volatile messages (periodic stats), which are not kept.
+    pub seq: Option<u64>,
+    pub topic: &'static str,
+    /// `{"type": ..., "data": ...}`
+    pub message: serde_json::Value,
+}
+
+/// Sequence-numbered journal of the websocket messages. The last
+/// messages are kept so that a client reconnecting can ask for the ones
+/// it missed instead of refetching everything. Sequence numbers restart
+/// with the process: `epoch` tells a client its numbers are stale.
+pub struct EventJournal {
+    entries: Mutex<VecDeque<JournalEntry>>,
+    next_seq: Mutex<u64>,
+    capacity: usize,
+    epoch: u64,
+    live: broadcast::Sender<JournalEntry>,
+}
+
+impl EventJournal {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+            next_seq: Mutex::new(1),
+            capacity,
+            epoch: crate::service_registry::now_millis(),
+            live: broadcast::channel(256).0,
+        }
+    }
+
+    /// Identifies this run of the journal.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Sequence number of the last kept message (0 before the first).
+    pub fn last_seq(&self) -> u64 {
+        *self.next_seq.lock().unwrap() - 1
+    }
+
+    /// Keep and broadcast a message; returns its sequence number.
+    pub fn publish(&self, topic: &'static str, message: serde_json::Value) -> u64 {
+        let mut next_seq = self.next_seq.lock().unwrap();
+        let seq = *next_seq;
+        *next_seq += 1;
+        let entry = JournalEntry { seq: Some(seq), topic, message };
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if entries.len() == self.capacity {
+                entries.pop_front();
+            }
+            entries.push_back(entry.clone());
+        }
+        // Sent under the lock so that live messages arrive in order
+        let _ = self.live.send(entry);
+        seq
+    }
+
+    /// Broadcast a message without keeping it.
+    pub fn publish_volatile(&self, topic: &'static str, message: serde_json::Value) {
+        let _ = self.live.send(JournalEntry { seq: None, topic, message });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<JournalEntry> {
+        self.live.subscribe()
+    }
+
+    /// Messages after `seq`, or `None` if some of them are no longer kept
+    /// (or `seq` is from another run).
+    pub fn since(&self, seq: u64) -> Option<Vec<JournalEntry>> {
+        let last = self.last_seq();
+        if seq > last {
+            return None;
+        }
+        let entries = self.entries.lock().unwrap();
+        let oldest = entries.front().and_then(|e| e.seq).unwrap_or(last + 1);
+        if seq + 1 < oldest {
+            return None;
+        }
+        Some(entries.iter().filter(|e| e.seq.is_some_and(|s| s > seq)).cloned().collect())
+    }
+}
+
 /// Command sent from the API to the tunnel client (e.g. push binary update).
 pub enum CloudRelayCommand {
     /// Push a new binary to the VPS via the QUIC tunnel.
@@ -323,4 +649,35 @@ pub enum CloudRelayCommand {
         sha256: String,
         response_tx: tokio::sync::oneshot::Sender<Result<String, String>>,
     },
+    /// Close the tunnel and connect again, e.g. to use new certificates.
+    Reconnect { reason: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn journal_replay() {
+        let journal = EventJournal::new(3);
+        assert_eq!(journal.last_seq(), 0);
+        assert_eq!(journal.since(0).unwrap().len(), 0);
+        assert!(journal.since(5).is_none());
+
+        let mut rx = journal.subscribe();
+        for i in 1..=4 {
+            assert_eq!(journal.publish("hosts", json!({"type": "hosts:status", "data": i})), i);
+        }
+        journal.publish_volatile("dns.stats", json!({"type": "dns:stats"}));
+        assert_eq!(journal.last_seq(), 4);
+        assert_eq!(rx.try_recv().unwrap().seq, Some(1));
+
+        // Messages 2 to 4 are kept
+        let seqs = |entries: Vec<JournalEntry>| entries.iter().map(|e| e.seq.unwrap()).collect::<Vec<_>>();
+        assert_eq!(seqs(journal.since(1).unwrap()), [2, 3, 4]);
+        assert_eq!(seqs(journal.since(3).unwrap()), [4]);
+        assert!(journal.since(4).unwrap().is_empty());
+        assert!(journal.since(0).is_none());
+    }
 }
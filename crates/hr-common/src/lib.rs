@@ -1,3 +1,6 @@
 pub mod config;
 pub mod events;
+pub mod metrics;
+pub mod selfmon;
 pub mod service_registry;
+pub mod tsdb;
@@ -0,0 +1,287 @@
+//! Process-wide metrics registry, rendered in Prometheus text format at
+//! `/api/metrics`.
+//!
+//! Services look up their series by name and labels on the global
+//! [`registry`]; the returned handles are cheap to clone and keep.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Default buckets for request latencies, in seconds.
+pub const LATENCY_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Debug, Clone, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Floating point gauge (stored as f64 bits).
+#[derive(Debug, Clone, Default)]
+pub struct Gauge(Arc<AtomicU64>);
+
+impl Gauge {
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn add(&self, delta: f64) {
+        let _ = self.0.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f64::from_bits(bits) + delta).to_bits())
+        });
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    /// Increment now, decrement when the guard is dropped.
+    pub fn track(&self) -> GaugeGuard {
+        self.add(1.0);
+        GaugeGuard(self.clone())
+    }
+}
+
+pub struct GaugeGuard(Gauge);
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.0.add(-1.0);
+    }
+}
+
+#[derive(Debug)]
+struct HistogramInner {
+    bounds: &'static [f64],
+    /// Per-bucket (non-cumulative) counts, plus +Inf.
+    buckets: Vec<AtomicU64>,
+    sum: Gauge,
+    count: AtomicU64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Histogram(Arc<HistogramInner>);
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self(Arc::new(HistogramInner {
+            bounds,
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum: Gauge::default(),
+            count: AtomicU64::new(0),
+        }))
+    }
+
+    pub fn observe(&self, value: f64) {
+        let index = self.0.bounds.iter().position(|b| value <= *b).unwrap_or(self.0.bounds.len());
+        self.0.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.0.sum.add(value);
+        self.0.count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Series {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+impl Series {
+    fn kind(&self) -> &'static str {
+        match self {
+            Series::Counter(_) => "counter",
+            Series::Gauge(_) => "gauge",
+            Series::Histogram(_) => "histogram",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Family {
+    help: &'static str,
+    /// Rendered label set (`a="x",b="y"`) → series.
+    series: BTreeMap<String, Series>,
+}
+
+#[derive(Debug, Default)]
+pub struct Registry {
+    families: Mutex<BTreeMap<&'static str, Family>>,
+}
+
+/// The registry shared by every service in the process.
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::default)
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn with_braces(labels: &str, extra: Option<String>) -> String {
+    let parts: Vec<String> = [(!labels.is_empty()).then(|| labels.to_string()), extra]
+        .into_iter()
+        .flatten()
+        .collect();
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", parts.join(","))
+    }
+}
+
+impl Registry {
+    fn series(&self, name: &'static str, help: &'static str, labels: &[(&str, &str)], make: impl FnOnce() -> Series) -> Series {
+        let mut families = self.families.lock().unwrap();
+        let family = families.entry(name).or_insert_with(|| Family { help, series: BTreeMap::new() });
+        family.series.entry(render_labels(labels)).or_insert_with(make).clone()
+    }
+
+    pub fn counter(&self, name: &'static str, help: &'static str, labels: &[(&str, &str)]) -> Counter {
+        match self.series(name, help, labels, || Series::Counter(Counter::default())) {
+            Series::Counter(c) => c,
+            other => panic!("metric {} registered as {}", name, other.kind()),
+        }
+    }
+
+    pub fn gauge(&self, name: &'static str, help: &'static str, labels: &[(&str, &str)]) -> Gauge {
+        match self.series(name, help, labels, || Series::Gauge(Gauge::default())) {
+            Series::Gauge(g) => g,
+            other => panic!("metric {} registered as {}", name, other.kind()),
+        }
+    }
+
+    pub fn histogram(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        buckets: &'static [f64],
+    ) -> Histogram {
+        match self.series(name, help, labels, || Series::Histogram(Histogram::new(buckets))) {
+            Series::Histogram(h) => h,
+            other => panic!("metric {} registered as {}", name, other.kind()),
+        }
+    }
+
+    /// Current value of every counter and gauge, plus histogram sums and
+    /// counts, keyed like the exposition format (`name{labels}`).
+    pub fn samples(&self) -> Vec<(String, f64)> {
+        let families = self.families.lock().unwrap();
+        let mut out = Vec::new();
+        for (name, family) in families.iter() {
+            for (labels, series) in &family.series {
+                let labels = with_braces(labels, None);
+                match series {
+                    Series::Counter(c) => out.push((format!("{}{}", name, labels), c.get() as f64)),
+                    Series::Gauge(g) => out.push((format!("{}{}", name, labels), g.get())),
+                    Series::Histogram(h) => {
+                        out.push((format!("{}_sum{}", name, labels), h.0.sum.get()));
+                        out.push((format!("{}_count{}", name, labels), h.0.count.load(Ordering::Relaxed) as f64));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Prometheus text exposition format (version 0.0.4).
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut out = String::new();
+        for (name, family) in families.iter() {
+            let Some(first) = family.series.values().next() else {
+                continue;
+            };
+            let _ = writeln!(out, "# HELP {} {}", name, family.help);
+            let _ = writeln!(out, "# TYPE {} {}", name, first.kind());
+            for (labels, series) in &family.series {
+                match series {
+                    Series::Counter(c) => {
+                        let _ = writeln!(out, "{}{} {}", name, with_braces(labels, None), c.get());
+                    }
+                    Series::Gauge(g) => {
+                        let _ = writeln!(out, "{}{} {}", name, with_braces(labels, None), g.get());
+                    }
+                    Series::Histogram(h) => {
+                        let mut cumulative = 0;
+                        for (i, bucket) in h.0.buckets.iter().enumerate() {
+                            cumulative += bucket.load(Ordering::Relaxed);
+                            let le = h.0.bounds.get(i).map_or("+Inf".to_string(), |b| b.to_string());
+                            let labels = with_braces(labels, Some(format!("le=\"{}\"", le)));
+                            let _ = writeln!(out, "{}_bucket{} {}", name, labels, cumulative);
+                        }
+                        let _ = writeln!(out, "{}_sum{} {}", name, with_braces(labels, None), h.0.sum.get());
+                        let count = h.0.count.load(Ordering::Relaxed);
+                        let _ = writeln!(out, "{}_count{} {}", name, with_braces(labels, None), count);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prometheus_text() {
+        let registry = Registry::default();
+        let requests = registry.counter("test_requests_total", "Requests.", &[("route", "a.example"), ("class", "2xx")]);
+        requests.add(3);
+        registry.counter("test_requests_total", "Requests.", &[("route", "a.example"), ("class", "2xx")]).inc();
+        registry.gauge("test_active", "Active.", &[]).set(2.0);
+        let latency = registry.histogram("test_latency_seconds", "Latency.", &[("route", "a\"b")], &[0.1, 1.0]);
+        latency.observe(0.05);
+        latency.observe(0.5);
+        latency.observe(3.0);
+
+        let text = registry.render();
+        assert!(text.contains("# TYPE test_requests_total counter\n"));
+        assert!(text.contains("test_requests_total{route=\"a.example\",class=\"2xx\"} 4\n"));
+        assert!(text.contains("test_active 2\n"));
+        assert!(text.contains("test_latency_seconds_bucket{route=\"a\\\"b\",le=\"0.1\"} 1\n"));
+        assert!(text.contains("test_latency_seconds_bucket{route=\"a\\\"b\",le=\"1\"} 2\n"));
+        assert!(text.contains("test_latency_seconds_bucket{route=\"a\\\"b\",le=\"+Inf\"} 3\n"));
+        assert!(text.contains("test_latency_seconds_count{route=\"a\\\"b\"} 3\n"));
+
+        let samples = registry.samples();
+        assert!(samples.contains(&("test_active".to_string(), 2.0)));
+        assert!(samples.contains(&("test_latency_seconds_count{route=\"a\\\"b\"}".to_string(), 3.0)));
+    }
+
+    #[test]
+    fn test_gauge_guard() {
+        let gauge = Gauge::default();
+        {
+            let _a = gauge.track();
+            let _b = gauge.track();
+            assert_eq!(gauge.get(), 2.0);
+        }
+        assert_eq!(gauge.get(), 0.0);
+    }
+}
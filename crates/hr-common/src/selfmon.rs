@@ -0,0 +1,146 @@
+//! Self-monitoring of the homeroute process: memory, file descriptors,
+//! tokio tasks per subsystem and event bus backlog.
+//!
+//! Everything lands in the metrics registry, so the history sampler keeps
+//! it in the TSDB and [`rising_floors`] can spot a slow leak over days.
+
+use std::future::Future;
+
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::metrics::{self, GaugeGuard};
+use crate::tsdb::Point;
+
+/// Count the caller as a running task of `subsystem` until the guard drops.
+pub fn track(subsystem: &'static str) -> GaugeGuard {
+    metrics::registry()
+        .gauge("homeroute_tasks", "Running tokio tasks, by subsystem.", &[("subsystem", subsystem)])
+        .track()
+}
+
+/// `tokio::spawn`, counted in `homeroute_tasks{subsystem}` while it runs.
+pub fn spawn<F>(subsystem: &'static str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let guard = track(subsystem);
+    tokio::spawn(async move {
+        let _guard = guard;
+        future.await
+    })
+}
+
+/// A broadcast receiver of `channel` fell behind and lost `skipped` events.
+pub fn record_lag(channel: &'static str, skipped: u64) {
+    warn!("Event channel {} lagged by {}", channel, skipped);
+    metrics::registry()
+        .counter(
+            "homeroute_event_lagged_total",
+            "Events dropped because a subscriber fell behind, by channel.",
+            &[("channel", channel)],
+        )
+        .add(skipped);
+}
+
+/// Refresh the process gauges (call from within the tokio runtime).
+pub fn refresh() {
+    let registry = metrics::registry();
+    if let Some(rss) = rss_bytes() {
+        registry
+            .gauge("homeroute_process_resident_memory_bytes", "Resident set size of the process.", &[])
+            .set(rss as f64);
+    }
+    if let Some(fds) = open_fds() {
+        registry
+            .gauge("homeroute_process_open_fds", "Open file descriptors of the process.", &[])
+            .set(fds as f64);
+    }
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        registry
+            .gauge("homeroute_tokio_alive_tasks", "Tokio tasks alive in the runtime.", &[])
+            .set(handle.metrics().num_alive_tasks() as f64);
+    }
+}
+
+/// VmRSS from `/proc/self/status`.
+fn rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+fn open_fds() -> Option<usize> {
+    Some(std::fs::read_dir("/proc/self/fd").ok()?.count())
+}
+
+/// Lowest value of each of the last `days` whole days before `now`, oldest
+/// first. `None` when a day has no data (e.g. the history is too short).
+pub fn daily_floors(points: &[Point], now: i64, days: usize) -> Option<Vec<f64>> {
+    (0..days as i64)
+        .rev()
+        .map(|day| {
+            let (from, to) = (now - (day + 1) * 86400, now - day * 86400);
+            points
+                .iter()
+                .filter(|p| p.ts >= from && p.ts < to)
+                .map(|p| p.min)
+                .min_by(f64::total_cmp)
+        })
+        .collect()
+}
+
+/// The daily floors went up every day, by `min_growth` (0.1 = 10 %) overall.
+/// The floor rather than the average, so that daily peaks don't count:
+/// memory that is never given back does.
+pub fn rising_floors(floors: &[f64], min_growth: f64) -> bool {
+    let (Some(first), Some(last)) = (floors.first(), floors.last()) else {
+        return false;
+    };
+    floors.len() >= 2
+        && floors.windows(2).all(|w| w[1] > w[0])
+        && *first > 0.0
+        && *last >= first * (1.0 + min_growth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(ts: i64, min: f64) -> Point {
+        Point { ts, min, max: min, avg: min, last: min, count: 1 }
+    }
+
+    #[test]
+    fn test_daily_floors_and_trend() {
+        let now = 10 * 86400;
+        // RSS creeping up by ~5 MB a day, with a daily peak on top
+        let points: Vec<Point> = (0..4 * 24)
+            .flat_map(|hour| {
+                let ts = now - 4 * 86400 + hour * 3600;
+                let base = 100e6 + (hour / 24) as f64 * 5e6;
+                [point(ts, base), point(ts + 1800, base + 40e6)]
+            })
+            .collect();
+        let floors = daily_floors(&points, now, 4).unwrap();
+        assert_eq!(floors, vec![100e6, 105e6, 110e6, 115e6]);
+        assert!(rising_floors(&floors, 0.1));
+        assert!(!rising_floors(&floors, 0.2));
+
+        // A flat day breaks the trend, a missing day gives no verdict
+        assert!(!rising_floors(&[100.0, 120.0, 120.0, 130.0], 0.1));
+        assert!(daily_floors(&points, now, 5).is_none());
+    }
+
+    #[test]
+    fn test_process_gauges() {
+        assert!(rss_bytes().unwrap() > 0);
+        assert!(open_fds().unwrap() > 0);
+
+        let _a = track("test-subsystem");
+        let samples = metrics::registry().samples();
+        assert!(samples.contains(&("homeroute_tasks{subsystem=\"test-subsystem\"}".to_string(), 1.0)));
+    }
+}
@@ -21,6 +21,18 @@ pub enum ServicePriorityLevel {
     Background,
 }
 
+/// A failure that made the supervisor restart a service.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestartEvent {
+    /// Unix milliseconds.
+    pub at: u64,
+    pub error: String,
+}
+
+/// Restart events kept per service.
+pub const RESTART_HISTORY_LEN: usize = 20;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ServiceStatus {
@@ -31,6 +43,51 @@ pub struct ServiceStatus {
     pub last_state_change: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// Services that must be running before this one starts.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Latest restarts, oldest first.
+    #[serde(default)]
+    pub restart_history: Vec<RestartEvent>,
+    /// Restart budget exhausted: the supervisor stopped restarting it, until
+    /// `retry_at` when set.
+    #[serde(default)]
+    pub circuit_open: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_at: Option<u64>,
+}
+
+impl ServiceStatus {
+    pub fn new(name: &str, state: ServiceState, priority: ServicePriorityLevel) -> Self {
+        Self {
+            name: name.to_string(),
+            state,
+            priority,
+            restart_count: 0,
+            last_state_change: now_millis(),
+            error: None,
+            depends_on: Vec::new(),
+            restart_history: Vec::new(),
+            circuit_open: false,
+            retry_at: None,
+        }
+    }
+}
+
+/// Action on a supervised service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceAction {
+    Start,
+    Stop,
+    Restart,
+}
+
+/// Command sent from the API to the supervisor.
+pub struct ServiceControlCommand {
+    pub name: String,
+    pub action: ServiceAction,
+    pub response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
 }
 
 pub type SharedServiceRegistry = Arc<RwLock<HashMap<String, ServiceStatus>>>;
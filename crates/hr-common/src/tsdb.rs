@@ -0,0 +1,274 @@
+//! Embedded time-series store for metric history (SQLite).
+//!
+//! Every sample is folded at write time into one bucket per tier
+//! (min/max/sum/count/last), so downsampling needs no background job:
+//! the fine tier is kept for days, the coarse ones for months. Queries
+//! read the finest tier that still covers the requested range.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+/// (bucket width, retention), both in seconds, finest first.
+pub const TIERS: &[(i64, i64)] = &[
+    (30, 2 * 86400),
+    (300, 35 * 86400),
+    (3600, 400 * 86400),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Point {
+    /// Bucket start (unix seconds).
+    pub ts: i64,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    /// Last sample of the bucket (the useful value for counters).
+    pub last: f64,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Range {
+    /// Bucket width of the tier the points come from.
+    pub step: i64,
+    pub points: Vec<Point>,
+}
+
+pub struct Tsdb {
+    conn: Mutex<Connection>,
+    ids: Mutex<HashMap<String, i64>>,
+}
+
+impl Tsdb {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS series (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE
+            );
+            CREATE TABLE IF NOT EXISTS points (
+                series INTEGER NOT NULL,
+                tier INTEGER NOT NULL,
+                ts INTEGER NOT NULL,
+                vmin REAL NOT NULL,
+                vmax REAL NOT NULL,
+                vsum REAL NOT NULL,
+                vcount INTEGER NOT NULL,
+                vlast REAL NOT NULL,
+                PRIMARY KEY (series, tier, ts)
+            ) WITHOUT ROWID;
+            CREATE INDEX IF NOT EXISTS idx_points_tier_ts ON points(tier, ts);",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            ids: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn series_id(&self, conn: &Connection, name: &str) -> rusqlite::Result<i64> {
+        if let Some(id) = self.ids.lock().unwrap().get(name) {
+            return Ok(*id);
+        }
+        conn.execute("INSERT OR IGNORE INTO series (name) VALUES (?1)", params![name])?;
+        let id = conn.query_row("SELECT id FROM series WHERE name = ?1", params![name], |r| r.get(0))?;
+        self.ids.lock().unwrap().insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    /// Record a sample of one series at the current time.
+    pub fn record(&self, series: &str, value: f64) -> anyhow::Result<()> {
+        self.record_batch(chrono::Utc::now().timestamp(), &[(series.to_string(), value)])
+    }
+
+    /// Record samples taken at `ts` (unix seconds) in one transaction.
+    pub fn record_batch(&self, ts: i64, samples: &[(String, f64)]) -> anyhow::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO points (series, tier, ts, vmin, vmax, vsum, vcount, vlast)
+                 VALUES (?1, ?2, ?3, ?4, ?4, ?4, 1, ?4)
+                 ON CONFLICT (series, tier, ts) DO UPDATE SET
+                    vmin = min(vmin, excluded.vmin),
+                    vmax = max(vmax, excluded.vmax),
+                    vsum = vsum + excluded.vsum,
+                    vcount = vcount + 1,
+                    vlast = excluded.vlast",
+            )?;
+            for (name, value) in samples {
+                if !value.is_finite() {
+                    continue;
+                }
+                let id = self.series_id(&tx, name)?;
+                for (tier, (step, _)) in TIERS.iter().enumerate() {
+                    stmt.execute(params![id, tier as i64, ts - ts.rem_euclid(*step), value])?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Points of `series` between `from` and `to` (unix seconds), from the
+    /// finest tier that covers `from` in at most `max_points` buckets.
+    pub fn query(&self, series: &str, from: i64, to: i64, max_points: usize) -> anyhow::Result<Range> {
+        let now = chrono::Utc::now().timestamp();
+        let span = (to - from).max(0);
+        let tier = TIERS
+            .iter()
+            .position(|(step, retention)| from >= now - retention && span / step <= max_points as i64)
+            .unwrap_or(TIERS.len() - 1);
+        let step = TIERS[tier].0;
+
+        let conn = self.conn.lock().unwrap();
+        let Some(id) = conn
+            .query_row("SELECT id FROM series WHERE name = ?1", params![series], |r| r.get::<_, i64>(0))
+            .optional()?
+        else {
+            return Ok(Range { step, points: Vec::new() });
+        };
+        let mut stmt = conn.prepare_cached(
+            "SELECT ts, vmin, vmax, vsum, vcount, vlast FROM points
+             WHERE series = ?1 AND tier = ?2 AND ts >= ?3 AND ts <= ?4
+             ORDER BY ts",
+        )?;
+        let points = stmt
+            .query_map(params![id, tier as i64, from - from.rem_euclid(step), to], |r| {
+                let count: i64 = r.get(4)?;
+                let sum: f64 = r.get(3)?;
+                Ok(Point {
+                    ts: r.get(0)?,
+                    min: r.get(1)?,
+                    max: r.get(2)?,
+                    avg: sum / count.max(1) as f64,
+                    last: r.get(5)?,
+                    count: count as u64,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Range { step, points })
+    }
+
+    /// Names of the recorded series starting with `prefix`.
+    pub fn series(&self, prefix: &str) -> anyhow::Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached("SELECT name FROM series WHERE substr(name, 1, length(?1)) = ?1 ORDER BY name")?;
+        let names = stmt
+            .query_map(params![prefix], |r| r.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(names)
+    }
+
+    /// Delete buckets past their tier's retention. Returns the number removed.
+    pub fn prune(&self, now: i64) -> anyhow::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let mut removed = 0;
+        for (tier, (_, retention)) in TIERS.iter().enumerate() {
+            removed += conn.execute(
+                "DELETE FROM points WHERE tier = ?1 AND ts < ?2",
+                params![tier as i64, now - retention],
+            )?;
+        }
+        Ok(removed)
+    }
+}
+
+/// Per-second increase between consecutive buckets of a counter series
+/// (a decrease is taken as a counter reset).
+pub fn rate(range: &Range) -> Vec<(i64, f64)> {
+    range
+        .points
+        .windows(2)
+        .map(|w| {
+            let elapsed = (w[1].ts - w[0].ts).max(1) as f64;
+            let delta = if w[1].last >= w[0].last { w[1].last - w[0].last } else { w[1].last };
+            (w[1].ts, delta / elapsed)
+        })
+        .collect()
+}
+
+static GLOBAL: OnceLock<Tsdb> = OnceLock::new();
+
+/// Open the process-wide store (once, at startup).
+pub fn init(path: &Path) -> anyhow::Result<&'static Tsdb> {
+    let tsdb = Tsdb::open(path)?;
+    Ok(GLOBAL.get_or_init(|| tsdb))
+}
+
+/// The process-wide store, if it could be opened.
+pub fn global() -> Option<&'static Tsdb> {
+    GLOBAL.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_downsampling_tiers() {
+        let db = Tsdb::open_in_memory().unwrap();
+        let now = chrono::Utc::now().timestamp();
+        let base = now - now.rem_euclid(3600) - 3600;
+        for i in 0..120 {
+            db.record_batch(base + i * 30, &[("temp".to_string(), i as f64)]).unwrap();
+        }
+
+        // One hour at the finest tier: 30s buckets
+        let fine = db.query("temp", base, base + 3599, 500).unwrap();
+        assert_eq!(fine.step, 30);
+        assert_eq!(fine.points.len(), 120);
+
+        // Same hour in at most 12 points: 5 minute buckets of 10 samples
+        let coarse = db.query("temp", base, base + 3599, 12).unwrap();
+        assert_eq!(coarse.step, 300);
+        assert_eq!(coarse.points.len(), 12);
+        assert_eq!(coarse.points[0].count, 10);
+        assert_eq!(coarse.points[0].min, 0.0);
+        assert_eq!(coarse.points[0].max, 9.0);
+        assert_eq!(coarse.points[0].avg, 4.5);
+        assert_eq!(coarse.points[0].last, 9.0);
+
+        let hourly = db.query("temp", base, base + 3599, 1).unwrap();
+        assert_eq!(hourly.points.len(), 1);
+        assert_eq!(hourly.points[0].count, 120);
+
+        assert!(db.query("missing", base, now, 100).unwrap().points.is_empty());
+        assert_eq!(db.series("te").unwrap(), vec!["temp"]);
+    }
+
+    #[test]
+    fn test_retention_and_rate() {
+        let db = Tsdb::open_in_memory().unwrap();
+        let now = 10 * 86400 * 40;
+        db.record_batch(now - 3 * 86400, &[("requests".to_string(), 1.0)]).unwrap();
+        db.record_batch(now, &[("requests".to_string(), 2.0)]).unwrap();
+        // The old sample leaves the 30s tier only
+        assert_eq!(db.prune(now).unwrap(), 1);
+
+        let range = Range {
+            step: 30,
+            points: [(0, 100.0), (30, 160.0), (60, 30.0)]
+                .iter()
+                .map(|(ts, last)| Point { ts: *ts, min: 0.0, max: 0.0, avg: 0.0, last: *last, count: 1 })
+                .collect(),
+        };
+        assert_eq!(rate(&range), vec![(30, 2.0), (60, 1.0)]);
+    }
+}
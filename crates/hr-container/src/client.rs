@@ -185,6 +185,7 @@ impl NspawnClient {
         // Remove .nspawn unit
         let unit_path = format!("{NSPAWN_UNIT_DIR}/{name}.nspawn");
         let _ = tokio::fs::remove_file(&unit_path).await;
+        Self::remove_limits(name).await;
 
         // Remove symlink from /var/lib/machines/ if it's a symlink (custom storage path)
         let machine_link = Path::new(DEFAULT_STORAGE).join(name);
@@ -196,6 +197,32 @@ impl NspawnClient {
         Ok(())
     }
 
+    /// Copy the rootfs of `name` (running or not) to `new_name`: a btrfs
+    /// snapshot when it is a subvolume, a reflink copy where the filesystem
+    /// allows it, a plain copy otherwise.
+    pub async fn clone_rootfs(name: &str, new_name: &str, storage_path: &Path) -> Result<()> {
+        let source = storage_path.join(name);
+        let target = storage_path.join(new_name);
+        if target.exists() {
+            anyhow::bail!("{} already exists", target.display());
+        }
+
+        let output = if crate::limits::is_subvolume(&source).await {
+            Command::new("btrfs").args(["subvolume", "snapshot"]).arg(&source).arg(&target).output().await
+        } else {
+            Command::new("cp").args(["-a", "--reflink=auto"]).arg(&source).arg(&target).output().await
+        }
+        .context("failed to copy rootfs")?;
+        if !output.status.success() {
+            let _ = tokio::fs::remove_dir_all(&target).await;
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("copying {} failed: {}", source.display(), stderr.trim());
+        }
+
+        info!(container = name, clone = new_name, "Rootfs cloned");
+        Ok(())
+    }
+
     /// List containers filtered by `hr-v2-` prefix.
     pub async fn list_containers() -> Result<Vec<NspawnContainerInfo>> {
         let output = Command::new("machinectl")
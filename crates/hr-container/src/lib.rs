@@ -1,4 +1,11 @@
 pub mod client;
+pub mod limits;
+pub mod preflight;
+pub mod pty;
 pub mod rootfs;
+pub mod template;
 
 pub use client::{NspawnClient, NspawnContainerInfo};
+pub use limits::{ContainerUsage, ResourceLimits};
+pub use preflight::HostPreflight;
+pub use template::{ContainerTemplate, Distro, TemplateCache};
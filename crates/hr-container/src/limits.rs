@@ -0,0 +1,296 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::client::NspawnClient;
+
+const UNIT_DIR: &str = "/etc/systemd/system";
+const CGROUP_ROOT: &str = "/sys/fs/cgroup/machine.slice";
+const DROP_IN: &str = "50-homeroute-limits.conf";
+
+/// Smallest memory limit accepted: below that systemd and the agent do not boot.
+pub const MIN_MEMORY_BYTES: u64 = 128 * 1024 * 1024;
+/// Smallest disk quota accepted: a bare rootfs is already ~400 MB.
+pub const MIN_DISK_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Resource limits of a container. Unset fields are unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceLimits {
+    /// CPU quota in percent of one core (200 = two cores).
+    #[serde(default)]
+    pub cpu_percent: Option<u32>,
+    /// `MemoryMax=` of the container unit.
+    #[serde(default)]
+    pub memory_bytes: Option<u64>,
+    /// btrfs qgroup limit on the rootfs subvolume.
+    #[serde(default)]
+    pub disk_bytes: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        self.cpu_percent.is_none() && self.memory_bytes.is_none() && self.disk_bytes.is_none()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.cpu_percent == Some(0) {
+            return Err("CPU quota must be at least 1%".to_string());
+        }
+        if self.memory_bytes.is_some_and(|m| m < MIN_MEMORY_BYTES) {
+            return Err(format!("Memory limit must be at least {} MiB", MIN_MEMORY_BYTES >> 20));
+        }
+        if self.disk_bytes.is_some_and(|d| d < MIN_DISK_BYTES) {
+            return Err(format!("Disk quota must be at least {} GiB", MIN_DISK_BYTES >> 30));
+        }
+        Ok(())
+    }
+}
+
+/// Resource usage of a running container, read from its cgroup and qgroup.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerUsage {
+    pub name: String,
+    /// Total CPU time consumed since the container started.
+    pub cpu_usage_usec: u64,
+    /// Quota actually enforced by the cgroup, in percent of one core.
+    pub cpu_quota_percent: Option<u32>,
+    pub memory_bytes: u64,
+    /// `memory.max` of the cgroup.
+    pub memory_max_bytes: Option<u64>,
+    /// Referenced bytes of the rootfs subvolume, when quotas are enabled.
+    pub disk_bytes: Option<u64>,
+    pub disk_max_bytes: Option<u64>,
+}
+
+/// Escape a name the way `systemd-escape` does for unit instances.
+pub fn systemd_escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for (i, b) in name.bytes().enumerate() {
+        match b {
+            b'/' => escaped.push('-'),
+            b'.' if i > 0 => escaped.push('.'),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b':' => escaped.push(b as char),
+            _ => escaped.push_str(&format!("\\x{b:02x}")),
+        }
+    }
+    escaped
+}
+
+/// systemd unit running the container under `machinectl start`.
+pub fn unit_name(name: &str) -> String {
+    format!("systemd-nspawn@{}.service", systemd_escape(name))
+}
+
+/// Contents of the unit drop-in enforcing CPU and memory limits.
+fn drop_in(limits: &ResourceLimits) -> String {
+    let mut content = String::from("# Managed by HomeRoute, do not edit.\n[Service]\n");
+    if let Some(cpu) = limits.cpu_percent {
+        content.push_str(&format!("CPUQuota={cpu}%\n"));
+    }
+    if let Some(memory) = limits.memory_bytes {
+        content.push_str(&format!("MemoryMax={memory}\n"));
+    }
+    content
+}
+
+/// Parse `btrfs qgroup show -rf --raw`: (referenced, max referenced).
+fn parse_qgroup(output: &str) -> Option<(u64, Option<u64>)> {
+    let mut lines = output.lines();
+    let header: Vec<&str> = lines.next()?.split_whitespace().collect();
+    let rfer = header.iter().position(|c| *c == "rfer")?;
+    let max_rfer = header.iter().position(|c| *c == "max_rfer")?;
+    let row: Vec<&str> = lines.find(|l| l.starts_with("0/"))?.split_whitespace().collect();
+    let used = row.get(rfer)?.parse().ok()?;
+    let max = row.get(max_rfer).and_then(|m| m.parse().ok());
+    Some((used, max))
+}
+
+/// Parse `cpu.max` ("max 100000" or "<quota> <period>") into percent of one core.
+fn parse_cpu_max(content: &str) -> Option<u32> {
+    let mut parts = content.split_whitespace();
+    let quota: u64 = parts.next()?.parse().ok()?;
+    let period: u64 = parts.next()?.parse().ok()?;
+    (period > 0).then(|| (quota * 100 / period) as u32)
+}
+
+fn read_cgroup(unit: &str, file: &str) -> Option<String> {
+    std::fs::read_to_string(Path::new(CGROUP_ROOT).join(unit).join(file)).ok()
+}
+
+async fn run(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .await
+        .with_context(|| format!("failed to run {program}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{program} {} failed: {}", args.join(" "), stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Whether `path` is the root of a btrfs subvolume.
+pub async fn is_subvolume(path: &Path) -> bool {
+    run("btrfs", &["subvolume", "show", &path.to_string_lossy()]).await.is_ok()
+}
+
+/// Create a container rootfs directory: a btrfs subvolume when the storage
+/// is on btrfs (so it can get a disk quota), a plain directory otherwise.
+pub async fn create_rootfs_dir(rootfs: &Path) -> Result<()> {
+    if rootfs.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = rootfs.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+        let fs_type = run("stat", &["-f", "-c", "%T", &parent.to_string_lossy()]).await.unwrap_or_default();
+        if fs_type.trim() == "btrfs" {
+            match run("btrfs", &["subvolume", "create", &rootfs.to_string_lossy()]).await {
+                Ok(_) => return Ok(()),
+                Err(e) => warn!(rootfs = %rootfs.display(), "Falling back to a plain directory: {e}"),
+            }
+        }
+    }
+    tokio::fs::create_dir_all(rootfs)
+        .await
+        .with_context(|| format!("failed to create {}", rootfs.display()))
+}
+
+impl NspawnClient {
+    /// Apply resource limits to a container: CPU and memory through a drop-in
+    /// on its `systemd-nspawn@` unit (also set live when it is running), disk
+    /// through a btrfs qgroup on its rootfs subvolume.
+    pub async fn apply_limits(name: &str, storage_path: &Path, limits: &ResourceLimits) -> Result<()> {
+        let unit = unit_name(name);
+        let dir = format!("{UNIT_DIR}/{unit}.d");
+        let path = format!("{dir}/{DROP_IN}");
+        if limits.cpu_percent.is_none() && limits.memory_bytes.is_none() {
+            let _ = tokio::fs::remove_file(&path).await;
+        } else {
+            tokio::fs::create_dir_all(&dir).await.with_context(|| format!("failed to create {dir}"))?;
+            tokio::fs::write(&path, drop_in(limits))
+                .await
+                .with_context(|| format!("failed to write {path}"))?;
+        }
+        run("systemctl", &["daemon-reload"]).await?;
+
+        if run("systemctl", &["is-active", "--quiet", &unit]).await.is_ok() {
+            let cpu = format!("CPUQuota={}", limits.cpu_percent.map(|c| format!("{c}%")).unwrap_or_default());
+            let memory = format!(
+                "MemoryMax={}",
+                limits.memory_bytes.map(|m| m.to_string()).unwrap_or_else(|| "infinity".to_string())
+            );
+            run("systemctl", &["set-property", "--runtime", &unit, &cpu, &memory]).await?;
+        }
+
+        let rootfs = storage_path.join(name);
+        match limits.disk_bytes {
+            Some(bytes) => {
+                if !is_subvolume(&rootfs).await {
+                    anyhow::bail!(
+                        "disk quota needs the rootfs {} to be a btrfs subvolume",
+                        rootfs.display()
+                    );
+                }
+                run("btrfs", &["quota", "enable", &storage_path.to_string_lossy()]).await?;
+                run("btrfs", &["qgroup", "limit", &bytes.to_string(), &rootfs.to_string_lossy()]).await?;
+            }
+            None => {
+                if is_subvolume(&rootfs).await {
+                    // Fails when quotas were never enabled: nothing to lift then
+                    let _ = run("btrfs", &["qgroup", "limit", "none", &rootfs.to_string_lossy()]).await;
+                }
+            }
+        }
+
+        info!(container = name, ?limits, "Resource limits applied");
+        Ok(())
+    }
+
+    /// Remove the limits drop-in of a deleted container.
+    pub async fn remove_limits(name: &str) {
+        let dir = format!("{UNIT_DIR}/{}.d", unit_name(name));
+        if tokio::fs::remove_file(format!("{dir}/{DROP_IN}")).await.is_ok() {
+            let _ = tokio::fs::remove_dir(&dir).await;
+            let _ = run("systemctl", &["daemon-reload"]).await;
+        }
+    }
+
+    /// Resource usage of a container, `None` when it is not running.
+    pub async fn usage(name: &str, storage_path: &Path) -> Option<ContainerUsage> {
+        let unit = unit_name(name);
+        let memory_bytes = read_cgroup(&unit, "memory.current")?.trim().parse().ok()?;
+        let cpu_usage_usec = read_cgroup(&unit, "cpu.stat")
+            .and_then(|s| {
+                s.lines()
+                    .find_map(|l| l.strip_prefix("usage_usec ").and_then(|v| v.trim().parse().ok()))
+            })
+            .unwrap_or(0);
+        let rootfs = storage_path.join(name);
+        let qgroup = run("btrfs", &["qgroup", "show", "-rf", "--raw", &rootfs.to_string_lossy()])
+            .await
+            .ok()
+            .and_then(|out| parse_qgroup(&out));
+        Some(ContainerUsage {
+            name: name.to_string(),
+            cpu_usage_usec,
+            cpu_quota_percent: read_cgroup(&unit, "cpu.max").and_then(|s| parse_cpu_max(&s)),
+            memory_bytes,
+            memory_max_bytes: read_cgroup(&unit, "memory.max").and_then(|s| s.trim().parse().ok()),
+            disk_bytes: qgroup.map(|(used, _)| used),
+            disk_max_bytes: qgroup.and_then(|(_, max)| max),
+        })
+    }
+
+    /// Resource usage of every running `hr-v2-` container.
+    pub async fn list_usage(storage_path: &Path) -> Vec<ContainerUsage> {
+        let mut usage = Vec::new();
+        for container in Self::list_containers().await.unwrap_or_default() {
+            if let Some(u) = Self::usage(&container.name, storage_path).await {
+                usage.push(u);
+            }
+        }
+        usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_name() {
+        assert_eq!(unit_name("hr-v2-my-app-dev"), r"systemd-nspawn@hr\x2dv2\x2dmy\x2dapp\x2ddev.service");
+        assert_eq!(systemd_escape(".hidden"), r"\x2ehidden");
+    }
+
+    #[test]
+    fn test_drop_in() {
+        let limits = ResourceLimits { cpu_percent: Some(150), memory_bytes: Some(1 << 30), disk_bytes: None };
+        assert_eq!(
+            drop_in(&limits),
+            "# Managed by HomeRoute, do not edit.\n[Service]\nCPUQuota=150%\nMemoryMax=1073741824\n"
+        );
+        assert!(limits.validate().is_ok());
+        assert!(ResourceLimits { memory_bytes: Some(1 << 20), ..Default::default() }.validate().is_err());
+        assert!(ResourceLimits { cpu_percent: Some(0), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_usage() {
+        let out = "qgroupid         rfer         excl     max_rfer \n\
+                   --------         ----         ----     -------- \n\
+                   0/257      524288000      4096   2147483648 \n";
+        assert_eq!(parse_qgroup(out), Some((524288000, Some(2147483648))));
+        let unlimited = "qgroupid rfer excl max_rfer\n-------- ---- ---- --------\n0/258 16384 16384 none\n";
+        assert_eq!(parse_qgroup(unlimited), Some((16384, None)));
+        assert_eq!(parse_cpu_max("max 100000\n"), None);
+        assert_eq!(parse_cpu_max("150000 100000\n"), Some(150));
+    }
+}
@@ -0,0 +1,124 @@
+//! What a host offers for receiving (or sending) a container, checked
+//! before a migration starts.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+/// State of a host as seen by a migration pre-flight.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostPreflight {
+    /// CPU architecture (`x86_64`, `aarch64`).
+    pub arch: String,
+    pub storage_path: String,
+    /// Space available on the filesystem of the storage path.
+    #[serde(default)]
+    pub free_bytes: Option<u64>,
+    /// `machinectl` and `systemd-nspawn` are installed.
+    #[serde(default)]
+    pub nspawn: bool,
+    /// Interface containers attach to (bridge or macvlan parent).
+    #[serde(default)]
+    pub network_device: Option<String>,
+    #[serde(default)]
+    pub network_ok: bool,
+    /// Size of the rootfs (and workspace) of the container asked about,
+    /// when it exists on this host.
+    #[serde(default)]
+    pub container_bytes: Option<u64>,
+}
+
+/// Interface of a network mode: `bridge:br0` → `br0`, `macvlan:eth0` → `eth0`.
+pub fn network_device(network_mode: &str) -> Option<&str> {
+    network_mode
+        .strip_prefix("bridge:")
+        .or_else(|| network_mode.strip_prefix("macvlan:"))
+        .filter(|device| !device.is_empty())
+}
+
+/// Check this host: storage, nspawn tools, the interface of `network_mode`
+/// and the size of `container` if it is already here.
+pub async fn probe(storage_path: &str, network_mode: Option<&str>, container: Option<&str>) -> HostPreflight {
+    let storage = Path::new(storage_path);
+    let network_device = network_mode.and_then(network_device).map(str::to_string);
+    let network_ok = match &network_device {
+        Some(device) => Path::new("/sys/class/net").join(device).exists(),
+        // Default nspawn networking (veth) needs nothing
+        None => true,
+    };
+    let container_bytes = match container {
+        Some(name) => disk_usage(&[storage.join(name), storage.join(format!("{name}-workspace"))]).await,
+        None => None,
+    };
+
+    HostPreflight {
+        arch: std::env::consts::ARCH.to_string(),
+        storage_path: storage_path.to_string(),
+        free_bytes: free_bytes(storage).await,
+        nspawn: succeeds("machinectl", &["--version"]).await && succeeds("systemd-nspawn", &["--version"]).await,
+        network_device,
+        network_ok,
+        container_bytes,
+    }
+}
+
+async fn succeeds(program: &str, args: &[&str]) -> bool {
+    Command::new(program).args(args).output().await.is_ok_and(|o| o.status.success())
+}
+
+/// Available bytes for `path`, or for its closest existing parent (the
+/// storage directory is created on first use).
+async fn free_bytes(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    let output = Command::new("df").args(["-B1", "--output=avail"]).arg(existing).output().await.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).lines().nth(1)?.trim().parse().ok()
+}
+
+/// Total size of the existing `paths`, None when none exists.
+async fn disk_usage(paths: &[std::path::PathBuf]) -> Option<u64> {
+    let existing: Vec<_> = paths.iter().filter(|p| p.exists()).collect();
+    if existing.is_empty() {
+        return None;
+    }
+    let output = Command::new("du").args(["-sbc"]).args(&existing).output().await.ok()?;
+    // The grand total is the last line: `<bytes>\ttotal`
+    String::from_utf8_lossy(&output.stdout).lines().last()?.split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_network_device() {
+        assert_eq!(network_device("bridge:br-lan"), Some("br-lan"));
+        assert_eq!(network_device("macvlan:enp3s0"), Some("enp3s0"));
+        assert_eq!(network_device("macvlan:"), None);
+        assert_eq!(network_device("veth"), None);
+    }
+
+    #[tokio::test]
+    async fn test_probe() {
+        let dir = std::env::temp_dir().join(format!("hr-preflight-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("app")).unwrap();
+        std::fs::write(dir.join("app/file"), vec![0u8; 10_000]).unwrap();
+
+        let storage = dir.to_string_lossy().to_string();
+        let report = probe(&storage, Some("bridge:hr-no-such-bridge"), Some("app")).await;
+        assert_eq!(report.arch, std::env::consts::ARCH);
+        assert!(report.free_bytes.is_some_and(|b| b > 0));
+        assert!(!report.network_ok);
+        assert!(report.container_bytes.is_some_and(|b| b >= 10_000));
+
+        let report = probe(&format!("{storage}/not-created-yet"), None, Some("missing")).await;
+        assert!(report.free_bytes.is_some());
+        assert!(report.network_ok);
+        assert_eq!(report.container_bytes, None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,197 @@
+//! Pseudo-terminals for the web terminal.
+//!
+//! The shell gets the slave side of the pty as its stdio and controlling
+//! terminal; the caller reads and writes the master side, and resizes it
+//! when the browser window changes (the kernel then sends SIGWINCH to the
+//! shell).
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::process::Stdio;
+
+use tokio::io::unix::AsyncFd;
+use tokio::process::{Child, Command};
+
+/// Size used until the browser reports its own.
+pub const DEFAULT_SIZE: WindowSize = WindowSize { cols: 80, rows: 24 };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowSize {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl WindowSize {
+    /// `cols`×`rows`, or the default when either is unknown (0).
+    pub fn or_default(cols: u16, rows: u16) -> Self {
+        if cols == 0 || rows == 0 { DEFAULT_SIZE } else { Self { cols, rows } }
+    }
+
+    fn winsize(self) -> libc::winsize {
+        libc::winsize { ws_row: self.rows, ws_col: self.cols, ws_xpixel: 0, ws_ypixel: 0 }
+    }
+}
+
+/// Master side of a pty with a process running on it.
+pub struct Pty {
+    master: AsyncFd<File>,
+}
+
+impl Pty {
+    /// Run `command` on a new pty. Its stdio is replaced by the pty.
+    pub fn spawn(mut command: Command, size: WindowSize) -> io::Result<(Self, Child)> {
+        let (master, slave) = open(size)?;
+        command
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave))
+            .kill_on_drop(true);
+        // SAFETY: only async-signal-safe calls between fork and exec.
+        unsafe {
+            command.pre_exec(|| {
+                // New session with the pty (now stdin) as controlling terminal
+                if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+        // The command, dropped here, held the last slave fds of this process:
+        // reads see the end of the session once the shell exits
+        drop(command);
+
+        set_nonblocking(&master)?;
+        let master = AsyncFd::new(File::from(master))?;
+        Ok((Self { master }, child))
+    }
+
+    /// Read output; 0 once the session is over.
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.master.readable().await?;
+            match guard.try_io(|master| master.get_ref().read(buf)) {
+                // Linux answers EIO once no process has the slave open
+                Ok(Err(e)) if e.raw_os_error() == Some(libc::EIO) => return Ok(0),
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Write input to the process.
+    pub async fn write_all(&self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            let mut guard = self.master.writable().await?;
+            match guard.try_io(|master| master.get_ref().write(data)) {
+                Ok(Ok(n)) => data = &data[n..],
+                Ok(Err(e)) => return Err(e),
+                Err(_would_block) => continue,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn resize(&self, size: WindowSize) -> io::Result<()> {
+        let winsize = size.winsize();
+        // SAFETY: `winsize` is a valid winsize that outlives the call.
+        let ret = unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &winsize) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Master and slave of a new pty, both close-on-exec (the child gets the
+/// slave through dup2, which clears the flag).
+fn open(size: WindowSize) -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut master = -1;
+    let mut slave = -1;
+    let winsize = size.winsize();
+    // SAFETY: the out pointers are valid, name and termios may be null.
+    let ret = unsafe { libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null(), &winsize) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: openpty succeeded, both fds are open and owned by nobody else.
+    let (master, slave) = unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+    for fd in [&master, &slave] {
+        // SAFETY: fcntl on an open fd.
+        if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok((master, slave))
+}
+
+fn set_nonblocking(fd: &OwnedFd) -> io::Result<()> {
+    // SAFETY: fcntl on an open fd.
+    let flags = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFL) };
+    // SAFETY: as above.
+    if flags < 0 || unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Login shell on this machine.
+pub fn host_shell() -> Command {
+    let mut command = Command::new("/bin/bash");
+    command.arg("-l");
+    with_terminal_env(&mut command);
+    command
+}
+
+/// Login shell inside a running nspawn container, entered through the
+/// namespaces of its leader process.
+pub async fn container_shell(container: &str) -> Result<Command, String> {
+    let output = Command::new("machinectl")
+        .args(["show", container, "--property=Leader", "--value"])
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run machinectl: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to get container PID: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let leader = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let mut command = Command::new("nsenter");
+    command.args(["-t", &leader, "-m", "-u", "-i", "-n", "-p", "--", "/bin/bash", "-l"]);
+    with_terminal_env(&mut command);
+    Ok(command)
+}
+
+fn with_terminal_env(command: &mut Command) {
+    command.env("TERM", "xterm-256color").env("HOME", "/root");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pty_session() {
+        let mut command = Command::new("/bin/sh");
+        command.args(["-c", "stty size; read line; echo got:$line"]);
+        let (pty, mut child) = Pty::spawn(command, WindowSize { cols: 100, rows: 30 }).unwrap();
+        pty.write_all(b"hello\n").await.unwrap();
+
+        let mut output = Vec::new();
+        let mut buf = [0u8; 1024];
+        loop {
+            let n = pty.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            output.extend_from_slice(&buf[..n]);
+        }
+        let output = String::from_utf8_lossy(&output);
+        assert!(output.contains("30 100"), "{output}");
+        assert!(output.contains("got:hello"), "{output}");
+        assert!(child.wait().await.unwrap().success());
+    }
+}
@@ -3,29 +3,41 @@ use std::path::Path;
 use tokio::process::Command;
 use tracing::{info, warn};
 
+use crate::template::Distro;
+
+pub const UBUNTU_MIRROR: &str = "http://archive.ubuntu.com/ubuntu";
+
 /// Bootstrap an Ubuntu 24.04 (Noble) rootfs using debootstrap.
+pub async fn bootstrap_ubuntu(container_name: &str, storage_path: &Path) -> Result<()> {
+    bootstrap(container_name, storage_path, Distro::Ubuntu, "noble", UBUNTU_MIRROR).await
+}
+
+/// Bootstrap a Debian-family rootfs (`suite` from `mirror`) using debootstrap.
 ///
 /// The rootfs is created at `{storage_path}/{container_name}/`.
 /// Post-bootstrap configuration:
 /// - Empty machine-id (regenerated on first boot)
 /// - systemd-networkd enabled
 /// - systemd-resolved disabled (uses static resolv.conf)
-pub async fn bootstrap_ubuntu(container_name: &str, storage_path: &Path) -> Result<()> {
+pub async fn bootstrap(container_name: &str, storage_path: &Path, distro: Distro, suite: &str, mirror: &str) -> Result<()> {
     let rootfs = storage_path.join(container_name);
 
-    info!(container = container_name, rootfs = %rootfs.display(), "Bootstrapping Ubuntu 24.04 rootfs");
+    info!(container = container_name, rootfs = %rootfs.display(), suite, "Bootstrapping rootfs");
 
     // Ensure parent directory exists
     tokio::fs::create_dir_all(storage_path).await
         .context("failed to create storage directory")?;
 
+    // btrfs subvolume when possible, so the container can get a disk quota
+    crate::limits::create_rootfs_dir(&rootfs).await?;
+
     // Run debootstrap
     let output = Command::new("debootstrap")
         .args([
             "--variant=minbase",
-            "noble",
+            suite,
             &rootfs.to_string_lossy(),
-            "http://archive.ubuntu.com/ubuntu",
+            mirror,
         ])
         .output()
         .await
@@ -37,31 +49,67 @@ pub async fn bootstrap_ubuntu(container_name: &str, storage_path: &Path) -> Resu
     }
 
     info!(container = container_name, "debootstrap complete, configuring rootfs");
+    configure(container_name, &rootfs, distro).await
+}
+
+/// Unpack a template tarball as the rootfs at `{storage_path}/{container_name}/`
+/// and prepare it to boot under nspawn.
+pub async fn extract(container_name: &str, storage_path: &Path, archive: &Path, distro: Distro) -> Result<()> {
+    let rootfs = storage_path.join(container_name);
+
+    info!(container = container_name, rootfs = %rootfs.display(), archive = %archive.display(), "Extracting template rootfs");
+
+    tokio::fs::create_dir_all(storage_path).await
+        .context("failed to create storage directory")?;
+    crate::limits::create_rootfs_dir(&rootfs).await?;
 
+    let output = Command::new("tar")
+        .args(["-xpf", &archive.to_string_lossy(), "--numeric-owner", "-C", &rootfs.to_string_lossy()])
+        .output()
+        .await
+        .context("failed to run tar")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("template extraction failed: {stderr}");
+    }
+
+    configure(container_name, &rootfs, distro).await
+}
+
+/// Configuration shared by every rootfs before its first boot.
+async fn configure(container_name: &str, rootfs: &Path, distro: Distro) -> Result<()> {
     // Post-bootstrap configuration
 
     // 1. Empty machine-id (will be regenerated on first boot)
     tokio::fs::write(rootfs.join("etc/machine-id"), "").await
         .context("failed to write machine-id")?;
 
+    // NixOS generates the rest of /etc at activation, see `template::nix_module`
+    if distro == Distro::Nixos {
+        return Ok(());
+    }
+
     // 1b. Set hostname to the container name
     tokio::fs::write(rootfs.join("etc/hostname"), format!("{container_name}\n")).await
         .context("failed to write hostname")?;
 
     // 2. Enable systemd-networkd for DHCP
-    let networkd_link = rootfs.join("etc/systemd/system/multi-user.target.wants/systemd-networkd.service");
-    if let Some(parent) = networkd_link.parent() {
-        tokio::fs::create_dir_all(parent).await.ok();
+    if distro.systemd() {
+        let networkd_link = rootfs.join("etc/systemd/system/multi-user.target.wants/systemd-networkd.service");
+        if let Some(parent) = networkd_link.parent() {
+            tokio::fs::create_dir_all(parent).await.ok();
+        }
+        let _ = tokio::fs::symlink(
+            "/lib/systemd/system/systemd-networkd.service",
+            &networkd_link,
+        ).await;
+
+        // 3. Mask systemd-resolved to prevent DNS interference
+        // (containers use static resolv.conf pointing to HomeRoute DNS)
+        let resolved_mask = rootfs.join("etc/systemd/system/systemd-resolved.service");
+        let _ = tokio::fs::symlink("/dev/null", &resolved_mask).await;
     }
-    let _ = tokio::fs::symlink(
-        "/lib/systemd/system/systemd-networkd.service",
-        &networkd_link,
-    ).await;
-
-    // 3. Mask systemd-resolved to prevent DNS interference
-    // (containers use static resolv.conf pointing to HomeRoute DNS)
-    let resolved_mask = rootfs.join("etc/systemd/system/systemd-resolved.service");
-    let _ = tokio::fs::symlink("/dev/null", &resolved_mask).await;
 
     // 4. Disable IPv6 (containers lack IPv6 routing, causes DNS failures in Node.js)
     let sysctl_dir = rootfs.join("etc/sysctl.d");
@@ -83,18 +131,31 @@ pub async fn bootstrap_ubuntu(container_name: &str, storage_path: &Path) -> Resu
 
     // 5. Install essential packages in the rootfs via chroot
     // (dbus is needed for machinectl shell, curl for runtime installs)
-    let setup_script = r#"
+    let (shell, setup_script) = match distro {
+        Distro::Alpine => {
+            // apk resolves through the rootfs resolv.conf
+            tokio::fs::write(rootfs.join("etc/resolv.conf"), "nameserver 10.0.0.254\nnameserver 8.8.8.8\n").await
+                .context("failed to write resolv.conf")?;
+            // No getty on the virtual console of a container
+            ("/bin/sh", r#"
+        apk add --no-cache -q openrc gcompat iproute2 curl ca-certificates
+        rc-update add networking default
+        sed -i 's/^tty/#tty/' /etc/inittab
+    "#)
+        }
+        _ => ("/bin/bash", r#"
         apt-get update -qq 2>/dev/null
         apt-get install -y -qq dbus systemd-sysv iproute2 curl ca-certificates e2fsprogs 2>/dev/null
         systemctl enable systemd-networkd 2>/dev/null || true
         systemctl mask systemd-resolved 2>/dev/null || true
         chattr +i /etc/resolv.conf 2>/dev/null || true
-    "#;
+    "#),
+    };
 
     // Use chroot to install packages in the rootfs
     let chroot_output = Command::new("chroot")
-        .arg(&rootfs)
-        .args(["/bin/bash", "-c", setup_script])
+        .arg(rootfs)
+        .args([shell, "-c", setup_script])
         .output()
         .await;
 
@@ -111,6 +172,6 @@ pub async fn bootstrap_ubuntu(container_name: &str, storage_path: &Path) -> Resu
         }
     }
 
-    info!(container = container_name, "Ubuntu 24.04 rootfs bootstrap complete");
+    info!(container = container_name, ?distro, "Rootfs configured");
     Ok(())
 }
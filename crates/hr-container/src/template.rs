@@ -0,0 +1,708 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::client::NspawnClient;
+
+/// Template used when none is requested: the rootfs HomeRoute always built.
+pub const DEFAULT_TEMPLATE: &str = "ubuntu-noble";
+
+/// PATH for commands entered with nsenter (the host's may not exist inside).
+const CONTAINER_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+const AGENT_UNIT: &str = r#"[Unit]
+Description=HomeRoute Agent
+After=network-online.target
+Wants=network-online.target
+
+[Service]
+ExecStart=/usr/local/bin/hr-agent
+Restart=always
+RestartSec=5
+Environment=RUST_LOG=info
+
+[Install]
+WantedBy=multi-user.target
+"#;
+
+const AGENT_OPENRC: &str = r#"#!/sbin/openrc-run
+description="HomeRoute Agent"
+command="/usr/local/bin/hr-agent"
+supervisor=supervise-daemon
+respawn_delay=5
+export RUST_LOG=info
+
+depend() {
+    need net
+}
+"#;
+
+/// Distribution of a template rootfs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Distro {
+    Ubuntu,
+    Debian,
+    Alpine,
+    Nixos,
+}
+
+impl Distro {
+    /// Whether the rootfs boots systemd (Alpine runs OpenRC).
+    pub fn systemd(self) -> bool {
+        !matches!(self, Self::Alpine)
+    }
+
+    /// Development containers need apt, glibc and systemd units
+    /// (code-server, Claude Code).
+    pub fn supports_dev(self) -> bool {
+        matches!(self, Self::Ubuntu | Self::Debian)
+    }
+}
+
+/// Where the rootfs of a template comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TemplateSource {
+    /// Built with debootstrap (Ubuntu and Debian).
+    Debootstrap { suite: String, mirror: String },
+    /// Downloaded once into the template cache. Without `sha256`, the
+    /// checksum of the first download is pinned.
+    Tarball {
+        url: String,
+        #[serde(default)]
+        sha256: Option<String>,
+    },
+}
+
+/// A user account created by the recipe.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateUser {
+    pub name: String,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub authorized_keys: Vec<String>,
+}
+
+/// Provisioning run in a fresh container of the template.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recipe {
+    /// Distribution packages (nixpkgs attributes on NixOS).
+    #[serde(default)]
+    pub packages: Vec<String>,
+    #[serde(default)]
+    pub users: Vec<TemplateUser>,
+    /// Shell commands run last, in order; the first failure aborts.
+    #[serde(default)]
+    pub commands: Vec<String>,
+    /// Run the HomeRoute agent as a service.
+    #[serde(default = "default_true")]
+    pub install_agent: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for Recipe {
+    fn default() -> Self {
+        Self { packages: Vec::new(), users: Vec::new(), commands: Vec::new(), install_agent: true }
+    }
+}
+
+/// A named base image with its provisioning recipe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerTemplate {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub distro: Distro,
+    pub source: TemplateSource,
+    #[serde(default)]
+    pub recipe: Recipe,
+    /// Shipped with HomeRoute, cannot be edited.
+    #[serde(default, skip_deserializing)]
+    pub builtin: bool,
+}
+
+fn is_name(s: &str, first: impl Fn(char) -> bool, rest: impl Fn(char) -> bool) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(first) && chars.all(rest)
+}
+
+fn is_package(s: &str) -> bool {
+    is_name(s, |c| c.is_ascii_alphanumeric(), |c| c.is_ascii_alphanumeric() || "._+-".contains(c))
+}
+
+fn is_account(s: &str) -> bool {
+    s.len() <= 32
+        && is_name(
+            s,
+            |c| c.is_ascii_lowercase() || c == '_',
+            |c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-',
+        )
+}
+
+fn is_url(s: &str) -> bool {
+    (s.starts_with("https://") || s.starts_with("http://")) && !s.contains(char::is_whitespace)
+}
+
+impl ContainerTemplate {
+    pub fn validate(&self) -> Result<(), String> {
+        let id_ok = (2..=40).contains(&self.id.len())
+            && is_name(&self.id, |c| c.is_ascii_lowercase(), |c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.');
+        if !id_ok {
+            return Err("Template id must be 2-40 lowercase letters, digits, dots or hyphens".to_string());
+        }
+        if self.name.trim().is_empty() {
+            return Err("Template name is required".to_string());
+        }
+        match &self.source {
+            TemplateSource::Debootstrap { suite, mirror } => {
+                if !matches!(self.distro, Distro::Ubuntu | Distro::Debian) {
+                    return Err("debootstrap only builds Ubuntu and Debian".to_string());
+                }
+                if !is_package(suite) || !is_url(mirror) {
+                    return Err("Invalid debootstrap suite or mirror".to_string());
+                }
+            }
+            TemplateSource::Tarball { url, sha256 } => {
+                if !is_url(url) {
+                    return Err("Tarball URL must be http(s)".to_string());
+                }
+                if let Some(sum) = sha256
+                    && !(sum.len() == 64 && sum.chars().all(|c| c.is_ascii_hexdigit()))
+                {
+                    return Err("sha256 must be 64 hex digits".to_string());
+                }
+            }
+        }
+        if let Some(p) = self.recipe.packages.iter().find(|p| !is_package(p)) {
+            return Err(format!("Invalid package name: {p}"));
+        }
+        for user in &self.recipe.users {
+            if !is_account(&user.name) || user.name == "root" {
+                return Err(format!("Invalid user name: {}", user.name));
+            }
+            if let Some(g) = user.groups.iter().find(|g| !is_account(g)) {
+                return Err(format!("Invalid group name: {g}"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Templates shipped with HomeRoute.
+pub fn builtin_templates() -> Vec<ContainerTemplate> {
+    let base = |id: &str, name: &str, description: &str, distro, source, packages: &[&str]| ContainerTemplate {
+        id: id.to_string(),
+        name: name.to_string(),
+        description: description.to_string(),
+        distro,
+        source,
+        recipe: Recipe { packages: packages.iter().map(|p| p.to_string()).collect(), ..Recipe::default() },
+        builtin: true,
+    };
+    vec![
+        base(
+            DEFAULT_TEMPLATE,
+            "Ubuntu 24.04 LTS",
+            "Debootstrap minbase, image par defaut des applications",
+            Distro::Ubuntu,
+            TemplateSource::Debootstrap { suite: "noble".to_string(), mirror: crate::rootfs::UBUNTU_MIRROR.to_string() },
+            &["curl", "ca-certificates"],
+        ),
+        base(
+            "debian-bookworm",
+            "Debian 12",
+            "Debootstrap minbase",
+            Distro::Debian,
+            TemplateSource::Debootstrap { suite: "bookworm".to_string(), mirror: "http://deb.debian.org/debian".to_string() },
+            &["curl", "ca-certificates"],
+        ),
+        base(
+            "alpine-3.20",
+            "Alpine Linux 3.20",
+            "Mini rootfs avec OpenRC et gcompat (production uniquement)",
+            Distro::Alpine,
+            TemplateSource::Tarball {
+                url: "https://dl-cdn.alpinelinux.org/alpine/v3.20/releases/x86_64/alpine-minirootfs-3.20.3-x86_64.tar.gz".to_string(),
+                sha256: None,
+            },
+            &[],
+        ),
+        base(
+            "nixos-24.05",
+            "NixOS 24.05",
+            "Image conteneur Hydra, recette appliquee par nixos-rebuild (production uniquement)",
+            Distro::Nixos,
+            TemplateSource::Tarball {
+                url: "https://hydra.nixos.org/job/nixos/release-24.05/nixos.lxdContainerImage.x86_64-linux/latest/download-by-type/file/system-tarball".to_string(),
+                sha256: None,
+            },
+            &["curl"],
+        ),
+    ]
+}
+
+// ── Tarball cache ────────────────────────────────────────────────
+
+/// Downloaded template tarballs, each with its pinned sha256.
+pub struct TemplateCache {
+    dir: PathBuf,
+}
+
+impl TemplateCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn archive_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.tar"))
+    }
+
+    fn pin_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.sha256"))
+    }
+
+    /// Checksum of the cached tarball of a template, if downloaded.
+    pub fn cached(&self, id: &str) -> Option<String> {
+        if !self.archive_path(id).exists() {
+            return None;
+        }
+        std::fs::read_to_string(self.pin_path(id)).ok().map(|s| s.trim().to_string())
+    }
+
+    /// Cached tarball of a template and its checksum, downloaded when
+    /// missing or corrupt. `refresh` drops a pinned checksum (not one set
+    /// in the template) to follow a moving URL.
+    pub async fn fetch(&self, template: &ContainerTemplate, refresh: bool) -> Result<(PathBuf, String)> {
+        let TemplateSource::Tarball { url, sha256 } = &template.source else {
+            anyhow::bail!("template {} is not built from a tarball", template.id);
+        };
+        let archive = self.archive_path(&template.id);
+        let pin = self.pin_path(&template.id);
+        let pinned = if refresh { None } else { self.cached(&template.id) };
+        let expected = sha256.clone().map(|s| s.to_lowercase()).or(pinned);
+
+        if archive.exists() && !refresh {
+            let sum = sha256_file(&archive).await?;
+            if expected.as_ref().is_none_or(|e| *e == sum) {
+                tokio::fs::write(&pin, &sum).await.context("failed to pin template checksum")?;
+                return Ok((archive, sum));
+            }
+            warn!(template = %template.id, "Cached template tarball is corrupt, downloading it again");
+        }
+
+        tokio::fs::create_dir_all(&self.dir).await.context("failed to create template cache")?;
+        let part = self.dir.join(format!("{}.tar.part", template.id));
+        info!(template = %template.id, url, "Downloading template tarball");
+        let output = Command::new("curl")
+            .args(["-fsSL", "--retry", "3", "-o", &part.to_string_lossy(), url])
+            .output()
+            .await
+            .context("failed to run curl")?;
+        if !output.status.success() {
+            let _ = tokio::fs::remove_file(&part).await;
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("download of {url} failed: {}", stderr.trim());
+        }
+
+        let sum = sha256_file(&part).await?;
+        if let Some(expected) = expected.filter(|e| *e != sum) {
+            let _ = tokio::fs::remove_file(&part).await;
+            anyhow::bail!("checksum mismatch for template {}: expected {expected}, got {sum}", template.id);
+        }
+        tokio::fs::rename(&part, &archive).await.context("failed to store template tarball")?;
+        tokio::fs::write(&pin, &sum).await.context("failed to pin template checksum")?;
+        info!(template = %template.id, sha256 = %sum, "Template tarball cached");
+        Ok((archive, sum))
+    }
+
+    /// Drop the cached tarball of a template.
+    pub async fn remove(&self, id: &str) {
+        let _ = tokio::fs::remove_file(self.archive_path(id)).await;
+        let _ = tokio::fs::remove_file(self.pin_path(id)).await;
+    }
+}
+
+async fn sha256_file(path: &Path) -> Result<String> {
+    let output = Command::new("sha256sum")
+        .arg(path)
+        .output()
+        .await
+        .context("failed to run sha256sum")?;
+    if !output.status.success() {
+        anyhow::bail!("sha256sum {} failed", path.display());
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .map(str::to_string)
+        .context("empty sha256sum output")
+}
+
+// ── Provisioning ─────────────────────────────────────────────────
+
+/// Shell command installing packages, `None` when declarative (NixOS).
+fn install_command(distro: Distro, packages: &[String]) -> Option<String> {
+    if packages.is_empty() {
+        return None;
+    }
+    let packages = packages.join(" ");
+    match distro {
+        Distro::Ubuntu | Distro::Debian => Some(format!(
+            "export DEBIAN_FRONTEND=noninteractive && apt-get update -qq && apt-get install -y -qq {packages}"
+        )),
+        Distro::Alpine => Some(format!("apk add --no-cache -q {packages}")),
+        Distro::Nixos => None,
+    }
+}
+
+/// Shell command creating a user (idempotent) and adding it to its groups.
+fn user_command(distro: Distro, user: &TemplateUser) -> String {
+    let name = &user.name;
+    let mut cmd = match distro {
+        Distro::Alpine => format!("id -u {name} >/dev/null 2>&1 || adduser -D -s /bin/sh {name}"),
+        _ => format!("id -u {name} >/dev/null 2>&1 || useradd -m -s /bin/bash {name}"),
+    };
+    for group in &user.groups {
+        match distro {
+            Distro::Alpine => cmd.push_str(&format!(" && addgroup {name} {group}")),
+            _ => cmd.push_str(&format!(" && usermod -aG {group} {name}")),
+        }
+    }
+    cmd
+}
+
+fn nix_str(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace("${", "\\${"))
+}
+
+fn nix_list(items: impl IntoIterator<Item = String>) -> String {
+    let items: Vec<String> = items.into_iter().collect();
+    format!("[ {} ]", items.join(" "))
+}
+
+/// NixOS module declaring the recipe (and the agent service).
+fn nix_module(recipe: &Recipe) -> String {
+    let mut module = String::from("# Managed by HomeRoute, do not edit.\n{ pkgs, ... }:\n{\n");
+    module.push_str("  networking.nameservers = [ \"10.0.0.254\" \"8.8.8.8\" ];\n");
+    module.push_str("  networking.enableIPv6 = false;\n");
+    // Runs the (glibc, non-Nix) agent binary
+    module.push_str("  programs.nix-ld.enable = true;\n");
+    module.push_str(&format!(
+        "  environment.systemPackages = with pkgs; {};\n",
+        nix_list(recipe.packages.iter().cloned())
+    ));
+    for user in &recipe.users {
+        module.push_str(&format!(
+            "  users.users.{} = {{ isNormalUser = true; extraGroups = {}; openssh.authorizedKeys.keys = {}; }};\n",
+            user.name,
+            nix_list(user.groups.iter().map(|g| nix_str(g))),
+            nix_list(user.authorized_keys.iter().map(|k| nix_str(k))),
+        ));
+    }
+    if recipe.install_agent {
+        module.push_str(concat!(
+            "  systemd.services.hr-agent = {\n",
+            "    description = \"HomeRoute Agent\";\n",
+            "    after = [ \"network-online.target\" ];\n",
+            "    wants = [ \"network-online.target\" ];\n",
+            "    wantedBy = [ \"multi-user.target\" ];\n",
+            "    environment.RUST_LOG = \"info\";\n",
+            "    serviceConfig = { ExecStart = \"/usr/local/bin/hr-agent\"; Restart = \"always\"; RestartSec = 5; };\n",
+            "  };\n",
+        ));
+    }
+    module.push_str("}\n");
+    module
+}
+
+/// Name of the container's network interface.
+fn interface_name(network_mode: &str) -> String {
+    match network_mode.strip_prefix("macvlan:") {
+        // nspawn prefixes the host interface, within IFNAMSIZ
+        Some(iface) => format!("mv-{iface}").chars().take(15).collect(),
+        None => "host0".to_string(),
+    }
+}
+
+impl NspawnClient {
+    /// Create and start a container from a template: debootstrap or
+    /// unpack its cached tarball, write the .nspawn unit and network config,
+    /// start it. The recipe runs separately, see `provision`.
+    pub async fn create_from_template(
+        name: &str,
+        storage_path: &Path,
+        network_mode: &str,
+        with_workspace: bool,
+        template: &ContainerTemplate,
+        cache: &TemplateCache,
+    ) -> Result<()> {
+        info!(container = name, template = %template.id, network_mode, with_workspace, "Creating nspawn container from template");
+
+        match &template.source {
+            TemplateSource::Debootstrap { suite, mirror } => {
+                crate::rootfs::bootstrap(name, storage_path, template.distro, suite, mirror).await?;
+            }
+            TemplateSource::Tarball { .. } => {
+                let (archive, _) = cache.fetch(template, false).await?;
+                crate::rootfs::extract(name, storage_path, &archive, template.distro).await?;
+            }
+        }
+
+        if with_workspace {
+            Self::create_workspace(name, storage_path).await?;
+        }
+        Self::write_nspawn_unit(name, storage_path, network_mode, with_workspace).await?;
+        Self::write_template_network_config(name, storage_path, network_mode, template.distro).await?;
+        Self::start_container(name).await?;
+        Self::wait_up(name, template.distro).await;
+
+        info!(container = name, template = %template.id, "Nspawn container created and running");
+        Ok(())
+    }
+
+    /// Network configuration of a template rootfs (NixOS: by its module).
+    async fn write_template_network_config(name: &str, storage_path: &Path, network_mode: &str, distro: Distro) -> Result<()> {
+        match distro {
+            Distro::Ubuntu | Distro::Debian => Self::write_network_config(name, storage_path).await,
+            Distro::Nixos => Ok(()),
+            Distro::Alpine => {
+                let rootfs = storage_path.join(name);
+                let iface = interface_name(network_mode);
+                tokio::fs::write(
+                    rootfs.join("etc/network/interfaces"),
+                    format!("auto lo\niface lo inet loopback\n\nauto {iface}\niface {iface} inet dhcp\n"),
+                )
+                .await
+                .context("failed to write network interfaces")?;
+                tokio::fs::write(rootfs.join("etc/resolv.conf"), "nameserver 10.0.0.254\nnameserver 8.8.8.8\noptions timeout:2 attempts:3\n")
+                    .await
+                    .context("failed to write resolv.conf")?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Run a shell script in a container of any template: `machinectl
+    /// shell` needs systemd inside, OpenRC containers are entered with nsenter.
+    pub async fn exec_sh(container: &str, distro: Distro, script: &str) -> Result<String> {
+        let output = if distro.systemd() {
+            Command::new("machinectl")
+                .args(["shell", container, "/bin/sh", "-c", script])
+                .output()
+                .await
+                .context("failed to run machinectl shell")?
+        } else {
+            let leader = Command::new("machinectl")
+                .args(["show", "-p", "Leader", "--value", container])
+                .output()
+                .await
+                .context("failed to run machinectl show")?;
+            let leader = String::from_utf8_lossy(&leader.stdout).trim().to_string();
+            if leader.is_empty() {
+                anyhow::bail!("container {container} is not running");
+            }
+            Command::new("nsenter")
+                .args(["--target", &leader, "--mount", "--uts", "--ipc", "--net", "--pid", "--", "/bin/sh", "-c", script])
+                .env("PATH", CONTAINER_PATH)
+                .output()
+                .await
+                .context("failed to run nsenter")?
+        };
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("`{script}` failed in {container}: {}", stderr.trim());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    async fn exec_sh_with_retry(container: &str, distro: Distro, script: &str, max_retries: u32) -> Result<String> {
+        let mut attempt = 1;
+        loop {
+            match Self::exec_sh(container, distro, script).await {
+                Ok(output) => return Ok(output),
+                Err(e) if attempt >= max_retries => return Err(e),
+                Err(e) => {
+                    warn!(container, attempt, max_retries, "Command failed, retrying in 3s: {e}");
+                    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Wait (up to 30s) for a container to have a network interface up.
+    async fn wait_up(name: &str, distro: Distro) {
+        for _ in 0..30 {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            if let Ok(out) = Self::exec_sh(name, distro, "ip link show").await
+                && out.contains("state UP")
+            {
+                return;
+            }
+        }
+        warn!(container = name, "Container network not ready after 30s, proceeding anyway");
+    }
+
+    /// Wait for name resolution inside a container of any template.
+    pub async fn wait_online(container: &str, distro: Distro, timeout_secs: u32) -> Result<()> {
+        for i in 0..timeout_secs {
+            if Self::exec_sh(container, distro, "getent hosts archive.ubuntu.com").await.is_ok() {
+                info!(container, elapsed_secs = i + 1, "Network connectivity confirmed");
+                return Ok(());
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+        anyhow::bail!("no network connectivity in {container} after {timeout_secs}s")
+    }
+
+    /// Install the agent (pushed to `/usr/local/bin/hr-agent`) as a service
+    /// and start it. On NixOS the service is part of the recipe module.
+    pub async fn install_agent_service(name: &str, storage_path: &Path, distro: Distro) -> Result<()> {
+        let rootfs = storage_path.join(name);
+        let (path, content, mode, enable) = match distro {
+            Distro::Ubuntu | Distro::Debian => (
+                "etc/systemd/system/hr-agent.service",
+                AGENT_UNIT,
+                0o644,
+                "systemctl daemon-reload && systemctl enable --now hr-agent",
+            ),
+            Distro::Alpine => (
+                "etc/init.d/hr-agent",
+                AGENT_OPENRC,
+                0o755,
+                "rc-update add hr-agent default && rc-service hr-agent start",
+            ),
+            Distro::Nixos => return Ok(()),
+        };
+        let target = rootfs.join(path);
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await.ok();
+        }
+        tokio::fs::write(&target, content)
+            .await
+            .with_context(|| format!("failed to write {}", target.display()))?;
+        {
+            use std::os::unix::fs::PermissionsExt;
+            tokio::fs::set_permissions(&target, std::fs::Permissions::from_mode(mode)).await?;
+        }
+        Self::exec_sh(name, distro, enable).await?;
+        Ok(())
+    }
+
+    /// Run the recipe of a template in its freshly started container:
+    /// packages, users, then commands (NixOS: module + `nixos-rebuild switch`).
+    pub async fn provision(name: &str, storage_path: &Path, template: &ContainerTemplate) -> Result<()> {
+        let distro = template.distro;
+        let recipe = &template.recipe;
+        let rootfs = storage_path.join(name);
+        info!(container = name, template = %template.id, "Provisioning container");
+
+        if distro == Distro::Nixos {
+            let nixos = rootfs.join("etc/nixos");
+            tokio::fs::create_dir_all(&nixos).await.context("failed to create /etc/nixos")?;
+            tokio::fs::write(nixos.join("homeroute.nix"), nix_module(recipe))
+                .await
+                .context("failed to write homeroute.nix")?;
+            // The image configuration is kept and imported next to ours
+            let config = nixos.join("configuration.nix");
+            let current = tokio::fs::read_to_string(&config).await.unwrap_or_default();
+            if !current.contains("./homeroute.nix") {
+                if !current.is_empty() {
+                    tokio::fs::rename(&config, nixos.join("image.nix")).await.context("failed to keep image.nix")?;
+                }
+                let imports = if current.is_empty() { "./homeroute.nix" } else { "./image.nix ./homeroute.nix" };
+                tokio::fs::write(&config, format!("{{ imports = [ {imports} ]; }}\n"))
+                    .await
+                    .context("failed to write configuration.nix")?;
+            }
+            Self::exec_sh_with_retry(name, distro, "nixos-rebuild switch", 3).await?;
+        } else {
+            if let Some(cmd) = install_command(distro, &recipe.packages) {
+                Self::exec_sh_with_retry(name, distro, &cmd, 3).await?;
+            }
+            for user in &recipe.users {
+                Self::exec_sh(name, distro, &user_command(distro, user)).await?;
+                if !user.authorized_keys.is_empty() {
+                    let ssh_dir = rootfs.join("home").join(&user.name).join(".ssh");
+                    tokio::fs::create_dir_all(&ssh_dir).await.ok();
+                    tokio::fs::write(ssh_dir.join("authorized_keys"), user.authorized_keys.join("\n") + "\n")
+                        .await
+                        .context("failed to write authorized_keys")?;
+                    let chown = format!("chown -R {0}:{0} /home/{0}/.ssh && chmod 700 /home/{0}/.ssh", user.name);
+                    Self::exec_sh(name, distro, &chown).await?;
+                }
+            }
+        }
+
+        for command in &recipe.commands {
+            Self::exec_sh(name, distro, command).await?;
+        }
+        info!(container = name, template = %template.id, "Container provisioned");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_templates() {
+        for template in builtin_templates() {
+            assert!(template.validate().is_ok(), "{}", template.id);
+        }
+        let mut template = builtin_templates().remove(0);
+        template.source = TemplateSource::Tarball { url: "ftp://example.com/a.tar".to_string(), sha256: None };
+        assert!(template.validate().is_err());
+        template.source = TemplateSource::Tarball { url: "https://example.com/a.tar".to_string(), sha256: Some("abc".to_string()) };
+        assert!(template.validate().is_err());
+        template.source = TemplateSource::Tarball { url: "https://example.com/a.tar".to_string(), sha256: None };
+        template.recipe.packages.push("curl; rm -rf /".to_string());
+        assert!(template.validate().is_err());
+    }
+
+    #[test]
+    fn test_recipe_commands() {
+        let user = TemplateUser { name: "dev".to_string(), groups: vec!["wheel".to_string()], ..Default::default() };
+        assert_eq!(
+            user_command(Distro::Alpine, &user),
+            "id -u dev >/dev/null 2>&1 || adduser -D -s /bin/sh dev && addgroup dev wheel"
+        );
+        assert_eq!(
+            install_command(Distro::Alpine, &["curl".to_string(), "git".to_string()]).as_deref(),
+            Some("apk add --no-cache -q curl git")
+        );
+        assert_eq!(install_command(Distro::Nixos, &["curl".to_string()]), None);
+        assert_eq!(interface_name("macvlan:enp7s0f0"), "mv-enp7s0f0");
+        assert_eq!(interface_name("bridge:br-lan"), "host0");
+    }
+
+    #[test]
+    fn test_nix_module() {
+        let recipe = Recipe {
+            packages: vec!["git".to_string()],
+            users: vec![TemplateUser {
+                name: "dev".to_string(),
+                groups: vec!["wheel".to_string()],
+                authorized_keys: vec!["ssh-ed25519 AAAA \"x\" ${y}".to_string()],
+            }],
+            ..Recipe::default()
+        };
+        let module = nix_module(&recipe);
+        assert!(module.contains("environment.systemPackages = with pkgs; [ git ];"));
+        assert!(module.contains(r#"openssh.authorizedKeys.keys = [ "ssh-ed25519 AAAA \"x\" \${y}" ];"#));
+        assert!(module.contains("systemd.services.hr-agent"));
+    }
+}
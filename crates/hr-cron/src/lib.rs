@@ -0,0 +1,340 @@
+//! Scheduled jobs of the apps, run centrally: each app declares commands
+//! with a cron schedule, HomeRoute execs them in its container through the
+//! registry (locally via machinectl, on remote hosts via the host agent),
+//! enforces the timeout and concurrency policy, and keeps the run history.
+
+pub mod schedule;
+pub mod scheduler;
+pub mod store;
+
+pub use schedule::Schedule;
+pub use store::{ConcurrencyPolicy, CronJob, CronRun, CronStore, JobSpec, SaveJob};
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::task::AbortHandle;
+use tracing::{info, warn};
+
+use crate::store::{
+    STATUS_CANCELLED, STATUS_FAILED, STATUS_RUNNING, STATUS_SKIPPED, STATUS_SUCCEEDED, STATUS_TIMED_OUT,
+};
+
+/// Output kept per stream and run; the tail is kept.
+const MAX_OUTPUT: usize = 16 * 1024;
+
+/// Extra wait for a remote result past the job timeout.
+const REMOTE_GRACE: Duration = Duration::from_secs(5);
+
+/// Result of a command run in an app container.
+#[derive(Debug, Clone, Default)]
+pub struct ExecOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Runs a job command in the container of an app.
+pub trait JobRunner: Send + Sync + 'static {
+    /// Run `command` with a shell; dropping the future abandons it.
+    fn exec(&self, app_id: &str, command: &str, timeout: Duration)
+    -> impl Future<Output = Result<ExecOutput, String>> + Send;
+}
+
+/// Local apps run through `machinectl shell`, killed with the future;
+/// remote ones through the host agent, which keeps running them past the
+/// timeout.
+impl JobRunner for hr_registry::AgentRegistry {
+    async fn exec(&self, app_id: &str, command: &str, timeout: Duration) -> Result<ExecOutput, String> {
+        let app = self.get_application(app_id).await.ok_or("Application not found")?;
+        if app.host_id == "local" {
+            let out = tokio::process::Command::new("machinectl")
+                .args(["shell", &app.container_name, "/bin/bash", "-c", command])
+                .kill_on_drop(true)
+                .output()
+                .await
+                .map_err(|e| e.to_string())?;
+            return Ok(ExecOutput {
+                success: out.status.success(),
+                stdout: String::from_utf8_lossy(&out.stdout).to_string(),
+                stderr: String::from_utf8_lossy(&out.stderr).to_string(),
+            });
+        }
+        // nspawn hosts wrap the command in `bash -c` themselves, LXC hosts exec it as is
+        let argv = if app.container_name.starts_with("hr-v2-") {
+            vec![command.to_string()]
+        } else {
+            vec!["/bin/bash".to_string(), "-c".to_string(), command.to_string()]
+        };
+        let (success, stdout, stderr) = self
+            .exec_in_remote_container_timeout(&app.host_id, &app.container_name, argv, timeout + REMOTE_GRACE)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(ExecOutput { success, stdout, stderr })
+    }
+}
+
+/// What started a run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    Schedule,
+    Manual,
+}
+
+impl Trigger {
+    fn as_str(self) -> &'static str {
+        match self {
+            Trigger::Schedule => "schedule",
+            Trigger::Manual => "manual",
+        }
+    }
+}
+
+/// Outcome of `Cron::trigger`, with the id of the run recorded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Triggered {
+    Started(i64),
+    /// Previous run still going and the policy is `forbid`.
+    Skipped(i64),
+}
+
+struct ActiveRun {
+    run_id: i64,
+    abort: AbortHandle,
+}
+
+/// Shared scheduler handle: the API manages jobs and triggers runs, the
+/// supervised `cron` service fires them on schedule.
+pub struct Cron<R: JobRunner> {
+    store: Arc<CronStore>,
+    runner: Arc<R>,
+    /// Runs in flight, per job id.
+    active: Mutex<HashMap<String, Vec<ActiveRun>>>,
+}
+
+pub type SharedCron = Arc<Cron<hr_registry::AgentRegistry>>;
+
+impl<R: JobRunner> Cron<R> {
+    /// Runs left open by a previous process are marked interrupted.
+    pub fn new(store: CronStore, runner: Arc<R>) -> Self {
+        match store.interrupt_running(chrono::Utc::now().timestamp_millis()) {
+            Ok(0) => {}
+            Ok(n) => info!("{} cron runs were interrupted by the restart", n),
+            Err(e) => warn!("Failed to close interrupted cron runs: {}", e),
+        }
+        Self { store: Arc::new(store), runner, active: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn store(&self) -> Arc<CronStore> {
+        self.store.clone()
+    }
+
+    /// Runs of `job_id` in flight.
+    pub fn running(&self, job_id: &str) -> usize {
+        self.active.lock().unwrap().get(job_id).map_or(0, Vec::len)
+    }
+
+    /// Start a run of `job`, applying its concurrency policy.
+    pub fn trigger(self: &Arc<Self>, job: &CronJob, trigger: Trigger) -> anyhow::Result<Triggered> {
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut active = self.active.lock().unwrap();
+        let runs = active.entry(job.id.clone()).or_default();
+        if !runs.is_empty() {
+            match job.spec.concurrency {
+                ConcurrencyPolicy::Allow => {}
+                ConcurrencyPolicy::Forbid => {
+                    let run_id = self.store.start_run(job, trigger.as_str(), STATUS_SKIPPED, now)?;
+                    count_run(STATUS_SKIPPED);
+                    return Ok(Triggered::Skipped(run_id));
+                }
+                ConcurrencyPolicy::Replace => {
+                    for run in runs.drain(..) {
+                        self.cancel_run(run, "Replaced by a newer run", now);
+                    }
+                }
+            }
+        }
+        let run_id = self.store.start_run(job, trigger.as_str(), STATUS_RUNNING, now)?;
+        let cron = self.clone();
+        let job = job.clone();
+        let task = tokio::spawn(async move { cron.execute(job, run_id).await });
+        runs.push(ActiveRun { run_id, abort: task.abort_handle() });
+        Ok(Triggered::Started(run_id))
+    }
+
+    async fn execute(self: Arc<Self>, job: CronJob, run_id: i64) {
+        let timeout = Duration::from_secs(job.spec.timeout_secs as u64);
+        let result =
+            tokio::time::timeout(timeout, self.runner.exec(&job.app_id, &job.spec.command, timeout)).await;
+        let (status, output, error) = match result {
+            Ok(Ok(out)) if out.success => (STATUS_SUCCEEDED, out, None),
+            Ok(Ok(out)) => (STATUS_FAILED, out, None),
+            Ok(Err(e)) => (STATUS_FAILED, ExecOutput::default(), Some(e)),
+            Err(_) => (
+                STATUS_TIMED_OUT,
+                ExecOutput::default(),
+                Some(format!("Timed out after {}s", job.spec.timeout_secs)),
+            ),
+        };
+        if let Some(runs) = self.active.lock().unwrap().get_mut(&job.id) {
+            runs.retain(|r| r.run_id != run_id);
+        }
+        let now = chrono::Utc::now().timestamp_millis();
+        match self.store.finish_run(
+            run_id,
+            status,
+            tail(&output.stdout),
+            tail(&output.stderr),
+            error.as_deref(),
+            now,
+        ) {
+            Ok(true) => count_run(status),
+            Ok(false) => {}
+            Err(e) => warn!(job = %job.id, "Failed to record cron run: {}", e),
+        }
+    }
+
+    fn cancel_run(&self, run: ActiveRun, reason: &str, now: i64) {
+        run.abort.abort();
+        match self.store.finish_run(run.run_id, STATUS_CANCELLED, "", "", Some(reason), now) {
+            Ok(true) => count_run(STATUS_CANCELLED),
+            Ok(false) => {}
+            Err(e) => warn!(run = run.run_id, "Failed to record cancelled cron run: {}", e),
+        }
+    }
+
+    /// Delete a job, cancelling its runs in flight.
+    pub fn delete_job(&self, job_id: &str) -> anyhow::Result<bool> {
+        let now = chrono::Utc::now().timestamp_millis();
+        for run in self.active.lock().unwrap().remove(job_id).unwrap_or_default() {
+            self.cancel_run(run, "Job deleted", now);
+        }
+        self.store.delete_job(job_id)
+    }
+
+    /// Delete all the jobs of a removed app.
+    pub fn remove_app(&self, app_id: &str) -> anyhow::Result<usize> {
+        let jobs = self.store.jobs(Some(app_id))?;
+        for job in &jobs {
+            self.delete_job(&job.id)?;
+        }
+        Ok(jobs.len())
+    }
+}
+
+fn count_run(status: &'static str) {
+    hr_common::metrics::registry()
+        .counter("homeroute_cron_runs_total", "Scheduled job runs by outcome.", &[("status", status)])
+        .inc();
+}
+
+/// Last `MAX_OUTPUT` bytes of `output`, on a character boundary.
+fn tail(output: &str) -> &str {
+    let mut start = output.len().saturating_sub(MAX_OUTPUT);
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    &output[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::STATUS_SKIPPED;
+
+    /// `sleep <ms>` sleeps, `fail` fails, anything else succeeds.
+    struct FakeRunner;
+
+    impl JobRunner for FakeRunner {
+        async fn exec(&self, _app_id: &str, command: &str, _timeout: Duration) -> Result<ExecOutput, String> {
+            if let Some(ms) = command.strip_prefix("sleep ") {
+                tokio::time::sleep(Duration::from_millis(ms.parse().unwrap())).await;
+            }
+            Ok(ExecOutput { success: command != "fail", stdout: format!("ran {}", command), stderr: String::new() })
+        }
+    }
+
+    fn create(cron: &Cron<FakeRunner>, name: &str, command: &str, concurrency: ConcurrencyPolicy) -> CronJob {
+        let spec = JobSpec {
+            name: name.to_string(),
+            schedule: "* * * * *".to_string(),
+            command: command.to_string(),
+            timeout_secs: 1,
+            concurrency,
+            enabled: true,
+        };
+        match cron.store.create_job("app", &spec, 0).unwrap() {
+            SaveJob::Saved(job) => job,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    async fn settle(cron: &Cron<FakeRunner>, job: &CronJob) -> Vec<CronRun> {
+        for _ in 0..100 {
+            if cron.running(&job.id) == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        cron.store.runs(&job.id, None).unwrap()
+    }
+
+    fn statuses(runs: &[CronRun]) -> Vec<&str> {
+        runs.iter().rev().map(|r| r.status.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_policies() {
+        let cron = Arc::new(Cron::new(CronStore::open_in_memory().unwrap(), Arc::new(FakeRunner)));
+
+        let forbid = create(&cron, "forbid", "sleep 200", ConcurrencyPolicy::Forbid);
+        assert!(matches!(cron.trigger(&forbid, Trigger::Schedule).unwrap(), Triggered::Started(_)));
+        assert!(matches!(cron.trigger(&forbid, Trigger::Manual).unwrap(), Triggered::Skipped(_)));
+        let runs = settle(&cron, &forbid).await;
+        assert_eq!(statuses(&runs), vec![STATUS_SUCCEEDED, STATUS_SKIPPED]);
+        assert_eq!(runs[1].stdout, "ran sleep 200");
+        assert_eq!(runs[0].trigger, "manual");
+
+        let replace = create(&cron, "replace", "sleep 200", ConcurrencyPolicy::Replace);
+        cron.trigger(&replace, Trigger::Schedule).unwrap();
+        cron.trigger(&replace, Trigger::Schedule).unwrap();
+        assert_eq!(cron.running(&replace.id), 1);
+        assert_eq!(statuses(&settle(&cron, &replace).await), vec![STATUS_CANCELLED, STATUS_SUCCEEDED]);
+
+        let allow = create(&cron, "allow", "sleep 100", ConcurrencyPolicy::Allow);
+        cron.trigger(&allow, Trigger::Schedule).unwrap();
+        cron.trigger(&allow, Trigger::Schedule).unwrap();
+        assert_eq!(cron.running(&allow.id), 2);
+        assert_eq!(statuses(&settle(&cron, &allow).await), vec![STATUS_SUCCEEDED, STATUS_SUCCEEDED]);
+    }
+
+    #[tokio::test]
+    async fn test_failures_and_deletion() {
+        let cron = Arc::new(Cron::new(CronStore::open_in_memory().unwrap(), Arc::new(FakeRunner)));
+
+        let failing = create(&cron, "failing", "fail", ConcurrencyPolicy::Forbid);
+        cron.trigger(&failing, Trigger::Manual).unwrap();
+        assert_eq!(statuses(&settle(&cron, &failing).await), vec![STATUS_FAILED]);
+
+        let slow = create(&cron, "slow", "sleep 1500", ConcurrencyPolicy::Forbid);
+        cron.trigger(&slow, Trigger::Manual).unwrap();
+        let runs = settle(&cron, &slow).await;
+        assert_eq!(statuses(&runs), vec![STATUS_TIMED_OUT]);
+        assert_eq!(runs[0].error.as_deref(), Some("Timed out after 1s"));
+
+        cron.trigger(&slow, Trigger::Manual).unwrap();
+        assert_eq!(cron.remove_app("app").unwrap(), 2);
+        assert_eq!(cron.running(&slow.id), 0);
+        assert!(cron.store.jobs(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tail() {
+        let long = "é".repeat(MAX_OUTPUT);
+        let kept = tail(&long);
+        assert!(kept.len() <= MAX_OUTPUT && kept.chars().all(|c| c == 'é'));
+        assert_eq!(tail("short"), "short");
+    }
+}
@@ -0,0 +1,170 @@
+//! Cron expressions: the five classic fields (`minute hour day month
+//! weekday`) with lists, ranges, steps and names, or one of the `@daily`
+//! style macros. Times are wall-clock times of the server.
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+
+/// Upper bound of the steps `next_after` takes before giving up, enough to
+/// look several years ahead (a Feb 29 job fires every 4 years).
+const MAX_STEPS: usize = 100_000;
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Day of month and weekday both restricted: either one matches, as in
+    /// Vixie cron.
+    either_day: bool,
+}
+
+impl Schedule {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let expr = expr.trim();
+        let expanded = match expr.to_ascii_lowercase().as_str() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            _ if expr.starts_with('@') => return Err(format!("unknown macro: {}", expr)),
+            _ => expr,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("expected 5 fields, got {}", fields.len()));
+        };
+        let weekdays = parse_field(weekday, 0, 7, &WEEKDAYS, "weekday")?;
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59, &[], "minute")?,
+            hours: parse_field(hour, 0, 23, &[], "hour")?,
+            days: parse_field(day, 1, 31, &[], "day of month")?,
+            months: parse_field(month, 1, 12, &MONTHS, "month")?,
+            // 7 is Sunday too
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            either_day: !day.starts_with('*') && !weekday.starts_with('*'),
+        })
+    }
+
+    /// The schedule fires during the minute of `t`.
+    pub fn matches(&self, t: NaiveDateTime) -> bool {
+        bit(self.minutes, t.minute()) && bit(self.hours, t.hour()) && self.matches_day(t.date())
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        if !bit(self.months, date.month()) {
+            return false;
+        }
+        let day = bit(self.days, date.day());
+        let weekday = bit(self.weekdays, date.weekday().num_days_from_sunday());
+        if self.either_day { day || weekday } else { day && weekday }
+    }
+
+    /// First minute strictly after `after` the schedule fires, `None` when
+    /// it never does (e.g. February 30).
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut t = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        for _ in 0..MAX_STEPS {
+            if !self.matches_day(t.date()) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !bit(self.hours, t.hour()) {
+                t = t.with_minute(0)? + Duration::hours(1);
+            } else if !bit(self.minutes, t.minute()) {
+                t += Duration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+}
+
+fn bit(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+/// Bitmask of the values `field` selects within `min..=max`.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str], what: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid {} field: {}", what, field);
+    let value = |s: &str| -> Result<u32, String> {
+        let lower = s.to_ascii_lowercase();
+        let v = match names.iter().position(|n| *n == lower) {
+            Some(i) => i as u32 + min,
+            None => s.parse().map_err(|_| invalid())?,
+        };
+        if v < min || v > max {
+            return Err(format!("{} {} out of range {}-{}", what, v, min, max));
+        }
+        Ok(v)
+    };
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0).ok_or_else(invalid)?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (value(a)?, value(b)?),
+                // `5/15` runs from 5 to the end of the range
+                None if part.contains('/') => (value(range)?, max),
+                None => {
+                    let v = value(range)?;
+                    (v, v)
+                }
+            },
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for v in (start..=end).step_by(step as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn next(expr: &str, after: &str) -> Option<String> {
+        Schedule::parse(expr).unwrap().next_after(at(after)).map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Schedule::parse("@daily"), Schedule::parse("0 0 * * *"));
+        assert_eq!(Schedule::parse("0 0 * * 7"), Schedule::parse("0 0 * * sun"));
+        assert!(Schedule::parse("*/15 9-17 * JAN-jun mon-fri").is_ok());
+        assert!(Schedule::parse("0 0 * *").is_err());
+        assert!(Schedule::parse("60 * * * *").is_err());
+        assert!(Schedule::parse("*/0 * * * *").is_err());
+        assert!(Schedule::parse("5-1 * * * *").is_err());
+        assert!(Schedule::parse("@reboot").is_err());
+    }
+
+    #[test]
+    fn test_next_after() {
+        assert_eq!(next("*/15 * * * *", "2026-03-01 10:07").as_deref(), Some("2026-03-01 10:15"));
+        assert_eq!(next("*/15 * * * *", "2026-03-01 10:45").as_deref(), Some("2026-03-01 11:00"));
+        assert_eq!(next("30 2 * * *", "2026-12-31 03:00").as_deref(), Some("2027-01-01 02:30"));
+        // 2026-10-17 is a Saturday
+        assert_eq!(next("0 9 * * mon-fri", "2026-10-17 08:00").as_deref(), Some("2026-10-19 09:00"));
+        // Day of month or weekday
+        assert_eq!(next("0 0 13 * fri", "2026-10-17 00:00").as_deref(), Some("2026-10-23 00:00"));
+        assert_eq!(next("0 0 29 2 *", "2026-03-01 00:00").as_deref(), Some("2028-02-29 00:00"));
+        assert_eq!(next("0 0 30 2 *", "2026-03-01 00:00"), None);
+        assert_eq!(next("10/20 * * * *", "2026-03-01 10:31").as_deref(), Some("2026-03-01 10:50"));
+    }
+}
@@ -0,0 +1,96 @@
+//! The `cron` service: wakes up at every minute and starts the jobs due.
+//!
+//! Minutes are counted in UTC and matched in local time, so across a DST
+//! change the jobs of the repeated hour run twice and those of the skipped
+//! hour do not run.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::{DateTime, DurationRound, Local, NaiveDateTime, TimeDelta, Utc};
+use tracing::warn;
+
+use crate::{Cron, CronJob, JobRunner, Schedule, Trigger};
+
+/// Minutes caught up after a late wake-up; older ones are dropped.
+const CATCH_UP: i64 = 5;
+
+pub async fn run_cron<R: JobRunner>(cron: Arc<Cron<R>>) -> Result<()> {
+    let mut last = minute_of(Utc::now());
+    loop {
+        let now = Utc::now();
+        let wait = (minute_of(now) + TimeDelta::minutes(1) - now).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+
+        let now = minute_of(Utc::now());
+        let mut minute = (last + TimeDelta::minutes(1)).max(now - TimeDelta::minutes(CATCH_UP - 1));
+        if minute > now {
+            continue;
+        }
+        let jobs = match cron.store().jobs(None) {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                warn!("Failed to load cron jobs: {}", e);
+                continue;
+            }
+        };
+        while minute <= now {
+            for job in due(&jobs, minute.with_timezone(&Local).naive_local()) {
+                if let Err(e) = cron.trigger(job, Trigger::Schedule) {
+                    warn!(job = %job.id, app = %job.app_id, "Failed to start cron job: {}", e);
+                }
+            }
+            minute += TimeDelta::minutes(1);
+        }
+        last = now;
+    }
+}
+
+fn minute_of(t: DateTime<Utc>) -> DateTime<Utc> {
+    t.duration_trunc(TimeDelta::minutes(1)).unwrap_or(t)
+}
+
+/// Enabled jobs whose schedule fires at `at` (local time).
+fn due(jobs: &[CronJob], at: NaiveDateTime) -> Vec<&CronJob> {
+    jobs.iter()
+        .filter(|job| job.spec.enabled)
+        .filter(|job| Schedule::parse(&job.spec.schedule).is_ok_and(|s| s.matches(at)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConcurrencyPolicy, JobSpec};
+
+    fn job(name: &str, schedule: &str, enabled: bool) -> CronJob {
+        CronJob {
+            id: name.to_string(),
+            app_id: "app".to_string(),
+            spec: JobSpec {
+                name: name.to_string(),
+                schedule: schedule.to_string(),
+                command: "true".to_string(),
+                timeout_secs: 60,
+                concurrency: ConcurrencyPolicy::Forbid,
+                enabled,
+            },
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_due() {
+        let jobs = vec![
+            job("quarter", "*/15 * * * *", true),
+            job("nightly", "30 2 * * *", true),
+            job("disabled", "* * * * *", false),
+        ];
+        let at = |s| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+        let names = |t| due(&jobs, at(t)).iter().map(|j| j.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(names("2026-10-17 02:30"), vec!["quarter", "nightly"]);
+        assert_eq!(names("2026-10-17 02:31"), Vec::<&str>::new());
+        assert_eq!(names("2026-10-17 14:45"), vec!["quarter"]);
+    }
+}
@@ -0,0 +1,433 @@
+//! SQLite job definitions and run history (`cron.db`). The last
+//! `HISTORY_PER_JOB` runs of each job are kept.
+
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::schedule::Schedule;
+
+/// Runs kept per job.
+pub const HISTORY_PER_JOB: u32 = 100;
+
+/// Longest command, in bytes.
+const MAX_COMMAND: usize = 4096;
+/// Longest timeout: one day.
+const MAX_TIMEOUT_SECS: u32 = 86_400;
+/// Default and maximum page size of `runs`.
+const DEFAULT_LIMIT: u32 = 50;
+
+pub const STATUS_RUNNING: &str = "running";
+pub const STATUS_SUCCEEDED: &str = "succeeded";
+pub const STATUS_FAILED: &str = "failed";
+pub const STATUS_TIMED_OUT: &str = "timed_out";
+/// Not started: the previous run was still going (`forbid`).
+pub const STATUS_SKIPPED: &str = "skipped";
+/// Stopped by a newer run (`replace`) or the deletion of its job.
+pub const STATUS_CANCELLED: &str = "cancelled";
+/// Still running when HomeRoute stopped.
+pub const STATUS_INTERRUPTED: &str = "interrupted";
+
+/// What happens when a job is due while its previous run is still going.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConcurrencyPolicy {
+    /// Start another run alongside.
+    Allow,
+    /// Skip this one.
+    #[default]
+    Forbid,
+    /// Cancel the running one, then start.
+    Replace,
+}
+
+impl ConcurrencyPolicy {
+    fn as_str(self) -> &'static str {
+        match self {
+            ConcurrencyPolicy::Allow => "allow",
+            ConcurrencyPolicy::Forbid => "forbid",
+            ConcurrencyPolicy::Replace => "replace",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "allow" => ConcurrencyPolicy::Allow,
+            "replace" => ConcurrencyPolicy::Replace,
+            _ => ConcurrencyPolicy::Forbid,
+        }
+    }
+}
+
+fn default_timeout() -> u32 {
+    300
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Job as declared through the API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobSpec {
+    pub name: String,
+    /// Cron expression, e.g. `*/15 * * * *` or `@daily`.
+    pub schedule: String,
+    /// Shell command run in the app container.
+    pub command: String,
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u32,
+    #[serde(default)]
+    pub concurrency: ConcurrencyPolicy,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+impl JobSpec {
+    pub fn validate(&self) -> Result<(), String> {
+        let name = self.name.trim();
+        if name.is_empty() || name.len() > 64 {
+            return Err("Job name must be 1-64 characters".to_string());
+        }
+        let schedule = Schedule::parse(&self.schedule).map_err(|e| format!("Invalid schedule: {}", e))?;
+        if schedule.next_after(chrono::Local::now().naive_local()).is_none() {
+            return Err("The schedule never fires".to_string());
+        }
+        if self.command.trim().is_empty() || self.command.len() > MAX_COMMAND {
+            return Err(format!("Command must be 1-{} bytes", MAX_COMMAND));
+        }
+        if self.timeout_secs == 0 || self.timeout_secs > MAX_TIMEOUT_SECS {
+            return Err(format!("Timeout must be 1-{} seconds", MAX_TIMEOUT_SECS));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CronJob {
+    pub id: String,
+    pub app_id: String,
+    #[serde(flatten)]
+    pub spec: JobSpec,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CronRun {
+    pub id: i64,
+    pub job_id: String,
+    pub app_id: String,
+    /// `schedule` or `manual`.
+    pub trigger: String,
+    pub status: String,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub stdout: String,
+    pub stderr: String,
+    pub error: Option<String>,
+}
+
+/// Outcome of `create_job` and `update_job`.
+#[derive(Debug)]
+pub enum SaveJob {
+    Saved(CronJob),
+    NotFound,
+    /// Another job of the app has this name.
+    NameTaken,
+}
+
+pub struct CronStore {
+    conn: Mutex<Connection>,
+}
+
+const JOB_COLUMNS: &str = "id, app_id, name, schedule, command, timeout_secs, concurrency, enabled, created_at, updated_at";
+const RUN_COLUMNS: &str = "id, job_id, app_id, trigger, status, started_at, finished_at, stdout, stderr, error";
+
+impl CronStore {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS jobs (
+                id TEXT PRIMARY KEY,
+                app_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                schedule TEXT NOT NULL,
+                command TEXT NOT NULL,
+                timeout_secs INTEGER NOT NULL,
+                concurrency TEXT NOT NULL,
+                enabled INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                UNIQUE (app_id, name)
+            );
+            CREATE TABLE IF NOT EXISTS runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                job_id TEXT NOT NULL,
+                app_id TEXT NOT NULL,
+                trigger TEXT NOT NULL,
+                status TEXT NOT NULL,
+                started_at INTEGER NOT NULL,
+                finished_at INTEGER,
+                stdout TEXT NOT NULL DEFAULT '',
+                stderr TEXT NOT NULL DEFAULT '',
+                error TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_runs_job ON runs(job_id, id);",
+        )?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    /// Jobs of one app, or all of them.
+    pub fn jobs(&self, app_id: Option<&str>) -> anyhow::Result<Vec<CronJob>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {JOB_COLUMNS} FROM jobs WHERE ?1 IS NULL OR app_id = ?1 ORDER BY app_id, name"
+        ))?;
+        let jobs = stmt.query_map(params![app_id], job_from_row)?.collect::<Result<_, _>>()?;
+        Ok(jobs)
+    }
+
+    pub fn job(&self, id: &str) -> anyhow::Result<Option<CronJob>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn
+            .query_row(&format!("SELECT {JOB_COLUMNS} FROM jobs WHERE id = ?1"), params![id], job_from_row)
+            .optional()?)
+    }
+
+    /// The spec must have been validated.
+    pub fn create_job(&self, app_id: &str, spec: &JobSpec, now: i64) -> anyhow::Result<SaveJob> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let inserted = self.conn.lock().unwrap().execute(
+            &format!("INSERT INTO jobs ({JOB_COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?9)"),
+            params![
+                id,
+                app_id,
+                spec.name.trim(),
+                spec.schedule.trim(),
+                spec.command,
+                spec.timeout_secs,
+                spec.concurrency.as_str(),
+                spec.enabled,
+                now
+            ],
+        );
+        match inserted {
+            Ok(_) => Ok(self.job(&id)?.map_or(SaveJob::NotFound, SaveJob::Saved)),
+            Err(e) if is_constraint(&e) => Ok(SaveJob::NameTaken),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn update_job(&self, id: &str, spec: &JobSpec, now: i64) -> anyhow::Result<SaveJob> {
+        let updated = self.conn.lock().unwrap().execute(
+            "UPDATE jobs SET name = ?2, schedule = ?3, command = ?4, timeout_secs = ?5, concurrency = ?6,
+                enabled = ?7, updated_at = ?8
+             WHERE id = ?1",
+            params![
+                id,
+                spec.name.trim(),
+                spec.schedule.trim(),
+                spec.command,
+                spec.timeout_secs,
+                spec.concurrency.as_str(),
+                spec.enabled,
+                now
+            ],
+        );
+        match updated {
+            Ok(0) => Ok(SaveJob::NotFound),
+            Ok(_) => Ok(self.job(id)?.map_or(SaveJob::NotFound, SaveJob::Saved)),
+            Err(e) if is_constraint(&e) => Ok(SaveJob::NameTaken),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Delete a job and its history.
+    pub fn delete_job(&self, id: &str) -> anyhow::Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM runs WHERE job_id = ?1", params![id])?;
+        Ok(conn.execute("DELETE FROM jobs WHERE id = ?1", params![id])? > 0)
+    }
+
+    /// Record a run, dropping the oldest ones of the job beyond
+    /// `HISTORY_PER_JOB`.
+    pub fn start_run(&self, job: &CronJob, trigger: &str, status: &str, now: i64) -> anyhow::Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        let finished = (status != STATUS_RUNNING).then_some(now);
+        let id = conn.query_row(
+            "INSERT INTO runs (job_id, app_id, trigger, status, started_at, finished_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6) RETURNING id",
+            params![job.id, job.app_id, trigger, status, now, finished],
+            |row| row.get(0),
+        )?;
+        conn.execute(
+            "DELETE FROM runs WHERE job_id = ?1 AND id <= (
+                SELECT id FROM runs WHERE job_id = ?1 ORDER BY id DESC LIMIT 1 OFFSET ?2
+             )",
+            params![job.id, HISTORY_PER_JOB],
+        )?;
+        Ok(id)
+    }
+
+    /// Close a running run; false when it already was (cancelled).
+    pub fn finish_run(
+        &self,
+        id: i64,
+        status: &str,
+        stdout: &str,
+        stderr: &str,
+        error: Option<&str>,
+        now: i64,
+    ) -> anyhow::Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE runs SET status = ?2, stdout = ?3, stderr = ?4, error = ?5, finished_at = ?6
+             WHERE id = ?1 AND status = ?7",
+            params![id, status, stdout, stderr, error, now, STATUS_RUNNING],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Mark the runs left running by a previous process as interrupted.
+    pub fn interrupt_running(&self, now: i64) -> anyhow::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute(
+            "UPDATE runs SET status = ?1, finished_at = ?2 WHERE status = ?3",
+            params![STATUS_INTERRUPTED, now, STATUS_RUNNING],
+        )?)
+    }
+
+    /// Latest runs of a job, newest first.
+    pub fn runs(&self, job_id: &str, limit: Option<u32>) -> anyhow::Result<Vec<CronRun>> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, HISTORY_PER_JOB);
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {RUN_COLUMNS} FROM runs WHERE job_id = ?1 ORDER BY id DESC LIMIT ?2"
+        ))?;
+        let runs = stmt.query_map(params![job_id, limit], run_from_row)?.collect::<Result<_, _>>()?;
+        Ok(runs)
+    }
+
+    pub fn last_run(&self, job_id: &str) -> anyhow::Result<Option<CronRun>> {
+        Ok(self.runs(job_id, Some(1))?.pop())
+    }
+}
+
+fn is_constraint(e: &rusqlite::Error) -> bool {
+    matches!(e, rusqlite::Error::SqliteFailure(err, _) if err.code == rusqlite::ErrorCode::ConstraintViolation)
+}
+
+fn job_from_row(row: &Row) -> rusqlite::Result<CronJob> {
+    Ok(CronJob {
+        id: row.get(0)?,
+        app_id: row.get(1)?,
+        spec: JobSpec {
+            name: row.get(2)?,
+            schedule: row.get(3)?,
+            command: row.get(4)?,
+            timeout_secs: row.get(5)?,
+            concurrency: ConcurrencyPolicy::parse(&row.get::<_, String>(6)?),
+            enabled: row.get(7)?,
+        },
+        created_at: row.get(8)?,
+        updated_at: row.get(9)?,
+    })
+}
+
+fn run_from_row(row: &Row) -> rusqlite::Result<CronRun> {
+    Ok(CronRun {
+        id: row.get(0)?,
+        job_id: row.get(1)?,
+        app_id: row.get(2)?,
+        trigger: row.get(3)?,
+        status: row.get(4)?,
+        started_at: row.get(5)?,
+        finished_at: row.get(6)?,
+        stdout: row.get(7)?,
+        stderr: row.get(8)?,
+        error: row.get(9)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(name: &str) -> JobSpec {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "schedule": "*/5 * * * *",
+            "command": "php artisan schedule:run",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_jobs() {
+        let store = CronStore::open_in_memory().unwrap();
+        let spec = spec("cleanup");
+        assert!(spec.validate().is_ok());
+        assert_eq!(spec.concurrency, ConcurrencyPolicy::Forbid);
+        let SaveJob::Saved(job) = store.create_job("app1", &spec, 1).unwrap() else { panic!() };
+        assert!(matches!(store.create_job("app1", &spec, 2).unwrap(), SaveJob::NameTaken));
+        assert!(matches!(store.create_job("app2", &spec, 2).unwrap(), SaveJob::Saved(_)));
+        assert_eq!(store.jobs(Some("app1")).unwrap().len(), 1);
+        assert_eq!(store.jobs(None).unwrap().len(), 2);
+
+        let mut changed = spec.clone();
+        changed.concurrency = ConcurrencyPolicy::Replace;
+        changed.enabled = false;
+        let SaveJob::Saved(updated) = store.update_job(&job.id, &changed, 3).unwrap() else { panic!() };
+        assert_eq!(updated.spec.concurrency, ConcurrencyPolicy::Replace);
+        assert!(!updated.spec.enabled);
+        assert_eq!((updated.created_at, updated.updated_at), (1, 3));
+        assert!(matches!(store.update_job("nope", &changed, 3).unwrap(), SaveJob::NotFound));
+
+        let mut invalid = spec.clone();
+        invalid.schedule = "0 0 31 2 *".to_string();
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_run_history() {
+        let store = CronStore::open_in_memory().unwrap();
+        let SaveJob::Saved(job) = store.create_job("app1", &spec("backup"), 0).unwrap() else { panic!() };
+        let run = store.start_run(&job, "schedule", STATUS_RUNNING, 10).unwrap();
+        assert!(store.finish_run(run, STATUS_SUCCEEDED, "done", "", None, 20).unwrap());
+        // Already closed
+        assert!(!store.finish_run(run, STATUS_CANCELLED, "", "", None, 30).unwrap());
+        let last = store.last_run(&job.id).unwrap().unwrap();
+        assert_eq!((last.status.as_str(), last.stdout.as_str(), last.finished_at), (STATUS_SUCCEEDED, "done", Some(20)));
+
+        store.start_run(&job, "manual", STATUS_RUNNING, 40).unwrap();
+        assert_eq!(store.interrupt_running(50).unwrap(), 1);
+        assert_eq!(store.last_run(&job.id).unwrap().unwrap().status, STATUS_INTERRUPTED);
+
+        for i in 0..HISTORY_PER_JOB + 5 {
+            store.start_run(&job, "schedule", STATUS_SKIPPED, 100 + i as i64).unwrap();
+        }
+        let runs = store.runs(&job.id, Some(1000)).unwrap();
+        assert_eq!(runs.len(), HISTORY_PER_JOB as usize);
+        assert_eq!(runs[0].started_at, 100 + HISTORY_PER_JOB as i64 + 4);
+
+        assert!(store.delete_job(&job.id).unwrap());
+        assert!(store.runs(&job.id, None).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,304 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::Ipv6Addr;
+use std::path::Path;
+
+use hr_common::config::EnvConfig;
+
+use crate::provider::{Cloudflare, ProviderConfig};
+
+pub const DEFAULT_IPV4_URL: &str = "https://api.ipify.org";
+pub const DEFAULT_IPV6_URL: &str = "https://api6.ipify.org";
+
+/// Dynamic DNS (ddns-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DdnsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Addresses are checked this often; records are only pushed when
+    /// their address changed (or after a failure, with backoff).
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+    #[serde(default)]
+    pub ipv4: Ipv4Detection,
+    #[serde(default)]
+    pub ipv6: Ipv6Detection,
+    #[serde(default)]
+    pub records: Vec<DdnsRecord>,
+}
+
+fn default_interval() -> u64 {
+    300
+}
+
+fn yes() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ipv4Source {
+    /// No IPv4 (A records are left alone), except the VPS address in
+    /// cloud relay mode.
+    #[default]
+    None,
+    /// Global address of an interface (the box holds the public IPv4).
+    Interface,
+    /// Asked to a "what is my IP" service (behind a modem doing NAT).
+    Web,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ipv4Detection {
+    #[serde(default)]
+    pub source: Ipv4Source,
+    #[serde(default)]
+    pub interface: String,
+    /// Plain-text answer; [`DEFAULT_IPV4_URL`] when empty.
+    #[serde(default)]
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ipv6Source {
+    None,
+    /// First stable global address of an interface.
+    #[default]
+    Interface,
+    /// The delegated (DHCPv6-PD) /64 followed by `suffix`, for a record
+    /// pointing to a fixed LAN host.
+    Prefix,
+    Web,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ipv6Detection {
+    #[serde(default)]
+    pub source: Ipv6Source,
+    #[serde(default)]
+    pub interface: String,
+    /// Interface identifier appended to the prefix (`::1`, `::a:b:c:d`).
+    #[serde(default)]
+    pub suffix: String,
+    /// Plain-text answer; [`DEFAULT_IPV6_URL`] when empty.
+    #[serde(default)]
+    pub url: String,
+}
+
+impl Default for Ipv4Detection {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl Default for Ipv6Detection {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+/// A host name kept pointed at the detected addresses.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DdnsRecord {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default = "yes")]
+    pub enabled: bool,
+    /// Families published for this name.
+    #[serde(default = "yes")]
+    pub ipv4: bool,
+    #[serde(default = "yes")]
+    pub ipv6: bool,
+    pub provider: ProviderConfig,
+}
+
+impl Default for DdnsConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+fn valid_interface(name: &str) -> bool {
+    (1..=15).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.@".contains(c))
+}
+
+fn valid_hostname(name: &str) -> bool {
+    let name = name.trim_end_matches('.');
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            (1..=63).contains(&label.len())
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '*')
+        })
+}
+
+impl DdnsConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Config of an install that only has the `CF_*` settings of `.env`:
+    /// the record follows the IPv6 of `CF_INTERFACE` (and the VPS IPv4 in
+    /// cloud relay mode), as before.
+    pub fn from_env(env: &EnvConfig) -> Self {
+        let mut config = Self::default();
+        config.ipv6.interface = env.cf_interface.clone();
+        config.ipv4.interface = env.cf_interface.clone();
+        if let (Some(token), Some(zone_id), Some(name)) = (&env.cf_api_token, &env.cf_zone_id, &env.cf_record_name) {
+            config.enabled = true;
+            config.records.push(DdnsRecord {
+                id: uuid::Uuid::new_v4().to_string(),
+                name: name.clone(),
+                enabled: true,
+                ipv4: true,
+                ipv6: true,
+                provider: ProviderConfig::Cloudflare(Cloudflare {
+                    api_token: token.clone(),
+                    zone_id: zone_id.clone(),
+                    proxied: env.cf_proxied,
+                }),
+            });
+        }
+        config
+    }
+
+    /// Give new records an id and trim the names.
+    pub fn normalize(&mut self) {
+        for record in &mut self.records {
+            record.name = record.name.trim().trim_end_matches('.').to_ascii_lowercase();
+            if record.id.is_empty() {
+                record.id = uuid::Uuid::new_v4().to_string();
+            }
+        }
+    }
+
+    /// Put back the secrets the API masked, record by record.
+    pub fn restore_secrets(&mut self, previous: &DdnsConfig) {
+        for record in &mut self.records {
+            if let Some(old) = previous.records.iter().find(|r| r.id == record.id) {
+                record.provider.restore_secrets(&old.provider);
+            }
+        }
+    }
+
+    /// Copy safe to return from the API.
+    pub fn masked(&self) -> Self {
+        let mut masked = self.clone();
+        for record in &mut masked.records {
+            record.provider = record.provider.masked();
+        }
+        masked
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(60..=86_400).contains(&self.interval_secs) {
+            return Err("The check interval must be between 60 s and 24 h".to_string());
+        }
+        if self.ipv4.source == Ipv4Source::Interface && !valid_interface(&self.ipv4.interface) {
+            return Err(format!("Invalid IPv4 interface: {}", self.ipv4.interface));
+        }
+        if self.ipv6.source == Ipv6Source::Interface && !valid_interface(&self.ipv6.interface) {
+            return Err(format!("Invalid IPv6 interface: {}", self.ipv6.interface));
+        }
+        if self.ipv6.source == Ipv6Source::Prefix && self.ipv6.suffix.parse::<Ipv6Addr>().is_err() {
+            return Err(format!("Invalid IPv6 suffix: {}", self.ipv6.suffix));
+        }
+        for url in [&self.ipv4.url, &self.ipv6.url] {
+            if !url.is_empty() && !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(format!("Invalid detection URL: {url}"));
+            }
+        }
+        let mut ids = HashSet::new();
+        for record in &self.records {
+            if !valid_hostname(&record.name) {
+                return Err(format!("Invalid host name: {}", record.name));
+            }
+            if !ids.insert(record.id.as_str()) {
+                return Err(format!("Duplicate record id: {}", record.id));
+            }
+            if !record.ipv4 && !record.ipv6 {
+                return Err(format!("{}: publish IPv4, IPv6 or both", record.name));
+            }
+            record.provider.validate().map_err(|e| format!("{}: {e}", record.name))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::MASK;
+
+    #[test]
+    fn test_validate() {
+        let mut config: DdnsConfig = serde_json::from_str(
+            r#"{
+                "enabled": true,
+                "ipv6": {"source": "prefix", "suffix": "::10"},
+                "records": [
+                    {"name": "Home.DuckDNS.org.", "provider": {"type": "duckdns", "token": "t"}},
+                    {"name": "nas.example.com", "ipv4": false,
+                     "provider": {"type": "http", "url": "https://dns.example.com/?h={name}&ip={ipv6}"}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        config.normalize();
+        assert_eq!(config.records[0].name, "home.duckdns.org");
+        assert_eq!(config.interval_secs, 300);
+        assert!(config.validate().is_ok());
+
+        config.ipv6.suffix = "10".to_string();
+        assert!(config.validate().is_err());
+        config.ipv6.suffix = "::10".to_string();
+        config.records[1].ipv6 = false;
+        assert!(config.validate().is_err());
+        config.records[1].ipv6 = true;
+        config.records[1].name = "bad name".to_string();
+        assert!(config.validate().is_err());
+        config.records[1].name = "nas.example.com".to_string();
+        config.records[1].id = config.records[0].id.clone();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_from_env_and_secrets() {
+        let env = EnvConfig {
+            cf_api_token: Some("cf-token".to_string()),
+            cf_zone_id: Some("zone".to_string()),
+            cf_record_name: Some("home.example.com".to_string()),
+            cf_interface: "enp1s0".to_string(),
+            ..Default::default()
+        };
+        let config = DdnsConfig::from_env(&env);
+        assert!(config.enabled);
+        assert_eq!(config.ipv4.source, Ipv4Source::None);
+        assert_eq!(config.ipv6.interface, "enp1s0");
+        assert!(config.validate().is_ok());
+
+        let mut masked = config.masked();
+        assert!(serde_json::to_string(&masked).unwrap().contains(MASK));
+        masked.restore_secrets(&config);
+        assert_eq!(masked, config);
+        assert!(!DdnsConfig::from_env(&EnvConfig::default()).enabled);
+    }
+}
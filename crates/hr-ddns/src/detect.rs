@@ -0,0 +1,135 @@
+//! WAN address detection: interface addresses (`ip addr`), the delegated
+//! prefix, "what is my IP" services and the VPS of the cloud relay.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+use hr_ipv6::PrefixWatch;
+use hr_ipv6::config::PdState;
+
+use crate::config::{DEFAULT_IPV4_URL, DEFAULT_IPV6_URL};
+
+/// Addresses of `ip -o addr show` output usable as a public address:
+/// global scope, neither temporary nor deprecated, no ULA.
+pub(crate) fn parse_ip_addr(output: &str) -> Vec<IpAddr> {
+    output
+        .lines()
+        .filter(|line| line.contains(" scope global") && !line.contains("temporary") && !line.contains("deprecated"))
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            words.find(|w| *w == "inet" || *w == "inet6")?;
+            words.next()?.split('/').next()?.parse().ok()
+        })
+        .filter(|ip| match ip {
+            // fc00::/7
+            IpAddr::V6(v6) => v6.segments()[0] & 0xfe00 != 0xfc00,
+            IpAddr::V4(_) => true,
+        })
+        .collect()
+}
+
+async fn interface_addrs(family: &str, interface: &str) -> Vec<IpAddr> {
+    let output = tokio::process::Command::new("ip")
+        .args(["-o", family, "addr", "show", "dev", interface, "scope", "global"])
+        .output()
+        .await;
+    match output {
+        Ok(output) => parse_ip_addr(&String::from_utf8_lossy(&output.stdout)),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub async fn interface_ipv4(interface: &str) -> Option<Ipv4Addr> {
+    interface_addrs("-4", interface).await.into_iter().find_map(|ip| match ip {
+        IpAddr::V4(v4) => Some(v4),
+        IpAddr::V6(_) => None,
+    })
+}
+
+pub async fn interface_ipv6(interface: &str) -> Option<Ipv6Addr> {
+    interface_addrs("-6", interface).await.into_iter().find_map(|ip| match ip {
+        IpAddr::V6(v6) => Some(v6),
+        IpAddr::V4(_) => None,
+    })
+}
+
+/// Network bits of `prefix`, host bits of `suffix`.
+pub(crate) fn prefix_address(prefix: Ipv6Addr, prefix_len: u8, suffix: Ipv6Addr) -> Ipv6Addr {
+    let mask = if prefix_len == 0 { 0 } else { u128::MAX << (128 - prefix_len.min(128) as u32) };
+    Ipv6Addr::from((u128::from(prefix) & mask) | (u128::from(suffix) & !mask))
+}
+
+/// Address of the host at `suffix` in the LAN /64 delegated to us (the
+/// live prefix, or the persisted lease while the PD client is starting).
+pub fn delegated_ipv6(prefix: &PrefixWatch, suffix: &str) -> Option<Ipv6Addr> {
+    let suffix: Ipv6Addr = suffix.parse().ok()?;
+    if let Some(info) = prefix.borrow().as_ref() {
+        return Some(prefix_address(info.prefix, info.prefix_len, suffix));
+    }
+    let state = PdState::load().filter(PdState::is_valid)?;
+    let (addr, len) = state.selected_subnet.split_once('/')?;
+    Some(prefix_address(addr.parse().ok()?, len.parse().ok()?, suffix))
+}
+
+async fn web_ip(http: &reqwest::Client, url: &str) -> Result<IpAddr, String> {
+    let response = http.get(url).send().await.map_err(|e| e.to_string())?;
+    let body = response.error_for_status().map_err(|e| e.to_string())?.text().await.map_err(|e| e.to_string())?;
+    body.trim().parse().map_err(|_| format!("{url} did not answer an address"))
+}
+
+pub async fn web_ipv4(http: &reqwest::Client, url: &str) -> Result<Ipv4Addr, String> {
+    let url = if url.is_empty() { DEFAULT_IPV4_URL } else { url };
+    match web_ip(http, url).await? {
+        IpAddr::V4(v4) => Ok(v4),
+        IpAddr::V6(_) => Err(format!("{url} answered an IPv6 address")),
+    }
+}
+
+pub async fn web_ipv6(http: &reqwest::Client, url: &str) -> Result<Ipv6Addr, String> {
+    let url = if url.is_empty() { DEFAULT_IPV6_URL } else { url };
+    match web_ip(http, url).await? {
+        IpAddr::V6(v6) => Ok(v6),
+        IpAddr::V4(_) => Err(format!("{url} answered an IPv4 address")),
+    }
+}
+
+/// Public IPv4 of the VPS (`cloud-relay/config.json`).
+pub fn relay_vps_ipv4(data_dir: &Path) -> Option<Ipv4Addr> {
+    let content = std::fs::read_to_string(data_dir.join("cloud-relay/config.json")).ok()?;
+    let value: serde_json::Value = serde_json::from_str(&content).ok()?;
+    value.get("vps_ipv4")?.as_str()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ip_addr() {
+        let output = "\
+2: eth0    inet6 2001:db8:1:2:aaaa:bbbb:cccc:1/64 scope global temporary dynamic \\       valid_lft 86000sec
+2: eth0    inet6 2001:db8:1:2::9/64 scope global deprecated dynamic \\       valid_lft 100sec
+2: eth0    inet6 fd00::1/64 scope global \\       valid_lft forever
+2: eth0    inet6 2001:db8:1:2::1/64 scope global dynamic mngtmpaddr \\       valid_lft 86000sec
+2: eth0    inet 203.0.113.7/24 brd 203.0.113.255 scope global eth0\\       valid_lft forever";
+        assert_eq!(
+            parse_ip_addr(output),
+            vec!["2001:db8:1:2::1".parse::<IpAddr>().unwrap(), "203.0.113.7".parse::<IpAddr>().unwrap()]
+        );
+        assert!(parse_ip_addr("").is_empty());
+    }
+
+    #[test]
+    fn test_prefix_address() {
+        let prefix: Ipv6Addr = "2001:db8:aa:1::".parse().unwrap();
+        assert_eq!(
+            prefix_address(prefix, 64, "::10".parse().unwrap()),
+            "2001:db8:aa:1::10".parse::<Ipv6Addr>().unwrap()
+        );
+        // Only the host bits of the suffix are kept
+        assert_eq!(
+            prefix_address(prefix, 64, "ffff::a:b:c:d".parse().unwrap()),
+            "2001:db8:aa:1:a:b:c:d".parse::<Ipv6Addr>().unwrap()
+        );
+    }
+}
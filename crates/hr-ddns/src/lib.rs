@@ -0,0 +1,132 @@
+//! Dynamic DNS: detects the WAN addresses (interface, delegated prefix,
+//! web lookup, cloud relay VPS) and keeps host names pointed at them
+//! through pluggable providers (Cloudflare, DuckDNS, deSEC, Dynu, generic
+//! HTTP), retrying failed updates with backoff.
+
+pub mod config;
+pub mod detect;
+pub mod provider;
+pub mod service;
+
+pub use config::{DdnsConfig, DdnsRecord, Ipv4Detection, Ipv4Source, Ipv6Detection, Ipv6Source};
+pub use provider::{Provider, ProviderConfig};
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::{Notify, watch};
+
+/// Lines kept in the update log.
+const LOG_LINES: usize = 50;
+
+/// Addresses pushed to a provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Addresses {
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+    /// Cloud relay mode: the IPv4 is the VPS.
+    pub relayed: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordStatus {
+    pub id: String,
+    pub name: String,
+    pub provider: String,
+    /// Addresses the provider last accepted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv4: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_update: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Consecutive failures; the next try waits longer each time.
+    pub failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_retry: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DdnsStatus {
+    pub active: bool,
+    pub relayed: bool,
+    /// Detected addresses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv4: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detection_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_check: Option<String>,
+    pub records: Vec<RecordStatus>,
+    /// Newest first.
+    pub logs: VecDeque<String>,
+}
+
+/// Shared DDNS handle: the API pushes config, reads status and asks for
+/// immediate updates; the supervised `ddns` service does the work.
+pub struct Ddns {
+    config: watch::Sender<DdnsConfig>,
+    status: RwLock<DdnsStatus>,
+    forced: AtomicBool,
+    wake: Notify,
+}
+
+pub type SharedDdns = Arc<Ddns>;
+
+impl Ddns {
+    pub fn new(config: DdnsConfig) -> Self {
+        Self {
+            config: watch::channel(config).0,
+            status: RwLock::new(DdnsStatus::default()),
+            forced: AtomicBool::new(false),
+            wake: Notify::new(),
+        }
+    }
+
+    pub fn config(&self) -> DdnsConfig {
+        self.config.borrow().clone()
+    }
+
+    /// Replace the configuration; addresses are checked right away.
+    pub fn apply(&self, config: DdnsConfig) {
+        self.config.send_replace(config);
+    }
+
+    /// Push every record now, even unchanged or waiting for a retry.
+    pub fn force_update(&self) {
+        self.forced.store(true, Ordering::SeqCst);
+        self.wake.notify_one();
+    }
+
+    pub(crate) fn take_forced(&self) -> bool {
+        self.forced.swap(false, Ordering::SeqCst)
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<DdnsConfig> {
+        self.config.subscribe()
+    }
+
+    pub fn status(&self) -> DdnsStatus {
+        self.status.read().unwrap().clone()
+    }
+
+    pub(crate) fn update_status(&self, f: impl FnOnce(&mut DdnsStatus)) {
+        f(&mut self.status.write().unwrap());
+    }
+
+    pub(crate) fn log(&self, message: String) {
+        let line = format!("[{}] {}", chrono::Utc::now().to_rfc3339(), message);
+        self.update_status(|s| {
+            s.logs.push_front(line);
+            s.logs.truncate(LOG_LINES);
+        });
+    }
+}
@@ -0,0 +1,406 @@
+//! DNS providers. Each one turns the detected addresses into the update
+//! call of its API; dyndns-style providers answer `good` / `nochg`.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Addresses;
+
+/// Shown instead of the secrets; sent back unchanged, it keeps them.
+pub const MASK: &str = "********";
+
+/// Points a record to the detected addresses.
+pub trait Provider {
+    /// Update `name`; a family missing from `addrs` is left alone.
+    fn update(
+        &self,
+        http: &reqwest::Client,
+        name: &str,
+        addrs: &Addresses,
+    ) -> impl Future<Output = Result<(), String>> + Send;
+}
+
+/// Cloudflare API (A and AAAA records of a zone).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Cloudflare {
+    pub api_token: String,
+    pub zone_id: String,
+    /// Orange cloud; never set on the A record in cloud relay mode.
+    #[serde(default)]
+    pub proxied: bool,
+}
+
+/// DuckDNS (`<name>.duckdns.org`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuckDns {
+    pub token: String,
+}
+
+/// deSEC dynDNS (`dedyn.io` and the zones hosted there).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Desec {
+    pub token: String,
+}
+
+/// Dynu IP update protocol.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dynu {
+    #[serde(default)]
+    pub username: String,
+    /// Plain, MD5 or SHA-256 of the account (or IP update) password.
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    #[default]
+    Get,
+    Put,
+}
+
+/// Any other service: one request built from templates where `{name}`,
+/// `{ipv4}`, `{ipv6}` and `{ip}` (IPv6 when known, IPv4 otherwise) are
+/// replaced; a 2xx answer is a success.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpProvider {
+    #[serde(default)]
+    pub method: HttpMethod,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Values are secrets (authorization headers).
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProviderConfig {
+    Cloudflare(Cloudflare),
+    Duckdns(DuckDns),
+    Desec(Desec),
+    Dynu(Dynu),
+    Http(HttpProvider),
+}
+
+impl ProviderConfig {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Cloudflare(_) => "cloudflare",
+            Self::Duckdns(_) => "duckdns",
+            Self::Desec(_) => "desec",
+            Self::Dynu(_) => "dynu",
+            Self::Http(_) => "http",
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let required = |value: &str, what: &str| {
+            if value.trim().is_empty() {
+                Err(format!("{what} is required"))
+            } else {
+                Ok(())
+            }
+        };
+        match self {
+            Self::Cloudflare(p) => {
+                required(&p.api_token, "Cloudflare API token")?;
+                required(&p.zone_id, "Cloudflare zone ID")
+            }
+            Self::Duckdns(p) => required(&p.token, "DuckDNS token"),
+            Self::Desec(p) => required(&p.token, "deSEC token"),
+            Self::Dynu(p) => required(&p.password, "Dynu password"),
+            Self::Http(p) => {
+                if !p.url.starts_with("http://") && !p.url.starts_with("https://") {
+                    return Err(format!("Invalid update URL: {}", p.url));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn secrets_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Self::Cloudflare(p) => vec![&mut p.api_token],
+            Self::Duckdns(p) => vec![&mut p.token],
+            Self::Desec(p) => vec![&mut p.token],
+            Self::Dynu(p) => vec![&mut p.password],
+            Self::Http(p) => p.headers.values_mut().collect(),
+        }
+    }
+
+    /// Copy with the secrets replaced by [`MASK`].
+    pub fn masked(&self) -> Self {
+        let mut masked = self.clone();
+        for secret in masked.secrets_mut() {
+            if !secret.is_empty() {
+                *secret = MASK.to_string();
+            }
+        }
+        masked
+    }
+
+    /// Put back the secrets of `previous` where this one still has the mask.
+    pub fn restore_secrets(&mut self, previous: &ProviderConfig) {
+        match (self, previous) {
+            (Self::Http(current), Self::Http(previous)) => {
+                for (header, value) in current.headers.iter_mut() {
+                    if value == MASK
+                        && let Some(old) = previous.headers.get(header)
+                    {
+                        *value = old.clone();
+                    }
+                }
+            }
+            (current, previous) if current.kind() == previous.kind() => {
+                let mut previous = previous.clone();
+                for (secret, old) in current.secrets_mut().into_iter().zip(previous.secrets_mut()) {
+                    if secret == MASK {
+                        *secret = std::mem::take(old);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Provider for ProviderConfig {
+    async fn update(&self, http: &reqwest::Client, name: &str, addrs: &Addresses) -> Result<(), String> {
+        match self {
+            Self::Cloudflare(p) => p.update(http, name, addrs).await,
+            Self::Duckdns(p) => p.update(http, name, addrs).await,
+            Self::Desec(p) => p.update(http, name, addrs).await,
+            Self::Dynu(p) => p.update(http, name, addrs).await,
+            Self::Http(p) => p.update(http, name, addrs).await,
+        }
+    }
+}
+
+impl Provider for Cloudflare {
+    async fn update(&self, _http: &reqwest::Client, name: &str, addrs: &Addresses) -> Result<(), String> {
+        use hr_registry::cloudflare;
+        if let Some(ip) = addrs.ipv4 {
+            // The relay terminates TLS itself, it cannot sit behind the proxy
+            let proxied = self.proxied && !addrs.relayed;
+            cloudflare::upsert_a_record(&self.api_token, &self.zone_id, name, &ip.to_string(), proxied).await?;
+        }
+        if let Some(ip) = addrs.ipv6 {
+            cloudflare::upsert_aaaa_record(&self.api_token, &self.zone_id, name, &ip.to_string(), self.proxied)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+impl DuckDns {
+    /// Without `ip`, DuckDNS takes the IPv4 the request comes from.
+    pub(crate) fn request(&self, http: &reqwest::Client, name: &str, addrs: &Addresses) -> reqwest::RequestBuilder {
+        let domain = name.trim_end_matches('.').trim_end_matches(".duckdns.org");
+        let mut query = vec![("domains", domain.to_string()), ("token", self.token.clone())];
+        if let Some(ip) = addrs.ipv4 {
+            query.push(("ip", ip.to_string()));
+        }
+        if let Some(ip) = addrs.ipv6 {
+            query.push(("ipv6", ip.to_string()));
+        }
+        http.get("https://www.duckdns.org/update").query(&query)
+    }
+}
+
+impl Provider for DuckDns {
+    async fn update(&self, http: &reqwest::Client, name: &str, addrs: &Addresses) -> Result<(), String> {
+        let body = send(self.request(http, name, addrs)).await?;
+        match body.lines().next().map(str::trim) {
+            Some("OK") => Ok(()),
+            _ => Err(format!("DuckDNS refused the update: {}", body.trim())),
+        }
+    }
+}
+
+impl Desec {
+    /// `preserve` keeps the family that is not updated (deSEC would
+    /// otherwise clear it or use the requesting address).
+    pub(crate) fn request(&self, http: &reqwest::Client, name: &str, addrs: &Addresses) -> reqwest::RequestBuilder {
+        let or_preserve = |ip: Option<String>| ip.unwrap_or_else(|| "preserve".to_string());
+        http.get("https://update.dedyn.io/")
+            .header("Authorization", format!("Token {}", self.token))
+            .query(&[
+                ("hostname", name.to_string()),
+                ("myipv4", or_preserve(addrs.ipv4.map(|ip| ip.to_string()))),
+                ("myipv6", or_preserve(addrs.ipv6.map(|ip| ip.to_string()))),
+            ])
+    }
+}
+
+impl Provider for Desec {
+    async fn update(&self, http: &reqwest::Client, name: &str, addrs: &Addresses) -> Result<(), String> {
+        check_dyndns("deSEC", &send(self.request(http, name, addrs)).await?)
+    }
+}
+
+impl Dynu {
+    pub(crate) fn request(&self, http: &reqwest::Client, name: &str, addrs: &Addresses) -> reqwest::RequestBuilder {
+        let mut query = vec![("hostname", name.to_string())];
+        if let Some(ip) = addrs.ipv4 {
+            query.push(("myip", ip.to_string()));
+        }
+        if let Some(ip) = addrs.ipv6 {
+            query.push(("myipv6", ip.to_string()));
+        }
+        if !self.username.is_empty() {
+            query.push(("username", self.username.clone()));
+        }
+        query.push(("password", self.password.clone()));
+        http.get("https://api.dynu.com/nic/update").query(&query)
+    }
+}
+
+impl Provider for Dynu {
+    async fn update(&self, http: &reqwest::Client, name: &str, addrs: &Addresses) -> Result<(), String> {
+        check_dyndns("Dynu", &send(self.request(http, name, addrs)).await?)
+    }
+}
+
+/// Replace the placeholders of an HTTP provider template.
+pub(crate) fn render(template: &str, name: &str, addrs: &Addresses) -> String {
+    let ipv4 = addrs.ipv4.map(|ip| ip.to_string()).unwrap_or_default();
+    let ipv6 = addrs.ipv6.map(|ip| ip.to_string()).unwrap_or_default();
+    let ip = if ipv6.is_empty() { &ipv4 } else { &ipv6 };
+    template
+        .replace("{name}", name)
+        .replace("{ipv4}", &ipv4)
+        .replace("{ipv6}", &ipv6)
+        .replace("{ip}", ip)
+}
+
+impl HttpProvider {
+    pub(crate) fn request(&self, http: &reqwest::Client, name: &str, addrs: &Addresses) -> reqwest::RequestBuilder {
+        let url = render(&self.url, name, addrs);
+        let mut request = match self.method {
+            HttpMethod::Get => http.get(url),
+            HttpMethod::Put => http.put(url),
+        };
+        for (header, value) in &self.headers {
+            request = request.header(header, value);
+        }
+        if let Some(body) = &self.body {
+            request = request.body(render(body, name, addrs));
+        }
+        request
+    }
+}
+
+impl Provider for HttpProvider {
+    async fn update(&self, http: &reqwest::Client, name: &str, addrs: &Addresses) -> Result<(), String> {
+        send(self.request(http, name, addrs)).await.map(|_| ())
+    }
+}
+
+/// Send the request; a non-2xx answer is an error carrying its body.
+async fn send(request: reqwest::RequestBuilder) -> Result<String, String> {
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(format!("HTTP {}: {}", status.as_u16(), body.trim()));
+    }
+    Ok(body)
+}
+
+/// `good <ip>` and `nochg <ip>` are successes, anything else (`badauth`,
+/// `nohost`, `abuse`, `911`...) is an error.
+pub(crate) fn check_dyndns(provider: &str, body: &str) -> Result<(), String> {
+    match body.split_whitespace().next() {
+        Some("good") | Some("nochg") => Ok(()),
+        _ => Err(format!("{provider} refused the update: {}", body.trim())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn addrs(ipv4: bool, ipv6: bool) -> Addresses {
+        Addresses {
+            ipv4: ipv4.then(|| Ipv4Addr::new(203, 0, 113, 7)),
+            ipv6: ipv6.then(|| "2001:db8:1:2::1".parse::<Ipv6Addr>().unwrap()),
+            relayed: false,
+        }
+    }
+
+    fn url(request: reqwest::RequestBuilder) -> String {
+        request.build().unwrap().url().to_string()
+    }
+
+    #[test]
+    fn test_requests() {
+        let http = reqwest::Client::new();
+        let duck = DuckDns { token: "t0k".to_string() };
+        assert_eq!(
+            url(duck.request(&http, "home.duckdns.org", &addrs(true, true))),
+            "https://www.duckdns.org/update?domains=home&token=t0k&ip=203.0.113.7&ipv6=2001%3Adb8%3A1%3A2%3A%3A1"
+        );
+
+        let desec = Desec { token: "secret".to_string() }.request(&http, "home.dedyn.io", &addrs(false, true));
+        let request = desec.build().unwrap();
+        assert_eq!(request.headers()["authorization"], "Token secret");
+        assert_eq!(
+            request.url().as_str(),
+            "https://update.dedyn.io/?hostname=home.dedyn.io&myipv4=preserve&myipv6=2001%3Adb8%3A1%3A2%3A%3A1"
+        );
+
+        let dynu = Dynu { username: String::new(), password: "pw".to_string() };
+        assert_eq!(
+            url(dynu.request(&http, "home.dynu.net", &addrs(true, false))),
+            "https://api.dynu.com/nic/update?hostname=home.dynu.net&myip=203.0.113.7&password=pw"
+        );
+
+        let generic = HttpProvider {
+            method: HttpMethod::Put,
+            url: "https://dns.example.com/update/{name}?addr={ip}".to_string(),
+            body: Some(r#"{"a":"{ipv4}","aaaa":"{ipv6}"}"#.to_string()),
+            headers: BTreeMap::from([("Authorization".to_string(), "Bearer x".to_string())]),
+        };
+        let request = generic.request(&http, "home.example.com", &addrs(true, false)).build().unwrap();
+        assert_eq!(request.method(), reqwest::Method::PUT);
+        assert_eq!(request.url().as_str(), "https://dns.example.com/update/home.example.com?addr=203.0.113.7");
+        assert_eq!(request.body().unwrap().as_bytes().unwrap(), br#"{"a":"203.0.113.7","aaaa":""}"#);
+    }
+
+    #[test]
+    fn test_check_dyndns() {
+        assert!(check_dyndns("Dynu", "good 203.0.113.7").is_ok());
+        assert!(check_dyndns("Dynu", "nochg\n").is_ok());
+        assert_eq!(check_dyndns("Dynu", "badauth").unwrap_err(), "Dynu refused the update: badauth");
+        assert!(check_dyndns("deSEC", "").is_err());
+    }
+
+    #[test]
+    fn test_secrets() {
+        let provider: ProviderConfig =
+            serde_json::from_str(r#"{"type": "cloudflare", "apiToken": "abc", "zoneId": "z1"}"#).unwrap();
+        let mut masked = provider.masked();
+        assert_eq!(masked, ProviderConfig::Cloudflare(Cloudflare {
+            api_token: MASK.to_string(),
+            zone_id: "z1".to_string(),
+            proxied: false,
+        }));
+        masked.restore_secrets(&provider);
+        assert_eq!(masked, provider);
+
+        // Another provider type does not inherit the token
+        let mut duck = ProviderConfig::Duckdns(DuckDns { token: MASK.to_string() });
+        duck.restore_secrets(&provider);
+        assert_eq!(duck, ProviderConfig::Duckdns(DuckDns { token: MASK.to_string() }));
+    }
+}
@@ -0,0 +1,276 @@
+//! The `ddns` service: detects the addresses every interval (and when the
+//! config, the cloud relay mode or the delegated prefix change), pushes the
+//! records whose address changed and retries failures with backoff.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use hr_common::events::{DdnsEvent, EventBus};
+use hr_ipv6::PrefixWatch;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::config::{DdnsConfig, DdnsRecord, Ipv4Source, Ipv6Source};
+use crate::detect;
+use crate::provider::Provider;
+use crate::{Addresses, RecordStatus, SharedDdns};
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// What a provider last accepted for a record, and its retry schedule.
+#[derive(Default)]
+struct Published {
+    /// Config it was pushed with; a new one starts over.
+    record: Option<DdnsRecord>,
+    addrs: Addresses,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+/// 30 s, 1 min, 2 min... up to 1 h.
+pub(crate) fn backoff(failures: u32) -> Duration {
+    Duration::from_secs(30u64.saturating_mul(1 << failures.saturating_sub(1).min(7)).min(3600))
+}
+
+pub async fn run_ddns(
+    ddns: SharedDdns,
+    events: Arc<EventBus>,
+    mut relay: watch::Receiver<bool>,
+    mut prefix: PrefixWatch,
+    data_dir: PathBuf,
+) -> Result<()> {
+    let http = reqwest::Client::builder().timeout(HTTP_TIMEOUT).build()?;
+    let mut config_rx = ddns.subscribe();
+    let mut published: HashMap<String, Published> = HashMap::new();
+    loop {
+        let config = config_rx.borrow_and_update().clone();
+        let relayed = *relay.borrow_and_update();
+        prefix.borrow_and_update();
+        let force = ddns.take_forced();
+        check(&ddns, &events, &http, &config, relayed, &prefix, &data_dir, &mut published, force).await;
+
+        let now = Instant::now();
+        let wait = published
+            .values()
+            .filter_map(|p| p.retry_at)
+            .map(|at| at.saturating_duration_since(now))
+            .fold(Duration::from_secs(config.interval_secs), Duration::min);
+        tokio::select! {
+            changed = config_rx.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+            }
+            Ok(()) = relay.changed() => {}
+            Ok(()) = prefix.changed() => {}
+            _ = ddns.wake.notified() => {}
+            _ = tokio::time::sleep(wait) => {}
+        }
+    }
+}
+
+/// Detected addresses; an error of one family does not stop the other.
+async fn detect(
+    config: &DdnsConfig,
+    http: &reqwest::Client,
+    relayed: bool,
+    prefix: &PrefixWatch,
+    data_dir: &std::path::Path,
+) -> (Addresses, Vec<String>) {
+    let mut errors = Vec::new();
+    let ipv4 = if relayed {
+        // Clients reach the VPS, which tunnels to us
+        let ip = detect::relay_vps_ipv4(data_dir);
+        if ip.is_none() {
+            errors.push("Cloud relay enabled but its VPS IPv4 is unknown".to_string());
+        }
+        ip
+    } else {
+        match config.ipv4.source {
+            Ipv4Source::None => None,
+            Ipv4Source::Interface => {
+                let ip = detect::interface_ipv4(&config.ipv4.interface).await;
+                if ip.is_none() {
+                    errors.push(format!("No global IPv4 on {}", config.ipv4.interface));
+                }
+                ip
+            }
+            Ipv4Source::Web => detect::web_ipv4(http, &config.ipv4.url).await.map_err(|e| errors.push(e)).ok(),
+        }
+    };
+    // In relay mode the AAAA records would bypass the relay
+    let ipv6 = if relayed {
+        None
+    } else {
+        match config.ipv6.source {
+            Ipv6Source::None => None,
+            Ipv6Source::Interface => {
+                let ip = detect::interface_ipv6(&config.ipv6.interface).await;
+                if ip.is_none() {
+                    errors.push(format!("No global IPv6 on {}", config.ipv6.interface));
+                }
+                ip
+            }
+            Ipv6Source::Prefix => {
+                let ip = detect::delegated_ipv6(prefix, &config.ipv6.suffix);
+                if ip.is_none() {
+                    errors.push("No delegated IPv6 prefix".to_string());
+                }
+                ip
+            }
+            Ipv6Source::Web => detect::web_ipv6(http, &config.ipv6.url).await.map_err(|e| errors.push(e)).ok(),
+        }
+    };
+    (Addresses { ipv4, ipv6, relayed }, errors)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn check(
+    ddns: &SharedDdns,
+    events: &EventBus,
+    http: &reqwest::Client,
+    config: &DdnsConfig,
+    relayed: bool,
+    prefix: &PrefixWatch,
+    data_dir: &std::path::Path,
+    published: &mut HashMap<String, Published>,
+    force: bool,
+) {
+    published.retain(|id, _| config.records.iter().any(|r| &r.id == id));
+    if !config.enabled {
+        ddns.update_status(|s| {
+            s.active = false;
+            s.records.clear();
+        });
+        return;
+    }
+
+    let (detected, errors) = detect(config, http, relayed, prefix, data_dir).await;
+    ddns.update_status(|s| {
+        s.active = true;
+        s.relayed = relayed;
+        s.ipv4 = detected.ipv4.map(|ip| ip.to_string());
+        s.ipv6 = detected.ipv6.map(|ip| ip.to_string());
+        s.detection_error = (!errors.is_empty()).then(|| errors.join("; "));
+        s.last_check = Some(chrono::Utc::now().to_rfc3339());
+    });
+
+    let mut statuses = Vec::new();
+    for record in config.records.iter().filter(|r| r.enabled) {
+        let state = published.entry(record.id.clone()).or_default();
+        if state.record.as_ref() != Some(record) {
+            *state = Published { record: Some(record.clone()), ..Default::default() };
+        }
+        let wanted = Addresses {
+            ipv4: detected.ipv4.filter(|_| record.ipv4),
+            ipv6: detected.ipv6.filter(|_| record.ipv6),
+            relayed,
+        };
+        // An address that disappeared is left in place, not removed
+        let changed = (wanted.ipv4.is_some() && wanted.ipv4 != state.addrs.ipv4)
+            || (wanted.ipv6.is_some() && wanted.ipv6 != state.addrs.ipv6);
+        let waiting = state.retry_at.is_some_and(|at| Instant::now() < at);
+        let due = (wanted.ipv4.is_some() || wanted.ipv6.is_some()) && (force || (changed && !waiting));
+
+        let mut status = previous_status(ddns, record);
+        if due {
+            let result = record.provider.update(http, &record.name, &wanted).await;
+            hr_common::metrics::registry()
+                .counter(
+                    "homeroute_ddns_updates_total",
+                    "DDNS record updates pushed to the providers, by result.",
+                    &[("provider", record.provider.kind()), ("result", if result.is_ok() { "ok" } else { "error" })],
+                )
+                .inc();
+            match result {
+                Ok(()) => {
+                    let previous = state.addrs;
+                    state.addrs = Addresses {
+                        ipv4: wanted.ipv4.or(previous.ipv4),
+                        ipv6: wanted.ipv6.or(previous.ipv6),
+                        relayed,
+                    };
+                    state.failures = 0;
+                    state.retry_at = None;
+                    status.last_update = Some(chrono::Utc::now().to_rfc3339());
+                    status.last_error = None;
+                    let summary = describe(&wanted);
+                    info!(record = record.name, provider = record.provider.kind(), "DDNS record updated: {summary}");
+                    ddns.log(format!("Updated {} ({}) to {summary}", record.name, record.provider.kind()));
+                    if changed {
+                        let _ = events.ddns.send(DdnsEvent {
+                            record_id: record.id.clone(),
+                            name: record.name.clone(),
+                            provider: record.provider.kind().to_string(),
+                            ipv4: state.addrs.ipv4.map(|ip| ip.to_string()),
+                            ipv6: state.addrs.ipv6.map(|ip| ip.to_string()),
+                            previous_ipv4: previous.ipv4.map(|ip| ip.to_string()),
+                            previous_ipv6: previous.ipv6.map(|ip| ip.to_string()),
+                            updated_at: chrono::Utc::now().to_rfc3339(),
+                        });
+                    }
+                }
+                Err(e) => {
+                    state.failures += 1;
+                    let delay = backoff(state.failures);
+                    state.retry_at = Some(Instant::now() + delay);
+                    status.last_error = Some(e.clone());
+                    warn!(record = record.name, failures = state.failures, "DDNS update failed: {e}");
+                    ddns.log(format!("Update of {} failed: {e}", record.name));
+                }
+            }
+        }
+        status.ipv4 = state.addrs.ipv4.map(|ip| ip.to_string());
+        status.ipv6 = state.addrs.ipv6.map(|ip| ip.to_string());
+        status.failures = state.failures;
+        status.next_retry = state.retry_at.map(|at| {
+            (chrono::Utc::now() + at.saturating_duration_since(Instant::now())).to_rfc3339()
+        });
+        statuses.push(status);
+    }
+    ddns.update_status(|s| s.records = statuses);
+}
+
+/// Status of the record from the last check (keeps its last update time).
+fn previous_status(ddns: &SharedDdns, record: &DdnsRecord) -> RecordStatus {
+    let previous = ddns.status().records.into_iter().find(|r| r.id == record.id);
+    RecordStatus {
+        id: record.id.clone(),
+        name: record.name.clone(),
+        provider: record.provider.kind().to_string(),
+        last_update: previous.as_ref().and_then(|p| p.last_update.clone()),
+        last_error: previous.and_then(|p| p.last_error),
+        ..Default::default()
+    }
+}
+
+fn describe(addrs: &Addresses) -> String {
+    let mut parts = Vec::new();
+    if let Some(ip) = addrs.ipv4 {
+        parts.push(format!("A {ip}"));
+    }
+    if let Some(ip) = addrs.ipv6 {
+        parts.push(format!("AAAA {ip}"));
+    }
+    if addrs.relayed {
+        parts.push("(relay mode)".to_string());
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_secs(30));
+        assert_eq!(backoff(2), Duration::from_secs(60));
+        assert_eq!(backoff(5), Duration::from_secs(480));
+        assert_eq!(backoff(8), Duration::from_secs(3600));
+        assert_eq!(backoff(40), Duration::from_secs(3600));
+    }
+}
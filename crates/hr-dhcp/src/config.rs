@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DhcpConfig {
@@ -18,6 +19,9 @@ pub struct DhcpConfig {
     pub dns_server: String,
     #[serde(default)]
     pub domain: String,
+    /// NTP servers advertised to clients (option 42).
+    #[serde(default)]
+    pub ntp_servers: Vec<String>,
     #[serde(default = "default_lease_time")]
     pub default_lease_time_secs: u64,
     #[serde(default)]
@@ -58,6 +62,52 @@ impl Default for DhcpConfig {
     }
 }
 
+impl DhcpConfig {
+    /// Cross-checks of an enabled config: the range, gateway, DNS server and
+    /// static leases must all be inside the subnet of the range.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let start = parse_ipv4("range_start", &self.range_start)?;
+        let end = parse_ipv4("range_end", &self.range_end)?;
+        let mask = u32::from(parse_ipv4("netmask", &self.netmask)?);
+        if mask.leading_ones() + mask.trailing_zeros() != 32 || mask == 0 {
+            return Err(format!("Invalid netmask: {}", self.netmask));
+        }
+        if start > end {
+            return Err(format!("DHCP range starts after its end: {} > {}", start, end));
+        }
+        let network = u32::from(start) & mask;
+        let inside = |ip: Ipv4Addr| u32::from(ip) & mask == network;
+        let subnet = format!("{}/{}", Ipv4Addr::from(network), mask.leading_ones());
+        if !inside(end) {
+            return Err(format!("DHCP range {} - {} is not inside {}", start, end, subnet));
+        }
+        for (field, value) in [("gateway", &self.gateway), ("dns_server", &self.dns_server)] {
+            if !value.is_empty() && !inside(parse_ipv4(field, value)?) {
+                return Err(format!("{} {} is not inside {}", field, value, subnet));
+            }
+        }
+        let mut macs = std::collections::HashSet::new();
+        for lease in &self.static_leases {
+            if !macs.insert(lease.mac.to_lowercase()) {
+                return Err(format!("Duplicate static lease for {}", lease.mac));
+            }
+            if !inside(parse_ipv4("static lease", &lease.ip)?) {
+                return Err(format!("Static lease {} ({}) is not inside {}", lease.ip, lease.mac, subnet));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_ipv4(field: &str, value: &str) -> Result<Ipv4Addr, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid {}: '{}'", field, value))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -83,5 +133,53 @@ mod tests {
         let config: DhcpConfig = serde_json::from_str(json).unwrap();
         assert_eq!(config.range_start, "10.0.0.10");
         assert_eq!(config.range_end, "10.0.0.200");
+        assert!(config.ntp_servers.is_empty());
+    }
+
+    fn lan() -> DhcpConfig {
+        serde_json::from_str(
+            r#"{
+                "range_start": "192.168.1.100",
+                "range_end": "192.168.1.200",
+                "gateway": "192.168.1.1",
+                "static_leases": [{"mac": "aa:bb:cc:dd:ee:ff", "ip": "192.168.1.10"}]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(lan().validate().is_ok());
+
+        let mut config = lan();
+        config.range_end = "192.168.2.10".to_string();
+        assert!(config.validate().unwrap_err().contains("not inside 192.168.1.0/24"));
+
+        let mut config = lan();
+        config.range_start = "192.168.1.201".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = lan();
+        config.gateway = "10.0.0.1".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = lan();
+        config.static_leases[0].ip = "192.168.0.10".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = lan();
+        config.netmask = "255.0.255.0".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = lan();
+        config.netmask = "255.255.0.0".to_string();
+        config.range_end = "192.168.2.10".to_string();
+        assert!(config.validate().is_ok());
+
+        let mut config = DhcpConfig::default();
+        assert!(config.validate().is_err());
+        config.enabled = false;
+        assert!(config.validate().is_ok());
     }
 }
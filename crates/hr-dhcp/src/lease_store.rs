@@ -3,8 +3,12 @@ use std::net::Ipv4Addr;
 use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
 use anyhow::{Context, Result};
+use tokio::sync::broadcast;
 use tracing::{info, warn};
 
+/// Changes buffered for a slow subscriber before it has to resync.
+const EVENT_CAPACITY: usize = 1024;
+
 /// A DHCP lease
 #[derive(Debug, Clone)]
 pub struct Lease {
@@ -15,12 +19,24 @@ pub struct Lease {
     pub client_id: Option<String>,
 }
 
+/// A change to the lease set, sent to the copies of the store (DNS).
+#[derive(Debug, Clone)]
+pub enum LeaseEvent {
+    /// Lease added or renewed; applied with `add_lease`.
+    Upsert(Lease),
+    Removed(Ipv4Addr),
+    /// The whole set was reloaded from the file: copies must resync.
+    Reloaded,
+}
+
 /// DHCP lease store with indexes for fast lookups
 pub struct LeaseStore {
     leases: HashMap<Ipv4Addr, Lease>,
     by_mac: HashMap<String, Ipv4Addr>,
     by_hostname: HashMap<String, Ipv4Addr>,
     file_path: PathBuf,
+    /// Change notifications, created by the first `subscribe`.
+    events: Option<broadcast::Sender<LeaseEvent>>,
 }
 
 impl LeaseStore {
@@ -30,7 +46,39 @@ impl LeaseStore {
             by_mac: HashMap::new(),
             by_hostname: HashMap::new(),
             file_path: PathBuf::from(file_path),
+            events: None,
+        }
+    }
+
+    /// Changes made to the store from now on.
+    pub fn subscribe(&mut self) -> broadcast::Receiver<LeaseEvent> {
+        self.events.get_or_insert_with(|| broadcast::channel(EVENT_CAPACITY).0).subscribe()
+    }
+
+    fn notify(&self, event: LeaseEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+
+    /// Apply a change received from another store.
+    pub fn apply(&mut self, event: LeaseEvent) {
+        match event {
+            LeaseEvent::Upsert(lease) => self.add_lease(lease),
+            LeaseEvent::Removed(ip) => self.remove_lease(ip),
+            LeaseEvent::Reloaded => {}
+        }
+    }
+
+    /// Replace every lease (resync of a copy).
+    pub fn replace_all(&mut self, leases: Vec<Lease>) {
+        self.leases.clear();
+        self.by_mac.clear();
+        self.by_hostname.clear();
+        for lease in leases {
+            self.add_lease_inner(lease);
         }
+        self.notify(LeaseEvent::Reloaded);
     }
 
     /// Load leases from dnsmasq-compatible file format.
@@ -94,6 +142,7 @@ impl LeaseStore {
         }
 
         info!("Loaded {} leases from {}", count, path.display());
+        self.notify(LeaseEvent::Reloaded);
         Ok(count)
     }
 
@@ -149,6 +198,9 @@ impl LeaseStore {
                 self.leases.remove(old_ip);
             }
         }
+        if self.events.is_some() {
+            self.notify(LeaseEvent::Upsert(lease.clone()));
+        }
         self.add_lease_inner(lease);
     }
 
@@ -159,6 +211,7 @@ impl LeaseStore {
             if let Some(ref hostname) = lease.hostname {
                 self.by_hostname.remove(&hostname.to_lowercase());
             }
+            self.notify(LeaseEvent::Removed(ip));
         }
     }
 
@@ -325,6 +378,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_events_keep_copy_in_step() {
+        let mut store = LeaseStore::new("/tmp/test-leases");
+        let mut copy = LeaseStore::new("/tmp/test-leases");
+        let mut events = store.subscribe();
+        let lease = |mac: &str, ip: u8, hostname: &str| Lease {
+            expiry: u64::MAX,
+            mac: mac.to_string(),
+            ip: Ipv4Addr::new(10, 0, 0, ip),
+            hostname: Some(hostname.to_string()),
+            client_id: None,
+        };
+
+        store.add_lease(lease("aa:bb:cc:dd:ee:01", 50, "laptop"));
+        store.add_lease(lease("aa:bb:cc:dd:ee:02", 51, "phone"));
+        store.add_lease(lease("aa:bb:cc:dd:ee:01", 50, "laptop-2"));
+        store.remove_lease(Ipv4Addr::new(10, 0, 0, 51));
+        while let Ok(event) = events.try_recv() {
+            copy.apply(event);
+        }
+        assert_eq!(copy.find_ip_by_hostname("laptop-2"), Some(Ipv4Addr::new(10, 0, 0, 50)));
+        assert_eq!(copy.find_ip_by_hostname("laptop"), None);
+        assert_eq!(copy.find_ip_by_hostname("phone"), None);
+        assert_eq!(copy.all_leases().len(), 1);
+
+        store.replace_all(Vec::new());
+        assert!(matches!(events.try_recv(), Ok(LeaseEvent::Reloaded)));
+    }
+
     #[test]
     fn test_allocate_ip() {
         let store = LeaseStore::new("/tmp/test-leases");
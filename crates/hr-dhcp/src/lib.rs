@@ -6,11 +6,12 @@ pub mod state_machine;
 pub mod server;
 
 pub use config::DhcpConfig;
-pub use lease_store::LeaseStore;
+pub use lease_store::{LeaseEvent, LeaseStore};
 
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use std::net::Ipv4Addr;
+use tracing::warn;
 
 pub struct DhcpState {
     pub config: config::DhcpConfig,
@@ -18,4 +19,51 @@ pub struct DhcpState {
     pub server_ip: Ipv4Addr,
 }
 
+impl DhcpState {
+    /// Size of the dynamic range and addresses of it currently leased.
+    pub fn pool_usage(&self) -> (u32, u32) {
+        let (Ok(start), Ok(end)) = (
+            self.config.range_start.parse::<Ipv4Addr>(),
+            self.config.range_end.parse::<Ipv4Addr>(),
+        ) else {
+            return (0, 0);
+        };
+        let range = u32::from(start)..=u32::from(end);
+        let size = u32::from(end).checked_sub(u32::from(start)).map_or(0, |d| d + 1);
+        let used = self
+            .lease_store
+            .all_leases()
+            .iter()
+            .filter(|l| range.contains(&u32::from(l.ip)) && self.lease_store.is_ip_in_use(l.ip))
+            .count() as u32;
+        (size, used)
+    }
+}
+
 pub type SharedDhcpState = Arc<RwLock<DhcpState>>;
+
+/// Keep `copy` (the lease store of the DNS) in step with the DHCP store,
+/// change by change. The leases are copied whole only at start and when
+/// changes were missed or the file reloaded.
+pub async fn mirror_leases(dhcp: SharedDhcpState, copy: Arc<RwLock<LeaseStore>>) -> anyhow::Result<()> {
+    loop {
+        // Subscribe and snapshot under the same lock: no change falls between
+        let (mut events, leases) = {
+            let mut state = dhcp.write().await;
+            let events = state.lease_store.subscribe();
+            (events, state.lease_store.all_leases().into_iter().cloned().collect())
+        };
+        copy.write().await.replace_all(leases);
+        loop {
+            match events.recv().await {
+                Ok(LeaseEvent::Reloaded) => break,
+                Ok(event) => copy.write().await.apply(event),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("Lease copy missed {} changes, resyncing", missed);
+                    break;
+                }
+                Err(broadcast::error::RecvError::Closed) => anyhow::bail!("DHCP lease store dropped"),
+            }
+        }
+    }
+}
@@ -7,6 +7,7 @@ pub const OPT_DNS_SERVER: u8 = 6;
 pub const OPT_HOSTNAME: u8 = 12;
 pub const OPT_DOMAIN_NAME: u8 = 15;
 pub const OPT_BROADCAST_ADDR: u8 = 28;
+pub const OPT_NTP_SERVERS: u8 = 42;
 pub const OPT_REQUESTED_IP: u8 = 50;
 pub const OPT_LEASE_TIME: u8 = 51;
 pub const OPT_RENEWAL_TIME: u8 = 58;
@@ -84,6 +85,10 @@ impl DhcpOption {
         Self::new(OPT_BROADCAST_ADDR, ip.octets().to_vec())
     }
 
+    pub fn ntp_servers(ips: &[Ipv4Addr]) -> Self {
+        Self::new(OPT_NTP_SERVERS, ips.iter().flat_map(|ip| ip.octets()).collect())
+    }
+
     /// Extract IPv4 address from option data
     pub fn as_ipv4(&self) -> Option<Ipv4Addr> {
         if self.data.len() == 4 {
@@ -15,6 +15,17 @@ pub fn handle_dhcp_packet(
     server_ip: Ipv4Addr,
 ) -> Option<DhcpPacket> {
     let msg_type = packet.msg_type()?;
+    let type_name = match msg_type {
+        DHCPDISCOVER => "discover",
+        DHCPREQUEST => "request",
+        DHCPRELEASE => "release",
+        DHCPINFORM => "inform",
+        DHCPDECLINE => "decline",
+        _ => "other",
+    };
+    hr_common::metrics::registry()
+        .counter("homeroute_dhcp_messages_total", "DHCP messages received, by type.", &[("type", type_name)])
+        .inc();
 
     match msg_type {
         DHCPDISCOVER => handle_discover(packet, config, lease_store, server_ip),
@@ -280,6 +291,11 @@ fn build_standard_options(config: &DhcpConfig, server_ip: Ipv4Addr) -> Vec<DhcpO
         opts.push(DhcpOption::domain_name(&config.domain));
     }
 
+    let ntp: Vec<Ipv4Addr> = config.ntp_servers.iter().filter_map(|s| s.parse().ok()).collect();
+    if !ntp.is_empty() {
+        opts.push(DhcpOption::ntp_servers(&ntp));
+    }
+
     // Broadcast address: network_address | ~netmask
     if let (Ok(gw), Ok(mask)) = (
         config.gateway.parse::<Ipv4Addr>(),
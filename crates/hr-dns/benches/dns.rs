@@ -0,0 +1,60 @@
+//! Hot path of the DNS server: wire parsing, response encoding and cache hits.
+//! Run with `cargo bench -p hr-dns`.
+
+use std::net::Ipv4Addr;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use hr_dns::cache::DnsCache;
+use hr_dns::packet::{build_response, encode_name, parse_query, parse_response_records, RCODE_NOERROR};
+use hr_dns::records::{DnsRecord, RecordType};
+
+fn query_bytes(name: &str) -> Vec<u8> {
+    // ID=0x1234, RD, one question
+    let mut buf = vec![0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+    encode_name(name, &mut buf);
+    buf.extend_from_slice(&[0x00, 0x01, 0x00, 0x01]); // A, IN
+    buf
+}
+
+fn answers(name: &str) -> Vec<DnsRecord> {
+    (1..=4)
+        .map(|i| DnsRecord::a(name, Ipv4Addr::new(192, 0, 2, i), 300))
+        .collect()
+}
+
+fn packet(c: &mut Criterion) {
+    let name = "www.example.com";
+    let raw = query_bytes(name);
+    let query = parse_query(&raw).unwrap();
+    let records = answers(name);
+    let response = build_response(&query, &records, RCODE_NOERROR);
+
+    c.bench_function("parse_query", |b| b.iter(|| parse_query(black_box(&raw)).unwrap()));
+    c.bench_function("build_response_4a", |b| {
+        b.iter(|| build_response(black_box(&query), black_box(&records), RCODE_NOERROR))
+    });
+    c.bench_function("parse_response_4a", |b| {
+        b.iter(|| parse_response_records(black_box(&response)).unwrap())
+    });
+}
+
+fn cache(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let cache = DnsCache::new(10_000);
+    rt.block_on(async {
+        for i in 0..5_000 {
+            let name = format!("host{}.example.com", i);
+            cache.insert(&name, RecordType::A, &answers(&name)).await;
+        }
+    });
+
+    c.bench_function("cache_hit", |b| {
+        b.iter(|| rt.block_on(cache.get(black_box("host2500.example.com"), RecordType::A)))
+    });
+    c.bench_function("cache_miss", |b| {
+        b.iter(|| rt.block_on(cache.get(black_box("absent.example.com"), RecordType::A)))
+    });
+}
+
+criterion_group!(benches, packet, cache);
+criterion_main!(benches);
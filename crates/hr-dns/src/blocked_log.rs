@@ -0,0 +1,293 @@
+//! Recently blocked queries, for the "why is this app broken" view.
+//!
+//! A ring buffer of the last blocked queries with the client and the cause,
+//! optionally appended to a JSON lines file and reloaded at startup (the
+//! file is compacted to the buffer size then).
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{debug, error, warn};
+
+/// Entries kept when the config does not say.
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+/// What blocked a query.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "lowercase")]
+pub enum BlockReason {
+    /// A filter list rule matched the name.
+    Rule { rule: String, source: String },
+    /// The name is a CNAME to a blocked tracker.
+    Cname { rule: String, source: String, target: String },
+    /// A scheduled profile blocks the name for this client.
+    Profile { profile: String },
+}
+
+impl BlockReason {
+    pub fn from_match(m: hr_adblock::RuleMatch, query: &str) -> Self {
+        if m.name.eq_ignore_ascii_case(query) {
+            Self::Rule { rule: m.rule, source: m.source }
+        } else {
+            Self::Cname { rule: m.rule, source: m.source, target: m.name }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockedEntry {
+    pub id: u64,
+    pub ts: DateTime<Utc>,
+    pub domain: String,
+    #[serde(rename = "type")]
+    pub query_type: String,
+    pub client: String,
+    /// DHCP hostname of the client.
+    #[serde(default)]
+    pub client_name: Option<String>,
+    #[serde(flatten)]
+    pub reason: BlockReason,
+}
+
+pub struct BlockedLog {
+    entries: Mutex<VecDeque<BlockedEntry>>,
+    capacity: usize,
+    writer: Option<mpsc::UnboundedSender<String>>,
+}
+
+impl Default for BlockedLog {
+    fn default() -> Self {
+        Self::in_memory(DEFAULT_CAPACITY)
+    }
+}
+
+impl BlockedLog {
+    pub fn in_memory(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::with_capacity(capacity.min(DEFAULT_CAPACITY))),
+            capacity: capacity.max(1),
+            writer: None,
+        }
+    }
+
+    /// Reload the last `capacity` entries of `path`, then append the new
+    /// ones to it from a background task.
+    pub fn persistent(capacity: usize, path: &Path) -> Self {
+        let mut log = Self::in_memory(capacity);
+        let entries = load(path, log.capacity);
+        if !entries.is_empty() {
+            debug!("Loaded {} blocked queries from {}", entries.len(), path.display());
+        }
+        if let Err(e) = compact(path, &entries) {
+            warn!("Failed to compact blocked query log {}: {}", path.display(), e);
+        }
+        *log.entries.get_mut().unwrap() = entries.into();
+        log.writer = Some(spawn_writer(path.to_path_buf()));
+        log
+    }
+
+    pub fn record(
+        &self,
+        domain: &str,
+        query_type: &str,
+        client: &str,
+        client_name: Option<String>,
+        reason: BlockReason,
+    ) {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = BlockedEntry {
+            id: entries.back().map_or(1, |e| e.id + 1),
+            ts: Utc::now(),
+            domain: domain.trim_end_matches('.').to_lowercase(),
+            query_type: query_type.to_string(),
+            client: client.to_string(),
+            client_name,
+            reason,
+        };
+        if let Some(writer) = &self.writer
+            && let Ok(json) = serde_json::to_string(&entry)
+            && writer.send(format!("{json}\n")).is_err()
+        {
+            debug!("Blocked query log channel closed");
+        }
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Entries after `since` (every entry when `None`), optionally of one
+    /// client, newest first.
+    pub fn since(&self, since: Option<DateTime<Utc>>, client: Option<&str>, limit: usize) -> Vec<BlockedEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .take_while(|e| since.is_none_or(|s| e.ts > s))
+            .filter(|e| client.is_none_or(|c| e.client == c))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<BlockedEntry> {
+        self.entries.lock().unwrap().iter().rev().find(|e| e.id == id).cloned()
+    }
+
+    /// Newest entry of `domain`, the one of `client` if any.
+    pub fn last(&self, domain: &str, client: &str) -> Option<BlockedEntry> {
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        let entries = self.entries.lock().unwrap();
+        let mut matching = entries.iter().rev().filter(|e| e.domain == domain);
+        let newest = matching.next()?;
+        if newest.client == client {
+            return Some(newest.clone());
+        }
+        Some(matching.find(|e| e.client == client).unwrap_or(newest).clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn load(path: &Path, capacity: usize) -> Vec<BlockedEntry> {
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let entries: Vec<BlockedEntry> = content.lines().filter_map(|l| serde_json::from_str(l).ok()).collect();
+    let skip = entries.len().saturating_sub(capacity);
+    entries.into_iter().skip(skip).collect()
+}
+
+fn compact(path: &Path, entries: &[BlockedEntry]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut content = String::new();
+    for entry in entries {
+        if let Ok(json) = serde_json::to_string(entry) {
+            content.push_str(&json);
+            content.push('\n');
+        }
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(&tmp, path)
+}
+
+fn spawn_writer(path: PathBuf) -> mpsc::UnboundedSender<String> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        use tokio::fs::OpenOptions;
+        use tokio::io::AsyncWriteExt;
+
+        let mut file = match OpenOptions::new().create(true).append(true).open(&path).await {
+            Ok(f) => f,
+            Err(e) => {
+                error!("Failed to open blocked query log {}: {}", path.display(), e);
+                return;
+            }
+        };
+        while let Some(line) = receiver.recv().await {
+            if let Err(e) = file.write_all(line.as_bytes()).await {
+                error!("Failed to write to blocked query log: {}", e);
+            }
+        }
+    });
+    sender
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(rule: &str) -> BlockReason {
+        BlockReason::Rule { rule: rule.to_string(), source: "EasyList".to_string() }
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let log = BlockedLog::in_memory(2);
+        log.record("a.example", "A", "192.168.1.10", None, rule("||a.example^"));
+        let first = log.since(None, None, 10)[0].ts;
+        log.record("b.example", "A", "192.168.1.11", Some("tablet".into()), rule("||b.example^"));
+        log.record("c.example.", "AAAA", "192.168.1.10", None, rule("||c.example^"));
+
+        let all = log.since(None, None, 10);
+        assert_eq!(all.iter().map(|e| e.domain.as_str()).collect::<Vec<_>>(), ["c.example", "b.example"]);
+        assert_eq!(all[0].id, 3);
+        assert!(log.get(1).is_none());
+        assert_eq!(log.get(2).unwrap().client_name.as_deref(), Some("tablet"));
+        assert_eq!(log.since(None, Some("192.168.1.10"), 10).len(), 1);
+        assert!(log.since(Some(Utc::now()), None, 10).is_empty());
+        assert!(log.since(Some(first), None, 10).len() <= 2);
+    }
+
+    #[test]
+    fn test_last() {
+        let log = BlockedLog::in_memory(10);
+        log.record("a.example", "A", "192.168.1.10", None, rule("||a.example^"));
+        log.record("a.example", "A", "192.168.1.11", None, rule("||a.example^"));
+        assert_eq!(log.last("A.example.", "192.168.1.10").unwrap().id, 1);
+        assert_eq!(log.last("a.example", "192.168.1.12").unwrap().id, 2);
+        assert!(log.last("b.example", "192.168.1.10").is_none());
+    }
+
+    #[test]
+    fn test_entry_format() {
+        let entry = BlockedEntry {
+            id: 7,
+            ts: Utc::now(),
+            domain: "metrics.shop.example".to_string(),
+            query_type: "A".to_string(),
+            client: "192.168.1.10".to_string(),
+            client_name: None,
+            reason: BlockReason::Cname {
+                rule: "||tracker.example^".to_string(),
+                source: "AdGuard".to_string(),
+                target: "shop.tracker.example".to_string(),
+            },
+        };
+        let json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["reason"], "cname");
+        assert_eq!(json["target"], "shop.tracker.example");
+        assert_eq!(json["type"], "A");
+        assert_eq!(serde_json::from_value::<BlockedEntry>(json).unwrap(), entry);
+    }
+
+    #[tokio::test]
+    async fn test_persistence() {
+        let path = std::env::temp_dir().join(format!("hr-dns-blocked-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let log = BlockedLog::persistent(2, &path);
+            for d in ["a.example", "b.example", "c.example"] {
+                log.record(d, "A", "192.168.1.10", None, BlockReason::Profile { profile: "Kids".into() });
+            }
+            // Let the writer flush
+            for _ in 0..50 {
+                if std::fs::read_to_string(&path).unwrap_or_default().lines().count() == 3 {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        }
+        let log = BlockedLog::persistent(2, &path);
+        let entries = log.since(None, None, 10);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].domain, "c.example");
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+        let _ = std::fs::remove_file(&path);
+    }
+}
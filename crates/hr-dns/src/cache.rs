@@ -4,6 +4,11 @@ use tokio::sync::RwLock;
 
 use crate::records::{DnsRecord, RecordType};
 
+/// How long expired entries are kept for serve-stale (RFC 8767 §5).
+const STALE_WINDOW: Duration = Duration::from_secs(86400);
+/// TTL given to stale answers, so clients come back soon.
+pub const STALE_TTL: u32 = 30;
+
 #[derive(Clone)]
 struct CacheEntry {
     records: Vec<DnsRecord>,
@@ -16,6 +21,11 @@ impl CacheEntry {
         self.inserted_at.elapsed() >= self.ttl
     }
 
+    /// Too old even to be served stale.
+    fn is_past_stale_window(&self) -> bool {
+        self.inserted_at.elapsed() >= self.ttl + STALE_WINDOW
+    }
+
     /// Returns records with adjusted TTL (remaining time)
     fn records_with_remaining_ttl(&self) -> Vec<DnsRecord> {
         let elapsed = self.inserted_at.elapsed().as_secs() as u32;
@@ -174,11 +184,43 @@ impl DnsCache {
         }
     }
 
-    /// Remove expired entries (called periodically)
+    /// Records of a positive entry, even expired, as long as it is within the
+    /// stale window. Only used when no upstream can answer (RFC 8767).
+    pub async fn get_stale(&self, name: &str, qtype: RecordType) -> Option<Vec<DnsRecord>> {
+        let key = CacheKey {
+            name: name.to_lowercase(),
+            qtype: qtype.to_u16(),
+        };
+
+        let entries = self.entries.read().await;
+        let entry = entries.get(&key)?;
+
+        if entry.records.is_empty() || entry.is_past_stale_window() {
+            return None;
+        }
+        if !entry.is_expired() {
+            return Some(entry.records_with_remaining_ttl());
+        }
+
+        Some(
+            entry
+                .records
+                .iter()
+                .map(|r| {
+                    let mut r = r.clone();
+                    r.ttl = STALE_TTL;
+                    r
+                })
+                .collect(),
+        )
+    }
+
+    /// Remove entries past the stale window (called periodically). Expired
+    /// entries stay until then for serve-stale, but go first on eviction.
     pub async fn purge_expired(&self) -> usize {
         let mut entries = self.entries.write().await;
         let before = entries.len();
-        entries.retain(|_, v| !v.is_expired());
+        entries.retain(|_, v| !(v.is_past_stale_window() || v.records.is_empty() && v.is_expired()));
         before - entries.len()
     }
 
@@ -238,4 +280,32 @@ mod tests {
 
         assert!(cache.len().await <= 2);
     }
+
+    #[tokio::test]
+    async fn test_serve_stale() {
+        let cache = DnsCache::new(100);
+        let records = vec![DnsRecord::a("example.com", Ipv4Addr::new(1, 2, 3, 4), 60)];
+        cache.insert("example.com", RecordType::A, &records).await;
+        cache.insert_negative("missing.com", RecordType::A, 60).await;
+
+        // Both entries expire
+        for entry in cache.entries.write().await.values_mut() {
+            entry.ttl = Duration::ZERO;
+        }
+        assert!(cache.get("example.com", RecordType::A).await.is_none());
+        let stale = cache.get_stale("example.com", RecordType::A).await.unwrap();
+        assert_eq!(stale[0].ttl, STALE_TTL);
+        assert!(cache.get_stale("missing.com", RecordType::A).await.is_none());
+
+        // The periodic purge drops the negative entry, keeps the stale one
+        assert_eq!(cache.purge_expired().await, 1);
+        let Some(long_ago) = Instant::now().checked_sub(STALE_WINDOW) else {
+            return; // Monotonic clock younger than the window
+        };
+        for entry in cache.entries.write().await.values_mut() {
+            entry.inserted_at = long_ago;
+        }
+        assert!(cache.get_stale("example.com", RecordType::A).await.is_none());
+        assert_eq!(cache.purge_expired().await, 1);
+    }
 }
@@ -24,17 +24,112 @@ pub struct DnsConfig {
     pub expand_hosts: bool,
     #[serde(default)]
     pub query_log_path: String,
+    /// Journal des requêtes bloquées (JSON lines), rechargé au démarrage ;
+    /// en mémoire seulement si vide.
+    #[serde(default)]
+    pub blocked_log_path: String,
+    /// Nombre de requêtes bloquées conservées.
+    #[serde(default = "default_blocked_log_size")]
+    pub blocked_log_size: usize,
+    /// Protection anti-rebinding : retire les réponses amont pointant vers
+    /// des adresses privées/link-local.
+    #[serde(default = "default_true")]
+    pub rebind_protection: bool,
+    /// Domaines (et sous-domaines) autorisés à résoudre vers des adresses privées.
+    #[serde(default)]
+    pub rebind_allowlist: Vec<String>,
+    /// Adresse renvoyée pour les noms bloqués en mode `sinkhole`, où le
+    /// proxy sert la page « bloqué » ; la première adresse d'écoute si vide.
+    #[serde(default)]
+    pub sinkhole_ip: String,
+    /// SafeSearch imposé (Google, Bing, DuckDuckGo, YouTube), global ou par client.
+    #[serde(default)]
+    pub safesearch: crate::safesearch::SafeSearchConfig,
+    /// EDNS Client Subnet des requêtes amont (aucun par défaut).
+    #[serde(default)]
+    pub ecs: EcsConfig,
+    /// Enregistrements locaux des routes du proxy et des applications,
+    /// pointant vers le routeur (hairpin).
+    #[serde(default)]
+    pub route_records: RouteRecordsConfig,
 }
 
+/// Confidentialité des requêtes transmises aux amonts.
+///
+/// Pas d'option de minimisation QNAME (RFC 9156) : hr-dns ne fait que
+/// relayer vers des résolveurs récursifs et n'interroge jamais de serveur
+/// faisant autorité, il n'y a donc pas de résolution itérative à minimiser.
+/// C'est aux amonts choisis de la pratiquer.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EcsConfig {
+    /// `strip` : aucun ECS ; `set` : `subnet` ; `client` : l'adresse
+    /// publique du client tronquée à `prefix_v4`/`prefix_v6`.
+    #[serde(default)]
+    pub mode: crate::ecs::EcsMode,
+    /// Sous-réseau envoyé en mode `set` (`203.0.113.0/24`).
+    #[serde(default)]
+    pub subnet: String,
+    #[serde(default = "default_ecs_prefix_v4")]
+    pub prefix_v4: u8,
+    #[serde(default = "default_ecs_prefix_v6")]
+    pub prefix_v6: u8,
+}
+
+impl EcsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.mode == crate::ecs::EcsMode::Set {
+            crate::ecs::ClientSubnet::parse(&self.subnet)?;
+        }
+        // RFC 7871 §11.1 : pas plus de /24 et /56 pour la vie privée
+        if self.prefix_v4 > 24 || self.prefix_v6 > 56 {
+            return Err("ECS prefixes must be at most /24 (IPv4) and /56 (IPv6)".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StaticRecord {
+    /// Nom exact, `*.suffixe` (tout nom sous le suffixe, le plus proche
+    /// l'emporte) ou `/regex/` (nom complet, insensible à la casse).
     pub name: String,
+    /// A, AAAA, CNAME, ou ALIAS/ANAME : la valeur est un nom dont les
+    /// adresses sont renvoyées sous `name` (utilisable à l'apex, contrairement
+    /// à un CNAME).
     #[serde(rename = "type")]
     pub record_type: String,
     pub value: String,
     #[serde(default = "default_ttl")]
     pub ttl: u32,
 }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteRecordsConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Adresse IPv4 publiée ; la passerelle DHCP si vide.
+    #[serde(default)]
+    pub ipv4: String,
+    /// Adresse IPv6 publiée ; l'adresse globale de l'interface DHCP si vide.
+    #[serde(default)]
+    pub ipv6: String,
+    /// Domaines jamais publiés (en plus des routes avec `local_dns` à false).
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default = "default_route_records_ttl")]
+    pub ttl: u32,
+}
+
+impl RouteRecordsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.ipv4.is_empty() && self.ipv4.parse::<std::net::Ipv4Addr>().is_err() {
+            return Err(format!("Invalid route records IPv4: {}", self.ipv4));
+        }
+        if !self.ipv6.is_empty() && self.ipv6.parse::<std::net::Ipv6Addr>().is_err() {
+            return Err(format!("Invalid route records IPv6: {}", self.ipv6));
+        }
+        Ok(())
+    }
+}
 
 /// Adblock resolver config: the subset of adblock config that the DNS resolver needs.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -61,6 +156,18 @@ fn default_upstream_timeout() -> u64 {
 fn default_cache_size() -> usize {
     1000
 }
+fn default_blocked_log_size() -> usize {
+    crate::blocked_log::DEFAULT_CAPACITY
+}
+fn default_ecs_prefix_v4() -> u8 {
+    24
+}
+fn default_ecs_prefix_v6() -> u8 {
+    56
+}
+fn default_route_records_ttl() -> u32 {
+    60
+}
 fn default_ttl() -> u32 {
     300
 }
@@ -77,6 +184,18 @@ impl Default for DnsConfig {
     }
 }
 
+impl Default for EcsConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl Default for RouteRecordsConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
 impl Default for AdblockResolverConfig {
     fn default() -> Self {
         serde_json::from_str("{}").unwrap()
@@ -94,6 +213,32 @@ mod tests {
         assert_eq!(config.cache_size, 1000);
         assert!(config.expand_hosts);
         assert_eq!(config.upstream_servers.len(), 2);
+        assert_eq!(config.ecs.mode, crate::ecs::EcsMode::Strip);
+        assert!(config.route_records.enabled);
+        assert_eq!(config.route_records.ttl, 60);
+    }
+
+    #[test]
+    fn test_ecs_validate() {
+        let mut ecs = EcsConfig::default();
+        assert!(ecs.validate().is_ok());
+        ecs.mode = crate::ecs::EcsMode::Set;
+        assert!(ecs.validate().is_err());
+        ecs.subnet = "203.0.113.0/24".to_string();
+        assert!(ecs.validate().is_ok());
+        ecs.prefix_v4 = 32;
+        assert!(ecs.validate().is_err());
+    }
+
+    #[test]
+    fn test_route_records_validate() {
+        let mut records = RouteRecordsConfig::default();
+        assert!(records.validate().is_ok());
+        records.ipv4 = "10.0.0.254".to_string();
+        records.ipv6 = "fd00::fe".to_string();
+        assert!(records.validate().is_ok());
+        records.ipv6 = "10.0.0.254".to_string();
+        assert!(records.validate().is_err());
     }
 
     #[test]
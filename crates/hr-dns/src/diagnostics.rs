@@ -0,0 +1,385 @@
+//! Self-test of the resolver, behind `/api/dns/diagnostics`: each upstream
+//! resolves a few probe names (latency, rcode) and is checked for DNSSEC
+//! validation, a blocked name must get the block response and a second
+//! lookup must come from the cache.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::SharedDnsState;
+use crate::packet::{self, encode_name, RCODE_NOERROR, RCODE_NXDOMAIN, RCODE_SERVFAIL};
+use crate::records::{RData, RecordType};
+use crate::resolver;
+use crate::upstream::UpstreamForwarder;
+
+/// Names resolved through each upstream.
+pub const PROBE_NAMES: &[(&str, RecordType)] = &[
+    ("example.com", RecordType::A),
+    ("cloudflare.com", RecordType::AAAA),
+    ("wikipedia.org", RecordType::A),
+];
+/// Signed zone: a validating upstream sets AD in its answer.
+const DNSSEC_SIGNED: &str = "cloudflare.com";
+/// Zone with a broken signature: a validating upstream answers SERVFAIL.
+const DNSSEC_BOGUS: &str = "dnssec-failed.org";
+/// Names tried for the block check when the blocked log has none.
+const BLOCK_CANDIDATES: &[&str] = &["doubleclick.net", "googleadservices.com", "ads.google.com"];
+/// Client the resolver checks are made as.
+const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// AD (authenticated data) flag of the header.
+const FLAG_AD: u16 = 0x0020;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Skipped,
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self { name, status, detail: detail.into() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeResult {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub qtype: String,
+    pub rcode: Option<u8>,
+    pub answers: usize,
+    pub latency_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpstreamReport {
+    pub server: String,
+    pub reachable: bool,
+    pub avg_latency_ms: Option<f64>,
+    pub probes: Vec<ProbeResult>,
+    /// AD set on a signed zone; `None` when the upstream did not answer.
+    pub dnssec_validates: Option<bool>,
+    /// SERVFAIL on a zone with a broken signature.
+    pub dnssec_rejects_bogus: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticsReport {
+    /// Worst status of the checks.
+    pub status: CheckStatus,
+    pub ran_at: DateTime<Utc>,
+    pub duration_ms: f64,
+    pub upstreams: Vec<UpstreamReport>,
+    pub checks: Vec<Check>,
+}
+
+/// Query for `name` with recursion desired and an EDNS0 OPT record;
+/// `dnssec` sets the DO and AD bits.
+pub fn probe_query(name: &str, qtype: RecordType, dnssec: bool) -> Vec<u8> {
+    let flags: u16 = if dnssec { 0x0100 | FLAG_AD } else { 0x0100 };
+    let mut buf = vec![0x00, 0x00];
+    buf.extend_from_slice(&flags.to_be_bytes());
+    buf.extend_from_slice(&[0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    encode_name(name, &mut buf);
+    buf.extend_from_slice(&qtype.to_u16().to_be_bytes());
+    buf.extend_from_slice(&1u16.to_be_bytes()); // IN
+    // OPT: root, type 41, 1232 bytes of payload, DO in the flags
+    buf.push(0x00);
+    buf.extend_from_slice(&41u16.to_be_bytes());
+    buf.extend_from_slice(&1232u16.to_be_bytes());
+    buf.extend_from_slice(&(if dnssec { 0x8000u32 } else { 0 }).to_be_bytes());
+    buf.extend_from_slice(&0u16.to_be_bytes());
+    buf
+}
+
+fn millis(d: Duration) -> f64 {
+    (d.as_secs_f64() * 10_000.0).round() / 10.0
+}
+
+/// Rcode and answer count of `name` through `server`, with the latency.
+async fn ask(
+    forwarder: &UpstreamForwarder,
+    server: SocketAddr,
+    name: &str,
+    qtype: RecordType,
+    dnssec: bool,
+) -> anyhow::Result<(packet::DnsHeader, usize, Duration)> {
+    let start = Instant::now();
+    let response = forwarder.query(&probe_query(name, qtype, dnssec), server).await?;
+    let elapsed = start.elapsed();
+    let (header, answers) =
+        packet::parse_response_records(&response).map_err(|e| anyhow::anyhow!("Invalid response: {}", e))?;
+    Ok((header, answers.len(), elapsed))
+}
+
+async fn probe_upstream(forwarder: &UpstreamForwarder, server: SocketAddr) -> UpstreamReport {
+    let mut probes = Vec::new();
+    for (name, qtype) in PROBE_NAMES {
+        let probe = match ask(forwarder, server, name, *qtype, false).await {
+            Ok((header, answers, elapsed)) => ProbeResult {
+                name: name.to_string(),
+                qtype: qtype.to_string(),
+                rcode: Some(header.rcode()),
+                answers,
+                latency_ms: Some(millis(elapsed)),
+                error: None,
+            },
+            Err(e) => ProbeResult {
+                name: name.to_string(),
+                qtype: qtype.to_string(),
+                rcode: None,
+                answers: 0,
+                latency_ms: None,
+                error: Some(e.to_string()),
+            },
+        };
+        probes.push(probe);
+    }
+
+    let latencies: Vec<f64> = probes.iter().filter_map(|p| p.latency_ms).collect();
+    let reachable = !latencies.is_empty();
+    let avg_latency_ms =
+        reachable.then(|| (latencies.iter().sum::<f64>() / latencies.len() as f64 * 10.0).round() / 10.0);
+
+    let (mut dnssec_validates, mut dnssec_rejects_bogus) = (None, None);
+    if reachable {
+        dnssec_validates = ask(forwarder, server, DNSSEC_SIGNED, RecordType::A, true)
+            .await
+            .ok()
+            .map(|(header, _, _)| header.flags & FLAG_AD != 0);
+        dnssec_rejects_bogus = ask(forwarder, server, DNSSEC_BOGUS, RecordType::A, true)
+            .await
+            .ok()
+            .map(|(header, _, _)| header.rcode() == RCODE_SERVFAIL);
+    }
+
+    UpstreamReport {
+        server: server.to_string(),
+        reachable,
+        avg_latency_ms,
+        probes,
+        dnssec_validates,
+        dnssec_rejects_bogus,
+    }
+}
+
+pub fn upstream_check(upstreams: &[UpstreamReport]) -> Check {
+    let reachable = upstreams.iter().filter(|u| u.reachable).count();
+    let mut detail = format!("{}/{} upstreams answer", reachable, upstreams.len());
+    // Answering, but not resolving well-known names
+    let failing: Vec<&str> = upstreams
+        .iter()
+        .filter(|u| u.reachable && u.probes.iter().any(|p| p.rcode.is_some_and(|r| r != RCODE_NOERROR)))
+        .map(|u| u.server.as_str())
+        .collect();
+    if !failing.is_empty() {
+        detail.push_str(&format!(", errors from {}", failing.join(", ")));
+    }
+    let status = match reachable {
+        0 => CheckStatus::Fail,
+        n if n < upstreams.len() || !failing.is_empty() => CheckStatus::Warn,
+        _ => CheckStatus::Pass,
+    };
+    Check::new("upstreams", status, detail)
+}
+
+fn dnssec_check(upstreams: &[UpstreamReport]) -> Check {
+    let answered: Vec<&UpstreamReport> = upstreams.iter().filter(|u| u.dnssec_validates.is_some()).collect();
+    if answered.is_empty() {
+        return Check::new("dnssec", CheckStatus::Skipped, "No upstream answered");
+    }
+    let validating: Vec<&str> = answered
+        .iter()
+        .filter(|u| u.dnssec_validates == Some(true) && u.dnssec_rejects_bogus == Some(true))
+        .map(|u| u.server.as_str())
+        .collect();
+    if validating.len() == answered.len() {
+        Check::new("dnssec", CheckStatus::Pass, "Every upstream validates DNSSEC")
+    } else if validating.is_empty() {
+        Check::new("dnssec", CheckStatus::Warn, "No upstream validates DNSSEC")
+    } else {
+        Check::new("dnssec", CheckStatus::Warn, format!("Only {} validate DNSSEC", validating.join(", ")))
+    }
+}
+
+/// Resolve `name` as the resolver would for a client, with the time taken.
+async fn resolve(state: &SharedDnsState, name: &str, qtype: RecordType) -> Option<(resolver::ResolveResult, Duration)> {
+    let query = packet::parse_query(&probe_query(name, qtype, false)).ok()?;
+    let start = Instant::now();
+    let result = resolver::resolve(&query, state, CLIENT).await;
+    Some((result, start.elapsed()))
+}
+
+/// A name on the block lists must get the configured block response.
+async fn blocking_check(state: &SharedDnsState) -> Check {
+    let (candidate, mode) = {
+        let dns = state.read().await;
+        if !dns.adblock_enabled {
+            return Check::new("blocking", CheckStatus::Skipped, "Ad blocking is disabled");
+        }
+        if dns.adblock_schedule.is_paused(CLIENT, Utc::now()) {
+            return Check::new("blocking", CheckStatus::Skipped, "Blocking is paused");
+        }
+        let adblock = dns.adblock.read().await;
+        let candidate = dns
+            .blocked_log
+            .since(None, None, 20)
+            .into_iter()
+            .map(|e| e.domain)
+            .chain(BLOCK_CANDIDATES.iter().map(|d| d.to_string()))
+            .find(|d| adblock.is_blocked(d));
+        (candidate, dns.adblock_block_response.clone())
+    };
+    let Some(name) = candidate else {
+        return Check::new("blocking", CheckStatus::Skipped, "No blocked name to test (empty block lists)");
+    };
+    let Some((result, _)) = resolve(state, &name, RecordType::A).await else {
+        return Check::new("blocking", CheckStatus::Fail, format!("Invalid name: {}", name));
+    };
+    if result.blocked.is_none() {
+        return Check::new("blocking", CheckStatus::Fail, format!("{} is on the block lists but was resolved", name));
+    }
+    let answer = match result.records.first().map(|r| &r.rdata) {
+        Some(RData::A(ip)) => ip.to_string(),
+        _ if result.rcode == RCODE_NXDOMAIN => "NXDOMAIN".to_string(),
+        _ => format!("rcode {}", result.rcode),
+    };
+    Check::new("blocking", CheckStatus::Pass, format!("{} -> {} ({})", name, answer, mode))
+}
+
+/// A second lookup of a probe name must come from the cache.
+async fn cache_check(state: &SharedDnsState) -> Check {
+    let (name, qtype) = PROBE_NAMES[0];
+    let Some((first, first_time)) = resolve(state, name, qtype).await else {
+        return Check::new("cache", CheckStatus::Fail, format!("Invalid name: {}", name));
+    };
+    if first.rcode == RCODE_SERVFAIL {
+        return Check::new("cache", CheckStatus::Fail, format!("{} could not be resolved", name));
+    }
+    let Some((second, second_time)) = resolve(state, name, qtype).await else {
+        return Check::new("cache", CheckStatus::Fail, format!("Invalid name: {}", name));
+    };
+    if !second.cached {
+        return Check::new("cache", CheckStatus::Fail, format!("{} was not cached after a first lookup", name));
+    }
+    Check::new(
+        "cache",
+        CheckStatus::Pass,
+        format!("{}: {} ms, then {} ms from the cache", name, millis(first_time), millis(second_time)),
+    )
+}
+
+/// Probe every upstream of `forwarder` in parallel, in configuration order.
+pub async fn probe_upstreams(forwarder: Arc<UpstreamForwarder>) -> Vec<UpstreamReport> {
+    let mut tasks = tokio::task::JoinSet::new();
+    for (i, server) in forwarder.servers().iter().copied().enumerate() {
+        let forwarder = forwarder.clone();
+        tasks.spawn(async move { (i, probe_upstream(&forwarder, server).await) });
+    }
+    let mut upstreams: Vec<(usize, UpstreamReport)> = tasks.join_all().await;
+    upstreams.sort_by_key(|(i, _)| *i);
+    upstreams.into_iter().map(|(_, u)| u).collect()
+}
+
+/// Run every check.
+pub async fn run(state: &SharedDnsState) -> DiagnosticsReport {
+    let ran_at = Utc::now();
+    let start = Instant::now();
+
+    let (forwarder, degraded) = {
+        let dns = state.read().await;
+        let forwarder =
+            UpstreamForwarder::new(dns.config.upstream_servers.clone(), dns.config.upstream_timeout_ms);
+        (Arc::new(forwarder), dns.health.is_degraded())
+    };
+
+    let upstreams = probe_upstreams(forwarder).await;
+    let mut checks = vec![upstream_check(&upstreams), dnssec_check(&upstreams)];
+    checks.push(if degraded {
+        Check::new("mode", CheckStatus::Fail, "Local-only mode: the resolver stopped forwarding")
+    } else {
+        Check::new("mode", CheckStatus::Pass, "Forwarding to the upstreams")
+    });
+    checks.push(blocking_check(state).await);
+    checks.push(cache_check(state).await);
+
+    DiagnosticsReport {
+        status: checks.iter().map(|c| c.status).max().unwrap_or(CheckStatus::Skipped),
+        ran_at,
+        duration_ms: millis(start.elapsed()),
+        upstreams,
+        checks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upstream(reachable: bool, validates: Option<bool>) -> UpstreamReport {
+        UpstreamReport {
+            server: "1.1.1.1:53".into(),
+            reachable,
+            avg_latency_ms: reachable.then_some(12.5),
+            probes: vec![],
+            dnssec_validates: validates,
+            dnssec_rejects_bogus: validates,
+        }
+    }
+
+    #[test]
+    fn test_probe_query() {
+        let query = packet::parse_query(&probe_query("example.com", RecordType::AAAA, true)).unwrap();
+        assert_eq!(query.questions[0].name, "example.com");
+        assert_eq!(query.questions[0].qtype, RecordType::AAAA);
+        assert!(query.header.recursion_desired());
+        assert_eq!(query.header.flags & FLAG_AD, FLAG_AD);
+        assert_eq!(query.edns_udp_size, 1232);
+    }
+
+    #[test]
+    fn test_checks() {
+        let all = [upstream(true, Some(true)), upstream(true, Some(true))];
+        assert_eq!(upstream_check(&all).status, CheckStatus::Pass);
+        assert_eq!(dnssec_check(&all).status, CheckStatus::Pass);
+
+        let mut nxdomain = upstream(true, Some(true));
+        nxdomain.probes.push(ProbeResult {
+            name: "example.com".into(),
+            qtype: "A".into(),
+            rcode: Some(RCODE_NXDOMAIN),
+            answers: 0,
+            latency_ms: Some(1.0),
+            error: None,
+        });
+        assert_eq!(upstream_check(&[nxdomain]).status, CheckStatus::Warn);
+
+        let some = [upstream(true, Some(false)), upstream(false, None)];
+        assert_eq!(upstream_check(&some).status, CheckStatus::Warn);
+        assert_eq!(dnssec_check(&some).status, CheckStatus::Warn);
+
+        let none = [upstream(false, None)];
+        assert_eq!(upstream_check(&none).status, CheckStatus::Fail);
+        assert_eq!(dnssec_check(&none).status, CheckStatus::Skipped);
+        assert!(CheckStatus::Fail > CheckStatus::Warn && CheckStatus::Warn > CheckStatus::Pass);
+    }
+}
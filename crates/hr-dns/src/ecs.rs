@@ -0,0 +1,159 @@
+//! EDNS Client Subnet (RFC 7871) on forwarded queries. By default none is
+//! sent: queries are rebuilt for the upstream, so a client's own ECS option
+//! never leaves the network. `set` sends a fixed subnet (the household's
+//! public /24, for CDN locality without per-client detail), `client` the
+//! client's public address truncated (private ones get none).
+//!
+//! An answer may depend on the subnet it was asked for, so with ECS the
+//! cache is keyed by name, type and subnet ([`cache_name`]).
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::rebind;
+
+/// EDNS option code of ECS.
+pub const OPTION_CODE: u16 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EcsMode {
+    #[default]
+    Strip,
+    Set,
+    Client,
+}
+
+/// Subnet sent upstream; the address is masked to the prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientSubnet {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+impl ClientSubnet {
+    pub fn new(addr: IpAddr, prefix: u8) -> Self {
+        match addr {
+            IpAddr::V4(v4) => {
+                let prefix = prefix.min(32);
+                let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix) };
+                Self { addr: IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask)), prefix }
+            }
+            IpAddr::V6(v6) => {
+                let prefix = prefix.min(128);
+                let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix) };
+                Self { addr: IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask)), prefix }
+            }
+        }
+    }
+
+    /// `203.0.113.0/24`, `2001:db8:1200::/56`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid ECS subnet: {} (address/prefix)", s);
+        let (addr, prefix) = s.trim().split_once('/').ok_or_else(invalid)?;
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let prefix: u8 = prefix.parse().map_err(|_| invalid())?;
+        if prefix > if addr.is_ipv4() { 32 } else { 128 } {
+            return Err(invalid());
+        }
+        Ok(Self::new(addr, prefix))
+    }
+
+    /// The option as it goes in the OPT RDATA (code, length, data).
+    pub fn encode(&self) -> Vec<u8> {
+        let (family, octets): (u16, Vec<u8>) = match self.addr {
+            IpAddr::V4(v4) => (1, v4.octets().to_vec()),
+            IpAddr::V6(v6) => (2, v6.octets().to_vec()),
+        };
+        // Only the bytes the prefix covers (RFC 7871 §6)
+        let address = &octets[..(self.prefix as usize).div_ceil(8)];
+        let mut buf = Vec::with_capacity(8 + address.len());
+        buf.extend_from_slice(&OPTION_CODE.to_be_bytes());
+        buf.extend_from_slice(&(4 + address.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&family.to_be_bytes());
+        buf.push(self.prefix);
+        buf.push(0); // scope prefix, 0 in queries
+        buf.extend_from_slice(address);
+        buf
+    }
+}
+
+impl fmt::Display for ClientSubnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Subnet to send upstream for `client`, `None` for no ECS option.
+pub fn subnet_for(config: &crate::config::EcsConfig, client: IpAddr) -> Option<ClientSubnet> {
+    match config.mode {
+        EcsMode::Strip => None,
+        EcsMode::Set => ClientSubnet::parse(&config.subnet).ok(),
+        EcsMode::Client if rebind::is_private(client) => None,
+        EcsMode::Client => {
+            let prefix = if client.is_ipv4() { config.prefix_v4 } else { config.prefix_v6 };
+            Some(ClientSubnet::new(client, prefix))
+        }
+    }
+}
+
+/// Name the cache stores an upstream answer under: the subnet it was
+/// asked for is part of the key.
+pub fn cache_name(name: &str, subnet: Option<ClientSubnet>) -> String {
+    match subnet {
+        Some(subnet) => format!("{}|ecs={}", name, subnet),
+        None => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EcsConfig;
+
+    #[test]
+    fn test_parse_and_mask() {
+        let subnet = ClientSubnet::parse("203.0.113.77/24").unwrap();
+        assert_eq!(subnet.to_string(), "203.0.113.0/24");
+        let subnet = ClientSubnet::parse("2001:db8:1234:5678::1/56").unwrap();
+        assert_eq!(subnet.to_string(), "2001:db8:1234:5600::/56");
+        assert!(ClientSubnet::parse("203.0.113.0").is_err());
+        assert!(ClientSubnet::parse("203.0.113.0/33").is_err());
+        assert!(ClientSubnet::parse("example.com/24").is_err());
+    }
+
+    #[test]
+    fn test_encode() {
+        let option = ClientSubnet::parse("203.0.113.0/24").unwrap().encode();
+        assert_eq!(option, vec![0, 8, 0, 7, 0, 1, 24, 0, 203, 0, 113]);
+        let option = ClientSubnet::parse("2001:db8::/20").unwrap().encode();
+        assert_eq!(option, vec![0, 8, 0, 7, 0, 2, 20, 0, 0x20, 0x01, 0x00]);
+        let option = ClientSubnet::new("198.51.100.1".parse().unwrap(), 0).encode();
+        assert_eq!(option, vec![0, 8, 0, 4, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_subnet_for() {
+        let mut config = EcsConfig::default();
+        let public: IpAddr = "2a01:e0a:1:2:3:4:5:6".parse().unwrap();
+        let private: IpAddr = "192.168.1.20".parse().unwrap();
+        assert_eq!(subnet_for(&config, public), None);
+
+        config.mode = EcsMode::Set;
+        config.subnet = "203.0.113.0/24".to_string();
+        assert_eq!(subnet_for(&config, private).unwrap().to_string(), "203.0.113.0/24");
+
+        config.mode = EcsMode::Client;
+        assert_eq!(subnet_for(&config, private), None);
+        assert_eq!(subnet_for(&config, public).unwrap().to_string(), "2a01:e0a:1::/56");
+    }
+
+    #[test]
+    fn test_cache_name() {
+        assert_eq!(cache_name("example.com", None), "example.com");
+        let subnet = ClientSubnet::parse("203.0.113.0/24").ok();
+        assert_eq!(cache_name("example.com", subnet), "example.com|ecs=203.0.113.0/24");
+    }
+}
@@ -0,0 +1,149 @@
+//! Upstream health and the degraded (local-only) mode.
+//!
+//! After a few forwards in a row where every upstream failed, the resolver
+//! stops forwarding: local zones, leases and the cache (stale entries
+//! included) keep answering, everything else gets an immediate SERVFAIL
+//! instead of a client-side timeout. A probe brings it back once the
+//! upstreams have answered several times in a row.
+
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, Ordering};
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::{info, warn};
+
+use crate::SharedDnsState;
+use crate::packet::encode_name;
+
+/// Forwards in a row with every upstream failing before going degraded.
+pub const FAILURES_TO_DEGRADE: u32 = 3;
+/// Probes in a row that must succeed before leaving degraded mode.
+pub const SUCCESSES_TO_RECOVER: u32 = 3;
+/// Interval between probes while degraded.
+pub const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+pub struct UpstreamHealth {
+    degraded: AtomicBool,
+    failures: AtomicU32,
+    successes: AtomicU32,
+    /// Unix time the current degraded period started (0 when healthy).
+    degraded_since: AtomicI64,
+}
+
+impl UpstreamHealth {
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    /// Every upstream failed for one query (or probe). Returns true when
+    /// this switches to degraded mode.
+    pub fn record_failure(&self) -> bool {
+        self.successes.store(0, Ordering::Relaxed);
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= FAILURES_TO_DEGRADE && !self.degraded.swap(true, Ordering::Relaxed) {
+            self.degraded_since.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+            set_gauge(true);
+            return true;
+        }
+        false
+    }
+
+    /// An upstream answered. Returns true when this ends degraded mode.
+    pub fn record_success(&self) -> bool {
+        self.failures.store(0, Ordering::Relaxed);
+        if !self.is_degraded() {
+            return false;
+        }
+        let successes = self.successes.fetch_add(1, Ordering::Relaxed) + 1;
+        if successes >= SUCCESSES_TO_RECOVER && self.degraded.swap(false, Ordering::Relaxed) {
+            self.successes.store(0, Ordering::Relaxed);
+            self.degraded_since.store(0, Ordering::Relaxed);
+            set_gauge(false);
+            return true;
+        }
+        false
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        let since = self.degraded_since.load(Ordering::Relaxed);
+        serde_json::json!({
+            "mode": if self.is_degraded() { "degraded" } else { "normal" },
+            "degradedSince": (since > 0)
+                .then(|| chrono::DateTime::from_timestamp(since, 0).map(|t| t.to_rfc3339()))
+                .flatten(),
+            "consecutiveFailures": self.failures.load(Ordering::Relaxed),
+        })
+    }
+}
+
+fn set_gauge(degraded: bool) {
+    hr_common::metrics::registry()
+        .gauge("homeroute_dns_degraded", "1 while every DNS upstream is unreachable (local-only mode).", &[])
+        .set(if degraded { 1.0 } else { 0.0 });
+}
+
+/// Probe the upstreams while degraded (a root NS query) until they recover.
+pub async fn run_upstream_probe(state: SharedDnsState) -> Result<()> {
+    let mut probe = vec![0x00, 0x00, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+    encode_name("", &mut probe);
+    probe.extend_from_slice(&[0x00, 0x02, 0x00, 0x01]); // NS, IN
+
+    let mut tick = tokio::time::interval(PROBE_INTERVAL);
+    loop {
+        tick.tick().await;
+        let state = state.read().await;
+        if !state.health.is_degraded() {
+            continue;
+        }
+        match state.upstream.forward(&probe).await {
+            Ok(_) => {
+                if state.health.record_success() {
+                    info!("DNS upstreams reachable again, leaving local-only mode");
+                }
+            }
+            Err(_) => {
+                state.health.record_failure();
+            }
+        }
+    }
+}
+
+/// Log the switch to degraded mode once, from the resolver.
+pub(crate) fn record_forward_failure(health: &UpstreamHealth) {
+    if health.record_failure() {
+        warn!(
+            "All DNS upstreams failed {} times in a row: local-only mode (cache, local zones, SERVFAIL otherwise)",
+            FAILURES_TO_DEGRADE
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hysteresis() {
+        let health = UpstreamHealth::default();
+        assert!(!health.record_failure());
+        assert!(!health.record_failure());
+        // A success in between resets the count
+        health.record_success();
+        assert!(!health.record_failure());
+        assert!(!health.record_failure());
+        assert!(health.record_failure());
+        assert!(health.is_degraded());
+        assert_eq!(health.snapshot()["mode"], "degraded");
+
+        // One good probe isn't enough, and a failure starts over
+        assert!(!health.record_success());
+        assert!(!health.record_success());
+        assert!(!health.record_failure());
+        assert!(!health.record_success());
+        assert!(!health.record_success());
+        assert!(health.record_success());
+        assert!(!health.is_degraded());
+        assert!(health.snapshot()["degradedSince"].is_null());
+    }
+}
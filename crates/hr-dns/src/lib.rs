@@ -2,34 +2,125 @@ pub mod config;
 pub mod records;
 pub mod packet;
 pub mod cache;
+pub mod health;
 pub mod upstream;
 pub mod resolver;
 pub mod server;
 pub mod logging;
+pub mod rebind;
+pub mod safesearch;
+pub mod blocked_log;
+pub mod schedule;
+pub mod diagnostics;
+pub mod ecs;
+pub mod patterns;
 
 pub use config::DnsConfig;
 
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::sync::RwLock;
 
+/// Compteurs de requêtes depuis le démarrage du service.
+#[derive(Debug, Default)]
+pub struct DnsStats {
+    pub queries: AtomicU64,
+    pub blocked: AtomicU64,
+    pub cached: AtomicU64,
+    /// Réponses amont retirées par la protection anti-rebinding.
+    pub rebind_stripped: AtomicU64,
+}
+
+impl DnsStats {
+    pub fn record(&self, blocked: bool, cached: bool) {
+        let result = match (blocked, cached) {
+            (true, _) => "blocked",
+            (false, true) => "cached",
+            (false, false) => "resolved",
+        };
+        hr_common::metrics::registry()
+            .counter("homeroute_dns_queries_total", "DNS queries answered, by outcome.", &[("result", result)])
+            .inc();
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        if blocked {
+            self.blocked.fetch_add(1, Ordering::Relaxed);
+        }
+        if cached {
+            self.cached.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_rebind_stripped(&self, count: usize) {
+        hr_common::metrics::registry()
+            .counter(
+                "homeroute_dns_rebind_stripped_total",
+                "Upstream answers removed by DNS rebinding protection.",
+                &[],
+            )
+            .add(count as u64);
+        self.rebind_stripped.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "queries": self.queries.load(Ordering::Relaxed),
+            "blocked": self.blocked.load(Ordering::Relaxed),
+            "cached": self.cached.load(Ordering::Relaxed),
+            "rebindStripped": self.rebind_stripped.load(Ordering::Relaxed),
+        })
+    }
+}
+
 pub struct DnsState {
     pub config: config::DnsConfig,
     pub dns_cache: cache::DnsCache,
     pub upstream: upstream::UpstreamForwarder,
     pub query_logger: Option<logging::QueryLogger>,
+    /// Recently blocked queries, with the client and the cause.
+    pub blocked_log: blocked_log::BlockedLog,
     pub adblock: Arc<RwLock<hr_adblock::AdblockEngine>>,
     pub lease_store: Arc<RwLock<hr_dhcp::LeaseStore>>,
     pub adblock_enabled: bool,
+    /// SafeSearch compiled from `config.safesearch`.
+    pub safesearch: safesearch::SafeSearch,
+    /// `/regex/` static records compiled from `config.static_records`.
+    pub static_patterns: patterns::StaticPatterns,
+    /// A/AAAA records of the proxy routes and applications, published by
+    /// the API (`config.route_records`); configured records take precedence.
+    pub route_records: Vec<config::StaticRecord>,
+    /// Blocking pauses and scheduled profiles, shared with the API.
+    pub adblock_schedule: Arc<hr_adblock::AdblockSchedule>,
     pub adblock_block_response: String,
+    pub stats: DnsStats,
+    /// Ban manager: banned clients are ignored, amplification attempts reported.
+    pub bans: Option<hr_firewall::SharedBanManager>,
+    /// Upstream reachability; drives the local-only mode.
+    pub health: health::UpstreamHealth,
 }
 
 impl DnsState {
+    /// Replace the config, recompiling what derives from it. An invalid
+    /// SafeSearch section is left off.
+    pub fn set_config(&mut self, config: config::DnsConfig) {
+        self.safesearch = safesearch::SafeSearch::new(&config.safesearch).unwrap_or_else(|e| {
+            tracing::warn!("Invalid SafeSearch config, disabled: {}", e);
+            Default::default()
+        });
+        self.static_patterns = patterns::StaticPatterns::new(&config.static_records);
+        self.config = config;
+    }
+
     pub fn server_ip(&self) -> std::net::Ipv4Addr {
         self.config.listen_addresses.first()
             .and_then(|s| s.parse().ok())
             .unwrap_or(std::net::Ipv4Addr::UNSPECIFIED)
     }
 
+    /// Address answered for blocked names in `sinkhole` mode.
+    pub fn sinkhole_ip(&self) -> std::net::Ipv4Addr {
+        self.config.sinkhole_ip.parse().unwrap_or_else(|_| self.server_ip())
+    }
+
     /// Add a static record at runtime (not persisted).
     /// Deduplicates by name + record_type: if an existing record has the same
     /// name (case-insensitive) and type, it is replaced.
@@ -41,12 +132,22 @@ impl DnsState {
             !(r.name.to_lowercase() == name_lc && r.record_type.to_uppercase() == rtype)
         });
         self.config.static_records.push(record);
+        self.static_patterns = patterns::StaticPatterns::new(&self.config.static_records);
+    }
+
+    /// Remove the static records of a name and type (case-insensitive).
+    pub fn remove_static_record(&mut self, name: &str, record_type: &str) {
+        self.config.static_records.retain(|r| {
+            !(r.name.eq_ignore_ascii_case(name) && r.record_type.eq_ignore_ascii_case(record_type))
+        });
+        self.static_patterns = patterns::StaticPatterns::new(&self.config.static_records);
     }
 
     /// Remove all static records whose value matches the given string.
     /// Useful for cleaning up all DNS records pointing to a specific IP.
     pub fn remove_static_records_by_value(&mut self, value: &str) {
         self.config.static_records.retain(|r| r.value != value);
+        self.static_patterns = patterns::StaticPatterns::new(&self.config.static_records);
     }
 }
 
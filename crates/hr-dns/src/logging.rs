@@ -14,6 +14,13 @@ struct QueryLogEntry {
     blocked: bool,
     cached: bool,
     ms: u64,
+    /// Answers removed by the rebinding protection.
+    #[serde(skip_serializing_if = "is_zero")]
+    sanitized: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
 }
 
 /// Async query logger using a background writer (same pattern as rust-proxy).
@@ -60,6 +67,7 @@ impl QueryLogger {
         Self { sender }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn log(
         &self,
         domain: &str,
@@ -68,6 +76,7 @@ impl QueryLogger {
         blocked: bool,
         cached: bool,
         elapsed_ms: u64,
+        sanitized: usize,
     ) {
         let entry = QueryLogEntry {
             ts: Utc::now().to_rfc3339(),
@@ -77,6 +86,7 @@ impl QueryLogger {
             blocked,
             cached,
             ms: elapsed_ms,
+            sanitized,
         };
 
         match serde_json::to_string(&entry) {
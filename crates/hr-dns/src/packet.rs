@@ -183,10 +183,20 @@ pub fn parse_query(buf: &[u8]) -> Result<DnsQuery, DnsParseError> {
     let header = parse_header(buf)?;
     let mut offset = 12;
     let question_start = offset;
-    let mut questions = Vec::with_capacity(header.qd_count as usize);
+    // Each question takes at least 5 bytes: don't trust the count for the allocation
+    let mut questions = Vec::with_capacity((header.qd_count as usize).min(buf.len() / 5));
 
     for _ in 0..header.qd_count {
         let (name, new_offset) = parse_name(buf, offset)?;
+        // The question section is copied verbatim into our responses, where a
+        // compression pointer would no longer point at the same bytes
+        let mut pos = offset;
+        while buf[pos] != 0 {
+            if buf[pos] & 0xC0 != 0 {
+                return Err(DnsParseError::InvalidLabel(pos));
+            }
+            pos += buf[pos] as usize + 1;
+        }
         offset = new_offset;
 
         if offset + 4 > buf.len() {
@@ -602,12 +612,11 @@ pub fn peek_edns_udp_size(buf: &[u8]) -> u16 {
 /// Keeps as many complete answer records as will fit (smart truncation)
 /// rather than dropping all records. For UDP without EDNS0, max_size should be 512.
 pub fn truncate_for_udp(response: &mut Vec<u8>, max_size: usize) {
+    // The header always stays, whatever size the client advertised
+    let max_size = max_size.max(12);
     if response.len() <= max_size {
         return;
     }
-    if response.len() < 12 {
-        return;
-    }
 
     // Parse header to find answer boundaries
     let qd_count = u16::from_be_bytes([response[4], response[5]]) as usize;
@@ -754,4 +763,31 @@ mod tests {
             panic!("Expected A record");
         }
     }
+
+    #[test]
+    fn test_parse_query_rejects_compressed_question() {
+        // Question name pointing into the header: meaningless once copied into a response
+        let buf = [0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0x04, 0x00, 0x01, 0x00, 0x01];
+        assert!(matches!(parse_query(&buf), Err(DnsParseError::InvalidLabel(12))));
+
+        // Huge QDCOUNT on a tiny packet
+        let buf = [0x12, 0x34, 0x01, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert!(parse_query(&buf).is_err());
+    }
+
+    #[test]
+    fn test_truncate_below_header_size() {
+        let mut query_buf: Vec<u8> = Vec::new();
+        query_buf.extend_from_slice(&[0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        encode_name("example.com", &mut query_buf);
+        query_buf.extend_from_slice(&[0x00, 0x01, 0x00, 0x01]);
+        let query = parse_query(&query_buf).unwrap();
+        let answers = vec![DnsRecord::a("example.com", Ipv4Addr::new(93, 184, 216, 34), 300)];
+
+        let mut response = build_response(&query, &answers, RCODE_NOERROR);
+        truncate_for_udp(&mut response, 4);
+        let header = parse_header(&response).unwrap();
+        assert!(header.is_truncated());
+        assert_eq!(header.an_count, 0);
+    }
 }
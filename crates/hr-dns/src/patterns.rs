@@ -0,0 +1,148 @@
+//! Pattern static records. A name written `/regex/` matches the whole
+//! query name (case-insensitive), for per-branch hostnames like
+//! `/feature-[a-z0-9-]+\.dev\.home\.arpa/`. Patterns are compiled once per
+//! config; validation refuses the ones that are too large or that would
+//! capture public names.
+
+use regex::{Regex, RegexBuilder};
+use tracing::warn;
+
+use crate::config::StaticRecord;
+
+pub const MAX_PATTERN_LEN: usize = 256;
+/// Compiled program and lazy DFA budgets of a pattern.
+const SIZE_LIMIT: usize = 256 * 1024;
+/// Names no pattern may match: it would take over public resolution.
+const PROBES: &[&str] = &["example.com", "www.google.com", "cloudflare.com", "a.root-servers.net", "localhost"];
+
+/// The regex of a `/regex/` name.
+pub fn pattern(name: &str) -> Option<&str> {
+    name.strip_prefix('/')?.strip_suffix('/').filter(|p| !p.is_empty())
+}
+
+pub fn compile(pattern: &str) -> Result<Regex, String> {
+    if pattern.len() > MAX_PATTERN_LEN {
+        return Err(format!("Pattern longer than {} characters", MAX_PATTERN_LEN));
+    }
+    let regex = RegexBuilder::new(&format!("^(?:{})$", pattern))
+        .case_insensitive(true)
+        .size_limit(SIZE_LIMIT)
+        .dfa_size_limit(SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid pattern /{}/: {}", pattern, e))?;
+    if let Some(probe) = PROBES.iter().find(|p| regex.is_match(p)) {
+        return Err(format!("Pattern /{}/ is too broad (matches {})", pattern, probe));
+    }
+    Ok(regex)
+}
+
+/// Checks a static record as the API receives it.
+pub fn validate(record: &StaticRecord) -> Result<(), String> {
+    let name = record.name.trim();
+    if let Some(pattern) = pattern(name) {
+        compile(pattern)?;
+    } else {
+        let labels = name.strip_prefix("*.").unwrap_or(name);
+        if labels.is_empty() || labels.contains('*') || labels.contains(char::is_whitespace) {
+            return Err(format!("Invalid record name: {} (name, *.suffix or /regex/)", record.name));
+        }
+    }
+    let value = record.value.trim();
+    let valid = match record.record_type.to_uppercase().as_str() {
+        "A" => value.parse::<std::net::Ipv4Addr>().is_ok(),
+        "AAAA" => value.parse::<std::net::Ipv6Addr>().is_ok(),
+        "CNAME" | "ALIAS" | "ANAME" => !value.is_empty() && !value.contains(char::is_whitespace),
+        other => return Err(format!("Unsupported record type {} for {}", other, record.name)),
+    };
+    if !valid {
+        return Err(format!("Invalid {} value for {}: {}", record.record_type, record.name, record.value));
+    }
+    Ok(())
+}
+
+/// The `/regex/` static records of a config, compiled.
+#[derive(Default)]
+pub struct StaticPatterns {
+    patterns: Vec<(Regex, Vec<StaticRecord>)>,
+}
+
+impl StaticPatterns {
+    /// Invalid patterns are left out.
+    pub fn new(records: &[StaticRecord]) -> Self {
+        let mut patterns: Vec<(Regex, Vec<StaticRecord>)> = Vec::new();
+        for record in records {
+            let Some(pattern) = pattern(record.name.trim()) else {
+                continue;
+            };
+            if let Some((_, group)) = patterns.iter_mut().find(|(re, _)| re.as_str() == format!("^(?:{})$", pattern)) {
+                group.push(record.clone());
+                continue;
+            }
+            match compile(pattern) {
+                Ok(regex) => patterns.push((regex, vec![record.clone()])),
+                Err(e) => warn!("Static record ignored: {}", e),
+            }
+        }
+        Self { patterns }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Records of the first pattern matching `name`, in config order.
+    pub fn find(&self, name: &str) -> Option<&[StaticRecord]> {
+        self.patterns
+            .iter()
+            .find(|(regex, _)| regex.is_match(name))
+            .map(|(_, records)| records.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, rtype: &str, value: &str) -> StaticRecord {
+        StaticRecord { name: name.to_string(), record_type: rtype.to_string(), value: value.to_string(), ttl: 60 }
+    }
+
+    #[test]
+    fn test_compile() {
+        let re = compile(r"feature-[a-z0-9-]+\.dev\.home\.arpa").unwrap();
+        assert!(re.is_match("feature-login-42.dev.home.arpa"));
+        assert!(re.is_match("FEATURE-X.dev.home.arpa"));
+        assert!(!re.is_match("feature-x.dev.home.arpa.evil.com"));
+        assert!(!re.is_match("x.feature-x.dev.home.arpa"));
+        assert!(compile(".*").is_err());
+        assert!(compile(r".+\.com").is_err());
+        assert!(compile("(a").is_err());
+        assert!(compile(&"a".repeat(MAX_PATTERN_LEN + 1)).is_err());
+        assert!(compile(r"\w{1000}{1000}\.lab").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&record("*.lab.home.arpa", "A", "10.0.0.50")).is_ok());
+        assert!(validate(&record("/pr-[0-9]+\\.lab/", "AAAA", "fd00::50")).is_ok());
+        assert!(validate(&record("nas.lan", "CNAME", "storage.lan")).is_ok());
+        assert!(validate(&record("a.*.lab", "A", "10.0.0.50")).is_err());
+        assert!(validate(&record("*.lab", "A", "fd00::1")).is_err());
+        assert!(validate(&record("/.*/", "A", "10.0.0.50")).is_err());
+        assert!(validate(&record("nas.lan", "MX", "mail.lan")).is_err());
+    }
+
+    #[test]
+    fn test_find() {
+        let patterns = StaticPatterns::new(&[
+            record("nas.lan", "A", "10.0.0.2"),
+            record(r"/pr-[0-9]+\.lab/", "A", "10.0.0.50"),
+            record(r"/pr-[0-9]+\.lab/", "AAAA", "fd00::50"),
+            record(r"/[a-z0-9-]+\.lab/", "A", "10.0.0.60"),
+            record("/(/", "A", "10.0.0.70"),
+        ]);
+        assert_eq!(patterns.find("pr-12.lab").unwrap().len(), 2);
+        assert_eq!(patterns.find("branch-x.lab").unwrap()[0].value, "10.0.0.60");
+        assert!(patterns.find("nas.lan").is_none());
+    }
+}
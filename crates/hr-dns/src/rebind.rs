@@ -0,0 +1,101 @@
+//! DNS rebinding protection: upstream answers must not point public names
+//! at addresses of the LAN (or the router itself), or a web page could
+//! reach local services through the victim's browser.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::records::{DnsRecord, RData};
+
+fn is_private_v4(ip: Ipv4Addr) -> bool {
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.octets()[0] == 0
+        // CGNAT 100.64.0.0/10 (Tailscale and other overlays)
+        || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64)
+}
+
+fn is_private_v6(ip: Ipv6Addr) -> bool {
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_private_v4(v4);
+    }
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || first & 0xfe00 == 0xfc00 // ULA
+        || first & 0xffc0 == 0xfe80 // link-local
+}
+
+/// Whether an address must not be returned for a public name.
+pub fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_private_v4(v4),
+        IpAddr::V6(v6) => is_private_v6(v6),
+    }
+}
+
+/// Whether `name` is one of the allowlisted domains or below one.
+pub fn is_allowed(name: &str, allowlist: &[String]) -> bool {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    allowlist.iter().any(|domain| {
+        let domain = domain.trim().trim_start_matches("*.").trim_end_matches('.').to_ascii_lowercase();
+        !domain.is_empty()
+            && (name == domain || name.strip_suffix(&domain).is_some_and(|rest| rest.ends_with('.')))
+    })
+}
+
+/// Remove A/AAAA answers pointing to private ranges unless `name` is
+/// allowlisted. Returns the number of answers removed.
+pub fn strip_private_answers(name: &str, answers: &mut Vec<DnsRecord>, allowlist: &[String]) -> usize {
+    let private = |r: &DnsRecord| match r.rdata {
+        RData::A(ip) => is_private_v4(ip),
+        RData::AAAA(ip) => is_private_v6(ip),
+        _ => false,
+    };
+    if !answers.iter().any(private) || is_allowed(name, allowlist) {
+        return 0;
+    }
+    let before = answers.len();
+    answers.retain(|r| !private(r));
+    before - answers.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_private_answers() {
+        let mut answers = vec![
+            DnsRecord::cname("evil.example.com", "target.example.net", 60),
+            DnsRecord::a("target.example.net", "192.168.1.1".parse().unwrap(), 60),
+            DnsRecord::a("target.example.net", "93.184.216.34".parse().unwrap(), 60),
+            DnsRecord::aaaa("target.example.net", "fd00::1".parse().unwrap(), 60),
+        ];
+        assert_eq!(strip_private_answers("evil.example.com", &mut answers, &[]), 2);
+        assert_eq!(answers.len(), 2);
+        assert!(matches!(answers[1].rdata, RData::A(ip) if ip == Ipv4Addr::new(93, 184, 216, 34)));
+
+        let mut answers = vec![DnsRecord::a("vpn.corp.example", "10.8.0.1".parse().unwrap(), 60)];
+        let allowlist = vec!["corp.example".to_string()];
+        assert_eq!(strip_private_answers("vpn.corp.example", &mut answers, &allowlist), 0);
+        assert_eq!(answers.len(), 1);
+    }
+
+    #[test]
+    fn test_allowlist_and_ranges() {
+        let allowlist = vec!["plex.direct".to_string(), "*.vpn.example.".to_string()];
+        assert!(is_allowed("abc.plex.direct", &allowlist));
+        assert!(is_allowed("PLEX.DIRECT.", &allowlist));
+        assert!(is_allowed("gw.vpn.example", &allowlist));
+        assert!(!is_allowed("notplex.direct", &allowlist));
+
+        for ip in ["127.0.0.1", "169.254.1.1", "0.0.0.0", "100.101.1.1", "::1", "fe80::1", "::ffff:10.0.0.1"] {
+            assert!(is_private(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["8.8.8.8", "2606:4700::1111"] {
+            assert!(!is_private(ip.parse().unwrap()), "{ip}");
+        }
+    }
+}
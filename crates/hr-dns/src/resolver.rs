@@ -1,9 +1,14 @@
-use std::net::{Ipv4Addr, Ipv6Addr};
-use tracing::{debug, warn};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use tracing::{debug, info, warn};
 
-use crate::SharedDnsState;
+use crate::{DnsState, SharedDnsState};
+use crate::blocked_log::BlockReason;
+use crate::cache::DnsCache;
 use crate::config::StaticRecord;
+use crate::ecs::{self, ClientSubnet};
+use crate::health;
 use crate::packet::{self, DnsQuery, RCODE_NOERROR, RCODE_NXDOMAIN, RCODE_SERVFAIL};
+use crate::rebind;
 use crate::records::{DnsRecord, RData, RecordType};
 
 /// Result of DNS resolution
@@ -11,23 +16,39 @@ pub struct ResolveResult {
     pub records: Vec<DnsRecord>,
     pub rcode: u8,
     pub cached: bool,
-    pub blocked: bool,
+    /// Why the query was blocked, `None` when answered.
+    pub blocked: Option<BlockReason>,
+    /// Upstream answers removed by the rebinding protection.
+    pub sanitized: usize,
 }
 
+/// ALIAS records followed in a row before giving up (loops).
+const MAX_ALIAS_DEPTH: u8 = 8;
+
 /// Resolve a DNS query through the resolution chain:
 /// 1. DHCP lease hostnames (expand-hosts)
-/// 2. Static records (exact match, then wildcard)
+/// 2. Static records (exact match, then wildcard, then regex); ALIAS/ANAME records are
+///    flattened: the target's addresses are answered under the queried name.
+///    Then the records published for the proxy routes
 /// 3. Wildcard local domain (fallback for unknown hosts)
-/// 4. Adblock filter
+/// 4. Adblock: scheduled profiles, then the filter lists (both skipped
+///    while blocking is paused for the client or the name temporarily
+///    allowed); SafeSearch rewrites
 /// 5. Cache
-/// 6. Upstream forward
-pub async fn resolve(query: &DnsQuery, state: &SharedDnsState) -> ResolveResult {
+/// 6. Upstream forward, unless in local-only mode: stale cache or an
+///    immediate SERVFAIL then
+pub async fn resolve(query: &DnsQuery, state: &SharedDnsState, client: IpAddr) -> ResolveResult {
+    resolve_depth(query, state, client, 0).await
+}
+
+async fn resolve_depth(query: &DnsQuery, state: &SharedDnsState, client: IpAddr, depth: u8) -> ResolveResult {
     if query.questions.is_empty() {
         return ResolveResult {
             records: vec![],
             rcode: RCODE_NOERROR,
             cached: false,
-            blocked: false,
+            blocked: None,
+            sanitized: 0,
         };
     }
 
@@ -54,7 +75,8 @@ pub async fn resolve(query: &DnsQuery, state: &SharedDnsState) -> ResolveResult
                         records: vec![DnsRecord::a(name, ip, 60)],
                         rcode: RCODE_NOERROR,
                         cached: false,
-                        blocked: false,
+                        blocked: None,
+                        sanitized: 0,
                     };
                 }
                 // Hostname exists in DHCP leases but only has IPv4 — return NODATA
@@ -63,7 +85,8 @@ pub async fn resolve(query: &DnsQuery, state: &SharedDnsState) -> ResolveResult
                     records: vec![],
                     rcode: RCODE_NOERROR,
                     cached: false,
-                    blocked: false,
+                    blocked: None,
+                    sanitized: 0,
                 };
             }
         }
@@ -71,6 +94,7 @@ pub async fn resolve(query: &DnsQuery, state: &SharedDnsState) -> ResolveResult
 
     // 2. Static records (exact match)
     let mut has_static_exact = false;
+    let mut alias = None;
     for static_rec in &config.static_records {
         if static_rec.name.to_lowercase() == *name {
             has_static_exact = true;
@@ -78,6 +102,10 @@ pub async fn resolve(query: &DnsQuery, state: &SharedDnsState) -> ResolveResult
                 "A" => RecordType::A,
                 "AAAA" => RecordType::AAAA,
                 "CNAME" => RecordType::CNAME,
+                "ALIAS" | "ANAME" => {
+                    alias = Some((static_rec.value.to_lowercase(), static_rec.ttl));
+                    continue;
+                }
                 _ => continue,
             };
 
@@ -88,12 +116,20 @@ pub async fn resolve(query: &DnsQuery, state: &SharedDnsState) -> ResolveResult
                         records: vec![record],
                         rcode: RCODE_NOERROR,
                         cached: false,
-                        blocked: false,
+                        blocked: None,
+                        sanitized: 0,
                     };
                 }
             }
         }
     }
+    if let Some((target, ttl)) = alias
+        && matches!(qtype, RecordType::A | RecordType::AAAA | RecordType::ANY)
+    {
+        let name = name.clone();
+        drop(state_read);
+        return flatten_alias(&name, &target, ttl, qtype, state, client, depth).await;
+    }
     // Static record exists but not for the queried type (e.g. AAAA query when
     // only A record exists) — return NODATA to prevent wildcard/upstream from
     // returning the server IPv6, which would bypass direct container access.
@@ -103,48 +139,41 @@ pub async fn resolve(query: &DnsQuery, state: &SharedDnsState) -> ResolveResult
             records: vec![],
             rcode: RCODE_NOERROR,
             cached: false,
-            blocked: false,
+            blocked: None,
+            sanitized: 0,
         };
     }
 
-    // 2b. Static records (wildcard: *.example.com matches foo.example.com)
-    if let Some(dot_pos) = name.find('.') {
-        let wildcard = format!("*.{}", &name[dot_pos + 1..]);
-        let mut has_wildcard_record = false;
-        for static_rec in &config.static_records {
-            if static_rec.name.to_lowercase() == wildcard {
-                has_wildcard_record = true;
-                let matching_type = match static_rec.record_type.to_uppercase().as_str() {
-                    "A" => RecordType::A,
-                    "AAAA" => RecordType::AAAA,
-                    "CNAME" => RecordType::CNAME,
-                    _ => continue,
-                };
-
-                if qtype == matching_type || qtype == RecordType::ANY {
-                    if let Some(record) = parse_static_record(name, static_rec, matching_type) {
-                        debug!("Resolved {} via wildcard static record ({})", name, wildcard);
-                        return ResolveResult {
-                            records: vec![record],
-                            rcode: RCODE_NOERROR,
-                            cached: false,
-                            blocked: false,
-                        };
-                    }
-                }
-            }
+    // 2b. Static records (wildcard: *.example.com matches foo.example.com
+    // and a.foo.example.com; the closest wildcard wins)
+    let mut suffix = name.as_str();
+    while let Some(dot_pos) = suffix.find('.') {
+        suffix = &suffix[dot_pos + 1..];
+        let wildcard = format!("*.{}", suffix);
+        let records: Vec<&StaticRecord> =
+            config.static_records.iter().filter(|r| r.name.to_lowercase() == wildcard).collect();
+        if let Some(result) = static_answer(name, &records, qtype) {
+            debug!("Resolved {} via wildcard static record ({})", name, wildcard);
+            return result;
         }
-        if has_wildcard_record {
-            debug!("Wildcard static record exists for {} but not type {:?} — NODATA", name, qtype);
-            return ResolveResult {
-                records: vec![],
-                rcode: RCODE_NOERROR,
-                cached: false,
-                blocked: false,
-            };
+    }
+
+    // 2c. Static records (regex: /pattern/ matches the whole name)
+    if let Some(records) = state_read.static_patterns.find(name) {
+        let records: Vec<&StaticRecord> = records.iter().collect();
+        if let Some(result) = static_answer(name, &records, qtype) {
+            debug!("Resolved {} via pattern static record ({})", name, records[0].name);
+            return result;
         }
     }
 
+    // 2d. Route records (proxy routes and applications -> the router)
+    let records: Vec<&StaticRecord> = state_read.route_records.iter().filter(|r| r.name == *name).collect();
+    if let Some(result) = static_answer(name, &records, qtype) {
+        debug!("Resolved {} via route record", name);
+        return result;
+    }
+
     // 3. Wildcard local domain (*.mynetwk.biz -> server IP, fallback for unknown hosts)
     if !config.local_domain.is_empty() {
         let is_local = name.ends_with(&format!(".{}", config.local_domain))
@@ -185,7 +214,8 @@ pub async fn resolve(query: &DnsQuery, state: &SharedDnsState) -> ResolveResult
                     records,
                     rcode: RCODE_NOERROR,
                     cached: false,
-                    blocked: false,
+                    blocked: None,
+                    sanitized: 0,
                 };
             }
 
@@ -197,85 +227,142 @@ pub async fn resolve(query: &DnsQuery, state: &SharedDnsState) -> ResolveResult
                 records: vec![],
                 rcode: RCODE_NOERROR,
                 cached: false,
-                blocked: false,
+                blocked: None,
+                sanitized: 0,
             };
         }
     }
 
-    // 4. Adblock filter
-    if state_read.adblock_enabled && state_read.adblock.read().await.is_blocked(name) {
-        debug!("Blocked {} via adblock", name);
-        let records = match state_read.adblock_block_response.as_str() {
-            "zero_ip" => match qtype {
-                RecordType::A => vec![DnsRecord::a(name, Ipv4Addr::UNSPECIFIED, 300)],
-                RecordType::AAAA => vec![DnsRecord::aaaa(name, Ipv6Addr::UNSPECIFIED, 300)],
-                _ => vec![],
-            },
-            _ => {
+    // 4. Adblock: scheduled profiles, then the filter lists
+    let schedule = &state_read.adblock_schedule;
+    let now = chrono::Utc::now();
+    let paused = schedule.is_paused(client, now) || schedule.is_allowed(name, client, now);
+    if !paused {
+        let mac = match client {
+            IpAddr::V4(ip) if schedule.needs_mac() => {
+                state_read.lease_store.read().await.get_lease(ip).map(|l| l.mac.to_lowercase())
+            }
+            _ => None,
+        };
+        let who = hr_adblock::schedule::Client { ip: client, mac: mac.as_deref() };
+        if let Some(profile) = schedule.profile_block(name, who, chrono::Local::now().naive_local()) {
+            debug!("Blocked {} for {} via profile {}", name, client, profile);
+            return blocked_response(name, qtype, &state_read, BlockReason::Profile { profile });
+        }
+    }
+    let filtering = state_read.adblock_enabled && !paused;
+    if filtering {
+        let adblock = state_read.adblock.read().await;
+        if adblock.is_blocked(name)
+            && let Some(m) = adblock.explain(name)
+        {
+            debug!("Blocked {} via adblock ({} from {})", name, m.rule, m.source);
+            return blocked_response(name, qtype, &state_read, BlockReason::from_match(m, name));
+        }
+    }
+
+    // 4b. SafeSearch: the safe host's addresses under the queried name
+    if state_read.safesearch.covers(name) {
+        let mac = match client {
+            IpAddr::V4(ip) if state_read.safesearch.needs_mac() => {
+                state_read.lease_store.read().await.get_lease(ip).map(|l| l.mac.to_lowercase())
+            }
+            _ => None,
+        };
+        let who = hr_adblock::schedule::Client { ip: client, mac: mac.as_deref() };
+        if let Some(target) = state_read.safesearch.rewrite(name, who) {
+            debug!("SafeSearch: {} -> {}", name, target);
+            if !matches!(qtype, RecordType::A | RecordType::AAAA | RecordType::ANY) {
+                // No HTTPS/SVCB record leading around the rewrite
                 return ResolveResult {
                     records: vec![],
-                    rcode: RCODE_NXDOMAIN,
+                    rcode: RCODE_NOERROR,
                     cached: false,
-                    blocked: true,
+                    blocked: None,
+                    sanitized: 0,
                 };
             }
-        };
-        return ResolveResult {
-            records,
-            rcode: RCODE_NOERROR,
-            cached: false,
-            blocked: true,
-        };
+            let name = name.clone();
+            drop(state_read);
+            return flatten_alias(&name, target, 300, qtype, state, client, depth).await;
+        }
     }
 
-    // 5. Cache lookup (including negative cache)
-    if let Some((cached_records, is_negative)) = state_read.dns_cache.get_with_negative(name, qtype).await {
+    // 5. Cache lookup (including negative cache), per ECS subnet
+    let subnet = ecs::subnet_for(&config.ecs, client);
+    let cache_name = ecs::cache_name(name, subnet);
+    if let Some((cached_records, is_negative)) = state_read.dns_cache.get_with_negative(&cache_name, qtype).await {
         if is_negative {
             debug!("Resolved {} via negative cache (NXDOMAIN)", name);
             return ResolveResult {
                 records: vec![],
                 rcode: RCODE_NXDOMAIN,
                 cached: true,
-                blocked: false,
+                blocked: None,
+                sanitized: 0,
             };
         }
+        if filtering && let Some(reason) = cname_cloaked(name, &cached_records, &state_read.adblock).await {
+            return blocked_response(name, qtype, &state_read, reason);
+        }
         debug!("Resolved {} via cache ({} records)", name, cached_records.len());
         return ResolveResult {
             records: cached_records,
             rcode: RCODE_NOERROR,
             cached: true,
-            blocked: false,
+            blocked: None,
+            sanitized: 0,
         };
     }
 
     // 6. Upstream forward
-    let forward_bytes = build_forward_query(query);
+    if state_read.health.is_degraded() {
+        return stale_or_servfail(&state_read.dns_cache, &cache_name, qtype).await;
+    }
+
+    let forward_bytes = build_forward_query(query, subnet);
 
     match state_read.upstream.forward(&forward_bytes).await {
         Ok(response_bytes) => {
+            state_read.health.record_success();
             match packet::parse_response_sections(&response_bytes) {
-                Ok(parsed) => {
+                Ok(mut parsed) => {
                     let rcode = parsed.header.rcode();
 
+                    let sanitized = if config.rebind_protection {
+                        rebind::strip_private_answers(name, &mut parsed.answers, &config.rebind_allowlist)
+                    } else {
+                        0
+                    };
+                    if sanitized > 0 {
+                        info!("Stripped {} private answers for {} (DNS rebinding protection)", sanitized, name);
+                        state_read.stats.record_rebind_stripped(sanitized);
+                    }
+
                     // Cache only answer records (not authority/additional)
                     // OPT records already filtered by parse_response_sections
                     if !parsed.answers.is_empty() {
-                        state_read.dns_cache.insert(name, qtype, &parsed.answers).await;
+                        state_read.dns_cache.insert(&cache_name, qtype, &parsed.answers).await;
                     } else if rcode == RCODE_NXDOMAIN || (rcode == RCODE_NOERROR && parsed.answers.is_empty()) {
                         // Negative caching (RFC 2308): cache NXDOMAIN/NODATA
                         // Extract TTL from SOA record in authority section
                         let neg_ttl = extract_soa_negative_ttl(&parsed.authority);
                         if neg_ttl > 0 {
-                            state_read.dns_cache.insert_negative(name, qtype, neg_ttl).await;
+                            state_read.dns_cache.insert_negative(&cache_name, qtype, neg_ttl).await;
                         }
                     }
 
+                    if filtering && let Some(reason) = cname_cloaked(name, &parsed.answers, &state_read.adblock).await {
+                        return blocked_response(name, qtype, &state_read, reason);
+                    }
+
                     debug!("Resolved {} via upstream ({} answers, rcode={})", name, parsed.answers.len(), rcode);
                     ResolveResult {
                         records: parsed.answers,
                         rcode,
                         cached: false,
-                        blocked: false,
+                        blocked: None,
+                        sanitized,
                     }
                 }
                 Err(e) => {
@@ -284,24 +371,170 @@ pub async fn resolve(query: &DnsQuery, state: &SharedDnsState) -> ResolveResult
                         records: vec![],
                         rcode: RCODE_SERVFAIL,
                         cached: false,
-                        blocked: false,
+                        blocked: None,
+                        sanitized: 0,
                     }
                 }
             }
         }
         Err(e) => {
             warn!("Upstream forward failed for {}: {}", name, e);
-            ResolveResult {
+            health::record_forward_failure(&state_read.health);
+            stale_or_servfail(&state_read.dns_cache, &cache_name, qtype).await
+        }
+    }
+}
+
+/// Answer of a blocked name: unspecified addresses, the sinkhole address
+/// (where the proxy serves the "blocked" page) or NXDOMAIN.
+fn blocked_response(name: &str, qtype: RecordType, state: &DnsState, reason: BlockReason) -> ResolveResult {
+    let records = match state.adblock_block_response.as_str() {
+        "zero_ip" => match qtype {
+            RecordType::A => vec![DnsRecord::a(name, Ipv4Addr::UNSPECIFIED, 300)],
+            RecordType::AAAA => vec![DnsRecord::aaaa(name, Ipv6Addr::UNSPECIFIED, 300)],
+            _ => vec![],
+        },
+        // No AAAA: clients fall back to the IPv4 sinkhole. Short TTL so a
+        // temporary allow takes effect quickly.
+        "sinkhole" => match qtype {
+            RecordType::A => vec![DnsRecord::a(name, state.sinkhole_ip(), 10)],
+            _ => vec![],
+        },
+        _ => {
+            return ResolveResult {
                 records: vec![],
-                rcode: RCODE_SERVFAIL,
+                rcode: RCODE_NXDOMAIN,
                 cached: false,
-                blocked: false,
+                blocked: Some(reason),
+                sanitized: 0,
+            };
+        }
+    };
+    ResolveResult {
+        records,
+        rcode: RCODE_NOERROR,
+        cached: false,
+        blocked: Some(reason),
+        sanitized: 0,
+    }
+}
+
+/// CNAME cloaking: a first-party name aliased to a blocked tracker.
+async fn cname_cloaked(
+    name: &str,
+    answers: &[DnsRecord],
+    adblock: &tokio::sync::RwLock<hr_adblock::AdblockEngine>,
+) -> Option<BlockReason> {
+    let targets: Vec<&str> = answers
+        .iter()
+        .filter_map(|r| match &r.rdata {
+            RData::CNAME(target) => Some(target.trim_end_matches('.')),
+            _ => None,
+        })
+        .collect();
+    if targets.is_empty() {
+        return None;
+    }
+    let m = adblock.read().await.blocked_cname(name, targets)?;
+    debug!("Blocked {} via CNAME {} ({} from {})", name, m.name, m.rule, m.source);
+    Some(BlockReason::from_match(m, name))
+}
+
+/// Answer `name` with the addresses of `target` (CNAME chain dropped, TTL
+/// capped by the ALIAS record's), as if they were its own: unlike a CNAME,
+/// this works at a zone apex.
+async fn flatten_alias(
+    name: &str,
+    target: &str,
+    ttl: u32,
+    qtype: RecordType,
+    state: &SharedDnsState,
+    client: IpAddr,
+    depth: u8,
+) -> ResolveResult {
+    if depth >= MAX_ALIAS_DEPTH {
+        warn!("ALIAS chain too long (or looping) at {} -> {}", name, target);
+        return ResolveResult {
+            records: vec![],
+            rcode: RCODE_SERVFAIL,
+            cached: false,
+            blocked: None,
+            sanitized: 0,
+        };
+    }
+
+    let qtypes: &[RecordType] = match qtype {
+        RecordType::ANY => &[RecordType::A, RecordType::AAAA],
+        RecordType::A => &[RecordType::A],
+        _ => &[RecordType::AAAA],
+    };
+    let mut records = Vec::new();
+    for &qtype in qtypes {
+        let mut raw = vec![0x00, 0x00, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        packet::encode_name(target, &mut raw);
+        raw.extend_from_slice(&qtype.to_u16().to_be_bytes());
+        raw.extend_from_slice(&[0x00, 0x01]);
+        let Ok(query) = packet::parse_query(&raw) else {
+            break;
+        };
+
+        let result = Box::pin(resolve_depth(&query, state, client, depth + 1)).await;
+        if result.rcode == RCODE_SERVFAIL {
+            return result;
+        }
+        records.extend(result.records.into_iter().filter_map(|r| {
+            let rdata = match r.rdata {
+                RData::A(_) | RData::AAAA(_) => r.rdata,
+                _ => return None,
+            };
+            Some(DnsRecord { name: name.to_string(), ttl: r.ttl.min(ttl), rdata, ..r })
+        }));
+    }
+
+    debug!("Resolved {} via ALIAS to {} ({} records)", name, target, records.len());
+    // The alias exists even when its target doesn't: NODATA, not NXDOMAIN
+    ResolveResult {
+        records,
+        rcode: RCODE_NOERROR,
+        cached: false,
+        blocked: None,
+        sanitized: 0,
+    }
+}
+
+/// No upstream to ask: an expired cache entry if there is one, else SERVFAIL.
+async fn stale_or_servfail(cache: &DnsCache, name: &str, qtype: RecordType) -> ResolveResult {
+    let stale = cache.get_stale(name, qtype).await;
+    let outcome = if stale.is_some() { "stale" } else { "servfail" };
+    hr_common::metrics::registry()
+        .counter(
+            "homeroute_dns_upstream_unavailable_total",
+            "Queries that could not be forwarded upstream, by answer given.",
+            &[("answer", outcome)],
+        )
+        .inc();
+    match stale {
+        Some(records) => {
+            debug!("Resolved {} from stale cache (upstreams unavailable)", name);
+            ResolveResult {
+                records,
+                rcode: RCODE_NOERROR,
+                cached: true,
+                blocked: None,
+                sanitized: 0,
             }
         }
+        None => ResolveResult {
+            records: vec![],
+            rcode: RCODE_SERVFAIL,
+            cached: false,
+            blocked: None,
+            sanitized: 0,
+        },
     }
 }
 
-fn build_forward_query(query: &DnsQuery) -> Vec<u8> {
+fn build_forward_query(query: &DnsQuery, subnet: Option<ClientSubnet>) -> Vec<u8> {
     let mut buf = Vec::with_capacity(512);
 
     // Header
@@ -326,8 +559,10 @@ fn build_forward_query(query: &DnsQuery) -> Vec<u8> {
     buf.extend_from_slice(&1232u16.to_be_bytes());
     // TTL: extended RCODE (0) + version (0) + flags (0, no DO bit)
     buf.extend_from_slice(&0u32.to_be_bytes());
-    // RDLENGTH: 0 (no options)
-    buf.extend_from_slice(&0u16.to_be_bytes());
+    // RDLENGTH + options: only our ECS, the client's options are not forwarded
+    let options = subnet.map(|s| s.encode()).unwrap_or_default();
+    buf.extend_from_slice(&(options.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&options);
 
     buf
 }
@@ -343,6 +578,41 @@ fn extract_soa_negative_ttl(authority: &[DnsRecord]) -> u32 {
     0 // No SOA found — don't cache negative response (RFC 2308)
 }
 
+/// Answer from the wildcard or pattern records matching `name`: the
+/// record of the queried type, or NODATA when they have other types only.
+fn static_answer(name: &str, records: &[&StaticRecord], qtype: RecordType) -> Option<ResolveResult> {
+    if records.is_empty() {
+        return None;
+    }
+    for static_rec in records {
+        let matching_type = match static_rec.record_type.to_uppercase().as_str() {
+            "A" => RecordType::A,
+            "AAAA" => RecordType::AAAA,
+            "CNAME" => RecordType::CNAME,
+            _ => continue,
+        };
+        if (qtype == matching_type || qtype == RecordType::ANY)
+            && let Some(record) = parse_static_record(name, static_rec, matching_type)
+        {
+            return Some(ResolveResult {
+                records: vec![record],
+                rcode: RCODE_NOERROR,
+                cached: false,
+                blocked: None,
+                sanitized: 0,
+            });
+        }
+    }
+    debug!("Static record matches {} but not type {:?} — NODATA", name, qtype);
+    Some(ResolveResult {
+        records: vec![],
+        rcode: RCODE_NOERROR,
+        cached: false,
+        blocked: None,
+        sanitized: 0,
+    })
+}
+
 fn parse_static_record(name: &str, rec: &StaticRecord, rtype: RecordType) -> Option<DnsRecord> {
     match rtype {
         RecordType::A => {
@@ -359,3 +629,87 @@ fn parse_static_record(name: &str, rec: &StaticRecord, rtype: RecordType) -> Opt
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::probe_query;
+    use std::sync::Arc;
+    use tokio::net::UdpSocket;
+    use tokio::sync::RwLock;
+
+    /// Upstream answering every query with a private address.
+    async fn private_upstream() -> String {
+        let socket = UdpSocket::bind("[::1]:0").await.unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 4096];
+            while let Ok((len, peer)) = socket.recv_from(&mut buf).await {
+                let query = packet::parse_query(&buf[..len]).unwrap();
+                let name = query.questions[0].name.clone();
+                let answer = DnsRecord::a(&name, Ipv4Addr::new(192, 168, 1, 10), 60);
+                let response = packet::build_response(&query, &[answer], RCODE_NOERROR);
+                let _ = socket.send_to(&response, peer).await;
+            }
+        });
+        addr
+    }
+
+    fn state(upstream: String, query_log: Option<&std::path::Path>) -> SharedDnsState {
+        let config: crate::config::DnsConfig =
+            serde_json::from_value(serde_json::json!({"upstream_servers": [upstream]})).unwrap();
+        let leases = std::env::temp_dir().join("hr-dns-resolver-test-leases");
+        Arc::new(RwLock::new(DnsState {
+            dns_cache: DnsCache::new(config.cache_size),
+            upstream: crate::upstream::UpstreamForwarder::new(
+                config.upstream_servers.clone(),
+                config.upstream_timeout_ms,
+            ),
+            config,
+            query_logger: query_log.map(|p| crate::logging::QueryLogger::new(&p.to_string_lossy())),
+            blocked_log: Default::default(),
+            adblock: Arc::new(RwLock::new(hr_adblock::AdblockEngine::new())),
+            lease_store: Arc::new(RwLock::new(hr_dhcp::LeaseStore::new(&leases.to_string_lossy()))),
+            adblock_enabled: false,
+            adblock_schedule: Default::default(),
+            safesearch: Default::default(),
+            static_patterns: Default::default(),
+            route_records: Vec::new(),
+            adblock_block_response: String::new(),
+            stats: Default::default(),
+            bans: None,
+            health: Default::default(),
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_sanitized_answers_reported() {
+        let log_path = std::env::temp_dir().join(format!("hr-dns-sanitized-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&log_path);
+        let state = state(private_upstream().await, Some(&log_path));
+        let client: IpAddr = "192.168.1.50".parse().unwrap();
+
+        let query_bytes = probe_query("rebind.example.com", RecordType::A, false);
+        let query = packet::parse_query(&query_bytes).unwrap();
+        let result = resolve(&query, &state, client).await;
+        assert_eq!(result.sanitized, 1);
+        assert!(result.records.is_empty());
+        assert_eq!(result.rcode, RCODE_NOERROR);
+
+        let query_bytes = probe_query("other.example.com", RecordType::A, false);
+        crate::server::handle_dns_query(&query_bytes, &state, (client, 5353).into()).await;
+        let mut entry = None;
+        for _ in 0..50 {
+            let content = std::fs::read_to_string(&log_path).unwrap_or_default();
+            if let Some(line) = content.lines().next() {
+                entry = Some(serde_json::from_str::<serde_json::Value>(line).unwrap());
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let _ = std::fs::remove_file(&log_path);
+        let entry = entry.expect("query log entry");
+        assert_eq!(entry["domain"], "other.example.com");
+        assert_eq!(entry["sanitized"], 1);
+    }
+}
@@ -0,0 +1,202 @@
+//! SafeSearch: search engines and YouTube answered with the addresses of
+//! their enforced-safe hosts (`forcesafesearch.google.com`,
+//! `strict.bing.com`, `safe.duckduckgo.com`, `restrict.youtube.com`), which
+//! serve the same sites with filtering forced on.
+
+use hr_adblock::schedule::{Client, ClientSet};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum YoutubeMode {
+    Off,
+    Moderate,
+    #[default]
+    Strict,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SafeSearchConfig {
+    /// Enforced for every client, except those overridden.
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_true")]
+    pub google: bool,
+    #[serde(default = "default_true")]
+    pub bing: bool,
+    #[serde(default = "default_true")]
+    pub duckduckgo: bool,
+    #[serde(default)]
+    pub youtube: YoutubeMode,
+    /// Per client overrides, the first matching one wins.
+    #[serde(default)]
+    pub clients: Vec<SafeSearchClient>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SafeSearchClient {
+    /// IPs, CIDR ranges or MAC addresses.
+    pub clients: Vec<String>,
+    pub enabled: bool,
+    /// YouTube mode for these clients, the global one when absent.
+    #[serde(default)]
+    pub youtube: Option<YoutubeMode>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for SafeSearchConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Service {
+    Google,
+    Bing,
+    DuckDuckGo,
+    Youtube,
+}
+
+const YOUTUBE_HOSTS: &[&str] = &[
+    "www.youtube.com",
+    "m.youtube.com",
+    "youtubei.googleapis.com",
+    "youtube.googleapis.com",
+    "www.youtube-nocookie.com",
+];
+
+fn service(name: &str) -> Option<Service> {
+    match name {
+        "bing.com" | "www.bing.com" => return Some(Service::Bing),
+        "duckduckgo.com" | "www.duckduckgo.com" | "start.duckduckgo.com" => return Some(Service::DuckDuckGo),
+        _ if YOUTUBE_HOSTS.contains(&name) => return Some(Service::Youtube),
+        _ => {}
+    }
+    // google.<tld> and www.google.<tld>, country domains included
+    // (google.fr, google.co.uk)
+    let tld = name.strip_prefix("www.").unwrap_or(name).strip_prefix("google.")?;
+    let labels = tld.split('.').count();
+    (labels <= 2 && tld.chars().all(|c| c.is_ascii_lowercase() || c == '.')).then_some(Service::Google)
+}
+
+/// The SafeSearch config compiled for the resolver.
+#[derive(Debug, Clone, Default)]
+pub struct SafeSearch {
+    config: SafeSearchConfig,
+    clients: Vec<(ClientSet, bool, Option<YoutubeMode>)>,
+}
+
+impl SafeSearch {
+    pub fn new(config: &SafeSearchConfig) -> Result<Self, String> {
+        let clients = config
+            .clients
+            .iter()
+            .map(|c| Ok((ClientSet::parse(&c.clients)?, c.enabled, c.youtube)))
+            .collect::<Result<_, String>>()?;
+        Ok(Self { config: config.clone(), clients })
+    }
+
+    pub fn config(&self) -> &SafeSearchConfig {
+        &self.config
+    }
+
+    /// Whether a client override matches by MAC, i.e. the resolver has to
+    /// look the client's lease up.
+    pub fn needs_mac(&self) -> bool {
+        self.clients.iter().any(|(set, _, _)| set.has_macs())
+    }
+
+    /// Whether `name` may be rewritten at all, before any client lookup.
+    pub fn covers(&self, name: &str) -> bool {
+        (self.config.enabled || self.clients.iter().any(|(_, enabled, _)| *enabled)) && service(name).is_some()
+    }
+
+    /// The safe host `name` is answered with for `client`.
+    pub fn rewrite(&self, name: &str, client: Client) -> Option<&'static str> {
+        let service = service(name)?;
+        let (enabled, youtube) = match self.clients.iter().find(|(set, _, _)| set.contains(client)) {
+            Some((_, enabled, youtube)) => (*enabled, youtube.unwrap_or(self.config.youtube)),
+            None => (self.config.enabled, self.config.youtube),
+        };
+        if !enabled {
+            return None;
+        }
+        match service {
+            Service::Google if self.config.google => Some("forcesafesearch.google.com"),
+            Service::Bing if self.config.bing => Some("strict.bing.com"),
+            Service::DuckDuckGo if self.config.duckduckgo => Some("safe.duckduckgo.com"),
+            Service::Youtube => match youtube {
+                YoutubeMode::Off => None,
+                YoutubeMode::Moderate => Some("restrictmoderate.youtube.com"),
+                YoutubeMode::Strict => Some("restrict.youtube.com"),
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(ip: &str) -> Client<'static> {
+        Client { ip: ip.parse().unwrap(), mac: None }
+    }
+
+    #[test]
+    fn test_services() {
+        assert_eq!(service("www.google.com"), Some(Service::Google));
+        assert_eq!(service("google.co.uk"), Some(Service::Google));
+        assert_eq!(service("mail.google.com"), None);
+        assert_eq!(service("forcesafesearch.google.com"), None);
+        assert_eq!(service("google.example.evil.com"), None);
+        assert_eq!(service("m.youtube.com"), Some(Service::Youtube));
+        assert_eq!(service("restrict.youtube.com"), None);
+        assert_eq!(service("www.bing.com"), Some(Service::Bing));
+        assert_eq!(service("duckduckgo.com"), Some(Service::DuckDuckGo));
+    }
+
+    #[test]
+    fn test_global() {
+        let config = SafeSearchConfig { enabled: true, bing: false, youtube: YoutubeMode::Moderate, ..Default::default() };
+        let safe = SafeSearch::new(&config).unwrap();
+        let anyone = client("192.168.1.20");
+        assert_eq!(safe.rewrite("www.google.fr", anyone), Some("forcesafesearch.google.com"));
+        assert_eq!(safe.rewrite("www.youtube.com", anyone), Some("restrictmoderate.youtube.com"));
+        assert_eq!(safe.rewrite("www.bing.com", anyone), None);
+        assert_eq!(safe.rewrite("www.example.com", anyone), None);
+
+        let off = SafeSearch::new(&SafeSearchConfig::default()).unwrap();
+        assert!(!off.covers("www.google.com"));
+        assert_eq!(off.rewrite("www.google.com", anyone), None);
+    }
+
+    #[test]
+    fn test_client_overrides() {
+        let config: SafeSearchConfig = serde_json::from_value(serde_json::json!({
+            "enabled": false,
+            "clients": [
+                { "clients": ["192.168.1.50", "aa:bb:cc:dd:ee:ff"], "enabled": true, "youtube": "strict" },
+                { "clients": ["192.168.1.0/24"], "enabled": false },
+            ],
+        }))
+        .unwrap();
+        let safe = SafeSearch::new(&config).unwrap();
+        assert!(safe.covers("www.google.com"));
+        assert!(safe.needs_mac());
+        assert_eq!(safe.rewrite("www.youtube.com", client("192.168.1.50")), Some("restrict.youtube.com"));
+        assert_eq!(safe.rewrite("www.google.com", client("192.168.1.51")), None);
+        let by_mac = Client { ip: "10.0.0.9".parse().unwrap(), mac: Some("aa:bb:cc:dd:ee:ff") };
+        assert_eq!(safe.rewrite("duckduckgo.com", by_mac), Some("safe.duckduckgo.com"));
+
+        let bad = SafeSearchConfig {
+            clients: vec![SafeSearchClient { clients: vec!["kid".to_string()], enabled: true, youtube: None }],
+            ..Default::default()
+        };
+        assert!(SafeSearch::new(&bad).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! Adblock pauses and scheduled profiles over time.
+//!
+//! The resolver reads the shared `AdblockSchedule` on every query; this
+//! loop only expires pauses and temporary allows, and publishes the state flips.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{Local, Utc};
+use hr_adblock::AdblockSchedule;
+use hr_adblock::schedule::ScheduleChange;
+use hr_common::events::{AdblockEvent, EventBus};
+use tracing::info;
+
+/// Profile windows open and close on minute boundaries.
+const TICK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Expire pauses and allows, and publish the profiles opening or closing.
+pub async fn run_adblock_schedule(schedule: Arc<AdblockSchedule>, events: Arc<EventBus>) -> Result<()> {
+    let mut tick = tokio::time::interval(TICK_INTERVAL);
+    loop {
+        tick.tick().await;
+        let now = Utc::now();
+        for change in schedule.tick(now, now.with_timezone(&Local).naive_local()) {
+            publish(&events, &change);
+        }
+    }
+}
+
+/// Log a pause, allow or profile flip and send it on the event bus.
+pub fn publish(events: &EventBus, change: &ScheduleChange) {
+    let mut event = AdblockEvent {
+        change: String::new(),
+        client: None,
+        profile: None,
+        domain: None,
+        until: None,
+        at: Utc::now().to_rfc3339(),
+    };
+    match change {
+        ScheduleChange::Paused(pause) => {
+            info!(
+                "Adblock paused for {} until {}",
+                pause.client.map_or("every client".to_string(), |c| c.to_string()),
+                pause.until.with_timezone(&Local).format("%H:%M")
+            );
+            event.change = "paused".to_string();
+            event.client = pause.client.map(|c| c.to_string());
+            event.until = Some(pause.until.to_rfc3339());
+        }
+        ScheduleChange::Resumed { client } => {
+            info!("Adblock resumed for {}", client.map_or("every client".to_string(), |c| c.to_string()));
+            event.change = "resumed".to_string();
+            event.client = client.map(|c| c.to_string());
+        }
+        ScheduleChange::Allowed(allow) => {
+            info!(
+                "{} allowed for {} until {}",
+                allow.domain,
+                allow.client.map_or("every client".to_string(), |c| c.to_string()),
+                allow.until.with_timezone(&Local).format("%H:%M")
+            );
+            event.change = "allowed".to_string();
+            event.client = allow.client.map(|c| c.to_string());
+            event.domain = Some(allow.domain.clone());
+            event.until = Some(allow.until.to_rfc3339());
+        }
+        ScheduleChange::AllowExpired { domain, client } => {
+            info!(
+                "{} blocked again for {}",
+                domain,
+                client.map_or("every client".to_string(), |c| c.to_string())
+            );
+            event.change = "allow_expired".to_string();
+            event.client = client.map(|c| c.to_string());
+            event.domain = Some(domain.clone());
+        }
+        ScheduleChange::ProfileActive { name, .. } => {
+            info!("Adblock profile {} active", name);
+            event.change = "profile_active".to_string();
+            event.profile = Some(name.clone());
+        }
+        ScheduleChange::ProfileInactive { name, .. } => {
+            info!("Adblock profile {} inactive", name);
+            event.change = "profile_inactive".to_string();
+            event.profile = Some(name.clone());
+        }
+    }
+    let _ = events.adblock.send(event);
+}
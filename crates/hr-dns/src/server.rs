@@ -7,7 +7,10 @@ use tracing::{debug, info, warn};
 
 use crate::SharedDnsState;
 use crate::packet::{self, RCODE_FORMERR};
+use crate::records::RecordType;
 use crate::resolver;
+use hr_common::selfmon;
+use hr_firewall::Offense;
 
 /// Run a DNS UDP server on the given address.
 pub async fn run_udp_server(addr: SocketAddr, state: SharedDnsState) -> Result<()> {
@@ -29,12 +32,21 @@ pub async fn run_udp_server(addr: SocketAddr, state: SharedDnsState) -> Result<(
         let socket = socket.clone();
         let state = state.clone();
 
-        tokio::spawn(async move {
+        selfmon::spawn("dns", async move {
+            let bans = state.read().await.bans.clone();
+            if bans.as_ref().is_some_and(|b| b.is_banned(src.ip())) {
+                return;
+            }
             let (mut response, edns_udp_size) = handle_dns_query_with_edns(&packet, &state, src).await;
             // Silently drop responses for malformed packets (empty = nothing parseable)
             if response.is_empty() {
                 return;
             }
+            if let Some(bans) = &bans
+                && is_amplification(&packet, &response)
+            {
+                bans.report(src.ip(), Offense::DnsAmplification);
+            }
             // Use client's EDNS0 UDP payload size if available, else RFC 1035 limit (512)
             let max_udp = if edns_udp_size > 0 {
                 (edns_udp_size as usize).min(4096)
@@ -49,6 +61,17 @@ pub async fn run_udp_server(addr: SocketAddr, state: SharedDnsState) -> Result<(
     }
 }
 
+/// Answer/query size ratio from which a UDP exchange counts as amplification.
+const AMPLIFICATION_RATIO: usize = 10;
+
+/// ANY queries and answers much larger than their query are the raw
+/// material of reflection attacks (UDP source addresses are spoofable).
+fn is_amplification(query: &[u8], response: &[u8]) -> bool {
+    let any = packet::parse_query(query)
+        .is_ok_and(|q| q.questions.iter().any(|q| q.qtype == RecordType::ANY));
+    any || response.len() >= query.len() * AMPLIFICATION_RATIO
+}
+
 /// Run a DNS TCP server on the given address.
 pub async fn run_tcp_server(addr: SocketAddr, state: SharedDnsState) -> Result<()> {
     let listener = TcpListener::bind(addr).await?;
@@ -64,7 +87,7 @@ pub async fn run_tcp_server(addr: SocketAddr, state: SharedDnsState) -> Result<(
         };
 
         let state = state.clone();
-        tokio::spawn(async move {
+        selfmon::spawn("dns", async move {
             if let Err(e) = handle_tcp_connection(stream, src, &state).await {
                 debug!("TCP connection error from {}: {}", src, e);
             }
@@ -106,7 +129,7 @@ async fn handle_dns_query_with_edns(query_bytes: &[u8], state: &SharedDnsState,
     (response, edns_size)
 }
 
-async fn handle_dns_query(query_bytes: &[u8], state: &SharedDnsState, src: SocketAddr) -> Vec<u8> {
+pub(crate) async fn handle_dns_query(query_bytes: &[u8], state: &SharedDnsState, src: SocketAddr) -> Vec<u8> {
     // Parse query
     let query = match packet::parse_query(query_bytes) {
         Ok(q) => q,
@@ -127,8 +150,16 @@ async fn handle_dns_query(query_bytes: &[u8], state: &SharedDnsState, src: Socke
     let start = std::time::Instant::now();
 
     // Resolve
-    let result = resolver::resolve(&query, state).await;
+    let result = resolver::resolve(&query, state, src.ip()).await;
     let elapsed_ms = start.elapsed().as_millis() as u64;
+    hr_common::metrics::registry()
+        .histogram(
+            "homeroute_dns_query_duration_seconds",
+            "DNS resolution time.",
+            &[],
+            hr_common::metrics::LATENCY_BUCKETS,
+        )
+        .observe(start.elapsed().as_secs_f64());
 
     // Build response
     let response = packet::build_response(&query, &result.records, result.rcode);
@@ -137,16 +168,28 @@ async fn handle_dns_query(query_bytes: &[u8], state: &SharedDnsState, src: Socke
     if !query.questions.is_empty() {
         let q = &query.questions[0];
         let state_read = state.read().await;
+        let blocked = result.blocked.is_some();
+        state_read.stats.record(blocked, result.cached);
         if let Some(ref logger) = state_read.query_logger {
             logger.log(
                 &q.name,
                 &q.qtype.to_string(),
                 &src.ip().to_string(),
-                result.blocked,
+                blocked,
                 result.cached,
                 elapsed_ms,
+                result.sanitized,
             );
         }
+        if let Some(reason) = result.blocked {
+            let client_name = match src.ip() {
+                std::net::IpAddr::V4(ip) => {
+                    state_read.lease_store.read().await.get_lease(ip).and_then(|l| l.hostname.clone())
+                }
+                _ => None,
+            };
+            state_read.blocked_log.record(&q.name, &q.qtype.to_string(), &src.ip().to_string(), client_name, reason);
+        }
     }
 
     response
@@ -91,6 +91,28 @@ impl UpstreamForwarder {
         anyhow::bail!("All upstream servers failed")
     }
 
+    pub fn servers(&self) -> &[SocketAddr] {
+        &self.servers
+    }
+
+    /// Send a query to one upstream (UDP, TCP when truncated), with a
+    /// random TXID. The response keeps that TXID.
+    pub async fn query(&self, query_bytes: &[u8], server: SocketAddr) -> Result<Vec<u8>> {
+        if query_bytes.len() < 12 {
+            anyhow::bail!("Query too short to forward");
+        }
+        let dur = Duration::from_millis(self.timeout_ms);
+        let txid: u16 = rand::rng().random();
+        let mut query = query_bytes.to_vec();
+        query[..2].copy_from_slice(&txid.to_be_bytes());
+
+        let response = self.forward_udp(&query, server, dur, txid).await?;
+        if response[2] & 0x02 != 0 {
+            return self.forward_tcp(&query, server, dur).await;
+        }
+        Ok(response)
+    }
+
     async fn forward_udp(
         &self,
         query: &[u8],
@@ -0,0 +1,246 @@
+//! Scripted virtual clients: a DHCP handshake, DNS lookups and HTTPS
+//! requests, as a LAN device would make them. They retry for a few seconds
+//! since the services start concurrently.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use axum::body::Body;
+use http_body_util::BodyExt;
+use hyper::client::conn::http1::SendRequest;
+use hyper::{HeaderMap, Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use rustls::pki_types::{CertificateDer, ServerName};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::{sleep, timeout, Instant};
+
+use hr_dhcp::options::{self as dhcp_opt, DhcpOption};
+use hr_dhcp::packet::DhcpPacket;
+use hr_dns::packet::{encode_name, parse_response_records};
+use hr_dns::records::{DnsRecord, RecordType};
+
+const ATTEMPTS: u32 = 20;
+const RETRY: Duration = Duration::from_millis(250);
+
+/// Configuration received at the end of a DHCP handshake.
+#[derive(Debug, Clone)]
+pub struct DhcpLease {
+    pub ip: Ipv4Addr,
+    pub server: Ipv4Addr,
+    pub router: Option<Ipv4Addr>,
+    pub dns: Option<Ipv4Addr>,
+    pub domain: Option<String>,
+    pub lease_secs: Option<u32>,
+}
+
+/// DISCOVER → OFFER → REQUEST → ACK on `iface`, announcing `hostname`.
+pub async fn dhcp_handshake(iface: &str, mac: [u8; 6], hostname: &str) -> Result<DhcpLease> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_broadcast(true)?;
+    socket.bind_device(Some(iface.as_bytes()))?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 68)).into())?;
+    socket.set_nonblocking(true)?;
+    let socket = UdpSocket::from_std(socket.into())?;
+
+    let xid = std::process::id() ^ u32::from_be_bytes([mac[2], mac[3], mac[4], mac[5]]);
+    let discover = dhcp_request(xid, mac, vec![
+        DhcpOption::msg_type(dhcp_opt::DHCPDISCOVER),
+        DhcpOption::hostname(hostname),
+    ]);
+    let offer = dhcp_exchange(&socket, &discover, xid, dhcp_opt::DHCPOFFER).await?;
+    let server = offer.server_id().context("OFFER without server identifier")?;
+
+    let request = dhcp_request(xid, mac, vec![
+        DhcpOption::msg_type(dhcp_opt::DHCPREQUEST),
+        DhcpOption::new(dhcp_opt::OPT_REQUESTED_IP, offer.yiaddr.octets().to_vec()),
+        DhcpOption::server_id(server),
+        DhcpOption::hostname(hostname),
+    ]);
+    let ack = dhcp_exchange(&socket, &request, xid, dhcp_opt::DHCPACK).await?;
+
+    Ok(DhcpLease {
+        ip: ack.yiaddr,
+        server,
+        router: ack.get_option(dhcp_opt::OPT_ROUTER).and_then(DhcpOption::as_ipv4),
+        dns: ack.get_option(dhcp_opt::OPT_DNS_SERVER).and_then(DhcpOption::as_ipv4),
+        domain: ack.get_option(dhcp_opt::OPT_DOMAIN_NAME).and_then(DhcpOption::as_str),
+        lease_secs: ack.get_option(dhcp_opt::OPT_LEASE_TIME).and_then(DhcpOption::as_u32),
+    })
+}
+
+fn dhcp_request(xid: u32, mac: [u8; 6], options: Vec<DhcpOption>) -> DhcpPacket {
+    let mut chaddr = [0u8; 16];
+    chaddr[..6].copy_from_slice(&mac);
+    DhcpPacket {
+        op: 1,
+        htype: 1,
+        hlen: 6,
+        hops: 0,
+        xid,
+        secs: 0,
+        // No address yet: ask for broadcast replies
+        flags: 0x8000,
+        ciaddr: Ipv4Addr::UNSPECIFIED,
+        yiaddr: Ipv4Addr::UNSPECIFIED,
+        siaddr: Ipv4Addr::UNSPECIFIED,
+        giaddr: Ipv4Addr::UNSPECIFIED,
+        chaddr,
+        sname: [0; 64],
+        file: [0; 128],
+        options,
+    }
+}
+
+/// Broadcast `packet` until a reply of type `expected` for `xid` arrives.
+async fn dhcp_exchange(
+    socket: &UdpSocket,
+    packet: &DhcpPacket,
+    xid: u32,
+    expected: u8,
+) -> Result<DhcpPacket> {
+    let bytes = packet.to_bytes();
+    let mut buf = [0u8; 1500];
+    for _ in 0..ATTEMPTS {
+        socket
+            .send_to(&bytes, (Ipv4Addr::BROADCAST, 67))
+            .await
+            .context("DHCP broadcast failed")?;
+        let deadline = Instant::now() + RETRY;
+        while let Ok(Ok(len)) = timeout(deadline - Instant::now(), socket.recv(&mut buf)).await {
+            let Ok(reply) = DhcpPacket::parse(&buf[..len]) else {
+                continue;
+            };
+            if reply.op != 2 || reply.xid != xid {
+                continue;
+            }
+            match reply.msg_type() {
+                Some(t) if t == expected => return Ok(reply),
+                Some(dhcp_opt::DHCPNAK) => bail!("DHCPNAK received"),
+                _ => {}
+            }
+        }
+    }
+    bail!("no DHCP reply of type {}", expected)
+}
+
+/// Query `server` over UDP and return the answer section.
+pub async fn dns_query(server: SocketAddr, name: &str, qtype: RecordType) -> Result<Vec<DnsRecord>> {
+    let (rcode, records) = dns_query_rcode(server, name, qtype).await?;
+    if rcode != 0 {
+        bail!("{} {:?}: rcode {}", name, qtype, rcode);
+    }
+    Ok(records)
+}
+
+/// Query `server` over UDP and return the response code and answers.
+pub async fn dns_query_rcode(
+    server: SocketAddr,
+    name: &str,
+    qtype: RecordType,
+) -> Result<(u8, Vec<DnsRecord>)> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let id: u16 = (std::process::id() as u16) ^ (name.len() as u16).rotate_left(8);
+    let query = dns_query_bytes(id, name, qtype);
+
+    let mut buf = [0u8; 4096];
+    for _ in 0..ATTEMPTS {
+        socket.send_to(&query, server).await?;
+        // The server may not be listening yet (ICMP unreachable): retry
+        let Ok(Ok(len)) = timeout(RETRY, socket.recv(&mut buf)).await else {
+            sleep(RETRY).await;
+            continue;
+        };
+        let (header, records) = parse_response_records(&buf[..len])
+            .map_err(|e| anyhow!("invalid DNS response: {}", e))?;
+        if header.id != id {
+            continue;
+        }
+        return Ok((header.rcode(), records));
+    }
+    bail!("no DNS response from {} for {}", server, name)
+}
+
+/// Wire format of a recursive query for `name`.
+pub(crate) fn dns_query_bytes(id: u16, name: &str, qtype: RecordType) -> Vec<u8> {
+    let mut query = Vec::with_capacity(64);
+    query.extend_from_slice(&id.to_be_bytes());
+    query.extend_from_slice(&0x0100u16.to_be_bytes()); // RD
+    query.extend_from_slice(&1u16.to_be_bytes());
+    query.extend_from_slice(&[0; 6]);
+    encode_name(name, &mut query);
+    query.extend_from_slice(&qtype.to_u16().to_be_bytes());
+    query.extend_from_slice(&1u16.to_be_bytes()); // IN
+    query
+}
+
+/// Response of an HTTPS request.
+#[derive(Debug)]
+pub struct HttpsResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+/// HTTPS client trusting only the harness CA.
+#[derive(Clone)]
+pub struct HttpsClient {
+    connector: tokio_rustls::TlsConnector,
+}
+
+impl HttpsClient {
+    pub fn new(ca: CertificateDer<'static>) -> Result<Self> {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(ca)?;
+        let mut config = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(Self {
+            connector: tokio_rustls::TlsConnector::from(Arc::new(config)),
+        })
+    }
+
+    /// `GET https://{host}{path}` sent to `addr` (SNI and Host set to `host`).
+    pub async fn get(&self, addr: SocketAddr, host: &str, path: &str) -> Result<HttpsResponse> {
+        let mut sender = self.open(addr, host).await?;
+        let req = Request::get(path)
+            .header("host", host)
+            .body(Body::empty())?;
+        let resp = sender.send_request(req).await?;
+        let (parts, body) = resp.into_parts();
+        let body = body.collect().await?.to_bytes();
+        Ok(HttpsResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        })
+    }
+
+    /// Keep-alive HTTP/1.1 connection to `addr` with SNI `host`.
+    pub async fn open(&self, addr: SocketAddr, host: &str) -> Result<SendRequest<Body>> {
+        let tcp = connect(addr).await?;
+        let server_name = ServerName::try_from(host.to_string())?;
+        let tls = self.connector.connect(server_name, tcp).await?;
+
+        let (sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(tls)).await?;
+        tokio::spawn(conn);
+        Ok(sender)
+    }
+}
+
+/// Connect, retrying while the listener isn't up yet.
+async fn connect(addr: SocketAddr) -> Result<TcpStream> {
+    let mut last = None;
+    for _ in 0..ATTEMPTS {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last = Some(e),
+        }
+        sleep(RETRY).await;
+    }
+    Err(last.map(Into::into).unwrap_or_else(|| anyhow!("connect to {} failed", addr)))
+}
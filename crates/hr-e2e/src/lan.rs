@@ -0,0 +1,314 @@
+//! The simulated home network: a router namespace running DNS, DHCP and the
+//! HTTPS proxy the way `homeroute` wires them, a LAN namespace for the
+//! virtual clients and a backend namespace for the proxied services.
+//!
+//! ```text
+//!  client (eth0, DHCP) ── lan0 10.77.0.1 [router] app0 10.78.0.1 ── backend (eth0 10.78.0.2)
+//! ```
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use axum::extract::Request;
+use axum::response::IntoResponse;
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, IsCa, KeyPair, KeyUsagePurpose,
+    PKCS_ECDSA_P256_SHA256,
+};
+use rustls::pki_types::CertificateDer;
+use tokio::net::TcpListener;
+use tokio::sync::RwLock;
+use tracing::debug;
+
+use hr_dhcp::{DhcpConfig, DhcpState, LeaseStore, SharedDhcpState};
+use hr_dns::{DnsConfig, DnsState, SharedDnsState};
+use hr_proxy::{AppRoute, LoadBalancePolicy, ProxyConfig, ProxyState, TlsManager};
+use hr_registry::protocol::ServiceType;
+
+use crate::clients::HttpsClient;
+use crate::netns::{veth, Netns, Task};
+
+pub const ROUTER_IP: Ipv4Addr = Ipv4Addr::new(10, 77, 0, 1);
+pub const BACKEND_GATEWAY: Ipv4Addr = Ipv4Addr::new(10, 78, 0, 1);
+pub const BACKEND_IP: Ipv4Addr = Ipv4Addr::new(10, 78, 0, 2);
+pub const RANGE_START: Ipv4Addr = Ipv4Addr::new(10, 77, 0, 100);
+pub const RANGE_END: Ipv4Addr = Ipv4Addr::new(10, 77, 0, 150);
+/// Base domain of the proxy (certificate `*.e2e.test` + `e2e.test`).
+pub const BASE_DOMAIN: &str = "e2e.test";
+/// DHCP domain, expanded by the DNS server for lease hostnames.
+pub const LOCAL_DOMAIN: &str = "lan";
+/// Static route `app.e2e.test` → backend on this port.
+pub const APP_PORT: u16 = 8080;
+
+/// Running topology. Services stop, then namespaces are deleted, on drop.
+pub struct Lan {
+    tasks: Vec<Task>,
+    pub router: Netns,
+    pub client: Netns,
+    pub backend: Netns,
+    pub dns: SharedDnsState,
+    pub dhcp: SharedDhcpState,
+    pub proxy: Arc<ProxyState>,
+    /// CA of the proxy certificate, for `HttpsClient`.
+    pub ca: CertificateDer<'static>,
+    dir: PathBuf,
+}
+
+impl Lan {
+    pub fn start() -> Result<Self> {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+
+        let router = Netns::new("router")?;
+        let client = Netns::new("client")?;
+        let backend = Netns::new("backend")?;
+
+        veth(&router, "lan0", &client, "eth0")?;
+        veth(&router, "app0", &backend, "eth0")?;
+        router.ip(&["addr", "add", &format!("{}/24", ROUTER_IP), "dev", "lan0"])?;
+        router.ip(&["addr", "add", &format!("{}/24", BACKEND_GATEWAY), "dev", "app0"])?;
+        backend.ip(&["addr", "add", &format!("{}/24", BACKEND_IP), "dev", "eth0"])?;
+        backend.ip(&["route", "add", "default", "via", &BACKEND_GATEWAY.to_string()])?;
+
+        let dir = std::env::temp_dir().join(format!("{}-data", router.name()));
+        std::fs::create_dir_all(&dir)?;
+        let lease_file = dir.join("dhcp-leases").to_string_lossy().into_owned();
+
+        let dhcp_config: DhcpConfig = serde_json::from_value(serde_json::json!({
+            "interface": "lan0",
+            "range_start": RANGE_START.to_string(),
+            "range_end": RANGE_END.to_string(),
+            "gateway": ROUTER_IP.to_string(),
+            "dns_server": ROUTER_IP.to_string(),
+            "domain": LOCAL_DOMAIN,
+            "default_lease_time_secs": 3600,
+            "authoritative": true,
+            "lease_file": lease_file,
+        }))?;
+        let dhcp: SharedDhcpState = Arc::new(RwLock::new(DhcpState {
+            config: dhcp_config,
+            lease_store: LeaseStore::new(&lease_file),
+            server_ip: ROUTER_IP,
+        }));
+
+        let dns_config: DnsConfig = serde_json::from_value(serde_json::json!({
+            "listen_addresses": [ROUTER_IP.to_string()],
+            "upstream_servers": [],
+            "upstream_timeout_ms": 200,
+            "local_domain": LOCAL_DOMAIN,
+            "static_records": [
+                { "name": format!("app.{}", BASE_DOMAIN), "type": "A", "value": ROUTER_IP.to_string() },
+                { "name": format!("wake.{}", BASE_DOMAIN), "type": "A", "value": ROUTER_IP.to_string() },
+            ],
+        }))?;
+        let dns_leases = Arc::new(RwLock::new(LeaseStore::new(&lease_file)));
+        let dns: SharedDnsState = Arc::new(RwLock::new(DnsState {
+            dns_cache: hr_dns::cache::DnsCache::new(dns_config.cache_size),
+            upstream: hr_dns::upstream::UpstreamForwarder::new(
+                dns_config.upstream_servers.clone(),
+                dns_config.upstream_timeout_ms,
+            ),
+            config: dns_config,
+            query_logger: None,
+            blocked_log: Default::default(),
+            adblock: Arc::new(RwLock::new(hr_adblock::AdblockEngine::new())),
+            lease_store: dns_leases.clone(),
+            adblock_enabled: false,
+            adblock_schedule: Default::default(),
+            safesearch: Default::default(),
+            static_patterns: Default::default(),
+            route_records: Vec::new(),
+            adblock_block_response: String::new(),
+            stats: Default::default(),
+            bans: None,
+            health: Default::default(),
+        }));
+
+        let proxy_config: ProxyConfig = serde_json::from_value(serde_json::json!({
+            "base_domain": BASE_DOMAIN,
+            "ca_storage_path": dir,
+            "routes": [{
+                "id": "app",
+                "domain": format!("app.{}", BASE_DOMAIN),
+                "target_host": BACKEND_IP.to_string(),
+                "target_port": APP_PORT,
+            }],
+        }))?;
+        let (ca, tls_config) = tls_config(&dir)?;
+        let proxy = Arc::new(ProxyState::new(proxy_config, 0));
+
+        let mut tasks = Vec::new();
+        {
+            let dns = dns.clone();
+            let addr = SocketAddr::from((ROUTER_IP, 53));
+            tasks.push(router.spawn(move || hr_dns::server::run_udp_server(addr, dns)));
+        }
+        {
+            let dhcp = dhcp.clone();
+            tasks.push(router.spawn(move || hr_dhcp::server::run_dhcp_server(dhcp)));
+        }
+        {
+            let (dhcp, leases) = (dhcp.clone(), dns_leases.clone());
+            tasks.push(router.spawn(move || hr_dhcp::mirror_leases(dhcp, leases)));
+        }
+        {
+            let proxy = proxy.clone();
+            let addr = SocketAddr::from((ROUTER_IP, 443));
+            tasks.push(router.spawn(move || serve_https(addr, proxy, tls_config)));
+        }
+
+        Ok(Self {
+            tasks,
+            router,
+            client,
+            backend,
+            dns,
+            dhcp,
+            proxy,
+            ca,
+            dir,
+        })
+    }
+
+    /// Client for the proxy, to use from the client namespace.
+    pub fn https_client(&self) -> Result<HttpsClient> {
+        HttpsClient::new(self.ca.clone())
+    }
+
+    /// Give the client namespace the address obtained by DHCP.
+    pub fn configure_client(&self, ip: Ipv4Addr) -> Result<()> {
+        self.client.ip(&["addr", "add", &format!("{}/24", ip), "dev", "eth0"])
+    }
+
+    /// Start an HTTP backend on `port` echoing the request's host, path and
+    /// forwarding headers as JSON. Stops when the task is dropped.
+    pub fn echo_backend(&self, port: u16) -> Task {
+        self.backend.spawn(move || async move {
+            let listener = TcpListener::bind((BACKEND_IP, port)).await?;
+            let app = axum::Router::new().fallback(echo);
+            axum::serve(listener, app).await?;
+            Ok(())
+        })
+    }
+
+    /// Register an agent-managed app on the backend (`host_id` "local", no
+    /// registry: Wake-on-Demand waits for the port to open).
+    pub fn add_app_route(&self, subdomain: &str, port: u16, wake_page: bool) {
+        self.proxy.set_app_route(
+            format!("{}.{}", subdomain, BASE_DOMAIN),
+            AppRoute {
+                app_id: subdomain.to_string(),
+                host_id: "local".to_string(),
+                target_ip: BACKEND_IP,
+                target_port: port,
+                auth_required: false,
+                allowed_groups: Vec::new(),
+                service_type: ServiceType::App,
+                wake_page_enabled: wake_page,
+                local_only: false,
+                extra_targets: Vec::new(),
+                load_balancing: LoadBalancePolicy::default(),
+                sticky: false,
+            },
+        );
+    }
+}
+
+impl Drop for Lan {
+    fn drop(&mut self) {
+        self.tasks.clear();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+async fn echo(req: Request) -> axum::Json<serde_json::Value> {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    axum::Json(serde_json::json!({
+        "host": header("host"),
+        "path": req.uri().path(),
+        "x_real_ip": header("x-real-ip"),
+        "x_forwarded_for": header("x-forwarded-for"),
+        "x_forwarded_proto": header("x-forwarded-proto"),
+    }))
+}
+
+/// CA and `*.e2e.test` certificate (apex included, as the global ACME
+/// certificate), loaded through `TlsManager`.
+fn tls_config(dir: &std::path::Path) -> Result<(CertificateDer<'static>, Arc<rustls::ServerConfig>)> {
+    let ca_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256)?;
+    let mut ca_params = CertificateParams::new(Vec::<String>::new())?;
+    ca_params.distinguished_name.push(DnType::CommonName, "HomeRoute E2E CA");
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign];
+    let ca_cert = ca_params.self_signed(&ca_key)?;
+
+    let key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256)?;
+    let wildcard = format!("*.{}", BASE_DOMAIN);
+    let params = CertificateParams::new(vec![wildcard.clone(), BASE_DOMAIN.to_string()])?;
+    let cert = params.signed_by(&key, &ca_cert, &ca_key)?;
+
+    let cert_path = dir.join("wildcard.crt");
+    let key_path = dir.join("wildcard.key");
+    std::fs::write(&cert_path, cert.pem())?;
+    std::fs::write(&key_path, key.serialize_pem())?;
+
+    let tls = TlsManager::new(dir.to_path_buf());
+    for domain in [wildcard.as_str(), BASE_DOMAIN] {
+        tls.load_certificate_from_pem(domain, &cert_path.to_string_lossy(), &key_path.to_string_lossy())?;
+    }
+    Ok((ca_cert.der().clone(), tls.build_server_config()?))
+}
+
+/// TLS termination and `proxy_handler`, as the HTTPS listener of `homeroute`.
+async fn serve_https(
+    addr: SocketAddr,
+    proxy: Arc<ProxyState>,
+    tls_config: Arc<rustls::ServerConfig>,
+) -> Result<()> {
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper_util::rt::TokioIo;
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("bind {}", addr))?;
+    let acceptor = tokio_rustls::TlsAcceptor::from(tls_config);
+
+    loop {
+        let (tcp, remote) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let proxy = proxy.clone();
+        tokio::spawn(async move {
+            let tls = match acceptor.accept(tcp).await {
+                Ok(s) => s,
+                Err(e) => {
+                    debug!("TLS handshake failed from {}: {}", remote, e);
+                    return;
+                }
+            };
+            let client_ip: IpAddr = remote.ip();
+            let service = service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
+                let proxy = proxy.clone();
+                async move {
+                    let (parts, body) = req.into_parts();
+                    let req = Request::from_parts(parts, axum::body::Body::new(body));
+                    let resp = hr_proxy::proxy_handler(proxy, client_ip, req).await;
+                    Ok::<_, std::convert::Infallible>(resp.into_response())
+                }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(tls), service)
+                .with_upgrades()
+                .await
+            {
+                debug!("HTTP/1 connection error from {}: {}", remote, e);
+            }
+        });
+    }
+}
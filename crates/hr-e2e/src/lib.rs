@@ -0,0 +1,23 @@
+//! End-to-end test harness (dev only, never shipped): runs hr-dns, hr-dhcp
+//! and hr-proxy inside Linux network namespaces, wired like the production
+//! router, and drives them with scripted virtual clients. The tests in
+//! `tests/` need root (`ip netns`); without it they are skipped.
+
+pub mod clients;
+pub mod lan;
+pub mod load;
+pub mod netns;
+
+pub use lan::Lan;
+pub use netns::{available, Netns, Task};
+
+/// Skip the current test when network namespaces can't be created.
+#[macro_export]
+macro_rules! require_netns {
+    () => {
+        if !$crate::available() {
+            eprintln!("skipped: network namespaces unavailable (needs root and iproute2)");
+            return;
+        }
+    };
+}
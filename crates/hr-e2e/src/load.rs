@@ -0,0 +1,301 @@
+//! Load generators for the performance baseline: DNS queries per second,
+//! HTTPS requests per second through the proxy and QUIC tunnel throughput.
+//! Results are compared against `baseline.json` by `tests/load.rs`.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use axum::body::Body;
+use http_body_util::BodyExt;
+use hyper::Request;
+use serde::{Deserialize, Serialize};
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+use hr_dns::records::RecordType;
+use hr_tunnel::protocol::StreamHeader;
+
+use crate::clients::{dns_query_bytes, HttpsClient};
+
+/// Give up on a reply after this long (counted as an error).
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Outcome of a load run.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadResult {
+    pub ok: u64,
+    pub errors: u64,
+    pub elapsed: Duration,
+}
+
+impl LoadResult {
+    /// Successful operations per second.
+    pub fn rate(&self) -> f64 {
+        self.ok as f64 / self.elapsed.as_secs_f64()
+    }
+
+    fn merge(results: impl IntoIterator<Item = (u64, u64)>, elapsed: Duration) -> Self {
+        let (ok, errors) = results
+            .into_iter()
+            .fold((0, 0), |(ok, err), (o, e)| (ok + o, err + e));
+        Self { ok, errors, elapsed }
+    }
+}
+
+/// `concurrency` clients each sending one query at a time to `server`
+/// for `duration`. Only NOERROR answers count as successes.
+pub async fn dns_load(
+    server: SocketAddr,
+    name: &str,
+    concurrency: usize,
+    duration: Duration,
+) -> Result<LoadResult> {
+    let started = Instant::now();
+    let deadline = started + duration;
+    let mut workers = Vec::with_capacity(concurrency);
+    for worker in 0..concurrency {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        socket.connect(server).await?;
+        let name = name.to_string();
+        workers.push(tokio::spawn(async move {
+            let mut buf = [0u8; 4096];
+            let (mut ok, mut errors) = (0u64, 0u64);
+            let mut id = (worker as u16).wrapping_mul(4099);
+            while Instant::now() < deadline {
+                id = id.wrapping_add(1);
+                socket.send(&dns_query_bytes(id, &name, RecordType::A)).await?;
+                loop {
+                    match timeout(REPLY_TIMEOUT, socket.recv(&mut buf)).await {
+                        // Late answer to an earlier, timed-out query
+                        Ok(Ok(len)) if len >= 4 && buf[..2] != id.to_be_bytes() => continue,
+                        Ok(Ok(len)) if len >= 4 && buf[3] & 0x0f == 0 => ok += 1,
+                        _ => errors += 1,
+                    }
+                    break;
+                }
+            }
+            Ok::<_, anyhow::Error>((ok, errors))
+        }));
+    }
+    let mut results = Vec::with_capacity(concurrency);
+    for worker in workers {
+        results.push(worker.await??);
+    }
+    Ok(LoadResult::merge(results, started.elapsed()))
+}
+
+/// `concurrency` keep-alive connections sending `GET path` back to back
+/// for `duration`. A failed connection is reopened.
+pub async fn http_load(
+    client: &HttpsClient,
+    addr: SocketAddr,
+    host: &str,
+    path: &str,
+    concurrency: usize,
+    duration: Duration,
+) -> Result<LoadResult> {
+    let started = Instant::now();
+    let deadline = started + duration;
+    let mut workers = Vec::with_capacity(concurrency);
+    for _ in 0..concurrency {
+        let client = client.clone();
+        let (host, path) = (host.to_string(), path.to_string());
+        workers.push(tokio::spawn(async move {
+            let (mut ok, mut errors) = (0u64, 0u64);
+            let mut sender = client.open(addr, &host).await?;
+            while Instant::now() < deadline {
+                let req = Request::get(path.as_str())
+                    .header("host", host.as_str())
+                    .body(Body::empty())?;
+                let sent = async {
+                    sender.ready().await?;
+                    let resp = sender.send_request(req).await?;
+                    let status = resp.status();
+                    resp.into_body().collect().await?;
+                    Ok::<_, anyhow::Error>(status)
+                };
+                match timeout(REPLY_TIMEOUT, sent).await {
+                    Ok(Ok(status)) if status.is_success() => ok += 1,
+                    Ok(Ok(_)) => errors += 1,
+                    _ => {
+                        errors += 1;
+                        sender = client.open(addr, &host).await?;
+                    }
+                }
+            }
+            Ok::<_, anyhow::Error>((ok, errors))
+        }));
+    }
+    let mut results = Vec::with_capacity(concurrency);
+    for worker in workers {
+        results.push(worker.await??);
+    }
+    Ok(LoadResult::merge(results, started.elapsed()))
+}
+
+/// Push `bytes` through an hr-tunnel QUIC connection on 127.0.0.1, split
+/// over `streams` parallel streams framed like relayed requests. Returns
+/// MB/s, as seen once the far end has acknowledged every byte.
+pub async fn tunnel_throughput(bytes: u64, streams: usize) -> Result<f64> {
+    let certs = hr_tunnel::crypto::generate_tunnel_certs("127.0.0.1")?;
+    let server_config = hr_tunnel::quic::build_server_config(
+        certs.server_cert_pem.as_bytes(),
+        certs.server_key_pem.as_bytes(),
+        certs.ca_cert_pem.as_bytes(),
+    )?;
+    let client_config = hr_tunnel::quic::build_client_config(
+        certs.client_cert_pem.as_bytes(),
+        certs.client_key_pem.as_bytes(),
+        certs.ca_cert_pem.as_bytes(),
+    )?;
+
+    let server = quinn::Endpoint::server(server_config, SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))?;
+    let server_addr = server.local_addr()?;
+    let sink = tokio::spawn(async move {
+        let conn = server.accept().await.context("no tunnel connection")?.await?;
+        // Count the payload of each stream and echo the total back
+        while let Ok((mut send, mut recv)) = conn.accept_bi().await {
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; 64 * 1024];
+                let mut total = 0u64;
+                while let Some(n) = recv.read(&mut buf).await? {
+                    total += n as u64;
+                }
+                send.write_all(&total.to_be_bytes()).await?;
+                send.finish()?;
+                send.stopped().await?;
+                Ok::<_, anyhow::Error>(())
+            });
+        }
+        Ok::<_, anyhow::Error>(())
+    });
+
+    let mut endpoint = quinn::Endpoint::client(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))?;
+    endpoint.set_default_client_config(client_config);
+    let conn = endpoint.connect(server_addr, "127.0.0.1")?.await?;
+
+    let per_stream = bytes / streams.max(1) as u64;
+    let started = Instant::now();
+    let mut senders = Vec::with_capacity(streams);
+    for _ in 0..streams {
+        let conn = conn.clone();
+        senders.push(tokio::spawn(async move {
+            let (mut send, mut recv) = conn.open_bi().await?;
+            let header = StreamHeader {
+                client_ip: Ipv4Addr::new(192, 0, 2, 1).into(),
+                timestamp: 0,
+                proxy_ip: None,
+            }
+            .encode();
+            send.write_all(&header).await?;
+            let chunk = vec![0u8; 64 * 1024];
+            let mut sent = 0u64;
+            while sent < per_stream {
+                let n = (per_stream - sent).min(chunk.len() as u64) as usize;
+                send.write_all(&chunk[..n]).await?;
+                sent += n as u64;
+            }
+            send.finish()?;
+            let mut ack = [0u8; 8];
+            recv.read_exact(&mut ack).await?;
+            let received = u64::from_be_bytes(ack);
+            if received != per_stream + header.len() as u64 {
+                bail!("tunnel stream received {} of {} bytes", received, per_stream);
+            }
+            Ok(per_stream)
+        }));
+    }
+    let mut total = 0u64;
+    for sender in senders {
+        total += sender.await??;
+    }
+    let elapsed = started.elapsed();
+
+    conn.close(0u32.into(), b"done");
+    endpoint.wait_idle().await;
+    sink.abort();
+    Ok(total as f64 / 1e6 / elapsed.as_secs_f64())
+}
+
+/// Measured rates of a load run.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Measurements {
+    pub dns_qps: f64,
+    pub http_rps: f64,
+    pub tunnel_mbps: f64,
+}
+
+/// Reference rates checked by the load test (`baseline.json`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Baseline {
+    /// Allowed drop below the reference, as a fraction (0.25 = 25 %).
+    pub tolerance: f64,
+    #[serde(flatten)]
+    pub rates: Measurements,
+}
+
+impl Baseline {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read {}", path.display()))?;
+        Ok(serde_json::from_str(&raw)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    /// One line per rate that fell below the tolerated floor.
+    pub fn regressions(&self, measured: &Measurements) -> Vec<String> {
+        let checks = [
+            ("dns_qps", self.rates.dns_qps, measured.dns_qps),
+            ("http_rps", self.rates.http_rps, measured.http_rps),
+            ("tunnel_mbps", self.rates.tunnel_mbps, measured.tunnel_mbps),
+        ];
+        checks
+            .into_iter()
+            .filter(|(_, reference, value)| *value < reference * (1.0 - self.tolerance))
+            .map(|(name, reference, value)| {
+                format!(
+                    "{}: {:.0} < {:.0} (baseline) - {:.0}%",
+                    name,
+                    value,
+                    reference,
+                    self.tolerance * 100.0
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regressions_respect_tolerance() {
+        let baseline = Baseline {
+            tolerance: 0.2,
+            rates: Measurements {
+                dns_qps: 1000.0,
+                http_rps: 500.0,
+                tunnel_mbps: 100.0,
+            },
+        };
+        let measured = Measurements {
+            dns_qps: 810.0,
+            http_rps: 390.0,
+            tunnel_mbps: 250.0,
+        };
+        let regressions = baseline.regressions(&measured);
+        assert_eq!(regressions.len(), 1);
+        assert!(regressions[0].starts_with("http_rps"));
+
+        let json = serde_json::to_value(&baseline).unwrap();
+        assert_eq!(json["dns_qps"], 1000.0);
+        assert_eq!(json["tolerance"], 0.2);
+    }
+}
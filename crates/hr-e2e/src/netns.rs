@@ -0,0 +1,186 @@
+//! Named network namespaces (`ip netns`) and threads running inside them.
+//!
+//! A namespace is entered per thread (`setns`), so every piece of the
+//! topology gets its own thread with a current-thread tokio runtime:
+//! sockets opened by that runtime live in the namespace.
+
+use std::future::Future;
+use std::os::fd::AsRawFd;
+use std::process::Command;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread::JoinHandle;
+
+use anyhow::{bail, Context, Result};
+use tokio::sync::oneshot;
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Whether namespaces can be created here (root with iproute2).
+pub fn available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        // SAFETY: geteuid has no preconditions.
+        if unsafe { libc::geteuid() } != 0 {
+            return false;
+        }
+        Netns::new("probe").is_ok()
+    })
+}
+
+fn ip(args: &[&str]) -> Result<()> {
+    let output = Command::new("ip")
+        .args(args)
+        .output()
+        .context("failed to run ip")?;
+    if !output.status.success() {
+        bail!(
+            "ip {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// A network namespace, deleted on drop.
+pub struct Netns {
+    name: String,
+}
+
+impl Netns {
+    /// Create a namespace with its loopback up. Names are unique per
+    /// process so tests can run in parallel.
+    pub fn new(role: &str) -> Result<Self> {
+        let name = format!(
+            "hre2e-{}-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            role
+        );
+        ip(&["netns", "add", &name])?;
+        let ns = Self { name };
+        ns.ip(&["link", "set", "lo", "up"])?;
+        Ok(ns)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Run `ip` inside the namespace.
+    pub fn ip(&self, args: &[&str]) -> Result<()> {
+        let mut full = vec!["-n", self.name.as_str()];
+        full.extend_from_slice(args);
+        ip(&full)
+    }
+
+    /// Move the calling thread into the namespace.
+    pub fn enter(&self) -> Result<()> {
+        setns(&self.name)
+    }
+
+    /// Run a future to completion inside the namespace.
+    pub fn run<F, Fut, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T>>,
+        T: Send + 'static,
+    {
+        self.start(f)
+            .join()
+            .map_err(|_| anyhow::anyhow!("namespace thread panicked"))?
+    }
+
+    /// Like `run`, without waiting for the result.
+    pub fn start<F, Fut, T>(&self, f: F) -> JoinHandle<Result<T>>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T>>,
+        T: Send + 'static,
+    {
+        let name = self.name.clone();
+        std::thread::spawn(move || {
+            setns(&name)?;
+            runtime()?.block_on(f())
+        })
+    }
+
+    /// Run a background service inside the namespace until the task is
+    /// dropped.
+    pub fn spawn<F, Fut>(&self, f: F) -> Task
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>>,
+    {
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        let name = self.name.clone();
+        let thread = std::thread::spawn(move || {
+            let result = setns(&name).and_then(|_| runtime()).and_then(|rt| {
+                rt.block_on(async move {
+                    tokio::select! {
+                        res = f() => res,
+                        _ = stopped => Ok(()),
+                    }
+                })
+            });
+            if let Err(e) = result {
+                tracing::warn!("service in {} stopped: {:#}", name, e);
+            }
+        });
+        Task {
+            shutdown: Some(shutdown),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Netns {
+    fn drop(&mut self) {
+        let _ = ip(&["netns", "del", &self.name]);
+    }
+}
+
+/// Create a veth pair between two namespaces and bring both ends up.
+pub fn veth(a: &Netns, a_if: &str, b: &Netns, b_if: &str) -> Result<()> {
+    ip(&[
+        "link", "add", a_if, "netns", a.name(), "type", "veth", "peer", "name", b_if, "netns",
+        b.name(),
+    ])?;
+    a.ip(&["link", "set", a_if, "up"])?;
+    b.ip(&["link", "set", b_if, "up"])?;
+    Ok(())
+}
+
+fn setns(name: &str) -> Result<()> {
+    let file = std::fs::File::open(format!("/run/netns/{}", name))
+        .with_context(|| format!("namespace {} not found", name))?;
+    // SAFETY: the descriptor is a valid namespace file for the call's duration.
+    if unsafe { libc::setns(file.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("setns failed");
+    }
+    Ok(())
+}
+
+fn runtime() -> Result<tokio::runtime::Runtime> {
+    Ok(tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?)
+}
+
+/// Background service running in a namespace; stopped and joined on drop.
+pub struct Task {
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Cross-crate scenarios on the simulated LAN. Skipped without root.
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use hyper::StatusCode;
+
+use hr_dns::records::{RData, RecordType};
+use hr_e2e::clients::{self, dns_query};
+use hr_e2e::lan::{APP_PORT, BASE_DOMAIN, LOCAL_DOMAIN, RANGE_END, RANGE_START, ROUTER_IP};
+use hr_e2e::{require_netns, Lan};
+
+const MAC: [u8; 6] = [0x02, 0x00, 0x5e, 0x10, 0x00, 0x01];
+
+fn a_records(records: &[hr_dns::records::DnsRecord]) -> Vec<IpAddr> {
+    records
+        .iter()
+        .filter_map(|r| match r.rdata {
+            RData::A(ip) => Some(IpAddr::V4(ip)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn dhcp_lease_resolves_and_reaches_proxy() {
+    require_netns!();
+    let lan = Lan::start().unwrap();
+    let _backend = lan.echo_backend(APP_PORT);
+
+    // DHCP handshake from a fresh device
+    let lease = lan
+        .client
+        .run(|| clients::dhcp_handshake("eth0", MAC, "laptop"))
+        .unwrap();
+    assert!((RANGE_START..=RANGE_END).contains(&lease.ip), "{:?}", lease);
+    assert_eq!(lease.server, ROUTER_IP);
+    assert_eq!(lease.router, Some(ROUTER_IP));
+    assert_eq!(lease.dns, Some(ROUTER_IP));
+    assert_eq!(lease.domain.as_deref(), Some(LOCAL_DOMAIN));
+    assert_eq!(lease.lease_secs, Some(3600));
+    lan.configure_client(lease.ip).unwrap();
+
+    // Same MAC again: same address
+    let again = lan
+        .client
+        .run(|| clients::dhcp_handshake("eth0", MAC, "laptop"))
+        .unwrap();
+    assert_eq!(again.ip, lease.ip);
+
+    // The lease hostname is served by the DNS server once synced
+    let dns = SocketAddr::from((ROUTER_IP, 53));
+    let leased = IpAddr::V4(lease.ip);
+    let found = lan
+        .client
+        .run(move || async move {
+            for _ in 0..20 {
+                let records =
+                    dns_query(dns, &format!("laptop.{}", LOCAL_DOMAIN), RecordType::A).await?;
+                if a_records(&records).contains(&leased) {
+                    return Ok(true);
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            Ok(false)
+        })
+        .unwrap();
+    assert!(found, "laptop.{} never resolved to {}", LOCAL_DOMAIN, leased);
+
+    // Static record, then HTTPS through the proxy from the leased address
+    let client = lan.https_client().unwrap();
+    let (records, resp) = lan
+        .client
+        .run(move || async move {
+            let host = format!("app.{}", BASE_DOMAIN);
+            let records = dns_query(dns, &host, RecordType::A).await?;
+            let proxy = SocketAddr::from((ROUTER_IP, 443));
+            let resp = client.get(proxy, &host, "/hello").await?;
+            Ok((records, resp))
+        })
+        .unwrap();
+    assert_eq!(a_records(&records), vec![IpAddr::V4(ROUTER_IP)]);
+    assert_eq!(resp.status, StatusCode::OK, "{}", resp.body);
+    let echo: serde_json::Value = serde_json::from_str(&resp.body).unwrap();
+    assert_eq!(echo["path"], "/hello");
+    assert_eq!(echo["x_real_ip"], lease.ip.to_string());
+    assert_eq!(echo["x_forwarded_proto"], "https");
+}
+
+#[test]
+fn wake_on_demand_waits_for_backend() {
+    require_netns!();
+    let lan = Lan::start().unwrap();
+    lan.configure_client("10.77.0.10".parse().unwrap()).unwrap();
+    lan.add_app_route("wake", 8090, false);
+    lan.add_app_route("page", 8091, true);
+
+    let client = lan.https_client().unwrap();
+    let proxy = SocketAddr::from((ROUTER_IP, 443));
+
+    // Wake page: answered right away while the service starts
+    let page = {
+        let client = client.clone();
+        lan.client
+            .run(move || async move { client.get(proxy, &format!("page.{}", BASE_DOMAIN), "/").await })
+            .unwrap()
+    };
+    assert_eq!(page.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(page.body.contains("Demarrage du service"), "{}", page.body);
+
+    // Transparent: the request is held until the backend listens, then
+    // the client is told to retry immediately
+    let pending = {
+        let client = client.clone();
+        lan.client
+            .start(move || async move { client.get(proxy, &format!("wake.{}", BASE_DOMAIN), "/").await })
+    };
+    std::thread::sleep(Duration::from_millis(1000));
+    let _backend = lan.echo_backend(8090);
+
+    let held = pending.join().unwrap().unwrap();
+    assert_eq!(held.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(held.headers["retry-after"], "0");
+
+    let resp = lan
+        .client
+        .run(move || async move { client.get(proxy, &format!("wake.{}", BASE_DOMAIN), "/").await })
+        .unwrap();
+    assert_eq!(resp.status, StatusCode::OK, "{}", resp.body);
+    let echo: serde_json::Value = serde_json::from_str(&resp.body).unwrap();
+    assert_eq!(echo["host"], format!("wake.{}", BASE_DOMAIN));
+    assert_eq!(echo["x_real_ip"], "10.77.0.10");
+}
+
+#[test]
+fn upstream_outage_switches_to_local_only() {
+    require_netns!();
+    // The LAN has no upstream at all: every forward fails
+    let lan = Lan::start().unwrap();
+    lan.configure_client("10.77.0.11".parse().unwrap()).unwrap();
+    let dns = SocketAddr::from((ROUTER_IP, 53));
+
+    let cache_state = lan.dns.clone();
+    let outcome = lan
+        .client
+        .run(move || async move {
+            // Answered from the cache earlier, expired since
+            let cached = hr_dns::records::DnsRecord::a("cached.example", "192.0.2.7".parse()?, 1);
+            cache_state.read().await.dns_cache.insert("cached.example", RecordType::A, &[cached]).await;
+            tokio::time::sleep(Duration::from_millis(1100)).await;
+
+            let mut rcodes = Vec::new();
+            for i in 0..hr_dns::health::FAILURES_TO_DEGRADE {
+                let (rcode, _) = clients::dns_query_rcode(dns, &format!("n{}.example", i), RecordType::A).await?;
+                rcodes.push(rcode);
+            }
+            let degraded = cache_state.read().await.health.is_degraded();
+            let local = dns_query(dns, &format!("app.{}", BASE_DOMAIN), RecordType::A).await?;
+            let stale = dns_query(dns, "cached.example", RecordType::A).await?;
+            let (other, _) = clients::dns_query_rcode(dns, "other.example", RecordType::A).await?;
+            Ok((rcodes, degraded, local, stale, other))
+        })
+        .unwrap();
+    let (rcodes, degraded, local, stale, other) = outcome;
+    assert!(rcodes.iter().all(|&r| r == hr_dns::packet::RCODE_SERVFAIL), "{:?}", rcodes);
+    assert!(degraded);
+    assert_eq!(a_records(&local), vec![IpAddr::V4(ROUTER_IP)]);
+    assert_eq!(a_records(&stale), vec!["192.0.2.7".parse::<IpAddr>().unwrap()]);
+    assert_eq!(stale[0].ttl, hr_dns::cache::STALE_TTL);
+    assert_eq!(other, hr_dns::packet::RCODE_SERVFAIL);
+}
+
+#[test]
+fn apex_is_flattened_and_routed() {
+    require_netns!();
+    let lan = Lan::start().unwrap();
+    lan.configure_client("10.77.0.12".parse().unwrap()).unwrap();
+    let _backend = lan.echo_backend(APP_PORT);
+
+    // Apex → app.e2e.test: ALIAS on the DNS side, apex target on the proxy side
+    let app = format!("app.{}", BASE_DOMAIN);
+    let mut config = lan.proxy.config();
+    config.apex_target = Some(app.clone());
+    lan.proxy.reload_config(config);
+
+    let dns = SocketAddr::from((ROUTER_IP, 53));
+    let dns_state = lan.dns.clone();
+    let client = lan.https_client().unwrap();
+    let (records, looping, resp) = lan
+        .client
+        .run(move || async move {
+            {
+                let mut state = dns_state.write().await;
+                for (name, target) in [(BASE_DOMAIN, app.as_str()), ("a.loop", "b.loop"), ("b.loop", "a.loop")] {
+                    state.add_static_record(hr_dns::config::StaticRecord {
+                        name: name.to_string(),
+                        record_type: "ALIAS".to_string(),
+                        value: target.to_string(),
+                        ttl: 30,
+                    });
+                }
+            }
+            let records = dns_query(dns, BASE_DOMAIN, RecordType::A).await?;
+            let (looping, _) = clients::dns_query_rcode(dns, "a.loop", RecordType::A).await?;
+            let proxy = SocketAddr::from((ROUTER_IP, 443));
+            let resp = client.get(proxy, BASE_DOMAIN, "/apex").await?;
+            Ok((records, looping, resp))
+        })
+        .unwrap();
+
+    assert_eq!(a_records(&records), vec![IpAddr::V4(ROUTER_IP)]);
+    assert_eq!(records[0].name, BASE_DOMAIN);
+    assert!(records[0].ttl <= 30);
+    assert_eq!(looping, hr_dns::packet::RCODE_SERVFAIL);
+    assert_eq!(resp.status, StatusCode::OK, "{}", resp.body);
+    let echo: serde_json::Value = serde_json::from_str(&resp.body).unwrap();
+    assert_eq!(echo["path"], "/apex");
+    assert_eq!(echo["host"], BASE_DOMAIN);
+}
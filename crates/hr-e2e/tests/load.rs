@@ -0,0 +1,67 @@
+//! Performance baseline: DNS qps and HTTPS rps on the simulated LAN, plus
+//! tunnel throughput, checked against `baseline.json`. Ignored by default
+//! (`make bench` runs it in release mode); `HR_BENCH_UPDATE=1` records the
+//! current rates as the new baseline.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use hr_e2e::lan::{APP_PORT, BASE_DOMAIN, ROUTER_IP};
+use hr_e2e::load::{self, Baseline, Measurements};
+use hr_e2e::{require_netns, Lan};
+
+const DURATION: Duration = Duration::from_secs(3);
+const CONCURRENCY: usize = 8;
+const TUNNEL_BYTES: u64 = 256 * 1024 * 1024;
+const TUNNEL_STREAMS: usize = 4;
+
+#[test]
+#[ignore = "load run, see `make bench`"]
+fn load_baseline() {
+    require_netns!();
+    let lan = Lan::start().unwrap();
+    let _backend = lan.echo_backend(APP_PORT);
+    lan.configure_client("10.77.0.10".parse().unwrap()).unwrap();
+    let host = format!("app.{}", BASE_DOMAIN);
+
+    let dns = SocketAddr::from((ROUTER_IP, 53));
+    let dns_host = host.clone();
+    let dns_run = lan
+        .client
+        .run(move || async move { load::dns_load(dns, &dns_host, CONCURRENCY, DURATION).await })
+        .unwrap();
+
+    let client = lan.https_client().unwrap();
+    let proxy = SocketAddr::from((ROUTER_IP, 443));
+    let http_run = lan
+        .client
+        .run(move || async move {
+            load::http_load(&client, proxy, &host, "/", CONCURRENCY, DURATION).await
+        })
+        .unwrap();
+
+    let tunnel_mbps = lan
+        .router
+        .run(|| load::tunnel_throughput(TUNNEL_BYTES, TUNNEL_STREAMS))
+        .unwrap();
+
+    let measured = Measurements {
+        dns_qps: dns_run.rate().round(),
+        http_rps: http_run.rate().round(),
+        tunnel_mbps: tunnel_mbps.round(),
+    };
+    eprintln!("dns: {:?}\nhttp: {:?}\n{:#?}", dns_run, http_run, measured);
+    assert!(dns_run.ok > 0 && http_run.ok > 0);
+
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("baseline.json");
+    if std::env::var_os("HR_BENCH_UPDATE").is_some() {
+        let tolerance = Baseline::load(&path).map(|b| b.tolerance).unwrap_or(0.25);
+        Baseline { tolerance, rates: measured }.save(&path).unwrap();
+        eprintln!("baseline updated: {}", path.display());
+        return;
+    }
+    let baseline = Baseline::load(&path).unwrap();
+    let regressions = baseline.regressions(&measured);
+    assert!(regressions.is_empty(), "performance regressions:\n{}", regressions.join("\n"));
+}
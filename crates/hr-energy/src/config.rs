@@ -0,0 +1,249 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Power metering (energy-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnergyConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Interval between two polls of the HTTP and SNMP meters.
+    #[serde(default = "default_poll")]
+    pub poll_secs: u64,
+    /// Samples older than this are purged.
+    #[serde(default = "default_retention")]
+    pub retention_days: u64,
+    /// Broker of the `mqtt` meters.
+    #[serde(default)]
+    pub mqtt: MqttBroker,
+    #[serde(default)]
+    pub meters: Vec<Meter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MqttBroker {
+    #[serde(default)]
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// One power reading source.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Meter {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "yes")]
+    pub enabled: bool,
+    /// Host of hosts.json powered through this meter.
+    #[serde(default)]
+    pub host_id: Option<String>,
+    /// At or above this draw the host is busy: its agent holds the auto-off
+    /// countdown even with an idle CPU (GPU jobs, disk scrubs...).
+    #[serde(default)]
+    pub busy_watts: Option<f64>,
+    #[serde(flatten)]
+    pub source: MeterSource,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum MeterSource {
+    /// Tasmota plug, polled over HTTP (`Status 10`).
+    Tasmota { address: String },
+    /// Shelly plug or relay (Gen1 `/status`, Gen2+ RPC).
+    Shelly {
+        address: String,
+        #[serde(default)]
+        channel: u8,
+    },
+    /// SNMP v2c GET of a power OID (UPS, PDU, PoE switch).
+    Snmp {
+        address: String,
+        #[serde(default = "default_snmp_port")]
+        port: u16,
+        #[serde(default = "default_community")]
+        community: String,
+        /// e.g. UPS-MIB upsOutputPower `1.3.6.1.2.1.33.1.4.4.1.4.1`.
+        oid: String,
+        /// Multiplier giving watts (0.1 for deciwatts).
+        #[serde(default = "default_scale")]
+        scale: f64,
+    },
+    /// Plug publishing its readings on the broker (Tasmota `tele/+/SENSOR`,
+    /// Shelly `shellies/+/relay/0/power` or `+/status/switch:0`).
+    Mqtt { topic: String },
+}
+
+impl MeterSource {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MeterSource::Tasmota { .. } => "tasmota",
+            MeterSource::Shelly { .. } => "shelly",
+            MeterSource::Snmp { .. } => "snmp",
+            MeterSource::Mqtt { .. } => "mqtt",
+        }
+    }
+}
+
+fn yes() -> bool {
+    true
+}
+
+fn default_poll() -> u64 {
+    30
+}
+
+fn default_retention() -> u64 {
+    365
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_snmp_port() -> u16 {
+    161
+}
+
+fn default_community() -> String {
+    "public".to_string()
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+impl Default for EnergyConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl Default for MqttBroker {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl EnergyConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// MQTT meters are read only with a broker configured.
+    pub fn uses_mqtt(&self) -> bool {
+        !self.mqtt.host.is_empty()
+            && self.meters.iter().any(|m| m.enabled && matches!(m.source, MeterSource::Mqtt { .. }))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(5..=3600).contains(&self.poll_secs) {
+            return Err("The poll interval must be between 5 s and 1 h".to_string());
+        }
+        if !(1..=3650).contains(&self.retention_days) {
+            return Err("Samples must be kept between 1 day and 10 years".to_string());
+        }
+        let mut ids = std::collections::HashSet::new();
+        for meter in &self.meters {
+            if meter.id.is_empty() || !meter.id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                return Err(format!("Invalid meter id: {:?} (letters, digits, - and _)", meter.id));
+            }
+            if !ids.insert(meter.id.as_str()) {
+                return Err(format!("Duplicate meter id: {}", meter.id));
+            }
+            if meter.busy_watts.is_some_and(|w| !w.is_finite() || w <= 0.0) {
+                return Err(format!("{}: the busy threshold must be positive", meter.id));
+            }
+            match &meter.source {
+                MeterSource::Tasmota { address } | MeterSource::Shelly { address, .. } => {
+                    if address.trim().is_empty() {
+                        return Err(format!("{}: address required", meter.id));
+                    }
+                }
+                MeterSource::Snmp { address, oid, scale, .. } => {
+                    if address.trim().is_empty() {
+                        return Err(format!("{}: address required", meter.id));
+                    }
+                    crate::snmp::parse_oid(oid).map_err(|e| format!("{}: {e}", meter.id))?;
+                    if !scale.is_finite() || *scale <= 0.0 {
+                        return Err(format!("{}: the scale must be positive", meter.id));
+                    }
+                }
+                MeterSource::Mqtt { topic } => {
+                    if topic.is_empty() || topic.contains(['+', '#']) {
+                        return Err(format!("{}: a topic without wildcards is required", meter.id));
+                    }
+                    if self.mqtt.host.is_empty() {
+                        return Err(format!("{}: MQTT meters need a broker", meter.id));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut config: EnergyConfig = serde_json::from_value(serde_json::json!({
+            "enabled": true,
+            "meters": [
+                {"id": "nas", "type": "shelly", "address": "192.168.1.40", "hostId": "h1", "busyWatts": 45},
+                {"id": "ups", "type": "snmp", "address": "192.168.1.2", "oid": "1.3.6.1.2.1.33.1.4.4.1.4.1"}
+            ]
+        }))
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.meters[0].source, MeterSource::Shelly { address: "192.168.1.40".to_string(), channel: 0 });
+        assert!(matches!(&config.meters[1].source, MeterSource::Snmp { port: 161, community, .. } if community == "public"));
+        assert!(!config.uses_mqtt());
+
+        config.meters.push(Meter {
+            id: "desk".to_string(),
+            name: String::new(),
+            enabled: true,
+            host_id: None,
+            busy_watts: None,
+            source: MeterSource::Mqtt { topic: "tele/desk/SENSOR".to_string() },
+        });
+        assert!(config.validate().is_err());
+        config.mqtt.host = "broker.lan".to_string();
+        assert!(config.validate().is_ok() && config.uses_mqtt());
+
+        config.meters[2].id = "nas".to_string();
+        assert!(config.validate().is_err());
+        config.meters[2].id = "desk".to_string();
+        config.meters[1].source = MeterSource::Snmp {
+            address: "192.168.1.2".to_string(),
+            port: 161,
+            community: "public".to_string(),
+            oid: "1.3.x".to_string(),
+            scale: 1.0,
+        };
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Power metering: polls smart plugs (Tasmota, Shelly), SNMP UPSes/PDUs and
+//! listens to plugs publishing on MQTT, stores the draw and the watt-hours
+//! in SQLite, and tells the host agents when their host draws enough power
+//! to be considered busy so auto-off waits.
+
+pub mod config;
+pub mod plug;
+pub mod service;
+pub mod snmp;
+pub mod store;
+
+pub use config::{EnergyConfig, Meter, MeterSource, MqttBroker};
+pub use store::{EnergyStore, SeriesPoint};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnergyStatus {
+    pub active: bool,
+    pub mqtt_connected: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_poll: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mqtt_error: Option<String>,
+}
+
+/// Latest reading of a meter.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeterReading {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub host_id: Option<String>,
+    /// Last draw; `None` before the first reading or once stale.
+    pub watts: Option<f64>,
+    pub at: Option<String>,
+    pub error: Option<String>,
+    pub busy: bool,
+}
+
+#[derive(Debug, Default)]
+struct MeterState {
+    watts: Option<f64>,
+    at: Option<DateTime<Utc>>,
+    error: Option<String>,
+    /// Watt-hours integrated since the last stored sample.
+    pending_wh: f64,
+    stored_at: Option<DateTime<Utc>>,
+}
+
+/// Shared metering handle: the API reads the readings and the store and
+/// pushes config; the supervised `energy` service records the readings.
+pub struct PowerMeters {
+    config: watch::Sender<EnergyConfig>,
+    store: EnergyStore,
+    states: RwLock<HashMap<String, MeterState>>,
+    status: RwLock<EnergyStatus>,
+}
+
+pub type SharedPowerMeters = Arc<PowerMeters>;
+
+/// Readings older than this many poll intervals are stale.
+const STALE_POLLS: i64 = 3;
+
+impl PowerMeters {
+    pub fn new(config: EnergyConfig, store: EnergyStore) -> Self {
+        Self {
+            config: watch::channel(config).0,
+            store,
+            states: RwLock::new(HashMap::new()),
+            status: RwLock::new(EnergyStatus::default()),
+        }
+    }
+
+    pub fn config(&self) -> EnergyConfig {
+        self.config.borrow().clone()
+    }
+
+    pub fn apply(&self, config: EnergyConfig) {
+        self.config.send_replace(config);
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<EnergyConfig> {
+        self.config.subscribe()
+    }
+
+    pub fn store(&self) -> &EnergyStore {
+        &self.store
+    }
+
+    pub fn status(&self) -> EnergyStatus {
+        self.status.read().unwrap().clone()
+    }
+
+    pub(crate) fn update_status(&self, f: impl FnOnce(&mut EnergyStatus)) {
+        f(&mut self.status.write().unwrap());
+    }
+
+    /// Record a draw: integrates the watt-hours since the previous reading
+    /// and stores a sample at most twice per poll interval.
+    pub(crate) fn record(&self, meter_id: &str, watts: f64, now: DateTime<Utc>) {
+        let poll_secs = self.config.borrow().poll_secs as i64;
+        let sample = {
+            let mut states = self.states.write().unwrap();
+            let state = states.entry(meter_id.to_string()).or_default();
+            if let (Some(prev), Some(at)) = (state.watts, state.at) {
+                let secs = (now - at).num_milliseconds() as f64 / 1000.0;
+                // Across a gap the consumption is unknown, not interpolated
+                if secs > 0.0 && secs <= (STALE_POLLS * poll_secs) as f64 {
+                    state.pending_wh += (prev + watts) / 2.0 * secs / 3600.0;
+                }
+            }
+            state.watts = Some(watts);
+            state.at = Some(now);
+            state.error = None;
+            let due = state.stored_at.is_none_or(|t| (now - t).num_seconds() * 2 >= poll_secs);
+            due.then(|| {
+                state.stored_at = Some(now);
+                std::mem::take(&mut state.pending_wh)
+            })
+        };
+        hr_common::metrics::registry()
+            .gauge("homeroute_meter_power_watts", "Last power draw of each energy meter.", &[("meter", meter_id)])
+            .set(watts);
+        if let Some(wh) = sample
+            && let Err(e) = self.store.insert(meter_id, now.timestamp(), watts, wh)
+        {
+            tracing::warn!("Failed to store the {} power sample: {}", meter_id, e);
+        }
+    }
+
+    pub(crate) fn record_error(&self, meter_id: &str, error: String) {
+        let mut states = self.states.write().unwrap();
+        states.entry(meter_id.to_string()).or_default().error = Some(error);
+    }
+
+    /// Fresh draw of a meter.
+    fn fresh_watts(&self, state: &MeterState, now: DateTime<Utc>, poll_secs: i64) -> Option<f64> {
+        let at = state.at?;
+        ((now - at).num_seconds() <= STALE_POLLS * poll_secs).then_some(state.watts).flatten()
+    }
+
+    pub fn readings(&self) -> Vec<MeterReading> {
+        let config = self.config();
+        let now = Utc::now();
+        let states = self.states.read().unwrap();
+        config
+            .meters
+            .iter()
+            .map(|meter| {
+                let state = states.get(&meter.id);
+                let watts = state.and_then(|s| self.fresh_watts(s, now, config.poll_secs as i64));
+                MeterReading {
+                    id: meter.id.clone(),
+                    name: meter.name.clone(),
+                    kind: meter.source.kind(),
+                    host_id: meter.host_id.clone(),
+                    watts,
+                    at: state.and_then(|s| s.at).map(|t| t.to_rfc3339()),
+                    error: state.and_then(|s| s.error.clone()),
+                    busy: config.enabled
+                        && meter.enabled
+                        && meter.busy_watts.zip(watts).is_some_and(|(threshold, w)| w >= threshold),
+                }
+            })
+            .collect()
+    }
+
+    /// Current draw of a host: sum of its fresh meters, `None` without any.
+    pub fn host_watts(&self, host_id: &str) -> Option<f64> {
+        let readings: Vec<f64> = self
+            .readings()
+            .into_iter()
+            .filter(|r| r.host_id.as_deref() == Some(host_id))
+            .filter_map(|r| r.watts)
+            .collect();
+        (!readings.is_empty()).then(|| readings.iter().sum())
+    }
+
+    /// Busy state of every host having a meter with a busy threshold.
+    pub fn busy_hosts(&self) -> HashMap<String, bool> {
+        let config = self.config();
+        let readings = self.readings();
+        let mut hosts = HashMap::new();
+        if !config.enabled {
+            return hosts;
+        }
+        for (meter, reading) in config.meters.iter().zip(&readings) {
+            if let (Some(host_id), Some(_)) = (&meter.host_id, meter.busy_watts) {
+                *hosts.entry(host_id.clone()).or_insert(false) |= reading.busy;
+            }
+        }
+        hosts
+    }
+
+    pub fn host_busy(&self, host_id: &str) -> bool {
+        self.busy_hosts().get(host_id).copied().unwrap_or(false)
+    }
+
+    /// Forget the readings of meters no longer configured.
+    pub(crate) fn retain(&self, config: &EnergyConfig) {
+        self.states
+            .write()
+            .unwrap()
+            .retain(|id, _| config.meters.iter().any(|m| &m.id == id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meters() -> PowerMeters {
+        let config: EnergyConfig = serde_json::from_value(serde_json::json!({
+            "enabled": true,
+            "pollSecs": 60,
+            "meters": [
+                {"id": "a", "type": "tasmota", "address": "plug-a", "hostId": "h1", "busyWatts": 50},
+                {"id": "b", "type": "tasmota", "address": "plug-b", "hostId": "h1"}
+            ]
+        }))
+        .unwrap();
+        PowerMeters::new(config, EnergyStore::open_in_memory().unwrap())
+    }
+
+    #[test]
+    fn test_record_integrates() {
+        let meters = meters();
+        let t0 = Utc::now() - chrono::Duration::minutes(2);
+        meters.record("a", 100.0, t0);
+        meters.record("a", 200.0, t0 + chrono::Duration::seconds(60));
+        // Too close to the previous sample: integrated, not stored yet
+        meters.record("a", 200.0, t0 + chrono::Duration::seconds(80));
+        meters.record("a", 200.0, t0 + chrono::Duration::seconds(120));
+
+        let totals = meters.store().totals(0, i64::MAX).unwrap();
+        // 150 W × 60 s + 200 W × 60 s
+        assert!((totals["a"] - (2.5 + 200.0 / 60.0)).abs() < 1e-9);
+
+        // A gap longer than three polls is not interpolated
+        meters.record("b", 100.0, t0 - chrono::Duration::hours(1));
+        meters.record("b", 100.0, t0);
+        assert_eq!(meters.store().totals(0, i64::MAX).unwrap()["b"], 0.0);
+    }
+
+    #[test]
+    fn test_host_busy() {
+        let meters = meters();
+        let now = Utc::now();
+        assert!(!meters.host_busy("h1"));
+        assert_eq!(meters.host_watts("h1"), None);
+
+        meters.record("a", 20.0, now);
+        meters.record("b", 40.0, now);
+        assert_eq!(meters.host_watts("h1"), Some(60.0));
+        assert!(!meters.host_busy("h1"));
+
+        meters.record("a", 80.0, now);
+        assert!(meters.host_busy("h1"));
+        assert_eq!(meters.busy_hosts().len(), 1);
+
+        // Stale readings do not count
+        meters.record("a", 80.0, now - chrono::Duration::minutes(10));
+        assert!(!meters.host_busy("h1"));
+    }
+}
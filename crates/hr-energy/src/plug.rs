@@ -0,0 +1,105 @@
+//! Smart plug readings: Tasmota and Shelly over HTTP, and the payloads both
+//! publish on MQTT.
+
+use serde_json::Value;
+
+/// Sum of `Power`, a number or one per channel.
+fn sum_power(power: &Value) -> Option<f64> {
+    match power {
+        Value::Array(channels) => channels.iter().map(Value::as_f64).sum(),
+        v => v.as_f64(),
+    }
+}
+
+/// Tasmota `Status 10` answer (`StatusSNS`) or `tele/.../SENSOR` payload.
+pub fn tasmota_power(doc: &Value) -> Option<f64> {
+    let sensors = doc.get("StatusSNS").unwrap_or(doc);
+    sum_power(sensors.get("ENERGY")?.get("Power")?)
+}
+
+/// Shelly Gen2+ `Shelly.GetStatus` (`switch:N` / `pm1:N`), a single
+/// component status (`apower`), or Gen1 `/status` (`meters[N]`).
+pub fn shelly_power(doc: &Value, channel: u8) -> Option<f64> {
+    for component in ["switch", "pm1", "cover"] {
+        if let Some(power) = doc.get(format!("{component}:{channel}")).and_then(|c| c.get("apower")) {
+            return power.as_f64();
+        }
+    }
+    if let Some(power) = doc.get("apower") {
+        return power.as_f64();
+    }
+    doc.get("meters")
+        .or_else(|| doc.get("emeters"))?
+        .get(channel as usize)?
+        .get("power")?
+        .as_f64()
+}
+
+/// Watts of an MQTT payload: a bare number (Shelly Gen1) or one of the
+/// JSON documents above.
+pub fn mqtt_power(payload: &[u8]) -> Option<f64> {
+    let text = std::str::from_utf8(payload).ok()?.trim();
+    if let Ok(watts) = text.parse::<f64>() {
+        return Some(watts);
+    }
+    let doc: Value = serde_json::from_str(text).ok()?;
+    tasmota_power(&doc).or_else(|| shelly_power(&doc, 0))
+}
+
+fn base_url(address: &str) -> String {
+    let address = address.trim().trim_end_matches('/');
+    if address.starts_with("http://") || address.starts_with("https://") {
+        address.to_string()
+    } else {
+        format!("http://{address}")
+    }
+}
+
+async fn get_json(client: &reqwest::Client, url: &str) -> Result<Value, String> {
+    let response = client.get(url).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    response.json().await.map_err(|e| e.to_string())
+}
+
+pub async fn read_tasmota(client: &reqwest::Client, address: &str) -> Result<f64, String> {
+    let doc = get_json(client, &format!("{}/cm?cmnd=Status%2010", base_url(address))).await?;
+    tasmota_power(&doc).ok_or_else(|| "No energy sensor in the Tasmota status".to_string())
+}
+
+pub async fn read_shelly(client: &reqwest::Client, address: &str, channel: u8) -> Result<f64, String> {
+    let base = base_url(address);
+    // Gen2+ first; Gen1 devices answer 404 on RPC paths
+    let doc = match get_json(client, &format!("{base}/rpc/Shelly.GetStatus")).await {
+        Ok(doc) => doc,
+        Err(_) => get_json(client, &format!("{base}/status")).await?,
+    };
+    shelly_power(&doc, channel).ok_or_else(|| format!("No power meter on Shelly channel {channel}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_payloads() {
+        let status = json!({"StatusSNS": {"Time": "2026-06-01T10:00:00", "ENERGY": {"Total": 12.3, "Power": 41}}});
+        assert_eq!(tasmota_power(&status), Some(41.0));
+        assert_eq!(tasmota_power(&json!({"ENERGY": {"Power": [10, 5.5]}})), Some(15.5));
+        assert_eq!(tasmota_power(&json!({"DS18B20": {"Temperature": 20}})), None);
+
+        let gen2 = json!({"sys": {}, "switch:0": {"apower": 3.2}, "switch:1": {"apower": 80.0}});
+        assert_eq!(shelly_power(&gen2, 1), Some(80.0));
+        let gen1 = json!({"relays": [{"ison": true}], "meters": [{"power": 12.5, "is_valid": true}]});
+        assert_eq!(shelly_power(&gen1, 0), Some(12.5));
+        assert_eq!(shelly_power(&gen1, 1), None);
+
+        assert_eq!(mqtt_power(b"17.25\n"), Some(17.25));
+        assert_eq!(mqtt_power(br#"{"id":0,"output":true,"apower":9.1}"#), Some(9.1));
+        assert_eq!(mqtt_power(br#"{"ENERGY":{"Power":60}}"#), Some(60.0));
+        assert_eq!(mqtt_power(b"on"), None);
+        assert_eq!(base_url("192.168.1.40/"), "http://192.168.1.40");
+    }
+}
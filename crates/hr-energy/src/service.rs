@@ -0,0 +1,188 @@
+//! The `energy` service: polls the HTTP and SNMP meters every poll
+//! interval, records the MQTT meters as they publish, purges old samples
+//! and pushes the busy state of metered hosts to their agents.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::Utc;
+use hr_registry::protocol::HostRegistryMessage;
+use hr_registry::AgentRegistry;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+use crate::config::{EnergyConfig, MeterSource};
+use crate::{plug, snmp, SharedPowerMeters};
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+const SNMP_TIMEOUT: Duration = Duration::from_secs(2);
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+/// Delay between reconnection attempts after a broker error.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Aborts the MQTT listener when the configuration changes.
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+pub async fn run_energy(meters: SharedPowerMeters, registry: Arc<AgentRegistry>) -> Result<()> {
+    let mut config_rx = meters.subscribe();
+    let client = reqwest::Client::builder().timeout(HTTP_TIMEOUT).build()?;
+    let mut busy_sent: HashMap<String, bool> = HashMap::new();
+    let mut next_purge = Instant::now();
+    loop {
+        let config = config_rx.borrow_and_update().clone();
+        meters.retain(&config);
+        meters.update_status(|s| {
+            s.active = config.enabled;
+            s.mqtt_connected = false;
+            s.mqtt_error = None;
+        });
+        let _mqtt = (config.enabled && config.uses_mqtt())
+            .then(|| AbortOnDrop(tokio::spawn(run_mqtt(meters.clone(), config.clone()))));
+
+        loop {
+            if config.enabled {
+                poll(&meters, &client, &config).await;
+                if Instant::now() >= next_purge {
+                    next_purge = Instant::now() + PURGE_INTERVAL;
+                    purge(&meters, &config).await;
+                }
+            }
+            push_busy(&meters, &registry, &mut busy_sent).await;
+
+            tokio::select! {
+                changed = config_rx.changed() => {
+                    if changed.is_err() {
+                        return Ok(());
+                    }
+                    break;
+                }
+                _ = tokio::time::sleep(Duration::from_secs(config.poll_secs)) => {}
+            }
+        }
+    }
+}
+
+/// Read every polled meter concurrently.
+async fn poll(meters: &SharedPowerMeters, client: &reqwest::Client, config: &EnergyConfig) {
+    let mut tasks = JoinSet::new();
+    for meter in config.meters.iter().filter(|m| m.enabled) {
+        let client = client.clone();
+        let id = meter.id.clone();
+        let source = meter.source.clone();
+        tasks.spawn(async move {
+            let watts = match &source {
+                MeterSource::Tasmota { address } => plug::read_tasmota(&client, address).await,
+                MeterSource::Shelly { address, channel } => plug::read_shelly(&client, address, *channel).await,
+                MeterSource::Snmp { address, port, community, oid, scale } => {
+                    snmp::get(address, *port, community, oid, SNMP_TIMEOUT).await.map(|v| v * scale)
+                }
+                MeterSource::Mqtt { .. } => return None,
+            };
+            Some((id, watts))
+        });
+    }
+    while let Some(done) = tasks.join_next().await {
+        match done {
+            Ok(Some((id, Ok(watts)))) => meters.record(&id, watts, Utc::now()),
+            Ok(Some((id, Err(e)))) => {
+                debug!("Energy meter {}: {}", id, e);
+                meters.record_error(&id, e);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Energy meter poll task failed: {}", e),
+        }
+    }
+    meters.update_status(|s| s.last_poll = Some(Utc::now().to_rfc3339()));
+}
+
+async fn purge(meters: &SharedPowerMeters, config: &EnergyConfig) {
+    let before = Utc::now().timestamp() - config.retention_days as i64 * 86_400;
+    let m = meters.clone();
+    match tokio::task::spawn_blocking(move || m.store().purge(before)).await {
+        Ok(Ok(n)) if n > 0 => info!("Purged {} power samples", n),
+        Ok(Err(e)) => warn!("Failed to purge power samples: {}", e),
+        _ => {}
+    }
+}
+
+/// Send the busy state of each metered host when it changes; hosts whose
+/// meter went away are released.
+async fn push_busy(meters: &SharedPowerMeters, registry: &AgentRegistry, sent: &mut HashMap<String, bool>) {
+    let mut current = meters.busy_hosts();
+    for (host_id, was_busy) in sent.iter() {
+        if *was_busy {
+            current.entry(host_id.clone()).or_insert(false);
+        }
+    }
+    for (host_id, busy) in current {
+        if sent.get(&host_id).copied().unwrap_or(false) == busy {
+            sent.insert(host_id, busy);
+            continue;
+        }
+        info!("Host {} power draw: {}", host_id, if busy { "busy, auto-off held" } else { "idle" });
+        // A disconnected agent gets the state when it reconnects
+        let _ = registry.send_host_command(&host_id, HostRegistryMessage::PowerBusy { busy }).await;
+        sent.insert(host_id, busy);
+    }
+}
+
+/// Subscribe to the topics of the MQTT meters until aborted.
+async fn run_mqtt(meters: SharedPowerMeters, config: EnergyConfig) {
+    let topics: Vec<(String, String)> = config
+        .meters
+        .iter()
+        .filter(|m| m.enabled)
+        .filter_map(|m| match &m.source {
+            MeterSource::Mqtt { topic } => Some((topic.clone(), m.id.clone())),
+            _ => None,
+        })
+        .collect();
+
+    let mut options = MqttOptions::new("homeroute-energy", &config.mqtt.host, config.mqtt.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(user) = &config.mqtt.username {
+        options.set_credentials(user, config.mqtt.password.clone().unwrap_or_default());
+    }
+    let (client, mut eventloop) = AsyncClient::new(options, 64);
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("Energy meters connected to MQTT broker {}:{}", config.mqtt.host, config.mqtt.port);
+                meters.update_status(|s| {
+                    s.mqtt_connected = true;
+                    s.mqtt_error = None;
+                });
+                for (topic, _) in &topics {
+                    if let Err(e) = client.try_subscribe(topic, QoS::AtMostOnce) {
+                        warn!("Energy MQTT subscribe to {} failed: {}", topic, e);
+                    }
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(p))) => {
+                for (_, id) in topics.iter().filter(|(t, _)| *t == p.topic) {
+                    match plug::mqtt_power(&p.payload) {
+                        Some(watts) => meters.record(id, watts, Utc::now()),
+                        None => meters.record_error(id, format!("No power reading on {}", p.topic)),
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                meters.update_status(|s| {
+                    s.mqtt_connected = false;
+                    s.mqtt_error = Some(e.to_string());
+                });
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
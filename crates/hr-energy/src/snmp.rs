@@ -0,0 +1,256 @@
+//! Minimal SNMP v2c client: a single GET of a numeric OID, enough to read
+//! the output power of a UPS, a PDU outlet or a PoE switch.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_NULL: u8 = 0x05;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_COUNTER32: u8 = 0x41;
+const TAG_GAUGE32: u8 = 0x42;
+const TAG_TIMETICKS: u8 = 0x43;
+const TAG_COUNTER64: u8 = 0x46;
+const TAG_GET_REQUEST: u8 = 0xa0;
+const TAG_GET_RESPONSE: u8 = 0xa2;
+const TAG_NO_SUCH_OBJECT: u8 = 0x80;
+const TAG_NO_SUCH_INSTANCE: u8 = 0x81;
+const TAG_END_OF_MIB: u8 = 0x82;
+
+/// SNMP version field of v2c messages.
+const VERSION_2C: i64 = 1;
+
+/// Dotted OID (`1.3.6.1...`, leading dot allowed).
+pub fn parse_oid(oid: &str) -> Result<Vec<u32>, String> {
+    let invalid = || format!("Invalid OID: {oid}");
+    let arcs = oid
+        .trim()
+        .trim_start_matches('.')
+        .split('.')
+        .map(|a| a.parse::<u32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if arcs.len() < 2 || arcs[0] > 2 || (arcs[0] < 2 && arcs[1] > 39) {
+        return Err(invalid());
+    }
+    Ok(arcs)
+}
+
+fn push_len(out: &mut Vec<u8>, len: usize) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else if len <= 0xff {
+        out.extend_from_slice(&[0x81, len as u8]);
+    } else {
+        out.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]);
+    }
+}
+
+fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    push_len(&mut out, value.len());
+    out.extend_from_slice(value);
+    out
+}
+
+fn integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    // Shortest two's complement form
+    let mut start = 0;
+    while start < 7
+        && ((bytes[start] == 0 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+    tlv(TAG_INTEGER, &bytes[start..])
+}
+
+fn oid(arcs: &[u32]) -> Vec<u8> {
+    let mut value = Vec::new();
+    let mut push_arc = |mut arc: u32| {
+        let mut chunk = vec![(arc & 0x7f) as u8];
+        arc >>= 7;
+        while arc > 0 {
+            chunk.push(0x80 | (arc & 0x7f) as u8);
+            arc >>= 7;
+        }
+        value.extend(chunk.iter().rev());
+    };
+    push_arc(arcs[0] * 40 + arcs[1]);
+    arcs[2..].iter().for_each(|a| push_arc(*a));
+    tlv(TAG_OID, &value)
+}
+
+/// GetRequest of one OID.
+pub fn encode_get(community: &str, request_id: i32, arcs: &[u32]) -> Vec<u8> {
+    let varbind = tlv(TAG_SEQUENCE, &[oid(arcs), tlv(TAG_NULL, &[])].concat());
+    let pdu = tlv(
+        TAG_GET_REQUEST,
+        &[integer(request_id as i64), integer(0), integer(0), tlv(TAG_SEQUENCE, &varbind)].concat(),
+    );
+    tlv(
+        TAG_SEQUENCE,
+        &[integer(VERSION_2C), tlv(TAG_OCTET_STRING, community.as_bytes()), pdu].concat(),
+    )
+}
+
+/// Next TLV of `buf`: (tag, value, rest).
+fn read_tlv(buf: &[u8]) -> Result<(u8, &[u8], &[u8]), String> {
+    let truncated = || "Truncated SNMP response".to_string();
+    let (&tag, buf) = buf.split_first().ok_or_else(truncated)?;
+    let (&first, mut buf) = buf.split_first().ok_or_else(truncated)?;
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 3 || buf.len() < n {
+            return Err("Invalid SNMP length".to_string());
+        }
+        let len = buf[..n].iter().fold(0usize, |acc, b| acc << 8 | *b as usize);
+        buf = &buf[n..];
+        len
+    };
+    if buf.len() < len {
+        return Err(truncated());
+    }
+    Ok((tag, &buf[..len], &buf[len..]))
+}
+
+fn expect(buf: &[u8], tag: u8) -> Result<(&[u8], &[u8]), String> {
+    let (t, value, rest) = read_tlv(buf)?;
+    if t != tag {
+        return Err(format!("Unexpected SNMP tag 0x{t:02x} (expected 0x{tag:02x})"));
+    }
+    Ok((value, rest))
+}
+
+fn decode_integer(value: &[u8]) -> Result<i64, String> {
+    if value.is_empty() || value.len() > 8 {
+        return Err("Invalid SNMP integer".to_string());
+    }
+    let init = if value[0] & 0x80 != 0 { -1i64 } else { 0 };
+    Ok(value.iter().fold(init, |acc, b| acc << 8 | *b as i64))
+}
+
+fn decode_unsigned(value: &[u8]) -> Result<u64, String> {
+    if value.is_empty() || value.len() > 9 {
+        return Err("Invalid SNMP counter".to_string());
+    }
+    Ok(value.iter().fold(0u64, |acc, b| acc << 8 | *b as u64))
+}
+
+/// Numeric value of the GetResponse answering `request_id`.
+pub fn decode_response(buf: &[u8], request_id: i32) -> Result<f64, String> {
+    let (message, _) = expect(buf, TAG_SEQUENCE)?;
+    let (_version, rest) = expect(message, TAG_INTEGER)?;
+    let (_community, rest) = expect(rest, TAG_OCTET_STRING)?;
+    let (pdu, _) = expect(rest, TAG_GET_RESPONSE)?;
+    let (id, rest) = expect(pdu, TAG_INTEGER)?;
+    if decode_integer(id)? != request_id as i64 {
+        return Err("SNMP response to another request".to_string());
+    }
+    let (status, rest) = expect(rest, TAG_INTEGER)?;
+    let (_index, rest) = expect(rest, TAG_INTEGER)?;
+    match decode_integer(status)? {
+        0 => {}
+        2 => return Err("No such name".to_string()),
+        s => return Err(format!("SNMP error status {s}")),
+    }
+    let (varbinds, _) = expect(rest, TAG_SEQUENCE)?;
+    let (varbind, _) = expect(varbinds, TAG_SEQUENCE)?;
+    let (_oid, rest) = expect(varbind, TAG_OID)?;
+    let (tag, value, _) = read_tlv(rest)?;
+    match tag {
+        TAG_INTEGER => Ok(decode_integer(value)? as f64),
+        TAG_COUNTER32 | TAG_GAUGE32 | TAG_TIMETICKS | TAG_COUNTER64 => Ok(decode_unsigned(value)? as f64),
+        // Some PDUs report readings as text ("123.4")
+        TAG_OCTET_STRING => std::str::from_utf8(value)
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .ok_or_else(|| "Non-numeric SNMP string".to_string()),
+        TAG_NO_SUCH_OBJECT | TAG_NO_SUCH_INSTANCE | TAG_END_OF_MIB => Err("No such object".to_string()),
+        t => Err(format!("Unsupported SNMP value type 0x{t:02x}")),
+    }
+}
+
+/// GET `oid` from `address:port`, one retry.
+pub async fn get(address: &str, port: u16, community: &str, oid: &str, timeout: Duration) -> Result<f64, String> {
+    let arcs = parse_oid(oid)?;
+    let target: SocketAddr = tokio::net::lookup_host((address, port))
+        .await
+        .map_err(|e| format!("{address}: {e}"))?
+        .next()
+        .ok_or_else(|| format!("{address}: no address"))?;
+    let bind = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(bind).await.map_err(|e| e.to_string())?;
+    socket.connect(target).await.map_err(|e| e.to_string())?;
+
+    let request_id = (chrono::Utc::now().timestamp_subsec_nanos() & 0x7fff_ffff) as i32;
+    let request = encode_get(community, request_id, &arcs);
+    let mut buf = vec![0u8; 4096];
+    for _ in 0..2 {
+        socket.send(&request).await.map_err(|e| e.to_string())?;
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            match tokio::time::timeout_at(deadline, socket.recv(&mut buf)).await {
+                Ok(Ok(n)) => match decode_response(&buf[..n], request_id) {
+                    // Late answer to the first attempt: identical, fine
+                    Err(e) if e.contains("another request") => continue,
+                    result => return result,
+                },
+                Ok(Err(e)) => return Err(e.to_string()),
+                Err(_) => break,
+            }
+        }
+    }
+    Err(format!("{address}: SNMP timeout"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_get() {
+        let arcs = parse_oid(".1.3.6.1.2.1.1.3.0").unwrap();
+        assert_eq!(
+            encode_get("public", 1, &arcs),
+            [
+                0x30, 0x26, 0x02, 0x01, 0x01, 0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c', 0xa0, 0x19, 0x02,
+                0x01, 0x01, 0x02, 0x01, 0x00, 0x02, 0x01, 0x00, 0x30, 0x0e, 0x30, 0x0c, 0x06, 0x08, 0x2b, 0x06,
+                0x01, 0x02, 0x01, 0x01, 0x03, 0x00, 0x05, 0x00,
+            ]
+        );
+        assert_eq!(integer(128), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(integer(-1), [0x02, 0x01, 0xff]);
+        assert_eq!(oid(&[1, 3, 6, 1, 4, 1, 318]), [0x06, 0x07, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x3e]);
+        assert!(parse_oid("1.3.x").is_err());
+        assert!(parse_oid("3.1").is_err());
+    }
+
+    fn response(request_id: i32, status: i64, value: Vec<u8>) -> Vec<u8> {
+        let varbind = tlv(TAG_SEQUENCE, &[oid(&[1, 3, 6, 1, 2, 1, 33, 1, 4, 4, 1, 4, 1]), value].concat());
+        let pdu = tlv(
+            TAG_GET_RESPONSE,
+            &[integer(request_id as i64), integer(status), integer(0), tlv(TAG_SEQUENCE, &varbind)].concat(),
+        );
+        tlv(TAG_SEQUENCE, &[integer(1), tlv(TAG_OCTET_STRING, b"public"), pdu].concat())
+    }
+
+    #[test]
+    fn test_decode_response() {
+        assert_eq!(decode_response(&response(7, 0, integer(342)), 7), Ok(342.0));
+        assert_eq!(decode_response(&response(7, 0, tlv(TAG_GAUGE32, &[0x00, 0xff, 0xff, 0xff, 0xff])), 7), Ok(4294967295.0));
+        assert_eq!(decode_response(&response(7, 0, tlv(TAG_OCTET_STRING, b" 12.5")), 7), Ok(12.5));
+        assert!(decode_response(&response(7, 0, tlv(TAG_NO_SUCH_INSTANCE, &[])), 7).is_err());
+        assert!(decode_response(&response(7, 2, tlv(TAG_NULL, &[])), 7).is_err());
+        assert!(decode_response(&response(8, 0, integer(1)), 7).unwrap_err().contains("another request"));
+        let full = response(7, 0, integer(342));
+        assert!(decode_response(&full[..full.len() - 2], 7).is_err());
+        assert!(decode_response(&[0x30, 0x84, 0xff], 7).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! SQLite storage of power samples (`energy.db`): one row per meter and
+//! poll with the draw and the watt-hours since the previous sample.
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// One bucket of a series.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeriesPoint {
+    /// Unix seconds, start of the bucket.
+    pub ts: i64,
+    pub avg_watts: f64,
+    pub max_watts: f64,
+    pub wh: f64,
+}
+
+pub struct EnergyStore {
+    conn: Mutex<Connection>,
+}
+
+impl EnergyStore {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS samples (
+                meter_id TEXT NOT NULL,
+                ts INTEGER NOT NULL,
+                watts REAL NOT NULL,
+                wh REAL NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_samples_meter_ts ON samples(meter_id, ts);
+            CREATE INDEX IF NOT EXISTS idx_samples_ts ON samples(ts);",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn insert(&self, meter_id: &str, ts: i64, watts: f64, wh: f64) -> anyhow::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.prepare_cached("INSERT INTO samples (meter_id, ts, watts, wh) VALUES (?1, ?2, ?3, ?4)")?
+            .execute(params![meter_id, ts, watts, wh])?;
+        Ok(())
+    }
+
+    /// Samples of `meter_id` in `[since, until)` grouped by `bucket_secs`.
+    pub fn series(&self, meter_id: &str, since: i64, until: i64, bucket_secs: i64) -> anyhow::Result<Vec<SeriesPoint>> {
+        let bucket_secs = bucket_secs.max(1);
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT (ts / ?2) * ?2 AS bucket, AVG(watts), MAX(watts), SUM(wh) FROM samples
+             WHERE meter_id = ?1 AND ts >= ?3 AND ts < ?4
+             GROUP BY bucket ORDER BY bucket",
+        )?;
+        let rows = stmt.query_map(params![meter_id, bucket_secs, since, until], |row| {
+            Ok(SeriesPoint {
+                ts: row.get(0)?,
+                avg_watts: row.get(1)?,
+                max_watts: row.get(2)?,
+                wh: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Watt-hours of each meter in `[since, until)`.
+    pub fn totals(&self, since: i64, until: i64) -> anyhow::Result<HashMap<String, f64>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT meter_id, SUM(wh) FROM samples WHERE ts >= ?1 AND ts < ?2 GROUP BY meter_id",
+        )?;
+        let rows = stmt.query_map(params![since, until], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Drop samples older than `before`; returns the count.
+    pub fn purge(&self, before: i64) -> anyhow::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM samples WHERE ts < ?1", params![before])?)
+    }
+
+    /// Drop the samples of a removed meter.
+    pub fn remove_meter(&self, meter_id: &str) -> anyhow::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM samples WHERE meter_id = ?1", params![meter_id])?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_series_and_totals() {
+        let store = EnergyStore::open_in_memory().unwrap();
+        for (ts, watts) in [(0, 100.0), (1800, 200.0), (3600, 50.0), (5400, 50.0)] {
+            store.insert("nas", ts, watts, watts / 2.0).unwrap();
+        }
+        store.insert("ups", 10, 300.0, 1.0).unwrap();
+
+        let series = store.series("nas", 0, 7200, 3600).unwrap();
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0], SeriesPoint { ts: 0, avg_watts: 150.0, max_watts: 200.0, wh: 150.0 });
+        assert_eq!(series[1].wh, 50.0);
+
+        let totals = store.totals(0, 3600).unwrap();
+        assert_eq!(totals["nas"], 150.0);
+        assert_eq!(totals["ups"], 1.0);
+
+        assert_eq!(store.purge(3600).unwrap(), 3);
+        assert_eq!(store.remove_meter("nas").unwrap(), 2);
+        assert!(store.totals(0, i64::MAX).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Offenses tolerated from one address within a sliding window.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Threshold {
+    pub max: u32,
+    pub window_secs: u64,
+}
+
+/// Ban manager configuration (firewall-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FirewallConfig {
+    /// Ban offending addresses automatically.
+    #[serde(default = "default_true")]
+    pub auto_ban: bool,
+    /// Duration of automatic bans.
+    #[serde(default = "default_ban_duration")]
+    pub ban_duration_secs: u64,
+    /// 401/403 responses sent by the proxy.
+    #[serde(default = "default_auth_failures")]
+    pub auth_failures: Threshold,
+    /// Failed TLS handshakes on the proxy listeners.
+    #[serde(default = "default_tls_failures")]
+    pub tls_failures: Threshold,
+    /// UDP DNS queries with an amplified answer (ANY, large responses).
+    #[serde(default = "default_dns_amplification")]
+    pub dns_amplification: Threshold,
+    /// Addresses/networks never banned automatically.
+    #[serde(default = "default_whitelist")]
+    pub whitelist: Vec<String>,
+    /// Also drop banned addresses in the kernel (nftables `inet homeroute_bans` table).
+    #[serde(default)]
+    pub nftables: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_ban_duration() -> u64 {
+    3600
+}
+
+fn default_auth_failures() -> Threshold {
+    Threshold { max: 20, window_secs: 60 }
+}
+
+fn default_tls_failures() -> Threshold {
+    Threshold { max: 30, window_secs: 60 }
+}
+
+fn default_dns_amplification() -> Threshold {
+    Threshold { max: 50, window_secs: 10 }
+}
+
+fn default_whitelist() -> Vec<String> {
+    ["127.0.0.0/8", "::1/128", "10.0.0.0/8", "172.16.0.0/12", "192.168.0.0/16", "fc00::/7"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+impl Default for FirewallConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+/// Parse a CIDR or a bare address (/32, /128).
+pub(crate) fn parse_net(entry: &str) -> Option<IpNet> {
+    let entry = entry.trim();
+    entry
+        .parse::<IpNet>()
+        .ok()
+        .or_else(|| entry.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+impl FirewallConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.ban_duration_secs < 60 {
+            return Err("durée de bannissement trop courte (minimum 60 secondes)".to_string());
+        }
+        for (name, t) in [
+            ("authentification", self.auth_failures),
+            ("TLS", self.tls_failures),
+            ("DNS", self.dns_amplification),
+        ] {
+            if t.max == 0 || !(1..=3600).contains(&t.window_secs) {
+                return Err(format!("seuil {} invalide", name));
+            }
+        }
+        if let Some(bad) = self.whitelist.iter().find(|e| parse_net(e).is_none()) {
+            return Err(format!("réseau invalide dans la liste blanche: {}", bad));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn whitelist_nets(&self) -> Vec<IpNet> {
+        self.whitelist.iter().filter_map(|e| parse_net(e)).collect()
+    }
+}
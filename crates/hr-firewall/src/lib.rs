@@ -0,0 +1,308 @@
+//! Ban manager: counts offenses per client address (proxy auth failures,
+//! TLS handshake failures, DNS amplification attempts) and bans addresses
+//! exceeding their threshold. Bans are enforced by the proxy accept loops
+//! and the DNS server, and optionally in the kernel with nftables.
+
+pub mod config;
+pub mod nft;
+pub mod service;
+
+pub use config::{FirewallConfig, Threshold};
+
+use chrono::{DateTime, Utc};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Notify};
+use tracing::{info, warn};
+
+/// Address/offense windows tracked at once; new ones are ignored past this.
+const MAX_TRACKED: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Offense {
+    AuthFailure,
+    TlsHandshake,
+    DnsAmplification,
+}
+
+impl Offense {
+    fn label(self) -> &'static str {
+        match self {
+            Offense::AuthFailure => "auth",
+            Offense::TlsHandshake => "tls",
+            Offense::DnsAmplification => "dns",
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Offense::AuthFailure => "échecs d'authentification",
+            Offense::TlsHandshake => "échecs de négociation TLS",
+            Offense::DnsAmplification => "requêtes DNS d'amplification",
+        }
+    }
+
+    fn threshold(self, config: &FirewallConfig) -> Threshold {
+        match self {
+            Offense::AuthFailure => config.auth_failures,
+            Offense::TlsHandshake => config.tls_failures,
+            Offense::DnsAmplification => config.dns_amplification,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BanSource {
+    Auto,
+    Manual,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ban {
+    pub ip: IpAddr,
+    pub reason: String,
+    pub source: BanSource,
+    pub created_at: DateTime<Utc>,
+    /// `None` for permanent bans.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Ban {
+    fn expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+/// Shared ban manager: fed by the proxy and DNS server, read by the accept
+/// loops, maintained by the supervised `firewall` service.
+pub struct BanManager {
+    config: watch::Sender<FirewallConfig>,
+    whitelist: RwLock<Vec<IpNet>>,
+    bans: RwLock<HashMap<IpAddr, Ban>>,
+    offenses: Mutex<HashMap<(IpAddr, Offense), VecDeque<Instant>>>,
+    bans_path: PathBuf,
+    /// Bans changed: persist and resync nftables.
+    pub(crate) changed: Notify,
+}
+
+impl BanManager {
+    /// Create the manager, restoring unexpired bans from `bans_path`.
+    pub fn new(config: FirewallConfig, bans_path: PathBuf) -> Self {
+        let bans = load_bans(&bans_path);
+        if !bans.is_empty() {
+            info!("Restored {} bans", bans.len());
+        }
+        Self {
+            whitelist: RwLock::new(config.whitelist_nets()),
+            config: watch::channel(config).0,
+            bans: RwLock::new(bans),
+            offenses: Mutex::new(HashMap::new()),
+            bans_path,
+            changed: Notify::new(),
+        }
+    }
+
+    /// Current configuration.
+    pub fn config(&self) -> FirewallConfig {
+        self.config.borrow().clone()
+    }
+
+    /// Replace the configuration; nftables rules are resynced.
+    pub fn apply(&self, config: FirewallConfig) {
+        *self.whitelist.write().unwrap() = config.whitelist_nets();
+        self.config.send_replace(config);
+        self.changed.notify_one();
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<FirewallConfig> {
+        self.config.subscribe()
+    }
+
+    /// Whether connections/queries from `ip` must be dropped.
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        let bans = self.bans.read().unwrap();
+        if bans.is_empty() {
+            return false;
+        }
+        bans.get(&ip.to_canonical()).is_some_and(|b| !b.expired(Utc::now()))
+    }
+
+    fn whitelisted(&self, ip: IpAddr) -> bool {
+        self.whitelist.read().unwrap().iter().any(|net| net.contains(&ip))
+    }
+
+    /// Count an offense; bans the address once its threshold is reached.
+    /// Returns true if this offense triggered a ban.
+    pub fn report(&self, ip: IpAddr, offense: Offense) -> bool {
+        let ip = ip.to_canonical();
+        let config = self.config.borrow().clone();
+        if !config.auto_ban || self.whitelisted(ip) || self.is_banned(ip) {
+            return false;
+        }
+        let threshold = offense.threshold(&config);
+        let window = Duration::from_secs(threshold.window_secs);
+        let now = Instant::now();
+        {
+            let mut offenses = self.offenses.lock().unwrap();
+            if offenses.len() >= MAX_TRACKED && !offenses.contains_key(&(ip, offense)) {
+                return false;
+            }
+            let hits = offenses.entry((ip, offense)).or_default();
+            while hits.front().is_some_and(|t| now.duration_since(*t) > window) {
+                hits.pop_front();
+            }
+            hits.push_back(now);
+            if hits.len() < threshold.max as usize {
+                return false;
+            }
+            offenses.remove(&(ip, offense));
+        }
+
+        let reason = format!("{} {} en {}s", threshold.max, offense.describe(), threshold.window_secs);
+        warn!("Banning {} for {}s: {}", ip, config.ban_duration_secs, reason);
+        hr_common::metrics::registry()
+            .counter("homeroute_bans_total", "Automatic bans by offense.", &[("offense", offense.label())])
+            .inc();
+        self.ban(ip, Some(Duration::from_secs(config.ban_duration_secs)), reason, BanSource::Auto);
+        true
+    }
+
+    /// Ban `ip` (permanently when `duration` is None), replacing any existing ban.
+    pub fn ban(&self, ip: IpAddr, duration: Option<Duration>, reason: String, source: BanSource) -> Ban {
+        let now = Utc::now();
+        let ban = Ban {
+            ip: ip.to_canonical(),
+            reason,
+            source,
+            created_at: now,
+            expires_at: duration.and_then(|d| chrono::Duration::from_std(d).ok()).map(|d| now + d),
+        };
+        self.bans.write().unwrap().insert(ban.ip, ban.clone());
+        self.changed.notify_one();
+        ban
+    }
+
+    /// Lift a ban. Returns false if the address was not banned.
+    pub fn unban(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        let removed = self.bans.write().unwrap().remove(&ip).is_some();
+        self.offenses.lock().unwrap().retain(|(addr, _), _| *addr != ip);
+        if removed {
+            info!("Unbanned {}", ip);
+            self.changed.notify_one();
+        }
+        removed
+    }
+
+    /// Active bans, most recent first.
+    pub fn list(&self) -> Vec<Ban> {
+        let now = Utc::now();
+        let mut bans: Vec<Ban> = self.bans.read().unwrap().values().filter(|b| !b.expired(now)).cloned().collect();
+        bans.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+        bans
+    }
+
+    /// Drop expired bans and stale offense windows. Returns true if a ban expired.
+    pub(crate) fn purge(&self) -> bool {
+        let now = Utc::now();
+        let expired = {
+            let mut bans = self.bans.write().unwrap();
+            let before = bans.len();
+            bans.retain(|_, b| !b.expired(now));
+            bans.len() != before
+        };
+        let config = self.config.borrow().clone();
+        let longest = [config.auth_failures, config.tls_failures, config.dns_amplification]
+            .iter()
+            .map(|t| t.window_secs)
+            .max()
+            .unwrap_or(60);
+        let instant = Instant::now();
+        self.offenses
+            .lock()
+            .unwrap()
+            .retain(|_, hits| hits.back().is_some_and(|t| instant.duration_since(*t).as_secs() <= longest));
+        expired
+    }
+
+    pub(crate) fn save(&self) -> anyhow::Result<()> {
+        let bans = self.list();
+        let content = serde_json::to_string_pretty(&bans)?;
+        let tmp_path = self.bans_path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, &self.bans_path)?;
+        Ok(())
+    }
+}
+
+fn load_bans(path: &Path) -> HashMap<IpAddr, Ban> {
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    let now = Utc::now();
+    match serde_json::from_str::<Vec<Ban>>(&content) {
+        Ok(bans) => bans.into_iter().filter(|b| !b.expired(now)).map(|b| (b.ip, b)).collect(),
+        Err(e) => {
+            warn!("Ignoring unreadable bans file {}: {}", path.display(), e);
+            HashMap::new()
+        }
+    }
+}
+
+pub type SharedBanManager = Arc<BanManager>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(max: u32) -> BanManager {
+        let config = FirewallConfig {
+            auth_failures: Threshold { max, window_secs: 60 },
+            ..Default::default()
+        };
+        BanManager::new(config, PathBuf::from("/nonexistent/bans.json"))
+    }
+
+    #[test]
+    fn test_auto_ban_after_threshold() {
+        let bans = manager(3);
+        let ip: IpAddr = "203.0.113.9".parse().unwrap();
+        assert!(!bans.report(ip, Offense::AuthFailure));
+        assert!(!bans.report(ip, Offense::TlsHandshake));
+        assert!(!bans.report(ip, Offense::AuthFailure));
+        assert!(!bans.is_banned(ip));
+        assert!(bans.report(ip, Offense::AuthFailure));
+        assert!(bans.is_banned(ip));
+        // Dual-stack listeners report IPv4 clients as mapped addresses
+        assert!(bans.is_banned("::ffff:203.0.113.9".parse().unwrap()));
+        assert_eq!(bans.list()[0].source, BanSource::Auto);
+
+        assert!(bans.unban(ip));
+        assert!(!bans.is_banned(ip));
+        assert!(!bans.unban(ip));
+    }
+
+    #[test]
+    fn test_whitelist_and_manual_ban() {
+        let bans = manager(1);
+        let lan: IpAddr = "192.168.1.50".parse().unwrap();
+        assert!(!bans.report(lan, Offense::AuthFailure));
+        assert!(!bans.is_banned(lan));
+
+        // Manual bans apply regardless of the whitelist
+        bans.ban(lan, None, "test".to_string(), BanSource::Manual);
+        assert!(bans.is_banned(lan));
+
+        let expired = bans.ban("198.51.100.1".parse().unwrap(), Some(Duration::ZERO), "test".to_string(), BanSource::Manual);
+        assert!(!bans.is_banned(expired.ip));
+        assert!(bans.purge());
+        assert_eq!(bans.list().len(), 1);
+    }
+}
@@ -0,0 +1,89 @@
+//! Kernel enforcement of bans in a dedicated nftables table, rewritten as
+//! a whole on every change (one atomic `nft -f` transaction).
+
+use std::fmt::Write;
+use std::process::Stdio;
+
+use anyhow::{bail, Context, Result};
+use tokio::io::AsyncWriteExt;
+
+use crate::Ban;
+
+pub const TABLE: &str = "homeroute_bans";
+
+/// Script replacing the ban table; without `bans` it only removes it.
+pub fn script(bans: Option<&[Ban]>) -> String {
+    // Declaring the table first makes the delete valid when it does not exist yet
+    let mut out = format!("table inet {TABLE}\ndelete table inet {TABLE}\n");
+    let Some(bans) = bans else {
+        return out;
+    };
+    let elements = |v4: bool| {
+        bans.iter()
+            .filter(|b| b.ip.is_ipv4() == v4)
+            .map(|b| b.ip.to_string())
+            .collect::<Vec<_>>()
+    };
+    let _ = writeln!(out, "table inet {TABLE} {{");
+    for (name, kind, ips) in [("banned4", "ipv4_addr", elements(true)), ("banned6", "ipv6_addr", elements(false))] {
+        let _ = write!(out, "  set {name} {{ type {kind};");
+        if !ips.is_empty() {
+            let _ = write!(out, " elements = {{ {} }};", ips.join(", "));
+        }
+        let _ = writeln!(out, " }}");
+    }
+    for hook in ["input", "forward"] {
+        let _ = writeln!(
+            out,
+            "  chain {hook} {{ type filter hook {hook} priority filter - 10; policy accept; \
+             ip saddr @banned4 drop; ip6 saddr @banned6 drop; }}"
+        );
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Run `nft -f -` with the given script.
+pub async fn apply(script: &str) -> Result<()> {
+    let mut child = tokio::process::Command::new("nft")
+        .args(["-f", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("nft introuvable")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        bail!("nft: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BanSource;
+
+    #[test]
+    fn test_script() {
+        let ban = |ip: &str| Ban {
+            ip: ip.parse().unwrap(),
+            reason: String::new(),
+            source: BanSource::Manual,
+            created_at: chrono::Utc::now(),
+            expires_at: None,
+        };
+        let text = script(Some(&[ban("203.0.113.9"), ban("198.51.100.1"), ban("2001:db8::1")]));
+        assert!(text.starts_with("table inet homeroute_bans\ndelete table inet homeroute_bans\n"));
+        assert!(text.contains("set banned4 { type ipv4_addr; elements = { 203.0.113.9, 198.51.100.1 }; }"));
+        assert!(text.contains("set banned6 { type ipv6_addr; elements = { 2001:db8::1 }; }"));
+        assert!(text.contains("chain forward { type filter hook forward"));
+
+        let empty = script(Some(&[]));
+        assert!(empty.contains("set banned4 { type ipv4_addr; }"));
+        assert_eq!(script(None).lines().count(), 2);
+    }
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::warn;
+
+use crate::config::FirewallConfig;
+use crate::{nft, BanManager};
+
+/// Interval between expiry checks.
+const PURGE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Expire bans, persist them and keep the nftables table in sync.
+pub async fn run_firewall(bans: Arc<BanManager>) -> Result<()> {
+    let config_rx = bans.subscribe();
+    let mut nft_active = false;
+    let mut tick = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        let changed = tokio::select! {
+            _ = tick.tick() => bans.purge(),
+            _ = bans.changed.notified() => true,
+        };
+        let config: FirewallConfig = config_rx.borrow().clone();
+        if !changed && config.nftables == nft_active {
+            continue;
+        }
+
+        let to_save = bans.clone();
+        match tokio::task::spawn_blocking(move || to_save.save()).await {
+            Ok(Err(e)) => warn!("Failed to save bans: {}", e),
+            Err(e) => warn!("Failed to save bans: {}", e),
+            Ok(Ok(())) => {}
+        }
+
+        if config.nftables || nft_active {
+            let list = bans.list();
+            let script = nft::script(config.nftables.then_some(list.as_slice()));
+            match nft::apply(&script).await {
+                Ok(()) => nft_active = config.nftables,
+                Err(e) => warn!("Failed to sync nftables bans: {:#}", e),
+            }
+        }
+    }
+}
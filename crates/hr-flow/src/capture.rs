@@ -0,0 +1,116 @@
+use std::io::Read;
+use std::os::fd::AsRawFd;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::io::unix::AsyncFd;
+use tracing::{info, warn};
+
+use crate::config::FlowConfig;
+use crate::table::FlowTable;
+use crate::FlowMonitor;
+
+/// Delay before reopening the capture socket after a failure.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+/// Interval between folds of flow counters into the usage totals.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Run the flow monitor, restarting capture whenever the configuration
+/// changes.
+pub async fn run_flow_monitor(monitor: Arc<FlowMonitor>) -> Result<()> {
+    let mut config_rx = monitor.subscribe();
+    loop {
+        let config = config_rx.borrow_and_update().clone();
+        tokio::select! {
+            _ = run_config(monitor.clone(), config) => {}
+            changed = config_rx.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Capture with one configuration until it is replaced (never returns on its own).
+async fn run_config(monitor: Arc<FlowMonitor>, config: FlowConfig) {
+    if !config.enabled {
+        monitor.set_status(false, None);
+        return std::future::pending().await;
+    }
+    loop {
+        if let Err(e) = capture(&monitor, &config).await {
+            warn!("Flow monitor on {} failed: {:#}", config.interface, e);
+            monitor.set_status(false, Some(format!("{:#}", e)));
+        }
+        tokio::time::sleep(RETRY_DELAY).await;
+    }
+}
+
+/// Packet socket receiving every IP packet entering or leaving `interface`,
+/// without link-layer header.
+fn open_socket(interface: &str) -> Result<Socket> {
+    let ifindex: i32 = std::fs::read_to_string(format!("/sys/class/net/{}/ifindex", interface))
+        .with_context(|| format!("interface {} introuvable", interface))?
+        .trim()
+        .parse()?;
+    let protocol = (libc::ETH_P_ALL as u16).to_be();
+    let socket = Socket::new(Domain::PACKET, Type::DGRAM, Some(Protocol::from(i32::from(protocol))))
+        .context("packet socket (CAP_NET_RAW required)")?;
+
+    // SO_BINDTODEVICE is ignored by packet sockets: bind a sockaddr_ll.
+    let addr = libc::sockaddr_ll {
+        sll_family: libc::AF_PACKET as u16,
+        sll_protocol: protocol,
+        sll_ifindex: ifindex,
+        sll_hatype: 0,
+        sll_pkttype: 0,
+        sll_halen: 0,
+        sll_addr: [0; 8],
+    };
+    // SAFETY: `addr` is a fully initialized sockaddr_ll that outlives the call.
+    let ret = unsafe {
+        libc::bind(
+            socket.as_raw_fd(),
+            &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error()).context(format!("bind {}", interface));
+    }
+    socket.set_nonblocking(true)?;
+    let _ = socket.set_recv_buffer_size(4 * 1024 * 1024);
+    Ok(socket)
+}
+
+async fn capture(monitor: &FlowMonitor, config: &FlowConfig) -> Result<()> {
+    let socket = AsyncFd::new(open_socket(&config.interface)?)?;
+    let retention = chrono::Duration::hours(config.retention_hours as i64);
+    let mut table = FlowTable::new(config.ports.clone());
+    let mut sweep = tokio::time::interval(SWEEP_INTERVAL);
+    let mut buf = vec![0u8; 65536];
+
+    info!("Flow monitor capturing on {} (ports {:?})", config.interface, config.ports);
+    monitor.set_status(true, None);
+
+    loop {
+        tokio::select! {
+            _ = sweep.tick() => {
+                monitor.record(table.sweep(Instant::now()), retention);
+                monitor.set_active_flows(table.len());
+            }
+            guard = socket.readable() => {
+                let mut guard = guard?;
+                while let Ok(result) = guard.try_io(|inner| inner.get_ref().read(&mut buf)) {
+                    let len = result?;
+                    monitor.packets.fetch_add(1, Ordering::Relaxed);
+                    table.process(&buf[..len], Instant::now());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Flow analytics configuration (flow-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlowConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// LAN interface whose forwarded traffic is observed (e.g. `br-lan`).
+    #[serde(default)]
+    pub interface: String,
+    /// TCP destination ports tracked (TLS ClientHello is parsed for SNI).
+    #[serde(default = "default_ports")]
+    pub ports: Vec<u16>,
+    /// Device/destination totals idle for longer than this are dropped.
+    #[serde(default = "default_retention")]
+    pub retention_hours: u64,
+}
+
+fn default_ports() -> Vec<u16> {
+    vec![443]
+}
+
+fn default_retention() -> u64 {
+    24
+}
+
+impl Default for FlowConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl FlowConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.enabled && self.interface.trim().is_empty() {
+            return Err("interface LAN requise".to_string());
+        }
+        if self.interface.contains('/') || self.interface.len() > 15 {
+            return Err(format!("interface invalide: {}", self.interface));
+        }
+        if self.ports.is_empty() || self.ports.contains(&0) {
+            return Err("ports invalides".to_string());
+        }
+        if !(1..=24 * 90).contains(&self.retention_hours) {
+            return Err("durée de rétention invalide (1 à 2160 heures)".to_string());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+//! Passive analytics of forwarded LAN traffic: TCP flows crossing the LAN
+//! interface are attributed to a device and a destination service, named
+//! after the SNI of their TLS ClientHello. Nothing is decrypted.
+
+pub mod capture;
+pub mod config;
+pub mod parse;
+pub mod table;
+
+pub use config::FlowConfig;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::watch;
+
+use crate::table::FlowUpdate;
+
+/// Device/destination pairs kept; the least recently active are dropped first.
+const MAX_USAGE_ENTRIES: usize = 20_000;
+/// Host names remembered per destination service.
+const MAX_HOSTS: usize = 8;
+
+/// Service a host name belongs to: its registrable domain, approximated as
+/// the last two labels (three under `co.uk`-style second levels).
+pub fn service_name(host: &str) -> String {
+    let labels: Vec<&str> = host.split('.').filter(|l| !l.is_empty()).collect();
+    let keep = match labels.as_slice() {
+        [.., second, tld] if tld.len() == 2 && matches!(*second, "co" | "com" | "net" | "org" | "gov" | "ac" | "edu") => 3,
+        _ => 2,
+    };
+    labels[labels.len().saturating_sub(keep)..].join(".")
+}
+
+#[derive(Debug, Clone)]
+struct Usage {
+    bytes_up: u64,
+    bytes_down: u64,
+    connections: u64,
+    hosts: BTreeSet<String>,
+    last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DestinationUsage {
+    /// Service domain, or the server address when no SNI was seen.
+    pub service: String,
+    pub hosts: Vec<String>,
+    pub bytes_up: u64,
+    pub bytes_down: u64,
+    pub connections: u64,
+    pub last_seen: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceUsage {
+    pub device: IpAddr,
+    pub bytes_up: u64,
+    pub bytes_down: u64,
+    pub connections: u64,
+    pub last_seen: String,
+    /// Sorted by total bytes, largest first.
+    pub destinations: Vec<DestinationUsage>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowStatus {
+    pub running: bool,
+    pub active_flows: usize,
+    /// Packets captured on the interface.
+    pub packets: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Shared flow analytics handle: the API pushes config and reads usage, the
+/// supervised `flow-monitor` service captures and aggregates.
+pub struct FlowMonitor {
+    config: watch::Sender<FlowConfig>,
+    status: RwLock<FlowStatus>,
+    usage: Mutex<HashMap<(IpAddr, String), Usage>>,
+    pub(crate) packets: AtomicU64,
+}
+
+impl FlowMonitor {
+    pub fn new(config: FlowConfig) -> Self {
+        Self {
+            config: watch::channel(config).0,
+            status: RwLock::new(FlowStatus::default()),
+            usage: Mutex::new(HashMap::new()),
+            packets: AtomicU64::new(0),
+        }
+    }
+
+    /// Current configuration.
+    pub fn config(&self) -> FlowConfig {
+        self.config.borrow().clone()
+    }
+
+    /// Replace the configuration; capture restarts on the new interface.
+    pub fn apply(&self, config: FlowConfig) {
+        self.config.send_replace(config);
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<FlowConfig> {
+        self.config.subscribe()
+    }
+
+    pub fn status(&self) -> FlowStatus {
+        let mut status = self.status.read().unwrap().clone();
+        status.packets = self.packets.load(Ordering::Relaxed);
+        status
+    }
+
+    pub(crate) fn set_status(&self, running: bool, error: Option<String>) {
+        let mut status = self.status.write().unwrap();
+        status.running = running;
+        status.last_error = error;
+        if !running {
+            status.active_flows = 0;
+        }
+    }
+
+    pub(crate) fn set_active_flows(&self, count: usize) {
+        self.status.write().unwrap().active_flows = count;
+    }
+
+    /// Fold flow traffic into the per device/destination totals and drop
+    /// totals idle for longer than the retention.
+    pub(crate) fn record(&self, updates: Vec<FlowUpdate>, retention: chrono::Duration) {
+        let now = Utc::now();
+        let mut usage = self.usage.lock().unwrap();
+        for update in updates {
+            let service = match &update.sni {
+                Some(host) => service_name(host),
+                None => update.server.to_string(),
+            };
+            let entry = usage.entry((update.device, service)).or_insert_with(|| Usage {
+                bytes_up: 0,
+                bytes_down: 0,
+                connections: 0,
+                hosts: BTreeSet::new(),
+                last_seen: now,
+            });
+            entry.bytes_up += update.bytes_up;
+            entry.bytes_down += update.bytes_down;
+            entry.connections += u64::from(update.new_connection);
+            entry.last_seen = now;
+            if let Some(host) = update.sni
+                && entry.hosts.len() < MAX_HOSTS
+            {
+                entry.hosts.insert(host);
+            }
+        }
+
+        usage.retain(|_, u| now - u.last_seen <= retention);
+        if usage.len() > MAX_USAGE_ENTRIES {
+            let mut by_age: Vec<_> = usage.iter().map(|(k, u)| (u.last_seen, k.clone())).collect();
+            by_age.sort();
+            for (_, key) in by_age.into_iter().take(usage.len() - MAX_USAGE_ENTRIES) {
+                usage.remove(&key);
+            }
+        }
+    }
+
+    /// Usage per device, most active first.
+    pub fn devices(&self) -> Vec<DeviceUsage> {
+        let usage = self.usage.lock().unwrap();
+        let mut devices: HashMap<IpAddr, DeviceUsage> = HashMap::new();
+        for ((device, service), u) in usage.iter() {
+            let entry = devices.entry(*device).or_insert_with(|| DeviceUsage {
+                device: *device,
+                bytes_up: 0,
+                bytes_down: 0,
+                connections: 0,
+                last_seen: String::new(),
+                destinations: Vec::new(),
+            });
+            entry.bytes_up += u.bytes_up;
+            entry.bytes_down += u.bytes_down;
+            entry.connections += u.connections;
+            let last_seen = u.last_seen.to_rfc3339();
+            if last_seen > entry.last_seen {
+                entry.last_seen = last_seen.clone();
+            }
+            entry.destinations.push(DestinationUsage {
+                service: service.clone(),
+                hosts: u.hosts.iter().cloned().collect(),
+                bytes_up: u.bytes_up,
+                bytes_down: u.bytes_down,
+                connections: u.connections,
+                last_seen,
+            });
+        }
+
+        let mut devices: Vec<DeviceUsage> = devices.into_values().collect();
+        for device in &mut devices {
+            device.destinations.sort_by_key(|d| std::cmp::Reverse(d.bytes_up + d.bytes_down));
+        }
+        devices.sort_by_key(|d| std::cmp::Reverse(d.bytes_up + d.bytes_down));
+        devices
+    }
+
+    /// Forget all totals.
+    pub fn reset(&self) {
+        self.usage.lock().unwrap().clear();
+    }
+}
+
+pub type SharedFlowMonitor = Arc<FlowMonitor>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(device: &str, sni: Option<&str>, up: u64, down: u64) -> FlowUpdate {
+        FlowUpdate {
+            device: device.parse().unwrap(),
+            server: "203.0.113.7".parse().unwrap(),
+            sni: sni.map(str::to_string),
+            bytes_up: up,
+            bytes_down: down,
+            new_connection: true,
+        }
+    }
+
+    #[test]
+    fn test_service_name() {
+        assert_eq!(service_name("rr3---sn-a5m7.googlevideo.com"), "googlevideo.com");
+        assert_eq!(service_name("www.bbc.co.uk"), "bbc.co.uk");
+        assert_eq!(service_name("example.fr"), "example.fr");
+        assert_eq!(service_name("localhost"), "localhost");
+    }
+
+    #[test]
+    fn test_usage_per_device_and_service() {
+        let monitor = FlowMonitor::new(FlowConfig::default());
+        monitor.record(
+            vec![
+                update("192.168.1.20", Some("a.nflxvideo.net"), 10, 5000),
+                update("192.168.1.20", Some("b.nflxvideo.net"), 10, 3000),
+                update("192.168.1.20", None, 1, 1),
+                update("192.168.1.30", Some("api.example.com"), 50, 50),
+            ],
+            chrono::Duration::hours(24),
+        );
+
+        let devices = monitor.devices();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].device, "192.168.1.20".parse::<IpAddr>().unwrap());
+        assert_eq!(devices[0].bytes_down, 8001);
+        assert_eq!(devices[0].connections, 3);
+        let top = &devices[0].destinations[0];
+        assert_eq!(top.service, "nflxvideo.net");
+        assert_eq!(top.hosts, vec!["a.nflxvideo.net", "b.nflxvideo.net"]);
+        assert_eq!(devices[0].destinations[1].service, "203.0.113.7");
+
+        // Totals idle for longer than the retention are dropped on the next fold
+        monitor.record(Vec::new(), chrono::Duration::seconds(-1));
+        assert!(monitor.devices().is_empty());
+    }
+}
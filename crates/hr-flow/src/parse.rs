@@ -0,0 +1,241 @@
+//! Just enough IPv4/IPv6, TCP and TLS parsing to attribute a packet to a
+//! flow and read the server name out of a ClientHello.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const PROTO_TCP: u8 = 6;
+
+pub const TCP_FIN: u8 = 0x01;
+pub const TCP_SYN: u8 = 0x02;
+pub const TCP_RST: u8 = 0x04;
+
+/// A TCP segment seen on the wire.
+#[derive(Debug)]
+pub struct Segment<'a> {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub seq: u32,
+    pub flags: u8,
+    pub payload: &'a [u8],
+    /// Size of the IP packet, counted as flow bytes.
+    pub ip_len: usize,
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+/// Parse a layer 3 packet (no link header). `None` for anything but TCP.
+pub fn parse_packet(data: &[u8]) -> Option<Segment<'_>> {
+    let (src, dst, ip_len, tcp_start) = match data.first()? >> 4 {
+        4 => {
+            let ihl = usize::from(data[0] & 0x0f) * 4;
+            let total = usize::from(u16_at(data, 2)?);
+            // Later fragments carry no TCP header
+            if ihl < 20 || data.len() < 20 || data[9] != PROTO_TCP || u16_at(data, 6)? & 0x1fff != 0 {
+                return None;
+            }
+            let src = Ipv4Addr::from(<[u8; 4]>::try_from(&data[12..16]).ok()?);
+            let dst = Ipv4Addr::from(<[u8; 4]>::try_from(&data[16..20]).ok()?);
+            (IpAddr::V4(src), IpAddr::V4(dst), total, ihl)
+        }
+        6 => {
+            // Extension headers are rare on TCP flows and not followed
+            if data.len() < 40 || data[6] != PROTO_TCP {
+                return None;
+            }
+            let src = Ipv6Addr::from(<[u8; 16]>::try_from(&data[8..24]).ok()?);
+            let dst = Ipv6Addr::from(<[u8; 16]>::try_from(&data[24..40]).ok()?);
+            (IpAddr::V6(src), IpAddr::V6(dst), 40 + usize::from(u16_at(data, 4)?), 40)
+        }
+        _ => return None,
+    };
+
+    let end = ip_len.min(data.len());
+    let tcp = data.get(tcp_start..end)?;
+    let offset = usize::from(*tcp.get(12)? >> 4) * 4;
+    if offset < 20 || tcp.len() < offset {
+        return None;
+    }
+    Some(Segment {
+        src,
+        dst,
+        src_port: u16_at(tcp, 0)?,
+        dst_port: u16_at(tcp, 2)?,
+        seq: u32::from_be_bytes(tcp[4..8].try_into().ok()?),
+        flags: tcp[13],
+        payload: &tcp[offset..],
+        ip_len,
+    })
+}
+
+/// Outcome of parsing the first bytes a client sent.
+#[derive(Debug, PartialEq)]
+pub enum Hello {
+    /// Looks like a ClientHello, more bytes are needed.
+    Incomplete,
+    /// Not a TLS handshake.
+    NotTls,
+    /// Complete ClientHello, with its server name if any.
+    Complete(Option<String>),
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn vec8(&mut self) -> Option<&'a [u8]> {
+        let n = self.u8()?;
+        self.take(usize::from(n))
+    }
+
+    fn vec16(&mut self) -> Option<&'a [u8]> {
+        let n = self.u16()?;
+        self.take(usize::from(n))
+    }
+}
+
+/// Read the SNI out of a TLS ClientHello (first record only; browsers never
+/// split the ClientHello across records, only across TCP segments).
+pub fn parse_client_hello(data: &[u8]) -> Hello {
+    if data.is_empty() {
+        return Hello::Incomplete;
+    }
+    if data[0] != 0x16 || data.get(1).is_some_and(|v| *v != 3) {
+        return Hello::NotTls;
+    }
+    if data.len() < 9 {
+        return Hello::Incomplete;
+    }
+    if data[5] != 1 {
+        return Hello::NotTls;
+    }
+    let len = usize::from(data[6]) << 16 | usize::from(data[7]) << 8 | usize::from(data[8]);
+    let Some(body) = data.get(9..9 + len) else {
+        return Hello::Incomplete;
+    };
+    Hello::Complete(server_name(body))
+}
+
+fn server_name(body: &[u8]) -> Option<String> {
+    let mut r = Reader(body);
+    r.take(2 + 32)?; // legacy_version, random
+    r.vec8()?; // session id
+    r.vec16()?; // cipher suites
+    r.vec8()?; // compression methods
+    let mut extensions = Reader(r.vec16()?);
+    while !extensions.0.is_empty() {
+        let kind = extensions.u16()?;
+        let data = extensions.vec16()?;
+        if kind != 0 {
+            continue;
+        }
+        let mut list = Reader(Reader(data).vec16()?);
+        while !list.0.is_empty() {
+            let name_type = list.u8()?;
+            let name = list.vec16()?;
+            if name_type == 0 {
+                let name = std::str::from_utf8(name).ok()?;
+                return Some(name.trim_end_matches('.').to_ascii_lowercase());
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Minimal TLS 1.2 record holding a ClientHello, optionally with SNI.
+    pub(crate) fn client_hello(sni: Option<&str>) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        // supported_groups, so SNI is not the first extension
+        extensions.extend_from_slice(&[0x00, 0x0a, 0x00, 0x04, 0x00, 0x02, 0x00, 0x1d]);
+        if let Some(name) = sni {
+            let name = name.as_bytes();
+            let mut list = vec![0u8];
+            list.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            list.extend_from_slice(name);
+            let mut ext = (list.len() as u16).to_be_bytes().to_vec();
+            ext.extend_from_slice(&list);
+            extensions.extend_from_slice(&[0x00, 0x00]);
+            extensions.extend_from_slice(&(ext.len() as u16).to_be_bytes());
+            extensions.extend_from_slice(&ext);
+        }
+
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[7u8; 32]);
+        body.push(0); // session id
+        body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]);
+        body.extend_from_slice(&[0x01, 0x00]);
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut handshake = vec![0x01, 0, (body.len() >> 8) as u8, body.len() as u8];
+        handshake.extend_from_slice(&body);
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    /// IPv4/TCP packet carrying `payload`.
+    pub(crate) fn tcp_packet(src: &str, dst: &str, ports: (u16, u16), seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let src: Ipv4Addr = src.parse().unwrap();
+        let dst: Ipv4Addr = dst.parse().unwrap();
+        let total = 40 + payload.len();
+        let mut p = vec![0x45, 0, (total >> 8) as u8, total as u8, 0, 0, 0x40, 0, 64, PROTO_TCP, 0, 0];
+        p.extend_from_slice(&src.octets());
+        p.extend_from_slice(&dst.octets());
+        p.extend_from_slice(&ports.0.to_be_bytes());
+        p.extend_from_slice(&ports.1.to_be_bytes());
+        p.extend_from_slice(&seq.to_be_bytes());
+        p.extend_from_slice(&[0, 0, 0, 0, 0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        p.extend_from_slice(payload);
+        p
+    }
+
+    #[test]
+    fn test_parse_sni() {
+        let hello = client_hello(Some("Video.Example.COM"));
+        assert_eq!(parse_client_hello(&hello), Hello::Complete(Some("video.example.com".to_string())));
+        assert_eq!(parse_client_hello(&client_hello(None)), Hello::Complete(None));
+        // Split across segments
+        assert_eq!(parse_client_hello(&hello[..40]), Hello::Incomplete);
+        assert_eq!(parse_client_hello(b"GET / HTTP/1.1\r\n"), Hello::NotTls);
+    }
+
+    #[test]
+    fn test_parse_tcp_packet() {
+        let packet = tcp_packet("192.168.1.20", "93.184.216.34", (51000, 443), 1000, 0x18, b"hello");
+        let segment = parse_packet(&packet).unwrap();
+        assert_eq!(segment.src, "192.168.1.20".parse::<IpAddr>().unwrap());
+        assert_eq!(segment.dst_port, 443);
+        assert_eq!(segment.seq, 1000);
+        assert_eq!(segment.payload, b"hello");
+        assert_eq!(segment.ip_len, 45);
+
+        let mut udp = packet.clone();
+        udp[9] = 17;
+        assert!(parse_packet(&udp).is_none());
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use crate::parse::{self, Hello, Segment};
+
+/// Flows tracked at once; new connections past this are ignored.
+const MAX_FLOWS: usize = 65536;
+/// ClientHello bytes buffered before giving up on SNI.
+const MAX_HELLO: usize = 16 * 1024;
+/// Time allowed for the ClientHello after the first packet.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(5);
+/// Flows without traffic for this long are forgotten.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// (device, device port, server, server port)
+type FlowKey = (IpAddr, u16, IpAddr, u16);
+
+enum HelloState {
+    Pending { buf: Vec<u8>, next_seq: Option<u32> },
+    Done(Option<String>),
+}
+
+struct Flow {
+    hello: HelloState,
+    bytes_up: u64,
+    bytes_down: u64,
+    /// Not yet counted as a connection.
+    new: bool,
+    closed: bool,
+    first_seen: Instant,
+    last_seen: Instant,
+}
+
+/// Traffic of one flow since the previous sweep.
+#[derive(Debug, PartialEq)]
+pub struct FlowUpdate {
+    pub device: IpAddr,
+    pub server: IpAddr,
+    pub sni: Option<String>,
+    pub bytes_up: u64,
+    pub bytes_down: u64,
+    pub new_connection: bool,
+}
+
+/// Whether `ip` can be the remote end of a forwarded Internet flow
+/// (connections to the router itself or between LAN hosts are skipped).
+fn is_remote(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_multicast()
+                || (v4.octets()[0] == 100 && v4.octets()[1] & 0xc0 == 64))
+        }
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+/// Live TCP flows on the observed interface, keyed from the device's side.
+pub struct FlowTable {
+    ports: Vec<u16>,
+    flows: HashMap<FlowKey, Flow>,
+}
+
+impl FlowTable {
+    pub fn new(ports: Vec<u16>) -> Self {
+        Self { ports, flows: HashMap::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    /// Account one captured packet.
+    pub fn process(&mut self, packet: &[u8], now: Instant) {
+        let Some(segment) = parse::parse_packet(packet) else {
+            return;
+        };
+        if self.ports.contains(&segment.dst_port) && is_remote(segment.dst) {
+            let key = (segment.src, segment.src_port, segment.dst, segment.dst_port);
+            if !self.flows.contains_key(&key) && self.flows.len() >= MAX_FLOWS {
+                return;
+            }
+            let flow = self.flows.entry(key).or_insert_with(|| Flow {
+                hello: HelloState::Pending { buf: Vec::new(), next_seq: None },
+                bytes_up: 0,
+                bytes_down: 0,
+                new: true,
+                closed: false,
+                first_seen: now,
+                last_seen: now,
+            });
+            flow.bytes_up += segment.ip_len as u64;
+            flow.observe(&segment, now);
+            flow.read_hello(&segment);
+        } else if self.ports.contains(&segment.src_port) && is_remote(segment.src) {
+            let key = (segment.dst, segment.dst_port, segment.src, segment.src_port);
+            if let Some(flow) = self.flows.get_mut(&key) {
+                flow.bytes_down += segment.ip_len as u64;
+                flow.observe(&segment, now);
+            }
+        }
+    }
+
+    /// Collect traffic since the last sweep for flows whose destination is
+    /// known, and forget closed or idle flows.
+    pub fn sweep(&mut self, now: Instant) -> Vec<FlowUpdate> {
+        let mut updates = Vec::new();
+        self.flows.retain(|(device, _, server, _), flow| {
+            let finished = flow.closed || now.duration_since(flow.last_seen) > IDLE_TIMEOUT;
+            if let HelloState::Pending { .. } = flow.hello
+                && (finished || now.duration_since(flow.first_seen) > HELLO_TIMEOUT)
+            {
+                flow.hello = HelloState::Done(None);
+            }
+            if let HelloState::Done(sni) = &flow.hello
+                && (flow.new || flow.bytes_up > 0 || flow.bytes_down > 0)
+            {
+                updates.push(FlowUpdate {
+                    device: *device,
+                    server: *server,
+                    sni: sni.clone(),
+                    bytes_up: std::mem::take(&mut flow.bytes_up),
+                    bytes_down: std::mem::take(&mut flow.bytes_down),
+                    new_connection: std::mem::take(&mut flow.new),
+                });
+            }
+            !finished
+        });
+        updates
+    }
+}
+
+impl Flow {
+    fn observe(&mut self, segment: &Segment<'_>, now: Instant) {
+        self.last_seen = now;
+        if segment.flags & (parse::TCP_FIN | parse::TCP_RST) != 0 {
+            self.closed = true;
+        }
+    }
+
+    /// Reassemble the client's first bytes (in-order segments only) until
+    /// the ClientHello can be parsed.
+    fn read_hello(&mut self, segment: &Segment<'_>) {
+        let HelloState::Pending { buf, next_seq } = &mut self.hello else {
+            return;
+        };
+        if segment.payload.is_empty() {
+            return;
+        }
+        if next_seq.is_some_and(|seq| seq != segment.seq) {
+            return;
+        }
+        buf.extend_from_slice(segment.payload);
+        *next_seq = Some(segment.seq.wrapping_add(segment.payload.len() as u32));
+        match parse::parse_client_hello(buf) {
+            Hello::Incomplete if buf.len() < MAX_HELLO => {}
+            Hello::Complete(sni) => self.hello = HelloState::Done(sni),
+            Hello::Incomplete | Hello::NotTls => self.hello = HelloState::Done(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::{client_hello, tcp_packet};
+
+    #[test]
+    fn test_sni_split_across_segments() {
+        let mut table = FlowTable::new(vec![443]);
+        let now = Instant::now();
+        let hello = client_hello(Some("cdn.example.net"));
+        let (a, b) = hello.split_at(30);
+
+        table.process(&tcp_packet("192.168.1.20", "203.0.113.7", (50000, 443), 99, parse::TCP_SYN, &[]), now);
+        table.process(&tcp_packet("203.0.113.7", "192.168.1.20", (443, 50000), 7, 0x12, &[]), now);
+        table.process(&tcp_packet("192.168.1.20", "203.0.113.7", (50000, 443), 100, 0x18, a), now);
+        // Destination unknown yet: nothing reported
+        assert!(table.sweep(now).is_empty());
+
+        table.process(&tcp_packet("192.168.1.20", "203.0.113.7", (50000, 443), 130, 0x18, b), now);
+        table.process(&tcp_packet("203.0.113.7", "192.168.1.20", (443, 50000), 8, 0x18, &[0u8; 1000]), now);
+        let updates = table.sweep(now);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].sni.as_deref(), Some("cdn.example.net"));
+        assert_eq!(updates[0].bytes_up, 40 + 40 + 30 + 40 + (hello.len() as u64 - 30));
+        assert_eq!(updates[0].bytes_down, 40 + 1040);
+        assert!(updates[0].new_connection);
+
+        // Counters restart after each sweep
+        table.process(&tcp_packet("203.0.113.7", "192.168.1.20", (443, 50000), 1008, parse::TCP_FIN, &[]), now);
+        let updates = table.sweep(now);
+        assert_eq!(updates[0].bytes_down, 40);
+        assert!(!updates[0].new_connection);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn test_ignores_local_and_untracked_traffic() {
+        let mut table = FlowTable::new(vec![443]);
+        let now = Instant::now();
+        // Device to the router's own reverse proxy
+        table.process(&tcp_packet("192.168.1.20", "192.168.1.1", (50000, 443), 1, parse::TCP_SYN, &[]), now);
+        // Not a tracked port
+        table.process(&tcp_packet("192.168.1.20", "203.0.113.7", (50001, 80), 1, parse::TCP_SYN, &[]), now);
+        assert!(table.is_empty());
+
+        // No ClientHello within the timeout: reported by server address
+        table.process(&tcp_packet("192.168.1.20", "203.0.113.7", (50002, 443), 1, parse::TCP_SYN, &[]), now);
+        assert!(table.sweep(now).is_empty());
+        let updates = table.sweep(now + HELLO_TIMEOUT + Duration::from_secs(1));
+        assert_eq!(updates[0].sni, None);
+        assert_eq!(updates[0].server, "203.0.113.7".parse::<IpAddr>().unwrap());
+    }
+}
@@ -1,10 +1,18 @@
 use serde::Deserialize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
+    /// File the config was loaded from.
+    #[serde(skip)]
+    pub path: PathBuf,
     pub homeroute_url: String,
+    /// Empty until the agent enrolls with its `join_code`.
+    #[serde(default)]
     pub token: String,
+    /// One-time code to enroll with, removed once the token is saved.
+    #[serde(default)]
+    pub join_code: Option<String>,
     pub host_name: String,
     #[serde(default = "default_reconnect")]
     pub reconnect_interval_secs: u64,
@@ -17,21 +25,97 @@ pub struct Config {
     /// Container runtime: "lxd" (default) or "nspawn".
     #[serde(default)]
     pub container_runtime: Option<String>,
+    /// Public key (hex) the update manifests must be signed with. Without
+    /// it, updates pushed by HomeRoute are refused.
+    #[serde(default)]
+    pub update_key: Option<String>,
 }
 
 fn default_reconnect() -> u64 {
     5
 }
 
+/// `content` with its `key` line set to `value` (added when missing), or
+/// removed when `value` is None.
+fn set_key(content: &str, key: &str, value: Option<&str>) -> String {
+    let line = value.map(|v| format!("{key} = \"{v}\""));
+    let mut replaced = false;
+    let mut lines: Vec<String> = content
+        .lines()
+        .filter_map(|l| {
+            if !replaced && l.split('=').next().map(str::trim) == Some(key) {
+                replaced = true;
+                line.clone()
+            } else {
+                Some(l.to_string())
+            }
+        })
+        .collect();
+    if let Some(line) = line
+        && !replaced
+    {
+        lines.insert(0, line);
+    }
+    lines.join("\n") + "\n"
+}
+
+fn restrict_permissions(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("Failed to set config permissions: {}", e))
+}
+
 impl Config {
     pub fn load(path: &PathBuf) -> Result<Self, String> {
         let content = std::fs::read_to_string(path)
             .map_err(|e| format!("Failed to read config {}: {}", path.display(), e))?;
-        toml::from_str(&content)
-            .map_err(|e| format!("Failed to parse config: {}", e))
+        let mut config: Self = toml::from_str(&content)
+            .map_err(|e| format!("Failed to parse config: {}", e))?;
+        config.path = path.clone();
+        Ok(config)
+    }
+
+    /// Write a rotated token to the config file, used from the next connection.
+    pub fn save_token(&self, token: &str) -> Result<(), String> {
+        self.save(&[("token", Some(token))])
+    }
+
+    /// Write the credentials received when enrolling, in place of the join code.
+    pub fn save_enrollment(&self, token: &str, host_name: &str) -> Result<(), String> {
+        self.save(&[("token", Some(token)), ("host_name", Some(host_name)), ("join_code", None)])
+    }
+
+    fn save(&self, keys: &[(&str, Option<&str>)]) -> Result<(), String> {
+        let content = std::fs::read_to_string(&self.path)
+            .map_err(|e| format!("Failed to read config {}: {}", self.path.display(), e))?;
+        let updated = keys.iter().fold(content, |content, (key, value)| set_key(&content, key, *value));
+        let tmp = self.path.with_extension("toml.tmp");
+        std::fs::write(&tmp, updated).map_err(|e| format!("Failed to write config: {}", e))?;
+        restrict_permissions(&tmp)?;
+        std::fs::rename(&tmp, &self.path).map_err(|e| format!("Failed to replace config: {}", e))
     }
 
     pub fn ws_url(&self) -> String {
         format!("ws://{}/api/hosts/agent/ws", self.homeroute_url)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_key() {
+        let content = "homeroute_url = \"10.0.0.254:4000\"\ntoken = \"\"\nhost_name = \"h1\"\n";
+        let updated = set_key(content, "token", Some("abc"));
+        assert_eq!(updated, "homeroute_url = \"10.0.0.254:4000\"\ntoken = \"abc\"\nhost_name = \"h1\"\n");
+        assert_eq!(set_key("host_name = \"h1\"", "token", Some("abc")), "token = \"abc\"\nhost_name = \"h1\"\n");
+    }
+
+    #[test]
+    fn test_set_key_remove() {
+        let content = "join_code = \"ABCD-EFGH-JKMN\"\nhost_name = \"h1\"\n";
+        assert_eq!(set_key(content, "join_code", None), "host_name = \"h1\"\n");
+        assert_eq!(set_key(content, "token", None), content);
+    }
+}
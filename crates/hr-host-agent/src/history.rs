@@ -0,0 +1,176 @@
+//! Metric samples kept on disk, so the registry gets the ones taken while
+//! the agent was disconnected (`MetricsBackfill`) and its series have no
+//! gap. The buffer holds the last `CAPACITY` samples; the file is appended
+//! to and rewritten from memory once it holds twice that.
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use hr_registry::protocol::MetricsSample;
+use tracing::warn;
+
+pub const HISTORY_FILE: &str = "/var/lib/hr-host-agent/metrics-history.jsonl";
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+/// One day of samples.
+const CAPACITY: usize = 2880;
+/// Samples per `MetricsBackfill` message.
+pub const BACKFILL_BATCH: usize = 500;
+
+pub struct MetricsHistory {
+    path: PathBuf,
+    capacity: usize,
+    samples: VecDeque<MetricsSample>,
+    lines_on_disk: usize,
+    /// Samples up to this time reached the registry.
+    synced_until: i64,
+    /// Live metrics are flowing: new samples need no backfill.
+    connected: bool,
+}
+
+impl MetricsHistory {
+    /// Load the buffer of `path`, empty when missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        Self::with_capacity(path, CAPACITY)
+    }
+
+    fn with_capacity(path: &Path, capacity: usize) -> Self {
+        let mut samples = VecDeque::new();
+        let mut lines_on_disk = 0;
+        if let Ok(content) = std::fs::read_to_string(path) {
+            for line in content.lines() {
+                lines_on_disk += 1;
+                if let Ok(sample) = serde_json::from_str::<MetricsSample>(line) {
+                    if samples.len() == capacity {
+                        samples.pop_front();
+                    }
+                    samples.push_back(sample);
+                }
+            }
+        }
+        let synced_until = std::fs::read_to_string(synced_path(path))
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(0);
+        Self { path: path.to_path_buf(), capacity, samples, lines_on_disk, synced_until, connected: false }
+    }
+
+    /// Buffer a sample; while connected it is already synced.
+    pub fn push(&mut self, sample: MetricsSample) {
+        let ts = sample.ts;
+        if let Err(e) = self.append(&sample) {
+            warn!("Failed to write metrics history: {}", e);
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        if self.connected {
+            self.mark_synced(ts);
+        }
+    }
+
+    /// Samples the registry has not seen, oldest first.
+    pub fn pending(&self) -> Vec<MetricsSample> {
+        self.samples.iter().filter(|s| s.ts > self.synced_until).cloned().collect()
+    }
+
+    pub fn mark_synced(&mut self, ts: i64) {
+        self.synced_until = self.synced_until.max(ts);
+        if let Err(e) = std::fs::write(synced_path(&self.path), self.synced_until.to_string()) {
+            warn!("Failed to write metrics history state: {}", e);
+        }
+    }
+
+    pub fn set_connected(&mut self, connected: bool) {
+        self.connected = connected;
+    }
+
+    fn append(&mut self, sample: &MetricsSample) -> std::io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        if self.lines_on_disk >= 2 * self.capacity {
+            return self.rewrite(sample);
+        }
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(sample)?)?;
+        self.lines_on_disk += 1;
+        Ok(())
+    }
+
+    /// Replace the file with the buffered samples and `sample`.
+    fn rewrite(&mut self, sample: &MetricsSample) -> std::io::Result<()> {
+        let skip = (self.samples.len() + 1).saturating_sub(self.capacity);
+        let mut content = String::new();
+        for s in self.samples.iter().skip(skip).chain(std::iter::once(sample)) {
+            content.push_str(&serde_json::to_string(s)?);
+            content.push('\n');
+        }
+        let tmp = self.path.with_extension("jsonl.tmp");
+        std::fs::write(&tmp, content)?;
+        std::fs::rename(&tmp, &self.path)?;
+        self.lines_on_disk = self.samples.len() + 1 - skip;
+        Ok(())
+    }
+}
+
+fn synced_path(path: &Path) -> PathBuf {
+    path.with_extension("synced")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hr_registry::protocol::HostMetrics;
+
+    fn sample(ts: i64) -> MetricsSample {
+        MetricsSample {
+            ts,
+            metrics: HostMetrics {
+                cpu_percent: ts as f32,
+                memory_used_bytes: 0,
+                memory_total_bytes: 0,
+                disk_used_bytes: 0,
+                disk_total_bytes: 0,
+                load_avg: [0.0; 3],
+                ..Default::default()
+            },
+        }
+    }
+
+    fn timestamps(samples: &[MetricsSample]) -> Vec<i64> {
+        samples.iter().map(|s| s.ts).collect()
+    }
+
+    #[test]
+    fn test_backfill_after_disconnection() {
+        let dir = std::env::temp_dir().join(format!("hr-history-{}", std::process::id()));
+        let path = dir.join("history.jsonl");
+
+        let mut history = MetricsHistory::with_capacity(&path, 4);
+        history.set_connected(true);
+        history.push(sample(1));
+        history.set_connected(false);
+        history.push(sample(2));
+        history.push(sample(3));
+        assert_eq!(timestamps(&history.pending()), [2, 3]);
+
+        // Survives a restart of the agent, ring capacity included
+        let mut history = MetricsHistory::with_capacity(&path, 4);
+        for ts in 4..=12 {
+            history.push(sample(ts));
+        }
+        assert_eq!(timestamps(&history.pending()), [9, 10, 11, 12]);
+        assert!(history.lines_on_disk <= 8);
+        let reloaded = MetricsHistory::with_capacity(&path, 4);
+        assert_eq!(timestamps(&reloaded.pending()), [9, 10, 11, 12]);
+
+        history.mark_synced(12);
+        assert!(history.pending().is_empty());
+        assert!(MetricsHistory::with_capacity(&path, 4).pending().is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
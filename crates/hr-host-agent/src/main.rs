@@ -1,17 +1,40 @@
 use futures_util::{SinkExt, StreamExt};
-use hr_registry::protocol::{AutoOffMode, HostAgentMessage, HostMetrics, HostRegistryMessage};
+use hr_registry::codec;
+use hr_registry::logs::{LogSource, LogTasks};
+use hr_registry::protocol::{
+    capability, capability_list, AutoOffMode, HostAgentMessage, HostMetrics, HostRegistryMessage, MetricsSample,
+    RuntimeAction, HOST_AGENT_CAPABILITIES, PROTOCOL_VERSION,
+};
+use hr_registry::transfer::{ChunkOrder, ChunkReader, Compression, Counted, ResumePoint, StreamManifest, TransferStream};
+use hr_registry::update::{SignedManifest, UpdateManifest};
+use hr_container::pty::{self, Pty, WindowSize};
 use std::collections::HashMap;
+use std::sync::Arc;
 use tokio_tungstenite::{connect_async, tungstenite::Message};
 use tracing::{error, info, warn};
 
-/// Outgoing WebSocket message: either a JSON text message or raw binary data.
+/// Outgoing WebSocket message: a control message, or a transfer chunk with
+/// its data. Encoded as JSON text (then raw binary), or as one MessagePack
+/// frame when the registry agreed on `binary_frames`.
 enum OutgoingWsMessage {
     Text(HostAgentMessage),
-    Binary(Vec<u8>),
+    Chunk(HostAgentMessage, Vec<u8>),
 }
 
+/// Tarballs of the templates the registry asked containers to be built from.
+const TEMPLATE_CACHE_DIR: &str = "/var/cache/homeroute/templates";
+
 mod config;
+mod history;
+mod packages;
+mod runtime;
+mod sensors;
+mod services;
+mod smart;
+mod transfer;
 use config::Config;
+use history::MetricsHistory;
+use transfer::{ExportJob, ImportPhase, NspawnImport, Transfers};
 
 #[tokio::main]
 async fn main() {
@@ -26,7 +49,8 @@ async fn main() {
         .nth(1)
         .unwrap_or_else(|| "/etc/hr-host-agent/config.toml".to_string());
 
-    let config = match Config::load(&std::path::PathBuf::from(&config_path)) {
+    let config_path = std::path::PathBuf::from(&config_path);
+    let mut config = match Config::load(&config_path) {
         Ok(c) => c,
         Err(e) => {
             eprintln!("Configuration error: {}", e);
@@ -42,8 +66,31 @@ async fn main() {
 
     let mut backoff = config.reconnect_interval_secs;
 
+    // Outgoing messages (control messages and transfer chunks), kept across
+    // reconnections so transfers carry on once the link is back
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<OutgoingWsMessage>(512);
+    let mut transfers = Transfers::default();
+
+    // Metrics history, sampled whether connected or not
+    let history = Arc::new(std::sync::Mutex::new(MetricsHistory::load(std::path::Path::new(history::HISTORY_FILE))));
+    let sampler_history = Arc::clone(&history);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(history::SAMPLE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let ts = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            let sample = MetricsSample { ts, metrics: collect_metrics() };
+            sampler_history.lock().unwrap().push(sample);
+        }
+    });
+
     loop {
-        match run_connection(&config).await {
+        let result = run_connection(&config, &tx, &mut rx, &mut transfers, &history).await;
+        history.lock().unwrap().set_connected(false);
+        match result {
             Ok(()) => {
                 info!("Connection closed normally");
                 backoff = config.reconnect_interval_secs;
@@ -55,10 +102,27 @@ async fn main() {
         }
         info!(secs = backoff, "Reconnecting...");
         tokio::time::sleep(std::time::Duration::from_secs(backoff)).await;
+        // Pick up a token rotated during the last connection
+        match Config::load(&config_path) {
+            Ok(c) => config = c,
+            Err(e) => warn!("Keeping the current config: {}", e),
+        }
     }
 }
 
-async fn run_connection(config: &Config) -> Result<(), String> {
+async fn run_connection(
+    config: &Config,
+    tx: &tokio::sync::mpsc::Sender<OutgoingWsMessage>,
+    rx: &mut tokio::sync::mpsc::Receiver<OutgoingWsMessage>,
+    transfers: &mut Transfers,
+    history: &std::sync::Mutex<MetricsHistory>,
+) -> Result<(), String> {
+    if config.token.is_empty()
+        && let Some(join_code) = &config.join_code
+    {
+        return enroll(config, join_code).await;
+    }
+
     let url = config.ws_url();
     info!(url, "Connecting to HomeRoute");
 
@@ -79,6 +143,9 @@ async fn run_connection(config: &Config) -> Result<(), String> {
         version: env!("CARGO_PKG_VERSION").to_string(),
         lan_interface: config.lan_interface.clone(),
         container_storage_path: config.container_storage_path.clone(),
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: capability_list(HOST_AGENT_CAPABILITIES),
+        arch: Some(hr_registry::binaries::local_arch().to_string()),
     };
     let auth_json = serde_json::to_string(&auth).map_err(|e| e.to_string())?;
     tokio::time::timeout(
@@ -96,19 +163,27 @@ async fn run_connection(config: &Config) -> Result<(), String> {
         .ok_or("Connection closed during auth")?
         .map_err(|e| format!("WebSocket error: {}", e))?;
 
-    match auth_response {
+    let (registry_protocol, registry_capabilities) = match auth_response {
         Message::Text(text) => {
             let msg: HostRegistryMessage =
                 serde_json::from_str(&text).map_err(|e| format!("Parse auth response: {}", e))?;
             match msg {
                 HostRegistryMessage::AuthResult {
-                    success: true, ..
+                    success: true,
+                    protocol_version,
+                    capabilities,
+                    ..
                 } => {
-                    info!("Authenticated successfully");
+                    info!(protocol_version, ?capabilities, "Authenticated successfully");
+                    if protocol_version < PROTOCOL_VERSION {
+                        warn!(protocol_version, "Registry speaks an older protocol, newer messages will be ignored");
+                    }
+                    (protocol_version, capabilities)
                 }
                 HostRegistryMessage::AuthResult {
                     success: false,
                     error,
+                    ..
                 } => {
                     return Err(format!("Auth failed: {}", error.unwrap_or_default()));
                 }
@@ -116,52 +191,77 @@ async fn run_connection(config: &Config) -> Result<(), String> {
             }
         }
         _ => return Err("Unexpected message type during auth".to_string()),
+    };
+    let binary_frames = registry_capabilities.iter().any(|c| c == capability::BINARY_FRAMES);
+    let resumable = registry_capabilities.iter().any(|c| c == capability::RESUMABLE_TRANSFERS);
+
+    // Imports interrupted by the previous disconnection: ask their sender to
+    // replay from the last chunk written
+    if resumable {
+        for (transfer_id, import) in &mut transfers.imports {
+            let point = import.resume_point();
+            info!(transfer_id = %transfer_id, ?point, "Resuming interrupted nspawn import");
+            import.resume_requested = true;
+            let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::ResumeTransfer {
+                transfer_id: transfer_id.clone(),
+                point,
+            })).await;
+        }
     }
 
-    // Channel for outgoing messages (Text JSON or Binary frames)
-    let (tx, mut rx) = tokio::sync::mpsc::channel::<OutgoingWsMessage>(512);
-
-    // Import phase state machine
-    #[derive(Debug, Clone, Copy, PartialEq)]
-    enum ImportPhase {
-        ReceivingContainer,
-        ReceivingWorkspace,
-    }
-
-    // Track active nspawn imports
-    struct ActiveNspawnImport {
-        container_name: String,
-        storage_path: String,
-        tar_child: tokio::process::Child,
-        tar_stdin: tokio::process::ChildStdin,
-        phase: ImportPhase,
-        ws_tar_child: Option<tokio::process::Child>,
-        ws_tar_stdin: Option<tokio::process::ChildStdin>,
-        network_mode: String,
+    // Samples taken while disconnected, for registries keeping history.
+    // Marked synced once queued: a drop right now loses them.
+    let backfill = {
+        let mut history = history.lock().unwrap();
+        history.set_connected(true);
+        let pending = history.pending();
+        if let Some(last) = pending.last() {
+            history.mark_synced(last.ts);
+        }
+        pending
+    };
+    if registry_capabilities.iter().any(|c| c == capability::METRICS_BACKFILL) && !backfill.is_empty() {
+        info!(samples = backfill.len(), "Sending metrics backfill");
+        for batch in backfill.chunks(history::BACKFILL_BATCH) {
+            let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::MetricsBackfill(batch.to_vec()))).await;
+        }
     }
-    let mut active_nspawn_imports: HashMap<String, ActiveNspawnImport> = HashMap::new();
 
+    let resource_limits = registry_capabilities.iter().any(|c| c == capability::RESOURCE_LIMITS);
+    let disk_health = registry_capabilities.iter().any(|c| c == capability::DISK_HEALTH);
+    let package_updates = registry_capabilities.iter().any(|c| c == capability::PACKAGE_UPDATES);
+    // Docker/Podman installed next to nspawn, for registries listing them
+    let runtimes = if registry_capabilities.iter().any(|c| c == capability::CONTAINER_RUNTIMES) {
+        runtime::detect().await
+    } else {
+        Vec::new()
+    };
     // Read nspawn storage path from config
-    let _nspawn_storage_path = config.container_storage_path.clone()
+    let nspawn_storage_path = config.container_storage_path.clone()
         .unwrap_or_else(|| "/var/lib/machines".to_string());
 
-    // Pending binary chunk metadata (from ReceiveChunkBinary, awaiting next Binary frame)
-    let mut pending_binary_chunk: Option<(String, u32)> = None; // (transfer_id, checksum)
+    // Pending ReceiveChunkBinary from a registry without `binary_frames`,
+    // awaiting the next Binary frame with its data
+    let mut pending_binary_chunk: Option<HostRegistryMessage> = None;
 
     // Auto-off: idle monitoring (sleep or shutdown)
     let mut auto_off_mode: Option<AutoOffMode> = None;
     let mut auto_off_minutes: u32 = 0;
     let mut idle_since: Option<tokio::time::Instant> = None;
+    // Metered power draw above the host's busy threshold (server-side meters)
+    let mut power_busy = false;
     const CPU_IDLE_THRESHOLD: f32 = 5.0;
 
     let (cpu_tx, mut cpu_rx) = tokio::sync::watch::channel(0.0f32);
 
     // Terminal sessions for remote shell access
     struct TerminalSession {
-        stdin: tokio::process::ChildStdin,
+        pty: Arc<Pty>,
         kill_tx: tokio::sync::oneshot::Sender<()>,
     }
     let mut terminal_sessions: HashMap<String, TerminalSession> = HashMap::new();
+    // Container journals streamed to the registry
+    let log_tasks = LogTasks::default();
 
     // Heartbeat task
     let tx_hb = tx.clone();
@@ -211,6 +311,84 @@ async fn run_connection(config: &Config) -> Result<(), String> {
         }
     });
 
+    // Container usage task (every 30 seconds), for registries tracking limits
+    let tx_usage = tx.clone();
+    let usage_handle = tokio::spawn(async move {
+        if !resource_limits {
+            return;
+        }
+        let storage = std::path::PathBuf::from(nspawn_storage_path);
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+        loop {
+            interval.tick().await;
+            let usage = hr_container::NspawnClient::list_usage(&storage).await;
+            if tx_usage.send(OutgoingWsMessage::Text(HostAgentMessage::ContainerUsage(usage))).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // Docker/Podman containers task (every 30 seconds)
+    let tx_runtimes = tx.clone();
+    let listed_runtimes = runtimes.clone();
+    let runtimes_handle = tokio::spawn(async move {
+        if listed_runtimes.is_empty() {
+            return;
+        }
+        info!(?listed_runtimes, "Reporting container runtimes");
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+        loop {
+            interval.tick().await;
+            let containers = runtime::list_all(&listed_runtimes).await;
+            if tx_runtimes.send(OutgoingWsMessage::Text(HostAgentMessage::RuntimeContainers(containers))).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // SMART disk health task (every 30 minutes), for registries watching disks
+    let tx_disks = tx.clone();
+    let disks_handle = tokio::spawn(async move {
+        if !disk_health {
+            return;
+        }
+        let mut interval = tokio::time::interval(smart::REPORT_INTERVAL);
+        loop {
+            interval.tick().await;
+            let disks = smart::collect().await;
+            if disks.is_empty() {
+                continue;
+            }
+            if tx_disks.send(OutgoingWsMessage::Text(HostAgentMessage::DiskHealth(disks))).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // Package updates task (every 6 hours), for registries tracking them
+    let tx_packages = tx.clone();
+    let packages_handle = tokio::spawn(async move {
+        if !package_updates {
+            return;
+        }
+        let mut interval = tokio::time::interval(packages::REPORT_INTERVAL);
+        loop {
+            interval.tick().await;
+            // The upgrade reports the new state when it is done
+            if packages::upgrading() {
+                continue;
+            }
+            match packages::check().await {
+                Ok(status) => {
+                    if tx_packages.send(OutgoingWsMessage::Text(HostAgentMessage::PackageUpdates(status))).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) => warn!("Package update check failed: {}", e),
+            }
+        }
+    });
+
     // Interfaces task - report network interfaces periodically
     let tx_ifaces = tx.clone();
     let ifaces_handle = tokio::spawn(async move {
@@ -250,21 +428,34 @@ async fn run_connection(config: &Config) -> Result<(), String> {
             }
             // Outgoing messages
             Some(msg) = rx.recv() => {
-                match msg {
-                    OutgoingWsMessage::Text(agent_msg) => {
-                        let text = match serde_json::to_string(&agent_msg) {
-                            Ok(t) => t,
-                            Err(_) => continue,
-                        };
-                        if write.send(Message::Text(text.into())).await.is_err() {
-                            break;
-                        }
-                    }
-                    OutgoingWsMessage::Binary(data) => {
-                        if write.send(Message::Binary(data.into())).await.is_err() {
-                            break;
+                let (agent_msg, data) = match msg {
+                    OutgoingWsMessage::Text(agent_msg) => (agent_msg, None),
+                    OutgoingWsMessage::Chunk(agent_msg, data) => (agent_msg, Some(data)),
+                };
+                if binary_frames {
+                    let frame = match codec::encode(&agent_msg, data.as_deref().unwrap_or_default()) {
+                        Ok(f) => f,
+                        Err(e) => {
+                            warn!("Failed to encode frame: {}", e);
+                            continue;
                         }
+                    };
+                    if write.send(Message::Binary(frame.into())).await.is_err() {
+                        break;
                     }
+                    continue;
+                }
+                let text = match serde_json::to_string(&agent_msg) {
+                    Ok(t) => t,
+                    Err(_) => continue,
+                };
+                if write.send(Message::Text(text.into())).await.is_err() {
+                    break;
+                }
+                if let Some(data) = data
+                    && write.send(Message::Binary(data.into())).await.is_err()
+                {
+                    break;
                 }
             }
             // Read deadline: no message from server in 30s → reconnect
@@ -276,20 +467,86 @@ async fn run_connection(config: &Config) -> Result<(), String> {
             msg = read.next() => {
                 read_deadline.as_mut().reset(tokio::time::Instant::now() + std::time::Duration::from_secs(30));
                 match msg {
-                    Some(Ok(Message::Text(text))) => {
-                        match serde_json::from_str::<HostRegistryMessage>(&text) {
+                    Some(Ok(frame @ (Message::Text(_) | Message::Binary(_)))) => {
+                        let (parsed, chunk) = match frame {
+                            Message::Text(text) => (serde_json::from_str::<HostRegistryMessage>(&text).map_err(|e| e.to_string()), None),
+                            Message::Binary(data) if binary_frames => match codec::decode::<HostRegistryMessage>(&data) {
+                                Ok((m, payload)) => {
+                                    let offset = data.len() - payload.len();
+                                    (Ok(m), Some(data.slice(offset..)))
+                                }
+                                Err(e) => (Err(e), None),
+                            },
+                            // Legacy registry: raw data following a ReceiveChunkBinary text message
+                            Message::Binary(data) => match pending_binary_chunk.take() {
+                                Some(meta) => (Ok(meta), Some(data)),
+                                None => {
+                                    warn!("Unexpected binary WebSocket frame (no pending metadata)");
+                                    continue;
+                                }
+                            },
+                            _ => continue,
+                        };
+                        match parsed {
                             Ok(HostRegistryMessage::Shutdown { drain }) => {
                                 info!(drain, "Shutdown requested");
                                 break;
                             }
-                            Ok(HostRegistryMessage::ReceiveChunkBinary { transfer_id, sequence: _, size: _, checksum }) => {
-                                // Store metadata; the next Binary frame carries the actual data
-                                pending_binary_chunk = Some((transfer_id, checksum));
+                            Ok(HostRegistryMessage::ReceiveChunkBinary { transfer_id, sequence, size, checksum, offset }) => {
+                                let Some(data) = chunk else {
+                                    // Legacy registry: the next Binary frame carries the actual data
+                                    pending_binary_chunk = Some(HostRegistryMessage::ReceiveChunkBinary { transfer_id, sequence, size, checksum, offset });
+                                    continue;
+                                };
+                                let actual_checksum = xxhash_rust::xxh32::xxh32(&data, 0);
+                                if actual_checksum != checksum {
+                                    warn!(
+                                        transfer_id = %transfer_id,
+                                        expected = checksum,
+                                        actual = actual_checksum,
+                                        "Binary chunk checksum mismatch, skipping"
+                                    );
+                                } else if let Some(import) = transfers.imports.get_mut(&transfer_id) {
+                                    // Nspawn import
+                                    use tokio::io::AsyncWriteExt;
+                                    if resumable {
+                                        match import.manifest.check(sequence, offset) {
+                                            ChunkOrder::Next => import.resume_requested = false,
+                                            ChunkOrder::Duplicate => continue,
+                                            ChunkOrder::Gap => {
+                                                if !import.resume_requested {
+                                                    import.resume_requested = true;
+                                                    let point = import.resume_point();
+                                                    warn!(transfer_id = %transfer_id, sequence, ?point, "Missing chunks, asking the sender to resume");
+                                                    let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::ResumeTransfer {
+                                                        transfer_id: transfer_id.clone(),
+                                                        point,
+                                                    })).await;
+                                                }
+                                                continue;
+                                            }
+                                        }
+                                    }
+                                    let target = match import.phase {
+                                        ImportPhase::ReceivingWorkspace => import.ws_tar_stdin.as_mut().unwrap_or(&mut import.tar_stdin),
+                                        ImportPhase::ReceivingContainer => &mut import.tar_stdin,
+                                    };
+                                    if let Err(e) = target.write_all(&data).await {
+                                        error!("Failed to write binary chunk for {}: {}", transfer_id, e);
+                                    } else {
+                                        import.manifest.record(data.len() as u32, checksum);
+                                    }
+                                } else {
+                                    warn!(transfer_id = %transfer_id, "Binary chunk for unknown import");
+                                }
                             }
                             Ok(HostRegistryMessage::WorkspaceReady { transfer_id, size_bytes }) => {
                                 info!(transfer_id = %transfer_id, size_bytes, "Workspace data incoming");
 
-                                if let Some(import) = active_nspawn_imports.get_mut(&transfer_id) {
+                                if let Some(import) = transfers.imports.get_mut(&transfer_id)
+                                    && import.phase == ImportPhase::ReceivingContainer
+                                    && !import.resume_requested
+                                {
                                     use tokio::io::AsyncWriteExt;
 
                                     // 1. Close container tar stdin
@@ -307,7 +564,7 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                                                 transfer_id: transfer_id.clone(),
                                                 error: format!("Container tar extraction failed: {}", s),
                                             })).await;
-                                            active_nspawn_imports.remove(&transfer_id);
+                                            transfers.imports.remove(&transfer_id);
                                             continue;
                                         }
                                         Err(e) => {
@@ -316,7 +573,7 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                                                 transfer_id: transfer_id.clone(),
                                                 error: format!("Container tar wait error: {}", e),
                                             })).await;
-                                            active_nspawn_imports.remove(&transfer_id);
+                                            transfers.imports.remove(&transfer_id);
                                             continue;
                                         }
                                     }
@@ -329,7 +586,7 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                                             transfer_id: transfer_id.clone(),
                                             error: format!("Failed to create workspace dir: {}", e),
                                         })).await;
-                                        active_nspawn_imports.remove(&transfer_id);
+                                        transfers.imports.remove(&transfer_id);
                                         continue;
                                     }
 
@@ -344,8 +601,9 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                                         Ok(mut ws_child) => {
                                             let ws_stdin = ws_child.stdin.take().expect("ws tar stdin");
                                             import.ws_tar_child = Some(ws_child);
-                                            import.ws_tar_stdin = Some(ws_stdin);
+                                            import.ws_tar_stdin = Some(import.compression.decoder(ws_stdin));
                                             import.phase = ImportPhase::ReceivingWorkspace;
+                                            import.manifest = StreamManifest::default();
                                         }
                                         Err(e) => {
                                             error!("Failed to spawn nspawn workspace tar: {}", e);
@@ -353,13 +611,16 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                                                 transfer_id: transfer_id.clone(),
                                                 error: format!("Failed to spawn workspace tar: {}", e),
                                             })).await;
-                                            active_nspawn_imports.remove(&transfer_id);
+                                            transfers.imports.remove(&transfer_id);
                                         }
                                     }
                                 }
                             }
                             Ok(HostRegistryMessage::TransferComplete { transfer_id }) => {
-                                if let Some(mut import) = active_nspawn_imports.remove(&transfer_id) {
+                                if transfers.imports.get(&transfer_id).is_some_and(|i| i.resume_requested) {
+                                    // The end of the stream is missing: finalize after the replay
+                                    info!(transfer_id = %transfer_id, "TransferComplete while resuming, ignored");
+                                } else if let Some(mut import) = transfers.imports.remove(&transfer_id) {
                                     use tokio::io::AsyncWriteExt;
 
                                     let container_name = import.container_name.clone();
@@ -537,13 +798,18 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                             Ok(HostRegistryMessage::CreateContainer { .. }) => {
                                 warn!("CreateContainer not yet implemented");
                             }
-                            Ok(HostRegistryMessage::PushAgentUpdate { version, download_url, sha256 }) => {
-                                info!(version = %version, "Agent update received, starting self-update");
-                                tokio::spawn(async move {
-                                    if let Err(e) = self_update(&download_url, &sha256).await {
-                                        error!("Self-update failed: {}", e);
+                            Ok(HostRegistryMessage::PushAgentUpdate { download_url, manifest }) => {
+                                match verify_update(config, &manifest, registry_protocol) {
+                                    Ok(manifest) => {
+                                        info!(version = %manifest.version, "Agent update received, starting self-update");
+                                        tokio::spawn(async move {
+                                            if let Err(e) = self_update(&download_url, &manifest).await {
+                                                error!("Self-update failed: {}", e);
+                                            }
+                                        });
                                     }
-                                });
+                                    Err(e) => error!("Agent update refused: {}", e),
+                                }
                             }
                             Ok(HostRegistryMessage::PowerOff) => {
                                 info!("Poweroff requested via agent");
@@ -581,41 +847,87 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                                 auto_off_minutes = minutes;
                                 idle_since = None;
                             }
+                            Ok(HostRegistryMessage::PowerBusy { busy }) => {
+                                info!(busy, "Metered power draw changed");
+                                power_busy = busy;
+                            }
                             Ok(HostRegistryMessage::CancelTransfer { transfer_id }) => {
                                 info!(transfer_id = %transfer_id, "Transfer cancelled");
-                                if let Some(mut import) = active_nspawn_imports.remove(&transfer_id) {
-                                    // Kill container tar
-                                    let _ = import.tar_child.kill().await;
-                                    drop(import.tar_stdin);
-                                    // Kill workspace tar if active
-                                    if let Some(mut ws_child) = import.ws_tar_child.take() {
-                                        let _ = ws_child.kill().await;
-                                    }
-                                    if let Some(ws_stdin) = import.ws_tar_stdin.take() {
-                                        drop(ws_stdin);
-                                    }
-                                    // Clean up extracted nspawn files
-                                    let rootfs_dir = format!("{}/{}", import.storage_path, import.container_name);
-                                    let _ = tokio::fs::remove_dir_all(&rootfs_dir).await;
-                                    let ws_dir = format!("{}/{}-workspace", import.storage_path, import.container_name);
-                                    let _ = tokio::fs::remove_dir_all(&ws_dir).await;
+                                if let Some(import) = transfers.imports.remove(&transfer_id) {
+                                    import.discard().await;
                                     info!(transfer_id = %transfer_id, "Cleaned up cancelled nspawn import");
                                 }
-                                if let Some((ref tid, _)) = pending_binary_chunk {
-                                    if tid == &transfer_id {
-                                        pending_binary_chunk = None;
+                                if let Some(job) = transfers.exports.remove(&transfer_id) {
+                                    job.handle.abort();
+                                }
+                                if matches!(&pending_binary_chunk, Some(HostRegistryMessage::ReceiveChunkBinary { transfer_id: tid, .. }) if tid == &transfer_id) {
+                                    pending_binary_chunk = None;
+                                }
+                            }
+                            Ok(HostRegistryMessage::TransferManifest { transfer_id, stream, chunk_count, total_bytes, digest }) => {
+                                let Some(import) = transfers.imports.get_mut(&transfer_id) else { continue };
+                                if import.stream() != stream {
+                                    continue;
+                                }
+                                if import.manifest.next_sequence() < chunk_count {
+                                    // The last chunks were lost: ask for them
+                                    if !import.resume_requested {
+                                        import.resume_requested = true;
+                                        let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::ResumeTransfer {
+                                            transfer_id: transfer_id.clone(),
+                                            point: import.resume_point(),
+                                        })).await;
+                                    }
+                                } else if let Err(e) = import.manifest.verify(chunk_count, total_bytes, digest) {
+                                    error!(transfer_id = %transfer_id, ?stream, "Transfer manifest mismatch: {}", e);
+                                    if let Some(import) = transfers.imports.remove(&transfer_id) {
+                                        import.discard().await;
                                     }
+                                    let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::ImportFailed {
+                                        transfer_id, error: e,
+                                    })).await;
                                 }
                             }
+                            Ok(HostRegistryMessage::ResumeTransfer { transfer_id, point }) => {
+                                // The receiver of one of our exports lost chunks: replay from its point
+                                let Some(job) = transfers.exports.get_mut(&transfer_id) else {
+                                    warn!(transfer_id = %transfer_id, "Resume requested for unknown export");
+                                    let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::ExportFailed {
+                                        transfer_id, error: "Unknown transfer, cannot resume".to_string(),
+                                    })).await;
+                                    continue;
+                                };
+                                info!(transfer_id = %transfer_id, ?point, "Resuming nspawn export");
+                                job.handle.abort();
+                                let export = NspawnExport {
+                                    tx: tx.clone(),
+                                    transfer_id,
+                                    container_name: job.container_name.clone(),
+                                    storage_path: job.storage_path.clone(),
+                                    resumable,
+                                    compression: job.compression,
+                                };
+                                job.handle = tokio::spawn(export.run(Some(point)));
+                            }
                             // ── Nspawn container handlers ──────────────────
                             Ok(HostRegistryMessage::CreateNspawnContainer {
                                 app_id: _, slug: _, container_name, storage_path, network_mode,
-                                agent_token: _, agent_config: _,
+                                agent_token: _, agent_config: _, template,
                             }) => {
                                 info!(container = %container_name, storage = %storage_path, network_mode = %network_mode, "Creating nspawn container");
                                 tokio::spawn(async move {
                                     let sp = std::path::Path::new(&storage_path);
-                                    match hr_container::NspawnClient::create_container(&container_name, sp, &network_mode, true).await {
+                                    let created = match &template {
+                                        Some(template) => {
+                                            let cache = hr_container::TemplateCache::new(TEMPLATE_CACHE_DIR);
+                                            match hr_container::NspawnClient::create_from_template(&container_name, sp, &network_mode, true, template, &cache).await {
+                                                Ok(()) => hr_container::NspawnClient::provision(&container_name, sp, template).await,
+                                                Err(e) => Err(e),
+                                            }
+                                        }
+                                        None => hr_container::NspawnClient::create_container(&container_name, sp, &network_mode, true).await,
+                                    };
+                                    match created {
                                         Ok(()) => {
                                             info!(container = %container_name, "Nspawn container created successfully");
                                         }
@@ -627,6 +939,7 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                             }
                             Ok(HostRegistryMessage::DeleteNspawnContainer { container_name, storage_path }) => {
                                 info!(container = %container_name, "Deleting nspawn container");
+                                transfers.forget_exports(&container_name);
                                 tokio::spawn(async move {
                                     let sp = std::path::Path::new(&storage_path);
                                     if let Err(e) = hr_container::NspawnClient::delete_container(&container_name, sp).await {
@@ -650,6 +963,121 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                                     }
                                 });
                             }
+                            Ok(HostRegistryMessage::SetContainerLimits { container_name, storage_path, limits }) => {
+                                info!(container = %container_name, ?limits, "Setting nspawn container limits");
+                                tokio::spawn(async move {
+                                    let sp = std::path::Path::new(&storage_path);
+                                    if let Err(e) = hr_container::NspawnClient::apply_limits(&container_name, sp, &limits).await {
+                                        error!(container = %container_name, "Nspawn limits failed: {e}");
+                                    }
+                                });
+                            }
+                            Ok(HostRegistryMessage::RuntimeContainerAction { request_id, runtime, container, action }) => {
+                                info!(container = %container, ?runtime, ?action, "Runtime container action");
+                                let tx_action = tx.clone();
+                                let runtimes = runtimes.clone();
+                                tokio::spawn(async move {
+                                    let (success, stdout, stderr) = if !runtimes.contains(&runtime) {
+                                        (false, String::new(), format!("{} is not available on this host", runtime.binary()))
+                                    } else {
+                                        match runtime::action(runtime, &container, action).await {
+                                            Ok(out) => (true, out, String::new()),
+                                            Err(e) => (false, String::new(), e),
+                                        }
+                                    };
+                                    let _ = tx_action.send(OutgoingWsMessage::Text(HostAgentMessage::ExecResult {
+                                        request_id,
+                                        success,
+                                        stdout,
+                                        stderr,
+                                    })).await;
+                                    // Report the new state without waiting for the next tick
+                                    if success && action != RuntimeAction::Inspect {
+                                        let containers = runtime::list_all(&runtimes).await;
+                                        let _ = tx_action.send(OutgoingWsMessage::Text(HostAgentMessage::RuntimeContainers(containers))).await;
+                                    }
+                                });
+                            }
+                            Ok(HostRegistryMessage::ListSystemdServices { request_id, patterns }) => {
+                                let tx_services = tx.clone();
+                                tokio::spawn(async move {
+                                    let (success, stdout, stderr) = match services::list(&patterns).await {
+                                        Ok(list) => (true, serde_json::to_string(&list).unwrap_or_default(), String::new()),
+                                        Err(e) => (false, String::new(), e),
+                                    };
+                                    let _ = tx_services.send(OutgoingWsMessage::Text(HostAgentMessage::ExecResult {
+                                        request_id,
+                                        success,
+                                        stdout,
+                                        stderr,
+                                    })).await;
+                                });
+                            }
+                            Ok(HostRegistryMessage::SystemdServiceAction { request_id, unit, action }) => {
+                                info!(unit = %unit, ?action, "Service action");
+                                let tx_action = tx.clone();
+                                tokio::spawn(async move {
+                                    let (success, stdout, stderr) = match services::action(&unit, action).await {
+                                        Ok(out) => (true, out, String::new()),
+                                        Err(e) => (false, String::new(), e),
+                                    };
+                                    let _ = tx_action.send(OutgoingWsMessage::Text(HostAgentMessage::ExecResult {
+                                        request_id,
+                                        success,
+                                        stdout,
+                                        stderr,
+                                    })).await;
+                                });
+                            }
+                            Ok(HostRegistryMessage::CheckPackageUpdates) => {
+                                let tx_packages = tx.clone();
+                                tokio::spawn(async move {
+                                    match packages::check().await {
+                                        Ok(status) => {
+                                            let _ = tx_packages.send(OutgoingWsMessage::Text(HostAgentMessage::PackageUpdates(status))).await;
+                                        }
+                                        Err(e) => warn!("Package update check failed: {}", e),
+                                    }
+                                });
+                            }
+                            Ok(HostRegistryMessage::UpgradePackages { upgrade_id, full, snapshot }) => {
+                                info!(upgrade_id = %upgrade_id, full, snapshot, "Upgrading packages");
+                                let tx_upgrade = tx.clone();
+                                tokio::spawn(async move {
+                                    let (line_tx, mut line_rx) = tokio::sync::mpsc::channel::<String>(256);
+                                    let tx_lines = tx_upgrade.clone();
+                                    let id = upgrade_id.clone();
+                                    let forward = tokio::spawn(async move {
+                                        while let Some(line) = line_rx.recv().await {
+                                            let _ = tx_lines.send(OutgoingWsMessage::Text(HostAgentMessage::UpgradeOutput {
+                                                upgrade_id: id.clone(),
+                                                line,
+                                            })).await;
+                                        }
+                                    });
+                                    let result = packages::upgrade(full, snapshot, line_tx).await;
+                                    let _ = forward.await;
+                                    let status = packages::check().await;
+                                    let reboot_required = status.as_ref().is_ok_and(|s| s.reboot_required);
+                                    let (success, error, snapshot) = match result {
+                                        Ok(snapshot) => (true, None, snapshot),
+                                        Err(e) => {
+                                            error!(upgrade_id = %upgrade_id, "Package upgrade failed: {}", e);
+                                            (false, Some(e), None)
+                                        }
+                                    };
+                                    let _ = tx_upgrade.send(OutgoingWsMessage::Text(HostAgentMessage::UpgradeFinished {
+                                        upgrade_id,
+                                        success,
+                                        error,
+                                        snapshot,
+                                        reboot_required,
+                                    })).await;
+                                    if let Ok(status) = status {
+                                        let _ = tx_upgrade.send(OutgoingWsMessage::Text(HostAgentMessage::PackageUpdates(status))).await;
+                                    }
+                                });
+                            }
                             Ok(HostRegistryMessage::ExecInNspawnContainer { request_id, container_name, command }) => {
                                 info!(container = %container_name, "Executing command in nspawn container");
                                 let tx_exec = tx.clone();
@@ -667,15 +1095,54 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                                     })).await;
                                 });
                             }
-                            Ok(HostRegistryMessage::StartNspawnExport { container_name, storage_path, transfer_id }) => {
-                                info!(container = %container_name, transfer_id = %transfer_id, "Starting nspawn export");
-                                let tx_export = tx.clone();
-                                tokio::spawn(async move {
-                                    handle_nspawn_export(tx_export, transfer_id, container_name, storage_path).await;
+                            Ok(HostRegistryMessage::LogStream { request_id, request }) => {
+                                info!(request_id = %request_id, source = ?request.source, "Opening log stream");
+                                let tx_logs = tx.clone();
+                                let id = request_id.clone();
+                                log_tasks.spawn(request_id, async move {
+                                    // Never the host's own journal
+                                    let result = match request.source {
+                                        LogSource::Container { .. } => {
+                                            hr_registry::logs::stream_journal(&request, |lines| {
+                                                let tx = tx_logs.clone();
+                                                let request_id = id.clone();
+                                                async move {
+                                                    tx.send(OutgoingWsMessage::Text(HostAgentMessage::LogStreamData { request_id, lines })).await.is_ok()
+                                                }
+                                            })
+                                            .await
+                                        }
+                                        LogSource::Journal { .. } => Err("Host agents only stream container journals".to_string()),
+                                    };
+                                    let _ = tx_logs.send(OutgoingWsMessage::Text(HostAgentMessage::LogStreamEnd {
+                                        request_id: id,
+                                        error: result.err(),
+                                    })).await;
+                                });
+                            }
+                            Ok(HostRegistryMessage::LogStreamClose { request_id }) => {
+                                log_tasks.stop(&request_id);
+                            }
+                            Ok(HostRegistryMessage::StartNspawnExport { container_name, storage_path, transfer_id, compression }) => {
+                                info!(container = %container_name, transfer_id = %transfer_id, ?compression, "Starting nspawn export");
+                                transfers.forget_exports(&container_name);
+                                let export = NspawnExport {
+                                    tx: tx.clone(),
+                                    transfer_id: transfer_id.clone(),
+                                    container_name: container_name.clone(),
+                                    storage_path: storage_path.clone(),
+                                    resumable,
+                                    compression,
+                                };
+                                transfers.exports.insert(transfer_id, ExportJob {
+                                    container_name,
+                                    storage_path,
+                                    compression,
+                                    handle: tokio::spawn(export.run(None)),
                                 });
                             }
-                            Ok(HostRegistryMessage::StartNspawnImport { container_name, storage_path, transfer_id, network_mode }) => {
-                                info!(container = %container_name, transfer_id = %transfer_id, "Preparing nspawn import");
+                            Ok(HostRegistryMessage::StartNspawnImport { container_name, storage_path, transfer_id, network_mode, compression }) => {
+                                info!(container = %container_name, transfer_id = %transfer_id, ?compression, "Preparing nspawn import");
 
                                 // Pre-flight: ensure systemd-container is installed
                                 if tokio::process::Command::new("machinectl")
@@ -717,7 +1184,7 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                                 let rootfs_dir = format!("{}/{}", storage_path, container_name);
 
                                 // Create target directory
-                                if let Err(e) = tokio::fs::create_dir_all(&rootfs_dir).await {
+                                if let Err(e) = hr_container::limits::create_rootfs_dir(std::path::Path::new(&rootfs_dir)).await {
                                     error!("Failed to create rootfs dir: {}", e);
                                     let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::ImportFailed {
                                         transfer_id, error: format!("Failed to create rootfs dir: {}", e),
@@ -735,15 +1202,18 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                                 {
                                     Ok(mut child) => {
                                         let stdin = child.stdin.take().expect("tar stdin");
-                                        active_nspawn_imports.insert(transfer_id, ActiveNspawnImport {
+                                        transfers.imports.insert(transfer_id, NspawnImport {
                                             container_name,
                                             storage_path,
                                             tar_child: child,
-                                            tar_stdin: stdin,
+                                            tar_stdin: compression.decoder(stdin),
                                             phase: ImportPhase::ReceivingContainer,
                                             ws_tar_child: None,
                                             ws_tar_stdin: None,
                                             network_mode,
+                                            compression,
+                                            manifest: StreamManifest::default(),
+                                            resume_requested: false,
                                         });
                                     }
                                     Err(e) => {
@@ -754,144 +1224,79 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                                     }
                                 }
                             }
-                            Ok(HostRegistryMessage::TerminalOpen { session_id, container_name }) => {
-                                info!(session_id = %session_id, container = %container_name, "Opening terminal session");
-                                let tx_term = tx.clone();
-                                let sid = session_id.clone();
-
-                                // Get the container's leader PID via machinectl show
-                                let leader_pid = match tokio::process::Command::new("machinectl")
-                                    .args(["show", &container_name, "--property=Leader", "--value"])
-                                    .output()
-                                    .await
-                                {
-                                    Ok(output) if output.status.success() => {
-                                        String::from_utf8_lossy(&output.stdout).trim().to_string()
-                                    }
-                                    Ok(output) => {
-                                        let stderr = String::from_utf8_lossy(&output.stderr);
-                                        error!(container = %container_name, "Failed to get leader PID: {stderr}");
-                                        let _ = tx_term.send(OutgoingWsMessage::Text(HostAgentMessage::TerminalClosed {
-                                            session_id: sid, exit_code: None,
-                                        })).await;
-                                        continue;
-                                    }
+                            Ok(HostRegistryMessage::TerminalOpen { session_id, container_name, cols, rows }) => {
+                                info!(session_id = %session_id, container = ?container_name, "Opening terminal session");
+                                let command = match &container_name {
+                                    Some(container) => pty::container_shell(container).await,
+                                    None => Ok(pty::host_shell()),
+                                };
+                                let spawned = command.and_then(|command| {
+                                    Pty::spawn(command, WindowSize::or_default(cols, rows))
+                                        .map_err(|e| format!("Failed to start shell: {e}"))
+                                });
+                                let (session_pty, mut child) = match spawned {
+                                    Ok(spawned) => spawned,
                                     Err(e) => {
-                                        error!(container = %container_name, "machinectl show error: {e}");
-                                        let _ = tx_term.send(OutgoingWsMessage::Text(HostAgentMessage::TerminalClosed {
-                                            session_id: sid, exit_code: None,
+                                        error!(session_id = %session_id, "Failed to open terminal: {e}");
+                                        let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::TerminalClosed {
+                                            session_id, exit_code: None,
                                         })).await;
                                         continue;
                                     }
                                 };
+                                let session_pty = Arc::new(session_pty);
+                                let (kill_tx, mut kill_rx) = tokio::sync::oneshot::channel::<()>();
+                                terminal_sessions.insert(session_id.clone(), TerminalSession {
+                                    pty: session_pty.clone(),
+                                    kill_tx,
+                                });
+                                let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::TerminalOpened {
+                                    session_id: session_id.clone(),
+                                })).await;
 
-                                let nsenter_cmd = format!(
-                                    "nsenter -t {} -m -u -i -n -p -- /bin/bash -l",
-                                    leader_pid
-                                );
-                                match tokio::process::Command::new("script")
-                                    .args(["-qfec", &nsenter_cmd, "/dev/null"])
-                                    .env("TERM", "xterm-256color")
-                                    .env("HOME", "/root")
-                                    .stdin(std::process::Stdio::piped())
-                                    .stdout(std::process::Stdio::piped())
-                                    .stderr(std::process::Stdio::piped())
-                                    .spawn()
-                                {
-                                    Ok(mut child) => {
-                                        let child_stdin = child.stdin.take().expect("child stdin");
-                                        let mut child_stdout = child.stdout.take().expect("child stdout");
-                                        let mut child_stderr = child.stderr.take().expect("child stderr");
-                                        let (kill_tx, mut kill_rx) = tokio::sync::oneshot::channel::<()>();
-
-                                        terminal_sessions.insert(sid.clone(), TerminalSession {
-                                            stdin: child_stdin,
-                                            kill_tx,
-                                        });
-
-                                        // Send TerminalOpened
-                                        let _ = tx_term.send(OutgoingWsMessage::Text(HostAgentMessage::TerminalOpened {
-                                            session_id: sid.clone(),
-                                        })).await;
-
-                                        // Spawn task to read stdout/stderr and send TerminalData
-                                        let tx_reader = tx_term.clone();
-                                        let reader_sid = sid.clone();
-                                        tokio::spawn(async move {
-                                            use tokio::io::AsyncReadExt;
-                                            let mut stdout_buf = vec![0u8; 4096];
-                                            let mut stderr_buf = vec![0u8; 4096];
-                                            let exit_code;
-                                            loop {
-                                                tokio::select! {
-                                                    n = child_stdout.read(&mut stdout_buf) => {
-                                                        match n {
-                                                            Ok(0) | Err(_) => {
-                                                                exit_code = child.wait().await.ok().and_then(|s| s.code());
-                                                                break;
-                                                            }
-                                                            Ok(n) => {
-                                                                if tx_reader.send(OutgoingWsMessage::Text(HostAgentMessage::TerminalData {
-                                                                    session_id: reader_sid.clone(),
-                                                                    data: stdout_buf[..n].to_vec(),
-                                                                })).await.is_err() {
-                                                                    exit_code = None;
-                                                                    let _ = child.kill().await;
-                                                                    break;
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                    n = child_stderr.read(&mut stderr_buf) => {
-                                                        match n {
-                                                            Ok(0) | Err(_) => {
-                                                                exit_code = child.wait().await.ok().and_then(|s| s.code());
-                                                                break;
-                                                            }
-                                                            Ok(n) => {
-                                                                if tx_reader.send(OutgoingWsMessage::Text(HostAgentMessage::TerminalData {
-                                                                    session_id: reader_sid.clone(),
-                                                                    data: stderr_buf[..n].to_vec(),
-                                                                })).await.is_err() {
-                                                                    exit_code = None;
-                                                                    let _ = child.kill().await;
-                                                                    break;
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                    _ = &mut kill_rx => {
+                                // Relay the output until the shell exits or the session is closed
+                                let tx_term = tx.clone();
+                                tokio::spawn(async move {
+                                    let mut buf = vec![0u8; 4096];
+                                    loop {
+                                        tokio::select! {
+                                            n = session_pty.read(&mut buf) => match n {
+                                                Ok(0) | Err(_) => break,
+                                                Ok(n) => {
+                                                    if tx_term.send(OutgoingWsMessage::Text(HostAgentMessage::TerminalData {
+                                                        session_id: session_id.clone(),
+                                                        data: buf[..n].to_vec(),
+                                                    })).await.is_err() {
                                                         let _ = child.kill().await;
-                                                        exit_code = child.wait().await.ok().and_then(|s| s.code());
-                                                        break;
-                                                    }
-                                                    status = child.wait() => {
-                                                        exit_code = status.ok().and_then(|s| s.code());
                                                         break;
                                                     }
                                                 }
+                                            },
+                                            _ = &mut kill_rx => {
+                                                let _ = child.kill().await;
+                                                break;
                                             }
-                                            // Send TerminalClosed
-                                            let _ = tx_reader.send(OutgoingWsMessage::Text(HostAgentMessage::TerminalClosed {
-                                                session_id: reader_sid,
-                                                exit_code,
-                                            })).await;
-                                        });
-                                    }
-                                    Err(e) => {
-                                        error!(session_id = %sid, "Failed to spawn terminal: {e}");
-                                        let _ = tx_term.send(OutgoingWsMessage::Text(HostAgentMessage::TerminalClosed {
-                                            session_id: sid, exit_code: None,
-                                        })).await;
+                                        }
                                     }
-                                }
+                                    let exit_code = child.wait().await.ok().and_then(|s| s.code());
+                                    let _ = tx_term.send(OutgoingWsMessage::Text(HostAgentMessage::TerminalClosed {
+                                        session_id,
+                                        exit_code,
+                                    })).await;
+                                });
                             }
                             Ok(HostRegistryMessage::TerminalData { session_id, data }) => {
-                                if let Some(session) = terminal_sessions.get_mut(&session_id) {
-                                    use tokio::io::AsyncWriteExt;
-                                    if let Err(e) = session.stdin.write_all(&data).await {
-                                        warn!(session_id = %session_id, "Terminal stdin write error: {e}");
-                                    }
+                                if let Some(session) = terminal_sessions.get(&session_id)
+                                    && let Err(e) = session.pty.write_all(&data).await
+                                {
+                                    warn!(session_id = %session_id, "Terminal write error: {e}");
+                                }
+                            }
+                            Ok(HostRegistryMessage::TerminalResize { session_id, cols, rows }) => {
+                                if let Some(session) = terminal_sessions.get(&session_id)
+                                    && let Err(e) = session.pty.resize(WindowSize::or_default(cols, rows))
+                                {
+                                    warn!(session_id = %session_id, "Terminal resize error: {e}");
                                 }
                             }
                             Ok(HostRegistryMessage::TerminalClose { session_id }) => {
@@ -900,7 +1305,27 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                                     let _ = session.kill_tx.send(());
                                 }
                             }
-                            Ok(HostRegistryMessage::AuthResult { .. }) => {
+                            Ok(HostRegistryMessage::MigrationPreflight { request_id, storage_path, network_mode, container_name }) => {
+                                let tx_preflight = tx.clone();
+                                tokio::spawn(async move {
+                                    let report = hr_container::preflight::probe(
+                                        &storage_path,
+                                        network_mode.as_deref(),
+                                        container_name.as_deref(),
+                                    ).await;
+                                    let _ = tx_preflight.send(OutgoingWsMessage::Text(HostAgentMessage::MigrationPreflight {
+                                        request_id,
+                                        report,
+                                    })).await;
+                                });
+                            }
+                            Ok(HostRegistryMessage::RotateToken { token }) => {
+                                match config.save_token(&token) {
+                                    Ok(()) => info!("Token rotated, used from the next connection"),
+                                    Err(e) => error!("Failed to save rotated token: {}", e),
+                                }
+                            }
+                            Ok(HostRegistryMessage::AuthResult { .. } | HostRegistryMessage::EnrollResult { .. }) => {
                                 // Already handled during auth phase
                             }
                             Err(e) => {
@@ -908,33 +1333,6 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                             }
                         }
                     }
-                    Some(Ok(Message::Binary(data))) => {
-                        if let Some((transfer_id, expected_checksum)) = pending_binary_chunk.take() {
-                            let actual_checksum = xxhash_rust::xxh32::xxh32(&data, 0);
-                            if actual_checksum != expected_checksum {
-                                warn!(
-                                    transfer_id = %transfer_id,
-                                    expected = expected_checksum,
-                                    actual = actual_checksum,
-                                    "Binary chunk checksum mismatch, skipping"
-                                );
-                            } else if let Some(import) = active_nspawn_imports.get_mut(&transfer_id) {
-                                // Nspawn import
-                                use tokio::io::AsyncWriteExt;
-                                let target = match import.phase {
-                                    ImportPhase::ReceivingWorkspace => import.ws_tar_stdin.as_mut().unwrap_or(&mut import.tar_stdin),
-                                    ImportPhase::ReceivingContainer => &mut import.tar_stdin,
-                                };
-                                if let Err(e) = target.write_all(&data).await {
-                                    error!("Failed to write binary chunk for {}: {}", transfer_id, e);
-                                }
-                            } else {
-                                warn!(transfer_id = %transfer_id, "Binary chunk for unknown import");
-                            }
-                        } else {
-                            warn!("Unexpected binary WebSocket frame (no pending metadata)");
-                        }
-                    }
                     Some(Ok(Message::Ping(data))) => {
                         let _ = write.send(Message::Pong(data)).await;
                     }
@@ -959,7 +1357,7 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                     }
                 };
                 let cpu = *cpu_rx.borrow();
-                if cpu < CPU_IDLE_THRESHOLD {
+                if cpu < CPU_IDLE_THRESHOLD && !power_busy && !packages::upgrading() {
                     if idle_since.is_none() {
                         info!(cpu_percent = cpu, timeout_minutes = auto_off_minutes, ?mode,
                               "Host entering idle state, starting auto-off countdown");
@@ -988,7 +1386,7 @@ async fn run_connection(config: &Config) -> Result<(), String> {
                     }
                 } else {
                     if idle_since.is_some() {
-                        info!(cpu_percent = cpu, "Host no longer idle, resetting auto-off countdown");
+                        info!(cpu_percent = cpu, power_busy, "Host no longer idle, resetting auto-off countdown");
                     }
                     idle_since = None;
                 }
@@ -1002,90 +1400,247 @@ async fn run_connection(config: &Config) -> Result<(), String> {
         let _ = session.kill_tx.send(());
     }
 
-    // Clean up orphaned nspawn imports on disconnect
-    for (tid, mut import) in active_nspawn_imports {
-        warn!(transfer_id = %tid, "Cleaning orphaned nspawn import on disconnect");
-        let _ = import.tar_child.kill().await;
-        drop(import.tar_stdin);
-        if let Some(mut ws_child) = import.ws_tar_child.take() {
-            let _ = ws_child.kill().await;
-        }
-        if let Some(ws_stdin) = import.ws_tar_stdin.take() {
-            drop(ws_stdin);
-        }
-        let rootfs_dir = format!("{}/{}", import.storage_path, import.container_name);
-        let _ = tokio::fs::remove_dir_all(&rootfs_dir).await;
-        let ws_dir = format!("{}/{}-workspace", import.storage_path, import.container_name);
-        let _ = tokio::fs::remove_dir_all(&ws_dir).await;
+    // Transfers wait for the next connection, unless the registry cannot
+    // resume them
+    if !resumable {
+        transfers.abandon().await;
     }
 
     heartbeat_handle.abort();
     metrics_handle.abort();
+    usage_handle.abort();
+    runtimes_handle.abort();
+    disks_handle.abort();
+    packages_handle.abort();
+    log_tasks.stop_all();
     ifaces_handle.abort();
     Ok(())
 }
 
-/// Handle nspawn container export (stop + tar rootfs + workspace).
-async fn handle_nspawn_export(
+/// An nspawn container export (stop + tar rootfs + workspace).
+struct NspawnExport {
     tx: tokio::sync::mpsc::Sender<OutgoingWsMessage>,
     transfer_id: String,
     container_name: String,
     storage_path: String,
-) {
-    // 1. Stop container
-    info!(container = %container_name, "Stopping nspawn container for export");
-    if let Err(e) = hr_container::NspawnClient::stop_container(&container_name).await {
-        let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::ExportFailed {
-            transfer_id, error: format!("Failed to stop container: {}", e),
-        })).await;
-        return;
-    }
+    /// The registry checks manifests and asks for resumes.
+    resumable: bool,
+    compression: Compression,
+}
 
-    // Wait for container to fully stop
-    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+impl NspawnExport {
+    /// Run the export, or replay it from the receiver's resume point (the
+    /// container is already stopped and the receiver already set up).
+    async fn run(self, from: Option<ResumePoint>) {
+        let tx = &self.tx;
+        let transfer_id = self.transfer_id.clone();
+
+        // 1. Build paths
+        let rootfs_dir = format!("{}/{}", self.storage_path, self.container_name);
+        let workspace_dir = format!("{}/{}-workspace", self.storage_path, self.container_name);
+
+        let fresh = from.is_none();
+        if fresh {
+            // 2. Stop container
+            info!(container = %self.container_name, "Stopping nspawn container for export");
+            if let Err(e) = hr_container::NspawnClient::stop_container(&self.container_name).await {
+                let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::ExportFailed {
+                    transfer_id, error: format!("Failed to stop container: {}", e),
+                })).await;
+                return;
+            }
 
-    // 2. Build paths
-    let rootfs_dir = format!("{}/{}", storage_path, container_name);
-    let workspace_dir = format!("{}/{}-workspace", storage_path, container_name);
+            // Wait for container to fully stop
+            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+        }
+        let from = from.unwrap_or_default();
+
+        if from.stream == TransferStream::Container {
+            // 3. Estimate container size
+            let estimated_size = estimate_dir_size(&rootfs_dir).await;
+
+            // 4. Send ExportReady
+            if fresh {
+                let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::ExportReady {
+                    transfer_id: transfer_id.clone(),
+                    container_name: self.container_name.clone(),
+                    size_bytes: estimated_size,
+                })).await;
+            }
 
-    // 3. Estimate container size
-    let estimated_size = estimate_dir_size(&rootfs_dir).await;
+            // 5. Stream container tar
+            if let Err(e) = self.stream_tar(TransferStream::Container, &rootfs_dir, estimated_size, from).await {
+                let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::ExportFailed {
+                    transfer_id, error: e,
+                })).await;
+                return;
+            }
+        }
 
-    // 4. Send ExportReady
-    let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::ExportReady {
-        transfer_id: transfer_id.clone(),
-        container_name: container_name.clone(),
-        size_bytes: estimated_size,
-    })).await;
+        // 6. Stream workspace if directory exists
+        let ws_path = std::path::Path::new(&workspace_dir);
+        if ws_path.exists() {
+            let ws_size = estimate_dir_size(&workspace_dir).await;
+            if from.stream == TransferStream::Container {
+                let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::WorkspaceReady {
+                    transfer_id: transfer_id.clone(),
+                    size_bytes: ws_size,
+                })).await;
+            }
 
-    // 5. Stream container tar
-    if let Err(e) = stream_tar_export(&tx, &transfer_id, &rootfs_dir, estimated_size).await {
-        let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::ExportFailed {
-            transfer_id, error: e,
-        })).await;
-        return;
-    }
+            let ws_from = if from.stream == TransferStream::Workspace { from } else { ResumePoint::default() };
+            if let Err(e) = self.stream_tar(TransferStream::Workspace, &workspace_dir, ws_size, ws_from).await {
+                warn!(container = %self.container_name, "Nspawn workspace export failed (non-fatal): {}", e);
+            }
+        }
 
-    // 6. Stream workspace if directory exists
-    let ws_path = std::path::Path::new(&workspace_dir);
-    if ws_path.exists() {
-        let ws_size = estimate_dir_size(&workspace_dir).await;
-        let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::WorkspaceReady {
+        // 7. Send TransferComplete
+        let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::TransferComplete {
             transfer_id: transfer_id.clone(),
-            size_bytes: ws_size,
         })).await;
 
-        if let Err(e) = stream_tar_export(&tx, &transfer_id, &workspace_dir, ws_size).await {
-            warn!(container = %container_name, "Nspawn workspace export failed (non-fatal): {}", e);
+        info!(transfer_id = %transfer_id, "Nspawn export complete");
+    }
+
+    /// Stream a directory via tar to the WebSocket channel, compressed as
+    /// requested, from `from` on, then send its manifest.
+    async fn stream_tar(
+        &self,
+        stream: TransferStream,
+        dir_path: &str,
+        estimated_size: u64,
+        from: ResumePoint,
+    ) -> Result<(), String> {
+        let transfer_id = self.transfer_id.as_str();
+        let mut child = tokio::process::Command::new("tar")
+            .args(["cf", "-", "--numeric-owner", "--xattrs", "--xattrs-include=*", "-C", dir_path, "."])
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to spawn tar: {e}"))?;
+
+        let stdout = child.stdout.take()
+            .ok_or_else(|| "Failed to get tar stdout".to_string())?;
+        let (stdout, tar_bytes) = Counted::new(stdout);
+        let mut reader = ChunkReader::new(self.compression.encoder(stdout));
+
+        if from.next_sequence > 0 {
+            reader.skip_to(from.next_sequence, from.last_checksum).await?;
+            info!(transfer_id = %transfer_id, ?stream, sequence = from.next_sequence, "Export resumed");
+        }
+
+        let mut send_failed = false;
+
+        loop {
+            let (entry, data) = match reader.next_chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(e) => {
+                    child.kill().await.ok();
+                    return Err(format!("Read error from tar stdout: {e}"));
+                }
+            };
+
+            if self.tx.send(OutgoingWsMessage::Chunk(
+                HostAgentMessage::TransferChunkBinary {
+                    transfer_id: transfer_id.to_string(),
+                    sequence: entry.sequence,
+                    size: entry.size,
+                    checksum: entry.checksum,
+                    offset: entry.offset,
+                },
+                data.to_vec(),
+            )).await.is_err() {
+                send_failed = true;
+                break;
+            }
+
+            let sent = entry.sequence + 1;
+            let total_sent = tar_bytes.load(std::sync::atomic::Ordering::Relaxed);
+            if sent % 4 == 0 && estimated_size > 0 {
+                info!(
+                    transfer_id = %transfer_id,
+                    sent_bytes = total_sent,
+                    estimated_bytes = estimated_size,
+                    "Export progress: {:.1}%",
+                    (total_sent as f64 / estimated_size as f64 * 100.0).min(100.0)
+                );
+            }
+        }
+
+        let status = child.wait().await
+            .map_err(|e| format!("Wait for tar: {e}"))?;
+
+        if send_failed {
+            return Err("Transfer channel closed during export".to_string());
+        }
+
+        if !status.success() {
+            return Err(format!("tar exited with status: {}", status));
         }
+
+        let (chunk_count, total_bytes, digest) = reader.summary();
+        if self.resumable {
+            let _ = self.tx.send(OutgoingWsMessage::Text(HostAgentMessage::TransferManifest {
+                transfer_id: transfer_id.to_string(),
+                stream,
+                chunk_count,
+                total_bytes,
+                digest,
+            })).await;
+        }
+
+        info!(transfer_id = %transfer_id, total_bytes, tar_bytes = tar_bytes.load(std::sync::atomic::Ordering::Relaxed), "Tar export stream complete");
+        Ok(())
     }
+}
 
-    // 7. Send TransferComplete
-    let _ = tx.send(OutgoingWsMessage::Text(HostAgentMessage::TransferComplete {
-        transfer_id: transfer_id.clone(),
-    })).await;
+/// Trade the join code for a token, saved in the config for the next
+/// connection.
+async fn enroll(config: &Config, join_code: &str) -> Result<(), String> {
+    let url = config.ws_url();
+    info!(url, "Enrolling with HomeRoute");
 
-    info!(transfer_id = %transfer_id, "Nspawn export complete");
+    let (mut ws_stream, _) = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        connect_async(&url),
+    )
+    .await
+    .map_err(|_| "WebSocket connect timeout (10s)".to_string())?
+    .map_err(|e| format!("WebSocket connect failed: {}", e))?;
+
+    let enroll = HostAgentMessage::Enroll {
+        join_code: join_code.to_string(),
+        host_name: config.host_name.clone(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        lan_interface: config.lan_interface.clone(),
+        container_storage_path: config.container_storage_path.clone(),
+        interfaces: collect_interfaces(),
+    };
+    let json = serde_json::to_string(&enroll).map_err(|e| e.to_string())?;
+    ws_stream.send(Message::Text(json.into())).await.map_err(|e| e.to_string())?;
+
+    let response = tokio::time::timeout(std::time::Duration::from_secs(10), ws_stream.next())
+        .await
+        .map_err(|_| "Enrollment timeout".to_string())?
+        .ok_or("Connection closed during enrollment")?
+        .map_err(|e| format!("WebSocket error: {}", e))?;
+    let Message::Text(text) = response else {
+        return Err("Unexpected message type during enrollment".to_string());
+    };
+    match serde_json::from_str(&text).map_err(|e| format!("Parse enrollment response: {}", e))? {
+        HostRegistryMessage::EnrollResult { success: true, token: Some(token), host_name, .. } => {
+            let host_name = host_name.unwrap_or_else(|| config.host_name.clone());
+            config.save_enrollment(&token, &host_name)?;
+            info!(host = host_name, "Enrolled, connecting with the new token");
+            Ok(())
+        }
+        HostRegistryMessage::EnrollResult { error, .. } => {
+            Err(format!("Enrollment failed: {}", error.unwrap_or_default()))
+        }
+        _ => Err("Unexpected enrollment response".to_string()),
+    }
 }
 
 async fn estimate_dir_size(dir: &str) -> u64 {
@@ -1104,89 +1659,16 @@ async fn estimate_dir_size(dir: &str) -> u64 {
     }
 }
 
-/// Stream a directory via tar to the WebSocket channel.
-async fn stream_tar_export(
-    tx: &tokio::sync::mpsc::Sender<OutgoingWsMessage>,
-    transfer_id: &str,
-    dir_path: &str,
-    estimated_size: u64,
-) -> Result<(), String> {
-    use tokio::io::AsyncReadExt;
-
-    let mut child = tokio::process::Command::new("tar")
-        .args(["cf", "-", "--numeric-owner", "--xattrs", "--xattrs-include=*", "-C", dir_path, "."])
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .map_err(|e| format!("Failed to spawn tar: {e}"))?;
-
-    let mut stdout = child.stdout.take()
-        .ok_or_else(|| "Failed to get tar stdout".to_string())?;
-
-    let mut buf = vec![0u8; 524288]; // 512KB
-    let mut sequence: u32 = 0;
-    let mut send_failed = false;
-    let mut total_sent: u64 = 0;
-
-    loop {
-        let n = match stdout.read(&mut buf).await {
-            Ok(0) => break,
-            Ok(n) => n,
-            Err(e) => {
-                child.kill().await.ok();
-                return Err(format!("Read error from tar stdout: {e}"));
-            }
-        };
-
-        let checksum = xxhash_rust::xxh32::xxh32(&buf[..n], 0);
-
-        if tx.send(OutgoingWsMessage::Text(HostAgentMessage::TransferChunkBinary {
-            transfer_id: transfer_id.to_string(),
-            sequence,
-            size: n as u32,
-            checksum,
-        })).await.is_err() {
-            send_failed = true;
-            break;
-        }
-
-        if tx.send(OutgoingWsMessage::Binary(buf[..n].to_vec())).await.is_err() {
-            send_failed = true;
-            break;
-        }
-
-        sequence += 1;
-        total_sent += n as u64;
-
-        if sequence % 4 == 0 && estimated_size > 0 {
-            info!(
-                transfer_id = %transfer_id,
-                sent_bytes = total_sent,
-                estimated_bytes = estimated_size,
-                "Export progress: {:.1}%",
-                (total_sent as f64 / estimated_size as f64 * 100.0).min(100.0)
-            );
-        }
-    }
-
-    let status = child.wait().await
-        .map_err(|e| format!("Wait for tar: {e}"))?;
-
-    if send_failed {
-        return Err("Transfer channel closed during export".to_string());
-    }
-
-    if !status.success() {
-        return Err(format!("tar exited with status: {}", status));
-    }
-
-    info!(transfer_id = %transfer_id, total_bytes = total_sent, "Tar export stream complete");
-    Ok(())
+/// The manifest of a pushed update, once signed by the pinned key and
+/// meant for this host.
+fn verify_update(config: &Config, signed: &SignedManifest, registry_protocol: u32) -> Result<UpdateManifest, String> {
+    let key = config.update_key.as_deref().ok_or("No update_key in the config")?;
+    let manifest = signed.verify(key)?;
+    manifest.check("hr-host-agent", env!("CARGO_PKG_VERSION"), registry_protocol)?;
+    Ok(manifest)
 }
 
-async fn self_update(download_url: &str, expected_sha256: &str) -> Result<(), String> {
-    use sha2::{Sha256, Digest};
-
+async fn self_update(download_url: &str, manifest: &UpdateManifest) -> Result<(), String> {
     let current_exe = std::env::current_exe()
         .map_err(|e| format!("Cannot determine current exe: {}", e))?;
     let tmp_path = format!("{}.new", current_exe.display());
@@ -1205,13 +1687,9 @@ async fn self_update(download_url: &str, expected_sha256: &str) -> Result<(), St
 
     let data = std::fs::read(&tmp_path)
         .map_err(|e| format!("Read downloaded binary: {}", e))?;
-    let mut hasher = Sha256::new();
-    hasher.update(&data);
-    let actual_sha256 = hex::encode(hasher.finalize());
-
-    if actual_sha256 != expected_sha256 {
+    if !manifest.matches(&data) {
         let _ = tokio::fs::remove_file(&tmp_path).await;
-        return Err(format!("SHA256 mismatch: expected {}, got {}", expected_sha256, actual_sha256));
+        return Err(format!("SHA256 mismatch: expected {}", manifest.sha256));
     }
 
     let _ = tokio::process::Command::new("chmod")
@@ -1334,6 +1812,8 @@ fn collect_metrics() -> HostMetrics {
         }
     };
 
+    let sensors = sensors::collect();
+
     HostMetrics {
         cpu_percent: load_avg[0] * 100.0 / num_cpus().max(1) as f32,
         memory_used_bytes: mem_total.saturating_sub(mem_available),
@@ -1341,6 +1821,10 @@ fn collect_metrics() -> HostMetrics {
         disk_used_bytes: disk_used,
         disk_total_bytes: disk_total,
         load_avg,
+        cpu_temp_c: sensors.cpu_temp_c,
+        nvme_temps: sensors.nvme_temps,
+        fans: sensors.fans,
+        gpus: sensors.gpus,
     }
 }
 
@@ -0,0 +1,299 @@
+//! OS package updates of the host (apt or dnf): pending updates are
+//! reported to the registry, which can ask for an upgrade, optionally
+//! preceded by a snapshot of the root filesystem (snapper, btrfs or zfs).
+
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use hr_registry::protocol::{PackageStatus, PackageUpdate};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+/// Package lists are refreshed a few times a day.
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(6 * 3600);
+const APT_REBOOT_FLAG: &str = "/var/run/reboot-required";
+const BTRFS_SNAPSHOT_DIR: &str = "/.hr-snapshots";
+
+/// An upgrade is running: one at a time, and no auto-off meanwhile.
+static UPGRADING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Manager {
+    Apt,
+    Dnf,
+}
+
+impl Manager {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Apt => "apt",
+            Self::Dnf => "dnf",
+        }
+    }
+}
+
+pub fn upgrading() -> bool {
+    UPGRADING.load(Ordering::Relaxed)
+}
+
+async fn detect() -> Option<Manager> {
+    for manager in [Manager::Apt, Manager::Dnf] {
+        let binary = match manager {
+            Manager::Apt => "apt-get",
+            Manager::Dnf => "dnf",
+        };
+        if Command::new(binary).arg("--version").output().await.is_ok_and(|o| o.status.success()) {
+            return Some(manager);
+        }
+    }
+    None
+}
+
+/// Refresh the package lists and list the pending updates.
+pub async fn check() -> Result<PackageStatus, String> {
+    let manager = detect().await.ok_or("No supported package manager (apt or dnf)")?;
+    let (updates, reboot_required) = match manager {
+        Manager::Apt => {
+            run(Command::new("apt-get").args(["update", "-qq"])).await?;
+            let listed = run(Command::new("apt").args(["list", "--upgradable"])).await?;
+            (parse_apt_upgradable(&listed), std::path::Path::new(APT_REBOOT_FLAG).exists())
+        }
+        Manager::Dnf => {
+            // Exits with 100 when updates are available
+            let output = Command::new("dnf")
+                .args(["-q", "check-update"])
+                .output()
+                .await
+                .map_err(|e| format!("failed to run dnf: {e}"))?;
+            if !matches!(output.status.code(), Some(0 | 100)) {
+                return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+            }
+            let mut updates = parse_dnf_check_update(&String::from_utf8_lossy(&output.stdout));
+            // Advisories are optional: without them nothing is flagged
+            if let Ok(advisories) = run(Command::new("dnf").args(["-q", "updateinfo", "list", "--security"])).await {
+                flag_dnf_security(&mut updates, &advisories);
+            }
+            let reboot_required = Command::new("dnf")
+                .args(["-q", "needs-restarting", "-r"])
+                .output()
+                .await
+                .is_ok_and(|o| o.status.code() == Some(1));
+            (updates, reboot_required)
+        }
+    };
+    Ok(PackageStatus {
+        manager: manager.name().to_string(),
+        updates,
+        reboot_required,
+        checked_at: unix_now(),
+    })
+}
+
+/// Upgrade the packages, sending each output line to `output`. Returns the
+/// snapshot taken first, if asked for; no upgrade happens when it fails.
+pub async fn upgrade(full: bool, snapshot: bool, output: mpsc::Sender<String>) -> Result<Option<String>, String> {
+    if UPGRADING.swap(true, Ordering::SeqCst) {
+        return Err("An upgrade is already running".to_string());
+    }
+    let result = run_upgrade(full, snapshot, &output).await;
+    UPGRADING.store(false, Ordering::SeqCst);
+    result
+}
+
+async fn run_upgrade(full: bool, snapshot: bool, output: &mpsc::Sender<String>) -> Result<Option<String>, String> {
+    let manager = detect().await.ok_or("No supported package manager (apt or dnf)")?;
+    let taken = if snapshot {
+        let name = take_snapshot().await.map_err(|e| format!("Snapshot failed, nothing upgraded: {e}"))?;
+        let _ = output.send(format!("Snapshot: {name}")).await;
+        Some(name)
+    } else {
+        None
+    };
+    match manager {
+        Manager::Apt => {
+            let mut update = Command::new("apt-get");
+            update.args(["update"]);
+            stream(update, output).await?;
+            let mut upgrade = Command::new("apt-get");
+            upgrade
+                .args(["-y", "-o", "Dpkg::Options::=--force-confdef", "-o", "Dpkg::Options::=--force-confold"])
+                .arg(if full { "dist-upgrade" } else { "upgrade" })
+                .env("DEBIAN_FRONTEND", "noninteractive");
+            stream(upgrade, output).await?;
+        }
+        Manager::Dnf => {
+            let mut upgrade = Command::new("dnf");
+            upgrade.args(["-y", "upgrade"]);
+            if full {
+                upgrade.arg("--allowerasing");
+            }
+            stream(upgrade, output).await?;
+        }
+    }
+    Ok(taken)
+}
+
+/// Snapshot of the root filesystem: snapper when installed, else a
+/// read-only btrfs subvolume or a zfs snapshot of the root dataset.
+async fn take_snapshot() -> Result<String, String> {
+    let stamp = unix_now();
+    if let Ok(number) = run(Command::new("snapper").args(["create", "--description", "homeroute upgrade", "--print-number"])).await {
+        return Ok(format!("snapper #{}", number.trim()));
+    }
+    let root = run(Command::new("findmnt").args(["-no", "FSTYPE,SOURCE", "/"])).await?;
+    let mut fields = root.split_whitespace();
+    match (fields.next(), fields.next()) {
+        (Some("btrfs"), _) => {
+            let target = format!("{BTRFS_SNAPSHOT_DIR}/upgrade-{stamp}");
+            tokio::fs::create_dir_all(BTRFS_SNAPSHOT_DIR).await.map_err(|e| e.to_string())?;
+            run(Command::new("btrfs").args(["subvolume", "snapshot", "-r", "/", &target])).await?;
+            Ok(target)
+        }
+        (Some("zfs"), Some(dataset)) => {
+            let name = format!("{dataset}@homeroute-upgrade-{stamp}");
+            run(Command::new("zfs").args(["snapshot", &name])).await?;
+            Ok(name)
+        }
+        (fstype, _) => Err(format!("root filesystem {} has no snapshots", fstype.unwrap_or("?"))),
+    }
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+async fn run(command: &mut Command) -> Result<String, String> {
+    let program = command.as_std().get_program().to_string_lossy().to_string();
+    let output = command.output().await.map_err(|e| format!("failed to run {program}: {e}"))?;
+    if !output.status.success() {
+        return Err(format!("{program}: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Run `command`, forwarding its stdout and stderr lines.
+async fn stream(mut command: Command, output: &mpsc::Sender<String>) -> Result<(), String> {
+    let program = command.as_std().get_program().to_string_lossy().to_string();
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run {program}: {e}"))?;
+    let stdout = forward(child.stdout.take(), output.clone());
+    let stderr = forward(child.stderr.take(), output.clone());
+    let status = child.wait().await.map_err(|e| e.to_string())?;
+    let _ = tokio::join!(stdout, stderr);
+    if !status.success() {
+        return Err(format!("{program} exited with {status}"));
+    }
+    Ok(())
+}
+
+fn forward<R: AsyncRead + Unpin + Send + 'static>(
+    pipe: Option<R>,
+    output: mpsc::Sender<String>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let Some(pipe) = pipe else {
+            return;
+        };
+        let mut lines = BufReader::new(pipe).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let _ = output.send(line).await;
+        }
+    })
+}
+
+/// `openssl/jammy-security 3.0.2-0ubuntu1.15 amd64 [upgradable from: 3.0.2-0ubuntu1.14]`
+fn parse_apt_upgradable(output: &str) -> Vec<PackageUpdate> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (name, source) = fields.next()?.split_once('/')?;
+            let new_version = fields.next()?;
+            let current_version = line
+                .split_once("upgradable from: ")
+                .map(|(_, v)| v.trim_end_matches(']').to_string())
+                .unwrap_or_default();
+            Some(PackageUpdate {
+                name: name.to_string(),
+                current_version,
+                new_version: new_version.to_string(),
+                security: source.split(',').any(|s| s.ends_with("-security")),
+            })
+        })
+        .collect()
+}
+
+/// `openssl-libs.x86_64   1:3.2.1-2.fc40   updates`, up to the obsoleted
+/// packages section.
+fn parse_dnf_check_update(output: &str) -> Vec<PackageUpdate> {
+    output
+        .lines()
+        .take_while(|line| !line.starts_with("Obsoleting"))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [package, version, _repo] = fields[..] else {
+                return None;
+            };
+            let (name, _arch) = package.rsplit_once('.')?;
+            Some(PackageUpdate {
+                name: name.to_string(),
+                new_version: version.to_string(),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Flag the updates named by security advisories
+/// (`FEDORA-2024-1a2b  Important/Sec.  openssl-libs-1:3.2.1-2.fc40.x86_64`).
+fn flag_dnf_security(updates: &mut [PackageUpdate], advisories: &str) {
+    let packages: Vec<&str> = advisories.lines().filter_map(|line| line.split_whitespace().nth(2)).collect();
+    for update in updates {
+        let nevr = format!("{}-{}.", update.name, update.new_version);
+        update.security = packages.iter().any(|p| p.starts_with(&nevr));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_apt_upgradable() {
+        let output = "Listing...\n\
+            openssl/jammy-updates,jammy-security 3.0.2-0ubuntu1.15 amd64 [upgradable from: 3.0.2-0ubuntu1.14]\n\
+            curl/jammy-updates 7.81.0-1ubuntu1.16 amd64 [upgradable from: 7.81.0-1ubuntu1.15]\n";
+        let updates = parse_apt_upgradable(output);
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].name, "openssl");
+        assert_eq!(updates[0].current_version, "3.0.2-0ubuntu1.14");
+        assert_eq!(updates[0].new_version, "3.0.2-0ubuntu1.15");
+        assert!(updates[0].security);
+        assert!(!updates[1].security);
+    }
+
+    #[test]
+    fn test_parse_dnf() {
+        let output = "\n\
+            kernel.x86_64                  6.8.9-300.fc40          updates\n\
+            openssl-libs.x86_64            1:3.2.1-2.fc40          updates\n\
+            Obsoleting Packages\n\
+            grub2-tools.x86_64             1:2.06-120.fc40         updates\n";
+        let mut updates = parse_dnf_check_update(output);
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[1].name, "openssl-libs");
+        assert_eq!(updates[1].new_version, "1:3.2.1-2.fc40");
+
+        flag_dnf_security(&mut updates, "FEDORA-2024-1a2b Important/Sec. openssl-libs-1:3.2.1-2.fc40.x86_64\n");
+        assert!(!updates[0].security);
+        assert!(updates[1].security);
+    }
+}
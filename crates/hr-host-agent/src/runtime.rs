@@ -0,0 +1,248 @@
+//! Docker and Podman containers running on the host next to the nspawn ones.
+//!
+//! Both are driven through their CLI: `ps` is reported to the registry so it
+//! can suggest proxy routes for the published ports, and single containers
+//! can be started, stopped or inspected on request.
+
+use hr_registry::protocol::{ContainerRuntime, PublishedPort, RuntimeAction, RuntimeContainerInfo};
+use serde::Deserialize;
+use tokio::process::Command;
+
+const RUNTIMES: [ContainerRuntime; 2] = [ContainerRuntime::Docker, ContainerRuntime::Podman];
+
+/// Runtimes whose CLI is installed and reaches its daemon (Docker) or
+/// storage (Podman).
+pub async fn detect() -> Vec<ContainerRuntime> {
+    let mut found = Vec::new();
+    for runtime in RUNTIMES {
+        let answers = Command::new(runtime.binary())
+            .args(["ps", "-q"])
+            .output()
+            .await
+            .is_ok_and(|o| o.status.success());
+        if answers {
+            found.push(runtime);
+        }
+    }
+    found
+}
+
+async fn run(runtime: ContainerRuntime, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(runtime.binary())
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("failed to run {}: {e}", runtime.binary()))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// All containers of the detected runtimes, stopped ones included. A
+/// runtime failing to answer is left out of the report.
+pub async fn list_all(runtimes: &[ContainerRuntime]) -> Vec<RuntimeContainerInfo> {
+    let mut containers = Vec::new();
+    for &runtime in runtimes {
+        let listed = match runtime {
+            ContainerRuntime::Docker => run(runtime, &["ps", "-a", "--no-trunc", "--format", "{{json .}}"])
+                .await
+                .map(|out| parse_docker_ps(&out)),
+            ContainerRuntime::Podman => run(runtime, &["ps", "-a", "--format", "json"])
+                .await
+                .and_then(|out| parse_podman_ps(&out)),
+        };
+        match listed {
+            Ok(list) => containers.extend(list),
+            Err(e) => tracing::warn!(runtime = runtime.binary(), "Listing containers failed: {e}"),
+        }
+    }
+    containers
+}
+
+/// Run `action` on a container, returning the `inspect` JSON or the
+/// runtime's output.
+pub async fn action(runtime: ContainerRuntime, container: &str, action: RuntimeAction) -> Result<String, String> {
+    // Never let a name be read as an option of the CLI
+    if container.is_empty() || container.starts_with('-') {
+        return Err(format!("Invalid container name: {container}"));
+    }
+    let verb = match action {
+        RuntimeAction::Start => "start",
+        RuntimeAction::Stop => "stop",
+        RuntimeAction::Inspect => "inspect",
+    };
+    run(runtime, &[verb, container]).await
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DockerPsLine {
+    #[serde(rename = "ID")]
+    id: String,
+    image: String,
+    names: String,
+    state: String,
+    status: String,
+    #[serde(default)]
+    ports: String,
+}
+
+/// `docker ps --format '{{json .}}'`: one object per line, ports as text.
+fn parse_docker_ps(output: &str) -> Vec<RuntimeContainerInfo> {
+    output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<DockerPsLine>(line).ok())
+        .map(|c| RuntimeContainerInfo {
+            runtime: ContainerRuntime::Docker,
+            id: c.id,
+            name: c.names.split(',').next().unwrap_or_default().to_string(),
+            image: c.image,
+            state: c.state,
+            status: c.status,
+            ports: parse_docker_ports(&c.ports),
+        })
+        .collect()
+}
+
+/// `0.0.0.0:8080->80/tcp, :::8080->80/tcp, 443/tcp`: published mappings only,
+/// ranges expanded, one entry per host port and protocol.
+fn parse_docker_ports(ports: &str) -> Vec<PublishedPort> {
+    let mut published: Vec<PublishedPort> = Vec::new();
+    for mapping in ports.split(", ") {
+        let Some((host, container)) = mapping.split_once("->") else { continue };
+        let Some((host_ip, host_ports)) = host.rsplit_once(':') else { continue };
+        let (container_ports, protocol) = container.split_once('/').unwrap_or((container, "tcp"));
+        let (Some(host_ports), Some(container_ports)) = (port_range(host_ports), port_range(container_ports)) else {
+            continue;
+        };
+        let host_ip = host_ip.trim_start_matches('[').trim_end_matches(']');
+        let host_ip = match host_ip {
+            "" | "0.0.0.0" | "::" => None,
+            ip => Some(ip.to_string()),
+        };
+        for (host_port, container_port) in host_ports.zip(container_ports) {
+            let seen = published.iter().any(|p| p.host_port == host_port && p.protocol == protocol);
+            if !seen {
+                published.push(PublishedPort { host_ip: host_ip.clone(), host_port, container_port, protocol: protocol.to_string() });
+            }
+        }
+    }
+    published
+}
+
+/// `8080` or `8080-8090`.
+fn port_range(ports: &str) -> Option<std::ops::RangeInclusive<u16>> {
+    match ports.split_once('-') {
+        Some((first, last)) => Some(first.parse().ok()?..=last.parse().ok()?),
+        None => {
+            let port = ports.parse().ok()?;
+            Some(port..=port)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PodmanPsEntry {
+    id: String,
+    image: String,
+    names: Vec<String>,
+    state: String,
+    status: String,
+    #[serde(default)]
+    ports: Option<Vec<PodmanPort>>,
+}
+
+#[derive(Deserialize)]
+struct PodmanPort {
+    #[serde(default)]
+    host_ip: String,
+    container_port: u16,
+    host_port: u16,
+    #[serde(default = "one")]
+    range: u16,
+    protocol: String,
+}
+
+fn one() -> u16 {
+    1
+}
+
+/// `podman ps --format json`: a JSON array with structured ports.
+fn parse_podman_ps(output: &str) -> Result<Vec<RuntimeContainerInfo>, String> {
+    let entries: Vec<PodmanPsEntry> =
+        serde_json::from_str(output).map_err(|e| format!("unexpected podman ps output: {e}"))?;
+    Ok(entries
+        .into_iter()
+        .map(|c| RuntimeContainerInfo {
+            runtime: ContainerRuntime::Podman,
+            id: c.id,
+            name: c.names.into_iter().next().unwrap_or_default(),
+            image: c.image,
+            state: c.state,
+            status: c.status,
+            ports: c
+                .ports
+                .unwrap_or_default()
+                .into_iter()
+                .flat_map(|p| {
+                    let host_ip = (!p.host_ip.is_empty() && p.host_ip != "0.0.0.0").then_some(p.host_ip);
+                    (0..p.range.max(1)).map(move |i| PublishedPort {
+                        host_ip: host_ip.clone(),
+                        host_port: p.host_port.saturating_add(i),
+                        container_port: p.container_port.saturating_add(i),
+                        protocol: p.protocol.clone(),
+                    })
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_docker_ps() {
+        let output = concat!(
+            r#"{"ID":"abc","Image":"nginx:latest","Names":"web","State":"running","Status":"Up 2 hours","Ports":"0.0.0.0:8080->80/tcp, :::8080->80/tcp, 127.0.0.1:5353->53/udp, 9000-9001->9000-9001/tcp, 443/tcp"}"#,
+            "\n",
+            r#"{"ID":"def","Image":"redis","Names":"cache","State":"exited","Status":"Exited (0) 3 days ago","Ports":""}"#,
+            "\n",
+        );
+        let containers = parse_docker_ps(output);
+        assert_eq!(containers.len(), 2);
+        assert_eq!(containers[0].name, "web");
+        let ports: Vec<(Option<&str>, u16, u16, &str)> = containers[0]
+            .ports
+            .iter()
+            .map(|p| (p.host_ip.as_deref(), p.host_port, p.container_port, p.protocol.as_str()))
+            .collect();
+        assert_eq!(
+            ports,
+            [
+                (None, 8080, 80, "tcp"),
+                (Some("127.0.0.1"), 5353, 53, "udp"),
+                (None, 9000, 9000, "tcp"),
+                (None, 9001, 9001, "tcp"),
+            ]
+        );
+        assert!(containers[1].ports.is_empty());
+    }
+
+    #[test]
+    fn test_parse_podman_ps() {
+        let output = r#"[
+            {"Id":"123","Image":"docker.io/library/nginx:latest","Names":["proxy"],"State":"running","Status":"Up 5 minutes",
+             "Ports":[{"host_ip":"","container_port":80,"host_port":8081,"range":2,"protocol":"tcp"}]},
+            {"Id":"456","Image":"alpine","Names":["job"],"State":"exited","Status":"Exited (1)","Ports":null}
+        ]"#;
+        let containers = parse_podman_ps(output).unwrap();
+        assert_eq!(containers[0].runtime, ContainerRuntime::Podman);
+        assert_eq!(containers[0].ports.iter().map(|p| (p.host_port, p.container_port)).collect::<Vec<_>>(), [(8081, 80), (8082, 81)]);
+        assert!(containers[1].ports.is_empty());
+        assert!(parse_podman_ps("not json").is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! Temperatures, fans and GPUs of the host: hwmon sensors, NVIDIA GPUs
+//! through `nvidia-smi` and AMD ones through their sysfs files. Hosts
+//! without them report nothing.
+
+use std::path::Path;
+
+use hr_registry::protocol::{GpuMetrics, SensorReading};
+
+const HWMON_DIR: &str = "/sys/class/hwmon";
+const DRM_DIR: &str = "/sys/class/drm";
+/// hwmon drivers of CPU temperature sensors, and the label of their
+/// package (or die) sensor when they have several.
+const CPU_CHIPS: &[(&str, &[&str])] = &[
+    ("coretemp", &["Package id 0"]),
+    ("k10temp", &["Tdie", "Tctl"]),
+    ("zenpower", &["Tdie", "Tctl"]),
+    ("cpu_thermal", &[]),
+    ("soc_thermal", &[]),
+];
+const AMD_VENDOR: &str = "0x1002";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Sensors {
+    pub cpu_temp_c: Option<f32>,
+    pub nvme_temps: Vec<SensorReading>,
+    pub fans: Vec<SensorReading>,
+    pub gpus: Vec<GpuMetrics>,
+}
+
+pub fn collect() -> Sensors {
+    let mut sensors = read_hwmon(Path::new(HWMON_DIR));
+    sensors.gpus = nvidia_gpus();
+    sensors.gpus.extend(amd_gpus(Path::new(DRM_DIR)));
+    sensors
+}
+
+fn read_hwmon(dir: &Path) -> Sensors {
+    let mut sensors = Sensors::default();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return sensors;
+    };
+    let mut chips: Vec<_> = entries.flatten().map(|e| e.path()).collect();
+    chips.sort();
+    for chip in chips {
+        let name = read_trimmed(&chip.join("name")).unwrap_or_default();
+        if let Some((_, labels)) = CPU_CHIPS.iter().find(|(driver, _)| *driver == name)
+            && sensors.cpu_temp_c.is_none()
+        {
+            sensors.cpu_temp_c = cpu_temp(&chip, labels);
+        }
+        if name == "nvme"
+            && let Some(temp) = read_number(&chip.join("temp1_input"))
+        {
+            // hwmonN/device links to the controller (nvme0)
+            let drive = std::fs::read_link(chip.join("device"))
+                .ok()
+                .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+                .unwrap_or_else(|| format!("nvme{}", sensors.nvme_temps.len()));
+            sensors.nvme_temps.push(SensorReading { name: drive, value: temp / 1000.0 });
+        }
+        for index in 1..=16 {
+            let Some(rpm) = read_number(&chip.join(format!("fan{index}_input"))) else {
+                continue;
+            };
+            let label = read_trimmed(&chip.join(format!("fan{index}_label")))
+                .unwrap_or_else(|| format!("{name} fan{index}"));
+            sensors.fans.push(SensorReading { name: label, value: rpm });
+        }
+    }
+    sensors
+}
+
+/// Temperature of the sensor labelled with one of `labels`, or the
+/// hottest one of the chip.
+fn cpu_temp(chip: &Path, labels: &[&str]) -> Option<f32> {
+    let temps: Vec<(String, f32)> = (1..=64)
+        .filter_map(|index| {
+            let value = read_number(&chip.join(format!("temp{index}_input")))?;
+            let label = read_trimmed(&chip.join(format!("temp{index}_label"))).unwrap_or_default();
+            Some((label, value / 1000.0))
+        })
+        .collect();
+    labels
+        .iter()
+        .find_map(|wanted| temps.iter().find(|(label, _)| label == wanted).map(|(_, t)| *t))
+        .or_else(|| temps.iter().map(|(_, t)| *t).reduce(f32::max))
+}
+
+fn nvidia_gpus() -> Vec<GpuMetrics> {
+    let output = std::process::Command::new("nvidia-smi")
+        .args([
+            "--query-gpu=name,utilization.gpu,memory.used,memory.total,temperature.gpu",
+            "--format=csv,noheader,nounits",
+        ])
+        .output();
+    match output {
+        Ok(o) if o.status.success() => parse_nvidia_smi(&String::from_utf8_lossy(&o.stdout)),
+        _ => Vec::new(),
+    }
+}
+
+/// One GPU per line: `name, utilization %, used MiB, total MiB, °C`
+/// (`[N/A]` for what the GPU does not report).
+fn parse_nvidia_smi(output: &str) -> Vec<GpuMetrics> {
+    const MIB: u64 = 1024 * 1024;
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [name, utilization, used, total, temp] = fields[..] else {
+                return None;
+            };
+            Some(GpuMetrics {
+                vendor: "nvidia".to_string(),
+                name: name.to_string(),
+                utilization_percent: utilization.parse().ok(),
+                vram_used_bytes: used.parse::<u64>().ok().map(|m| m * MIB),
+                vram_total_bytes: total.parse::<u64>().ok().map(|m| m * MIB),
+                temp_c: temp.parse().ok(),
+            })
+        })
+        .collect()
+}
+
+fn amd_gpus(dir: &Path) -> Vec<GpuMetrics> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut cards: Vec<_> = entries
+        .flatten()
+        .map(|e| e.path())
+        // cardN, not its connectors (cardN-DP-1)
+        .filter(|p| p.file_name().is_some_and(|n| n.to_string_lossy().starts_with("card") && !n.to_string_lossy().contains('-')))
+        .collect();
+    cards.sort();
+    cards
+        .into_iter()
+        .filter_map(|card| {
+            let device = card.join("device");
+            if read_trimmed(&device.join("vendor")).as_deref() != Some(AMD_VENDOR) {
+                return None;
+            }
+            let utilization = read_number(&device.join("gpu_busy_percent"));
+            let temp = std::fs::read_dir(device.join("hwmon"))
+                .ok()
+                .and_then(|mut hwmons| hwmons.find_map(|h| read_number(&h.ok()?.path().join("temp1_input"))));
+            let card_name = card.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            Some(GpuMetrics {
+                vendor: "amd".to_string(),
+                name: read_trimmed(&device.join("product_name")).unwrap_or_else(|| format!("AMD {card_name}")),
+                utilization_percent: utilization,
+                vram_used_bytes: read_trimmed(&device.join("mem_info_vram_used")).and_then(|v| v.parse().ok()),
+                vram_total_bytes: read_trimmed(&device.join("mem_info_vram_total")).and_then(|v| v.parse().ok()),
+                temp_c: temp.map(|t| t / 1000.0),
+            })
+        })
+        .collect()
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    let value = std::fs::read_to_string(path).ok()?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+fn read_number(path: &Path) -> Option<f32> {
+    read_trimmed(path)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_read_hwmon() {
+        let dir = std::env::temp_dir().join(format!("hr-sensors-{}", std::process::id()));
+        write(&dir.join("hwmon0/name"), "acpitz\n");
+        write(&dir.join("hwmon0/temp1_input"), "27800\n");
+        write(&dir.join("hwmon1/name"), "coretemp\n");
+        write(&dir.join("hwmon1/temp1_input"), "52000\n");
+        write(&dir.join("hwmon1/temp1_label"), "Package id 0\n");
+        write(&dir.join("hwmon1/temp2_input"), "55000\n");
+        write(&dir.join("hwmon1/temp2_label"), "Core 0\n");
+        write(&dir.join("hwmon2/name"), "nvme\n");
+        write(&dir.join("hwmon2/temp1_input"), "38000\n");
+        write(&dir.join("hwmon3/name"), "nct6775\n");
+        write(&dir.join("hwmon3/fan2_input"), "1180\n");
+
+        let sensors = read_hwmon(&dir);
+        assert_eq!(sensors.cpu_temp_c, Some(52.0));
+        assert_eq!(sensors.nvme_temps, [SensorReading { name: "nvme0".into(), value: 38.0 }]);
+        assert_eq!(sensors.fans, [SensorReading { name: "nct6775 fan2".into(), value: 1180.0 }]);
+
+        // No package sensor: the hottest one
+        std::fs::remove_file(dir.join("hwmon1/temp1_label")).unwrap();
+        assert_eq!(read_hwmon(&dir).cpu_temp_c, Some(55.0));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_nvidia_smi() {
+        let gpus = parse_nvidia_smi("NVIDIA GeForce RTX 3060, 37, 1024, 12288, 45\nTesla K80, [N/A], 0, 11441, 30\n");
+        assert_eq!(gpus.len(), 2);
+        assert_eq!(gpus[0].utilization_percent, Some(37.0));
+        assert_eq!(gpus[0].vram_total_bytes, Some(12288 * 1024 * 1024));
+        assert_eq!(gpus[0].temp_c, Some(45.0));
+        assert_eq!(gpus[1].utilization_percent, None);
+        assert!(parse_nvidia_smi("No devices were found").is_empty());
+    }
+
+    #[test]
+    fn test_amd_gpus() {
+        let dir = std::env::temp_dir().join(format!("hr-drm-{}", std::process::id()));
+        write(&dir.join("card0/device/vendor"), "0x1002\n");
+        write(&dir.join("card0/device/gpu_busy_percent"), "12\n");
+        write(&dir.join("card0/device/mem_info_vram_used"), "536870912\n");
+        write(&dir.join("card0/device/mem_info_vram_total"), "8589934592\n");
+        write(&dir.join("card0/device/hwmon/hwmon4/temp1_input"), "48000\n");
+        write(&dir.join("card0-DP-1/status"), "connected\n");
+        write(&dir.join("card1/device/vendor"), "0x8086\n");
+
+        let gpus = amd_gpus(&dir);
+        assert_eq!(gpus.len(), 1);
+        assert_eq!(gpus[0].name, "AMD card0");
+        assert_eq!(gpus[0].utilization_percent, Some(12.0));
+        assert_eq!(gpus[0].vram_used_bytes, Some(536870912));
+        assert_eq!(gpus[0].temp_c, Some(48.0));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
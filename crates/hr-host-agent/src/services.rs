@@ -0,0 +1,128 @@
+//! systemd services of the host, listed and driven through `systemctl`.
+//! The registry decides which services may be touched: it sends the
+//! allowlist with `ListSystemdServices` and checks units before
+//! `SystemdServiceAction`.
+
+use std::collections::BTreeMap;
+
+use hr_registry::protocol::{is_service_unit, service_allowed, SystemdAction, SystemdService};
+use serde::Deserialize;
+use tokio::process::Command;
+
+/// Row of `systemctl list-units --output=json`.
+#[derive(Deserialize)]
+struct UnitRow {
+    unit: String,
+    load: String,
+    active: String,
+    sub: String,
+    #[serde(default)]
+    description: String,
+}
+
+/// Row of `systemctl list-unit-files --output=json`.
+#[derive(Deserialize)]
+struct UnitFileRow {
+    unit_file: String,
+    state: String,
+}
+
+async fn systemctl(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("systemctl")
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("failed to run systemctl: {e}"))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Services matching `patterns`, installed ones included even when
+/// systemd has not loaded them.
+pub async fn list(patterns: &[String]) -> Result<Vec<SystemdService>, String> {
+    let units = systemctl(&["list-units", "--type=service", "--all", "--output=json", "--no-pager"]).await?;
+    let files = systemctl(&["list-unit-files", "--type=service", "--output=json", "--no-pager"]).await?;
+    let mut services = merge(&units, &files)?;
+    services.retain(|s| service_allowed(&s.unit, patterns));
+    Ok(services)
+}
+
+fn merge(units: &str, files: &str) -> Result<Vec<SystemdService>, String> {
+    let units: Vec<UnitRow> = serde_json::from_str(units).map_err(|e| format!("list-units: {e}"))?;
+    let files: Vec<UnitFileRow> = serde_json::from_str(files).map_err(|e| format!("list-unit-files: {e}"))?;
+    let mut services: BTreeMap<String, SystemdService> = BTreeMap::new();
+    for file in files {
+        // Templates (`getty@.service`) only run as instances
+        if file.unit_file.ends_with("@.service") {
+            continue;
+        }
+        services.insert(file.unit_file.clone(), SystemdService {
+            unit: file.unit_file,
+            load_state: "not-loaded".to_string(),
+            active_state: "inactive".to_string(),
+            sub_state: "dead".to_string(),
+            enabled: Some(file.state),
+            ..Default::default()
+        });
+    }
+    for row in units {
+        let service = services.entry(row.unit.clone()).or_default();
+        service.unit = row.unit;
+        service.description = row.description;
+        service.load_state = row.load;
+        service.active_state = row.active;
+        service.sub_state = row.sub;
+    }
+    Ok(services.into_values().collect())
+}
+
+/// Run `action` on `unit`; `Status` returns the `systemctl status` text.
+pub async fn action(unit: &str, action: SystemdAction) -> Result<String, String> {
+    if !is_service_unit(unit) {
+        return Err(format!("Invalid service unit: {unit}"));
+    }
+    let verb = match action {
+        SystemdAction::Start => "start",
+        SystemdAction::Stop => "stop",
+        SystemdAction::Restart => "restart",
+        SystemdAction::Status => {
+            // Exits with 3 for a stopped unit: the text is still the answer
+            let output = Command::new("systemctl")
+                .args(["status", "--no-pager", "--lines=30", "--", unit])
+                .output()
+                .await
+                .map_err(|e| format!("failed to run systemctl: {e}"))?;
+            return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+        }
+    };
+    systemctl(&[verb, "--", unit]).await.map(|_| String::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let units = r#"[
+            {"unit":"nginx.service","load":"loaded","active":"active","sub":"running","description":"A high performance web server"},
+            {"unit":"wg-quick@wg0.service","load":"loaded","active":"failed","sub":"failed","description":"WireGuard via wg-quick(8) for wg0"}
+        ]"#;
+        let files = r#"[
+            {"unit_file":"nginx.service","state":"enabled","preset":"enabled"},
+            {"unit_file":"smbd.service","state":"disabled","preset":"enabled"},
+            {"unit_file":"wg-quick@.service","state":"indirect","preset":"enabled"}
+        ]"#;
+        let services = merge(units, files).unwrap();
+        let units: Vec<&str> = services.iter().map(|s| s.unit.as_str()).collect();
+        assert_eq!(units, ["nginx.service", "smbd.service", "wg-quick@wg0.service"]);
+        assert_eq!(services[0].enabled.as_deref(), Some("enabled"));
+        assert_eq!(services[0].sub_state, "running");
+        assert_eq!(services[1].load_state, "not-loaded");
+        assert_eq!(services[1].active_state, "inactive");
+        assert_eq!(services[2].active_state, "failed");
+        assert_eq!(services[2].enabled, None);
+    }
+}
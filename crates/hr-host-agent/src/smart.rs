@@ -0,0 +1,146 @@
+//! SMART health of the host's disks, read with `smartctl --json`. Hosts
+//! without smartmontools report nothing.
+
+use std::time::Duration;
+
+use hr_registry::protocol::{DiskHealth, SmartAttribute};
+use serde_json::Value;
+use tokio::process::Command;
+use tracing::warn;
+
+/// Disks wear over days: a report every half hour is plenty.
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// ATA attributes also reported as counters.
+const REALLOCATED: u16 = 5;
+const PENDING: u16 = 197;
+const UNCORRECTABLE: u16 = 198;
+
+/// Report of every disk `smartctl --scan` finds.
+pub async fn collect() -> Vec<DiskHealth> {
+    let Some(scan) = smartctl(&["--scan", "--json"]).await else {
+        return Vec::new();
+    };
+    let mut disks = Vec::new();
+    for (name, kind) in scanned_devices(&scan) {
+        match smartctl(&["--all", "--json", "-d", &kind, &name]).await {
+            Some(report) => disks.push(parse_report(&name, &report)),
+            None => warn!(device = %name, "smartctl gave no report"),
+        }
+    }
+    disks
+}
+
+/// JSON output of smartctl. Its exit status is a bit mask that is set for
+/// failing disks too, so only the output is looked at.
+async fn smartctl(args: &[&str]) -> Option<Value> {
+    let output = Command::new("smartctl").args(args).output().await.ok()?;
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+/// `(name, type)` of the devices of a `--scan` output.
+fn scanned_devices(scan: &Value) -> Vec<(String, String)> {
+    let Some(devices) = scan["devices"].as_array() else {
+        return Vec::new();
+    };
+    devices
+        .iter()
+        .filter_map(|d| {
+            let name = d["name"].as_str()?;
+            let kind = d["type"].as_str().unwrap_or("auto");
+            Some((name.to_string(), kind.to_string()))
+        })
+        .collect()
+}
+
+fn parse_report(device: &str, report: &Value) -> DiskHealth {
+    let attributes: Vec<SmartAttribute> = report["ata_smart_attributes"]["table"]
+        .as_array()
+        .map(|table| table.iter().filter_map(parse_attribute).collect())
+        .unwrap_or_default();
+    let raw = |id: u16| attributes.iter().find(|a| a.id == id).map(|a| a.raw);
+    let nvme = &report["nvme_smart_health_information_log"];
+
+    DiskHealth {
+        device: device.to_string(),
+        model: report["model_name"].as_str().map(str::to_string),
+        serial: report["serial_number"].as_str().map(str::to_string),
+        capacity_bytes: report["user_capacity"]["bytes"].as_u64(),
+        passed: report["smart_status"]["passed"].as_bool(),
+        temp_c: report["temperature"]["current"].as_f64().map(|t| t as f32),
+        power_on_hours: report["power_on_time"]["hours"].as_u64(),
+        reallocated_sectors: raw(REALLOCATED),
+        pending_sectors: raw(PENDING),
+        uncorrectable_sectors: raw(UNCORRECTABLE),
+        critical_warning: nvme["critical_warning"].as_u64().map(|w| w as u8),
+        percentage_used: nvme["percentage_used"].as_u64().map(|p| p.min(255) as u8),
+        media_errors: nvme["media_errors"].as_u64(),
+        attributes,
+    }
+}
+
+fn parse_attribute(row: &Value) -> Option<SmartAttribute> {
+    Some(SmartAttribute {
+        id: row["id"].as_u64()? as u16,
+        name: row["name"].as_str().unwrap_or_default().to_string(),
+        value: row["value"].as_u64().unwrap_or(0) as u16,
+        worst: row["worst"].as_u64().unwrap_or(0) as u16,
+        threshold: row["thresh"].as_u64().unwrap_or(0) as u16,
+        raw: row["raw"]["value"].as_u64().unwrap_or(0),
+        failing: row["when_failed"].as_str() == Some("now"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_reports() {
+        let scan = json!({"devices": [
+            {"name": "/dev/sda", "info_name": "/dev/sda [SAT]", "type": "sat", "protocol": "ATA"},
+            {"name": "/dev/nvme0", "type": "nvme", "protocol": "NVMe"}
+        ]});
+        assert_eq!(
+            scanned_devices(&scan),
+            [("/dev/sda".to_string(), "sat".to_string()), ("/dev/nvme0".to_string(), "nvme".to_string())]
+        );
+
+        let ata = json!({
+            "model_name": "WDC WD40EFRX-68N32N0",
+            "serial_number": "WD-WCC7K1234567",
+            "user_capacity": {"blocks": 7814037168u64, "bytes": 4000787030016u64},
+            "smart_status": {"passed": true},
+            "temperature": {"current": 34},
+            "power_on_time": {"hours": 31022},
+            "ata_smart_attributes": {"table": [
+                {"id": 5, "name": "Reallocated_Sector_Ct", "value": 200, "worst": 200, "thresh": 140, "when_failed": "", "raw": {"value": 3, "string": "3"}},
+                {"id": 197, "name": "Current_Pending_Sector", "value": 200, "worst": 200, "thresh": 0, "when_failed": "", "raw": {"value": 0, "string": "0"}},
+                {"id": 1, "name": "Raw_Read_Error_Rate", "value": 40, "worst": 40, "thresh": 51, "when_failed": "now", "raw": {"value": 912, "string": "912"}}
+            ]}
+        });
+        let disk = parse_report("/dev/sda", &ata);
+        assert_eq!(disk.model.as_deref(), Some("WDC WD40EFRX-68N32N0"));
+        assert_eq!(disk.capacity_bytes, Some(4000787030016));
+        assert_eq!(disk.passed, Some(true));
+        assert_eq!(disk.temp_c, Some(34.0));
+        assert_eq!(disk.reallocated_sectors, Some(3));
+        assert_eq!(disk.pending_sectors, Some(0));
+        assert_eq!(disk.uncorrectable_sectors, None);
+        assert!(disk.attributes[2].failing);
+        assert_eq!(disk.media_errors, None);
+
+        let nvme = json!({
+            "model_name": "Samsung SSD 980 1TB",
+            "smart_status": {"passed": true, "nvme": {"value": 0}},
+            "temperature": {"current": 41},
+            "nvme_smart_health_information_log": {"critical_warning": 0, "percentage_used": 7, "media_errors": 0, "power_on_hours": 5200}
+        });
+        let disk = parse_report("/dev/nvme0", &nvme);
+        assert_eq!(disk.percentage_used, Some(7));
+        assert_eq!(disk.critical_warning, Some(0));
+        assert_eq!(disk.media_errors, Some(0));
+        assert!(disk.attributes.is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+//! Nspawn transfers that outlive the WebSocket connection.
+//!
+//! Imports keep their tar extraction running while the agent reconnects, with
+//! the manifest of the chunks written so far: once authenticated again, the
+//! agent sends `ResumeTransfer` for each and the sender replays the stream
+//! from there. Exports keep their job so a receiver's `ResumeTransfer`
+//! restarts tar at its resume point. Nothing survives an agent restart.
+
+use std::collections::HashMap;
+
+use hr_registry::transfer::{Compression, ResumePoint, StreamManifest, TransferStream};
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportPhase {
+    ReceivingContainer,
+    ReceivingWorkspace,
+}
+
+/// An nspawn import, extracting the incoming tar streams.
+pub struct NspawnImport {
+    pub container_name: String,
+    pub storage_path: String,
+    pub tar_child: tokio::process::Child,
+    /// tar's stdin, through the transfer's decompression.
+    pub tar_stdin: Box<dyn tokio::io::AsyncWrite + Unpin + Send>,
+    pub phase: ImportPhase,
+    pub ws_tar_child: Option<tokio::process::Child>,
+    pub ws_tar_stdin: Option<Box<dyn tokio::io::AsyncWrite + Unpin + Send>>,
+    pub network_mode: String,
+    pub compression: Compression,
+    /// Chunks written to the current stream.
+    pub manifest: StreamManifest,
+    /// A `ResumeTransfer` was sent, chunks past the hole are dropped until
+    /// the sender replays the stream.
+    pub resume_requested: bool,
+}
+
+impl NspawnImport {
+    pub fn stream(&self) -> TransferStream {
+        match self.phase {
+            ImportPhase::ReceivingContainer => TransferStream::Container,
+            ImportPhase::ReceivingWorkspace => TransferStream::Workspace,
+        }
+    }
+
+    pub fn resume_point(&self) -> ResumePoint {
+        self.manifest.resume_point(self.stream())
+    }
+
+    /// Kill the extraction and remove what it wrote.
+    pub async fn discard(mut self) {
+        let _ = self.tar_child.kill().await;
+        drop(self.tar_stdin);
+        if let Some(mut ws_child) = self.ws_tar_child.take() {
+            let _ = ws_child.kill().await;
+        }
+        drop(self.ws_tar_stdin);
+        let rootfs_dir = format!("{}/{}", self.storage_path, self.container_name);
+        let _ = tokio::fs::remove_dir_all(&rootfs_dir).await;
+        let ws_dir = format!("{}/{}-workspace", self.storage_path, self.container_name);
+        let _ = tokio::fs::remove_dir_all(&ws_dir).await;
+    }
+}
+
+/// An nspawn export, replayed from a resume point on request.
+pub struct ExportJob {
+    pub container_name: String,
+    pub storage_path: String,
+    pub compression: Compression,
+    pub handle: tokio::task::JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub struct Transfers {
+    pub imports: HashMap<String, NspawnImport>,
+    pub exports: HashMap<String, ExportJob>,
+}
+
+impl Transfers {
+    /// Drop everything, for a registry that cannot resume.
+    pub async fn abandon(&mut self) {
+        for (tid, import) in self.imports.drain() {
+            warn!(transfer_id = %tid, "Cleaning orphaned nspawn import on disconnect");
+            import.discard().await;
+        }
+        for (_, job) in self.exports.drain() {
+            job.handle.abort();
+        }
+    }
+
+    /// Forget the exports of a container, superseded or deleted.
+    pub fn forget_exports(&mut self, container_name: &str) {
+        self.exports.retain(|_, job| {
+            let keep = job.container_name != container_name;
+            if !keep {
+                job.handle.abort();
+            }
+            keep
+        });
+    }
+}
@@ -52,6 +52,51 @@ impl Default for Ipv6Config {
     }
 }
 
+impl Ipv6Config {
+    /// Check the values the RA, DHCPv6 and PD services would otherwise
+    /// truncate or silently ignore.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        // RFC 4861 §6.2.1: the router lifetime is at most 9000 s
+        if self.ra_lifetime_secs > 9000 {
+            return Err(format!("RA lifetime {} s exceeds 9000 s", self.ra_lifetime_secs));
+        }
+        for server in &self.dhcpv6_dns_servers {
+            if server.parse::<std::net::Ipv6Addr>().is_err() {
+                return Err(format!("Invalid DHCPv6 DNS server: '{}'", server));
+            }
+        }
+        if self.dhcpv6_range_start > self.dhcpv6_range_end {
+            return Err(format!(
+                "DHCPv6 range starts after its end: {:#x} > {:#x}",
+                self.dhcpv6_range_start, self.dhcpv6_range_end
+            ));
+        }
+        if self.dhcpv6_lease_time == 0 {
+            return Err("DHCPv6 lease time must be positive".to_string());
+        }
+        if self.pd_enabled {
+            if self.pd_wan_interface.trim().is_empty() {
+                return Err("Prefix delegation needs a WAN interface".to_string());
+            }
+            if !(1..=64).contains(&self.pd_prefix_hint_len) {
+                return Err(format!("Invalid PD prefix hint length: /{}", self.pd_prefix_hint_len));
+            }
+            // The subnet id selects one /64 inside the delegated prefix
+            let subnet_bits = 64 - u32::from(self.pd_prefix_hint_len);
+            if subnet_bits < 16 && u32::from(self.pd_subnet_id) >= 1 << subnet_bits {
+                return Err(format!(
+                    "PD subnet id {} does not fit in a /{} prefix",
+                    self.pd_subnet_id, self.pd_prefix_hint_len
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Persisted state for a DHCPv6 prefix delegation.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PdState {
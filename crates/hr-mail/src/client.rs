@@ -0,0 +1,273 @@
+//! SMTP client delivering one queued message to the smarthost.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use rustls::pki_types::ServerName;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+use crate::config::{Smarthost, SmarthostTls};
+
+/// Whole transaction, connection included.
+const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(120);
+/// Longest reply line accepted from the server.
+const MAX_REPLY_LINE: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryError {
+    /// 5xx reply: retrying won't help.
+    pub permanent: bool,
+    pub message: String,
+}
+
+impl DeliveryError {
+    fn transient(message: impl Into<String>) -> Self {
+        Self { permanent: false, message: message.into() }
+    }
+}
+
+impl std::fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<std::io::Error> for DeliveryError {
+    fn from(e: std::io::Error) -> Self {
+        Self::transient(e.to_string())
+    }
+}
+
+struct Reply {
+    code: u16,
+    text: String,
+}
+
+struct Conn<S> {
+    stream: BufReader<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Conn<S> {
+    fn new(stream: S) -> Self {
+        Self { stream: BufReader::new(stream) }
+    }
+
+    /// Read a (possibly multi-line) reply.
+    async fn reply(&mut self) -> Result<Reply, DeliveryError> {
+        let mut text = String::new();
+        loop {
+            let mut line = String::new();
+            let n = (&mut self.stream).take(MAX_REPLY_LINE as u64).read_line(&mut line).await?;
+            if n == 0 {
+                return Err(DeliveryError::transient("connection closed by the smarthost"));
+            }
+            let line = line.trim_end();
+            let code = line.get(..3).and_then(|c| c.parse::<u16>().ok());
+            let Some(code) = code else {
+                return Err(DeliveryError::transient(format!("invalid SMTP reply: {}", line)));
+            };
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(line.get(4..).unwrap_or(""));
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(Reply { code, text });
+            }
+        }
+    }
+
+    /// Expect a reply in the `expected` class (2 = 2xx, 3 = 3xx).
+    async fn expect(&mut self, step: &str, expected: u16) -> Result<Reply, DeliveryError> {
+        let reply = self.reply().await?;
+        if reply.code / 100 == expected {
+            return Ok(reply);
+        }
+        Err(DeliveryError {
+            permanent: reply.code / 100 == 5,
+            message: format!("{}: {} {}", step, reply.code, reply.text),
+        })
+    }
+
+    async fn command(&mut self, line: &str, expected: u16) -> Result<Reply, DeliveryError> {
+        self.stream.get_mut().write_all(format!("{}\r\n", line).as_bytes()).await?;
+        self.stream.get_mut().flush().await?;
+        let step = line.split(':').next().unwrap_or(line);
+        // Never echo credentials in errors
+        let step = if step.starts_with("AUTH") { "AUTH" } else { step };
+        self.expect(step, expected).await
+    }
+
+    fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+}
+
+/// Deliver `data` from `sender` to `recipients` through the smarthost.
+pub async fn send(
+    smarthost: &Smarthost,
+    helo: &str,
+    sender: &str,
+    recipients: &[String],
+    data: &[u8],
+) -> Result<(), DeliveryError> {
+    match tokio::time::timeout(TRANSACTION_TIMEOUT, transaction(smarthost, helo, sender, recipients, data)).await {
+        Ok(result) => result,
+        Err(_) => Err(DeliveryError::transient("smarthost timed out")),
+    }
+}
+
+async fn transaction(
+    smarthost: &Smarthost,
+    helo: &str,
+    sender: &str,
+    recipients: &[String],
+    data: &[u8],
+) -> Result<(), DeliveryError> {
+    let tcp = TcpStream::connect((smarthost.host.as_str(), smarthost.port))
+        .await
+        .map_err(|e| DeliveryError::transient(format!("connect {}:{}: {}", smarthost.host, smarthost.port, e)))?;
+
+    match smarthost.tls {
+        SmarthostTls::None => {
+            let mut conn = Conn::new(tcp);
+            conn.expect("greeting", 2).await?;
+            conn.command(&format!("EHLO {}", helo), 2).await?;
+            session(&mut conn, smarthost, sender, recipients, data).await
+        }
+        SmarthostTls::Implicit => {
+            let tls = tls_connect(&smarthost.host, tcp).await?;
+            let mut conn = Conn::new(tls);
+            conn.expect("greeting", 2).await?;
+            conn.command(&format!("EHLO {}", helo), 2).await?;
+            session(&mut conn, smarthost, sender, recipients, data).await
+        }
+        SmarthostTls::Starttls => {
+            let mut conn = Conn::new(tcp);
+            conn.expect("greeting", 2).await?;
+            let ehlo = conn.command(&format!("EHLO {}", helo), 2).await?;
+            if !has_extension(&ehlo.text, "STARTTLS") {
+                return Err(DeliveryError::transient("smarthost does not offer STARTTLS"));
+            }
+            conn.command("STARTTLS", 2).await?;
+            let tls = tls_connect(&smarthost.host, conn.into_inner()).await?;
+            let mut conn = Conn::new(tls);
+            conn.command(&format!("EHLO {}", helo), 2).await?;
+            session(&mut conn, smarthost, sender, recipients, data).await
+        }
+    }
+}
+
+/// AUTH, envelope and content, after EHLO.
+async fn session<S: AsyncRead + AsyncWrite + Unpin>(
+    conn: &mut Conn<S>,
+    smarthost: &Smarthost,
+    sender: &str,
+    recipients: &[String],
+    data: &[u8],
+) -> Result<(), DeliveryError> {
+    if !smarthost.username.is_empty() {
+        let credentials = format!("\0{}\0{}", smarthost.username, smarthost.password);
+        conn.command(&format!("AUTH PLAIN {}", STANDARD.encode(credentials)), 2).await?;
+    }
+    conn.command(&format!("MAIL FROM:<{}>", sender), 2).await?;
+    for recipient in recipients {
+        conn.command(&format!("RCPT TO:<{}>", recipient), 2).await?;
+    }
+    conn.command("DATA", 3).await?;
+
+    let mut content = dot_stuff(data);
+    content.extend_from_slice(b".\r\n");
+    conn.stream.get_mut().write_all(&content).await?;
+    conn.stream.get_mut().flush().await?;
+    conn.expect("DATA", 2).await?;
+
+    let _ = conn.command("QUIT", 2).await;
+    Ok(())
+}
+
+fn has_extension(ehlo: &str, name: &str) -> bool {
+    ehlo.lines()
+        .any(|l| l.split_whitespace().next().is_some_and(|k| k.eq_ignore_ascii_case(name)))
+}
+
+async fn tls_connect<S: AsyncRead + AsyncWrite + Unpin>(
+    host: &str,
+    stream: S,
+) -> Result<tokio_rustls::client::TlsStream<S>, DeliveryError> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = ServerName::try_from(host.to_string())
+        .map_err(|_| DeliveryError::transient(format!("invalid smarthost name: {}", host)))?;
+    TlsConnector::from(Arc::new(config))
+        .connect(name, stream)
+        .await
+        .map_err(|e| DeliveryError::transient(format!("TLS with {}: {}", host, e)))
+}
+
+/// Lines starting with a dot get a second one; the content ends with CRLF.
+pub(crate) fn dot_stuff(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 64);
+    let mut line_start = true;
+    for &b in data {
+        if line_start && b == b'.' {
+            out.push(b'.');
+        }
+        out.push(b);
+        line_start = b == b'\n';
+    }
+    if !out.ends_with(b"\r\n") {
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot_stuff() {
+        assert_eq!(dot_stuff(b".hidden\r\nok\r\n..two"), b"..hidden\r\nok\r\n...two\r\n");
+    }
+
+    #[test]
+    fn test_has_extension() {
+        assert!(has_extension("mx.example.net\nSIZE 1000\nSTARTTLS", "starttls"));
+        assert!(!has_extension("mx.example.net\nAUTH PLAIN", "STARTTLS"));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_recipient_is_permanent() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut conn = BufReader::new(socket);
+            conn.get_mut().write_all(b"220 mx ESMTP\r\n").await.unwrap();
+            let mut line = String::new();
+            for reply in ["250-mx\r\n250 SIZE 1000\r\n", "250 OK\r\n", "550 5.1.1 No such user\r\n"] {
+                line.clear();
+                conn.read_line(&mut line).await.unwrap();
+                conn.get_mut().write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        let smarthost = Smarthost {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: SmarthostTls::None,
+            ..Default::default()
+        };
+        let err = send(&smarthost, "example.com", "a@example.com", &["nobody@example.net".to_string()], b"x")
+            .await
+            .unwrap_err();
+        assert!(err.permanent);
+        assert_eq!(err.message, "RCPT TO: 550 5.1.1 No such user");
+    }
+}
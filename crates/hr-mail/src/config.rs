@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Outbound mail relay configuration (mail-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MailConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Domain apps send from (`From:` and DKIM `d=`); empty = base domain.
+    #[serde(default)]
+    pub domain: String,
+    #[serde(default = "default_bind")]
+    pub bind_address: String,
+    /// SMTP submission port offered on the LAN (AUTH required).
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Where queued mail is delivered.
+    #[serde(default)]
+    pub smarthost: Smarthost,
+    #[serde(default = "default_true")]
+    pub dkim: bool,
+    #[serde(default = "default_selector")]
+    pub dkim_selector: String,
+    /// Default quotas, per app.
+    #[serde(default = "default_hourly_quota")]
+    pub hourly_quota: u32,
+    #[serde(default = "default_daily_quota")]
+    pub daily_quota: u32,
+    /// Per-app overrides, by app slug.
+    #[serde(default)]
+    pub app_quotas: BTreeMap<String, AppQuota>,
+    #[serde(default = "default_max_message_size")]
+    pub max_message_size: usize,
+    /// Delivery log entries older than this are purged.
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Smarthost {
+    #[serde(default)]
+    pub host: String,
+    #[serde(default = "default_smarthost_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub tls: SmarthostTls,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmarthostTls {
+    /// STARTTLS, required (port 587).
+    #[default]
+    Starttls,
+    /// TLS from the first byte (port 465).
+    Implicit,
+    /// Cleartext, for a relay on the LAN only.
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AppQuota {
+    pub hourly: u32,
+    pub daily: u32,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_bind() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_port() -> u16 {
+    587
+}
+
+fn default_smarthost_port() -> u16 {
+    587
+}
+
+fn default_selector() -> String {
+    "homeroute".to_string()
+}
+
+fn default_hourly_quota() -> u32 {
+    100
+}
+
+fn default_daily_quota() -> u32 {
+    1000
+}
+
+fn default_max_message_size() -> usize {
+    10 * 1024 * 1024
+}
+
+fn default_retention_days() -> u32 {
+    30
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl Default for Smarthost {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl MailConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Sending domain, falling back to the base domain.
+    pub fn domain_or<'a>(&'a self, base_domain: &'a str) -> &'a str {
+        if self.domain.is_empty() { base_domain } else { &self.domain }
+    }
+
+    /// Quota of `app`: its override, or the defaults.
+    pub fn quota(&self, app: &str) -> AppQuota {
+        self.app_quotas.get(app).copied().unwrap_or(AppQuota {
+            hourly: self.hourly_quota,
+            daily: self.daily_quota,
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bind_address.parse::<std::net::IpAddr>().is_err() {
+            return Err(format!("adresse d'écoute invalide: {}", self.bind_address));
+        }
+        if self.port == 0 || self.smarthost.port == 0 {
+            return Err("port invalide".to_string());
+        }
+        if self.enabled && self.smarthost.host.trim().is_empty() {
+            return Err("smarthost requis".to_string());
+        }
+        if self.smarthost.username.is_empty() != self.smarthost.password.is_empty() {
+            return Err("identifiant et mot de passe du smarthost vont ensemble".to_string());
+        }
+        if !self.domain.is_empty() && !is_domain(&self.domain) {
+            return Err(format!("domaine invalide: {}", self.domain));
+        }
+        if self.dkim_selector.is_empty()
+            || !self.dkim_selector.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            return Err(format!("sélecteur DKIM invalide: {}", self.dkim_selector));
+        }
+        if self.max_message_size < 1024 {
+            return Err("taille maximale de message invalide".to_string());
+        }
+        if self.retention_days == 0 {
+            return Err("rétention d'au moins un jour requise".to_string());
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn is_domain(domain: &str) -> bool {
+    domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_and_validate() {
+        let config = MailConfig::default();
+        assert!(!config.enabled);
+        assert_eq!(config.port, 587);
+        assert_eq!(config.smarthost.tls, SmarthostTls::Starttls);
+        assert!(config.dkim);
+        assert!(config.validate().is_ok());
+        assert_eq!(config.domain_or("example.com"), "example.com");
+
+        let mut enabled = config.clone();
+        enabled.enabled = true;
+        assert!(enabled.validate().is_err());
+        enabled.smarthost.host = "smtp.example.net".to_string();
+        assert!(enabled.validate().is_ok());
+        enabled.smarthost.username = "relay".to_string();
+        assert!(enabled.validate().is_err());
+        enabled.smarthost.password = "secret".to_string();
+        assert!(enabled.validate().is_ok());
+
+        enabled.dkim_selector = "bad selector".to_string();
+        assert!(enabled.validate().is_err());
+    }
+
+    #[test]
+    fn test_app_quota_override() {
+        let config: MailConfig =
+            serde_json::from_str(r#"{"hourly_quota": 5, "app_quotas": {"shop": {"hourly": 50, "daily": 200}}}"#).unwrap();
+        assert_eq!(config.quota("shop"), AppQuota { hourly: 50, daily: 200 });
+        assert_eq!(config.quota("blog"), AppQuota { hourly: 5, daily: 1000 });
+    }
+}
@@ -0,0 +1,119 @@
+//! Queue runner: hands due messages to the smarthost, retries temporary
+//! failures with a growing delay, and purges the log past retention.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{debug, info, warn};
+
+use crate::Mail;
+use crate::client;
+use crate::store::QueuedMessage;
+
+/// Delay before each retry; once exhausted the message is marked failed
+/// (about 18 hours of attempts).
+const RETRY_DELAYS: &[i64] = &[60, 300, 900, 3600, 4 * 3600, 12 * 3600];
+/// Messages taken from the queue per pass.
+const BATCH_SIZE: u32 = 20;
+/// Idle wake-up, for deferred messages coming due.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+const RETENTION_INTERVAL: Duration = Duration::from_secs(3600);
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// Deliver queued messages until the process stops.
+pub async fn run_delivery(mail: Arc<Mail>) {
+    loop {
+        let config = mail.config();
+        if config.enabled && !config.smarthost.host.is_empty() {
+            let store = mail.store();
+            let due = tokio::task::spawn_blocking(move || store.due(now_ms(), BATCH_SIZE))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|r| r);
+            match due {
+                Ok(due) => {
+                    let full = due.len() == BATCH_SIZE as usize;
+                    for message in due {
+                        deliver(&mail, message).await;
+                    }
+                    if full {
+                        continue;
+                    }
+                }
+                Err(e) => warn!("Mail queue unavailable: {:#}", e),
+            }
+        }
+        tokio::select! {
+            _ = mail.queued.notified() => {}
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+        }
+    }
+}
+
+async fn deliver(mail: &Mail, message: QueuedMessage) {
+    let config = mail.config();
+    let result = client::send(
+        &config.smarthost,
+        &mail.domain(),
+        &message.sender,
+        &message.recipients,
+        &message.data,
+    )
+    .await;
+
+    let store = mail.store();
+    let metrics = hr_common::metrics::registry();
+    let outcome = match result {
+        Ok(()) => {
+            debug!(id = message.id, app = %message.app, "Mail delivered to the smarthost");
+            mail.set_delivery_error(None);
+            metrics
+                .counter("homeroute_mail_delivered_total", "Messages accepted by the smarthost, by app.", &[("app", &message.app)])
+                .inc();
+            store.mark_sent(message.id, now_ms())
+        }
+        Err(e) => {
+            let attempt = message.attempts as usize;
+            mail.set_delivery_error(Some(e.message.clone()));
+            match RETRY_DELAYS.get(attempt).filter(|_| !e.permanent) {
+                Some(delay) => {
+                    info!(id = message.id, app = %message.app, error = %e, "Mail deferred, retry in {}s", delay);
+                    store.mark_deferred(message.id, &e.message, now_ms() + delay * 1000)
+                }
+                None => {
+                    warn!(id = message.id, app = %message.app, error = %e, "Mail delivery failed");
+                    metrics
+                        .counter(
+                            "homeroute_mail_failed_total",
+                            "Messages given up on (rejected or retries exhausted), by app.",
+                            &[("app", &message.app)],
+                        )
+                        .inc();
+                    store.mark_failed(message.id, &e.message)
+                }
+            }
+        }
+    };
+    if let Err(e) = outcome {
+        warn!(id = message.id, "Failed to update the mail queue: {:#}", e);
+    }
+}
+
+/// Purge delivered and failed entries past the retention period.
+pub(crate) async fn retention(mail: Arc<Mail>) {
+    let mut tick = tokio::time::interval(RETENTION_INTERVAL);
+    loop {
+        tick.tick().await;
+        let before = now_ms() - i64::from(mail.config().retention_days) * 86_400_000;
+        let store = mail.store();
+        match tokio::task::spawn_blocking(move || store.purge(before)).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(n)) => debug!("Purged {} mail log entries", n),
+            Ok(Err(e)) => warn!("Mail log purge failed: {:#}", e),
+            Err(e) => warn!("Mail log purge failed: {}", e),
+        }
+    }
+}
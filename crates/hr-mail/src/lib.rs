@@ -0,0 +1,342 @@
+//! Outbound mail relay for the apps (password resets, notifications): apps
+//! submit over SMTP on the LAN or through the API, HomeRoute queues the
+//! message, signs it with DKIM and delivers it through a smarthost, within
+//! per-app quotas. The queue doubles as the delivery log.
+
+pub mod client;
+pub mod config;
+pub mod delivery;
+pub mod dkim;
+pub mod message;
+pub mod server;
+pub mod store;
+
+pub use config::{AppQuota, MailConfig, Smarthost, SmarthostTls};
+pub use store::{AppUsage, DeliveryRecord, LogQuery, MailStore};
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use serde::Serialize;
+use tokio::sync::{Notify, watch};
+use tracing::warn;
+
+use crate::dkim::DkimKey;
+
+/// Recipients accepted per message.
+pub const MAX_RECIPIENTS: usize = 100;
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MailStatus {
+    pub running: bool,
+    /// SMTP submission listener, e.g. `0.0.0.0:587`.
+    pub listener: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Last temporary or permanent smarthost failure.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_delivery_error: Option<String>,
+}
+
+/// DNS record to publish for the DKIM key.
+#[derive(Debug, Clone, Serialize)]
+pub struct DkimRecord {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub record_type: &'static str,
+    pub value: String,
+}
+
+/// Why a submission was refused.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubmitError {
+    Disabled,
+    /// Sender (envelope or `From:`) outside the relay domain.
+    Sender(String),
+    Recipient(String),
+    TooLarge(usize),
+    Quota { limit: u32, window: &'static str },
+    Storage(String),
+}
+
+impl std::fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubmitError::Disabled => write!(f, "mail relay disabled"),
+            SubmitError::Sender(s) => write!(f, "sender not allowed: {}", s),
+            SubmitError::Recipient(s) => write!(f, "invalid recipient: {}", s),
+            SubmitError::TooLarge(max) => write!(f, "message larger than {} bytes", max),
+            SubmitError::Quota { limit, window } => write!(f, "quota of {} messages per {} reached", limit, window),
+            SubmitError::Storage(e) => write!(f, "queue error: {}", e),
+        }
+    }
+}
+
+impl SubmitError {
+    /// SMTP reply for this refusal.
+    pub fn smtp_reply(&self) -> String {
+        match self {
+            SubmitError::Disabled => "421 4.3.2 Mail relay disabled".to_string(),
+            SubmitError::Sender(_) => format!("553 5.7.1 {}", self),
+            SubmitError::Recipient(_) => format!("553 5.1.3 {}", self),
+            SubmitError::TooLarge(_) => format!("552 5.3.4 {}", self),
+            SubmitError::Quota { .. } => format!("450 4.7.1 {}", self),
+            SubmitError::Storage(_) => "451 4.3.0 Queue unavailable".to_string(),
+        }
+    }
+}
+
+/// Checks the credentials an app presents (SMTP AUTH, API bearer token).
+pub trait AppAuth: Send + Sync + 'static {
+    /// Slug of the app owning `secret`; `username`, when given, must be it.
+    fn app_for(&self, username: Option<&str>, secret: &str) -> impl Future<Output = Option<String>> + Send;
+}
+
+/// Apps authenticate with their agent token.
+impl AppAuth for hr_registry::AgentRegistry {
+    async fn app_for(&self, username: Option<&str>, secret: &str) -> Option<String> {
+        match username {
+            Some(slug) => self.authenticate(secret, slug).await.map(|_| slug.to_string()),
+            None => self.authenticate_by_token(secret).await.map(|(_, slug)| slug),
+        }
+    }
+}
+
+/// Shared relay handle: the API pushes config, submits and reads the log;
+/// the supervised `mail` service runs the listener and delivery.
+pub struct Mail {
+    config: watch::Sender<MailConfig>,
+    base_domain: String,
+    dkim_dir: PathBuf,
+    dkim: RwLock<Option<Arc<DkimKey>>>,
+    status: RwLock<MailStatus>,
+    store: Arc<MailStore>,
+    /// Wakes the delivery loop when something is queued.
+    pub(crate) queued: Notify,
+}
+
+impl Mail {
+    pub fn new(config: MailConfig, base_domain: &str, dkim_dir: PathBuf, store: MailStore) -> Self {
+        let mail = Self {
+            config: watch::channel(config.clone()).0,
+            base_domain: base_domain.to_string(),
+            dkim_dir,
+            dkim: RwLock::new(None),
+            status: RwLock::new(MailStatus::default()),
+            store: Arc::new(store),
+            queued: Notify::new(),
+        };
+        mail.load_dkim(&config);
+        mail
+    }
+
+    /// Current configuration.
+    pub fn config(&self) -> MailConfig {
+        self.config.borrow().clone()
+    }
+
+    /// Replace the configuration; the listener is rebound.
+    pub fn apply(&self, config: MailConfig) {
+        self.load_dkim(&config);
+        self.config.send_replace(config);
+        self.queued.notify_one();
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<MailConfig> {
+        self.config.subscribe()
+    }
+
+    fn load_dkim(&self, config: &MailConfig) {
+        let current = self.dkim.read().unwrap().as_ref().map(|k| k.selector().to_string());
+        if !config.dkim || current.as_deref() == Some(&config.dkim_selector) {
+            return;
+        }
+        match DkimKey::load_or_create(&self.dkim_dir, &config.dkim_selector) {
+            Ok(key) => *self.dkim.write().unwrap() = Some(Arc::new(key)),
+            Err(e) => warn!("DKIM key for selector {} unavailable: {:#}", config.dkim_selector, e),
+        }
+    }
+
+    /// Domain apps send from.
+    pub fn domain(&self) -> String {
+        self.config.borrow().domain_or(&self.base_domain).to_string()
+    }
+
+    /// TXT record to publish, when DKIM is on.
+    pub fn dkim_record(&self) -> Option<DkimRecord> {
+        let config = self.config();
+        if !config.dkim {
+            return None;
+        }
+        let key = self.dkim.read().unwrap().clone()?;
+        Some(DkimRecord {
+            name: format!("{}._domainkey.{}", key.selector(), config.domain_or(&self.base_domain)),
+            record_type: "TXT",
+            value: key.dns_record(),
+        })
+    }
+
+    pub fn store(&self) -> Arc<MailStore> {
+        self.store.clone()
+    }
+
+    pub fn status(&self) -> MailStatus {
+        self.status.read().unwrap().clone()
+    }
+
+    pub(crate) fn set_status(&self, running: bool, listener: Option<String>, error: Option<String>) {
+        let mut status = self.status.write().unwrap();
+        status.running = running;
+        status.listener = listener;
+        status.last_error = error;
+    }
+
+    pub(crate) fn set_delivery_error(&self, error: Option<String>) {
+        self.status.write().unwrap().last_delivery_error = error;
+    }
+
+    /// The envelope sender is in the relay domain (or a subdomain).
+    pub fn check_sender(&self, sender: &str) -> Result<(), SubmitError> {
+        let domain = self.domain();
+        let sender_domain = message::address_domain(sender).to_ascii_lowercase();
+        if sender_domain == domain || sender_domain.ends_with(&format!(".{}", domain)) {
+            Ok(())
+        } else {
+            Err(SubmitError::Sender(sender.to_string()))
+        }
+    }
+
+    /// `app` has room left in its hourly and daily quotas.
+    pub fn check_quota(&self, app: &str) -> Result<(), SubmitError> {
+        let quota = self.config.borrow().quota(app);
+        let now = chrono::Utc::now().timestamp_millis();
+        let storage = |e: anyhow::Error| SubmitError::Storage(e.to_string());
+        for (limit, window, since) in [
+            (quota.hourly, "hour", now - 3_600_000),
+            (quota.daily, "day", now - 86_400_000),
+        ] {
+            if self.store.count_since(app, since).map_err(storage)? >= limit {
+                return Err(SubmitError::Quota { limit, window });
+            }
+        }
+        Ok(())
+    }
+
+    /// Queue a message from `app`: sender, quota and size are checked, the
+    /// missing headers added and the message signed. Returns the queue id.
+    pub fn submit(&self, app: &str, sender: &str, recipients: &[String], data: &[u8]) -> Result<i64, SubmitError> {
+        let config = self.config();
+        if !config.enabled {
+            return Err(SubmitError::Disabled);
+        }
+        if data.len() > config.max_message_size {
+            return Err(SubmitError::TooLarge(config.max_message_size));
+        }
+        if recipients.is_empty() || recipients.len() > MAX_RECIPIENTS {
+            return Err(SubmitError::Recipient(format!("{} recipients", recipients.len())));
+        }
+        if let Some(bad) = recipients.iter().find(|r| message::parse_address(r).is_none()) {
+            return Err(SubmitError::Recipient(bad.clone()));
+        }
+        self.check_sender(sender)?;
+        self.check_quota(app)?;
+
+        let domain = config.domain_or(&self.base_domain);
+        let now = chrono::Utc::now();
+        let data = message::complete_headers(&message::normalize_crlf(data), sender, domain, now);
+        // The visible sender must be ours too, or DKIM would vouch for a spoof
+        let from = message::header(&data, "From").unwrap_or_default();
+        match message::header_address(&from) {
+            Some(address) => self.check_sender(&address)?,
+            None => return Err(SubmitError::Sender(from)),
+        }
+
+        let signed = match self.dkim.read().unwrap().clone().filter(|_| config.dkim) {
+            Some(key) => {
+                let mut signed = key.sign(&data, domain, now.timestamp()).into_bytes();
+                signed.extend_from_slice(&data);
+                signed
+            }
+            None => data,
+        };
+
+        let subject = message::header(&signed, "Subject");
+        let id = self
+            .store
+            .enqueue(app, sender, recipients, subject.as_deref(), &signed, now.timestamp_millis())
+            .map_err(|e| SubmitError::Storage(e.to_string()))?;
+        hr_common::metrics::registry()
+            .counter("homeroute_mail_submitted_total", "Messages queued for delivery, by app.", &[("app", app)])
+            .inc();
+        self.queued.notify_one();
+        Ok(id)
+    }
+
+    /// Put a failed message back in the queue for immediate delivery.
+    /// False when it is unknown, not failed, or its content was purged.
+    pub fn retry(&self, id: i64) -> anyhow::Result<bool> {
+        let requeued = self.store.retry(id, chrono::Utc::now().timestamp_millis())?;
+        if requeued {
+            self.queued.notify_one();
+        }
+        Ok(requeued)
+    }
+}
+
+pub type SharedMail = Arc<Mail>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(crate) fn test_mail(dir: &std::path::Path) -> Mail {
+        let config = MailConfig {
+            enabled: true,
+            hourly_quota: 2,
+            smarthost: Smarthost { host: "127.0.0.1".to_string(), ..Default::default() },
+            ..Default::default()
+        };
+        Mail::new(config, "example.com", dir.join("dkim"), MailStore::open_in_memory().unwrap())
+    }
+
+    #[test]
+    fn test_submit_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let mail = test_mail(dir.path());
+        let rcpts = vec!["alice@example.net".to_string()];
+        let message = b"From: Shop <shop@example.com>\nSubject: Reset\n\nClick here\n";
+
+        let id = mail.submit("shop", "shop@example.com", &rcpts, message).unwrap();
+        let queued = mail.store().due(i64::MAX, 10).unwrap();
+        assert_eq!(queued[0].id, id);
+        let data = String::from_utf8(queued[0].data.clone()).unwrap();
+        assert!(data.starts_with("DKIM-Signature: v=1; a=ed25519-sha256;"));
+        assert!(data.contains("Message-ID: <") && data.contains("\r\n\r\nClick here\r\n"));
+        assert_eq!(mail.store().log(&LogQuery::default()).unwrap()[0].subject.as_deref(), Some("Reset"));
+
+        // Subdomains are ours, anything else is a spoof
+        assert!(mail.submit("shop", "shop@mail.example.com", &rcpts, b"Subject: x\r\n\r\nx").is_ok());
+        assert_eq!(
+            mail.submit("blog", "ceo@bank.example", &rcpts, message),
+            Err(SubmitError::Sender("ceo@bank.example".to_string()))
+        );
+        assert!(matches!(
+            mail.submit("blog", "blog@example.com", &rcpts, b"From: ceo@bank.example\r\n\r\nx"),
+            Err(SubmitError::Sender(_))
+        ));
+        assert!(matches!(mail.submit("blog", "blog@example.com", &["nope".to_string()], message), Err(SubmitError::Recipient(_))));
+
+        // Two per hour
+        assert_eq!(
+            mail.submit("shop", "shop@example.com", &rcpts, message),
+            Err(SubmitError::Quota { limit: 2, window: "hour" })
+        );
+        assert!(mail.submit("blog", "blog@example.com", &rcpts, b"Subject: x\r\n\r\nx").is_ok());
+
+        let record = mail.dkim_record().unwrap();
+        assert_eq!(record.name, "homeroute._domainkey.example.com");
+        assert!(record.value.starts_with("v=DKIM1; k=ed25519; p="));
+    }
+}
@@ -0,0 +1,313 @@
+//! RFC 5322 helpers: line endings, header access, and building a message
+//! from an API submission.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Deserialize;
+
+/// Bare `\n` (and lone `\r`) become `\r\n`, as SMTP and DKIM expect.
+pub fn normalize_crlf(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 40);
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            b'\r' => {
+                out.extend_from_slice(b"\r\n");
+                if data.get(i + 1) == Some(&b'\n') {
+                    i += 1;
+                }
+            }
+            b'\n' => out.extend_from_slice(b"\r\n"),
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    out
+}
+
+/// Header section (without the blank line) and body of a CRLF message.
+pub fn split(message: &[u8]) -> (&[u8], &[u8]) {
+    if message.starts_with(b"\r\n") {
+        return (&[], &message[2..]);
+    }
+    match message.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(pos) => (&message[..pos + 2], &message[pos + 4..]),
+        None => (message, &[]),
+    }
+}
+
+/// Header fields as (name, value), continuation lines unfolded into the value.
+pub fn headers(section: &[u8]) -> Vec<(String, String)> {
+    let text = String::from_utf8_lossy(section);
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in text.split("\r\n").filter(|l| !l.is_empty()) {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push_str("\r\n");
+                value.push_str(line);
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            fields.push((name.trim().to_string(), value.to_string()));
+        }
+    }
+    fields
+}
+
+/// Last value of header `name`, unfolded and trimmed.
+pub fn header(message: &[u8], name: &str) -> Option<String> {
+    let (section, _) = split(message);
+    headers(section)
+        .into_iter()
+        .rev()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.replace("\r\n", "").trim().to_string())
+}
+
+/// Add the `Date`, `Message-ID` and `From` headers a submission left out.
+pub fn complete_headers(message: &[u8], sender: &str, domain: &str, now: chrono::DateTime<chrono::Utc>) -> Vec<u8> {
+    let mut missing = String::new();
+    if header(message, "Date").is_none() {
+        missing.push_str(&format!("Date: {}\r\n", now.to_rfc2822()));
+    }
+    if header(message, "Message-ID").is_none() {
+        missing.push_str(&format!("Message-ID: <{}@{}>\r\n", uuid::Uuid::new_v4(), domain));
+    }
+    if header(message, "From").is_none() {
+        missing.push_str(&format!("From: {}\r\n", sender));
+    }
+    let mut out = missing.into_bytes();
+    out.extend_from_slice(message);
+    out
+}
+
+/// A bare `local@domain` address, lowercased domain; `None` if malformed.
+pub fn parse_address(address: &str) -> Option<String> {
+    let address = address.trim();
+    let (local, domain) = address.rsplit_once('@')?;
+    let bad = |c: char| c.is_whitespace() || c.is_control() || "<>()[]\\,;:\"".contains(c);
+    if local.is_empty() || local.len() > 64 || local.chars().any(bad) || !crate::config::is_domain(domain) {
+        return None;
+    }
+    Some(format!("{}@{}", local, domain.to_ascii_lowercase()))
+}
+
+/// Domain part of an address.
+pub fn address_domain(address: &str) -> &str {
+    address.rsplit_once('@').map(|(_, d)| d).unwrap_or("")
+}
+
+/// Address in a `From:`-style header value (`Name <a@b>` or `a@b`).
+pub fn header_address(value: &str) -> Option<String> {
+    let inner = match (value.rfind('<'), value.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &value[start + 1..end],
+        _ => value,
+    };
+    parse_address(inner)
+}
+
+/// Message submitted through `POST /api/mail/send`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Outgoing {
+    /// Defaults to `{app}@{domain}`.
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub from_name: Option<String>,
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub cc: Vec<String>,
+    /// Envelope only, never written in the headers.
+    #[serde(default)]
+    pub bcc: Vec<String>,
+    #[serde(default)]
+    pub reply_to: Option<String>,
+    pub subject: String,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub html: Option<String>,
+}
+
+/// Envelope and content ready for [`crate::Mail::submit`].
+#[derive(Debug, Clone)]
+pub struct Built {
+    pub sender: String,
+    pub recipients: Vec<String>,
+    pub data: Vec<u8>,
+}
+
+impl Outgoing {
+    pub fn build(&self, app: &str, domain: &str, now: chrono::DateTime<chrono::Utc>) -> Result<Built, String> {
+        let sender = match &self.from {
+            Some(from) => parse_address(from).ok_or_else(|| format!("invalid sender: {}", from))?,
+            None => format!("{}@{}", app, domain),
+        };
+        let parse_all = |list: &[String]| {
+            list.iter()
+                .map(|a| parse_address(a).ok_or_else(|| format!("invalid recipient: {}", a)))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let to = parse_all(&self.to)?;
+        let cc = parse_all(&self.cc)?;
+        let bcc = parse_all(&self.bcc)?;
+        if to.is_empty() && cc.is_empty() && bcc.is_empty() {
+            return Err("no recipient".to_string());
+        }
+        let reply_to = match &self.reply_to {
+            Some(a) => Some(parse_address(a).ok_or_else(|| format!("invalid reply-to: {}", a))?),
+            None => None,
+        };
+        if self.text.is_none() && self.html.is_none() {
+            return Err("text or html body required".to_string());
+        }
+
+        let mut head = String::new();
+        let from = match self.from_name.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+            Some(name) => format!("{} <{}>", encode_word(name), sender),
+            None => sender.clone(),
+        };
+        head.push_str(&format!("From: {}\r\n", from));
+        if !to.is_empty() {
+            head.push_str(&format!("To: {}\r\n", to.join(", ")));
+        }
+        if !cc.is_empty() {
+            head.push_str(&format!("Cc: {}\r\n", cc.join(", ")));
+        }
+        if let Some(reply_to) = reply_to {
+            head.push_str(&format!("Reply-To: {}\r\n", reply_to));
+        }
+        head.push_str(&format!("Subject: {}\r\n", encode_word(self.subject.trim())));
+        head.push_str(&format!("Date: {}\r\n", now.to_rfc2822()));
+        head.push_str(&format!("Message-ID: <{}@{}>\r\n", uuid::Uuid::new_v4(), domain));
+        head.push_str("MIME-Version: 1.0\r\n");
+
+        let body = match (&self.text, &self.html) {
+            (Some(text), Some(html)) => {
+                let boundary = format!("hr-{}", uuid::Uuid::new_v4().simple());
+                head.push_str(&format!("Content-Type: multipart/alternative; boundary=\"{}\"\r\n", boundary));
+                format!(
+                    "--{b}\r\n{}\r\n--{b}\r\n{}\r\n--{b}--\r\n",
+                    part("text/plain", text),
+                    part("text/html", html),
+                    b = boundary
+                )
+            }
+            (Some(text), None) => {
+                head.push_str(&part_headers("text/plain"));
+                base64_lines(text)
+            }
+            (None, Some(html)) => {
+                head.push_str(&part_headers("text/html"));
+                base64_lines(html)
+            }
+            (None, None) => unreachable!(),
+        };
+
+        let mut data = head.into_bytes();
+        data.extend_from_slice(b"\r\n");
+        data.extend_from_slice(body.as_bytes());
+        Ok(Built {
+            sender,
+            recipients: to.into_iter().chain(cc).chain(bcc).collect(),
+            data,
+        })
+    }
+}
+
+fn part_headers(content_type: &str) -> String {
+    format!("Content-Type: {}; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n", content_type)
+}
+
+fn part(content_type: &str, content: &str) -> String {
+    format!("{}\r\n{}", part_headers(content_type), base64_lines(content))
+}
+
+/// Base64 in 76-character lines.
+fn base64_lines(content: &str) -> String {
+    let encoded = STANDARD.encode(normalize_crlf(content.as_bytes()));
+    let mut out = String::with_capacity(encoded.len() + encoded.len() / 38);
+    for chunk in encoded.as_bytes().chunks(76) {
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// RFC 2047 encoded-word for non-ASCII header text; control characters
+/// (header injection) are dropped.
+fn encode_word(text: &str) -> String {
+    let clean: String = text.chars().filter(|c| !c.is_control()).collect();
+    if clean.is_ascii() {
+        clean
+    } else {
+        format!("=?UTF-8?B?{}?=", STANDARD.encode(clean.as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap()
+    }
+
+    #[test]
+    fn test_normalize_and_split() {
+        let message = normalize_crlf(b"Subject: a\n b\nFrom: x@example.com\n\nbody\r\nline\n");
+        assert_eq!(message, b"Subject: a\r\n b\r\nFrom: x@example.com\r\n\r\nbody\r\nline\r\n");
+        let (head, body) = split(&message);
+        assert_eq!(body, b"body\r\nline\r\n");
+        assert_eq!(headers(head)[0], ("Subject".to_string(), " a\r\n b".to_string()));
+        assert_eq!(header(&message, "subject").as_deref(), Some("a b"));
+    }
+
+    #[test]
+    fn test_addresses() {
+        assert_eq!(parse_address(" Bob@Example.COM ").as_deref(), Some("Bob@example.com"));
+        assert!(parse_address("bob").is_none());
+        assert!(parse_address("bob@localhost").is_none());
+        assert!(parse_address("bo b@example.com").is_none());
+        assert!(parse_address("bob@example.com\r\nBcc: x@evil.net").is_none());
+        assert_eq!(header_address("\"Shop\" <shop@example.com>").as_deref(), Some("shop@example.com"));
+        assert_eq!(address_domain("shop@example.com"), "example.com");
+    }
+
+    #[test]
+    fn test_complete_headers() {
+        let message = complete_headers(b"Subject: hi\r\n\r\nbody\r\n", "shop@example.com", "example.com", now());
+        assert_eq!(header(&message, "From").as_deref(), Some("shop@example.com"));
+        assert!(header(&message, "Message-ID").unwrap().ends_with("@example.com>"));
+        assert!(header(&message, "Date").is_some());
+        let again = complete_headers(&message, "other@example.com", "example.com", now());
+        assert_eq!(again, message);
+    }
+
+    #[test]
+    fn test_build() {
+        let outgoing = Outgoing {
+            from_name: Some("Boutique été".to_string()),
+            to: vec!["alice@example.net".to_string()],
+            bcc: vec!["audit@example.org".to_string()],
+            subject: "Réinitialisation\r\nBcc: victim@evil.net".to_string(),
+            text: Some("Bonjour\n".to_string()),
+            html: Some("<p>Bonjour</p>".to_string()),
+            ..Default::default()
+        };
+        let built = outgoing.build("shop", "example.com", now()).unwrap();
+        assert_eq!(built.sender, "shop@example.com");
+        assert_eq!(built.recipients, vec!["alice@example.net", "audit@example.org"]);
+        let text = String::from_utf8(built.data.clone()).unwrap();
+        assert!(text.starts_with("From: =?UTF-8?B?"));
+        assert!(!text.contains("audit@example.org"));
+        assert!(!text.contains("\r\nBcc:"));
+        assert!(text.contains("multipart/alternative"));
+        assert_eq!(header(&built.data, "To").as_deref(), Some("alice@example.net"));
+
+        let no_body = Outgoing { to: vec!["a@example.net".to_string()], ..Default::default() };
+        assert!(no_body.build("shop", "example.com", now()).is_err());
+        let no_rcpt = Outgoing { text: Some("x".to_string()), ..Default::default() };
+        assert!(no_rcpt.build("shop", "example.com", now()).is_err());
+    }
+}
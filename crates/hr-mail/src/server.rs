@@ -0,0 +1,394 @@
+//! SMTP submission listener for the apps on the LAN (RFC 6409 subset):
+//! EHLO, AUTH PLAIN/LOGIN with the app's credentials, one or more
+//! transactions, no relaying without authentication.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+use crate::config::MailConfig;
+use crate::{AppAuth, MAX_RECIPIENTS, Mail, delivery, message};
+
+/// Delay before rebinding after a listener failure.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+/// Connections idle this long are closed.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// Longest command line (RFC 5321 says 512, AUTH lines can be longer).
+const MAX_LINE: usize = 4096;
+/// Failed AUTH attempts before the connection is dropped.
+const MAX_AUTH_FAILURES: u32 = 3;
+
+/// Run the relay: the submission listener is rebound whenever the
+/// configuration changes; delivery and retention keep running across changes.
+pub async fn run_mail<A: AppAuth>(mail: Arc<Mail>, auth: Arc<A>) -> Result<()> {
+    let mut config_rx = mail.subscribe();
+
+    let listener = async {
+        loop {
+            let config = config_rx.borrow_and_update().clone();
+            tokio::select! {
+                _ = run_config(mail.clone(), auth.clone(), config) => {}
+                changed = config_rx.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = listener => Ok(()),
+        _ = delivery::run_delivery(mail.clone()) => Ok(()),
+        _ = delivery::retention(mail.clone()) => Ok(()),
+    }
+}
+
+/// Serve one configuration until it is replaced (never returns on its own).
+async fn run_config<A: AppAuth>(mail: Arc<Mail>, auth: Arc<A>, config: MailConfig) {
+    if !config.enabled {
+        mail.set_status(false, None, None);
+        return std::future::pending().await;
+    }
+
+    let ip: IpAddr = config.bind_address.parse().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    let addr = SocketAddr::new(ip, config.port);
+    loop {
+        match TcpListener::bind(addr).await.with_context(|| format!("SMTP bind {}", addr)) {
+            Ok(listener) => {
+                info!("Mail submission listening on {}", addr);
+                mail.set_status(true, Some(addr.to_string()), None);
+                if let Err(e) = accept_loop(mail.clone(), auth.clone(), listener).await {
+                    warn!("Mail listener stopped: {:#}", e);
+                    mail.set_status(false, None, Some(format!("{e:#}")));
+                }
+            }
+            Err(e) => {
+                warn!("Mail listener failed to start: {:#}", e);
+                mail.set_status(false, None, Some(format!("{e:#}")));
+            }
+        }
+        tokio::time::sleep(RETRY_DELAY).await;
+    }
+}
+
+pub(crate) async fn accept_loop<A: AppAuth>(mail: Arc<Mail>, auth: Arc<A>, listener: TcpListener) -> Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let mail = mail.clone();
+        let auth = auth.clone();
+        hr_common::selfmon::spawn("mail", async move {
+            if let Err(e) = session(&mail, auth.as_ref(), stream, peer.ip()).await {
+                debug!("SMTP session with {} ended: {:#}", peer, e);
+            }
+        });
+    }
+}
+
+/// Envelope of the transaction in progress.
+#[derive(Default)]
+struct Transaction {
+    sender: Option<String>,
+    recipients: Vec<String>,
+}
+
+struct Session<S> {
+    stream: BufReader<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Session<S> {
+    async fn send(&mut self, reply: &str) -> Result<()> {
+        self.stream.get_mut().write_all(format!("{}\r\n", reply).as_bytes()).await?;
+        self.stream.get_mut().flush().await?;
+        Ok(())
+    }
+
+    /// Next line without its CRLF; `None` on EOF.
+    async fn line(&mut self, limit: usize) -> Result<Option<Vec<u8>>> {
+        let mut buf = Vec::new();
+        let mut limited = (&mut self.stream).take(limit as u64);
+        let n = tokio::time::timeout(IDLE_TIMEOUT, limited.read_until(b'\n', &mut buf))
+            .await
+            .context("idle timeout")??;
+        if n == 0 {
+            return Ok(None);
+        }
+        if !buf.ends_with(b"\n") {
+            anyhow::bail!("line too long");
+        }
+        buf.pop();
+        if buf.ends_with(b"\r") {
+            buf.pop();
+        }
+        Ok(Some(buf))
+    }
+
+    /// Message content up to the lone dot, unstuffed. `None` when larger
+    /// than `max` (the rest is still read, to stay in sync).
+    async fn data(&mut self, max: usize) -> Result<Option<Vec<u8>>> {
+        let mut content = Vec::new();
+        let mut too_large = false;
+        loop {
+            let Some(line) = self.line(MAX_LINE * 4).await? else {
+                anyhow::bail!("connection closed during DATA");
+            };
+            if line == b"." {
+                return Ok((!too_large).then_some(content));
+            }
+            let line = line.strip_prefix(b".").unwrap_or(&line);
+            if content.len() + line.len() + 2 > max {
+                too_large = true;
+                content.clear();
+            }
+            if !too_large {
+                content.extend_from_slice(line);
+                content.extend_from_slice(b"\r\n");
+            }
+        }
+    }
+}
+
+/// `FROM:<addr> [params]` / `TO:<addr>` argument; `Some("")` for `<>`.
+fn path_argument<'a>(arg: &'a str, keyword: &str) -> Option<&'a str> {
+    let rest = arg.get(..keyword.len()).filter(|k| k.eq_ignore_ascii_case(keyword))?;
+    let rest = arg[rest.len()..].trim_start();
+    let rest = rest.strip_prefix('<')?;
+    let end = rest.find('>')?;
+    Some(&rest[..end])
+}
+
+pub(crate) async fn session<S, A>(mail: &Mail, auth: &A, stream: S, peer: IpAddr) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    A: AppAuth,
+{
+    let mut session = Session { stream: BufReader::new(stream) };
+    let domain = mail.domain();
+    session.send(&format!("220 {} ESMTP HomeRoute", domain)).await?;
+
+    let mut app: Option<String> = None;
+    let mut auth_failures = 0;
+    let mut tx = Transaction::default();
+
+    while let Some(line) = session.line(MAX_LINE).await? {
+        let line = String::from_utf8_lossy(&line).into_owned();
+        let (verb, arg) = line.split_once(' ').unwrap_or((&line, ""));
+        let arg = arg.trim();
+        match verb.to_ascii_uppercase().as_str() {
+            "EHLO" => {
+                tx = Transaction::default();
+                let max = mail.config().max_message_size;
+                session
+                    .send(&format!(
+                        "250-{}\r\n250-SIZE {}\r\n250-8BITMIME\r\n250-ENHANCEDSTATUSCODES\r\n250 AUTH PLAIN LOGIN",
+                        domain, max
+                    ))
+                    .await?;
+            }
+            "HELO" => {
+                tx = Transaction::default();
+                session.send(&format!("250 {}", domain)).await?;
+            }
+            "AUTH" if app.is_some() => session.send("503 5.5.1 Already authenticated").await?,
+            "AUTH" => {
+                let credentials = read_credentials(&mut session, arg).await?;
+                let slug = match credentials {
+                    Some((user, secret)) => auth.app_for(Some(&user), &secret).await,
+                    None => None,
+                };
+                match slug {
+                    Some(slug) => {
+                        debug!(app = %slug, %peer, "SMTP client authenticated");
+                        app = Some(slug);
+                        session.send("235 2.7.0 Authentication successful").await?;
+                    }
+                    None => {
+                        auth_failures += 1;
+                        warn!(%peer, "SMTP authentication failed");
+                        if auth_failures >= MAX_AUTH_FAILURES {
+                            session.send("421 4.7.0 Too many failed attempts").await?;
+                            return Ok(());
+                        }
+                        session.send("535 5.7.8 Authentication credentials invalid").await?;
+                    }
+                }
+            }
+            "MAIL" => {
+                let Some(slug) = &app else {
+                    session.send("530 5.7.0 Authentication required").await?;
+                    continue;
+                };
+                if tx.sender.is_some() {
+                    session.send("503 5.5.1 Nested MAIL command").await?;
+                    continue;
+                }
+                let Some(sender) = path_argument(arg, "FROM:").and_then(message::parse_address) else {
+                    session.send("501 5.1.7 Invalid sender address").await?;
+                    continue;
+                };
+                match mail.check_sender(&sender).and_then(|_| mail.check_quota(slug)) {
+                    Ok(()) => {
+                        tx.sender = Some(sender);
+                        session.send("250 2.1.0 OK").await?;
+                    }
+                    Err(e) => session.send(&e.smtp_reply()).await?,
+                }
+            }
+            "RCPT" => {
+                if tx.sender.is_none() {
+                    session.send("503 5.5.1 MAIL first").await?;
+                    continue;
+                }
+                if tx.recipients.len() >= MAX_RECIPIENTS {
+                    session.send("452 4.5.3 Too many recipients").await?;
+                    continue;
+                }
+                match path_argument(arg, "TO:").and_then(message::parse_address) {
+                    Some(recipient) => {
+                        tx.recipients.push(recipient);
+                        session.send("250 2.1.5 OK").await?;
+                    }
+                    None => session.send("501 5.1.3 Invalid recipient address").await?,
+                }
+            }
+            "DATA" => {
+                let (Some(slug), Some(sender)) = (&app, &tx.sender) else {
+                    session.send("503 5.5.1 MAIL first").await?;
+                    continue;
+                };
+                if tx.recipients.is_empty() {
+                    session.send("554 5.5.1 No valid recipients").await?;
+                    continue;
+                }
+                session.send("354 End data with <CR><LF>.<CR><LF>").await?;
+                let max = mail.config().max_message_size;
+                let reply = match session.data(max).await? {
+                    Some(content) => match mail.submit(slug, sender, &tx.recipients, &content) {
+                        Ok(id) => {
+                            debug!(app = %slug, id, "Mail queued from SMTP");
+                            format!("250 2.0.0 Queued as {}", id)
+                        }
+                        Err(e) => e.smtp_reply(),
+                    },
+                    None => format!("552 5.3.4 Message larger than {} bytes", max),
+                };
+                tx = Transaction::default();
+                session.send(&reply).await?;
+            }
+            "RSET" => {
+                tx = Transaction::default();
+                session.send("250 2.0.0 OK").await?;
+            }
+            "NOOP" => session.send("250 2.0.0 OK").await?,
+            "QUIT" => {
+                session.send("221 2.0.0 Bye").await?;
+                return Ok(());
+            }
+            "STARTTLS" => session.send("454 4.7.0 TLS not available").await?,
+            _ => session.send("502 5.5.2 Command not recognized").await?,
+        }
+    }
+    Ok(())
+}
+
+/// (username, secret) from AUTH PLAIN or LOGIN, prompting as needed.
+async fn read_credentials<S: AsyncRead + AsyncWrite + Unpin>(
+    session: &mut Session<S>,
+    arg: &str,
+) -> Result<Option<(String, String)>> {
+    let (mechanism, initial) = arg.split_once(' ').unwrap_or((arg, ""));
+    let decode = |b64: &[u8]| STANDARD.decode(b64).ok().and_then(|v| String::from_utf8(v).ok());
+    match mechanism.to_ascii_uppercase().as_str() {
+        "PLAIN" => {
+            let response = if initial.is_empty() {
+                session.send("334 ").await?;
+                session.line(MAX_LINE).await?.unwrap_or_default()
+            } else {
+                initial.as_bytes().to_vec()
+            };
+            // authzid \0 authcid \0 password
+            let Some(decoded) = decode(&response) else {
+                return Ok(None);
+            };
+            let mut parts = decoded.split('\0');
+            let (_, user, secret) = (parts.next(), parts.next(), parts.next());
+            Ok(user.zip(secret).map(|(u, s)| (u.to_string(), s.to_string())))
+        }
+        "LOGIN" => {
+            session.send("334 VXNlcm5hbWU6").await?;
+            let user = session.line(MAX_LINE).await?.and_then(|l| decode(&l));
+            session.send("334 UGFzc3dvcmQ6").await?;
+            let secret = session.line(MAX_LINE).await?.and_then(|l| decode(&l));
+            Ok(user.zip(secret))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Smarthost, SmarthostTls};
+    use crate::{MailStore, client};
+
+    struct Apps;
+
+    impl AppAuth for Apps {
+        async fn app_for(&self, username: Option<&str>, secret: &str) -> Option<String> {
+            (username == Some("shop") && secret == "s3cret").then(|| "shop".to_string())
+        }
+    }
+
+    #[test]
+    fn test_path_argument() {
+        assert_eq!(path_argument("FROM:<a@example.com> SIZE=100", "FROM:"), Some("a@example.com"));
+        assert_eq!(path_argument("to: <b@example.net>", "TO:"), Some("b@example.net"));
+        assert_eq!(path_argument("FROM:a@example.com", "FROM:"), None);
+    }
+
+    /// Submit through our own client, as an app would, then check the queue.
+    #[tokio::test]
+    async fn test_submission_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = MailConfig {
+            enabled: true,
+            smarthost: Smarthost { host: "unused.invalid".to_string(), ..Default::default() },
+            ..Default::default()
+        };
+        let mail = Arc::new(Mail::new(config, "example.com", dir.path().join("dkim"), MailStore::open_in_memory().unwrap()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(accept_loop(mail.clone(), Arc::new(Apps), listener));
+
+        let app = |password: &str| Smarthost {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: SmarthostTls::None,
+            username: "shop".to_string(),
+            password: password.to_string(),
+        };
+        let rcpts = vec!["alice@example.net".to_string()];
+        let message = b"From: shop@example.com\r\nSubject: Reset\r\n\r\n.dot line\r\n";
+
+        let err = client::send(&app("wrong"), "app", "shop@example.com", &rcpts, message).await.unwrap_err();
+        assert!(err.message.starts_with("AUTH: 535"));
+        let err = client::send(&app("s3cret"), "app", "boss@bank.example", &rcpts, message).await.unwrap_err();
+        assert!(err.permanent && err.message.contains("553 5.7.1"));
+
+        client::send(&app("s3cret"), "app", "shop@example.com", &rcpts, message).await.unwrap();
+        let queued = mail.store().due(i64::MAX, 10).unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].app, "shop");
+        assert_eq!(queued[0].recipients, rcpts);
+        let data = String::from_utf8(queued[0].data.clone()).unwrap();
+        assert!(data.starts_with("DKIM-Signature:"));
+        assert!(data.ends_with("\r\n\r\n.dot line\r\n"));
+    }
+}
@@ -0,0 +1,344 @@
+//! SQLite queue and delivery log (`mail.db`). A message stays queued until
+//! the smarthost accepts it; its content is dropped once delivered, the log
+//! entry is kept for the retention period.
+
+use rusqlite::{Connection, params, params_from_iter};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Default and maximum page size of `log`.
+const DEFAULT_LIMIT: u32 = 200;
+const MAX_LIMIT: u32 = 1000;
+
+pub const STATUS_QUEUED: &str = "queued";
+pub const STATUS_SENT: &str = "sent";
+pub const STATUS_FAILED: &str = "failed";
+
+/// A message due for delivery.
+#[derive(Debug, Clone)]
+pub struct QueuedMessage {
+    pub id: i64,
+    pub app: String,
+    pub sender: String,
+    pub recipients: Vec<String>,
+    pub data: Vec<u8>,
+    pub attempts: u32,
+}
+
+/// Delivery log entry (no content).
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryRecord {
+    pub id: i64,
+    pub app: String,
+    pub sender: String,
+    pub recipients: Vec<String>,
+    pub subject: Option<String>,
+    pub size: u64,
+    pub status: String,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub next_attempt: Option<i64>,
+    pub delivered_at: Option<i64>,
+}
+
+/// Filters of `GET /api/mail/log`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LogQuery {
+    pub app: Option<String>,
+    /// queued, sent or failed.
+    pub status: Option<String>,
+    /// Only entries older than this id (pagination).
+    pub before: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+/// Messages submitted by one app over the last hour and day.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppUsage {
+    pub app: String,
+    pub last_hour: u32,
+    pub last_day: u32,
+}
+
+pub struct MailStore {
+    conn: Mutex<Connection>,
+}
+
+impl MailStore {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                app TEXT NOT NULL,
+                sender TEXT NOT NULL,
+                recipients TEXT NOT NULL,
+                subject TEXT,
+                size INTEGER NOT NULL,
+                data BLOB,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                next_attempt INTEGER NOT NULL,
+                last_error TEXT,
+                created_at INTEGER NOT NULL,
+                delivered_at INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS idx_messages_due ON messages(status, next_attempt);
+            CREATE INDEX IF NOT EXISTS idx_messages_app ON messages(app, created_at);",
+        )?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    pub fn enqueue(
+        &self,
+        app: &str,
+        sender: &str,
+        recipients: &[String],
+        subject: Option<&str>,
+        data: &[u8],
+        now: i64,
+    ) -> anyhow::Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO messages (app, sender, recipients, subject, size, data, status, next_attempt, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)",
+            params![
+                app,
+                sender,
+                serde_json::to_string(recipients)?,
+                subject,
+                data.len() as i64,
+                data,
+                STATUS_QUEUED,
+                now
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Queued messages whose next attempt is due, oldest first.
+    pub fn due(&self, now: i64, limit: u32) -> anyhow::Result<Vec<QueuedMessage>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, app, sender, recipients, data, attempts FROM messages
+             WHERE status = ?1 AND next_attempt <= ?2 AND data IS NOT NULL
+             ORDER BY next_attempt, id LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(params![STATUS_QUEUED, now, limit], |row| {
+            let recipients: String = row.get(3)?;
+            Ok(QueuedMessage {
+                id: row.get(0)?,
+                app: row.get(1)?,
+                sender: row.get(2)?,
+                recipients: serde_json::from_str(&recipients).unwrap_or_default(),
+                data: row.get(4)?,
+                attempts: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Accepted by the smarthost: the content is dropped.
+    pub fn mark_sent(&self, id: i64, now: i64) -> anyhow::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE messages SET status = ?1, attempts = attempts + 1, delivered_at = ?2, last_error = NULL, data = NULL
+             WHERE id = ?3",
+            params![STATUS_SENT, now, id],
+        )?;
+        Ok(())
+    }
+
+    /// Temporary failure: try again at `next_attempt`.
+    pub fn mark_deferred(&self, id: i64, error: &str, next_attempt: i64) -> anyhow::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE messages SET attempts = attempts + 1, last_error = ?1, next_attempt = ?2 WHERE id = ?3",
+            params![error, next_attempt, id],
+        )?;
+        Ok(())
+    }
+
+    /// Permanent failure (or retries exhausted); kept for a manual retry.
+    pub fn mark_failed(&self, id: i64, error: &str) -> anyhow::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE messages SET status = ?1, attempts = attempts + 1, last_error = ?2 WHERE id = ?3",
+            params![STATUS_FAILED, error, id],
+        )?;
+        Ok(())
+    }
+
+    /// Queue a failed message again. False if unknown, not failed, or purged.
+    pub fn retry(&self, id: i64, now: i64) -> anyhow::Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE messages SET status = ?1, next_attempt = ?2, attempts = 0
+             WHERE id = ?3 AND status = ?4 AND data IS NOT NULL",
+            params![STATUS_QUEUED, now, id, STATUS_FAILED],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Messages submitted by `app` since `since` (quotas).
+    pub fn count_since(&self, app: &str, since: i64) -> anyhow::Result<u32> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row(
+            "SELECT COUNT(*) FROM messages WHERE app = ?1 AND created_at >= ?2",
+            params![app, since],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Per-app submissions over the last hour and day.
+    pub fn usage(&self, now: i64) -> anyhow::Result<Vec<AppUsage>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT app, SUM(created_at >= ?1), COUNT(*) FROM messages
+             WHERE created_at >= ?2 GROUP BY app ORDER BY app",
+        )?;
+        let rows = stmt.query_map(params![now - 3_600_000, now - 86_400_000], |row| {
+            Ok(AppUsage {
+                app: row.get(0)?,
+                last_hour: row.get(1)?,
+                last_day: row.get(2)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Number of messages by status (queued, failed).
+    pub fn counts(&self) -> anyhow::Result<(u64, u64)> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row(
+            "SELECT COALESCE(SUM(status = ?1), 0), COALESCE(SUM(status = ?2), 0) FROM messages",
+            params![STATUS_QUEUED, STATUS_FAILED],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?)
+    }
+
+    pub fn log(&self, query: &LogQuery) -> anyhow::Result<Vec<DeliveryRecord>> {
+        let mut sql = String::from(
+            "SELECT id, app, sender, recipients, subject, size, status, attempts, last_error, created_at, next_attempt, delivered_at
+             FROM messages WHERE 1 = 1",
+        );
+        let mut args: Vec<rusqlite::types::Value> = Vec::new();
+        if let Some(app) = &query.app {
+            sql.push_str(" AND app = ?");
+            args.push(app.clone().into());
+        }
+        if let Some(status) = &query.status {
+            sql.push_str(" AND status = ?");
+            args.push(status.clone().into());
+        }
+        if let Some(before) = query.before {
+            sql.push_str(" AND id < ?");
+            args.push(before.into());
+        }
+        sql.push_str(" ORDER BY id DESC LIMIT ?");
+        args.push(i64::from(query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)).into());
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(args), |row| {
+            let recipients: String = row.get(3)?;
+            let status: String = row.get(6)?;
+            let next_attempt: i64 = row.get(10)?;
+            Ok(DeliveryRecord {
+                id: row.get(0)?,
+                app: row.get(1)?,
+                sender: row.get(2)?,
+                recipients: serde_json::from_str(&recipients).unwrap_or_default(),
+                subject: row.get(4)?,
+                size: row.get(5)?,
+                next_attempt: (status == STATUS_QUEUED).then_some(next_attempt),
+                status,
+                attempts: row.get(7)?,
+                last_error: row.get(8)?,
+                created_at: row.get(9)?,
+                delivered_at: row.get(11)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Delete sent and failed entries created before `before`.
+    pub fn purge(&self, before: i64) -> anyhow::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute(
+            "DELETE FROM messages WHERE created_at < ?1 AND status != ?2",
+            params![before, STATUS_QUEUED],
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_lifecycle() {
+        let store = MailStore::open_in_memory().unwrap();
+        let rcpts = vec!["alice@example.net".to_string()];
+        let a = store.enqueue("shop", "shop@example.com", &rcpts, Some("Order"), b"data", 1000).unwrap();
+        let b = store.enqueue("shop", "shop@example.com", &rcpts, None, b"data", 1000).unwrap();
+        let c = store.enqueue("blog", "blog@example.com", &rcpts, None, b"data", 2000).unwrap();
+
+        let due = store.due(1500, 10).unwrap();
+        assert_eq!(due.iter().map(|m| m.id).collect::<Vec<_>>(), vec![a, b]);
+        assert_eq!(due[0].recipients, rcpts);
+
+        store.mark_sent(a, 1600).unwrap();
+        store.mark_deferred(b, "451 try later", 5000).unwrap();
+        store.mark_failed(c, "550 no such user").unwrap();
+        assert!(store.due(3000, 10).unwrap().is_empty());
+        assert_eq!(store.due(5000, 10).unwrap()[0].attempts, 1);
+        assert_eq!(store.counts().unwrap(), (1, 1));
+
+        let log = store.log(&LogQuery::default()).unwrap();
+        assert_eq!(log.len(), 3);
+        assert_eq!(log[2].status, STATUS_SENT);
+        assert_eq!(log[2].delivered_at, Some(1600));
+        assert_eq!(log[1].next_attempt, Some(5000));
+        let failed = store.log(&LogQuery { status: Some(STATUS_FAILED.to_string()), ..Default::default() }).unwrap();
+        assert_eq!(failed[0].last_error.as_deref(), Some("550 no such user"));
+
+        // Sent content is gone, failed can be retried
+        assert!(!store.retry(a, 6000).unwrap());
+        assert!(store.retry(c, 6000).unwrap());
+        assert_eq!(store.due(6000, 10).unwrap().len(), 2);
+
+        assert_eq!(store.count_since("shop", 1000).unwrap(), 2);
+        assert_eq!(store.count_since("shop", 1001).unwrap(), 0);
+        assert_eq!(store.purge(1500).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_usage() {
+        let store = MailStore::open_in_memory().unwrap();
+        let now = 100_000_000;
+        let rcpts = vec!["a@example.net".to_string()];
+        store.enqueue("shop", "s@example.com", &rcpts, None, b"x", now - 60_000).unwrap();
+        store.enqueue("shop", "s@example.com", &rcpts, None, b"x", now - 7_200_000).unwrap();
+        store.enqueue("old", "o@example.com", &rcpts, None, b"x", now - 90_000_000).unwrap();
+        let usage = store.usage(now).unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!((usage[0].last_hour, usage[0].last_day), (1, 2));
+    }
+}
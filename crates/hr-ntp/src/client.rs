@@ -0,0 +1,146 @@
+//! SNTP client used to poll the upstream servers.
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use tokio::net::UdpSocket;
+
+use crate::packet::{self, NtpPacket, Timestamp};
+use crate::{SyncState, UpstreamStatus};
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// One upstream measurement.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub address: SocketAddr,
+    pub offset: f64,
+    pub delay: f64,
+    pub stratum: u8,
+    pub root_delay: f64,
+    pub root_dispersion: f64,
+}
+
+impl Sample {
+    /// Maximum error bound to the primary source (RFC 5905 root distance).
+    fn root_distance(&self) -> f64 {
+        (self.root_delay + self.delay) / 2.0 + self.root_dispersion
+    }
+}
+
+/// Check a server reply against our request and turn it into a sample.
+pub fn sample_from_reply(address: SocketAddr, sent: Timestamp, reply: &NtpPacket, received: Timestamp) -> Result<Sample> {
+    if reply.mode != packet::MODE_SERVER {
+        bail!("unexpected mode {}", reply.mode);
+    }
+    if reply.origin != sent {
+        bail!("origin timestamp mismatch");
+    }
+    if reply.leap == packet::LEAP_ALARM || reply.stratum == 0 || reply.stratum >= packet::STRATUM_UNSYNC {
+        bail!("server unsynchronized (stratum {})", reply.stratum);
+    }
+    let (offset, delay) = packet::offset_and_delay(sent, reply.receive, reply.transmit, received);
+    Ok(Sample {
+        address,
+        offset,
+        delay,
+        stratum: reply.stratum,
+        root_delay: packet::from_short_format(reply.root_delay),
+        root_dispersion: packet::from_short_format(reply.root_dispersion),
+    })
+}
+
+pub async fn query(address: SocketAddr) -> Result<Sample> {
+    let bind: SocketAddr = if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse()?;
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(address).await?;
+
+    let sent = Timestamp::from_system(SystemTime::now());
+    socket.send(&NtpPacket::client_request(sent).encode()).await?;
+    let mut buf = [0u8; 512];
+    let len = tokio::time::timeout(QUERY_TIMEOUT, socket.recv(&mut buf))
+        .await
+        .context("timeout")??;
+    let received = Timestamp::from_system(SystemTime::now());
+    let reply = NtpPacket::parse(&buf[..len]).context("malformed reply")?;
+    sample_from_reply(address, sent, &reply, received)
+}
+
+async fn resolve(server: &str) -> Result<SocketAddr> {
+    if let Ok(ip) = server.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, 123));
+    }
+    let mut addrs = tokio::net::lookup_host((server, 123)).await?;
+    addrs.next().context("no address")
+}
+
+/// Reference ID announced downstream: the IPv4 address of the source
+/// (first bytes of the address for IPv6).
+fn reference_id(address: SocketAddr) -> [u8; 4] {
+    match address.ip() {
+        IpAddr::V4(ip) => ip.octets(),
+        IpAddr::V6(ip) => ip.octets()[..4].try_into().unwrap(),
+    }
+}
+
+/// Query every upstream and keep the one with the smallest root distance.
+pub async fn poll_upstreams(upstreams: &[String]) -> (Option<SyncState>, Vec<UpstreamStatus>) {
+    let results = query_all(upstreams).await;
+    let mut statuses = Vec::new();
+    let mut best: Option<(&String, Sample)> = None;
+    for (server, result) in upstreams.iter().zip(results) {
+        match result {
+            Ok(sample) => {
+                statuses.push(UpstreamStatus {
+                    server: server.clone(),
+                    address: Some(sample.address.ip().to_string()),
+                    reachable: true,
+                    stratum: Some(sample.stratum),
+                    offset_ms: Some(sample.offset * 1000.0),
+                    delay_ms: Some(sample.delay * 1000.0),
+                    error: None,
+                });
+                if best.as_ref().is_none_or(|(_, b)| sample.root_distance() < b.root_distance()) {
+                    best = Some((server, sample));
+                }
+            }
+            Err(e) => statuses.push(UpstreamStatus {
+                server: server.clone(),
+                address: None,
+                reachable: false,
+                stratum: None,
+                offset_ms: None,
+                delay_ms: None,
+                error: Some(format!("{e:#}")),
+            }),
+        }
+    }
+    let sync = best.map(|(server, s)| SyncState {
+        offset: s.offset,
+        delay: s.delay,
+        stratum: s.stratum,
+        root_delay: s.root_delay + s.delay,
+        root_dispersion: s.root_dispersion,
+        reference_id: reference_id(s.address),
+        source: server.clone(),
+        synced_at: SystemTime::now(),
+    });
+    (sync, statuses)
+}
+
+async fn query_all(upstreams: &[String]) -> Vec<Result<Sample>> {
+    let mut tasks = tokio::task::JoinSet::new();
+    for (i, server) in upstreams.iter().enumerate() {
+        let server = server.clone();
+        tasks.spawn(async move { (i, async { query(resolve(&server).await?).await }.await) });
+    }
+    let mut results: Vec<Option<Result<Sample>>> = upstreams.iter().map(|_| None).collect();
+    while let Some(Ok((i, result))) = tasks.join_next().await {
+        results[i] = Some(result);
+    }
+    results
+        .into_iter()
+        .map(|r| r.unwrap_or_else(|| Err(anyhow::anyhow!("query aborted"))))
+        .collect()
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// NTP server configuration (ntp-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NtpConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_bind")]
+    pub bind_address: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Upstream servers or pools queried for the reference time.
+    #[serde(default = "default_upstreams")]
+    pub upstreams: Vec<String>,
+    /// Seconds between upstream polls.
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval_secs: u64,
+}
+
+fn default_bind() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_port() -> u16 {
+    123
+}
+
+fn default_upstreams() -> Vec<String> {
+    (0..4).map(|i| format!("{}.pool.ntp.org", i)).collect()
+}
+
+fn default_poll_interval() -> u64 {
+    256
+}
+
+impl Default for NtpConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl NtpConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bind_address.parse::<IpAddr>().is_err() {
+            return Err(format!("adresse d'écoute invalide: {}", self.bind_address));
+        }
+        if self.port == 0 {
+            return Err("port invalide".to_string());
+        }
+        if self.upstreams.is_empty() {
+            return Err("au moins un serveur amont est requis".to_string());
+        }
+        if let Some(bad) = self.upstreams.iter().find(|u| u.trim().is_empty() || u.contains(' ')) {
+            return Err(format!("serveur amont invalide: '{}'", bad));
+        }
+        if !(16..=86400).contains(&self.poll_interval_secs) {
+            return Err("intervalle d'interrogation invalide (16 à 86400 secondes)".to_string());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+//! NTP server for the LAN: polls upstream servers to measure the offset of
+//! the local clock, and answers clients with the corrected time at
+//! upstream stratum + 1. The host clock itself is left alone.
+
+pub mod client;
+pub mod config;
+pub mod packet;
+pub mod server;
+
+pub use config::NtpConfig;
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tokio::sync::watch;
+
+/// Result of the last successful upstream poll.
+#[derive(Debug, Clone)]
+pub struct SyncState {
+    /// Seconds to add to the local clock.
+    pub offset: f64,
+    pub delay: f64,
+    pub stratum: u8,
+    pub root_delay: f64,
+    pub root_dispersion: f64,
+    pub reference_id: [u8; 4],
+    pub source: String,
+    pub synced_at: SystemTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpstreamStatus {
+    pub server: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    pub reachable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stratum: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NtpStatus {
+    pub running: bool,
+    pub synced: bool,
+    /// Stratum announced to clients (16 = unsynchronized).
+    pub stratum: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_sync: Option<String>,
+    pub upstreams: Vec<UpstreamStatus>,
+    /// Client requests answered.
+    pub served: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Shared NTP handle: the API pushes config and reads status, the
+/// supervised `ntp` service polls upstreams and answers clients.
+pub struct Ntp {
+    config: watch::Sender<NtpConfig>,
+    status: RwLock<NtpStatus>,
+    sync: RwLock<Option<SyncState>>,
+    pub(crate) served: AtomicU64,
+}
+
+impl Ntp {
+    pub fn new(config: NtpConfig) -> Self {
+        Self {
+            config: watch::channel(config).0,
+            status: RwLock::new(NtpStatus::default()),
+            sync: RwLock::new(None),
+            served: AtomicU64::new(0),
+        }
+    }
+
+    /// Current configuration.
+    pub fn config(&self) -> NtpConfig {
+        self.config.borrow().clone()
+    }
+
+    /// Replace the configuration; the socket is rebound and upstreams re-polled.
+    pub fn apply(&self, config: NtpConfig) {
+        self.config.send_replace(config);
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<NtpConfig> {
+        self.config.subscribe()
+    }
+
+    /// Last sync, if recent enough to serve time from.
+    pub fn sync_state(&self) -> Option<SyncState> {
+        let max_age = server::max_sync_age(&self.config.borrow());
+        self.sync
+            .read()
+            .unwrap()
+            .clone()
+            .filter(|s| s.synced_at.elapsed().is_ok_and(|age| age < max_age))
+    }
+
+    pub(crate) fn set_sync(&self, sync: SyncState) {
+        *self.sync.write().unwrap() = Some(sync);
+    }
+
+    pub fn status(&self) -> NtpStatus {
+        let mut status = self.status.read().unwrap().clone();
+        status.served = self.served.load(Ordering::Relaxed);
+        match self.sync_state() {
+            Some(sync) => {
+                status.synced = true;
+                status.stratum = sync.stratum.saturating_add(1);
+                status.offset_ms = Some(sync.offset * 1000.0);
+                status.delay_ms = Some(sync.delay * 1000.0);
+                status.source = Some(sync.source);
+                status.last_sync = Some(chrono::DateTime::<chrono::Utc>::from(sync.synced_at).to_rfc3339());
+            }
+            None => status.stratum = packet::STRATUM_UNSYNC,
+        }
+        status
+    }
+
+    pub(crate) fn set_status(&self, running: bool, error: Option<String>) {
+        let mut status = self.status.write().unwrap();
+        status.running = running;
+        status.last_error = error;
+    }
+
+    pub(crate) fn set_upstreams(&self, upstreams: Vec<UpstreamStatus>) {
+        self.status.write().unwrap().upstreams = upstreams;
+    }
+}
+
+pub type SharedNtp = Arc<Ntp>;
@@ -0,0 +1,168 @@
+//! NTPv4 packet header (RFC 5905 §7.3) and 64-bit timestamps.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const PACKET_LEN: usize = 48;
+
+pub const MODE_CLIENT: u8 = 3;
+pub const MODE_SERVER: u8 = 4;
+
+/// Leap indicator "clock unsynchronized".
+pub const LEAP_ALARM: u8 = 3;
+/// Stratum of an unsynchronized server.
+pub const STRATUM_UNSYNC: u8 = 16;
+
+/// Seconds between 1900 (NTP era 0) and 1970.
+const UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// NTP timestamp: seconds since 1900 in the high 32 bits, fraction below.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timestamp(pub u64);
+
+impl Timestamp {
+    pub fn from_system(time: SystemTime) -> Self {
+        let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = (since.as_secs() + UNIX_OFFSET) & 0xffff_ffff;
+        let frac = ((since.subsec_nanos() as u64) << 32) / 1_000_000_000;
+        Self((secs << 32) | frac)
+    }
+
+    /// Local clock shifted by `offset` seconds.
+    pub fn now_with_offset(offset: f64) -> Self {
+        let now = SystemTime::now();
+        let shifted = if offset >= 0.0 {
+            now + Duration::from_secs_f64(offset)
+        } else {
+            now - Duration::from_secs_f64(-offset)
+        };
+        Self::from_system(shifted)
+    }
+
+    /// Seconds as a float, for offset arithmetic.
+    pub fn as_secs_f64(self) -> f64 {
+        (self.0 >> 32) as f64 + (self.0 & 0xffff_ffff) as f64 / 4_294_967_296.0
+    }
+
+    pub fn to_system(self) -> SystemTime {
+        let secs = (self.0 >> 32).saturating_sub(UNIX_OFFSET);
+        let nanos = ((self.0 & 0xffff_ffff) * 1_000_000_000) >> 32;
+        UNIX_EPOCH + Duration::new(secs, nanos as u32)
+    }
+}
+
+/// Seconds as NTP short format (16.16 fixed point).
+pub fn short_format(secs: f64) -> u32 {
+    (secs.clamp(0.0, 65535.0) * 65536.0) as u32
+}
+
+pub fn from_short_format(value: u32) -> f64 {
+    value as f64 / 65536.0
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NtpPacket {
+    pub leap: u8,
+    pub version: u8,
+    pub mode: u8,
+    pub stratum: u8,
+    pub poll: i8,
+    pub precision: i8,
+    pub root_delay: u32,
+    pub root_dispersion: u32,
+    pub reference_id: [u8; 4],
+    pub reference: Timestamp,
+    pub origin: Timestamp,
+    pub receive: Timestamp,
+    pub transmit: Timestamp,
+}
+
+impl NtpPacket {
+    /// Parse the header, ignoring extension fields and MAC.
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < PACKET_LEN {
+            return None;
+        }
+        let u32_at = |i: usize| u32::from_be_bytes(buf[i..i + 4].try_into().unwrap());
+        let ts_at = |i: usize| Timestamp(u64::from_be_bytes(buf[i..i + 8].try_into().unwrap()));
+        Some(Self {
+            leap: buf[0] >> 6,
+            version: (buf[0] >> 3) & 0x07,
+            mode: buf[0] & 0x07,
+            stratum: buf[1],
+            poll: buf[2] as i8,
+            precision: buf[3] as i8,
+            root_delay: u32_at(4),
+            root_dispersion: u32_at(8),
+            reference_id: buf[12..16].try_into().unwrap(),
+            reference: ts_at(16),
+            origin: ts_at(24),
+            receive: ts_at(32),
+            transmit: ts_at(40),
+        })
+    }
+
+    pub fn encode(&self) -> [u8; PACKET_LEN] {
+        let mut buf = [0u8; PACKET_LEN];
+        buf[0] = (self.leap << 6) | ((self.version & 0x07) << 3) | (self.mode & 0x07);
+        buf[1] = self.stratum;
+        buf[2] = self.poll as u8;
+        buf[3] = self.precision as u8;
+        buf[4..8].copy_from_slice(&self.root_delay.to_be_bytes());
+        buf[8..12].copy_from_slice(&self.root_dispersion.to_be_bytes());
+        buf[12..16].copy_from_slice(&self.reference_id);
+        buf[16..24].copy_from_slice(&self.reference.0.to_be_bytes());
+        buf[24..32].copy_from_slice(&self.origin.0.to_be_bytes());
+        buf[32..40].copy_from_slice(&self.receive.0.to_be_bytes());
+        buf[40..48].copy_from_slice(&self.transmit.0.to_be_bytes());
+        buf
+    }
+
+    /// Client request carrying `transmit` as its origin cookie.
+    pub fn client_request(transmit: Timestamp) -> Self {
+        Self {
+            version: 4,
+            mode: MODE_CLIENT,
+            transmit,
+            ..Default::default()
+        }
+    }
+}
+
+/// Clock offset and round-trip delay (seconds) from the four timestamps of
+/// an exchange: t1 sent, t2 received by the server, t3 sent back, t4 received.
+pub fn offset_and_delay(t1: Timestamp, t2: Timestamp, t3: Timestamp, t4: Timestamp) -> (f64, f64) {
+    let (t1, t2, t3, t4) = (t1.as_secs_f64(), t2.as_secs_f64(), t3.as_secs_f64(), t4.as_secs_f64());
+    let offset = ((t2 - t1) + (t3 - t4)) / 2.0;
+    let delay = (t4 - t1) - (t3 - t2);
+    (offset, delay.max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp_round_trip() {
+        let time = UNIX_EPOCH + Duration::new(1_700_000_000, 250_000_000);
+        let ts = Timestamp::from_system(time);
+        assert_eq!(ts.0 >> 32, 1_700_000_000 + UNIX_OFFSET);
+        assert_eq!(ts.0 & 0xffff_ffff, 1 << 30);
+        let back = ts.to_system().duration_since(time).unwrap_or_else(|e| e.duration());
+        assert!(back < Duration::from_micros(1));
+    }
+
+    #[test]
+    fn test_packet_and_offset() {
+        let request = NtpPacket::client_request(Timestamp(42 << 32));
+        let parsed = NtpPacket::parse(&request.encode()).unwrap();
+        assert_eq!(parsed, request);
+        assert_eq!((parsed.version, parsed.mode), (4, MODE_CLIENT));
+        assert!(NtpPacket::parse(&[0u8; 47]).is_none());
+
+        // Server 2 s ahead, 100 ms each way
+        let s = |secs: f64| Timestamp((secs * 4_294_967_296.0) as u64);
+        let (offset, delay) = offset_and_delay(s(100.0), s(102.1), s(102.2), s(100.3));
+        assert!((offset - 2.0).abs() < 1e-6);
+        assert!((delay - 0.2).abs() < 1e-6);
+    }
+}
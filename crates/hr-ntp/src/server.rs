@@ -0,0 +1,209 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
+
+use crate::client;
+use crate::config::NtpConfig;
+use crate::packet::{self, NtpPacket, Timestamp};
+use crate::{Ntp, SyncState};
+
+/// Delay before rebinding after a socket failure.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+/// Poll interval while no upstream answers.
+const UNSYNC_POLL: Duration = Duration::from_secs(32);
+/// Advertised clock precision (log2 seconds, ~1 µs).
+const PRECISION: i8 = -20;
+/// Dispersion growth rate (RFC 5905 PHI), seconds per second.
+const PHI: f64 = 15e-6;
+
+/// A sync older than this is not served anymore (4 missed polls).
+pub(crate) fn max_sync_age(config: &NtpConfig) -> Duration {
+    Duration::from_secs(config.poll_interval_secs.saturating_mul(4))
+}
+
+/// Run the NTP server, restarting polling and rebinding whenever the
+/// configuration changes.
+pub async fn run_ntp(ntp: Arc<Ntp>) -> Result<()> {
+    let mut config_rx = ntp.subscribe();
+    loop {
+        let config = config_rx.borrow_and_update().clone();
+        tokio::select! {
+            _ = run_config(ntp.clone(), config) => {}
+            changed = config_rx.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Serve one configuration until it is replaced (never returns on its own).
+async fn run_config(ntp: Arc<Ntp>, config: NtpConfig) {
+    if !config.enabled {
+        ntp.set_status(false, None);
+        ntp.set_upstreams(Vec::new());
+        return std::future::pending().await;
+    }
+    tokio::join!(poll_loop(&ntp, &config), listen_loop(&ntp, &config));
+}
+
+async fn poll_loop(ntp: &Ntp, config: &NtpConfig) {
+    loop {
+        let (sync, upstreams) = client::poll_upstreams(&config.upstreams).await;
+        ntp.set_upstreams(upstreams);
+        let next = match sync {
+            Some(sync) => {
+                debug!(
+                    "NTP synced to {} (stratum {}, offset {:.3} ms)",
+                    sync.source,
+                    sync.stratum,
+                    sync.offset * 1000.0
+                );
+                ntp.set_sync(sync);
+                Duration::from_secs(config.poll_interval_secs)
+            }
+            None => {
+                warn!("NTP: no upstream server reachable");
+                UNSYNC_POLL
+            }
+        };
+        tokio::time::sleep(next).await;
+    }
+}
+
+async fn listen_loop(ntp: &Ntp, config: &NtpConfig) {
+    loop {
+        let ip: IpAddr = config.bind_address.parse().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let addr = SocketAddr::new(ip, config.port);
+        match UdpSocket::bind(addr).await.with_context(|| format!("UDP bind {}", addr)) {
+            Ok(socket) => {
+                info!("NTP server listening on udp/{}", addr);
+                ntp.set_status(true, None);
+                let e = serve(ntp, socket).await;
+                warn!("NTP server stopped: {:#}", e);
+                ntp.set_status(false, Some(format!("{e:#}")));
+            }
+            Err(e) => {
+                warn!("NTP server failed to start: {:#}", e);
+                ntp.set_status(false, Some(format!("{e:#}")));
+            }
+        }
+        tokio::time::sleep(RETRY_DELAY).await;
+    }
+}
+
+async fn serve(ntp: &Ntp, socket: UdpSocket) -> anyhow::Error {
+    let mut buf = [0u8; 512];
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buf).await {
+            Ok(r) => r,
+            Err(e) => return e.into(),
+        };
+        let sync = ntp.sync_state();
+        let received = Timestamp::now_with_offset(sync.as_ref().map_or(0.0, |s| s.offset));
+        let Some(request) = NtpPacket::parse(&buf[..len]) else {
+            continue;
+        };
+        let Some(reply) = build_reply(sync.as_ref(), &request, received) else {
+            continue;
+        };
+        ntp.served.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = socket.send_to(&reply.encode(), peer).await {
+            debug!("NTP reply to {} failed: {}", peer, e);
+        }
+    }
+}
+
+/// Answer a client-mode request. Without a recent upstream sync the reply
+/// carries the alarm leap indicator and stratum 16, so clients ignore it.
+pub fn build_reply(sync: Option<&SyncState>, request: &NtpPacket, received: Timestamp) -> Option<NtpPacket> {
+    if request.mode != packet::MODE_CLIENT || !(1..=4).contains(&request.version) {
+        return None;
+    }
+    let mut reply = NtpPacket {
+        version: request.version,
+        mode: packet::MODE_SERVER,
+        poll: request.poll,
+        precision: PRECISION,
+        origin: request.transmit,
+        receive: received,
+        ..Default::default()
+    };
+    match sync {
+        Some(sync) => {
+            let age = sync.synced_at.elapsed().unwrap_or_default().as_secs_f64();
+            reply.stratum = sync.stratum.saturating_add(1);
+            reply.root_delay = packet::short_format(sync.root_delay);
+            reply.root_dispersion = packet::short_format(sync.root_dispersion + age * PHI);
+            reply.reference_id = sync.reference_id;
+            reply.reference = Timestamp::from_system(sync.synced_at);
+            reply.transmit = Timestamp::now_with_offset(sync.offset);
+        }
+        None => {
+            reply.leap = packet::LEAP_ALARM;
+            reply.stratum = packet::STRATUM_UNSYNC;
+            reply.reference_id = *b"INIT";
+            reply.transmit = Timestamp::from_system(SystemTime::now());
+        }
+    }
+    Some(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sync(offset: f64) -> SyncState {
+        SyncState {
+            offset,
+            delay: 0.02,
+            stratum: 2,
+            root_delay: 0.03,
+            root_dispersion: 0.01,
+            reference_id: [192, 0, 2, 1],
+            source: "0.pool.ntp.org".to_string(),
+            synced_at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn test_reply_serves_corrected_time() {
+        let request = NtpPacket::client_request(Timestamp::from_system(SystemTime::now()));
+        let received = Timestamp::now_with_offset(5.0);
+        let reply = build_reply(Some(&sync(5.0)), &request, received).unwrap();
+        assert_eq!(reply.mode, packet::MODE_SERVER);
+        assert_eq!(reply.stratum, 3);
+        assert_eq!(reply.leap, 0);
+        assert_eq!(reply.origin, request.transmit);
+        assert_eq!(reply.reference_id, [192, 0, 2, 1]);
+
+        // The client sees our clock 5 s ahead of its own
+        let (offset, _) = packet::offset_and_delay(
+            request.transmit,
+            reply.receive,
+            reply.transmit,
+            Timestamp::from_system(SystemTime::now()),
+        );
+        assert!((offset - 5.0).abs() < 0.1);
+        let sample = client::sample_from_reply("192.0.2.10:123".parse().unwrap(), request.transmit, &reply, request.transmit);
+        assert!(sample.is_ok());
+    }
+
+    #[test]
+    fn test_unsynced_reply_and_filtering() {
+        let request = NtpPacket::client_request(Timestamp(1 << 32));
+        let reply = build_reply(None, &request, Timestamp(2 << 32)).unwrap();
+        assert_eq!((reply.leap, reply.stratum), (packet::LEAP_ALARM, packet::STRATUM_UNSYNC));
+        let err = client::sample_from_reply("192.0.2.10:123".parse().unwrap(), request.transmit, &reply, Timestamp(3 << 32));
+        assert!(err.is_err());
+
+        // Server-mode packets are not requests
+        assert!(build_reply(None, &reply, Timestamp(2 << 32)).is_none());
+    }
+}
@@ -0,0 +1,264 @@
+//! Target selection for routes with several upstreams.
+//!
+//! Targets the health checker reports as unhealthy are never selected.
+//! Targets that just refused a connection are put aside for
+//! `FAILURE_COOLDOWN` and only used when nothing else is left.
+//! Draining targets only keep the clients pinned to them by the affinity
+//! cookie (and their open WebSockets); new clients go elsewhere.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::LoadBalancePolicy;
+
+/// How long a target that refused a connection is skipped.
+const FAILURE_COOLDOWN: Duration = Duration::from_secs(10);
+
+/// Name of the session affinity cookie.
+pub const AFFINITY_COOKIE: &str = "hr_backend";
+
+/// State of a target for one selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetState {
+    Up,
+    /// Being retired: only sticky clients keep using it.
+    Draining,
+    /// Reported unhealthy: never selected.
+    Down,
+}
+
+/// Opaque affinity cookie value for a target (FNV-1a, so the backend
+/// address isn't exposed to clients).
+pub fn affinity_token(target: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in target.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+#[derive(Default)]
+pub struct LoadBalancer {
+    /// Round-robin position per route key.
+    cursors: Mutex<HashMap<String, usize>>,
+    /// In-flight requests per target (`host:port`).
+    active: Arc<Mutex<HashMap<String, usize>>>,
+    /// Targets that recently refused a connection → when they may be retried.
+    failed: Mutex<HashMap<String, Instant>>,
+    /// Targets drained at runtime (app migrations), on top of route config.
+    draining: Mutex<HashSet<String>>,
+}
+
+impl LoadBalancer {
+    /// Order `targets` for one request, preferred target first.
+    ///
+    /// `Down` targets are dropped, recently failed ones go after the other
+    /// up targets and draining ones last. The target matching the client's
+    /// `affinity` token comes first unless it is down or just failed. An
+    /// empty result means every target is down.
+    pub fn order(
+        &self,
+        key: &str,
+        targets: &[String],
+        policy: LoadBalancePolicy,
+        affinity: Option<&str>,
+        state: impl Fn(&str) -> TargetState,
+    ) -> Vec<String> {
+        let mut up = Vec::new();
+        let mut draining = Vec::new();
+        {
+            let drained = self.draining.lock().unwrap();
+            for target in targets {
+                match state(target) {
+                    TargetState::Down => {}
+                    TargetState::Draining => draining.push(target.clone()),
+                    TargetState::Up if drained.contains(target) => draining.push(target.clone()),
+                    TargetState::Up => up.push(target.clone()),
+                }
+            }
+        }
+
+        if up.len() > 1 {
+            // Rotate so equal candidates take turns
+            let cursor = {
+                let mut cursors = self.cursors.lock().unwrap();
+                let cursor = cursors.entry(key.to_string()).or_insert(0);
+                let current = *cursor;
+                *cursor = cursor.wrapping_add(1);
+                current
+            };
+            let len = up.len();
+            up.rotate_left(cursor % len);
+
+            if policy == LoadBalancePolicy::LeastConnections {
+                let active = self.active.lock().unwrap();
+                up.sort_by_key(|t| active.get(t).copied().unwrap_or(0));
+            }
+        }
+
+        let now = Instant::now();
+        let mut failed = self.failed.lock().unwrap();
+        failed.retain(|_, until| *until > now);
+        up.sort_by_key(|t| failed.contains_key(t));
+
+        let mut candidates = up;
+        candidates.extend(draining);
+        if let Some(token) = affinity
+            && let Some(pos) = candidates
+                .iter()
+                .position(|t| affinity_token(t) == token && !failed.contains_key(t))
+        {
+            let pinned = candidates.remove(pos);
+            candidates.insert(0, pinned);
+        }
+        candidates
+    }
+
+    /// Count a request as in flight on `target` until the guard is dropped.
+    pub fn acquire(&self, target: &str) -> ActiveGuard {
+        *self.active.lock().unwrap().entry(target.to_string()).or_insert(0) += 1;
+        ActiveGuard {
+            active: self.active.clone(),
+            target: target.to_string(),
+        }
+    }
+
+    /// Put a target aside after a refused connection.
+    pub fn mark_failed(&self, target: &str) {
+        self.failed
+            .lock()
+            .unwrap()
+            .insert(target.to_string(), Instant::now() + FAILURE_COOLDOWN);
+    }
+
+    /// Clear a previous failure once the target answered again.
+    pub fn mark_ok(&self, target: &str) {
+        let mut failed = self.failed.lock().unwrap();
+        if !failed.is_empty() {
+            failed.remove(target);
+        }
+    }
+
+    /// Stop sending new clients to a target.
+    pub fn drain(&self, target: &str) {
+        self.draining.lock().unwrap().insert(target.to_string());
+    }
+
+    pub fn undrain(&self, target: &str) {
+        self.draining.lock().unwrap().remove(target);
+    }
+
+    /// In-flight requests and open WebSockets on a target.
+    pub fn active(&self, target: &str) -> usize {
+        self.active.lock().unwrap().get(target).copied().unwrap_or(0)
+    }
+}
+
+/// Decrements the in-flight counter of a target on drop.
+pub struct ActiveGuard {
+    active: Arc<Mutex<HashMap<String, usize>>>,
+    target: String,
+}
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        let mut active = self.active.lock().unwrap();
+        if let Some(count) = active.get_mut(&self.target) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                active.remove(&self.target);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets() -> Vec<String> {
+        vec!["a:80".to_string(), "b:80".to_string(), "c:80".to_string()]
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let lb = LoadBalancer::default();
+        let first: Vec<String> = (0..4)
+            .map(|_| lb.order("r", &targets(), LoadBalancePolicy::RoundRobin, None, |_| TargetState::Up)[0].clone())
+            .collect();
+        assert_eq!(first, vec!["a:80", "b:80", "c:80", "a:80"]);
+    }
+
+    #[test]
+    fn test_least_connections() {
+        let lb = LoadBalancer::default();
+        let _a = lb.acquire("a:80");
+        let _c1 = lb.acquire("c:80");
+        let _c2 = lb.acquire("c:80");
+        let order = lb.order("r", &targets(), LoadBalancePolicy::LeastConnections, None, |_| TargetState::Up);
+        assert_eq!(order, vec!["b:80", "a:80", "c:80"]);
+
+        drop(_c1);
+        drop(_c2);
+        assert_eq!(lb.active("c:80"), 0);
+        assert_eq!(lb.active("a:80"), 1);
+    }
+
+    fn down_if_a(t: &str) -> TargetState {
+        if t == "a:80" { TargetState::Down } else { TargetState::Up }
+    }
+
+    #[test]
+    fn test_exclusion() {
+        let lb = LoadBalancer::default();
+        let order = lb.order("r", &targets(), LoadBalancePolicy::RoundRobin, None, down_if_a);
+        assert!(!order.contains(&"a:80".to_string()));
+
+        // Recently failed targets are kept as a last resort only
+        lb.mark_failed("b:80");
+        for _ in 0..3 {
+            let order = lb.order("r", &targets(), LoadBalancePolicy::RoundRobin, None, down_if_a);
+            assert_eq!(order, vec!["c:80", "b:80"]);
+        }
+        lb.mark_ok("b:80");
+        let order = lb.order("r", &targets(), LoadBalancePolicy::RoundRobin, None, |_| TargetState::Up);
+        assert_eq!(order.len(), 3);
+
+        let order = lb.order("r", &targets(), LoadBalancePolicy::RoundRobin, None, |_| TargetState::Down);
+        assert!(order.is_empty());
+    }
+
+    #[test]
+    fn test_affinity_and_draining() {
+        let lb = LoadBalancer::default();
+        let token = affinity_token("b:80");
+        assert_eq!(token.len(), 16);
+        assert_ne!(token, affinity_token("c:80"));
+
+        for _ in 0..3 {
+            let order = lb.order("r", &targets(), LoadBalancePolicy::RoundRobin, Some(&token), |_| TargetState::Up);
+            assert_eq!(order[0], "b:80");
+        }
+
+        // New clients avoid a draining target, pinned ones keep it
+        lb.drain("b:80");
+        for _ in 0..3 {
+            let order = lb.order("r", &targets(), LoadBalancePolicy::RoundRobin, None, |_| TargetState::Up);
+            assert_eq!(order[2], "b:80");
+        }
+        let order = lb.order("r", &targets(), LoadBalancePolicy::RoundRobin, Some(&token), |_| TargetState::Up);
+        assert_eq!(order[0], "b:80");
+
+        // Pinned target that just refused a connection is not forced first
+        lb.mark_failed("b:80");
+        let order = lb.order("r", &targets(), LoadBalancePolicy::RoundRobin, Some(&token), |_| TargetState::Up);
+        assert_ne!(order[0], "b:80");
+
+        lb.undrain("b:80");
+        lb.mark_ok("b:80");
+        let order = lb.order("r", &targets(), LoadBalancePolicy::RoundRobin, Some(&token), down_if_a);
+        assert_eq!(order, vec!["b:80", "c:80"]);
+    }
+}
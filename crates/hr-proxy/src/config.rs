@@ -1,5 +1,7 @@
+use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::path::PathBuf;
 
 /// Configuration principale du reverse proxy
@@ -32,6 +34,54 @@ pub struct ProxyConfig {
     /// Chemin du fichier de log d'accès JSON (optionnel)
     #[serde(default)]
     pub access_log_path: Option<String>,
+
+    /// Rotation et mémoire tampon du log d'accès
+    #[serde(default)]
+    pub access_log: AccessLogConfig,
+
+    /// Base GeoIP au format MMDB (GeoLite2-Country, DB-IP...) pour les
+    /// filtres par pays des routes
+    #[serde(default)]
+    pub geoip_database: Option<String>,
+
+    /// En-têtes de transfert pour les routes d'applications et les domaines
+    /// de gestion (les routes statiques ont leur propre réglage)
+    #[serde(default)]
+    pub forwarding: ForwardingConfig,
+
+    /// Domaine dont la route sert l'apex du domaine de base (ex.
+    /// `www.mynetwk.biz` ou le domaine d'une application), quand aucune
+    /// route ne déclare l'apex lui-même
+    #[serde(default)]
+    pub apex_target: Option<String>,
+}
+
+/// Rotation du fichier de log d'accès et taille du tampon mémoire
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessLogConfig {
+    /// Rotation quand le fichier dépasse cette taille (Mo)
+    #[serde(default = "default_log_max_size_mb")]
+    pub max_size_mb: u64,
+    /// Rotation quand le fichier est plus vieux (heures, 0 = jamais)
+    #[serde(default = "default_log_max_age_hours")]
+    pub max_age_hours: u64,
+    /// Nombre de fichiers archivés conservés (`access.log.1`, `.2`...)
+    #[serde(default = "default_log_keep_files")]
+    pub keep_files: usize,
+    /// Entrées gardées en mémoire pour `/api/reverseproxy/logs`
+    #[serde(default = "default_log_buffer_size")]
+    pub buffer_size: usize,
+}
+
+fn default_log_max_size_mb() -> u64 { 50 }
+fn default_log_max_age_hours() -> u64 { 24 }
+fn default_log_keep_files() -> usize { 7 }
+fn default_log_buffer_size() -> usize { 10_000 }
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
 }
 
 fn default_http_port() -> u16 { 80 }
@@ -61,10 +111,19 @@ pub struct RouteConfig {
     #[serde(default)]
     pub local_only: bool,
 
+    /// Accessible uniquement depuis le tailnet (plages VPN de l'intégration
+    /// Tailscale), pour l'accès distant sans exposition publique
+    #[serde(default)]
+    pub vpn_only: bool,
+
     /// Requérir authentification
     #[serde(default)]
     pub require_auth: bool,
 
+    /// Route sensible : session ouverte avec un second facteur (TOTP ou passkey)
+    #[serde(default)]
+    pub require_mfa: bool,
+
     /// Actif ou non
     #[serde(default = "default_enabled")]
     pub enabled: bool,
@@ -72,11 +131,255 @@ pub struct RouteConfig {
     /// ID du certificat CA (auto-généré si vide)
     #[serde(default)]
     pub cert_id: Option<String>,
+
+    /// Health check actif de la cible
+    #[serde(default)]
+    pub health_check: HealthCheckConfig,
+
+    /// Cibles supplémentaires (répliques), en plus de target_host:target_port
+    #[serde(default)]
+    pub targets: Vec<RouteTarget>,
+
+    /// Répartition des requêtes entre les cibles
+    #[serde(default)]
+    pub load_balancing: LoadBalancePolicy,
+
+    /// Affinité de session (cookie) quand il y a plusieurs cibles
+    #[serde(default)]
+    pub sticky: bool,
+
+    /// Cibles en cours de retrait (`host:port`) : seules les sessions déjà
+    /// attachées y restent, les nouvelles vont ailleurs
+    #[serde(default)]
+    pub draining: Vec<String>,
+
+    /// Filtrage des clients publics par pays (GeoIP)
+    #[serde(default)]
+    pub geo: GeoAccess,
+
+    /// Mode maintenance : page d'attente au lieu du service
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+
+    /// Traitement des en-têtes X-Forwarded-* / Forwarded reçus
+    #[serde(default)]
+    pub forwarding: ForwardingConfig,
+
+    /// La cible parle HTTPS : le trafic est rechiffré jusqu'à elle (SNI du
+    /// domaine de la route, certificat de la cible non vérifié)
+    #[serde(default)]
+    pub backend_tls: bool,
+
+    /// Publier un enregistrement DNS local vers le routeur pour le domaine
+    /// (les clients du LAN n'ont pas à passer par l'adresse publique)
+    #[serde(default = "default_enabled")]
+    pub local_dns: bool,
+}
+
+/// Listes de pays (codes ISO 3166-1 alpha-2) autorisés / refusés sur une
+/// route. Les adresses privées ne sont jamais filtrées.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeoAccess {
+    /// Si non vide, seuls ces pays passent (pays inconnu refusé)
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Pays toujours refusés
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl GeoAccess {
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// Whether a client from `country` may use the route.
+    pub fn allows(&self, country: Option<&str>) -> bool {
+        let listed = |list: &[String]| country.is_some_and(|c| list.iter().any(|l| l.eq_ignore_ascii_case(c)));
+        if listed(&self.deny) {
+            return false;
+        }
+        self.allow.is_empty() || listed(&self.allow)
+    }
+}
+
+/// Mode maintenance d'une route. Les clients des adresses autorisées
+/// (administrateurs) accèdent toujours au service.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Titre de la page (défaut : "Maintenance en cours")
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Message affiché sous le titre
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Page HTML complète remplaçant la page par défaut
+    #[serde(default)]
+    pub html: Option<String>,
+    /// Adresses ou réseaux (CIDR) qui passent malgré la maintenance
+    #[serde(default)]
+    pub allow_ips: Vec<String>,
+}
+
+impl MaintenanceConfig {
+    /// Whether `ip` is on the allowlist (a bare address or a CIDR entry).
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        ip_listed(&self.allow_ips, ip)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(bad) = invalid_ip_entry(&self.allow_ips) {
+            return Err(format!("adresse invalide dans la liste autorisée: {}", bad));
+        }
+        Ok(())
+    }
+}
+
+/// Sort des en-têtes X-Forwarded-* / Forwarded envoyés par le client
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForwardedProfile {
+    /// Supprimés : la chaîne repart de l'adresse du pair (accès direct)
+    #[default]
+    Strip,
+    /// Conservés et complétés quand le pair est un proxy de confiance
+    /// (relais VPS, Cloudflare...), supprimés sinon
+    Trust,
+}
+
+/// En-têtes de transfert d'une route : proxies de confiance et émission
+/// de `Forwarded` (RFC 7239) en plus des X-Forwarded-*.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForwardingConfig {
+    #[serde(default)]
+    pub profile: ForwardedProfile,
+    /// Adresses ou réseaux (CIDR) des proxies de confiance
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    /// Ajouter l'en-tête `Forwarded` vers le backend
+    #[serde(default)]
+    pub emit_forwarded: bool,
+}
+
+impl ForwardingConfig {
+    /// Whether headers sent by `peer` are honored.
+    pub fn trusts(&self, peer: IpAddr) -> bool {
+        self.profile == ForwardedProfile::Trust && ip_listed(&self.trusted_proxies, peer)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(bad) = invalid_ip_entry(&self.trusted_proxies) {
+            return Err(format!("proxy de confiance invalide: {}", bad));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `ip` matches one of `entries` (bare addresses or CIDR networks).
+fn ip_listed(entries: &[String], ip: IpAddr) -> bool {
+    let ip = ip.to_canonical();
+    entries.iter().any(|entry| {
+        let entry = entry.trim();
+        match entry.parse::<IpNet>() {
+            Ok(net) => net.contains(&ip),
+            Err(_) => entry.parse::<IpAddr>().is_ok_and(|addr| addr == ip),
+        }
+    })
+}
+
+/// First entry that is neither an address nor a CIDR network.
+fn invalid_ip_entry(entries: &[String]) -> Option<&String> {
+    entries
+        .iter()
+        .find(|e| e.trim().parse::<IpNet>().is_err() && e.trim().parse::<IpAddr>().is_err())
+}
+
+/// Cible supplémentaire d'une route
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteTarget {
+    pub host: String,
+    pub port: u16,
+}
+
+/// Politique de répartition entre les cibles d'une route
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBalancePolicy {
+    /// Chaque requête va à la cible suivante
+    #[default]
+    RoundRobin,
+    /// La cible avec le moins de requêtes en cours
+    LeastConnections,
+}
+
+impl RouteConfig {
+    /// Toutes les cibles `host:port`, la cible principale en premier (sans doublons)
+    pub fn upstreams(&self) -> Vec<String> {
+        let mut upstreams = vec![format!("{}:{}", self.target_host, self.target_port)];
+        for target in &self.targets {
+            let addr = format!("{}:{}", target.host, target.port);
+            if !upstreams.contains(&addr) {
+                upstreams.push(addr);
+            }
+        }
+        upstreams
+    }
 }
 
 fn default_backend() -> String { "rust".to_string() }
 fn default_enabled() -> bool { true }
 
+/// Type de sonde pour le health check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthCheckKind {
+    /// Simple connexion TCP sur target_host:target_port
+    Tcp,
+    /// GET HTTP sur `path`, sain si le statut est < 500 (ou égal à `expected_status`)
+    Http,
+}
+
+/// Configuration du health check d'une route
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthCheckConfig {
+    /// Sondes actives, à activer route par route : désactivées par défaut,
+    /// les routes existantes ne changent pas de comportement
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_health_kind")]
+    pub kind: HealthCheckKind,
+    /// Chemin sondé en mode HTTP
+    #[serde(default = "default_health_path")]
+    pub path: String,
+    #[serde(default)]
+    pub expected_status: Option<u16>,
+    #[serde(default = "default_health_interval")]
+    pub interval_secs: u64,
+    #[serde(default = "default_health_timeout")]
+    pub timeout_secs: u64,
+    /// Échecs consécutifs avant de marquer la cible hors service
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+    /// Succès consécutifs avant de la remettre en service
+    #[serde(default = "default_healthy_threshold")]
+    pub healthy_threshold: u32,
+}
+
+fn default_health_kind() -> HealthCheckKind { HealthCheckKind::Tcp }
+fn default_health_path() -> String { "/".to_string() }
+fn default_health_interval() -> u64 { 10 }
+fn default_health_timeout() -> u64 { 3 }
+fn default_unhealthy_threshold() -> u32 { 3 }
+fn default_healthy_threshold() -> u32 { 1 }
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
 impl ProxyConfig {
     /// Charge la configuration depuis un fichier JSON
     pub fn load_from_file(path: &PathBuf) -> anyhow::Result<Self> {
@@ -123,6 +426,10 @@ mod tests {
             ca_storage_path: PathBuf::from("/var/lib/server-dashboard/ca"),
             routes: vec![],
             access_log_path: None,
+            access_log: Default::default(),
+            geoip_database: None,
+            forwarding: Default::default(),
+            apex_target: None,
         };
 
         assert_eq!(config.https_port, 443);
@@ -145,9 +452,21 @@ mod tests {
                     target_host: "localhost".to_string(),
                     target_port: 8080,
                     local_only: false,
+                    vpn_only: false,
                     require_auth: false,
+                    require_mfa: false,
                     enabled: true,
                     cert_id: None,
+                    health_check: Default::default(),
+                    targets: Vec::new(),
+                    load_balancing: Default::default(),
+                    sticky: false,
+                    draining: Vec::new(),
+                    geo: Default::default(),
+                    maintenance: Default::default(),
+                    forwarding: Default::default(),
+                    backend_tls: false,
+                    local_dns: true,
                 },
                 RouteConfig {
                     id: "2".to_string(),
@@ -156,9 +475,21 @@ mod tests {
                     target_host: "localhost".to_string(),
                     target_port: 8081,
                     local_only: false,
+                    vpn_only: false,
                     require_auth: false,
+                    require_mfa: false,
                     enabled: true,
                     cert_id: None,
+                    health_check: Default::default(),
+                    targets: Vec::new(),
+                    load_balancing: Default::default(),
+                    sticky: false,
+                    draining: Vec::new(),
+                    geo: Default::default(),
+                    maintenance: Default::default(),
+                    forwarding: Default::default(),
+                    backend_tls: false,
+                    local_dns: true,
                 },
                 RouteConfig {
                     id: "3".to_string(),
@@ -167,12 +498,28 @@ mod tests {
                     target_host: "localhost".to_string(),
                     target_port: 8082,
                     local_only: false,
+                    vpn_only: false,
                     require_auth: false,
+                    require_mfa: false,
                     enabled: false,
                     cert_id: None,
+                    health_check: Default::default(),
+                    targets: Vec::new(),
+                    load_balancing: Default::default(),
+                    sticky: false,
+                    draining: Vec::new(),
+                    geo: Default::default(),
+                    maintenance: Default::default(),
+                    forwarding: Default::default(),
+                    backend_tls: false,
+                    local_dns: true,
                 },
             ],
             access_log_path: None,
+            access_log: Default::default(),
+            geoip_database: None,
+            forwarding: Default::default(),
+            apex_target: None,
         };
 
         let active = config.active_routes();
@@ -181,4 +528,45 @@ mod tests {
         assert_eq!(active[0].domain, "test1.example.com");
         assert_eq!(active[1].domain, "test2.example.com");
     }
+
+    #[test]
+    fn test_health_check_defaults() {
+        let route: RouteConfig = serde_json::from_str(
+            r#"{"id":"1","domain":"a.example.com","target_host":"10.0.0.5","target_port":80}"#,
+        )
+        .unwrap();
+        assert!(!route.health_check.enabled);
+        assert_eq!(route.health_check.kind, HealthCheckKind::Tcp);
+        assert_eq!(route.health_check.interval_secs, 10);
+
+        let route: RouteConfig = serde_json::from_str(
+            r#"{"id":"1","domain":"a.example.com","target_host":"10.0.0.5","target_port":80,
+                "health_check":{"enabled":true,"kind":"http","path":"/healthz","expected_status":204}}"#,
+        )
+        .unwrap();
+        assert!(route.health_check.enabled);
+        assert_eq!(route.health_check.kind, HealthCheckKind::Http);
+        assert_eq!(route.health_check.path, "/healthz");
+        assert_eq!(route.health_check.expected_status, Some(204));
+        assert_eq!(route.health_check.unhealthy_threshold, 3);
+    }
+
+    #[test]
+    fn test_upstreams() {
+        let route: RouteConfig = serde_json::from_str(
+            r#"{"id":"1","domain":"a.example.com","target_host":"10.0.0.5","target_port":80}"#,
+        )
+        .unwrap();
+        assert_eq!(route.upstreams(), vec!["10.0.0.5:80"]);
+        assert_eq!(route.load_balancing, LoadBalancePolicy::RoundRobin);
+
+        let route: RouteConfig = serde_json::from_str(
+            r#"{"id":"1","domain":"a.example.com","target_host":"10.0.0.5","target_port":80,
+                "targets":[{"host":"10.0.0.6","port":80},{"host":"10.0.0.5","port":80}],
+                "load_balancing":"least_connections"}"#,
+        )
+        .unwrap();
+        assert_eq!(route.upstreams(), vec!["10.0.0.5:80", "10.0.0.6:80"]);
+        assert_eq!(route.load_balancing, LoadBalancePolicy::LeastConnections);
+    }
 }
@@ -0,0 +1,245 @@
+//! Forwarding headers sent upstream (`X-Forwarded-*`, `X-Real-IP` and
+//! RFC 7239 `Forwarded`).
+//!
+//! Headers received from the peer are only kept when it is a trusted proxy
+//! (the relay VPS, Cloudflare...): the peer is then appended to the chain
+//! and the client is the last address of the chain that isn't a trusted
+//! proxy. Anything else is stripped and the chain restarts at the peer, so
+//! a client can't spoof its address to the backend.
+
+use std::net::IpAddr;
+
+use axum::http::{HeaderMap, HeaderValue};
+
+use crate::config::ForwardingConfig;
+
+/// Headers a client must never be able to set itself. The identity headers
+/// are only ever added by forward-auth, after this sanitation.
+const SPOOFABLE: &[&str] = &[
+    "x-forwarded-for",
+    "x-forwarded-host",
+    "x-forwarded-proto",
+    "x-forwarded-port",
+    "x-real-ip",
+    "forwarded",
+];
+
+/// Identity headers set by forward-auth for the backend.
+pub const IDENTITY: &[&str] = &["x-forwarded-user", "x-forwarded-groups"];
+
+/// Where a request comes from: the connection's address (TCP peer, or the
+/// client address of a relay `StreamHeader`) and the client resolved from
+/// the forwarding headers of trusted proxies. Bans, GeoIP, maintenance
+/// allowlists and access logs all go by `ip`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientAddr {
+    pub peer: IpAddr,
+    pub ip: IpAddr,
+}
+
+/// Request extension set on requests from the cloud relay when the VPS
+/// itself sits behind a proxy that announced the client with the PROXY
+/// protocol: the address of that proxy, for the access log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelayUpstream(pub IpAddr);
+
+impl ClientAddr {
+    /// A client connected without any proxy in front.
+    pub fn direct(ip: IpAddr) -> Self {
+        let ip = ip.to_canonical();
+        Self { peer: ip, ip }
+    }
+
+    /// Client of a request received from `peer`.
+    pub fn resolve(headers: &HeaderMap, peer: IpAddr, config: &ForwardingConfig) -> Self {
+        let peer = peer.to_canonical();
+        if !config.trusts(peer) {
+            return Self { peer, ip: peer };
+        }
+        let mut chain = header_list(headers, "x-forwarded-for");
+        chain.push(peer.to_string());
+        let ip = client_from_chain(&chain, config).unwrap_or(peer);
+        Self { peer, ip }
+    }
+}
+
+/// Rewrite the forwarding headers of a request from `client` for `host`.
+pub fn apply(headers: &mut HeaderMap, client: ClientAddr, host: &str, config: &ForwardingConfig) {
+    let peer = client.peer;
+    let trusted = config.trusts(peer);
+
+    let mut chain: Vec<String> = Vec::new();
+    let mut forwarded: Vec<String> = Vec::new();
+    let mut proto = None;
+    let mut fwd_host = None;
+    if trusted {
+        chain = header_list(headers, "x-forwarded-for");
+        forwarded = header_list(headers, "forwarded");
+        proto = header_str(headers, "x-forwarded-proto");
+        fwd_host = header_str(headers, "x-forwarded-host");
+    }
+    for name in SPOOFABLE {
+        headers.remove(*name);
+    }
+
+    chain.push(peer.to_string());
+
+    let proto = proto.unwrap_or_else(|| "https".to_string());
+    let fwd_host = fwd_host.unwrap_or_else(|| host.to_string());
+
+    if let Ok(v) = HeaderValue::from_str(&chain.join(", ")) {
+        headers.insert("X-Forwarded-For", v);
+    }
+    if let Ok(v) = HeaderValue::from_str(&client.ip.to_string()) {
+        headers.insert("X-Real-IP", v);
+    }
+    if let Ok(v) = HeaderValue::from_str(&fwd_host) {
+        headers.insert("X-Forwarded-Host", v);
+    }
+    if let Ok(v) = HeaderValue::from_str(&proto) {
+        headers.insert("X-Forwarded-Proto", v);
+    }
+
+    if config.emit_forwarded {
+        forwarded.push(format!(
+            "for={};host={};proto={}",
+            forwarded_node(peer),
+            quote_if_needed(host),
+            proto
+        ));
+        if let Ok(v) = HeaderValue::from_str(&forwarded.join(", ")) {
+            headers.insert("Forwarded", v);
+        }
+    }
+}
+
+/// Rightmost address of the chain that isn't a trusted proxy (the
+/// leftmost one if every hop is trusted).
+fn client_from_chain(chain: &[String], config: &ForwardingConfig) -> Option<IpAddr> {
+    let hops: Vec<IpAddr> = chain
+        .iter()
+        .filter_map(|h| h.parse::<IpAddr>().ok())
+        .map(|ip| ip.to_canonical())
+        .collect();
+    hops.iter()
+        .rev()
+        .find(|ip| !config.trusts(**ip))
+        .or_else(|| hops.first())
+        .copied()
+}
+
+/// Comma-separated elements of every occurrence of a header.
+fn header_list(headers: &HeaderMap, name: &str) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|e| e.trim().to_string())
+        .filter(|e| !e.is_empty())
+        .collect()
+}
+
+fn header_str(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(',').next().unwrap_or("").trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// `for=` node of RFC 7239: IPv6 addresses are bracketed and quoted.
+fn forwarded_node(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => format!("\"[{}]\"", v6),
+    }
+}
+
+/// Quote a value unless it is a plain RFC 7230 token (`host:port` isn't).
+fn quote_if_needed(value: &str) -> String {
+    let is_token = value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+    if is_token {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ForwardedProfile;
+
+    fn trusting(proxies: &[&str]) -> ForwardingConfig {
+        ForwardingConfig {
+            profile: ForwardedProfile::Trust,
+            trusted_proxies: proxies.iter().map(|p| p.to_string()).collect(),
+            emit_forwarded: true,
+        }
+    }
+
+    fn spoofed() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_static("1.2.3.4"));
+        headers.insert("x-real-ip", HeaderValue::from_static("1.2.3.4"));
+        headers.insert("forwarded", HeaderValue::from_static("for=1.2.3.4"));
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("http"));
+        headers
+    }
+
+    #[test]
+    fn test_strip_untrusted() {
+        let mut headers = spoofed();
+        let peer: IpAddr = "203.0.113.7".parse().unwrap();
+        let config = ForwardingConfig::default();
+        let client = ClientAddr::resolve(&headers, peer, &config);
+        assert_eq!(client, ClientAddr::direct(peer));
+        apply(&mut headers, client, "app.example.com", &config);
+        assert_eq!(headers["x-forwarded-for"], "203.0.113.7");
+        assert_eq!(headers["x-real-ip"], "203.0.113.7");
+        assert_eq!(headers["x-forwarded-proto"], "https");
+        assert!(headers.get("forwarded").is_none());
+
+        // Trust profile, but the peer isn't one of the proxies
+        let mut headers = spoofed();
+        let config = trusting(&["10.0.0.0/8"]);
+        let client = ClientAddr::resolve(&headers, peer, &config);
+        assert_eq!(client.ip, peer);
+        apply(&mut headers, client, "app.example.com", &config);
+        assert_eq!(headers["x-forwarded-for"], "203.0.113.7");
+        assert_eq!(headers["forwarded"], "for=203.0.113.7;host=app.example.com;proto=https");
+    }
+
+    #[test]
+    fn test_trusted_chain_append() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_static("198.51.100.9, 172.70.1.1"));
+        headers.insert("forwarded", HeaderValue::from_static("for=198.51.100.9"));
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("https"));
+        let config = trusting(&["172.64.0.0/13", "10.0.0.1"]);
+
+        let client = ClientAddr::resolve(&headers, "10.0.0.1".parse().unwrap(), &config);
+        assert_eq!(client.ip, "198.51.100.9".parse::<IpAddr>().unwrap());
+        apply(&mut headers, client, "app.example.com", &config);
+        assert_eq!(headers["x-forwarded-for"], "198.51.100.9, 172.70.1.1, 10.0.0.1");
+        assert_eq!(headers["x-real-ip"], "198.51.100.9");
+        assert_eq!(
+            headers["forwarded"],
+            "for=198.51.100.9, for=10.0.0.1;host=app.example.com;proto=https"
+        );
+    }
+
+    #[test]
+    fn test_forwarded_ipv6_and_port() {
+        let mut headers = HeaderMap::new();
+        let client = ClientAddr::direct("2001:db8::1".parse().unwrap());
+        apply(&mut headers, client, "app.example.com:8443", &trusting(&[]));
+        assert_eq!(
+            headers["forwarded"],
+            "for=\"[2001:db8::1]\";host=\"app.example.com:8443\";proto=https"
+        );
+    }
+}
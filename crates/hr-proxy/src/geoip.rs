@@ -0,0 +1,134 @@
+use std::net::IpAddr;
+use std::path::Path;
+
+use maxminddb::geoip2;
+
+/// Country lookups in a MaxMind-format database (GeoLite2-Country,
+/// GeoLite2-City, DB-IP country lite...).
+pub struct GeoIp {
+    reader: maxminddb::Reader<Vec<u8>>,
+}
+
+impl GeoIp {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let reader = maxminddb::Reader::open_readfile(path)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        Ok(Self { reader })
+    }
+
+    /// ISO 3166-1 alpha-2 code of the client's country. `None` for private
+    /// addresses and addresses missing from the database.
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        if !is_public(ip) {
+            return None;
+        }
+        let record: geoip2::Country = self.reader.lookup(ip).ok()?;
+        record
+            .country
+            .or(record.registered_country)
+            .and_then(|c| c.iso_code)
+            .map(str::to_string)
+    }
+}
+
+/// Whether `ip` is a routable Internet address (the only ones GeoIP rules
+/// apply to).
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                // CGNAT 100.64.0.0/10 (Tailscale, carrier NAT)
+                || (v4.octets()[0] == 100 && v4.octets()[1] & 0xc0 == 64))
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || first & 0xfe00 == 0xfc00 // ULA
+                || first & 0xffc0 == 0xfe80) // link-local
+        }
+    }
+}
+
+/// Database configured in `geoip_database` and the outcome of loading it.
+#[derive(Clone, Default)]
+pub(crate) struct GeoIpState {
+    pub path: Option<String>,
+    pub db: Option<std::sync::Arc<GeoIp>>,
+    pub error: Option<String>,
+}
+
+impl GeoIpState {
+    pub fn load(path: Option<&str>) -> Self {
+        let Some(path) = path.filter(|p| !p.is_empty()) else {
+            return Self::default();
+        };
+        match GeoIp::open(Path::new(path)) {
+            Ok(db) => {
+                tracing::info!("GeoIP database loaded from {}", path);
+                Self { path: Some(path.to_string()), db: Some(std::sync::Arc::new(db)), error: None }
+            }
+            Err(e) => {
+                tracing::warn!("Failed to load GeoIP database: {}", e);
+                Self { path: Some(path.to_string()), db: None, error: Some(e.to_string()) }
+            }
+        }
+    }
+
+    pub fn status(&self) -> GeoIpStatus {
+        GeoIpStatus {
+            database: self.path.clone(),
+            loaded: self.db.is_some(),
+            error: self.error.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GeoIpStatus {
+    pub database: Option<String>,
+    pub loaded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GeoAccess;
+
+    #[test]
+    fn test_public_addresses() {
+        for ip in ["10.0.0.5", "192.168.1.1", "100.100.1.1", "127.0.0.1", "fd00::1", "fe80::1", "::ffff:192.168.1.1"] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["8.8.8.8", "100.128.0.1", "2001:4860::8888", "::ffff:1.1.1.1"] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn test_geo_access() {
+        let open = GeoAccess::default();
+        assert!(open.allows(None) && open.allows(Some("CN")));
+
+        let deny = GeoAccess { allow: vec![], deny: vec!["cn".into(), "RU".into()] };
+        assert!(!deny.allows(Some("CN")));
+        assert!(deny.allows(Some("FR")));
+        assert!(deny.allows(None));
+
+        let allow = GeoAccess { allow: vec!["FR".into(), "BE".into()], deny: vec!["BE".into()] };
+        assert!(allow.allows(Some("fr")));
+        assert!(!allow.allows(Some("BE")));
+        assert!(!allow.allows(Some("US")));
+        // Unknown country fails closed when an allow list is set
+        assert!(!allow.allows(None));
+    }
+}
@@ -5,6 +5,7 @@ use axum::{
     response::{IntoResponse, Response},
 };
 use hr_auth::forward_auth::{check_forward_auth, ForwardAuthResult};
+use hr_auth::shares::{SHARE_COOKIE, SHARE_PARAM};
 use hr_auth::AuthService;
 use hyper_util::client::legacy::Client;
 use hyper_util::rt::TokioExecutor;
@@ -15,10 +16,15 @@ use tokio::net::TcpStream;
 use tracing::{debug, error, info, warn};
 
 use hr_common::events::{EventBus, HostPowerState};
+use hr_firewall::{Offense, SharedBanManager};
 use hr_registry::protocol::{ServiceAction, ServiceType};
 use hr_registry::AgentRegistry;
 
-use crate::config::{ProxyConfig, RouteConfig};
+use crate::balancer::{affinity_token, ActiveGuard, LoadBalancer, TargetState, AFFINITY_COOKIE};
+use crate::config::{ForwardingConfig, LoadBalancePolicy, MaintenanceConfig, ProxyConfig, RouteConfig};
+use crate::forwarded::{self, ClientAddr, RelayUpstream};
+use crate::geoip::{self, GeoIpState, GeoIpStatus};
+use crate::health::{BackendHealth, HealthStatus};
 use crate::logging::{self, AccessLogEntry, OptionalAccessLogger};
 
 /// Route to an agent-managed application (LXC container).
@@ -33,6 +39,26 @@ pub struct AppRoute {
     pub service_type: ServiceType,
     pub wake_page_enabled: bool,
     pub local_only: bool,
+    /// Replicas serving the same app (other hosts, or the destination of a
+    /// live migration), balanced with `target_ip`.
+    pub extra_targets: Vec<std::net::Ipv4Addr>,
+    pub load_balancing: LoadBalancePolicy,
+    /// Pin each client to one replica with the affinity cookie.
+    pub sticky: bool,
+}
+
+impl AppRoute {
+    /// All `ip:port` targets, `target_ip` first (without duplicates).
+    pub fn upstreams(&self) -> Vec<String> {
+        let mut upstreams = vec![format!("{}:{}", self.target_ip, self.target_port)];
+        for ip in &self.extra_targets {
+            let addr = format!("{}:{}", ip, self.target_port);
+            if !upstreams.contains(&addr) {
+                upstreams.push(addr);
+            }
+        }
+        upstreams
+    }
 }
 
 /// Snapshot of parsed config for fast lookups
@@ -52,6 +78,8 @@ pub struct ProxyState {
     pub access_logger: OptionalAccessLogger,
     /// Auth service (direct call, no HTTP round-trip)
     pub auth: Option<Arc<AuthService>>,
+    /// Ban manager: banned clients are refused, 401/403 bursts reported.
+    pub bans: Option<SharedBanManager>,
     /// Management API port for proxy.{base_domain} and auth.{base_domain}
     pub management_port: u16,
     /// Application routes: domain → AppRoute (agent-managed LXC containers).
@@ -60,6 +88,18 @@ pub struct ProxyState {
     registry: RwLock<Option<Arc<AgentRegistry>>>,
     /// Event bus for service command notifications (WOD transparent wait).
     events: RwLock<Option<Arc<EventBus>>>,
+    /// DNS state, for the page of hosts blocked by the adblock sinkhole.
+    dns: RwLock<Option<hr_dns::SharedDnsState>>,
+    /// Health of static route targets: route id → one entry per target (see `health`).
+    pub(crate) health: RwLock<std::collections::HashMap<String, Vec<BackendHealth>>>,
+    /// Target selection for routes with several upstreams.
+    pub(crate) balancer: LoadBalancer,
+    /// Country database for per-route GeoIP rules and access logs.
+    geoip: RwLock<GeoIpState>,
+    /// Tailnet address ranges, the only clients of VPN-only routes.
+    vpn_ranges: RwLock<Vec<ipnet::IpNet>>,
+    /// Notified when the config is reloaded or an app route changes.
+    pub routes_changed: tokio::sync::Notify,
 }
 
 impl ProxyState {
@@ -73,7 +113,8 @@ impl ProxyState {
             .build()
             .expect("Failed to build HTTPS client");
 
-        let access_logger = OptionalAccessLogger::new(config.access_log_path.clone());
+        let access_logger = OptionalAccessLogger::new(config.access_log_path.clone(), config.access_log.clone());
+        let geoip = GeoIpState::load(config.geoip_database.as_deref());
 
         Self {
             client,
@@ -83,10 +124,17 @@ impl ProxyState {
             }),
             access_logger,
             auth: None,
+            bans: None,
             management_port,
             app_routes: RwLock::new(std::collections::HashMap::new()),
             registry: RwLock::new(None),
             events: RwLock::new(None),
+            dns: RwLock::new(None),
+            health: RwLock::new(std::collections::HashMap::new()),
+            balancer: LoadBalancer::default(),
+            geoip: RwLock::new(geoip),
+            vpn_ranges: RwLock::new(Vec::new()),
+            routes_changed: tokio::sync::Notify::new(),
         }
     }
 
@@ -96,6 +144,12 @@ impl ProxyState {
         self
     }
 
+    /// Set the ban manager fed with auth failures.
+    pub fn with_bans(mut self, bans: SharedBanManager) -> Self {
+        self.bans = Some(bans);
+        self
+    }
+
     /// Set the agent registry for ActivityPing and Wake-on-Demand.
     pub fn set_registry(&self, registry: Arc<AgentRegistry>) {
         *self.registry.write().unwrap() = Some(registry);
@@ -112,14 +166,51 @@ impl ProxyState {
     }
 
     /// Get a clone of the event bus reference.
-    fn get_events(&self) -> Option<Arc<EventBus>> {
+    pub(crate) fn get_events(&self) -> Option<Arc<EventBus>> {
         self.events.read().unwrap().clone()
     }
 
+    /// Set the DNS state, enabling the page of sinkholed hosts.
+    pub fn set_dns(&self, dns: hr_dns::SharedDnsState) {
+        *self.dns.write().unwrap() = Some(dns);
+    }
+
+    /// "Blocked by HomeRoute" page of a host the adblock sinkhole sent
+    /// `client` to, `None` when the host was not blocked.
+    pub async fn sinkhole_page(&self, host: &str, client: IpAddr) -> Option<String> {
+        let dns = self.dns.read().unwrap().clone()?;
+        crate::sinkhole::blocked_page(&dns, &self.base_domain(), host, client).await
+    }
+
     /// Reload the proxy config (called on SIGHUP)
     pub fn reload_config(&self, new_config: ProxyConfig) {
+        if self.geoip.read().unwrap().path != new_config.geoip_database {
+            *self.geoip.write().unwrap() = GeoIpState::load(new_config.geoip_database.as_deref());
+        }
         let mut snapshot = self.snapshot.write().unwrap();
         snapshot.config = new_config;
+        self.routes_changed.notify_one();
+    }
+
+    /// Country of a client (public addresses, GeoIP database loaded).
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        let db = self.geoip.read().unwrap().db.clone()?;
+        db.country(ip)
+    }
+
+    pub fn geoip_status(&self) -> GeoIpStatus {
+        self.geoip.read().unwrap().status()
+    }
+
+    /// Set the tailnet ranges VPN-only routes accept clients from.
+    pub fn set_vpn_ranges(&self, ranges: Vec<ipnet::IpNet>) {
+        *self.vpn_ranges.write().unwrap() = ranges;
+    }
+
+    /// Whether a client connects through the tailnet.
+    pub fn is_vpn_client(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.vpn_ranges.read().unwrap().iter().any(|net| net.contains(&ip))
     }
 
     /// Find the route matching a given Host header
@@ -140,6 +231,48 @@ impl ProxyState {
         snapshot.config.base_domain.clone()
     }
 
+    /// Forwarding headers policy of app routes and management domains.
+    fn default_forwarding(&self) -> ForwardingConfig {
+        self.snapshot.read().unwrap().config.forwarding.clone()
+    }
+
+    /// Domain whose route serves `domain`: `apex_target` for the bare base
+    /// domain when no route declares it, `domain` itself otherwise.
+    pub fn route_domain(&self, domain: &str) -> String {
+        let apex_target = {
+            let snapshot = self.snapshot.read().unwrap();
+            let config = &snapshot.config;
+            match &config.apex_target {
+                Some(target)
+                    if domain == config.base_domain
+                        && !config.routes.iter().any(|r| r.enabled && r.domain == domain) =>
+                {
+                    Some(target.clone())
+                }
+                _ => None,
+            }
+        };
+        match apex_target {
+            Some(target) if self.get_app_route(domain).is_none() => target,
+            _ => domain.to_string(),
+        }
+    }
+
+    /// Forwarding headers policy for requests to `host`, following the same
+    /// precedence as request dispatch (management, app, then static routes).
+    pub fn forwarding_for(&self, host: &str) -> ForwardingConfig {
+        let domain = self.route_domain(host.split(':').next().unwrap_or(host));
+        let base_domain = self.base_domain();
+        let is_management = domain == format!("proxy.{}", base_domain) || domain == format!("auth.{}", base_domain);
+        if !is_management
+            && self.get_app_route(&domain).is_none()
+            && let Some(route) = self.find_route(&domain)
+        {
+            return route.forwarding;
+        }
+        self.default_forwarding()
+    }
+
     /// Get a clone of the current proxy config
     pub fn config(&self) -> ProxyConfig {
         let snapshot = self.snapshot.read().unwrap();
@@ -150,7 +283,9 @@ impl ProxyState {
     pub fn set_app_route(&self, domain: String, route: AppRoute) {
         let mut map = self.app_routes.write().unwrap();
         info!(domain = domain, target = %route.target_ip, port = route.target_port, "Added app route");
-        map.insert(domain, route);
+        if map.insert(domain, route).is_none() {
+            self.routes_changed.notify_one();
+        }
     }
 
     /// Remove an application route by domain.
@@ -158,20 +293,113 @@ impl ProxyState {
         let mut map = self.app_routes.write().unwrap();
         if map.remove(domain).is_some() {
             info!(domain = domain, "Removed app route");
+            self.routes_changed.notify_one();
         }
     }
 
+    /// Replace the replicas of every route of an application (empty = single target).
+    pub fn set_app_route_targets(&self, app_id: &str, extra_targets: Vec<std::net::Ipv4Addr>) {
+        let mut map = self.app_routes.write().unwrap();
+        for (domain, route) in map.iter_mut().filter(|(_, r)| r.app_id == app_id) {
+            info!(domain = %domain, extra = ?extra_targets, "Updated app route targets");
+            route.extra_targets = extra_targets.clone();
+        }
+    }
+
+    /// Domains of the application routes.
+    pub fn app_route_domains(&self) -> Vec<String> {
+        self.app_routes.read().unwrap().keys().cloned().collect()
+    }
+
     /// Look up an application route for a given domain.
     pub fn get_app_route(&self, domain: &str) -> Option<AppRoute> {
         let map = self.app_routes.read().unwrap();
         map.get(domain).cloned()
     }
+
+    /// Current health of a static route's targets (None if not checked).
+    pub fn route_health(&self, route_id: &str) -> Option<Vec<BackendHealth>> {
+        self.health.read().unwrap().get(route_id).cloned()
+    }
+
+    /// Health of all checked route targets, keyed by route id.
+    pub fn all_route_health(&self) -> std::collections::HashMap<String, Vec<BackendHealth>> {
+        self.health.read().unwrap().clone()
+    }
+
+    /// Targets of a static route in the order they should be tried.
+    /// Empty when the health checker reports every target down.
+    fn route_candidates(&self, route: &RouteConfig, affinity: Option<&str>) -> Vec<String> {
+        let health = self.health.read().unwrap();
+        let checked = health.get(&route.id);
+        let affinity = affinity.filter(|_| route.sticky);
+        self.balancer.order(&route.id, &route.upstreams(), route.load_balancing, affinity, |target| {
+            let down = checked.is_some_and(|entries| {
+                entries
+                    .iter()
+                    .any(|h| h.target == target && h.status == HealthStatus::Unhealthy)
+            });
+            if down {
+                TargetState::Down
+            } else if route.draining.iter().any(|d| d == target) {
+                TargetState::Draining
+            } else {
+                TargetState::Up
+            }
+        })
+    }
+
+    /// In-flight requests and open WebSockets on a target (`host:port`).
+    pub fn target_connections(&self, target: &str) -> usize {
+        self.balancer.active(target)
+    }
+
+    /// Move an app route to a new target while clients pinned to the previous
+    /// one (and its open WebSockets) finish there. Returns the drained
+    /// `ip:port` when the target changed; call `finish_app_drain` with it.
+    pub fn set_app_route_draining(&self, domain: String, mut route: AppRoute) -> Option<String> {
+        let previous = self.get_app_route(&domain)?;
+        if previous.target_ip == route.target_ip || previous.target_port != route.target_port {
+            self.set_app_route(domain, route);
+            return None;
+        }
+        let drained = format!("{}:{}", previous.target_ip, previous.target_port);
+        if !route.extra_targets.contains(&previous.target_ip) {
+            route.extra_targets.push(previous.target_ip);
+        }
+        self.balancer.drain(&drained);
+        info!(domain = %domain, from = %drained, to = %route.target_ip, "Draining app route target");
+        self.set_app_route(domain, route);
+        Some(drained)
+    }
+
+    /// Wait until a drained app target has no connection left (or `timeout`),
+    /// then drop it from the app's routes.
+    pub async fn finish_app_drain(&self, app_id: &str, target: &str, timeout: std::time::Duration) {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.balancer.active(target) > 0 && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+        let remaining = self.balancer.active(target);
+        {
+            let mut map = self.app_routes.write().unwrap();
+            for route in map.values_mut().filter(|r| r.app_id == app_id) {
+                route
+                    .extra_targets
+                    .retain(|ip| format!("{}:{}", ip, route.target_port) != target);
+            }
+        }
+        self.balancer.undrain(target);
+        info!(app_id, target, remaining, "App route target drained");
+    }
 }
 
-/// Main proxy handler - dispatches by Host header
+/// Main proxy handler - dispatches by Host header. `peer_ip` is the TCP
+/// peer, or the client address announced by the relay for tunneled streams;
+/// the client is resolved from it the same way on both paths.
 pub async fn proxy_handler(
     state: Arc<ProxyState>,
-    client_ip: IpAddr,
+    peer_ip: IpAddr,
     req: Request,
 ) -> Result<Response, ProxyError> {
     let start = std::time::Instant::now();
@@ -195,22 +423,45 @@ pub async fn proxy_handler(
         .and_then(|v| v.to_str().ok())
         .unwrap_or("")
         .to_string();
-
-    let result = proxy_handler_inner(state.clone(), client_ip, req).await;
+    let via = req.extensions().get::<RelayUpstream>().map(|u| u.0.to_string());
+
+    let client = ClientAddr::resolve(req.headers(), peer_ip, &state.forwarding_for(&host_for_log));
+    let client_ip = client.ip;
+    let country = state.country(client_ip);
+    let result = match &state.bans {
+        Some(bans) if bans.is_banned(client_ip) => Err(ProxyError::Forbidden),
+        _ => proxy_handler_inner(state.clone(), client, country.as_deref(), req).await,
+    };
 
     let status = match &result {
         Ok(resp) => resp.status().as_u16(),
         Err(e) => match e {
             ProxyError::DomainNotFound(_) => 404,
-            ProxyError::Forbidden => 403,
+            ProxyError::Forbidden | ProxyError::Sinkholed(_) => 403,
             ProxyError::AuthRequired(_) => 302,
             ProxyError::UpstreamError(_) => 502,
+            ProxyError::ServiceUnavailable(_) | ProxyError::Maintenance(_) => 503,
             ProxyError::InvalidUri(_) => 400,
         },
     };
 
+    // Blocked trackers are not auth failures
+    if matches!(status, 401 | 403)
+        && !matches!(result, Err(ProxyError::Sinkholed(_)))
+        && let Some(bans) = &state.bans
+    {
+        bans.report(client_ip, Offense::AuthFailure);
+    }
+
     let duration_ms = start.elapsed().as_millis() as u64;
 
+    // Unknown hosts share one series so scanners can't grow the label set
+    let route = match &result {
+        Err(ProxyError::DomainNotFound(_) | ProxyError::Sinkholed(_)) => "unknown".to_string(),
+        _ => host_for_log.split(':').next().unwrap_or("").to_lowercase(),
+    };
+    record_request_metrics(&route, status, start.elapsed().as_secs_f64());
+
     // Log to file
     state.access_logger.log(AccessLogEntry {
         timestamp: logging::now_timestamp(),
@@ -221,6 +472,8 @@ pub async fn proxy_handler(
         status,
         duration_ms,
         user_agent,
+        country,
+        via,
     });
 
     // Clear Alt-Svc to prevent QUIC/h3 errors in LAN — Cloudflare advertises
@@ -236,12 +489,35 @@ pub async fn proxy_handler(
     }
 }
 
+fn record_request_metrics(route: &str, status: u16, seconds: f64) {
+    let registry = hr_common::metrics::registry();
+    let class = format!("{}xx", status / 100);
+    registry
+        .counter(
+            "homeroute_http_requests_total",
+            "Proxied HTTP requests, by route and status class.",
+            &[("route", route), ("status", &class)],
+        )
+        .inc();
+    registry
+        .histogram(
+            "homeroute_http_request_duration_seconds",
+            "Proxied HTTP request latency, by route.",
+            &[("route", route)],
+            hr_common::metrics::LATENCY_BUCKETS,
+        )
+        .observe(seconds);
+}
+
 /// Inner proxy handler logic
 async fn proxy_handler_inner(
     state: Arc<ProxyState>,
-    client_ip: IpAddr,
+    client: ClientAddr,
+    country: Option<&str>,
     mut req: Request,
 ) -> Result<Response, ProxyError> {
+    let client_ip = client.ip;
+
     // Extract Host header
     let host = req
         .headers()
@@ -257,15 +533,22 @@ async fn proxy_handler_inner(
         req.uri().path()
     );
 
+    // Only forward-auth may tell backends who the user is
+    for name in forwarded::IDENTITY {
+        req.headers_mut().remove(*name);
+    }
+
     // Built-in routes for management domains (proxy.* and auth.*)
     let base_domain = state.base_domain();
     let domain_only = host.split(':').next().unwrap_or(&host);
     let is_management = domain_only == format!("proxy.{}", base_domain)
         || domain_only == format!("auth.{}", base_domain);
+    // The bare base domain may be served by another domain's route
+    let route_domain = state.route_domain(domain_only);
 
     // Check for agent-managed application routes (before static route lookup)
     if !is_management {
-        if let Some(app_route) = state.get_app_route(domain_only) {
+        if let Some(app_route) = state.get_app_route(&route_domain) {
             // Block ALL traffic for local-only apps
             if app_route.local_only {
                 warn!("Blocked request for local-only app {} from {}", domain_only, client_ip);
@@ -294,13 +577,16 @@ async fn proxy_handler_inner(
                                 .find_map(|c| c.trim().strip_prefix("auth_session="))
                         });
 
+                    let client_ip = client_ip.to_string();
                     match check_forward_auth(
                         auth,
                         cookie_value,
+                        Some(&client_ip),
                         domain_only,
                         &req_uri,
                         "https",
                         &app_route.allowed_groups,
+                        false,
                     ) {
                         ForwardAuthResult::Success { user } => {
                             if let Ok(v) = HeaderValue::from_str(&user.username) {
@@ -326,27 +612,25 @@ async fn proxy_handler_inner(
                 return handle_wod_sse(&state, &app_route).await;
             }
 
-            // Determine scheme based on target port (re-encrypt for agent port 443)
-            let scheme = if app_route.target_port == 443 { "https" } else { "http" };
+            // Replicas in load-balancing order (apps have no active health checks)
+            let upstreams = app_route.upstreams();
+            let affinity = request_cookie(&req, AFFINITY_COOKIE).filter(|_| app_route.sticky);
+            let candidates = state.balancer.order(
+                domain_only,
+                &upstreams,
+                app_route.load_balancing,
+                affinity.as_deref(),
+                |_| TargetState::Up,
+            );
 
-            let target_host_for_url = app_route.target_ip.to_string();
+            forwarded::apply(req.headers_mut(), client, &host, &state.default_forwarding());
 
             // Check for WebSocket upgrade
             let is_websocket = is_websocket_upgrade(&req);
 
             if is_websocket {
                 debug!("WebSocket upgrade detected for app route {}", host);
-                let target_route = RouteConfig {
-                    id: app_route.app_id.clone(),
-                    domain: domain_only.to_string(),
-                    backend: "app".to_string(),
-                    target_host: target_host_for_url.clone(),
-                    target_port: app_route.target_port,
-                    local_only: false,
-                    require_auth: false,
-                    enabled: true,
-                    cert_id: None,
-                };
+                let backend_addr = &candidates[0];
                 let path_and_query = req
                     .uri()
                     .path_and_query()
@@ -355,14 +639,20 @@ async fn proxy_handler_inner(
                 let path_uri: Uri = path_and_query
                     .parse()
                     .unwrap_or_else(|_| "/".parse().unwrap());
+                let active = state.balancer.acquire(backend_addr);
                 let ws_result = if is_agent_route {
-                    handle_websocket_upgrade_tls(req, &target_route, path_uri, &host).await
+                    handle_websocket_upgrade_tls(req, backend_addr, path_uri, &host, active).await
                 } else {
-                    handle_websocket_upgrade(req, &target_route, path_uri).await
+                    handle_websocket_upgrade(req, backend_addr, path_uri, active).await
                 };
                 match ws_result {
                     Ok(resp) => return Ok(resp),
                     Err(ProxyError::UpstreamError(ref e)) if is_connection_refused(e) => {
+                        state.balancer.mark_failed(backend_addr);
+                        // Another replica will take the client's reconnect
+                        if candidates.len() > 1 {
+                            return Err(ProxyError::UpstreamError(e.clone()));
+                        }
                         // WOD: wake host or start service on connection refused
                         return Ok(handle_wod(&state, &app_route, &host).await);
                     }
@@ -370,65 +660,23 @@ async fn proxy_handler_inner(
                 }
             }
 
-            // Regular HTTP proxy to container
-            let path = req
-                .uri()
-                .path_and_query()
-                .map(|pq| pq.to_string())
-                .unwrap_or_else(|| "/".to_string());
-            let target_uri_str = format!(
-                "{}://{}:{}{}",
-                scheme, target_host_for_url, app_route.target_port, path
-            );
-
-            // Forward headers
+            // Regular HTTP proxy to container: remove hop-by-hop headers
             let headers = req.headers_mut();
-            if let Ok(v) = HeaderValue::from_str(&host) {
-                headers.insert("X-Forwarded-Host", v);
-            }
-            if let Ok(v) = HeaderValue::from_str(&client_ip.to_string()) {
-                headers.insert("X-Forwarded-For", v);
-            }
-            headers.insert("X-Forwarded-Proto", HeaderValue::from_static("https"));
-
-            // Remove hop-by-hop headers
             headers.remove("connection");
             headers.remove("upgrade");
 
-            // For HTTPS backends (re-encrypt), use reqwest with domain URL + IP resolve.
-            // We use the original domain in the URL (for correct SNI) but resolve it
-            // to the agent's IP address to avoid DNS lookups that may return Cloudflare.
-            let proxy_result = if is_agent_route {
-                let domain_uri = format!(
-                    "https://{}:443{}",
-                    domain_only,
-                    req.uri().path_and_query().map(|pq| pq.to_string()).unwrap_or_else(|| "/".to_string())
-                );
-                let agent_addr = std::net::SocketAddr::new(
-                    std::net::IpAddr::V4(app_route.target_ip),
-                    443,
-                );
-                let agent_client = reqwest::Client::builder()
-                    .danger_accept_invalid_certs(true)
-                    .resolve(domain_only, agent_addr)
-                    .build()
-                    .unwrap_or_else(|_| state.https_client.clone());
-                proxy_via_reqwest(&agent_client, req, &domain_uri, &host).await
+            let upstream = if is_agent_route {
+                Upstream::Tls { domain: &route_domain }
             } else {
-                let uri: Uri = target_uri_str
-                    .parse()
-                    .map_err(|e| ProxyError::InvalidUri(format!("{}", e)))?;
-                *req.uri_mut() = uri;
-                state
-                    .client
-                    .request(req)
-                    .await
-                    .map(|r| r.into_response())
-                    .map_err(|e| e.to_string())
+                Upstream::Http
             };
+            let proxy_result = send_balanced(&state, req, &candidates, &upstream, &host).await;
 
             match proxy_result {
-                Ok(resp) => {
+                Ok((mut resp, target)) => {
+                    if app_route.sticky && upstreams.len() > 1 {
+                        set_affinity_cookie(&mut resp, affinity.as_deref(), &target);
+                    }
                     // ActivityPing: notify agent of activity for powersave tracking
                     if let Some(registry) = state.get_registry() {
                         let app_id = app_route.app_id.clone();
@@ -459,15 +707,31 @@ async fn proxy_handler_inner(
             target_host: "localhost".to_string(),
             target_port: state.management_port,
             local_only: false,
+            vpn_only: false,
             require_auth: false,
+            require_mfa: false,
             enabled: true,
             cert_id: None,
+            health_check: Default::default(),
+            targets: Vec::new(),
+            load_balancing: Default::default(),
+            sticky: false,
+            draining: Vec::new(),
+            geo: Default::default(),
+            maintenance: Default::default(),
+            forwarding: Default::default(),
+            backend_tls: false,
+            local_dns: true,
         }
     } else {
         // Find matching route
-        state
-            .find_route(&host)
-            .ok_or(ProxyError::DomainNotFound(host.clone()))?
+        match state.find_route(&route_domain) {
+            Some(route) => route,
+            None => match state.sinkhole_page(domain_only, client_ip).await {
+                Some(page) => return Err(ProxyError::Sinkholed(page)),
+                None => return Err(ProxyError::DomainNotFound(host.clone())),
+            },
+        }
     };
 
     // Block ALL traffic for local-only routes
@@ -476,38 +740,35 @@ async fn proxy_handler_inner(
         return Err(ProxyError::Forbidden);
     }
 
+    if route.vpn_only && !state.is_vpn_client(client_ip) {
+        warn!("Blocked request for VPN-only route {} from {}", route.domain, client_ip);
+        return Err(ProxyError::Forbidden);
+    }
+
+    if !route.geo.is_empty() && geoip::is_public(client_ip) && !route.geo.allows(country) {
+        warn!(
+            "Blocked request for {} from {} (country {})",
+            route.domain,
+            client_ip,
+            country.unwrap_or("unknown")
+        );
+        return Err(ProxyError::Forbidden);
+    }
+
+    if route.maintenance.enabled && !route.maintenance.allows(client_ip) {
+        debug!("Serving maintenance page for {} to {}", route.domain, client_ip);
+        return Err(ProxyError::Maintenance(Box::new(route.maintenance.clone())));
+    }
+
     // Forward-auth for routes requiring authentication (direct call, no HTTP)
     if route.require_auth {
         if let Some(ref auth) = state.auth {
-            let req_uri = req
-                .uri()
-                .path_and_query()
-                .map(|pq| pq.to_string())
-                .unwrap_or_else(|| "/".to_string());
-
-            // Extract auth_session cookie
-            let cookie_value = req
-                .headers()
-                .get("cookie")
-                .and_then(|v| v.to_str().ok())
-                .and_then(|cookies| {
-                    cookies.split(';').find_map(|c| {
-                        let c = c.trim();
-                        c.strip_prefix("auth_session=")
-                    })
-                });
-
-            match check_forward_auth(auth, cookie_value, &host, &req_uri, "https", &[]) {
-                ForwardAuthResult::Success { user } => {
-                    debug!("Auth OK for user: {}", user.username);
-                }
-                ForwardAuthResult::Unauthorized { login_url } => {
-                    return Err(ProxyError::AuthRequired(Some(login_url)));
-                }
-                ForwardAuthResult::Forbidden { message } => {
-                    warn!("Auth forbidden: {}", message);
-                    return Err(ProxyError::Forbidden);
+            match guest_access(auth, &req, &route.id) {
+                Some(GuestAccess::Redeemed(resp)) => return Ok(resp),
+                Some(GuestAccess::Granted) => {
+                    debug!("Guest access to {} through a share link", route.domain);
                 }
+                None => check_route_auth(auth, &req, &host, client_ip, route.require_mfa)?,
             }
         } else {
             // No auth service configured but route requires auth
@@ -516,75 +777,319 @@ async fn proxy_handler_inner(
         }
     }
 
+    // Targets in load-balancing order; don't wait for backends the health
+    // checker already knows are down
+    let affinity = request_cookie(&req, AFFINITY_COOKIE);
+    let candidates = state.route_candidates(&route, affinity.as_deref());
+    if candidates.is_empty() {
+        return Err(ProxyError::ServiceUnavailable(route.domain.clone()));
+    }
+
     // Check if this is a WebSocket upgrade request
     let is_websocket = is_websocket_upgrade(&req);
 
-    // Build target URL
     let path_and_query = req
         .uri()
         .path_and_query()
         .map(|x| x.to_string())
         .unwrap_or_else(|| "/".to_string());
 
-    let target_url = format!(
-        "http://{}:{}{}",
-        route.target_host, route.target_port, &path_and_query
-    );
-
-    let target_uri: Uri = target_url
-        .parse()
-        .map_err(|e| ProxyError::InvalidUri(format!("{}", e)))?;
-
     // Set forwarding headers
+    let forwarding = if is_management { state.default_forwarding() } else { route.forwarding.clone() };
     let headers = req.headers_mut();
-
-    if let Ok(val) = HeaderValue::from_str(&host) {
-        headers.insert("X-Forwarded-Host", val);
-    }
-    headers.insert("X-Forwarded-Proto", HeaderValue::from_static("https"));
-    if let Ok(val) = HeaderValue::from_str(&client_ip.to_string()) {
-        headers.insert("X-Forwarded-For", val.clone());
-        headers.insert("X-Real-IP", val);
-    }
+    forwarded::apply(headers, client, &host, &forwarding);
 
     if is_websocket {
         debug!("WebSocket upgrade detected for {}", host);
         let path_only: Uri = path_and_query
             .parse()
             .unwrap_or_else(|_| "/".parse().unwrap());
-        return handle_websocket_upgrade(req, &route, path_only).await;
+        let active = state.balancer.acquire(&candidates[0]);
+        let result = if route.backend_tls {
+            handle_websocket_upgrade_tls(req, &candidates[0], path_only, &route.domain, active).await
+        } else {
+            handle_websocket_upgrade(req, &candidates[0], path_only, active).await
+        };
+        if matches!(result, Err(ProxyError::UpstreamError(ref e)) if is_connection_refused(e)) {
+            state.balancer.mark_failed(&candidates[0]);
+        }
+        return result;
     }
 
     // For normal HTTP: remove hop-by-hop headers
     headers.remove("connection");
     headers.remove("upgrade");
 
-    // Update the URI
-    *req.uri_mut() = target_uri;
-
-    // Forward the request via pooled client
-    let response = state
-        .client
-        .request(req)
+    // Forward the request via pooled client, or re-encrypted to an HTTPS target
+    let upstream = if route.backend_tls {
+        Upstream::Tls { domain: &route.domain }
+    } else {
+        Upstream::Http
+    };
+    let (mut response, target) = send_balanced(&state, req, &candidates, &upstream, &host)
         .await
-        .map_err(|e| ProxyError::UpstreamError(e.to_string()))?;
+        .map_err(|e| {
+            warn!("Upstream error for {}: {}", route.domain, e);
+            ProxyError::UpstreamError(e)
+        })?;
+    if route.sticky && route.upstreams().len() > 1 {
+        set_affinity_cookie(&mut response, affinity.as_deref(), &target);
+    }
+    Ok(response)
+}
+
+/// Forward-auth of a static route: a session cookie, with a second factor
+/// when the route is sensitive.
+fn check_route_auth(
+    auth: &Arc<AuthService>,
+    req: &Request,
+    host: &str,
+    client_ip: IpAddr,
+    require_mfa: bool,
+) -> Result<(), ProxyError> {
+    let req_uri = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.to_string())
+        .unwrap_or_else(|| "/".to_string());
+    let cookie_value = request_cookie(req, "auth_session");
+    let client_ip = client_ip.to_string();
+    match check_forward_auth(
+        auth,
+        cookie_value.as_deref(),
+        Some(&client_ip),
+        host,
+        &req_uri,
+        "https",
+        &[],
+        require_mfa,
+    ) {
+        ForwardAuthResult::Success { user } => {
+            debug!("Auth OK for user: {}", user.username);
+            Ok(())
+        }
+        ForwardAuthResult::Unauthorized { login_url } => Err(ProxyError::AuthRequired(Some(login_url))),
+        ForwardAuthResult::Forbidden { message } => {
+            warn!("Auth forbidden: {}", message);
+            Err(ProxyError::Forbidden)
+        }
+    }
+}
+
+enum GuestAccess {
+    /// Link opened: redirect to the clean URL with the share cookie set.
+    Redeemed(Response),
+    /// Share cookie still valid.
+    Granted,
+}
+
+/// Guest access through a share link. Opening the link (token in the
+/// query) counts one use and trades the token for a cookie; the cookie alone
+/// then grants access until the link expires.
+fn guest_access(auth: &AuthService, req: &Request, route_id: &str) -> Option<GuestAccess> {
+    if let Some(token) = query_param(req.uri(), SHARE_PARAM)
+        && let Some(link) = auth.shares.redeem(&token, route_id)
+    {
+        info!(route = route_id, link = %link.id, uses = link.uses, "Share link opened");
+        let location = without_query_param(req.uri(), SHARE_PARAM);
+        let cookie = format!(
+            "{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+            SHARE_COOKIE,
+            token,
+            link.remaining_secs()
+        );
+        let resp = Response::builder()
+            .status(StatusCode::FOUND)
+            .header("Location", location)
+            .header("Set-Cookie", cookie)
+            .header("Cache-Control", "no-store")
+            .body(Body::empty())
+            .unwrap();
+        return Some(GuestAccess::Redeemed(resp));
+    }
+    let token = request_cookie(req, SHARE_COOKIE)?;
+    auth.shares.validate(&token, route_id).map(|_| GuestAccess::Granted)
+}
+
+fn query_param(uri: &Uri, name: &str) -> Option<String> {
+    uri.query()?.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| value.to_string())
+    })
+}
+
+/// Path and query of `uri` without the `name` parameter.
+fn without_query_param(uri: &Uri, name: &str) -> String {
+    let rest: Vec<&str> = uri
+        .query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| !pair.is_empty() && pair.split('=').next() != Some(name))
+        .collect();
+    if rest.is_empty() {
+        uri.path().to_string()
+    } else {
+        format!("{}?{}", uri.path(), rest.join("&"))
+    }
+}
+
+/// Value of a request cookie.
+fn request_cookie(req: &Request, name: &str) -> Option<String> {
+    req.headers()
+        .get_all("cookie")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .find_map(|c| {
+            let (key, value) = c.trim().split_once('=')?;
+            (key == name).then(|| value.to_string())
+        })
+}
+
+/// Pin the client to `target` unless its cookie already does.
+fn set_affinity_cookie(resp: &mut Response, current: Option<&str>, target: &str) {
+    let token = affinity_token(target);
+    if current == Some(token.as_str()) {
+        return;
+    }
+    let cookie = format!("{}={}; Path=/; HttpOnly; Secure; SameSite=Lax", AFFINITY_COOKIE, token);
+    if let Ok(v) = HeaderValue::from_str(&cookie) {
+        resp.headers_mut().append("set-cookie", v);
+    }
+}
 
-    Ok(response.into_response())
+/// How a request reaches its upstream target.
+enum Upstream<'a> {
+    /// Plain HTTP via the pooled client.
+    Http,
+    /// HTTPS re-encrypt to an agent or a `backend_tls` route: the URL keeps
+    /// the domain (for SNI) but resolves to the target's address, to avoid
+    /// DNS lookups that may return Cloudflare.
+    Tls { domain: &'a str },
+}
+
+/// Send a request to one `host:port` target.
+async fn send_upstream(
+    state: &ProxyState,
+    mut req: Request,
+    target: &str,
+    upstream: &Upstream<'_>,
+    original_host: &str,
+) -> Result<Response, String> {
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.to_string())
+        .unwrap_or_else(|| "/".to_string());
+    match upstream {
+        Upstream::Http => {
+            let uri: Uri = format!("http://{}{}", target, path)
+                .parse()
+                .map_err(|e| format!("Invalid URI: {}", e))?;
+            *req.uri_mut() = uri;
+            state
+                .client
+                .request(req)
+                .await
+                .map(|r| r.into_response())
+                .map_err(|e| e.to_string())
+        }
+        Upstream::Tls { domain } => {
+            let (client, url) = match target.parse::<std::net::SocketAddr>() {
+                Ok(addr) => (
+                    reqwest::Client::builder()
+                        .danger_accept_invalid_certs(true)
+                        .resolve(domain, addr)
+                        .build()
+                        .unwrap_or_else(|_| state.https_client.clone()),
+                    format!("https://{}:{}{}", domain, addr.port(), path),
+                ),
+                // Target given by name: SNI is that name, Host stays the domain
+                Err(_) => (state.https_client.clone(), format!("https://{}{}", target, path)),
+            };
+            proxy_via_reqwest(&client, req, &url, original_host).await
+        }
+    }
+}
+
+/// Send a request to the first candidate that accepts the connection and
+/// return the response with the target that served it.
+///
+/// A target refusing the connection is put aside by the balancer. Requests
+/// without a body are then replayed on the next candidate; others can't be
+/// and return the error.
+async fn send_balanced(
+    state: &ProxyState,
+    req: Request,
+    candidates: &[String],
+    upstream: &Upstream<'_>,
+    original_host: &str,
+) -> Result<(Response, String), String> {
+    let replayable = candidates.len() > 1 && is_replayable(&req);
+    let mut req = Some(req);
+    let mut last_err = String::from("no upstream target");
+
+    for (i, target) in candidates.iter().enumerate() {
+        let Some(original) = req.as_ref() else {
+            break;
+        };
+        let attempt = if replayable && i + 1 < candidates.len() {
+            replay(original)
+        } else {
+            req.take().unwrap()
+        };
+
+        let _active = state.balancer.acquire(target);
+        match send_upstream(state, attempt, target, upstream, original_host).await {
+            Err(e) if is_connection_refused(&e) => {
+                state.balancer.mark_failed(target);
+                if candidates.len() > 1 {
+                    warn!("Target {} for {} refused the connection: {}", target, original_host, e);
+                }
+                last_err = e;
+            }
+            Ok(resp) => {
+                state.balancer.mark_ok(target);
+                return Ok((resp, target.clone()));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Err(last_err)
+}
+
+/// Whether a request can be sent again (nothing to re-read from the body).
+fn is_replayable(req: &Request) -> bool {
+    let headers = req.headers();
+    !headers.contains_key("transfer-encoding")
+        && headers
+            .get("content-length")
+            .is_none_or(|v| v.as_bytes() == b"0")
+}
+
+/// Copy of a bodiless request's head, for a retry on another target.
+fn replay(req: &Request) -> Request {
+    let mut copy = Request::new(Body::empty());
+    *copy.method_mut() = req.method().clone();
+    *copy.uri_mut() = req.uri().clone();
+    *copy.version_mut() = req.version();
+    *copy.headers_mut() = req.headers().clone();
+    copy
 }
 
 /// Handle WebSocket upgrade by establishing a direct connection to the backend
 async fn handle_websocket_upgrade(
     mut req: Request,
-    route: &RouteConfig,
+    backend_addr: &str,
     target_uri: Uri,
+    active: ActiveGuard,
 ) -> Result<Response, ProxyError> {
     use hyper::client::conn::http1::Builder;
     use tokio::io::AsyncWriteExt;
 
     let client_upgrade = hyper::upgrade::on(&mut req);
 
-    let backend_addr = format!("{}:{}", route.target_host, route.target_port);
-    let tcp_stream = TcpStream::connect(&backend_addr)
+    let tcp_stream = TcpStream::connect(backend_addr)
         .await
         .map_err(|e| {
             ProxyError::UpstreamError(format!(
@@ -639,6 +1144,8 @@ async fn handle_websocket_upgrade(
     let client_response = response_builder.body(Body::empty()).unwrap();
 
     tokio::spawn(async move {
+        // Counts as a connection on the target until the socket closes (draining)
+        let _active = active;
         match tokio::try_join!(client_upgrade, backend_upgrade) {
             Ok((client_io, backend_io)) => {
                 let mut client_io = TokioIo::new(client_io);
@@ -735,9 +1242,10 @@ async fn proxy_via_reqwest(
 /// then performs the HTTP/1.1 upgrade handshake and bridges the streams.
 async fn handle_websocket_upgrade_tls(
     mut req: Request,
-    route: &RouteConfig,
+    backend_addr: &str,
     target_uri: Uri,
     original_host: &str,
+    active: ActiveGuard,
 ) -> Result<Response, ProxyError> {
     use hyper::client::conn::http1::Builder;
     use tokio::io::AsyncWriteExt;
@@ -745,8 +1253,7 @@ async fn handle_websocket_upgrade_tls(
 
     let client_upgrade = hyper::upgrade::on(&mut req);
 
-    let backend_addr = format!("{}:{}", route.target_host, route.target_port);
-    let tcp_stream = TcpStream::connect(&backend_addr)
+    let tcp_stream = TcpStream::connect(backend_addr)
         .await
         .map_err(|e| {
             ProxyError::UpstreamError(format!(
@@ -821,6 +1328,8 @@ async fn handle_websocket_upgrade_tls(
     let client_response = response_builder.body(Body::empty()).unwrap();
 
     tokio::spawn(async move {
+        // Counts as a connection on the target until the socket closes (draining)
+        let _active = active;
         match tokio::try_join!(client_upgrade, backend_upgrade) {
             Ok((client_io, backend_io)) => {
                 let mut client_io = TokioIo::new(client_io);
@@ -931,6 +1440,16 @@ pub enum ProxyError {
 
     #[error("Domain not found: {0}")]
     DomainNotFound(String),
+
+    /// Host blocked by the adblock sinkhole; the page explaining it.
+    #[error("Blocked by adblock")]
+    Sinkholed(String),
+
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
+
+    #[error("Under maintenance")]
+    Maintenance(Box<MaintenanceConfig>),
 }
 
 impl IntoResponse for ProxyError {
@@ -944,7 +1463,29 @@ impl IntoResponse for ProxyError {
             other => {
                 let (status, message) = match other {
                     ProxyError::InvalidUri(msg) => (StatusCode::BAD_REQUEST, msg),
-                    ProxyError::UpstreamError(msg) => (StatusCode::BAD_GATEWAY, msg),
+                    ProxyError::UpstreamError(_) => {
+                        return error_page(
+                            StatusCode::BAD_GATEWAY,
+                            "Service injoignable",
+                            "Le service n'a pas repondu. Nouvel essai automatique dans quelques secondes...",
+                        );
+                    }
+                    ProxyError::ServiceUnavailable(_) => {
+                        return error_page(
+                            StatusCode::SERVICE_UNAVAILABLE,
+                            "Service indisponible",
+                            "Le service est hors ligne ou en cours de demarrage. Cette page se rechargera automatiquement.",
+                        );
+                    }
+                    ProxyError::Maintenance(maintenance) => return maintenance_page(&maintenance),
+                    ProxyError::Sinkholed(html) => {
+                        return Response::builder()
+                            .status(StatusCode::FORBIDDEN)
+                            .header("Content-Type", "text/html; charset=utf-8")
+                            .header("Cache-Control", "no-store")
+                            .body(Body::from(html))
+                            .unwrap();
+                    }
                     ProxyError::Forbidden => {
                         (StatusCode::FORBIDDEN, "Forbidden".to_string())
                     }
@@ -1280,6 +1821,75 @@ es.onerror = function() {{ es.close(); setTimeout(function(){{ location.reload()
         .unwrap()
 }
 
+/// Maintenance page of a route: its custom HTML if set, otherwise the
+/// branded page with the configured title and message.
+fn maintenance_page(maintenance: &MaintenanceConfig) -> Response {
+    let Some(html) = maintenance.html.as_ref().filter(|h| !h.trim().is_empty()) else {
+        return error_page(
+            StatusCode::SERVICE_UNAVAILABLE,
+            maintenance.title.as_deref().unwrap_or("Maintenance en cours"),
+            maintenance
+                .message
+                .as_deref()
+                .unwrap_or("Le service est en cours de maintenance. Cette page se rechargera automatiquement."),
+        );
+    };
+    Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .header("Cache-Control", "no-store")
+        .header("Retry-After", "60")
+        .body(Body::from(html.clone()))
+        .unwrap()
+}
+
+/// Branded error page for unreachable/unhealthy backends. Reloads itself
+/// periodically so the user lands on the service as soon as it is back.
+fn error_page(status: StatusCode, title: &str, message: &str) -> Response {
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="fr">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta http-equiv="refresh" content="10">
+<title>{title}</title>
+<style>
+*{{margin:0;padding:0;box-sizing:border-box}}
+body{{font-family:-apple-system,BlinkMacSystemFont,'Segoe UI',Roboto,sans-serif;
+background:#0f172a;color:#e2e8f0;display:flex;justify-content:center;align-items:center;
+min-height:100vh}}
+.card{{background:#1e293b;border-radius:16px;padding:3rem;text-align:center;
+max-width:420px;box-shadow:0 25px 50px rgba(0,0,0,.3)}}
+.code{{font-size:2.5rem;font-weight:700;color:#f87171;margin-bottom:1rem}}
+h1{{font-size:1.25rem;font-weight:600;margin-bottom:.75rem}}
+p{{color:#94a3b8;font-size:.9rem;line-height:1.5}}
+.brand{{color:#60a5fa;font-size:.8rem;margin-top:1.5rem}}
+</style>
+</head>
+<body>
+<div class="card">
+<div class="code">{code}</div>
+<h1>{title}</h1>
+<p>{message}</p>
+<div class="brand">HomeRoute</div>
+</div>
+</body>
+</html>"#,
+        code = status.as_u16(),
+        title = title,
+        message = message,
+    );
+
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .header("Cache-Control", "no-store")
+        .header("Retry-After", "10")
+        .body(Body::from(html))
+        .unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1301,9 +1911,21 @@ mod tests {
                     target_host: "localhost".to_string(),
                     target_port: 3000,
                     local_only: false,
+                    vpn_only: false,
                     require_auth: false,
+                    require_mfa: false,
                     enabled: true,
                     cert_id: Some("cert-1".to_string()),
+                    health_check: Default::default(),
+                    targets: Vec::new(),
+                    load_balancing: Default::default(),
+                    sticky: false,
+                    draining: Vec::new(),
+                    geo: Default::default(),
+                    maintenance: Default::default(),
+                    forwarding: Default::default(),
+                    backend_tls: false,
+                    local_dns: true,
                 },
                 RouteConfig {
                     id: "route-2".to_string(),
@@ -1312,9 +1934,21 @@ mod tests {
                     target_host: "localhost".to_string(),
                     target_port: 3001,
                     local_only: true,
+                    vpn_only: false,
                     require_auth: false,
+                    require_mfa: false,
                     enabled: true,
                     cert_id: Some("cert-2".to_string()),
+                    health_check: Default::default(),
+                    targets: Vec::new(),
+                    load_balancing: Default::default(),
+                    sticky: false,
+                    draining: Vec::new(),
+                    geo: Default::default(),
+                    maintenance: Default::default(),
+                    forwarding: Default::default(),
+                    backend_tls: false,
+                    local_dns: true,
                 },
                 RouteConfig {
                     id: "route-3".to_string(),
@@ -1323,9 +1957,21 @@ mod tests {
                     target_host: "localhost".to_string(),
                     target_port: 3002,
                     local_only: false,
+                    vpn_only: false,
                     require_auth: true,
+                    require_mfa: false,
                     enabled: true,
                     cert_id: Some("cert-3".to_string()),
+                    health_check: Default::default(),
+                    targets: Vec::new(),
+                    load_balancing: Default::default(),
+                    sticky: false,
+                    draining: Vec::new(),
+                    geo: Default::default(),
+                    maintenance: Default::default(),
+                    forwarding: Default::default(),
+                    backend_tls: false,
+                    local_dns: true,
                 },
                 RouteConfig {
                     id: "route-4".to_string(),
@@ -1334,12 +1980,28 @@ mod tests {
                     target_host: "localhost".to_string(),
                     target_port: 3003,
                     local_only: false,
+                    vpn_only: false,
                     require_auth: false,
+                    require_mfa: false,
                     enabled: false,
                     cert_id: None,
+                    health_check: Default::default(),
+                    targets: Vec::new(),
+                    load_balancing: Default::default(),
+                    sticky: false,
+                    draining: Vec::new(),
+                    geo: Default::default(),
+                    maintenance: Default::default(),
+                    forwarding: Default::default(),
+                    backend_tls: false,
+                    local_dns: true,
                 },
             ],
             access_log_path: None,
+            access_log: Default::default(),
+            geoip_database: None,
+            forwarding: Default::default(),
+            apex_target: None,
         }
     }
 
@@ -1359,6 +2021,26 @@ mod tests {
         assert_eq!(route.unwrap().domain, "app.example.com");
     }
 
+    #[test]
+    fn test_route_domain_apex() {
+        let state = ProxyState::new(test_config(), 4000);
+        assert_eq!(state.route_domain("example.com"), "example.com");
+
+        let mut config = test_config();
+        config.apex_target = Some("app.example.com".to_string());
+        let state = ProxyState::new(config.clone(), 4000);
+        assert_eq!(state.route_domain("example.com"), "app.example.com");
+        assert_eq!(state.route_domain("other.example.com"), "other.example.com");
+        assert_eq!(state.find_route(&state.route_domain("example.com")).unwrap().target_port, 3000);
+
+        // A route declaring the apex itself wins
+        let mut apex = config.routes[0].clone();
+        apex.domain = "example.com".to_string();
+        config.routes.push(apex);
+        let state = ProxyState::new(config, 4000);
+        assert_eq!(state.route_domain("example.com"), "example.com");
+    }
+
     #[test]
     fn test_find_route_unknown_domain() {
         let state = ProxyState::new(test_config(), 4000);
@@ -1407,6 +2089,18 @@ mod tests {
         assert!(!is_websocket_upgrade(&req));
     }
 
+    #[test]
+    fn test_share_token_query() {
+        let uri: Uri = "/album?hr_share=abc.def&page=2".parse().unwrap();
+        assert_eq!(query_param(&uri, SHARE_PARAM).as_deref(), Some("abc.def"));
+        assert_eq!(without_query_param(&uri, SHARE_PARAM), "/album?page=2");
+
+        let uri: Uri = "/?hr_share=abc.def".parse().unwrap();
+        assert_eq!(without_query_param(&uri, SHARE_PARAM), "/");
+        let uri: Uri = "/album".parse().unwrap();
+        assert!(query_param(&uri, SHARE_PARAM).is_none());
+    }
+
     #[test]
     fn test_proxy_error_status_codes() {
         let err = ProxyError::DomainNotFound("test.com".to_string());
@@ -1417,6 +2111,10 @@ mod tests {
         let resp = err.into_response();
         assert_eq!(resp.status(), StatusCode::FORBIDDEN);
 
+        let err = ProxyError::Sinkholed("<html></html>".to_string());
+        let resp = err.into_response();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
         let err = ProxyError::AuthRequired(Some("https://auth.example.com/login".to_string()));
         let resp = err.into_response();
         assert_eq!(resp.status(), StatusCode::FOUND);
@@ -1424,6 +2122,250 @@ mod tests {
         let err = ProxyError::UpstreamError("timeout".to_string());
         let resp = err.into_response();
         assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+
+        let err = ProxyError::ServiceUnavailable("app.example.com".to_string());
+        let resp = err.into_response();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(resp.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+    }
+
+    #[tokio::test]
+    async fn test_unhealthy_route_serves_error_page() {
+        let state = Arc::new(ProxyState::new(test_config(), 4000));
+        let mut health = BackendHealth::new("localhost:3000".to_string());
+        health.status = HealthStatus::Unhealthy;
+        state.health.write().unwrap().insert("route-1".to_string(), vec![health]);
+
+        let req = Request::builder()
+            .header("host", "app.example.com")
+            .body(Body::empty())
+            .unwrap();
+        let err = proxy_handler_inner(state.clone(), ClientAddr::direct("10.0.0.2".parse().unwrap()), None, req)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ProxyError::ServiceUnavailable(_)));
+        assert_eq!(state.route_health("route-1").unwrap()[0].status, HealthStatus::Unhealthy);
+    }
+
+    #[tokio::test]
+    async fn test_geo_rules_block_public_clients() {
+        let mut config = test_config();
+        config.routes[0].geo.deny = vec!["CN".to_string()];
+        let state = Arc::new(ProxyState::new(config, 4000));
+        let req = || Request::builder().header("host", "app.example.com").body(Body::empty()).unwrap();
+
+        let err = proxy_handler_inner(state.clone(), ClientAddr::direct("1.2.3.4".parse().unwrap()), Some("CN"), req())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ProxyError::Forbidden));
+
+        // LAN clients are never subject to country rules
+        let result = proxy_handler_inner(state.clone(), ClientAddr::direct("10.0.0.2".parse().unwrap()), Some("CN"), req()).await;
+        assert!(!matches!(result, Err(ProxyError::Forbidden)));
+    }
+
+    #[tokio::test]
+    async fn test_vpn_only_route() {
+        let mut config = test_config();
+        config.routes[0].vpn_only = true;
+        let state = Arc::new(ProxyState::new(config, 4000));
+        state.set_vpn_ranges(vec!["100.64.0.0/10".parse().unwrap(), "fd7a:115c:a1e0::/48".parse().unwrap()]);
+        let req = || Request::builder().header("host", "app.example.com").body(Body::empty()).unwrap();
+
+        let err = proxy_handler_inner(state.clone(), ClientAddr::direct("192.168.1.20".parse().unwrap()), None, req())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ProxyError::Forbidden));
+
+        // Tailnet clients reach the backend (absent here, so it fails upstream)
+        for ip in ["100.101.102.103", "fd7a:115c:a1e0::5", "::ffff:100.64.0.9"] {
+            let result = proxy_handler_inner(state.clone(), ClientAddr::direct(ip.parse().unwrap()), None, req()).await;
+            assert!(!matches!(result, Err(ProxyError::Forbidden)), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_page_and_admin_bypass() {
+        let mut config = test_config();
+        config.routes[0].maintenance = MaintenanceConfig {
+            enabled: true,
+            message: Some("Mise à jour en cours".to_string()),
+            allow_ips: vec!["10.0.0.0/24".to_string()],
+            ..Default::default()
+        };
+        let state = Arc::new(ProxyState::new(config, 4000));
+        let req = || Request::builder().header("host", "app.example.com").body(Body::empty()).unwrap();
+
+        let err = proxy_handler_inner(state.clone(), ClientAddr::direct("192.168.1.20".parse().unwrap()), None, req())
+            .await
+            .unwrap_err();
+        let resp = err.into_response();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(resp.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+
+        // Admin addresses reach the backend (absent here, so it fails upstream)
+        let result = proxy_handler_inner(state.clone(), ClientAddr::direct("10.0.0.2".parse().unwrap()), None, req()).await;
+        assert!(!matches!(result, Err(ProxyError::Maintenance(_))));
+    }
+
+    #[tokio::test]
+    async fn test_relay_client_ip_propagation() {
+        // Backend echoing the client address it is told about
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let app = axum::Router::new().route(
+                "/",
+                axum::routing::get(|headers: axum::http::HeaderMap| async move {
+                    headers["x-real-ip"].to_str().unwrap().to_string()
+                }),
+            );
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let mut config = test_config();
+        config.routes[0].target_host = "127.0.0.1".to_string();
+        config.routes[0].target_port = port;
+        config.routes[0].forwarding = ForwardingConfig {
+            profile: crate::config::ForwardedProfile::Trust,
+            trusted_proxies: vec!["10.99.0.1".to_string()],
+            emit_forwarded: false,
+        };
+        let bans_path = std::env::temp_dir().join(format!("hr-proxy-bans-{}.json", std::process::id()));
+        let bans = Arc::new(hr_firewall::BanManager::new(Default::default(), bans_path));
+        let state = Arc::new(ProxyState::new(config, 4000).with_bans(bans.clone()));
+
+        let send = |peer: &str, xff: &str| {
+            let state = state.clone();
+            let peer: IpAddr = peer.parse().unwrap();
+            let req = Request::builder()
+                .header("host", "app.example.com")
+                .header("x-forwarded-for", xff)
+                .body(Body::empty())
+                .unwrap();
+            async move {
+                match proxy_handler(state, peer, req).await {
+                    Ok(resp) => {
+                        let body = axum::body::to_bytes(resp.into_body(), 1024).await.unwrap();
+                        Ok(String::from_utf8(body.to_vec()).unwrap())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+        };
+
+        // Through the relay: the announced client is the one that counts
+        assert_eq!(send("10.99.0.1", "198.51.100.9").await.unwrap(), "198.51.100.9");
+        let logged = state.access_logger.recent(&crate::logging::AccessLogFilter::default());
+        assert_eq!(logged[0].client_ip, "198.51.100.9");
+
+        // Anyone else can't claim another address
+        assert_eq!(send("203.0.113.5", "198.51.100.9").await.unwrap(), "203.0.113.5");
+
+        // Bans apply to the relayed client, not to the relay
+        bans.ban("198.51.100.9".parse().unwrap(), None, "test".to_string(), hr_firewall::BanSource::Manual);
+        assert!(matches!(send("10.99.0.1", "198.51.100.9").await, Err(ProxyError::Forbidden)));
+        assert_eq!(send("10.99.0.1", "198.51.100.10").await.unwrap(), "198.51.100.10");
+    }
+
+    #[tokio::test]
+    async fn test_failover_to_next_target() {
+        // Primary target refuses connections, the replica answers
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_port = closed.local_addr().unwrap().port();
+        drop(closed);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live_port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let app = axum::Router::new().route("/", axum::routing::get(|| async { "replica" }));
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let mut config = test_config();
+        config.routes[0].target_host = "127.0.0.1".to_string();
+        config.routes[0].target_port = closed_port;
+        config.routes[0].targets = vec![crate::config::RouteTarget {
+            host: "127.0.0.1".to_string(),
+            port: live_port,
+        }];
+        let state = Arc::new(ProxyState::new(config, 4000));
+
+        for _ in 0..2 {
+            let req = Request::builder()
+                .header("host", "app.example.com")
+                .body(Body::empty())
+                .unwrap();
+            let resp = proxy_handler_inner(state.clone(), ClientAddr::direct("10.0.0.2".parse().unwrap()), None, req)
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+
+        // The refused target is now tried last
+        let route = state.find_route("app.example.com").unwrap();
+        assert_eq!(state.route_candidates(&route, None)[0], format!("127.0.0.1:{}", live_port));
+    }
+
+    #[tokio::test]
+    async fn test_sticky_session() {
+        let mut ports = Vec::new();
+        for name in ["one", "two"] {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            ports.push(listener.local_addr().unwrap().port());
+            tokio::spawn(async move {
+                let app = axum::Router::new().route("/", axum::routing::get(move || async move { name }));
+                axum::serve(listener, app).await.unwrap();
+            });
+        }
+
+        let mut config = test_config();
+        config.routes[0].target_host = "127.0.0.1".to_string();
+        config.routes[0].target_port = ports[0];
+        config.routes[0].targets = vec![crate::config::RouteTarget {
+            host: "127.0.0.1".to_string(),
+            port: ports[1],
+        }];
+        config.routes[0].sticky = true;
+        let state = Arc::new(ProxyState::new(config, 4000));
+
+        let send = |cookie: Option<String>| {
+            let state = state.clone();
+            async move {
+                let mut req = Request::builder().header("host", "app.example.com");
+                if let Some(cookie) = cookie {
+                    req = req.header("cookie", cookie);
+                }
+                let resp = proxy_handler_inner(state, ClientAddr::direct("10.0.0.2".parse().unwrap()), None, req.body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                let set_cookie = resp
+                    .headers()
+                    .get("set-cookie")
+                    .map(|v| v.to_str().unwrap().split(';').next().unwrap().to_string());
+                let body = axum::body::to_bytes(resp.into_body(), 1024).await.unwrap();
+                (String::from_utf8(body.to_vec()).unwrap(), set_cookie)
+            }
+        };
+
+        let (first, cookie) = send(None).await;
+        let cookie = cookie.expect("affinity cookie");
+        assert!(cookie.starts_with("hr_backend="));
+        for _ in 0..3 {
+            let (body, set_cookie) = send(Some(format!("theme=dark; {}", cookie))).await;
+            assert_eq!(body, first);
+            assert!(set_cookie.is_none());
+        }
+
+        // Draining the pinned target keeps the session but not new clients
+        let pinned = format!("127.0.0.1:{}", if first == "one" { ports[0] } else { ports[1] });
+        state.balancer.drain(&pinned);
+        let (body, _) = send(Some(cookie)).await;
+        assert_eq!(body, first);
+        for _ in 0..2 {
+            let (body, set_cookie) = send(None).await;
+            assert_ne!(body, first);
+            assert!(set_cookie.is_some());
+        }
     }
 
     #[test]
@@ -1440,9 +2382,21 @@ mod tests {
             target_host: "localhost".to_string(),
             target_port: 5000,
             local_only: false,
+            vpn_only: false,
             require_auth: false,
+            require_mfa: false,
             enabled: true,
             cert_id: None,
+            health_check: Default::default(),
+            targets: Vec::new(),
+            load_balancing: Default::default(),
+            sticky: false,
+            draining: Vec::new(),
+            geo: Default::default(),
+            maintenance: Default::default(),
+            forwarding: Default::default(),
+            backend_tls: false,
+            local_dns: true,
         });
         state.reload_config(config);
 
@@ -0,0 +1,357 @@
+//! Active health checks for static route targets.
+//!
+//! Every target of each enabled route with `health_check.enabled` (off
+//! unless set on the route) is probed on its own interval (TCP connect or
+//! HTTP GET). A target becomes unhealthy after `unhealthy_threshold`
+//! consecutive failures and is left out of load balancing; when all targets
+//! of a route are down, requests get the "service unavailable" page instead
+//! of waiting for the upstream to fail.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use hyper::Request;
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+use hr_common::events::BackendHealthEvent;
+
+use crate::config::{HealthCheckConfig, HealthCheckKind, RouteConfig};
+use crate::handler::ProxyState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    /// Not probed yet (treated as healthy).
+    Unknown,
+    Healthy,
+    Unhealthy,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendHealth {
+    pub status: HealthStatus,
+    /// `host:port` being probed.
+    pub target: String,
+    pub consecutive_failures: u32,
+    pub consecutive_successes: u32,
+    /// Unix millis of the last probe.
+    pub last_check: Option<i64>,
+    /// Unix millis of the last status change.
+    pub last_change: Option<i64>,
+    pub latency_ms: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+impl BackendHealth {
+    pub fn new(target: String) -> Self {
+        Self {
+            status: HealthStatus::Unknown,
+            target,
+            consecutive_failures: 0,
+            consecutive_successes: 0,
+            last_check: None,
+            last_change: None,
+            latency_ms: None,
+            last_error: None,
+        }
+    }
+
+    /// Record a probe result (latency or error). Returns the previous status
+    /// when the status changed.
+    pub fn record(
+        &mut self,
+        result: Result<u64, String>,
+        cfg: &HealthCheckConfig,
+        now_ms: i64,
+    ) -> Option<HealthStatus> {
+        self.last_check = Some(now_ms);
+        let previous = self.status;
+        match result {
+            Ok(latency) => {
+                self.consecutive_successes += 1;
+                self.consecutive_failures = 0;
+                self.latency_ms = Some(latency);
+                self.last_error = None;
+                if self.status != HealthStatus::Healthy
+                    && self.consecutive_successes >= cfg.healthy_threshold.max(1)
+                {
+                    self.status = HealthStatus::Healthy;
+                }
+            }
+            Err(e) => {
+                self.consecutive_failures += 1;
+                self.consecutive_successes = 0;
+                self.latency_ms = None;
+                self.last_error = Some(e);
+                if self.status != HealthStatus::Unhealthy
+                    && self.consecutive_failures >= cfg.unhealthy_threshold.max(1)
+                {
+                    self.status = HealthStatus::Unhealthy;
+                }
+            }
+        }
+        if self.status != previous {
+            self.last_change = Some(now_ms);
+            Some(previous)
+        } else {
+            None
+        }
+    }
+
+    fn is_due(&self, cfg: &HealthCheckConfig, now_ms: i64) -> bool {
+        match self.last_check {
+            None => true,
+            Some(last) => now_ms - last >= (cfg.interval_secs.max(1) * 1000) as i64,
+        }
+    }
+}
+
+/// Probe one target (`host:port`) of a route. Returns the latency in milliseconds.
+pub async fn probe(state: &ProxyState, route: &RouteConfig, target: &str) -> Result<u64, String> {
+    let cfg = &route.health_check;
+    let timeout = Duration::from_secs(cfg.timeout_secs.max(1));
+    let start = Instant::now();
+
+    match cfg.kind {
+        HealthCheckKind::Tcp => {
+            match tokio::time::timeout(timeout, TcpStream::connect(target)).await {
+                Ok(Ok(_)) => Ok(start.elapsed().as_millis() as u64),
+                Ok(Err(e)) => Err(e.to_string()),
+                Err(_) => Err("timeout".to_string()),
+            }
+        }
+        HealthCheckKind::Http => {
+            let path = if cfg.path.starts_with('/') {
+                cfg.path.clone()
+            } else {
+                format!("/{}", cfg.path)
+            };
+            let status = if route.backend_tls {
+                probe_tls(route, target, &path, timeout).await?
+            } else {
+                let url = format!("http://{}{}", target, path);
+                let req = Request::get(&url)
+                    .header("host", &route.domain)
+                    .header("user-agent", "HomeRoute-HealthCheck")
+                    .body(Body::empty())
+                    .map_err(|e| e.to_string())?;
+                match tokio::time::timeout(timeout, state.client.request(req)).await {
+                    Ok(Ok(r)) => r.status().as_u16(),
+                    Ok(Err(e)) => return Err(e.to_string()),
+                    Err(_) => return Err("timeout".to_string()),
+                }
+            };
+            let ok = match cfg.expected_status {
+                Some(expected) => status == expected,
+                None => status < 500,
+            };
+            if ok {
+                Ok(start.elapsed().as_millis() as u64)
+            } else {
+                Err(format!("HTTP {}", status))
+            }
+        }
+    }
+}
+
+/// HTTP check of a `backend_tls` target, over HTTPS with the route's SNI.
+async fn probe_tls(
+    route: &RouteConfig,
+    target: &str,
+    path: &str,
+    timeout: Duration,
+) -> Result<u16, String> {
+    let mut builder = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(timeout);
+    let url = match target.parse::<std::net::SocketAddr>() {
+        Ok(addr) => {
+            builder = builder.resolve(&route.domain, addr);
+            format!("https://{}:{}{}", route.domain, addr.port(), path)
+        }
+        Err(_) => format!("https://{}{}", target, path),
+    };
+    let client = builder.build().map_err(|e| e.to_string())?;
+    let resp = client
+        .get(&url)
+        .header("host", &route.domain)
+        .header("user-agent", "HomeRoute-HealthCheck")
+        .send()
+        .await
+        .map_err(|e| {
+            if e.is_timeout() {
+                "timeout".to_string()
+            } else {
+                e.to_string()
+            }
+        })?;
+    Ok(resp.status().as_u16())
+}
+
+/// Background loop probing every enabled route whose check is due.
+pub async fn run_health_checker(state: Arc<ProxyState>) -> anyhow::Result<()> {
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tick.tick().await;
+
+        let routes: Vec<RouteConfig> = state
+            .config()
+            .routes
+            .into_iter()
+            .filter(|r| r.enabled && r.health_check.enabled)
+            .collect();
+        let now = chrono::Utc::now().timestamp_millis();
+
+        // Drop entries of removed routes/targets, add new targets
+        let due: Vec<(RouteConfig, String)> = {
+            let mut health = state.health.write().unwrap();
+            health.retain(|id, _| routes.iter().any(|r| &r.id == id));
+            let mut due = Vec::new();
+            for route in routes {
+                let upstreams = route.upstreams();
+                let entries = health.entry(route.id.clone()).or_default();
+                entries.retain(|h| upstreams.contains(&h.target));
+                for target in &upstreams {
+                    if !entries.iter().any(|h| &h.target == target) {
+                        entries.push(BackendHealth::new(target.clone()));
+                    }
+                }
+                for entry in entries.iter() {
+                    if entry.is_due(&route.health_check, now) {
+                        due.push((route.clone(), entry.target.clone()));
+                    }
+                }
+            }
+            due
+        };
+        if due.is_empty() {
+            continue;
+        }
+
+        let results =
+            futures_util::future::join_all(due.iter().map(|(r, target)| probe(&state, r, target)))
+                .await;
+        let now = chrono::Utc::now().timestamp_millis();
+
+        let mut events = Vec::new();
+        {
+            let mut health = state.health.write().unwrap();
+            for ((route, target), result) in due.iter().zip(results) {
+                let Some(entry) = health
+                    .get_mut(&route.id)
+                    .and_then(|entries| entries.iter_mut().find(|h| &h.target == target))
+                else {
+                    continue;
+                };
+                let Some(previous) = entry.record(result, &route.health_check, now) else {
+                    continue;
+                };
+                // First successful probe after startup is not a transition worth reporting
+                if previous == HealthStatus::Unknown && entry.status == HealthStatus::Healthy {
+                    continue;
+                }
+                let healthy = entry.status == HealthStatus::Healthy;
+                if healthy {
+                    info!(
+                        route = %route.id,
+                        "Backend {} for {} is healthy again",
+                        entry.target, route.domain
+                    );
+                } else {
+                    warn!(
+                        route = %route.id,
+                        "Backend {} for {} is unhealthy: {}",
+                        entry.target,
+                        route.domain,
+                        entry.last_error.as_deref().unwrap_or("")
+                    );
+                }
+                events.push(BackendHealthEvent {
+                    route_id: route.id.clone(),
+                    domain: route.domain.clone(),
+                    target: entry.target.clone(),
+                    healthy,
+                    error: entry.last_error.clone(),
+                });
+            }
+        }
+
+        if let Some(bus) = state.get_events() {
+            for event in events {
+                let _ = bus.backend_health.send(event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thresholds() {
+        let cfg = HealthCheckConfig {
+            unhealthy_threshold: 2,
+            healthy_threshold: 2,
+            ..Default::default()
+        };
+        let mut h = BackendHealth::new("10.0.0.5:80".to_string());
+
+        assert_eq!(h.record(Err("refused".into()), &cfg, 1), None);
+        assert_eq!(h.status, HealthStatus::Unknown);
+        assert_eq!(
+            h.record(Err("refused".into()), &cfg, 2),
+            Some(HealthStatus::Unknown)
+        );
+        assert_eq!(h.status, HealthStatus::Unhealthy);
+        assert_eq!(h.last_change, Some(2));
+
+        // One success is not enough to recover with healthy_threshold = 2
+        assert_eq!(h.record(Ok(3), &cfg, 3), None);
+        assert_eq!(h.status, HealthStatus::Unhealthy);
+        assert_eq!(h.record(Ok(3), &cfg, 4), Some(HealthStatus::Unhealthy));
+        assert_eq!(h.status, HealthStatus::Healthy);
+        assert_eq!(h.latency_ms, Some(3));
+        assert!(h.last_error.is_none());
+    }
+
+    #[test]
+    fn test_is_due() {
+        let cfg = HealthCheckConfig::default();
+        let mut h = BackendHealth::new("x:1".to_string());
+        assert!(h.is_due(&cfg, 0));
+        h.record(Ok(1), &cfg, 1_000);
+        assert!(!h.is_due(&cfg, 5_000));
+        assert!(h.is_due(&cfg, 11_000));
+    }
+
+    #[tokio::test]
+    async fn test_tcp_probe() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config: crate::config::ProxyConfig = serde_json::from_value(serde_json::json!({
+            "base_domain": "example.com",
+            "routes": [{
+                "id": "r",
+                "domain": "a.example.com",
+                "target_host": "127.0.0.1",
+                "target_port": port
+            }]
+        }))
+        .unwrap();
+        let state = ProxyState::new(config.clone(), 4000);
+        let mut route = config.routes[0].clone();
+        let target = format!("127.0.0.1:{}", port);
+        assert!(probe(&state, &route, &target).await.is_ok());
+
+        drop(listener);
+        route.health_check.timeout_secs = 1;
+        assert!(probe(&state, &route, &target).await.is_err());
+    }
+}
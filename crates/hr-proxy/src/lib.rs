@@ -1,9 +1,17 @@
+pub mod balancer;
 pub mod config;
+pub mod forwarded;
+pub mod geoip;
 pub mod handler;
+pub mod health;
 pub mod logging;
+pub mod sinkhole;
 pub mod tls;
 
-pub use config::{ProxyConfig, RouteConfig};
+pub use config::{AccessLogConfig, ForwardedProfile, ForwardingConfig, GeoAccess, HealthCheckConfig, HealthCheckKind, LoadBalancePolicy, MaintenanceConfig, ProxyConfig, RouteConfig, RouteTarget};
+pub use forwarded::{ClientAddr, RelayUpstream};
 pub use handler::{proxy_handler, AppRoute, ProxyError, ProxyState};
-pub use logging::{AccessLogEntry, AccessLogger, OptionalAccessLogger};
+pub use geoip::{GeoIp, GeoIpStatus};
+pub use health::{BackendHealth, HealthStatus};
+pub use logging::{AccessLogEntry, AccessLogFilter, AccessLogger, OptionalAccessLogger};
 pub use tls::{SniResolver, TlsManager};
@@ -1,12 +1,21 @@
-use chrono::Utc;
-use serde::Serialize;
-use std::path::PathBuf;
-use tokio::fs::OpenOptions;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::fs::{File, OpenOptions};
 use tokio::io::AsyncWriteExt;
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
 use tracing::{error, info};
 
-#[derive(Debug, Serialize)]
+use crate::config::AccessLogConfig;
+
+/// Default and maximum number of entries returned by `recent`.
+const DEFAULT_QUERY_LIMIT: usize = 200;
+const MAX_QUERY_LIMIT: usize = 5000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AccessLogEntry {
     pub timestamp: String,
     pub client_ip: String,
@@ -16,9 +25,70 @@ pub struct AccessLogEntry {
     pub status: u16,
     pub duration_ms: u64,
     pub user_agent: String,
+    /// Client country (GeoIP), public addresses only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    /// Proxy in front of the relay VPS the client came through.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub via: Option<String>,
+}
+
+/// Filters of `/api/reverseproxy/logs` and its live tail.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AccessLogFilter {
+    /// Host without port.
+    pub domain: Option<String>,
+    /// Exact status (`404`) or class (`5xx`).
+    pub status: Option<String>,
+    pub ip: Option<String>,
+    /// Client country code.
+    pub country: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl AccessLogFilter {
+    pub fn matches(&self, entry: &AccessLogEntry) -> bool {
+        if let Some(domain) = &self.domain {
+            let host = entry.host.split(':').next().unwrap_or(&entry.host);
+            if !host.eq_ignore_ascii_case(domain) {
+                return false;
+            }
+        }
+        if let Some(status) = &self.status {
+            let matched = match status.strip_suffix("xx") {
+                Some(class) => class.parse::<u16>().is_ok_and(|c| entry.status / 100 == c),
+                None => status.parse::<u16>().is_ok_and(|s| entry.status == s),
+            };
+            if !matched {
+                return false;
+            }
+        }
+        if let Some(ip) = &self.ip
+            && &entry.client_ip != ip
+        {
+            return false;
+        }
+        if let Some(country) = &self.country
+            && !entry.country.as_deref().is_some_and(|c| c.eq_ignore_ascii_case(country))
+        {
+            return false;
+        }
+        if self.since.is_some() || self.until.is_some() {
+            let Ok(at) = DateTime::parse_from_rfc3339(&entry.timestamp) else {
+                return false;
+            };
+            if self.since.is_some_and(|since| at < since) || self.until.is_some_and(|until| at > until) {
+                return false;
+            }
+        }
+        true
+    }
 }
 
-/// Async access logger that writes JSON lines via a channel
+/// Async access logger that writes JSON lines via a channel, rotating the
+/// file by size and age.
 #[derive(Clone)]
 pub struct AccessLogger {
     sender: mpsc::UnboundedSender<AccessLogEntry>,
@@ -26,18 +96,15 @@ pub struct AccessLogger {
 
 impl AccessLogger {
     /// Start the access logger. Spawns a background task that writes to the log file.
-    pub fn start(log_path: PathBuf) -> Self {
+    pub fn start(log_path: PathBuf, settings: AccessLogConfig) -> Self {
         let (sender, mut receiver) = mpsc::unbounded_channel::<AccessLogEntry>();
 
         tokio::spawn(async move {
-            let file = OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(&log_path)
-                .await;
-
-            let mut file = match file {
-                Ok(f) => f,
+            let max_size = settings.max_size_mb.saturating_mul(1024 * 1024);
+            let max_age = (settings.max_age_hours > 0).then(|| Duration::from_secs(settings.max_age_hours * 3600));
+
+            let (mut file, mut size, mut opened_at) = match open_log(&log_path).await {
+                Ok(opened) => opened,
                 Err(e) => {
                     error!("Failed to open access log file {:?}: {}", log_path, e);
                     return;
@@ -47,11 +114,32 @@ impl AccessLogger {
             info!("Access logging to {:?}", log_path);
 
             while let Some(entry) = receiver.recv().await {
+                let expired = max_age.is_some_and(|age| opened_at.elapsed().unwrap_or_default() >= age);
+                if size > 0 && (size >= max_size || expired) {
+                    let _ = file.flush().await;
+                    let path = log_path.clone();
+                    let keep = settings.keep_files;
+                    match tokio::task::spawn_blocking(move || rotate_files(&path, keep)).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => error!("Failed to rotate access log: {}", e),
+                        Err(e) => error!("Access log rotation task failed: {}", e),
+                    }
+                    match open_log(&log_path).await {
+                        Ok(opened) => (file, size, opened_at) = opened,
+                        Err(e) => {
+                            error!("Failed to reopen access log file {:?}: {}", log_path, e);
+                            return;
+                        }
+                    }
+                }
+
                 match serde_json::to_string(&entry) {
                     Ok(json) => {
                         let line = format!("{}\n", json);
                         if let Err(e) = file.write_all(line.as_bytes()).await {
                             error!("Failed to write access log: {}", e);
+                        } else {
+                            size += line.len() as u64;
                         }
                     }
                     Err(e) => {
@@ -70,30 +158,172 @@ impl AccessLogger {
     }
 }
 
-/// Optional access logger wrapper
+/// Open the log for appending, with its current size and creation time.
+async fn open_log(path: &Path) -> std::io::Result<(File, u64, SystemTime)> {
+    let file = OpenOptions::new().create(true).append(true).open(path).await?;
+    let meta = file.metadata().await?;
+    let created = meta.created().or_else(|_| meta.modified()).unwrap_or_else(|_| SystemTime::now());
+    Ok((file, meta.len(), created))
+}
+
+/// Shift `access.log` → `access.log.1` → ... → `access.log.{keep}`, dropping
+/// the oldest archive.
+fn rotate_files(path: &Path, keep: usize) -> std::io::Result<()> {
+    let archive = |n: usize| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    };
+    if keep == 0 {
+        return std::fs::remove_file(path);
+    }
+    let _ = std::fs::remove_file(archive(keep));
+    for n in (1..keep).rev() {
+        let from = archive(n);
+        if from.exists() {
+            std::fs::rename(&from, archive(n + 1))?;
+        }
+    }
+    std::fs::rename(path, archive(1))
+}
+
+/// Access logger: optional JSON lines file, plus an in-memory ring buffer
+/// and a live feed for the API.
 #[derive(Clone)]
 pub struct OptionalAccessLogger {
     inner: Option<AccessLogger>,
+    recent: Arc<Mutex<VecDeque<AccessLogEntry>>>,
+    capacity: usize,
+    tail: broadcast::Sender<AccessLogEntry>,
 }
 
 impl OptionalAccessLogger {
-    pub fn new(log_path: Option<String>) -> Self {
-        let inner = log_path.map(|p| AccessLogger::start(PathBuf::from(p)));
-        Self { inner }
+    pub fn new(log_path: Option<String>, settings: AccessLogConfig) -> Self {
+        let capacity = settings.buffer_size;
+        let inner = log_path.map(|p| AccessLogger::start(PathBuf::from(p), settings));
+        Self {
+            inner,
+            recent: Arc::new(Mutex::new(VecDeque::with_capacity(capacity.min(1024)))),
+            capacity,
+            tail: broadcast::channel(1024).0,
+        }
     }
 
     pub fn none() -> Self {
-        Self { inner: None }
+        Self::new(None, AccessLogConfig::default())
     }
 
     pub fn log(&self, entry: AccessLogEntry) {
+        if self.capacity > 0 {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() >= self.capacity {
+                recent.pop_front();
+            }
+            recent.push_back(entry.clone());
+        }
+        if self.tail.receiver_count() > 0 {
+            let _ = self.tail.send(entry.clone());
+        }
         if let Some(logger) = &self.inner {
             logger.log(entry);
         }
     }
+
+    /// Buffered entries matching `filter`, newest first.
+    pub fn recent(&self, filter: &AccessLogFilter) -> Vec<AccessLogEntry> {
+        let limit = filter.limit.unwrap_or(DEFAULT_QUERY_LIMIT).clamp(1, MAX_QUERY_LIMIT);
+        let recent = self.recent.lock().unwrap();
+        recent
+            .iter()
+            .rev()
+            .filter(|e| filter.matches(e))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Live feed of new entries (unfiltered).
+    pub fn subscribe(&self) -> broadcast::Receiver<AccessLogEntry> {
+        self.tail.subscribe()
+    }
 }
 
 /// Create a timestamp string for the current time
 pub fn now_timestamp() -> String {
     Utc::now().to_rfc3339()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(host: &str, ip: &str, status: u16, timestamp: &str) -> AccessLogEntry {
+        AccessLogEntry {
+            timestamp: timestamp.to_string(),
+            client_ip: ip.to_string(),
+            host: host.to_string(),
+            method: "GET".to_string(),
+            path: "/".to_string(),
+            status,
+            duration_ms: 3,
+            user_agent: String::new(),
+            country: None,
+            via: None,
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let e = entry("app.example.com:443", "10.0.0.2", 502, "2026-10-17T10:00:00.5+00:00");
+        assert!(AccessLogFilter::default().matches(&e));
+
+        let by = |f: AccessLogFilter| f.matches(&e);
+        assert!(by(AccessLogFilter { domain: Some("app.example.com".into()), ..Default::default() }));
+        assert!(!by(AccessLogFilter { domain: Some("other.example.com".into()), ..Default::default() }));
+        assert!(by(AccessLogFilter { status: Some("5xx".into()), ..Default::default() }));
+        assert!(by(AccessLogFilter { status: Some("502".into()), ..Default::default() }));
+        assert!(!by(AccessLogFilter { status: Some("4xx".into()), ..Default::default() }));
+        assert!(!by(AccessLogFilter { ip: Some("10.0.0.3".into()), ..Default::default() }));
+
+        let at = |s: &str| Some(s.parse::<DateTime<Utc>>().unwrap());
+        assert!(by(AccessLogFilter { since: at("2026-10-17T09:00:00Z"), until: at("2026-10-17T11:00:00Z"), ..Default::default() }));
+        assert!(!by(AccessLogFilter { since: at("2026-10-17T10:00:01Z"), ..Default::default() }));
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let settings = AccessLogConfig {
+            buffer_size: 2,
+            ..Default::default()
+        };
+        let logger = OptionalAccessLogger::new(None, settings);
+        let mut tail = logger.subscribe();
+        for status in [200, 404, 500] {
+            logger.log(entry("app.example.com", "10.0.0.2", status, "2026-10-17T10:00:00Z"));
+        }
+
+        let recent = logger.recent(&AccessLogFilter::default());
+        assert_eq!(recent.iter().map(|e| e.status).collect::<Vec<_>>(), vec![500, 404]);
+        let errors = logger.recent(&AccessLogFilter { status: Some("4xx".into()), ..Default::default() });
+        assert_eq!(errors.len(), 1);
+        assert_eq!(tail.try_recv().unwrap().status, 200);
+    }
+
+    #[test]
+    fn test_rotate_files() {
+        let dir = std::env::temp_dir().join(format!("hr-proxy-rotate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+
+        for generation in ["a", "b", "c"] {
+            std::fs::write(&path, generation).unwrap();
+            rotate_files(&path, 2).unwrap();
+        }
+        assert!(!path.exists());
+        assert_eq!(std::fs::read_to_string(dir.join("access.log.1")).unwrap(), "c");
+        assert_eq!(std::fs::read_to_string(dir.join("access.log.2")).unwrap(), "b");
+        assert!(!dir.join("access.log.3").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+//! "Blocked by HomeRoute" page for the DNS sinkhole.
+//!
+//! In `sinkhole` mode the resolver answers blocked names with an address of
+//! the router, so browsers reach the proxy instead of failing with a cryptic
+//! connection error. A host without route that the blocked query log shows
+//! as blocked gets this page: the cause of the block and a link to the
+//! dashboard allowing the domain for a while (login required there).
+//!
+//! Plain HTTP gets the page directly; HTTPS only after the certificate
+//! warning, the proxy holding no certificate for the blocked name.
+
+use std::net::IpAddr;
+
+use hr_dns::SharedDnsState;
+use hr_dns::blocked_log::{BlockReason, BlockedEntry};
+
+/// Length of the temporary allow offered by the page.
+pub const ALLOW_MINUTES: u32 = 15;
+
+/// The page for `host` requested by `client`, `None` when the sinkhole is
+/// off or the host was not blocked.
+pub async fn blocked_page(dns: &SharedDnsState, base_domain: &str, host: &str, client: IpAddr) -> Option<String> {
+    let domain = host.split(':').next().unwrap_or(host).trim_end_matches('.').to_lowercase();
+    let client = client.to_canonical();
+    let dns = dns.read().await;
+    if dns.adblock_block_response != "sinkhole" {
+        return None;
+    }
+    let entry = dns.blocked_log.last(&domain, &client.to_string())?;
+    // Allowed since, the device still has the sinkhole address cached
+    let allowed = dns.adblock_schedule.is_allowed(&domain, client, chrono::Utc::now());
+    Some(render(&entry, base_domain, allowed))
+}
+
+fn describe(reason: &BlockReason) -> String {
+    match reason {
+        BlockReason::Rule { rule, source } => {
+            format!("Règle <code>{}</code> de la liste {}.", escape(rule), escape(source))
+        }
+        BlockReason::Cname { rule, source, target } => format!(
+            "Alias vers <code>{}</code>, bloqué par la règle <code>{}</code> de la liste {}.",
+            escape(target),
+            escape(rule),
+            escape(source)
+        ),
+        BlockReason::Profile { profile } => format!("Profil horaire « {} ».", escape(profile)),
+    }
+}
+
+fn render(entry: &BlockedEntry, base_domain: &str, allowed: bool) -> String {
+    let domain = escape(&entry.domain);
+    let action = if allowed {
+        "<p class=\"ok\">Ce domaine vient d'être autorisé. La page se rechargera dès que l'appareil \
+         aura oublié l'ancienne réponse DNS.</p>"
+            .to_string()
+    } else {
+        format!(
+            "<a class=\"btn\" href=\"https://proxy.{base}/adblock?allow={domain}&amp;client={client}\">\
+             Autoriser {ALLOW_MINUTES} minutes</a>\
+             <p class=\"hint\">Connexion au tableau de bord requise.</p>",
+            base = escape(base_domain),
+            client = escape(&entry.client),
+        )
+    };
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="fr">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+{refresh}<title>Bloqué par HomeRoute</title>
+<style>
+*{{margin:0;padding:0;box-sizing:border-box}}
+body{{font-family:-apple-system,BlinkMacSystemFont,'Segoe UI',Roboto,sans-serif;
+background:#0f172a;color:#e2e8f0;display:flex;justify-content:center;align-items:center;
+min-height:100vh}}
+.card{{background:#1e293b;border-radius:16px;padding:3rem;text-align:center;
+max-width:480px;box-shadow:0 25px 50px rgba(0,0,0,.3)}}
+h1{{font-size:1.25rem;font-weight:600;margin-bottom:.75rem}}
+.domain{{font-size:1.1rem;color:#f87171;margin-bottom:1rem;word-break:break-all}}
+p{{color:#94a3b8;font-size:.9rem;line-height:1.5;margin-bottom:1rem}}
+code{{color:#e2e8f0;word-break:break-all}}
+.btn{{display:inline-block;background:#2563eb;color:#fff;text-decoration:none;
+padding:.6rem 1.2rem;border-radius:8px;font-size:.9rem}}
+.hint{{font-size:.75rem;margin:.75rem 0 0}}
+.ok{{color:#4ade80}}
+.brand{{color:#60a5fa;font-size:.8rem;margin-top:1.5rem}}
+</style>
+</head>
+<body>
+<div class="card">
+<h1>Bloqué par HomeRoute</h1>
+<div class="domain">{domain}</div>
+<p>{reason}</p>
+{action}
+<div class="brand">HomeRoute</div>
+</div>
+</body>
+</html>"#,
+        refresh = if allowed { "<meta http-equiv=\"refresh\" content=\"10\">\n" } else { "" },
+        reason = describe(&entry.reason),
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(reason: BlockReason) -> BlockedEntry {
+        BlockedEntry {
+            id: 1,
+            ts: chrono::Utc::now(),
+            domain: "ads.example".to_string(),
+            query_type: "A".to_string(),
+            client: "192.168.1.10".to_string(),
+            client_name: None,
+            reason,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let rule = BlockReason::Rule { rule: "||ads.example^<script>".to_string(), source: "EasyList".to_string() };
+        let html = render(&entry(rule), "example.com", false);
+        assert!(html.contains("https://proxy.example.com/adblock?allow=ads.example&amp;client=192.168.1.10"));
+        assert!(html.contains("||ads.example^&lt;script&gt;"));
+        assert!(!html.contains("http-equiv"));
+
+        let html = render(&entry(BlockReason::Profile { profile: "Kids".to_string() }), "example.com", true);
+        assert!(html.contains("Profil horaire « Kids »"));
+        assert!(html.contains("http-equiv=\"refresh\""));
+        assert!(!html.contains("/adblock?allow="));
+    }
+}
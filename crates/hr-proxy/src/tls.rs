@@ -384,9 +384,21 @@ mod tests {
                 target_host: "localhost".to_string(),
                 target_port: 8080,
                 local_only: false,
+                vpn_only: false,
                 require_auth: false,
+                require_mfa: false,
                 enabled: true,
                 cert_id: None, // No cert_id, so loading is skipped
+                health_check: Default::default(),
+                targets: Vec::new(),
+                load_balancing: Default::default(),
+                sticky: false,
+                draining: Vec::new(),
+                geo: Default::default(),
+                maintenance: Default::default(),
+                forwarding: Default::default(),
+                backend_tls: false,
+                local_dns: true,
             },
             crate::config::RouteConfig {
                 id: "2".to_string(),
@@ -395,9 +407,21 @@ mod tests {
                 target_host: "localhost".to_string(),
                 target_port: 8081,
                 local_only: false,
+                vpn_only: false,
                 require_auth: false,
+                require_mfa: false,
                 enabled: false,
                 cert_id: Some("cert-2".to_string()),
+                health_check: Default::default(),
+                targets: Vec::new(),
+                load_balancing: Default::default(),
+                sticky: false,
+                draining: Vec::new(),
+                geo: Default::default(),
+                maintenance: Default::default(),
+                forwarding: Default::default(),
+                backend_tls: false,
+                local_dns: true,
             },
         ];
         // Should succeed - disabled route is skipped, enabled route has no cert_id so skipped too
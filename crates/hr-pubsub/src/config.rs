@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::topic;
+
+/// Pub/sub settings and topic grants (pubsub-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PubSubConfig {
+    /// Largest payload, serialized, in bytes.
+    #[serde(default = "default_max_payload_bytes")]
+    pub max_payload_bytes: usize,
+    #[serde(default)]
+    pub grants: Vec<TopicGrant>,
+}
+
+fn default_max_payload_bytes() -> usize {
+    64 * 1024
+}
+
+/// Access of an app to topics of another app. An app always has full
+/// access to its own topics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    /// Subscribe.
+    Read,
+    /// Publish.
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    pub fn can_read(self) -> bool {
+        matches!(self, Access::Read | Access::ReadWrite)
+    }
+
+    pub fn can_write(self) -> bool {
+        matches!(self, Access::Write | Access::ReadWrite)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopicGrant {
+    pub id: String,
+    /// Pattern of the topics granted; its first segment is the owner app.
+    pub topics: String,
+    /// App granted (slug), `*` for every app.
+    pub app: String,
+    pub access: Access,
+    #[serde(default)]
+    pub created_at: String,
+}
+
+impl TopicGrant {
+    pub fn validate(&self) -> Result<(), String> {
+        if !topic::valid_pattern(&self.topics) {
+            return Err(format!("Invalid topic pattern: {}", self.topics));
+        }
+        if self.app.is_empty() {
+            return Err("The granted app is required".to_string());
+        }
+        if self.app == topic::owner(&self.topics) {
+            return Err("An app always has access to its own topics".to_string());
+        }
+        Ok(())
+    }
+
+    fn covers(&self, app: &str) -> bool {
+        self.app == "*" || self.app == app
+    }
+}
+
+impl Default for PubSubConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl PubSubConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn can_publish(&self, app: &str, topic: &str) -> bool {
+        topic::owner(topic) == app
+            || self
+                .grants
+                .iter()
+                .any(|g| g.access.can_write() && g.covers(app) && topic::matches(&g.topics, topic))
+    }
+
+    pub fn can_read(&self, app: &str, topic: &str) -> bool {
+        topic::owner(topic) == app
+            || self
+                .grants
+                .iter()
+                .any(|g| g.access.can_read() && g.covers(app) && topic::matches(&g.topics, topic))
+    }
+
+    /// Whether `app` may subscribe to `pattern`: its own topics, or those of
+    /// an app granting it read access to some of them. Messages are still
+    /// checked one by one with `can_read`.
+    pub fn can_subscribe(&self, app: &str, pattern: &str) -> bool {
+        let owner = topic::owner(pattern);
+        owner == app
+            || self
+                .grants
+                .iter()
+                .any(|g| g.access.can_read() && g.covers(app) && topic::owner(&g.topics) == owner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grant(topics: &str, app: &str, access: Access) -> TopicGrant {
+        TopicGrant { id: String::new(), topics: topics.to_string(), app: app.to_string(), access, created_at: String::new() }
+    }
+
+    #[test]
+    fn test_grants() {
+        let config = PubSubConfig {
+            grants: vec![
+                grant("billing.invoice.>", "shop", Access::Read),
+                grant("billing.commands.*", "*", Access::Write),
+            ],
+            ..Default::default()
+        };
+        assert!(config.can_publish("billing", "billing.invoice.paid"));
+        assert!(!config.can_publish("shop", "billing.invoice.paid"));
+        assert!(config.can_publish("shop", "billing.commands.refund"));
+        assert!(config.can_read("shop", "billing.invoice.paid"));
+        assert!(!config.can_read("shop", "billing.commands.refund"));
+        assert!(!config.can_read("crm", "billing.invoice.paid"));
+        assert!(config.can_subscribe("shop", "billing.>"));
+        assert!(!config.can_subscribe("crm", "billing.>"));
+
+        assert!(grant("billing.>", "shop", Access::Read).validate().is_ok());
+        assert!(grant("billing.>", "billing", Access::Read).validate().is_err());
+        assert!(grant("*.invoice", "shop", Access::Read).validate().is_err());
+    }
+}
@@ -0,0 +1,324 @@
+//! Realtime pub/sub between the apps, over their agent connections: an app
+//! publishes on its own topics (`{slug}.…`), and the apps subscribed to them
+//! receive each message right away, as does the dashboard websocket. Other
+//! apps reach a topic only through a grant of its owner (read to subscribe,
+//! write to publish). Nothing is stored: offline subscribers miss messages,
+//! the queues are there for that.
+
+pub mod config;
+pub mod topic;
+
+pub use config::{Access, PubSubConfig, TopicGrant};
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, RwLock};
+
+use hr_common::events::{EventBus, PubSubEvent};
+use hr_registry::protocol::{PubSubRequest, RegistryMessage};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+/// Subscription patterns per app.
+pub const MAX_SUBSCRIPTIONS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PubSubError {
+    InvalidTopic(String),
+    /// No grant for this topic.
+    Forbidden(String),
+    TooLarge,
+    TooManySubscriptions,
+}
+
+impl std::fmt::Display for PubSubError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PubSubError::InvalidTopic(t) => write!(
+                f,
+                "invalid topic {} ({{app}}.{{name}}, segments of a-z, A-Z, 0-9, '_', '-'; '*' and a trailing '>' in subscriptions)",
+                t
+            ),
+            PubSubError::Forbidden(t) => write!(f, "no grant for topic {}", t),
+            PubSubError::TooLarge => write!(f, "payload too large"),
+            PubSubError::TooManySubscriptions => write!(f, "at most {} subscriptions per app", MAX_SUBSCRIPTIONS),
+        }
+    }
+}
+
+/// Delivers messages to the connected apps.
+pub trait EventSink: Send + Sync + 'static {
+    /// False when the app is not connected.
+    fn deliver(&self, app_id: &str, event: PubSubEvent) -> impl Future<Output = bool> + Send;
+}
+
+impl EventSink for hr_registry::AgentRegistry {
+    async fn deliver(&self, app_id: &str, event: PubSubEvent) -> bool {
+        self.send_to_agent(app_id, RegistryMessage::PubSubMessage(event)).await.is_ok()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Subscriber {
+    slug: String,
+    patterns: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Subscription {
+    pub app_id: String,
+    pub app: String,
+    pub topics: Vec<String>,
+}
+
+/// Shared pub/sub hub: the agent connections subscribe and publish, the API
+/// publishes for apps without a connection and manages the grants.
+pub struct PubSub<S: EventSink = hr_registry::AgentRegistry> {
+    config: RwLock<PubSubConfig>,
+    /// By app id; dropped when the app's last connection closes.
+    subscribers: RwLock<HashMap<String, Subscriber>>,
+    sink: Arc<S>,
+    events: Arc<EventBus>,
+}
+
+pub type SharedPubSub = Arc<PubSub>;
+
+impl<S: EventSink> PubSub<S> {
+    pub fn new(config: PubSubConfig, sink: Arc<S>, events: Arc<EventBus>) -> Self {
+        Self { config: RwLock::new(config), subscribers: RwLock::new(HashMap::new()), sink, events }
+    }
+
+    pub fn config(&self) -> PubSubConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Replace the configuration; revoked grants stop deliveries right away.
+    pub fn apply(&self, config: PubSubConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Run an operation for the app `app_id` (slug `slug`); the result is
+    /// the JSON returned to it.
+    pub async fn handle(&self, app_id: &str, slug: &str, request: PubSubRequest) -> Result<Value, PubSubError> {
+        match request {
+            PubSubRequest::Subscribe { topics } => {
+                let subscriptions = self.subscribe(app_id, slug, &topics)?;
+                Ok(json!({"subscriptions": subscriptions}))
+            }
+            PubSubRequest::Unsubscribe { topics } => {
+                let subscriptions = self.unsubscribe(app_id, &topics);
+                Ok(json!({"subscriptions": subscriptions}))
+            }
+            PubSubRequest::Publish { topic, payload } => {
+                let (id, delivered) = self.publish(slug, &topic, payload).await?;
+                Ok(json!({"id": id, "delivered": delivered}))
+            }
+        }
+    }
+
+    /// Add subscriptions (all or none); returns the app's patterns.
+    pub fn subscribe(&self, app_id: &str, slug: &str, patterns: &[String]) -> Result<Vec<String>, PubSubError> {
+        {
+            let config = self.config.read().unwrap();
+            for pattern in patterns {
+                if !topic::valid_pattern(pattern) {
+                    return Err(PubSubError::InvalidTopic(pattern.clone()));
+                }
+                if !config.can_subscribe(slug, pattern) {
+                    return Err(PubSubError::Forbidden(pattern.clone()));
+                }
+            }
+        }
+        let mut subscribers = self.subscribers.write().unwrap();
+        let subscriber = subscribers
+            .entry(app_id.to_string())
+            .or_insert_with(|| Subscriber { slug: slug.to_string(), patterns: BTreeSet::new() });
+        let added = patterns.iter().filter(|p| !subscriber.patterns.contains(*p)).count();
+        if subscriber.patterns.len() + added > MAX_SUBSCRIPTIONS {
+            return Err(PubSubError::TooManySubscriptions);
+        }
+        subscriber.patterns.extend(patterns.iter().cloned());
+        Ok(subscriber.patterns.iter().cloned().collect())
+    }
+
+    /// Remove subscriptions; returns the app's remaining patterns.
+    pub fn unsubscribe(&self, app_id: &str, patterns: &[String]) -> Vec<String> {
+        let mut subscribers = self.subscribers.write().unwrap();
+        let Some(subscriber) = subscribers.get_mut(app_id) else {
+            return Vec::new();
+        };
+        for pattern in patterns {
+            subscriber.patterns.remove(pattern);
+        }
+        let remaining = subscriber.patterns.iter().cloned().collect();
+        if subscriber.patterns.is_empty() {
+            subscribers.remove(app_id);
+        }
+        remaining
+    }
+
+    /// Drop every subscription of an app (its connection closed).
+    pub fn remove_subscriber(&self, app_id: &str) {
+        self.subscribers.write().unwrap().remove(app_id);
+    }
+
+    pub fn subscriptions(&self) -> Vec<Subscription> {
+        let mut subscriptions: Vec<Subscription> = self
+            .subscribers
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(app_id, s)| Subscription {
+                app_id: app_id.clone(),
+                app: s.slug.clone(),
+                topics: s.patterns.iter().cloned().collect(),
+            })
+            .collect();
+        subscriptions.sort_by(|a, b| a.app.cmp(&b.app));
+        subscriptions
+    }
+
+    /// Publish for the app `slug`; returns the message id and the number of
+    /// apps it was delivered to.
+    pub async fn publish(&self, slug: &str, topic: &str, payload: Value) -> Result<(String, usize), PubSubError> {
+        if !topic::valid_topic(topic) {
+            return Err(PubSubError::InvalidTopic(topic.to_string()));
+        }
+        let recipients: Vec<(String, String)> = {
+            let config = self.config.read().unwrap();
+            if !config.can_publish(slug, topic) {
+                return Err(PubSubError::Forbidden(topic.to_string()));
+            }
+            if payload.to_string().len() > config.max_payload_bytes {
+                return Err(PubSubError::TooLarge);
+            }
+            self.subscribers
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|(_, s)| s.patterns.iter().any(|p| topic::matches(p, topic)) && config.can_read(&s.slug, topic))
+                .map(|(app_id, s)| (app_id.clone(), s.slug.clone()))
+                .collect()
+        };
+
+        let event = PubSubEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            topic: topic.to_string(),
+            publisher: slug.to_string(),
+            payload,
+            published_at: chrono::Utc::now().to_rfc3339(),
+        };
+        let metrics = hr_common::metrics::registry();
+        metrics
+            .counter("homeroute_pubsub_published_total", "Messages published on pub/sub topics, by app.", &[("app", slug)])
+            .inc();
+        let mut delivered = 0;
+        for (app_id, subscriber) in recipients {
+            if self.sink.deliver(&app_id, event.clone()).await {
+                delivered += 1;
+                metrics
+                    .counter(
+                        "homeroute_pubsub_delivered_total",
+                        "Pub/sub messages delivered, by subscribed app.",
+                        &[("app", &subscriber)],
+                    )
+                    .inc();
+            }
+        }
+        let id = event.id.clone();
+        let _ = self.events.pubsub.send(event);
+        Ok((id, delivered))
+    }
+
+    /// Drop the grants given by or to a deleted app, so that a new app with
+    /// the same slug starts without them. Returns the new configuration when
+    /// it changed.
+    pub fn remove_app(&self, slug: &str) -> Option<PubSubConfig> {
+        let mut config = self.config.write().unwrap();
+        let before = config.grants.len();
+        config.grants.retain(|g| g.app != slug && topic::owner(&g.topics) != slug);
+        (config.grants.len() != before).then(|| config.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Sink {
+        delivered: Mutex<Vec<(String, String)>>,
+    }
+
+    impl EventSink for Sink {
+        async fn deliver(&self, app_id: &str, event: PubSubEvent) -> bool {
+            self.delivered.lock().unwrap().push((app_id.to_string(), event.topic));
+            true
+        }
+    }
+
+    fn hub(grants: Vec<TopicGrant>) -> (PubSub<Sink>, Arc<Sink>) {
+        let sink = Arc::new(Sink::default());
+        let config = PubSubConfig { grants, ..Default::default() };
+        (PubSub::new(config, sink.clone(), Arc::new(EventBus::new())), sink)
+    }
+
+    fn grant(topics: &str, app: &str, access: Access) -> TopicGrant {
+        TopicGrant { id: "g".to_string(), topics: topics.to_string(), app: app.to_string(), access, created_at: String::new() }
+    }
+
+    #[tokio::test]
+    async fn test_publish_follows_grants() {
+        let (hub, sink) = hub(vec![grant("billing.invoice.>", "shop", Access::Read)]);
+        let mut events = hub.events.pubsub.subscribe();
+        hub.subscribe("id-billing", "billing", &["billing.>".to_string()]).unwrap();
+        // Subscribed to all of billing, but only granted the invoices
+        hub.subscribe("id-shop", "shop", &["billing.>".to_string()]).unwrap();
+        assert_eq!(
+            hub.subscribe("id-crm", "crm", &["billing.>".to_string()]),
+            Err(PubSubError::Forbidden("billing.>".to_string()))
+        );
+
+        let (_, delivered) = hub.publish("billing", "billing.invoice.paid", json!({"id": 1})).await.unwrap();
+        assert_eq!(delivered, 2);
+        let (_, delivered) = hub.publish("billing", "billing.internal.audit", json!(null)).await.unwrap();
+        assert_eq!(delivered, 1);
+        assert_eq!(
+            hub.publish("shop", "billing.invoice.paid", json!(null)).await,
+            Err(PubSubError::Forbidden("billing.invoice.paid".to_string()))
+        );
+        assert_eq!(sink.delivered.lock().unwrap().len(), 3);
+        assert_eq!(events.try_recv().unwrap().publisher, "billing");
+
+        // Revoking the grant stops the deliveries of the live subscription
+        hub.apply(PubSubConfig::default());
+        let (_, delivered) = hub.publish("billing", "billing.invoice.paid", json!(null)).await.unwrap();
+        assert_eq!(delivered, 1);
+
+        hub.remove_subscriber("id-billing");
+        let (_, delivered) = hub.publish("billing", "billing.invoice.paid", json!(null)).await.unwrap();
+        assert_eq!(delivered, 0);
+    }
+
+    #[tokio::test]
+    async fn test_limits_and_app_removal() {
+        let (hub, _) = hub(vec![grant("billing.>", "shop", Access::ReadWrite), grant("shop.>", "crm", Access::Read)]);
+        let big = json!("x".repeat(hub.config().max_payload_bytes));
+        assert_eq!(hub.publish("shop", "shop.big", big).await, Err(PubSubError::TooLarge));
+        assert!(matches!(hub.publish("shop", "shop", json!(1)).await, Err(PubSubError::InvalidTopic(_))));
+
+        let patterns: Vec<String> = (0..MAX_SUBSCRIPTIONS).map(|i| format!("shop.t{}", i)).collect();
+        hub.subscribe("id-shop", "shop", &patterns).unwrap();
+        assert_eq!(
+            hub.subscribe("id-shop", "shop", &["shop.more".to_string()]),
+            Err(PubSubError::TooManySubscriptions)
+        );
+        assert_eq!(hub.unsubscribe("id-shop", &patterns).len(), 0);
+        assert!(hub.subscriptions().is_empty());
+
+        let config = hub.remove_app("shop").unwrap();
+        assert!(config.grants.is_empty());
+        assert!(hub.remove_app("shop").is_none());
+    }
+}
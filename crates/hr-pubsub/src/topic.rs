@@ -0,0 +1,70 @@
+//! Topic names and subscription patterns. A topic is dot-separated and its
+//! first segment is the slug of the app owning it: `billing.invoice.paid`
+//! belongs to `billing`. In patterns, `*` matches one segment and a trailing
+//! `>` one or more; the owner segment is always literal.
+
+/// Longest topic or pattern.
+pub const MAX_TOPIC_LEN: usize = 200;
+
+fn valid_segment(segment: &str) -> bool {
+    (1..=64).contains(&segment.len())
+        && segment.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-'))
+}
+
+pub fn valid_topic(topic: &str) -> bool {
+    topic.len() <= MAX_TOPIC_LEN && topic.split('.').count() >= 2 && topic.split('.').all(valid_segment)
+}
+
+pub fn valid_pattern(pattern: &str) -> bool {
+    let segments: Vec<&str> = pattern.split('.').collect();
+    pattern.len() <= MAX_TOPIC_LEN
+        && segments.len() >= 2
+        && valid_segment(segments[0])
+        && segments.iter().enumerate().skip(1).all(|(i, s)| {
+            *s == "*" || (*s == ">" && i == segments.len() - 1) || valid_segment(s)
+        })
+}
+
+/// App owning a topic or pattern.
+pub fn owner(topic: &str) -> &str {
+    topic.split('.').next().unwrap_or(topic)
+}
+
+pub fn matches(pattern: &str, topic: &str) -> bool {
+    let mut topic_segments = topic.split('.');
+    for segment in pattern.split('.') {
+        match (segment, topic_segments.next()) {
+            (">", Some(_)) => return true,
+            ("*", Some(_)) => {}
+            (s, Some(t)) if s == t => {}
+            _ => return false,
+        }
+    }
+    topic_segments.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topics_and_patterns() {
+        assert!(valid_topic("billing.invoice.paid"));
+        assert!(!valid_topic("billing"));
+        assert!(!valid_topic("billing..paid"));
+        assert!(!valid_topic("billing.*"));
+        assert!(valid_pattern("billing.*.paid"));
+        assert!(valid_pattern("billing.>"));
+        assert!(!valid_pattern("*.invoice"));
+        assert!(!valid_pattern("billing.>.paid"));
+        assert_eq!(owner("billing.invoice.paid"), "billing");
+
+        assert!(matches("billing.invoice.paid", "billing.invoice.paid"));
+        assert!(matches("billing.*.paid", "billing.invoice.paid"));
+        assert!(!matches("billing.*", "billing.invoice.paid"));
+        assert!(matches("billing.>", "billing.invoice.paid"));
+        assert!(!matches("billing.invoice.>", "billing.invoice"));
+        assert!(!matches("billing.invoice", "billing.invoice.paid"));
+        assert!(!matches("shop.>", "billing.invoice"));
+    }
+}
@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Traffic shaping (qos-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QosConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Upload is shaped on this interface's egress.
+    #[serde(default)]
+    pub wan_interface: String,
+    /// Download is shaped on this interface's egress (towards the LAN);
+    /// the DHCP interface when empty.
+    #[serde(default)]
+    pub lan_interface: String,
+    /// Shaped rates, a little below the line rates (about 90 %) so the
+    /// queue builds here instead of in the modem.
+    #[serde(default)]
+    pub download_mbit: u32,
+    #[serde(default)]
+    pub upload_mbit: u32,
+    #[serde(default)]
+    pub qdisc: Qdisc,
+    #[serde(default)]
+    pub devices: Vec<DeviceRule>,
+}
+
+/// Queue discipline of the leaf classes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Qdisc {
+    #[default]
+    Cake,
+    FqCodel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Served first (video calls, gaming).
+    High,
+    #[default]
+    Normal,
+    /// Only gets the bandwidth the others leave (backups, downloads).
+    Bulk,
+}
+
+/// Priority and caps of a LAN device, identified by its MAC address (its
+/// IPv4 address comes from the DHCP leases).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceRule {
+    pub mac: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub download_mbit: Option<u32>,
+    #[serde(default)]
+    pub upload_mbit: Option<u32>,
+}
+
+impl DeviceRule {
+    pub fn capped(&self) -> bool {
+        self.download_mbit.is_some() || self.upload_mbit.is_some()
+    }
+}
+
+impl Default for QosConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+fn valid_interface(name: &str) -> bool {
+    (1..=15).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.@".contains(c))
+}
+
+fn valid_mac(mac: &str) -> bool {
+    let parts: Vec<&str> = mac.split(':').collect();
+    parts.len() == 6 && parts.iter().all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+impl QosConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Lowercase the MAC addresses, as in the lease store.
+    pub fn normalize(&mut self) {
+        for device in &mut self.devices {
+            device.mac = device.mac.trim().to_ascii_lowercase().replace('-', ":");
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.enabled {
+            if !valid_interface(&self.wan_interface) {
+                return Err(format!("Invalid WAN interface: {}", self.wan_interface));
+            }
+            if self.download_mbit == 0 || self.upload_mbit == 0 {
+                return Err("Download and upload rates are required".to_string());
+            }
+        }
+        if !self.lan_interface.is_empty() && !valid_interface(&self.lan_interface) {
+            return Err(format!("Invalid LAN interface: {}", self.lan_interface));
+        }
+        if self.download_mbit > 100_000 || self.upload_mbit > 100_000 {
+            return Err("Rates are limited to 100 Gbit/s".to_string());
+        }
+        let mut macs = std::collections::HashSet::new();
+        for device in &self.devices {
+            if !valid_mac(&device.mac) {
+                return Err(format!("Invalid MAC address: {}", device.mac));
+            }
+            if !macs.insert(device.mac.to_ascii_lowercase()) {
+                return Err(format!("Duplicate device: {}", device.mac));
+            }
+            if device.download_mbit == Some(0) || device.upload_mbit == Some(0) {
+                return Err(format!("Caps of {} must be at least 1 Mbit/s", device.mac));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut config: QosConfig = serde_json::from_str(
+            r#"{
+                "enabled": true, "wanInterface": "eth0", "downloadMbit": 900, "uploadMbit": 90,
+                "devices": [{"mac": "AA-BB-CC-DD-EE-01", "name": "TV", "priority": "bulk", "downloadMbit": 25}]
+            }"#,
+        )
+        .unwrap();
+        config.normalize();
+        assert_eq!(config.devices[0].mac, "aa:bb:cc:dd:ee:01");
+        assert!(config.validate().is_ok());
+        assert!(config.devices[0].capped());
+
+        config.devices.push(config.devices[0].clone());
+        assert!(config.validate().is_err());
+        config.devices.truncate(1);
+        config.upload_mbit = 0;
+        assert!(config.validate().is_err());
+        config.enabled = false;
+        assert!(config.validate().is_ok());
+        config.wan_interface = "eth0; reboot".to_string();
+        config.enabled = true;
+        config.upload_mbit = 90;
+        assert!(config.validate().is_err());
+    }
+}
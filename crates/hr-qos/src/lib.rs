@@ -0,0 +1,79 @@
+//! Traffic shaping (SQM) on the WAN link: HTB with CAKE or fq_codel leaves
+//! in both directions, per-device priorities and bandwidth caps for the LAN
+//! devices known to the DHCP server.
+
+pub mod config;
+pub mod service;
+pub mod shaper;
+
+pub use config::{DeviceRule, Priority, QosConfig, Qdisc};
+
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+
+/// A configured device as currently applied.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceStatus {
+    pub mac: String,
+    pub name: String,
+    /// Address of its lease; without one only its upload is shaped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QosStatus {
+    /// The shaper is in place.
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lan_interface: Option<String>,
+    pub devices: Vec<DeviceStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_applied: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Shared QoS handle: the API pushes config and reads status, the
+/// supervised `qos` service applies it and follows the leases.
+pub struct Qos {
+    config: watch::Sender<QosConfig>,
+    status: RwLock<QosStatus>,
+}
+
+pub type SharedQos = Arc<Qos>;
+
+impl Qos {
+    pub fn new(config: QosConfig) -> Self {
+        Self {
+            config: watch::channel(config).0,
+            status: RwLock::new(QosStatus::default()),
+        }
+    }
+
+    pub fn config(&self) -> QosConfig {
+        self.config.borrow().clone()
+    }
+
+    /// Replace the configuration; the shaper is rebuilt right away.
+    pub fn apply(&self, config: QosConfig) {
+        self.config.send_replace(config);
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<QosConfig> {
+        self.config.subscribe()
+    }
+
+    pub fn status(&self) -> QosStatus {
+        self.status.read().unwrap().clone()
+    }
+
+    pub(crate) fn update_status(&self, f: impl FnOnce(&mut QosStatus)) {
+        f(&mut self.status.write().unwrap());
+    }
+}
@@ -0,0 +1,128 @@
+//! The `qos` service: rebuilds the shaper when the config changes or a
+//! configured device gets another address, and removes it when disabled.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use hr_dhcp::SharedDhcpState;
+use tracing::{info, warn};
+
+use crate::config::QosConfig;
+use crate::shaper::{self, Direction, ResolvedDevice};
+use crate::{DeviceStatus, SharedQos};
+
+/// Lease changes are picked up this often.
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What is in place: the config, the LAN interface and the device addresses.
+#[derive(PartialEq)]
+struct Applied {
+    config: QosConfig,
+    lan: String,
+    devices: Vec<ResolvedDevice>,
+}
+
+pub async fn run_qos(qos: SharedQos, dhcp: SharedDhcpState) -> Result<()> {
+    let mut config_rx = qos.subscribe();
+    let mut applied: Option<Applied> = None;
+    loop {
+        let config = config_rx.borrow_and_update().clone();
+        reconcile(&qos, &dhcp, config, &mut applied).await;
+
+        tokio::select! {
+            changed = config_rx.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+            }
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+        }
+    }
+}
+
+async fn reconcile(qos: &SharedQos, dhcp: &SharedDhcpState, config: QosConfig, applied: &mut Option<Applied>) {
+    let (lan, devices, statuses) = {
+        let dhcp = dhcp.read().await;
+        let lan = if config.lan_interface.is_empty() {
+            dhcp.config.interface.clone()
+        } else {
+            config.lan_interface.clone()
+        };
+        let mut devices = Vec::new();
+        let mut statuses = Vec::new();
+        for rule in &config.devices {
+            let lease = dhcp.lease_store.get_lease_by_mac(&rule.mac);
+            devices.push(ResolvedDevice { rule: rule.clone(), ip: lease.map(|l| l.ip) });
+            statuses.push(DeviceStatus {
+                mac: rule.mac.clone(),
+                name: rule.name.clone(),
+                ip: lease.map(|l| l.ip.to_string()),
+                hostname: lease.and_then(|l| l.hostname.clone()),
+            });
+        }
+        (lan, devices, statuses)
+    };
+    qos.update_status(|s| s.devices = statuses);
+
+    if !config.enabled {
+        if let Some(previous) = applied.take() {
+            let result = teardown(&previous.config.wan_interface, &previous.lan).await;
+            qos.update_status(|s| {
+                s.active = false;
+                s.lan_interface = None;
+                s.last_error = result.err().map(|e| e.to_string());
+            });
+            info!("Traffic shaping disabled");
+        }
+        return;
+    }
+
+    let next = Applied { config, lan, devices };
+    if applied.as_ref() == Some(&next) {
+        return;
+    }
+    if let Some(previous) = applied.take()
+        && (previous.config.wan_interface != next.config.wan_interface || previous.lan != next.lan)
+    {
+        let _ = teardown(&previous.config.wan_interface, &previous.lan).await;
+    }
+    let result = setup(&next).await;
+    qos.update_status(|s| {
+        s.active = result.is_ok();
+        s.lan_interface = Some(next.lan.clone());
+        s.last_applied = Some(chrono::Utc::now().to_rfc3339());
+        s.last_error = result.as_ref().err().map(|e| e.to_string());
+    });
+    match result {
+        Ok(()) => {
+            info!(
+                "Traffic shaping applied ({} down / {} up Mbit/s, {} devices)",
+                next.config.download_mbit,
+                next.config.upload_mbit,
+                next.devices.len()
+            );
+            *applied = Some(next);
+        }
+        // Retried at the next poll
+        Err(e) => warn!("Failed to apply traffic shaping: {}", e),
+    }
+}
+
+async fn setup(applied: &Applied) -> Result<()> {
+    let config = &applied.config;
+    if applied.lan.is_empty() {
+        anyhow::bail!("No LAN interface (set one or bind the DHCP server to an interface)");
+    }
+    shaper::apply_tc(&shaper::tc_script(config, &config.wan_interface, Direction::Upload, &applied.devices)).await?;
+    shaper::apply_tc(&shaper::tc_script(config, &applied.lan, Direction::Download, &applied.devices)).await?;
+    shaper::apply_nft(&shaper::nft_script(&config.wan_interface, &applied.lan, Some(&applied.devices))).await
+}
+
+async fn teardown(wan: &str, lan: &str) -> Result<()> {
+    shaper::apply_nft(&shaper::nft_script(wan, lan, None)).await?;
+    shaper::apply_tc(&shaper::tc_teardown(wan)).await?;
+    if !lan.is_empty() {
+        shaper::apply_tc(&shaper::tc_teardown(lan)).await?;
+    }
+    Ok(())
+}
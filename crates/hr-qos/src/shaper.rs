@@ -0,0 +1,228 @@
+//! tc and nftables scripts of the shaper. Each direction gets an HTB tree
+//! on the egress of its interface (upload: WAN, download: LAN) with three
+//! priority classes and one class per capped device, CAKE or fq_codel in
+//! the leaves. Devices are steered by a firewall mark set in the forward
+//! hook (upload by source MAC, download by the IPv4 address of their
+//! lease) and matched by `fw` filters; unmarked traffic is `normal`.
+
+use std::fmt::Write;
+use std::net::Ipv4Addr;
+use std::process::Stdio;
+
+use anyhow::{Context, Result, bail};
+use tokio::io::AsyncWriteExt;
+
+use crate::config::{DeviceRule, Priority, QosConfig, Qdisc};
+
+pub const TABLE: &str = "homeroute_qos";
+
+/// Only the low 16 bits of the mark are ours (Tailscale and others use the
+/// high ones).
+const MARK_MASK: u32 = 0xffff;
+
+const CLASS_HIGH: u32 = 0x10;
+const CLASS_NORMAL: u32 = 0x20;
+const CLASS_BULK: u32 = 0x30;
+const CLASS_DEVICE: u32 = 0x100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+/// A device rule with the address of its current lease.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedDevice {
+    pub rule: DeviceRule,
+    pub ip: Option<Ipv4Addr>,
+}
+
+impl ResolvedDevice {
+    fn cap(&self, direction: Direction) -> Option<u32> {
+        match direction {
+            Direction::Upload => self.rule.upload_mbit,
+            Direction::Download => self.rule.download_mbit,
+        }
+    }
+}
+
+fn priority_class(priority: Priority) -> u32 {
+    match priority {
+        Priority::High => CLASS_HIGH,
+        Priority::Normal => CLASS_NORMAL,
+        Priority::Bulk => CLASS_BULK,
+    }
+}
+
+fn htb_prio(priority: Priority) -> u32 {
+    match priority {
+        Priority::High => 0,
+        Priority::Normal => 1,
+        Priority::Bulk => 2,
+    }
+}
+
+/// Class (and mark) of the device at `index`, if it is not plain `normal`.
+fn device_class(index: usize, device: &ResolvedDevice, direction: Direction) -> Option<u32> {
+    if device.cap(direction).is_some() {
+        Some(CLASS_DEVICE + index as u32)
+    } else if device.rule.priority != Priority::Normal {
+        Some(priority_class(device.rule.priority))
+    } else {
+        None
+    }
+}
+
+/// `tc -batch` lines replacing the root qdisc of `interface`.
+pub fn tc_script(config: &QosConfig, interface: &str, direction: Direction, devices: &[ResolvedDevice]) -> String {
+    let link = match direction {
+        Direction::Upload => config.upload_mbit,
+        Direction::Download => config.download_mbit,
+    } as u64
+        * 1000;
+    let mut out = String::new();
+    let _ = writeln!(out, "qdisc del dev {interface} root");
+    let _ = writeln!(out, "qdisc add dev {interface} root handle 1: htb default {CLASS_NORMAL:x}");
+    let _ = writeln!(out, "class add dev {interface} parent 1: classid 1:1 htb rate {link}kbit ceil {link}kbit");
+
+    // Guaranteed shares (the rest is borrowed by priority): 40/40/10 %, the
+    // last 10 % split between the capped devices
+    let mut classes = vec![
+        (CLASS_HIGH, link * 4 / 10, link, 0),
+        (CLASS_NORMAL, link * 4 / 10, link, 1),
+        (CLASS_BULK, link / 10, link, 2),
+    ];
+    let capped: Vec<(usize, &ResolvedDevice)> =
+        devices.iter().enumerate().filter(|(_, d)| d.cap(direction).is_some()).collect();
+    for (index, device) in &capped {
+        let ceil = (device.cap(direction).unwrap_or_default() as u64 * 1000).min(link);
+        let rate = (link / 10 / capped.len() as u64).clamp(1, ceil);
+        classes.push((CLASS_DEVICE + *index as u32, rate, ceil, htb_prio(device.rule.priority)));
+    }
+    for (class, rate, ceil, prio) in &classes {
+        let _ = writeln!(
+            out,
+            "class add dev {interface} parent 1:1 classid 1:{class:x} htb rate {rate}kbit ceil {ceil}kbit prio {prio}"
+        );
+        let leaf = match config.qdisc {
+            Qdisc::Cake if direction == Direction::Upload => "cake unlimited besteffort nat",
+            Qdisc::Cake => "cake unlimited besteffort",
+            Qdisc::FqCodel => "fq_codel",
+        };
+        let _ = writeln!(out, "qdisc add dev {interface} parent 1:{class:x} handle {class:x}: {leaf}");
+        if *class != CLASS_NORMAL {
+            let _ = writeln!(
+                out,
+                "filter add dev {interface} parent 1: protocol all prio 1 handle 0x{class:x}/0x{MARK_MASK:x} fw classid 1:{class:x}"
+            );
+        }
+    }
+    out
+}
+
+/// `tc -batch` lines removing the shaper from `interface`.
+pub fn tc_teardown(interface: &str) -> String {
+    format!("qdisc del dev {interface} root\n")
+}
+
+/// Script replacing the marking table; without shaping it only removes it.
+pub fn nft_script(wan: &str, lan: &str, devices: Option<&[ResolvedDevice]>) -> String {
+    // Declaring the table first makes the delete valid when it does not exist yet
+    let mut out = format!("table inet {TABLE}\ndelete table inet {TABLE}\n");
+    let Some(devices) = devices else {
+        return out;
+    };
+    let _ = writeln!(out, "table inet {TABLE} {{");
+    let _ = writeln!(out, "  chain forward {{ type filter hook forward priority mangle; policy accept;");
+    let set_mark = |class: u32| format!("meta mark set meta mark and 0x{:x} or 0x{class:x}", !MARK_MASK);
+    for (index, device) in devices.iter().enumerate() {
+        if let Some(class) = device_class(index, device, Direction::Upload) {
+            let _ = writeln!(out, "    oifname \"{wan}\" ether saddr {} {}", device.rule.mac, set_mark(class));
+        }
+        if let (Some(class), Some(ip)) = (device_class(index, device, Direction::Download), device.ip) {
+            let _ = writeln!(out, "    oifname \"{lan}\" ip daddr {ip} {}", set_mark(class));
+        }
+    }
+    out.push_str("  }\n}\n");
+    out
+}
+
+async fn run_with_stdin(program: &str, args: &[&str], script: &str) -> Result<()> {
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("{program} not found"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        bail!("{program}: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Run `tc -force -batch -`: the deletion of a missing root qdisc fails
+/// without stopping the rest.
+pub async fn apply_tc(script: &str) -> Result<()> {
+    run_with_stdin("tc", &["-force", "-batch", "-"], script).await
+}
+
+/// Run `nft -f -` (one atomic transaction).
+pub async fn apply_nft(script: &str) -> Result<()> {
+    run_with_stdin("nft", &["-f", "-"], script).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn devices() -> Vec<ResolvedDevice> {
+        let rule = |mac: &str, priority, download_mbit| DeviceRule {
+            mac: mac.to_string(),
+            name: String::new(),
+            priority,
+            download_mbit,
+            upload_mbit: None,
+        };
+        vec![
+            ResolvedDevice { rule: rule("aa:bb:cc:dd:ee:01", Priority::Bulk, Some(25)), ip: Some(Ipv4Addr::new(192, 168, 1, 20)) },
+            ResolvedDevice { rule: rule("aa:bb:cc:dd:ee:02", Priority::High, None), ip: None },
+            ResolvedDevice { rule: rule("aa:bb:cc:dd:ee:03", Priority::Normal, None), ip: Some(Ipv4Addr::new(192, 168, 1, 30)) },
+        ]
+    }
+
+    #[test]
+    fn test_tc_script() {
+        let config = QosConfig { download_mbit: 500, upload_mbit: 50, ..Default::default() };
+        let download = tc_script(&config, "br-lan", Direction::Download, &devices());
+        assert!(download.starts_with("qdisc del dev br-lan root\nqdisc add dev br-lan root handle 1: htb default 20\n"));
+        assert!(download.contains("classid 1:1 htb rate 500000kbit ceil 500000kbit"));
+        // The TV: 25 Mbit/s at most, bulk priority
+        assert!(download.contains("classid 1:100 htb rate 25000kbit ceil 25000kbit prio 2"));
+        assert!(download.contains("parent 1:100 handle 100: cake unlimited besteffort\n"));
+        assert!(download.contains("handle 0x100/0xffff fw classid 1:100"));
+        assert!(!download.contains("fw classid 1:20"));
+
+        // No upload cap: no device class
+        let upload = tc_script(&config, "eth0", Direction::Upload, &devices());
+        assert!(!upload.contains("1:100"));
+        assert!(upload.contains("cake unlimited besteffort nat"));
+    }
+
+    #[test]
+    fn test_nft_script() {
+        let script = nft_script("eth0", "br-lan", Some(&devices()));
+        // Uploads by MAC: the TV in bulk, the laptop in high, the last one untouched
+        assert!(script.contains("oifname \"eth0\" ether saddr aa:bb:cc:dd:ee:01 meta mark set meta mark and 0xffff0000 or 0x30"));
+        assert!(script.contains("oifname \"eth0\" ether saddr aa:bb:cc:dd:ee:02 meta mark set meta mark and 0xffff0000 or 0x10"));
+        assert!(script.contains("oifname \"br-lan\" ip daddr 192.168.1.20 meta mark set meta mark and 0xffff0000 or 0x100"));
+        // No lease: no download rule
+        assert!(!script.contains("ee:03") && !script.contains("192.168.1.30"));
+        assert_eq!(nft_script("eth0", "br-lan", None), "table inet homeroute_qos\ndelete table inet homeroute_qos\n");
+    }
+}
@@ -0,0 +1,296 @@
+//! Message queues for the apps, so they don't each ship a Redis: SQLite
+//! backed, at-least-once delivery with visibility timeouts, dead letters
+//! after too many deliveries. Each app has its own namespace (its slug) and
+//! uses them over its agent connection or `/api/queues` with its token.
+
+pub mod monitor;
+pub mod store;
+
+pub use store::{DeadLetter, Delivery, QueueSettings, QueueStats, QueueStore};
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use hr_registry::protocol::QueueRequest;
+use serde_json::{Value, json};
+use tokio::sync::Notify;
+
+/// Largest message body, in bytes.
+pub const MAX_BODY: usize = 256 * 1024;
+/// Queues per app.
+pub const MAX_QUEUES_PER_APP: u32 = 50;
+/// Messages per receive.
+pub const MAX_RECEIVE: u32 = 10;
+/// Longest receive wait (long polling).
+pub const MAX_WAIT_SECS: u32 = 20;
+/// Longest send or nack delay: 15 minutes.
+pub const MAX_DELAY_SECS: u32 = 900;
+/// Longest visibility timeout: 12 hours.
+pub const MAX_VISIBILITY_TIMEOUT_SECS: u32 = 43_200;
+
+/// How often a waiting receive looks again for messages whose delay or
+/// visibility timeout expired.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueueError {
+    InvalidName,
+    Invalid(String),
+    TooLarge,
+    TooManyQueues,
+    /// Unknown or expired receipt.
+    ReceiptNotFound,
+    Storage(String),
+}
+
+impl std::fmt::Display for QueueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueueError::InvalidName => write!(f, "invalid queue name (1-80 characters: a-z, A-Z, 0-9, '.', '_', '-')"),
+            QueueError::Invalid(e) => write!(f, "{}", e),
+            QueueError::TooLarge => write!(f, "message body larger than {} bytes", MAX_BODY),
+            QueueError::TooManyQueues => write!(f, "at most {} queues per app", MAX_QUEUES_PER_APP),
+            QueueError::ReceiptNotFound => write!(f, "receipt not found (acked, or redelivered since)"),
+            QueueError::Storage(e) => write!(f, "queue storage error: {}", e),
+        }
+    }
+}
+
+impl From<anyhow::Error> for QueueError {
+    fn from(e: anyhow::Error) -> Self {
+        QueueError::Storage(e.to_string())
+    }
+}
+
+pub fn valid_queue_name(name: &str) -> bool {
+    (1..=80).contains(&name.len())
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'))
+}
+
+/// Shared queue handle: the API and the agent connections run operations,
+/// the supervised `queues` service publishes depths and purges dead letters.
+pub struct Queues {
+    store: Arc<QueueStore>,
+    /// Wakes the receives waiting for a message.
+    sent: Notify,
+}
+
+pub type SharedQueues = Arc<Queues>;
+
+impl Queues {
+    pub fn new(store: QueueStore) -> Self {
+        Self { store: Arc::new(store), sent: Notify::new() }
+    }
+
+    pub fn store(&self) -> Arc<QueueStore> {
+        self.store.clone()
+    }
+
+    /// Run an operation for `app`; the result is the JSON returned to it.
+    pub async fn handle(&self, app: &str, request: QueueRequest) -> Result<Value, QueueError> {
+        match request {
+            QueueRequest::Send { queue, body, delay_secs } => {
+                let id = self.send(app, &queue, body, delay_secs).await?;
+                Ok(json!({"id": id}))
+            }
+            QueueRequest::Receive { queue, max, visibility_timeout_secs, wait_secs } => {
+                let messages = self.receive(app, &queue, max, visibility_timeout_secs, wait_secs).await?;
+                Ok(json!({"messages": messages}))
+            }
+            QueueRequest::Ack { queue, receipt } => {
+                let (app, queue) = (app.to_string(), checked(queue)?);
+                self.with_receipt(move |store| store.ack(&app, &queue, &receipt)).await
+            }
+            QueueRequest::Nack { queue, receipt, delay_secs, error } => {
+                let (app, queue) = (app.to_string(), checked(queue)?);
+                let visible_at = now() + delay_ms(delay_secs)?;
+                let acked = self
+                    .with_receipt(move |store| store.nack(&app, &queue, &receipt, visible_at, error.as_deref()))
+                    .await;
+                self.sent.notify_waiters();
+                acked
+            }
+            QueueRequest::Extend { queue, receipt, visibility_timeout_secs } => {
+                let (app, queue) = (app.to_string(), checked(queue)?);
+                let visible_at = now() + visibility_ms(visibility_timeout_secs)?;
+                self.with_receipt(move |store| store.extend(&app, &queue, &receipt, visible_at)).await
+            }
+        }
+    }
+
+    async fn send(&self, app: &str, queue: &str, body: String, delay_secs: u32) -> Result<i64, QueueError> {
+        if !valid_queue_name(queue) {
+            return Err(QueueError::InvalidName);
+        }
+        if body.len() > MAX_BODY {
+            return Err(QueueError::TooLarge);
+        }
+        let now = now();
+        let visible_at = now + delay_ms(delay_secs)?;
+        let (app, queue) = (app.to_string(), queue.to_string());
+        let id = self
+            .blocking(move |store| {
+                if !store.ensure_queue(&app, &queue, MAX_QUEUES_PER_APP, now)? {
+                    return Ok(None);
+                }
+                let id = store.send(&app, &queue, &body, now, visible_at)?;
+                hr_common::metrics::registry()
+                    .counter("homeroute_queue_sent_total", "Messages sent to app queues, by app.", &[("app", &app)])
+                    .inc();
+                Ok(Some(id))
+            })
+            .await?
+            .ok_or(QueueError::TooManyQueues)?;
+        self.sent.notify_waiters();
+        Ok(id)
+    }
+
+    async fn receive(
+        &self,
+        app: &str,
+        queue: &str,
+        max: u32,
+        visibility_timeout_secs: Option<u32>,
+        wait_secs: u32,
+    ) -> Result<Vec<Delivery>, QueueError> {
+        if !valid_queue_name(queue) {
+            return Err(QueueError::InvalidName);
+        }
+        if max == 0 || max > MAX_RECEIVE {
+            return Err(QueueError::Invalid(format!("max must be 1-{}", MAX_RECEIVE)));
+        }
+        if wait_secs > MAX_WAIT_SECS {
+            return Err(QueueError::Invalid(format!("wait_secs must be at most {}", MAX_WAIT_SECS)));
+        }
+        let visibility = visibility_timeout_secs.map(visibility_ms).transpose()?;
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(wait_secs as u64);
+        loop {
+            // Registered before looking, so a send in between still wakes us
+            let sent = self.sent.notified();
+            let (app, queue) = (app.to_string(), queue.to_string());
+            let messages = self
+                .blocking(move |store| {
+                    let settings = store.settings(&app, &queue)?.unwrap_or_default();
+                    let visibility = visibility.unwrap_or(settings.visibility_timeout_secs as i64 * 1000);
+                    let (messages, dead) =
+                        store.receive(&app, &queue, max, visibility, settings.max_attempts, now())?;
+                    if dead > 0 {
+                        hr_common::metrics::registry()
+                            .counter(
+                                "homeroute_queue_dead_lettered_total",
+                                "Messages dead-lettered after too many deliveries, by app.",
+                                &[("app", &app)],
+                            )
+                            .add(dead as u64);
+                    }
+                    Ok(messages)
+                })
+                .await?;
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            if !messages.is_empty() || remaining.is_zero() {
+                return Ok(messages);
+            }
+            tokio::select! {
+                _ = sent => {}
+                _ = tokio::time::sleep(remaining.min(POLL_INTERVAL)) => {}
+            }
+        }
+    }
+
+    /// Run a receipt operation; unknown receipts are an error.
+    async fn with_receipt(
+        &self,
+        f: impl FnOnce(&QueueStore) -> anyhow::Result<bool> + Send + 'static,
+    ) -> Result<Value, QueueError> {
+        match self.blocking(f).await? {
+            true => Ok(json!({})),
+            false => Err(QueueError::ReceiptNotFound),
+        }
+    }
+
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&QueueStore) -> anyhow::Result<T> + Send + 'static,
+    ) -> Result<T, QueueError> {
+        let store = self.store.clone();
+        match tokio::task::spawn_blocking(move || f(&store)).await {
+            Ok(result) => Ok(result?),
+            Err(e) => Err(QueueError::Storage(e.to_string())),
+        }
+    }
+}
+
+fn checked(queue: String) -> Result<String, QueueError> {
+    if valid_queue_name(&queue) { Ok(queue) } else { Err(QueueError::InvalidName) }
+}
+
+fn delay_ms(delay_secs: u32) -> Result<i64, QueueError> {
+    if delay_secs > MAX_DELAY_SECS {
+        return Err(QueueError::Invalid(format!("delay_secs must be at most {}", MAX_DELAY_SECS)));
+    }
+    Ok(delay_secs as i64 * 1000)
+}
+
+fn visibility_ms(secs: u32) -> Result<i64, QueueError> {
+    if secs == 0 || secs > MAX_VISIBILITY_TIMEOUT_SECS {
+        return Err(QueueError::Invalid(format!(
+            "visibility_timeout_secs must be 1-{}",
+            MAX_VISIBILITY_TIMEOUT_SECS
+        )));
+    }
+    Ok(secs as i64 * 1000)
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(value: Value) -> QueueRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_long_poll_and_ack() {
+        let queues = Arc::new(Queues::new(QueueStore::open_in_memory().unwrap()));
+        let waiting = {
+            let queues = queues.clone();
+            tokio::spawn(async move {
+                queues.handle("app", request(json!({"op": "receive", "queue": "jobs", "wait_secs": 5}))).await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let sent = queues.handle("app", request(json!({"op": "send", "queue": "jobs", "body": "hello"}))).await;
+        assert!(sent.unwrap()["id"].is_i64());
+
+        let received = waiting.await.unwrap().unwrap();
+        let message = &received["messages"][0];
+        assert_eq!(message["body"], "hello");
+        let receipt = message["receipt"].as_str().unwrap();
+
+        let ack = json!({"op": "ack", "queue": "jobs", "receipt": receipt});
+        assert!(queues.handle("app", request(ack.clone())).await.is_ok());
+        assert_eq!(queues.handle("app", request(ack)).await, Err(QueueError::ReceiptNotFound));
+        // Nothing left: returns empty once the wait is over
+        let empty = queues.handle("app", request(json!({"op": "receive", "queue": "jobs"}))).await.unwrap();
+        assert_eq!(empty["messages"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_validation() {
+        let queues = Queues::new(QueueStore::open_in_memory().unwrap());
+        let send = |queue: &str, body: String| request(json!({"op": "send", "queue": queue, "body": body}));
+        assert_eq!(queues.handle("app", send("bad name", String::new())).await, Err(QueueError::InvalidName));
+        assert_eq!(queues.handle("app", send("q", "x".repeat(MAX_BODY + 1))).await, Err(QueueError::TooLarge));
+        let too_many = request(json!({"op": "receive", "queue": "q", "max": MAX_RECEIVE + 1}));
+        assert!(matches!(queues.handle("app", too_many).await, Err(QueueError::Invalid(_))));
+        for i in 0..MAX_QUEUES_PER_APP {
+            queues.handle("app", send(&format!("q{}", i), String::new())).await.unwrap();
+        }
+        assert_eq!(queues.handle("app", send("one-more", String::new())).await, Err(QueueError::TooManyQueues));
+        assert!(queues.handle("other-app", send("one-more", String::new())).await.is_ok());
+    }
+}
@@ -0,0 +1,73 @@
+//! The `queues` service: publishes the depth of every queue as gauges and
+//! drops the dead letters past their retention.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::{info, warn};
+
+use crate::SharedQueues;
+
+const INTERVAL: Duration = Duration::from_secs(15);
+
+/// Dead letters are kept 14 days.
+const DEAD_LETTER_RETENTION_MS: i64 = 14 * 24 * 3600 * 1000;
+
+pub async fn run_queues(queues: SharedQueues) -> Result<()> {
+    let metrics = hr_common::metrics::registry();
+    // Queues published last time, zeroed once deleted
+    let mut published: HashSet<(String, String)> = HashSet::new();
+    let mut interval = tokio::time::interval(INTERVAL);
+    loop {
+        interval.tick().await;
+        let store = queues.store();
+        let now = chrono::Utc::now().timestamp_millis();
+        let swept = tokio::task::spawn_blocking(move || {
+            let purged = store.purge_dead(now - DEAD_LETTER_RETENTION_MS)?;
+            Ok::<_, anyhow::Error>((purged, store.stats(None, now)?))
+        })
+        .await?;
+        let (purged, stats) = match swept {
+            Ok(swept) => swept,
+            Err(e) => {
+                warn!("Failed to read queue depths: {}", e);
+                continue;
+            }
+        };
+        if purged > 0 {
+            info!("Purged {} expired dead letters", purged);
+        }
+
+        let mut current = HashSet::new();
+        for s in &stats {
+            for (state, value) in [
+                ("available", s.available),
+                ("in_flight", s.in_flight),
+                ("delayed", s.delayed),
+                ("dead", s.dead),
+            ] {
+                metrics
+                    .gauge(
+                        "homeroute_queue_messages",
+                        "Messages in app queues, by app, queue and state.",
+                        &[("app", &s.app), ("queue", &s.queue), ("state", state)],
+                    )
+                    .set(value as f64);
+            }
+            current.insert((s.app.clone(), s.queue.clone()));
+        }
+        for (app, queue) in published.difference(&current) {
+            for state in ["available", "in_flight", "delayed", "dead"] {
+                metrics
+                    .gauge(
+                        "homeroute_queue_messages",
+                        "Messages in app queues, by app, queue and state.",
+                        &[("app", app), ("queue", queue), ("state", state)],
+                    )
+                    .set(0.0);
+            }
+        }
+        published = current;
+    }
+}
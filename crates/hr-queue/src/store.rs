@@ -0,0 +1,416 @@
+//! SQLite queues (`queue.db`). A received message stays hidden until its
+//! visibility timeout expires, then is delivered again unless acked with
+//! the receipt of its last delivery; past `max_attempts` deliveries it is
+//! dead-lettered.
+
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+
+pub const STATE_READY: &str = "ready";
+pub const STATE_DEAD: &str = "dead";
+
+fn default_visibility_timeout() -> u32 {
+    30
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+/// Per-queue settings; queues are created with the defaults on first use.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueSettings {
+    /// How long a received message stays hidden before redelivery.
+    #[serde(default = "default_visibility_timeout")]
+    pub visibility_timeout_secs: u32,
+    /// Deliveries before the message is dead-lettered.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+}
+
+impl Default for QueueSettings {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl QueueSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.visibility_timeout_secs == 0 || self.visibility_timeout_secs > crate::MAX_VISIBILITY_TIMEOUT_SECS {
+            return Err(format!(
+                "Visibility timeout must be 1-{} seconds",
+                crate::MAX_VISIBILITY_TIMEOUT_SECS
+            ));
+        }
+        if self.max_attempts == 0 || self.max_attempts > 100 {
+            return Err("Max attempts must be 1-100".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A message handed to a consumer.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Delivery {
+    pub id: i64,
+    /// Needed to ack, nack or extend this delivery.
+    pub receipt: String,
+    pub body: String,
+    /// Deliveries so far, this one included.
+    pub attempts: u32,
+    pub enqueued_at: i64,
+}
+
+/// A dead-lettered message.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetter {
+    pub id: i64,
+    pub body: String,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub enqueued_at: i64,
+    pub dead_at: i64,
+}
+
+/// Depth of a queue.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueStats {
+    pub app: String,
+    pub queue: String,
+    #[serde(flatten)]
+    pub settings: QueueSettings,
+    /// Ready to be received.
+    pub available: u64,
+    /// Received, not acked yet.
+    pub in_flight: u64,
+    /// Sent or nacked with a delay.
+    pub delayed: u64,
+    pub dead: u64,
+}
+
+pub struct QueueStore {
+    conn: Mutex<Connection>,
+}
+
+impl QueueStore {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS queues (
+                app TEXT NOT NULL,
+                name TEXT NOT NULL,
+                visibility_timeout_secs INTEGER NOT NULL,
+                max_attempts INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (app, name)
+            );
+            CREATE TABLE IF NOT EXISTS messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                app TEXT NOT NULL,
+                queue TEXT NOT NULL,
+                body TEXT NOT NULL,
+                state TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                visible_at INTEGER NOT NULL,
+                receipt TEXT,
+                last_error TEXT,
+                enqueued_at INTEGER NOT NULL,
+                dead_at INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS idx_messages_queue ON messages(app, queue, state, visible_at);
+            CREATE INDEX IF NOT EXISTS idx_messages_receipt ON messages(receipt);",
+        )?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    /// Settings of a queue, `None` when it does not exist.
+    pub fn settings(&self, app: &str, queue: &str) -> anyhow::Result<Option<QueueSettings>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn
+            .query_row(
+                "SELECT visibility_timeout_secs, max_attempts FROM queues WHERE app = ?1 AND name = ?2",
+                params![app, queue],
+                |row| Ok(QueueSettings { visibility_timeout_secs: row.get(0)?, max_attempts: row.get(1)? }),
+            )
+            .optional()?)
+    }
+
+    /// Create the queue if needed; false when the app already has
+    /// `max_queues` others.
+    pub fn ensure_queue(&self, app: &str, queue: &str, max_queues: u32, now: i64) -> anyhow::Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let (exists, count): (bool, u32) = conn.query_row(
+            "SELECT COALESCE(SUM(name = ?2), 0) > 0, COUNT(*) FROM queues WHERE app = ?1",
+            params![app, queue],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        if exists {
+            return Ok(true);
+        }
+        if count >= max_queues {
+            return Ok(false);
+        }
+        let defaults = QueueSettings::default();
+        conn.execute(
+            "INSERT INTO queues (app, name, visibility_timeout_secs, max_attempts, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![app, queue, defaults.visibility_timeout_secs, defaults.max_attempts, now],
+        )?;
+        Ok(true)
+    }
+
+    /// Change the settings of an existing queue.
+    pub fn set_settings(&self, app: &str, queue: &str, settings: &QueueSettings) -> anyhow::Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute(
+            "UPDATE queues SET visibility_timeout_secs = ?3, max_attempts = ?4 WHERE app = ?1 AND name = ?2",
+            params![app, queue, settings.visibility_timeout_secs, settings.max_attempts],
+        )? > 0)
+    }
+
+    /// Delete a queue and all its messages.
+    pub fn delete_queue(&self, app: &str, queue: &str) -> anyhow::Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM messages WHERE app = ?1 AND queue = ?2", params![app, queue])?;
+        Ok(conn.execute("DELETE FROM queues WHERE app = ?1 AND name = ?2", params![app, queue])? > 0)
+    }
+
+    /// Enqueue a message, visible from `visible_at`.
+    pub fn send(&self, app: &str, queue: &str, body: &str, now: i64, visible_at: i64) -> anyhow::Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row(
+            "INSERT INTO messages (app, queue, body, state, visible_at, enqueued_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6) RETURNING id",
+            params![app, queue, body, STATE_READY, visible_at, now],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Deliver up to `max` visible messages, hiding them for `visibility`
+    /// ms. Messages delivered `max_attempts` times already are
+    /// dead-lettered instead; their count is returned alongside.
+    pub fn receive(
+        &self,
+        app: &str,
+        queue: &str,
+        max: u32,
+        visibility: i64,
+        max_attempts: u32,
+        now: i64,
+    ) -> anyhow::Result<(Vec<Delivery>, usize)> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let dead = tx.execute(
+            "UPDATE messages SET state = ?4, dead_at = ?5, receipt = NULL,
+                last_error = COALESCE(last_error, 'Not acked after ' || attempts || ' deliveries')
+             WHERE app = ?1 AND queue = ?2 AND state = ?3 AND visible_at <= ?5 AND attempts >= ?6",
+            params![app, queue, STATE_READY, STATE_DEAD, now, max_attempts],
+        )?;
+        let candidates: Vec<(i64, String, u32, i64)> = {
+            let mut stmt = tx.prepare(
+                "SELECT id, body, attempts, enqueued_at FROM messages
+                 WHERE app = ?1 AND queue = ?2 AND state = ?3 AND visible_at <= ?4
+                 ORDER BY visible_at, id LIMIT ?5",
+            )?;
+            stmt.query_map(params![app, queue, STATE_READY, now, max], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })?
+            .collect::<Result<_, _>>()?
+        };
+        let mut deliveries = Vec::with_capacity(candidates.len());
+        for (id, body, attempts, enqueued_at) in candidates {
+            let receipt = uuid::Uuid::new_v4().to_string();
+            tx.execute(
+                "UPDATE messages SET attempts = attempts + 1, visible_at = ?2, receipt = ?3 WHERE id = ?1",
+                params![id, now + visibility, receipt],
+            )?;
+            deliveries.push(Delivery { id, receipt, body, attempts: attempts + 1, enqueued_at });
+        }
+        tx.commit()?;
+        Ok((deliveries, dead))
+    }
+
+    /// Delete the message of a delivery.
+    pub fn ack(&self, app: &str, queue: &str, receipt: &str) -> anyhow::Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute(
+            "DELETE FROM messages WHERE receipt = ?3 AND app = ?1 AND queue = ?2 AND state = ?4",
+            params![app, queue, receipt, STATE_READY],
+        )? > 0)
+    }
+
+    /// Give a delivery back, visible again from `visible_at`.
+    pub fn nack(
+        &self,
+        app: &str,
+        queue: &str,
+        receipt: &str,
+        visible_at: i64,
+        error: Option<&str>,
+    ) -> anyhow::Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute(
+            "UPDATE messages SET visible_at = ?4, receipt = NULL, last_error = COALESCE(?5, last_error)
+             WHERE receipt = ?3 AND app = ?1 AND queue = ?2 AND state = ?6",
+            params![app, queue, receipt, visible_at, error, STATE_READY],
+        )? > 0)
+    }
+
+    /// Keep a delivery hidden until `visible_at` (long-running work).
+    pub fn extend(&self, app: &str, queue: &str, receipt: &str, visible_at: i64) -> anyhow::Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute(
+            "UPDATE messages SET visible_at = ?4 WHERE receipt = ?3 AND app = ?1 AND queue = ?2 AND state = ?5",
+            params![app, queue, receipt, visible_at, STATE_READY],
+        )? > 0)
+    }
+
+    /// Depth of every queue (or of one app's).
+    pub fn stats(&self, app: Option<&str>, now: i64) -> anyhow::Result<Vec<QueueStats>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT q.app, q.name, q.visibility_timeout_secs, q.max_attempts,
+                COALESCE(SUM(m.state = ?2 AND m.visible_at <= ?3), 0),
+                COALESCE(SUM(m.state = ?2 AND m.visible_at > ?3 AND m.receipt IS NOT NULL), 0),
+                COALESCE(SUM(m.state = ?2 AND m.visible_at > ?3 AND m.receipt IS NULL), 0),
+                COALESCE(SUM(m.state = ?4), 0)
+             FROM queues q LEFT JOIN messages m ON m.app = q.app AND m.queue = q.name
+             WHERE ?1 IS NULL OR q.app = ?1
+             GROUP BY q.app, q.name ORDER BY q.app, q.name",
+        )?;
+        let stats = stmt
+            .query_map(params![app, STATE_READY, now, STATE_DEAD], |row| {
+                Ok(QueueStats {
+                    app: row.get(0)?,
+                    queue: row.get(1)?,
+                    settings: QueueSettings { visibility_timeout_secs: row.get(2)?, max_attempts: row.get(3)? },
+                    available: row.get(4)?,
+                    in_flight: row.get(5)?,
+                    delayed: row.get(6)?,
+                    dead: row.get(7)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(stats)
+    }
+
+    /// Dead letters of a queue, newest first.
+    pub fn dead_letters(&self, app: &str, queue: &str, limit: u32) -> anyhow::Result<Vec<DeadLetter>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, body, attempts, last_error, enqueued_at, dead_at FROM messages
+             WHERE app = ?1 AND queue = ?2 AND state = ?3 ORDER BY dead_at DESC, id DESC LIMIT ?4",
+        )?;
+        let letters = stmt
+            .query_map(params![app, queue, STATE_DEAD, limit], |row| {
+                Ok(DeadLetter {
+                    id: row.get(0)?,
+                    body: row.get(1)?,
+                    attempts: row.get(2)?,
+                    last_error: row.get(3)?,
+                    enqueued_at: row.get(4)?,
+                    dead_at: row.get(5)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(letters)
+    }
+
+    /// Put the dead letters of a queue back, with their attempts reset.
+    pub fn redrive(&self, app: &str, queue: &str, now: i64) -> anyhow::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute(
+            "UPDATE messages SET state = ?3, attempts = 0, visible_at = ?5, dead_at = NULL
+             WHERE app = ?1 AND queue = ?2 AND state = ?4",
+            params![app, queue, STATE_READY, STATE_DEAD, now],
+        )?)
+    }
+
+    /// Drop the dead letters older than `before`.
+    pub fn purge_dead(&self, before: i64) -> anyhow::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM messages WHERE state = ?1 AND dead_at < ?2", params![STATE_DEAD, before])?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delivery_cycle() {
+        let store = QueueStore::open_in_memory().unwrap();
+        assert!(store.ensure_queue("app", "jobs", 1, 0).unwrap());
+        assert!(store.ensure_queue("app", "jobs", 1, 0).unwrap());
+        assert!(!store.ensure_queue("app", "other", 1, 0).unwrap());
+
+        store.send("app", "jobs", "a", 0, 0).unwrap();
+        store.send("app", "jobs", "b", 0, 0).unwrap();
+        store.send("app", "jobs", "later", 0, 500).unwrap();
+
+        let (batch, dead) = store.receive("app", "jobs", 10, 100, 5, 10).unwrap();
+        assert_eq!((batch.len(), dead), (2, 0));
+        assert_eq!((batch[0].body.as_str(), batch[0].attempts), ("a", 1));
+        // Hidden until the visibility timeout expires
+        assert!(store.receive("app", "jobs", 10, 100, 5, 50).unwrap().0.is_empty());
+        // Namespaced per app
+        assert!(store.receive("other", "jobs", 10, 100, 5, 50).unwrap().0.is_empty());
+
+        assert!(store.ack("app", "jobs", &batch[0].receipt).unwrap());
+        assert!(!store.ack("app", "jobs", &batch[0].receipt).unwrap());
+        assert!(store.nack("app", "jobs", &batch[1].receipt, 60, Some("boom")).unwrap());
+        let stats = &store.stats(None, 50).unwrap()[0];
+        assert_eq!((stats.available, stats.in_flight, stats.delayed, stats.dead), (0, 0, 2, 0));
+
+        let (again, _) = store.receive("app", "jobs", 10, 100, 5, 60).unwrap();
+        assert_eq!((again[0].body.as_str(), again[0].attempts), ("b", 2));
+        assert_ne!(again[0].receipt, batch[1].receipt);
+        // A stale receipt no longer acks
+        assert!(!store.ack("app", "jobs", &batch[1].receipt).unwrap());
+        assert!(store.extend("app", "jobs", &again[0].receipt, 1000).unwrap());
+        assert!(store.receive("app", "jobs", 10, 100, 5, 600).unwrap().0.iter().all(|d| d.body == "later"));
+    }
+
+    #[test]
+    fn test_dead_letters() {
+        let store = QueueStore::open_in_memory().unwrap();
+        store.ensure_queue("app", "jobs", 10, 0).unwrap();
+        store.send("app", "jobs", "poison", 0, 0).unwrap();
+        let mut now = 0;
+        for _ in 0..2 {
+            let (batch, _) = store.receive("app", "jobs", 1, 10, 2, now).unwrap();
+            assert_eq!(batch.len(), 1);
+            now += 20;
+        }
+        let (batch, dead) = store.receive("app", "jobs", 1, 10, 2, now).unwrap();
+        assert_eq!((batch.len(), dead), (0, 1));
+        let letters = store.dead_letters("app", "jobs", 10).unwrap();
+        assert_eq!((letters[0].attempts, letters[0].last_error.as_deref()), (2, Some("Not acked after 2 deliveries")));
+
+        assert_eq!(store.redrive("app", "jobs", now).unwrap(), 1);
+        assert_eq!(store.receive("app", "jobs", 1, 10, 2, now).unwrap().0[0].attempts, 1);
+
+        now += 20;
+        store.receive("app", "jobs", 1, 10, 1, now).unwrap();
+        assert_eq!(store.purge_dead(now + 1).unwrap(), 1);
+        assert!(store.delete_queue("app", "jobs").unwrap());
+        assert!(store.stats(Some("app"), now).unwrap().is_empty());
+    }
+}
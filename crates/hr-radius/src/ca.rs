@@ -0,0 +1,234 @@
+//! RADIUS certificate authority: signs the server certificate presented to
+//! Wi-Fi clients and the per-user client certificates used for EAP-TLS.
+//!
+//! Issued certificates are indexed by SHA-256 fingerprint (`issued.json`),
+//! which maps an authenticated client back to its HomeRoute user and allows
+//! revocation without CRLs.
+
+use anyhow::{Context, Result};
+use rcgen::{
+    CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose,
+    PKCS_ECDSA_P256_SHA256,
+};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const CA_NAME: &str = "HomeRoute RADIUS CA";
+const SERVER_NAME: &str = "homeroute-radius";
+const CA_VALIDITY: Duration = Duration::from_secs(20 * 365 * 24 * 3600);
+const SERVER_VALIDITY: Duration = Duration::from_secs(10 * 365 * 24 * 3600);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IssuedCert {
+    pub fingerprint: String,
+    pub username: String,
+    /// Free label, e.g. the device the certificate is installed on.
+    #[serde(default)]
+    pub label: String,
+    pub issued_at: String,
+    pub expires_at: String,
+    #[serde(default)]
+    pub revoked: bool,
+}
+
+/// A freshly issued client certificate, returned once to the user.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientBundle {
+    pub cert: IssuedCert,
+    pub cert_pem: String,
+    pub key_pem: String,
+    pub ca_pem: String,
+}
+
+pub struct RadiusCa {
+    dir: PathBuf,
+    ca_key: KeyPair,
+    /// Re-signed from `ca_key` with the stored subject: only used as issuer.
+    ca_issuer: rcgen::Certificate,
+    ca_pem: String,
+    ca_der: CertificateDer<'static>,
+    server_chain: Vec<CertificateDer<'static>>,
+    server_key: PrivatePkcs8KeyDer<'static>,
+    issued: Mutex<Vec<IssuedCert>>,
+}
+
+fn ca_params() -> Result<CertificateParams> {
+    let mut params = CertificateParams::new(Vec::<String>::new())?;
+    params.distinguished_name.push(DnType::CommonName, CA_NAME);
+    params.is_ca = IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    params.not_before = time::OffsetDateTime::now_utc();
+    params.not_after = time::OffsetDateTime::now_utc() + CA_VALIDITY;
+    Ok(params)
+}
+
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    std::fs::write(path, content)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+fn first_cert(pem: &str) -> Result<CertificateDer<'static>> {
+    rustls_pemfile::certs(&mut pem.as_bytes())
+        .next()
+        .context("no certificate in PEM")?
+        .context("invalid certificate PEM")
+}
+
+pub fn fingerprint(der: &[u8]) -> String {
+    hex::encode(ring::digest::digest(&ring::digest::SHA256, der))
+}
+
+impl RadiusCa {
+    /// Load the CA from `dir`, creating the CA and server certificate on
+    /// first use.
+    pub fn load_or_create(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("create {:?}", dir))?;
+        let (ca_key_path, ca_cert_path) = (dir.join("ca.key"), dir.join("ca.crt"));
+
+        let (ca_key, ca_pem) = if ca_key_path.exists() {
+            let key = KeyPair::from_pem(&std::fs::read_to_string(&ca_key_path)?)?;
+            (key, std::fs::read_to_string(&ca_cert_path)?)
+        } else {
+            let key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256)?;
+            let cert = ca_params()?.self_signed(&key)?;
+            write_private(&ca_key_path, &key.serialize_pem())?;
+            std::fs::write(&ca_cert_path, cert.pem())?;
+            tracing::info!("Generated RADIUS CA in {:?}", dir);
+            (key, cert.pem())
+        };
+        let ca_issuer = ca_params()?.self_signed(&ca_key)?;
+        let ca_der = first_cert(&ca_pem)?;
+
+        let (server_key_path, server_cert_path) = (dir.join("server.key"), dir.join("server.crt"));
+        if !server_key_path.exists() {
+            let key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256)?;
+            let mut params = CertificateParams::new(vec![SERVER_NAME.to_string()])?;
+            params.distinguished_name.push(DnType::CommonName, SERVER_NAME);
+            params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+            params.not_before = time::OffsetDateTime::now_utc();
+            params.not_after = time::OffsetDateTime::now_utc() + SERVER_VALIDITY;
+            let cert = params.signed_by(&key, &ca_issuer, &ca_key)?;
+            write_private(&server_key_path, &key.serialize_pem())?;
+            std::fs::write(&server_cert_path, cert.pem())?;
+        }
+        let server_key = KeyPair::from_pem(&std::fs::read_to_string(&server_key_path)?)?;
+        let server_chain = vec![first_cert(&std::fs::read_to_string(&server_cert_path)?)?, ca_der.clone()];
+
+        let issued_path = dir.join("issued.json");
+        let issued = if issued_path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&issued_path)?)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            server_key: PrivatePkcs8KeyDer::from(server_key.serialize_der()),
+            ca_key,
+            ca_issuer,
+            ca_pem,
+            ca_der,
+            server_chain,
+            issued: Mutex::new(issued),
+        })
+    }
+
+    /// CA certificate to install on clients (to trust the RADIUS server).
+    pub fn ca_pem(&self) -> &str {
+        &self.ca_pem
+    }
+
+    /// TLS 1.2 server configuration requiring a client certificate from
+    /// this CA. Resumption is off: every session must present its certificate.
+    pub fn tls_config(&self) -> Result<Arc<rustls::ServerConfig>> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(self.ca_der.clone())?;
+        let verifier = rustls::server::WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+            .build()
+            .context("client verifier")?;
+        let mut config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS12])?
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(self.server_chain.clone(), PrivateKeyDer::Pkcs8(self.server_key.clone_key()))?;
+        config.session_storage = Arc::new(rustls::server::NoServerSessionStorage {});
+        config.send_tls13_tickets = 0;
+        Ok(Arc::new(config))
+    }
+
+    /// Issue a client certificate for `username`.
+    pub fn issue_client(&self, username: &str, label: &str, validity_days: u32) -> Result<ClientBundle> {
+        let key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256)?;
+        let mut params = CertificateParams::new(Vec::<String>::new())?;
+        params.distinguished_name.push(DnType::CommonName, username);
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let now = time::OffsetDateTime::now_utc();
+        params.not_before = now;
+        params.not_after = now + Duration::from_secs(validity_days as u64 * 86400);
+        let cert = params.signed_by(&key, &self.ca_issuer, &self.ca_key)?;
+
+        let issued_at = chrono::Utc::now();
+        let record = IssuedCert {
+            fingerprint: fingerprint(cert.der()),
+            username: username.to_string(),
+            label: label.to_string(),
+            issued_at: issued_at.to_rfc3339(),
+            expires_at: (issued_at + chrono::Duration::days(validity_days as i64)).to_rfc3339(),
+            revoked: false,
+        };
+        {
+            let mut issued = self.issued.lock().unwrap();
+            issued.push(record.clone());
+            self.save(&issued)?;
+        }
+        Ok(ClientBundle {
+            cert: record,
+            cert_pem: cert.pem(),
+            key_pem: key.serialize_pem(),
+            ca_pem: self.ca_pem.clone(),
+        })
+    }
+
+    pub fn list(&self) -> Vec<IssuedCert> {
+        self.issued.lock().unwrap().clone()
+    }
+
+    /// Mark a certificate revoked. `false` if unknown.
+    pub fn revoke(&self, fingerprint: &str) -> Result<bool> {
+        let mut issued = self.issued.lock().unwrap();
+        let Some(cert) = issued.iter_mut().find(|c| c.fingerprint == fingerprint) else {
+            return Ok(false);
+        };
+        cert.revoked = true;
+        self.save(&issued)?;
+        Ok(true)
+    }
+
+    /// Valid (issued here, not revoked) certificate with this fingerprint.
+    pub fn lookup(&self, fingerprint: &str) -> Option<IssuedCert> {
+        self.issued
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|c| c.fingerprint == fingerprint && !c.revoked)
+            .cloned()
+    }
+
+    fn save(&self, issued: &[IssuedCert]) -> Result<()> {
+        let path = self.dir.join("issued.json");
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(issued)?)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// RADIUS server configuration (radius-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RadiusConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_bind")]
+    pub bind_address: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Access points and switches allowed to query the server.
+    #[serde(default)]
+    pub clients: Vec<RadiusClient>,
+    /// WPA-Enterprise with per-user certificates (EAP-TLS).
+    #[serde(default = "default_true")]
+    pub eap_tls: bool,
+    /// MAC authentication bypass for devices without 802.1X (MAB).
+    #[serde(default)]
+    pub mac_auth: bool,
+    /// Accept unknown MACs (on `default_vlan`) instead of rejecting them.
+    #[serde(default)]
+    pub allow_unknown_macs: bool,
+    /// Group → VLAN. Applies to users' groups and to device groups.
+    #[serde(default)]
+    pub vlans: BTreeMap<String, u16>,
+    /// VLAN for authenticated clients matching no group (none = AP default).
+    #[serde(default)]
+    pub default_vlan: Option<u16>,
+    /// Device inventory for MAC authentication.
+    #[serde(default)]
+    pub devices: Vec<RadiusDevice>,
+    /// Validity of issued client certificates.
+    #[serde(default = "default_cert_days")]
+    pub cert_validity_days: u32,
+}
+
+/// A NAS (access point, switch) sharing a secret with the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RadiusClient {
+    pub name: String,
+    /// IP or CIDR of the NAS.
+    pub address: String,
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RadiusDevice {
+    pub mac: String,
+    #[serde(default)]
+    pub name: String,
+    /// Group of the device, mapped to a VLAN through `vlans`.
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Explicit VLAN, overrides the group.
+    #[serde(default)]
+    pub vlan: Option<u16>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_bind() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_port() -> u16 {
+    1812
+}
+
+fn default_cert_days() -> u32 {
+    825
+}
+
+impl Default for RadiusConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+/// `aa:bb:cc:dd:ee:ff` from any usual notation (`AA-BB-..`, `aabb.ccdd.eeff`,
+/// `aabbccddeeff`). `None` if not a MAC address.
+pub fn normalize_mac(value: &str) -> Option<String> {
+    let hex: String = value.chars().filter(|c| !matches!(c, ':' | '-' | '.')).collect();
+    if hex.len() != 12 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let hex = hex.to_ascii_lowercase();
+    Some(
+        (0..6)
+            .map(|i| &hex[i * 2..i * 2 + 2])
+            .collect::<Vec<_>>()
+            .join(":"),
+    )
+}
+
+fn valid_vlan(vlan: u16) -> bool {
+    (1..=4094).contains(&vlan)
+}
+
+impl RadiusClient {
+    pub fn matches(&self, ip: IpAddr) -> bool {
+        match self.address.parse::<IpNet>() {
+            Ok(net) => net.contains(&ip),
+            Err(_) => self.address.parse::<IpAddr>().is_ok_and(|a| a == ip),
+        }
+    }
+}
+
+impl RadiusConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bind_address.parse::<IpAddr>().is_err() {
+            return Err(format!("adresse d'écoute invalide: {}", self.bind_address));
+        }
+        if self.port == 0 {
+            return Err("port invalide".to_string());
+        }
+        for client in &self.clients {
+            if client.address.parse::<IpNet>().is_err() && client.address.parse::<IpAddr>().is_err() {
+                return Err(format!("adresse de client invalide: {}", client.address));
+            }
+            if client.secret.len() < 8 {
+                return Err(format!("{}: secret trop court (8 caractères minimum)", client.name));
+            }
+        }
+        for (group, vlan) in &self.vlans {
+            if !valid_vlan(*vlan) {
+                return Err(format!("{}: VLAN invalide ({})", group, vlan));
+            }
+        }
+        if self.default_vlan.is_some_and(|v| !valid_vlan(v)) {
+            return Err("VLAN par défaut invalide".to_string());
+        }
+        let mut macs = HashSet::new();
+        for device in &self.devices {
+            let Some(mac) = normalize_mac(&device.mac) else {
+                return Err(format!("adresse MAC invalide: {}", device.mac));
+            };
+            if !macs.insert(mac) {
+                return Err(format!("adresse MAC en double: {}", device.mac));
+            }
+            if device.vlan.is_some_and(|v| !valid_vlan(v)) {
+                return Err(format!("{}: VLAN invalide", device.mac));
+            }
+        }
+        if self.cert_validity_days == 0 {
+            return Err("durée de validité des certificats invalide".to_string());
+        }
+        Ok(())
+    }
+
+    /// NAS allowed to query from `ip`.
+    pub fn client_for(&self, ip: IpAddr) -> Option<&RadiusClient> {
+        self.clients.iter().find(|c| c.matches(ip))
+    }
+
+    /// VLAN of the first of `groups` mapped to one, else `default_vlan`.
+    pub fn vlan_for_groups<'a>(&self, groups: impl IntoIterator<Item = &'a String>) -> Option<u16> {
+        groups
+            .into_iter()
+            .find_map(|g| self.vlans.get(g).copied())
+            .or(self.default_vlan)
+    }
+
+    pub fn device(&self, mac: &str) -> Option<&RadiusDevice> {
+        self.devices.iter().find(|d| normalize_mac(&d.mac).as_deref() == Some(mac))
+    }
+
+    pub fn device_vlan(&self, device: &RadiusDevice) -> Option<u16> {
+        device.vlan.or_else(|| self.vlan_for_groups(device.group.iter()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_mac() {
+        let expected = Some("aa:bb:cc:dd:ee:ff".to_string());
+        assert_eq!(normalize_mac("AA-BB-CC-DD-EE-FF"), expected);
+        assert_eq!(normalize_mac("aabb.ccdd.eeff"), expected);
+        assert_eq!(normalize_mac("aabbccddeeff"), expected);
+        assert_eq!(normalize_mac("alice"), None);
+        assert_eq!(normalize_mac("aabbccddeeffgg"), None);
+    }
+
+    #[test]
+    fn test_vlan_assignment() {
+        let config: RadiusConfig = serde_json::from_str(
+            r#"{
+                "clients": [{"name": "ap", "address": "192.168.1.0/24", "secret": "supersecret"}],
+                "vlans": {"iot": 30, "family": 10},
+                "default_vlan": 20,
+                "devices": [
+                    {"mac": "AA-BB-CC-DD-EE-01", "group": "iot"},
+                    {"mac": "aa:bb:cc:dd:ee:02", "group": "iot", "vlan": 99},
+                    {"mac": "aa:bb:cc:dd:ee:03"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert!(config.client_for("192.168.1.5".parse().unwrap()).is_some());
+        assert!(config.client_for("10.0.0.5".parse().unwrap()).is_none());
+
+        let vlan = |mac: &str| config.device(mac).and_then(|d| config.device_vlan(d));
+        assert_eq!(vlan("aa:bb:cc:dd:ee:01"), Some(30));
+        assert_eq!(vlan("aa:bb:cc:dd:ee:02"), Some(99));
+        assert_eq!(vlan("aa:bb:cc:dd:ee:03"), Some(20));
+
+        let groups = ["admins".to_string(), "family".to_string()];
+        assert_eq!(config.vlan_for_groups(groups.iter()), Some(10));
+
+        let mut bad = config.clone();
+        bad.clients[0].secret = "short".to_string();
+        assert!(bad.validate().is_err());
+    }
+}
@@ -0,0 +1,293 @@
+//! EAP (RFC 3748) and the EAP-TLS method (RFC 5216), TLS 1.2 only.
+//!
+//! TLS records are carried in EAP-Request/Response packets, fragmented to
+//! fit RADIUS attributes; each fragment is acknowledged by an empty packet.
+
+use rustls::ServerConnection;
+use std::io::Read;
+use std::sync::Arc;
+
+pub const EAP_REQUEST: u8 = 1;
+pub const EAP_RESPONSE: u8 = 2;
+pub const EAP_SUCCESS: u8 = 3;
+pub const EAP_FAILURE: u8 = 4;
+
+pub const TYPE_IDENTITY: u8 = 1;
+pub const TYPE_NAK: u8 = 3;
+pub const TYPE_TLS: u8 = 13;
+
+const FLAG_LENGTH: u8 = 0x80;
+const FLAG_MORE: u8 = 0x40;
+const FLAG_START: u8 = 0x20;
+
+/// TLS bytes per EAP packet, so the RADIUS packet stays under the usual MTU.
+const FRAGMENT_SIZE: usize = 1000;
+/// Refuse clients sending more than this in one TLS flight.
+const MAX_FLIGHT: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EapPacket {
+    pub code: u8,
+    pub id: u8,
+    /// Method type (requests and responses only).
+    pub kind: Option<u8>,
+    pub data: Vec<u8>,
+}
+
+impl EapPacket {
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 4 {
+            return None;
+        }
+        let len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+        if len < 4 || len > buf.len() {
+            return None;
+        }
+        let (kind, data) = match buf[0] {
+            EAP_REQUEST | EAP_RESPONSE if len >= 5 => (Some(buf[4]), buf[5..len].to_vec()),
+            EAP_REQUEST | EAP_RESPONSE => return None,
+            _ => (None, Vec::new()),
+        };
+        Some(Self {
+            code: buf[0],
+            id: buf[1],
+            kind,
+            data,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![self.code, self.id, 0, 0];
+        if let Some(kind) = self.kind {
+            buf.push(kind);
+            buf.extend_from_slice(&self.data);
+        }
+        let len = (buf.len() as u16).to_be_bytes();
+        buf[2..4].copy_from_slice(&len);
+        buf
+    }
+
+    pub fn success(id: u8) -> Self {
+        Self { code: EAP_SUCCESS, id, kind: None, data: Vec::new() }
+    }
+
+    pub fn failure(id: u8) -> Self {
+        Self { code: EAP_FAILURE, id, kind: None, data: Vec::new() }
+    }
+
+    /// Identity carried by an EAP-Response/Identity.
+    pub fn identity(&self) -> Option<String> {
+        (self.code == EAP_RESPONSE && self.kind == Some(TYPE_IDENTITY))
+            .then(|| String::from_utf8_lossy(&self.data).trim_end_matches('\0').to_string())
+    }
+}
+
+/// Outcome of one EAP-TLS exchange step.
+#[derive(Debug)]
+pub enum Step {
+    /// Send this request in an Access-Challenge.
+    Challenge(EapPacket),
+    /// Handshake done and client certificate verified against the CA.
+    Success {
+        /// Master Session Key for MS-MPPE keys.
+        msk: [u8; 64],
+        /// SHA-256 of the client certificate.
+        fingerprint: String,
+    },
+    Failure(String),
+}
+
+/// One EAP-TLS conversation, kept between RADIUS round trips.
+pub struct EapTlsSession {
+    conn: ServerConnection,
+    pub identity: String,
+    next_id: u8,
+    /// Last response id, for the final Success/Failure.
+    pub last_id: u8,
+    outgoing: Vec<u8>,
+    sent: usize,
+    incoming: Vec<u8>,
+}
+
+impl EapTlsSession {
+    pub fn new(config: Arc<rustls::ServerConfig>, identity: String, response_id: u8) -> Result<Self, rustls::Error> {
+        Ok(Self {
+            conn: ServerConnection::new(config)?,
+            identity,
+            next_id: response_id.wrapping_add(1),
+            last_id: response_id,
+            outgoing: Vec::new(),
+            sent: 0,
+            incoming: Vec::new(),
+        })
+    }
+
+    fn request(&mut self, flags: u8, payload: &[u8]) -> EapPacket {
+        let mut data = vec![flags];
+        data.extend_from_slice(payload);
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        EapPacket { code: EAP_REQUEST, id, kind: Some(TYPE_TLS), data }
+    }
+
+    /// EAP-TLS Start, sent after the identity exchange.
+    pub fn start(&mut self) -> EapPacket {
+        self.request(FLAG_START, &[])
+    }
+
+    /// Next fragment of the pending server flight.
+    fn next_fragment(&mut self) -> EapPacket {
+        let end = (self.sent + FRAGMENT_SIZE).min(self.outgoing.len());
+        let first = self.sent == 0;
+        let more = end < self.outgoing.len();
+        let mut flags = 0;
+        let mut payload = Vec::new();
+        if first && more {
+            flags |= FLAG_LENGTH;
+            payload.extend_from_slice(&(self.outgoing.len() as u32).to_be_bytes());
+        }
+        if more {
+            flags |= FLAG_MORE;
+        }
+        payload.extend_from_slice(&self.outgoing[self.sent..end]);
+        self.sent = end;
+        self.request(flags, &payload)
+    }
+
+    fn finish(&mut self) -> Step {
+        let Some(cert) = self.conn.peer_certificates().and_then(|c| c.first()) else {
+            return Step::Failure("no client certificate".to_string());
+        };
+        let fingerprint = crate::ca::fingerprint(cert);
+        match self.conn.export_keying_material([0u8; 64], b"client EAP encryption", None) {
+            Ok(msk) => Step::Success { msk, fingerprint },
+            Err(e) => Step::Failure(format!("key export: {}", e)),
+        }
+    }
+
+    pub fn handle(&mut self, response: &EapPacket) -> Step {
+        self.last_id = response.id;
+        match response.kind {
+            Some(TYPE_TLS) => {}
+            Some(TYPE_NAK) => return Step::Failure("client refused EAP-TLS".to_string()),
+            _ => return Step::Failure("unexpected EAP method".to_string()),
+        }
+        let Some((&flags, mut payload)) = response.data.split_first() else {
+            return Step::Failure("empty EAP-TLS packet".to_string());
+        };
+        if flags & FLAG_LENGTH != 0 {
+            if payload.len() < 4 {
+                return Step::Failure("truncated EAP-TLS length".to_string());
+            }
+            payload = &payload[4..];
+        }
+
+        // Acknowledgement of one of our fragments
+        if payload.is_empty() && flags & FLAG_MORE == 0 {
+            if self.sent < self.outgoing.len() {
+                return Step::Challenge(self.next_fragment());
+            }
+            if !self.conn.is_handshaking() {
+                return self.finish();
+            }
+            return Step::Failure("unexpected acknowledgement".to_string());
+        }
+
+        self.incoming.extend_from_slice(payload);
+        if self.incoming.len() > MAX_FLIGHT {
+            return Step::Failure("TLS flight too large".to_string());
+        }
+        if flags & FLAG_MORE != 0 {
+            return Step::Challenge(self.request(0, &[]));
+        }
+
+        let mut reader: &[u8] = &std::mem::take(&mut self.incoming);
+        while !reader.is_empty() {
+            if let Err(e) = self.conn.read_tls(&mut reader) {
+                return Step::Failure(format!("TLS read: {}", e));
+            }
+            if let Err(e) = self.conn.process_new_packets() {
+                return Step::Failure(format!("TLS: {}", e));
+            }
+        }
+        // Drain application data, if any (none expected)
+        let _ = self.conn.reader().read_to_end(&mut Vec::new());
+
+        self.outgoing.clear();
+        self.sent = 0;
+        while self.conn.wants_write() {
+            if let Err(e) = self.conn.write_tls(&mut self.outgoing) {
+                return Step::Failure(format!("TLS write: {}", e));
+            }
+        }
+        if !self.outgoing.is_empty() {
+            return Step::Challenge(self.next_fragment());
+        }
+        if self.conn.is_handshaking() {
+            return Step::Failure("TLS handshake stalled".to_string());
+        }
+        self.finish()
+    }
+}
+
+/// Client side of EAP-TLS, to exercise the server in tests.
+#[cfg(test)]
+pub(crate) struct TestPeer {
+    pub conn: rustls::ClientConnection,
+    buffered: Vec<u8>,
+}
+
+#[cfg(test)]
+impl TestPeer {
+    pub fn new(config: Arc<rustls::ClientConfig>) -> Self {
+        let name = rustls::pki_types::ServerName::try_from("homeroute-radius").unwrap();
+        Self {
+            conn: rustls::ClientConnection::new(config, name).unwrap(),
+            buffered: Vec::new(),
+        }
+    }
+
+    /// Answer a server request (acknowledging fragments).
+    pub fn respond(&mut self, request: &EapPacket) -> EapPacket {
+        let flags = request.data[0];
+        let mut payload = &request.data[1..];
+        if flags & FLAG_LENGTH != 0 {
+            payload = &payload[4..];
+        }
+        self.buffered.extend_from_slice(payload);
+        let mut out = Vec::new();
+        if flags & FLAG_MORE == 0 && flags & FLAG_START == 0 {
+            let mut reader: &[u8] = &std::mem::take(&mut self.buffered);
+            while !reader.is_empty() {
+                self.conn.read_tls(&mut reader).unwrap();
+                self.conn.process_new_packets().unwrap();
+            }
+        }
+        if flags & FLAG_MORE == 0 {
+            while self.conn.wants_write() {
+                self.conn.write_tls(&mut out).unwrap();
+            }
+        }
+        // Client flights are small enough to send unfragmented
+        let mut data = vec![0u8];
+        data.extend_from_slice(&out);
+        EapPacket { code: EAP_RESPONSE, id: request.id, kind: Some(TYPE_TLS), data }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_round_trip() {
+        let identity = EapPacket { code: EAP_RESPONSE, id: 7, kind: Some(TYPE_IDENTITY), data: b"alice".to_vec() };
+        let parsed = EapPacket::parse(&identity.encode()).unwrap();
+        assert_eq!(parsed, identity);
+        assert_eq!(parsed.identity().as_deref(), Some("alice"));
+
+        let success = EapPacket::success(8).encode();
+        assert_eq!(success, vec![EAP_SUCCESS, 8, 0, 4]);
+        assert!(EapPacket::parse(&[EAP_REQUEST, 1, 0, 4]).is_none());
+    }
+}
@@ -0,0 +1,155 @@
+//! Minimal RADIUS server for the access points: WPA-Enterprise with per-user
+//! certificates (EAP-TLS) and MAC authentication bypass, with dynamic VLAN
+//! assignment from the user's or device's group.
+
+pub mod ca;
+pub mod config;
+pub mod eap;
+pub mod packet;
+pub mod server;
+
+pub use ca::{ClientBundle, IssuedCert, RadiusCa};
+pub use config::{RadiusClient, RadiusConfig, RadiusDevice};
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use tokio::sync::watch;
+
+/// Authentication attempts kept for the API.
+const MAX_EVENTS: usize = 200;
+
+/// Users allowed to authenticate, with their groups.
+pub trait UserDirectory: Send + Sync {
+    /// Groups of an enabled user, `None` if unknown or disabled.
+    fn groups(&self, username: &str) -> Option<Vec<String>>;
+}
+
+impl UserDirectory for hr_auth::AuthService {
+    fn groups(&self, username: &str) -> Option<Vec<String>> {
+        self.users.get(username).filter(|u| !u.disabled).map(|u| u.groups)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthEvent {
+    pub timestamp: String,
+    /// `eap-tls` or `mac`.
+    pub method: &'static str,
+    /// Access point or switch that asked.
+    pub nas: String,
+    pub identity: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    pub accepted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vlan: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RadiusStatus {
+    pub running: bool,
+    pub accepted: u64,
+    pub rejected: u64,
+    /// EAP conversations in progress.
+    pub sessions: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Shared RADIUS handle: the API pushes config and manages certificates,
+/// the supervised `radius` service answers the access points.
+pub struct Radius {
+    config: watch::Sender<RadiusConfig>,
+    status: RwLock<RadiusStatus>,
+    accepted: AtomicU64,
+    rejected: AtomicU64,
+    ca: RadiusCa,
+    tls: Arc<rustls::ServerConfig>,
+    users: Arc<dyn UserDirectory>,
+    pub(crate) sessions: Mutex<HashMap<Vec<u8>, (Instant, eap::EapTlsSession)>>,
+    events: Mutex<VecDeque<AuthEvent>>,
+}
+
+impl Radius {
+    pub fn new(config: RadiusConfig, ca: RadiusCa, users: Arc<dyn UserDirectory>) -> anyhow::Result<Self> {
+        let tls = ca.tls_config()?;
+        Ok(Self {
+            config: watch::channel(config).0,
+            status: RwLock::new(RadiusStatus::default()),
+            accepted: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            ca,
+            tls,
+            users,
+            sessions: Mutex::new(HashMap::new()),
+            events: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// Current configuration.
+    pub fn config(&self) -> RadiusConfig {
+        self.config.borrow().clone()
+    }
+
+    /// Replace the configuration; the socket is rebound.
+    pub fn apply(&self, config: RadiusConfig) {
+        self.config.send_replace(config);
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<RadiusConfig> {
+        self.config.subscribe()
+    }
+
+    pub fn ca(&self) -> &RadiusCa {
+        &self.ca
+    }
+
+    pub(crate) fn tls(&self) -> Arc<rustls::ServerConfig> {
+        self.tls.clone()
+    }
+
+    pub(crate) fn users(&self) -> &dyn UserDirectory {
+        self.users.as_ref()
+    }
+
+    pub fn status(&self) -> RadiusStatus {
+        let mut status = self.status.read().unwrap().clone();
+        status.accepted = self.accepted.load(Ordering::Relaxed);
+        status.rejected = self.rejected.load(Ordering::Relaxed);
+        status.sessions = self.sessions.lock().unwrap().len();
+        status
+    }
+
+    pub(crate) fn set_status(&self, running: bool, error: Option<String>) {
+        let mut status = self.status.write().unwrap();
+        status.running = running;
+        status.last_error = error;
+    }
+
+    /// Recent authentication attempts, newest first.
+    pub fn events(&self) -> Vec<AuthEvent> {
+        self.events.lock().unwrap().iter().rev().cloned().collect()
+    }
+
+    pub(crate) fn record(&self, event: AuthEvent) {
+        if event.accepted {
+            self.accepted.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        let mut events = self.events.lock().unwrap();
+        if events.len() >= MAX_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+}
+
+pub type SharedRadius = Arc<Radius>;
@@ -0,0 +1,322 @@
+//! RADIUS packets (RFC 2865), Message-Authenticator (RFC 3579) and the
+//! attribute encryptions used here: User-Password and MS-MPPE keys (RFC 2548).
+
+use hmac::{Hmac, Mac};
+use md5::{Digest, Md5};
+
+pub const ACCESS_REQUEST: u8 = 1;
+pub const ACCESS_ACCEPT: u8 = 2;
+pub const ACCESS_REJECT: u8 = 3;
+pub const ACCESS_CHALLENGE: u8 = 11;
+
+pub const ATTR_USER_NAME: u8 = 1;
+pub const ATTR_USER_PASSWORD: u8 = 2;
+pub const ATTR_SERVICE_TYPE: u8 = 6;
+pub const ATTR_REPLY_MESSAGE: u8 = 18;
+pub const ATTR_STATE: u8 = 24;
+pub const ATTR_VENDOR_SPECIFIC: u8 = 26;
+pub const ATTR_CALLING_STATION_ID: u8 = 31;
+pub const ATTR_TUNNEL_TYPE: u8 = 64;
+pub const ATTR_TUNNEL_MEDIUM_TYPE: u8 = 65;
+pub const ATTR_EAP_MESSAGE: u8 = 79;
+pub const ATTR_MESSAGE_AUTHENTICATOR: u8 = 80;
+pub const ATTR_TUNNEL_PRIVATE_GROUP_ID: u8 = 81;
+
+/// Service-Type used by switches for MAC authentication.
+pub const SERVICE_CALL_CHECK: u32 = 10;
+
+const VENDOR_MICROSOFT: u32 = 311;
+const MS_MPPE_SEND_KEY: u8 = 16;
+const MS_MPPE_RECV_KEY: u8 = 17;
+
+const HEADER_LEN: usize = 20;
+pub const MAX_PACKET_LEN: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub code: u8,
+    pub identifier: u8,
+    pub authenticator: [u8; 16],
+    pub attributes: Vec<(u8, Vec<u8>)>,
+}
+
+impl Packet {
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < HEADER_LEN {
+            return None;
+        }
+        let length = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+        if length < HEADER_LEN || length > buf.len() || length > MAX_PACKET_LEN {
+            return None;
+        }
+        let mut attributes = Vec::new();
+        let mut offset = HEADER_LEN;
+        while offset < length {
+            let kind = buf[offset];
+            let len = *buf.get(offset + 1)? as usize;
+            if len < 2 || offset + len > length {
+                return None;
+            }
+            attributes.push((kind, buf[offset + 2..offset + len].to_vec()));
+            offset += len;
+        }
+        Some(Self {
+            code: buf[0],
+            identifier: buf[1],
+            authenticator: buf[4..20].try_into().ok()?,
+            attributes,
+        })
+    }
+
+    /// Reply to `self` with no attributes yet.
+    pub fn reply(&self, code: u8) -> Self {
+        Self {
+            code,
+            identifier: self.identifier,
+            authenticator: self.authenticator,
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn attr(&self, kind: u8) -> Option<&[u8]> {
+        self.attributes.iter().find(|(k, _)| *k == kind).map(|(_, v)| v.as_slice())
+    }
+
+    pub fn attr_str(&self, kind: u8) -> Option<String> {
+        self.attr(kind).map(|v| String::from_utf8_lossy(v).into_owned())
+    }
+
+    pub fn attr_u32(&self, kind: u8) -> Option<u32> {
+        Some(u32::from_be_bytes(self.attr(kind)?.try_into().ok()?))
+    }
+
+    /// Concatenation of every instance of an attribute (EAP-Message).
+    pub fn attr_concat(&self, kind: u8) -> Option<Vec<u8>> {
+        let parts: Vec<&Vec<u8>> = self.attributes.iter().filter(|(k, _)| *k == kind).map(|(_, v)| v).collect();
+        (!parts.is_empty()).then(|| parts.into_iter().flatten().copied().collect())
+    }
+
+    pub fn push(&mut self, kind: u8, value: impl Into<Vec<u8>>) {
+        self.attributes.push((kind, value.into()));
+    }
+
+    /// Split a long value (EAP-Message) over several attributes.
+    pub fn push_split(&mut self, kind: u8, value: &[u8]) {
+        for chunk in value.chunks(253) {
+            self.push(kind, chunk);
+        }
+    }
+
+    fn encode_raw(&self) -> Vec<u8> {
+        let mut buf = vec![self.code, self.identifier, 0, 0];
+        buf.extend_from_slice(&self.authenticator);
+        for (kind, value) in &self.attributes {
+            buf.push(*kind);
+            buf.push((value.len() + 2) as u8);
+            buf.extend_from_slice(value);
+        }
+        let len = (buf.len() as u16).to_be_bytes();
+        buf[2..4].copy_from_slice(&len);
+        buf
+    }
+
+    /// Encode a response to a request with authenticator `request_auth`:
+    /// adds Message-Authenticator, then computes the Response Authenticator.
+    pub fn encode_response(mut self, secret: &[u8], request_auth: &[u8; 16]) -> Vec<u8> {
+        self.attributes.retain(|(k, _)| *k != ATTR_MESSAGE_AUTHENTICATOR);
+        self.push(ATTR_MESSAGE_AUTHENTICATOR, [0u8; 16]);
+        self.authenticator = *request_auth;
+        let mut buf = self.encode_raw();
+        let mac = hmac_md5(secret, &buf);
+        let offset = buf.len() - 16;
+        buf[offset..].copy_from_slice(&mac);
+
+        let mut hasher = Md5::new();
+        hasher.update(&buf);
+        hasher.update(secret);
+        let auth = hasher.finalize();
+        buf[4..20].copy_from_slice(&auth);
+        buf
+    }
+
+    /// Encode a request signed with Message-Authenticator, as a NAS would.
+    #[cfg(test)]
+    pub(crate) fn encode_request(mut self, secret: &[u8]) -> Vec<u8> {
+        self.attributes.retain(|(k, _)| *k != ATTR_MESSAGE_AUTHENTICATOR);
+        self.push(ATTR_MESSAGE_AUTHENTICATOR, [0u8; 16]);
+        let mut buf = self.encode_raw();
+        let mac = hmac_md5(secret, &buf);
+        let offset = buf.len() - 16;
+        buf[offset..].copy_from_slice(&mac);
+        buf
+    }
+
+    /// Check the Message-Authenticator of a request (`true` when absent).
+    pub fn verify_message_authenticator(raw: &[u8], secret: &[u8]) -> bool {
+        let Some(packet) = Self::parse(raw) else {
+            return false;
+        };
+        let Some(received) = packet.attr(ATTR_MESSAGE_AUTHENTICATOR) else {
+            return true;
+        };
+        // Recompute over the raw packet with the attribute zeroed
+        let mut buf = raw[..u16::from_be_bytes([raw[2], raw[3]]) as usize].to_vec();
+        let mut offset = HEADER_LEN;
+        while offset + 2 <= buf.len() {
+            let len = buf[offset + 1] as usize;
+            if buf[offset] == ATTR_MESSAGE_AUTHENTICATOR && len == 18 {
+                buf[offset + 2..offset + 18].fill(0);
+            }
+            offset += len.max(2);
+        }
+        let mut mac = <Hmac<Md5> as Mac>::new_from_slice(secret).expect("HMAC accepts any key length");
+        mac.update(&buf);
+        mac.verify_slice(received).is_ok()
+    }
+}
+
+fn hmac_md5(secret: &[u8], data: &[u8]) -> [u8; 16] {
+    let mut mac = <Hmac<Md5> as Mac>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+fn md5_parts(parts: &[&[u8]]) -> [u8; 16] {
+    let mut hasher = Md5::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// Decrypt a User-Password attribute (RFC 2865 §5.2).
+pub fn decrypt_password(value: &[u8], secret: &[u8], request_auth: &[u8; 16]) -> Option<String> {
+    if value.is_empty() || !value.len().is_multiple_of(16) {
+        return None;
+    }
+    let mut plain = Vec::with_capacity(value.len());
+    let mut prev: &[u8] = request_auth;
+    for chunk in value.chunks(16) {
+        let b = md5_parts(&[secret, prev]);
+        plain.extend(chunk.iter().zip(b).map(|(c, k)| c ^ k));
+        prev = chunk;
+    }
+    while plain.last() == Some(&0) {
+        plain.pop();
+    }
+    String::from_utf8(plain).ok()
+}
+
+/// Encrypt a User-Password value (what a NAS sends; used by tests).
+pub fn encrypt_password(password: &str, secret: &[u8], request_auth: &[u8; 16]) -> Vec<u8> {
+    let mut plain = password.as_bytes().to_vec();
+    plain.resize(plain.len().div_ceil(16).max(1) * 16, 0);
+    let mut out: Vec<u8> = Vec::with_capacity(plain.len());
+    for (i, chunk) in plain.chunks(16).enumerate() {
+        let prev: &[u8] = if i == 0 { request_auth } else { &out[(i - 1) * 16..i * 16] };
+        let b = md5_parts(&[secret, prev]);
+        let block: Vec<u8> = chunk.iter().zip(b).map(|(p, k)| p ^ k).collect();
+        out.extend(block);
+    }
+    out
+}
+
+/// Salt-encrypted MS-MPPE key attribute value (RFC 2548 §2.4.2).
+fn mppe_key(key: &[u8], secret: &[u8], request_auth: &[u8; 16], salt: [u8; 2]) -> Vec<u8> {
+    let mut plain = vec![key.len() as u8];
+    plain.extend_from_slice(key);
+    plain.resize(plain.len().div_ceil(16) * 16, 0);
+
+    let mut out = salt.to_vec();
+    let mut prev = md5_parts(&[secret, request_auth, &salt]);
+    for chunk in plain.chunks(16) {
+        let block: Vec<u8> = chunk.iter().zip(prev).map(|(p, k)| p ^ k).collect();
+        prev = md5_parts(&[secret, &block]);
+        out.extend(block);
+    }
+    out
+}
+
+fn vendor_attr(vendor: u32, kind: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = vendor.to_be_bytes().to_vec();
+    out.push(kind);
+    out.push((value.len() + 2) as u8);
+    out.extend_from_slice(value);
+    out
+}
+
+/// MS-MPPE-Recv-Key / Send-Key from the EAP MSK, for WPA key derivation
+/// on the access point.
+pub fn push_mppe_keys(packet: &mut Packet, msk: &[u8], secret: &[u8], request_auth: &[u8; 16]) {
+    let salt: u16 = rand::random::<u16>() | 0x8000;
+    let recv = mppe_key(&msk[..32], secret, request_auth, salt.to_be_bytes());
+    let send = mppe_key(&msk[32..64], secret, request_auth, (salt ^ 1).to_be_bytes());
+    packet.push(ATTR_VENDOR_SPECIFIC, vendor_attr(VENDOR_MICROSOFT, MS_MPPE_RECV_KEY, &recv));
+    packet.push(ATTR_VENDOR_SPECIFIC, vendor_attr(VENDOR_MICROSOFT, MS_MPPE_SEND_KEY, &send));
+}
+
+/// Dynamic VLAN assignment (RFC 3580 §3.31).
+pub fn push_vlan(packet: &mut Packet, vlan: u16) {
+    packet.push(ATTR_TUNNEL_TYPE, [0, 0, 0, 13]); // VLAN
+    packet.push(ATTR_TUNNEL_MEDIUM_TYPE, [0, 0, 0, 6]); // IEEE-802
+    packet.push(ATTR_TUNNEL_PRIVATE_GROUP_ID, vlan.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"testing123";
+
+    #[test]
+    fn test_password_round_trip() {
+        let auth = [7u8; 16];
+        for password in ["aabbccddeeff", "a-password-longer-than-16-bytes"] {
+            let hidden = encrypt_password(password, SECRET, &auth);
+            assert_eq!(hidden.len() % 16, 0);
+            assert_eq!(decrypt_password(&hidden, SECRET, &auth).as_deref(), Some(password));
+        }
+        assert!(decrypt_password(&[1, 2, 3], SECRET, &auth).is_none());
+    }
+
+    #[test]
+    fn test_response_authenticators() {
+        let mut request = Packet {
+            code: ACCESS_REQUEST,
+            identifier: 42,
+            authenticator: [3u8; 16],
+            attributes: vec![(ATTR_USER_NAME, b"aa:bb:cc:dd:ee:ff".to_vec())],
+        };
+        // A request signed by the NAS verifies; a tampered one doesn't
+        request.push(ATTR_MESSAGE_AUTHENTICATOR, [0u8; 16]);
+        let mut raw = request.encode_raw();
+        let mac = hmac_md5(SECRET, &raw);
+        let len = raw.len();
+        raw[len - 16..].copy_from_slice(&mac);
+        assert!(Packet::verify_message_authenticator(&raw, SECRET));
+        assert!(!Packet::verify_message_authenticator(&raw, b"wrong"));
+
+        let parsed = Packet::parse(&raw).unwrap();
+        assert_eq!(parsed.attr_str(ATTR_USER_NAME).as_deref(), Some("aa:bb:cc:dd:ee:ff"));
+
+        let mut accept = parsed.reply(ACCESS_ACCEPT);
+        push_vlan(&mut accept, 30);
+        let out = accept.encode_response(SECRET, &parsed.authenticator);
+        let decoded = Packet::parse(&out).unwrap();
+        assert_eq!(decoded.code, ACCESS_ACCEPT);
+        assert_eq!(decoded.attr_str(ATTR_TUNNEL_PRIVATE_GROUP_ID).as_deref(), Some("30"));
+
+        // Response Authenticator = MD5(packet with request authenticator + secret)
+        let mut check = out.clone();
+        check[4..20].copy_from_slice(&parsed.authenticator);
+        assert_eq!(&out[4..20], &md5_parts(&[&check, SECRET]));
+    }
+
+    #[test]
+    fn test_mppe_key_layout() {
+        let value = mppe_key(&[9u8; 32], SECRET, &[1u8; 16], [0x80, 0x01]);
+        // salt + (1 length byte + 32 key bytes, padded to 48)
+        assert_eq!(value.len(), 2 + 48);
+        assert_eq!(&value[..2], &[0x80, 0x01]);
+    }
+}
@@ -0,0 +1,394 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
+
+use crate::config::{normalize_mac, RadiusConfig};
+use crate::eap::{EapPacket, EapTlsSession, Step};
+use crate::packet::{self, Packet};
+use crate::{AuthEvent, Radius};
+
+/// Delay before rebinding after a socket failure.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+/// EAP conversations idle this long are forgotten.
+const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+/// Bound on concurrent EAP conversations.
+const MAX_SESSIONS: usize = 1024;
+
+/// Run the RADIUS server, rebinding whenever the configuration changes.
+pub async fn run_radius(radius: Arc<Radius>) -> Result<()> {
+    let mut config_rx = radius.subscribe();
+    loop {
+        let config = config_rx.borrow_and_update().clone();
+        tokio::select! {
+            _ = run_config(radius.clone(), config) => {}
+            changed = config_rx.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Serve one configuration until it is replaced (never returns on its own).
+async fn run_config(radius: Arc<Radius>, config: RadiusConfig) {
+    if !config.enabled {
+        radius.set_status(false, None);
+        return std::future::pending().await;
+    }
+
+    loop {
+        let ip: IpAddr = config.bind_address.parse().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let addr = SocketAddr::new(ip, config.port);
+        match UdpSocket::bind(addr).await.with_context(|| format!("UDP bind {}", addr)) {
+            Ok(socket) => {
+                info!("RADIUS server listening on udp/{}", addr);
+                radius.set_status(true, None);
+                let e = serve(&radius, &config, socket).await;
+                warn!("RADIUS server stopped: {:#}", e);
+                radius.set_status(false, Some(format!("{e:#}")));
+            }
+            Err(e) => {
+                warn!("RADIUS server failed to start: {:#}", e);
+                radius.set_status(false, Some(format!("{e:#}")));
+            }
+        }
+        tokio::time::sleep(RETRY_DELAY).await;
+    }
+}
+
+async fn serve(radius: &Radius, config: &RadiusConfig, socket: UdpSocket) -> anyhow::Error {
+    let mut buf = vec![0u8; packet::MAX_PACKET_LEN];
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buf).await {
+            Ok(r) => r,
+            Err(e) => return e.into(),
+        };
+        if let Some(reply) = handle_packet(radius, config, peer.ip(), &buf[..len])
+            && let Err(e) = socket.send_to(&reply, peer).await
+        {
+            debug!("RADIUS reply to {} failed: {}", peer, e);
+        }
+    }
+}
+
+/// Answer one datagram. `None` when it must be silently dropped (unknown
+/// NAS, malformed or forged packet).
+pub(crate) fn handle_packet(radius: &Radius, config: &RadiusConfig, source: IpAddr, raw: &[u8]) -> Option<Vec<u8>> {
+    let Some(client) = config.client_for(source) else {
+        debug!("RADIUS request from unknown client {}", source);
+        return None;
+    };
+    let secret = client.secret.as_bytes();
+    let request = Packet::parse(raw)?;
+    if request.code != packet::ACCESS_REQUEST {
+        return None;
+    }
+    // RFC 3579: Message-Authenticator is mandatory alongside EAP-Message
+    let eap = request.attr_concat(packet::ATTR_EAP_MESSAGE);
+    let signed = request.attr(packet::ATTR_MESSAGE_AUTHENTICATOR).is_some();
+    if (eap.is_some() && !signed) || !Packet::verify_message_authenticator(raw, secret) {
+        warn!("RADIUS request from {} ({}) failed authentication", client.name, source);
+        return None;
+    }
+
+    let reply = match eap {
+        Some(eap) => eap_request(radius, config, &client.name, &request, &eap, secret),
+        None => mac_request(radius, config, &client.name, &request, secret),
+    };
+    Some(reply.encode_response(secret, &request.authenticator))
+}
+
+fn event(method: &'static str, nas: &str, identity: &str) -> AuthEvent {
+    AuthEvent {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        method,
+        nas: nas.to_string(),
+        identity: identity.to_string(),
+        user: None,
+        accepted: false,
+        vlan: None,
+        reason: None,
+    }
+}
+
+fn eap_reply(request: &Packet, code: u8, eap: &EapPacket) -> Packet {
+    let mut reply = request.reply(code);
+    reply.push_split(packet::ATTR_EAP_MESSAGE, &eap.encode());
+    reply
+}
+
+fn eap_reject(radius: &Radius, request: &Packet, id: u8, mut event: AuthEvent, reason: String) -> Packet {
+    debug!("EAP-TLS rejected for {}: {}", event.identity, reason);
+    event.reason = Some(reason);
+    radius.record(event);
+    eap_reply(request, packet::ACCESS_REJECT, &EapPacket::failure(id))
+}
+
+fn eap_request(
+    radius: &Radius,
+    config: &RadiusConfig,
+    nas: &str,
+    request: &Packet,
+    raw_eap: &[u8],
+    secret: &[u8],
+) -> Packet {
+    let Some(eap) = EapPacket::parse(raw_eap) else {
+        return request.reply(packet::ACCESS_REJECT);
+    };
+
+    let state = request.attr(packet::ATTR_STATE).map(<[u8]>::to_vec);
+    let session = {
+        let mut sessions = radius.sessions.lock().unwrap();
+        sessions.retain(|_, (at, _)| at.elapsed() < SESSION_TIMEOUT);
+        state.and_then(|s| sessions.remove(&s)).map(|(_, session)| session)
+    };
+
+    let (session, step) = match session {
+        Some(mut session) => {
+            let step = session.handle(&eap);
+            (session, step)
+        }
+        None => {
+            // A new conversation starts with the client's identity
+            let Some(identity) = eap.identity() else {
+                return eap_reply(request, packet::ACCESS_REJECT, &EapPacket::failure(eap.id));
+            };
+            let event = event("eap-tls", nas, &identity);
+            if !config.eap_tls {
+                return eap_reject(radius, request, eap.id, event, "EAP-TLS disabled".to_string());
+            }
+            let mut session = match EapTlsSession::new(radius.tls(), identity, eap.id) {
+                Ok(s) => s,
+                Err(e) => return eap_reject(radius, request, eap.id, event, format!("TLS: {}", e)),
+            };
+            let start = session.start();
+            (session, Step::Challenge(start))
+        }
+    };
+
+    let mut event = event("eap-tls", nas, &session.identity);
+    match step {
+        Step::Challenge(next) => {
+            let mut sessions = radius.sessions.lock().unwrap();
+            if sessions.len() >= MAX_SESSIONS {
+                drop(sessions);
+                return eap_reject(radius, request, eap.id, event, "too many sessions".to_string());
+            }
+            let state: [u8; 16] = rand::random();
+            sessions.insert(state.to_vec(), (Instant::now(), session));
+            let mut reply = eap_reply(request, packet::ACCESS_CHALLENGE, &next);
+            reply.push(packet::ATTR_STATE, state);
+            reply
+        }
+        Step::Failure(reason) => eap_reject(radius, request, session.last_id, event, reason),
+        Step::Success { msk, fingerprint } => {
+            let id = session.last_id;
+            let Some(cert) = radius.ca().lookup(&fingerprint) else {
+                return eap_reject(radius, request, id, event, "certificate unknown or revoked".to_string());
+            };
+            event.user = Some(cert.username.clone());
+            let Some(groups) = radius.users().groups(&cert.username) else {
+                return eap_reject(radius, request, id, event, "user unknown or disabled".to_string());
+            };
+            let vlan = config.vlan_for_groups(groups.iter());
+
+            let mut reply = eap_reply(request, packet::ACCESS_ACCEPT, &EapPacket::success(id));
+            reply.push(packet::ATTR_USER_NAME, cert.username.as_str());
+            packet::push_mppe_keys(&mut reply, &msk, secret, &request.authenticator);
+            if let Some(vlan) = vlan {
+                packet::push_vlan(&mut reply, vlan);
+            }
+            info!("EAP-TLS accepted {} via {} (vlan {:?})", cert.username, nas, vlan);
+            event.accepted = true;
+            event.vlan = vlan;
+            radius.record(event);
+            reply
+        }
+    }
+}
+
+/// MAC authentication bypass: the NAS sends the station MAC as User-Name
+/// (and usually as password, or with Service-Type Call-Check).
+fn mac_request(radius: &Radius, config: &RadiusConfig, nas: &str, request: &Packet, secret: &[u8]) -> Packet {
+    let user_name = request.attr_str(packet::ATTR_USER_NAME).unwrap_or_default();
+    let mut event = event("mac", nas, &user_name);
+    let reject = |mut event: AuthEvent, reason: &str| {
+        debug!("MAC authentication rejected for {}: {}", event.identity, reason);
+        event.reason = Some(reason.to_string());
+        radius.record(event);
+        request.reply(packet::ACCESS_REJECT)
+    };
+
+    if !config.mac_auth {
+        return reject(event, "MAC authentication disabled");
+    }
+    let Some(mac) = normalize_mac(&user_name) else {
+        return reject(event, "User-Name is not a MAC address");
+    };
+    let password_ok = request
+        .attr(packet::ATTR_USER_PASSWORD)
+        .and_then(|p| packet::decrypt_password(p, secret, &request.authenticator))
+        .is_some_and(|p| normalize_mac(&p).as_deref() == Some(mac.as_str()));
+    let call_check = request.attr_u32(packet::ATTR_SERVICE_TYPE) == Some(packet::SERVICE_CALL_CHECK);
+    if !password_ok && !call_check {
+        return reject(event, "password does not match the MAC address");
+    }
+
+    let vlan = match config.device(&mac) {
+        Some(device) => {
+            event.user = Some(device.name.clone()).filter(|n| !n.is_empty());
+            config.device_vlan(device)
+        }
+        None if config.allow_unknown_macs => config.default_vlan,
+        None => return reject(event, "unknown device"),
+    };
+
+    let mut reply = request.reply(packet::ACCESS_ACCEPT);
+    if let Some(vlan) = vlan {
+        packet::push_vlan(&mut reply, vlan);
+    }
+    debug!("MAC authentication accepted {} via {} (vlan {:?})", mac, nas, vlan);
+    event.accepted = true;
+    event.vlan = vlan;
+    radius.record(event);
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eap::{TestPeer, EAP_RESPONSE, TYPE_IDENTITY};
+    use crate::{RadiusCa, UserDirectory};
+
+    const SECRET: &str = "supersecret";
+    const NAS: &str = "192.168.1.2";
+
+    struct Users;
+
+    impl UserDirectory for Users {
+        fn groups(&self, username: &str) -> Option<Vec<String>> {
+            (username == "alice").then(|| vec!["family".to_string()])
+        }
+    }
+
+    fn setup(dir: &std::path::Path) -> (Radius, RadiusConfig) {
+        let config: RadiusConfig = serde_json::from_value(serde_json::json!({
+            "enabled": true,
+            "clients": [{"name": "ap", "address": "192.168.1.0/24", "secret": SECRET}],
+            "mac_auth": true,
+            "vlans": {"family": 10, "iot": 30},
+            "devices": [{"mac": "aa:bb:cc:dd:ee:01", "name": "printer", "group": "iot"}]
+        }))
+        .unwrap();
+        let ca = RadiusCa::load_or_create(dir).unwrap();
+        let radius = Radius::new(config.clone(), ca, Arc::new(Users)).unwrap();
+        (radius, config)
+    }
+
+    fn request(id: u8, attributes: Vec<(u8, Vec<u8>)>) -> Packet {
+        Packet {
+            code: packet::ACCESS_REQUEST,
+            identifier: id,
+            authenticator: rand::random(),
+            attributes,
+        }
+    }
+
+    fn send(radius: &Radius, config: &RadiusConfig, request: Packet) -> Packet {
+        let raw = request.encode_request(SECRET.as_bytes());
+        let reply = handle_packet(radius, config, NAS.parse().unwrap(), &raw).unwrap();
+        Packet::parse(&reply).unwrap()
+    }
+
+    fn client_config(radius: &Radius, cert_pem: &str, key_pem: &str) -> Arc<rustls::ClientConfig> {
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut radius.ca().ca_pem().as_bytes()) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let chain = rustls_pemfile::certs(&mut cert_pem.as_bytes()).collect::<Result<Vec<_>, _>>().unwrap();
+        let key = rustls_pemfile::private_key(&mut key_pem.as_bytes()).unwrap().unwrap();
+        let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_protocol_versions(&[&rustls::version::TLS12])
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(chain, key)
+            .unwrap();
+        Arc::new(config)
+    }
+
+    /// Full EAP-TLS conversation through RADIUS; returns the final reply.
+    fn eap_tls(radius: &Radius, config: &RadiusConfig, peer: &mut TestPeer) -> Packet {
+        let identity = EapPacket { code: EAP_RESPONSE, id: 1, kind: Some(TYPE_IDENTITY), data: b"alice".to_vec() };
+        let mut reply = send(radius, config, request(1, vec![(packet::ATTR_EAP_MESSAGE, identity.encode())]));
+        for id in 2..40 {
+            if reply.code != packet::ACCESS_CHALLENGE {
+                return reply;
+            }
+            let challenge = EapPacket::parse(&reply.attr_concat(packet::ATTR_EAP_MESSAGE).unwrap()).unwrap();
+            let mut next = request(id, Vec::new());
+            next.push_split(packet::ATTR_EAP_MESSAGE, &peer.respond(&challenge).encode());
+            next.push(packet::ATTR_STATE, reply.attr(packet::ATTR_STATE).unwrap());
+            reply = send(radius, config, next);
+        }
+        panic!("EAP-TLS did not finish");
+    }
+
+    #[test]
+    fn test_eap_tls_assigns_user_vlan() {
+        let dir = std::env::temp_dir().join(format!("hr-radius-test-{}", rand::random::<u64>()));
+        let (radius, config) = setup(&dir);
+
+        let bundle = radius.ca().issue_client("alice", "laptop", 30).unwrap();
+        let client = client_config(&radius, &bundle.cert_pem, &bundle.key_pem);
+        let reply = eap_tls(&radius, &config, &mut TestPeer::new(client.clone()));
+        assert_eq!(reply.code, packet::ACCESS_ACCEPT);
+        assert_eq!(reply.attr_str(packet::ATTR_USER_NAME).as_deref(), Some("alice"));
+        assert_eq!(reply.attr_str(packet::ATTR_TUNNEL_PRIVATE_GROUP_ID).as_deref(), Some("10"));
+        let mppe = reply.attributes.iter().filter(|(k, _)| *k == packet::ATTR_VENDOR_SPECIFIC).count();
+        assert_eq!(mppe, 2);
+        assert_eq!(radius.status().sessions, 0);
+
+        // Revoked certificates are refused
+        radius.ca().revoke(&bundle.cert.fingerprint).unwrap();
+        let reply = eap_tls(&radius, &config, &mut TestPeer::new(client));
+        assert_eq!(reply.code, packet::ACCESS_REJECT);
+
+        let events = radius.events();
+        assert!(!events[0].accepted);
+        assert!(events[1].accepted);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_mac_auth() {
+        let dir = std::env::temp_dir().join(format!("hr-radius-test-{}", rand::random::<u64>()));
+        let (radius, mut config) = setup(&dir);
+        let mab = |config: &RadiusConfig, mac: &str| {
+            let auth: [u8; 16] = rand::random();
+            let mut req = request(1, vec![(packet::ATTR_USER_NAME, mac.as_bytes().to_vec())]);
+            req.authenticator = auth;
+            req.push(packet::ATTR_USER_PASSWORD, packet::encrypt_password(mac, SECRET.as_bytes(), &auth));
+            send(&radius, config, req)
+        };
+
+        let reply = mab(&config, "AABBCCDDEE01");
+        assert_eq!(reply.code, packet::ACCESS_ACCEPT);
+        assert_eq!(reply.attr_str(packet::ATTR_TUNNEL_PRIVATE_GROUP_ID).as_deref(), Some("30"));
+        assert_eq!(mab(&config, "aabbccddee02").code, packet::ACCESS_REJECT);
+
+        config.allow_unknown_macs = true;
+        config.default_vlan = Some(99);
+        let reply = mab(&config, "aabbccddee02");
+        assert_eq!(reply.attr_str(packet::ATTR_TUNNEL_PRIVATE_GROUP_ID).as_deref(), Some("99"));
+
+        // Unknown NAS: dropped
+        let raw = request(1, Vec::new()).encode_request(SECRET.as_bytes());
+        assert!(handle_packet(&radius, &config, "10.0.0.1".parse().unwrap(), &raw).is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::rules::parse_nets;
+
+/// Multicast reflector configuration (reflector-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReflectorConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Interfaces (VLANs) bridged by the reflector, e.g. `["br-lan", "vlan20"]`.
+    /// A single one is enough for the responder and the discovery.
+    #[serde(default)]
+    pub interfaces: Vec<String>,
+    /// Reflect mDNS (224.0.0.251:5353): AirPlay, Chromecast, printers...
+    #[serde(default = "default_true")]
+    pub mdns: bool,
+    /// Reflect SSDP (239.255.255.250:1900): DLNA, UPnP, DIAL.
+    #[serde(default = "default_true")]
+    pub ssdp: bool,
+    /// Browse the services announced by mDNS (`/api/network/discovery`).
+    #[serde(default = "default_true")]
+    pub discovery: bool,
+    /// Answer for `<hostname>.local` with the address of each interface
+    /// (empty: no responder).
+    #[serde(default)]
+    pub hostname: String,
+    /// Reflect everything between interfaces, ignoring pairing rules.
+    #[serde(default)]
+    pub reflect_unpaired: bool,
+    #[serde(default)]
+    pub rules: Vec<PairingRule>,
+}
+
+/// "These clients may discover these devices".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PairingRule {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    /// Discovering side (phones, laptops): IPs or CIDRs.
+    pub clients: Vec<String>,
+    /// Discovered side (TVs, speakers, printers): IPs or CIDRs.
+    pub devices: Vec<String>,
+    /// mDNS service types (`_googlecast._tcp`) or SSDP targets
+    /// (`urn:dial-multi-screen-org:service:dial:1`). Empty = all.
+    #[serde(default)]
+    pub services: Vec<String>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for ReflectorConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl PairingRule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.trim().is_empty() {
+            return Err("id requis".to_string());
+        }
+        if parse_nets(&self.clients)?.is_empty() {
+            return Err("au moins un client requis".to_string());
+        }
+        if parse_nets(&self.devices)?.is_empty() {
+            return Err("au moins un appareil requis".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl ReflectorConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let mut interfaces = HashSet::new();
+        for iface in &self.interfaces {
+            if iface.trim().is_empty() || iface.len() > 15 || iface.contains(['/', ' ']) {
+                return Err(format!("interface invalide: {}", iface));
+            }
+            if !interfaces.insert(iface.as_str()) {
+                return Err(format!("interface en double: {}", iface));
+            }
+        }
+        if self.enabled && self.interfaces.is_empty() {
+            return Err("au moins une interface requise".to_string());
+        }
+        if !self.hostname.is_empty()
+            && (self.hostname.len() > 63
+                || self.hostname.starts_with('-')
+                || !self.hostname.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        {
+            return Err(format!("nom d'hôte invalide: {}", self.hostname));
+        }
+        let mut ids = HashSet::new();
+        for rule in &self.rules {
+            rule.validate().map_err(|e| format!("{}: {}", rule.id, e))?;
+            if !ids.insert(rule.id.as_str()) {
+                return Err(format!("id en double: {}", rule.id));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let config = ReflectorConfig::default();
+        assert!(!config.enabled);
+        assert!(config.mdns && config.ssdp && config.discovery);
+        assert!(config.hostname.is_empty());
+        assert!(!config.reflect_unpaired);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config: ReflectorConfig = serde_json::from_str(
+            r#"{"enabled":true,"interfaces":["br-lan","vlan20"],
+                "rules":[{"id":"tv","clients":["192.168.1.23"],"devices":["192.168.20.0/24"]}]}"#,
+        )
+        .unwrap();
+        assert!(config.rules[0].enabled);
+        assert!(config.validate().is_ok());
+
+        config.rules[0].devices = vec![];
+        assert!(config.validate().is_err());
+        config.rules[0].devices = vec!["tv.lan".to_string()];
+        assert!(config.validate().is_err());
+        config.rules[0].devices = vec!["192.168.20.5".to_string()];
+        config.interfaces.pop();
+        assert!(config.validate().is_ok());
+        config.interfaces.pop();
+        assert!(config.validate().is_err());
+        config.interfaces.push("br-lan".to_string());
+        config.hostname = "homeroute".to_string();
+        assert!(config.validate().is_ok());
+        config.hostname = "home.route".to_string();
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! Registry of the services announced by mDNS on the bridged networks,
+//! fed by every answer the reflector sees (its own browse queries
+//! included) and pruned when the records expire or are withdrawn.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::mdns::{MdnsRecord, RecordData};
+
+/// Lists the service types present on a network.
+pub const META_QUERY: &str = "_services._dns-sd._udp.local";
+
+/// Kept at least this long, whatever the announced TTL.
+const MIN_LIFETIME: Duration = Duration::from_secs(120);
+
+/// A service instance (`Living Room._googlecast._tcp.local`).
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredService {
+    /// Stable id derived from the instance name.
+    pub id: String,
+    /// Instance label (`Living Room`).
+    pub name: String,
+    /// `_googlecast._tcp`
+    pub service_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Addresses of the host, else of the device that answered.
+    pub addresses: Vec<String>,
+    pub txt: Vec<String>,
+    /// Interface it was heard on.
+    pub interface: String,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// Plain HTTP service (`_http._tcp`) with a known port: a proxy route
+    /// can point to it.
+    pub web: bool,
+}
+
+struct Instance {
+    service_type: String,
+    host: Option<String>,
+    port: Option<u16>,
+    txt: Vec<String>,
+    interface: String,
+    source: Ipv4Addr,
+    first_seen: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    expires: Instant,
+}
+
+struct Host {
+    addrs: BTreeSet<IpAddr>,
+    expires: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    /// By lowercase instance name.
+    instances: HashMap<String, Instance>,
+    hosts: HashMap<String, Host>,
+    /// Service types learnt from `_services._dns-sd._udp`, browsed too.
+    types: BTreeSet<String>,
+}
+
+#[derive(Default)]
+pub struct Discovery {
+    state: Mutex<State>,
+}
+
+fn lifetime(ttl: u32) -> Instant {
+    Instant::now() + Duration::from_secs(ttl as u64).max(MIN_LIFETIME)
+}
+
+/// `_http._tcp.local` for `My NAS._http._tcp.local`.
+fn instance_type(instance: &str) -> Option<&str> {
+    let start = instance.find("._")?;
+    Some(&instance[start + 1..])
+}
+
+/// FNV-1a: ids survive restarts without storing anything.
+pub(crate) fn service_id(instance: &str) -> String {
+    let hash = instance
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+    format!("{hash:016x}")
+}
+
+impl Discovery {
+    /// Record the services described by an mDNS answer from `source`.
+    pub(crate) fn ingest(&self, records: &[MdnsRecord], source: Ipv4Addr, interface: &str) {
+        let mut state = self.state.lock().unwrap();
+        let now = Utc::now();
+        for record in records {
+            match &record.data {
+                RecordData::Ptr(target) if record.name == META_QUERY && record.ttl > 0 => {
+                    state.types.insert(target.to_ascii_lowercase());
+                }
+                RecordData::Ptr(target) if target.to_ascii_lowercase().ends_with(&format!(".{}", record.name)) => {
+                    let key = target.to_ascii_lowercase();
+                    if record.ttl == 0 {
+                        state.instances.remove(&key);
+                        continue;
+                    }
+                    let instance = state.instances.entry(key).or_insert_with(|| Instance {
+                        service_type: record.name.clone(),
+                        host: None,
+                        port: None,
+                        txt: Vec::new(),
+                        interface: interface.to_string(),
+                        source,
+                        first_seen: now,
+                        last_seen: now,
+                        expires: Instant::now(),
+                    });
+                    instance.interface = interface.to_string();
+                    instance.source = source;
+                    instance.last_seen = now;
+                    instance.expires = instance.expires.max(lifetime(record.ttl));
+                }
+                RecordData::Srv { port, target } => {
+                    if let Some(instance) = state.instances.get_mut(&record.name) {
+                        instance.host = Some(target.to_ascii_lowercase());
+                        instance.port = Some(*port);
+                    } else if record.ttl > 0
+                        && let Some(service_type) = instance_type(&record.name)
+                    {
+                        // SRV without its PTR (answer to a direct lookup)
+                        let service_type = service_type.to_string();
+                        state.instances.insert(record.name.clone(), Instance {
+                            service_type,
+                            host: Some(target.to_ascii_lowercase()),
+                            port: Some(*port),
+                            txt: Vec::new(),
+                            interface: interface.to_string(),
+                            source,
+                            first_seen: now,
+                            last_seen: now,
+                            expires: lifetime(record.ttl),
+                        });
+                    }
+                }
+                RecordData::Txt(strings) => {
+                    if let Some(instance) = state.instances.get_mut(&record.name) {
+                        instance.txt = strings.clone();
+                    }
+                }
+                RecordData::A(ip) => add_host(&mut state, record, IpAddr::V4(*ip)),
+                RecordData::Aaaa(ip) => add_host(&mut state, record, IpAddr::V6(*ip)),
+                _ => {}
+            }
+        }
+    }
+
+    fn prune(state: &mut State) {
+        let now = Instant::now();
+        state.instances.retain(|_, i| i.expires > now);
+        state.hosts.retain(|_, h| h.expires > now);
+    }
+
+    /// Names to ask for: the service types, and the list of them.
+    pub(crate) fn browse_names(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        std::iter::once(META_QUERY.to_string()).chain(state.types.iter().cloned()).collect()
+    }
+
+    /// Services currently announced, by type then name.
+    pub fn services(&self) -> Vec<DiscoveredService> {
+        let mut state = self.state.lock().unwrap();
+        Self::prune(&mut state);
+        let mut services: Vec<DiscoveredService> = state
+            .instances
+            .iter()
+            .map(|(key, instance)| {
+                let mut addresses: Vec<String> = instance
+                    .host
+                    .as_ref()
+                    .and_then(|h| state.hosts.get(h))
+                    .map(|h| h.addrs.iter().map(|a| a.to_string()).collect())
+                    .unwrap_or_default();
+                if addresses.is_empty() {
+                    addresses.push(instance.source.to_string());
+                }
+                let service_type = instance.service_type.trim_end_matches(".local").to_string();
+                DiscoveredService {
+                    id: service_id(key),
+                    name: key
+                        .strip_suffix(&format!(".{}", instance.service_type))
+                        .unwrap_or(key)
+                        .to_string(),
+                    web: service_type == "_http._tcp" && instance.port.is_some(),
+                    service_type,
+                    host: instance.host.clone(),
+                    port: instance.port,
+                    addresses,
+                    txt: instance.txt.clone(),
+                    interface: instance.interface.clone(),
+                    first_seen: instance.first_seen,
+                    last_seen: instance.last_seen,
+                }
+            })
+            .collect();
+        services.sort_by(|a, b| (&a.service_type, &a.name).cmp(&(&b.service_type, &b.name)));
+        services
+    }
+
+    pub fn service(&self, id: &str) -> Option<DiscoveredService> {
+        self.services().into_iter().find(|s| s.id == id)
+    }
+
+    /// Count of services per type.
+    pub fn type_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for service in self.services() {
+            *counts.entry(service.service_type).or_default() += 1;
+        }
+        counts
+    }
+}
+
+fn add_host(state: &mut State, record: &MdnsRecord, ip: IpAddr) {
+    if record.ttl == 0 {
+        if let Some(host) = state.hosts.get_mut(&record.name) {
+            host.addrs.remove(&ip);
+        }
+        return;
+    }
+    let host = state
+        .hosts
+        .entry(record.name.clone())
+        .or_insert_with(|| Host { addrs: BTreeSet::new(), expires: Instant::now() });
+    host.addrs.insert(ip);
+    host.expires = host.expires.max(lifetime(record.ttl));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, ttl: u32, data: RecordData) -> MdnsRecord {
+        MdnsRecord { name: name.to_string(), ttl, data }
+    }
+
+    #[test]
+    fn test_ingest() {
+        let discovery = Discovery::default();
+        let nas = Ipv4Addr::new(192, 168, 20, 10);
+        discovery.ingest(
+            &[
+                record(META_QUERY, 4500, RecordData::Ptr("_http._tcp.local".to_string())),
+                record("_http._tcp.local", 4500, RecordData::Ptr("My NAS._http._tcp.local".to_string())),
+                record("my nas._http._tcp.local", 120, RecordData::Srv { port: 5000, target: "nas.local".to_string() }),
+                record("my nas._http._tcp.local", 4500, RecordData::Txt(vec!["path=/".to_string()])),
+                record("nas.local", 120, RecordData::A(nas)),
+            ],
+            nas,
+            "vlan20",
+        );
+        assert_eq!(discovery.browse_names(), vec![META_QUERY.to_string(), "_http._tcp.local".to_string()]);
+
+        let services = discovery.services();
+        assert_eq!(services.len(), 1);
+        let nas_service = &services[0];
+        assert_eq!(nas_service.name, "my nas");
+        assert_eq!(nas_service.service_type, "_http._tcp");
+        assert_eq!((nas_service.host.as_deref(), nas_service.port), (Some("nas.local"), Some(5000)));
+        assert_eq!(nas_service.addresses, vec!["192.168.20.10"]);
+        assert_eq!(nas_service.txt, vec!["path=/"]);
+        assert!(nas_service.web);
+        assert_eq!(nas_service.id, service_id("my nas._http._tcp.local"));
+        assert!(discovery.service(&nas_service.id).is_some());
+
+        // SRV answer alone: a printer, not a web service
+        discovery.ingest(
+            &[record("office._ipp._tcp.local", 120, RecordData::Srv { port: 631, target: "printer.local".to_string() })],
+            Ipv4Addr::new(192, 168, 1, 40),
+            "br-lan",
+        );
+        let printer = discovery.services().into_iter().find(|s| s.service_type == "_ipp._tcp").unwrap();
+        assert_eq!(printer.addresses, vec!["192.168.1.40"]);
+        assert!(!printer.web);
+        assert_eq!(discovery.type_counts().get("_ipp._tcp"), Some(&1));
+
+        // Goodbye
+        discovery.ingest(
+            &[record("_http._tcp.local", 0, RecordData::Ptr("My NAS._http._tcp.local".to_string()))],
+            nas,
+            "vlan20",
+        );
+        assert_eq!(discovery.services().len(), 1);
+    }
+}
@@ -0,0 +1,113 @@
+//! mDNS/SSDP reflector for segmented networks: repeats discovery traffic
+//! between VLANs, filtered by per-device pairing rules, so casting and DLNA
+//! keep working without flattening the network. It also answers for its
+//! own `.local` name and keeps a registry of the announced services.
+
+pub mod config;
+pub mod discovery;
+pub mod mdns;
+pub mod rules;
+pub mod server;
+pub mod ssdp;
+
+pub use config::{PairingRule, ReflectorConfig};
+pub use discovery::DiscoveredService;
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+
+/// Interface currently bridged by the reflector.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InterfaceStatus {
+    pub name: String,
+    pub address: String,
+    pub subnet: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReflectorStatus {
+    pub running: bool,
+    pub interfaces: Vec<InterfaceStatus>,
+    pub mdns_reflected: u64,
+    pub ssdp_reflected: u64,
+    /// Packets not reflected because no pairing rule allowed them.
+    pub filtered: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    pub mdns_reflected: AtomicU64,
+    pub ssdp_reflected: AtomicU64,
+    pub filtered: AtomicU64,
+}
+
+/// Shared reflector handle: the API pushes config, the supervised
+/// `reflector` service rebuilds its sockets against it.
+pub struct Reflector {
+    config: watch::Sender<ReflectorConfig>,
+    status: RwLock<ReflectorStatus>,
+    pub(crate) counters: Counters,
+    pub(crate) discovery: discovery::Discovery,
+}
+
+impl Reflector {
+    pub fn new(config: ReflectorConfig) -> Self {
+        Self {
+            config: watch::channel(config).0,
+            status: RwLock::new(ReflectorStatus::default()),
+            counters: Counters::default(),
+            discovery: discovery::Discovery::default(),
+        }
+    }
+
+    /// Current configuration.
+    pub fn config(&self) -> ReflectorConfig {
+        self.config.borrow().clone()
+    }
+
+    /// Replace the configuration; sockets are rebuilt.
+    pub fn apply(&self, config: ReflectorConfig) {
+        self.config.send_replace(config);
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<ReflectorConfig> {
+        self.config.subscribe()
+    }
+
+    pub fn status(&self) -> ReflectorStatus {
+        let mut status = self.status.read().unwrap().clone();
+        status.mdns_reflected = self.counters.mdns_reflected.load(Ordering::Relaxed);
+        status.ssdp_reflected = self.counters.ssdp_reflected.load(Ordering::Relaxed);
+        status.filtered = self.counters.filtered.load(Ordering::Relaxed);
+        status
+    }
+
+    /// Services announced on the bridged networks.
+    pub fn discovered(&self) -> Vec<DiscoveredService> {
+        self.discovery.services()
+    }
+
+    pub fn discovered_service(&self, id: &str) -> Option<DiscoveredService> {
+        self.discovery.service(id)
+    }
+
+    /// Number of discovered services per type.
+    pub fn discovered_types(&self) -> std::collections::BTreeMap<String, usize> {
+        self.discovery.type_counts()
+    }
+
+    pub(crate) fn set_status(&self, running: bool, interfaces: Vec<InterfaceStatus>, error: Option<String>) {
+        let mut status = self.status.write().unwrap();
+        status.running = running;
+        status.interfaces = interfaces;
+        status.last_error = error;
+    }
+}
+
+pub type SharedReflector = Arc<Reflector>;
@@ -0,0 +1,288 @@
+//! Minimal mDNS packet inspection: query/response flag, service names, and
+//! clearing the "unicast response" bit so answers come back by multicast.
+//! Also the records used by service discovery and the few messages the
+//! reflector sends itself (browse queries, answers for its own name).
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use hr_dns::packet::{encode_name, parse_name};
+
+pub const MDNS_GROUP: std::net::Ipv4Addr = std::net::Ipv4Addr::new(224, 0, 0, 251);
+pub const MDNS_PORT: u16 = 5353;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_SRV: u16 = 33;
+pub const TYPE_ANY: u16 = 255;
+
+/// Class IN with the cache-flush bit (unique records).
+const CLASS_IN_FLUSH: u16 = 0x8001;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MdnsInfo {
+    pub is_response: bool,
+    /// Service names found in questions and records (`_x._tcp.local` and
+    /// instances of it). Empty for plain host lookups.
+    pub services: Vec<String>,
+    /// Questions (name, type).
+    pub questions: Vec<(String, u16)>,
+    /// Offsets of the question classes, for `clear_unicast_bits`.
+    qclass_offsets: Vec<usize>,
+}
+
+fn is_service_name(name: &str) -> bool {
+    name.split('.').any(|label| label.starts_with('_'))
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*buf.get(offset)?, *buf.get(offset + 1)?]))
+}
+
+/// Parse the sections of an mDNS message. `None` if it is malformed.
+pub fn inspect(buf: &[u8]) -> Option<MdnsInfo> {
+    if buf.len() < 12 {
+        return None;
+    }
+    let flags = read_u16(buf, 2)?;
+    let counts = [read_u16(buf, 4)?, read_u16(buf, 6)?, read_u16(buf, 8)?, read_u16(buf, 10)?];
+    let mut offset = 12;
+    let mut services = Vec::new();
+    let mut qclass_offsets = Vec::new();
+    let mut questions = Vec::new();
+
+    for _ in 0..counts[0] {
+        let (name, end) = parse_name(buf, offset).ok()?;
+        let qtype = read_u16(buf, end)?;
+        read_u16(buf, end + 2)?;
+        qclass_offsets.push(end + 2);
+        offset = end + 4;
+        if is_service_name(&name) && !services.contains(&name) {
+            services.push(name.clone());
+        }
+        questions.push((name, qtype));
+    }
+    let records = counts[1] as usize + counts[2] as usize + counts[3] as usize;
+    for _ in 0..records {
+        let (name, end) = parse_name(buf, offset).ok()?;
+        let rdlen = read_u16(buf, end + 8)? as usize;
+        offset = end + 10 + rdlen;
+        if offset > buf.len() {
+            return None;
+        }
+        if is_service_name(&name) && !services.contains(&name) {
+            services.push(name);
+        }
+    }
+
+    Some(MdnsInfo {
+        is_response: flags & 0x8000 != 0,
+        services,
+        questions,
+        qclass_offsets,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordData {
+    Ptr(String),
+    Srv { port: u16, target: String },
+    /// `key=value` strings.
+    Txt(Vec<String>),
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Other,
+}
+
+/// A resource record of an mDNS answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MdnsRecord {
+    pub name: String,
+    /// 0 is a "goodbye": the record is withdrawn.
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(buf.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn parse_rdata(buf: &[u8], rtype: u16, start: usize, len: usize) -> Option<RecordData> {
+    let rdata = buf.get(start..start + len)?;
+    Some(match rtype {
+        TYPE_PTR => RecordData::Ptr(parse_name(buf, start).ok()?.0),
+        TYPE_SRV => RecordData::Srv {
+            port: read_u16(buf, start + 4)?,
+            target: parse_name(buf, start + 6).ok()?.0,
+        },
+        TYPE_TXT => {
+            let mut strings = Vec::new();
+            let mut pos = 0;
+            while pos < rdata.len() {
+                let end = (pos + 1 + rdata[pos] as usize).min(rdata.len());
+                if end > pos + 1 {
+                    strings.push(String::from_utf8_lossy(&rdata[pos + 1..end]).into_owned());
+                }
+                pos = end;
+            }
+            RecordData::Txt(strings)
+        }
+        TYPE_A if len == 4 => RecordData::A(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3])),
+        TYPE_AAAA if len == 16 => RecordData::Aaaa(Ipv6Addr::from(<[u8; 16]>::try_from(rdata).ok()?)),
+        _ => RecordData::Other,
+    })
+}
+
+/// Records of every section (answers, authority, additional). `None` if
+/// the message is malformed.
+pub fn records(buf: &[u8]) -> Option<Vec<MdnsRecord>> {
+    let counts = [read_u16(buf, 4)?, read_u16(buf, 6)?, read_u16(buf, 8)?, read_u16(buf, 10)?];
+    let mut offset = 12;
+    for _ in 0..counts[0] {
+        offset = parse_name(buf, offset).ok()?.1 + 4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..counts[1] as usize + counts[2] as usize + counts[3] as usize {
+        let (name, end) = parse_name(buf, offset).ok()?;
+        let rtype = read_u16(buf, end)?;
+        let ttl = read_u32(buf, end + 4)?;
+        let len = read_u16(buf, end + 8)? as usize;
+        let data = parse_rdata(buf, rtype, end + 10, len)?;
+        offset = end + 10 + len;
+        records.push(MdnsRecord { name: name.to_ascii_lowercase(), ttl, data });
+    }
+    Some(records)
+}
+
+/// Query asking for the PTR records of `names` (multicast answers).
+pub fn encode_browse(names: &[String]) -> Vec<u8> {
+    let mut buf = vec![0, 0, 0, 0];
+    buf.extend_from_slice(&(names.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&[0; 6]);
+    for name in names {
+        encode_name(name, &mut buf);
+        buf.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        buf.extend_from_slice(&1u16.to_be_bytes());
+    }
+    buf
+}
+
+/// Authoritative answer `name A ip`.
+pub fn encode_host_answer(name: &str, ip: Ipv4Addr, ttl: u32) -> Vec<u8> {
+    let mut buf = vec![0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+    encode_name(name, &mut buf);
+    buf.extend_from_slice(&TYPE_A.to_be_bytes());
+    buf.extend_from_slice(&CLASS_IN_FLUSH.to_be_bytes());
+    buf.extend_from_slice(&ttl.to_be_bytes());
+    buf.extend_from_slice(&4u16.to_be_bytes());
+    buf.extend_from_slice(&ip.octets());
+    buf
+}
+
+impl MdnsInfo {
+    /// Clear the QU bit of every question: the reflector can't relay
+    /// unicast answers, so ask for multicast ones.
+    pub fn clear_unicast_bits(&self, buf: &mut [u8]) {
+        for &offset in &self.qclass_offsets {
+            buf[offset] &= 0x7F;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn question(name: &str, qtype: u16, qclass: u16) -> Vec<u8> {
+        let mut buf = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        hr_dns::packet::encode_name(name, &mut buf);
+        buf.extend_from_slice(&qtype.to_be_bytes());
+        buf.extend_from_slice(&qclass.to_be_bytes());
+        buf
+    }
+
+    #[test]
+    fn test_query_with_qu_bit() {
+        let mut buf = question("_googlecast._tcp.local", 12, 0x8001);
+        let info = inspect(&buf).unwrap();
+        assert!(!info.is_response);
+        assert_eq!(info.services, vec!["_googlecast._tcp.local"]);
+        info.clear_unicast_bits(&mut buf);
+        assert_eq!(&buf[buf.len() - 2..], &[0x00, 0x01]);
+    }
+
+    #[test]
+    fn test_response_records() {
+        // Response: PTR _airplay._tcp.local → (rdata skipped) and an A record
+        let mut buf = vec![0, 0, 0x84, 0, 0, 0, 0, 2, 0, 0, 0, 0];
+        hr_dns::packet::encode_name("_airplay._tcp.local", &mut buf);
+        buf.extend_from_slice(&[0, 12, 0, 1, 0, 0, 0x11, 0x94, 0, 2, 0xC0, 12]);
+        hr_dns::packet::encode_name("tv.local", &mut buf);
+        buf.extend_from_slice(&[0, 1, 0x80, 1, 0, 0, 0, 120, 0, 4, 192, 168, 20, 50]);
+
+        let info = inspect(&buf).unwrap();
+        assert!(info.is_response);
+        assert_eq!(info.services, vec!["_airplay._tcp.local"]);
+
+        // Host lookups carry no service
+        let info = inspect(&question("tv.local", 1, 1)).unwrap();
+        assert!(info.services.is_empty());
+    }
+
+    #[test]
+    fn test_records() {
+        // Chromecast answer: PTR, SRV and TXT of the instance, A of its host
+        let mut buf = vec![0, 0, 0x84, 0, 0, 0, 0, 4, 0, 0, 0, 0];
+        hr_dns::packet::encode_name("_googlecast._tcp.local", &mut buf);
+        let mut rdata = Vec::new();
+        hr_dns::packet::encode_name("TV._googlecast._tcp.local", &mut rdata);
+        buf.extend_from_slice(&[0, 12, 0, 1, 0, 0, 0x11, 0x94, 0, rdata.len() as u8]);
+        buf.extend_from_slice(&rdata);
+        hr_dns::packet::encode_name("TV._googlecast._tcp.local", &mut buf);
+        let mut rdata = vec![0, 0, 0, 0, 0x1f, 0x49];
+        hr_dns::packet::encode_name("tv-1.local", &mut rdata);
+        buf.extend_from_slice(&[0, 33, 0x80, 1, 0, 0, 0, 120, 0, rdata.len() as u8]);
+        buf.extend_from_slice(&rdata);
+        hr_dns::packet::encode_name("TV._googlecast._tcp.local", &mut buf);
+        buf.extend_from_slice(&[0, 16, 0x80, 1, 0, 0, 0, 120, 0, 9, 3, b'a', b'=', b'1', 4, b'f', b'n', b'=', b'x']);
+        hr_dns::packet::encode_name("tv-1.local", &mut buf);
+        buf.extend_from_slice(&[0, 1, 0x80, 1, 0, 0, 0, 0, 0, 4, 192, 168, 20, 50]);
+
+        let records = records(&buf).unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].data, RecordData::Ptr("TV._googlecast._tcp.local".to_string()));
+        assert_eq!(records[1].name, "tv._googlecast._tcp.local");
+        assert_eq!(records[1].data, RecordData::Srv { port: 8009, target: "tv-1.local".to_string() });
+        assert_eq!(records[2].data, RecordData::Txt(vec!["a=1".to_string(), "fn=x".to_string()]));
+        assert_eq!(records[3].ttl, 0);
+        assert_eq!(records[3].data, RecordData::A(Ipv4Addr::new(192, 168, 20, 50)));
+        assert!(super::records(&buf[..buf.len() - 2]).is_none());
+    }
+
+    #[test]
+    fn test_encode() {
+        let query = encode_browse(&["_services._dns-sd._udp.local".to_string(), "_http._tcp.local".to_string()]);
+        let info = inspect(&query).unwrap();
+        assert!(!info.is_response);
+        assert_eq!(info.questions[1], ("_http._tcp.local".to_string(), TYPE_PTR));
+
+        let answer = encode_host_answer("homeroute.local", Ipv4Addr::new(192, 168, 1, 1), 120);
+        assert!(inspect(&answer).unwrap().is_response);
+        assert_eq!(
+            records(&answer).unwrap(),
+            vec![MdnsRecord {
+                name: "homeroute.local".to_string(),
+                ttl: 120,
+                data: RecordData::A(Ipv4Addr::new(192, 168, 1, 1)),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        let buf = question("_googlecast._tcp.local", 12, 1);
+        assert!(inspect(&buf[..buf.len() - 3]).is_none());
+        assert!(inspect(&buf[..8]).is_none());
+    }
+}
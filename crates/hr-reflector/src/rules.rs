@@ -0,0 +1,154 @@
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+use crate::config::ReflectorConfig;
+
+/// Parse IPs or CIDRs (bare addresses are treated as /32 or /128).
+pub fn parse_nets(entries: &[String]) -> Result<Vec<IpNet>, String> {
+    let mut nets = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+        let net = match entry.parse::<IpNet>() {
+            Ok(net) => net,
+            Err(_) => entry
+                .parse::<IpAddr>()
+                .map(IpNet::from)
+                .map_err(|_| format!("adresse invalide: {}", entry))?,
+        };
+        nets.push(net);
+    }
+    Ok(nets)
+}
+
+fn overlaps(a: &IpNet, b: &IpNet) -> bool {
+    a.contains(&b.network()) || b.contains(&a.network())
+}
+
+struct CompiledRule {
+    clients: Vec<IpNet>,
+    devices: Vec<IpNet>,
+    /// Lowercased service patterns.
+    services: Vec<String>,
+}
+
+impl CompiledRule {
+    /// A packet naming no service (host lookups, `ssdp:all`) matches any rule;
+    /// otherwise one of its services must contain one of the rule's patterns.
+    fn matches_services(&self, services: &[String]) -> bool {
+        self.services.is_empty()
+            || services.is_empty()
+            || services.iter().any(|s| {
+                let s = s.to_ascii_lowercase();
+                self.services.iter().any(|p| s.contains(p.as_str()))
+            })
+    }
+}
+
+/// Pairing rules compiled for per-packet decisions.
+pub struct PairingTable {
+    rules: Vec<CompiledRule>,
+    reflect_unpaired: bool,
+}
+
+impl PairingTable {
+    pub fn compile(config: &ReflectorConfig) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for rule in config.rules.iter().filter(|r| r.enabled) {
+            rules.push(CompiledRule {
+                clients: parse_nets(&rule.clients)?,
+                devices: parse_nets(&rule.devices)?,
+                services: rule.services.iter().map(|s| s.trim().to_ascii_lowercase()).collect(),
+            });
+        }
+        Ok(Self {
+            rules,
+            reflect_unpaired: config.reflect_unpaired,
+        })
+    }
+
+    /// A query from `client` may be repeated on the `egress` network if a rule
+    /// pairs that client with devices living there.
+    pub fn query_allowed(&self, client: IpAddr, services: &[String], egress: &IpNet) -> bool {
+        self.reflect_unpaired
+            || self.rules.iter().any(|r| {
+                r.clients.iter().any(|n| n.contains(&client))
+                    && r.devices.iter().any(|n| overlaps(n, egress))
+                    && r.matches_services(services)
+            })
+    }
+
+    /// An announcement/response from `device` may be repeated on the `egress`
+    /// network if a rule pairs that device with clients living there.
+    pub fn announce_allowed(&self, device: IpAddr, services: &[String], egress: &IpNet) -> bool {
+        self.reflect_unpaired
+            || self.rules.iter().any(|r| {
+                r.devices.iter().any(|n| n.contains(&device))
+                    && r.clients.iter().any(|n| overlaps(n, egress))
+                    && r.matches_services(services)
+            })
+    }
+
+    /// A unicast reply from `device` may be relayed back to `client`.
+    pub fn pair_allowed(&self, client: IpAddr, device: IpAddr, services: &[String]) -> bool {
+        self.reflect_unpaired
+            || self.rules.iter().any(|r| {
+                r.clients.iter().any(|n| n.contains(&client))
+                    && r.devices.iter().any(|n| n.contains(&device))
+                    && r.matches_services(services)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(json: &str) -> PairingTable {
+        let config: ReflectorConfig = serde_json::from_str(json).unwrap();
+        PairingTable::compile(&config).unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn net(s: &str) -> IpNet {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_phone_may_discover_tv() {
+        let t = table(
+            r#"{"rules":[{"id":"cast","clients":["192.168.1.23"],"devices":["192.168.20.50"],
+                "services":["_googlecast._tcp"]}]}"#,
+        );
+        let lan = net("192.168.1.0/24");
+        let iot = net("192.168.20.0/24");
+        let cast = vec!["_googlecast._tcp.local".to_string()];
+
+        assert!(t.query_allowed(ip("192.168.1.23"), &cast, &iot));
+        assert!(!t.query_allowed(ip("192.168.1.24"), &cast, &iot));
+        assert!(!t.query_allowed(ip("192.168.1.23"), &["_airplay._tcp.local".to_string()], &iot));
+        // Host lookups carry no service name
+        assert!(t.query_allowed(ip("192.168.1.23"), &[], &iot));
+
+        assert!(t.announce_allowed(ip("192.168.20.50"), &["tv._googlecast._tcp.local".to_string()], &lan));
+        assert!(!t.announce_allowed(ip("192.168.20.51"), &cast, &lan));
+        assert!(!t.announce_allowed(ip("192.168.20.50"), &cast, &net("192.168.30.0/24")));
+
+        assert!(t.pair_allowed(ip("192.168.1.23"), ip("192.168.20.50"), &[]));
+        assert!(!t.pair_allowed(ip("192.168.1.23"), ip("192.168.20.51"), &[]));
+    }
+
+    #[test]
+    fn test_disabled_rule_and_unpaired() {
+        let t = table(r#"{"rules":[{"id":"a","clients":["10.0.0.0/8"],"devices":["10.0.0.0/8"],"enabled":false}]}"#);
+        assert!(!t.query_allowed(ip("10.0.0.1"), &[], &net("10.1.0.0/16")));
+
+        let t = table(r#"{"reflect_unpaired":true}"#);
+        assert!(t.query_allowed(ip("10.0.0.1"), &[], &net("10.1.0.0/16")));
+    }
+}
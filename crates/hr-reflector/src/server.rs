@@ -0,0 +1,382 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use ipnet::{IpNet, Ipv4Net};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+use crate::config::ReflectorConfig;
+use crate::mdns::{self, MDNS_GROUP, MDNS_PORT};
+use crate::rules::PairingTable;
+use crate::ssdp::{self, SsdpMessage, SSDP_GROUP, SSDP_PORT};
+use crate::{InterfaceStatus, Reflector};
+
+/// Delay before rebuilding sockets after a failure (interface down...).
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Services are asked for this often (they also announce themselves).
+const BROWSE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// TTL of the responder's answers (RFC 6762 host records).
+const HOST_TTL: u32 = 120;
+
+/// One bridged interface with its multicast sockets.
+struct Net {
+    name: String,
+    addr: Ipv4Addr,
+    subnet: IpNet,
+    mdns: Option<Arc<UdpSocket>>,
+    ssdp: Option<Arc<UdpSocket>>,
+}
+
+struct Bridge {
+    reflector: Arc<Reflector>,
+    table: PairingTable,
+    nets: Vec<Net>,
+    /// `<hostname>.local` answered by the responder.
+    hostname: Option<String>,
+    discovery: bool,
+}
+
+impl Bridge {
+    /// Packets we sent ourselves on another interface.
+    fn is_own(&self, ip: &Ipv4Addr) -> bool {
+        self.nets.iter().any(|n| &n.addr == ip)
+    }
+
+    fn filtered(&self) {
+        self.reflector.counters.filtered.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Run the reflector: rebuilds every socket whenever the configuration changes.
+pub async fn run_reflector(reflector: Arc<Reflector>) -> Result<()> {
+    let mut config_rx = reflector.subscribe();
+
+    loop {
+        let config = config_rx.borrow_and_update().clone();
+        tokio::select! {
+            _ = run_config(reflector.clone(), config) => {}
+            changed = config_rx.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Serve one configuration until it is replaced (never returns on its own).
+async fn run_config(reflector: Arc<Reflector>, config: ReflectorConfig) {
+    if !config.enabled || config.interfaces.is_empty() || !(config.mdns || config.ssdp) {
+        reflector.set_status(false, Vec::new(), None);
+        return std::future::pending().await;
+    }
+
+    loop {
+        match start(reflector.clone(), &config).await {
+            Ok(mut tasks) => {
+                // Any socket failing tears the whole bridge down
+                if let Some(Ok(Err(e))) = tasks.join_next().await {
+                    warn!("Multicast reflector stopped: {:#}", e);
+                    reflector.set_status(false, Vec::new(), Some(format!("{e:#}")));
+                }
+            }
+            Err(e) => {
+                warn!("Multicast reflector failed to start: {:#}", e);
+                reflector.set_status(false, Vec::new(), Some(format!("{e:#}")));
+            }
+        }
+        tokio::time::sleep(RETRY_DELAY).await;
+    }
+}
+
+async fn start(reflector: Arc<Reflector>, config: &ReflectorConfig) -> Result<JoinSet<Result<()>>> {
+    let table = PairingTable::compile(config).map_err(anyhow::Error::msg)?;
+
+    let mut nets = Vec::new();
+    for name in &config.interfaces {
+        let (addr, subnet) = interface_ipv4(name).await?;
+        let mdns = if config.mdns {
+            Some(Arc::new(multicast_socket(name, addr, MDNS_GROUP, MDNS_PORT, 255).with_context(|| format!("mDNS socket on {}", name))?))
+        } else {
+            None
+        };
+        let ssdp = if config.ssdp {
+            Some(Arc::new(multicast_socket(name, addr, SSDP_GROUP, SSDP_PORT, 4).with_context(|| format!("SSDP socket on {}", name))?))
+        } else {
+            None
+        };
+        nets.push(Net {
+            name: name.clone(),
+            addr,
+            subnet: IpNet::V4(subnet),
+            mdns,
+            ssdp,
+        });
+    }
+
+    let interfaces: Vec<InterfaceStatus> = nets
+        .iter()
+        .map(|n| InterfaceStatus {
+            name: n.name.clone(),
+            address: n.addr.to_string(),
+            subnet: n.subnet.to_string(),
+        })
+        .collect();
+    info!(
+        "Multicast reflector bridging {} ({} pairing rules)",
+        config.interfaces.join(", "),
+        config.rules.iter().filter(|r| r.enabled).count()
+    );
+    reflector.set_status(true, interfaces, None);
+
+    let hostname = (!config.hostname.is_empty()).then(|| format!("{}.local", config.hostname.to_ascii_lowercase()));
+    let bridge = Arc::new(Bridge { reflector, table, nets, hostname, discovery: config.discovery });
+    let mut tasks = JoinSet::new();
+    for (idx, net) in bridge.nets.iter().enumerate() {
+        if net.mdns.is_some() {
+            tasks.spawn(mdns_loop(bridge.clone(), idx));
+            if bridge.discovery {
+                tasks.spawn(browse_loop(bridge.clone(), idx));
+            }
+        }
+        if net.ssdp.is_some() {
+            tasks.spawn(ssdp_loop(bridge.clone(), idx));
+        }
+    }
+    Ok(tasks)
+}
+
+/// First IPv4 address and subnet of an interface (`ip -j -4 addr show dev X`).
+async fn interface_ipv4(name: &str) -> Result<(Ipv4Addr, Ipv4Net)> {
+    let output = tokio::process::Command::new("ip")
+        .args(["-j", "-4", "addr", "show", "dev", name])
+        .output()
+        .await?;
+    if !output.status.success() {
+        anyhow::bail!("interface {} not found", name);
+    }
+    let raw: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)?;
+    raw.iter()
+        .filter_map(|iface| iface.get("addr_info")?.as_array())
+        .flatten()
+        .find_map(|a| {
+            let local: Ipv4Addr = a.get("local")?.as_str()?.parse().ok()?;
+            let prefix = a.get("prefixlen")?.as_u64()? as u8;
+            Some((local, Ipv4Net::new(local, prefix).ok()?.trunc()))
+        })
+        .with_context(|| format!("no IPv4 address on {}", name))
+}
+
+/// Socket receiving `group:port` on one interface only, and sending back
+/// out of that interface.
+fn multicast_socket(iface: &str, addr: Ipv4Addr, group: Ipv4Addr, port: u16, ttl: u32) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+    socket.bind_device(Some(iface.as_bytes()))?;
+    socket.join_multicast_v4(&group, &addr)?;
+    socket.set_multicast_if_v4(&addr)?;
+    socket.set_multicast_loop_v4(false)?;
+    socket.set_multicast_ttl_v4(ttl)?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+async fn mdns_loop(bridge: Arc<Bridge>, idx: usize) -> Result<()> {
+    let socket = bridge.nets[idx].mdns.clone().expect("mDNS socket");
+    let mut buf = vec![0u8; 9000];
+
+    loop {
+        let (len, src) = socket.recv_from(&mut buf).await?;
+        let SocketAddr::V4(src) = src else {
+            continue;
+        };
+        // Legacy unicast queries (source port ≠ 5353) expect a direct answer
+        if src.port() != MDNS_PORT || bridge.is_own(src.ip()) {
+            continue;
+        }
+        let packet = &mut buf[..len];
+        let Some(info) = mdns::inspect(packet) else {
+            debug!("Malformed mDNS packet from {}", src);
+            continue;
+        };
+        if info.is_response {
+            if bridge.discovery
+                && let Some(records) = mdns::records(packet)
+            {
+                bridge.reflector.discovery.ingest(&records, *src.ip(), &bridge.nets[idx].name);
+            }
+        } else {
+            answer_own_name(&bridge, idx, &socket, &info).await;
+            info.clear_unicast_bits(packet);
+        }
+
+        let source = IpAddr::V4(*src.ip());
+        for (i, net) in bridge.nets.iter().enumerate() {
+            if i == idx {
+                continue;
+            }
+            let allowed = if info.is_response {
+                bridge.table.announce_allowed(source, &info.services, &net.subnet)
+            } else {
+                bridge.table.query_allowed(source, &info.services, &net.subnet)
+            };
+            if !allowed {
+                bridge.filtered();
+                continue;
+            }
+            let Some(out) = &net.mdns else {
+                continue;
+            };
+            match out.send_to(packet, (MDNS_GROUP, MDNS_PORT)).await {
+                Ok(_) => {
+                    bridge.reflector.counters.mdns_reflected.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => debug!("mDNS send on {} failed: {}", net.name, e),
+            }
+        }
+    }
+}
+
+/// Responder: `<hostname>.local` is the address of the interface asked on.
+async fn answer_own_name(bridge: &Bridge, idx: usize, socket: &UdpSocket, info: &mdns::MdnsInfo) {
+    let Some(hostname) = &bridge.hostname else {
+        return;
+    };
+    let asked = info
+        .questions
+        .iter()
+        .any(|(name, qtype)| name.eq_ignore_ascii_case(hostname) && matches!(*qtype, mdns::TYPE_A | mdns::TYPE_ANY));
+    if asked {
+        let answer = mdns::encode_host_answer(hostname, bridge.nets[idx].addr, HOST_TTL);
+        if let Err(e) = socket.send_to(&answer, (MDNS_GROUP, MDNS_PORT)).await {
+            debug!("mDNS answer on {} failed: {}", bridge.nets[idx].name, e);
+        }
+    }
+}
+
+/// Ask the network of interface `idx` for its services; the answers are
+/// recorded by `mdns_loop` like any other.
+async fn browse_loop(bridge: Arc<Bridge>, idx: usize) -> Result<()> {
+    let net = &bridge.nets[idx];
+    let socket = net.mdns.clone().expect("mDNS socket");
+    // The first answers list the service types, asked for right after
+    let mut delays = [Duration::from_secs(5)].into_iter().chain(std::iter::repeat(BROWSE_INTERVAL));
+    loop {
+        let query = mdns::encode_browse(&bridge.reflector.discovery.browse_names());
+        if let Err(e) = socket.send_to(&query, (MDNS_GROUP, MDNS_PORT)).await {
+            debug!("mDNS browse on {} failed: {}", net.name, e);
+        }
+        tokio::time::sleep(delays.next().unwrap_or(BROWSE_INTERVAL)).await;
+    }
+}
+
+async fn ssdp_loop(bridge: Arc<Bridge>, idx: usize) -> Result<()> {
+    let socket = bridge.nets[idx].ssdp.clone().expect("SSDP socket");
+    let mut buf = vec![0u8; 4096];
+
+    loop {
+        let (len, src) = socket.recv_from(&mut buf).await?;
+        let SocketAddr::V4(src) = src else {
+            continue;
+        };
+        if bridge.is_own(src.ip()) {
+            continue;
+        }
+        let packet = &buf[..len];
+        let Some(msg) = ssdp::parse(packet) else {
+            continue;
+        };
+        let services = msg.services();
+        let source = IpAddr::V4(*src.ip());
+
+        for (i, net) in bridge.nets.iter().enumerate() {
+            if i == idx {
+                continue;
+            }
+            match msg {
+                SsdpMessage::Search { mx, .. } => {
+                    if !bridge.table.query_allowed(source, &services, &net.subnet) {
+                        bridge.filtered();
+                        continue;
+                    }
+                    let bridge = bridge.clone();
+                    let payload = packet.to_vec();
+                    tokio::spawn(async move {
+                        if let Err(e) = relay_search(&bridge, idx, i, src, &payload, mx).await {
+                            debug!("SSDP search relay failed: {}", e);
+                        }
+                    });
+                }
+                SsdpMessage::Notify { .. } => {
+                    if !bridge.table.announce_allowed(source, &services, &net.subnet) {
+                        bridge.filtered();
+                        continue;
+                    }
+                    let Some(out) = &net.ssdp else {
+                        continue;
+                    };
+                    match out.send_to(packet, (SSDP_GROUP, SSDP_PORT)).await {
+                        Ok(_) => {
+                            bridge.reflector.counters.ssdp_reflected.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(e) => debug!("SSDP send on {} failed: {}", net.name, e),
+                    }
+                }
+                // Unicast answers only come back on relay sockets
+                SsdpMessage::Response { .. } => break,
+            }
+        }
+    }
+}
+
+/// Repeat an M-SEARCH on another interface from a fresh socket, and pass
+/// the devices' unicast answers back to the client.
+async fn relay_search(
+    bridge: &Bridge,
+    ingress: usize,
+    egress: usize,
+    client: SocketAddrV4,
+    payload: &[u8],
+    mx: u64,
+) -> Result<()> {
+    let net = &bridge.nets[egress];
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.bind(&SocketAddr::from((net.addr, 0)).into())?;
+    socket.set_multicast_if_v4(&net.addr)?;
+    socket.set_multicast_ttl_v4(4)?;
+    socket.set_nonblocking(true)?;
+    let socket = UdpSocket::from_std(socket.into())?;
+    socket.send_to(payload, (SSDP_GROUP, SSDP_PORT)).await?;
+
+    // Devices spread their answers over MX seconds
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(mx.clamp(1, 4) + 1);
+    let Some(reply_socket) = &bridge.nets[ingress].ssdp else {
+        return Ok(());
+    };
+    let mut buf = vec![0u8; 4096];
+    loop {
+        let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await else {
+            return Ok(());
+        };
+        let (len, from) = received?;
+        let reply = &buf[..len];
+        let Some(msg @ SsdpMessage::Response { .. }) = ssdp::parse(reply) else {
+            continue;
+        };
+        if !bridge.table.pair_allowed(IpAddr::V4(*client.ip()), from.ip(), &msg.services()) {
+            bridge.filtered();
+            continue;
+        }
+        reply_socket.send_to(reply, client).await?;
+        bridge.reflector.counters.ssdp_reflected.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,89 @@
+//! SSDP message classification (HTTP-over-UDP headers).
+
+pub const SSDP_GROUP: std::net::Ipv4Addr = std::net::Ipv4Addr::new(239, 255, 255, 250);
+pub const SSDP_PORT: u16 = 1900;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SsdpMessage {
+    /// `M-SEARCH` from a client; devices answer it by unicast.
+    Search { target: Option<String>, mx: u64 },
+    /// `NOTIFY` announcement from a device.
+    Notify { target: Option<String> },
+    /// `HTTP/1.1 200 OK` unicast answer to a search.
+    Response { target: Option<String> },
+}
+
+impl SsdpMessage {
+    /// Service named by the message, for pairing rules. Wildcard searches
+    /// (`ssdp:all`) name none.
+    pub fn services(&self) -> Vec<String> {
+        let target = match self {
+            Self::Search { target, .. } | Self::Notify { target } | Self::Response { target } => target,
+        };
+        target
+            .iter()
+            .filter(|t| !t.eq_ignore_ascii_case("ssdp:all"))
+            .cloned()
+            .collect()
+    }
+}
+
+fn header<'a>(lines: &[&'a str], name: &str) -> Option<&'a str> {
+    lines.iter().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+pub fn parse(buf: &[u8]) -> Option<SsdpMessage> {
+    let text = std::str::from_utf8(buf).ok()?;
+    let lines: Vec<&str> = text.split("\r\n").flat_map(|l| l.split('\n')).collect();
+    let start = lines.first()?.trim().to_ascii_uppercase();
+    if start.starts_with("M-SEARCH ") {
+        let mx = header(&lines, "MX").and_then(|v| v.parse().ok()).unwrap_or(1);
+        Some(SsdpMessage::Search {
+            target: header(&lines, "ST").map(String::from),
+            mx,
+        })
+    } else if start.starts_with("NOTIFY ") {
+        Some(SsdpMessage::Notify {
+            target: header(&lines, "NT").map(String::from),
+        })
+    } else if start.starts_with("HTTP/1.1 200") || start.starts_with("HTTP/1.0 200") {
+        Some(SsdpMessage::Response {
+            target: header(&lines, "ST").map(String::from),
+        })
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let search = b"M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: urn:dial-multi-screen-org:service:dial:1\r\n\r\n";
+        let msg = parse(search).unwrap();
+        assert_eq!(
+            msg,
+            SsdpMessage::Search {
+                target: Some("urn:dial-multi-screen-org:service:dial:1".to_string()),
+                mx: 2
+            }
+        );
+        assert_eq!(msg.services().len(), 1);
+
+        let all = b"M-SEARCH * HTTP/1.1\r\nst: ssdp:all\r\n\r\n";
+        assert!(parse(all).unwrap().services().is_empty());
+
+        let notify = b"NOTIFY * HTTP/1.1\r\nNT: urn:schemas-upnp-org:device:MediaRenderer:1\r\nNTS: ssdp:alive\r\n\r\n";
+        assert!(matches!(parse(notify), Some(SsdpMessage::Notify { .. })));
+
+        let response = b"HTTP/1.1 200 OK\r\nST: upnp:rootdevice\r\nLOCATION: http://192.168.20.50:8008/ssdp/device-desc.xml\r\n\r\n";
+        assert!(matches!(parse(response), Some(SsdpMessage::Response { .. })));
+
+        assert!(parse(b"GET / HTTP/1.1\r\n\r\n").is_none());
+    }
+}
@@ -0,0 +1,74 @@
+//! Release signing of the agent binaries, run on the release machine: the
+//! private key never goes on the router.
+//!
+//! ```text
+//! hr-sign-update keygen release.pk8
+//! hr-sign-update sign release.pk8 hr-host-agent aarch64 1.4.0 target/aarch64/hr-host-agent
+//! ```
+//!
+//! `keygen` prints the public key to install as
+//! `/opt/homeroute/data/update-key.pub`. `sign` writes
+//! `{binary}.manifest.json`, uploaded with the binary to
+//! `PUT /api/updates/agents/{component}/{arch}`. Agents refuse a version
+//! that is not newer than theirs unless it is signed with
+//! `--allow-downgrade` (rolling back a broken release).
+
+use std::path::Path;
+use std::process::ExitCode;
+
+use hr_registry::binaries::{normalize_arch, COMPONENTS};
+use hr_registry::protocol::PROTOCOL_VERSION;
+use hr_registry::update::{UpdateManifest, UpdateSigner};
+
+const USAGE: &str = "usage:
+  hr-sign-update keygen <key.pk8>
+  hr-sign-update sign [--allow-downgrade] <key.pk8> <component> <arch> <version> <binary> [min_protocol]";
+
+fn run(args: &[String]) -> Result<(), String> {
+    let allow_downgrade = args.iter().any(|a| a == "--allow-downgrade");
+    let args: Vec<String> = args.iter().filter(|a| *a != "--allow-downgrade").cloned().collect();
+    match args.as_slice() {
+        [command, key] if command == "keygen" => {
+            let signer = UpdateSigner::generate(Path::new(key))?;
+            println!("{}", signer.public_key_hex());
+            Ok(())
+        }
+        [command, key, component, arch, version, binary, rest @ ..] if command == "sign" && rest.len() <= 1 => {
+            if !COMPONENTS.contains(&component.as_str()) {
+                return Err(format!("Unknown component: {}", component));
+            }
+            let arch = normalize_arch(arch).ok_or_else(|| format!("Unknown architecture: {}", arch))?;
+            let min_protocol = match rest.first() {
+                Some(p) => p.parse().map_err(|_| format!("Invalid protocol: {}", p))?,
+                None => PROTOCOL_VERSION,
+            };
+            let data = std::fs::read(binary).map_err(|e| format!("Failed to read {}: {}", binary, e))?;
+            let signer = UpdateSigner::load(Path::new(key))?;
+            let signed = signer.sign(&UpdateManifest {
+                component: component.clone(),
+                version: version.clone(),
+                arch: arch.to_string(),
+                sha256: hex::encode(ring::digest::digest(&ring::digest::SHA256, &data).as_ref()),
+                min_protocol,
+                allow_downgrade,
+            });
+            let path = format!("{}.manifest.json", binary);
+            let json = serde_json::to_string_pretty(&signed).map_err(|e| e.to_string())?;
+            std::fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+            println!("{}", path);
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! Agent binaries, one per component and architecture, stored as
+//! `{dir}/{arch}/{component}` with their SHA-256 (`{component}.sha256`) and
+//! the manifest signed at release time (`{component}.manifest.json`) next
+//! to them. A binary left at the top of the directory by older installs
+//! stands for the server's own architecture.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::update::SignedManifest;
+
+pub const BINARIES_DIR: &str = "/opt/homeroute/data/agent-binaries";
+pub const ARCHES: &[&str] = &["x86_64", "aarch64", "armv7"];
+pub const COMPONENTS: &[&str] = &["hr-agent", "hr-host-agent"];
+
+/// Canonical name of an architecture, as reported by `uname -m`, Debian or
+/// Rust (`arm64`, `armhf`, `arm`…).
+pub fn normalize_arch(arch: &str) -> Option<&'static str> {
+    match arch {
+        "x86_64" | "amd64" => Some("x86_64"),
+        "aarch64" | "arm64" => Some("aarch64"),
+        "armv7" | "armv7l" | "armhf" | "arm" => Some("armv7"),
+        _ => None,
+    }
+}
+
+/// Architecture of the running binary.
+pub fn local_arch() -> &'static str {
+    normalize_arch(std::env::consts::ARCH).unwrap_or(std::env::consts::ARCH)
+}
+
+/// ELF machine of each architecture.
+fn elf_machine(arch: &str) -> Option<u16> {
+    match arch {
+        "x86_64" => Some(0x3e),
+        "aarch64" => Some(0xb7),
+        "armv7" => Some(0x28),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Artifact {
+    pub component: String,
+    pub arch: String,
+    pub size: u64,
+    pub sha256: String,
+    /// Version of the signed manifest, else the modification time of the
+    /// binary.
+    pub version: String,
+    /// Whether a signed manifest of this very binary is stored: only those
+    /// are pushed to the agents.
+    pub signed: bool,
+    #[serde(skip)]
+    pub manifest: Option<SignedManifest>,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+pub struct BinaryStore {
+    dir: PathBuf,
+}
+
+impl Default for BinaryStore {
+    fn default() -> Self {
+        Self::new(BINARIES_DIR)
+    }
+}
+
+fn check(component: &str, arch: &str) -> Result<(), String> {
+    if !COMPONENTS.contains(&component) {
+        return Err(format!("Unknown component: {}", component));
+    }
+    if !ARCHES.contains(&arch) {
+        return Err(format!("Unknown architecture: {}", arch));
+    }
+    Ok(())
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(ring::digest::digest(&ring::digest::SHA256, data).as_ref())
+}
+
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+fn checksum_path(path: &Path) -> PathBuf {
+    sibling(path, ".sha256")
+}
+
+fn manifest_path(path: &Path) -> PathBuf {
+    sibling(path, ".manifest.json")
+}
+
+impl BinaryStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The binary of `component` for `arch`, if there is one.
+    pub fn find(&self, component: &str, arch: &str) -> Option<PathBuf> {
+        check(component, arch).ok()?;
+        let path = self.dir.join(arch).join(component);
+        if path.is_file() {
+            return Some(path);
+        }
+        let legacy = self.dir.join(component);
+        (arch == local_arch() && legacy.is_file()).then_some(legacy)
+    }
+
+    /// Size, checksum and version of a binary. The checksum is computed and
+    /// saved when missing (binaries copied by hand).
+    pub fn artifact(&self, component: &str, arch: &str) -> Result<Option<Artifact>, String> {
+        let Some(path) = self.find(component, arch) else {
+            return Ok(None);
+        };
+        let metadata = std::fs::metadata(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let checksum_path = checksum_path(&path);
+        let sha256 = match std::fs::read_to_string(&checksum_path) {
+            Ok(sha256) if is_fresh(&checksum_path, &metadata) => sha256.trim().to_string(),
+            _ => {
+                let data = std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                let sha256 = sha256_hex(&data);
+                let _ = std::fs::write(&checksum_path, format!("{}\n", sha256));
+                sha256
+            }
+        };
+        // A manifest left from a binary replaced by hand does not count
+        let manifest = std::fs::read(manifest_path(&path))
+            .ok()
+            .and_then(|data| serde_json::from_slice::<SignedManifest>(&data).ok())
+            .and_then(|signed| Some((signed.parsed().ok()?, signed)))
+            .filter(|(manifest, _)| {
+                manifest.component == component && manifest.arch == arch && manifest.sha256.eq_ignore_ascii_case(&sha256)
+            });
+        let version = match &manifest {
+            Some((manifest, _)) => manifest.version.clone(),
+            None => metadata
+                .modified()
+                .map(|t| DateTime::<Utc>::from(t).format("%Y%m%d-%H%M%S").to_string())
+                .unwrap_or_else(|_| "unknown".to_string()),
+        };
+        Ok(Some(Artifact {
+            component: component.to_string(),
+            arch: arch.to_string(),
+            size: metadata.len(),
+            sha256,
+            version,
+            signed: manifest.is_some(),
+            manifest: manifest.map(|(_, signed)| signed),
+            path,
+        }))
+    }
+
+    /// Every stored binary.
+    pub fn list(&self) -> Vec<Artifact> {
+        COMPONENTS
+            .iter()
+            .flat_map(|component| ARCHES.iter().map(move |arch| (component, arch)))
+            .filter_map(|(component, arch)| self.artifact(component, arch).ok().flatten())
+            .collect()
+    }
+
+    /// Store a binary with its release manifest, replacing the previous
+    /// one. It must be an ELF executable for `arch`, described by a
+    /// manifest signed with `update_key`.
+    pub fn store(
+        &self,
+        component: &str,
+        arch: &str,
+        data: &[u8],
+        signed: &SignedManifest,
+        update_key: &str,
+    ) -> Result<Artifact, String> {
+        check(component, arch)?;
+        if data.len() < 20 || &data[..4] != b"\x7fELF" {
+            return Err("Not an ELF binary".to_string());
+        }
+        let machine = u16::from_le_bytes([data[18], data[19]]);
+        if Some(machine) != elf_machine(arch) {
+            return Err(format!("Binary is not built for {}", arch));
+        }
+        let manifest = signed.verify(update_key)?;
+        if manifest.component != component || manifest.arch != arch {
+            return Err(format!(
+                "Manifest is for {} {}, not {} {}",
+                manifest.component, manifest.arch, component, arch
+            ));
+        }
+        if !manifest.matches(data) {
+            return Err("Binary does not match its manifest (SHA-256)".to_string());
+        }
+        let signed = serde_json::to_vec_pretty(signed).map_err(|e| format!("Invalid manifest: {}", e))?;
+
+        let dir = self.dir.join(arch);
+        std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let path = dir.join(component);
+        let tmp = dir.join(format!(".{}.tmp", component));
+        let write = || -> std::io::Result<()> {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::write(&tmp, data)?;
+            std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o755))?;
+            std::fs::write(checksum_path(&path), format!("{}\n", sha256_hex(data)))?;
+            std::fs::write(manifest_path(&path), &signed)?;
+            std::fs::rename(&tmp, &path)
+        };
+        write().map_err(|e| {
+            let _ = std::fs::remove_file(&tmp);
+            format!("Failed to store {}: {}", path.display(), e)
+        })?;
+        self.artifact(component, arch)?.ok_or_else(|| "Binary vanished".to_string())
+    }
+
+    /// Remove the binary of `component` for `arch`. Returns whether there
+    /// was one.
+    pub fn remove(&self, component: &str, arch: &str) -> Result<bool, String> {
+        let Some(path) = self.find(component, arch) else {
+            return Ok(false);
+        };
+        std::fs::remove_file(&path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+        let _ = std::fs::remove_file(checksum_path(&path));
+        let _ = std::fs::remove_file(manifest_path(&path));
+        Ok(true)
+    }
+}
+
+/// A checksum file written after the binary was last modified.
+fn is_fresh(checksum_path: &Path, binary: &std::fs::Metadata) -> bool {
+    let written = std::fs::metadata(checksum_path).and_then(|m| m.modified());
+    matches!((written, binary.modified()), (Ok(written), Ok(modified)) if written >= modified)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::update::{UpdateManifest, UpdateSigner};
+
+    fn elf(machine: u16) -> Vec<u8> {
+        let mut data = b"\x7fELF".to_vec();
+        data.resize(18, 0);
+        data.extend_from_slice(&machine.to_le_bytes());
+        data.extend_from_slice(b"rest of the binary");
+        data
+    }
+
+    #[test]
+    fn arch_names() {
+        assert_eq!(normalize_arch("amd64"), Some("x86_64"));
+        assert_eq!(normalize_arch("arm64"), Some("aarch64"));
+        assert_eq!(normalize_arch("arm"), Some("armv7"));
+        assert_eq!(normalize_arch("riscv64"), None);
+    }
+
+    fn manifest(component: &str, arch: &str, data: &[u8]) -> UpdateManifest {
+        UpdateManifest {
+            component: component.into(),
+            version: "1.2.3".into(),
+            arch: arch.into(),
+            sha256: sha256_hex(data),
+            min_protocol: 1,
+            allow_downgrade: false,
+        }
+    }
+
+    #[test]
+    fn store_per_arch() {
+        let dir = std::env::temp_dir().join(format!("hr-binaries-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = BinaryStore::new(&dir);
+        let signer = UpdateSigner::generate(&dir.join("release.pk8")).unwrap();
+        let key = signer.public_key_hex();
+
+        let arm = elf(0xb7);
+        let signed = signer.sign(&manifest("hr-host-agent", "aarch64", &arm));
+        let artifact = store.store("hr-host-agent", "aarch64", &arm, &signed, &key).unwrap();
+        assert_eq!(artifact.sha256, sha256_hex(&arm));
+        assert_eq!(artifact.size, arm.len() as u64);
+        assert_eq!(artifact.version, "1.2.3");
+        assert!(artifact.signed);
+        assert_eq!(artifact.manifest.unwrap().signature, signed.signature);
+        assert!(store.find("hr-host-agent", "aarch64").is_some());
+        assert!(store.find("hr-host-agent", "armv7").is_none());
+
+        // Wrong machine, not ELF, unknown names
+        assert!(store.store("hr-host-agent", "armv7", &arm, &signed, &key).is_err());
+        assert!(store.store("hr-agent", "x86_64", b"#!/bin/sh\nexit 0\n", &signed, &key).is_err());
+        assert!(store.store("other", "aarch64", &arm, &signed, &key).is_err());
+        assert!(store.store("hr-agent", "mips", &arm, &signed, &key).is_err());
+
+        // Manifest of another binary or component, or signed by another key
+        let other = elf(0xb7).into_iter().chain(*b"patched").collect::<Vec<_>>();
+        assert!(store.store("hr-host-agent", "aarch64", &other, &signed, &key).is_err());
+        let for_agent = signer.sign(&manifest("hr-agent", "aarch64", &arm));
+        assert!(store.store("hr-host-agent", "aarch64", &arm, &for_agent, &key).is_err());
+        let rogue = UpdateSigner::generate(&dir.join("rogue.pk8")).unwrap();
+        let forged = rogue.sign(&manifest("hr-host-agent", "aarch64", &other));
+        assert!(store.store("hr-host-agent", "aarch64", &other, &forged, &key).is_err());
+
+        // Binary replaced by hand: its manifest no longer applies
+        let path = store.find("hr-host-agent", "aarch64").unwrap();
+        std::fs::write(&path, &other).unwrap();
+        std::fs::remove_file(checksum_path(&path)).unwrap();
+        assert!(!store.artifact("hr-host-agent", "aarch64").unwrap().unwrap().signed);
+
+        // Binary at the top of the directory: the server's architecture
+        std::fs::write(dir.join("hr-agent"), b"legacy").unwrap();
+        let legacy = store.artifact("hr-agent", local_arch()).unwrap().unwrap();
+        assert_eq!(legacy.sha256, sha256_hex(b"legacy"));
+        assert!(!legacy.signed);
+        assert_eq!(store.list().len(), 2);
+
+        assert!(store.remove("hr-host-agent", "aarch64").unwrap());
+        assert!(!store.remove("hr-host-agent", "aarch64").unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -496,6 +496,37 @@ pub async fn switch_to_direct_dns(
     Ok(())
 }
 
+/// Check that the token is active and can read the zone.
+pub async fn verify_token(token: &str, zone_id: &str) -> Result<(), String> {
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let auth = format!("Bearer {}", token);
+
+    let resp = client
+        .get(format!("{}/user/tokens/verify", CF_API_BASE))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let body: serde_json::Value = resp.json().await.map_err(|e| e.to_string())?;
+    check_cf_errors(&body)?;
+    let status = body.pointer("/result/status").and_then(|s| s.as_str()).unwrap_or("unknown");
+    if status != "active" {
+        return Err(format!("Token is {}", status));
+    }
+
+    let resp = client
+        .get(format!("{}/zones/{}", CF_API_BASE, zone_id))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let body: serde_json::Value = resp.json().await.map_err(|e| e.to_string())?;
+    check_cf_errors(&body)
+}
+
 fn check_cf_errors(body: &serde_json::Value) -> Result<(), String> {
     if let Some(false) = body.get("success").and_then(|s| s.as_bool()) {
         let errors = body
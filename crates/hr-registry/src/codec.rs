@@ -0,0 +1,93 @@
+//! Binary framing of the host-agent WebSocket (capability `binary_frames`).
+//!
+//! Once negotiated, every message travels as one WebSocket Binary message:
+//!
+//! ```text
+//! [u32 BE header length][header: MessagePack message][payload]
+//! ```
+//!
+//! Control messages have an empty payload; transfer chunks carry their data
+//! right after their metadata, so control and data interleave on the same
+//! stream without pairing a text message with the binary frame that follows.
+//! Agents that did not announce the capability keep JSON text messages.
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+const LEN_SIZE: usize = 4;
+
+/// Encode `message` followed by `payload` (empty for control messages).
+pub fn encode<T: Serialize>(message: &T, payload: &[u8]) -> Result<Vec<u8>, String> {
+    let header = rmp_serde::to_vec_named(message).map_err(|e| format!("Encode frame header: {e}"))?;
+    let len = u32::try_from(header.len()).map_err(|_| "Frame header too large".to_string())?;
+    let mut frame = Vec::with_capacity(LEN_SIZE + header.len() + payload.len());
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(&header);
+    frame.extend_from_slice(payload);
+    Ok(frame)
+}
+
+/// Decode a frame into its message and payload.
+pub fn decode<T: DeserializeOwned>(frame: &[u8]) -> Result<(T, &[u8]), String> {
+    let Some((len, rest)) = frame.split_first_chunk::<LEN_SIZE>() else {
+        return Err("Truncated frame".to_string());
+    };
+    let len = u32::from_be_bytes(*len) as usize;
+    if rest.len() < len {
+        return Err(format!("Truncated frame header ({} of {len} bytes)", rest.len()));
+    }
+    let (header, payload) = rest.split_at(len);
+    let message = rmp_serde::from_slice(header).map_err(|e| format!("Decode frame header: {e}"))?;
+    Ok((message, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{HostAgentMessage, HostMetrics, HostRegistryMessage};
+
+    #[test]
+    fn test_roundtrip() {
+        let chunk = HostAgentMessage::TransferChunkBinary {
+            transfer_id: "t1".into(),
+            sequence: 3,
+            size: 5,
+            checksum: 42,
+            offset: 0,
+        };
+        let frame = encode(&chunk, b"hello").unwrap();
+        let (msg, payload) = decode::<HostAgentMessage>(&frame).unwrap();
+        assert_eq!(payload, b"hello");
+        match msg {
+            HostAgentMessage::TransferChunkBinary { transfer_id, sequence, .. } => {
+                assert_eq!(transfer_id, "t1");
+                assert_eq!(sequence, 3);
+            }
+            _ => panic!("wrong variant"),
+        }
+
+        let metrics = HostAgentMessage::Metrics(HostMetrics {
+            cpu_percent: 12.5,
+            memory_used_bytes: 1,
+            memory_total_bytes: 2,
+            disk_used_bytes: 3,
+            disk_total_bytes: 4,
+            load_avg: [0.5, 0.25, 0.1],
+            ..Default::default()
+        });
+        let frame = encode(&metrics, &[]).unwrap();
+        let (msg, payload) = decode::<HostAgentMessage>(&frame).unwrap();
+        assert!(payload.is_empty());
+        assert!(matches!(msg, HostAgentMessage::Metrics(m) if m.cpu_percent == 12.5));
+
+        let (msg, _) = decode::<HostRegistryMessage>(&encode(&HostRegistryMessage::PowerOff, &[]).unwrap()).unwrap();
+        assert!(matches!(msg, HostRegistryMessage::PowerOff));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let frame = encode(&HostRegistryMessage::Reboot, &[]).unwrap();
+        assert!(decode::<HostRegistryMessage>(&frame[..2]).is_err());
+        assert!(decode::<HostRegistryMessage>(&frame[..frame.len() - 1]).is_err());
+    }
+}
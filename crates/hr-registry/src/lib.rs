@@ -1,8 +1,13 @@
+pub mod binaries;
 pub mod types;
 pub mod protocol;
+pub mod codec;
+pub mod transfer;
+pub mod logs;
 pub mod state;
 pub mod cloudflare;
+pub mod update;
 
 pub use types::*;
 pub use protocol::*;
-pub use state::{AgentRegistry, HostConnection, MigrationResult, OutgoingHostMessage};
+pub use state::{AgentRegistry, HostConnection, MigrationResult, OutgoingHostMessage, generate_token, hash_token, verify_token};
@@ -0,0 +1,310 @@
+//! Live log streaming (capability `log_stream`).
+//!
+//! The registry asks an agent for the logs of a [`LogSource`] with
+//! `LogStream`. Several streams share one agent connection, told apart by
+//! their `request_id`. The agent runs `journalctl`, sends the output in
+//! `LogStreamData` batches of lines and ends with `LogStreamEnd` once the
+//! command exits; `LogStreamClose` stops a stream early.
+//!
+//! Agents read the command output only as fast as their connection drains,
+//! so a slow link holds `journalctl` back instead of piling up lines. The
+//! registry cannot hold an agent connection for one slow browser: each
+//! stream has a bounded queue ([`LogSink`]) and the lines that do not fit
+//! are dropped and counted ([`LogEvent::Dropped`]).
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::AbortHandle;
+
+/// Lines of history sent before following when a request does not say.
+pub const DEFAULT_TAIL_LINES: u32 = 200;
+/// Most lines of history a request can ask for.
+pub const MAX_TAIL_LINES: u32 = 10_000;
+/// Batches queued for one reader before lines get dropped.
+pub const SINK_CAPACITY: usize = 64;
+
+/// Most lines in one `LogStreamData`.
+const BATCH_LINES: usize = 200;
+/// Longest a line waits for its batch to fill.
+const BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Journal to stream.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LogSource {
+    /// Journal of the app container, read by its agent: one unit or all.
+    Journal {
+        #[serde(default)]
+        unit: Option<String>,
+    },
+    /// Journal of an nspawn container, read from its host (`journalctl -M`).
+    Container {
+        container_name: String,
+        #[serde(default)]
+        unit: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogRequest {
+    pub source: LogSource,
+    /// Lines of history sent first.
+    #[serde(default = "default_tail_lines")]
+    pub lines: u32,
+    /// Keep streaming new lines until closed.
+    #[serde(default)]
+    pub follow: bool,
+}
+
+fn default_tail_lines() -> u32 {
+    DEFAULT_TAIL_LINES
+}
+
+impl LogRequest {
+    /// `journalctl` arguments for the request.
+    pub fn journalctl_args(&self) -> Result<Vec<String>, String> {
+        let mut args = vec![
+            "--no-pager".to_string(),
+            "--output=short-iso".to_string(),
+            format!("--lines={}", self.lines.min(MAX_TAIL_LINES)),
+        ];
+        if self.follow {
+            args.push("--follow".to_string());
+        }
+        let unit = match &self.source {
+            LogSource::Journal { unit } => unit,
+            LogSource::Container { container_name, unit } => {
+                check_name(container_name)?;
+                args.push(format!("--machine={container_name}"));
+                unit
+            }
+        };
+        if let Some(unit) = unit {
+            check_name(unit)?;
+            args.push(format!("--unit={unit}"));
+        }
+        Ok(args)
+    }
+}
+
+/// Container and unit names: `myapp`, `app.service`, `getty@tty1.service`.
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('-')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.@:".contains(c));
+    if valid { Ok(()) } else { Err(format!("Invalid name: {name}")) }
+}
+
+/// Run `journalctl` for `request` and hand its output to `send` in batches:
+/// up to [`BATCH_LINES`] lines, or those that arrived within
+/// [`BATCH_INTERVAL`]. Stops quietly when `send` returns false (the
+/// connection is gone); otherwise returns the error to put in
+/// `LogStreamEnd`.
+pub async fn stream_journal<F, Fut>(request: &LogRequest, mut send: F) -> Result<(), String>
+where
+    F: FnMut(Vec<String>) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let args = request.journalctl_args()?;
+    let mut child = Command::new("journalctl")
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to run journalctl: {e}"))?;
+    let mut lines = BufReader::new(child.stdout.take().expect("piped stdout")).split(b'\n');
+
+    let mut batch = Vec::new();
+    let mut flush = tokio::time::interval(BATCH_INTERVAL);
+    flush.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            line = lines.next_segment() => match line {
+                Ok(Some(line)) => {
+                    batch.push(String::from_utf8_lossy(&line).into_owned());
+                    if batch.len() >= BATCH_LINES && !send(std::mem::take(&mut batch)).await {
+                        return Ok(());
+                    }
+                }
+                Ok(None) | Err(_) => break,
+            },
+            _ = flush.tick(), if !batch.is_empty() => {
+                if !send(std::mem::take(&mut batch)).await {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    if !batch.is_empty() && !send(batch).await {
+        return Ok(());
+    }
+
+    let mut stderr = String::new();
+    if let Some(mut pipe) = child.stderr.take() {
+        let _ = pipe.read_to_string(&mut stderr).await;
+    }
+    match child.wait().await {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) if stderr.trim().is_empty() => Err(format!("journalctl exited with {status}")),
+        Ok(_) => Err(stderr.trim().to_string()),
+        Err(e) => Err(format!("journalctl failed: {e}")),
+    }
+}
+
+/// Streams running on an agent, stopped on `LogStreamClose` or when the
+/// connection drops.
+#[derive(Clone, Default)]
+pub struct LogTasks(Arc<Mutex<HashMap<String, AbortHandle>>>);
+
+impl LogTasks {
+    /// Run the stream of `request_id` until it ends or is stopped.
+    pub fn spawn<F>(&self, request_id: String, stream: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        // Held while spawning so the task cannot remove itself before it is listed
+        let mut tasks = self.0.lock().unwrap();
+        let this = self.clone();
+        let id = request_id.clone();
+        let handle = tokio::spawn(async move {
+            stream.await;
+            this.0.lock().unwrap().remove(&id);
+        });
+        if let Some(previous) = tasks.insert(request_id, handle.abort_handle()) {
+            previous.abort();
+        }
+    }
+
+    pub fn stop(&self, request_id: &str) {
+        if let Some(handle) = self.0.lock().unwrap().remove(request_id) {
+            handle.abort();
+        }
+    }
+
+    pub fn stop_all(&self) {
+        for (_, handle) in self.0.lock().unwrap().drain() {
+            handle.abort();
+        }
+    }
+}
+
+/// What the reader of a stream receives, as sent to the UI.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LogEvent {
+    Lines { lines: Vec<String> },
+    /// Lines lost because the reader did not keep up.
+    Dropped { lines: u64 },
+    /// The stream failed; a stream that just ends closes the channel.
+    Error { error: String },
+}
+
+/// Registry end of a stream: queues lines for its reader without ever
+/// waiting on it.
+pub struct LogSink {
+    tx: mpsc::Sender<LogEvent>,
+    dropped: u64,
+}
+
+impl LogSink {
+    pub fn new() -> (Self, mpsc::Receiver<LogEvent>) {
+        let (tx, rx) = mpsc::channel(SINK_CAPACITY);
+        (Self { tx, dropped: 0 }, rx)
+    }
+
+    /// Queue `lines`, or count them as dropped when the queue is full.
+    /// Returns false once the reader is gone.
+    pub fn push(&mut self, lines: Vec<String>) -> bool {
+        if self.dropped > 0 {
+            match self.tx.try_send(LogEvent::Dropped { lines: self.dropped }) {
+                Ok(()) => self.dropped = 0,
+                Err(TrySendError::Full(_)) => {
+                    self.dropped += lines.len() as u64;
+                    return true;
+                }
+                Err(TrySendError::Closed(_)) => return false,
+            }
+        }
+        let count = lines.len() as u64;
+        match self.tx.try_send(LogEvent::Lines { lines }) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.dropped += count;
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+
+    /// End the stream, reporting `error` when there is room for it.
+    pub fn end(self, error: Option<String>) {
+        if let Some(error) = error {
+            let _ = self.tx.try_send(LogEvent::Error { error });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journalctl_args() {
+        let request = LogRequest {
+            source: LogSource::Container { container_name: "hr-v2-blog".into(), unit: Some("app.service".into()) },
+            lines: 50_000,
+            follow: true,
+        };
+        assert_eq!(
+            request.journalctl_args().unwrap(),
+            ["--no-pager", "--output=short-iso", "--lines=10000", "--follow", "--machine=hr-v2-blog", "--unit=app.service"]
+        );
+
+        let request: LogRequest = serde_json::from_str(r#"{"source":{"kind":"journal"}}"#).unwrap();
+        assert_eq!(request.lines, DEFAULT_TAIL_LINES);
+        assert!(!request.journalctl_args().unwrap().contains(&"--follow".to_string()));
+
+        let request = LogRequest { source: LogSource::Journal { unit: Some("-k".into()) }, lines: 10, follow: false };
+        assert!(request.journalctl_args().is_err());
+        let request = LogRequest { source: LogSource::Journal { unit: Some("a b".into()) }, lines: 10, follow: false };
+        assert!(request.journalctl_args().is_err());
+    }
+
+    #[test]
+    fn test_sink_drops_when_full() {
+        let (mut sink, mut rx) = LogSink::new();
+        for i in 0..SINK_CAPACITY {
+            assert!(sink.push(vec![format!("line {i}")]));
+        }
+        assert!(sink.push(vec!["lost".into(), "lost".into()]));
+        assert!(sink.push(vec!["lost".into()]));
+
+        // Room again: the count goes first, then the lines
+        for _ in 0..SINK_CAPACITY {
+            rx.try_recv().unwrap();
+        }
+        assert!(sink.push(vec!["kept".into()]));
+        assert_eq!(rx.try_recv().unwrap(), LogEvent::Dropped { lines: 3 });
+        assert_eq!(rx.try_recv().unwrap(), LogEvent::Lines { lines: vec!["kept".into()] });
+
+        sink.end(Some("journalctl exited".into()));
+        assert_eq!(rx.try_recv().unwrap(), LogEvent::Error { error: "journalctl exited".into() });
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_sink_reader_gone() {
+        let (mut sink, rx) = LogSink::new();
+        drop(rx);
+        assert!(!sink.push(vec!["line".into()]));
+    }
+}
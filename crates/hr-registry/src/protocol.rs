@@ -1,6 +1,134 @@
 use serde::{Deserialize, Serialize};
 
+pub use hr_container::{ContainerTemplate, ContainerUsage, HostPreflight, ResourceLimits};
+
+use crate::logs::LogRequest;
+use crate::transfer::{Compression, ResumePoint, TransferStream};
 use crate::types::{Environment, FrontendEndpoint};
+use crate::update::SignedManifest;
+
+// ── Protocol Version and Capabilities ───────────────────────────
+
+/// Version of the agent protocols, sent in `Auth` and answered in
+/// `AuthResult`. Agents predating the handshake send none: version 0.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Optional message families. Agents announce the ones they handle in
+/// `Auth`, the registry answers with those it speaks too and only sends
+/// gated messages to agents that announced them.
+pub mod capability {
+    /// `QueueRequest`/`QueueResult` (app agents).
+    pub const QUEUES: &str = "queues";
+    /// `PubSubRequest`/`PubSubResult`/`PubSubMessage` (app agents).
+    pub const PUBSUB: &str = "pubsub";
+    /// `PowerBusy` (host agents).
+    pub const POWER_BUSY: &str = "power_busy";
+    /// MessagePack frames carrying transfer chunks inline, see
+    /// [`crate::codec`] (host agents).
+    pub const BINARY_FRAMES: &str = "binary_frames";
+    /// Chunk offsets, `TransferManifest` and `ResumeTransfer`, see
+    /// [`crate::transfer`] (host agents).
+    pub const RESUMABLE_TRANSFERS: &str = "resumable_transfers";
+    /// zstd-compressed transfer streams, requested in `StartNspawnExport`
+    /// and `StartNspawnImport` (host agents).
+    pub const ZSTD_TRANSFERS: &str = "zstd_transfers";
+    /// `SetContainerLimits` and `ContainerUsage` (host agents).
+    pub const RESOURCE_LIMITS: &str = "resource_limits";
+    /// `RuntimeContainers` and `RuntimeContainerAction`: Docker/Podman
+    /// containers of the host (host agents).
+    pub const CONTAINER_RUNTIMES: &str = "container_runtimes";
+    /// `LogStream`/`LogStreamClose` answered with `LogStreamData` and
+    /// `LogStreamEnd`, see [`crate::logs`] (app and host agents).
+    pub const LOG_STREAM: &str = "log_stream";
+    /// Terminal sessions on a real pty: `TerminalResize`, and host shells
+    /// (`TerminalOpen` without a container) (host agents).
+    pub const PTY_TERMINAL: &str = "pty_terminal";
+    /// `MigrationPreflight` answered with the host's `MigrationPreflight`
+    /// report (host agents).
+    pub const MIGRATION_PREFLIGHT: &str = "migration_preflight";
+    /// `RotateToken`: the agent saves the new token for its next `Auth`
+    /// (host agents).
+    pub const TOKEN_ROTATION: &str = "token_rotation";
+    /// `MetricsBackfill`: samples taken while disconnected, sent after
+    /// `Auth` (host agents).
+    pub const METRICS_BACKFILL: &str = "metrics_backfill";
+    /// `DiskHealth`: SMART reports of the host's disks (host agents).
+    pub const DISK_HEALTH: &str = "disk_health";
+    /// `ListSystemdServices` and `SystemdServiceAction`: systemd services
+    /// of the host (host agents).
+    pub const SYSTEMD_SERVICES: &str = "systemd_services";
+    /// `PackageUpdates` reports, `CheckPackageUpdates`, and
+    /// `UpgradePackages` answered with `UpgradeOutput`/`UpgradeFinished`
+    /// (host agents).
+    pub const PACKAGE_UPDATES: &str = "package_updates";
+}
+
+/// Capabilities of app agents built from this tree.
+pub const AGENT_CAPABILITIES: &[&str] = &[capability::QUEUES, capability::PUBSUB, capability::LOG_STREAM];
+/// Capabilities of host agents built from this tree.
+pub const HOST_AGENT_CAPABILITIES: &[&str] = &[
+    capability::POWER_BUSY,
+    capability::BINARY_FRAMES,
+    capability::RESUMABLE_TRANSFERS,
+    capability::ZSTD_TRANSFERS,
+    capability::RESOURCE_LIMITS,
+    capability::CONTAINER_RUNTIMES,
+    capability::LOG_STREAM,
+    capability::PTY_TERMINAL,
+    capability::MIGRATION_PREFLIGHT,
+    capability::TOKEN_ROTATION,
+    capability::METRICS_BACKFILL,
+    capability::DISK_HEALTH,
+    capability::SYSTEMD_SERVICES,
+    capability::PACKAGE_UPDATES,
+];
+
+/// Capability list as sent in `Auth`.
+pub fn capability_list(capabilities: &[&str]) -> Vec<String> {
+    capabilities.iter().map(|c| c.to_string()).collect()
+}
+
+/// Protocol agreed with one agent connection.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Negotiated {
+    /// The agent's protocol version.
+    pub version: u32,
+    /// Capabilities both sides speak.
+    pub capabilities: Vec<String>,
+    /// Architecture reported by the agent, normalized (`x86_64`,
+    /// `aarch64`, `armv7`). `None` for agents predating it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+}
+
+impl Negotiated {
+    /// Keep the capabilities announced by the agent that `supported` lists.
+    pub fn new(version: u32, announced: &[String], supported: &[&str]) -> Self {
+        let capabilities = announced
+            .iter()
+            .filter(|c| supported.contains(&c.as_str()))
+            .cloned()
+            .collect();
+        Self { version, capabilities, arch: None }
+    }
+
+    /// Record the architecture the agent reported in `Auth`.
+    pub fn with_arch(mut self, arch: Option<&str>) -> Self {
+        self.arch = arch.and_then(crate::binaries::normalize_arch).map(str::to_string);
+        self
+    }
+
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+
+    /// Whether the agent speaks an older protocol than the registry and
+    /// should be upgraded.
+    pub fn outdated(&self) -> bool {
+        self.version < PROTOCOL_VERSION
+    }
+}
 
 // ── Shared Types ────────────────────────────────────────────────
 
@@ -93,6 +221,21 @@ pub enum AgentMessage {
         /// Agent's IPv4 address (for local DNS A records).
         #[serde(default)]
         ipv4_address: Option<String>,
+        #[serde(default)]
+        protocol_version: u32,
+        #[serde(default)]
+        capabilities: Vec<String>,
+        /// Architecture of the agent binary, selecting the updates it gets.
+        #[serde(default)]
+        arch: Option<String>,
+    },
+    /// First connection of an agent installed without credentials: sent
+    /// instead of `Auth`, answered with `EnrollResult`, then the registry
+    /// closes the connection.
+    #[serde(rename = "enroll")]
+    Enroll {
+        join_code: String,
+        version: String,
     },
     /// Periodic health report.
     #[serde(rename = "heartbeat")]
@@ -147,6 +290,31 @@ pub enum AgentMessage {
     GetDataverseSchemas {
         request_id: String,
     },
+    /// Queue operation for the app, answered with `QueueResult`.
+    #[serde(rename = "queue_request")]
+    QueueRequest {
+        request_id: String,
+        request: QueueRequest,
+    },
+    /// Pub/sub operation for the app, answered with `PubSubResult`.
+    #[serde(rename = "pubsub_request")]
+    PubSubRequest {
+        request_id: String,
+        request: PubSubRequest,
+    },
+    /// Batch of lines of a log stream.
+    #[serde(rename = "log_stream_data")]
+    LogStreamData {
+        request_id: String,
+        lines: Vec<String>,
+    },
+    /// The log stream is over; `error` when journalctl failed.
+    #[serde(rename = "log_stream_end")]
+    LogStreamEnd {
+        request_id: String,
+        #[serde(default)]
+        error: Option<String>,
+    },
 }
 
 /// A route published by an agent for reverse proxy registration.
@@ -200,6 +368,24 @@ pub enum RegistryMessage {
         /// The authenticated application's ID (set on success).
         #[serde(skip_serializing_if = "Option::is_none", default)]
         app_id: Option<String>,
+        /// The registry's protocol version.
+        #[serde(default)]
+        protocol_version: u32,
+        /// Capabilities agreed for this connection.
+        #[serde(default)]
+        capabilities: Vec<String>,
+    },
+    /// Response to Enroll: the agent's token and service name, to save
+    /// before connecting again with `Auth`.
+    #[serde(rename = "enroll_result")]
+    EnrollResult {
+        success: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        token: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        service_name: Option<String>,
     },
     /// Full configuration push.
     #[serde(rename = "config")]
@@ -230,12 +416,11 @@ pub enum RegistryMessage {
         #[serde(default = "default_true")]
         wake_page_enabled: bool,
     },
-    /// Agent should self-update.
+    /// Agent should self-update, if the manifest verifies with its pinned key.
     #[serde(rename = "update_available")]
     UpdateAvailable {
-        version: String,
         download_url: String,
-        sha256: String,
+        manifest: SignedManifest,
     },
     /// Graceful shutdown request.
     #[serde(rename = "shutdown")]
@@ -267,6 +452,47 @@ pub enum RegistryMessage {
         request_id: String,
         schemas: Vec<AppSchemaOverview>,
     },
+    /// Response to a QueueRequest.
+    #[serde(rename = "queue_result")]
+    QueueResult {
+        request_id: String,
+        #[serde(default)]
+        data: Option<serde_json::Value>,
+        #[serde(default)]
+        error: Option<String>,
+    },
+    /// Response to a PubSubRequest.
+    #[serde(rename = "pubsub_result")]
+    PubSubResult {
+        request_id: String,
+        #[serde(default)]
+        data: Option<serde_json::Value>,
+        #[serde(default)]
+        error: Option<String>,
+    },
+    /// Message published on a topic the app subscribed to.
+    #[serde(rename = "pubsub_message")]
+    PubSubMessage(hr_common::events::PubSubEvent),
+    /// Stream the container journal, answered with `LogStreamData`.
+    #[serde(rename = "log_stream")]
+    LogStream {
+        request_id: String,
+        request: LogRequest,
+    },
+    /// Stop a log stream (the reader went away).
+    #[serde(rename = "log_stream_close")]
+    LogStreamClose { request_id: String },
+}
+
+impl RegistryMessage {
+    /// Capability the agent must have announced to be sent this message.
+    pub fn capability(&self) -> Option<&'static str> {
+        match self {
+            Self::PubSubMessage(_) => Some(capability::PUBSUB),
+            Self::LogStream { .. } | Self::LogStreamClose { .. } => Some(capability::LOG_STREAM),
+            _ => None,
+        }
+    }
 }
 
 fn default_true() -> bool {
@@ -323,6 +549,70 @@ fn default_query_limit() -> u64 {
     100
 }
 
+// ── Queue Types ──────────────────────────────────────────────────
+
+/// An operation on one of the app's queues (see hr-queue), over the agent
+/// connection or `POST /api/queues/{queue}/{op}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum QueueRequest {
+    Send {
+        queue: String,
+        body: String,
+        #[serde(default)]
+        delay_secs: u32,
+    },
+    /// Waits up to `wait_secs` for a message when none is available.
+    Receive {
+        queue: String,
+        #[serde(default = "default_receive_max")]
+        max: u32,
+        /// Overrides the queue's visibility timeout.
+        #[serde(default)]
+        visibility_timeout_secs: Option<u32>,
+        #[serde(default)]
+        wait_secs: u32,
+    },
+    Ack {
+        queue: String,
+        receipt: String,
+    },
+    /// Give a message back, for a retry after `delay_secs`.
+    Nack {
+        queue: String,
+        receipt: String,
+        #[serde(default)]
+        delay_secs: u32,
+        #[serde(default)]
+        error: Option<String>,
+    },
+    /// Keep a received message hidden `visibility_timeout_secs` longer.
+    Extend {
+        queue: String,
+        receipt: String,
+        visibility_timeout_secs: u32,
+    },
+}
+
+/// A pub/sub operation of the app (see hr-pubsub), over the agent
+/// connection; publishing also works with `POST /api/pubsub/publish/{topic}`.
+/// Subscriptions last as long as the connection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PubSubRequest {
+    /// Topic patterns: `*` matches one segment, a trailing `>` the rest.
+    Subscribe { topics: Vec<String> },
+    Unsubscribe { topics: Vec<String> },
+    Publish {
+        topic: String,
+        payload: serde_json::Value,
+    },
+}
+
+fn default_receive_max() -> u32 {
+    1
+}
+
 /// Overview of another app's schema (for inter-app visibility).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppSchemaOverview {
@@ -355,12 +645,38 @@ pub enum HostAgentMessage {
         lan_interface: Option<String>,
         #[serde(default)]
         container_storage_path: Option<String>,
+        #[serde(default)]
+        protocol_version: u32,
+        #[serde(default)]
+        capabilities: Vec<String>,
+        /// Architecture of the agent binary, selecting the updates it gets.
+        #[serde(default)]
+        arch: Option<String>,
+    },
+    /// First connection of a host agent installed with a join code instead
+    /// of a token: sent in place of `Auth`, answered with `EnrollResult`.
+    Enroll {
+        join_code: String,
+        /// Name asked for; the join code may impose another.
+        host_name: String,
+        version: String,
+        #[serde(default)]
+        lan_interface: Option<String>,
+        #[serde(default)]
+        container_storage_path: Option<String>,
+        /// Interfaces of the host, giving its address and MAC.
+        #[serde(default)]
+        interfaces: Vec<NetworkInterfaceInfo>,
     },
     Heartbeat {
         uptime_secs: u64,
         containers_running: u32,
     },
     Metrics(HostMetrics),
+    /// Samples buffered while the agent was disconnected, oldest first.
+    MetricsBackfill(Vec<MetricsSample>),
+    /// SMART report of every disk, sent on a slow interval.
+    DiskHealth(Vec<DiskHealth>),
     ContainerList(Vec<ContainerInfo>),
     ExportReady {
         transfer_id: String,
@@ -368,12 +684,16 @@ pub enum HostAgentMessage {
         container_name: String,
         size_bytes: u64,
     },
-    /// Binary chunk announcement — the actual data follows as a WebSocket Binary frame.
+    /// Binary chunk announcement — the actual data follows as a WebSocket
+    /// Binary frame, or is the payload of the same frame with `binary_frames`.
     TransferChunkBinary {
         transfer_id: String,
         sequence: u32,
         size: u32,
         checksum: u32, // xxhash32
+        /// Offset of the chunk in its stream.
+        #[serde(default)]
+        offset: u64,
     },
     WorkspaceReady {
         transfer_id: String,
@@ -382,6 +702,21 @@ pub enum HostAgentMessage {
     TransferComplete {
         transfer_id: String,
     },
+    /// End of one stream of a transfer, checked by the receiver against the
+    /// chunks it recorded.
+    TransferManifest {
+        transfer_id: String,
+        stream: TransferStream,
+        chunk_count: u32,
+        total_bytes: u64,
+        /// xxhash64 of the chunk checksums.
+        digest: u64,
+    },
+    /// The receiver lost chunks (reconnection): replay from `point`.
+    ResumeTransfer {
+        transfer_id: String,
+        point: ResumePoint,
+    },
     ImportComplete {
         transfer_id: String,
         container_name: String,
@@ -407,6 +742,21 @@ pub enum HostAgentMessage {
     },
     /// Nspawn container list reported by host-agent.
     NspawnContainerList(Vec<NspawnContainerInfo>),
+    /// Resource usage of the running nspawn containers.
+    ContainerUsage(Vec<ContainerUsage>),
+    /// Docker/Podman containers of the host.
+    RuntimeContainers(Vec<RuntimeContainerInfo>),
+    /// Batch of lines of a log stream.
+    LogStreamData {
+        request_id: String,
+        lines: Vec<String>,
+    },
+    /// The log stream is over; `error` when journalctl failed.
+    LogStreamEnd {
+        request_id: String,
+        #[serde(default)]
+        error: Option<String>,
+    },
     /// Terminal output data from a remote shell session.
     TerminalData {
         session_id: String,
@@ -421,6 +771,31 @@ pub enum HostAgentMessage {
         session_id: String,
         exit_code: Option<i32>,
     },
+    /// Answer to `MigrationPreflight`.
+    MigrationPreflight {
+        request_id: String,
+        report: HostPreflight,
+    },
+    /// Pending OS package updates, sent on a slow interval, on
+    /// `CheckPackageUpdates` and after an upgrade.
+    PackageUpdates(PackageStatus),
+    /// Output line of a running `UpgradePackages`.
+    UpgradeOutput {
+        upgrade_id: String,
+        line: String,
+    },
+    UpgradeFinished {
+        upgrade_id: String,
+        success: bool,
+        #[serde(default)]
+        error: Option<String>,
+        /// Snapshot taken before upgrading (`snapper #42`, a btrfs path or
+        /// a zfs snapshot name).
+        #[serde(default)]
+        snapshot: Option<String>,
+        #[serde(default)]
+        reboot_required: bool,
+    },
 }
 
 /// Nspawn container info reported by host-agent.
@@ -440,7 +815,7 @@ pub struct NetworkInterfaceInfo {
 }
 
 /// Host system metrics
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct HostMetrics {
     pub cpu_percent: f32,
     pub memory_used_bytes: u64,
@@ -448,6 +823,145 @@ pub struct HostMetrics {
     pub disk_used_bytes: u64,
     pub disk_total_bytes: u64,
     pub load_avg: [f32; 3],
+    /// CPU package temperature (°C), when a sensor reports it.
+    #[serde(default)]
+    pub cpu_temp_c: Option<f32>,
+    /// NVMe drive temperatures (°C).
+    #[serde(default)]
+    pub nvme_temps: Vec<SensorReading>,
+    /// Fan speeds (RPM).
+    #[serde(default)]
+    pub fans: Vec<SensorReading>,
+    #[serde(default)]
+    pub gpus: Vec<GpuMetrics>,
+}
+
+/// A named sensor value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SensorReading {
+    pub name: String,
+    pub value: f32,
+}
+
+/// Utilization of a GPU; fields its driver does not expose are None.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GpuMetrics {
+    /// `nvidia` or `amd`.
+    pub vendor: String,
+    pub name: String,
+    #[serde(default)]
+    pub utilization_percent: Option<f32>,
+    #[serde(default)]
+    pub vram_used_bytes: Option<u64>,
+    #[serde(default)]
+    pub vram_total_bytes: Option<u64>,
+    #[serde(default)]
+    pub temp_c: Option<f32>,
+}
+
+/// SMART report of one disk (`smartctl -a`). ATA counters and NVMe ones
+/// are None on the other kind of disk.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiskHealth {
+    /// Device path (`/dev/sda`, `/dev/nvme0`).
+    pub device: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub serial: Option<String>,
+    #[serde(default)]
+    pub capacity_bytes: Option<u64>,
+    /// Overall self-assessment; None when SMART is unavailable.
+    #[serde(default)]
+    pub passed: Option<bool>,
+    #[serde(default)]
+    pub temp_c: Option<f32>,
+    #[serde(default)]
+    pub power_on_hours: Option<u64>,
+    /// ATA attributes 5, 197 and 198.
+    #[serde(default)]
+    pub reallocated_sectors: Option<u64>,
+    #[serde(default)]
+    pub pending_sectors: Option<u64>,
+    #[serde(default)]
+    pub uncorrectable_sectors: Option<u64>,
+    /// NVMe health log.
+    #[serde(default)]
+    pub critical_warning: Option<u8>,
+    #[serde(default)]
+    pub percentage_used: Option<u8>,
+    #[serde(default)]
+    pub media_errors: Option<u64>,
+    /// ATA attribute table.
+    #[serde(default)]
+    pub attributes: Vec<SmartAttribute>,
+}
+
+/// One row of the ATA SMART attribute table.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SmartAttribute {
+    pub id: u16,
+    pub name: String,
+    pub value: u16,
+    pub worst: u16,
+    pub threshold: u16,
+    pub raw: u64,
+    /// The normalized value is at or below its threshold.
+    #[serde(default)]
+    pub failing: bool,
+}
+
+/// Wear level at which an SSD is reported as degrading.
+pub const DISK_WEAR_WARNING_PERCENT: u8 = 90;
+
+impl DiskHealth {
+    /// What got worse since `previous` (the last report of the same disk,
+    /// None for a disk seen for the first time), one reason per change.
+    pub fn degradation(&self, previous: Option<&DiskHealth>) -> Vec<String> {
+        let mut reasons = Vec::new();
+        if self.passed == Some(false) && previous.is_none_or(|p| p.passed != Some(false)) {
+            reasons.push("SMART health check failed".to_string());
+        }
+        let counters = [
+            ("reallocated sectors", self.reallocated_sectors, previous.and_then(|p| p.reallocated_sectors)),
+            ("pending sectors", self.pending_sectors, previous.and_then(|p| p.pending_sectors)),
+            ("uncorrectable sectors", self.uncorrectable_sectors, previous.and_then(|p| p.uncorrectable_sectors)),
+            ("media errors", self.media_errors, previous.and_then(|p| p.media_errors)),
+        ];
+        for (name, now, before) in counters {
+            if let Some(now) = now
+                && now > before.unwrap_or(0)
+            {
+                reasons.push(format!("{name}: {} -> {now}", before.unwrap_or(0)));
+            }
+        }
+        if let Some(warning) = self.critical_warning
+            && warning != 0
+            && previous.and_then(|p| p.critical_warning) != Some(warning)
+        {
+            reasons.push(format!("NVMe critical warning 0x{warning:02x}"));
+        }
+        if let Some(used) = self.percentage_used
+            && used >= DISK_WEAR_WARNING_PERCENT
+            && previous.and_then(|p| p.percentage_used).is_none_or(|p| p < DISK_WEAR_WARNING_PERCENT)
+        {
+            reasons.push(format!("wear level {used}%"));
+        }
+        for attribute in self.attributes.iter().filter(|a| a.failing) {
+            let was_failing = previous.is_some_and(|p| p.attributes.iter().any(|a| a.id == attribute.id && a.failing));
+            if !was_failing {
+                reasons.push(format!("attribute {} ({}) below threshold", attribute.id, attribute.name));
+            }
+        }
+        reasons
+    }
+}
+
+/// Host metrics sampled at `ts` (unix seconds).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsSample {
+    pub ts: i64,
+    pub metrics: HostMetrics,
 }
 
 /// LXC container info reported by host-agent
@@ -458,6 +972,149 @@ pub struct ContainerInfo {
     pub ipv4: Option<String>,
 }
 
+/// Container engine running next to nspawn on a host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerRuntime {
+    Docker,
+    Podman,
+}
+
+impl ContainerRuntime {
+    /// CLI driving the runtime.
+    pub fn binary(self) -> &'static str {
+        match self {
+            Self::Docker => "docker",
+            Self::Podman => "podman",
+        }
+    }
+}
+
+/// Port of a Docker/Podman container published on the host.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishedPort {
+    /// Address the port is bound to (all of them when absent).
+    #[serde(default)]
+    pub host_ip: Option<String>,
+    pub host_port: u16,
+    pub container_port: u16,
+    pub protocol: String,
+}
+
+/// Docker/Podman container reported by host-agent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuntimeContainerInfo {
+    pub runtime: ContainerRuntime,
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    /// `running`, `exited`, ...
+    pub state: String,
+    /// Human-readable status (`Up 2 hours`).
+    pub status: String,
+    #[serde(default)]
+    pub ports: Vec<PublishedPort>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuntimeAction {
+    Start,
+    Stop,
+    Inspect,
+}
+
+/// An OS package with a newer version available.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageUpdate {
+    pub name: String,
+    #[serde(default)]
+    pub current_version: String,
+    pub new_version: String,
+    /// Comes from a security repository or advisory.
+    #[serde(default)]
+    pub security: bool,
+}
+
+/// Pending package updates of a host.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageStatus {
+    /// `apt` or `dnf`.
+    pub manager: String,
+    pub updates: Vec<PackageUpdate>,
+    /// The running kernel or core libraries were upgraded.
+    #[serde(default)]
+    pub reboot_required: bool,
+    /// Unix seconds.
+    pub checked_at: i64,
+}
+
+/// systemd service of a host.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemdService {
+    pub unit: String,
+    #[serde(default)]
+    pub description: String,
+    /// `loaded`, `not-found`, ... (`not-loaded` for an installed unit
+    /// systemd has not loaded).
+    pub load_state: String,
+    /// `active`, `inactive`, `failed`, ...
+    pub active_state: String,
+    /// `running`, `exited`, `dead`, ...
+    pub sub_state: String,
+    /// Unit file state: `enabled`, `disabled`, `static`, ...
+    #[serde(default)]
+    pub enabled: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SystemdAction {
+    Start,
+    Stop,
+    Restart,
+    /// `systemctl status` output, with the last journal lines.
+    Status,
+}
+
+/// A plain service unit name (`nginx.service`, `wg-quick@wg0.service`),
+/// safe to hand to `systemctl`.
+pub fn is_service_unit(unit: &str) -> bool {
+    unit.len() <= 256
+        && unit.ends_with(".service")
+        && !unit.starts_with(['-', '.', '@'])
+        && unit.chars().all(|c| c.is_ascii_alphanumeric() || ":_.@-\\".contains(c))
+}
+
+/// `unit` matches one of `patterns`, where `*` stands for any run of
+/// characters (`postgresql*.service`).
+pub fn service_allowed(unit: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|pattern| glob_match(pattern, unit))
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let Some((first, rest)) = pattern.split_once('*') else {
+        return pattern == text;
+    };
+    let Some(mut remaining) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = rest.split('*').collect();
+    let (last, middle) = parts.split_last().expect("split yields one part at least");
+    for part in middle {
+        match remaining.find(part) {
+            Some(at) => remaining = &remaining[at + part.len()..],
+            None => return false,
+        }
+    }
+    remaining.len() >= last.len() && remaining.ends_with(last)
+}
+
 /// Messages from registry → host-agent (via WebSocket)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
@@ -466,6 +1123,23 @@ pub enum HostRegistryMessage {
         success: bool,
         #[serde(skip_serializing_if = "Option::is_none")]
         error: Option<String>,
+        /// The registry's protocol version.
+        #[serde(default)]
+        protocol_version: u32,
+        /// Capabilities agreed for this connection.
+        #[serde(default)]
+        capabilities: Vec<String>,
+    },
+    /// Response to Enroll: the host's token and name, to save before
+    /// connecting again with `Auth`.
+    EnrollResult {
+        success: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        #[serde(default)]
+        token: Option<String>,
+        #[serde(default)]
+        host_name: Option<String>,
     },
     CreateContainer {
         app_id: String,
@@ -481,20 +1155,24 @@ pub enum HostRegistryMessage {
     StopContainer {
         container_name: String,
     },
+    /// Self-update, if the manifest verifies with the pinned key.
     PushAgentUpdate {
-        version: String,
         download_url: String,
-        sha256: String,
+        manifest: SignedManifest,
     },
     Shutdown {
         drain: bool,
     },
-    /// Binary chunk announcement — the actual data follows as a WebSocket Binary frame.
+    /// Binary chunk announcement — the actual data follows as a WebSocket
+    /// Binary frame, or is the payload of the same frame with `binary_frames`.
     ReceiveChunkBinary {
         transfer_id: String,
         sequence: u32,
         size: u32,
         checksum: u32, // xxhash32
+        /// Offset of the chunk in its stream.
+        #[serde(default)]
+        offset: u64,
     },
     WorkspaceReady {
         transfer_id: String,
@@ -503,6 +1181,21 @@ pub enum HostRegistryMessage {
     TransferComplete {
         transfer_id: String,
     },
+    /// End of one stream of a transfer, checked by the receiver against the
+    /// chunks it recorded.
+    TransferManifest {
+        transfer_id: String,
+        stream: TransferStream,
+        chunk_count: u32,
+        total_bytes: u64,
+        /// xxhash64 of the chunk checksums.
+        digest: u64,
+    },
+    /// The receiver lost chunks (reconnection): replay from `point`.
+    ResumeTransfer {
+        transfer_id: String,
+        point: ResumePoint,
+    },
     ExecInContainer {
         request_id: String,
         container_name: String,
@@ -515,6 +1208,11 @@ pub enum HostRegistryMessage {
         mode: AutoOffMode,
         minutes: u32,
     },
+    /// The host's metered power draw is above its busy threshold: hold the
+    /// auto-off countdown even with an idle CPU.
+    PowerBusy {
+        busy: bool,
+    },
     /// Cancel an in-flight migration transfer.
     CancelTransfer {
         transfer_id: String,
@@ -528,6 +1226,9 @@ pub enum HostRegistryMessage {
         network_mode: String,
         agent_token: String,
         agent_config: String,
+        /// Template to build the rootfs from (default Ubuntu otherwise).
+        #[serde(default)]
+        template: Option<ContainerTemplate>,
     },
     DeleteNspawnContainer {
         container_name: String,
@@ -540,36 +1241,138 @@ pub enum HostRegistryMessage {
     StopNspawnContainer {
         container_name: String,
     },
+    /// Replace the resource limits of a container (empty lifts them).
+    SetContainerLimits {
+        container_name: String,
+        storage_path: String,
+        limits: ResourceLimits,
+    },
+    /// Refresh the package lists; answered with `PackageUpdates`.
+    CheckPackageUpdates,
+    /// Upgrade the OS packages (`full`: allow removals and new
+    /// dependencies), after a filesystem snapshot when `snapshot`.
+    UpgradePackages {
+        upgrade_id: String,
+        #[serde(default)]
+        full: bool,
+        #[serde(default)]
+        snapshot: bool,
+    },
+    /// Start, stop or inspect a Docker/Podman container; answered with an
+    /// `ExecResult` (the `inspect` JSON in `stdout`).
+    RuntimeContainerAction {
+        request_id: String,
+        runtime: ContainerRuntime,
+        container: String,
+        action: RuntimeAction,
+    },
+    /// List the systemd services matching `patterns` (see
+    /// [`service_allowed`]); answered with an `ExecResult` whose `stdout` is
+    /// the JSON array of [`SystemdService`].
+    ListSystemdServices {
+        request_id: String,
+        patterns: Vec<String>,
+    },
+    /// Start, stop, restart or get the status of a service; answered with
+    /// an `ExecResult` (the status text in `stdout`).
+    SystemdServiceAction {
+        request_id: String,
+        unit: String,
+        action: SystemdAction,
+    },
     ExecInNspawnContainer {
         request_id: String,
         container_name: String,
         command: Vec<String>,
     },
+    /// Stream the journal of a container of this host.
+    LogStream {
+        request_id: String,
+        request: LogRequest,
+    },
+    /// Stop a log stream (the reader went away).
+    LogStreamClose {
+        request_id: String,
+    },
     StartNspawnExport {
         container_name: String,
         storage_path: String,
         transfer_id: String,
+        /// Only set for agents announcing `zstd_transfers`.
+        #[serde(default)]
+        compression: Compression,
     },
     StartNspawnImport {
         container_name: String,
         storage_path: String,
         transfer_id: String,
         network_mode: String,
+        #[serde(default)]
+        compression: Compression,
     },
-    /// Open a terminal session in a container on this host.
+    /// Open a terminal session in a container on this host, or on the
+    /// host itself without one.
     TerminalOpen {
         session_id: String,
-        container_name: String,
+        #[serde(default)]
+        container_name: Option<String>,
+        /// Initial window size (0 when unknown).
+        #[serde(default)]
+        cols: u16,
+        #[serde(default)]
+        rows: u16,
     },
     /// Terminal input data from the user.
     TerminalData {
         session_id: String,
         data: Vec<u8>,
     },
+    /// The browser window of a terminal session changed size.
+    TerminalResize {
+        session_id: String,
+        cols: u16,
+        rows: u16,
+    },
     /// Close a terminal session.
     TerminalClose {
         session_id: String,
     },
+    /// Check the host before migrating a container to or from it.
+    MigrationPreflight {
+        request_id: String,
+        storage_path: String,
+        #[serde(default)]
+        network_mode: Option<String>,
+        /// Container to measure if it is on the host.
+        #[serde(default)]
+        container_name: Option<String>,
+    },
+    /// Credentials of the host changed: authenticate with `token` from now
+    /// on (the previous one stays valid for a grace window).
+    RotateToken {
+        token: String,
+    },
+}
+
+impl HostRegistryMessage {
+    /// Capability the host agent must have announced to be sent this message.
+    pub fn capability(&self) -> Option<&'static str> {
+        match self {
+            Self::PowerBusy { .. } => Some(capability::POWER_BUSY),
+            Self::SetContainerLimits { .. } => Some(capability::RESOURCE_LIMITS),
+            Self::RuntimeContainerAction { .. } => Some(capability::CONTAINER_RUNTIMES),
+            Self::LogStream { .. } | Self::LogStreamClose { .. } => Some(capability::LOG_STREAM),
+            Self::TerminalOpen { container_name: None, .. } | Self::TerminalResize { .. } => {
+                Some(capability::PTY_TERMINAL)
+            }
+            Self::TransferManifest { .. } | Self::ResumeTransfer { .. } => Some(capability::RESUMABLE_TRANSFERS),
+            Self::MigrationPreflight { .. } => Some(capability::MIGRATION_PREFLIGHT),
+            Self::RotateToken { .. } => Some(capability::TOKEN_ROTATION),
+            Self::ListSystemdServices { .. } | Self::SystemdServiceAction { .. } => Some(capability::SYSTEMD_SERVICES),
+            Self::CheckPackageUpdates | Self::UpgradePackages { .. } => Some(capability::PACKAGE_UPDATES),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -583,6 +1386,9 @@ mod tests {
             service_name: "test".into(),
             version: "0.1.0".into(),
             ipv4_address: Some("10.0.0.100".into()),
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: capability_list(AGENT_CAPABILITIES),
+            arch: Some("aarch64".into()),
         };
         let json = serde_json::to_string(&msg).unwrap();
         assert!(json.contains(r#""type":"auth"#));
@@ -599,6 +1405,8 @@ mod tests {
             success: true,
             error: None,
             app_id: Some("test-123".into()),
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: Vec::new(),
         };
         let json = serde_json::to_string(&msg).unwrap();
         let parsed: RegistryMessage = serde_json::from_str(&json).unwrap();
@@ -607,4 +1415,110 @@ mod tests {
             _ => panic!("wrong variant"),
         }
     }
+
+    #[test]
+    fn test_legacy_auth() {
+        // Agents predating versioning send neither field
+        let json = r#"{"type":"Auth","data":{"token":"t","host_name":"nas","version":"0.1.0"}}"#;
+        let HostAgentMessage::Auth { protocol_version, capabilities, .. } = serde_json::from_str(json).unwrap() else {
+            panic!("wrong variant");
+        };
+        let negotiated = Negotiated::new(protocol_version, &capabilities, HOST_AGENT_CAPABILITIES);
+        assert!(negotiated.outdated());
+        assert!(!negotiated.supports(capability::POWER_BUSY));
+        assert_eq!(HostRegistryMessage::PowerBusy { busy: true }.capability(), Some(capability::POWER_BUSY));
+    }
+
+    #[test]
+    fn test_enroll_serde() {
+        let json = r#"{"type":"Enroll","data":{"join_code":"ABCD-EFGH-JKLM","host_name":"nas","version":"0.2.0"}}"#;
+        let HostAgentMessage::Enroll { join_code, interfaces, .. } = serde_json::from_str(json).unwrap() else {
+            panic!("wrong variant");
+        };
+        assert_eq!(join_code, "ABCD-EFGH-JKLM");
+        assert!(interfaces.is_empty());
+
+        let result = RegistryMessage::EnrollResult {
+            success: false,
+            error: Some("Invalid join code".into()),
+            token: None,
+            service_name: None,
+        };
+        let json = serde_json::to_string(&result).unwrap();
+        assert_eq!(json, r#"{"type":"enroll_result","success":false,"error":"Invalid join code"}"#);
+    }
+
+    #[test]
+    fn test_disk_degradation() {
+        let healthy = DiskHealth {
+            device: "/dev/sda".into(),
+            passed: Some(true),
+            reallocated_sectors: Some(0),
+            pending_sectors: Some(0),
+            ..Default::default()
+        };
+        assert!(healthy.degradation(None).is_empty());
+        assert!(healthy.degradation(Some(&healthy)).is_empty());
+
+        let worse = DiskHealth { reallocated_sectors: Some(8), ..healthy.clone() };
+        assert_eq!(worse.degradation(Some(&healthy)), ["reallocated sectors: 0 -> 8"]);
+        // Already reported
+        assert!(worse.degradation(Some(&worse)).is_empty());
+
+        let failed = DiskHealth { passed: Some(false), ..worse.clone() };
+        assert_eq!(failed.degradation(Some(&worse)), ["SMART health check failed"]);
+
+        let nvme = DiskHealth { device: "/dev/nvme0".into(), percentage_used: Some(91), ..Default::default() };
+        assert_eq!(nvme.degradation(None), ["wear level 91%"]);
+    }
+
+    #[test]
+    fn test_service_allowlist() {
+        let patterns = vec!["nginx.service".to_string(), "postgresql*.service".to_string(), "wg-quick@*".to_string()];
+        assert!(service_allowed("nginx.service", &patterns));
+        assert!(service_allowed("postgresql.service", &patterns));
+        assert!(service_allowed("postgresql@16-main.service", &patterns));
+        assert!(service_allowed("wg-quick@wg0.service", &patterns));
+        assert!(!service_allowed("nginx-debug.service", &patterns));
+        assert!(!service_allowed("sshd.service", &patterns));
+        assert!(service_allowed("a.service", &["*".to_string()]));
+
+        assert!(is_service_unit("wg-quick@wg0.service"));
+        assert!(!is_service_unit("--force.service"));
+        assert!(!is_service_unit("nginx.socket"));
+        assert!(!is_service_unit("a b.service"));
+    }
+
+    #[test]
+    fn test_terminal_open_compat() {
+        // Container shells keep the shape older host agents parse
+        let open = HostRegistryMessage::TerminalOpen {
+            session_id: "s".into(),
+            container_name: Some("hr-v2-blog".into()),
+            cols: 0,
+            rows: 0,
+        };
+        let json = serde_json::to_string(&open).unwrap();
+        assert!(json.contains(r#""container_name":"hr-v2-blog""#));
+        assert_eq!(open.capability(), None);
+
+        let legacy = r#"{"type":"TerminalOpen","data":{"session_id":"s","container_name":"hr-v2-blog"}}"#;
+        let HostRegistryMessage::TerminalOpen { cols, rows, .. } = serde_json::from_str(legacy).unwrap() else {
+            panic!("wrong variant");
+        };
+        assert_eq!((cols, rows), (0, 0));
+
+        let host_shell = HostRegistryMessage::TerminalOpen { session_id: "s".into(), container_name: None, cols: 80, rows: 24 };
+        assert_eq!(host_shell.capability(), Some(capability::PTY_TERMINAL));
+    }
+
+    #[test]
+    fn test_negotiation() {
+        let announced = vec!["pubsub".to_string(), "teleport".to_string()];
+        let negotiated = Negotiated::new(PROTOCOL_VERSION, &announced, AGENT_CAPABILITIES);
+        assert_eq!(negotiated.capabilities, ["pubsub"]);
+        assert!(negotiated.supports(capability::PUBSUB));
+        assert!(!negotiated.supports(capability::QUEUES));
+        assert!(!negotiated.outdated());
+    }
 }
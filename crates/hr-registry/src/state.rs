@@ -2,7 +2,7 @@
 //! and pushes config to agents.
 
 use std::collections::HashMap;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
@@ -10,14 +10,18 @@ use chrono::{DateTime, Utc};
 use tokio::sync::{mpsc, RwLock};
 use tracing::{error, info, warn};
 
-use hr_acme::AcmeManager;
+use hr_acme::{AcmeManager, WildcardType};
 use hr_common::config::EnvConfig;
 use hr_common::events::{AgentMetricsEvent, AgentStatusEvent, AgentUpdateEvent, AgentUpdateStatus, EventBus, HostPowerEvent, HostPowerState, PowerAction, WakeResult};
-use crate::protocol::{AgentMetrics, ContainerInfo, HostMetrics, HostRegistryMessage, NetworkInterfaceInfo, PowerPolicy, RegistryMessage, ServiceAction, ServiceState, ServiceType};
+use crate::logs::{LogEvent, LogRequest, LogSink};
+use crate::transfer::ResumePoint;
+use crate::binaries::{local_arch, Artifact, BinaryStore, ARCHES, BINARIES_DIR};
+use crate::update::SignedManifest;
+use crate::protocol::{AgentMetrics, ContainerInfo, ContainerRuntime, ContainerUsage, HostMetrics, HostPreflight, HostRegistryMessage, Negotiated, NetworkInterfaceInfo, PowerPolicy, RegistryMessage, RuntimeAction, RuntimeContainerInfo, ServiceAction, ServiceState, ServiceType, SystemdAction, SystemdService};
 use crate::types::{
-    AgentNotifyResult, AgentSkipResult, AgentStatus, AgentUpdateStatusInfo,
-    Application, CreateApplicationRequest, RegistryState, UpdateApplicationRequest,
-    UpdateBatchResult, UpdateStatusResult,
+    normalize_custom_domain, AgentNotifyResult, AgentSkipResult, AgentStatus, AgentUpdateStatusInfo,
+    Application, CreateApplicationRequest, CustomDomain, CustomDomainStatus, Environment, RegistryState,
+    UpdateApplicationRequest, UpdateBatchResult, UpdateStatusResult,
 };
 
 /// Tracks all active WebSocket connections for a single app_id.
@@ -30,13 +34,19 @@ struct AppConnections {
     last_heartbeat: DateTime<Utc>,
     /// Number of active WebSocket connections for this app_id.
     active_count: usize,
+    /// Protocol agreed with the primary agent.
+    protocol: Negotiated,
 }
 
-/// Wrapper for outgoing messages to host-agents: text (JSON) or raw binary.
+/// Wrapper for outgoing messages to host-agents: a control message, or a
+/// transfer chunk with its data. The connection writer encodes them per the
+/// negotiated protocol (JSON text then raw binary, or one MessagePack frame).
 #[derive(Debug)]
 pub enum OutgoingHostMessage {
     Text(HostRegistryMessage),
-    Binary(Vec<u8>),
+    Chunk(HostRegistryMessage, Vec<u8>),
+    /// Close the connection (revoked credentials), with the reason.
+    Close(String),
 }
 
 /// In-memory host-agent connection state.
@@ -46,9 +56,21 @@ pub struct HostConnection {
     pub connected_at: DateTime<Utc>,
     pub last_heartbeat: DateTime<Utc>,
     pub version: Option<String>,
+    /// Protocol agreed at `Auth`.
+    pub protocol: Negotiated,
     pub metrics: Option<HostMetrics>,
     pub containers: Vec<ContainerInfo>,
     pub interfaces: Vec<NetworkInterfaceInfo>,
+    /// Last `ContainerUsage` report.
+    pub usage: Vec<ContainerUsage>,
+    /// Last `RuntimeContainers` report (Docker/Podman).
+    pub runtime_containers: Vec<RuntimeContainerInfo>,
+}
+
+/// Agent serving a log stream, told to stop it when its reader goes away.
+enum LogOrigin {
+    App(String),
+    Host(String),
 }
 
 pub enum MigrationResult {
@@ -92,16 +114,22 @@ pub struct AgentRegistry {
     events: Arc<EventBus>,
     migration_signals: Arc<RwLock<HashMap<String, tokio::sync::oneshot::Sender<MigrationResult>>>>,
     exec_signals: Arc<RwLock<HashMap<String, tokio::sync::oneshot::Sender<(bool, String, String)>>>>,
+    /// Pending `MigrationPreflight` requests to host agents, by request_id.
+    preflight_signals: Arc<RwLock<HashMap<String, tokio::sync::oneshot::Sender<HostPreflight>>>>,
     /// Maps transfer_id → container_name for in-flight migrations (set when StartExport is sent)
     pub transfer_container_names: Arc<RwLock<HashMap<String, String>>>,
     /// Maps transfer_id → (target_host_id, container_name) for remote→remote relay migrations
     pub transfer_relay_targets: Arc<RwLock<HashMap<String, (String, String)>>>,
+    /// Local senders of resumable transfers, woken by the receiver's `ResumeTransfer`.
+    transfer_resumes: Arc<RwLock<HashMap<String, mpsc::UnboundedSender<ResumePoint>>>>,
     /// Host power state machine for WOL dedup, conflict detection, and progress tracking.
     host_power_states: Arc<RwLock<HashMap<String, HostPowerInfo>>>,
     /// ACME manager for per-app wildcard certificate lifecycle.
     pub acme: RwLock<Option<Arc<AcmeManager>>>,
     /// Terminal sessions: maps session_id → sender for data from host-agent to API WS handler.
     terminal_sessions: Arc<RwLock<HashMap<String, mpsc::Sender<Vec<u8>>>>>,
+    /// Log streams: maps request_id → queue of the API WS handler reading it.
+    log_streams: Arc<RwLock<HashMap<String, (LogOrigin, LogSink)>>>,
     /// Dataverse query signals: maps request_id → oneshot sender for query results.
     dataverse_query_signals: Arc<RwLock<HashMap<String, tokio::sync::oneshot::Sender<Result<serde_json::Value, String>>>>>,
 }
@@ -137,11 +165,14 @@ impl AgentRegistry {
             events,
             migration_signals: Arc::new(RwLock::new(HashMap::new())),
             exec_signals: Arc::new(RwLock::new(HashMap::new())),
+            preflight_signals: Arc::new(RwLock::new(HashMap::new())),
             transfer_container_names: Arc::new(RwLock::new(HashMap::new())),
             transfer_relay_targets: Arc::new(RwLock::new(HashMap::new())),
+            transfer_resumes: Arc::new(RwLock::new(HashMap::new())),
             host_power_states: Arc::new(RwLock::new(HashMap::new())),
             acme: RwLock::new(None),
             terminal_sessions: Arc::new(RwLock::new(HashMap::new())),
+            log_streams: Arc::new(RwLock::new(HashMap::new())),
             dataverse_query_signals: Arc::new(RwLock::new(HashMap::new())),
         }
     }
@@ -181,13 +212,12 @@ impl AgentRegistry {
         self: &Arc<Self>,
         req: CreateApplicationRequest,
     ) -> Result<(Application, String)> {
-        use crate::types::Environment;
-
         let token_clear = generate_token();
         let token_hash = hash_token(&token_clear)?;
 
         let id = uuid::Uuid::new_v4().to_string();
         let container_name = match req.environment {
+            _ if req.process.is_some() => format!("hr-proc-{}", req.slug),
             Environment::Production => format!("hr-v2-{}-prod", req.slug),
             Environment::Development => format!("hr-v2-{}-dev", req.slug),
         };
@@ -202,7 +232,8 @@ impl AgentRegistry {
             enabled: true,
             container_name: container_name.clone(),
             token_hash,
-            ipv4_address: None,
+            // Process apps listen on the host itself
+            ipv4_address: req.process.as_ref().map(|_| std::net::Ipv4Addr::LOCALHOST),
             status: AgentStatus::Deploying,
             last_heartbeat: None,
             agent_version: None,
@@ -212,6 +243,8 @@ impl AgentRegistry {
             services: req.services,
             power_policy: req.power_policy,
             wake_page_enabled: req.wake_page_enabled,
+            custom_domains: Vec::new(),
+            process: req.process,
             metrics: None,
         };
 
@@ -232,14 +265,31 @@ impl AgentRegistry {
         Ok((app, token_clear))
     }
 
-    /// Set an application's status and persist.
-    async fn set_app_status(&self, app_id: &str, status: AgentStatus) {
-        {
+    /// Set an application's status and persist. Process apps, which have
+    /// no agent, report their health this way.
+    pub async fn set_app_status(&self, app_id: &str, status: AgentStatus, message: Option<String>) {
+        let slug = {
             let mut state = self.state.write().await;
-            if let Some(app) = state.applications.iter_mut().find(|a| a.id == app_id) {
-                app.status = status;
+            match state.applications.iter_mut().find(|a| a.id == app_id) {
+                Some(app) if app.status != status => {
+                    app.status = status.clone();
+                    Some(app.slug.clone())
+                }
+                _ => None,
             }
-        }
+        };
+        let Some(slug) = slug else {
+            return;
+        };
+        let _ = self.events.agent_status.send(AgentStatusEvent {
+            app_id: app_id.to_string(),
+            slug,
+            status: serde_json::to_value(&status)
+                .ok()
+                .and_then(|v| v.as_str().map(str::to_string))
+                .unwrap_or_default(),
+            message,
+        });
         let _ = self.persist().await;
     }
 
@@ -283,6 +333,11 @@ impl AgentRegistry {
         if let Some(wake_page_enabled) = req.wake_page_enabled {
             app.wake_page_enabled = wake_page_enabled;
         }
+        if let Some(process) = req.process
+            && app.process.is_some()
+        {
+            app.process = Some(process);
+        }
 
         let app = app.clone();
         drop(state);
@@ -321,13 +376,16 @@ impl AgentRegistry {
             }
         }
 
-        // Delete per-app wildcard certificate
+        // Delete per-app wildcard certificate and custom domain certificates
         {
             let acme_guard = self.acme.read().await;
             if let Some(ref acme) = *acme_guard {
                 if let Err(e) = acme.delete_app_certificate(&app.slug) {
                     warn!(slug = app.slug, error = %e, "Failed to delete app certificate");
                 }
+                for custom in &app.custom_domains {
+                    let _ = acme.delete_certificate(&WildcardType::for_custom(&app.slug, &custom.domain));
+                }
             }
         }
 
@@ -362,6 +420,92 @@ impl AgentRegistry {
         Ok(Some(app))
     }
 
+    // ── Custom domains ──────────────────────────────────────────
+
+    /// Attach an external domain to a production application (status pending).
+    pub async fn add_custom_domain(&self, app_id: &str, domain: &str) -> Result<Option<CustomDomain>> {
+        let domain = normalize_custom_domain(domain, &self.env.base_domain).map_err(|e| anyhow::anyhow!(e))?;
+        let mut state = self.state.write().await;
+        if let Some(owner) = state
+            .applications
+            .iter()
+            .find(|a| a.custom_domains.iter().any(|d| d.domain == domain))
+        {
+            anyhow::bail!("{} is already attached to {}", domain, owner.slug);
+        }
+        let Some(app) = state.applications.iter_mut().find(|a| a.id == app_id) else {
+            return Ok(None);
+        };
+        if app.environment != Environment::Production {
+            anyhow::bail!("Custom domains are only available for production applications");
+        }
+        let custom = CustomDomain {
+            domain,
+            status: CustomDomainStatus::Pending,
+            added_at: Utc::now(),
+            verified_at: None,
+            last_error: None,
+        };
+        app.custom_domains.push(custom.clone());
+        drop(state);
+        self.persist().await?;
+        info!(app_id, domain = custom.domain, "Custom domain added");
+        Ok(Some(custom))
+    }
+
+    /// Update the verification status of a custom domain.
+    pub async fn set_custom_domain_status(
+        &self,
+        app_id: &str,
+        domain: &str,
+        status: CustomDomainStatus,
+        error: Option<String>,
+    ) -> Result<Option<CustomDomain>> {
+        let mut state = self.state.write().await;
+        let Some(custom) = state
+            .applications
+            .iter_mut()
+            .find(|a| a.id == app_id)
+            .and_then(|a| a.custom_domains.iter_mut().find(|d| d.domain == domain))
+        else {
+            return Ok(None);
+        };
+        if status == CustomDomainStatus::Verified {
+            custom.verified_at = Some(Utc::now());
+        }
+        custom.status = status;
+        custom.last_error = error;
+        let custom = custom.clone();
+        drop(state);
+        self.persist().await?;
+        Ok(Some(custom))
+    }
+
+    /// Detach a custom domain and delete its certificate.
+    pub async fn remove_custom_domain(&self, app_id: &str, domain: &str) -> Result<bool> {
+        let slug = {
+            let mut state = self.state.write().await;
+            let Some(app) = state.applications.iter_mut().find(|a| a.id == app_id) else {
+                return Ok(false);
+            };
+            let before = app.custom_domains.len();
+            app.custom_domains.retain(|d| d.domain != domain);
+            if app.custom_domains.len() == before {
+                return Ok(false);
+            }
+            app.slug.clone()
+        };
+        self.persist().await?;
+
+        if let Some(ref acme) = *self.acme.read().await
+            && let Err(e) = acme.delete_certificate(&WildcardType::for_custom(&slug, domain))
+        {
+            warn!(domain, error = %e, "Failed to delete custom domain certificate");
+        }
+        info!(app_id, domain, "Custom domain removed");
+        Ok(true)
+    }
+
     pub async fn list_applications(&self) -> Vec<Application> {
         self.state.read().await.applications.clone()
     }
@@ -427,9 +571,13 @@ impl AgentRegistry {
         app_id: &str,
         tx: mpsc::Sender<RegistryMessage>,
         agent_version: String,
+        protocol: Negotiated,
         reported_ipv4: Option<String>,
     ) -> Result<()> {
         let now = Utc::now();
+        if protocol.outdated() {
+            warn!(app_id, version = protocol.version, "Agent speaks an older protocol, upgrade it");
+        }
 
         // Increment connection count (or create new entry).
         // Only overwrite the primary tx if this connection has an IPv4 (real agent).
@@ -441,6 +589,7 @@ impl AgentRegistry {
                 // Only overwrite tx if this is the main agent (has IPv4)
                 if reported_ipv4.is_some() {
                     existing.tx = tx.clone();
+                    existing.protocol = protocol;
                 }
                 info!(app_id, count = existing.active_count, has_ipv4 = reported_ipv4.is_some(),
                     "Additional agent connection registered");
@@ -452,6 +601,7 @@ impl AgentRegistry {
                         connected_at: now,
                         last_heartbeat: now,
                         active_count: 1,
+                        protocol,
                     },
                 );
             }
@@ -538,6 +688,7 @@ impl AgentRegistry {
         if !is_last {
             return false;
         }
+        self.end_log_streams(|origin| matches!(origin, LogOrigin::App(id) if id == app_id), "Agent disconnected").await;
 
         let slug = {
             let mut state = self.state.write().await;
@@ -667,16 +818,23 @@ impl AgentRegistry {
         host_name: String,
         tx: mpsc::Sender<OutgoingHostMessage>,
         version: String,
+        protocol: Negotiated,
     ) {
+        if protocol.outdated() {
+            warn!(host = %host_name, version = protocol.version, "Host agent speaks an older protocol, upgrade it");
+        }
         let conn = HostConnection {
             tx,
             host_name: host_name.clone(),
             connected_at: Utc::now(),
             last_heartbeat: Utc::now(),
             version: Some(version.clone()),
+            protocol,
             metrics: None,
             containers: Vec::new(),
             interfaces: Vec::new(),
+            usage: Vec::new(),
+            runtime_containers: Vec::new(),
         };
         self.host_connections.write().await.insert(host_id.clone(), conn);
 
@@ -707,6 +865,17 @@ impl AgentRegistry {
         if let Some(conn) = self.host_connections.write().await.remove(host_id) {
             info!("Host agent disconnected: {} ({})", conn.host_name, host_id);
         }
+        self.end_log_streams(|origin| matches!(origin, LogOrigin::Host(id) if id == host_id), "Host disconnected").await;
+    }
+
+    /// Close the connection of a host agent now. Returns false when it is
+    /// not connected.
+    pub async fn disconnect_host(&self, host_id: &str, reason: &str) -> bool {
+        let tx = match self.host_connections.read().await.get(host_id) {
+            Some(conn) => conn.tx.clone(),
+            None => return false,
+        };
+        tx.send(OutgoingHostMessage::Close(reason.to_string())).await.is_ok()
     }
 
     pub async fn is_host_connected(&self, host_id: &str) -> bool {
@@ -718,6 +887,15 @@ impl AgentRegistry {
         self.connections.read().await.contains_key(app_id)
     }
 
+    /// Whether the connected agent of an app agreed on `capability`.
+    pub async fn agent_supports(&self, app_id: &str, capability: &str) -> bool {
+        self.connections
+            .read()
+            .await
+            .get(app_id)
+            .is_some_and(|conn| conn.protocol.supports(capability))
+    }
+
     // ── Host power state machine ────────────────────────────────
 
     /// Get the current power state of a host.
@@ -987,6 +1165,38 @@ impl AgentRegistry {
         }
     }
 
+    pub async fn update_host_usage(&self, host_id: &str, usage: Vec<ContainerUsage>) {
+        if let Some(conn) = self.host_connections.write().await.get_mut(host_id) {
+            conn.usage = usage;
+        }
+    }
+
+    /// Last reported resource usage of the containers of a connected host.
+    pub async fn host_usage(&self, host_id: &str) -> Vec<ContainerUsage> {
+        self.host_connections
+            .read()
+            .await
+            .get(host_id)
+            .map(|conn| conn.usage.clone())
+            .unwrap_or_default()
+    }
+
+    pub async fn update_host_runtime_containers(&self, host_id: &str, containers: Vec<RuntimeContainerInfo>) {
+        if let Some(conn) = self.host_connections.write().await.get_mut(host_id) {
+            conn.runtime_containers = containers;
+        }
+    }
+
+    /// Containers of a connected host: nspawn/LXC ones, then Docker/Podman
+    /// ones. `None` when the host is offline.
+    pub async fn host_containers(&self, host_id: &str) -> Option<(Vec<ContainerInfo>, Vec<RuntimeContainerInfo>)> {
+        self.host_connections
+            .read()
+            .await
+            .get(host_id)
+            .map(|conn| (conn.containers.clone(), conn.runtime_containers.clone()))
+    }
+
     pub async fn send_host_command(
         &self,
         host_id: &str,
@@ -998,7 +1208,14 @@ impl AgentRegistry {
         let tx = {
             let conns = self.host_connections.read().await;
             match conns.get(host_id) {
-                Some(conn) => conn.tx.clone(),
+                Some(conn) => {
+                    if let Some(capability) = msg.capability()
+                        && !conn.protocol.supports(capability)
+                    {
+                        return Err(format!("Host agent {} does not support {}, upgrade it", conn.host_name, capability));
+                    }
+                    conn.tx.clone()
+                }
                 None => return Err(format!("Host {} not connected", host_id)),
             }
         };
@@ -1012,10 +1229,12 @@ impl AgentRegistry {
         }
     }
 
-    /// Send raw binary data to a host-agent (for migration chunk relay).
-    pub async fn send_host_binary(
+    /// Send a `ReceiveChunkBinary` with its data to a host-agent (migration
+    /// chunks and relay).
+    pub async fn send_host_chunk(
         &self,
         host_id: &str,
+        msg: HostRegistryMessage,
         data: Vec<u8>,
     ) -> Result<(), String> {
         let tx = {
@@ -1027,11 +1246,11 @@ impl AgentRegistry {
         };
         match tokio::time::timeout(
             std::time::Duration::from_secs(30),
-            tx.send(OutgoingHostMessage::Binary(data)),
+            tx.send(OutgoingHostMessage::Chunk(msg, data)),
         ).await {
             Ok(Ok(())) => Ok(()),
-            Ok(Err(e)) => Err(format!("Failed to send binary to host {}: {}", host_id, e)),
-            Err(_) => Err(format!("Timeout sending binary to host {} (channel full for 30s)", host_id)),
+            Ok(Err(e)) => Err(format!("Failed to send chunk to host {}: {}", host_id, e)),
+            Err(_) => Err(format!("Timeout sending chunk to host {} (channel full for 30s)", host_id)),
         }
     }
 
@@ -1071,6 +1290,84 @@ impl AgentRegistry {
         self.transfer_relay_targets.write().await.remove(transfer_id)
     }
 
+    /// Register the local sender of a transfer for `ResumeTransfer` requests.
+    pub async fn register_transfer_resume(&self, transfer_id: &str) -> mpsc::UnboundedReceiver<ResumePoint> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.transfer_resumes.write().await.insert(transfer_id.to_string(), tx);
+        rx
+    }
+
+    pub async fn unregister_transfer_resume(&self, transfer_id: &str) {
+        self.transfer_resumes.write().await.remove(transfer_id);
+    }
+
+    /// Hand a receiver's resume point to the local sender. False when the
+    /// transfer is not sent from here.
+    pub async fn on_transfer_resume(&self, transfer_id: &str, point: ResumePoint) -> bool {
+        match self.transfer_resumes.read().await.get(transfer_id) {
+            Some(tx) => tx.send(point).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Whether a connected host agent agreed on `capability`.
+    pub async fn host_supports(&self, host_id: &str, capability: &str) -> bool {
+        self.host_connections
+            .read()
+            .await
+            .get(host_id)
+            .is_some_and(|conn| conn.protocol.supports(capability))
+    }
+
+    /// Agent version and protocol of a connected host.
+    pub async fn host_protocol(&self, host_id: &str) -> Option<(Option<String>, Negotiated)> {
+        self.host_connections
+            .read()
+            .await
+            .get(host_id)
+            .map(|conn| (conn.version.clone(), conn.protocol.clone()))
+    }
+
+    /// Ask a host agent for its migration pre-flight report.
+    pub async fn host_preflight(
+        &self,
+        host_id: &str,
+        storage_path: &str,
+        network_mode: Option<String>,
+        container_name: Option<String>,
+    ) -> Result<HostPreflight> {
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.preflight_signals.write().await.insert(request_id.clone(), tx);
+
+        let msg = HostRegistryMessage::MigrationPreflight {
+            request_id: request_id.clone(),
+            storage_path: storage_path.to_string(),
+            network_mode,
+            container_name,
+        };
+        if let Err(e) = self.send_host_command(host_id, msg).await {
+            self.preflight_signals.write().await.remove(&request_id);
+            anyhow::bail!("{}", e);
+        }
+
+        // Measuring a large container takes a while
+        match tokio::time::timeout(std::time::Duration::from_secs(120), rx).await {
+            Ok(Ok(report)) => Ok(report),
+            Ok(Err(_)) => anyhow::bail!("Pre-flight signal channel closed"),
+            Err(_) => {
+                self.preflight_signals.write().await.remove(&request_id);
+                anyhow::bail!("Pre-flight timeout after 120s");
+            }
+        }
+    }
+
+    pub async fn on_host_preflight(&self, request_id: &str, report: HostPreflight) {
+        if let Some(tx) = self.preflight_signals.write().await.remove(request_id) {
+            let _ = tx.send(report);
+        }
+    }
+
     pub async fn on_host_import_complete(&self, _host_id: &str, transfer_id: &str, container_name: &str) {
         if let Some(tx) = self.migration_signals.write().await.remove(transfer_id) {
             let _ = tx.send(MigrationResult::ImportComplete { container_name: container_name.to_string() });
@@ -1096,6 +1393,18 @@ impl AgentRegistry {
     }
 
     pub async fn exec_in_remote_container(&self, host_id: &str, container_name: &str, command: Vec<String>) -> Result<(bool, String, String)> {
+        self.exec_in_remote_container_timeout(host_id, container_name, command, std::time::Duration::from_secs(60)).await
+    }
+
+    /// Same as `exec_in_remote_container`, waiting up to `timeout` for the
+    /// result. The command is not killed on the host when it expires.
+    pub async fn exec_in_remote_container_timeout(
+        &self,
+        host_id: &str,
+        container_name: &str,
+        command: Vec<String>,
+        timeout: std::time::Duration,
+    ) -> Result<(bool, String, String)> {
         let request_id = uuid::Uuid::new_v4().to_string();
         let (tx, rx) = tokio::sync::oneshot::channel();
         self.exec_signals.write().await.insert(request_id.clone(), tx);
@@ -1106,14 +1415,95 @@ impl AgentRegistry {
             command,
         }).await.map_err(|e| anyhow::anyhow!("{}", e))?;
 
-        match tokio::time::timeout(std::time::Duration::from_secs(60), rx).await {
+        self.wait_exec_result(&request_id, rx, timeout).await
+    }
+
+    /// Start, stop or inspect a Docker/Podman container of a host. Returns
+    /// the success flag, the output (`inspect` JSON) and the error.
+    pub async fn runtime_container_action(
+        &self,
+        host_id: &str,
+        runtime: ContainerRuntime,
+        container: &str,
+        action: RuntimeAction,
+    ) -> Result<(bool, String, String)> {
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.exec_signals.write().await.insert(request_id.clone(), tx);
+
+        if let Err(e) = self.send_host_command(host_id, HostRegistryMessage::RuntimeContainerAction {
+            request_id: request_id.clone(),
+            runtime,
+            container: container.to_string(),
+            action,
+        }).await {
+            self.exec_signals.write().await.remove(&request_id);
+            anyhow::bail!("{}", e);
+        }
+
+        self.wait_exec_result(&request_id, rx, std::time::Duration::from_secs(60)).await
+    }
+
+    /// systemd services of a host matching `patterns`.
+    pub async fn list_host_services(&self, host_id: &str, patterns: Vec<String>) -> Result<Vec<SystemdService>> {
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.exec_signals.write().await.insert(request_id.clone(), tx);
+
+        if let Err(e) = self.send_host_command(host_id, HostRegistryMessage::ListSystemdServices {
+            request_id: request_id.clone(),
+            patterns,
+        }).await {
+            self.exec_signals.write().await.remove(&request_id);
+            anyhow::bail!("{}", e);
+        }
+
+        let (success, stdout, stderr) = self.wait_exec_result(&request_id, rx, std::time::Duration::from_secs(30)).await?;
+        if !success {
+            anyhow::bail!("{}", stderr);
+        }
+        serde_json::from_str(&stdout).context("Invalid service list")
+    }
+
+    /// Start, stop, restart or get the status of a systemd service of a
+    /// host. Returns the success flag, the output (status text) and the error.
+    pub async fn host_service_action(
+        &self,
+        host_id: &str,
+        unit: &str,
+        action: SystemdAction,
+    ) -> Result<(bool, String, String)> {
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.exec_signals.write().await.insert(request_id.clone(), tx);
+
+        if let Err(e) = self.send_host_command(host_id, HostRegistryMessage::SystemdServiceAction {
+            request_id: request_id.clone(),
+            unit: unit.to_string(),
+            action,
+        }).await {
+            self.exec_signals.write().await.remove(&request_id);
+            anyhow::bail!("{}", e);
+        }
+
+        // Stopping a service waits for its stop timeout (90s by default)
+        self.wait_exec_result(&request_id, rx, std::time::Duration::from_secs(120)).await
+    }
+
+    async fn wait_exec_result(
+        &self,
+        request_id: &str,
+        rx: tokio::sync::oneshot::Receiver<(bool, String, String)>,
+        timeout: std::time::Duration,
+    ) -> Result<(bool, String, String)> {
+        match tokio::time::timeout(timeout, rx).await {
             Ok(Ok(result)) => Ok(result),
             Ok(Err(_)) => {
                 anyhow::bail!("Exec signal channel closed");
             }
             Err(_) => {
-                self.exec_signals.write().await.remove(&request_id);
-                anyhow::bail!("Exec timeout after 60s");
+                self.exec_signals.write().await.remove(request_id);
+                anyhow::bail!("Exec timeout after {}s", timeout.as_secs());
             }
         }
     }
@@ -1353,6 +1743,11 @@ impl AgentRegistry {
         let connections = self.connections.read().await;
         let conn = connections.get(app_id)
             .ok_or_else(|| anyhow::anyhow!("Agent not connected for app {}", app_id))?;
+        if let Some(capability) = msg.capability()
+            && !conn.protocol.supports(capability)
+        {
+            anyhow::bail!("Agent of app {} does not support {}, upgrade it", app_id, capability);
+        }
         conn.tx.send(msg).await.map_err(|_| anyhow::anyhow!("Failed to send to agent"))?;
         Ok(())
     }
@@ -1392,45 +1787,26 @@ impl AgentRegistry {
     // ── Agent Update ────────────────────────────────────────────────
 
     /// Trigger update to specified agents (or all connected if None).
-    /// Sends `UpdateAvailable` message to each agent with the current binary info.
+    /// Sends `UpdateAvailable` message to each agent with the signed manifest
+    /// of the binary built for its architecture.
     pub async fn trigger_update(
         &self,
         agent_ids: Option<Vec<String>>,
     ) -> Result<UpdateBatchResult> {
-        use ring::digest::{Context, SHA256};
-        use std::io::Read;
-
-        // Read current binary and compute SHA256
-        let binary_path = Path::new("/opt/homeroute/data/agent-binaries/hr-agent");
-        if !binary_path.exists() {
-            anyhow::bail!("Agent binary not found at {}", binary_path.display());
-        }
-
-        let metadata = std::fs::metadata(binary_path)?;
-        let modified = metadata
-            .modified()
-            .map(|t| {
-                let dt: DateTime<Utc> = t.into();
-                dt.format("%Y%m%d-%H%M%S").to_string()
-            })
-            .unwrap_or_else(|_| "unknown".to_string());
-
-        let mut file = std::fs::File::open(binary_path)?;
-        let mut context = Context::new(&SHA256);
-        let mut buffer = [0u8; 8192];
-        loop {
-            let count = file.read(&mut buffer)?;
-            if count == 0 {
-                break;
-            }
-            context.update(&buffer[..count]);
-        }
-        let sha256 = hex::encode(context.finish().as_ref());
+        let store = BinaryStore::default();
+        let artifacts: HashMap<&str, Artifact> = ARCHES
+            .iter()
+            .filter_map(|arch| Some((*arch, store.artifact("hr-agent", arch).ok().flatten()?)))
+            .collect();
+        let Some(server) = artifacts.get(local_arch()).or_else(|| artifacts.values().next()).cloned() else {
+            anyhow::bail!("No agent binary found in {}", BINARIES_DIR);
+        };
 
-        let download_url = format!(
-            "http://10.0.0.254:{}/api/applications/agents/binary",
-            self.env.api_port
-        );
+        // Manifests signed at release time, relayed as they are
+        let manifests: HashMap<&str, SignedManifest> = artifacts
+            .iter()
+            .filter_map(|(arch, artifact)| Some((*arch, artifact.manifest.clone()?)))
+            .collect();
 
         let state = self.state.read().await;
         let conns = self.connections.read().await;
@@ -1450,10 +1826,30 @@ impl AgentRegistry {
             }
 
             if let Some(conn) = conns.get(&app.id) {
+                // Agents older than the arch field run on the server's architecture
+                let arch = conn.protocol.arch.as_deref().unwrap_or(local_arch());
+                let Some(artifact) = artifacts.get(arch) else {
+                    skipped.push(AgentSkipResult {
+                        id: app.id.clone(),
+                        slug: app.slug.clone(),
+                        reason: "no_binary_for_arch".to_string(),
+                    });
+                    continue;
+                };
+                let Some(manifest) = manifests.get(arch) else {
+                    skipped.push(AgentSkipResult {
+                        id: app.id.clone(),
+                        slug: app.slug.clone(),
+                        reason: "unsigned_binary".to_string(),
+                    });
+                    continue;
+                };
                 let msg = RegistryMessage::UpdateAvailable {
-                    version: modified.clone(),
-                    download_url: download_url.clone(),
-                    sha256: sha256.clone(),
+                    download_url: format!(
+                        "http://10.0.0.254:{}/api/applications/agents/binary?arch={}",
+                        self.env.api_port, arch
+                    ),
+                    manifest: manifest.clone(),
                 };
 
                 if conn.tx.send(msg).await.is_ok() {
@@ -1468,11 +1864,11 @@ impl AgentRegistry {
                         app_id: app.id.clone(),
                         slug: app.slug.clone(),
                         status: AgentUpdateStatus::Notified,
-                        version: Some(modified.clone()),
+                        version: Some(artifact.version.clone()),
                         error: None,
                     });
 
-                    info!(app = app.slug, version = modified, "Update notification sent");
+                    info!(app = app.slug, arch, version = artifact.version, "Update notification sent");
                 } else {
                     skipped.push(AgentSkipResult {
                         id: app.id.clone(),
@@ -1492,37 +1888,31 @@ impl AgentRegistry {
         info!(
             notified = notified.len(),
             skipped = skipped.len(),
-            version = modified,
+            version = server.version,
             "Agent update triggered"
         );
 
         Ok(UpdateBatchResult {
-            version: modified,
-            sha256,
+            version: server.version,
+            sha256: server.sha256,
+            artifacts: artifacts.into_values().collect(),
             agents_notified: notified,
             agents_skipped: skipped,
         })
     }
 
-    /// Get update status for all agents: whether they're connected with the expected version.
+    /// Get update status for all agents: whether they're connected with the
+    /// version of the binary for their architecture.
     pub async fn get_update_status(&self) -> Result<UpdateStatusResult> {
-        use ring::digest::{Context, SHA256};
-        use std::io::Read;
-
-        // Get expected version from current binary
-        let binary_path = Path::new("/opt/homeroute/data/agent-binaries/hr-agent");
-        let expected_version = if binary_path.exists() {
-            std::fs::metadata(binary_path)
-                .ok()
-                .and_then(|m| m.modified().ok())
-                .map(|t| {
-                    let dt: DateTime<Utc> = t.into();
-                    dt.format("%Y%m%d-%H%M%S").to_string()
-                })
-                .unwrap_or_else(|| "unknown".to_string())
-        } else {
-            "no_binary".to_string()
-        };
+        let store = BinaryStore::default();
+        let versions: HashMap<&str, String> = ARCHES
+            .iter()
+            .filter_map(|arch| Some((*arch, store.artifact("hr-agent", arch).ok().flatten()?.version)))
+            .collect();
+        let expected_version = versions
+            .get(local_arch())
+            .cloned()
+            .unwrap_or_else(|| "no_binary".to_string());
 
         let state = self.state.read().await;
         let conns = self.connections.read().await;
@@ -1532,12 +1922,14 @@ impl AgentRegistry {
             .applications
             .iter()
             .map(|app| {
-                let is_connected = conns.contains_key(&app.id);
+                let conn = conns.get(&app.id);
+                let is_connected = conn.is_some();
+                let arch = conn.map(|c| c.protocol.arch.clone().unwrap_or_else(|| local_arch().to_string()));
+                let expected = versions.get(arch.as_deref().unwrap_or(local_arch()));
                 let version_matches = app
                     .agent_version
                     .as_ref()
-                    .map(|v| v == &expected_version)
-                    .unwrap_or(false);
+                    .is_some_and(|v| Some(v) == expected);
                 let has_recent_heartbeat = app
                     .last_heartbeat
                     .map(|hb| now - hb < chrono::Duration::seconds(90))
@@ -1562,6 +1954,9 @@ impl AgentRegistry {
                     }
                     .to_string(),
                     current_version: app.agent_version.clone(),
+                    protocol_version: conn.map(|c| c.protocol.version),
+                    protocol_outdated: conn.is_some_and(|c| c.protocol.outdated()),
+                    arch,
                     update_status: update_status.to_string(),
                     metrics_flowing: is_connected && has_recent_heartbeat,
                     last_heartbeat: app.last_heartbeat,
@@ -1594,7 +1989,7 @@ impl AgentRegistry {
 
         // Download new binary directly in the container and restart
         let download_cmd = format!(
-            "curl -fsSL http://10.0.0.254:{}/api/applications/agents/binary -o /usr/local/bin/hr-agent.new && \
+            "curl -fsSL \"http://10.0.0.254:{}/api/applications/agents/binary?arch=$(uname -m)\" -o /usr/local/bin/hr-agent.new && \
              chmod +x /usr/local/bin/hr-agent.new && \
              mv /usr/local/bin/hr-agent.new /usr/local/bin/hr-agent && \
              systemctl restart hr-agent",
@@ -1658,6 +2053,15 @@ impl AgentRegistry {
                 tracing::info!("Cleaned up {} stale transfer container name mappings", removed);
             }
         }
+        {
+            let mut resumes = self.transfer_resumes.write().await;
+            let before = resumes.len();
+            resumes.retain(|_tid, tx| !tx.is_closed());
+            let removed = before - resumes.len();
+            if removed > 0 {
+                tracing::info!("Cleaned up {} stale transfer resume signals", removed);
+            }
+        }
         {
             let signal_keys: std::collections::HashSet<String> = self.migration_signals.read().await.keys().cloned().collect();
             let mut relays = self.transfer_relay_targets.write().await;
@@ -1699,6 +2103,88 @@ impl AgentRegistry {
         }
     }
 
+    // ── Log streams ─────────────────────────────────────────────
+
+    /// Ask the agent of an app for its container journal. Returns the
+    /// request_id and the queue the lines arrive on.
+    pub async fn open_agent_log_stream(&self, app_id: &str, request: LogRequest) -> Result<(String, mpsc::Receiver<LogEvent>)> {
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let rx = self.register_log_stream(&request_id, LogOrigin::App(app_id.to_string())).await;
+        let msg = RegistryMessage::LogStream { request_id: request_id.clone(), request };
+        if let Err(e) = self.send_to_agent(app_id, msg).await {
+            self.log_streams.write().await.remove(&request_id);
+            return Err(e);
+        }
+        Ok((request_id, rx))
+    }
+
+    /// Ask a host agent for the journal of one of its containers.
+    pub async fn open_host_log_stream(&self, host_id: &str, request: LogRequest) -> Result<(String, mpsc::Receiver<LogEvent>)> {
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let rx = self.register_log_stream(&request_id, LogOrigin::Host(host_id.to_string())).await;
+        let msg = HostRegistryMessage::LogStream { request_id: request_id.clone(), request };
+        if let Err(e) = self.send_host_command(host_id, msg).await {
+            self.log_streams.write().await.remove(&request_id);
+            anyhow::bail!("{}", e);
+        }
+        Ok((request_id, rx))
+    }
+
+    async fn register_log_stream(&self, request_id: &str, origin: LogOrigin) -> mpsc::Receiver<LogEvent> {
+        let (sink, rx) = LogSink::new();
+        self.log_streams.write().await.insert(request_id.to_string(), (origin, sink));
+        rx
+    }
+
+    /// Route a `LogStreamData` batch to its reader. Never waits on the
+    /// reader: the agent connection carries other messages too.
+    pub async fn on_log_stream_data(&self, request_id: &str, lines: Vec<String>) {
+        let reader_gone = match self.log_streams.write().await.get_mut(request_id) {
+            Some((_, sink)) => !sink.push(lines),
+            None => false,
+        };
+        if reader_gone {
+            self.close_log_stream(request_id).await;
+        }
+    }
+
+    pub async fn on_log_stream_end(&self, request_id: &str, error: Option<String>) {
+        if let Some((_, sink)) = self.log_streams.write().await.remove(request_id) {
+            sink.end(error);
+        }
+    }
+
+    /// Stop a stream whose reader went away.
+    pub async fn close_log_stream(&self, request_id: &str) {
+        let Some((origin, _)) = self.log_streams.write().await.remove(request_id) else {
+            return;
+        };
+        let request_id = request_id.to_string();
+        let result = match origin {
+            LogOrigin::App(app_id) => self
+                .send_to_agent(&app_id, RegistryMessage::LogStreamClose { request_id })
+                .await
+                .map_err(|e| e.to_string()),
+            LogOrigin::Host(host_id) => {
+                self.send_host_command(&host_id, HostRegistryMessage::LogStreamClose { request_id }).await
+            }
+        };
+        if let Err(e) = result {
+            tracing::debug!("Failed to close log stream: {e}");
+        }
+    }
+
+    /// End the streams served by an agent that went away.
+    async fn end_log_streams(&self, served_by: impl Fn(&LogOrigin) -> bool, error: &str) {
+        let mut streams = self.log_streams.write().await;
+        let ended: Vec<String> = streams.iter().filter(|(_, (origin, _))| served_by(origin)).map(|(id, _)| id.clone()).collect();
+        for id in ended {
+            if let Some((_, sink)) = streams.remove(&id) {
+                sink.end(Some(error.to_string()));
+            }
+        }
+    }
+
     /// Persist state to disk (atomic write).
     async fn persist(&self) -> Result<()> {
         let state = self.state.read().await;
@@ -1716,14 +2202,16 @@ impl AgentRegistry {
 
 // ── Token helpers ───────────────────────────────────────────────
 
-fn generate_token() -> String {
+/// Random 256-bit token, hex-encoded (agent credentials).
+pub fn generate_token() -> String {
     use rand::Rng;
     let mut bytes = [0u8; 32];
     rand::rng().fill(&mut bytes);
     hex::encode(bytes)
 }
 
-fn hash_token(token: &str) -> Result<String> {
+/// Argon2 hash of a token, as stored.
+pub fn hash_token(token: &str) -> Result<String> {
     use argon2::{Argon2, PasswordHasher};
     use argon2::password_hash::SaltString;
     use rand_core::OsRng;
@@ -1736,7 +2224,8 @@ fn hash_token(token: &str) -> Result<String> {
     Ok(hash.to_string())
 }
 
-fn verify_token(token: &str, hash: &str) -> bool {
+/// Whether `token` matches a hash made by `hash_token`.
+pub fn verify_token(token: &str, hash: &str) -> bool {
     use argon2::{Argon2, PasswordVerifier};
     use argon2::password_hash::PasswordHash;
 
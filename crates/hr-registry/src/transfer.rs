@@ -0,0 +1,416 @@
+//! Resumable container transfers (capability `resumable_transfers`).
+//!
+//! A transfer carries one or two tar streams — the container rootfs, then
+//! its workspace — cut in chunks of [`CHUNK_SIZE`] bytes, only the last one
+//! shorter. Fixed chunks keep their boundaries when the sender regenerates a
+//! stream after an interruption (the container is stopped, so tar produces
+//! the same bytes again).
+//!
+//! The receiver records each chunk (index, offset, xxhash32) in a
+//! [`StreamManifest`] kept with the partial data. After a reconnection it
+//! sends `ResumeTransfer` with its [`ResumePoint`]; the sender replays the
+//! stream, checks the last chunk already received against its checksum and
+//! carries on from there. At the end of each stream the sender sends a
+//! `TransferManifest` (chunk count, size, digest of the chunk checksums) the
+//! receiver compares with its own before extracting anything.
+//!
+//! With `zstd_transfers`, the tar streams are compressed before being cut in
+//! chunks ([`Compression`]): offsets, checksums and manifests then cover the
+//! compressed bytes, zstd output being the same for the same input and level.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, BufReader, ReadBuf};
+
+/// Size of every chunk but the last of a stream.
+pub const CHUNK_SIZE: usize = 512 * 1024;
+
+/// zstd level used when a migration does not pick one.
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+/// Highest zstd level accepted (above, zstd needs far more memory).
+pub const MAX_ZSTD_LEVEL: i32 = 19;
+
+/// Compression of the tar streams of a transfer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    #[default]
+    None,
+    /// zstd, from 1 (fast, light on CPU) to [`MAX_ZSTD_LEVEL`] (smallest).
+    Zstd { level: i32 },
+}
+
+impl Compression {
+    /// Compression for a requested level (`None`: default, 0: off), when
+    /// both ends of the transfer support it.
+    pub fn negotiate(level: Option<i32>, supported: bool) -> Self {
+        match level.unwrap_or(DEFAULT_ZSTD_LEVEL) {
+            level if supported && level > 0 => Self::Zstd { level: level.min(MAX_ZSTD_LEVEL) },
+            _ => Self::None,
+        }
+    }
+
+    /// Compress a tar stream on its way to the [`ChunkReader`].
+    pub fn encoder<R>(self, reader: R) -> Box<dyn AsyncRead + Unpin + Send>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        match self {
+            Self::None => Box::new(reader),
+            Self::Zstd { level } => Box::new(async_compression::tokio::bufread::ZstdEncoder::with_quality(
+                BufReader::new(reader),
+                async_compression::Level::Precise(level),
+            )),
+        }
+    }
+
+    /// Decompress received chunks into `writer` (`shutdown` ends the stream).
+    pub fn decoder<W>(self, writer: W) -> Box<dyn AsyncWrite + Unpin + Send + Sync>
+    where
+        W: AsyncWrite + Unpin + Send + Sync + 'static,
+    {
+        match self {
+            Self::None => Box::new(writer),
+            Self::Zstd { .. } => Box::new(async_compression::tokio::write::ZstdDecoder::new(writer)),
+        }
+    }
+}
+
+/// The streams of a transfer, in sending order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferStream {
+    #[default]
+    Container,
+    Workspace,
+}
+
+/// Counts the bytes read or written through it: progress of a compressed
+/// stream is measured on the tar data, not on the chunks.
+pub struct Counted<T> {
+    inner: T,
+    count: Arc<AtomicU64>,
+}
+
+impl<T> Counted<T> {
+    pub fn new(inner: T) -> (Self, Arc<AtomicU64>) {
+        let count = Arc::new(AtomicU64::new(0));
+        (Self { inner, count: count.clone() }, count)
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Counted<T> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        self.count.fetch_add((buf.filled().len() - before) as u64, Ordering::Relaxed);
+        poll
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Counted<T> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.count.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// One chunk received.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkEntry {
+    pub sequence: u32,
+    pub offset: u64,
+    pub size: u32,
+    pub checksum: u32,
+}
+
+/// Where a sender restarts an interrupted transfer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumePoint {
+    pub stream: TransferStream,
+    /// First chunk the receiver lacks.
+    pub next_sequence: u32,
+    /// Checksum of the chunk before, checked against the replayed stream.
+    pub last_checksum: Option<u32>,
+}
+
+/// How an incoming chunk fits the chunks received so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkOrder {
+    /// The next chunk: write it.
+    Next,
+    /// Already received (sent again before the resume point got through).
+    Duplicate,
+    /// Past a hole: ask the sender to resume.
+    Gap,
+}
+
+/// Chunks received for one stream.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamManifest {
+    pub chunks: Vec<ChunkEntry>,
+}
+
+impl StreamManifest {
+    pub fn next_sequence(&self) -> u32 {
+        self.chunks.len() as u32
+    }
+
+    /// Bytes received, i.e. the offset of the next chunk.
+    pub fn offset(&self) -> u64 {
+        self.chunks.last().map(|c| c.offset + c.size as u64).unwrap_or(0)
+    }
+
+    pub fn check(&self, sequence: u32, offset: u64) -> ChunkOrder {
+        let next = self.next_sequence();
+        if sequence < next {
+            ChunkOrder::Duplicate
+        } else if sequence == next && offset == self.offset() {
+            ChunkOrder::Next
+        } else {
+            ChunkOrder::Gap
+        }
+    }
+
+    /// Append the next chunk.
+    pub fn record(&mut self, size: u32, checksum: u32) {
+        self.chunks.push(ChunkEntry {
+            sequence: self.next_sequence(),
+            offset: self.offset(),
+            size,
+            checksum,
+        });
+    }
+
+    /// Drop the chunks from `sequence` on (data not known to be on disk).
+    pub fn truncate(&mut self, sequence: u32) {
+        self.chunks.truncate(sequence as usize);
+    }
+
+    pub fn resume_point(&self, stream: TransferStream) -> ResumePoint {
+        ResumePoint {
+            stream,
+            next_sequence: self.next_sequence(),
+            last_checksum: self.chunks.last().map(|c| c.checksum),
+        }
+    }
+
+    pub fn digest(&self) -> u64 {
+        let mut digest = Digest::default();
+        for chunk in &self.chunks {
+            digest.update(chunk.checksum);
+        }
+        digest.finish()
+    }
+
+    /// Compare with the sender's `TransferManifest`.
+    pub fn verify(&self, chunk_count: u32, total_bytes: u64, digest: u64) -> Result<(), String> {
+        if self.next_sequence() != chunk_count || self.offset() != total_bytes {
+            return Err(format!(
+                "Incomplete stream: {} chunks / {} bytes received, {} / {} sent",
+                self.next_sequence(),
+                self.offset(),
+                chunk_count,
+                total_bytes
+            ));
+        }
+        if self.digest() != digest {
+            return Err("Stream digest mismatch".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Digest of a stream: xxhash64 of its chunk checksums in order.
+#[derive(Default)]
+pub struct Digest(xxhash_rust::xxh64::Xxh64);
+
+impl Digest {
+    pub fn update(&mut self, checksum: u32) {
+        self.0.update(&checksum.to_le_bytes());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0.digest()
+    }
+}
+
+/// Sender side of a stream: fixed chunks, running digest.
+pub struct ChunkReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    sequence: u32,
+    offset: u64,
+    digest: Digest,
+}
+
+impl<R: AsyncRead + Unpin> ChunkReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: vec![0u8; CHUNK_SIZE],
+            sequence: 0,
+            offset: 0,
+            digest: Digest::default(),
+        }
+    }
+
+    /// Next chunk and its data, `None` at the end of the stream.
+    pub async fn next_chunk(&mut self) -> std::io::Result<Option<(ChunkEntry, &[u8])>> {
+        let mut len = 0;
+        while len < CHUNK_SIZE {
+            match self.reader.read(&mut self.buf[len..]).await? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        if len == 0 {
+            return Ok(None);
+        }
+        let data = &self.buf[..len];
+        let checksum = xxhash_rust::xxh32::xxh32(data, 0);
+        self.digest.update(checksum);
+        let entry = ChunkEntry {
+            sequence: self.sequence,
+            offset: self.offset,
+            size: len as u32,
+            checksum,
+        };
+        self.sequence += 1;
+        self.offset += len as u64;
+        Ok(Some((entry, data)))
+    }
+
+    /// Read through the chunks the receiver already has, checking the last
+    /// one against what it received.
+    pub async fn skip_to(&mut self, next_sequence: u32, last_checksum: Option<u32>) -> Result<(), String> {
+        let mut last = None;
+        while self.sequence < next_sequence {
+            match self.next_chunk().await.map_err(|e| format!("Read error: {e}"))? {
+                Some((entry, _)) => last = Some(entry.checksum),
+                None => return Err("Stream ended before the resume point".to_string()),
+            }
+        }
+        if last_checksum.is_some() && last != last_checksum {
+            return Err("Source changed since the interruption, cannot resume".to_string());
+        }
+        Ok(())
+    }
+
+    /// Chunks sent so far, bytes and digest, as in `TransferManifest`.
+    pub fn summary(&self) -> (u32, u64, u64) {
+        (self.sequence, self.offset, self.digest.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    async fn receive(data: &[u8]) -> StreamManifest {
+        let mut reader = ChunkReader::new(data);
+        let mut manifest = StreamManifest::default();
+        while let Some((entry, chunk)) = reader.next_chunk().await.unwrap() {
+            assert_eq!(manifest.check(entry.sequence, entry.offset), ChunkOrder::Next);
+            manifest.record(chunk.len() as u32, entry.checksum);
+        }
+        let (count, bytes, digest) = reader.summary();
+        manifest.verify(count, bytes, digest).unwrap();
+        manifest
+    }
+
+    #[tokio::test]
+    async fn test_fixed_chunks() {
+        let data = stream(CHUNK_SIZE * 2 + 10);
+        let manifest = receive(&data).await;
+        assert_eq!(manifest.next_sequence(), 3);
+        assert_eq!(manifest.chunks[2].offset, CHUNK_SIZE as u64 * 2);
+        assert_eq!(manifest.chunks[2].size, 10);
+        assert_eq!(manifest.check(1, CHUNK_SIZE as u64), ChunkOrder::Duplicate);
+        assert_eq!(manifest.check(4, 0), ChunkOrder::Gap);
+        assert!(manifest.verify(3, data.len() as u64, manifest.digest() ^ 1).is_err());
+        assert!(manifest.verify(2, data.len() as u64, manifest.digest()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_zstd_stream() {
+        use tokio::io::AsyncWriteExt;
+
+        let data = stream(CHUNK_SIZE * 3);
+        let compression = Compression::negotiate(None, true);
+        assert_eq!(compression, Compression::Zstd { level: DEFAULT_ZSTD_LEVEL });
+
+        let mut compressed = Vec::new();
+        compression.encoder(std::io::Cursor::new(data.clone())).read_to_end(&mut compressed).await.unwrap();
+        assert!(compressed.len() < data.len());
+
+        // Same input, same chunks: a replay can resume
+        let first = receive(&compressed).await;
+        let mut again = Vec::new();
+        compression.encoder(std::io::Cursor::new(data.clone())).read_to_end(&mut again).await.unwrap();
+        assert_eq!(receive(&again).await.digest(), first.digest());
+
+        let (mut output, input) = tokio::io::duplex(data.len() + 1);
+        let (input, written) = Counted::new(input);
+        let mut decoder = compression.decoder(input);
+        for chunk in compressed.chunks(1000) {
+            decoder.write_all(chunk).await.unwrap();
+        }
+        decoder.shutdown().await.unwrap();
+        drop(decoder);
+        assert_eq!(written.load(Ordering::Relaxed), data.len() as u64);
+        let mut decompressed = Vec::new();
+        output.read_to_end(&mut decompressed).await.unwrap();
+        assert_eq!(decompressed, data);
+
+        assert_eq!(Compression::negotiate(Some(0), true), Compression::None);
+        assert_eq!(Compression::negotiate(Some(3), false), Compression::None);
+        assert_eq!(Compression::negotiate(Some(40), true), Compression::Zstd { level: MAX_ZSTD_LEVEL });
+    }
+
+    #[tokio::test]
+    async fn test_resume() {
+        let data = stream(CHUNK_SIZE * 3 + 1);
+        let full = receive(&data).await;
+
+        // Interrupted after two chunks
+        let mut partial = full.clone();
+        partial.truncate(2);
+        let point = partial.resume_point(TransferStream::Container);
+        assert_eq!(point.next_sequence, 2);
+
+        let mut reader = ChunkReader::new(data.as_slice());
+        reader.skip_to(point.next_sequence, point.last_checksum).await.unwrap();
+        while let Some((entry, chunk)) = reader.next_chunk().await.unwrap() {
+            assert_eq!(partial.check(entry.sequence, entry.offset), ChunkOrder::Next);
+            partial.record(chunk.len() as u32, entry.checksum);
+        }
+        let (count, bytes, digest) = reader.summary();
+        partial.verify(count, bytes, digest).unwrap();
+
+        // The source changed: refused
+        let mut changed = data.clone();
+        changed[CHUNK_SIZE + 5] ^= 0xff;
+        let mut reader = ChunkReader::new(changed.as_slice());
+        assert!(reader.skip_to(point.next_sequence, point.last_checksum).await.is_err());
+    }
+}
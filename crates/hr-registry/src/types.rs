@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::net::Ipv4Addr;
 
 use crate::protocol::{AgentMetrics, PowerPolicy, ServiceConfig, ServiceType};
@@ -64,6 +65,12 @@ pub struct Application {
     /// Whether to show a wake page when service is starting (vs transparent wait).
     #[serde(default = "default_true")]
     pub wake_page_enabled: bool,
+    /// External domains attached to the application (production only).
+    #[serde(default)]
+    pub custom_domains: Vec<CustomDomain>,
+    /// Process run on the HomeRoute host instead of a container.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process: Option<ProcessSpec>,
     /// Current metrics from agent (volatile, not persisted to disk).
     #[serde(skip_deserializing)]
     pub metrics: Option<AgentMetrics>,
@@ -72,7 +79,7 @@ pub struct Application {
 impl Application {
     /// Return all domains this application serves.
     /// Dev: `code.{slug}.{base}` (if code_server_enabled).
-    /// Prod: `{slug}.{base}`.
+    /// Prod: `{slug}.{base}` plus the active custom domains.
     pub fn domains(&self, base_domain: &str) -> Vec<String> {
         match self.environment {
             Environment::Development => {
@@ -83,14 +90,16 @@ impl Application {
                 domains
             }
             Environment::Production => {
-                vec![format!("{}.{}", self.slug, base_domain)]
+                let mut domains = vec![format!("{}.{}", self.slug, base_domain)];
+                domains.extend(self.active_custom_domains().map(str::to_string));
+                domains
             }
         }
     }
 
     /// Return all (domain, port, auth_required, allowed_groups) tuples for agent routing.
     /// Dev: `code.{slug}.{base}` (if code_server_enabled).
-    /// Prod: `{slug}.{base}`.
+    /// Prod: `{slug}.{base}` plus the active custom domains (same frontend).
     pub fn routes(&self, base_domain: &str) -> Vec<RouteInfo> {
         match self.environment {
             Environment::Development => {
@@ -106,18 +115,33 @@ impl Application {
                 }
                 routes
             }
-            Environment::Production => {
-                vec![RouteInfo {
-                    domain: format!("{}.{}", self.slug, base_domain),
+            Environment::Production => self
+                .domains(base_domain)
+                .into_iter()
+                .map(|domain| RouteInfo {
+                    domain,
                     target_port: self.frontend.target_port,
                     auth_required: self.frontend.auth_required,
                     allowed_groups: self.frontend.allowed_groups.clone(),
                     service_type: ServiceType::App,
-                }]
-            }
+                })
+                .collect(),
         }
     }
 
+    /// Custom domains with an issued certificate, routed by the proxy.
+    pub fn active_custom_domains(&self) -> impl Iterator<Item = &str> {
+        self.custom_domains
+            .iter()
+            .filter(|d| d.status == CustomDomainStatus::Active)
+            .map(|d| d.domain.as_str())
+    }
+
+    /// Process app: supervised by HomeRoute on the host, no container or agent.
+    pub fn is_process(&self) -> bool {
+        self.process.is_some()
+    }
+
     /// Return the wildcard domain for this application's per-app certificate.
     /// e.g., `*.{slug}.{base_domain}`
     pub fn wildcard_domain(&self, base_domain: &str) -> String {
@@ -125,6 +149,113 @@ impl Application {
     }
 }
 
+/// How a process app is run on the HomeRoute host. It listens on
+/// `127.0.0.1:{frontend.target_port}` and is routed like a container app.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProcessSpec {
+    /// Program and arguments, run without a shell (ignored with `systemd_unit`).
+    #[serde(default)]
+    pub command: Vec<String>,
+    /// Existing systemd unit started and stopped instead of `command`.
+    #[serde(default)]
+    pub systemd_unit: Option<String>,
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    /// Extra environment; `PORT`, `HOMEROUTE_APP`, `HOMEROUTE_TOKEN` and
+    /// `HOMEROUTE_API` are always set.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Run `command` as this user instead of root.
+    #[serde(default)]
+    pub user: Option<String>,
+    /// HTTP path probed for health (2xx/3xx); a TCP connect when unset.
+    #[serde(default)]
+    pub health_path: Option<String>,
+}
+
+impl ProcessSpec {
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.systemd_unit {
+            Some(unit) => {
+                let valid = !unit.is_empty()
+                    && unit.len() <= 255
+                    && unit.chars().all(|c| c.is_ascii_alphanumeric() || "-_.@:".contains(c));
+                if !valid {
+                    return Err(format!("Invalid systemd unit: {}", unit));
+                }
+            }
+            None if self.command.first().is_none_or(|p| p.trim().is_empty()) => {
+                return Err("A command or a systemd unit is required".to_string());
+            }
+            None => {}
+        }
+        if let Some(user) = &self.user
+            && (user.is_empty() || !user.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        {
+            return Err(format!("Invalid user: {}", user));
+        }
+        if let Some(path) = &self.health_path
+            && !path.starts_with('/')
+        {
+            return Err("The health path must start with '/'".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Verification status of a custom domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomDomainStatus {
+    /// Added, DNS not (yet) pointing at us.
+    #[default]
+    Pending,
+    /// DNS checked, certificate being issued.
+    Verified,
+    /// Certificate issued, domain routed.
+    Active,
+    /// Verification or issuance failed (see `last_error`).
+    Failed,
+}
+
+/// An external domain attached to an application ("bring your own domain").
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomDomain {
+    pub domain: String,
+    #[serde(default)]
+    pub status: CustomDomainStatus,
+    pub added_at: DateTime<Utc>,
+    #[serde(default)]
+    pub verified_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+/// Lowercase a custom domain and check it is a plain hostname outside the
+/// base domain (whose names are already covered by the wildcards).
+pub fn normalize_custom_domain(domain: &str, base_domain: &str) -> Result<String, String> {
+    let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+    let labels: Vec<&str> = domain.split('.').collect();
+    if domain.len() > 253 || labels.len() < 2 {
+        return Err(format!("Invalid domain: {}", domain));
+    }
+    let valid_label = |l: &&str| {
+        !l.is_empty()
+            && l.len() <= 63
+            && !l.starts_with('-')
+            && !l.ends_with('-')
+            && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    if !labels.iter().all(valid_label) {
+        return Err(format!("Invalid domain: {}", domain));
+    }
+    let base = base_domain.to_ascii_lowercase();
+    if domain == base || domain.ends_with(&format!(".{}", base)) {
+        return Err(format!("{} is already served under {}", domain, base));
+    }
+    Ok(domain)
+}
+
 /// Route metadata for proxy registration at startup and agent-driven publishing.
 #[derive(Debug, Clone)]
 pub struct RouteInfo {
@@ -199,6 +330,8 @@ pub struct CreateApplicationRequest {
     pub power_policy: PowerPolicy,
     #[serde(default = "default_true")]
     pub wake_page_enabled: bool,
+    #[serde(default)]
+    pub process: Option<ProcessSpec>,
 }
 
 /// Request body for updating an application.
@@ -220,6 +353,8 @@ pub struct UpdateApplicationRequest {
     pub power_policy: Option<PowerPolicy>,
     #[serde(default)]
     pub wake_page_enabled: Option<bool>,
+    #[serde(default)]
+    pub process: Option<ProcessSpec>,
 }
 
 // ── Agent Update Types ──────────────────────────────────────────
@@ -253,6 +388,8 @@ pub struct AgentSkipResult {
 pub struct UpdateBatchResult {
     pub version: String,
     pub sha256: String,
+    /// Binaries sent, one per architecture.
+    pub artifacts: Vec<crate::binaries::Artifact>,
     pub agents_notified: Vec<AgentNotifyResult>,
     pub agents_skipped: Vec<AgentSkipResult>,
 }
@@ -265,6 +402,12 @@ pub struct AgentUpdateStatusInfo {
     pub container_name: String,
     pub status: String,
     pub current_version: Option<String>,
+    /// Protocol version of the connected agent.
+    pub protocol_version: Option<u32>,
+    /// Connected with an older protocol than the registry's.
+    pub protocol_outdated: bool,
+    /// Architecture of the connected agent.
+    pub arch: Option<String>,
     pub update_status: String,
     pub metrics_flowing: bool,
     pub last_heartbeat: Option<DateTime<Utc>>,
@@ -307,6 +450,8 @@ mod tests {
             services: ServiceConfig::default(),
             power_policy: PowerPolicy::default(),
             wake_page_enabled: true,
+            custom_domains: vec![],
+            process: None,
             metrics: None,
         }
     }
@@ -349,6 +494,37 @@ mod tests {
         assert_eq!(routes[0].domain, "myapp.example.com");
     }
 
+    #[test]
+    fn test_routes_custom_domains() {
+        let mut app = make_test_app(Environment::Production, true);
+        for (domain, status) in [
+            ("shop.example.org", CustomDomainStatus::Active),
+            ("www.example.net", CustomDomainStatus::Pending),
+        ] {
+            app.custom_domains.push(CustomDomain {
+                domain: domain.into(),
+                status,
+                added_at: Utc::now(),
+                verified_at: None,
+                last_error: None,
+            });
+        }
+        let routes = app.routes("example.com");
+        let domains: Vec<&str> = routes.iter().map(|r| r.domain.as_str()).collect();
+        assert_eq!(domains, vec!["myapp.example.com", "shop.example.org"]);
+        assert_eq!(routes[1].target_port, 3000);
+    }
+
+    #[test]
+    fn test_normalize_custom_domain() {
+        assert_eq!(normalize_custom_domain(" Shop.Example.org. ", "example.com").unwrap(), "shop.example.org");
+        assert!(normalize_custom_domain("localhost", "example.com").is_err());
+        assert!(normalize_custom_domain("a.example.com", "example.com").is_err());
+        assert!(normalize_custom_domain("example.com", "example.com").is_err());
+        assert!(normalize_custom_domain("*.example.org", "example.com").is_err());
+        assert!(normalize_custom_domain("bad_name.example.org", "example.com").is_err());
+    }
+
     #[test]
     fn test_wildcard_domain() {
         let app = make_test_app(Environment::Development, true);
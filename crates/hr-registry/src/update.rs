@@ -0,0 +1,270 @@
+//! Signed update manifests for the agent binaries.
+//!
+//! A [`UpdateManifest`] (component, version, architecture, SHA-256 of the
+//! binary, protocol needed) is signed at release time, offline, with an
+//! ed25519 key that never lives on the router (`hr-sign-update`). The
+//! signed manifest is uploaded next to the binary and HomeRoute only relays
+//! it. Agents pin the public key in their config (`update_key`) and only
+//! install a binary whose manifest verifies with it: whoever controls the
+//! API, an admin session or the download URL cannot push a binary of their
+//! own to every host. A manifest older than the running version is refused
+//! unless it was signed with `allow_downgrade`, so an old signed manifest
+//! cannot be replayed to roll an agent back to a vulnerable build.
+//!
+//! The manifest travels as the JSON text that was signed, so verifying does
+//! not depend on how it is serialized again.
+
+use std::cmp::Ordering;
+use std::path::Path;
+
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+
+use crate::binaries::local_arch;
+
+/// Public key of the release signing key, hex, installed with HomeRoute.
+/// Uploaded binaries are checked against it and new agent configs pin it.
+pub const UPDATE_KEY_PATH: &str = "/opt/homeroute/data/update-key.pub";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateManifest {
+    /// `hr-agent` or `hr-host-agent`.
+    pub component: String,
+    pub version: String,
+    /// Architecture the binary is built for (`x86_64`, `aarch64`, `armv7`).
+    pub arch: String,
+    /// SHA-256 of the binary, hex.
+    pub sha256: String,
+    /// Oldest registry protocol the new binary can talk to.
+    pub min_protocol: u32,
+    /// Install even when `version` is not newer than the running one.
+    #[serde(default)]
+    pub allow_downgrade: bool,
+}
+
+/// A manifest as sent to the agents: the signed JSON text and its
+/// signature, hex.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedManifest {
+    pub manifest: String,
+    pub signature: String,
+}
+
+/// The release signing key. Only `hr-sign-update` uses it, on the release
+/// machine: the router never holds it.
+pub struct UpdateSigner {
+    key_pair: Ed25519KeyPair,
+}
+
+impl UpdateSigner {
+    /// Generate a key into `path`, which must not exist yet.
+    pub fn generate(path: &Path) -> Result<Self, String> {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| "Failed to generate the update signing key".to_string())?;
+        write_private(path, pkcs8.as_ref())?;
+        Self::from_pkcs8(path, pkcs8.as_ref())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let pkcs8 = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_pkcs8(path, &pkcs8)
+    }
+
+    fn from_pkcs8(path: &Path, pkcs8: &[u8]) -> Result<Self, String> {
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8)
+            .map_err(|e| format!("Invalid update signing key {}: {}", path.display(), e))?;
+        Ok(Self { key_pair })
+    }
+
+    /// Public key to install as [`UPDATE_KEY_PATH`], hex.
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.key_pair.public_key().as_ref())
+    }
+
+    pub fn sign(&self, manifest: &UpdateManifest) -> SignedManifest {
+        let text = serde_json::to_string(manifest).expect("manifest serializes");
+        let signature = self.key_pair.sign(text.as_bytes());
+        SignedManifest {
+            manifest: text,
+            signature: hex::encode(signature.as_ref()),
+        }
+    }
+}
+
+/// The installed release public key, hex.
+pub fn update_key() -> Result<String, String> {
+    let key = std::fs::read_to_string(UPDATE_KEY_PATH)
+        .map_err(|e| format!("No update key in {}: {}", UPDATE_KEY_PATH, e))?;
+    parse_key(&key)
+}
+
+fn parse_key(key: &str) -> Result<String, String> {
+    match hex::decode(key.trim()) {
+        Ok(bytes) if bytes.len() == 32 => Ok(key.trim().to_lowercase()),
+        _ => Err(format!("Invalid update key in {}", UPDATE_KEY_PATH)),
+    }
+}
+
+/// `update_key` line of a new agent config. Empty when no release key is
+/// installed: the agent then refuses updates until it is added.
+pub fn config_line() -> String {
+    match update_key() {
+        Ok(key) => format!("update_key = \"{}\"\n", key),
+        Err(e) => {
+            tracing::warn!("Agent config written without update key: {}", e);
+            String::new()
+        }
+    }
+}
+
+fn write_private(path: &Path, data: &[u8]) -> Result<(), String> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut f| f.write_all(data))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+impl SignedManifest {
+    /// The manifest, signature not checked (the agents check it).
+    pub fn parsed(&self) -> Result<UpdateManifest, String> {
+        serde_json::from_str(&self.manifest).map_err(|e| format!("Invalid manifest: {}", e))
+    }
+
+    /// The manifest, if signed by `public_key_hex`.
+    pub fn verify(&self, public_key_hex: &str) -> Result<UpdateManifest, String> {
+        let public_key = hex::decode(public_key_hex.trim()).map_err(|_| "Invalid update key".to_string())?;
+        let signature = hex::decode(&self.signature).map_err(|_| "Invalid manifest signature".to_string())?;
+        UnparsedPublicKey::new(&ED25519, &public_key)
+            .verify(self.manifest.as_bytes(), &signature)
+            .map_err(|_| "Manifest signature does not match the pinned update key".to_string())?;
+        self.parsed()
+    }
+}
+
+impl UpdateManifest {
+    /// Check that the manifest is meant for this `component`, built for
+    /// this machine, newer than `running_version` (unless it allows a
+    /// downgrade) and able to talk to a registry speaking `registry_protocol`.
+    pub fn check(&self, component: &str, running_version: &str, registry_protocol: u32) -> Result<(), String> {
+        if self.component != component {
+            return Err(format!("Manifest is for {}, not {}", self.component, component));
+        }
+        if self.arch != local_arch() {
+            return Err(format!("Manifest is for {}, this host is {}", self.arch, local_arch()));
+        }
+        if !self.allow_downgrade && compare_versions(&self.version, running_version) != Ordering::Greater {
+            return Err(format!(
+                "Version {} is not newer than the running {} and the manifest does not allow a downgrade",
+                self.version, running_version
+            ));
+        }
+        if registry_protocol < self.min_protocol {
+            return Err(format!(
+                "Version {} needs registry protocol {}, registry speaks {}",
+                self.version, self.min_protocol, registry_protocol
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether `data` is the binary the manifest describes.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        let digest = ring::digest::digest(&ring::digest::SHA256, data);
+        hex::encode(digest.as_ref()).eq_ignore_ascii_case(&self.sha256)
+    }
+}
+
+/// Compare versions part by part (`1.10.0` > `1.9.2`, `20260102-0900` >
+/// `20260101-1800`): numeric parts as numbers, others as text.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |v: &str| v.split(['.', '-', '+']).map(str::to_string).collect::<Vec<_>>();
+    let (a, b) = (parts(a), parts(b));
+    for (x, y) in a.iter().zip(&b) {
+        let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => x.cmp(y),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer(dir: &Path) -> UpdateSigner {
+        UpdateSigner::generate(&dir.join("key.pk8")).unwrap()
+    }
+
+    fn manifest(data: &[u8]) -> UpdateManifest {
+        UpdateManifest {
+            component: "hr-host-agent".into(),
+            version: "20260101-120000".into(),
+            arch: local_arch().into(),
+            sha256: hex::encode(ring::digest::digest(&ring::digest::SHA256, data).as_ref()),
+            min_protocol: 1,
+            allow_downgrade: false,
+        }
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let dir = std::env::temp_dir().join(format!("hr-update-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let signer = signer(&dir);
+        // Loaded again; never overwritten
+        let path = dir.join("key.pk8");
+        assert_eq!(UpdateSigner::load(&path).unwrap().public_key_hex(), signer.public_key_hex());
+        assert!(UpdateSigner::generate(&path).is_err());
+        assert_eq!(parse_key(&format!("{}\n", signer.public_key_hex())).unwrap(), signer.public_key_hex());
+        assert!(parse_key("abcd").is_err());
+
+        let signed = signer.sign(&manifest(b"binary"));
+        let verified = signed.verify(&signer.public_key_hex()).unwrap();
+        assert_eq!(verified, manifest(b"binary"));
+        assert!(verified.matches(b"binary"));
+        assert!(!verified.matches(b"other"));
+        assert!(verified.check("hr-host-agent", "0.1.0", 1).is_ok());
+        assert!(verified.check("hr-agent", "0.1.0", 1).is_err());
+        assert!(verified.check("hr-host-agent", "0.1.0", 0).is_err());
+
+        // Tampered manifest, or another key
+        let mut tampered = signed.clone();
+        tampered.manifest = tampered.manifest.replace("20260101", "20260102");
+        assert!(tampered.verify(&signer.public_key_hex()).is_err());
+        let other = UpdateSigner::generate(&dir.join("other.pk8")).unwrap();
+        assert!(signed.verify(&other.public_key_hex()).is_err());
+        assert!(signed.verify("not hex").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_downgrade() {
+        let mut m = manifest(b"binary");
+        m.version = "1.4.0".into();
+        assert!(m.check("hr-host-agent", "1.3.9", 1).is_ok());
+        // Replayed manifest of the running or an older build
+        assert!(m.check("hr-host-agent", "1.4.0", 1).is_err());
+        assert!(m.check("hr-host-agent", "1.10.0", 1).is_err());
+
+        m.allow_downgrade = true;
+        assert!(m.check("hr-host-agent", "1.10.0", 1).is_ok());
+
+        assert_eq!(compare_versions("20260102-0900", "20260101-1800"), Ordering::Greater);
+        assert_eq!(compare_versions("1.2", "1.2.1"), Ordering::Less);
+        assert_eq!(compare_versions("1.2.0-rc1", "1.2.0-rc2"), Ordering::Less);
+    }
+}
@@ -0,0 +1,162 @@
+//! `aws-chunked` content encoding, as SDKs stream uploads: each chunk is
+//! `{hex size}[;chunk-signature={sig}]\r\n{data}\r\n`, the last one empty,
+//! optionally followed by trailer headers.
+
+use ring::digest;
+
+use crate::sigv4::ChunkSigner;
+
+/// Longest chunk header or trailer line.
+const MAX_LINE: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChunkError {
+    Malformed(&'static str),
+    Signature,
+}
+
+impl std::fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChunkError::Malformed(e) => write!(f, "invalid chunked body: {}", e),
+            ChunkError::Signature => write!(f, "chunk signature does not match"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Header,
+    Data(usize),
+    /// CRLF closing a chunk.
+    DataEnd,
+    Trailer,
+    Done,
+}
+
+/// Incremental decoder: feed the body as it arrives, get the data back.
+pub struct ChunkDecoder {
+    state: State,
+    line: Vec<u8>,
+    signer: Option<ChunkSigner>,
+    signature: String,
+    chunk_hash: Option<digest::Context>,
+}
+
+impl ChunkDecoder {
+    /// With a signer, every chunk must carry a valid signature.
+    pub fn new(signer: Option<ChunkSigner>) -> Self {
+        Self { state: State::Header, line: Vec::new(), signer, signature: String::new(), chunk_hash: None }
+    }
+
+    /// Decode `input`, appending the data it carries to `out`.
+    pub fn feed(&mut self, mut input: &[u8], out: &mut Vec<u8>) -> Result<(), ChunkError> {
+        while !input.is_empty() {
+            match self.state {
+                State::Data(remaining) => {
+                    let n = remaining.min(input.len());
+                    out.extend_from_slice(&input[..n]);
+                    if let Some(hash) = &mut self.chunk_hash {
+                        hash.update(&input[..n]);
+                    }
+                    input = &input[n..];
+                    if n == remaining {
+                        self.end_chunk()?;
+                        self.state = State::DataEnd;
+                    } else {
+                        self.state = State::Data(remaining - n);
+                    }
+                }
+                State::Done => return Err(ChunkError::Malformed("data after the last chunk")),
+                _ => {
+                    let end = input.iter().position(|b| *b == b'\n');
+                    let taken = end.map_or(input.len(), |i| i + 1);
+                    self.line.extend_from_slice(&input[..end.unwrap_or(input.len())]);
+                    input = &input[taken..];
+                    if self.line.len() > MAX_LINE {
+                        return Err(ChunkError::Malformed("line too long"));
+                    }
+                    if end.is_some() {
+                        let mut line = std::mem::take(&mut self.line);
+                        if line.last() == Some(&b'\r') {
+                            line.pop();
+                        }
+                        self.on_line(&line)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The last chunk and the trailers were read.
+    pub fn finished(&self) -> bool {
+        self.state == State::Done
+    }
+
+    fn on_line(&mut self, line: &[u8]) -> Result<(), ChunkError> {
+        match self.state {
+            State::Header => {
+                let line = std::str::from_utf8(line).map_err(|_| ChunkError::Malformed("chunk header"))?;
+                let (size, extension) = line.split_once(';').unwrap_or((line, ""));
+                let size = usize::from_str_radix(size.trim(), 16).map_err(|_| ChunkError::Malformed("chunk size"))?;
+                self.signature = extension.trim().strip_prefix("chunk-signature=").unwrap_or_default().to_string();
+                if self.signer.is_some() && self.signature.is_empty() {
+                    return Err(ChunkError::Malformed("missing chunk signature"));
+                }
+                if self.signer.is_some() {
+                    self.chunk_hash = Some(digest::Context::new(&digest::SHA256));
+                }
+                if size == 0 {
+                    self.end_chunk()?;
+                    self.state = State::Trailer;
+                } else {
+                    self.state = State::Data(size);
+                }
+            }
+            State::DataEnd if line.is_empty() => self.state = State::Header,
+            State::DataEnd => return Err(ChunkError::Malformed("chunk longer than announced")),
+            // Trailing checksums are not checked, the content hash is
+            State::Trailer if line.is_empty() => self.state = State::Done,
+            State::Trailer => {}
+            State::Data(_) | State::Done => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn end_chunk(&mut self) -> Result<(), ChunkError> {
+        if let (Some(signer), Some(hash)) = (&mut self.signer, self.chunk_hash.take())
+            && !signer.verify(hash.finish().as_ref(), &self.signature)
+        {
+            return Err(ChunkError::Signature);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unsigned_with_trailer() {
+        let body = b"5\r\nhello\r\n6\r\n world\r\n0\r\nx-amz-checksum-crc32:DUoRhQ==\r\n\r\n";
+        // Byte by byte, to cross every boundary
+        let mut decoder = ChunkDecoder::new(None);
+        let mut out = Vec::new();
+        for byte in body.iter() {
+            decoder.feed(std::slice::from_ref(byte), &mut out).unwrap();
+        }
+        assert!(decoder.finished());
+        assert_eq!(out, b"hello world");
+
+        let mut decoder = ChunkDecoder::new(None);
+        assert_eq!(
+            decoder.feed(b"3\r\nhello\r\n", &mut Vec::new()),
+            Err(ChunkError::Malformed("chunk longer than announced"))
+        );
+        let mut decoder = ChunkDecoder::new(None);
+        decoder.feed(b"5\r\nhel", &mut Vec::new()).unwrap();
+        assert!(!decoder.finished());
+    }
+}
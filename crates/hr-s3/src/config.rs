@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Object storage gateway configuration (s3-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct S3Config {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_bind")]
+    pub bind_address: String,
+    /// S3 API port (path-style requests, SigV4).
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Storage per app, in bytes; 0 = unlimited.
+    #[serde(default = "default_quota")]
+    pub quota_bytes: u64,
+    /// Per-app overrides, by app slug.
+    #[serde(default)]
+    pub app_quotas: BTreeMap<String, u64>,
+    /// Largest object accepted in one PUT.
+    #[serde(default = "default_max_object_size")]
+    pub max_object_size: u64,
+    /// Buckets an app may own.
+    #[serde(default = "default_max_buckets")]
+    pub max_buckets_per_app: u32,
+}
+
+fn default_bind() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_port() -> u16 {
+    9000
+}
+
+fn default_quota() -> u64 {
+    10 * 1024 * 1024 * 1024
+}
+
+fn default_max_object_size() -> u64 {
+    5 * 1024 * 1024 * 1024
+}
+
+fn default_max_buckets() -> u32 {
+    10
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl S3Config {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Quota of `app` in bytes (0 = unlimited).
+    pub fn quota(&self, app: &str) -> u64 {
+        self.app_quotas.get(app).copied().unwrap_or(self.quota_bytes)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bind_address.parse::<std::net::IpAddr>().is_err() {
+            return Err(format!("adresse d'écoute invalide: {}", self.bind_address));
+        }
+        if self.port == 0 {
+            return Err("port invalide".to_string());
+        }
+        if self.max_object_size == 0 {
+            return Err("taille maximale d'objet invalide".to_string());
+        }
+        if self.max_buckets_per_app == 0 {
+            return Err("au moins un bucket par application requis".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_and_quota() {
+        let mut config = S3Config::default();
+        assert!(!config.enabled);
+        assert_eq!(config.port, 9000);
+        assert!(config.validate().is_ok());
+
+        config.app_quotas.insert("photos".to_string(), 0);
+        assert_eq!(config.quota("photos"), 0);
+        assert_eq!(config.quota("wiki"), 10 * 1024 * 1024 * 1024);
+
+        config.bind_address = "nope".to_string();
+        assert!(config.validate().is_err());
+    }
+}
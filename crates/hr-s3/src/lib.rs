@@ -0,0 +1,108 @@
+//! S3-compatible object storage for the apps: a filesystem-backed store
+//! under the data directory (so it follows the backups of HomeRoute's data),
+//! per-app buckets, access keys and quotas, served over a subset of the S3
+//! API (path-style, SigV4) that the usual SDKs and tools speak.
+
+pub mod chunked;
+pub mod config;
+pub mod server;
+pub mod sigv4;
+pub mod store;
+pub mod xml;
+
+pub use config::S3Config;
+pub use store::{AccessKey, Bucket, BucketInfo, DeleteBucket, ObjectStore};
+
+use std::sync::{Arc, RwLock};
+
+use serde::Serialize;
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct S3Status {
+    pub running: bool,
+    /// S3 API listener, e.g. `0.0.0.0:9000`.
+    pub listener: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Shared storage handle: the API manages buckets and keys; the supervised
+/// `s3` service serves the S3 API.
+pub struct ObjectStorage {
+    config: watch::Sender<S3Config>,
+    status: RwLock<S3Status>,
+    store: Arc<ObjectStore>,
+}
+
+impl ObjectStorage {
+    pub fn new(config: S3Config, store: ObjectStore) -> Self {
+        Self {
+            config: watch::channel(config).0,
+            status: RwLock::new(S3Status::default()),
+            store: Arc::new(store),
+        }
+    }
+
+    /// Current configuration.
+    pub fn config(&self) -> S3Config {
+        self.config.borrow().clone()
+    }
+
+    /// Replace the configuration; the listener is rebound.
+    pub fn apply(&self, config: S3Config) {
+        self.config.send_replace(config);
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<S3Config> {
+        self.config.subscribe()
+    }
+
+    pub fn store(&self) -> Arc<ObjectStore> {
+        self.store.clone()
+    }
+
+    pub fn status(&self) -> S3Status {
+        self.status.read().unwrap().clone()
+    }
+
+    pub(crate) fn set_status(&self, running: bool, listener: Option<String>, error: Option<String>) {
+        let mut status = self.status.write().unwrap();
+        status.running = running;
+        status.listener = listener;
+        status.last_error = error;
+    }
+}
+
+pub type SharedObjectStorage = Arc<ObjectStorage>;
+
+/// S3 bucket naming rules: 3 to 63 characters among lowercase letters,
+/// digits, dots and hyphens, starting and ending with a letter or digit,
+/// and not shaped like an IP address.
+pub fn valid_bucket_name(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    (3..=63).contains(&bytes.len())
+        && bytes.iter().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || *b == b'.' || *b == b'-')
+        && bytes[0].is_ascii_alphanumeric()
+        && bytes[bytes.len() - 1].is_ascii_alphanumeric()
+        && !name.contains("..")
+        && name.parse::<std::net::Ipv4Addr>().is_err()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_names() {
+        assert!(valid_bucket_name("photos"));
+        assert!(valid_bucket_name("my-app.uploads"));
+        assert!(!valid_bucket_name("ab"));
+        assert!(!valid_bucket_name("Photos"));
+        assert!(!valid_bucket_name("-photos"));
+        assert!(!valid_bucket_name("a..b"));
+        assert!(!valid_bucket_name("192.168.1.1"));
+        assert!(!valid_bucket_name("../etc"));
+    }
+}
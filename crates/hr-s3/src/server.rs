@@ -0,0 +1,758 @@
+//! S3 API listener: path-style requests (`/{bucket}/{key}`) signed with
+//! SigV4. Supported: bucket create/delete/head/list, ListObjects v1 and
+//! v2, DeleteObjects, and object put/get (with a single range)/head/delete.
+//! Multipart uploads and copies answer `NotImplemented`.
+
+use std::io::SeekFrom;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum::body::{Body, Bytes};
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use futures_util::StreamExt;
+use md5::{Digest, Md5};
+use ring::digest;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+use crate::chunked::ChunkDecoder;
+use crate::sigv4::{self, AuthError, Payload, SignedRequest};
+use crate::store::{Bucket, DeleteBucket, ListParams, NewObject, ObjectStore};
+use crate::xml::{self, ListRequest};
+use crate::{SharedObjectStorage, valid_bucket_name};
+
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+/// Keys per listing page, at most (the S3 default too).
+const MAX_KEYS: usize = 1000;
+/// Longest key, in bytes.
+const MAX_KEY_LEN: usize = 1024;
+/// Largest DeleteObjects request body.
+const MAX_DELETE_BODY: usize = 1024 * 1024;
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Serve the S3 API; the listener is rebound whenever the configuration
+/// changes.
+pub async fn run_s3(storage: SharedObjectStorage) -> Result<()> {
+    let mut config_rx = storage.subscribe();
+    loop {
+        let config = config_rx.borrow_and_update().clone();
+        tokio::select! {
+            _ = run_config(storage.clone(), config.enabled, &config.bind_address, config.port) => {}
+            changed = config_rx.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Serve one configuration until it is replaced (never returns on its own).
+async fn run_config(storage: SharedObjectStorage, enabled: bool, bind_address: &str, port: u16) {
+    if !enabled {
+        storage.set_status(false, None, None);
+        return std::future::pending().await;
+    }
+
+    let ip: IpAddr = bind_address.parse().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    let addr = SocketAddr::new(ip, port);
+    loop {
+        match TcpListener::bind(addr).await.with_context(|| format!("S3 bind {}", addr)) {
+            Ok(listener) => {
+                info!("S3 API listening on {}", addr);
+                storage.set_status(true, Some(addr.to_string()), None);
+                if let Err(e) = axum::serve(listener, router(storage.clone())).await {
+                    warn!("S3 listener stopped: {:#}", e);
+                    storage.set_status(false, None, Some(format!("{e:#}")));
+                }
+            }
+            Err(e) => {
+                warn!("S3 listener failed to start: {:#}", e);
+                storage.set_status(false, None, Some(format!("{e:#}")));
+            }
+        }
+        tokio::time::sleep(RETRY_DELAY).await;
+    }
+}
+
+pub fn router(storage: SharedObjectStorage) -> Router {
+    Router::new().fallback(handle).with_state(storage)
+}
+
+/// An S3 error response.
+#[derive(Debug)]
+struct S3Error {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl S3Error {
+    fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self { status, code, message: message.into() }
+    }
+
+    fn internal(e: impl std::fmt::Display) -> Self {
+        warn!("S3 request failed: {}", e);
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "InternalError", "internal error")
+    }
+
+    fn not_implemented(what: &str) -> Self {
+        Self::new(StatusCode::NOT_IMPLEMENTED, "NotImplemented", format!("{} is not supported", what))
+    }
+
+    fn into_response(self, resource: &str) -> Response {
+        xml_response(self.status, xml::error(self.code, &self.message, resource))
+    }
+}
+
+impl From<AuthError> for S3Error {
+    fn from(e: AuthError) -> Self {
+        let status = match e {
+            AuthError::Malformed(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::FORBIDDEN,
+        };
+        Self::new(status, e.code(), e.to_string())
+    }
+}
+
+/// Which operation a request is, for the metrics.
+fn operation(method: &Method, bucket: &str, key: &str) -> &'static str {
+    match (method.as_str(), bucket.is_empty(), key.is_empty()) {
+        ("GET", true, _) => "list_buckets",
+        ("PUT", false, true) => "create_bucket",
+        ("DELETE", false, true) => "delete_bucket",
+        ("HEAD", false, true) => "head_bucket",
+        ("GET", false, true) => "list_objects",
+        ("POST", false, true) => "delete_objects",
+        ("PUT", false, false) => "put_object",
+        ("GET", false, false) => "get_object",
+        ("HEAD", false, false) => "head_object",
+        ("DELETE", false, false) => "delete_object",
+        _ => "other",
+    }
+}
+
+async fn handle(State(storage): State<SharedObjectStorage>, request: Request) -> Response {
+    let (parts, body) = request.into_parts();
+    let path = sigv4::uri_decode(parts.uri.path());
+    let (bucket, key) = path.trim_start_matches('/').split_once('/').unwrap_or((path.trim_start_matches('/'), ""));
+    let op = operation(&parts.method, bucket, key);
+
+    let response = match authenticate(&storage.store(), &parts.method, &parts.uri, &parts.headers).await {
+        Ok((app, payload)) => {
+            let query = sigv4::query_pairs(parts.uri.query().unwrap_or(""));
+            let request = S3Request { storage: &storage, app, bucket, key, query, headers: &parts.headers };
+            dispatch(&request, &parts.method, payload, body).await
+        }
+        Err(e) => Err(e),
+    };
+    let response = response.unwrap_or_else(|e| e.into_response(&path));
+    hr_common::metrics::registry()
+        .counter(
+            "homeroute_s3_requests_total",
+            "S3 API requests, by operation and status.",
+            &[("op", op), ("status", response.status().as_str())],
+        )
+        .inc();
+    response
+}
+
+/// Verify the signature; returns the app owning the access key.
+async fn authenticate(
+    store: &Arc<ObjectStore>,
+    method: &Method,
+    uri: &axum::http::Uri,
+    headers: &HeaderMap,
+) -> Result<(String, Payload), S3Error> {
+    let signed = SignedRequest::parse(method, uri, headers)?;
+    let id = signed.access_key.clone();
+    let key = blocking(store, move |store| store.key(&id))
+        .await?
+        .ok_or(AuthError::UnknownKey)?;
+    let payload = signed.verify(&key.secret, chrono::Utc::now())?;
+    let id = key.id.clone();
+    if let Err(e) = blocking(store, move |store| store.touch_key(&id)).await {
+        warn!("S3 access key {} not touched: {:?}", key.id, e);
+    }
+    Ok((key.app, payload))
+}
+
+/// Run a store call on the blocking pool.
+async fn blocking<T: Send + 'static>(
+    store: &Arc<ObjectStore>,
+    f: impl FnOnce(&ObjectStore) -> Result<T> + Send + 'static,
+) -> Result<T, S3Error> {
+    let store = store.clone();
+    tokio::task::spawn_blocking(move || f(&store))
+        .await
+        .map_err(S3Error::internal)?
+        .map_err(S3Error::internal)
+}
+
+struct S3Request<'a> {
+    storage: &'a SharedObjectStorage,
+    /// App owning the access key.
+    app: String,
+    bucket: &'a str,
+    key: &'a str,
+    query: Vec<(String, String)>,
+    headers: &'a HeaderMap,
+}
+
+impl S3Request<'_> {
+    fn store(&self) -> Arc<ObjectStore> {
+        self.storage.store()
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    /// The bucket, which the app must own.
+    async fn owned_bucket(&self) -> Result<Bucket, S3Error> {
+        let name = self.bucket.to_string();
+        let bucket = blocking(&self.store(), move |store| store.bucket(&name))
+            .await?
+            .ok_or_else(|| S3Error::new(StatusCode::NOT_FOUND, "NoSuchBucket", "bucket does not exist"))?;
+        if bucket.app != self.app {
+            return Err(S3Error::new(StatusCode::FORBIDDEN, "AccessDenied", "bucket owned by another app"));
+        }
+        Ok(bucket)
+    }
+}
+
+async fn dispatch(request: &S3Request<'_>, method: &Method, payload: Payload, body: Body) -> Result<Response, S3Error> {
+    if request.param("uploads").is_some() || request.param("uploadId").is_some() {
+        return Err(S3Error::not_implemented("multipart upload"));
+    }
+    if request.bucket.is_empty() {
+        return match *method {
+            Method::GET => list_buckets(request).await,
+            _ => Err(S3Error::new(StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed", "method not allowed")),
+        };
+    }
+    if request.key.is_empty() {
+        return match *method {
+            Method::PUT => create_bucket(request).await,
+            Method::DELETE => delete_bucket(request).await,
+            Method::HEAD => request.owned_bucket().await.map(|_| StatusCode::OK.into_response()),
+            Method::GET if request.param("location").is_some() => {
+                request.owned_bucket().await?;
+                Ok(xml_response(
+                    StatusCode::OK,
+                    r#"<?xml version="1.0" encoding="UTF-8"?><LocationConstraint xmlns="http://s3.amazonaws.com/doc/2006-03-01/"></LocationConstraint>"#
+                        .to_string(),
+                ))
+            }
+            Method::GET => list_objects(request).await,
+            Method::POST if request.param("delete").is_some() => delete_objects(request, body).await,
+            _ => Err(S3Error::new(StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed", "method not allowed")),
+        };
+    }
+    if request.key.len() > MAX_KEY_LEN {
+        return Err(S3Error::new(StatusCode::BAD_REQUEST, "KeyTooLongError", "key longer than 1024 bytes"));
+    }
+    match *method {
+        Method::PUT if request.header("x-amz-copy-source").is_some() => Err(S3Error::not_implemented("copy")),
+        Method::PUT => put_object(request, payload, body).await,
+        Method::GET => get_object(request, true).await,
+        Method::HEAD => get_object(request, false).await,
+        Method::DELETE => {
+            request.owned_bucket().await?;
+            let (bucket, key) = (request.bucket.to_string(), request.key.to_string());
+            blocking(&request.store(), move |store| store.delete_object(&bucket, &key)).await?;
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        _ => Err(S3Error::new(StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed", "method not allowed")),
+    }
+}
+
+// ── Buckets ─────────────────────────────────────────────────────
+
+async fn list_buckets(request: &S3Request<'_>) -> Result<Response, S3Error> {
+    let app = request.app.clone();
+    let buckets = blocking(&request.store(), move |store| store.buckets(Some(&app))).await?;
+    Ok(xml_response(StatusCode::OK, xml::list_buckets(&request.app, &buckets)))
+}
+
+async fn create_bucket(request: &S3Request<'_>) -> Result<Response, S3Error> {
+    if !valid_bucket_name(request.bucket) {
+        return Err(S3Error::new(StatusCode::BAD_REQUEST, "InvalidBucketName", "invalid bucket name"));
+    }
+    let max = request.storage.config().max_buckets_per_app as usize;
+    let (app, name) = (request.app.clone(), request.bucket.to_string());
+    let created = blocking(&request.store(), move |store| {
+        if let Some(existing) = store.bucket(&name)? {
+            return Ok(Err(existing.app == app));
+        }
+        if store.buckets(Some(&app))?.len() >= max {
+            return Ok(Ok(false));
+        }
+        Ok(Ok(store.create_bucket(&app, &name)?))
+    })
+    .await?;
+    match created {
+        Ok(true) => {
+            let mut response = StatusCode::OK.into_response();
+            if let Ok(location) = HeaderValue::from_str(&format!("/{}", request.bucket)) {
+                response.headers_mut().insert(header::LOCATION, location);
+            }
+            Ok(response)
+        }
+        Ok(false) => Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "TooManyBuckets",
+            format!("at most {} buckets per app", max),
+        )),
+        Err(true) => Err(S3Error::new(StatusCode::CONFLICT, "BucketAlreadyOwnedByYou", "bucket already exists")),
+        Err(false) => Err(S3Error::new(StatusCode::CONFLICT, "BucketAlreadyExists", "bucket name is taken")),
+    }
+}
+
+async fn delete_bucket(request: &S3Request<'_>) -> Result<Response, S3Error> {
+    request.owned_bucket().await?;
+    let name = request.bucket.to_string();
+    match blocking(&request.store(), move |store| store.delete_bucket(&name)).await? {
+        DeleteBucket::Deleted => Ok(StatusCode::NO_CONTENT.into_response()),
+        DeleteBucket::NotFound => Err(S3Error::new(StatusCode::NOT_FOUND, "NoSuchBucket", "bucket does not exist")),
+        DeleteBucket::NotEmpty => Err(S3Error::new(StatusCode::CONFLICT, "BucketNotEmpty", "bucket is not empty")),
+    }
+}
+
+async fn list_objects(request: &S3Request<'_>) -> Result<Response, S3Error> {
+    request.owned_bucket().await?;
+    let v2 = request.param("list-type") == Some("2");
+    let max_keys = match request.param("max-keys") {
+        Some(value) => value
+            .parse::<usize>()
+            .map_err(|_| S3Error::new(StatusCode::BAD_REQUEST, "InvalidArgument", "invalid max-keys"))?
+            .min(MAX_KEYS),
+        None => MAX_KEYS,
+    };
+    let continuation_token = request.param("continuation-token").filter(|_| v2).map(str::to_string);
+    let start_after = request.param(if v2 { "start-after" } else { "marker" }).map(str::to_string);
+    let from = match &continuation_token {
+        Some(token) => Some(xml::parse_continuation_token(token).ok_or_else(|| {
+            S3Error::new(StatusCode::BAD_REQUEST, "InvalidArgument", "invalid continuation token")
+        })?),
+        None => start_after.clone(),
+    };
+    let list = ListRequest {
+        bucket: request.bucket.to_string(),
+        v2,
+        prefix: request.param("prefix").unwrap_or_default().to_string(),
+        delimiter: request.param("delimiter").filter(|d| !d.is_empty()).map(str::to_string),
+        max_keys,
+        start_after,
+        continuation_token,
+        url_encoded: request.param("encoding-type") == Some("url"),
+    };
+    let params = ListParams {
+        prefix: list.prefix.clone(),
+        delimiter: list.delimiter.clone(),
+        start_after: from,
+        max_keys,
+    };
+    let bucket = list.bucket.clone();
+    let listing = blocking(&request.store(), move |store| store.list(&bucket, &params)).await?;
+    Ok(xml_response(StatusCode::OK, xml::list_objects(&list, &listing)))
+}
+
+async fn delete_objects(request: &S3Request<'_>, body: Body) -> Result<Response, S3Error> {
+    request.owned_bucket().await?;
+    let body = axum::body::to_bytes(body, MAX_DELETE_BODY)
+        .await
+        .map_err(|_| S3Error::new(StatusCode::BAD_REQUEST, "MalformedXML", "request body too large"))?;
+    let (keys, quiet) = xml::parse_delete(&String::from_utf8_lossy(&body));
+    if keys.is_empty() || keys.len() > MAX_KEYS {
+        return Err(S3Error::new(StatusCode::BAD_REQUEST, "MalformedXML", "1 to 1000 keys per request"));
+    }
+    let bucket = request.bucket.to_string();
+    let deleted = blocking(&request.store(), move |store| {
+        let mut errors = Vec::new();
+        let mut deleted = Vec::new();
+        for key in keys {
+            match store.delete_object(&bucket, &key) {
+                Ok(_) => deleted.push(key),
+                Err(e) => errors.push((key, "InternalError", e.to_string())),
+            }
+        }
+        Ok((deleted, errors))
+    })
+    .await?;
+    let shown: &[String] = if quiet { &[] } else { &deleted.0 };
+    Ok(xml_response(StatusCode::OK, xml::delete_result(shown, &deleted.1)))
+}
+
+// ── Objects ─────────────────────────────────────────────────────
+
+async fn put_object(request: &S3Request<'_>, payload: Payload, body: Body) -> Result<Response, S3Error> {
+    request.owned_bucket().await?;
+    let config = request.storage.config();
+    let length = request
+        .header("x-amz-decoded-content-length")
+        .or_else(|| request.header("content-length"))
+        .and_then(|v| v.parse::<u64>().ok())
+        .ok_or_else(|| S3Error::new(StatusCode::LENGTH_REQUIRED, "MissingContentLength", "Content-Length required"))?;
+    if length > config.max_object_size {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "EntityTooLarge",
+            format!("objects are limited to {} bytes", config.max_object_size),
+        ));
+    }
+
+    let quota = config.quota(&request.app);
+    if quota > 0 {
+        let (app, bucket, key) = (request.app.clone(), request.bucket.to_string(), request.key.to_string());
+        let (usage, replaced) = blocking(&request.store(), move |store| {
+            let replaced = store.object(&bucket, &key)?.map_or(0, |o| o.size);
+            Ok((store.usage(&app)?, replaced))
+        })
+        .await?;
+        if usage.saturating_sub(replaced) + length > quota {
+            return Err(S3Error::new(
+                StatusCode::FORBIDDEN,
+                "QuotaExceeded",
+                format!("storage quota of {} bytes reached", quota),
+            ));
+        }
+    }
+
+    let store = request.store();
+    let temp = store.temp_path();
+    let received = receive(&temp, payload, body, length, request.header("content-md5")).await;
+    let etag = match received {
+        Ok(etag) => etag,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(e);
+        }
+    };
+
+    let object = NewObject {
+        size: length,
+        etag: etag.clone(),
+        content_type: request.header("content-type").unwrap_or("binary/octet-stream").to_string(),
+        metadata: request
+            .headers
+            .iter()
+            .filter_map(|(name, value)| {
+                let name = name.as_str().strip_prefix("x-amz-meta-")?;
+                Some((name.to_string(), value.to_str().ok()?.to_string()))
+            })
+            .collect(),
+    };
+    let (bucket, key) = (request.bucket.to_string(), request.key.to_string());
+    let stored = blocking(&store, {
+        let temp = temp.clone();
+        move |store| store.put_object(&bucket, &key, &temp, &object)
+    })
+    .await;
+    if let Err(e) = stored {
+        let _ = tokio::fs::remove_file(&temp).await;
+        return Err(e);
+    }
+    let mut response = StatusCode::OK.into_response();
+    if let Ok(etag) = HeaderValue::from_str(&format!("\"{}\"", etag)) {
+        response.headers_mut().insert(header::ETAG, etag);
+    }
+    Ok(response)
+}
+
+/// Stream the body into `temp`, checking its length and hashes; returns
+/// the hex MD5 (the ETag).
+async fn receive(
+    temp: &std::path::Path,
+    payload: Payload,
+    body: Body,
+    length: u64,
+    content_md5: Option<&str>,
+) -> Result<String, S3Error> {
+    let incomplete = || S3Error::new(StatusCode::BAD_REQUEST, "IncompleteBody", "body does not match the declared length");
+    let mut file = tokio::fs::File::create(temp).await.map_err(S3Error::internal)?;
+    let mut md5 = Md5::new();
+    let mut sha256 = digest::Context::new(&digest::SHA256);
+    let (mut decoder, expected_sha256) = match payload {
+        Payload::Streaming(signer) => (Some(ChunkDecoder::new(Some(*signer))), None),
+        Payload::StreamingUnsignedTrailer => (Some(ChunkDecoder::new(None)), None),
+        Payload::Sha256(hash) => (None, Some(hash)),
+        Payload::Unsigned => (None, None),
+    };
+
+    let mut stream = body.into_data_stream();
+    let mut decoded = Vec::new();
+    let mut size = 0u64;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|_| incomplete())?;
+        let data: &[u8] = match &mut decoder {
+            Some(decoder) => {
+                decoded.clear();
+                decoder.feed(&chunk, &mut decoded).map_err(|e| match e {
+                    crate::chunked::ChunkError::Signature => {
+                        S3Error::new(StatusCode::FORBIDDEN, "SignatureDoesNotMatch", e.to_string())
+                    }
+                    crate::chunked::ChunkError::Malformed(_) => incomplete(),
+                })?;
+                &decoded
+            }
+            None => &chunk,
+        };
+        size += data.len() as u64;
+        if size > length {
+            return Err(incomplete());
+        }
+        md5.update(data);
+        sha256.update(data);
+        file.write_all(data).await.map_err(S3Error::internal)?;
+    }
+    if size != length || decoder.is_some_and(|d| !d.finished()) {
+        return Err(incomplete());
+    }
+    file.sync_all().await.map_err(S3Error::internal)?;
+
+    if let Some(expected) = expected_sha256
+        && hex::encode(sha256.finish()) != expected
+    {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "XAmzContentSHA256Mismatch",
+            "body does not match x-amz-content-sha256",
+        ));
+    }
+    let md5 = md5.finalize();
+    if let Some(content_md5) = content_md5
+        && STANDARD.decode(content_md5.trim()).ok().as_deref() != Some(md5.as_slice())
+    {
+        return Err(S3Error::new(StatusCode::BAD_REQUEST, "BadDigest", "body does not match Content-MD5"));
+    }
+    Ok(hex::encode(md5))
+}
+
+async fn get_object(request: &S3Request<'_>, with_body: bool) -> Result<Response, S3Error> {
+    request.owned_bucket().await?;
+    let (bucket, key) = (request.bucket.to_string(), request.key.to_string());
+    let store = request.store();
+    let object = blocking(&store, move |store| store.object(&bucket, &key))
+        .await?
+        .ok_or_else(|| S3Error::new(StatusCode::NOT_FOUND, "NoSuchKey", "object does not exist"))?;
+
+    let range = match request.header("range") {
+        Some(range) => parse_range(range, object.size).map_err(|_| {
+            S3Error::new(StatusCode::RANGE_NOT_SATISFIABLE, "InvalidRange", "requested range not satisfiable")
+        })?,
+        None => None,
+    };
+    let (start, end) = range.unwrap_or((0, object.size.saturating_sub(1)));
+    let length = if object.size == 0 { 0 } else { end - start + 1 };
+
+    let mut headers = HeaderMap::new();
+    let mut set = |name: &str, value: &str| {
+        if let (Ok(name), Ok(value)) = (header::HeaderName::try_from(name), HeaderValue::from_str(value)) {
+            headers.insert(name, value);
+        }
+    };
+    set("content-type", &object.content_type);
+    set("content-length", &length.to_string());
+    set("etag", &format!("\"{}\"", object.etag));
+    set("accept-ranges", "bytes");
+    if let Some(modified) = chrono::DateTime::<chrono::Utc>::from_timestamp_millis(object.modified) {
+        set("last-modified", &modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+    }
+    for (name, value) in &object.metadata {
+        set(&format!("x-amz-meta-{}", name), value);
+    }
+    if range.is_some() {
+        set("content-range", &format!("bytes {}-{}/{}", start, end, object.size));
+    }
+    let status = if range.is_some() { StatusCode::PARTIAL_CONTENT } else { StatusCode::OK };
+
+    let body = if with_body && length > 0 {
+        let mut file = tokio::fs::File::open(store.content_path(request.bucket, &object))
+            .await
+            .map_err(S3Error::internal)?;
+        file.seek(SeekFrom::Start(start)).await.map_err(S3Error::internal)?;
+        file_body(file, length)
+    } else {
+        Body::empty()
+    };
+    Ok((status, headers, body).into_response())
+}
+
+/// Single `bytes=` range, inclusive; `Ok(None)` when the header is not
+/// one (the whole object is sent), `Err` when it cannot be satisfied.
+fn parse_range(value: &str, size: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().map_err(|_| ())?;
+            if suffix == 0 || size == 0 {
+                return Err(());
+            }
+            (size.saturating_sub(suffix), size - 1)
+        }
+        (start, "") => (start.parse().map_err(|_| ())?, size.saturating_sub(1)),
+        (start, end) => {
+            let (start, end): (u64, u64) = (start.parse().map_err(|_| ())?, end.parse().map_err(|_| ())?);
+            if end < start {
+                return Ok(None);
+            }
+            (start, end.min(size.saturating_sub(1)))
+        }
+    };
+    if start >= size {
+        return Err(());
+    }
+    Ok(Some((start, end)))
+}
+
+fn file_body(file: tokio::fs::File, length: u64) -> Body {
+    let stream = futures_util::stream::unfold(file.take(length), |mut reader| async move {
+        let mut buf = vec![0u8; READ_CHUNK];
+        match reader.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok::<_, std::io::Error>(Bytes::from(buf)), reader))
+            }
+            Err(e) => Some((Err(e), reader)),
+        }
+    });
+    Body::from_stream(stream)
+}
+
+fn xml_response(status: StatusCode, body: String) -> Response {
+    (status, [(header::CONTENT_TYPE, "application/xml")], body).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ObjectStorage, S3Config};
+    use axum::http::Uri;
+    use tower::ServiceExt;
+
+    struct Client {
+        router: Router,
+        id: String,
+        secret: String,
+    }
+
+    impl Client {
+        async fn send(&self, method: Method, uri: &str, extra: &[(&'static str, &str)], body: &[u8]) -> Response {
+            let uri: Uri = uri.parse().unwrap();
+            let mut headers = HeaderMap::new();
+            headers.insert("host", HeaderValue::from_static("127.0.0.1:9000"));
+            let now = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+            headers.insert("x-amz-date", HeaderValue::from_str(&now).unwrap());
+            headers.insert("x-amz-content-sha256", HeaderValue::from_str(&sigv4::sha256_hex(body)).unwrap());
+            for (name, value) in extra {
+                headers.insert(*name, HeaderValue::from_str(value).unwrap());
+            }
+            let authorization = sigv4::authorization(&method, &uri, &headers, &self.id, &self.secret, "us-east-1").unwrap();
+            let mut request = Request::builder().method(method).uri(uri);
+            for (name, value) in &headers {
+                request = request.header(name, value);
+            }
+            let request = request
+                .header("authorization", authorization)
+                .header("content-length", body.len())
+                .body(Body::from(body.to_vec()))
+                .unwrap();
+            self.router.clone().oneshot(request).await.unwrap()
+        }
+    }
+
+    async fn text(response: Response) -> String {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    fn client(storage: &SharedObjectStorage, app: &str) -> Client {
+        let key = storage.store().create_key(app, "test").unwrap();
+        Client { router: router(storage.clone()), id: key.id, secret: key.secret }
+    }
+
+    #[tokio::test]
+    async fn test_object_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = S3Config { enabled: true, quota_bytes: 20, ..Default::default() };
+        let storage = Arc::new(ObjectStorage::new(config, ObjectStore::open(dir.path()).unwrap()));
+        let photos = client(&storage, "photos");
+        let wiki = client(&storage, "wiki");
+
+        assert_eq!(photos.send(Method::PUT, "/albums", &[], b"").await.status(), StatusCode::OK);
+        assert_eq!(wiki.send(Method::PUT, "/albums", &[], b"").await.status(), StatusCode::CONFLICT);
+
+        let response = photos
+            .send(Method::PUT, "/albums/2024/beach%20day.txt", &[("x-amz-meta-camera", "x100")], b"hello world")
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["etag"], "\"5eb63bbbe01eeed093cb22bb8f5acdc3\"");
+
+        let response = photos.send(Method::GET, "/albums/2024/beach%20day.txt", &[], b"").await;
+        assert_eq!(response.headers()["x-amz-meta-camera"], "x100");
+        assert_eq!(text(response).await, "hello world");
+        let response = photos
+            .send(Method::GET, "/albums/2024/beach%20day.txt", &[("range", "bytes=6-")], b"")
+            .await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()["content-range"], "bytes 6-10/11");
+        assert_eq!(text(response).await, "world");
+
+        let listing = text(photos.send(Method::GET, "/albums?list-type=2&delimiter=%2F", &[], b"").await).await;
+        assert!(listing.contains("<CommonPrefixes><Prefix>2024/</Prefix></CommonPrefixes>"));
+
+        // Another app's key, a bad signature, the quota
+        let response = wiki.send(Method::GET, "/albums/2024/beach%20day.txt", &[], b"").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(text(response).await.contains("<Code>AccessDenied</Code>"));
+        let forged = Client { router: router(storage.clone()), id: photos.id.clone(), secret: "nope".to_string() };
+        let response = forged.send(Method::GET, "/albums", &[], b"").await;
+        assert!(text(response).await.contains("<Code>SignatureDoesNotMatch</Code>"));
+        let response = photos.send(Method::PUT, "/albums/big.bin", &[], &[0u8; 10]).await;
+        assert!(text(response).await.contains("<Code>QuotaExceeded</Code>"));
+        // Replacing an object only counts the difference
+        let response = photos.send(Method::PUT, "/albums/2024/beach%20day.txt", &[], &[0u8; 20]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(photos.send(Method::DELETE, "/albums", &[], b"").await.status(), StatusCode::CONFLICT);
+        let response = photos.send(Method::DELETE, "/albums/2024/beach%20day.txt", &[], b"").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = photos.send(Method::GET, "/albums/2024/beach%20day.txt", &[], b"").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(photos.send(Method::DELETE, "/albums", &[], b"").await.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-9", 100), Ok(Some((0, 9))));
+        assert_eq!(parse_range("bytes=90-", 100), Ok(Some((90, 99))));
+        assert_eq!(parse_range("bytes=-10", 100), Ok(Some((90, 99))));
+        assert_eq!(parse_range("bytes=50-500", 100), Ok(Some((50, 99))));
+        assert_eq!(parse_range("bytes=100-", 100), Err(()));
+        assert_eq!(parse_range("bytes=0-1,5-6", 100), Ok(None));
+        assert_eq!(parse_range("items=0-1", 100), Ok(None));
+    }
+}
@@ -0,0 +1,522 @@
+//! Filesystem-backed object store: one directory per bucket (under
+//! `buckets/`) holding the contents under opaque file names, and a SQLite
+//! index (objects.db) with keys, sizes, ETags and metadata. Access keys live
+//! in the same base.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rand_core::{OsRng, RngCore};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+
+/// Uploads in progress, renamed into their bucket once complete.
+const TMP_DIR: &str = "tmp";
+/// One directory per bucket under this one.
+const BUCKETS_DIR: &str = "buckets";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bucket {
+    pub name: String,
+    /// Owning app (slug).
+    pub app: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BucketInfo {
+    #[serde(flatten)]
+    pub bucket: Bucket,
+    pub objects: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectMeta {
+    pub key: String,
+    pub size: u64,
+    /// Hex MD5 of the content, without quotes.
+    pub etag: String,
+    pub content_type: String,
+    /// `x-amz-meta-*` headers, names lowercased without the prefix.
+    pub metadata: BTreeMap<String, String>,
+    /// Milliseconds since the epoch.
+    pub modified: i64,
+    file: String,
+}
+
+/// Content already written to a temporary file by the caller.
+#[derive(Debug, Clone)]
+pub struct NewObject {
+    pub size: u64,
+    pub etag: String,
+    pub content_type: String,
+    pub metadata: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListParams {
+    pub prefix: String,
+    pub delimiter: Option<String>,
+    /// Only keys after this one (marker, start-after or continuation).
+    pub start_after: Option<String>,
+    pub max_keys: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Listing {
+    pub objects: Vec<ObjectMeta>,
+    pub prefixes: Vec<String>,
+    pub truncated: bool,
+    /// Last key or prefix returned, to continue from.
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteBucket {
+    Deleted,
+    NotFound,
+    NotEmpty,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessKey {
+    pub id: String,
+    #[serde(skip)]
+    pub secret: String,
+    pub app: String,
+    pub description: String,
+    pub created_at: i64,
+    pub last_used: Option<i64>,
+}
+
+pub struct ObjectStore {
+    root: PathBuf,
+    conn: Mutex<Connection>,
+}
+
+impl ObjectStore {
+    pub fn open(root: &Path) -> Result<Self> {
+        std::fs::create_dir_all(root.join(TMP_DIR)).with_context(|| format!("create {}", root.display()))?;
+        // Leftovers of uploads interrupted by a restart
+        if let Ok(entries) = std::fs::read_dir(root.join(TMP_DIR)) {
+            for entry in entries.flatten() {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+        let conn = Connection::open(root.join("objects.db"))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS buckets (
+                name TEXT PRIMARY KEY,
+                app TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS objects (
+                bucket TEXT NOT NULL,
+                key TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                etag TEXT NOT NULL,
+                content_type TEXT NOT NULL,
+                metadata TEXT NOT NULL,
+                modified INTEGER NOT NULL,
+                file TEXT NOT NULL,
+                PRIMARY KEY (bucket, key)
+            );
+            CREATE TABLE IF NOT EXISTS access_keys (
+                id TEXT PRIMARY KEY,
+                secret TEXT NOT NULL,
+                app TEXT NOT NULL,
+                description TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                last_used INTEGER
+            );",
+        )?;
+        Ok(Self { root: root.to_path_buf(), conn: Mutex::new(conn) })
+    }
+
+    fn bucket_dir(&self, bucket: &str) -> PathBuf {
+        self.root.join(BUCKETS_DIR).join(bucket)
+    }
+
+    /// Fresh path for an upload in progress.
+    pub fn temp_path(&self) -> PathBuf {
+        self.root.join(TMP_DIR).join(uuid::Uuid::new_v4().to_string())
+    }
+
+    // ── Buckets ─────────────────────────────────────────────────
+
+    /// False when the name is taken.
+    pub fn create_bucket(&self, app: &str, name: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO buckets (name, app, created_at) VALUES (?1, ?2, ?3)",
+            params![name, app, now_ms()],
+        )?;
+        if inserted > 0 {
+            std::fs::create_dir_all(self.bucket_dir(name))?;
+        }
+        Ok(inserted > 0)
+    }
+
+    pub fn delete_bucket(&self, name: &str) -> Result<DeleteBucket> {
+        let conn = self.conn.lock().unwrap();
+        let not_empty = conn
+            .prepare("SELECT 1 FROM objects WHERE bucket = ?1 LIMIT 1")?
+            .exists(params![name])?;
+        if not_empty {
+            return Ok(DeleteBucket::NotEmpty);
+        }
+        if conn.execute("DELETE FROM buckets WHERE name = ?1", params![name])? == 0 {
+            return Ok(DeleteBucket::NotFound);
+        }
+        let _ = std::fs::remove_dir_all(self.bucket_dir(name));
+        Ok(DeleteBucket::Deleted)
+    }
+
+    pub fn bucket(&self, name: &str) -> Result<Option<Bucket>> {
+        let conn = self.conn.lock().unwrap();
+        let bucket = conn
+            .query_row(
+                "SELECT name, app, created_at FROM buckets WHERE name = ?1",
+                params![name],
+                |row| Ok(Bucket { name: row.get(0)?, app: row.get(1)?, created_at: row.get(2)? }),
+            )
+            .optional()?;
+        Ok(bucket)
+    }
+
+    /// Buckets with their object count and size, of one app or all.
+    pub fn buckets(&self, app: Option<&str>) -> Result<Vec<BucketInfo>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT b.name, b.app, b.created_at, COUNT(o.key), COALESCE(SUM(o.size), 0)
+             FROM buckets b LEFT JOIN objects o ON o.bucket = b.name
+             WHERE ?1 IS NULL OR b.app = ?1
+             GROUP BY b.name ORDER BY b.name",
+        )?;
+        let buckets = stmt
+            .query_map(params![app], |row| {
+                Ok(BucketInfo {
+                    bucket: Bucket { name: row.get(0)?, app: row.get(1)?, created_at: row.get(2)? },
+                    objects: row.get::<_, i64>(3)? as u64,
+                    bytes: row.get::<_, i64>(4)? as u64,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(buckets)
+    }
+
+    /// Bytes stored by `app`, all buckets included.
+    pub fn usage(&self, app: &str) -> Result<u64> {
+        let conn = self.conn.lock().unwrap();
+        let bytes: i64 = conn.query_row(
+            "SELECT COALESCE(SUM(o.size), 0) FROM objects o JOIN buckets b ON b.name = o.bucket WHERE b.app = ?1",
+            params![app],
+            |row| row.get(0),
+        )?;
+        Ok(bytes as u64)
+    }
+
+    // ── Objects ─────────────────────────────────────────────────
+
+    pub fn object(&self, bucket: &str, key: &str) -> Result<Option<ObjectMeta>> {
+        let conn = self.conn.lock().unwrap();
+        let object = conn
+            .query_row(
+                &format!("SELECT {} FROM objects WHERE bucket = ?1 AND key = ?2", OBJECT_COLUMNS),
+                params![bucket, key],
+                object_from_row,
+            )
+            .optional()?;
+        Ok(object)
+    }
+
+    /// Where the content of `object` is.
+    pub fn content_path(&self, bucket: &str, object: &ObjectMeta) -> PathBuf {
+        self.bucket_dir(bucket).join(&object.file)
+    }
+
+    /// Move the upload at `temp` into `bucket` as `key`, replacing any
+    /// previous version.
+    pub fn put_object(&self, bucket: &str, key: &str, temp: &Path, object: &NewObject) -> Result<()> {
+        let file = uuid::Uuid::new_v4().to_string();
+        let dir = self.bucket_dir(bucket);
+        std::fs::create_dir_all(&dir)?;
+        std::fs::rename(temp, dir.join(&file)).context("move upload into its bucket")?;
+
+        let conn = self.conn.lock().unwrap();
+        let previous: Option<String> = conn
+            .query_row(
+                "SELECT file FROM objects WHERE bucket = ?1 AND key = ?2",
+                params![bucket, key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO objects (bucket, key, size, etag, content_type, metadata, modified, file)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                bucket,
+                key,
+                object.size as i64,
+                object.etag,
+                object.content_type,
+                serde_json::to_string(&object.metadata)?,
+                now_ms(),
+                file,
+            ],
+        )?;
+        if let Some(previous) = previous {
+            let _ = std::fs::remove_file(dir.join(previous));
+        }
+        Ok(())
+    }
+
+    /// False when there was no such object.
+    pub fn delete_object(&self, bucket: &str, key: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let file: Option<String> = conn
+            .query_row(
+                "DELETE FROM objects WHERE bucket = ?1 AND key = ?2 RETURNING file",
+                params![bucket, key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match file {
+            Some(file) => {
+                let _ = std::fs::remove_file(self.bucket_dir(bucket).join(file));
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Keys under `prefix` in order; with a delimiter, keys sharing the part
+    /// up to the next delimiter are rolled up into one common prefix.
+    pub fn list(&self, bucket: &str, params: &ListParams) -> Result<Listing> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM objects WHERE bucket = ?1 AND key >= ?2 AND key > ?3 ORDER BY key",
+            OBJECT_COLUMNS
+        ))?;
+        let start = params.start_after.clone().unwrap_or_default();
+        let mut rows = stmt.query(params![bucket, params.prefix, start])?;
+
+        let delimiter = params.delimiter.as_deref().filter(|d| !d.is_empty());
+        // A continuation on a common prefix skips everything under it
+        let mut skip_prefix = delimiter
+            .filter(|d| start.ends_with(d) && start.starts_with(&params.prefix))
+            .map(|_| start.clone());
+        let mut listing = Listing::default();
+        while let Some(row) = rows.next()? {
+            let object = object_from_row(row)?;
+            if !object.key.starts_with(&params.prefix) {
+                break;
+            }
+            if skip_prefix.as_deref().is_some_and(|p| object.key.starts_with(p)) {
+                continue;
+            }
+            let common = delimiter.and_then(|d| {
+                let rest = &object.key[params.prefix.len()..];
+                rest.find(d).map(|i| object.key[..params.prefix.len() + i + d.len()].to_string())
+            });
+            if listing.objects.len() + listing.prefixes.len() >= params.max_keys {
+                listing.truncated = true;
+                break;
+            }
+            match common {
+                Some(prefix) => {
+                    listing.next = Some(prefix.clone());
+                    skip_prefix = Some(prefix.clone());
+                    listing.prefixes.push(prefix);
+                }
+                None => {
+                    listing.next = Some(object.key.clone());
+                    listing.objects.push(object);
+                }
+            }
+        }
+        Ok(listing)
+    }
+
+    // ── Access keys ─────────────────────────────────────────────
+
+    /// New key pair for `app`; the secret is shown once by the API.
+    pub fn create_key(&self, app: &str, description: &str) -> Result<AccessKey> {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+        let mut id_bytes = [0u8; 18];
+        OsRng.fill_bytes(&mut id_bytes);
+        let id: String = "HR".chars()
+            .chain(id_bytes.iter().map(|b| ALPHABET[(*b & 31) as usize] as char))
+            .collect();
+        let mut secret = [0u8; 30];
+        OsRng.fill_bytes(&mut secret);
+        let key = AccessKey {
+            id,
+            secret: URL_SAFE_NO_PAD.encode(secret),
+            app: app.to_string(),
+            description: description.to_string(),
+            created_at: now_ms(),
+            last_used: None,
+        };
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO access_keys (id, secret, app, description, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![key.id, key.secret, key.app, key.description, key.created_at],
+        )?;
+        Ok(key)
+    }
+
+    pub fn keys(&self, app: Option<&str>) -> Result<Vec<AccessKey>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, secret, app, description, created_at, last_used FROM access_keys
+             WHERE ?1 IS NULL OR app = ?1 ORDER BY created_at",
+        )?;
+        let keys = stmt
+            .query_map(params![app], key_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(keys)
+    }
+
+    pub fn key(&self, id: &str) -> Result<Option<AccessKey>> {
+        let conn = self.conn.lock().unwrap();
+        let key = conn
+            .query_row(
+                "SELECT id, secret, app, description, created_at, last_used FROM access_keys WHERE id = ?1",
+                params![id],
+                key_from_row,
+            )
+            .optional()?;
+        Ok(key)
+    }
+
+    pub fn delete_key(&self, id: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM access_keys WHERE id = ?1", params![id])? > 0)
+    }
+
+    pub fn touch_key(&self, id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("UPDATE access_keys SET last_used = ?1 WHERE id = ?2", params![now_ms(), id])?;
+        Ok(())
+    }
+}
+
+const OBJECT_COLUMNS: &str = "key, size, etag, content_type, metadata, modified, file";
+
+fn object_from_row(row: &rusqlite::Row) -> rusqlite::Result<ObjectMeta> {
+    let metadata: String = row.get(4)?;
+    Ok(ObjectMeta {
+        key: row.get(0)?,
+        size: row.get::<_, i64>(1)? as u64,
+        etag: row.get(2)?,
+        content_type: row.get(3)?,
+        metadata: serde_json::from_str(&metadata).unwrap_or_default(),
+        modified: row.get(5)?,
+        file: row.get(6)?,
+    })
+}
+
+fn key_from_row(row: &rusqlite::Row) -> rusqlite::Result<AccessKey> {
+    Ok(AccessKey {
+        id: row.get(0)?,
+        secret: row.get(1)?,
+        app: row.get(2)?,
+        description: row.get(3)?,
+        created_at: row.get(4)?,
+        last_used: row.get(5)?,
+    })
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(store: &ObjectStore, bucket: &str, key: &str, content: &[u8]) {
+        let temp = store.temp_path();
+        std::fs::write(&temp, content).unwrap();
+        let object = NewObject {
+            size: content.len() as u64,
+            etag: "0".repeat(32),
+            content_type: "application/octet-stream".to_string(),
+            metadata: BTreeMap::new(),
+        };
+        store.put_object(bucket, key, &temp, &object).unwrap();
+    }
+
+    #[test]
+    fn test_put_replace_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::open(dir.path()).unwrap();
+        assert!(store.create_bucket("photos", "albums").unwrap());
+        assert!(!store.create_bucket("wiki", "albums").unwrap());
+
+        put(&store, "albums", "a.jpg", b"first");
+        let first = store.object("albums", "a.jpg").unwrap().unwrap();
+        put(&store, "albums", "a.jpg", b"second!");
+        let second = store.object("albums", "a.jpg").unwrap().unwrap();
+        assert_eq!(second.size, 7);
+        assert!(!store.content_path("albums", &first).exists());
+        assert_eq!(std::fs::read(store.content_path("albums", &second)).unwrap(), b"second!");
+        assert_eq!(store.usage("photos").unwrap(), 7);
+        assert_eq!(store.usage("wiki").unwrap(), 0);
+
+        assert_eq!(store.delete_bucket("albums").unwrap(), DeleteBucket::NotEmpty);
+        assert!(store.delete_object("albums", "a.jpg").unwrap());
+        assert!(!store.delete_object("albums", "a.jpg").unwrap());
+        assert!(!store.content_path("albums", &second).exists());
+        assert_eq!(store.delete_bucket("albums").unwrap(), DeleteBucket::Deleted);
+        assert_eq!(store.delete_bucket("albums").unwrap(), DeleteBucket::NotFound);
+    }
+
+    #[test]
+    fn test_list_with_delimiter_and_continuation() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::open(dir.path()).unwrap();
+        store.create_bucket("photos", "albums").unwrap();
+        for key in ["2024/a.jpg", "2024/b.jpg", "2025/c.jpg", "index.html", "readme.txt"] {
+            put(&store, "albums", key, b"x");
+        }
+
+        let mut params = ListParams { delimiter: Some("/".to_string()), max_keys: 2, ..Default::default() };
+        let page = store.list("albums", &params).unwrap();
+        assert_eq!(page.prefixes, vec!["2024/", "2025/"]);
+        assert!(page.objects.is_empty() && page.truncated);
+
+        params.start_after = page.next;
+        let page = store.list("albums", &params).unwrap();
+        let keys: Vec<_> = page.objects.iter().map(|o| o.key.as_str()).collect();
+        assert_eq!(keys, vec!["index.html", "readme.txt"]);
+        assert!(!page.truncated);
+
+        let params = ListParams { prefix: "2024/".to_string(), max_keys: 1000, ..Default::default() };
+        let page = store.list("albums", &params).unwrap();
+        assert_eq!(page.objects.len(), 2);
+    }
+
+    #[test]
+    fn test_access_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::open(dir.path()).unwrap();
+        let key = store.create_key("photos", "backend").unwrap();
+        assert_eq!(key.id.len(), 20);
+        assert!(key.id.starts_with("HR"));
+        assert_eq!(store.key(&key.id).unwrap().unwrap().secret, key.secret);
+        assert_eq!(store.keys(Some("photos")).unwrap().len(), 1);
+        assert!(store.keys(Some("wiki")).unwrap().is_empty());
+        assert!(store.delete_key(&key.id).unwrap());
+        assert!(store.key(&key.id).unwrap().is_none());
+    }
+}
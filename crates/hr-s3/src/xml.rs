@@ -0,0 +1,206 @@
+//! The few XML documents of the S3 API, written by hand.
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::sigv4::uri_encode;
+use crate::store::{BucketInfo, Listing, ObjectMeta};
+
+const HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>"#;
+const NAMESPACE: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+
+pub fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+pub fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// ISO 8601 with milliseconds, as S3 dates.
+pub fn timestamp(ms: i64) -> String {
+    DateTime::<Utc>::from_timestamp_millis(ms)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+pub fn error(code: &str, message: &str, resource: &str) -> String {
+    format!(
+        "{HEADER}<Error><Code>{}</Code><Message>{}</Message><Resource>{}</Resource></Error>",
+        code,
+        escape(message),
+        escape(resource)
+    )
+}
+
+pub fn list_buckets(owner: &str, buckets: &[BucketInfo]) -> String {
+    let mut xml = format!(
+        "{HEADER}<ListAllMyBucketsResult xmlns=\"{NAMESPACE}\"><Owner><ID>{0}</ID><DisplayName>{0}</DisplayName></Owner><Buckets>",
+        escape(owner)
+    );
+    for info in buckets {
+        xml.push_str(&format!(
+            "<Bucket><Name>{}</Name><CreationDate>{}</CreationDate></Bucket>",
+            escape(&info.bucket.name),
+            timestamp(info.bucket.created_at)
+        ));
+    }
+    xml.push_str("</Buckets></ListAllMyBucketsResult>");
+    xml
+}
+
+/// Parameters of a ListObjects (v1) or ListObjectsV2 request, echoed back.
+#[derive(Debug, Clone, Default)]
+pub struct ListRequest {
+    pub bucket: String,
+    pub v2: bool,
+    pub prefix: String,
+    pub delimiter: Option<String>,
+    pub max_keys: usize,
+    /// v1 `marker`, v2 `start-after`.
+    pub start_after: Option<String>,
+    pub continuation_token: Option<String>,
+    /// `encoding-type=url`: keys and prefixes are URL-encoded.
+    pub url_encoded: bool,
+}
+
+/// Opaque v2 continuation token for `key`.
+pub fn continuation_token(key: &str) -> String {
+    URL_SAFE_NO_PAD.encode(key)
+}
+
+pub fn parse_continuation_token(token: &str) -> Option<String> {
+    URL_SAFE_NO_PAD.decode(token).ok().and_then(|key| String::from_utf8(key).ok())
+}
+
+pub fn list_objects(request: &ListRequest, listing: &Listing) -> String {
+    let encode = |value: &str| {
+        if request.url_encoded {
+            uri_encode(value, false)
+        } else {
+            escape(value)
+        }
+    };
+    let mut xml = format!("{HEADER}<ListBucketResult xmlns=\"{NAMESPACE}\">");
+    xml.push_str(&format!("<Name>{}</Name><Prefix>{}</Prefix>", escape(&request.bucket), encode(&request.prefix)));
+    if let Some(delimiter) = &request.delimiter {
+        xml.push_str(&format!("<Delimiter>{}</Delimiter>", encode(delimiter)));
+    }
+    xml.push_str(&format!("<MaxKeys>{}</MaxKeys>", request.max_keys));
+    if request.url_encoded {
+        xml.push_str("<EncodingType>url</EncodingType>");
+    }
+    xml.push_str(&format!("<IsTruncated>{}</IsTruncated>", listing.truncated));
+    if request.v2 {
+        xml.push_str(&format!("<KeyCount>{}</KeyCount>", listing.objects.len() + listing.prefixes.len()));
+        if let Some(token) = &request.continuation_token {
+            xml.push_str(&format!("<ContinuationToken>{}</ContinuationToken>", escape(token)));
+        }
+        if let Some(start_after) = &request.start_after {
+            xml.push_str(&format!("<StartAfter>{}</StartAfter>", encode(start_after)));
+        }
+        if listing.truncated
+            && let Some(next) = &listing.next
+        {
+            xml.push_str(&format!("<NextContinuationToken>{}</NextContinuationToken>", continuation_token(next)));
+        }
+    } else {
+        xml.push_str(&format!("<Marker>{}</Marker>", encode(request.start_after.as_deref().unwrap_or(""))));
+        if listing.truncated
+            && let Some(next) = &listing.next
+        {
+            xml.push_str(&format!("<NextMarker>{}</NextMarker>", encode(next)));
+        }
+    }
+    for object in &listing.objects {
+        xml.push_str(&object_entry(object, &encode(&object.key)));
+    }
+    for prefix in &listing.prefixes {
+        xml.push_str(&format!("<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>", encode(prefix)));
+    }
+    xml.push_str("</ListBucketResult>");
+    xml
+}
+
+fn object_entry(object: &ObjectMeta, key: &str) -> String {
+    format!(
+        "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>&quot;{}&quot;</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+        key,
+        timestamp(object.modified),
+        object.etag,
+        object.size
+    )
+}
+
+/// Keys of a DeleteObjects request, and whether it is quiet.
+pub fn parse_delete(body: &str) -> (Vec<String>, bool) {
+    let mut keys = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("<Key>") {
+        rest = &rest[start + 5..];
+        let Some(end) = rest.find("</Key>") else {
+            break;
+        };
+        keys.push(unescape(&rest[..end]));
+        rest = &rest[end + 6..];
+    }
+    let quiet = body.contains("<Quiet>true</Quiet>");
+    (keys, quiet)
+}
+
+/// Outcome of a DeleteObjects request: deleted keys, then errors.
+pub fn delete_result(deleted: &[String], errors: &[(String, &str, String)]) -> String {
+    let mut xml = format!("{HEADER}<DeleteResult xmlns=\"{NAMESPACE}\">");
+    for key in deleted {
+        xml.push_str(&format!("<Deleted><Key>{}</Key></Deleted>", escape(key)));
+    }
+    for (key, code, message) in errors {
+        xml.push_str(&format!(
+            "<Error><Key>{}</Key><Code>{}</Code><Message>{}</Message></Error>",
+            escape(key),
+            code,
+            escape(message)
+        ));
+    }
+    xml.push_str("</DeleteResult>");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delete_request_and_escaping() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <Delete xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+              <Object><Key>a &amp; b.txt</Key></Object>
+              <Object><Key>dir/&lt;c&gt;.txt</Key><VersionId>1</VersionId></Object>
+              <Quiet>true</Quiet>
+            </Delete>"#;
+        let (keys, quiet) = parse_delete(body);
+        assert_eq!(keys, vec!["a & b.txt", "dir/<c>.txt"]);
+        assert!(quiet);
+
+        let xml = delete_result(&keys, &[]);
+        assert!(xml.contains("<Deleted><Key>a &amp; b.txt</Key></Deleted>"));
+        assert_eq!(parse_continuation_token(&continuation_token("été/1")).as_deref(), Some("été/1"));
+    }
+}
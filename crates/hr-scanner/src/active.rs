@@ -0,0 +1,104 @@
+//! Active scan: ping sweep of IPv4 subnets (the replies fill the ARP
+//! table) and TCP connect probe of a few ports on the hosts that answer.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ipnet::Ipv4Net;
+use tokio::sync::Semaphore;
+
+/// Pings or connections in flight.
+const CONCURRENCY: usize = 64;
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(700);
+
+/// Host addresses of a subnet (no network or broadcast address below /31).
+pub fn hosts(net: &Ipv4Net) -> Vec<Ipv4Addr> {
+    net.hosts().collect()
+}
+
+async fn ping(ip: Ipv4Addr) -> bool {
+    tokio::process::Command::new("ping")
+        .args(["-c", "1", "-W", "1", "-n", "-q", &ip.to_string()])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await
+        .is_ok_and(|s| s.success())
+}
+
+/// Addresses that answered, in order.
+pub async fn ping_sweep(targets: Vec<Ipv4Addr>) -> Vec<Ipv4Addr> {
+    let limit = Arc::new(Semaphore::new(CONCURRENCY));
+    let tasks: Vec<_> = targets
+        .into_iter()
+        .map(|ip| {
+            let limit = limit.clone();
+            tokio::spawn(async move {
+                let _permit = limit.acquire_owned().await.ok()?;
+                ping(ip).await.then_some(ip)
+            })
+        })
+        .collect();
+    let mut alive = Vec::new();
+    for task in tasks {
+        if let Ok(Some(ip)) = task.await {
+            alive.push(ip);
+        }
+    }
+    alive
+}
+
+/// Ports of `ip` accepting a TCP connection.
+pub async fn open_ports(ip: IpAddr, ports: &[u16]) -> Vec<u16> {
+    let limit = Arc::new(Semaphore::new(CONCURRENCY));
+    let tasks: Vec<_> = ports
+        .iter()
+        .map(|&port| {
+            let limit = limit.clone();
+            tokio::spawn(async move {
+                let _permit = limit.acquire_owned().await.ok()?;
+                let connect = tokio::net::TcpStream::connect(SocketAddr::new(ip, port));
+                matches!(tokio::time::timeout(CONNECT_TIMEOUT, connect).await, Ok(Ok(_))).then_some(port)
+            })
+        })
+        .collect();
+    let mut open = Vec::new();
+    for task in tasks {
+        if let Ok(Some(port)) = task.await {
+            open.push(port);
+        }
+    }
+    open.sort_unstable();
+    open
+}
+
+/// Network of the DHCP server: its address and netmask.
+pub fn dhcp_subnet(server_ip: Ipv4Addr, netmask: &str) -> Option<Ipv4Net> {
+    let mask: Ipv4Addr = netmask.parse().ok()?;
+    let prefix = u32::from(mask).leading_ones() as u8;
+    Ipv4Net::new(server_ip, prefix).ok().map(|n| n.trunc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hosts() {
+        let net: Ipv4Net = "192.168.1.0/30".parse().unwrap();
+        assert_eq!(hosts(&net), vec![Ipv4Addr::new(192, 168, 1, 1), Ipv4Addr::new(192, 168, 1, 2)]);
+        let subnet = dhcp_subnet(Ipv4Addr::new(10, 0, 4, 1), "255.255.252.0").unwrap();
+        assert_eq!(subnet.to_string(), "10.0.4.0/22");
+        assert_eq!(hosts(&subnet).len(), 1022);
+        assert!(dhcp_subnet(Ipv4Addr::new(10, 0, 0, 1), "bogus").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_open_ports() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let open = open_ports(IpAddr::V4(Ipv4Addr::LOCALHOST), &[port]).await;
+        assert_eq!(open, vec![port]);
+    }
+}
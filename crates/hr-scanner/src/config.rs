@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use ipnet::Ipv4Net;
+
+/// Network device inventory (scanner-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScannerConfig {
+    /// Read the neighbor tables and the DHCP leases (nothing is sent).
+    #[serde(default = "yes")]
+    pub enabled: bool,
+    #[serde(default = "default_poll")]
+    pub poll_secs: u64,
+    /// A device not seen for this long is offline.
+    #[serde(default = "default_offline_after")]
+    pub offline_after_secs: u64,
+    /// Devices not seen for this long are dropped from the inventory.
+    #[serde(default = "default_forget_after")]
+    pub forget_after_days: u64,
+    /// IEEE OUI list (`oui.txt`, Debian package `ieee-data`) or Wireshark
+    /// `manuf` file; vendors are left empty without it.
+    #[serde(default = "default_oui_database")]
+    pub oui_database: String,
+    #[serde(default)]
+    pub active: ActiveScan,
+}
+
+/// Ping sweep and TCP port probe of the LAN, for devices that stay quiet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveScan {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_interval")]
+    pub interval_mins: u64,
+    /// IPv4 subnets swept (at most /22 each); the DHCP network when empty.
+    #[serde(default)]
+    pub subnets: Vec<String>,
+    /// TCP ports probed on the devices that answer.
+    #[serde(default = "default_ports")]
+    pub ports: Vec<u16>,
+}
+
+fn yes() -> bool {
+    true
+}
+
+fn default_poll() -> u64 {
+    60
+}
+
+fn default_offline_after() -> u64 {
+    600
+}
+
+fn default_forget_after() -> u64 {
+    90
+}
+
+fn default_oui_database() -> String {
+    "/usr/share/ieee-data/oui.txt".to_string()
+}
+
+fn default_interval() -> u64 {
+    60
+}
+
+fn default_ports() -> Vec<u16> {
+    vec![22, 80, 443, 445, 554, 8080, 9100]
+}
+
+impl Default for ScannerConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl Default for ActiveScan {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+/// Largest subnet swept (1024 addresses).
+pub const MIN_PREFIX_LEN: u8 = 22;
+
+impl ActiveScan {
+    pub fn parsed_subnets(&self) -> Result<Vec<Ipv4Net>, String> {
+        self.subnets
+            .iter()
+            .map(|s| {
+                let net: Ipv4Net = s.trim().parse().map_err(|_| format!("Invalid subnet: {s}"))?;
+                if net.prefix_len() < MIN_PREFIX_LEN {
+                    return Err(format!("{s} is too large (at most /{MIN_PREFIX_LEN})"));
+                }
+                Ok(net.trunc())
+            })
+            .collect()
+    }
+}
+
+impl ScannerConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(10..=3600).contains(&self.poll_secs) {
+            return Err("The poll interval must be between 10 s and 1 h".to_string());
+        }
+        if self.offline_after_secs < self.poll_secs {
+            return Err("The offline delay must be at least the poll interval".to_string());
+        }
+        if self.forget_after_days == 0 {
+            return Err("Devices must be kept at least one day".to_string());
+        }
+        if !(5..=10_080).contains(&self.active.interval_mins) {
+            return Err("The scan interval must be between 5 min and 7 days".to_string());
+        }
+        self.active.parsed_subnets()?;
+        if self.active.ports.len() > 64 || self.active.ports.contains(&0) {
+            return Err("Up to 64 ports, none of them 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut config = ScannerConfig::default();
+        assert!(config.enabled && !config.active.enabled);
+        assert_eq!(config.active.ports, default_ports());
+        assert!(config.validate().is_ok());
+
+        config.active.subnets = vec!["192.168.1.7/24".to_string()];
+        assert_eq!(config.active.parsed_subnets().unwrap()[0].to_string(), "192.168.1.0/24");
+        config.active.subnets = vec!["10.0.0.0/16".to_string()];
+        assert!(config.validate().is_err());
+        config.active.subnets.clear();
+        config.offline_after_secs = 30;
+        assert!(config.validate().is_err());
+        config.offline_after_secs = 600;
+        config.active.ports.push(0);
+        assert!(config.validate().is_err());
+    }
+}
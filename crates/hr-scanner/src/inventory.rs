@@ -0,0 +1,320 @@
+//! Device inventory keyed by MAC address: observations from the neighbor
+//! tables, the DHCP leases and the active scan are merged into it, and the
+//! changes (new device, online, offline, new address) reported.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::oui::{self, OuiTable};
+
+/// IPv6 addresses kept per device (privacy addresses rotate).
+const MAX_IPV6: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// ARP (IPv4) or NDP (IPv6) table.
+    Neighbor,
+    Dhcp,
+    Scan,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Device {
+    /// Lowercase, colon separated.
+    pub mac: String,
+    pub ipv4: Option<String>,
+    #[serde(default)]
+    pub ipv6: Vec<String>,
+    pub hostname: Option<String>,
+    pub vendor: Option<String>,
+    /// Locally administered MAC (private Wi-Fi address).
+    #[serde(default)]
+    pub randomized: bool,
+    pub interface: Option<String>,
+    #[serde(default)]
+    pub sources: BTreeSet<Source>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    #[serde(default)]
+    pub online: bool,
+    /// From the last active scan.
+    #[serde(default)]
+    pub open_ports: Vec<u16>,
+    pub ports_scanned_at: Option<DateTime<Utc>>,
+}
+
+/// Something seen of a device.
+#[derive(Debug, Clone)]
+pub struct Observation {
+    pub mac: String,
+    pub ip: Option<IpAddr>,
+    pub hostname: Option<String>,
+    pub interface: Option<String>,
+    pub source: Source,
+    /// Present right now (reachable neighbor, scan answer); a lease or a
+    /// stale neighbor entry only describes it.
+    pub seen: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    New,
+    Online,
+    Offline,
+    IpChanged,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::New => "new",
+            ChangeKind::Online => "online",
+            ChangeKind::Offline => "offline",
+            ChangeKind::IpChanged => "ip_changed",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub device: Device,
+    /// IPv4 before an `IpChanged`.
+    pub previous_ip: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Inventory {
+    devices: BTreeMap<String, Device>,
+}
+
+impl Inventory {
+    /// Empty when the file is missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        let devices: Vec<Device> = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        Self { devices: devices.into_iter().map(|d| (d.mac.clone(), d)).collect() }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(&self.devices())?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Sorted by MAC address.
+    pub fn devices(&self) -> Vec<Device> {
+        self.devices.values().cloned().collect()
+    }
+
+    pub fn get(&self, mac: &str) -> Option<&Device> {
+        self.devices.get(&mac.to_ascii_lowercase())
+    }
+
+    pub fn remove(&mut self, mac: &str) -> Option<Device> {
+        self.devices.remove(&mac.to_ascii_lowercase())
+    }
+
+    /// Merge observations made at `now`, then mark offline the devices not
+    /// seen for `offline_after`.
+    pub fn merge(
+        &mut self,
+        observations: &[Observation],
+        now: DateTime<Utc>,
+        offline_after: Duration,
+        oui: &OuiTable,
+    ) -> Vec<Change> {
+        let mut changes = Vec::new();
+        let mut new_macs = BTreeSet::new();
+        // Descriptions first, so a live address wins over a stale lease
+        let mut ordered: Vec<&Observation> = observations.iter().collect();
+        ordered.sort_by_key(|o| o.seen);
+
+        for obs in ordered {
+            let mac = obs.mac.to_ascii_lowercase();
+            let device = self.devices.entry(mac.clone()).or_insert_with(|| {
+                new_macs.insert(mac.clone());
+                Device {
+                    vendor: oui.lookup(&mac).map(str::to_string),
+                    randomized: oui::is_randomized(&mac),
+                    mac: mac.clone(),
+                    ipv4: None,
+                    ipv6: Vec::new(),
+                    hostname: None,
+                    interface: None,
+                    sources: BTreeSet::new(),
+                    first_seen: now,
+                    last_seen: now,
+                    online: false,
+                    open_ports: Vec::new(),
+                    ports_scanned_at: None,
+                }
+            });
+            if device.vendor.is_none() {
+                device.vendor = oui.lookup(&mac).map(str::to_string);
+            }
+            device.sources.insert(obs.source);
+            if let Some(hostname) = obs.hostname.as_ref().filter(|h| !h.is_empty()) {
+                device.hostname = Some(hostname.clone());
+            }
+            if obs.interface.is_some() {
+                device.interface = obs.interface.clone();
+            }
+            match obs.ip {
+                Some(IpAddr::V4(ip)) => {
+                    let ip = ip.to_string();
+                    if device.ipv4.as_deref() != Some(ip.as_str()) && (obs.seen || device.ipv4.is_none()) {
+                        let previous = device.ipv4.replace(ip);
+                        if previous.is_some() && !new_macs.contains(&mac) {
+                            changes.push(Change { kind: ChangeKind::IpChanged, device: device.clone(), previous_ip: previous });
+                        }
+                    }
+                }
+                Some(IpAddr::V6(ip)) => {
+                    let ip = ip.to_string();
+                    if let Some(pos) = device.ipv6.iter().position(|a| *a == ip) {
+                        device.ipv6.remove(pos);
+                    }
+                    device.ipv6.insert(0, ip);
+                    device.ipv6.truncate(MAX_IPV6);
+                }
+                None => {}
+            }
+            if obs.seen {
+                device.last_seen = now;
+                if !device.online {
+                    device.online = true;
+                    if !new_macs.contains(&mac) {
+                        changes.push(Change { kind: ChangeKind::Online, device: device.clone(), previous_ip: None });
+                    }
+                }
+            }
+        }
+
+        for mac in &new_macs {
+            let device = &self.devices[mac];
+            changes.push(Change { kind: ChangeKind::New, device: device.clone(), previous_ip: None });
+        }
+        for device in self.devices.values_mut() {
+            if device.online && now - device.last_seen > offline_after {
+                device.online = false;
+                changes.push(Change { kind: ChangeKind::Offline, device: device.clone(), previous_ip: None });
+            }
+        }
+        changes
+    }
+
+    /// Record the open ports found on a device.
+    pub fn set_ports(&mut self, mac: &str, ports: Vec<u16>, now: DateTime<Utc>) {
+        if let Some(device) = self.devices.get_mut(&mac.to_ascii_lowercase()) {
+            device.open_ports = ports;
+            device.ports_scanned_at = Some(now);
+        }
+    }
+
+    /// Drop the devices not seen since `cutoff`; returns how many.
+    pub fn forget_older(&mut self, cutoff: DateTime<Utc>) -> usize {
+        let before = self.devices.len();
+        self.devices.retain(|_, d| d.last_seen >= cutoff);
+        before - self.devices.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn obs(mac: &str, ip: &str, source: Source, seen: bool) -> Observation {
+        Observation {
+            mac: mac.to_string(),
+            ip: Some(ip.parse().unwrap()),
+            hostname: None,
+            interface: Some("br-lan".to_string()),
+            source,
+            seen,
+        }
+    }
+
+    fn kinds(changes: &[Change]) -> Vec<(ChangeKind, &str)> {
+        changes.iter().map(|c| (c.kind, c.device.mac.as_str())).collect()
+    }
+
+    #[test]
+    fn test_merge() {
+        let oui = OuiTable::parse("B8-27-EB   (hex)\t\tRaspberry Pi Foundation\n");
+        let mut inventory = Inventory::default();
+        let t0 = Utc::now();
+        let offline_after = Duration::minutes(10);
+
+        let mut lease = obs("B8:27:EB:00:00:01", "192.168.1.50", Source::Dhcp, false);
+        lease.hostname = Some("pi".to_string());
+        let changes = inventory.merge(
+            &[
+                obs("b8:27:eb:00:00:01", "192.168.1.20", Source::Neighbor, true),
+                lease,
+                obs("b8:27:eb:00:00:01", "fe80::1", Source::Neighbor, true),
+                obs("da:a1:19:00:00:02", "192.168.1.30", Source::Neighbor, false),
+            ],
+            t0,
+            offline_after,
+            &oui,
+        );
+        assert_eq!(kinds(&changes), vec![(ChangeKind::New, "b8:27:eb:00:00:01"), (ChangeKind::New, "da:a1:19:00:00:02")]);
+        let pi = inventory.get("b8:27:eb:00:00:01").unwrap();
+        // The live ARP address wins over the lease
+        assert_eq!(pi.ipv4.as_deref(), Some("192.168.1.20"));
+        assert_eq!(pi.ipv6, vec!["fe80::1"]);
+        assert_eq!(pi.hostname.as_deref(), Some("pi"));
+        assert_eq!(pi.vendor.as_deref(), Some("Raspberry Pi Foundation"));
+        assert_eq!(pi.sources, BTreeSet::from([Source::Neighbor, Source::Dhcp]));
+        assert!(pi.online && !pi.randomized);
+        let phone = inventory.get("da:a1:19:00:00:02").unwrap();
+        assert!(!phone.online && phone.randomized && phone.vendor.is_none());
+
+        // Phone answers with a new address, the Pi stays silent
+        let t1 = t0 + Duration::minutes(11);
+        let changes = inventory.merge(&[obs("da:a1:19:00:00:02", "192.168.1.31", Source::Scan, true)], t1, offline_after, &oui);
+        assert_eq!(
+            kinds(&changes),
+            vec![
+                (ChangeKind::IpChanged, "da:a1:19:00:00:02"),
+                (ChangeKind::Online, "da:a1:19:00:00:02"),
+                (ChangeKind::Offline, "b8:27:eb:00:00:01"),
+            ]
+        );
+        assert_eq!(changes[0].previous_ip.as_deref(), Some("192.168.1.30"));
+
+        inventory.set_ports("DA:A1:19:00:00:02", vec![80], t1);
+        assert_eq!(inventory.get("da:a1:19:00:00:02").unwrap().open_ports, vec![80]);
+        assert_eq!(inventory.forget_older(t1 - Duration::minutes(1)), 1);
+        assert!(inventory.get("b8:27:eb:00:00:01").is_none());
+    }
+
+    #[test]
+    fn test_persistence() {
+        let dir = std::env::temp_dir().join(format!("hr-scanner-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("devices.json");
+        let mut inventory = Inventory::default();
+        inventory.merge(
+            &[obs("aa:bb:cc:00:00:01", "10.0.0.2", Source::Dhcp, false)],
+            Utc::now(),
+            Duration::minutes(10),
+            &OuiTable::default(),
+        );
+        inventory.save(&path).unwrap();
+        assert_eq!(Inventory::load(&path).devices(), inventory.devices());
+        assert!(Inventory::load(&dir.join("missing.json")).devices().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+//! Network device inventory: merges the kernel neighbor tables (ARP/NDP),
+//! the DHCP leases and an optional active scan (ping sweep, TCP ports) into
+//! one list of devices with first/last seen times and vendor, and reports
+//! devices appearing, going on/offline or changing address.
+
+pub mod active;
+pub mod config;
+pub mod inventory;
+pub mod neighbors;
+pub mod oui;
+pub mod service;
+
+pub use config::{ActiveScan, ScannerConfig};
+pub use inventory::{Device, Source};
+
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::{Notify, watch};
+
+use inventory::Inventory;
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScannerStatus {
+    pub active: bool,
+    /// Active scan running.
+    pub scanning: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_poll: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_scan: Option<String>,
+    /// Entries of the OUI database (0: vendors unknown).
+    pub vendors: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Shared scanner handle: the API reads the inventory, pushes config and
+/// asks for scans; the supervised `scanner` service fills it.
+pub struct Scanner {
+    config: watch::Sender<ScannerConfig>,
+    inventory: RwLock<Inventory>,
+    status: RwLock<ScannerStatus>,
+    /// Inventory file (devices.json).
+    path: PathBuf,
+    scan_requested: AtomicBool,
+    wake: Notify,
+}
+
+pub type SharedScanner = Arc<Scanner>;
+
+impl Scanner {
+    /// Starts from the inventory saved at `path`.
+    pub fn new(config: ScannerConfig, path: PathBuf) -> Self {
+        Self {
+            config: watch::channel(config).0,
+            inventory: RwLock::new(Inventory::load(&path)),
+            status: RwLock::new(ScannerStatus::default()),
+            path,
+            scan_requested: AtomicBool::new(false),
+            wake: Notify::new(),
+        }
+    }
+
+    pub fn config(&self) -> ScannerConfig {
+        self.config.borrow().clone()
+    }
+
+    pub fn apply(&self, config: ScannerConfig) {
+        self.config.send_replace(config);
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<ScannerConfig> {
+        self.config.subscribe()
+    }
+
+    pub fn devices(&self) -> Vec<Device> {
+        self.inventory.read().unwrap().devices()
+    }
+
+    pub fn device(&self, mac: &str) -> Option<Device> {
+        self.inventory.read().unwrap().get(mac).cloned()
+    }
+
+    /// Device currently holding an IPv4 or IPv6 address.
+    pub fn device_by_ip(&self, ip: &str) -> Option<Device> {
+        self.devices()
+            .into_iter()
+            .find(|d| d.ipv4.as_deref() == Some(ip) || d.ipv6.iter().any(|a| a == ip))
+    }
+
+    /// Drop a device; it comes back if seen again.
+    pub fn forget(&self, mac: &str) -> Option<Device> {
+        let removed = self.inventory.write().unwrap().remove(mac);
+        if removed.is_some() {
+            self.save();
+        }
+        removed
+    }
+
+    /// Run an active scan now, enabled or not.
+    pub fn scan_now(&self) {
+        self.scan_requested.store(true, Ordering::SeqCst);
+        self.wake.notify_one();
+    }
+
+    pub(crate) fn take_scan_request(&self) -> bool {
+        self.scan_requested.swap(false, Ordering::SeqCst)
+    }
+
+    pub(crate) fn with_inventory<T>(&self, f: impl FnOnce(&mut Inventory) -> T) -> T {
+        f(&mut self.inventory.write().unwrap())
+    }
+
+    pub(crate) fn save(&self) {
+        if let Err(e) = self.inventory.read().unwrap().save(&self.path) {
+            tracing::warn!("Failed to save the device inventory: {}", e);
+        }
+    }
+
+    pub fn status(&self) -> ScannerStatus {
+        self.status.read().unwrap().clone()
+    }
+
+    pub(crate) fn update_status(&self, f: impl FnOnce(&mut ScannerStatus)) {
+        f(&mut self.status.write().unwrap());
+    }
+}
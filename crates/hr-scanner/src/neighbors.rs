@@ -0,0 +1,79 @@
+//! Kernel neighbor tables (ARP for IPv4, NDP for IPv6) via `ip -j neigh`.
+
+use std::net::IpAddr;
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Neighbor {
+    pub ip: IpAddr,
+    pub mac: String,
+    pub interface: String,
+    /// Confirmed recently (REACHABLE, DELAY, PROBE, PERMANENT), not just
+    /// remembered (STALE).
+    pub reachable: bool,
+}
+
+#[derive(Deserialize)]
+struct RawNeighbor {
+    dst: String,
+    #[serde(default)]
+    dev: String,
+    #[serde(default)]
+    lladdr: Option<String>,
+    #[serde(default)]
+    state: Vec<String>,
+}
+
+/// Entries with a link-layer address; failed and incomplete ones are
+/// skipped, as are multicast and loopback addresses.
+pub fn parse(json: &str) -> Vec<Neighbor> {
+    let raw: Vec<RawNeighbor> = serde_json::from_str(json).unwrap_or_default();
+    raw.into_iter()
+        .filter_map(|n| {
+            let ip: IpAddr = n.dst.parse().ok()?;
+            if ip.is_multicast() || ip.is_loopback() {
+                return None;
+            }
+            let mac = n.lladdr?.to_ascii_lowercase();
+            if mac == "00:00:00:00:00:00" || n.state.iter().any(|s| s == "FAILED" || s == "INCOMPLETE") {
+                return None;
+            }
+            let reachable = n.state.iter().any(|s| matches!(s.as_str(), "REACHABLE" | "DELAY" | "PROBE" | "PERMANENT"));
+            Some(Neighbor { ip, mac, interface: n.dev, reachable })
+        })
+        .collect()
+}
+
+pub async fn read() -> anyhow::Result<Vec<Neighbor>> {
+    let output = tokio::process::Command::new("ip").args(["-j", "neigh", "show"]).output().await?;
+    if !output.status.success() {
+        anyhow::bail!("ip neigh: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let neighbors = parse(
+            r#"[
+                {"dst":"192.168.1.20","dev":"br-lan","lladdr":"AA:BB:CC:00:00:01","state":["REACHABLE"]},
+                {"dst":"192.168.1.21","dev":"br-lan","lladdr":"aa:bb:cc:00:00:02","state":["STALE"]},
+                {"dst":"192.168.1.22","dev":"br-lan","state":["FAILED"]},
+                {"dst":"192.168.1.23","dev":"br-lan","lladdr":"aa:bb:cc:00:00:03","state":["INCOMPLETE"]},
+                {"dst":"fe80::1","dev":"br-lan","lladdr":"aa:bb:cc:00:00:01","router":null,"state":["DELAY"]},
+                {"dst":"ff02::16","dev":"br-lan","lladdr":"33:33:00:00:00:16","state":["NOARP"]}
+            ]"#,
+        );
+        assert_eq!(neighbors.len(), 3);
+        assert_eq!(neighbors[0].mac, "aa:bb:cc:00:00:01");
+        assert!(neighbors[0].reachable);
+        assert!(!neighbors[1].reachable);
+        assert_eq!(neighbors[2].ip, "fe80::1".parse::<IpAddr>().unwrap());
+        assert!(parse("not json").is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+//! Vendor of a MAC address from its OUI (first three octets).
+
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Default)]
+pub struct OuiTable {
+    vendors: HashMap<[u8; 3], String>,
+}
+
+/// `AA-BB-CC`, `AA:BB:CC` or `AABBCC` at the start of `text`.
+fn parse_prefix(text: &str) -> Option<[u8; 3]> {
+    let hex: String = text.chars().filter(|c| *c != '-' && *c != ':').take(6).collect();
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(&hex, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+impl OuiTable {
+    /// Parse the IEEE `oui.txt` (`AA-BB-CC   (hex)\t\tVendor`) or the
+    /// Wireshark `manuf` format (`AA:BB:CC\tShort\tVendor`); other lines,
+    /// and `manuf` entries narrower than /24, are ignored.
+    pub fn parse(content: &str) -> Self {
+        let mut vendors = HashMap::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((prefix, vendor)) = line.split_once("(hex)") {
+                if let Some(oui) = parse_prefix(prefix.trim()) {
+                    vendors.insert(oui, vendor.trim().to_string());
+                }
+                continue;
+            }
+            let mut fields = line.split('\t').filter(|f| !f.is_empty());
+            let (Some(prefix), Some(short)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            if prefix.len() != 8 || prefix.contains('/') {
+                continue;
+            }
+            if let Some(oui) = parse_prefix(prefix) {
+                vendors.insert(oui, fields.next().unwrap_or(short).trim().to_string());
+            }
+        }
+        Self { vendors }
+    }
+
+    /// Empty table when the file is missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        std::fs::read_to_string(path).map(|c| Self::parse(&c)).unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.vendors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vendors.is_empty()
+    }
+
+    pub fn lookup(&self, mac: &str) -> Option<&str> {
+        self.vendors.get(&parse_prefix(mac)?).map(String::as_str)
+    }
+}
+
+/// Locally administered address: phones and laptops use random ones per
+/// network, no vendor to find.
+pub fn is_randomized(mac: &str) -> bool {
+    parse_prefix(mac).is_some_and(|oui| oui[0] & 0x02 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_lookup() {
+        let oui = OuiTable::parse(
+            "OUI/MA-L                                                    Organization\n\
+             company_id                                                  Organization\n\
+             \n\
+             00-1A-11   (hex)\t\tGoogle, Inc.\n\
+             001A11     (base 16)\t\tGoogle, Inc.\n\
+             \t\t\t\t1600 Amphitheatre Parkway\n\
+             # manuf\n\
+             B8:27:EB\tRaspberr\tRaspberry Pi Foundation\n\
+             00:50:C2:00:00:00/36\tTlsCorpo\tTLS Corporation\n\
+             F4:F5:D8\tGoogle\n",
+        );
+        assert_eq!(oui.len(), 3);
+        assert_eq!(oui.lookup("00:1a:11:22:33:44"), Some("Google, Inc."));
+        assert_eq!(oui.lookup("b8-27-eb-00-00-01"), Some("Raspberry Pi Foundation"));
+        assert_eq!(oui.lookup("f4:f5:d8:01:02:03"), Some("Google"));
+        assert_eq!(oui.lookup("00:50:c2:00:00:01"), None);
+        assert_eq!(oui.lookup("zz"), None);
+
+        assert!(is_randomized("da:a1:19:00:00:01"));
+        assert!(!is_randomized("b8:27:eb:00:00:01"));
+    }
+}
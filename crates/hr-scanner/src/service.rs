@@ -0,0 +1,208 @@
+//! The `scanner` service: reads the neighbor tables and the DHCP leases
+//! every poll interval, runs the active scan when due (or asked for),
+//! merges everything into the inventory and publishes the changes.
+
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::Utc;
+use hr_common::events::{EventBus, NetworkDeviceEvent};
+use hr_dhcp::SharedDhcpState;
+use ipnet::Ipv4Net;
+use tracing::{info, warn};
+
+use crate::active;
+use crate::config::ScannerConfig;
+use crate::inventory::{Change, Observation, Source};
+use crate::neighbors;
+use crate::oui::OuiTable;
+use crate::SharedScanner;
+
+pub async fn run_scanner(scanner: SharedScanner, dhcp: SharedDhcpState, events: Arc<EventBus>) -> Result<()> {
+    let mut config_rx = scanner.subscribe();
+    let mut oui_path = String::new();
+    let mut oui = OuiTable::default();
+    let mut next_scan = Instant::now();
+    loop {
+        let config = config_rx.borrow_and_update().clone();
+        scanner.update_status(|s| s.active = config.enabled);
+        if config.enabled {
+            if config.oui_database != oui_path {
+                oui_path = config.oui_database.clone();
+                let path = oui_path.clone();
+                oui = tokio::task::spawn_blocking(move || OuiTable::load(Path::new(&path))).await?;
+                let vendors = oui.len();
+                scanner.update_status(|s| s.vendors = vendors);
+            }
+            poll(&scanner, &dhcp, &events, &config, &oui, &[]).await;
+
+            let requested = scanner.take_scan_request();
+            if requested || config.active.enabled && Instant::now() >= next_scan {
+                next_scan = Instant::now() + Duration::from_secs(config.active.interval_mins * 60);
+                scan(&scanner, &dhcp, &events, &config, &oui).await;
+            }
+        }
+
+        tokio::select! {
+            changed = config_rx.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+            }
+            _ = scanner.wake.notified() => {}
+            _ = tokio::time::sleep(Duration::from_secs(config.poll_secs)) => {}
+        }
+    }
+}
+
+async fn poll(
+    scanner: &SharedScanner,
+    dhcp: &SharedDhcpState,
+    events: &EventBus,
+    config: &ScannerConfig,
+    oui: &OuiTable,
+    extra: &[Observation],
+) {
+    let mut observations = extra.to_vec();
+    match neighbors::read().await {
+        Ok(entries) => {
+            scanner.update_status(|s| s.last_error = None);
+            observations.extend(entries.into_iter().map(|n| Observation {
+                mac: n.mac,
+                ip: Some(n.ip),
+                hostname: None,
+                interface: Some(n.interface),
+                source: Source::Neighbor,
+                seen: n.reachable,
+            }));
+        }
+        Err(e) => {
+            warn!("Neighbor table: {}", e);
+            scanner.update_status(|s| s.last_error = Some(e.to_string()));
+        }
+    }
+    {
+        let state = dhcp.read().await;
+        let now = Utc::now().timestamp().max(0) as u64;
+        observations.extend(state.lease_store.all_leases().into_iter().filter(|l| l.expiry > now).map(|l| {
+            Observation {
+                mac: l.mac.clone(),
+                ip: Some(IpAddr::V4(l.ip)),
+                hostname: l.hostname.clone(),
+                interface: Some(state.config.interface.clone()),
+                source: Source::Dhcp,
+                seen: false,
+            }
+        }));
+    }
+
+    let now = Utc::now();
+    let offline_after = chrono::Duration::seconds(config.offline_after_secs as i64);
+    let (changes, forgotten, online) = scanner.with_inventory(|inventory| {
+        let changes = inventory.merge(&observations, now, offline_after, oui);
+        let forgotten = inventory.forget_older(now - chrono::Duration::days(config.forget_after_days as i64));
+        let online = inventory.devices().iter().filter(|d| d.online).count();
+        (changes, forgotten, online)
+    });
+    if forgotten > 0 {
+        info!("Forgot {} devices not seen for {} days", forgotten, config.forget_after_days);
+    }
+    hr_common::metrics::registry()
+        .gauge("homeroute_network_devices_online", "LAN devices currently online", &[])
+        .set(online as f64);
+    publish(events, &changes, now);
+    scanner.save();
+    scanner.update_status(|s| s.last_poll = Some(now.to_rfc3339()));
+}
+
+fn publish(events: &EventBus, changes: &[Change], now: chrono::DateTime<Utc>) {
+    for change in changes {
+        if change.kind == crate::inventory::ChangeKind::New {
+            info!(
+                "New device {} ({}) at {}",
+                change.device.mac,
+                change.device.vendor.as_deref().unwrap_or("unknown vendor"),
+                change.device.ipv4.as_deref().unwrap_or("-")
+            );
+        }
+        let _ = events.network_device.send(NetworkDeviceEvent {
+            change: change.kind.as_str().to_string(),
+            mac: change.device.mac.clone(),
+            ip: change.device.ipv4.clone(),
+            previous_ip: change.previous_ip.clone(),
+            hostname: change.device.hostname.clone(),
+            vendor: change.device.vendor.clone(),
+            at: now.to_rfc3339(),
+        });
+    }
+}
+
+/// Subnets to sweep: the configured ones, else the DHCP network.
+async fn subnets(config: &ScannerConfig, dhcp: &SharedDhcpState) -> Vec<Ipv4Net> {
+    let configured = config.active.parsed_subnets().unwrap_or_default();
+    if !configured.is_empty() {
+        return configured;
+    }
+    let state = dhcp.read().await;
+    active::dhcp_subnet(state.server_ip, &state.config.netmask)
+        .filter(|n| n.prefix_len() >= crate::config::MIN_PREFIX_LEN)
+        .into_iter()
+        .collect()
+}
+
+async fn scan(
+    scanner: &SharedScanner,
+    dhcp: &SharedDhcpState,
+    events: &EventBus,
+    config: &ScannerConfig,
+    oui: &OuiTable,
+) {
+    let nets = subnets(config, dhcp).await;
+    if nets.is_empty() {
+        warn!("Active scan: no subnet to sweep");
+        return;
+    }
+    scanner.update_status(|s| s.scanning = true);
+    let started = Instant::now();
+    let targets: Vec<_> = nets.iter().flat_map(active::hosts).collect();
+    let alive = active::ping_sweep(targets).await;
+
+    // The replies filled the ARP table: map them to MAC addresses
+    let table = neighbors::read().await.unwrap_or_default();
+    let answered: Vec<Observation> = table
+        .into_iter()
+        .filter(|n| matches!(n.ip, IpAddr::V4(ip) if alive.contains(&ip)))
+        .map(|n| Observation {
+            mac: n.mac,
+            ip: Some(n.ip),
+            hostname: None,
+            interface: Some(n.interface),
+            source: Source::Scan,
+            seen: true,
+        })
+        .collect();
+    poll(scanner, dhcp, events, config, oui, &answered).await;
+
+    if !config.active.ports.is_empty() {
+        let now = Utc::now();
+        for obs in &answered {
+            let Some(ip) = obs.ip else { continue };
+            let ports = active::open_ports(ip, &config.active.ports).await;
+            scanner.with_inventory(|inventory| inventory.set_ports(&obs.mac, ports, now));
+        }
+        scanner.save();
+    }
+    info!(
+        "Active scan of {} subnet(s): {} hosts answered in {:.1}s",
+        nets.len(),
+        alive.len(),
+        started.elapsed().as_secs_f64()
+    );
+    scanner.update_status(|s| {
+        s.scanning = false;
+        s.last_scan = Some(Utc::now().to_rfc3339());
+    });
+}
@@ -0,0 +1,74 @@
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+/// Source-IP allowlist for a stream rule.
+#[derive(Debug, Clone, Default)]
+pub struct SourceAcl {
+    nets: Vec<IpNet>,
+}
+
+impl SourceAcl {
+    /// Parse CIDRs or bare addresses (treated as /32 or /128).
+    pub fn parse(entries: &[String]) -> Result<Self, String> {
+        let mut nets = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let net = match entry.parse::<IpNet>() {
+                Ok(net) => net,
+                Err(_) => entry
+                    .parse::<IpAddr>()
+                    .map(IpNet::from)
+                    .map_err(|_| format!("source invalide: {}", entry))?,
+            };
+            nets.push(net);
+        }
+        Ok(Self { nets })
+    }
+
+    /// An empty ACL allows everyone.
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        if self.nets.is_empty() {
+            return true;
+        }
+        // Dual-stack listeners report IPv4 clients as ::ffff:a.b.c.d
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            v4 => v4,
+        };
+        self.nets.iter().any(|net| net.contains(&ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_allows_all() {
+        let acl = SourceAcl::parse(&[]).unwrap();
+        assert!(acl.allows("203.0.113.7".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_cidr_and_bare_ip() {
+        let acl = SourceAcl::parse(&["10.0.0.0/24".to_string(), "2001:db8::1".to_string()]).unwrap();
+        assert!(acl.allows("10.0.0.42".parse().unwrap()));
+        assert!(!acl.allows("10.0.1.42".parse().unwrap()));
+        assert!(acl.allows("2001:db8::1".parse().unwrap()));
+        assert!(!acl.allows("2001:db8::2".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_ipv4_mapped_client() {
+        let acl = SourceAcl::parse(&["192.168.1.0/24".to_string()]).unwrap();
+        assert!(acl.allows("::ffff:192.168.1.10".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_invalid_entry() {
+        assert!(SourceAcl::parse(&["10.0.0.0/33".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use crate::acl::SourceAcl;
+
+/// Stream proxy configuration (streams-config.json).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamConfig {
+    #[serde(default)]
+    pub streams: Vec<StreamRule>,
+}
+
+/// Transport protocol of a forwarded stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamProtocol {
+    Tcp,
+    Udp,
+}
+
+impl std::fmt::Display for StreamProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp => write!(f, "tcp"),
+            Self::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+/// A single port forward: listen_address:listen_port → target_host:target_port.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamRule {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    pub protocol: StreamProtocol,
+    #[serde(default = "default_listen_address")]
+    pub listen_address: String,
+    pub listen_port: u16,
+    pub target_host: String,
+    pub target_port: u16,
+    /// Source CIDRs allowed to connect. Empty = everyone.
+    #[serde(default)]
+    pub allowed_sources: Vec<String>,
+    /// Idle timeout for UDP sessions and TCP connections (seconds).
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout_secs: u64,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// UDP only: also serve the cloud relay's public port `listen_port`
+    /// (a `[[udp]]` port of the relay config).
+    #[serde(default)]
+    pub relay: bool,
+}
+
+fn default_listen_address() -> String {
+    "::".to_string()
+}
+fn default_idle_timeout() -> u64 {
+    300
+}
+fn default_enabled() -> bool {
+    true
+}
+
+impl StreamRule {
+    /// Socket address string for the listener (brackets IPv6 literals).
+    pub fn listen_socket(&self) -> String {
+        if self.listen_address.contains(':') {
+            format!("[{}]:{}", self.listen_address, self.listen_port)
+        } else {
+            format!("{}:{}", self.listen_address, self.listen_port)
+        }
+    }
+
+    /// Target address string (host may be a name or an IP literal).
+    pub fn target_socket(&self) -> String {
+        if self.target_host.contains(':') {
+            format!("[{}]:{}", self.target_host, self.target_port)
+        } else {
+            format!("{}:{}", self.target_host, self.target_port)
+        }
+    }
+
+    /// Validate a single rule in isolation.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.trim().is_empty() {
+            return Err("id requis".to_string());
+        }
+        if self.listen_port == 0 {
+            return Err("listen_port invalide".to_string());
+        }
+        if self.target_port == 0 {
+            return Err("target_port invalide".to_string());
+        }
+        if self.target_host.trim().is_empty() {
+            return Err("target_host requis".to_string());
+        }
+        if self.listen_socket().parse::<SocketAddr>().is_err() {
+            return Err(format!("listen_address invalide: {}", self.listen_address));
+        }
+        if self.relay && self.protocol != StreamProtocol::Udp {
+            return Err("relay: UDP uniquement (le TCP passe par le proxy HTTPS)".to_string());
+        }
+        SourceAcl::parse(&self.allowed_sources)?;
+        Ok(())
+    }
+}
+
+impl StreamConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Validate all rules and reject duplicate ids or conflicting listeners.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut ids = HashSet::new();
+        let mut listeners: Vec<(StreamProtocol, SocketAddr)> = Vec::new();
+        for rule in &self.streams {
+            rule.validate().map_err(|e| format!("{}: {}", rule.id, e))?;
+            if !ids.insert(rule.id.as_str()) {
+                return Err(format!("id en double: {}", rule.id));
+            }
+            if !rule.enabled {
+                continue;
+            }
+            let addr: SocketAddr = rule.listen_socket().parse().map_err(|_| {
+                format!(
+                    "{}: listen_address invalide: {}",
+                    rule.id, rule.listen_address
+                )
+            })?;
+            // A wildcard address holds the port on every interface, so it
+            // clashes with any other listener on the same protocol and port.
+            let conflict = listeners.iter().any(|(protocol, other)| {
+                *protocol == rule.protocol
+                    && other.port() == addr.port()
+                    && (other.ip() == addr.ip()
+                        || other.ip().is_unspecified()
+                        || addr.ip().is_unspecified())
+            });
+            if conflict {
+                return Err(format!(
+                    "{}/{} déjà utilisé par une autre règle",
+                    rule.protocol, rule.listen_port
+                ));
+            }
+            listeners.push((rule.protocol, addr));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: &str, protocol: StreamProtocol, port: u16) -> StreamRule {
+        StreamRule {
+            id: id.to_string(),
+            name: String::new(),
+            protocol,
+            listen_address: "::".to_string(),
+            listen_port: port,
+            target_host: "10.0.0.20".to_string(),
+            target_port: 22,
+            allowed_sources: vec![],
+            idle_timeout_secs: 300,
+            enabled: true,
+            relay: false,
+        }
+    }
+
+    #[test]
+    fn test_defaults_from_json() {
+        let json = r#"{"streams":[{"id":"ssh","protocol":"tcp","listen_port":2222,
+            "target_host":"10.0.0.20","target_port":22}]}"#;
+        let config: StreamConfig = serde_json::from_str(json).unwrap();
+        let r = &config.streams[0];
+        assert_eq!(r.listen_address, "::");
+        assert!(r.enabled);
+        assert_eq!(r.idle_timeout_secs, 300);
+        assert_eq!(r.listen_socket(), "[::]:2222");
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_same_port_different_protocol_allowed() {
+        let config = StreamConfig {
+            streams: vec![
+                rule("a", StreamProtocol::Tcp, 25565),
+                rule("b", StreamProtocol::Udp, 25565),
+            ],
+        };
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_conflicting_listeners_rejected() {
+        let config = StreamConfig {
+            streams: vec![
+                rule("a", StreamProtocol::Udp, 51820),
+                rule("b", StreamProtocol::Udp, 51820),
+            ],
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_equivalent_and_wildcard_listeners_rejected() {
+        let mut a = rule("a", StreamProtocol::Udp, 51820);
+        let mut b = rule("b", StreamProtocol::Udp, 51820);
+        b.listen_address = "0::0".to_string();
+        let config = StreamConfig {
+            streams: vec![a.clone(), b.clone()],
+        };
+        assert!(config.validate().is_err());
+
+        a.listen_address = "0.0.0.0".to_string();
+        b.listen_address = "192.168.1.1".to_string();
+        let config = StreamConfig {
+            streams: vec![a.clone(), b.clone()],
+        };
+        assert!(config.validate().is_err());
+
+        a.listen_address = "192.168.1.2".to_string();
+        let config = StreamConfig {
+            streams: vec![a, b],
+        };
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_rule_rejected() {
+        let mut r = rule("a", StreamProtocol::Tcp, 2222);
+        r.allowed_sources = vec!["not-a-cidr".to_string()];
+        assert!(r.validate().is_err());
+
+        let mut r = rule("a", StreamProtocol::Tcp, 2222);
+        r.listen_address = "bogus".to_string();
+        assert!(r.validate().is_err());
+
+        let mut r = rule("a", StreamProtocol::Tcp, 2222);
+        r.relay = true;
+        assert!(r.validate().is_err());
+        r.protocol = StreamProtocol::Udp;
+        assert!(r.validate().is_ok());
+    }
+}
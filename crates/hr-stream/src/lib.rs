@@ -0,0 +1,115 @@
+pub mod acl;
+pub mod config;
+pub mod relay;
+pub mod server;
+pub mod tcp;
+pub mod udp;
+
+pub use config::{StreamConfig, StreamProtocol, StreamRule};
+pub use relay::RelayUdpSessions;
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::watch;
+
+/// Live counters for one stream rule.
+#[derive(Debug, Default)]
+pub struct StreamStats {
+    pub listening: AtomicBool,
+    /// Open TCP connections or live UDP sessions.
+    pub active: AtomicU64,
+    pub total: AtomicU64,
+    /// Connections/datagrams rejected by the source ACL.
+    pub denied: AtomicU64,
+    /// Bytes client → target.
+    pub bytes_in: AtomicU64,
+    /// Bytes target → client.
+    pub bytes_out: AtomicU64,
+    pub last_error: Mutex<Option<String>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamStatsSnapshot {
+    pub listening: bool,
+    pub active: u64,
+    pub total: u64,
+    pub denied: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+impl StreamStats {
+    pub fn snapshot(&self) -> StreamStatsSnapshot {
+        StreamStatsSnapshot {
+            listening: self.listening.load(Ordering::Relaxed),
+            active: self.active.load(Ordering::Relaxed),
+            total: self.total.load(Ordering::Relaxed),
+            denied: self.denied.load(Ordering::Relaxed),
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed),
+            last_error: self.last_error.lock().unwrap().clone(),
+        }
+    }
+
+    pub(crate) fn set_error(&self, error: Option<String>) {
+        *self.last_error.lock().unwrap() = error;
+    }
+}
+
+/// Shared stream proxy handle: the API pushes config, the supervised
+/// `stream-proxy` service reconciles listeners against it.
+pub struct StreamProxy {
+    config: watch::Sender<StreamConfig>,
+    stats: RwLock<HashMap<String, Arc<StreamStats>>>,
+}
+
+impl StreamProxy {
+    pub fn new(config: StreamConfig) -> Self {
+        Self {
+            config: watch::channel(config).0,
+            stats: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Current configuration.
+    pub fn config(&self) -> StreamConfig {
+        self.config.borrow().clone()
+    }
+
+    /// Replace the configuration; running listeners are reconciled.
+    pub fn apply(&self, config: StreamConfig) {
+        self.config.send_replace(config);
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<StreamConfig> {
+        self.config.subscribe()
+    }
+
+    /// Stats snapshot for a rule (None if it never started).
+    pub fn stats(&self, id: &str) -> Option<StreamStatsSnapshot> {
+        self.stats.read().unwrap().get(id).map(|s| s.snapshot())
+    }
+
+    pub(crate) fn stats_entry(&self, id: &str) -> Arc<StreamStats> {
+        self.stats
+            .write()
+            .unwrap()
+            .entry(id.to_string())
+            .or_default()
+            .clone()
+    }
+
+    pub(crate) fn retain_stats(&self, ids: &[&str]) {
+        self.stats
+            .write()
+            .unwrap()
+            .retain(|id, _| ids.contains(&id.as_str()));
+    }
+}
+
+pub type SharedStreamProxy = Arc<StreamProxy>;
@@ -0,0 +1,165 @@
+//! UDP port forwards reached through the cloud relay. Datagrams come out of
+//! the tunnel tagged with a flow id, the relay's public port and the
+//! client; each flow gets its own socket towards the target of the UDP rule
+//! with `relay` set on that port, like a local UDP session.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+use crate::acl::SourceAcl;
+use crate::config::StreamProtocol;
+use crate::{StreamProxy, StreamStats};
+
+struct RelaySession {
+    upstream: Arc<UdpSocket>,
+    /// Milliseconds since `epoch` of the last datagram in either direction.
+    last_activity: Arc<AtomicU64>,
+    reply: JoinHandle<()>,
+    stats: Arc<StreamStats>,
+}
+
+impl Drop for RelaySession {
+    fn drop(&mut self) {
+        self.reply.abort();
+        self.stats.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Flows of one tunnel session. Replies of the targets are sent to
+/// `replies` as `(flow_id, payload)`.
+pub struct RelayUdpSessions {
+    proxy: Arc<StreamProxy>,
+    sessions: HashMap<u32, RelaySession>,
+    replies: mpsc::Sender<(u32, Vec<u8>)>,
+    closed_tx: mpsc::UnboundedSender<u32>,
+    closed_rx: mpsc::UnboundedReceiver<u32>,
+    epoch: Instant,
+}
+
+impl RelayUdpSessions {
+    pub fn new(proxy: Arc<StreamProxy>, replies: mpsc::Sender<(u32, Vec<u8>)>) -> Self {
+        let (closed_tx, closed_rx) = mpsc::unbounded_channel();
+        Self {
+            proxy,
+            sessions: HashMap::new(),
+            replies,
+            closed_tx,
+            closed_rx,
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Forward a datagram of flow `flow_id`. `flow` (relay port and client)
+    /// is needed to open the flow when it is not known yet.
+    pub async fn forward(&mut self, flow_id: u32, flow: Option<(u16, SocketAddr)>, payload: &[u8]) {
+        while let Ok(id) = self.closed_rx.try_recv() {
+            self.sessions.remove(&id);
+        }
+        if !self.sessions.contains_key(&flow_id) {
+            let Some((port, client)) = flow else {
+                return;
+            };
+            match self.open(flow_id, port, client).await {
+                Ok(Some(session)) => {
+                    self.sessions.insert(flow_id, session);
+                }
+                Ok(None) => return,
+                Err(e) => {
+                    debug!("Relay UDP flow {} on port {} failed: {}", client, port, e);
+                    return;
+                }
+            }
+        }
+        let session = &self.sessions[&flow_id];
+        session
+            .last_activity
+            .store(self.epoch.elapsed().as_millis() as u64, Ordering::Relaxed);
+        match session.upstream.send(payload).await {
+            Ok(n) => {
+                session.stats.bytes_in.fetch_add(n as u64, Ordering::Relaxed);
+            }
+            Err(e) => debug!("Relay UDP send to target failed: {}", e),
+        }
+    }
+
+    /// Session towards the target of the relayed rule on `port`; `None`
+    /// when no enabled rule relays it or its ACL refuses `client`.
+    async fn open(&self, flow_id: u32, port: u16, client: SocketAddr) -> Result<Option<RelaySession>> {
+        let config = self.proxy.config();
+        let Some(rule) = config
+            .streams
+            .iter()
+            .find(|r| r.enabled && r.relay && r.protocol == StreamProtocol::Udp && r.listen_port == port)
+        else {
+            return Ok(None);
+        };
+        let stats = self.proxy.stats_entry(&rule.id);
+        let acl = SourceAcl::parse(&rule.allowed_sources).map_err(anyhow::Error::msg)?;
+        if !acl.allows(client.ip()) {
+            stats.denied.fetch_add(1, Ordering::Relaxed);
+            return Ok(None);
+        }
+
+        let target = tokio::net::lookup_host(rule.target_socket())
+            .await?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("cannot resolve {}", rule.target_socket()))?;
+        let bind_addr = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let upstream = Arc::new(UdpSocket::bind(bind_addr).await?);
+        upstream.connect(target).await?;
+        debug!(id = %rule.id, "Relay UDP flow {} from {} → {}", flow_id, client, target);
+
+        stats.total.fetch_add(1, Ordering::Relaxed);
+        stats.active.fetch_add(1, Ordering::Relaxed);
+
+        let epoch = self.epoch;
+        let idle_timeout = Duration::from_secs(rule.idle_timeout_secs.max(1));
+        let last_activity = Arc::new(AtomicU64::new(epoch.elapsed().as_millis() as u64));
+
+        // Reply path: target → tunnel, until the flow has been idle long enough.
+        let reply_upstream = upstream.clone();
+        let reply_activity = last_activity.clone();
+        let reply_stats = stats.clone();
+        let replies = self.replies.clone();
+        let closed_tx = self.closed_tx.clone();
+        let reply = tokio::spawn(async move {
+            let mut buf = vec![0u8; 65535];
+            loop {
+                match tokio::time::timeout(idle_timeout, reply_upstream.recv(&mut buf)).await {
+                    Ok(Ok(len)) => {
+                        reply_activity.store(epoch.elapsed().as_millis() as u64, Ordering::Relaxed);
+                        // A full queue drops the datagram, as a congested link would
+                        if replies.try_send((flow_id, buf[..len].to_vec())).is_ok() {
+                            reply_stats.bytes_out.fetch_add(len as u64, Ordering::Relaxed);
+                        }
+                    }
+                    Ok(Err(_)) => break,
+                    Err(_) => {
+                        let idle_ms = (epoch.elapsed().as_millis() as u64)
+                            .saturating_sub(reply_activity.load(Ordering::Relaxed));
+                        if idle_ms >= idle_timeout.as_millis() as u64 {
+                            break;
+                        }
+                    }
+                }
+            }
+            let _ = closed_tx.send(flow_id);
+        });
+
+        Ok(Some(RelaySession {
+            upstream,
+            last_activity,
+            reply,
+            stats,
+        }))
+    }
+}
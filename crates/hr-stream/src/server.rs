@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::acl::SourceAcl;
+use crate::config::{StreamConfig, StreamProtocol, StreamRule};
+use crate::{tcp, udp, StreamProxy, StreamStats};
+
+/// Delay before retrying a listener that failed (port in use, target down...).
+const LISTENER_RETRY: Duration = Duration::from_secs(5);
+
+/// A running listener; aborted when dropped so that a supervisor restart
+/// or a config change never leaves orphan sockets behind.
+struct RunningStream {
+    rule: StreamRule,
+    handle: JoinHandle<()>,
+}
+
+impl Drop for RunningStream {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Run the stream proxy: keeps one listener task per enabled rule and
+/// reconciles them whenever the configuration changes.
+pub async fn run_stream_proxy(proxy: Arc<StreamProxy>) -> Result<()> {
+    let mut config_rx = proxy.subscribe();
+    let mut running: HashMap<String, RunningStream> = HashMap::new();
+
+    loop {
+        let config = config_rx.borrow_and_update().clone();
+        reconcile(&proxy, &mut running, &config);
+
+        if config_rx.changed().await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+fn reconcile(proxy: &StreamProxy, running: &mut HashMap<String, RunningStream>, config: &StreamConfig) {
+    let wanted: HashMap<&str, &StreamRule> = config
+        .streams
+        .iter()
+        .filter(|r| r.enabled)
+        .map(|r| (r.id.as_str(), r))
+        .collect();
+
+    // Stop listeners that were removed, disabled or modified
+    running.retain(|id, stream| {
+        let keep = wanted.get(id.as_str()).is_some_and(|r| **r == stream.rule);
+        if !keep {
+            info!(id = %id, "Stopping stream listener");
+            proxy.stats_entry(id).listening.store(false, Ordering::Relaxed);
+        }
+        keep
+    });
+
+    for (id, rule) in &wanted {
+        if running.contains_key(*id) {
+            continue;
+        }
+        let acl = match SourceAcl::parse(&rule.allowed_sources) {
+            Ok(acl) => acl,
+            Err(e) => {
+                warn!(id = %id, "Invalid stream ACL: {}", e);
+                proxy.stats_entry(id).set_error(Some(e));
+                continue;
+            }
+        };
+        let stats = proxy.stats_entry(id);
+        let handle = tokio::spawn(run_listener((*rule).clone(), acl, stats));
+        running.insert(
+            id.to_string(),
+            RunningStream {
+                rule: (*rule).clone(),
+                handle,
+            },
+        );
+    }
+
+    let ids: Vec<&str> = config.streams.iter().map(|r| r.id.as_str()).collect();
+    proxy.retain_stats(&ids);
+}
+
+async fn run_listener(rule: StreamRule, acl: SourceAcl, stats: Arc<StreamStats>) {
+    loop {
+        let result = match rule.protocol {
+            StreamProtocol::Tcp => tcp::run_tcp_forwarder(rule.clone(), acl.clone(), stats.clone()).await,
+            StreamProtocol::Udp => udp::run_udp_forwarder(rule.clone(), acl.clone(), stats.clone()).await,
+        };
+        stats.listening.store(false, Ordering::Relaxed);
+        if let Err(e) = result {
+            warn!(id = %rule.id, "Stream {} listener on {} failed: {:#}", rule.protocol, rule.listen_socket(), e);
+            stats.set_error(Some(format!("{e:#}")));
+        }
+        tokio::time::sleep(LISTENER_RETRY).await;
+    }
+}
@@ -0,0 +1,172 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+use crate::acl::SourceAcl;
+use crate::config::StreamRule;
+use crate::StreamStats;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Accept TCP connections on the rule's listener and splice them to the target.
+pub async fn run_tcp_forwarder(
+    rule: StreamRule,
+    acl: SourceAcl,
+    stats: Arc<StreamStats>,
+) -> Result<()> {
+    let listener = TcpListener::bind(rule.listen_socket()).await?;
+    info!(id = %rule.id, "Stream TCP listening on {} → {}", rule.listen_socket(), rule.target_socket());
+    stats.listening.store(true, Ordering::Relaxed);
+    stats.set_error(None);
+
+    loop {
+        let (mut inbound, peer) = match listener.accept().await {
+            Ok(r) => r,
+            Err(e) => {
+                warn!(id = %rule.id, "Stream TCP accept error: {}", e);
+                continue;
+            }
+        };
+
+        if !acl.allows(peer.ip()) {
+            stats.denied.fetch_add(1, Ordering::Relaxed);
+            debug!(id = %rule.id, "Stream TCP connection from {} denied by ACL", peer);
+            continue;
+        }
+
+        let target = rule.target_socket();
+        let idle_timeout = Duration::from_secs(rule.idle_timeout_secs.max(1));
+        let id = rule.id.clone();
+        let stats = stats.clone();
+        tokio::spawn(async move {
+            let mut outbound =
+                match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(&target)).await {
+                    Ok(Ok(s)) => s,
+                    Ok(Err(e)) => {
+                        debug!(id = %id, "Stream TCP connect to {} failed: {}", target, e);
+                        return;
+                    }
+                    Err(_) => {
+                        debug!(id = %id, "Stream TCP connect to {} timed out", target);
+                        return;
+                    }
+                };
+            let _ = outbound.set_nodelay(true);
+
+            stats.total.fetch_add(1, Ordering::Relaxed);
+            stats.active.fetch_add(1, Ordering::Relaxed);
+            let (from_client, from_target, result) =
+                splice(&mut inbound, &mut outbound, idle_timeout).await;
+            stats.active.fetch_sub(1, Ordering::Relaxed);
+            stats.bytes_in.fetch_add(from_client, Ordering::Relaxed);
+            stats.bytes_out.fetch_add(from_target, Ordering::Relaxed);
+
+            match result {
+                Ok(Splice::Closed) => debug!(
+                    id = %id,
+                    "Stream TCP {} closed: {} bytes in, {} bytes out",
+                    peer, from_client, from_target
+                ),
+                Ok(Splice::Idle) => debug!(
+                    id = %id,
+                    "Stream TCP {} idle for {:?}, closed: {} bytes in, {} bytes out",
+                    peer, idle_timeout, from_client, from_target
+                ),
+                Err(e) => debug!(id = %id, "Stream TCP {} IO error: {}", peer, e),
+            }
+        });
+    }
+}
+
+/// How a spliced connection ended.
+enum Splice {
+    /// Both sides shut down their write half.
+    Closed,
+    /// No data in either direction for the idle timeout.
+    Idle,
+}
+
+/// Copy both directions until both sides close, an IO error occurs or the
+/// connection has been idle for `idle_timeout`. Returns the bytes copied
+/// client → target and target → client, whatever the outcome.
+async fn splice(
+    inbound: &mut TcpStream,
+    outbound: &mut TcpStream,
+    idle_timeout: Duration,
+) -> (u64, u64, std::io::Result<Splice>) {
+    let epoch = Instant::now();
+    let last_activity = AtomicU64::new(0);
+    let from_client = AtomicU64::new(0);
+    let from_target = AtomicU64::new(0);
+
+    let (mut client_read, mut client_write) = inbound.split();
+    let (mut target_read, mut target_write) = outbound.split();
+    let copy = async {
+        tokio::try_join!(
+            pipe(
+                &mut client_read,
+                &mut target_write,
+                &from_client,
+                &last_activity,
+                epoch
+            ),
+            pipe(
+                &mut target_read,
+                &mut client_write,
+                &from_target,
+                &last_activity,
+                epoch
+            ),
+        )
+    };
+    let idle = async {
+        loop {
+            let last = Duration::from_millis(last_activity.load(Ordering::Relaxed));
+            let deadline = epoch + last + idle_timeout;
+            if Instant::now() >= deadline {
+                return;
+            }
+            tokio::time::sleep_until(deadline.into()).await;
+        }
+    };
+
+    let result = tokio::select! {
+        r = copy => r.map(|_| Splice::Closed),
+        _ = idle => Ok(Splice::Idle),
+    };
+    (
+        from_client.load(Ordering::Relaxed),
+        from_target.load(Ordering::Relaxed),
+        result,
+    )
+}
+
+/// One direction of [`splice`]: forwards until EOF, then shuts down the
+/// write half so the other side sees the close.
+async fn pipe<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    copied: &AtomicU64,
+    last_activity: &AtomicU64,
+    epoch: Instant,
+) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; 16 * 1024];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            return writer.shutdown().await;
+        }
+        last_activity.store(epoch.elapsed().as_millis() as u64, Ordering::Relaxed);
+        writer.write_all(&buf[..n]).await?;
+        copied.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::acl::SourceAcl;
+use crate::config::StreamRule;
+use crate::StreamStats;
+
+/// Maximum concurrent sessions per rule; datagrams from new peers past it are dropped.
+const MAX_SESSIONS: usize = 4096;
+
+/// One client ↔ target mapping: a dedicated connected socket towards the target.
+/// Dropping the session stops its reply task, so stopping the forwarder
+/// releases the listening socket immediately.
+struct UdpSession {
+    upstream: Arc<UdpSocket>,
+    /// Milliseconds since `epoch` of the last datagram in either direction.
+    last_activity: Arc<AtomicU64>,
+    reply: JoinHandle<()>,
+    stats: Arc<StreamStats>,
+}
+
+impl Drop for UdpSession {
+    fn drop(&mut self) {
+        self.reply.abort();
+        self.stats.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Forward UDP datagrams for a rule, keeping one upstream socket per client
+/// so replies are routed back to the right peer (NAT-style session table).
+pub async fn run_udp_forwarder(
+    rule: StreamRule,
+    acl: SourceAcl,
+    stats: Arc<StreamStats>,
+) -> Result<()> {
+    // Resolved once per listener: a failure is retried by the server loop.
+    let target = tokio::net::lookup_host(rule.target_socket())
+        .await?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("cannot resolve {}", rule.target_socket()))?;
+    let socket = Arc::new(UdpSocket::bind(rule.listen_socket()).await?);
+    info!(id = %rule.id, "Stream UDP listening on {} → {}", rule.listen_socket(), rule.target_socket());
+    stats.listening.store(true, Ordering::Relaxed);
+    stats.set_error(None);
+
+    let idle_timeout = Duration::from_secs(rule.idle_timeout_secs.max(1));
+    let epoch = Instant::now();
+    let mut sessions: HashMap<SocketAddr, UdpSession> = HashMap::new();
+    let (closed_tx, mut closed_rx) = mpsc::unbounded_channel::<SocketAddr>();
+    let mut buf = vec![0u8; 65535];
+
+    loop {
+        tokio::select! {
+            result = socket.recv_from(&mut buf) => {
+                let (len, peer) = match result {
+                    Ok(r) => r,
+                    Err(e) => {
+                        warn!(id = %rule.id, "Stream UDP recv error: {}", e);
+                        continue;
+                    }
+                };
+
+                if !acl.allows(peer.ip()) {
+                    stats.denied.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+
+                let full = sessions.len() >= MAX_SESSIONS;
+                let session = match sessions.entry(peer) {
+                    Entry::Occupied(e) => e.into_mut(),
+                    Entry::Vacant(_) if full => {
+                        debug!(id = %rule.id, "Stream UDP session limit reached, dropping datagram from {}", peer);
+                        continue;
+                    }
+                    Entry::Vacant(v) => {
+                        match open_session(target, peer, &socket, &stats, &closed_tx, epoch, idle_timeout).await {
+                            Ok(s) => v.insert(s),
+                            Err(e) => {
+                                debug!(id = %rule.id, "Stream UDP session for {} failed: {}", peer, e);
+                                continue;
+                            }
+                        }
+                    }
+                };
+                session.last_activity.store(epoch.elapsed().as_millis() as u64, Ordering::Relaxed);
+                match session.upstream.send(&buf[..len]).await {
+                    Ok(n) => {
+                        stats.bytes_in.fetch_add(n as u64, Ordering::Relaxed);
+                    }
+                    Err(e) => debug!(id = %rule.id, "Stream UDP send to target failed: {}", e),
+                }
+            }
+            Some(peer) = closed_rx.recv() => {
+                if sessions.remove(&peer).is_some() {
+                    debug!(id = %rule.id, "Stream UDP session {} expired", peer);
+                }
+            }
+        }
+    }
+}
+
+async fn open_session(
+    target: SocketAddr,
+    peer: SocketAddr,
+    listener: &Arc<UdpSocket>,
+    stats: &Arc<StreamStats>,
+    closed_tx: &mpsc::UnboundedSender<SocketAddr>,
+    epoch: Instant,
+    idle_timeout: Duration,
+) -> Result<UdpSession> {
+    let bind_addr = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let upstream = Arc::new(UdpSocket::bind(bind_addr).await?);
+    upstream.connect(target).await?;
+
+    stats.total.fetch_add(1, Ordering::Relaxed);
+    stats.active.fetch_add(1, Ordering::Relaxed);
+
+    let last_activity = Arc::new(AtomicU64::new(epoch.elapsed().as_millis() as u64));
+
+    // Reply path: target → client, until the session has been idle long enough.
+    let reply_upstream = upstream.clone();
+    let reply_listener = listener.clone();
+    let reply_activity = last_activity.clone();
+    let reply_stats = stats.clone();
+    let closed_tx = closed_tx.clone();
+    let reply = tokio::spawn(async move {
+        let mut buf = vec![0u8; 65535];
+        loop {
+            match tokio::time::timeout(idle_timeout, reply_upstream.recv(&mut buf)).await {
+                Ok(Ok(len)) => {
+                    reply_activity.store(epoch.elapsed().as_millis() as u64, Ordering::Relaxed);
+                    if let Ok(n) = reply_listener.send_to(&buf[..len], peer).await {
+                        reply_stats.bytes_out.fetch_add(n as u64, Ordering::Relaxed);
+                    }
+                }
+                Ok(Err(_)) => break,
+                Err(_) => {
+                    let idle_ms = (epoch.elapsed().as_millis() as u64)
+                        .saturating_sub(reply_activity.load(Ordering::Relaxed));
+                    if idle_ms >= idle_timeout.as_millis() as u64 {
+                        break;
+                    }
+                }
+            }
+        }
+        let _ = closed_tx.send(peer);
+    });
+
+    Ok(UdpSession {
+        upstream,
+        last_activity,
+        reply,
+        stats: stats.clone(),
+    })
+}
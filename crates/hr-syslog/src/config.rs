@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Syslog receiver configuration (syslog-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyslogConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_bind")]
+    pub bind_address: String,
+    /// Plain syslog port, UDP and TCP (RFC 5426 / RFC 6587).
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_true")]
+    pub udp: bool,
+    #[serde(default = "default_true")]
+    pub tcp: bool,
+    /// Syslog over TLS (RFC 5425), needs `tls_cert_path` / `tls_key_path`.
+    #[serde(default)]
+    pub tls: bool,
+    #[serde(default = "default_tls_port")]
+    pub tls_port: u16,
+    #[serde(default)]
+    pub tls_cert_path: Option<String>,
+    #[serde(default)]
+    pub tls_key_path: Option<String>,
+    /// Messages older than this are purged.
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u32,
+    /// Upper bound on stored messages, oldest purged first.
+    #[serde(default = "default_max_messages")]
+    pub max_messages: u64,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_bind() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_port() -> u16 {
+    514
+}
+
+fn default_tls_port() -> u16 {
+    6514
+}
+
+fn default_retention_days() -> u32 {
+    14
+}
+
+fn default_max_messages() -> u64 {
+    1_000_000
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl SyslogConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bind_address.parse::<std::net::IpAddr>().is_err() {
+            return Err(format!("adresse d'écoute invalide: {}", self.bind_address));
+        }
+        if self.port == 0 || self.tls_port == 0 {
+            return Err("port invalide".to_string());
+        }
+        if self.tls && self.tcp && self.tls_port == self.port {
+            return Err("le port TLS doit différer du port TCP".to_string());
+        }
+        if self.tls && (self.tls_cert_path.is_none() || self.tls_key_path.is_none()) {
+            return Err("certificat et clé requis pour TLS".to_string());
+        }
+        if self.retention_days == 0 {
+            return Err("rétention d'au moins un jour requise".to_string());
+        }
+        if self.max_messages == 0 {
+            return Err("nombre maximal de messages invalide".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_and_validate() {
+        let config = SyslogConfig::default();
+        assert!(!config.enabled);
+        assert_eq!(config.port, 514);
+        assert!(config.udp && config.tcp && !config.tls);
+        assert!(config.validate().is_ok());
+
+        let mut tls = config.clone();
+        tls.tls = true;
+        assert!(tls.validate().is_err());
+        tls.tls_cert_path = Some("/etc/ssl/syslog.crt".to_string());
+        tls.tls_key_path = Some("/etc/ssl/syslog.key".to_string());
+        assert!(tls.validate().is_ok());
+        tls.tls_port = 514;
+        assert!(tls.validate().is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! Syslog receiver for network gear (switches, APs, NAS): UDP, TCP and TLS
+//! listeners, SQLite storage with retention, and senders matched to the
+//! DHCP inventory by source IP.
+
+pub mod config;
+pub mod parser;
+pub mod server;
+pub mod store;
+
+pub use config::SyslogConfig;
+pub use store::{LogQuery, LogStore, SourceSummary, StoredMessage};
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyslogStatus {
+    pub running: bool,
+    /// Active listeners, e.g. `udp/0.0.0.0:514`.
+    pub listeners: Vec<String>,
+    pub received: u64,
+    /// Messages lost because the writer couldn't keep up.
+    pub dropped: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    pub received: AtomicU64,
+    pub dropped: AtomicU64,
+}
+
+/// Shared syslog handle: the API pushes config and queries the store, the
+/// supervised `syslog` service rebinds its listeners against it.
+pub struct Syslog {
+    config: watch::Sender<SyslogConfig>,
+    status: RwLock<SyslogStatus>,
+    pub(crate) counters: Counters,
+    store: Arc<LogStore>,
+}
+
+impl Syslog {
+    pub fn new(config: SyslogConfig, store: LogStore) -> Self {
+        Self {
+            config: watch::channel(config).0,
+            status: RwLock::new(SyslogStatus::default()),
+            counters: Counters::default(),
+            store: Arc::new(store),
+        }
+    }
+
+    /// Current configuration.
+    pub fn config(&self) -> SyslogConfig {
+        self.config.borrow().clone()
+    }
+
+    /// Replace the configuration; listeners are rebound.
+    pub fn apply(&self, config: SyslogConfig) {
+        self.config.send_replace(config);
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<SyslogConfig> {
+        self.config.subscribe()
+    }
+
+    pub fn store(&self) -> Arc<LogStore> {
+        self.store.clone()
+    }
+
+    pub fn status(&self) -> SyslogStatus {
+        let mut status = self.status.read().unwrap().clone();
+        status.received = self.counters.received.load(Ordering::Relaxed);
+        status.dropped = self.counters.dropped.load(Ordering::Relaxed);
+        status
+    }
+
+    pub(crate) fn set_status(&self, running: bool, listeners: Vec<String>, error: Option<String>) {
+        let mut status = self.status.write().unwrap();
+        status.running = running;
+        status.listeners = listeners;
+        status.last_error = error;
+    }
+}
+
+pub type SharedSyslog = Arc<Syslog>;
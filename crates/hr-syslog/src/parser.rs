@@ -0,0 +1,227 @@
+//! Syslog message parsing (RFC 5424 and the BSD format of RFC 3164) and
+//! TCP framing (RFC 6587).
+
+use serde::Serialize;
+
+/// Longest message kept; the rest is cut.
+pub const MAX_MESSAGE_LEN: usize = 8192;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedMessage {
+    pub facility: u8,
+    /// 0 (emergency) to 7 (debug).
+    pub severity: u8,
+    /// Timestamp as sent by the device (not normalized: BSD stamps have no year).
+    pub timestamp: Option<String>,
+    pub hostname: Option<String>,
+    pub app_name: Option<String>,
+    pub proc_id: Option<String>,
+    pub msg_id: Option<String>,
+    pub message: String,
+}
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// `-` is the RFC 5424 nil value.
+fn nil(field: &str) -> Option<String> {
+    (field != "-" && !field.is_empty()).then(|| field.to_string())
+}
+
+/// Split off the first space-separated field.
+fn field(s: &str) -> (&str, &str) {
+    s.split_once(' ').unwrap_or((s, ""))
+}
+
+/// Parse a datagram or frame. Never fails: anything unrecognized ends up
+/// in `message` with the RFC 3164 default priority (user.notice).
+pub fn parse(raw: &[u8]) -> ParsedMessage {
+    let raw = &raw[..raw.len().min(MAX_MESSAGE_LEN)];
+    let text = String::from_utf8_lossy(raw);
+    let text = text.trim_end_matches(['\r', '\n', '\0']).trim_start_matches('\u{feff}');
+
+    let (pri, rest) = match parse_pri(text) {
+        Some((pri, rest)) => (pri, rest),
+        None => (13, text),
+    };
+    let mut msg = ParsedMessage {
+        facility: (pri >> 3) as u8,
+        severity: (pri & 7) as u8,
+        timestamp: None,
+        hostname: None,
+        app_name: None,
+        proc_id: None,
+        msg_id: None,
+        message: String::new(),
+    };
+
+    if let Some(rest) = rest.strip_prefix("1 ") {
+        parse_5424(rest, &mut msg);
+    } else {
+        parse_3164(rest, &mut msg);
+    }
+    msg
+}
+
+fn parse_pri(text: &str) -> Option<(u16, &str)> {
+    let rest = text.strip_prefix('<')?;
+    let end = rest.find('>')?;
+    if end == 0 || end > 3 {
+        return None;
+    }
+    let pri: u16 = rest[..end].parse().ok()?;
+    (pri <= 191).then(|| (pri, &rest[end + 1..]))
+}
+
+/// `TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA [MSG]`
+fn parse_5424(rest: &str, msg: &mut ParsedMessage) {
+    let (timestamp, rest) = field(rest);
+    let (hostname, rest) = field(rest);
+    let (app_name, rest) = field(rest);
+    let (proc_id, rest) = field(rest);
+    let (msg_id, rest) = field(rest);
+    msg.timestamp = nil(timestamp);
+    msg.hostname = nil(hostname);
+    msg.app_name = nil(app_name);
+    msg.proc_id = nil(proc_id);
+    msg.msg_id = nil(msg_id);
+    msg.message = skip_structured_data(rest).to_string();
+}
+
+/// Skip `-` or `[id k="v"]...` elements (values may contain escaped `]`).
+fn skip_structured_data(rest: &str) -> &str {
+    if let Some(after) = rest.strip_prefix('-') {
+        return after.strip_prefix(' ').unwrap_or(after);
+    }
+    let bytes = rest.as_bytes();
+    let mut i = 0;
+    while bytes.get(i) == Some(&b'[') {
+        let mut in_value = false;
+        i += 1;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' if in_value => i += 1,
+                b'"' => in_value = !in_value,
+                b']' if !in_value => break,
+                _ => {}
+            }
+            i += 1;
+        }
+        i += 1;
+    }
+    let rest = rest.get(i.min(rest.len())..).unwrap_or("");
+    rest.strip_prefix(' ').unwrap_or(rest)
+}
+
+/// `Mmm dd hh:mm:ss HOSTNAME TAG[PID]: MSG`, with the many variations
+/// seen on network gear (no hostname, no timestamp).
+fn parse_3164(rest: &str, msg: &mut ParsedMessage) {
+    let mut rest = rest.trim_start();
+    if let Some(stamp) = rest.get(..15)
+        && stamp.get(..3).is_some_and(|month| MONTHS.contains(&month))
+        && stamp.as_bytes()[9] == b':'
+    {
+        msg.timestamp = Some(stamp.to_string());
+        rest = rest[15..].trim_start();
+    }
+
+    // A hostname is a single token not ending like a tag
+    let (first, after) = field(rest);
+    if !first.is_empty() && !first.ends_with(':') && !first.contains('[') && !after.is_empty() {
+        let (next, _) = field(after);
+        if next.ends_with(':') || next.contains('[') {
+            msg.hostname = Some(first.to_string());
+            rest = after;
+        }
+    }
+
+    let (tag, after) = field(rest);
+    if let Some(tag) = tag.strip_suffix(':') {
+        let (name, pid) = match tag.split_once('[') {
+            Some((name, pid)) => (name, pid.strip_suffix(']')),
+            None => (tag, None),
+        };
+        if !name.is_empty() {
+            msg.app_name = Some(name.to_string());
+            msg.proc_id = pid.map(String::from);
+            rest = after;
+        }
+    }
+    msg.message = rest.to_string();
+}
+
+/// Extract the next frame of a TCP stream: octet-counted (`LEN SP MSG`) or
+/// newline-terminated. `None` until a whole frame is buffered.
+pub fn next_frame(buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let digits = buf.iter().take_while(|b| b.is_ascii_digit()).count();
+    if digits > 0 && buf.get(digits) == Some(&b' ') {
+        let len: usize = std::str::from_utf8(&buf[..digits]).ok()?.parse().ok()?;
+        let end = digits + 1 + len;
+        if buf.len() < end {
+            return None;
+        }
+        let frame = buf[digits + 1..end].to_vec();
+        buf.drain(..end);
+        return Some(frame);
+    }
+    let end = buf.iter().position(|&b| b == b'\n')?;
+    let frame = buf[..end].to_vec();
+    buf.drain(..=end);
+    Some(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc5424() {
+        let msg = parse(
+            br#"<165>1 2026-10-17T22:14:15.003Z ap-salon hostapd 1203 STA [meta sequenceId="1" note="a\]b"] wlan0: STA 3c:22:fb:01:02:03 associated"#,
+        );
+        assert_eq!(msg.facility, 20);
+        assert_eq!(msg.severity, 5);
+        assert_eq!(msg.timestamp.as_deref(), Some("2026-10-17T22:14:15.003Z"));
+        assert_eq!(msg.hostname.as_deref(), Some("ap-salon"));
+        assert_eq!(msg.app_name.as_deref(), Some("hostapd"));
+        assert_eq!(msg.proc_id.as_deref(), Some("1203"));
+        assert_eq!(msg.msg_id.as_deref(), Some("STA"));
+        assert_eq!(msg.message, "wlan0: STA 3c:22:fb:01:02:03 associated");
+
+        let msg = parse(b"<34>1 - - - - - -");
+        assert_eq!((msg.facility, msg.severity), (4, 2));
+        assert!(msg.hostname.is_none() && msg.message.is_empty());
+    }
+
+    #[test]
+    fn test_rfc3164() {
+        let msg = parse(b"<14>Oct 17 22:14:15 switch-garage kernel: port 3 link up\n");
+        assert_eq!((msg.facility, msg.severity), (1, 6));
+        assert_eq!(msg.timestamp.as_deref(), Some("Oct 17 22:14:15"));
+        assert_eq!(msg.hostname.as_deref(), Some("switch-garage"));
+        assert_eq!(msg.app_name.as_deref(), Some("kernel"));
+        assert_eq!(msg.message, "port 3 link up");
+
+        // No hostname, tag with pid
+        let msg = parse(b"<30>Oct  7 01:02:03 dnsmasq[812]: query A example.com");
+        assert!(msg.hostname.is_none());
+        assert_eq!(msg.app_name.as_deref(), Some("dnsmasq"));
+        assert_eq!(msg.proc_id.as_deref(), Some("812"));
+
+        // No priority, free text
+        let msg = parse(b"link flapping on port 7");
+        assert_eq!((msg.facility, msg.severity), (1, 5));
+        assert_eq!(msg.message, "link flapping on port 7");
+    }
+
+    #[test]
+    fn test_framing() {
+        let mut buf = b"11 <13>hello 1<13>plain line\n7 <13>ab".to_vec();
+        assert_eq!(next_frame(&mut buf).unwrap(), b"<13>hello 1");
+        assert_eq!(next_frame(&mut buf).unwrap(), b"<13>plain line");
+        assert!(next_frame(&mut buf).is_none());
+        buf.extend_from_slice(b"c");
+        assert_eq!(next_frame(&mut buf).unwrap(), b"<13>abc");
+        assert!(buf.is_empty());
+    }
+}
@@ -0,0 +1,284 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use hr_dhcp::SharedDhcpState;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, warn};
+
+use crate::config::SyslogConfig;
+use crate::parser::{self, MAX_MESSAGE_LEN};
+use crate::store::LogEntry;
+use crate::Syslog;
+
+/// Delay before rebinding after a listener failure.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+/// Messages waiting for the writer; beyond that they are dropped.
+const QUEUE_SIZE: usize = 10_000;
+/// Largest batch written in one transaction.
+const BATCH_SIZE: usize = 500;
+const RETENTION_INTERVAL: Duration = Duration::from_secs(3600);
+/// TCP connections idle this long are closed.
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// A datagram or frame with its sender.
+struct Incoming {
+    source: IpAddr,
+    received_at: i64,
+    raw: Vec<u8>,
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+/// Run the syslog receiver: listeners are rebound whenever the configuration
+/// changes; storage and retention keep running across changes.
+pub async fn run_syslog(syslog: Arc<Syslog>, dhcp: SharedDhcpState) -> Result<()> {
+    let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+    let mut config_rx = syslog.subscribe();
+
+    let listeners = async {
+        loop {
+            let config = config_rx.borrow_and_update().clone();
+            tokio::select! {
+                _ = run_config(syslog.clone(), config, tx.clone()) => {}
+                changed = config_rx.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = listeners => Ok(()),
+        _ = writer(syslog.clone(), dhcp, rx) => Ok(()),
+        _ = retention(syslog.clone()) => Ok(()),
+    }
+}
+
+/// Serve one configuration until it is replaced (never returns on its own).
+async fn run_config(syslog: Arc<Syslog>, config: SyslogConfig, tx: mpsc::Sender<Incoming>) {
+    if !config.enabled || !(config.udp || config.tcp || config.tls) {
+        syslog.set_status(false, Vec::new(), None);
+        return std::future::pending().await;
+    }
+
+    loop {
+        match start(&syslog, &config, &tx).await {
+            Ok((mut tasks, names)) => {
+                info!("Syslog receiver listening on {}", names.join(", "));
+                syslog.set_status(true, names, None);
+                // Any listener failing rebinds all of them
+                if let Some(Ok(Err(e))) = tasks.join_next().await {
+                    warn!("Syslog listener stopped: {:#}", e);
+                    syslog.set_status(false, Vec::new(), Some(format!("{e:#}")));
+                }
+            }
+            Err(e) => {
+                warn!("Syslog receiver failed to start: {:#}", e);
+                syslog.set_status(false, Vec::new(), Some(format!("{e:#}")));
+            }
+        }
+        tokio::time::sleep(RETRY_DELAY).await;
+    }
+}
+
+async fn start(
+    syslog: &Arc<Syslog>,
+    config: &SyslogConfig,
+    tx: &mpsc::Sender<Incoming>,
+) -> Result<(JoinSet<Result<()>>, Vec<String>)> {
+    let ip: IpAddr = config.bind_address.parse().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    let mut tasks = JoinSet::new();
+    let mut names = Vec::new();
+
+    if config.udp {
+        let addr = SocketAddr::new(ip, config.port);
+        let socket = UdpSocket::bind(addr).await.with_context(|| format!("UDP bind {}", addr))?;
+        tasks.spawn(udp_loop(syslog.clone(), socket, tx.clone()));
+        names.push(format!("udp/{}", addr));
+    }
+    if config.tcp {
+        let addr = SocketAddr::new(ip, config.port);
+        let listener = TcpListener::bind(addr).await.with_context(|| format!("TCP bind {}", addr))?;
+        tasks.spawn(tcp_loop(syslog.clone(), listener, None, tx.clone()));
+        names.push(format!("tcp/{}", addr));
+    }
+    if config.tls {
+        let acceptor = tls_acceptor(config)?;
+        let addr = SocketAddr::new(ip, config.tls_port);
+        let listener = TcpListener::bind(addr).await.with_context(|| format!("TLS bind {}", addr))?;
+        tasks.spawn(tcp_loop(syslog.clone(), listener, Some(acceptor), tx.clone()));
+        names.push(format!("tls/{}", addr));
+    }
+    Ok((tasks, names))
+}
+
+fn tls_acceptor(config: &SyslogConfig) -> Result<TlsAcceptor> {
+    let cert_path = config.tls_cert_path.as_deref().context("TLS certificate not configured")?;
+    let key_path = config.tls_key_path.as_deref().context("TLS key not configured")?;
+    let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(
+        std::fs::File::open(cert_path).with_context(|| format!("open {}", cert_path))?,
+    ))
+    .collect::<Result<Vec<_>, _>>()
+    .context("Failed to parse certificates")?;
+    let key = rustls_pemfile::private_key(&mut std::io::BufReader::new(
+        std::fs::File::open(key_path).with_context(|| format!("open {}", key_path))?,
+    ))
+    .context("Failed to parse private key")?
+    .context("No private key found")?;
+    let server_config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Queue a message for the writer, counting it as dropped when full.
+fn enqueue(syslog: &Syslog, tx: &mpsc::Sender<Incoming>, source: IpAddr, raw: Vec<u8>) {
+    syslog.counters.received.fetch_add(1, Ordering::Relaxed);
+    let incoming = Incoming {
+        source,
+        received_at: now_ms(),
+        raw,
+    };
+    if tx.try_send(incoming).is_err() {
+        syslog.counters.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+async fn udp_loop(syslog: Arc<Syslog>, socket: UdpSocket, tx: mpsc::Sender<Incoming>) -> Result<()> {
+    let mut buf = vec![0u8; 65536];
+    loop {
+        let (len, src) = socket.recv_from(&mut buf).await?;
+        enqueue(&syslog, &tx, src.ip().to_canonical(), buf[..len].to_vec());
+    }
+}
+
+async fn tcp_loop(
+    syslog: Arc<Syslog>,
+    listener: TcpListener,
+    tls: Option<TlsAcceptor>,
+    tx: mpsc::Sender<Incoming>,
+) -> Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let syslog = syslog.clone();
+        let tx = tx.clone();
+        let tls = tls.clone();
+        tokio::spawn(async move {
+            let source = peer.ip().to_canonical();
+            let result = match tls {
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => read_frames(&syslog, stream, source, &tx).await,
+                    Err(e) => Err(e.into()),
+                },
+                None => read_frames(&syslog, stream, source, &tx).await,
+            };
+            if let Err(e) = result {
+                debug!("Syslog connection from {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn read_frames<S: AsyncRead + Unpin>(
+    syslog: &Syslog,
+    mut stream: S,
+    source: IpAddr,
+    tx: &mpsc::Sender<Incoming>,
+) -> Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = vec![0u8; 16384];
+    loop {
+        let n = tokio::time::timeout(TCP_IDLE_TIMEOUT, stream.read(&mut chunk))
+            .await
+            .context("idle timeout")??;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        while let Some(frame) = parser::next_frame(&mut buf) {
+            if !frame.is_empty() {
+                enqueue(syslog, tx, source, frame);
+            }
+        }
+        // A sender not framing its messages: don't buffer forever
+        if buf.len() > MAX_MESSAGE_LEN * 8 {
+            anyhow::bail!("unframed message too long");
+        }
+    }
+}
+
+/// Parse, match senders to the DHCP inventory and store in batches.
+async fn writer(syslog: Arc<Syslog>, dhcp: SharedDhcpState, mut rx: mpsc::Receiver<Incoming>) {
+    let store = syslog.store();
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while rx.recv_many(&mut batch, BATCH_SIZE).await > 0 {
+        let entries: Vec<LogEntry> = {
+            let state = dhcp.read().await;
+            batch
+                .drain(..)
+                .map(|incoming| {
+                    let (device, mac) = match incoming.source {
+                        IpAddr::V4(ip) => lookup_device(&state, ip),
+                        IpAddr::V6(_) => (None, None),
+                    };
+                    LogEntry {
+                        received_at: incoming.received_at,
+                        source_ip: incoming.source.to_string(),
+                        device,
+                        mac,
+                        message: parser::parse(&incoming.raw),
+                    }
+                })
+                .collect()
+        };
+        let store = store.clone();
+        match tokio::task::spawn_blocking(move || store.insert_batch(&entries)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("Failed to store syslog messages: {}", e),
+            Err(e) => warn!("Syslog writer task failed: {}", e),
+        }
+    }
+}
+
+/// Device name and MAC of an address: active lease first, then static
+/// reservation.
+fn lookup_device(state: &hr_dhcp::DhcpState, ip: Ipv4Addr) -> (Option<String>, Option<String>) {
+    if let Some(lease) = state.lease_store.get_lease(ip) {
+        return (lease.hostname.clone(), Some(lease.mac.clone()));
+    }
+    let ip = ip.to_string();
+    match state.config.static_leases.iter().find(|l| l.ip == ip) {
+        Some(l) => ((!l.hostname.is_empty()).then(|| l.hostname.clone()), Some(l.mac.clone())),
+        None => (None, None),
+    }
+}
+
+async fn retention(syslog: Arc<Syslog>) {
+    let mut interval = tokio::time::interval(RETENTION_INTERVAL);
+    loop {
+        interval.tick().await;
+        let config = syslog.config();
+        let cutoff = now_ms() - config.retention_days as i64 * 86_400_000;
+        let store = syslog.store();
+        match tokio::task::spawn_blocking(move || store.purge(cutoff, config.max_messages)).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(n)) => info!("Syslog retention: purged {} messages", n),
+            Ok(Err(e)) => warn!("Syslog retention failed: {}", e),
+            Err(e) => warn!("Syslog retention task failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,317 @@
+//! SQLite storage of received messages (`syslog.db`).
+
+use rusqlite::{params, params_from_iter, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::parser::ParsedMessage;
+
+/// Default and maximum page size of `query`.
+const DEFAULT_LIMIT: u32 = 200;
+const MAX_LIMIT: u32 = 1000;
+
+/// A message as received, before storage.
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub received_at: i64,
+    pub source_ip: String,
+    /// Device name from the DHCP inventory (lease or static reservation).
+    pub device: Option<String>,
+    pub mac: Option<String>,
+    pub message: ParsedMessage,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredMessage {
+    pub id: i64,
+    pub received_at: i64,
+    pub source_ip: String,
+    pub device: Option<String>,
+    pub mac: Option<String>,
+    #[serde(flatten)]
+    pub message: ParsedMessage,
+}
+
+/// Filters of `GET /api/syslog/messages`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LogQuery {
+    /// Source IP.
+    pub source: Option<String>,
+    /// Inventory device name or the hostname sent by the device.
+    pub device: Option<String>,
+    /// Most verbose severity returned (3 = errors and worse).
+    pub severity: Option<u8>,
+    pub app: Option<String>,
+    /// Substring of the message.
+    pub q: Option<String>,
+    /// Epoch milliseconds.
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    /// Only messages older than this id (pagination).
+    pub before: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+/// One sending device with its message count.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceSummary {
+    pub source_ip: String,
+    pub device: Option<String>,
+    pub mac: Option<String>,
+    pub hostname: Option<String>,
+    pub count: u64,
+    pub last_seen: i64,
+}
+
+pub struct LogStore {
+    conn: Mutex<Connection>,
+}
+
+impl LogStore {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                received_at INTEGER NOT NULL,
+                source_ip TEXT NOT NULL,
+                device TEXT,
+                mac TEXT,
+                facility INTEGER NOT NULL,
+                severity INTEGER NOT NULL,
+                timestamp TEXT,
+                hostname TEXT,
+                app_name TEXT,
+                proc_id TEXT,
+                msg_id TEXT,
+                message TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_messages_received_at ON messages(received_at);
+            CREATE INDEX IF NOT EXISTS idx_messages_source_ip ON messages(source_ip);
+            CREATE INDEX IF NOT EXISTS idx_messages_device ON messages(device);",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn insert_batch(&self, entries: &[LogEntry]) -> anyhow::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO messages (received_at, source_ip, device, mac, facility, severity,
+                    timestamp, hostname, app_name, proc_id, msg_id, message)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            )?;
+            for e in entries {
+                let m = &e.message;
+                stmt.execute(params![
+                    e.received_at,
+                    e.source_ip,
+                    e.device,
+                    e.mac,
+                    m.facility,
+                    m.severity,
+                    m.timestamp,
+                    m.hostname,
+                    m.app_name,
+                    m.proc_id,
+                    m.msg_id,
+                    m.message,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Newest first.
+    pub fn query(&self, query: &LogQuery) -> anyhow::Result<Vec<StoredMessage>> {
+        let mut clauses = Vec::new();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+        let mut bind = |value: rusqlite::types::Value| {
+            values.push(value);
+            format!("?{}", values.len())
+        };
+        if let Some(source) = &query.source {
+            clauses.push(format!("source_ip = {}", bind(source.clone().into())));
+        }
+        if let Some(device) = &query.device {
+            let (a, b) = (bind(device.clone().into()), bind(device.clone().into()));
+            clauses.push(format!("(device = {} OR hostname = {})", a, b));
+        }
+        if let Some(severity) = query.severity {
+            clauses.push(format!("severity <= {}", bind((severity as i64).into())));
+        }
+        if let Some(app) = &query.app {
+            clauses.push(format!("app_name = {}", bind(app.clone().into())));
+        }
+        if let Some(q) = &query.q {
+            let escaped = q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            clauses.push(format!("message LIKE {} ESCAPE '\\'", bind(format!("%{}%", escaped).into())));
+        }
+        if let Some(since) = query.since {
+            clauses.push(format!("received_at >= {}", bind(since.into())));
+        }
+        if let Some(until) = query.until {
+            clauses.push(format!("received_at <= {}", bind(until.into())));
+        }
+        if let Some(before) = query.before {
+            clauses.push(format!("id < {}", bind(before.into())));
+        }
+
+        let filter = if clauses.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", clauses.join(" AND "))
+        };
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let sql = format!(
+            "SELECT id, received_at, source_ip, device, mac, facility, severity, timestamp,
+                hostname, app_name, proc_id, msg_id, message
+             FROM messages {} ORDER BY id DESC LIMIT {}",
+            filter, limit
+        );
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(values), |row| {
+            Ok(StoredMessage {
+                id: row.get(0)?,
+                received_at: row.get(1)?,
+                source_ip: row.get(2)?,
+                device: row.get(3)?,
+                mac: row.get(4)?,
+                message: ParsedMessage {
+                    facility: row.get(5)?,
+                    severity: row.get(6)?,
+                    timestamp: row.get(7)?,
+                    hostname: row.get(8)?,
+                    app_name: row.get(9)?,
+                    proc_id: row.get(10)?,
+                    msg_id: row.get(11)?,
+                    message: row.get(12)?,
+                },
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Devices that sent messages, most recent first.
+    pub fn sources(&self) -> anyhow::Result<Vec<SourceSummary>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT source_ip, device, mac, hostname, COUNT(*), MAX(received_at)
+             FROM messages GROUP BY source_ip ORDER BY MAX(received_at) DESC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(SourceSummary {
+                source_ip: row.get(0)?,
+                device: row.get(1)?,
+                mac: row.get(2)?,
+                hostname: row.get(3)?,
+                count: row.get(4)?,
+                last_seen: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    pub fn count(&self) -> anyhow::Result<u64> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0))?)
+    }
+
+    /// Delete messages received before `older_than` (epoch ms), then the
+    /// oldest ones beyond `max_messages`. Returns the number deleted.
+    pub fn purge(&self, older_than: i64, max_messages: u64) -> anyhow::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let mut deleted = conn.execute("DELETE FROM messages WHERE received_at < ?1", params![older_than])?;
+        deleted += conn.execute(
+            "DELETE FROM messages WHERE id <= (SELECT id FROM messages ORDER BY id DESC LIMIT 1 OFFSET ?1)",
+            params![max_messages as i64],
+        )?;
+        Ok(deleted)
+    }
+
+    pub fn clear(&self) -> anyhow::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM messages", [])?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn entry(at: i64, ip: &str, device: Option<&str>, raw: &str) -> LogEntry {
+        LogEntry {
+            received_at: at,
+            source_ip: ip.to_string(),
+            device: device.map(String::from),
+            mac: None,
+            message: parse(raw.as_bytes()),
+        }
+    }
+
+    #[test]
+    fn test_query_and_purge() {
+        let store = LogStore::open_in_memory().unwrap();
+        store
+            .insert_batch(&[
+                entry(1000, "10.0.0.2", Some("switch"), "<11>Oct 17 10:00:00 sw1 kernel: port 3 err 50%"),
+                entry(2000, "10.0.0.2", Some("switch"), "<14>Oct 17 10:00:01 sw1 kernel: port 3 up"),
+                entry(3000, "10.0.0.3", None, "<13>Oct 17 10:00:02 ap-salon hostapd: STA associated"),
+            ])
+            .unwrap();
+
+        let all = store.query(&LogQuery::default()).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].received_at, 3000);
+
+        let errors = store.query(&LogQuery { severity: Some(3), ..Default::default() }).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message.message, "port 3 err 50%");
+
+        // Device matches the inventory name or the sent hostname
+        let by_device = store.query(&LogQuery { device: Some("switch".into()), ..Default::default() }).unwrap();
+        assert_eq!(by_device.len(), 2);
+        let by_host = store.query(&LogQuery { device: Some("ap-salon".into()), ..Default::default() }).unwrap();
+        assert_eq!(by_host.len(), 1);
+
+        // LIKE wildcards in the search are literal
+        let search = store.query(&LogQuery { q: Some("50%".into()), ..Default::default() }).unwrap();
+        assert_eq!(search.len(), 1);
+        let search = store.query(&LogQuery { q: Some("%".into()), source: Some("10.0.0.3".into()), ..Default::default() }).unwrap();
+        assert!(search.is_empty());
+
+        let page = store.query(&LogQuery { before: Some(all[0].id), limit: Some(1), ..Default::default() }).unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].received_at, 2000);
+
+        let sources = store.sources().unwrap();
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[1].count, 2);
+
+        assert_eq!(store.purge(1500, 10).unwrap(), 1);
+        assert_eq!(store.purge(0, 1).unwrap(), 1);
+        assert_eq!(store.count().unwrap(), 1);
+    }
+}
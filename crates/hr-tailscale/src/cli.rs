@@ -0,0 +1,199 @@
+//! The `tailscale` CLI: node state from `tailscale status --json`, node
+//! settings through `tailscale up`.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::config::TailscaleConfig;
+
+const STATUS_TIMEOUT: Duration = Duration::from_secs(10);
+/// `tailscale up` waits this long for the control server.
+const UP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// State of the local node, as the dashboard shows it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeStatus {
+    pub version: String,
+    /// Running, NeedsLogin, Stopped, Starting, NoState...
+    pub backend_state: String,
+    pub hostname: String,
+    pub dns_name: String,
+    pub tailnet: Option<String>,
+    pub addresses: Vec<String>,
+    pub online: bool,
+    /// Advertised subnets approved by the control server.
+    pub approved_routes: Vec<String>,
+    pub peers: usize,
+    pub peers_online: usize,
+    /// Interactive login URL, when the node waits for one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_url: Option<String>,
+    pub health: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawStatus {
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    backend_state: String,
+    #[serde(rename = "AuthURL", default)]
+    auth_url: String,
+    #[serde(rename = "Self", default)]
+    self_node: Option<RawPeer>,
+    #[serde(default)]
+    health: Option<Vec<String>>,
+    #[serde(default)]
+    current_tailnet: Option<RawTailnet>,
+    #[serde(default)]
+    peer: Option<HashMap<String, RawPeer>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawTailnet {
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawPeer {
+    #[serde(default)]
+    host_name: String,
+    #[serde(rename = "DNSName", default)]
+    dns_name: String,
+    #[serde(default)]
+    tailscale_i_ps: Option<Vec<String>>,
+    #[serde(default)]
+    online: bool,
+    #[serde(default)]
+    primary_routes: Option<Vec<String>>,
+}
+
+/// Parse the output of `tailscale status --json`.
+pub fn parse_status(json: &str) -> Result<NodeStatus> {
+    let raw: RawStatus = serde_json::from_str(json).context("invalid tailscale status")?;
+    let node = raw.self_node.unwrap_or_default();
+    let peers = raw.peer.unwrap_or_default();
+    Ok(NodeStatus {
+        version: raw.version,
+        backend_state: raw.backend_state,
+        hostname: node.host_name,
+        dns_name: node.dns_name.trim_end_matches('.').to_string(),
+        tailnet: raw.current_tailnet.map(|t| t.name).filter(|n| !n.is_empty()),
+        addresses: node.tailscale_i_ps.unwrap_or_default(),
+        online: node.online,
+        approved_routes: node.primary_routes.unwrap_or_default(),
+        peers: peers.len(),
+        peers_online: peers.values().filter(|p| p.online).count(),
+        auth_url: Some(raw.auth_url).filter(|u| !u.is_empty()),
+        health: raw.health.unwrap_or_default(),
+    })
+}
+
+/// Current node state; an error when tailscaled is missing or stopped.
+pub async fn status(binary: &str) -> Result<NodeStatus> {
+    let output = run(binary, &["status", "--json"], STATUS_TIMEOUT).await?;
+    parse_status(&output)
+}
+
+/// Arguments of `tailscale up` for `config`; the auth key only when the
+/// node has to log in.
+pub fn up_args(config: &TailscaleConfig, needs_login: bool) -> Vec<String> {
+    let mut args = vec![
+        "up".to_string(),
+        // Flags not given go back to their defaults
+        "--reset".to_string(),
+        format!("--hostname={}", config.hostname),
+        format!("--advertise-routes={}", config.advertise_routes.join(",")),
+        format!("--accept-dns={}", config.accept_dns),
+        format!("--timeout={}s", UP_TIMEOUT.as_secs()),
+    ];
+    if !config.login_server.is_empty() {
+        args.push(format!("--login-server={}", config.login_server));
+    }
+    if needs_login && !config.auth_key.is_empty() {
+        args.push(format!("--auth-key={}", config.auth_key));
+    }
+    args
+}
+
+/// Apply the node settings of `config`.
+pub async fn up(config: &TailscaleConfig, needs_login: bool) -> Result<()> {
+    let args = up_args(config, needs_login);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    run(&config.binary, &args, UP_TIMEOUT + Duration::from_secs(5)).await.map(|_| ())
+}
+
+async fn run(binary: &str, args: &[&str], timeout: Duration) -> Result<String> {
+    let output = tokio::time::timeout(timeout, Command::new(binary).args(args).kill_on_drop(true).output())
+        .await
+        .with_context(|| format!("{} {} timed out", binary, args[0]))?
+        .with_context(|| format!("failed to run {}", binary))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("{} {} failed: {}", binary, args[0], stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        let json = r#"{
+            "Version": "1.76.1-t1234",
+            "BackendState": "Running",
+            "AuthURL": "",
+            "TailscaleIPs": ["100.101.102.103", "fd7a:115c:a1e0::1"],
+            "Self": {
+                "HostName": "homeroute",
+                "DNSName": "homeroute.tail1234.ts.net.",
+                "TailscaleIPs": ["100.101.102.103", "fd7a:115c:a1e0::1"],
+                "PrimaryRoutes": ["192.168.1.0/24"],
+                "Online": true
+            },
+            "Health": null,
+            "CurrentTailnet": {"Name": "alice@example.com", "MagicDNSSuffix": "tail1234.ts.net"},
+            "Peer": {
+                "nodekey:a": {"HostName": "phone", "Online": true},
+                "nodekey:b": {"HostName": "laptop", "Online": false}
+            }
+        }"#;
+        let status = parse_status(json).unwrap();
+        assert_eq!(status.backend_state, "Running");
+        assert_eq!(status.dns_name, "homeroute.tail1234.ts.net");
+        assert_eq!(status.tailnet.as_deref(), Some("alice@example.com"));
+        assert_eq!(status.approved_routes, vec!["192.168.1.0/24"]);
+        assert_eq!((status.peers, status.peers_online), (2, 1));
+        assert!(status.auth_url.is_none() && status.health.is_empty());
+
+        let logged_out = parse_status(r#"{"BackendState": "NeedsLogin", "AuthURL": "https://login.example/a", "Self": null, "Peer": null}"#).unwrap();
+        assert_eq!(logged_out.auth_url.as_deref(), Some("https://login.example/a"));
+    }
+
+    #[test]
+    fn test_up_args() {
+        let config = TailscaleConfig {
+            advertise_routes: vec!["192.168.1.0/24".to_string(), "10.0.0.0/24".to_string()],
+            login_server: "https://hs.example.com".to_string(),
+            auth_key: "tskey-123".to_string(),
+            ..Default::default()
+        };
+        let args = up_args(&config, false);
+        assert!(args.contains(&"--advertise-routes=192.168.1.0/24,10.0.0.0/24".to_string()));
+        assert!(args.contains(&"--login-server=https://hs.example.com".to_string()));
+        assert!(args.contains(&"--accept-dns=false".to_string()));
+        assert!(!args.iter().any(|a| a.starts_with("--auth-key")));
+        assert!(up_args(&config, true).contains(&"--auth-key=tskey-123".to_string()));
+    }
+}
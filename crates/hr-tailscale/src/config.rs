@@ -0,0 +1,139 @@
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Tailscale / Headscale integration (tailscale-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TailscaleConfig {
+    /// Configure tailscaled (`tailscale up`); otherwise its state is only
+    /// reported.
+    #[serde(default)]
+    pub manage: bool,
+    /// Control server of a Headscale instance; Tailscale's when empty.
+    #[serde(default)]
+    pub login_server: String,
+    /// Pre-authentication key, used when the node is logged out.
+    #[serde(default)]
+    pub auth_key: String,
+    #[serde(default = "default_hostname")]
+    pub hostname: String,
+    /// LAN subnets advertised to the tailnet (they must still be approved
+    /// in the admin console or with `headscale routes enable`).
+    #[serde(default)]
+    pub advertise_routes: Vec<String>,
+    /// Let Tailscale take over the resolver; off, HomeRoute is the DNS.
+    #[serde(default)]
+    pub accept_dns: bool,
+    /// Client addresses considered on the tailnet by VPN-only routes.
+    #[serde(default = "default_vpn_ranges")]
+    pub vpn_ranges: Vec<String>,
+    /// `tailscale` CLI.
+    #[serde(default = "default_binary")]
+    pub binary: String,
+}
+
+fn default_hostname() -> String {
+    "homeroute".to_string()
+}
+
+/// Tailscale's CGNAT and ULA ranges.
+fn default_vpn_ranges() -> Vec<String> {
+    vec!["100.64.0.0/10".to_string(), "fd7a:115c:a1e0::/48".to_string()]
+}
+
+fn default_binary() -> String {
+    "tailscale".to_string()
+}
+
+impl Default for TailscaleConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl TailscaleConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.login_server.is_empty()
+            && !self.login_server.starts_with("https://")
+            && !self.login_server.starts_with("http://")
+        {
+            return Err(format!("Invalid login server (http:// or https:// URL): {}", self.login_server));
+        }
+        let hostname_ok = (1..=63).contains(&self.hostname.len())
+            && self.hostname.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+            && !self.hostname.starts_with('-');
+        if !hostname_ok {
+            return Err(format!("Invalid hostname: {}", self.hostname));
+        }
+        for route in &self.advertise_routes {
+            let net: IpNet = route.parse().map_err(|_| format!("Invalid advertised route: {}", route))?;
+            if net.trunc() != net {
+                return Err(format!("Advertised route has host bits set: {}", route));
+            }
+        }
+        for range in &self.vpn_ranges {
+            range.parse::<IpNet>().map_err(|_| format!("Invalid VPN range: {}", range))?;
+        }
+        if self.binary.trim().is_empty() {
+            return Err("The tailscale binary is required".to_string());
+        }
+        Ok(())
+    }
+
+    /// Parsed `vpn_ranges` (invalid entries skipped).
+    pub fn vpn_networks(&self) -> Vec<IpNet> {
+        self.vpn_ranges.iter().filter_map(|r| r.parse().ok()).collect()
+    }
+
+    /// Settings `tailscale up` is run with changed (not the vpn ranges).
+    pub fn node_settings_differ(&self, other: &Self) -> bool {
+        (&self.manage, &self.login_server, &self.auth_key, &self.hostname, &self.advertise_routes, self.accept_dns)
+            != (&other.manage, &other.login_server, &other.auth_key, &other.hostname, &other.advertise_routes, other.accept_dns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let config = TailscaleConfig::default();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.vpn_networks().len(), 2);
+
+        let mut c = config.clone();
+        c.advertise_routes = vec!["192.168.1.0/24".to_string()];
+        c.login_server = "https://headscale.example.com".to_string();
+        assert!(c.validate().is_ok());
+        assert!(c.node_settings_differ(&config));
+        c.advertise_routes = vec!["192.168.1.1/24".to_string()];
+        assert!(c.validate().is_err());
+        c.advertise_routes.clear();
+        c.login_server = "headscale.example.com".to_string();
+        assert!(c.validate().is_err());
+
+        let mut c = config.clone();
+        c.vpn_ranges.push("10.0.0.0/8".to_string());
+        assert!(!c.node_settings_differ(&config));
+        c.hostname = "-bad".to_string();
+        assert!(c.validate().is_err());
+    }
+}
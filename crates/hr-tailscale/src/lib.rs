@@ -0,0 +1,84 @@
+//! Remote access fallback over Tailscale or a Headscale control server:
+//! reports the state of the local tailscaled and, when managed, logs it in
+//! and advertises the LAN subnets. The proxy's VPN-only routes accept the
+//! tailnet ranges configured here.
+
+pub mod cli;
+pub mod config;
+pub mod service;
+
+pub use cli::NodeStatus;
+pub use config::TailscaleConfig;
+
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TailscaleStatus {
+    /// tailscaled answered the last status call.
+    pub available: bool,
+    pub managed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node: Option<NodeStatus>,
+    /// Advertised subnets not approved yet by the control server.
+    pub pending_routes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_applied: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Shared Tailscale handle: the API pushes config and reads status, the
+/// supervised `tailscale` service polls tailscaled and applies the config.
+pub struct Tailscale {
+    config: watch::Sender<TailscaleConfig>,
+    status: RwLock<TailscaleStatus>,
+}
+
+pub type SharedTailscale = Arc<Tailscale>;
+
+impl Tailscale {
+    pub fn new(config: TailscaleConfig) -> Self {
+        Self {
+            config: watch::channel(config).0,
+            status: RwLock::new(TailscaleStatus::default()),
+        }
+    }
+
+    /// Current configuration.
+    pub fn config(&self) -> TailscaleConfig {
+        self.config.borrow().clone()
+    }
+
+    /// Replace the configuration; the node is reconfigured right away when
+    /// managed.
+    pub fn apply(&self, config: TailscaleConfig) {
+        self.config.send_replace(config);
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<TailscaleConfig> {
+        self.config.subscribe()
+    }
+
+    pub fn status(&self) -> TailscaleStatus {
+        let mut status = self.status.read().unwrap().clone();
+        let config = self.config.borrow();
+        status.managed = config.manage;
+        status.pending_routes = match &status.node {
+            Some(node) => config
+                .advertise_routes
+                .iter()
+                .filter(|r| !node.approved_routes.contains(r))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        status
+    }
+
+    pub(crate) fn update_status(&self, f: impl FnOnce(&mut TailscaleStatus)) {
+        f(&mut self.status.write().unwrap());
+    }
+}
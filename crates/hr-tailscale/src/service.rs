@@ -0,0 +1,86 @@
+//! The `tailscale` service: polls tailscaled and, when managed, runs
+//! `tailscale up` whenever the node settings change or the node fell out of
+//! the Running state.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tracing::{info, warn};
+
+use crate::config::TailscaleConfig;
+use crate::{SharedTailscale, cli};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Delay between two `tailscale up` attempts for the same settings.
+const RETRY_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Last `tailscale up`.
+struct Applied {
+    config: TailscaleConfig,
+    at: Instant,
+    error: Option<String>,
+}
+
+pub async fn run_tailscale(tailscale: SharedTailscale) -> Result<()> {
+    let mut config_rx = tailscale.subscribe();
+    let mut applied: Option<Applied> = None;
+    loop {
+        let config = config_rx.borrow_and_update().clone();
+        poll(&tailscale, &config, &mut applied).await;
+
+        tokio::select! {
+            changed = config_rx.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+            }
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+        }
+    }
+}
+
+async fn poll(tailscale: &SharedTailscale, config: &TailscaleConfig, applied: &mut Option<Applied>) {
+    let mut node = match cli::status(&config.binary).await {
+        Ok(node) => node,
+        Err(e) => {
+            tailscale.update_status(|s| {
+                s.available = false;
+                s.node = None;
+                s.last_error = Some(e.to_string());
+            });
+            return;
+        }
+    };
+
+    if !config.manage {
+        *applied = None;
+    } else {
+        let settings_changed = applied.as_ref().is_none_or(|a| a.config.node_settings_differ(config));
+        let retry = node.backend_state != "Running"
+            && applied.as_ref().is_none_or(|a| a.at.elapsed() >= RETRY_INTERVAL);
+        if settings_changed || retry {
+            let needs_login = node.backend_state == "NeedsLogin";
+            let error = match cli::up(config, needs_login).await {
+                Ok(()) => {
+                    info!("Tailscale node configured (routes: {:?})", config.advertise_routes);
+                    None
+                }
+                Err(e) => {
+                    warn!("tailscale up failed: {}", e);
+                    Some(e.to_string())
+                }
+            };
+            *applied = Some(Applied { config: config.clone(), at: Instant::now(), error });
+            tailscale.update_status(|s| s.last_applied = Some(chrono::Utc::now().to_rfc3339()));
+            if let Ok(updated) = cli::status(&config.binary).await {
+                node = updated;
+            }
+        }
+    }
+
+    tailscale.update_status(|s| {
+        s.available = true;
+        s.node = Some(node);
+        s.last_error = applied.as_ref().and_then(|a| a.error.clone());
+    });
+}
@@ -0,0 +1,35 @@
+//! Per-stream framing cost of the relay tunnel. Run with
+//! `cargo bench -p hr-tunnel`.
+
+use std::net::IpAddr;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use hr_tunnel::protocol::{ControlMessage, StreamHeader};
+
+fn framing(c: &mut Criterion) {
+    let header = StreamHeader {
+        client_ip: "2001:db8::42".parse::<IpAddr>().unwrap(),
+        timestamp: 1_700_000_000,
+        proxy_ip: None,
+    };
+    let encoded = header.encode();
+    let ping = ControlMessage::Ping { ts: 1_700_000_000 };
+    let ping_encoded = ping.encode().unwrap();
+
+    c.bench_function("stream_header_encode", |b| b.iter(|| black_box(&header).encode()));
+    c.bench_function("stream_header_decode", |b| {
+        b.iter(|| StreamHeader::decode(&mut black_box(encoded.clone())).unwrap())
+    });
+    c.bench_function("control_message_roundtrip", |b| {
+        b.iter(|| {
+            let bytes = black_box(&ping).encode().unwrap();
+            ControlMessage::decode(&mut bytes.clone()).unwrap()
+        })
+    });
+    c.bench_function("control_message_decode", |b| {
+        b.iter(|| ControlMessage::decode(&mut black_box(ping_encoded.clone())).unwrap())
+    });
+}
+
+criterion_group!(benches, framing);
+criterion_main!(benches);
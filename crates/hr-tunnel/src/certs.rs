@@ -0,0 +1,141 @@
+//! Certificates of the tunnel client in `data/cloud-relay/`: the client
+//! certificate (renewed with the tunnel CA key kept at bootstrap) and the
+//! relay certificate pins.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::crypto;
+
+/// Validity of a renewed client certificate.
+const CLIENT_CERT_VALIDITY: Duration = Duration::from_secs(365 * 24 * 3600);
+/// The client certificate is renewed this long before it expires.
+pub const RENEW_BEFORE: Duration = Duration::from_secs(30 * 24 * 3600);
+
+/// A pinned relay certificate.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelayPin {
+    /// SHA-256 of the DER certificate, hex.
+    pub sha256: String,
+    #[serde(default)]
+    pub label: String,
+}
+
+/// Relay certificates accepted by the tunnel client (pins.json). Empty:
+/// any certificate of the tunnel CA.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RelayPins {
+    #[serde(default)]
+    pub pins: Vec<RelayPin>,
+}
+
+impl RelayPins {
+    pub fn load(relay_dir: &Path) -> Result<Self> {
+        let path = relay_dir.join("pins.json");
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, relay_dir: &Path) -> Result<()> {
+        let path = relay_dir.join("pins.json");
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    /// Normalize the fingerprints and reject malformed or duplicate ones.
+    pub fn normalize(&mut self) -> Result<(), String> {
+        let mut seen = std::collections::HashSet::new();
+        for pin in &mut self.pins {
+            pin.sha256 = crate::quic::normalize_pin(&pin.sha256);
+            if pin.sha256.len() != 64 {
+                return Err(format!("Invalid SHA-256 fingerprint: {}", pin.sha256));
+            }
+            if !seen.insert(pin.sha256.clone()) {
+                return Err(format!("Duplicate pin: {}", pin.sha256));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn fingerprints(&self) -> Vec<String> {
+        self.pins.iter().map(|p| p.sha256.clone()).collect()
+    }
+}
+
+/// The tunnel client certificate.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientCertStatus {
+    pub common_name: Option<String>,
+    pub sha256: String,
+    /// Unix seconds.
+    pub not_after: Option<i64>,
+    /// The tunnel CA key is here: the certificate can be renewed.
+    pub renewable: bool,
+}
+
+fn first_cert_der(pem: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = std::io::BufReader::new(pem);
+    let cert = rustls_pemfile::certs(&mut reader)
+        .next()
+        .context("No certificate in PEM")??;
+    Ok(cert.to_vec())
+}
+
+pub fn client_cert_status(relay_dir: &Path) -> Result<ClientCertStatus> {
+    let pem = std::fs::read(relay_dir.join("client.pem")).context("No client certificate")?;
+    let der = first_cert_der(&pem)?;
+    Ok(ClientCertStatus {
+        common_name: crypto::certificate_common_name(&der),
+        sha256: crypto::certificate_sha256(&der),
+        not_after: crypto::certificate_not_after(&der),
+        renewable: relay_dir.join("ca-key.pem").exists(),
+    })
+}
+
+/// Issue a new client certificate with the same CN and replace the
+/// current one; the tunnel uses it from its next connection.
+pub fn renew_client_cert(relay_dir: &Path) -> Result<ClientCertStatus> {
+    let status = client_cert_status(relay_dir)?;
+    let ca_key = std::fs::read_to_string(relay_dir.join("ca-key.pem"))
+        .context("The tunnel CA key is not on this server, run the bootstrap again")?;
+    let common_name = status.common_name.as_deref().unwrap_or("homeroute-onprem");
+    let (cert_pem, key_pem) =
+        crypto::issue_client_cert(&ca_key, common_name, CLIENT_CERT_VALIDITY)?;
+
+    // Each file is replaced atomically; the tunnel only reads them when it
+    // (re)connects
+    for (name, content) in [("client-key.pem", &key_pem), ("client.pem", &cert_pem)] {
+        let path = relay_dir.join(name);
+        let tmp_path = path.with_extension("pem.tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &path)?;
+    }
+    client_cert_status(relay_dir)
+}
+
+/// Whether the client certificate expires within `RENEW_BEFORE` and can
+/// be renewed.
+pub fn client_cert_due(relay_dir: &Path) -> bool {
+    let Ok(status) = client_cert_status(relay_dir) else {
+        return false;
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    status.renewable
+        && status
+            .not_after
+            .is_some_and(|t| t - now < RENEW_BEFORE.as_secs() as i64)
+}
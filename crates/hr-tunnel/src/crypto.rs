@@ -27,20 +27,7 @@ pub fn generate_tunnel_certs(vps_host: &str) -> Result<TunnelCerts> {
     // ── CA ────────────────────────────────────────────────────────────
     let ca_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256)
         .context("Failed to generate CA key pair")?;
-
-    let mut ca_params = CertificateParams::new(Vec::<String>::new())
-        .context("Failed to create CA params")?;
-    ca_params
-        .distinguished_name
-        .push(DnType::CommonName, "HomeRoute Tunnel CA");
-    ca_params.is_ca = IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
-    ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
-    ca_params.not_before = time::OffsetDateTime::now_utc();
-    ca_params.not_after = time::OffsetDateTime::now_utc() + validity;
-
-    let ca_cert = ca_params
-        .self_signed(&ca_key)
-        .context("Failed to self-sign CA cert")?;
+    let ca_cert = ca_certificate(&ca_key, validity)?;
 
     // ── Server cert ──────────────────────────────────────────────────
     let server_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256)
@@ -62,6 +49,51 @@ pub fn generate_tunnel_certs(vps_host: &str) -> Result<TunnelCerts> {
         .context("Failed to sign server cert")?;
 
     // ── Client cert ──────────────────────────────────────────────────
+    let (client_cert_pem, client_key_pem) =
+        sign_client_cert(&ca_cert, &ca_key, "homeroute-onprem", validity)?;
+
+    Ok(TunnelCerts {
+        ca_cert_pem: ca_cert.pem(),
+        ca_key_pem: ca_key.serialize_pem(),
+        server_cert_pem: server_cert.pem(),
+        server_key_pem: server_key.serialize_pem(),
+        client_cert_pem,
+        client_key_pem,
+    })
+}
+
+/// Issue a new client certificate for `common_name` with the tunnel CA
+/// key, to renew the on-prem certificate without touching the relay.
+/// Returns `(cert_pem, key_pem)`.
+pub fn issue_client_cert(ca_key_pem: &str, common_name: &str, validity: Duration) -> Result<(String, String)> {
+    let ca_key = KeyPair::from_pem(ca_key_pem).context("Invalid CA key")?;
+    // Same subject and key as the original CA: what the relay verifies
+    let ca_cert = ca_certificate(&ca_key, validity)?;
+    sign_client_cert(&ca_cert, &ca_key, common_name, validity)
+}
+
+fn ca_certificate(ca_key: &KeyPair, validity: Duration) -> Result<rcgen::Certificate> {
+    let mut ca_params = CertificateParams::new(Vec::<String>::new())
+        .context("Failed to create CA params")?;
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "HomeRoute Tunnel CA");
+    ca_params.is_ca = IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    ca_params.not_before = time::OffsetDateTime::now_utc();
+    ca_params.not_after = time::OffsetDateTime::now_utc() + validity;
+
+    ca_params
+        .self_signed(ca_key)
+        .context("Failed to self-sign CA cert")
+}
+
+fn sign_client_cert(
+    ca_cert: &rcgen::Certificate,
+    ca_key: &KeyPair,
+    common_name: &str,
+    validity: Duration,
+) -> Result<(String, String)> {
     let client_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256)
         .context("Failed to generate client key pair")?;
 
@@ -69,23 +101,29 @@ pub fn generate_tunnel_certs(vps_host: &str) -> Result<TunnelCerts> {
         .context("Failed to create client params")?;
     client_params
         .distinguished_name
-        .push(DnType::CommonName, "homeroute-onprem");
+        .push(DnType::CommonName, common_name);
     client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
     client_params.not_before = time::OffsetDateTime::now_utc();
     client_params.not_after = time::OffsetDateTime::now_utc() + validity;
 
     let client_cert = client_params
-        .signed_by(&client_key, &ca_cert, &ca_key)
+        .signed_by(&client_key, ca_cert, ca_key)
         .context("Failed to sign client cert")?;
+    Ok((client_cert.pem(), client_key.serialize_pem()))
+}
 
-    Ok(TunnelCerts {
-        ca_cert_pem: ca_cert.pem(),
-        ca_key_pem: ca_key.serialize_pem(),
-        server_cert_pem: server_cert.pem(),
-        server_key_pem: server_key.serialize_pem(),
-        client_cert_pem: client_cert.pem(),
-        client_key_pem: client_key.serialize_pem(),
-    })
+/// SHA-256 fingerprint of a DER certificate, lowercase hex.
+pub fn certificate_sha256(der: &[u8]) -> String {
+    sha256_hex(der)
+}
+
+/// SHA-256 of `data`, lowercase hex, as in `BinaryUpdate`.
+pub fn sha256_hex(data: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, data)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
 }
 
 /// Build SAN entries: if `host` parses as an IP, use IpAddress SAN; otherwise DNS.
@@ -96,3 +134,99 @@ fn build_sans(host: &str) -> Vec<SanType> {
         vec![SanType::DnsName(host.try_into().expect("valid DNS name"))]
     }
 }
+
+/// OID 2.5.4.3 (commonName), DER-encoded.
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+/// Subject common name of a DER certificate. The relay identifies tunnels
+/// by the CN of their client certificate.
+pub fn certificate_common_name(der: &[u8]) -> Option<String> {
+    let fields = tbs_fields(der)?;
+    let (_, subject) = *fields.get(4)?;
+
+    let mut sets = subject;
+    while !sets.is_empty() {
+        let (_, set, next) = der_element(sets)?;
+        sets = next;
+        let (_, attribute, _) = der_element(set)?;
+        let (_, oid, value) = der_element(attribute)?;
+        if oid == OID_COMMON_NAME {
+            let (_, name, _) = der_element(value)?;
+            return String::from_utf8(name.to_vec()).ok();
+        }
+    }
+    None
+}
+
+/// End of validity of a DER certificate, in Unix seconds.
+pub fn certificate_not_after(der: &[u8]) -> Option<i64> {
+    let fields = tbs_fields(der)?;
+    let (_, validity) = *fields.get(3)?;
+    let (_, _, rest) = der_element(validity)?;
+    let (tag, time, _) = der_element(rest)?;
+    let time = std::str::from_utf8(time).ok()?.strip_suffix('Z')?;
+    // UTCTime: YYMMDDHHMMSS, GeneralizedTime: YYYYMMDDHHMMSS
+    let (year, rest) = match tag {
+        0x17 => {
+            let yy: i64 = time.get(..2)?.parse().ok()?;
+            (if yy >= 50 { 1900 + yy } else { 2000 + yy }, time.get(2..)?)
+        }
+        0x18 => (time.get(..4)?.parse().ok()?, time.get(4..)?),
+        _ => return None,
+    };
+    if rest.len() != 10 || !rest.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let part = |i: usize| rest[i..i + 2].parse::<i64>().unwrap();
+    let (month, day, hour, minute, second) = (part(0), part(2), part(4), part(6), part(8));
+
+    // Days since the epoch of a proleptic Gregorian date
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Fields of the TBSCertificate of a DER certificate from the serial
+/// number on: serial, signature, issuer, validity, subject...
+fn tbs_fields(der: &[u8]) -> Option<Vec<(u8, &[u8])>> {
+    let (_, certificate, _) = der_element(der)?;
+    let (_, tbs, _) = der_element(certificate)?;
+    let mut fields = Vec::new();
+    let mut rest = tbs;
+    while !rest.is_empty() {
+        let (tag, content, next) = der_element(rest)?;
+        fields.push((tag, content));
+        rest = next;
+    }
+    // Optional [0] version
+    if fields.first()?.0 == 0xa0 {
+        fields.remove(0);
+    }
+    Some(fields)
+}
+
+/// Split the first DER element of `data` into `(tag, content, rest)`.
+fn der_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&first, mut data) = data.split_first()?;
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || data.len() < count {
+            return None;
+        }
+        let len = data[..count].iter().fold(0usize, |len, &b| (len << 8) | b as usize);
+        data = &data[count..];
+        len
+    };
+    if data.len() < len {
+        return None;
+    }
+    Some((tag, &data[..len], &data[len..]))
+}
@@ -1,3 +1,6 @@
 pub mod protocol;
 pub mod crypto;
 pub mod quic;
+pub mod usage;
+pub mod certs;
+pub mod provision;
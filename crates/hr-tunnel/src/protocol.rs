@@ -1,64 +1,160 @@
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use serde::{Deserialize, Serialize};
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 
 /// Header sent at the beginning of each QUIC stream (VPS -> on-prem).
-/// Binary format: [version:u8][ip_type:u8][ip_bytes:4or16][timestamp:u64]
+/// Binary format: [version:u8][ip_type:u8][ip_bytes:4or16][timestamp:u64],
+/// followed in version 2 by [ip_type:u8][ip_bytes:4or16] of `proxy_ip`.
+/// Version 1 is still sent when there is no proxy, for older on-prem sides.
 #[derive(Debug, Clone)]
 pub struct StreamHeader {
     pub client_ip: IpAddr,
     pub timestamp: u64,
+    /// Proxy in front of the relay that announced `client_ip` with the
+    /// PROXY protocol; `None` when the client reached the relay directly.
+    pub proxy_ip: Option<IpAddr>,
 }
 
 impl StreamHeader {
     pub fn encode(&self) -> Bytes {
         let mut buf = BytesMut::new();
-        buf.put_u8(1); // version
-        match self.client_ip {
-            IpAddr::V4(ip) => {
-                buf.put_u8(4);
-                buf.put_slice(&ip.octets());
-            }
-            IpAddr::V6(ip) => {
-                buf.put_u8(6);
-                buf.put_slice(&ip.octets());
-            }
-        }
+        buf.put_u8(if self.proxy_ip.is_some() { 2 } else { 1 }); // version
+        put_ip(&mut buf, self.client_ip);
         buf.put_u64(self.timestamp);
+        if let Some(ip) = self.proxy_ip {
+            put_ip(&mut buf, ip);
+        }
         buf.freeze()
     }
 
     pub fn decode(buf: &mut impl Buf) -> anyhow::Result<Self> {
         anyhow::ensure!(buf.remaining() >= 2, "StreamHeader too short");
         let version = buf.get_u8();
-        anyhow::ensure!(version == 1, "Unsupported StreamHeader version {}", version);
-        let ip_type = buf.get_u8();
-        let client_ip = match ip_type {
-            4 => {
-                anyhow::ensure!(buf.remaining() >= 4, "Incomplete IPv4");
-                let mut octets = [0u8; 4];
-                buf.copy_to_slice(&mut octets);
-                IpAddr::V4(octets.into())
-            }
-            6 => {
-                anyhow::ensure!(buf.remaining() >= 16, "Incomplete IPv6");
-                let mut octets = [0u8; 16];
-                buf.copy_to_slice(&mut octets);
-                IpAddr::V6(octets.into())
-            }
-            other => anyhow::bail!("Invalid IP type: {}", other),
-        };
+        anyhow::ensure!(
+            version == 1 || version == 2,
+            "Unsupported StreamHeader version {}",
+            version
+        );
+        let client_ip = get_ip(buf)?;
         anyhow::ensure!(buf.remaining() >= 8, "Incomplete timestamp");
         let timestamp = buf.get_u64();
-        Ok(Self { client_ip, timestamp })
+        let proxy_ip = if version == 2 {
+            anyhow::ensure!(buf.remaining() >= 1, "Incomplete proxy address");
+            Some(get_ip(buf)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            client_ip,
+            timestamp,
+            proxy_ip,
+        })
     }
 }
 
+/// A UDP flow exposed by the relay: a client of one of its public ports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UdpFlow {
+    pub port: u16,
+    pub client: SocketAddr,
+}
+
+/// UDP payload carried in a QUIC datagram, in either direction. The relay
+/// names the flow in each datagram so the on-prem side can pick it up at
+/// any point (after a reconnect for instance); replies only carry the id.
+/// Binary format: [version:u8][has_flow:u8][flow_id:u32], then with a flow
+/// [port:u16][ip_type:u8][ip_bytes:4or16][client_port:u16], then the payload.
+#[derive(Debug, Clone)]
+pub struct UdpDatagram {
+    pub flow_id: u32,
+    pub flow: Option<UdpFlow>,
+    pub payload: Bytes,
+}
+
+impl UdpDatagram {
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(6 + 21 + self.payload.len());
+        buf.put_u8(1); // version
+        buf.put_u8(self.flow.is_some() as u8);
+        buf.put_u32(self.flow_id);
+        if let Some(flow) = self.flow {
+            buf.put_u16(flow.port);
+            put_ip(&mut buf, flow.client.ip());
+            buf.put_u16(flow.client.port());
+        }
+        buf.put_slice(&self.payload);
+        buf.freeze()
+    }
+
+    pub fn decode(mut buf: Bytes) -> anyhow::Result<Self> {
+        anyhow::ensure!(buf.remaining() >= 6, "UdpDatagram too short");
+        let version = buf.get_u8();
+        anyhow::ensure!(version == 1, "Unsupported UdpDatagram version {}", version);
+        let has_flow = buf.get_u8() != 0;
+        let flow_id = buf.get_u32();
+        let flow = if has_flow {
+            anyhow::ensure!(buf.remaining() >= 3, "Incomplete UDP flow");
+            let port = buf.get_u16();
+            let ip = get_ip(&mut buf)?;
+            anyhow::ensure!(buf.remaining() >= 2, "Incomplete UDP flow");
+            let client = SocketAddr::new(ip, buf.get_u16());
+            Some(UdpFlow { port, client })
+        } else {
+            None
+        };
+        Ok(Self {
+            flow_id,
+            flow,
+            payload: buf,
+        })
+    }
+}
+
+/// Length of the address following an `ip_type` byte.
+pub(crate) fn ip_len(ip_type: u8) -> anyhow::Result<usize> {
+    match ip_type {
+        4 => Ok(4),
+        6 => Ok(16),
+        other => anyhow::bail!("Invalid IP type: {}", other),
+    }
+}
+
+fn put_ip(buf: &mut BytesMut, ip: IpAddr) {
+    match ip {
+        IpAddr::V4(ip) => {
+            buf.put_u8(4);
+            buf.put_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            buf.put_u8(6);
+            buf.put_slice(&ip.octets());
+        }
+    }
+}
+
+fn get_ip(buf: &mut impl Buf) -> anyhow::Result<IpAddr> {
+    let ip_type = buf.get_u8();
+    let len = ip_len(ip_type)?;
+    anyhow::ensure!(buf.remaining() >= len, "Incomplete IPv{}", ip_type);
+    Ok(if len == 4 {
+        let mut octets = [0u8; 4];
+        buf.copy_to_slice(&mut octets);
+        IpAddr::V4(octets.into())
+    } else {
+        let mut octets = [0u8; 16];
+        buf.copy_to_slice(&mut octets);
+        IpAddr::V6(octets.into())
+    })
+}
+
 /// Control messages exchanged on a dedicated QUIC stream.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ControlMessage {
+    /// Keepalive probe from the tunnel client; `ts` is its clock in µs.
     Ping { ts: u64 },
+    /// Answer to a `Ping`, echoing `ts`. `latency_us` is the relay's own
+    /// estimate of the path RTT.
     Pong { ts: u64, latency_us: u64 },
     RelayStats { active_streams: u32, total_bytes: u64 },
     Shutdown { reason: String },
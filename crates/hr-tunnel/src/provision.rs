@@ -0,0 +1,197 @@
+//! Files of a new relay VPS: its config.toml, the systemd unit and an
+//! install script (or cloud-init user data running it) carrying the relay
+//! side of the tunnel certificates.
+
+use serde::Deserialize;
+
+use crate::crypto::TunnelCerts;
+
+/// Where the relay binary is built on the HomeRoute server.
+pub const LOCAL_RELAY_BINARY: &str = "/opt/homeroute/crates/target/release/hr-cloud-relay";
+/// Marker in `data/cloud-relay/`: push the local relay binary through the
+/// tunnel at its next connection.
+pub const PUSH_BINARY_MARKER: &str = "push-binary";
+
+const RELAY_DIR: &str = "/etc/hr-cloud-relay";
+const RELAY_BINARY: &str = "/usr/local/bin/hr-cloud-relay";
+
+pub const SYSTEMD_UNIT: &str = r#"[Unit]
+Description=HomeRoute Cloud Relay
+After=network.target
+
+[Service]
+ExecStart=/usr/local/bin/hr-cloud-relay
+Restart=always
+RestartSec=5
+Environment=RUST_LOG=info
+
+[Install]
+WantedBy=multi-user.target
+"#;
+
+/// Relay to provision.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProvisionOptions {
+    /// Public hostname or IP of the VPS, in the relay certificate.
+    pub host: String,
+    #[serde(default = "default_quic_port")]
+    pub quic_port: u16,
+    #[serde(default = "default_tcp_listen_port")]
+    pub tcp_listen_port: u16,
+    #[serde(default = "default_http_redirect_port")]
+    pub http_redirect_port: u16,
+    /// UDP ports relayed through the tunnel (`[[udp]]`).
+    #[serde(default)]
+    pub udp_ports: Vec<u16>,
+    /// HTTPS URL the VPS downloads the relay binary from. Without it the
+    /// install script carries the binary itself.
+    #[serde(default)]
+    pub binary_url: Option<String>,
+}
+
+fn default_quic_port() -> u16 {
+    4443
+}
+fn default_tcp_listen_port() -> u16 {
+    443
+}
+fn default_http_redirect_port() -> u16 {
+    80
+}
+
+impl ProvisionOptions {
+    /// Defaults of a relay on `host`.
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            quic_port: default_quic_port(),
+            tcp_listen_port: default_tcp_listen_port(),
+            http_redirect_port: default_http_redirect_port(),
+            udp_ports: Vec::new(),
+            binary_url: None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let host = self.host.trim();
+        if host.is_empty()
+            || !host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':'))
+        {
+            return Err(format!("Invalid relay host: {}", self.host));
+        }
+        if self.quic_port == 0
+            || self.tcp_listen_port == 0
+            || self.http_redirect_port == 0
+            || self.tcp_listen_port == self.http_redirect_port
+        {
+            return Err("The relay ports must be distinct and not 0".to_string());
+        }
+        for (i, port) in self.udp_ports.iter().enumerate() {
+            if *port == 0 || *port == self.quic_port || self.udp_ports[..i].contains(port) {
+                return Err(format!("Invalid UDP port: {}", port));
+            }
+        }
+        if let Some(url) = &self.binary_url
+            && (!url.starts_with("https://") || url.contains(['\'', '"', ' ', '\n']))
+        {
+            return Err("The binary URL must be an https:// URL".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// config.toml of the relay.
+pub fn relay_config_toml(options: &ProvisionOptions) -> String {
+    let mut config = format!(
+        r#"quic_port = {}
+tcp_listen_port = {}
+http_redirect_port = {}
+
+[tls]
+ca_cert = "{dir}/ca.pem"
+server_cert = "{dir}/server.pem"
+server_key = "{dir}/server-key.pem"
+"#,
+        options.quic_port,
+        options.tcp_listen_port,
+        options.http_redirect_port,
+        dir = RELAY_DIR,
+    );
+    for port in &options.udp_ports {
+        config.push_str(&format!("\n[[udp]]\nport = {}\n", port));
+    }
+    config
+}
+
+/// Shell script installing the relay on a fresh VPS, as root. Only the
+/// relay side of `certs` goes in it: the CA key and the client pair stay
+/// on the HomeRoute server. `binary_base64` is used when no binary URL is
+/// set.
+pub fn install_script(
+    options: &ProvisionOptions,
+    certs: &TunnelCerts,
+    binary_base64: Option<&str>,
+) -> String {
+    let binary_step = match (&options.binary_url, binary_base64) {
+        (Some(url), _) => format!("curl -fsSL -o {bin}.new '{url}'\n", bin = RELAY_BINARY),
+        (None, Some(data)) => format!("base64 -d > {}.new << 'BIN'\n{}\nBIN\n", RELAY_BINARY, data),
+        (None, None) => format!(
+            "test -x {0} || {{ echo 'Copy hr-cloud-relay to {0} first' >&2; exit 1; }}\ncp {0} {0}.new\n",
+            RELAY_BINARY
+        ),
+    };
+    format!(
+        r#"#!/bin/bash
+# HomeRoute cloud relay for {host}
+set -euo pipefail
+
+mkdir -p {dir}
+chmod 700 {dir}
+cat > {dir}/ca.pem << 'PEM'
+{ca}PEM
+cat > {dir}/server.pem << 'PEM'
+{server}PEM
+cat > {dir}/server-key.pem << 'PEM'
+{server_key}PEM
+chmod 600 {dir}/server-key.pem
+cat > {dir}/config.toml << 'CONF'
+{config}CONF
+
+{binary_step}chmod +x {bin}.new
+mv {bin}.new {bin}
+
+cat > /etc/systemd/system/hr-cloud-relay.service << 'SVC'
+{unit}SVC
+systemctl daemon-reload
+systemctl enable hr-cloud-relay
+systemctl restart hr-cloud-relay
+echo "hr-cloud-relay installed, QUIC on UDP {quic_port}"
+"#,
+        host = options.host.trim(),
+        dir = RELAY_DIR,
+        bin = RELAY_BINARY,
+        ca = certs.ca_cert_pem,
+        server = certs.server_cert_pem,
+        server_key = certs.server_key_pem,
+        config = relay_config_toml(options),
+        unit = SYSTEMD_UNIT,
+        quic_port = options.quic_port,
+    )
+}
+
+/// cloud-init user data running the install script at first boot.
+pub fn cloud_init(script: &str) -> String {
+    let mut user_data = String::from(
+        "#cloud-config\nwrite_files:\n  - path: /root/hr-cloud-relay-install.sh\n    permissions: '0700'\n    content: |\n",
+    );
+    for line in script.lines() {
+        user_data.push_str("      ");
+        user_data.push_str(line);
+        user_data.push('\n');
+    }
+    user_data.push_str("runcmd:\n  - [bash, /root/hr-cloud-relay-install.sh]\n");
+    user_data
+}
@@ -1,7 +1,11 @@
 use anyhow::{Context, Result};
-use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
 use std::sync::Arc;
 
+use crate::protocol::{ip_len, ControlMessage, StreamHeader};
+
 /// Build a quinn::ServerConfig for the VPS side (accepts tunnel connections).
 /// Uses the server cert and requires client certs signed by our CA.
 pub fn build_server_config(
@@ -51,6 +55,18 @@ pub fn build_client_config(
     client_cert_pem: &[u8],
     client_key_pem: &[u8],
     ca_cert_pem: &[u8],
+) -> Result<quinn::ClientConfig> {
+    build_pinned_client_config(client_cert_pem, client_key_pem, ca_cert_pem, &[])
+}
+
+/// Like `build_client_config`, and when `pins` is not empty the relay
+/// certificate must also be one of them (SHA-256 fingerprints): a cert
+/// issued by a leaked CA key is refused. Several pins allow a rotation.
+pub fn build_pinned_client_config(
+    client_cert_pem: &[u8],
+    client_key_pem: &[u8],
+    ca_cert_pem: &[u8],
+    pins: &[String],
 ) -> Result<quinn::ClientConfig> {
     let certs = load_certs(client_cert_pem)?;
     let key = load_private_key(client_key_pem)?;
@@ -64,10 +80,24 @@ pub fn build_client_config(
             .context("Failed to add CA cert to root store")?;
     }
 
-    let client_crypto = rustls::ClientConfig::builder()
-        .with_root_certificates(root_store)
-        .with_client_auth_cert(certs, key)
-        .context("Failed to build client TLS config")?;
+    let client_crypto = if pins.is_empty() {
+        rustls::ClientConfig::builder()
+            .with_root_certificates(root_store)
+            .with_client_auth_cert(certs, key)
+            .context("Failed to build client TLS config")?
+    } else {
+        let verifier = PinnedServerVerifier {
+            inner: rustls::client::WebPkiServerVerifier::builder(Arc::new(root_store))
+                .build()
+                .context("Failed to build server verifier")?,
+            pins: pins.iter().map(|p| normalize_pin(p)).collect(),
+        };
+        rustls::ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_client_auth_cert(certs, key)
+            .context("Failed to build client TLS config")?
+    };
 
     let mut client_config = quinn::ClientConfig::new(Arc::new(
         quinn::crypto::rustls::QuicClientConfig::try_from(client_crypto)
@@ -84,6 +114,129 @@ pub fn build_client_config(
     Ok(client_config)
 }
 
+/// Largest control message accepted on a uni stream.
+const MAX_CONTROL_MESSAGE: usize = 1024 * 1024;
+
+/// Send `msg` on a new uni stream of `connection`.
+pub async fn send_control_message(connection: &quinn::Connection, msg: &ControlMessage) -> Result<()> {
+    let mut send = connection.open_uni().await?;
+    send.write_all(&msg.encode()?).await?;
+    send.finish()?;
+    Ok(())
+}
+
+/// Read the length-prefixed control message at the start of a uni stream.
+/// A `BinaryUpdate` leaves its payload in `recv`.
+pub async fn read_control_message(recv: &mut quinn::RecvStream) -> Result<ControlMessage> {
+    let mut len_buf = [0u8; 4];
+    recv.read_exact(&mut len_buf).await?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+    anyhow::ensure!(len <= MAX_CONTROL_MESSAGE, "Control message too large: {} bytes", len);
+    let mut json_buf = vec![0u8; len];
+    recv.read_exact(&mut json_buf).await?;
+    Ok(serde_json::from_slice(&json_buf)?)
+}
+
+/// Read the `StreamHeader` at the start of a relayed stream, and nothing
+/// past it: what follows is the client's data.
+pub async fn read_stream_header(recv: &mut quinn::RecvStream) -> Result<StreamHeader> {
+    let mut buf = vec![0u8; 2];
+    recv.read_exact(&mut buf).await?;
+    let (version, ip_type) = (buf[0], buf[1]);
+    anyhow::ensure!(
+        version == 1 || version == 2,
+        "Unsupported StreamHeader version {}",
+        version
+    );
+    let fixed = buf.len();
+    buf.resize(fixed + ip_len(ip_type)? + 8, 0);
+    recv.read_exact(&mut buf[fixed..]).await?;
+    if version == 2 {
+        let mut proxy_type = [0u8; 1];
+        recv.read_exact(&mut proxy_type).await?;
+        buf.push(proxy_type[0]);
+        let start = buf.len();
+        buf.resize(start + ip_len(proxy_type[0])?, 0);
+        recv.read_exact(&mut buf[start..]).await?;
+    }
+    StreamHeader::decode(&mut &buf[..])
+}
+
+/// Common name of the client certificate a connection was authenticated with.
+pub fn peer_common_name(connection: &quinn::Connection) -> Option<String> {
+    let identity = connection.peer_identity()?;
+    let certs = identity.downcast::<Vec<CertificateDer<'static>>>().ok()?;
+    crate::crypto::certificate_common_name(certs.first()?)
+}
+
+/// SHA-256 fingerprint of the certificate the peer presented.
+pub fn peer_certificate_sha256(connection: &quinn::Connection) -> Option<String> {
+    let identity = connection.peer_identity()?;
+    let certs = identity.downcast::<Vec<CertificateDer<'static>>>().ok()?;
+    Some(crate::crypto::certificate_sha256(certs.first()?))
+}
+
+/// Lowercase hex without separators, as `certificate_sha256` returns.
+pub fn normalize_pin(pin: &str) -> String {
+    pin.chars()
+        .filter(|c| c.is_ascii_hexdigit())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// CA verification, then the certificate pins.
+#[derive(Debug)]
+struct PinnedServerVerifier {
+    inner: Arc<rustls::client::WebPkiServerVerifier>,
+    pins: Vec<String>,
+}
+
+impl ServerCertVerifier for PinnedServerVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let verified = self
+            .inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        let fingerprint = crate::crypto::certificate_sha256(end_entity);
+        if self.pins.contains(&fingerprint) {
+            Ok(verified)
+        } else {
+            Err(rustls::Error::General(format!(
+                "Relay certificate {} is not pinned",
+                fingerprint
+            )))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
 fn load_certs(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>> {
     let mut reader = std::io::BufReader::new(pem);
     let certs: Vec<_> = rustls_pemfile::certs(&mut reader)
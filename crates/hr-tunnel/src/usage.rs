@@ -0,0 +1,318 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+const GB: u64 = 1_000_000_000;
+
+/// Route of the traffic no SNI could be attributed to.
+pub const UNKNOWN_ROUTE: &str = "unknown";
+
+/// Limits of the traffic through the cloud relay (relay-usage-config.json).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelayUsageConfig {
+    /// Relay traffic allowed per calendar month (UTC), both directions, in
+    /// GB; new streams are refused once it is reached. 0 for no cap.
+    #[serde(default)]
+    pub monthly_cap_gb: u64,
+    /// Rate of each tunnel stream and direction, in Mbit/s. 0 for no limit.
+    #[serde(default)]
+    pub stream_mbit: u32,
+}
+
+impl Default for RelayUsageConfig {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+impl RelayUsageConfig {
+    pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.monthly_cap_gb > 1_000_000 {
+            return Err("Monthly cap is limited to 1 PB".to_string());
+        }
+        if self.stream_mbit > 100_000 {
+            return Err("Stream rate is limited to 100 Gbit/s".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Traffic of one route (the SNI of the streams, or `udp:<port>`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteUsage {
+    /// Bytes from internet clients to HomeRoute.
+    pub bytes_in: u64,
+    /// Bytes from HomeRoute to internet clients.
+    pub bytes_out: u64,
+    pub streams: u64,
+}
+
+/// Relay traffic of a calendar month.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonthUsage {
+    /// `YYYY-MM`, UTC.
+    pub month: String,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub streams: u64,
+    /// Streams and datagrams refused because the cap was reached.
+    pub refused: u64,
+    pub routes: BTreeMap<String, RouteUsage>,
+}
+
+impl MonthUsage {
+    fn new(month: String) -> Self {
+        Self {
+            month,
+            ..Default::default()
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.bytes_in + self.bytes_out
+    }
+}
+
+/// Relay traffic accounting of the tunnel client: totals of the current
+/// month per route, persisted to `path`, and the limits applied to the
+/// streams.
+pub struct RelayUsage {
+    config: RwLock<RelayUsageConfig>,
+    current: Mutex<MonthUsage>,
+    path: PathBuf,
+}
+
+impl RelayUsage {
+    /// Start from the totals saved in `path` when they are of this month.
+    pub fn new(config: RelayUsageConfig, path: PathBuf) -> Self {
+        let month = current_month();
+        let current = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<MonthUsage>(&content).ok())
+            .filter(|usage| usage.month == month)
+            .unwrap_or_else(|| MonthUsage::new(month));
+        Self {
+            config: RwLock::new(config),
+            current: Mutex::new(current),
+            path,
+        }
+    }
+
+    pub fn config(&self) -> RelayUsageConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Replace the limits; the rate applies to the streams opened from now.
+    pub fn apply(&self, config: RelayUsageConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Totals of the current month.
+    pub fn current(&self) -> MonthUsage {
+        let mut current = self.current.lock().unwrap();
+        roll_over(&mut current);
+        current.clone()
+    }
+
+    /// Whether the monthly cap is reached.
+    pub fn cap_reached(&self) -> bool {
+        let cap = self.config.read().unwrap().monthly_cap_gb;
+        if cap == 0 {
+            return false;
+        }
+        let mut current = self.current.lock().unwrap();
+        roll_over(&mut current);
+        current.total() >= cap * GB
+    }
+
+    /// Count a new stream or datagram flow, or refuse it once the cap is
+    /// reached.
+    pub fn admit(&self) -> bool {
+        let reached = self.cap_reached();
+        let mut current = self.current.lock().unwrap();
+        roll_over(&mut current);
+        if reached {
+            current.refused += 1;
+            return false;
+        }
+        current.streams += 1;
+        true
+    }
+
+    /// Count a stream admitted earlier on `route`.
+    pub fn add_stream(&self, route: &str) {
+        let mut current = self.current.lock().unwrap();
+        roll_over(&mut current);
+        current.routes.entry(route.to_string()).or_default().streams += 1;
+    }
+
+    /// Add traffic to `route`.
+    pub fn record(&self, route: &str, bytes_in: u64, bytes_out: u64) {
+        if bytes_in == 0 && bytes_out == 0 {
+            return;
+        }
+        let cap = self.config.read().unwrap().monthly_cap_gb * GB;
+        let mut current = self.current.lock().unwrap();
+        roll_over(&mut current);
+        let before = current.total();
+        current.bytes_in += bytes_in;
+        current.bytes_out += bytes_out;
+        let entry = current.routes.entry(route.to_string()).or_default();
+        entry.bytes_in += bytes_in;
+        entry.bytes_out += bytes_out;
+        if cap > 0 && before < cap && current.total() >= cap {
+            warn!(
+                "Cloud relay monthly cap of {} GB reached, new streams are refused until next month",
+                cap / GB
+            );
+        }
+    }
+
+    /// Meter of a new stream, with the configured rate.
+    pub fn meter(self: &Arc<Self>) -> StreamMeter {
+        let rate = u64::from(self.config.read().unwrap().stream_mbit) * 1_000_000 / 8;
+        StreamMeter {
+            usage: self.clone(),
+            route: OnceLock::new(),
+            pending_in: AtomicU64::new(0),
+            pending_out: AtomicU64::new(0),
+            rate: (rate > 0).then_some(rate),
+        }
+    }
+
+    /// Forget the totals of the current month.
+    pub fn reset(&self) {
+        *self.current.lock().unwrap() = MonthUsage::new(current_month());
+    }
+
+    /// Write the totals of the current month to disk.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(&self.current())?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+pub type SharedRelayUsage = Arc<RelayUsage>;
+
+/// Byte counters of one tunnel stream. The route (SNI) is only known
+/// after the TLS handshake: bytes seen before are held back until then.
+pub struct StreamMeter {
+    usage: Arc<RelayUsage>,
+    route: OnceLock<String>,
+    pending_in: AtomicU64,
+    pending_out: AtomicU64,
+    rate: Option<u64>,
+}
+
+impl StreamMeter {
+    /// Attribute the stream to `route`.
+    pub fn set_route(&self, route: &str) {
+        let route = route.to_ascii_lowercase();
+        self.usage.add_stream(&route);
+        if self.route.set(route).is_ok() {
+            self.flush();
+        }
+    }
+
+    pub fn add_in(&self, bytes: u64) {
+        match self.route.get() {
+            Some(route) => self.usage.record(route, bytes, 0),
+            None => {
+                self.pending_in.fetch_add(bytes, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn add_out(&self, bytes: u64) {
+        match self.route.get() {
+            Some(route) => self.usage.record(route, 0, bytes),
+            None => {
+                self.pending_out.fetch_add(bytes, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Pacer of one direction of the stream.
+    pub fn pacer(&self) -> Pacer {
+        Pacer {
+            rate: self.rate,
+            start: Instant::now(),
+            bytes: 0,
+        }
+    }
+
+    fn flush(&self) {
+        let route = self.route.get().map(String::as_str).unwrap_or(UNKNOWN_ROUTE);
+        let bytes_in = self.pending_in.swap(0, Ordering::Relaxed);
+        let bytes_out = self.pending_out.swap(0, Ordering::Relaxed);
+        self.usage.record(route, bytes_in, bytes_out);
+    }
+}
+
+impl Drop for StreamMeter {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Keeps one direction of a stream under the configured rate.
+pub struct Pacer {
+    rate: Option<u64>,
+    start: Instant,
+    bytes: u64,
+}
+
+impl Pacer {
+    /// Account `bytes` just copied, sleeping when ahead of the rate.
+    pub async fn pace(&mut self, bytes: usize) {
+        let Some(rate) = self.rate else {
+            return;
+        };
+        self.bytes += bytes as u64;
+        let due = Duration::from_secs_f64(self.bytes as f64 / rate as f64);
+        let elapsed = self.start.elapsed();
+        if due > elapsed {
+            tokio::time::sleep(due - elapsed).await;
+        }
+    }
+}
+
+fn current_month() -> String {
+    let now = time::OffsetDateTime::now_utc();
+    format!("{:04}-{:02}", now.year(), u8::from(now.month()))
+}
+
+/// Start a new month of totals when the calendar month changed.
+fn roll_over(current: &mut MonthUsage) {
+    let month = current_month();
+    if current.month != month {
+        *current = MonthUsage::new(month);
+    }
+}
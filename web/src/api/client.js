@@ -18,6 +18,12 @@ api.interceptors.response.use(
     return response;
   },
   (error) => {
+    // Typed error envelope: { success: false, code, error }
+    const data = error.response?.data;
+    if (data && data.success === false && data.code) {
+      error.code = data.code;
+      error.message = data.error;
+    }
     // Handle 401 errors
     if (error.response && error.response.status === 401) {
       // Force cookie deletion
@@ -30,10 +36,12 @@ api.interceptors.response.use(
 
 // Services Status
 export const getServicesStatus = () => api.get('/services/status');
+export const serviceAction = (name, action) => api.post(`/services/${encodeURIComponent(name)}/${action}`);
 
 // DNS/DHCP
 export const getDnsConfig = () => api.get('/dns-dhcp/config');
 export const getDhcpLeases = () => api.get('/dns-dhcp/leases');
+export const getDnsDiagnostics = () => api.get('/dns/diagnostics');
 
 // AdBlock
 export const getAdblockStats = () => api.get('/adblock/stats');
@@ -42,12 +50,46 @@ export const addToWhitelist = (domain) => api.post('/adblock/whitelist', { domai
 export const removeFromWhitelist = (domain) => api.delete(`/adblock/whitelist/${domain}`);
 export const updateAdblockLists = () => api.post('/adblock/update');
 export const searchBlocked = (query) => api.get('/adblock/search', { params: { q: query } });
+export const explainBlocked = (domain, cname) => api.get('/adblock/explain', { params: { domain, cname } });
+export const pauseAdblock = (minutes, client) => api.post('/adblock/pause', { minutes, client });
+export const resumeAdblock = (client) => api.delete('/adblock/pause', { params: { client } });
+export const getAdblockSchedule = () => api.get('/adblock/schedule');
+export const getAdblockProfiles = () => api.get('/adblock/profiles');
+export const updateAdblockProfiles = (profiles) => api.put('/adblock/profiles', { profiles });
+export const getBlockedQueries = (since, client) => api.get('/adblock/blocked', { params: { since, client } });
+export const allowBlockedQuery = (id) => api.post(`/adblock/blocked/${id}/allow`);
+export const allowDomainTemporarily = (domain, client, minutes) => api.post('/adblock/allow', { domain, client, minutes });
+export const getSafeSearch = () => api.get('/adblock/safesearch');
+export const updateSafeSearch = (config) => api.put('/adblock/safesearch', config);
 
 // DDNS
 export const getDdnsStatus = () => api.get('/ddns/status');
 export const forceDdnsUpdate = () => api.post('/ddns/update');
 export const updateDdnsToken = (token) => api.put('/ddns/token', { token });
 export const updateDdnsConfig = (config) => api.put('/ddns/config', config);
+export const getDdnsSettings = () => api.get('/ddns/settings');
+export const updateDdnsSettings = (config) => api.put('/ddns/settings', config);
+
+// Tailscale / Headscale
+export const getTailscaleStatus = () => api.get('/network/tailscale');
+export const updateTailscaleConfig = (config) => api.put('/network/tailscale', config);
+
+// Traffic shaping (QoS)
+export const getQos = () => api.get('/network/qos');
+export const updateQos = (config) => api.put('/network/qos', config);
+export const getRelayUsage = () => api.get('/network/relay/usage');
+export const resetRelayUsage = () => api.delete('/network/relay/usage');
+export const updateRelayUsageConfig = (config) => api.put('/network/relay/usage/config', config);
+export const getRelayCerts = () => api.get('/network/relay/certs');
+export const updateRelayPins = (pins) => api.put('/network/relay/certs/pins', { pins });
+export const renewRelayClientCert = () => api.post('/network/relay/certs/renew');
+export const getDiscovery = () => api.get('/network/discovery');
+export const createDiscoveryRoute = (id, route) => api.post(`/network/discovery/${id}/route`, route);
+export const getNetworkDevices = () => api.get('/network/devices');
+export const updateNetworkDevicesConfig = (config) => api.put('/network/devices/config', config);
+export const scanNetworkDevices = () => api.post('/network/devices/scan');
+export const forgetNetworkDevice = (mac) => api.delete(`/network/devices/${mac}`);
+export const wakeNetworkDevice = (mac) => api.post(`/network/devices/${mac}/wake`);
 
 // Reverse Proxy
 export const getReverseProxyConfig = () => api.get('/reverseproxy/config');
@@ -89,6 +131,12 @@ export const runAptFullUpgrade = () => api.post('/updates/upgrade/apt-full', {},
 export const runSnapRefresh = () => api.post('/updates/upgrade/snap', {}, { timeout: 1800000 });
 export const cancelUpgrade = () => api.post('/updates/upgrade/cancel');
 
+// System Updates - Remote hosts
+export const getHostsUpdates = () => api.get('/updates/hosts');
+export const checkHostUpdates = (id) => api.post(`/updates/hosts/${id}/check`);
+export const getHostUpgrade = (id) => api.get(`/updates/hosts/${id}/upgrade`);
+export const upgradeHost = (id, options = {}) => api.post(`/updates/hosts/${id}/upgrade`, options);
+
 // Energy - CPU Info
 export const getCpuInfo = () => api.get('/energy/cpu');
 
@@ -110,6 +158,12 @@ export const getBenchmarkStatus = () => api.get('/energy/benchmark');
 export const startBenchmark = (duration = 60) => api.post('/energy/benchmark/start', { duration });
 export const stopBenchmark = () => api.post('/energy/benchmark/stop');
 
+// Energy - Power meters
+export const getPowerMeters = () => api.get('/energy/meters');
+export const updatePowerMeters = (config) => api.put('/energy/meters', config);
+export const getConsumption = (params) => api.get('/energy/consumption', { params });
+export const getHostConsumption = () => api.get('/energy/hosts');
+
 // Users - Authelia Status
 export const getAutheliaStatus = () => api.get('/users/authelia/status');
 export const getAutheliaInstallInstructions = () => api.get('/users/authelia/install');
@@ -145,6 +199,22 @@ export const rebootHost = (id) => api.post(`/hosts/${id}/reboot`);
 export const sleepHost = (id) => api.post(`/hosts/${id}/sleep`);
 export const setWolMac = (id, mac) => api.post(`/hosts/${id}/wol-mac`, { mac });
 export const setAutoOff = (id, mode, minutes) => api.post(`/hosts/${id}/auto-off`, { mode, minutes });
+export const getHostSchedules = (id) => api.get(`/hosts/${id}/schedules`);
+export const updateHostSchedules = (id, schedules) => api.put(`/hosts/${id}/schedules`, schedules);
+export const rotateHostToken = (id, graceMinutes = 60) => api.post(`/hosts/${id}/rotate-token`, { grace_minutes: graceMinutes });
+export const revokeHostToken = (id) => api.post(`/hosts/${id}/revoke-token`);
+export const createHostJoinCode = (data) => api.post('/hosts/join-codes', data);
+export const getHostDisks = (id) => api.get(`/hosts/${id}/disks`);
+export const getHostServices = (id) => api.get(`/hosts/${id}/services`);
+export const getHostServiceStatus = (id, unit) => api.get(`/hosts/${id}/services/${encodeURIComponent(unit)}`);
+export const hostServiceAction = (id, unit, action) =>
+  api.post(`/hosts/${id}/services/${encodeURIComponent(unit)}/${action}`);
+export const getHostDrain = (id) => api.get(`/hosts/${id}/drain`);
+export const drainHost = (id, targetHostId) => api.post(`/hosts/${id}/drain`, { target_host_id: targetHostId || null });
+export const undrainHost = (id) => api.delete(`/hosts/${id}/drain`);
+export const getHostContainers = (id) => api.get(`/hosts/${id}/containers`);
+export const runtimeContainerAction = (id, runtime, name, action) =>
+  api.post(`/hosts/${id}/containers/${runtime}/${encodeURIComponent(name)}/${action}`);
 export const updateHostAgents = () => api.post('/hosts/agents/update');
 export const updateLocalHostConfig = (data) => api.put('/hosts/local/config', data);
 export const getLocalInterfaces = () => api.get('/hosts/local/interfaces');
@@ -156,26 +226,90 @@ export const disableCloudRelay = () => api.post('/cloud-relay/disable');
 export const bootstrapCloudRelay = (data) => api.post('/cloud-relay/bootstrap', data, { timeout: 300000 });
 export const updateCloudRelayConfig = (config) => api.put('/cloud-relay/config', config);
 export const pushCloudRelayUpdate = () => api.post('/cloud-relay/update', {}, { timeout: 120000 });
+export const provisionCloudRelay = async (options) => {
+  const res = await api.post('/network/relay/provision', options, { responseType: 'blob', timeout: 120000 });
+  if (res.headers['content-type']?.includes('application/json')) {
+    return JSON.parse(await res.data.text());
+  }
+  const match = /filename="([^"]+)"/.exec(res.headers['content-disposition'] || '');
+  const a = document.createElement('a');
+  a.href = URL.createObjectURL(res.data);
+  a.download = match ? match[1] : 'hr-cloud-relay-install.sh';
+  document.body.appendChild(a);
+  a.click();
+  document.body.removeChild(a);
+  URL.revokeObjectURL(a.href);
+  return { success: true };
+};
+
+// Configuration backup
+export const downloadConfigBackup = async (passphrase, includeAcme) => {
+  let res;
+  try {
+    res = await api.post('/updates/backup', { passphrase, includeAcme }, { responseType: 'blob', timeout: 120000 });
+  } catch (e) {
+    if (e.response?.data instanceof Blob) return JSON.parse(await e.response.data.text());
+    throw e;
+  }
+  const match = /filename="([^"]+)"/.exec(res.headers['content-disposition'] || '');
+  const a = document.createElement('a');
+  a.href = URL.createObjectURL(res.data);
+  a.download = match ? match[1] : 'homeroute-backup.hrbak';
+  document.body.appendChild(a);
+  a.click();
+  document.body.removeChild(a);
+  URL.revokeObjectURL(a.href);
+  return { success: true };
+};
+export const restoreConfigBackup = (archive, passphrase) =>
+  api.post('/updates/restore', { archive, passphrase }, { timeout: 120000 });
 
 // Containers (nspawn)
 export const getContainers = () => api.get('/containers');
 export const createContainer = (data) => api.post('/containers', data);
+export const previewPlacement = (constraints = {}) => api.post('/applications/placement/preview', constraints);
 export const updateContainer = (id, data) => api.put(`/containers/${id}`, data);
 export const deleteContainer = (id) => api.delete(`/containers/${id}`);
 export const startContainer = (id) => api.post(`/containers/${id}/start`);
 export const stopContainer = (id) => api.post(`/containers/${id}/stop`);
-export const migrateContainer = (id, targetHostId) => api.post(`/containers/${id}/migrate`, { target_host_id: targetHostId });
+export const migrateContainer = (id, targetHostId, compressionLevel) => api.post(`/containers/${id}/migrate`, { target_host_id: targetHostId, compression_level: compressionLevel });
+export const preflightMigration = (id, targetHostId, compressionLevel) => api.post(`/containers/${id}/migrate?dry_run=true`, { target_host_id: targetHostId, compression_level: compressionLevel });
 export const getMigrationStatus = (id) => api.get(`/containers/${id}/migrate/status`);
 export const cancelMigration = (id) => api.post(`/containers/${id}/migrate/cancel`);
 export const getContainersConfig = () => api.get('/containers/config');
 export const updateContainersConfig = (data) => api.put('/containers/config', data);
 export const renameContainer = (id, data) => api.post(`/containers/${id}/rename`, data);
 export const getRenameStatus = (id) => api.get(`/containers/${id}/rename/status`);
+export const getContainerTemplates = () => api.get('/applications/templates');
+
+// Process apps (supervised on the host, no container)
+export const getProcesses = () => api.get('/processes');
+export const createProcess = (data) => api.post('/processes', data);
+export const updateProcess = (id, data) => api.put(`/processes/${id}`, data);
+export const deleteProcess = (id) => api.delete(`/processes/${id}`);
+export const startProcess = (id) => api.post(`/processes/${id}/start`);
+export const stopProcess = (id) => api.post(`/processes/${id}/stop`);
+export const restartProcess = (id) => api.post(`/processes/${id}/restart`);
+export const getProcessLogs = (id) => api.get(`/processes/${id}/logs`);
 
 // Prod status/logs (queried via dev container's linked prod)
 export const getProdStatus = (devAppId) => api.get(`/applications/${devAppId}/prod/status`);
 export const getProdLogs = (devAppId, lines = 50) => api.get(`/applications/${devAppId}/prod/logs`, { params: { lines } });
 
+// Blue/green deployments (progress: 'deployment:progress' WebSocket events)
+export const getDeployStatus = (appId) => api.get(`/applications/${appId}/deploy/status`);
+export const rollbackDeploy = (appId) => api.post(`/applications/${appId}/deploy/rollback`);
+export const discardDeployStandby = (appId) => api.delete(`/applications/${appId}/deploy/standby`);
+
+// Live logs: JSON frames {type: 'lines'|'dropped'|'error', ...}
+export const openLogStream = (appId, { unit, lines, follow = true } = {}) => {
+  const proto = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
+  const params = new URLSearchParams({ follow });
+  if (unit) params.set('unit', unit);
+  if (lines) params.set('lines', lines);
+  return new WebSocket(`${proto}//${window.location.host}/api/applications/${appId}/logs/stream?${params}`);
+};
+
 // Dataverse
 export const getDataverseOverview = () => api.get('/dataverse/overview');
 export const getDataverseSchema = (appId) => api.get(`/dataverse/apps/${appId}/schema`);
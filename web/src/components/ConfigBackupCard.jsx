@@ -0,0 +1,115 @@
+import { useState } from 'react';
+import { Archive, Download, Upload } from 'lucide-react';
+import Card from './Card';
+import Button from './Button';
+import { downloadConfigBackup, restoreConfigBackup } from '../api/client';
+
+function readAsBase64(file) {
+  return new Promise((resolve, reject) => {
+    const reader = new FileReader();
+    reader.onload = () => resolve(reader.result.split(',', 2)[1] || '');
+    reader.onerror = () => reject(reader.error);
+    reader.readAsDataURL(file);
+  });
+}
+
+function ConfigBackupCard() {
+  const [passphrase, setPassphrase] = useState('');
+  const [includeAcme, setIncludeAcme] = useState(false);
+  const [file, setFile] = useState(null);
+  const [restorePassphrase, setRestorePassphrase] = useState('');
+  const [busy, setBusy] = useState(null);
+  const [message, setMessage] = useState(null);
+
+  async function handleBackup() {
+    setBusy('backup');
+    setMessage(null);
+    try {
+      const res = await downloadConfigBackup(passphrase, includeAcme);
+      if (!res.success) setMessage({ type: 'error', text: res.error });
+    } catch (e) {
+      setMessage({ type: 'error', text: e.message });
+    } finally {
+      setBusy(null);
+    }
+  }
+
+  async function handleRestore() {
+    if (!confirm('Remplacer toute la configuration par celle de la sauvegarde ?')) return;
+    setBusy('restore');
+    setMessage(null);
+    try {
+      const archive = await readAsBase64(file);
+      const res = await restoreConfigBackup(archive, restorePassphrase);
+      if (res.data.success) {
+        const restart = res.data.restartRequired || [];
+        setMessage({
+          type: restart.length ? 'warning' : 'success',
+          text: `Sauvegarde du ${new Date(res.data.manifest.createdAt).toLocaleString('fr-FR')} restauree`
+            + (restart.length ? ` - redemarrer homeroute pour appliquer : ${restart.join(', ')}` : ''),
+        });
+        setFile(null);
+        setRestorePassphrase('');
+      } else {
+        setMessage({ type: 'error', text: res.data.error });
+      }
+    } catch (e) {
+      setMessage({ type: 'error', text: e.response?.data?.error || e.message });
+    } finally {
+      setBusy(null);
+    }
+  }
+
+  const inputClass = 'w-full bg-gray-900 border border-gray-700 px-3 py-1.5 text-sm';
+
+  return (
+    <Card title="Sauvegarde de la configuration" icon={Archive}>
+      <div className="grid md:grid-cols-2 gap-6">
+        <div className="space-y-3">
+          <p className="text-sm text-gray-400">
+            Archive chiffree de toutes les configurations (DNS/DHCP, proxy, hotes, utilisateurs, services).
+          </p>
+          <input
+            type="password"
+            className={inputClass}
+            placeholder="Phrase de passe (8 caracteres min.)"
+            value={passphrase}
+            onChange={e => setPassphrase(e.target.value)}
+          />
+          <label className="flex items-center gap-2 text-sm text-gray-300">
+            <input type="checkbox" checked={includeAcme} onChange={e => setIncludeAcme(e.target.checked)} />
+            Inclure le compte et les certificats ACME
+          </label>
+          <Button onClick={handleBackup} loading={busy === 'backup'} disabled={passphrase.length < 8 || busy !== null}>
+            <Download className="w-4 h-4" /> Telecharger
+          </Button>
+        </div>
+        <div className="space-y-3">
+          <p className="text-sm text-gray-400">
+            Restaure une archive : tout est verifie avant de remplacer les fichiers.
+          </p>
+          <input type="file" accept=".hrbak" className="text-sm" onChange={e => setFile(e.target.files[0] || null)} />
+          <input
+            type="password"
+            className={inputClass}
+            placeholder="Phrase de passe de l'archive"
+            value={restorePassphrase}
+            onChange={e => setRestorePassphrase(e.target.value)}
+          />
+          <Button variant="warning" onClick={handleRestore} loading={busy === 'restore'} disabled={!file || !restorePassphrase || busy !== null}>
+            <Upload className="w-4 h-4" /> Restaurer
+          </Button>
+        </div>
+      </div>
+      {message && (
+        <p className={`mt-4 text-sm ${
+          message.type === 'success' ? 'text-green-400' : message.type === 'error' ? 'text-red-400' : 'text-yellow-400'
+        }`}>
+          {message.text}
+        </p>
+      )}
+    </Card>
+  );
+}
+
+export default ConfigBackupCard;
@@ -1,6 +1,10 @@
-import { useState } from 'react';
-import { Key, Shield, Code2, HardDrive } from 'lucide-react';
+import { useEffect, useState } from 'react';
+import { Key, Shield, Code2, HardDrive, Package } from 'lucide-react';
 import Button from './Button';
+import { getContainerTemplates, previewPlacement } from '../api/client';
+
+// Development containers need apt and systemd (code-server)
+const DEV_DISTROS = ['ubuntu', 'debian'];
 
 function CreateContainerModal({
   baseDomain,
@@ -24,9 +28,28 @@ function CreateContainerModal({
     frontend: { auth_required: true, allowed_groups: [], local_only: false },
     code_server_enabled: isPaired ? (initialEnvironment !== 'production') : true,
     linked_app_id: initialLinkedAppId || '',
+    template: 'ubuntu-noble',
   });
+  const [templates, setTemplates] = useState([]);
+  const [placement, setPlacement] = useState(null);
+
+  useEffect(() => {
+    getContainerTemplates()
+      .then(res => setTemplates(res.data.templates || []))
+      .catch(() => setTemplates([]));
+  }, []);
+
+  useEffect(() => {
+    if (form.host_id !== 'auto') return;
+    previewPlacement()
+      .then(res => setPlacement(res.data))
+      .catch(() => setPlacement(null));
+  }, [form.host_id]);
+
+  const placedHost = placement?.hosts?.find(h => h.host_id === placement.host_id);
 
   const isDev = form.environment === 'development';
+  const availableTemplates = templates.filter(t => !isDev || DEV_DISTROS.includes(t.distro));
 
   function handleSubmit() {
     if (!form.name || !form.slug) return;
@@ -44,6 +67,7 @@ function CreateContainerModal({
       },
       code_server_enabled: isDev ? form.code_server_enabled : false,
       linked_app_id: form.linked_app_id || null,
+      template: form.template || null,
     };
 
     onCreate(payload);
@@ -130,13 +154,40 @@ function CreateContainerModal({
               onChange={e => setForm({ ...form, host_id: e.target.value })}
               className="w-full px-3 py-2 bg-gray-900 border border-gray-600 text-sm"
             >
+              <option value="auto">Automatique</option>
               <option value="local">HomeRoute (local)</option>
-              {hosts.filter(h => h.status === 'online').map(h => (
+              {hosts.filter(h => h.status === 'online' && !h.draining).map(h => (
                 <option key={h.id} value={h.id}>{h.name} ({h.host})</option>
               ))}
             </select>
+            {form.host_id === 'auto' && placement && (
+              <p className="text-xs text-gray-500 mt-1">
+                {placedHost
+                  ? `${placedHost.name} : ${placedHost.reasons.join(', ')}`
+                  : 'Aucun hote disponible'}
+              </p>
+            )}
           </div>
 
+          {/* Template selector */}
+          {availableTemplates.length > 0 && (
+            <div>
+              <label className="block text-sm text-gray-400 mb-1">
+                <Package className="w-3.5 h-3.5 inline mr-1" />
+                Template
+              </label>
+              <select
+                value={form.template}
+                onChange={e => setForm({ ...form, template: e.target.value })}
+                className="w-full px-3 py-2 bg-gray-900 border border-gray-600 text-sm"
+              >
+                {availableTemplates.map(t => (
+                  <option key={t.id} value={t.id}>{t.name}{t.builtin ? '' : ' (personnalise)'}</option>
+                ))}
+              </select>
+            </div>
+          )}
+
           {/* Auto-creation note (only when creating from scratch) */}
           {!isPaired && (
             <p className="text-xs text-gray-500">Un conteneur de production sera automatiquement créé</p>
@@ -1,4 +1,6 @@
-import { Activity, CheckCircle, XCircle, MinusCircle, AlertTriangle } from 'lucide-react';
+import { useState } from 'react';
+import { Activity, CheckCircle, XCircle, MinusCircle, AlertTriangle, Play, Square, RotateCw } from 'lucide-react';
+import { serviceAction } from '../api/client';
 
 const stateConfig = {
   running:  { icon: CheckCircle,   color: 'text-green-400',  label: 'Actif' },
@@ -14,9 +16,45 @@ const priorityLabel = {
   background: 'Arriere-plan',
 };
 
-function ServiceStatusPanel({ services }) {
+const actionLabel = {
+  start:   'Demarrer',
+  stop:    'Arreter',
+  restart: 'Redemarrer',
+};
+
+function ServiceStatusPanel({ services, onServiceChange }) {
+  const [busy, setBusy] = useState(null);
+  const [error, setError] = useState(null);
+
   if (!services || services.length === 0) return null;
 
+  async function runAction(name, action) {
+    setBusy(name);
+    setError(null);
+    try {
+      const res = await serviceAction(name, action);
+      if (res.data.success) {
+        if (res.data.service) onServiceChange?.(res.data.service);
+      } else {
+        setError(`${name} : ${res.data.error}`);
+      }
+    } catch (e) {
+      setError(`${name} : ${e.response?.data?.error || e.message}`);
+    } finally {
+      setBusy(null);
+    }
+  }
+
+  function actionsFor(svc) {
+    if (svc.state === 'disabled') return [];
+    if (svc.state === 'running' || svc.state === 'starting') {
+      return svc.name === 'api' ? ['restart'] : ['restart', 'stop'];
+    }
+    return ['start'];
+  }
+
+  const actionIcon = { start: Play, stop: Square, restart: RotateCw };
+
   const grouped = { critical: [], important: [], background: [] };
   for (const svc of services) {
     (grouped[svc.priority] || grouped.background).push(svc);
@@ -29,6 +67,7 @@ function ServiceStatusPanel({ services }) {
         <h3 className="font-semibold text-sm">Services</h3>
       </div>
       <div className="p-3 space-y-4">
+        {error && <p className="text-xs text-red-400">{error}</p>}
         {Object.entries(grouped).map(([priority, svcs]) =>
           svcs.length > 0 && (
             <div key={priority}>
@@ -39,11 +78,33 @@ function ServiceStatusPanel({ services }) {
                 {svcs.map(svc => {
                   const cfg = stateConfig[svc.state] || stateConfig.stopped;
                   const Icon = cfg.icon;
+                  const history = (svc.restartHistory || [])
+                    .slice(-5)
+                    .map(r => `${new Date(r.at).toLocaleString('fr-FR')} : ${r.error}`);
                   return (
-                    <div key={svc.name} className="flex items-center justify-between px-2 py-1.5 hover:bg-gray-700/50" title={svc.error || cfg.label}>
+                    <div key={svc.name} className="flex items-center justify-between px-2 py-1.5 hover:bg-gray-700/50" title={[svc.error || cfg.label, ...history].join('\n')}>
                       <span className="text-sm font-mono truncate">{svc.name}</span>
                       <div className="flex items-center gap-1.5">
+                        {svc.circuitOpen && (
+                          <span className="text-xs text-red-400">
+                            {svc.retryAt ? `pause jusqu'a ${new Date(svc.retryAt).toLocaleTimeString('fr-FR')}` : 'abandonne'}
+                          </span>
+                        )}
                         {svc.restartCount > 0 && <span className="text-xs text-yellow-500">{svc.restartCount}x</span>}
+                        {actionsFor(svc).map(action => {
+                          const ActionIcon = actionIcon[action];
+                          return (
+                            <button
+                              key={action}
+                              onClick={() => runAction(svc.name, action)}
+                              disabled={busy !== null}
+                              title={actionLabel[action]}
+                              className="p-0.5 text-gray-500 hover:text-gray-200 disabled:opacity-40"
+                            >
+                              <ActionIcon className={`w-3.5 h-3.5 ${busy === svc.name && action === 'restart' ? 'animate-spin' : ''}`} />
+                            </button>
+                          );
+                        })}
                         <Icon className={`w-4 h-4 ${cfg.color}`} />
                       </div>
                     </div>
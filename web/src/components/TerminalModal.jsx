@@ -0,0 +1,131 @@
+import { useEffect, useRef } from 'react';
+import { Terminal, X } from 'lucide-react';
+
+// Full-screen web terminal on a pty behind `path` (a terminal WebSocket
+// endpoint). Keystrokes go out as-is, size changes as resize messages.
+export default function TerminalModal({ path, title, subtitle, connectingLabel, onClose }) {
+  const termRef = useRef(null);
+  const termInstance = useRef(null);
+  const wsRef = useRef(null);
+
+  useEffect(() => {
+    let cancelled = false;
+    let removeResizeListener = null;
+
+    async function init() {
+      const { Terminal: XTerm } = await import('@xterm/xterm');
+      const { FitAddon } = await import('@xterm/addon-fit');
+      await import('@xterm/xterm/css/xterm.css');
+
+      if (cancelled || !termRef.current) return;
+
+      const fitAddon = new FitAddon();
+
+      const term = new XTerm({
+        cursorBlink: true,
+        fontSize: 14,
+        fontFamily: 'Menlo, Monaco, "Courier New", monospace',
+        theme: {
+          background: '#111827',
+          foreground: '#e5e7eb',
+          cursor: '#10b981',
+          selectionBackground: '#374151',
+        },
+      });
+
+      term.loadAddon(fitAddon);
+      term.open(termRef.current);
+      fitAddon.fit();
+      termInstance.current = term;
+
+      const proto = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
+      const sep = path.includes('?') ? '&' : '?';
+      const ws = new WebSocket(
+        `${proto}//${window.location.host}${path}${sep}cols=${term.cols}&rows=${term.rows}`
+      );
+      ws.binaryType = 'arraybuffer';
+      wsRef.current = ws;
+
+      ws.onopen = () => {
+        term.write('\r\n\x1b[32m' + (connectingLabel || 'Connexion...') + '\x1b[0m\r\n\r\n');
+      };
+
+      ws.onmessage = (event) => {
+        if (event.data instanceof ArrayBuffer) {
+          term.write(new Uint8Array(event.data));
+          return;
+        }
+        try {
+          const msg = JSON.parse(event.data);
+          if (msg.error) {
+            term.write('\r\n\x1b[31m' + msg.error + '\x1b[0m\r\n');
+            return;
+          }
+        } catch {
+          // plain terminal output
+        }
+        term.write(event.data);
+      };
+
+      ws.onclose = () => {
+        term.write('\r\n\x1b[31mConnexion fermee.\x1b[0m\r\n');
+      };
+
+      ws.onerror = () => {
+        term.write('\r\n\x1b[31mErreur de connexion.\x1b[0m\r\n');
+      };
+
+      term.onData((data) => {
+        if (ws.readyState === WebSocket.OPEN) {
+          ws.send(data);
+        }
+      });
+
+      term.onResize(({ cols, rows }) => {
+        if (ws.readyState === WebSocket.OPEN) {
+          ws.send(JSON.stringify({ type: 'resize', cols, rows }));
+        }
+      });
+
+      const handleResize = () => {
+        fitAddon.fit();
+      };
+      window.addEventListener('resize', handleResize);
+      removeResizeListener = () => window.removeEventListener('resize', handleResize);
+    }
+
+    init();
+
+    return () => {
+      cancelled = true;
+      if (removeResizeListener) removeResizeListener();
+      if (wsRef.current) {
+        wsRef.current.close();
+        wsRef.current = null;
+      }
+      if (termInstance.current) {
+        termInstance.current.dispose();
+        termInstance.current = null;
+      }
+    };
+  }, [path, connectingLabel]);
+
+  return (
+    <div className="fixed inset-0 bg-black/80 flex flex-col z-50">
+      <div className="flex items-center justify-between px-4 py-2 bg-gray-900 border-b border-gray-700">
+        <div className="flex items-center gap-2 text-sm">
+          <Terminal className="w-4 h-4 text-emerald-400" />
+          <span className="font-medium">{title}</span>
+          {subtitle && <span className="text-gray-500 font-mono">({subtitle})</span>}
+        </div>
+        <button
+          onClick={onClose}
+          className="text-gray-400 hover:text-white p-1 transition-colors"
+        >
+          <X className="w-5 h-5" />
+        </button>
+      </div>
+      <div ref={termRef} className="flex-1 p-2" style={{ backgroundColor: '#111827' }} />
+    </div>
+  );
+}
@@ -4,11 +4,17 @@ import { useEffect, useRef, useCallback } from 'react';
  * Hook to connect to the HomeRoute WebSocket and listen for events.
  * @param {Object<string, function>} handlers - Map of event type to handler function
  *   e.g. { 'servers:status': (data) => { ... }, 'updates:started': () => { ... } }
+ * @param {string[]} [topics] - Topics to subscribe to (e.g. ['services', 'hosts']).
+ *   Each one first sends a '<topic>:snapshot' event; after a reconnect, only the
+ *   missed events are replayed. Without topics, every event is received.
  */
-export default function useWebSocket(handlers) {
+export default function useWebSocket(handlers, topics) {
   const wsRef = useRef(null);
   const handlersRef = useRef(handlers);
   handlersRef.current = handlers;
+  const topicsKey = topics ? topics.join(',') : '';
+  // Resume point kept across reconnects
+  const resumeRef = useRef({ epoch: null, seq: null });
 
   useEffect(() => {
     const proto = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
@@ -16,6 +22,7 @@ export default function useWebSocket(handlers) {
 
     let ws;
     let reconnectTimer;
+    const resume = resumeRef.current;
 
     function connect() {
       ws = new WebSocket(url);
@@ -24,6 +31,22 @@ export default function useWebSocket(handlers) {
       ws.onmessage = (e) => {
         try {
           const msg = JSON.parse(e.data);
+          if (msg.type === 'hello') {
+            if (topicsKey) {
+              const subscribe = { op: 'subscribe', topics: topicsKey.split(',') };
+              if (resume.seq !== null) {
+                subscribe.since = resume.seq;
+                subscribe.epoch = resume.epoch;
+              }
+              ws.send(JSON.stringify(subscribe));
+            }
+            resume.epoch = msg.epoch;
+            if (resume.seq === null) resume.seq = msg.seq;
+            return;
+          }
+          if (typeof msg.seq === 'number') {
+            resume.seq = msg.seq;
+          }
           const handler = handlersRef.current[msg.type];
           if (handler) {
             handler(msg.data);
@@ -48,10 +71,11 @@ export default function useWebSocket(handlers) {
     return () => {
       clearTimeout(reconnectTimer);
       if (wsRef.current) {
+        wsRef.current.onclose = null;
         wsRef.current.close();
       }
     };
-  }, []);
+  }, [topicsKey]);
 
   return wsRef;
 }
@@ -1,5 +1,6 @@
 import { useState, useEffect } from 'react';
-import { Shield, RefreshCw, Plus, Trash2, Search, ExternalLink } from 'lucide-react';
+import { useSearchParams } from 'react-router-dom';
+import { Shield, RefreshCw, Plus, Trash2, Search, ExternalLink, Clock } from 'lucide-react';
 import Card from '../components/Card';
 import Button from '../components/Button';
 import PageHeader from '../components/PageHeader';
@@ -10,7 +11,8 @@ import {
   addToWhitelist,
   removeFromWhitelist,
   updateAdblockLists,
-  searchBlocked
+  searchBlocked,
+  allowDomainTemporarily
 } from '../api/client';
 
 function Adblock() {
@@ -22,6 +24,12 @@ function Adblock() {
   const [loading, setLoading] = useState(true);
   const [updating, setUpdating] = useState(false);
   const [searching, setSearching] = useState(false);
+  // Domain to allow, from the link of the "blocked" page
+  const [searchParams, setSearchParams] = useSearchParams();
+  const allowDomain = searchParams.get('allow');
+  const allowClient = searchParams.get('client');
+  const [allowing, setAllowing] = useState(false);
+  const [allowResult, setAllowResult] = useState(null);
 
   useEffect(() => {
     fetchData();
@@ -75,6 +83,23 @@ function Adblock() {
     }
   }
 
+  async function handleAllowTemporarily() {
+    setAllowing(true);
+    try {
+      const res = await allowDomainTemporarily(allowDomain, allowClient || undefined, 15);
+      if (res.data.success) {
+        setAllowResult({ ok: true, until: res.data.allow.until });
+        setSearchParams({});
+      } else {
+        setAllowResult({ ok: false, error: res.data.error });
+      }
+    } catch (error) {
+      setAllowResult({ ok: false, error: error.response?.data?.error || 'Erreur' });
+    } finally {
+      setAllowing(false);
+    }
+  }
+
   async function handleSearch() {
     if (searchQuery.length < 3) return;
     setSearching(true);
@@ -107,6 +132,32 @@ function Adblock() {
         </Button>
       </PageHeader>
 
+      {(allowDomain || allowResult) && (
+        <Section title="Autorisation temporaire">
+          <Card title={allowDomain || 'Domaine autorisé'} icon={Clock}>
+            {allowResult?.ok ? (
+              <p className="text-sm text-green-400">
+                Autorisé jusqu'à {new Date(allowResult.until).toLocaleTimeString('fr-FR')}.
+                Rechargez la page bloquée dans quelques secondes.
+              </p>
+            ) : (
+              <div className="flex items-center gap-3">
+                <p className="text-sm text-gray-400 flex-1">
+                  Débloquer {allowDomain} pendant 15 minutes
+                  {allowClient ? ` pour ${allowClient}` : ' pour tous les appareils'} ?
+                </p>
+                <Button onClick={handleAllowTemporarily} loading={allowing} variant="success">
+                  Autoriser
+                </Button>
+              </div>
+            )}
+            {allowResult?.ok === false && (
+              <p className="text-sm text-red-400 mt-2">{allowResult.error}</p>
+            )}
+          </Card>
+        </Section>
+      )}
+
       <Section title="Vue d'ensemble">
         <div className="grid grid-cols-1 md:grid-cols-3 gap-px">
           <Card title="Domaines Bloqués" icon={Shield}>
@@ -1,5 +1,5 @@
 import { useState, useEffect, useCallback } from 'react';
-import { Cloud, Power, PowerOff, RefreshCw, Server, Activity, Wifi, Upload, ArrowUpCircle } from 'lucide-react';
+import { Cloud, Power, PowerOff, RefreshCw, Server, Activity, Wifi, Upload, ArrowUpCircle, Lock, Trash2 } from 'lucide-react';
 import Button from '../components/Button';
 import StatusBadge from '../components/StatusBadge';
 import PageHeader from '../components/PageHeader';
@@ -8,9 +8,19 @@ import ConfirmModal from '../components/ConfirmModal';
 import useWebSocket from '../hooks/useWebSocket';
 import {
   getCloudRelayStatus, enableCloudRelay, disableCloudRelay,
-  bootstrapCloudRelay, pushCloudRelayUpdate,
+  bootstrapCloudRelay, pushCloudRelayUpdate, provisionCloudRelay,
+  getRelayUsage, resetRelayUsage, updateRelayUsageConfig,
+  getRelayCerts, updateRelayPins, renewRelayClientCert,
 } from '../api/client';
 
+function formatBytes(bytes) {
+  if (!bytes) return '0 B';
+  const k = 1000;
+  const sizes = ['B', 'KB', 'MB', 'GB', 'TB'];
+  const i = Math.min(Math.floor(Math.log(bytes) / Math.log(k)), sizes.length - 1);
+  return parseFloat((bytes / Math.pow(k, i)).toFixed(1)) + ' ' + sizes[i];
+}
+
 function CloudRelay() {
   const [status, setStatus] = useState(null);
   const [loading, setLoading] = useState(true);
@@ -23,6 +33,17 @@ function CloudRelay() {
   const [showDisableConfirm, setShowDisableConfirm] = useState(false);
   const [showBootstrapForm, setShowBootstrapForm] = useState(false);
   const [bootstrapForm, setBootstrapForm] = useState({ host: '', ssh_user: 'root', ssh_port: '22', ssh_password: '' });
+  const [showProvisionForm, setShowProvisionForm] = useState(false);
+  const [provisionForm, setProvisionForm] = useState({ host: '', binaryUrl: '', udpPorts: '', format: 'script' });
+  const [provisioning, setProvisioning] = useState(false);
+  const [usage, setUsage] = useState(null);
+  const [limits, setLimits] = useState({ monthlyCapGb: '0', streamMbit: '0' });
+  const [savingLimits, setSavingLimits] = useState(false);
+  const [limitsError, setLimitsError] = useState(null);
+  const [certs, setCerts] = useState(null);
+  const [newPin, setNewPin] = useState('');
+  const [certsError, setCertsError] = useState(null);
+  const [renewing, setRenewing] = useState(false);
 
   const fetchStatus = useCallback(async () => {
     try {
@@ -35,11 +56,42 @@ function CloudRelay() {
     }
   }, []);
 
+  const fetchUsage = useCallback(async () => {
+    try {
+      const res = await getRelayUsage();
+      if (res.data.success) setUsage(res.data);
+    } catch (error) {
+      console.error('Error fetching relay usage:', error);
+    }
+  }, []);
+
+  const fetchCerts = useCallback(async () => {
+    try {
+      const res = await getRelayCerts();
+      if (res.data.success) setCerts(res.data);
+    } catch (error) {
+      console.error('Error fetching relay certificates:', error);
+    }
+  }, []);
+
   useEffect(() => {
     fetchStatus();
-    const interval = setInterval(fetchStatus, 10000);
+    fetchUsage();
+    fetchCerts();
+    const interval = setInterval(() => { fetchStatus(); fetchUsage(); }, 10000);
     return () => clearInterval(interval);
-  }, [fetchStatus]);
+  }, [fetchStatus, fetchUsage, fetchCerts]);
+
+  useEffect(() => {
+    getRelayUsage().then(res => {
+      if (res.data.success) {
+        setLimits({
+          monthlyCapGb: String(res.data.config.monthlyCapGb),
+          streamMbit: String(res.data.config.streamMbit),
+        });
+      }
+    }).catch(() => {});
+  }, []);
 
   // Real-time WebSocket updates
   useWebSocket({
@@ -111,6 +163,91 @@ function CloudRelay() {
     }
   }
 
+  async function handleProvision() {
+    if (!provisionForm.host.trim()) return;
+    setProvisioning(true);
+    setBootstrapLog(null);
+    try {
+      const payload = {
+        host: provisionForm.host.trim(),
+        format: provisionForm.format,
+        udpPorts: provisionForm.udpPorts.split(',').map(p => parseInt(p.trim())).filter(p => p > 0),
+      };
+      if (provisionForm.binaryUrl.trim()) {
+        payload.binaryUrl = provisionForm.binaryUrl.trim();
+      }
+      const res = await provisionCloudRelay(payload);
+      if (res.success) {
+        setBootstrapLog({ success: true, message: 'Script telecharge : executez-le en root sur la VPS, puis redemarrez HomeRoute.' });
+        setShowProvisionForm(false);
+        await fetchStatus();
+      } else {
+        setBootstrapLog({ success: false, message: res.error || 'Erreur inconnue' });
+      }
+    } catch (error) {
+      setBootstrapLog({ success: false, message: error.message });
+    } finally {
+      setProvisioning(false);
+    }
+  }
+
+  async function handleSaveLimits() {
+    setSavingLimits(true);
+    setLimitsError(null);
+    try {
+      const res = await updateRelayUsageConfig({
+        monthlyCapGb: parseInt(limits.monthlyCapGb) || 0,
+        streamMbit: parseInt(limits.streamMbit) || 0,
+      });
+      if (res.data.success) {
+        await fetchUsage();
+      } else {
+        setLimitsError(res.data.error || 'Erreur inconnue');
+      }
+    } catch (error) {
+      setLimitsError(error.message);
+    } finally {
+      setSavingLimits(false);
+    }
+  }
+
+  async function handleResetUsage() {
+    await resetRelayUsage();
+    await fetchUsage();
+  }
+
+  async function savePins(pins) {
+    setCertsError(null);
+    try {
+      const res = await updateRelayPins(pins);
+      if (res.data.success) {
+        setNewPin('');
+        await fetchCerts();
+      } else {
+        setCertsError(res.data.error || 'Erreur inconnue');
+      }
+    } catch (error) {
+      setCertsError(error.message);
+    }
+  }
+
+  async function handleRenewCert() {
+    setRenewing(true);
+    setCertsError(null);
+    try {
+      const res = await renewRelayClientCert();
+      if (res.data.success) {
+        await fetchCerts();
+      } else {
+        setCertsError(res.data.error || 'Erreur inconnue');
+      }
+    } catch (error) {
+      setCertsError(error.message);
+    } finally {
+      setRenewing(false);
+    }
+  }
+
   async function handleUpdate() {
     setUpdating(true);
     setUpdateLog(null);
@@ -174,6 +311,9 @@ function CloudRelay() {
             <Server className="w-3.5 h-3.5 text-blue-400" />
             <span className="font-mono text-blue-400">{status?.vps_host || '-'}</span>
             {status?.vps_ipv4 && <span className="font-mono text-xs">({status.vps_ipv4})</span>}
+            {status?.relay_host && status.relay_host !== status.vps_host && (
+              <span className="text-xs text-yellow-400">secours : <span className="font-mono">{status.relay_host}</span></span>
+            )}
           </div>
           <div className="flex items-center gap-1.5">
             <Wifi className="w-3.5 h-3.5 text-gray-500" />
@@ -189,6 +329,168 @@ function CloudRelay() {
         </div>
       </Section>
 
+      {/* Monthly usage */}
+      <Section title={`Consommation ${usage?.usage?.month || ''}`}>
+        <div className="flex items-center gap-6 text-sm mb-3">
+          <div>
+            <span className="text-xs text-gray-500">Entrant </span>
+            <span className="font-mono text-blue-400">{formatBytes(usage?.usage?.bytesIn)}</span>
+          </div>
+          <div>
+            <span className="text-xs text-gray-500">Sortant </span>
+            <span className="font-mono text-blue-400">{formatBytes(usage?.usage?.bytesOut)}</span>
+          </div>
+          {usage?.remainingBytes != null && (
+            <div>
+              <span className="text-xs text-gray-500">Restant </span>
+              <span className={`font-mono ${usage.capReached ? 'text-red-400' : 'text-green-400'}`}>
+                {formatBytes(usage.remainingBytes)}
+              </span>
+            </div>
+          )}
+          {usage?.capReached && (
+            <span className="text-xs text-red-400">Plafond atteint : nouvelles connexions refusees ({usage.usage.refused})</span>
+          )}
+          <Button onClick={handleResetUsage} variant="secondary" size="sm">
+            Remettre a zero
+          </Button>
+        </div>
+
+        {usage?.usage && Object.keys(usage.usage.routes).length > 0 && (
+          <table className="w-full text-xs mb-3">
+            <thead>
+              <tr className="text-gray-500 text-left">
+                <th className="py-1 font-normal">Route</th>
+                <th className="py-1 font-normal text-right">Streams</th>
+                <th className="py-1 font-normal text-right">Entrant</th>
+                <th className="py-1 font-normal text-right">Sortant</th>
+              </tr>
+            </thead>
+            <tbody>
+              {Object.entries(usage.usage.routes)
+                .sort(([, a], [, b]) => (b.bytesIn + b.bytesOut) - (a.bytesIn + a.bytesOut))
+                .map(([route, r]) => (
+                  <tr key={route} className="border-t border-gray-800">
+                    <td className="py-1 font-mono text-gray-300">{route}</td>
+                    <td className="py-1 text-right text-gray-400">{r.streams}</td>
+                    <td className="py-1 text-right font-mono text-gray-400">{formatBytes(r.bytesIn)}</td>
+                    <td className="py-1 text-right font-mono text-gray-400">{formatBytes(r.bytesOut)}</td>
+                  </tr>
+                ))}
+            </tbody>
+          </table>
+        )}
+
+        <div className="flex items-end gap-2">
+          <div>
+            <label className="block text-xs text-gray-400 mb-0.5">Plafond mensuel (Go, 0 = aucun)</label>
+            <input
+              type="number"
+              min="0"
+              value={limits.monthlyCapGb}
+              onChange={(e) => setLimits(l => ({ ...l, monthlyCapGb: e.target.value }))}
+              className="w-40 bg-gray-900 border border-gray-600 px-2 py-1.5 text-sm font-mono text-white focus:outline-none focus:border-blue-500"
+            />
+          </div>
+          <div>
+            <label className="block text-xs text-gray-400 mb-0.5">Debit par stream (Mbit/s, 0 = illimite)</label>
+            <input
+              type="number"
+              min="0"
+              value={limits.streamMbit}
+              onChange={(e) => setLimits(l => ({ ...l, streamMbit: e.target.value }))}
+              className="w-40 bg-gray-900 border border-gray-600 px-2 py-1.5 text-sm font-mono text-white focus:outline-none focus:border-blue-500"
+            />
+          </div>
+          <Button onClick={handleSaveLimits} loading={savingLimits} variant="primary" size="sm">
+            Enregistrer
+          </Button>
+        </div>
+        {limitsError && <p className="text-xs text-red-400 mt-1">{limitsError}</p>}
+      </Section>
+
+      {/* Tunnel certificates */}
+      <Section title="Certificats du tunnel">
+        <div className="space-y-1 text-xs mb-3">
+          <div>
+            <span className="text-gray-500">Certificat client </span>
+            {certs?.client ? (
+              <span className="text-gray-300">
+                {certs.client.commonName}, expire le{' '}
+                {certs.client.notAfter ? new Date(certs.client.notAfter * 1000).toLocaleDateString('fr-FR') : '?'}
+                {!certs.client.renewable && <span className="text-yellow-400"> (cle CA absente, renouvellement impossible)</span>}
+              </span>
+            ) : (
+              <span className="text-gray-500">aucun</span>
+            )}
+          </div>
+          <div>
+            <span className="text-gray-500">Certificat du relay </span>
+            <span className="font-mono text-gray-300 break-all">{certs?.relaySha256 || '-'}</span>
+            {certs?.relaySha256 && (
+              <span className={certs.relayPinned ? 'text-green-400' : 'text-gray-500'}>
+                {certs.relayPinned ? ' (epingle)' : ' (non epingle)'}
+              </span>
+            )}
+          </div>
+        </div>
+
+        {certs?.pins?.length > 0 && (
+          <table className="w-full text-xs mb-3">
+            <tbody>
+              {certs.pins.map((pin) => (
+                <tr key={pin.sha256} className="border-t border-gray-800">
+                  <td className="py-1 font-mono text-gray-300 break-all">{pin.sha256}</td>
+                  <td className="py-1 text-gray-400">{pin.label}</td>
+                  <td className="py-1 text-right">
+                    <button
+                      onClick={() => savePins(certs.pins.filter(p => p.sha256 !== pin.sha256))}
+                      className="text-gray-500 hover:text-red-400"
+                      title="Retirer"
+                    >
+                      <Trash2 className="w-3.5 h-3.5" />
+                    </button>
+                  </td>
+                </tr>
+              ))}
+            </tbody>
+          </table>
+        )}
+
+        <div className="flex flex-wrap items-end gap-2">
+          {certs?.relaySha256 && !certs.relayPinned && (
+            <Button
+              onClick={() => savePins([...(certs.pins || []), { sha256: certs.relaySha256, label: 'actuel' }])}
+              variant="secondary"
+              size="sm"
+            >
+              <Lock className="w-3.5 h-3.5" />
+              Epingler le certificat actuel
+            </Button>
+          )}
+          <input
+            type="text"
+            value={newPin}
+            onChange={(e) => setNewPin(e.target.value)}
+            placeholder="SHA-256 du prochain certificat"
+            className="flex-1 min-w-64 bg-gray-900 border border-gray-600 px-2 py-1.5 text-xs font-mono text-white focus:outline-none focus:border-blue-500"
+          />
+          <Button
+            onClick={() => savePins([...(certs?.pins || []), { sha256: newPin.trim(), label: 'rotation' }])}
+            variant="secondary"
+            size="sm"
+            disabled={!newPin.trim()}
+          >
+            Ajouter
+          </Button>
+          <Button onClick={handleRenewCert} loading={renewing} variant="secondary" size="sm" disabled={!certs?.client?.renewable}>
+            <RefreshCw className="w-3.5 h-3.5" />
+            Renouveler le certificat client
+          </Button>
+        </div>
+        {certsError && <p className="text-xs text-red-400 mt-1">{certsError}</p>}
+      </Section>
+
       {/* Actions */}
       <Section title="Actions" contrast>
         <div className="flex flex-wrap items-center gap-2">
@@ -217,6 +519,10 @@ function CloudRelay() {
             <Upload className="w-3.5 h-3.5" />
             {isBootstrapped ? 'Re-bootstrap VPS' : 'Bootstrap VPS'}
           </Button>
+          <Button onClick={() => setShowProvisionForm(!showProvisionForm)} variant="secondary" size="sm">
+            <Server className="w-3.5 h-3.5" />
+            Script d&apos;installation
+          </Button>
           {isBootstrapped && isConnected && (
             <Button onClick={handleUpdate} loading={updating} variant="secondary" size="sm">
               <ArrowUpCircle className="w-3.5 h-3.5" />
@@ -289,6 +595,71 @@ function CloudRelay() {
           </div>
         )}
 
+        {/* Provision Form */}
+        {showProvisionForm && (
+          <div className="mt-3 bg-gray-800 border border-gray-700 p-3 max-w-lg">
+            <h3 className="text-sm font-semibold mb-2">Installer hr-cloud-relay sans SSH</h3>
+            <p className="text-xs text-gray-400 mb-3">
+              Genere de nouveaux certificats et un script a executer en root sur la VPS (ou des user data cloud-init).
+              Les certificats actuels du tunnel sont remplaces.
+            </p>
+            <div className="space-y-2">
+              <div>
+                <label className="block text-xs text-gray-400 mb-0.5">Hote VPS (IP ou hostname)</label>
+                <input
+                  type="text"
+                  value={provisionForm.host}
+                  onChange={(e) => setProvisionForm(f => ({ ...f, host: e.target.value }))}
+                  placeholder="203.0.113.10"
+                  className="w-full bg-gray-900 border border-gray-600 px-2 py-1.5 text-sm font-mono text-white focus:outline-none focus:border-blue-500"
+                />
+              </div>
+              <div>
+                <label className="block text-xs text-gray-400 mb-0.5">URL du binaire (vide : inclus dans le script)</label>
+                <input
+                  type="text"
+                  value={provisionForm.binaryUrl}
+                  onChange={(e) => setProvisionForm(f => ({ ...f, binaryUrl: e.target.value }))}
+                  placeholder="https://..."
+                  className="w-full bg-gray-900 border border-gray-600 px-2 py-1.5 text-sm font-mono text-white focus:outline-none focus:border-blue-500"
+                />
+              </div>
+              <div className="grid grid-cols-2 gap-2">
+                <div>
+                  <label className="block text-xs text-gray-400 mb-0.5">Ports UDP relayes</label>
+                  <input
+                    type="text"
+                    value={provisionForm.udpPorts}
+                    onChange={(e) => setProvisionForm(f => ({ ...f, udpPorts: e.target.value }))}
+                    placeholder="51820, 27015"
+                    className="w-full bg-gray-900 border border-gray-600 px-2 py-1.5 text-sm font-mono text-white focus:outline-none focus:border-blue-500"
+                  />
+                </div>
+                <div>
+                  <label className="block text-xs text-gray-400 mb-0.5">Format</label>
+                  <select
+                    value={provisionForm.format}
+                    onChange={(e) => setProvisionForm(f => ({ ...f, format: e.target.value }))}
+                    className="w-full bg-gray-900 border border-gray-600 px-2 py-1.5 text-sm text-white focus:outline-none focus:border-blue-500"
+                  >
+                    <option value="script">Script bash</option>
+                    <option value="cloud-init">cloud-init</option>
+                  </select>
+                </div>
+              </div>
+              <div className="flex gap-2 pt-1">
+                <Button onClick={handleProvision} loading={provisioning} variant="success" size="sm" disabled={!provisionForm.host.trim()}>
+                  <Upload className="w-3.5 h-3.5" />
+                  Telecharger
+                </Button>
+                <Button onClick={() => setShowProvisionForm(false)} variant="secondary" size="sm">
+                  Annuler
+                </Button>
+              </div>
+            </div>
+          </div>
+        )}
+
         {/* Bootstrap Result */}
         {bootstrapLog && (
           <div className={`mt-2 px-3 py-2 border ${bootstrapLog.success ? 'border-green-700 bg-green-900/20' : 'border-red-700 bg-red-900/20'}`}>
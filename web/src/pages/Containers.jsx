@@ -11,7 +11,6 @@ import {
   RefreshCw,
   AlertTriangle,
   X,
-  Terminal,
   Loader2,
   Play,
   Square,
@@ -23,6 +22,7 @@ import PageHeader from '../components/PageHeader';
 import AppGroupCard from '../components/AppGroupCard';
 import { CONTAINER_GRID } from '../components/ContainerCard';
 import CreateContainerModal from '../components/CreateContainerModal';
+import TerminalModal from '../components/TerminalModal';
 import useWebSocket from '../hooks/useWebSocket';
 import {
   getContainers,
@@ -93,6 +93,7 @@ function MigrationProgress({ appId, migration, onDismiss }) {
     exporting: 'Export...',
     transferring: 'Transfert conteneur...',
     transferring_workspace: 'Transfert workspace...',
+    resuming: 'Reprise du transfert...',
     importing: 'Import...',
     importing_workspace: 'Import workspace...',
     starting: 'Demarrage...',
@@ -192,6 +193,7 @@ function Containers() {
   const [terminalContainer, setTerminalContainer] = useState(null);
   const [migrateModal, setMigrateModal] = useState(null);
   const [selectedHostId, setSelectedHostId] = useState('');
+  const [compressionLevel, setCompressionLevel] = useState(3);
   const [migrating, setMigrating] = useState(false);
   const [migrations, setMigrations] = useState({});
   const [editingApp, setEditingApp] = useState(null);
@@ -493,7 +495,7 @@ function Containers() {
     if (!confirm(`Migrer ${migrateModal.name} vers ${targetName} ?\n\nLe conteneur sera arrete pendant la migration.`)) return;
     setMigrating(true);
     try {
-      await migrateContainer(migrateModal.id, selectedHostId);
+      await migrateContainer(migrateModal.id, selectedHostId, compressionLevel);
       setMigrateModal(null);
     } catch (err) {
       console.error('Migration failed:', err);
@@ -716,6 +718,18 @@ function Containers() {
                 <option value="local">HomeRoute (local)</option>
               )}
             </select>
+            <label className="block text-sm text-gray-400 mb-1">Compression du transfert</label>
+            <select
+              value={compressionLevel}
+              onChange={(e) => setCompressionLevel(Number(e.target.value))}
+              className="w-full px-3 py-2 bg-gray-700 border border-gray-600 text-white mb-4"
+            >
+              <option value={0}>Aucune (reseau local rapide)</option>
+              <option value={1}>Rapide (zstd 1, peu de CPU)</option>
+              <option value={3}>Standard (zstd 3)</option>
+              <option value={9}>Forte (zstd 9)</option>
+              <option value={19}>Maximale (zstd 19, lien lent)</option>
+            </select>
             <div className="flex justify-end gap-2">
               <button
                 onClick={() => setMigrateModal(null)}
@@ -738,122 +752,16 @@ function Containers() {
 
       {/* Terminal Modal */}
       {terminalContainer && (
-        <TerminalModal container={terminalContainer} onClose={() => setTerminalContainer(null)} />
+        <TerminalModal
+          path={`/api/containers/${terminalContainer.id}/terminal`}
+          title={terminalContainer.name}
+          subtitle={terminalContainer.container_name}
+          connectingLabel={`Connexion au conteneur ${terminalContainer.container_name}...`}
+          onClose={() => setTerminalContainer(null)}
+        />
       )}
     </div>
   );
 }
 
-function TerminalModal({ container, onClose }) {
-  const termRef = useRef(null);
-  const termInstance = useRef(null);
-  const wsRef = useRef(null);
-  const fitAddonRef = useRef(null);
-
-  useEffect(() => {
-    let cancelled = false;
-
-    async function init() {
-      const { Terminal: XTerm } = await import('@xterm/xterm');
-      const { FitAddon } = await import('@xterm/addon-fit');
-      await import('@xterm/xterm/css/xterm.css');
-
-      if (cancelled || !termRef.current) return;
-
-      const fitAddon = new FitAddon();
-      fitAddonRef.current = fitAddon;
-
-      const term = new XTerm({
-        cursorBlink: true,
-        fontSize: 14,
-        fontFamily: 'Menlo, Monaco, "Courier New", monospace',
-        theme: {
-          background: '#111827',
-          foreground: '#e5e7eb',
-          cursor: '#10b981',
-          selectionBackground: '#374151',
-        },
-      });
-
-      term.loadAddon(fitAddon);
-      term.open(termRef.current);
-      fitAddon.fit();
-      termInstance.current = term;
-
-      const proto = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
-      const ws = new WebSocket(`${proto}//${window.location.host}/api/containers/${container.id}/terminal`);
-      ws.binaryType = 'arraybuffer';
-      wsRef.current = ws;
-
-      ws.onopen = () => {
-        term.write('\r\n\x1b[32mConnexion au conteneur ' + container.container_name + '...\x1b[0m\r\n\r\n');
-      };
-
-      ws.onmessage = (event) => {
-        if (event.data instanceof ArrayBuffer) {
-          term.write(new Uint8Array(event.data));
-        } else {
-          term.write(event.data);
-        }
-      };
-
-      ws.onclose = () => {
-        term.write('\r\n\x1b[31mConnexion fermee.\x1b[0m\r\n');
-      };
-
-      ws.onerror = () => {
-        term.write('\r\n\x1b[31mErreur de connexion.\x1b[0m\r\n');
-      };
-
-      term.onData((data) => {
-        if (ws.readyState === WebSocket.OPEN) {
-          ws.send(data);
-        }
-      });
-
-      const handleResize = () => {
-        fitAddon.fit();
-      };
-      window.addEventListener('resize', handleResize);
-
-      return () => {
-        window.removeEventListener('resize', handleResize);
-      };
-    }
-
-    init();
-
-    return () => {
-      cancelled = true;
-      if (wsRef.current) {
-        wsRef.current.close();
-        wsRef.current = null;
-      }
-      if (termInstance.current) {
-        termInstance.current.dispose();
-        termInstance.current = null;
-      }
-    };
-  }, [container]);
-
-  return (
-    <div className="fixed inset-0 bg-black/80 flex flex-col z-50">
-      <div className="flex items-center justify-between px-4 py-2 bg-gray-900 border-b border-gray-700">
-        <div className="flex items-center gap-2 text-sm">
-          <Terminal className="w-4 h-4 text-emerald-400" />
-          <span className="font-medium">{container.name}</span>
-          <span className="text-gray-500 font-mono">({container.container_name})</span>
-        </div>
-        <button
-          onClick={onClose}
-          className="text-gray-400 hover:text-white p-1 transition-colors"
-        >
-          <X className="w-5 h-5" />
-        </button>
-      </div>
-      <div ref={termRef} className="flex-1 p-2" style={{ backgroundColor: '#111827' }} />
-    </div>
-  );
-}
-
 export default Containers;
@@ -1,17 +1,19 @@
 import { useState, useEffect } from 'react';
 import { Link } from 'react-router-dom';
-import { LayoutDashboard, Shield, Globe, Wifi, ArrowRight } from 'lucide-react';
+import { LayoutDashboard, Shield, Globe, Wifi, ArrowRight, Network } from 'lucide-react';
 import Card from '../components/Card';
 import ServiceStatusPanel from '../components/ServiceStatusPanel';
 import PageHeader from '../components/PageHeader';
 import Section from '../components/Section';
-import { getDhcpLeases, getAdblockStats, getDdnsStatus, getServicesStatus } from '../api/client';
+import useWebSocket from '../hooks/useWebSocket';
+import { getDhcpLeases, getAdblockStats, getDdnsStatus, getServicesStatus, getTailscaleStatus } from '../api/client';
 
 function Dashboard() {
   const [data, setData] = useState({
     leases: null,
     adblock: null,
     ddns: null,
+    tailscale: null,
     services: null,
     loading: true
   });
@@ -19,17 +21,19 @@ function Dashboard() {
   useEffect(() => {
     async function fetchData() {
       try {
-        const [leaseRes, adblockRes, ddnsRes, svcRes] = await Promise.all([
+        const [leaseRes, adblockRes, ddnsRes, svcRes, tailscaleRes] = await Promise.all([
           getDhcpLeases(),
           getAdblockStats(),
           getDdnsStatus(),
-          getServicesStatus()
+          getServicesStatus(),
+          getTailscaleStatus().catch(() => null)
         ]);
 
         setData({
           leases: leaseRes.data.success ? leaseRes.data.leases : [],
           adblock: adblockRes.data.success ? adblockRes.data.stats : null,
           ddns: ddnsRes.data.success ? ddnsRes.data.status : null,
+          tailscale: tailscaleRes?.data.success ? tailscaleRes.data.status : null,
           services: svcRes.data.success ? svcRes.data.services : [],
           loading: false
         });
@@ -44,6 +48,23 @@ function Dashboard() {
     return () => clearInterval(interval);
   }, []);
 
+  useWebSocket({
+    'services:snapshot': (services) => setData(prev => ({ ...prev, services })),
+    'services:status': (svc) => setData(prev => ({
+      ...prev,
+      services: prev.services?.some(s => s.name === svc.name)
+        ? prev.services.map(s => (s.name === svc.name ? svc : s))
+        : [...(prev.services || []), svc],
+    })),
+    'dhcp.leases:snapshot': (leases) => setData(prev => ({ ...prev, leases })),
+    'dhcp:lease': (change) => setData(prev => {
+      const others = (prev.leases || []).filter(l => l.mac.toLowerCase() !== change.mac);
+      if (change.action === 'removed') return { ...prev, leases: others };
+      const current = (prev.leases || []).find(l => l.mac.toLowerCase() === change.mac);
+      return { ...prev, leases: [{ ...current, mac: change.mac, ip: change.ip, hostname: change.hostname }, ...others] };
+    }),
+  }, ['services', 'dhcp.leases']);
+
   if (data.loading) {
     return (
       <div className="flex items-center justify-center h-full">
@@ -120,11 +141,41 @@ function Dashboard() {
                 {data.ddns?.lastUpdate ? `MAJ: ${data.ddns.lastUpdate}` : '-'}
               </p>
             </Card>
+
+            {/* Tailscale Card */}
+            <Card title="Tailscale" icon={Network}>
+              <div className={`text-lg font-bold ${data.tailscale?.node?.backendState === 'Running' ? 'text-green-400' : 'text-yellow-400'}`}>
+                {!data.tailscale?.available ? 'Indisponible' : data.tailscale.node?.backendState || '-'}
+              </div>
+              <p className="text-xs text-gray-500 mt-2 font-mono break-all">
+                {data.tailscale?.node?.dnsName || data.tailscale?.node?.addresses?.[0] || '-'}
+              </p>
+              {data.tailscale?.node && (
+                <p className="text-xs text-gray-500 mt-2">
+                  {data.tailscale.node.peersOnline}/{data.tailscale.node.peers} pairs en ligne
+                  {data.tailscale.node.approvedRoutes?.length > 0 && ` · ${data.tailscale.node.approvedRoutes.join(', ')}`}
+                </p>
+              )}
+              {data.tailscale?.pendingRoutes?.length > 0 && (
+                <p className="text-xs text-yellow-400 mt-2">
+                  Routes à approuver : {data.tailscale.pendingRoutes.join(', ')}
+                </p>
+              )}
+              {data.tailscale?.lastError && (
+                <p className="text-xs text-red-400 mt-2 break-all">{data.tailscale.lastError}</p>
+              )}
+            </Card>
           </div>
       </Section>
 
       <Section title="Services" contrast>
-        <ServiceStatusPanel services={data.services} />
+        <ServiceStatusPanel
+          services={data.services}
+          onServiceChange={svc => setData(prev => ({
+            ...prev,
+            services: prev.services.map(s => (s.name === svc.name ? svc : s)),
+          }))}
+        />
       </Section>
 
       <Section title="Baux DHCP Récents">
@@ -1,8 +1,33 @@
 import { useState, useEffect } from 'react';
-import { Server, Search, Globe, Network } from 'lucide-react';
+import { Server, Search, Globe, Network, Stethoscope, RefreshCw } from 'lucide-react';
 import PageHeader from '../components/PageHeader';
 import Section from '../components/Section';
-import { getDnsConfig, getDhcpLeases } from '../api/client';
+import { getDnsConfig, getDhcpLeases, getDnsDiagnostics } from '../api/client';
+
+const STATUS_STYLES = {
+  pass: 'bg-green-900/50 text-green-400',
+  warn: 'bg-yellow-900/50 text-yellow-400',
+  fail: 'bg-red-900/50 text-red-400',
+  skipped: 'bg-gray-700 text-gray-400',
+};
+
+const STATUS_LABELS = { pass: 'OK', warn: 'Attention', fail: 'Echec', skipped: 'Ignore' };
+
+const CHECK_LABELS = {
+  upstreams: 'Serveurs upstream',
+  dnssec: 'DNSSEC',
+  mode: 'Mode de resolution',
+  blocking: 'Reponse de blocage',
+  cache: 'Cache',
+};
+
+function StatusBadge({ status }) {
+  return (
+    <span className={`px-2 py-0.5 text-xs font-medium ${STATUS_STYLES[status] || STATUS_STYLES.skipped}`}>
+      {STATUS_LABELS[status] || status}
+    </span>
+  );
+}
 
 function Dns() {
   const [config, setConfig] = useState(null);
@@ -10,6 +35,9 @@ function Dns() {
   const [search, setSearch] = useState('');
   const [loading, setLoading] = useState(true);
   const [activeTab, setActiveTab] = useState('dhcp');
+  const [report, setReport] = useState(null);
+  const [diagRunning, setDiagRunning] = useState(false);
+  const [diagError, setDiagError] = useState(null);
 
   useEffect(() => {
     async function fetchData() {
@@ -135,6 +163,110 @@ function Dns() {
     );
   }
 
+  async function runDiagnostics() {
+    setDiagRunning(true);
+    setDiagError(null);
+    try {
+      const res = await getDnsDiagnostics();
+      if (res.data.success) setReport(res.data.report);
+      else setDiagError(res.data.error || 'Erreur');
+    } catch (error) {
+      setDiagError(error.message);
+    } finally {
+      setDiagRunning(false);
+    }
+  }
+
+  function renderDiagnosticsTab() {
+    return (
+      <div className="space-y-px">
+        <Section title="Diagnostic DNS">
+          <div className="flex items-center gap-3">
+            <button
+              onClick={runDiagnostics}
+              disabled={diagRunning}
+              className="flex items-center gap-2 px-3 py-1.5 bg-blue-600 hover:bg-blue-700 disabled:opacity-50 text-sm"
+            >
+              <RefreshCw className={`w-4 h-4 ${diagRunning ? 'animate-spin' : ''}`} />
+              {diagRunning ? 'Test en cours...' : 'Lancer le diagnostic'}
+            </button>
+            {report && (
+              <span className="text-sm text-gray-400">
+                <StatusBadge status={report.status} />
+                <span className="ml-2">
+                  {new Date(report.ranAt).toLocaleString('fr-FR')} ({Math.round(report.durationMs)} ms)
+                </span>
+              </span>
+            )}
+          </div>
+          {diagError && <p className="text-red-400 text-sm mt-2">{diagError}</p>}
+        </Section>
+
+        {report && (
+          <>
+            <Section title="Verifications">
+              <table className="w-full text-sm">
+                <tbody>
+                  {report.checks.map(check => (
+                    <tr key={check.name} className="border-b border-gray-700/50">
+                      <td className="py-2 w-48 text-gray-400">{CHECK_LABELS[check.name] || check.name}</td>
+                      <td className="py-2 w-24"><StatusBadge status={check.status} /></td>
+                      <td className="py-2 font-mono text-xs">{check.detail}</td>
+                    </tr>
+                  ))}
+                </tbody>
+              </table>
+            </Section>
+
+            <Section title="Serveurs upstream">
+              <table className="w-full text-sm">
+                <thead>
+                  <tr className="text-left text-gray-400 border-b border-gray-700">
+                    <th className="pb-2">Serveur</th>
+                    <th className="pb-2">Latence moyenne</th>
+                    <th className="pb-2">DNSSEC</th>
+                    <th className="pb-2">Requetes de test</th>
+                  </tr>
+                </thead>
+                <tbody>
+                  {report.upstreams.map(upstream => (
+                    <tr key={upstream.server} className="border-b border-gray-700/50 align-top">
+                      <td className="py-2 font-mono text-blue-400">{upstream.server}</td>
+                      <td className="py-2">
+                        {upstream.reachable
+                          ? `${upstream.avgLatencyMs} ms`
+                          : <span className="text-red-400">Injoignable</span>}
+                      </td>
+                      <td className="py-2">
+                        {upstream.dnssecValidates === null ? '-' : (
+                          upstream.dnssecValidates && upstream.dnssecRejectsBogus
+                            ? <span className="text-green-400">Valide</span>
+                            : <span className="text-yellow-400">Non valide</span>
+                        )}
+                      </td>
+                      <td className="py-2 font-mono text-xs space-y-0.5">
+                        {upstream.probes.map(probe => (
+                          <div key={`${probe.name}-${probe.type}`}>
+                            {probe.name} {probe.type}:{' '}
+                            {probe.error
+                              ? <span className="text-red-400">{probe.error}</span>
+                              : <span className={probe.rcode === 0 ? 'text-green-400' : 'text-yellow-400'}>
+                                  rcode {probe.rcode}, {probe.answers} rep., {probe.latencyMs} ms
+                                </span>}
+                          </div>
+                        ))}
+                      </td>
+                    </tr>
+                  ))}
+                </tbody>
+              </table>
+            </Section>
+          </>
+        )}
+      </div>
+    );
+  }
+
   function renderDhcpTab() {
     return (
       <div className="space-y-px">
@@ -296,12 +428,24 @@ function Dns() {
             <Network className="w-4 h-4" />
             DHCP
           </button>
+          <button
+            onClick={() => setActiveTab('diagnostics')}
+            className={`w-full flex items-center gap-2 px-4 py-2.5 text-sm text-left transition-colors ${
+              activeTab === 'diagnostics'
+                ? 'bg-gray-900 text-blue-400 border-l-2 border-blue-400'
+                : 'text-gray-400 hover:bg-gray-800 hover:text-gray-300 border-l-2 border-transparent'
+            }`}
+          >
+            <Stethoscope className="w-4 h-4" />
+            Diagnostic
+          </button>
         </div>
 
         {/* Tab Content */}
         <div className="flex-1 overflow-auto">
           {activeTab === 'dns' && renderDnsTab()}
           {activeTab === 'dhcp' && renderDhcpTab()}
+          {activeTab === 'diagnostics' && renderDiagnosticsTab()}
         </div>
       </div>
     </div>
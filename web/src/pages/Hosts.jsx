@@ -1,15 +1,17 @@
 import { useState, useEffect } from 'react';
 import {
   HardDrive, Plus, Trash2, RefreshCw, X, Check,
-  Play, Square, RotateCw, Moon, CheckCircle, XCircle, Settings
+  Play, Square, RotateCw, Moon, CheckCircle, XCircle, Settings, Terminal, Wrench, KeyRound
 } from 'lucide-react';
 import Button from '../components/Button';
 import StatusBadge from '../components/StatusBadge';
 import PageHeader from '../components/PageHeader';
 import Section from '../components/Section';
+import TerminalModal from '../components/TerminalModal';
 import {
   getHosts,
   addHost,
+  createHostJoinCode,
   updateHost,
   deleteHost,
   wakeHost,
@@ -18,6 +20,10 @@ import {
   sleepHost,
   setWolMac,
   setAutoOff,
+  drainHost,
+  rotateHostToken,
+  revokeHostToken,
+  undrainHost,
   updateLocalHostConfig,
   getLocalInterfaces
 } from '../api/client';
@@ -28,6 +34,7 @@ export default function Hosts() {
   const [loading, setLoading] = useState(true);
   const [showAddModal, setShowAddModal] = useState(false);
   const [settingsHost, setSettingsHost] = useState(null);
+  const [terminalHost, setTerminalHost] = useState(null);
   // Add host form
   const [formData, setFormData] = useState({
     name: '',
@@ -40,6 +47,7 @@ export default function Hosts() {
   const [addingHost, setAddingHost] = useState(false);
   const [addError, setAddError] = useState('');
   const [message, setMessage] = useState(null);
+  const [joinCode, setJoinCode] = useState(null);
 
   // Settings modal state
   const [settingsForm, setSettingsForm] = useState({
@@ -132,6 +140,21 @@ export default function Hosts() {
     }
   };
 
+  const handleJoinCode = async () => {
+    const name = prompt('Nom du nouvel hote (vide : nom de la machine)');
+    if (name === null) return;
+    try {
+      const res = await createHostJoinCode({ name: name.trim() || null });
+      if (res.data.success) {
+        setJoinCode(res.data);
+      } else {
+        setMessage({ type: 'error', text: res.data.error || 'Erreur' });
+      }
+    } catch (error) {
+      setMessage({ type: 'error', text: error.response?.data?.error || error.message });
+    }
+  };
+
   const handleDeleteHost = async (id) => {
     if (!confirm('Supprimer cet hote ?')) return;
 
@@ -189,6 +212,83 @@ export default function Hosts() {
     }
   };
 
+  const handleDrain = async (host) => {
+    if (host.draining) {
+      if (!confirm(`Sortir ${host.name} de maintenance et redemarrer ses applications ?`)) return;
+      try {
+        const res = await undrainHost(host.id);
+        if (res.data.success) {
+          const errors = res.data.errors || [];
+          setMessage(errors.length
+            ? { type: 'error', text: 'Hote remis en service, echecs : ' + errors.join(', ') }
+            : { type: 'success', text: 'Hote remis en service' });
+          loadHosts();
+        } else {
+          setMessage({ type: 'error', text: res.data.error });
+        }
+      } catch (error) {
+        setMessage({ type: 'error', text: 'Echec : ' + error.message });
+      }
+      return;
+    }
+    const targets = hosts.filter(h => h.id !== host.id && !h.draining);
+    const names = targets.map(h => h.is_local ? 'HomeRoute' : h.name).join(', ');
+    const answer = prompt(
+      `Mettre ${host.name} en maintenance.\nHote cible des applications (${names}), vide pour les arreter :`
+    );
+    if (answer === null) return;
+    const target = targets.find(h => (h.is_local ? 'HomeRoute' : h.name) === answer.trim());
+    if (answer.trim() && !target) {
+      setMessage({ type: 'error', text: 'Hote cible inconnu : ' + answer });
+      return;
+    }
+    try {
+      const res = await drainHost(host.id, target?.id);
+      if (res.data.success) {
+        setMessage({ type: 'success', text: `Maintenance : ${res.data.drain.apps.length} application(s) a deplacer` });
+        loadHosts();
+      } else {
+        setMessage({ type: 'error', text: res.data.error });
+      }
+    } catch (error) {
+      setMessage({ type: 'error', text: 'Echec : ' + error.message });
+    }
+  };
+
+  const handleRotateToken = async (host) => {
+    if (!confirm(`Generer un nouveau jeton pour ${host.name} ? L'ancien reste valide 60 minutes.`)) return;
+    try {
+      const res = await rotateHostToken(host.id);
+      if (!res.data.success) {
+        setMessage({ type: 'error', text: res.data.error });
+      } else if (res.data.pushed) {
+        setMessage({ type: 'success', text: 'Jeton renouvele et envoye a l\'agent' });
+      } else {
+        // Agent offline or too old: the token goes in its config by hand
+        prompt('Agent injoignable : copier ce jeton dans /etc/hr-host-agent/config.toml', res.data.token);
+      }
+      loadHosts();
+    } catch (error) {
+      setMessage({ type: 'error', text: 'Echec : ' + error.message });
+    }
+  };
+
+  const handleRevokeToken = async (host) => {
+    if (!confirm(`Revoquer le jeton de ${host.name} ? L'agent sera deconnecte immediatement.`)) return;
+    try {
+      const res = await revokeHostToken(host.id);
+      if (res.data.success) {
+        setMessage({ type: 'success', text: 'Jeton revoque' });
+        setSettingsHost(null);
+        loadHosts();
+      } else {
+        setMessage({ type: 'error', text: res.data.error });
+      }
+    } catch (error) {
+      setMessage({ type: 'error', text: 'Echec : ' + error.message });
+    }
+  };
+
   // ── Settings modal ─────────────────────────
 
   const filterPhysicalInterfaces = (interfaces) => {
@@ -332,6 +432,10 @@ export default function Hosts() {
   return (
     <div>
       <PageHeader title="Hotes" icon={HardDrive}>
+        <Button variant="secondary" onClick={handleJoinCode}>
+          <KeyRound className="w-4 h-4 mr-2" />
+          Code d'adhesion
+        </Button>
         <Button onClick={() => setShowAddModal(true)}>
           <Plus className="w-4 h-4 mr-2" />
           Ajouter
@@ -377,6 +481,19 @@ export default function Hosts() {
                       <div className="flex items-center gap-2">
                         <HardDrive className={`w-4 h-4 flex-shrink-0 ${host.is_local ? 'text-green-400' : 'text-blue-400'}`} />
                         {host.is_local ? 'HomeRoute' : host.name}
+                        {host.draining && (
+                          <span className="text-xs text-orange-400" title="Aucune application ne peut y etre placee">
+                            maintenance
+                          </span>
+                        )}
+                        {host.agent_protocol?.outdated && (
+                          <span
+                            className="text-xs text-yellow-400"
+                            title={`Protocole agent v${host.agent_protocol.version} : mettre à jour l'agent`}
+                          >
+                            agent obsolète
+                          </span>
+                        )}
                       </div>
                     </td>
                     {/* Status */}
@@ -402,6 +519,11 @@ export default function Hosts() {
                             <div className="h-1.5 bg-blue-500" style={{ width: `${Math.min(host.metrics.cpuPercent, 100)}%` }} />
                           </div>
                           <span className="text-gray-400 text-xs">{host.metrics.cpuPercent.toFixed(0)}%</span>
+                          {host.metrics.cpuTempC != null && (
+                            <span className={`text-xs ${host.metrics.cpuTempC >= 80 ? 'text-red-400' : 'text-gray-500'}`}>
+                              {host.metrics.cpuTempC.toFixed(0)}°C
+                            </span>
+                          )}
                         </div>
                       ) : <span className="text-gray-600 text-xs">--</span>}
                     </td>
@@ -434,12 +556,27 @@ export default function Hosts() {
                         >
                           <Settings className="w-3.5 h-3.5" />
                         </button>
+                        <button
+                          onClick={() => setTerminalHost(host)}
+                          disabled={!host.is_local && getEffectiveStatus(host) !== 'online'}
+                          className="p-1.5 text-emerald-400 hover:bg-emerald-600/20 disabled:opacity-30 disabled:cursor-not-allowed"
+                          title="Terminal"
+                        >
+                          <Terminal className="w-3.5 h-3.5" />
+                        </button>
                         {!host.is_local && (() => {
                           const st = getEffectiveStatus(host);
                           const isOnline = st === 'online';
                           const canWake = st === 'offline' || st === 'suspended';
                           return (
                             <>
+                              <button
+                                onClick={() => handleDrain(host)}
+                                className={`p-1.5 hover:bg-orange-600/20 ${host.draining ? 'text-orange-400' : 'text-gray-400'}`}
+                                title={host.draining ? 'Sortir de maintenance' : 'Maintenance (drain)'}
+                              >
+                                <Wrench className="w-3.5 h-3.5" />
+                              </button>
                               <button
                                 onClick={() => handleWake(host.id)}
                                 disabled={!canWake}
@@ -597,6 +734,26 @@ export default function Hosts() {
         </div>
       )}
 
+      {/* Join Code Modal */}
+      {joinCode && (
+        <div className="fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50">
+          <div className="bg-gray-800 p-4 w-full max-w-lg">
+            <div className="flex items-center justify-between mb-3">
+              <h2 className="text-sm font-bold text-white">Code d'adhesion {joinCode.join_code.code}</h2>
+              <button onClick={() => setJoinCode(null)} className="text-gray-400 hover:text-white">
+                <X className="w-4 h-4" />
+              </button>
+            </div>
+            <p className="text-xs text-gray-400 mb-2">
+              A executer sur le nouvel hote. Le code sert une seule fois et expire a {new Date(joinCode.join_code.expires_at).toLocaleTimeString()}.
+            </p>
+            <pre className="px-2 py-1.5 bg-gray-900 border border-gray-600 text-xs text-green-400 whitespace-pre-wrap break-all select-all">
+              {joinCode.install_command}
+            </pre>
+          </div>
+        </div>
+      )}
+
       {/* Host Settings Modal */}
       {settingsHost && (
         <div className="fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50">
@@ -669,6 +826,28 @@ export default function Hosts() {
                       )}
                     </div>
                   </div>
+
+                  {/* Agent credentials */}
+                  <div>
+                    <label className="block text-xs text-gray-400 mb-0.5">
+                      Jeton agent : {settingsHost.credentials === 'revoked' ? 'revoque' : settingsHost.credentials === 'token' ? 'actif' : 'aucun'}
+                    </label>
+                    <div className="flex gap-2">
+                      <Button type="button" variant="secondary" size="sm" onClick={() => handleRotateToken(settingsHost)} className="flex-1">
+                        Renouveler
+                      </Button>
+                      <Button
+                        type="button"
+                        variant="secondary"
+                        size="sm"
+                        onClick={() => handleRevokeToken(settingsHost)}
+                        disabled={settingsHost.credentials === 'revoked'}
+                        className="flex-1"
+                      >
+                        Revoquer
+                      </Button>
+                    </div>
+                  </div>
                 </>
               )}
 
@@ -733,6 +912,16 @@ export default function Hosts() {
         </div>
       )}
 
+      {/* Terminal Modal */}
+      {terminalHost && (
+        <TerminalModal
+          path={`/api/hosts/${terminalHost.id}/terminal`}
+          title={terminalHost.is_local ? 'HomeRoute' : terminalHost.name}
+          subtitle={terminalHost.is_local ? '127.0.0.1' : terminalHost.host}
+          connectingLabel="Connexion a l'hote..."
+          onClose={() => setTerminalHost(null)}
+        />
+      )}
     </div>
   );
 }
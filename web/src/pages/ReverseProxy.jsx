@@ -20,6 +20,7 @@ import {
   Copy,
   ChevronDown,
   ChevronUp,
+  Network,
 } from 'lucide-react';
 import Card from '../components/Card';
 import Button from '../components/Button';
@@ -66,8 +67,8 @@ function ReverseProxy() {
 
   // Form states
   const [hostType, setHostType] = useState('subdomain');
-  const [newHost, setNewHost] = useState({ subdomain: '', customDomain: '', targetHost: 'localhost', targetPort: '', localOnly: false, requireAuth: false });
-  const [editForm, setEditForm] = useState({ targetHost: '', targetPort: '', localOnly: false, requireAuth: false });
+  const [newHost, setNewHost] = useState({ subdomain: '', customDomain: '', targetHost: 'localhost', targetPort: '', localOnly: false, vpnOnly: false, requireAuth: false, backendTls: false });
+  const [editForm, setEditForm] = useState({ targetHost: '', targetPort: '', localOnly: false, vpnOnly: false, requireAuth: false, backendTls: false });
   const [configForm, setConfigForm] = useState({ baseDomain: '' });
 
   // Action states
@@ -191,7 +192,9 @@ Verification rapide (sans les details utilisateur).
         targetHost: newHost.targetHost,
         targetPort: parseInt(newHost.targetPort),
         localOnly: newHost.localOnly,
-        requireAuth: newHost.requireAuth
+        vpnOnly: newHost.vpnOnly,
+        requireAuth: newHost.requireAuth,
+        backendTls: newHost.backendTls
       };
       if (hostType === 'subdomain') payload.subdomain = newHost.subdomain;
       else payload.customDomain = newHost.customDomain;
@@ -200,7 +203,7 @@ Verification rapide (sans les details utilisateur).
       if (res.data.success) {
         setMessage({ type: 'success', text: 'Hote ajoute' });
         setShowAddModal(false);
-        setNewHost({ subdomain: '', customDomain: '', targetHost: 'localhost', targetPort: '', localOnly: false, requireAuth: false });
+        setNewHost({ subdomain: '', customDomain: '', targetHost: 'localhost', targetPort: '', localOnly: false, vpnOnly: false, requireAuth: false, backendTls: false });
         fetchData();
       } else {
         setMessage({ type: 'error', text: res.data.error });
@@ -239,7 +242,7 @@ Verification rapide (sans les details utilisateur).
 
   function openEditModal(host) {
     setEditingHost(host);
-    setEditForm({ targetHost: host.targetHost, targetPort: String(host.targetPort), localOnly: !!host.localOnly, requireAuth: !!host.requireAuth });
+    setEditForm({ targetHost: host.targetHost, targetPort: String(host.targetPort), localOnly: !!host.localOnly, vpnOnly: !!host.vpnOnly, requireAuth: !!host.requireAuth, backendTls: !!host.backendTls });
     setShowEditModal(true);
   }
 
@@ -254,7 +257,9 @@ Verification rapide (sans les details utilisateur).
         targetHost: editForm.targetHost,
         targetPort: parseInt(editForm.targetPort),
         localOnly: editForm.localOnly,
-        requireAuth: editForm.requireAuth
+        vpnOnly: editForm.vpnOnly,
+        requireAuth: editForm.requireAuth,
+        backendTls: editForm.backendTls
       });
       if (res.data.success) {
         setMessage({ type: 'success', text: 'Hote modifie' });
@@ -479,6 +484,12 @@ Verification rapide (sans les details utilisateur).
                                     Local
                                   </span>
                                 )}
+                                {host.vpnOnly && (
+                                  <span className="flex items-center gap-1 text-xs text-cyan-400 bg-cyan-900/30 px-2 py-0.5">
+                                    <Network className="w-3 h-3" />
+                                    VPN
+                                  </span>
+                                )}
                                 {host.requireAuth && (
                                   <span className="flex items-center gap-1 text-xs text-purple-400 bg-purple-900/30 px-2 py-0.5">
                                     <Key className="w-3 h-3" />
@@ -636,11 +647,22 @@ Verification rapide (sans les details utilisateur).
                 <div className={`w-10 h-6  ${newHost.localOnly ? 'bg-yellow-600' : 'bg-gray-600'}`}><div className={`w-4 h-4 bg-white  mt-1 ${newHost.localOnly ? 'translate-x-5' : 'translate-x-1'}`} /></div>
               </div>
 
+              <div onClick={() => setNewHost({ ...newHost, vpnOnly: !newHost.vpnOnly })} className={`flex items-center gap-3 p-3 border cursor-pointer ${newHost.vpnOnly ? 'bg-cyan-900/30 border-cyan-600' : 'bg-gray-900/50 border-gray-700'}`}>
+                <Network className="w-5 h-5" />
+                <div className="flex-1"><div className="text-sm">Tailnet uniquement (VPN)</div></div>
+                <div className={`w-10 h-6  ${newHost.vpnOnly ? 'bg-cyan-600' : 'bg-gray-600'}`}><div className={`w-4 h-4 bg-white  mt-1 ${newHost.vpnOnly ? 'translate-x-5' : 'translate-x-1'}`} /></div>
+              </div>
+
               <div onClick={() => setNewHost({ ...newHost, requireAuth: !newHost.requireAuth })} className={`flex items-center gap-3 p-3 border cursor-pointer ${newHost.requireAuth ? 'bg-purple-900/30 border-purple-600' : 'bg-gray-900/50 border-gray-700'}`}>
                 <Key className="w-5 h-5" />
                 <div className="flex-1"><div className="text-sm">Authentification requise</div></div>
                 <div className={`w-10 h-6  ${newHost.requireAuth ? 'bg-purple-600' : 'bg-gray-600'}`}><div className={`w-4 h-4 bg-white  mt-1 ${newHost.requireAuth ? 'translate-x-5' : 'translate-x-1'}`} /></div>
               </div>
+              <div onClick={() => setNewHost({ ...newHost, backendTls: !newHost.backendTls })} className={`flex items-center gap-3 p-3 border cursor-pointer ${newHost.backendTls ? 'bg-green-900/30 border-green-600' : 'bg-gray-900/50 border-gray-700'}`}>
+                <Lock className="w-5 h-5" />
+                <div className="flex-1"><div className="text-sm">Cible en HTTPS (rechiffrement)</div></div>
+                <div className={`w-10 h-6  ${newHost.backendTls ? 'bg-green-600' : 'bg-gray-600'}`}><div className={`w-4 h-4 bg-white  mt-1 ${newHost.backendTls ? 'translate-x-5' : 'translate-x-1'}`} /></div>
+              </div>
             </div>
             <div className="flex justify-end gap-2 mt-6">
               <Button variant="secondary" onClick={() => setShowAddModal(false)}>Annuler</Button>
@@ -675,11 +697,21 @@ Verification rapide (sans les details utilisateur).
                 <div className="flex-1"><div className="text-sm">Reseau local uniquement</div></div>
                 <div className={`w-10 h-6  ${editForm.localOnly ? 'bg-yellow-600' : 'bg-gray-600'}`}><div className={`w-4 h-4 bg-white  mt-1 ${editForm.localOnly ? 'translate-x-5' : 'translate-x-1'}`} /></div>
               </div>
+              <div onClick={() => setEditForm({ ...editForm, vpnOnly: !editForm.vpnOnly })} className={`flex items-center gap-3 p-3 border cursor-pointer ${editForm.vpnOnly ? 'bg-cyan-900/30 border-cyan-600' : 'bg-gray-900/50 border-gray-700'}`}>
+                <Network className="w-5 h-5" />
+                <div className="flex-1"><div className="text-sm">Tailnet uniquement (VPN)</div></div>
+                <div className={`w-10 h-6  ${editForm.vpnOnly ? 'bg-cyan-600' : 'bg-gray-600'}`}><div className={`w-4 h-4 bg-white  mt-1 ${editForm.vpnOnly ? 'translate-x-5' : 'translate-x-1'}`} /></div>
+              </div>
               <div onClick={() => setEditForm({ ...editForm, requireAuth: !editForm.requireAuth })} className={`flex items-center gap-3 p-3 border cursor-pointer ${editForm.requireAuth ? 'bg-purple-900/30 border-purple-600' : 'bg-gray-900/50 border-gray-700'}`}>
                 <Key className="w-5 h-5" />
                 <div className="flex-1"><div className="text-sm">Authentification requise</div></div>
                 <div className={`w-10 h-6  ${editForm.requireAuth ? 'bg-purple-600' : 'bg-gray-600'}`}><div className={`w-4 h-4 bg-white  mt-1 ${editForm.requireAuth ? 'translate-x-5' : 'translate-x-1'}`} /></div>
               </div>
+              <div onClick={() => setEditForm({ ...editForm, backendTls: !editForm.backendTls })} className={`flex items-center gap-3 p-3 border cursor-pointer ${editForm.backendTls ? 'bg-green-900/30 border-green-600' : 'bg-gray-900/50 border-gray-700'}`}>
+                <Lock className="w-5 h-5" />
+                <div className="flex-1"><div className="text-sm">Cible en HTTPS (rechiffrement)</div></div>
+                <div className={`w-10 h-6  ${editForm.backendTls ? 'bg-green-600' : 'bg-gray-600'}`}><div className={`w-4 h-4 bg-white  mt-1 ${editForm.backendTls ? 'translate-x-5' : 'translate-x-1'}`} /></div>
+              </div>
 
             </div>
             <div className="flex justify-end gap-2 mt-6">
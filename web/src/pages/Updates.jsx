@@ -5,6 +5,7 @@ import Button from '../components/Button';
 import StatusBadge from '../components/StatusBadge';
 import ConfirmModal from '../components/ConfirmModal';
 import PageHeader from '../components/PageHeader';
+import ConfigBackupCard from '../components/ConfigBackupCard';
 import {
   getUpdatesStatus,
   getLastUpdatesCheck,
@@ -551,6 +552,8 @@ function Updates() {
         </Card>
       )}
 
+      <ConfigBackupCard />
+
       {/* Confirmation Modal */}
       <ConfirmModal
         isOpen={confirmModal.show}